@@ -0,0 +1,354 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Alt text for attachments and custom emoji
+// ═══════════════════════════════════════════════════════
+//
+// Attachments and custom emoji carry an `alt_text` for screen readers,
+// `null` until someone writes one. It is set when uploading (`?alt_text=`
+// on `POST /api/files` and `POST /api/server/emojis/{name}`), by the
+// uploader afterwards (`PATCH /api/files/{id}`), or by moderators (admins)
+// catching up on older content: `GET /api/server/alt-text/missing` lists
+// what lacks it and `POST /api/server/alt-text` fills it in bulk.
+//
+// With `ALT_TEXT_POLICY=required`, a custom emoji cannot be uploaded
+// without alt text, and a message whose images or videos lack it is
+// refused: the sending connection gets
+// `{ type: "alt_text_required", room_id, attachment_ids }`. Voice messages
+// and other files never need it.
+//
+// Alt text set on content already sent is broadcast: `alt_text_updated`
+// (`{ room_id, message_id, attachment_id, alt_text }`) to the room, and
+// `emoji_updated` (`{ emoji }`) for emoji.
+//
+// Config (env):
+//   ALT_TEXT_POLICY  optional (default) or required
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::auth::extract_claims;
+use crate::emojis::CustomEmoji;
+use crate::files::Attachment;
+use crate::ws::Broadcaster;
+
+pub const MAX_ALT_TEXT_CHARS: usize = 1000;
+const DEFAULT_MISSING_LIMIT: i64 = 50;
+const MAX_MISSING_LIMIT: i64 = 200;
+const MAX_BACKFILL_ITEMS: usize = 100;
+/// Attachments (as `a`, joined with `files f`) that need alt text and have none.
+const LACKS_ALT_TEXT: &str =
+    "a.alt_text IS NULL AND a.kind = 'file' AND (f.content_type LIKE 'image/%' OR f.content_type LIKE 'video/%')";
+
+// ── Policy ──────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AltTextPolicy {
+    Optional,
+    Required,
+}
+
+impl AltTextPolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            AltTextPolicy::Optional => "optional",
+            AltTextPolicy::Required => "required",
+        }
+    }
+}
+
+pub fn policy() -> AltTextPolicy {
+    match std::env::var("ALT_TEXT_POLICY").unwrap_or_default().trim().to_lowercase().as_str() {
+        "required" => AltTextPolicy::Required,
+        _ => AltTextPolicy::Optional,
+    }
+}
+
+/// Trimmed alt text, `None` when blank; `Err` when it is too long.
+pub(crate) fn normalize(text: Option<&str>) -> Result<Option<String>, String> {
+    let text = text.map(str::trim).filter(|t| !t.is_empty());
+    if text.is_some_and(|t| t.chars().count() > MAX_ALT_TEXT_CHARS) {
+        return Err(format!("Alt text too long (max {MAX_ALT_TEXT_CHARS} characters)"));
+    }
+    Ok(text.map(str::to_string))
+}
+
+/// Of the attachments about to be sent, the images and videos without alt
+/// text; always empty unless the policy requires it.
+pub(crate) async fn missing_for_post(pool: &SqlitePool, attachment_ids: &[String]) -> Vec<String> {
+    if attachment_ids.is_empty() || policy() != AltTextPolicy::Required {
+        return Vec::new();
+    }
+    let sql = format!(
+        "SELECT a.id FROM attachments a JOIN files f ON f.hash = a.file_hash WHERE a.id IN ({}) AND {LACKS_ALT_TEXT}",
+        vec!["?"; attachment_ids.len()].join(", ")
+    );
+    let mut query = sqlx::query_scalar::<_, String>(&sql);
+    for id in attachment_ids {
+        query = query.bind(id);
+    }
+    let missing = query.fetch_all(pool).await.unwrap_or_default();
+    attachment_ids.iter().filter(|id| missing.contains(id)).cloned().collect()
+}
+
+/// The event for the WebSocket connection whose message was refused.
+pub fn required_event(room_id: &str, attachment_ids: &[String]) -> String {
+    serde_json::json!({
+        "type": "alt_text_required",
+        "room_id": room_id,
+        "attachment_ids": attachment_ids,
+    })
+    .to_string()
+}
+
+// ── Updates ─────────────────────────────────────────────
+
+/// Set the alt text of attachment `id` and tell its room if it was sent.
+/// `false` when there is no such attachment.
+async fn set_attachment(pool: &SqlitePool, broadcaster: &Broadcaster, id: &str, alt_text: Option<&str>) -> bool {
+    let updated = sqlx::query("UPDATE attachments SET alt_text = ? WHERE id = ?")
+        .bind(alt_text)
+        .bind(id)
+        .execute(pool)
+        .await
+        .is_ok_and(|r| r.rows_affected() > 0);
+    if !updated {
+        return false;
+    }
+
+    let sent: Option<(String, String)> = sqlx::query_as(
+        "SELECT m.id, m.room_id FROM attachments a JOIN messages m ON m.id = a.message_id WHERE a.id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+    if let Some((message_id, room_id)) = sent {
+        let event = serde_json::json!({
+            "type": "alt_text_updated",
+            "room_id": room_id,
+            "message_id": message_id,
+            "attachment_id": id,
+            "alt_text": alt_text,
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+    true
+}
+
+/// Set the alt text of emoji `id` and broadcast it. `false` when there is no such emoji.
+async fn set_emoji(pool: &SqlitePool, broadcaster: &Broadcaster, id: &str, alt_text: Option<&str>) -> bool {
+    let updated = sqlx::query("UPDATE custom_emojis SET alt_text = ? WHERE id = ?")
+        .bind(alt_text)
+        .bind(id)
+        .execute(pool)
+        .await
+        .is_ok_and(|r| r.rows_affected() > 0);
+    if !updated {
+        return false;
+    }
+    if let Some(emoji) = crate::emojis::fetch_emoji(pool, id).await {
+        let event = serde_json::json!({ "type": "emoji_updated", "emoji": emoji });
+        let _ = broadcaster.send(event.to_string());
+    }
+    true
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct AltTextQuery {
+    pub alt_text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetAltText {
+    pub alt_text: Option<String>,
+}
+
+/// GET /api/alt-text/policy — Whether alt text is required, and its maximum length
+pub async fn get_policy(req: HttpRequest) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "policy": policy().as_str(),
+        "max_chars": MAX_ALT_TEXT_CHARS,
+    }))
+}
+
+/// PATCH /api/files/{id} — Set or clear the alt text of an attachment (uploader or admin)
+pub async fn set_attachment_alt_text(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<SetAltText>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let id = path.into_inner();
+    let uploader: Option<Option<String>> = sqlx::query_scalar("SELECT uploader_id FROM attachments WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let Some(uploader) = uploader else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Attachment not found" }));
+    };
+    if uploader.as_deref() != Some(claims.sub.as_str())
+        && crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await.is_err()
+    {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only the uploader can edit this attachment" }));
+    }
+
+    let alt_text = match normalize(body.alt_text.as_deref()) {
+        Ok(text) => text,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+    if !set_attachment(pool.get_ref(), broadcaster.get_ref(), &id, alt_text.as_deref()).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Attachment not found" }));
+    }
+
+    match crate::files::fetch_attachments(pool.get_ref(), std::slice::from_ref(&id)).await.pop() {
+        Some(attachment) => HttpResponse::Ok().json(attachment),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Attachment not found" })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MissingQuery {
+    pub limit: Option<i64>,
+    /// `created_at` of the last attachment of the previous page.
+    pub before: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingAttachment {
+    pub room_id: String,
+    pub message_id: String,
+    pub created_at: String,
+    pub attachment: Attachment,
+}
+
+/// GET /api/server/alt-text/missing?limit=&before= — Sent images and videos, newest first, and emoji without alt text (MANAGE_MESSAGES)
+pub async fn list_missing(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<MissingQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+
+    let limit = crate::pagination::limit(query.limit, DEFAULT_MISSING_LIMIT, MAX_MISSING_LIMIT);
+    let sql = format!(
+        "SELECT a.id, a.created_at, m.id AS message_id, m.room_id FROM attachments a \
+         JOIN files f ON f.hash = a.file_hash \
+         JOIN messages m ON m.id = a.message_id \
+         WHERE m.deleted_at IS NULL AND {LACKS_ALT_TEXT} AND (? IS NULL OR a.created_at < ?) \
+         ORDER BY a.created_at DESC, a.id LIMIT ?"
+    );
+    let rows = sqlx::query(&sql)
+        .bind(&query.before)
+        .bind(&query.before)
+        .bind(limit)
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default();
+
+    let ids: Vec<String> = rows.iter().map(|row| row.get("id")).collect();
+    let mut attachments = crate::files::fetch_attachments(pool.get_ref(), &ids).await;
+    let missing: Vec<MissingAttachment> = rows
+        .iter()
+        .filter_map(|row| {
+            let id: String = row.get("id");
+            let index = attachments.iter().position(|a| a.id == id)?;
+            Some(MissingAttachment {
+                room_id: row.get("room_id"),
+                message_id: row.get("message_id"),
+                created_at: row.get("created_at"),
+                attachment: attachments.swap_remove(index),
+            })
+        })
+        .collect();
+    let next_before = (missing.len() as i64 == limit).then(|| missing.last().map(|m| m.created_at.clone())).flatten();
+
+    let emojis: Vec<CustomEmoji> = crate::emojis::fetch_without_alt_text(pool.get_ref()).await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "policy": policy().as_str(),
+        "attachments": missing,
+        "next_before": next_before,
+        "emojis": emojis,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillItem {
+    /// `attachment` or `emoji`.
+    pub kind: String,
+    pub id: String,
+    pub alt_text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillInput {
+    pub items: Vec<BackfillItem>,
+}
+
+/// POST /api/server/alt-text — Fill in the alt text of attachments and emoji in bulk (MANAGE_MESSAGES)
+pub async fn backfill(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<BackfillInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+
+    let items = body.into_inner().items;
+    if items.is_empty() || items.len() > MAX_BACKFILL_ITEMS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Send between 1 and {MAX_BACKFILL_ITEMS} items")
+        }));
+    }
+    let mut texts = Vec::with_capacity(items.len());
+    for item in &items {
+        if item.kind != "attachment" && item.kind != "emoji" {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "kind must be attachment or emoji" }));
+        }
+        match normalize(Some(&item.alt_text)) {
+            Ok(Some(text)) => texts.push(text),
+            Ok(None) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Alt text cannot be empty" })),
+            Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        }
+    }
+
+    let mut updated = 0;
+    let mut not_found = Vec::new();
+    for (item, text) in items.iter().zip(&texts) {
+        let found = match item.kind.as_str() {
+            "attachment" => set_attachment(pool.get_ref(), broadcaster.get_ref(), &item.id, Some(text)).await,
+            _ => set_emoji(pool.get_ref(), broadcaster.get_ref(), &item.id, Some(text)).await,
+        };
+        if found {
+            updated += 1;
+        } else {
+            not_found.push(item.id.clone());
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "updated": updated, "not_found": not_found }))
+}
@@ -0,0 +1,376 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Instance-wide announcements
+// ═══════════════════════════════════════════════════════
+//
+// Admins post an announcement, optionally scheduled (`starts_at`) and with
+// an expiry (`expires_at`). When it goes live it is sent to every connected
+// client as an `announcement` event, which clients show as a banner, and,
+// if `room_id` is set, also posted in that room as a system message
+// (`kind: announcement`). The instance is a single server, so that room
+// plays the part of the server's system channel.
+//
+// Scheduled announcements are published by a background task every
+// `PUBLISH_INTERVAL`. Clients that connect later fetch the live ones with
+// `GET /api/announcements`, which leaves out those the user dismissed.
+// Dismissing is per user and is pushed to the user's other connections.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::extract_claims;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::ws::Broadcaster;
+
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_CONTENT_CHARS: usize = 2000;
+const LEVELS: [&str; 3] = ["info", "warning", "critical"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Announcement {
+    pub id: String,
+    pub author_username: String,
+    pub content: String,
+    pub level: String,
+    pub room_id: Option<String>,
+    pub starts_at: String,
+    pub expires_at: Option<String>,
+    pub published_at: Option<String>,
+    pub cancelled_at: Option<String>,
+    pub created_at: String,
+    /// Only in the admin listing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dismissed_count: Option<i64>,
+}
+
+const COLUMNS: &str = "id, author_username, content, level, room_id, starts_at, expires_at, published_at, cancelled_at, created_at";
+
+impl Announcement {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            author_username: row.get("author_username"),
+            content: row.get("content"),
+            level: row.get("level"),
+            room_id: row.get("room_id"),
+            starts_at: row.get("starts_at"),
+            expires_at: row.get("expires_at"),
+            published_at: row.get("published_at"),
+            cancelled_at: row.get("cancelled_at"),
+            created_at: row.get("created_at"),
+            dismissed_count: row.try_get("dismissed_count").ok(),
+        }
+    }
+}
+
+fn parse_timestamp(value: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .ok()
+        .map(|t| t.with_timezone(&Utc).to_rfc3339())
+}
+
+// ── Publishing ──────────────────────────────────────────
+
+/// Mark due announcements published, broadcast them and post their room
+/// messages. Announcements that expired before their turn are skipped.
+/// Publish every due announcement, returns how many went out.
+async fn publish_due(pool: &SqlitePool, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let now = Utc::now().to_rfc3339();
+    let sql = format!(
+        "SELECT {COLUMNS}, author_id FROM announcements \
+         WHERE published_at IS NULL AND cancelled_at IS NULL AND starts_at <= ? \
+           AND (expires_at IS NULL OR expires_at > ?) ORDER BY starts_at"
+    );
+    let rows = sqlx::query(&sql)
+        .bind(&now)
+        .bind(&now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut published = 0;
+    for row in rows {
+        let mut announcement = Announcement::from_row(&row);
+        let author_id: String = row.get("author_id");
+
+        // Claim it first, so two ticks never publish the same one twice.
+        let claimed = sqlx::query("UPDATE announcements SET published_at = ? WHERE id = ? AND published_at IS NULL")
+            .bind(&now)
+            .bind(&announcement.id)
+            .execute(pool)
+            .await
+            .map(|r| r.rows_affected())
+            .unwrap_or(0);
+        if claimed == 0 {
+            continue;
+        }
+        announcement.published_at = Some(now.clone());
+        published += 1;
+
+        let event = serde_json::json!({ "type": "announcement", "announcement": announcement });
+        let _ = broadcaster.send(event.to_string());
+
+        if let Some(room_id) = &announcement.room_id {
+            crate::rooms::post_system_message(
+                pool,
+                broadcaster,
+                room_id,
+                &author_id,
+                &announcement.author_username,
+                "announcement",
+                &announcement.content,
+            )
+            .await;
+        }
+    }
+    Ok(published)
+}
+
+/// Due announcements still unpublished.
+async fn publish_backlog(pool: &SqlitePool) -> Backlog {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(starts_at) AS oldest_due_at FROM announcements \
+         WHERE published_at IS NULL AND cancelled_at IS NULL AND starts_at <= ? \
+           AND (expires_at IS NULL OR expires_at > ?)"
+    )
+    .bind(&now)
+    .bind(&now)
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_publisher(pool: SqlitePool, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::ANNOUNCEMENTS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::ANNOUNCEMENTS, None, publish_due(&pool, &broadcaster), publish_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAnnouncement {
+    pub content: String,
+    pub level: Option<String>,
+    /// Post it in this room as well.
+    pub room_id: Option<String>,
+    /// RFC 3339; defaults to now.
+    pub starts_at: Option<String>,
+    /// RFC 3339; no expiry by default.
+    pub expires_at: Option<String>,
+}
+
+/// POST /api/server/announcements — Create an announcement, now or scheduled (MANAGE_SERVER)
+pub async fn create(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    jobs: web::Data<JobRegistry>,
+    body: web::Json<CreateAnnouncement>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let content = body.content.trim();
+    if content.is_empty() || content.chars().count() > MAX_CONTENT_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Announcement must be 1 to 2000 chars" }));
+    }
+    let level = body.level.as_deref().unwrap_or("info").trim().to_lowercase();
+    if !LEVELS.contains(&level.as_str()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Level must be info, warning or critical" }));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let starts_at = match body.starts_at.as_deref() {
+        Some(value) => match parse_timestamp(value) {
+            Some(t) => t,
+            None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid starts_at (expected RFC 3339)" })),
+        },
+        None => now.clone(),
+    };
+    let expires_at = match body.expires_at.as_deref() {
+        Some(value) => match parse_timestamp(value) {
+            Some(t) if t > starts_at && t > now => Some(t),
+            Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "expires_at must be in the future and after starts_at" })),
+            None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid expires_at (expected RFC 3339)" })),
+        },
+        None => None,
+    };
+
+    if let Some(room_id) = &body.room_id {
+        let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+            .bind(room_id)
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None);
+        if kind.as_deref() != Some("text") {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "room_id must be a text room" }));
+        }
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(
+        "INSERT INTO announcements (id, author_id, author_username, content, level, room_id, starts_at, expires_at, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(content)
+    .bind(&level)
+    .bind(&body.room_id)
+    .bind(&starts_at)
+    .bind(&expires_at)
+    .bind(&now)
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if starts_at <= now {
+        let request_id = crate::jobs::request_id_of(&req);
+        crate::jobs::run(
+            jobs.get_ref(),
+            crate::jobs::ANNOUNCEMENTS,
+            request_id.as_deref(),
+            publish_due(pool.get_ref(), broadcaster.get_ref()),
+            publish_backlog(pool.get_ref()),
+        )
+        .await;
+    }
+
+    let sql = format!("SELECT {COLUMNS} FROM announcements WHERE id = ?");
+    match sqlx::query(&sql).bind(&id).fetch_one(pool.get_ref()).await {
+        Ok(row) => HttpResponse::Created().json(Announcement::from_row(&row)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/server/announcements — All announcements with dismissal counts (MANAGE_SERVER)
+pub async fn list_all(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let sql = format!(
+        "SELECT {COLUMNS}, (SELECT COUNT(*) FROM announcement_dismissals d WHERE d.announcement_id = a.id) AS dismissed_count \
+         FROM announcements a ORDER BY starts_at DESC LIMIT 100"
+    );
+    match sqlx::query(&sql).fetch_all(pool.get_ref()).await {
+        Ok(rows) => HttpResponse::Ok().json(rows.iter().map(Announcement::from_row).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/server/announcements/{id} — Cancel a scheduled or live announcement (MANAGE_SERVER)
+pub async fn cancel(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let updated = sqlx::query("UPDATE announcements SET cancelled_at = ? WHERE id = ? AND cancelled_at IS NULL")
+        .bind(Utc::now().to_rfc3339())
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+    if updated == 0 {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Announcement not found" }));
+    }
+
+    let event = serde_json::json!({ "type": "announcement_removed", "id": id });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(serde_json::json!({ "status": "announcement cancelled" }))
+}
+
+/// GET /api/announcements — Live announcements the caller has not dismissed
+pub async fn list_active(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let sql = format!(
+        "SELECT {COLUMNS} FROM announcements a \
+         WHERE published_at IS NOT NULL AND cancelled_at IS NULL AND (expires_at IS NULL OR expires_at > ?) \
+           AND NOT EXISTS (SELECT 1 FROM announcement_dismissals d WHERE d.announcement_id = a.id AND d.user_id = ?) \
+         ORDER BY published_at DESC"
+    );
+    match sqlx::query(&sql)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&claims.sub)
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(rows) => HttpResponse::Ok().json(rows.iter().map(Announcement::from_row).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/announcements/{id}/dismiss — Hide an announcement for the caller, on all their devices
+pub async fn dismiss(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let id = path.into_inner();
+    let exists: Option<String> = sqlx::query_scalar("SELECT id FROM announcements WHERE id = ? AND published_at IS NOT NULL")
+        .bind(&id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if exists.is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Announcement not found" }));
+    }
+
+    let result = sqlx::query("INSERT OR IGNORE INTO announcement_dismissals (announcement_id, user_id, dismissed_at) VALUES (?, ?, ?)")
+        .bind(&id)
+        .bind(&claims.sub)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let event = serde_json::json!({ "type": "announcement_removed", "id": id, "recipient_id": claims.sub });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(serde_json::json!({ "status": "announcement dismissed" }))
+}
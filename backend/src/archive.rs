@@ -0,0 +1,158 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — ZIP archives written to disk
+// ═══════════════════════════════════════════════════════
+//
+// The archives clients download (personal data exports, room transcripts)
+// are plain ZIP files written entry by entry to a spool file. Entries are
+// stored without compression, since most of the bytes are media that
+// already is compressed, and names are flagged as UTF-8. There are no
+// ZIP64 records: an archive stays under `MAX_ARCHIVE_BYTES` and 65535
+// entries, and `fits` tells whether another entry does.
+
+use chrono::{Datelike, Timelike, Utc};
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// The archive has no ZIP64 records: it stays under 4 GiB.
+pub(crate) const MAX_ARCHIVE_BYTES: u64 = u32::MAX as u64;
+/// Bytes copied at once by `add_file`.
+const COPY_CHUNK: usize = 64 * 1024;
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// A ZIP file written entry by entry, stored without compression.
+pub(crate) struct Archive {
+    out: tokio::io::BufWriter<tokio::fs::File>,
+    offset: u64,
+    entries: Vec<Entry>,
+    /// MS-DOS time and date of every entry.
+    modified: (u16, u16),
+}
+
+impl Archive {
+    pub(crate) async fn create(path: &Path) -> Result<Self, String> {
+        let file = tokio::fs::File::create(path).await.map_err(|e| e.to_string())?;
+        let now = Utc::now();
+        let time = (now.hour() << 11) | (now.minute() << 5) | (now.second() / 2);
+        let date = (((now.year() - 1980).max(0) as u32) << 9) | (now.month() << 5) | now.day();
+        Ok(Archive { out: tokio::io::BufWriter::new(file), offset: 0, entries: Vec::new(), modified: (time as u16, date as u16) })
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).await.map_err(|e| e.to_string())?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Room left for an entry of `len` bytes and its records.
+    pub(crate) fn fits(&self, name: &str, len: u64) -> bool {
+        self.offset + len + 2 * (46 + name.len() as u64) + 22 < MAX_ARCHIVE_BYTES && self.entries.len() < u16::MAX as usize
+    }
+
+    /// Start an entry: checks it fits and writes its local header.
+    async fn begin(&mut self, name: &str, crc: u32, len: u64) -> Result<(), String> {
+        if !self.fits(name, len) {
+            return Err("the archive would pass 4 GiB".to_string());
+        }
+        let entry = Entry { name: name.to_string(), crc, size: len as u32, offset: self.offset as u32 };
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        header.extend_from_slice(&0x0800u16.to_le_bytes()); // UTF-8 names
+        header.extend_from_slice(&0u16.to_le_bytes()); // stored
+        header.extend_from_slice(&self.modified.0.to_le_bytes());
+        header.extend_from_slice(&self.modified.1.to_le_bytes());
+        header.extend_from_slice(&entry.crc.to_le_bytes());
+        header.extend_from_slice(&entry.size.to_le_bytes());
+        header.extend_from_slice(&entry.size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.write(&header).await?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub(crate) async fn add(&mut self, name: &str, bytes: &[u8]) -> Result<(), String> {
+        self.begin(name, crc32fast::hash(bytes), bytes.len() as u64).await?;
+        self.write(bytes).await
+    }
+
+    pub(crate) async fn add_json(&mut self, name: &str, value: &serde_json::Value) -> Result<(), String> {
+        let bytes = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+        self.add(name, &bytes).await
+    }
+
+    /// Add the file at `path` without holding it in memory: it is read
+    /// once for its checksum, then copied.
+    pub(crate) async fn add_file(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        let mut buf = vec![0; COPY_CHUNK];
+        let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut len = 0u64;
+        loop {
+            let n = file.read(&mut buf).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            len += n as u64;
+        }
+        self.begin(name, hasher.finalize(), len).await?;
+        let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+        let mut copied = 0u64;
+        while copied < len {
+            let n = file.read(&mut buf).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                return Err(format!("{name} shrank while it was archived"));
+            }
+            let n = n.min((len - copied) as usize);
+            self.write(&buf[..n]).await?;
+            copied += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Write the central directory; returns the archive's size.
+    pub(crate) async fn finish(mut self) -> Result<u64, String> {
+        let start = self.offset;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            directory.extend_from_slice(&0x0800u16.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&self.modified.0.to_le_bytes());
+            directory.extend_from_slice(&self.modified.1.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0; 12]); // extra, comment, disk, attributes
+            directory.extend_from_slice(&entry.offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let count = self.entries.len() as u16;
+        directory.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&[0; 4]); // disk numbers
+        directory.extend_from_slice(&count.to_le_bytes());
+        directory.extend_from_slice(&count.to_le_bytes());
+        directory.extend_from_slice(&((directory.len() - 12) as u32).to_le_bytes());
+        directory.extend_from_slice(&(start as u32).to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&directory).await?;
+        self.out.flush().await.map_err(|e| e.to_string())?;
+        Ok(self.offset)
+    }
+}
+
+/// A file name safe as the last part of an archive path.
+pub(crate) fn archive_name(filename: &str) -> String {
+    filename.chars().map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c }).collect()
+}
@@ -0,0 +1,229 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Audit trail of the linked Discord account
+// ═══════════════════════════════════════════════════════
+//
+// A linked Discord token is a full credential for someone's Discord account,
+// so every use of it that matters is written to `auth_audit` with the IP
+// and user agent of the request behind it:
+//   - discord_linked       a Discord token was stored (token, OAuth2, password)
+//   - qr_login_completed   a QR code login finished and stored a token
+//   - discord_unlinked     the user removed the token
+//   - gateway_identify     Voxium opened a Discord gateway session with it
+//
+// Account recovery (see `recovery`) is logged here too, since it ends with
+// the Discord token being dropped:
+//   - recovery_codes_generated, recovery_requested, recovery_approved,
+//     recovery_cancelled, recovery_completed
+//
+// Users read their own entries through `GET /api/users/@me/security-log`.
+// Entries older than `RETENTION_DAYS` are dropped as new ones come in.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::auth::extract_claims;
+use crate::sessions::DeviceInfo;
+
+const RETENTION_DAYS: i64 = 180;
+const DEFAULT_LOG_LIMIT: i64 = 50;
+const MAX_LOG_LIMIT: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthEvent {
+    DiscordLinked,
+    DiscordUnlinked,
+    QrLoginCompleted,
+    GatewayIdentify,
+    RecoveryCodesGenerated,
+    RecoveryRequested,
+    RecoveryApproved,
+    RecoveryCancelled,
+    RecoveryCompleted,
+}
+
+impl AuthEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthEvent::DiscordLinked => "discord_linked",
+            AuthEvent::DiscordUnlinked => "discord_unlinked",
+            AuthEvent::QrLoginCompleted => "qr_login_completed",
+            AuthEvent::GatewayIdentify => "gateway_identify",
+            AuthEvent::RecoveryCodesGenerated => "recovery_codes_generated",
+            AuthEvent::RecoveryRequested => "recovery_requested",
+            AuthEvent::RecoveryApproved => "recovery_approved",
+            AuthEvent::RecoveryCancelled => "recovery_cancelled",
+            AuthEvent::RecoveryCompleted => "recovery_completed",
+        }
+    }
+}
+
+/// How a Discord token reached the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMethod {
+    Token,
+    OAuth2,
+    Password,
+    Qr,
+}
+
+impl LinkMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkMethod::Token => "token",
+            LinkMethod::OAuth2 => "oauth2",
+            LinkMethod::Password => "password",
+            LinkMethod::Qr => "qr",
+        }
+    }
+
+    /// QR logins get their own event, the others are plain links.
+    pub fn event(self) -> AuthEvent {
+        match self {
+            LinkMethod::Qr => AuthEvent::QrLoginCompleted,
+            _ => AuthEvent::DiscordLinked,
+        }
+    }
+}
+
+/// Append one entry. Failures are logged, never surfaced: auditing must not
+/// break the login it records.
+pub(crate) async fn record(
+    pool: &SqlitePool,
+    user_id: &str,
+    event: AuthEvent,
+    device: &DeviceInfo,
+    details: serde_json::Value,
+) {
+    let now = chrono::Utc::now();
+    let result = sqlx::query(
+        "INSERT INTO auth_audit (id, user_id, event, ip, user_agent, details, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(event.as_str())
+    .bind(&device.ip)
+    .bind(&device.user_agent)
+    .bind(details.to_string())
+    .bind(now.to_rfc3339())
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        eprintln!("Audit write failed ({}): {e}", event.as_str());
+        return;
+    }
+    crate::notifications::auth_event(pool, user_id, event, device, &details).await;
+
+    let cutoff = (now - chrono::Duration::days(RETENTION_DAYS)).to_rfc3339();
+    let _ = sqlx::query("DELETE FROM auth_audit WHERE user_id = ? AND created_at < ?")
+        .bind(user_id)
+        .bind(cutoff)
+        .execute(pool)
+        .await;
+}
+
+/// What a long-lived task (the Discord gateway) needs to audit on behalf of
+/// the request that started it.
+#[derive(Clone)]
+pub struct AuditContext {
+    pub pool: SqlitePool,
+    pub user_id: String,
+    pub device: DeviceInfo,
+}
+
+impl AuditContext {
+    pub async fn record(&self, event: AuthEvent, details: serde_json::Value) {
+        record(&self.pool, &self.user_id, event, &self.device, details).await;
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+const SECURITY_LOG: crate::pagination::Listing = crate::pagination::Listing {
+    name: "security_log",
+    default_limit: DEFAULT_LOG_LIMIT,
+    max_limit: MAX_LOG_LIMIT,
+    sorts: &[("created_at", "created_at")],
+    default_sort: "-created_at",
+    tiebreak: "id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SecurityLogQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    /// Only entries strictly older than this timestamp (older clients; prefer `cursor`).
+    pub before: Option<String>,
+    /// Only this event, e.g. `discord_linked`.
+    pub event: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SecurityLogEntry {
+    pub id: String,
+    pub event: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: serde_json::Value,
+    pub created_at: String,
+}
+
+/// GET /api/users/@me/security-log?limit=&sort=&cursor=&event= — Audit entries of the current user, newest first
+pub async fn security_log(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<SecurityLogQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let page = match SECURITY_LOG.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let before = crate::pagination::filter(&query.before);
+    let event = crate::pagination::filter(&query.event);
+
+    let sql = format!(
+        "SELECT id, event, ip, user_agent, details, created_at, {} FROM auth_audit \
+         WHERE user_id = ? AND (? IS NULL OR created_at < ?) AND (? IS NULL OR event = ?){}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut qx = sqlx::query(&sql)
+        .bind(&claims.sub)
+        .bind(&before)
+        .bind(&before)
+        .bind(&event)
+        .bind(&event);
+    for value in page.keyset_binds() {
+        qx = qx.bind(value);
+    }
+    let rows = qx.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await;
+
+    match rows {
+        Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
+            let entries: Vec<SecurityLogEntry> = rows
+                .into_iter()
+                .map(|row| SecurityLogEntry {
+                    id: row.get("id"),
+                    event: row.get("event"),
+                    ip: row.get("ip"),
+                    user_agent: row.get("user_agent"),
+                    details: row
+                        .get::<Option<String>, _>("details")
+                        .and_then(|d| serde_json::from_str(&d).ok())
+                        .unwrap_or(serde_json::Value::Null),
+                    created_at: row.get("created_at"),
+                })
+                .collect();
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(entries), next_cursor)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,384 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Server audit log (moderation and administration)
+// ═══════════════════════════════════════════════════════
+//
+// Actions taken on the server by its moderators and admins are written to
+// `audit_log`, with who did it, what it was done to and what changed:
+//   - member_role_updated, member_removed          target: user
+//   - role_created, role_updated, role_deleted     target: role (its name)
+//   - room_created, room_updated, room_deleted     target: room
+//   - permissions_updated, permissions_removed     target: room (overwrites)
+//   - messages_bulk_deleted, room_archived,
+//     messages_pruned (by retention)               target: room
+//   - voice_recording_started, voice_recording_stopped,
+//     voice_recording_deleted                      target: room
+//   - messages_purged                              target: user
+//   - member_kicked, member_banned, member_unbanned,
+//     ban_appeal_reviewed                          target: user
+//   - member_timed_out (by automod),
+//     member_timeout_removed                       target: user
+//   - member_voice_updated (server mute or deafen
+//     in a voice room)                             target: user
+//   - member_profile_updated (by a moderator)      target: user
+//   - automod_rule_created, automod_rule_updated,
+//     automod_rule_deleted                         target: automod_rule
+//   - lockdown_started, lockdown_ended             target: lockdown
+//   - verification_updated, join_surge_detected,
+//     join_surge_ended                             target: verification
+//   - retention_updated                            target: retention (`server`
+//                                                  or a room id)
+//   - bot_created, bot_updated, bot_token_reset,
+//     bot_deleted                                  target: bot
+//
+// `changes` is `{ field: { old, new } }` for edits and the created or
+// deleted thing otherwise. The reason comes from the `X-Audit-Log-Reason`
+// header of the request, when the client sends one. Unlike `audit` (the
+// security log of one account), this log is for the server's staff and is
+// read with `GET /api/server/audit-log` (MANAGE_SERVER).
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+
+pub const REASON_HEADER: &str = "x-audit-log-reason";
+const MAX_REASON_CHARS: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    MemberRoleUpdated,
+    MemberRemoved,
+    RoleCreated,
+    RoleUpdated,
+    RoleDeleted,
+    RoomCreated,
+    RoomUpdated,
+    RoomDeleted,
+    PermissionsUpdated,
+    PermissionsRemoved,
+    MessagesPurged,
+    MessagesBulkDeleted,
+    RoomArchived,
+    MessagesPruned,
+    VoiceRecordingStarted,
+    VoiceRecordingStopped,
+    VoiceRecordingDeleted,
+    MemberKicked,
+    MemberBanned,
+    MemberUnbanned,
+    BanAppealReviewed,
+    MemberTimedOut,
+    MemberTimeoutRemoved,
+    MemberVoiceUpdated,
+    MemberProfileUpdated,
+    AutomodRuleCreated,
+    AutomodRuleUpdated,
+    AutomodRuleDeleted,
+    LockdownStarted,
+    LockdownEnded,
+    VerificationUpdated,
+    JoinSurgeDetected,
+    JoinSurgeEnded,
+    RetentionUpdated,
+    BotCreated,
+    BotUpdated,
+    BotTokenReset,
+    BotDeleted,
+    ServerBannerUpdated,
+    UploadReleased,
+    UploadDeleted,
+}
+
+/// Every action, for the `action` filter.
+const ACTIONS: [Action; 41] = [
+    Action::MemberRoleUpdated,
+    Action::MemberRemoved,
+    Action::RoleCreated,
+    Action::RoleUpdated,
+    Action::RoleDeleted,
+    Action::RoomCreated,
+    Action::RoomUpdated,
+    Action::RoomDeleted,
+    Action::PermissionsUpdated,
+    Action::PermissionsRemoved,
+    Action::MessagesPurged,
+    Action::MessagesBulkDeleted,
+    Action::RoomArchived,
+    Action::MessagesPruned,
+    Action::VoiceRecordingStarted,
+    Action::VoiceRecordingStopped,
+    Action::VoiceRecordingDeleted,
+    Action::MemberKicked,
+    Action::MemberBanned,
+    Action::MemberUnbanned,
+    Action::BanAppealReviewed,
+    Action::MemberTimedOut,
+    Action::MemberTimeoutRemoved,
+    Action::MemberVoiceUpdated,
+    Action::MemberProfileUpdated,
+    Action::AutomodRuleCreated,
+    Action::AutomodRuleUpdated,
+    Action::AutomodRuleDeleted,
+    Action::LockdownStarted,
+    Action::LockdownEnded,
+    Action::VerificationUpdated,
+    Action::JoinSurgeDetected,
+    Action::JoinSurgeEnded,
+    Action::RetentionUpdated,
+    Action::BotCreated,
+    Action::BotUpdated,
+    Action::BotTokenReset,
+    Action::BotDeleted,
+    Action::ServerBannerUpdated,
+    Action::UploadReleased,
+    Action::UploadDeleted,
+];
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::MemberRoleUpdated => "member_role_updated",
+            Action::MemberRemoved => "member_removed",
+            Action::RoleCreated => "role_created",
+            Action::RoleUpdated => "role_updated",
+            Action::RoleDeleted => "role_deleted",
+            Action::RoomCreated => "room_created",
+            Action::RoomUpdated => "room_updated",
+            Action::RoomDeleted => "room_deleted",
+            Action::PermissionsUpdated => "permissions_updated",
+            Action::PermissionsRemoved => "permissions_removed",
+            Action::MessagesPurged => "messages_purged",
+            Action::MessagesBulkDeleted => "messages_bulk_deleted",
+            Action::RoomArchived => "room_archived",
+            Action::MessagesPruned => "messages_pruned",
+            Action::VoiceRecordingStarted => "voice_recording_started",
+            Action::VoiceRecordingStopped => "voice_recording_stopped",
+            Action::VoiceRecordingDeleted => "voice_recording_deleted",
+            Action::MemberKicked => "member_kicked",
+            Action::MemberBanned => "member_banned",
+            Action::MemberUnbanned => "member_unbanned",
+            Action::BanAppealReviewed => "ban_appeal_reviewed",
+            Action::MemberTimedOut => "member_timed_out",
+            Action::MemberTimeoutRemoved => "member_timeout_removed",
+            Action::MemberVoiceUpdated => "member_voice_updated",
+            Action::MemberProfileUpdated => "member_profile_updated",
+            Action::AutomodRuleCreated => "automod_rule_created",
+            Action::AutomodRuleUpdated => "automod_rule_updated",
+            Action::AutomodRuleDeleted => "automod_rule_deleted",
+            Action::LockdownStarted => "lockdown_started",
+            Action::LockdownEnded => "lockdown_ended",
+            Action::VerificationUpdated => "verification_updated",
+            Action::JoinSurgeDetected => "join_surge_detected",
+            Action::JoinSurgeEnded => "join_surge_ended",
+            Action::RetentionUpdated => "retention_updated",
+            Action::BotCreated => "bot_created",
+            Action::BotUpdated => "bot_updated",
+            Action::BotTokenReset => "bot_token_reset",
+            Action::BotDeleted => "bot_deleted",
+            Action::ServerBannerUpdated => "server_banner_updated",
+            Action::UploadReleased => "upload_released",
+            Action::UploadDeleted => "upload_deleted",
+        }
+    }
+
+    fn target_type(self) -> &'static str {
+        match self {
+            Action::MemberRoleUpdated
+            | Action::MemberRemoved
+            | Action::MessagesPurged
+            | Action::MemberKicked
+            | Action::MemberBanned
+            | Action::MemberUnbanned
+            | Action::BanAppealReviewed
+            | Action::MemberTimedOut
+            | Action::MemberTimeoutRemoved
+            | Action::MemberVoiceUpdated
+            | Action::MemberProfileUpdated => "user",
+            Action::RoleCreated | Action::RoleUpdated | Action::RoleDeleted => "role",
+            Action::RoomCreated
+            | Action::RoomUpdated
+            | Action::RoomDeleted
+            | Action::PermissionsUpdated
+            | Action::PermissionsRemoved
+            | Action::MessagesBulkDeleted
+            | Action::RoomArchived
+            | Action::MessagesPruned
+            | Action::VoiceRecordingStarted
+            | Action::VoiceRecordingStopped
+            | Action::VoiceRecordingDeleted => "room",
+            Action::AutomodRuleCreated | Action::AutomodRuleUpdated | Action::AutomodRuleDeleted => "automod_rule",
+            Action::LockdownStarted | Action::LockdownEnded => "lockdown",
+            Action::VerificationUpdated | Action::JoinSurgeDetected | Action::JoinSurgeEnded => "verification",
+            Action::RetentionUpdated => "retention",
+            Action::BotCreated | Action::BotUpdated | Action::BotTokenReset | Action::BotDeleted => "bot",
+            Action::ServerBannerUpdated => "server",
+            Action::UploadReleased | Action::UploadDeleted => "attachment",
+        }
+    }
+}
+
+/// The reason given in the `X-Audit-Log-Reason` header, if any.
+pub(crate) fn reason(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(REASON_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(|r| r.chars().take(MAX_REASON_CHARS).collect())
+}
+
+/// `{ field: { old, new } }` for the fields whose value changed.
+pub(crate) fn diff(fields: &[(&str, serde_json::Value, serde_json::Value)]) -> serde_json::Value {
+    let changed: serde_json::Map<String, serde_json::Value> = fields
+        .iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(name, old, new)| (name.to_string(), serde_json::json!({ "old": old, "new": new })))
+        .collect();
+    serde_json::Value::Object(changed)
+}
+
+/// Write an entry. `actor` is None for actions the server takes itself.
+/// Failures are only logged: the action already happened.
+pub(crate) async fn record(
+    pool: &SqlitePool,
+    actor: Option<&Claims>,
+    action: Action,
+    target_id: &str,
+    changes: serde_json::Value,
+    reason: Option<&str>,
+) {
+    let result = sqlx::query(
+        "INSERT INTO audit_log (id, action, actor_id, actor_username, target_type, target_id, changes, reason, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(action.as_str())
+    .bind(actor.map(|c| c.sub.as_str()))
+    .bind(actor.map(|c| c.username.as_str()))
+    .bind(action.target_type())
+    .bind(target_id)
+    .bind(changes.to_string())
+    .bind(reason)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        eprintln!("audit log: failed to record {}: {e}", action.as_str());
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogEntry {
+    pub id: String,
+    pub action: String,
+    pub actor_id: Option<String>,
+    pub actor_username: Option<String>,
+    pub target_type: String,
+    pub target_id: String,
+    pub changes: serde_json::Value,
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+const AUDIT_LOG: crate::pagination::Listing = crate::pagination::Listing {
+    name: "audit_log",
+    default_limit: 50,
+    max_limit: 200,
+    sorts: &[("created_at", "created_at")],
+    default_sort: "-created_at",
+    tiebreak: "id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub action: Option<String>,
+    pub actor_id: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// RFC 3339 bounds of `created_at`.
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_bound(value: Option<String>) -> Result<Option<String>, HttpResponse> {
+    match value {
+        Some(v) => DateTime::parse_from_rfc3339(&v)
+            .map(|t| Some(t.with_timezone(&Utc).to_rfc3339()))
+            .map_err(|_| HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid time (expected RFC 3339)" }))),
+        None => Ok(None),
+    }
+}
+
+/// GET /api/server/audit-log?limit=&sort=&cursor=&action=&actor_id=&target_type=&target_id=&since=&until= — Moderation log, newest first (MANAGE_SERVER)
+pub async fn list(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<AuditLogQuery>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+    let page = match AUDIT_LOG.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let action = crate::pagination::filter(&query.action);
+    if action.as_deref().is_some_and(|a| !ACTIONS.iter().any(|known| known.as_str() == a)) {
+        let actions: Vec<&str> = ACTIONS.iter().map(|a| a.as_str()).collect();
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown action", "actions": actions }));
+    }
+    let actor_id = crate::pagination::filter(&query.actor_id);
+    let target_type = crate::pagination::filter(&query.target_type);
+    let target_id = crate::pagination::filter(&query.target_id);
+    let (since, until) = match (parse_bound(crate::pagination::filter(&query.since)), parse_bound(crate::pagination::filter(&query.until))) {
+        (Ok(since), Ok(until)) => (since, until),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let sql = format!(
+        "SELECT id, action, actor_id, actor_username, target_type, target_id, changes, reason, created_at, {} \
+         FROM audit_log WHERE (? IS NULL OR action = ?) AND (? IS NULL OR actor_id = ?) \
+           AND (? IS NULL OR target_type = ?) AND (? IS NULL OR target_id = ?) \
+           AND (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?){}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut q = sqlx::query(&sql);
+    for value in [&action, &actor_id, &target_type, &target_id, &since, &until] {
+        q = q.bind(value).bind(value);
+    }
+    for value in page.keyset_binds() {
+        q = q.bind(value);
+    }
+    match q.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await {
+        Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
+            let entries: Vec<AuditLogEntry> = rows
+                .iter()
+                .map(|row| AuditLogEntry {
+                    id: row.get("id"),
+                    action: row.get("action"),
+                    actor_id: row.get("actor_id"),
+                    actor_username: row.get("actor_username"),
+                    target_type: row.get("target_type"),
+                    target_id: row.get("target_id"),
+                    changes: serde_json::from_str(&row.get::<String, _>("changes")).unwrap_or_default(),
+                    reason: row.get("reason"),
+                    created_at: row.get("created_at"),
+                })
+                .collect();
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(entries), next_cursor)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
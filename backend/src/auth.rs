@@ -7,14 +7,25 @@ use serde::{Deserialize, Serialize};
 use sqlx::{SqlitePool, Row};
 use uuid::Uuid;
 
+use crate::audit::LinkMethod;
+use crate::email::{self, VerificationMode};
+use crate::sessions::{is_revoked, issue_token, touch, DeviceInfo, SessionStore};
+use crate::sudo::Sudo;
+
 // ── Models ──────────────────────────────────────────────
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,       // user id
     pub username: String,
     pub role: String,      // "user" or "admin"
     pub exp: usize,
+    /// Session id (see `sessions`). Empty for tokens issued before sessions existed.
+    #[serde(default)]
+    pub sid: String,
+    /// Unix time until which the token is in sudo mode (see `sudo`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sudo_until: Option<usize>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,9 +34,24 @@ pub struct AuthPayload {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RegisterPayload {
+    pub username: String,
+    pub password: String,
+    /// Ignored unless `EMAIL_VERIFICATION` is on (see `email`).
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Required while joining takes a captcha (see `verification`).
+    #[serde(default)]
+    pub captcha: Option<crate::verification::CaptchaSolution>,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
+    /// Access token lifetime in seconds.
+    pub expires_in: i64,
     pub user_id: String,
     pub username: String,
     pub role: String,
@@ -33,6 +59,9 @@ pub struct AuthResponse {
     pub about: String,
     pub avatar_url: Option<String>,
     pub banner_url: Option<String>,
+    /// An admin requires 2FA on this account and it is not set up yet.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub two_factor_setup_required: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,21 +88,29 @@ pub struct DiscordProxyPayload {
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct DiscordUser {
-    id: String,
-    username: String,
-    global_name: Option<String>,
-    avatar: Option<String>,
+    pub(crate) id: String,
+    pub(crate) username: String,
+    pub(crate) global_name: Option<String>,
+    pub(crate) avatar: Option<String>,
 }
 
 // ── JWT helpers ─────────────────────────────────────────
 
-fn jwt_secret() -> String {
-    std::env::var("JWT_SECRET").expect("JWT_SECRET must be set")
+pub(crate) fn jwt_secret() -> String {
+    let secret = &crate::config::current().secrets.jwt_secret;
+    assert!(!secret.is_empty(), "JWT_SECRET must be set");
+    secret.clone()
 }
 
-pub fn create_token(user_id: &str, username: &str, role: &str) -> String {
+/// Lifetime of a Voxium access JWT. Clients renew it with `POST /api/auth/refresh`.
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+/// A session (and its refresh token) expires after this long without a refresh.
+pub const SESSION_TTL_DAYS: i64 = 30;
+
+/// Sign a JWT for an existing session. Use `sessions::issue_token` to log a user in.
+pub fn create_token(user_id: &str, username: &str, role: &str, session_id: &str) -> String {
     let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::days(7))
+        .checked_add_signed(chrono::Duration::minutes(ACCESS_TOKEN_TTL_MINUTES))
         .expect("valid timestamp")
         .timestamp() as usize;
 
@@ -82,11 +119,16 @@ pub fn create_token(user_id: &str, username: &str, role: &str) -> String {
         username: username.to_string(),
         role: role.to_string(),
         exp: expiration,
+        sid: session_id.to_string(),
+        sudo_until: None,
     };
+    sign_claims(&claims)
+}
 
+pub(crate) fn sign_claims(claims: &Claims) -> String {
     encode(
         &Header::default(),
-        &claims,
+        claims,
         &EncodingKey::from_secret(jwt_secret().as_bytes()),
     )
     .expect("token creation failed")
@@ -102,11 +144,32 @@ pub fn validate_token(token: &str) -> Option<Claims> {
     .ok()
 }
 
-/// Extract claims from the Authorization header.
+/// Extract claims from the Authorization header: a JWT (`Bearer`) or a bot
+/// token (`Bot`, see `bots`). Tokens whose session was revoked are rejected.
 pub fn extract_claims(req: &HttpRequest) -> Option<Claims> {
     let auth_header = req.headers().get("Authorization")?.to_str().ok()?;
+    if let Some(token) = auth_header.strip_prefix(crate::bots::AUTH_SCHEME) {
+        let store = req.app_data::<web::Data<crate::bots::BotTokens>>()?;
+        return crate::bots::claims_for(store, token);
+    }
     let token = auth_header.strip_prefix("Bearer ")?;
-    validate_token(token)
+    let claims = validate_token(token)?;
+
+    // Guests only reach the few routes meant for them (see `guests`).
+    if claims.role == crate::guests::GUEST_ROLE && !crate::guests::allows_route(req) {
+        return None;
+    }
+
+    if !claims.sid.is_empty() {
+        if let Some(store) = req.app_data::<web::Data<SessionStore>>() {
+            if is_revoked(store, &claims.sid) {
+                return None;
+            }
+            touch(store, &claims.sid);
+        }
+    }
+
+    Some(claims)
 }
 
 pub(crate) fn discord_api_base_url() -> String {
@@ -169,8 +232,10 @@ pub(crate) async fn allocate_unique_username(pool: &SqlitePool, preferred: &str)
 // ── Handlers ────────────────────────────────────────────
 
 pub async fn register(
+    req: HttpRequest,
     pool: web::Data<SqlitePool>,
-    body: web::Json<AuthPayload>,
+    broadcaster: web::Data<crate::ws::Broadcaster>,
+    body: web::Json<RegisterPayload>,
 ) -> HttpResponse {
     let username = body.username.trim();
     if username.is_empty() || body.password.len() < 8 {
@@ -192,23 +257,65 @@ pub async fn register(
         }));
     }
 
+    let raw_email = body.email.as_deref().map(str::trim).filter(|e| !e.is_empty());
+    let email = match (email::mode(), raw_email) {
+        (VerificationMode::Off, _) | (VerificationMode::Optional, None) => None,
+        (VerificationMode::Required, None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Email address is required" }));
+        }
+        (_, Some(raw)) => match email::validate_email(raw) {
+            Ok(e) => Some(e),
+            Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        },
+    };
+    if let Some(email) = &email {
+        let taken = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email = ?")
+            .bind(email)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or(0);
+        if taken > 0 {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": "Email already in use" }));
+        }
+    }
+
+    // Checked last, so a refused registration does not use the captcha up.
+    if let Err(response) = crate::verification::require_captcha(pool.get_ref(), body.captcha.as_ref()).await {
+        return response;
+    }
+
     let id = Uuid::new_v4().to_string();
     let password_hash = hash(&body.password, DEFAULT_COST).expect("hash failed");
     let role = "user"; // Default role
 
-    sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES (?, ?, ?, ?)")
+    sqlx::query("INSERT INTO users (id, username, password_hash, role, email) VALUES (?, ?, ?, ?, ?)")
         .bind(&id)
         .bind(username)
         .bind(&password_hash)
         .bind(role)
+        .bind(&email)
         .execute(pool.get_ref())
         .await
         .expect("insert user failed");
+    crate::quickswitch::invalidate_from(&req);
+    crate::verification::record_join(pool.get_ref(), broadcaster.get_ref()).await;
 
-    let token = create_token(&id, username, role);
+    // The account exists either way, a failed send can be retried with resend.
+    if let Some(email) = &email {
+        if let Err(email::SendError::Failed(e)) = email::send_verification(pool.get_ref(), &id, email).await {
+            eprintln!("Verification email to new user {id} failed: {e}");
+        }
+    }
+
+    let tokens = match issue_token(pool.get_ref(), &DeviceInfo::from_request(&req), &id, username, role).await {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    };
 
     HttpResponse::Ok().json(AuthResponse {
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
         user_id: id,
         username: username.to_string(),
         role: role.to_string(),
@@ -216,15 +323,18 @@ pub async fn register(
         about: "".to_string(),
         avatar_url: None,
         banner_url: None,
+        two_factor_setup_required: false,
     })
 }
 
 pub async fn login(
+    req: HttpRequest,
     pool: web::Data<SqlitePool>,
     body: web::Json<AuthPayload>,
+    two_factor: web::Data<crate::totp::TwoFactorChallenges>,
 ) -> HttpResponse {
     // We select all user fields now
-    let row = sqlx::query("SELECT id, password_hash, role, avatar_color, about, avatar_url, banner_url FROM users WHERE username = ?")
+    let row = sqlx::query("SELECT id, password_hash, role, avatar_color, about, avatar_url, banner_url, totp_enabled, totp_required FROM users WHERE username = ?")
         .bind(&body.username)
         .fetch_optional(pool.get_ref())
         .await
@@ -238,11 +348,30 @@ pub async fn login(
         let about: String = row.try_get("about").unwrap_or_default();
         let avatar_url: Option<String> = row.try_get("avatar_url").unwrap_or(None);
         let banner_url: Option<String> = row.try_get("banner_url").unwrap_or(None);
+        let totp_enabled = row.try_get::<i64, _>("totp_enabled").unwrap_or(0) != 0;
+        let totp_required = row.try_get::<i64, _>("totp_required").unwrap_or(0) != 0;
 
         if verify(&body.password, &password_hash).unwrap_or(false) {
-            let token = create_token(&id, &body.username, &role);
+            if let Some(ban) = crate::bans::in_force(pool.get_ref(), &id).await {
+                return ban.refusal();
+            }
+            mark_password_set(pool.get_ref(), &id).await;
+            if totp_enabled {
+                let ticket = crate::totp::start_login(two_factor.get_ref(), &id);
+                return HttpResponse::Ok().json(serde_json::json!({
+                    "two_factor_required": true,
+                    "ticket": ticket,
+                }));
+            }
+
+            let tokens = match issue_token(pool.get_ref(), &DeviceInfo::from_request(&req), &id, &body.username, &role).await {
+                Ok(t) => t,
+                Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+            };
             HttpResponse::Ok().json(AuthResponse {
-                token,
+                token: tokens.token,
+                refresh_token: tokens.refresh_token,
+                expires_in: tokens.expires_in,
                 user_id: id,
                 username: body.username.clone(),
                 role,
@@ -250,6 +379,7 @@ pub async fn login(
                 about,
                 avatar_url,
                 banner_url,
+                two_factor_setup_required: totp_required,
             })
         } else {
              HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Invalid username or password" }))
@@ -259,15 +389,31 @@ pub async fn login(
     }
 }
 
-/// Core logic: validate a Discord user token, create/update local user, return AuthResponse.
-pub(crate) async fn do_discord_token_login(
-    pool: &SqlitePool,
-    discord_token: &str,
-) -> Result<AuthResponse, String> {
-    let client = Client::new();
-    let discord_user_response = client
+/// An admin requires 2FA on the account and it has not been set up yet.
+pub(crate) async fn two_factor_setup_required(pool: &SqlitePool, user_id: &str) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT totp_required != 0 AND totp_enabled = 0 FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+        != 0
+}
+
+/// The account's password turned out to be known to its owner (see `sudo`).
+pub(crate) async fn mark_password_set(pool: &SqlitePool, user_id: &str) {
+    let _ = sqlx::query("UPDATE users SET password_set = 1 WHERE id = ? AND password_set = 0")
+        .bind(user_id)
+        .execute(pool)
+        .await;
+}
+
+/// Fetch `/users/@me` with a full Authorization header value (user token or "Bearer ...").
+pub(crate) async fn fetch_discord_user(authorization: &str) -> Result<DiscordUser, String> {
+    let discord_user_response = Client::new()
         .get(format!("{}/users/@me", discord_api_base_url()))
-        .header("Authorization", discord_token)
+        .header("Authorization", authorization)
         .send()
         .await
         .map_err(|_| "Discord API indisponible".to_string())?;
@@ -280,10 +426,34 @@ pub(crate) async fn do_discord_token_login(
         return Err(format!("Token Discord invalide ou expiré: {details}"));
     }
 
-    let discord_user: DiscordUser = discord_user_response
+    discord_user_response
         .json()
         .await
-        .map_err(|_| "Réponse Discord invalide".to_string())?;
+        .map_err(|_| "Réponse Discord invalide".to_string())
+}
+
+/// Core logic: validate a Discord user token, create/update local user, return AuthResponse.
+pub(crate) async fn do_discord_token_login(
+    pool: &SqlitePool,
+    discord_token: &str,
+    device: &DeviceInfo,
+    via: LinkMethod,
+) -> Result<AuthResponse, String> {
+    do_discord_login_with(pool, discord_token, None, None, device, via).await
+}
+
+/// Shared by user-token and OAuth2 logins. `authorization` is stored verbatim
+/// (encrypted); OAuth logins also pass their refresh token and expiry.
+pub(crate) async fn do_discord_login_with(
+    pool: &SqlitePool,
+    discord_token: &str,
+    refresh_token: Option<&str>,
+    expires_at: Option<i64>,
+    device: &DeviceInfo,
+    via: LinkMethod,
+) -> Result<AuthResponse, String> {
+    let discord_user = fetch_discord_user(discord_token).await?;
+    let encrypted_refresh = refresh_token.map(crate::crypto::encrypt_token);
 
     let discord_avatar = discord_avatar_url(&discord_user);
 
@@ -295,6 +465,7 @@ pub(crate) async fn do_discord_token_login(
     .await
     .ok()
     .flatten();
+    let new_account = existing.is_none();
 
     let (user_id, username, role, avatar_color, about, avatar_url, banner_url) =
         if let Some(row) = existing {
@@ -308,8 +479,10 @@ pub(crate) async fn do_discord_token_login(
             let merged_avatar_url = discord_avatar.clone().or(old_avatar_url);
 
             let encrypted_token = crate::crypto::encrypt_token(discord_token);
-            let _ = sqlx::query("UPDATE users SET discord_access_token = ?, discord_refresh_token = NULL, discord_token_expires_at = NULL, avatar_url = ? WHERE id = ?")
+            let _ = sqlx::query("UPDATE users SET discord_access_token = ?, discord_refresh_token = ?, discord_token_expires_at = ?, avatar_url = ?, discord_token_status = 'linked' WHERE id = ?")
                 .bind(encrypted_token)
+                .bind(&encrypted_refresh)
+                .bind(expires_at)
                 .bind(&merged_avatar_url)
                 .bind(&user_id)
                 .execute(pool)
@@ -328,7 +501,7 @@ pub(crate) async fn do_discord_token_login(
             let password_hash = hash(generated_password, DEFAULT_COST).expect("hash failed");
 
             let encrypted_token = crate::crypto::encrypt_token(discord_token);
-            let insert_result = sqlx::query("INSERT INTO users (id, username, password_hash, role, avatar_color, about, avatar_url, banner_url, discord_id, discord_access_token) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            let insert_result = sqlx::query("INSERT INTO users (id, username, password_hash, password_set, role, avatar_color, about, avatar_url, banner_url, discord_id, discord_access_token, discord_refresh_token, discord_token_expires_at) VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
                 .bind(&user_id)
                 .bind(&username)
                 .bind(&password_hash)
@@ -339,6 +512,8 @@ pub(crate) async fn do_discord_token_login(
                 .bind(&banner_url)
                 .bind(&discord_user.id)
                 .bind(encrypted_token)
+                .bind(&encrypted_refresh)
+                .bind(expires_at)
                 .execute(pool)
                 .await;
 
@@ -357,9 +532,21 @@ pub(crate) async fn do_discord_token_login(
             )
         };
 
-    let token = create_token(&user_id, &username, &role);
+    crate::audit::record(
+        pool,
+        &user_id,
+        via.event(),
+        device,
+        serde_json::json!({ "via": via.as_str(), "discord_id": discord_user.id, "new_account": new_account }),
+    )
+    .await;
+
+    let tokens = issue_token(pool, device, &user_id, &username, &role).await?;
+    let two_factor_setup_required = two_factor_setup_required(pool, &user_id).await;
     Ok(AuthResponse {
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
         user_id,
         username,
         role,
@@ -367,21 +554,27 @@ pub(crate) async fn do_discord_token_login(
         about,
         avatar_url,
         banner_url,
+        two_factor_setup_required,
     })
 }
 
 /// POST /api/auth/discord/token — Login with a Discord user token.
 pub async fn login_discord_token(
+    req: HttpRequest,
     pool: web::Data<SqlitePool>,
     body: web::Json<DiscordUserTokenPayload>,
 ) -> HttpResponse {
+    if !crate::discord_oauth::link_mode().allows_user_token() {
+        return crate::discord_oauth::user_token_disabled();
+    }
+
     let discord_token = body.discord_token.trim().to_string();
     if discord_token.is_empty() {
         return HttpResponse::BadRequest().json(serde_json::json!({
             "error": "discord_token manquant"
         }));
     }
-    match do_discord_token_login(pool.get_ref(), &discord_token).await {
+    match do_discord_token_login(pool.get_ref(), &discord_token, &DeviceInfo::from_request(&req), LinkMethod::Token).await {
         Ok(auth) => HttpResponse::Ok().json(auth),
         Err(msg) => HttpResponse::Unauthorized().json(serde_json::json!({ "error": msg })),
     }
@@ -394,6 +587,10 @@ pub async fn get_discord_me(req: HttpRequest, pool: web::Data<SqlitePool>) -> Ht
         None => return HttpResponse::Unauthorized().finish(),
     };
 
+    if let Err(e) = crate::discord_oauth::refresh_if_expired(pool.get_ref(), &claims.sub).await {
+        return HttpResponse::BadGateway().json(serde_json::json!({ "error": e }));
+    }
+
     let row = sqlx::query(
         "SELECT discord_access_token FROM users WHERE id = ?",
     )
@@ -492,6 +689,10 @@ pub async fn discord_proxy(
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Method not allowed" }));
     }
 
+    if let Err(e) = crate::discord_oauth::refresh_if_expired(pool.get_ref(), &claims.sub).await {
+        return HttpResponse::BadGateway().json(serde_json::json!({ "error": e }));
+    }
+
     let row = sqlx::query(
         "SELECT discord_access_token FROM users WHERE id = ?",
     )
@@ -591,17 +792,65 @@ pub async fn get_me(
     }
 }
 
+/// Send the user's current profile to every client.
+pub(crate) async fn broadcast_profile(pool: &SqlitePool, broadcaster: &crate::ws::Broadcaster, user_id: &str) {
+    let user_row = sqlx::query("SELECT username, role, about, avatar_color, avatar_url, banner_url FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+
+    if let Some(row) = user_row {
+        let username: String = row.get("username");
+        let role: String = row.get("role");
+        let about: String = row.get("about");
+        let avatar_color: i32 = row.try_get("avatar_color").unwrap_or(0);
+        let avatar_url: Option<String> = row.try_get("avatar_url").unwrap_or(None);
+        let banner_url: Option<String> = row.try_get("banner_url").unwrap_or(None);
+        let display = crate::roles::role_display(pool, &role).await;
+
+        let event = serde_json::json!({
+            "type": "join", // handled as upsert by frontend
+            "user_id": user_id,
+            "username": username,
+            "role": role,
+            "role_color": display.role_color,
+            "role_icon_url": display.role_icon_url,
+            "role_hoist": display.role_hoist,
+            "about": about,
+            "avatar_color": avatar_color,
+            "avatar_url": avatar_url,
+            "banner_url": banner_url
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+}
+
 pub async fn update_profile(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
     body: web::Json<UpdateProfile>,
     broadcaster: web::Data<crate::ws::Broadcaster>,
+    session_store: web::Data<SessionStore>,
 ) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
         None => return HttpResponse::Unauthorized().finish(),
     };
 
+    // Images go through `PUT /api/users/@me/avatar|banner`: only the URLs
+    // that minted, or an empty one to clear the field, are taken here.
+    for (field, url) in [("avatar_url", &body.avatar_url), ("banner_url", &body.banner_url)] {
+        let Some(url) = url.as_deref().filter(|url| !url.is_empty()) else {
+            continue;
+        };
+        if !crate::images::is_profile_image(pool.get_ref(), &claims.sub, field, url).await {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("{field} must be one of your uploaded images")
+            }));
+        }
+    }
+
     // ... existing update logic ...
     // Build UPDATE dynamically — avoid Separated API which can produce broken SQL
     let mut set_clauses: Vec<&str> = Vec::new();
@@ -625,7 +874,7 @@ pub async fn update_profile(
             }));
         }
         password_hash_val = Some(hash(password, DEFAULT_COST).expect("hash failed"));
-        set_clauses.push("password_hash = ?");
+        set_clauses.push("password_hash = ?, password_set = 1");
     }
     if body.avatar_url.is_some() {
         set_clauses.push("avatar_url = ?");
@@ -667,35 +916,14 @@ pub async fn update_profile(
 
     match query.execute(pool.get_ref()).await {
         Ok(_) => {
-            // Fetch updated user to broadcast
-            let user_row = sqlx::query("SELECT username, role, about, avatar_color, avatar_url, banner_url FROM users WHERE id = ?")
-                .bind(&claims.sub)
-                .fetch_optional(pool.get_ref())
-                .await
-                .unwrap_or(None);
-
-            if let Some(row) = user_row {
-                 use sqlx::Row;
-                 let username: String = row.get("username");
-                 let role: String = row.get("role");
-                 let about: String = row.get("about");
-                 let avatar_color: i32 = row.try_get("avatar_color").unwrap_or(0);
-                 let avatar_url: Option<String> = row.try_get("avatar_url").unwrap_or(None);
-                 let banner_url: Option<String> = row.try_get("banner_url").unwrap_or(None);
-
-                 let event = serde_json::json!({
-                     "type": "join", // handled as upsert by frontend
-                     "user_id": claims.sub,
-                     "username": username,
-                     "role": role,
-                     "about": about,
-                     "avatar_color": avatar_color,
-                     "avatar_url": avatar_url,
-                     "banner_url": banner_url
-                 });
-                 let _ = broadcaster.send(event.to_string());
+            crate::quickswitch::invalidate_from(&req);
+            // A new password logs out every other device.
+            if password_hash_val.is_some() {
+                crate::sessions::revoke_user_sessions(pool.get_ref(), session_store.get_ref(), &claims.sub, Some(&claims.sid)).await;
             }
 
+            broadcast_profile(pool.get_ref(), broadcaster.get_ref(), &claims.sub).await;
+
             HttpResponse::Ok().json(serde_json::json!({ "status": "updated" }))
         },
         Err(e) => {
@@ -710,16 +938,61 @@ pub struct UpdateRole {
     pub role: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ServerRole {
     pub name: String,
     pub color: String,
+    pub icon_url: Option<String>,
+    /// Members with this role are listed in their own group.
+    pub hoist: bool,
+    /// Anyone may `@name` the role; otherwise only admins can.
+    pub mentionable: bool,
+    /// Members post in slowmode rooms without waiting.
+    pub bypass_slowmode: bool,
+    /// Members can be made priority speaker in voice rooms.
+    pub priority_speaker: bool,
+    /// Permission bits (see `permissions`).
+    pub permissions: u64,
+    pub version: i64,
+}
+
+impl ServerRole {
+    pub(crate) const COLUMNS: &'static str = "name, color, icon_url, hoist, mentionable, bypass_slowmode, priority_speaker, permissions, version";
+
+    pub(crate) fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            name: row.get("name"),
+            color: row.get("color"),
+            icon_url: row.get("icon_url"),
+            hoist: row.get::<i64, _>("hoist") != 0,
+            mentionable: row.get::<i64, _>("mentionable") != 0,
+            bypass_slowmode: row.get::<i64, _>("bypass_slowmode") != 0,
+            priority_speaker: row.get::<i64, _>("priority_speaker") != 0,
+            permissions: row.get::<i64, _>("permissions") as u64,
+            version: row.get("version"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateServerRole {
     pub name: String,
     pub color: Option<String>,
+    pub hoist: Option<bool>,
+    pub mentionable: Option<bool>,
+    pub bypass_slowmode: Option<bool>,
+    pub priority_speaker: Option<bool>,
+    pub permissions: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateServerRole {
+    pub color: Option<String>,
+    pub hoist: Option<bool>,
+    pub mentionable: Option<bool>,
+    pub bypass_slowmode: Option<bool>,
+    pub priority_speaker: Option<bool>,
+    pub permissions: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -727,9 +1000,32 @@ pub struct ServerUser {
     pub id: String,
     pub username: String,
     pub role: String,
+    pub status: &'static str,
+    pub custom_status: Option<String>,
+    #[serde(flatten)]
+    pub role_display: crate::roles::RoleDisplay,
 }
 
-/// GET /api/server/roles — List roles (Admin only)
+const SERVER_USERS: crate::pagination::Listing = crate::pagination::Listing {
+    name: "server_users",
+    default_limit: 100,
+    max_limit: 500,
+    sorts: &[("username", "u.username"), ("created_at", "u.created_at")],
+    default_sort: "username",
+    tiebreak: "u.id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ServerUsersQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    /// Part of the username.
+    pub q: Option<String>,
+    pub role: Option<String>,
+}
+
+/// GET /api/server/roles — List roles (MANAGE_ROLES)
 pub async fn list_server_roles(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
@@ -739,42 +1035,48 @@ pub async fn list_server_roles(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
     }
 
-    let rows = sqlx::query("SELECT name, color FROM roles ORDER BY CASE WHEN name='admin' THEN 0 WHEN name='user' THEN 1 ELSE 2 END, name ASC")
-        .fetch_all(pool.get_ref())
-        .await;
+    let sql = format!(
+        "SELECT {} FROM roles ORDER BY CASE WHEN name='admin' THEN 0 WHEN name='user' THEN 1 ELSE 2 END, name ASC",
+        ServerRole::COLUMNS
+    );
+    let rows = sqlx::query(&sql).fetch_all(pool.get_ref()).await;
 
     match rows {
         Ok(rows) => {
-            let roles: Vec<ServerRole> = rows
-                .into_iter()
-                .map(|row| ServerRole {
-                    name: row.get("name"),
-                    color: row.get("color"),
-                })
-                .collect();
+            let roles: Vec<ServerRole> = rows.iter().map(ServerRole::from_row).collect();
             HttpResponse::Ok().json(roles)
         }
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
-/// POST /api/server/roles — Create role (Admin only)
+/// POST /api/server/roles — Create role (MANAGE_ROLES, granting only permissions the caller has)
 pub async fn create_server_role(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
     body: web::Json<CreateServerRole>,
+    broadcaster: web::Data<crate::ws::Broadcaster>,
 ) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
+    }
+
+    let permissions = body.permissions.unwrap_or(crate::permissions::MEMBER);
+    if permissions & !crate::permissions::ALL != 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown permission bits" }));
+    }
+    let granted = crate::permissions::role_permissions(pool.get_ref(), &claims.role).await;
+    if let Err(response) = crate::permissions::require_grantable(granted, permissions) {
+        return response;
     }
 
     let role_name = body.name.trim().to_lowercase();
@@ -787,6 +1089,9 @@ pub async fn create_server_role(
     {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Role name can only contain a-z, 0-9, _ and -" }));
     }
+    if role_name == crate::guests::GUEST_ROLE {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Role name is reserved" }));
+    }
 
     let color = body
         .color
@@ -795,49 +1100,225 @@ pub async fn create_server_role(
         .trim()
         .to_string();
 
-    if color.len() != 7 || !color.starts_with('#') || !color.chars().skip(1).all(|c| c.is_ascii_hexdigit()) {
+    if !is_valid_role_color(&color) {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid role color (expected #RRGGBB)" }));
     }
 
-    let result = sqlx::query("INSERT INTO roles (name, color) VALUES (?, ?)")
+    let result = sqlx::query("INSERT INTO roles (name, color, hoist, mentionable, bypass_slowmode, priority_speaker, permissions) VALUES (?, ?, ?, ?, ?, ?, ?)")
         .bind(&role_name)
         .bind(&color)
+        .bind(body.hoist.unwrap_or(false))
+        .bind(body.mentionable.unwrap_or(false))
+        .bind(body.bypass_slowmode.unwrap_or(false))
+        .bind(body.priority_speaker.unwrap_or(false))
+        .bind(permissions as i64)
         .execute(pool.get_ref())
         .await;
 
     match result {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "role created" })),
+        Ok(_) => {
+            if let Some(role) = crate::roles::fetch_role(pool.get_ref(), &role_name).await {
+                crate::roles::broadcast_role_event(&broadcaster, "role_created", &role);
+                crate::audit_log::record(
+                    pool.get_ref(),
+                    Some(&claims),
+                    crate::audit_log::Action::RoleCreated,
+                    &role_name,
+                    serde_json::to_value(&role).unwrap_or_default(),
+                    crate::audit_log::reason(&req).as_deref(),
+                )
+                .await;
+            }
+            HttpResponse::Ok().json(serde_json::json!({ "status": "role created" }))
+        }
         Err(_) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Role already exists" })),
     }
 }
 
-/// DELETE /api/server/roles/{name} — Delete role (Admin only)
+fn is_valid_role_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color.chars().skip(1).all(|c| c.is_ascii_hexdigit())
+}
+
+/// PATCH /api/server/roles/{name} — Change a role's color, permissions and its hoist, mentionable, bypass_slowmode or priority_speaker flag (MANAGE_ROLES, honours If-Match)
+pub async fn update_server_role(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<UpdateServerRole>,
+    broadcaster: web::Data<crate::ws::Broadcaster>,
+    access_cache: web::Data<crate::ws::AccessCache>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
+    }
+
+    let expected = match crate::concurrency::expected_version(&req) {
+        Ok(v) => v,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+
+    let role_name = path.into_inner().trim().to_lowercase();
+    let color = body.color.as_deref().map(|c| c.trim().to_string());
+    if color.as_deref().is_some_and(|c| !is_valid_role_color(c)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid role color (expected #RRGGBB)" }));
+    }
+    if let Some(permissions) = body.permissions {
+        if role_name == "admin" {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Admins always have every permission" }));
+        }
+        if permissions & !crate::permissions::ALL != 0 {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown permission bits" }));
+        }
+    }
+
+    let flag = |value: bool| if value { "true" } else { "false" };
+    let conflict = |current: ServerRole| {
+        let requested_color = color.as_deref().unwrap_or(&current.color);
+        let requested_hoist = flag(body.hoist.unwrap_or(current.hoist));
+        let requested_mentionable = flag(body.mentionable.unwrap_or(current.mentionable));
+        let requested_bypass = flag(body.bypass_slowmode.unwrap_or(current.bypass_slowmode));
+        let requested_priority = flag(body.priority_speaker.unwrap_or(current.priority_speaker));
+        let current_permissions = current.permissions.to_string();
+        let requested_permissions = body.permissions.unwrap_or(current.permissions).to_string();
+        let diff = crate::concurrency::diff_fields(&[
+            ("color", Some(&current.color), Some(requested_color)),
+            ("hoist", Some(flag(current.hoist)), Some(requested_hoist)),
+            ("mentionable", Some(flag(current.mentionable)), Some(requested_mentionable)),
+            ("bypass_slowmode", Some(flag(current.bypass_slowmode)), Some(requested_bypass)),
+            ("priority_speaker", Some(flag(current.priority_speaker)), Some(requested_priority)),
+            ("permissions", Some(&current_permissions), Some(&requested_permissions)),
+        ]);
+        crate::concurrency::precondition_failed(current.version, &current, diff)
+    };
+
+    let Some(current) = crate::roles::fetch_role(pool.get_ref(), &role_name).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Role not found" }));
+    };
+    if expected.is_some_and(|v| v != current.version) {
+        return conflict(current);
+    }
+    // Editing a role hands out its permissions, before and after.
+    let granted = crate::permissions::role_permissions(pool.get_ref(), &claims.role).await;
+    if let Err(response) =
+        crate::permissions::require_grantable(granted, current.permissions | body.permissions.unwrap_or(0))
+    {
+        return response;
+    }
+
+    let role = ServerRole {
+        color: color.clone().unwrap_or_else(|| current.color.clone()),
+        hoist: body.hoist.unwrap_or(current.hoist),
+        mentionable: body.mentionable.unwrap_or(current.mentionable),
+        bypass_slowmode: body.bypass_slowmode.unwrap_or(current.bypass_slowmode),
+        priority_speaker: body.priority_speaker.unwrap_or(current.priority_speaker),
+        permissions: body.permissions.unwrap_or(current.permissions),
+        version: current.version + 1,
+        ..current.clone()
+    };
+    let updated = sqlx::query(
+        "UPDATE roles SET color = ?, hoist = ?, mentionable = ?, bypass_slowmode = ?, priority_speaker = ?, permissions = ?, version = version + 1 WHERE name = ? AND version = ?"
+    )
+    .bind(&role.color)
+    .bind(role.hoist)
+    .bind(role.mentionable)
+    .bind(role.bypass_slowmode)
+    .bind(role.priority_speaker)
+    .bind(role.permissions as i64)
+    .bind(&role_name)
+    .bind(current.version)
+    .execute(pool.get_ref())
+    .await
+    .map(|r| r.rows_affected())
+    .unwrap_or(0);
+
+    if updated == 0 {
+        return match crate::roles::fetch_role(pool.get_ref(), &role_name).await {
+            Some(latest) => conflict(latest),
+            None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Role not found" })),
+        };
+    }
+
+    crate::ws::cache_clear_role_permissions(access_cache.get_ref());
+    crate::roles::broadcast_role_event(&broadcaster, "role_updated", &role);
+    let changes = crate::audit_log::diff(&[
+        ("color", current.color.clone().into(), role.color.clone().into()),
+        ("hoist", current.hoist.into(), role.hoist.into()),
+        ("mentionable", current.mentionable.into(), role.mentionable.into()),
+        ("bypass_slowmode", current.bypass_slowmode.into(), role.bypass_slowmode.into()),
+        ("priority_speaker", current.priority_speaker.into(), role.priority_speaker.into()),
+        ("permissions", current.permissions.into(), role.permissions.into()),
+    ]);
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::RoleUpdated,
+        &role_name,
+        changes,
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::Ok()
+        .insert_header(("ETag", crate::concurrency::etag(role.version)))
+        .json(role)
+}
+
+/// DELETE /api/server/roles/{name} — Delete role (MANAGE_ROLES)
 pub async fn delete_server_role(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
     path: web::Path<String>,
+    broadcaster: web::Data<crate::ws::Broadcaster>,
     access_cache: web::Data<crate::ws::AccessCache>,
+    storage: web::Data<crate::files::FileStorage>,
 ) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
     }
 
     let role_name = path.into_inner().trim().to_lowercase();
     if role_name == "admin" || role_name == "user" {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This role is protected" }));
     }
+    if crate::bots::owns_role(pool.get_ref(), &role_name).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This role belongs to a bot" }));
+    }
+    let granted = crate::permissions::role_permissions(pool.get_ref(), &claims.role).await;
+    let removed = crate::permissions::role_permissions(pool.get_ref(), &role_name).await;
+    if let Err(response) = crate::permissions::require_grantable(granted, removed) {
+        return response;
+    }
 
     let _ = sqlx::query("UPDATE users SET role = 'user' WHERE role = ?")
         .bind(&role_name)
         .execute(pool.get_ref())
         .await;
 
+    let _ = sqlx::query("DELETE FROM room_permission_overwrites WHERE target_type = 'role' AND target_id = ?")
+        .bind(&role_name)
+        .execute(pool.get_ref())
+        .await;
+
     crate::ws::cache_clear_user_roles(access_cache.get_ref());
+    crate::ws::cache_clear_room_overwrites(access_cache.get_ref());
+    crate::ws::cache_clear_role_permissions(access_cache.get_ref());
+
+    let icon_url: Option<String> = sqlx::query_scalar("SELECT icon_url FROM roles WHERE name = ?")
+        .bind(&role_name)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten()
+        .flatten();
 
     let result = sqlx::query("DELETE FROM roles WHERE name = ?")
         .bind(&role_name)
@@ -847,6 +1328,19 @@ pub async fn delete_server_role(
     match result {
         Ok(res) => {
             if res.rows_affected() > 0 {
+                crate::uploads::remove(pool.get_ref(), storage.get_ref(), icon_url.as_deref()).await;
+                // Members fall back to `user`; clients update them from this event.
+                let event = serde_json::json!({ "type": "role_deleted", "name": role_name, "fallback": "user" });
+                let _ = broadcaster.send(event.to_string());
+                crate::audit_log::record(
+                    pool.get_ref(),
+                    Some(&claims),
+                    crate::audit_log::Action::RoleDeleted,
+                    &role_name,
+                    serde_json::json!({ "name": role_name, "permissions": removed }),
+                    crate::audit_log::reason(&req).as_deref(),
+                )
+                .await;
                 HttpResponse::Ok().json(serde_json::json!({ "status": "role deleted" }))
             } else {
                 HttpResponse::NotFound().json(serde_json::json!({ "error": "Role not found" }))
@@ -856,41 +1350,71 @@ pub async fn delete_server_role(
     }
 }
 
-/// GET /api/server/users — List users with role (Admin only)
+/// GET /api/server/users?limit=&sort=&cursor=&q=&role= — List users with role (MANAGE_MEMBERS or MANAGE_ROLES)
 pub async fn list_server_users(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
+    presence: web::Data<crate::presence::PresenceTracker>,
+    query: web::Query<ServerUsersQuery>,
 ) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    let granted = crate::permissions::role_permissions(pool.get_ref(), &claims.role).await;
+    if granted & (crate::permissions::MANAGE_MEMBERS | crate::permissions::MANAGE_ROLES) == 0 {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Missing permission",
+            "missing": ["MANAGE_MEMBERS"],
+        }));
     }
-
-    let rows = sqlx::query("SELECT id, username, role FROM users ORDER BY username ASC")
-        .fetch_all(pool.get_ref())
-        .await;
+    let page = match SERVER_USERS.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let search = crate::pagination::filter(&query.q);
+    let role = crate::pagination::filter(&query.role);
+
+    let sql = format!(
+        "SELECT u.id, u.username, u.role, u.presence_status, u.custom_status, {}, \
+         r.color AS role_color, r.icon_url AS role_icon_url, r.hoist AS role_hoist \
+         FROM users u LEFT JOIN roles r ON r.name = u.role \
+         WHERE (? IS NULL OR u.username LIKE '%' || ? || '%') AND (? IS NULL OR u.role = ?){}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut qx = sqlx::query(&sql).bind(&search).bind(&search).bind(&role).bind(&role);
+    for value in page.keyset_binds() {
+        qx = qx.bind(value);
+    }
+    let rows = qx.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await;
 
     match rows {
         Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
             let users: Vec<ServerUser> = rows
                 .into_iter()
-                .map(|row| ServerUser {
-                    id: row.get("id"),
-                    username: row.get("username"),
-                    role: row.get("role"),
+                .map(|row| {
+                    let (status, custom_status) = crate::presence::from_row(presence.get_ref(), &row);
+                    ServerUser {
+                        id: row.get("id"),
+                        username: row.get("username"),
+                        role: row.get("role"),
+                        status,
+                        custom_status,
+                        role_display: crate::roles::RoleDisplay::from_row(&row),
+                    }
                 })
                 .collect();
-            HttpResponse::Ok().json(users)
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(users), next_cursor)
         }
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
-/// PATCH /api/users/{id}/role — Promote/Demote user (Admin only)
+/// PATCH /api/users/{id}/role — Promote/Demote user (MANAGE_ROLES, between roles whose permissions the caller has)
 pub async fn update_user_role(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
@@ -904,8 +1428,8 @@ pub async fn update_user_role(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
     }
 
     let target_id = path.into_inner();
@@ -920,6 +1444,24 @@ pub async fn update_user_role(
     if role_exists <= 0 {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid role" }));
     }
+    // A bot keeps its own role, and nobody else gets it.
+    if crate::bots::owns_role(pool.get_ref(), new_role).await || crate::bots::is_bot(pool.get_ref(), &target_id).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Bot roles are managed with the bot" }));
+    }
+
+    let current_role: String = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+        .bind(&target_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let granted = crate::permissions::role_permissions(pool.get_ref(), &claims.role).await;
+    let handed = crate::permissions::role_permissions(pool.get_ref(), &current_role).await
+        | crate::permissions::role_permissions(pool.get_ref(), new_role).await;
+    if let Err(response) = crate::permissions::require_grantable(granted, handed) {
+        return response;
+    }
 
     let result = sqlx::query("UPDATE users SET role = ? WHERE id = ?")
         .bind(new_role)
@@ -929,6 +1471,16 @@ pub async fn update_user_role(
 
     match result {
         Ok(_) => {
+            crate::quickswitch::invalidate_from(&req);
+            crate::audit_log::record(
+                pool.get_ref(),
+                Some(&claims),
+                crate::audit_log::Action::MemberRoleUpdated,
+                &target_id,
+                crate::audit_log::diff(&[("role", current_role.clone().into(), new_role.clone().into())]),
+                crate::audit_log::reason(&req).as_deref(),
+            )
+            .await;
             // Fetch updated user to broadcast
             let user_row = sqlx::query("SELECT username, role, about, avatar_color, avatar_url, banner_url FROM users WHERE id = ?")
                 .bind(&target_id)
@@ -944,6 +1496,7 @@ pub async fn update_user_role(
                  let avatar_color: i32 = row.try_get("avatar_color").unwrap_or(0);
                  let avatar_url: Option<String> = row.try_get("avatar_url").unwrap_or(None);
                  let banner_url: Option<String> = row.try_get("banner_url").unwrap_or(None);
+                 let display = crate::roles::role_display(pool.get_ref(), &role).await;
 
                   crate::ws::cache_set_user_role(access_cache.get_ref(), &target_id, &role);
 
@@ -952,6 +1505,9 @@ pub async fn update_user_role(
                      "user_id": target_id,
                      "username": username,
                      "role": role,
+                     "role_color": display.role_color,
+                     "role_icon_url": display.role_icon_url,
+                     "role_hoist": display.role_hoist,
                      "about": about,
                      "avatar_color": avatar_color,
                      "avatar_url": avatar_url,
@@ -965,22 +1521,30 @@ pub async fn update_user_role(
     }
 }
 
-/// DELETE /api/users/{id} — Delete a user (Admin only)
+/// DELETE /api/users/{id} — Delete a user (MANAGE_MEMBERS, whose role's permissions the caller has)
 pub async fn delete_user(
     req: HttpRequest,
+    Sudo(claims): Sudo,
     pool: web::Data<SqlitePool>,
     path: web::Path<String>,
 ) -> HttpResponse {
-    let claims = match extract_claims(&req) {
-        Some(c) => c,
-        None => return HttpResponse::Unauthorized().finish(),
-    };
-
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
     }
 
     let target_id = path.into_inner();
+    let target: Option<(String, String)> = sqlx::query_as("SELECT username, role FROM users WHERE id = ?")
+        .bind(&target_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if let Some((_, target_role)) = &target {
+        let granted = crate::permissions::role_permissions(pool.get_ref(), &claims.role).await;
+        let held = crate::permissions::role_permissions(pool.get_ref(), target_role).await;
+        if let Err(response) = crate::permissions::require_grantable(granted, held) {
+            return response;
+        }
+    }
 
     // Delete messages first
     let _ = sqlx::query("DELETE FROM messages WHERE user_id = ?")
@@ -997,6 +1561,17 @@ pub async fn delete_user(
     match result {
         Ok(res) => {
             if res.rows_affected() > 0 {
+                crate::quickswitch::invalidate_from(&req);
+                let (username, role) = target.unwrap_or_default();
+                crate::audit_log::record(
+                    pool.get_ref(),
+                    Some(&claims),
+                    crate::audit_log::Action::MemberRemoved,
+                    &target_id,
+                    serde_json::json!({ "username": username, "role": role }),
+                    crate::audit_log::reason(&req).as_deref(),
+                )
+                .await;
                 HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" }))
             } else {
                 HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }))
@@ -0,0 +1,729 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Automod (keyword and regex content filters)
+// ═══════════════════════════════════════════════════════
+//
+// Rules match the content of new messages (WebSocket messages, forum
+// posts, polls), either against a keyword list or against regexes, and
+// say what to do with a match:
+//   - block: the message is refused;
+//   - flag: it is posted, and queued for moderators to keep or remove;
+//   - timeout: it is refused and its author is timed out for
+//     `timeout_seconds` (see `timeouts`).
+// When several rules match, the strongest action wins. Keywords match
+// whole words, case-insensitively; a `*` at either end also matches inside
+// words (`*spam*`). Regexes use the `regex` crate syntax, which runs in
+// linear time; each rule is compiled into one regex, with a size limit.
+//
+// Members whose role has MANAGE_MESSAGES or ADMINISTRATOR, and the roles a
+// rule exempts, are not checked. Compiled rules are kept in the access
+// cache until a rule changes.
+//
+// A refused message gets an `automod_blocked` event back on the sending
+// WebSocket connection: `{ type, room_id, rule, timed_out_until }`; HTTP
+// endpoints answer 403 with the same fields.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::ws::{AccessCache, Broadcaster};
+
+const MAX_RULES: i64 = 50;
+const MAX_NAME_CHARS: usize = 100;
+const MAX_PATTERNS: usize = 100;
+const MAX_PATTERN_CHARS: usize = 200;
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+const DEFAULT_TIMEOUT_SECONDS: i64 = 600;
+/// Of the matched text kept in a flag.
+const MAX_MATCHED_CHARS: usize = 100;
+
+/// What a rule does with a match, weakest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Action {
+    Flag,
+    Block,
+    Timeout,
+}
+
+impl Action {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "flag" => Some(Action::Flag),
+            "block" => Some(Action::Block),
+            "timeout" => Some(Action::Timeout),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Rule {
+    pub id: String,
+    pub name: String,
+    /// "keyword" or "regex".
+    pub kind: String,
+    pub patterns: Vec<String>,
+    /// "block", "flag" or "timeout".
+    pub action: String,
+    pub timeout_seconds: Option<i64>,
+    pub exempt_roles: Vec<String>,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+const COLUMNS: &str = "id, name, kind, patterns, action, timeout_seconds, exempt_roles, enabled, created_at, updated_at";
+
+impl Rule {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Rule {
+            id: row.get("id"),
+            name: row.get("name"),
+            kind: row.get("kind"),
+            patterns: serde_json::from_str(&row.get::<String, _>("patterns")).unwrap_or_default(),
+            action: row.get("action"),
+            timeout_seconds: row.get("timeout_seconds"),
+            exempt_roles: serde_json::from_str(&row.get::<String, _>("exempt_roles")).unwrap_or_default(),
+            enabled: row.get::<i64, _>("enabled") != 0,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }
+    }
+}
+
+/// An enabled rule, ready to match.
+#[derive(Debug)]
+pub struct CompiledRule {
+    id: String,
+    name: String,
+    action: Action,
+    timeout_seconds: i64,
+    exempt_roles: Vec<String>,
+    regex: Regex,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// One regex matching any of `patterns`.
+fn compile(kind: &str, patterns: &[String]) -> Result<Regex, String> {
+    let alternatives: Vec<String> = match kind {
+        "keyword" => patterns
+            .iter()
+            .map(|keyword| {
+                let inner = keyword.trim_matches('*');
+                let start = if !keyword.starts_with('*') && inner.starts_with(is_word_char) { r"\b" } else { "" };
+                let end = if !keyword.ends_with('*') && inner.ends_with(is_word_char) { r"\b" } else { "" };
+                format!("{start}{}{end}", regex::escape(inner))
+            })
+            .collect(),
+        _ => {
+            for pattern in patterns {
+                RegexBuilder::new(pattern)
+                    .size_limit(REGEX_SIZE_LIMIT)
+                    .build()
+                    .map_err(|e| format!("Invalid regex {pattern:?}: {e}"))?;
+            }
+            patterns.iter().map(|p| format!("(?:{p})")).collect()
+        }
+    };
+    RegexBuilder::new(&alternatives.join("|"))
+        .case_insensitive(kind == "keyword")
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|_| "The patterns of this rule are too large".to_string())
+}
+
+/// The enabled rules, compiled once and kept in the access cache.
+async fn compiled_rules(pool: &SqlitePool, cache: &AccessCache) -> Arc<Vec<CompiledRule>> {
+    if let Some(rules) = &cache.lock().unwrap().automod_rules {
+        return rules.clone();
+    }
+    let rows = sqlx::query(&format!("SELECT {COLUMNS} FROM automod_rules WHERE enabled = 1 ORDER BY created_at"))
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let rules: Vec<CompiledRule> = rows
+        .iter()
+        .map(Rule::from_row)
+        .filter_map(|rule| match compile(&rule.kind, &rule.patterns) {
+            Ok(regex) => Some(CompiledRule {
+                action: Action::parse(&rule.action)?,
+                timeout_seconds: rule.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS),
+                id: rule.id,
+                name: rule.name,
+                exempt_roles: rule.exempt_roles,
+                regex,
+            }),
+            Err(e) => {
+                eprintln!("automod: skipping rule {}: {e}", rule.id);
+                None
+            }
+        })
+        .collect();
+    let rules = Arc::new(rules);
+    cache.lock().unwrap().automod_rules = Some(rules.clone());
+    rules
+}
+
+fn invalidate(cache: &AccessCache) {
+    cache.lock().unwrap().automod_rules = None;
+    crate::bus::publish_cache_change(crate::ws::CacheChange::AutomodRules);
+}
+
+/// A match of a `flag` rule, recorded once the message is posted.
+#[derive(Debug, Clone)]
+pub struct Flag {
+    rule_id: String,
+    rule_name: String,
+    matched: String,
+}
+
+/// A refused post: blocked by `rule`, which may have timed its author out
+/// until `timed_out_until`.
+#[derive(Debug, Clone)]
+pub struct Blocked {
+    pub rule: String,
+    pub timed_out_until: Option<String>,
+}
+
+impl Blocked {
+    /// The structured 403 for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Blocked by automod",
+            "rule": self.rule,
+            "timed_out_until": self.timed_out_until,
+        }))
+    }
+
+    /// The `automod_blocked` event for the WebSocket connection that sent the message.
+    pub fn event(&self, room_id: &str) -> String {
+        serde_json::json!({
+            "type": "automod_blocked",
+            "room_id": room_id,
+            "rule": self.rule,
+            "timed_out_until": self.timed_out_until,
+        })
+        .to_string()
+    }
+}
+
+/// Check `content` by `user_id` (with `role`) before it is posted in
+/// `room_id`. Returns the flags to record once it is, or why it is refused.
+pub(crate) async fn check(
+    pool: &SqlitePool,
+    cache: &AccessCache,
+    broadcaster: &Broadcaster,
+    room_id: &str,
+    user_id: &str,
+    role: &str,
+    content: &str,
+) -> Result<Vec<Flag>, Blocked> {
+    let rules = compiled_rules(pool, cache).await;
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    let permissions = crate::ws::get_role_permissions_cached(pool, cache, role).await;
+    if permissions & (crate::permissions::ADMINISTRATOR | crate::permissions::MANAGE_MESSAGES) != 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut flags = Vec::new();
+    let mut strongest: Option<&CompiledRule> = None;
+    for rule in rules.iter().filter(|r| !r.exempt_roles.iter().any(|e| e == role)) {
+        let Some(found) = rule.regex.find(content) else {
+            continue;
+        };
+        if rule.action == Action::Flag {
+            flags.push(Flag {
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                matched: found.as_str().chars().take(MAX_MATCHED_CHARS).collect(),
+            });
+        } else if strongest.is_none_or(|s| (rule.action, rule.timeout_seconds) > (s.action, s.timeout_seconds)) {
+            strongest = Some(rule);
+        }
+    }
+    let Some(rule) = strongest else {
+        return Ok(flags);
+    };
+    if rule.action == Action::Block {
+        return Err(Blocked { rule: rule.name.clone(), timed_out_until: None });
+    }
+
+    let reason = format!("Automod rule: {}", rule.name);
+    let timeout = crate::timeouts::apply(pool, broadcaster, user_id, rule.timeout_seconds, Some(&reason), None, Some(room_id)).await;
+    Err(Blocked { rule: rule.name.clone(), timed_out_until: timeout.ok().map(|t| t.expires_at) })
+}
+
+/// Queue posted message `message_id` for review, once per flagging rule.
+pub(crate) async fn record_flags(pool: &SqlitePool, message_id: &str, room_id: &str, user_id: &str, flags: &[Flag]) {
+    let now = Utc::now().to_rfc3339();
+    for flag in flags {
+        let _ = sqlx::query(
+            "INSERT INTO automod_flags (id, message_id, room_id, user_id, rule_id, rule_name, matched, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(message_id)
+        .bind(room_id)
+        .bind(user_id)
+        .bind(&flag.rule_id)
+        .bind(&flag.rule_name)
+        .bind(&flag.matched)
+        .bind(&now)
+        .execute(pool)
+        .await;
+    }
+}
+
+// ── HTTP Handlers: rules ────────────────────────────────
+
+fn bad_request(error: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+}
+
+async fn require_manage_server(req: &HttpRequest, pool: &SqlitePool) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require_server(pool, &claims, crate::permissions::MANAGE_SERVER).await?;
+    Ok(claims)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleInput {
+    pub name: String,
+    pub kind: String,
+    pub patterns: Vec<String>,
+    pub action: String,
+    pub timeout_seconds: Option<i64>,
+    #[serde(default)]
+    pub exempt_roles: Vec<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Check and normalize a rule as it will be stored.
+async fn validate(pool: &SqlitePool, mut input: RuleInput) -> Result<RuleInput, HttpResponse> {
+    input.name = input.name.trim().to_string();
+    if input.name.is_empty() || input.name.chars().count() > MAX_NAME_CHARS {
+        return Err(bad_request("Name must be 1 to 100 characters"));
+    }
+    if input.kind != "keyword" && input.kind != "regex" {
+        return Err(bad_request("kind must be \"keyword\" or \"regex\""));
+    }
+    let Some(action) = Action::parse(&input.action) else {
+        return Err(bad_request("action must be \"block\", \"flag\" or \"timeout\""));
+    };
+    input.timeout_seconds = match (action, input.timeout_seconds) {
+        (Action::Timeout, seconds) => {
+            let seconds = seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS);
+            if !(1..=crate::timeouts::MAX_SECONDS).contains(&seconds) {
+                return Err(bad_request(&format!("timeout_seconds must be between 1 and {}", crate::timeouts::MAX_SECONDS)));
+            }
+            Some(seconds)
+        }
+        _ => None,
+    };
+
+    let mut patterns: Vec<String> = input
+        .patterns
+        .iter()
+        .map(|p| if input.kind == "keyword" { p.trim().to_lowercase() } else { p.trim().to_string() })
+        .filter(|p| !p.trim_matches('*').is_empty())
+        .collect();
+    patterns.sort();
+    patterns.dedup();
+    if patterns.is_empty() || patterns.len() > MAX_PATTERNS {
+        return Err(bad_request(&format!("A rule needs 1 to {MAX_PATTERNS} patterns")));
+    }
+    if patterns.iter().any(|p| p.chars().count() > MAX_PATTERN_CHARS) {
+        return Err(bad_request(&format!("Patterns must be at most {MAX_PATTERN_CHARS} characters")));
+    }
+    compile(&input.kind, &patterns).map_err(|e| bad_request(&e))?;
+    input.patterns = patterns;
+
+    input.exempt_roles.sort();
+    input.exempt_roles.dedup();
+    for role in &input.exempt_roles {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM roles WHERE name = ?)")
+            .bind(role)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(false);
+        if !exists {
+            return Err(bad_request(&format!("Unknown role: {role}")));
+        }
+    }
+    Ok(input)
+}
+
+async fn load_rule(pool: &SqlitePool, id: &str) -> Option<Rule> {
+    sqlx::query(&format!("SELECT {COLUMNS} FROM automod_rules WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Rule::from_row(&row))
+}
+
+fn rule_json(rule: &Rule) -> serde_json::Value {
+    serde_json::to_value(rule).unwrap_or_default()
+}
+
+/// GET /api/server/automod/rules — Every rule, oldest first (MANAGE_SERVER)
+pub async fn list_rules(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = require_manage_server(&req, pool.get_ref()).await {
+        return response;
+    }
+    match sqlx::query(&format!("SELECT {COLUMNS} FROM automod_rules ORDER BY created_at"))
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(rows) => HttpResponse::Ok().json(rows.iter().map(Rule::from_row).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/server/automod/rules — Add a rule (MANAGE_SERVER)
+/// Body: { name, kind, patterns, action, timeout_seconds?, exempt_roles?, enabled? }
+pub async fn create_rule(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    access_cache: web::Data<AccessCache>,
+    body: web::Json<RuleInput>,
+) -> HttpResponse {
+    let claims = match require_manage_server(&req, pool.get_ref()).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM automod_rules")
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if count >= MAX_RULES {
+        return bad_request(&format!("A server can have at most {MAX_RULES} rules"));
+    }
+    let input = match validate(pool.get_ref(), body.into_inner()).await {
+        Ok(input) => input,
+        Err(response) => return response,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        "INSERT INTO automod_rules (id, name, kind, patterns, action, timeout_seconds, exempt_roles, enabled, created_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&input.name)
+    .bind(&input.kind)
+    .bind(serde_json::to_string(&input.patterns).unwrap_or_default())
+    .bind(&input.action)
+    .bind(input.timeout_seconds)
+    .bind(serde_json::to_string(&input.exempt_roles).unwrap_or_default())
+    .bind(input.enabled.unwrap_or(true) as i64)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool.get_ref())
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    invalidate(access_cache.get_ref());
+
+    let Some(rule) = load_rule(pool.get_ref(), &id).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::AutomodRuleCreated,
+        &id,
+        rule_json(&rule),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::Created().json(rule)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RulePatch {
+    pub name: Option<String>,
+    pub patterns: Option<Vec<String>>,
+    pub action: Option<String>,
+    pub timeout_seconds: Option<i64>,
+    pub exempt_roles: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+/// PATCH /api/server/automod/rules/{id} — Edit a rule; its kind cannot change (MANAGE_SERVER)
+pub async fn update_rule(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    access_cache: web::Data<AccessCache>,
+    path: web::Path<String>,
+    body: web::Json<RulePatch>,
+) -> HttpResponse {
+    let claims = match require_manage_server(&req, pool.get_ref()).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let id = path.into_inner();
+    let Some(old) = load_rule(pool.get_ref(), &id).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Rule not found" }));
+    };
+    let patch = body.into_inner();
+    let input = RuleInput {
+        name: patch.name.unwrap_or_else(|| old.name.clone()),
+        kind: old.kind.clone(),
+        patterns: patch.patterns.unwrap_or_else(|| old.patterns.clone()),
+        timeout_seconds: patch.timeout_seconds.or(old.timeout_seconds),
+        action: patch.action.unwrap_or_else(|| old.action.clone()),
+        exempt_roles: patch.exempt_roles.unwrap_or_else(|| old.exempt_roles.clone()),
+        enabled: Some(patch.enabled.unwrap_or(old.enabled)),
+    };
+    let input = match validate(pool.get_ref(), input).await {
+        Ok(input) => input,
+        Err(response) => return response,
+    };
+
+    let updated = sqlx::query(
+        "UPDATE automod_rules SET name = ?, patterns = ?, action = ?, timeout_seconds = ?, exempt_roles = ?, enabled = ?, updated_at = ? \
+         WHERE id = ?"
+    )
+    .bind(&input.name)
+    .bind(serde_json::to_string(&input.patterns).unwrap_or_default())
+    .bind(&input.action)
+    .bind(input.timeout_seconds)
+    .bind(serde_json::to_string(&input.exempt_roles).unwrap_or_default())
+    .bind(input.enabled.unwrap_or(true) as i64)
+    .bind(Utc::now().to_rfc3339())
+    .bind(&id)
+    .execute(pool.get_ref())
+    .await;
+    if updated.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    invalidate(access_cache.get_ref());
+
+    let Some(rule) = load_rule(pool.get_ref(), &id).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Rule not found" }));
+    };
+    let changes = crate::audit_log::diff(&[
+        ("name", old.name.clone().into(), rule.name.clone().into()),
+        ("patterns", old.patterns.clone().into(), rule.patterns.clone().into()),
+        ("action", old.action.clone().into(), rule.action.clone().into()),
+        ("timeout_seconds", old.timeout_seconds.into(), rule.timeout_seconds.into()),
+        ("exempt_roles", old.exempt_roles.clone().into(), rule.exempt_roles.clone().into()),
+        ("enabled", old.enabled.into(), rule.enabled.into()),
+    ]);
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::AutomodRuleUpdated,
+        &id,
+        changes,
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::Ok().json(rule)
+}
+
+/// DELETE /api/server/automod/rules/{id} — Remove a rule; its flags stay queued (MANAGE_SERVER)
+pub async fn delete_rule(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    access_cache: web::Data<AccessCache>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match require_manage_server(&req, pool.get_ref()).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let id = path.into_inner();
+    let Some(rule) = load_rule(pool.get_ref(), &id).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Rule not found" }));
+    };
+    if sqlx::query("DELETE FROM automod_rules WHERE id = ?").bind(&id).execute(pool.get_ref()).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    invalidate(access_cache.get_ref());
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::AutomodRuleDeleted,
+        &id,
+        rule_json(&rule),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::NoContent().finish()
+}
+
+// ── HTTP Handlers: moderation queue ─────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct QueuedFlag {
+    pub id: String,
+    pub message_id: String,
+    pub room_id: String,
+    pub user_id: String,
+    pub username: String,
+    /// The message as it is now; None once deleted.
+    pub content: Option<String>,
+    pub rule_id: Option<String>,
+    pub rule_name: String,
+    pub matched: String,
+    pub created_at: String,
+    /// "pending", "kept" or "removed".
+    pub status: String,
+    pub reviewed_by_username: Option<String>,
+    pub reviewed_at: Option<String>,
+}
+
+const QUEUE: crate::pagination::Listing = crate::pagination::Listing {
+    name: "automod_queue",
+    default_limit: 50,
+    max_limit: 200,
+    sorts: &[("created_at", "f.created_at")],
+    default_sort: "created_at",
+    tiebreak: "f.id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct QueueQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    /// "pending" by default, or "kept", "removed", "all".
+    pub status: Option<String>,
+    pub room_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+/// GET /api/server/automod/queue?limit=&sort=&cursor=&status=&room_id=&user_id= — Flagged messages, oldest first (MANAGE_MESSAGES)
+pub async fn list_queue(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<QueueQuery>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+    let page = match QUEUE.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let status_filter = crate::pagination::filter(&query.status);
+    let status = match status_filter.as_deref() {
+        None | Some("pending") => Some("pending"),
+        Some("all") => None,
+        Some(s @ ("kept" | "removed")) => Some(s),
+        Some(_) => return bad_request("status must be pending, kept, removed or all"),
+    };
+    let room_id = crate::pagination::filter(&query.room_id);
+    let user_id = crate::pagination::filter(&query.user_id);
+
+    let sql = format!(
+        "SELECT f.id, f.message_id, f.room_id, f.user_id, m.username, CASE WHEN m.deleted_at IS NULL THEN m.content END AS content, \
+                f.rule_id, f.rule_name, f.matched, f.created_at, f.status, f.reviewed_by_username, f.reviewed_at, {} \
+         FROM automod_flags f JOIN messages m ON m.id = f.message_id \
+         WHERE (? IS NULL OR f.status = ?) AND (? IS NULL OR f.room_id = ?) AND (? IS NULL OR f.user_id = ?){}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut q = sqlx::query(&sql).bind(status).bind(status);
+    for value in [&room_id, &user_id] {
+        q = q.bind(value).bind(value);
+    }
+    for value in page.keyset_binds() {
+        q = q.bind(value);
+    }
+    match q.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await {
+        Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
+            let flags: Vec<QueuedFlag> = rows
+                .iter()
+                .map(|row| QueuedFlag {
+                    id: row.get("id"),
+                    message_id: row.get("message_id"),
+                    room_id: row.get("room_id"),
+                    user_id: row.get("user_id"),
+                    username: row.get("username"),
+                    content: row.get("content"),
+                    rule_id: row.get("rule_id"),
+                    rule_name: row.get("rule_name"),
+                    matched: row.get("matched"),
+                    created_at: row.get("created_at"),
+                    status: row.get("status"),
+                    reviewed_by_username: row.get("reviewed_by_username"),
+                    reviewed_at: row.get("reviewed_at"),
+                })
+                .collect();
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(flags), next_cursor)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewInput {
+    /// "keep" or "remove" (deletes the message).
+    pub action: String,
+}
+
+/// POST /api/server/automod/queue/{id} — Keep or remove a flagged message, settling all its flags (MANAGE_MESSAGES)
+pub async fn review(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<ReviewInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+    let status = match body.action.as_str() {
+        "keep" => "kept",
+        "remove" => "removed",
+        _ => return bad_request("action must be \"keep\" or \"remove\""),
+    };
+    let flag_id = path.into_inner();
+    let message_id: Option<String> = sqlx::query_scalar("SELECT message_id FROM automod_flags WHERE id = ? AND status = 'pending'")
+        .bind(&flag_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let Some(message_id) = message_id else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No pending flag with this id" }));
+    };
+
+    if status == "removed" && crate::messages::tombstone(pool.get_ref(), broadcaster.get_ref(), &message_id, &claims.sub).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let settled = sqlx::query(
+        "UPDATE automod_flags SET status = ?, reviewed_by_username = ?, reviewed_at = ? WHERE message_id = ? AND status = 'pending'"
+    )
+    .bind(status)
+    .bind(&claims.username)
+    .bind(Utc::now().to_rfc3339())
+    .bind(&message_id)
+    .execute(pool.get_ref())
+    .await;
+    match settled {
+        Ok(res) => HttpResponse::Ok().json(serde_json::json!({ "status": status, "flags": res.rows_affected() })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
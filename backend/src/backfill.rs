@@ -0,0 +1,147 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Progressive history backfill
+// ═══════════════════════════════════════════════════════
+//
+// Opening a room of a large server should not pull its whole history.
+// `GET /api/rooms/{id}/backfill` serves it in tiers:
+//   - recent  no cursor: the latest messages, answered straight away
+//   - older   `before=<message id>`: the page before that message. These
+//             are meant for background prefetching, so they are rate
+//             limited per user (RATE_LIMIT_BACKFILL, see `ratelimit`) and
+//             only a few run at once across the server; the others queue
+//             behind them instead of competing with regular requests.
+//
+// Every response carries a `hint` telling the client how far to prefetch
+// on its own (`default_depth` messages) and how to pace it. Anything older
+// is left to explicit scrolling through `/messages`.
+//
+// Config (env):
+//   BACKFILL_RECENT_LIMIT   messages in the recent tier (default 50)
+//   BACKFILL_PAGE_SIZE      messages per older page (default 100, at most 200)
+//   BACKFILL_DEFAULT_DEPTH  messages clients prefetch per room (default 500,
+//                           0 = recent tier only)
+//   BACKFILL_CONCURRENCY    older pages served at once (default 4)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::auth::extract_claims;
+use crate::messages::{self, BlockedFilter, Direction, HistoryPage};
+use crate::ratelimit::{RateLimiter, RouteGroup};
+
+const DEFAULT_RECENT_LIMIT: i64 = 50;
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 200;
+const DEFAULT_DEPTH: i64 = 500;
+const DEFAULT_CONCURRENCY: usize = 4;
+/// An older page still queued after this is refused with 503.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok()).unwrap_or(default)
+}
+
+/// Bounds how many older pages are read at the same time.
+pub type BackfillGate = Arc<Semaphore>;
+
+pub fn create_backfill_gate() -> BackfillGate {
+    let permits = env_number("BACKFILL_CONCURRENCY", DEFAULT_CONCURRENCY).max(1);
+    Arc::new(Semaphore::new(permits))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillQuery {
+    pub before: Option<String>,
+    #[serde(default)]
+    pub blocked: BlockedFilter,
+}
+
+/// How a client should page through history by itself.
+#[derive(Debug, Serialize)]
+pub struct BackfillHint {
+    pub recent_limit: i64,
+    pub page_size: i64,
+    /// Messages to prefetch before waiting for the user to scroll.
+    pub default_depth: i64,
+    /// Spacing between older pages that stays under the rate limit.
+    pub min_interval_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackfillPage {
+    /// `recent` or `older`.
+    pub tier: &'static str,
+    #[serde(flatten)]
+    pub page: HistoryPage,
+    pub hint: BackfillHint,
+}
+
+fn hint(limiter: &RateLimiter) -> BackfillHint {
+    let min_interval = limiter.lock().unwrap().min_interval(RouteGroup::Backfill);
+    BackfillHint {
+        recent_limit: env_number("BACKFILL_RECENT_LIMIT", DEFAULT_RECENT_LIMIT).clamp(1, messages::MAX_HISTORY_LIMIT),
+        page_size: env_number("BACKFILL_PAGE_SIZE", DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        default_depth: env_number("BACKFILL_DEFAULT_DEPTH", DEFAULT_DEPTH).max(0),
+        min_interval_ms: min_interval.as_millis() as u64,
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/rooms/{id}/backfill?before= — Recent messages, or an older page at lower priority
+pub async fn backfill(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    limiter: web::Data<RateLimiter>,
+    gate: web::Data<BackfillGate>,
+    path: web::Path<String>,
+    query: web::Query<BackfillQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+
+    let hint = hint(limiter.get_ref());
+    let with_deleted = messages::sees_deleted(pool.get_ref(), &claims.role).await;
+
+    let (tier, mut page) = match &query.before {
+        None => {
+            let (messages, more) =
+                messages::fetch_page(pool.get_ref(), &room_id, with_deleted, None, Direction::Older, hint.recent_limit).await;
+            ("recent", HistoryPage { messages, has_more_before: more, has_more_after: false })
+        }
+        Some(id) => {
+            let Some(at) = messages::cursor_position(pool.get_ref(), &room_id, id).await else {
+                return messages::unknown_cursor();
+            };
+            let _permit = match tokio::time::timeout(QUEUE_TIMEOUT, gate.acquire()).await {
+                Ok(Ok(permit)) => permit,
+                _ => {
+                    return HttpResponse::ServiceUnavailable()
+                        .insert_header(("Retry-After", "1"))
+                        .json(serde_json::json!({ "error": "History backfill is busy, retry shortly", "retry_after": 1 }));
+                }
+            };
+            let (messages, more) =
+                messages::fetch_page(pool.get_ref(), &room_id, with_deleted, Some((&at, id)), Direction::Older, hint.page_size).await;
+            ("older", HistoryPage { messages, has_more_before: more, has_more_after: true })
+        }
+    };
+
+    messages::apply_block_filter(pool.get_ref(), &claims.sub, query.blocked, &mut page.messages).await;
+    messages::enrich_messages_with_reactions(pool.get_ref(), &mut page.messages).await;
+    crate::emojis::attach_custom_emojis(pool.get_ref(), &mut page.messages).await;
+    crate::files::attach_to_messages(pool.get_ref(), &mut page.messages).await;
+    crate::polls::attach_to_messages(pool.get_ref(), Some(&claims.sub), &mut page.messages).await;
+
+    HttpResponse::Ok().json(BackfillPage { tier, page, hint })
+}
@@ -0,0 +1,640 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Kicks and bans (with appeals)
+// ═══════════════════════════════════════════════════════
+//
+// A kick signs a member out everywhere: every session is revoked and the
+// member gets a `kicked` event, telling the client to disconnect. They can
+// sign in again right away.
+//
+// A ban does the same and keeps them out:
+//   - while a ban is in force, signing in fails with 403 (password, Discord,
+//     passkey, two-factor, guest conversion all go through `issue_token`)
+//     and messages sent on a connection still open are refused;
+//   - `duration_hours` makes it temporary: a background task lifts it once
+//     `expires_at` passes, every `UNBAN_INTERVAL`;
+//   - `delete_message_seconds` removes the member's messages of that window
+//     (at most 7 days), like a purge (`messages_purged` with `since`).
+//
+// A refused password login answers with the ban and, when it can still be
+// appealed, an `appeal_token` (signed, valid `APPEAL_TOKEN_MINUTES`). The
+// member sends their appeal with it to `POST /api/bans/appeal`, once per
+// ban; staff accept it (lifting the ban) or deny it. Kicks, bans, unbans
+// and reviewed appeals are written to the server audit log.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::jobs::{Backlog, JobRegistry};
+use crate::sessions::SessionStore;
+use crate::ws::Broadcaster;
+
+const UNBAN_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_DURATION_HOURS: i64 = 365 * 24;
+const MAX_DELETE_MESSAGE_SECONDS: i64 = 7 * 24 * 3600;
+const MAX_REASON_CHARS: usize = 512;
+const MAX_APPEAL_CHARS: usize = 2000;
+const APPEAL_TOKEN_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ban {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub reason: Option<String>,
+    pub banned_by_username: String,
+    pub created_at: String,
+    /// None for a permanent ban.
+    pub expires_at: Option<String>,
+    pub purged_messages: i64,
+    pub lifted_at: Option<String>,
+    /// None when it expired.
+    pub lifted_by_username: Option<String>,
+    pub appealable: bool,
+    pub appeal: Option<Appeal>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Appeal {
+    pub message: String,
+    pub submitted_at: String,
+    /// "pending", "accepted" or "denied".
+    pub status: String,
+    pub reviewed_by_username: Option<String>,
+    pub reviewed_at: Option<String>,
+    pub note: Option<String>,
+}
+
+const COLUMNS: &str = "id, user_id, username, reason, banned_by_username, created_at, expires_at, purged_messages, \
+                       lifted_at, lifted_by_username, appealable, appeal_message, appeal_submitted_at, appeal_status, \
+                       appeal_reviewed_by_username, appeal_reviewed_at, appeal_note";
+
+/// In force: not lifted, and not expired yet (the unbanner may lag behind).
+const IN_FORCE: &str = "lifted_at IS NULL AND (expires_at IS NULL OR expires_at > ?)";
+
+impl Ban {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let appeal = row.get::<Option<String>, _>("appeal_message").map(|message| Appeal {
+            message,
+            submitted_at: row.get::<Option<String>, _>("appeal_submitted_at").unwrap_or_default(),
+            status: row.get::<Option<String>, _>("appeal_status").unwrap_or_default(),
+            reviewed_by_username: row.get("appeal_reviewed_by_username"),
+            reviewed_at: row.get("appeal_reviewed_at"),
+            note: row.get("appeal_note"),
+        });
+        Ban {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            username: row.get("username"),
+            reason: row.get("reason"),
+            banned_by_username: row.get("banned_by_username"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+            purged_messages: row.get("purged_messages"),
+            lifted_at: row.get("lifted_at"),
+            lifted_by_username: row.get("lifted_by_username"),
+            appealable: row.get::<i64, _>("appealable") != 0,
+            appeal,
+        }
+    }
+
+    fn can_appeal(&self) -> bool {
+        self.appealable && self.appeal.is_none()
+    }
+
+    /// 403 for a sign-in refused by this ban.
+    pub(crate) fn refusal(&self) -> HttpResponse {
+        let mut body = serde_json::json!({
+            "error": "This account is banned",
+            "ban": {
+                "id": self.id,
+                "reason": self.reason,
+                "created_at": self.created_at,
+                "expires_at": self.expires_at,
+                "appealable": self.appealable,
+                "appeal_status": self.appeal.as_ref().map(|a| a.status.as_str()),
+            },
+        });
+        if self.can_appeal() {
+            body["appeal_token"] = serde_json::json!(appeal_token(&self.id));
+        }
+        HttpResponse::Forbidden().json(body)
+    }
+}
+
+/// The ban in force against `user_id`, if any.
+pub(crate) async fn in_force(pool: &SqlitePool, user_id: &str) -> Option<Ban> {
+    sqlx::query(&format!("SELECT {COLUMNS} FROM bans WHERE user_id = ? AND {IN_FORCE}"))
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Ban::from_row(&row))
+}
+
+pub(crate) async fn is_banned(pool: &SqlitePool, user_id: &str) -> bool {
+    sqlx::query_scalar(&format!("SELECT EXISTS(SELECT 1 FROM bans WHERE user_id = ? AND {IN_FORCE})"))
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false)
+}
+
+fn appeal_signature(payload: &str) -> Vec<u8> {
+    crate::crypto::sign(&format!("ban-appeal:{payload}"))
+}
+
+/// `<ban id>.<expiry, unix seconds>.<signature>`
+fn appeal_token(ban_id: &str) -> String {
+    let payload = format!("{ban_id}.{}", (Utc::now() + chrono::Duration::minutes(APPEAL_TOKEN_MINUTES)).timestamp());
+    let signature = general_purpose::URL_SAFE_NO_PAD.encode(appeal_signature(&payload));
+    format!("{payload}.{signature}")
+}
+
+/// The ban id of a valid, unexpired appeal token.
+fn verify_appeal_token(token: &str) -> Option<String> {
+    let (payload, signature) = token.trim().rsplit_once('.')?;
+    let signature = general_purpose::URL_SAFE_NO_PAD.decode(signature).ok()?;
+    crate::crypto::signatures_match(&appeal_signature(payload), &signature).then_some(())?;
+    let (ban_id, expires) = payload.split_once('.')?;
+    (expires.parse::<i64>().ok()? > Utc::now().timestamp()).then(|| ban_id.to_string())
+}
+
+/// Lift ban `id` if it still is in force, by `lifted_by` (None when it ran
+/// out). Returns whether it was.
+async fn lift(pool: &SqlitePool, id: &str, lifted_by: Option<&Claims>, reason: Option<&str>) -> bool {
+    let lifted: Option<String> = sqlx::query_scalar(
+        "UPDATE bans SET lifted_at = ?, lifted_by_username = ? WHERE id = ? AND lifted_at IS NULL RETURNING user_id"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(lifted_by.map(|c| c.username.as_str()))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+    let Some(user_id) = lifted else {
+        return false;
+    };
+    crate::audit_log::record(
+        pool,
+        lifted_by,
+        crate::audit_log::Action::MemberUnbanned,
+        &user_id,
+        serde_json::json!({ "ban_id": id, "expired": lifted_by.is_none() }),
+        reason,
+    )
+    .await;
+    true
+}
+
+async fn lift_expired(pool: &SqlitePool) -> Result<u64, String> {
+    let expired: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM bans WHERE lifted_at IS NULL AND expires_at IS NOT NULL AND expires_at <= ?"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let mut lifted = 0;
+    for id in expired {
+        if lift(pool, &id, None, None).await {
+            lifted += 1;
+        }
+    }
+    Ok(lifted)
+}
+
+async fn unban_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(expires_at) AS oldest_due_at FROM bans \
+         WHERE lifted_at IS NULL AND expires_at IS NOT NULL AND expires_at <= ?"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_unbanner(pool: SqlitePool, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::BANS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(UNBAN_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::BANS, None, lift_expired(&pool), unban_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+fn bad_request(error: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+}
+
+/// The trimmed reason of a request body, or the `X-Audit-Log-Reason` header.
+#[allow(clippy::result_large_err)]
+fn reason(body: Option<&str>, req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
+    match body.map(str::trim).filter(|r| !r.is_empty()) {
+        Some(r) if r.chars().count() > MAX_REASON_CHARS => Err(bad_request("Reason must be at most 512 chars")),
+        Some(r) => Ok(Some(r.to_string())),
+        None => Ok(crate::audit_log::reason(req)),
+    }
+}
+
+/// Check that `claims` may kick, ban or time out `target_id`, returns its
+/// username.
+pub(crate) async fn moderatable(pool: &SqlitePool, claims: &Claims, target_id: &str) -> Result<String, HttpResponse> {
+    crate::permissions::require_server(pool, claims, crate::permissions::MANAGE_MEMBERS).await?;
+    if target_id == claims.sub {
+        return Err(bad_request("You cannot do this to yourself"));
+    }
+    let target: Option<(String, String)> = sqlx::query_as("SELECT username, role FROM users WHERE id = ?")
+        .bind(target_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    let Some((username, role)) = target else {
+        return Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" })));
+    };
+    let granted = crate::permissions::role_permissions(pool, &claims.role).await;
+    let held = crate::permissions::role_permissions(pool, &role).await;
+    crate::permissions::require_grantable(granted, held)?;
+    Ok(username)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KickRequest {
+    pub reason: Option<String>,
+}
+
+/// POST /api/users/{id}/kick — Sign a member out everywhere (MANAGE_MEMBERS)
+pub async fn kick(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<KickRequest>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let target_id = path.into_inner();
+    let username = match moderatable(pool.get_ref(), &claims, &target_id).await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let reason = match reason(body.reason.as_deref(), &req) {
+        Ok(reason) => reason,
+        Err(response) => return response,
+    };
+
+    let revoked = crate::sessions::revoke_user_sessions(pool.get_ref(), store.get_ref(), &target_id, None).await;
+    let event = serde_json::json!({ "type": "kicked", "recipient_id": target_id, "reason": reason });
+    let _ = broadcaster.send(event.to_string());
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::MemberKicked,
+        &target_id,
+        serde_json::json!({ "username": username, "sessions_revoked": revoked }),
+        reason.as_deref(),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({ "status": "kicked", "sessions_revoked": revoked }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BanRequest {
+    pub reason: Option<String>,
+    /// Omitted for a permanent ban.
+    pub duration_hours: Option<i64>,
+    /// Remove the member's messages of this many last seconds (at most 7 days).
+    #[serde(default)]
+    pub delete_message_seconds: i64,
+    pub appealable: Option<bool>,
+}
+
+/// POST /api/users/{id}/ban — Ban a member, for a while or for good (MANAGE_MEMBERS)
+pub async fn ban(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<BanRequest>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let target_id = path.into_inner();
+    let username = match moderatable(pool.get_ref(), &claims, &target_id).await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let reason = match reason(body.reason.as_deref(), &req) {
+        Ok(reason) => reason,
+        Err(response) => return response,
+    };
+    if body.duration_hours.is_some_and(|h| !(1..=MAX_DURATION_HOURS).contains(&h)) {
+        return bad_request(&format!("duration_hours must be between 1 and {MAX_DURATION_HOURS}"));
+    }
+    if !(0..=MAX_DELETE_MESSAGE_SECONDS).contains(&body.delete_message_seconds) {
+        return bad_request(&format!("delete_message_seconds must be between 0 and {MAX_DELETE_MESSAGE_SECONDS}"));
+    }
+
+    let now = Utc::now();
+    let expires_at = body.duration_hours.map(|h| (now + chrono::Duration::hours(h)).to_rfc3339());
+    let id = Uuid::new_v4().to_string();
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // An expired ban the unbanner has not reached yet makes way for this one.
+    let _ = sqlx::query("UPDATE bans SET lifted_at = ? WHERE user_id = ? AND lifted_at IS NULL AND expires_at <= ?")
+        .bind(now.to_rfc3339())
+        .bind(&target_id)
+        .bind(now.to_rfc3339())
+        .execute(&mut *tx)
+        .await;
+    let inserted = sqlx::query(
+        "INSERT INTO bans (id, user_id, username, reason, banned_by, banned_by_username, created_at, expires_at, appealable) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&target_id)
+    .bind(&username)
+    .bind(&reason)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(now.to_rfc3339())
+    .bind(&expires_at)
+    .bind(body.appealable.unwrap_or(true) as i64)
+    .execute(&mut *tx)
+    .await;
+    match inserted {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": "This member is already banned" }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let mut purged = 0;
+    if body.delete_message_seconds > 0 {
+        let since = (now - chrono::Duration::seconds(body.delete_message_seconds)).to_rfc3339();
+        purged = match sqlx::query("DELETE FROM messages WHERE user_id = ? AND created_at >= ?")
+            .bind(&target_id)
+            .bind(&since)
+            .execute(&mut *tx)
+            .await
+        {
+            Ok(res) => res.rows_affected(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        let _ = sqlx::query("UPDATE bans SET purged_messages = ? WHERE id = ?")
+            .bind(purged as i64)
+            .bind(&id)
+            .execute(&mut *tx)
+            .await;
+        if tx.commit().await.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+        crate::forum::refresh_replies(pool.get_ref(), None).await;
+        let event = serde_json::json!({
+            "type": "messages_purged",
+            "user_id": target_id,
+            "since": since,
+            "count": purged,
+        });
+        let _ = broadcaster.send(event.to_string());
+    } else if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    crate::sessions::revoke_user_sessions(pool.get_ref(), store.get_ref(), &target_id, None).await;
+    let event = serde_json::json!({
+        "type": "banned",
+        "recipient_id": target_id,
+        "reason": reason,
+        "expires_at": expires_at,
+    });
+    let _ = broadcaster.send(event.to_string());
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::MemberBanned,
+        &target_id,
+        serde_json::json!({
+            "username": username,
+            "ban_id": id,
+            "expires_at": expires_at,
+            "purged_messages": purged,
+        }),
+        reason.as_deref(),
+    )
+    .await;
+
+    match in_force(pool.get_ref(), &target_id).await {
+        Some(ban) => HttpResponse::Created().json(ban),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/users/{id}/ban — Lift a member's ban (MANAGE_MEMBERS)
+pub async fn unban(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+    let Some(ban) = in_force(pool.get_ref(), &path.into_inner()).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "This member is not banned" }));
+    };
+    lift(pool.get_ref(), &ban.id, Some(&claims), crate::audit_log::reason(&req).as_deref()).await;
+    HttpResponse::NoContent().finish()
+}
+
+const BANS: crate::pagination::Listing = crate::pagination::Listing {
+    name: "bans",
+    default_limit: 50,
+    max_limit: 200,
+    sorts: &[("created_at", "created_at")],
+    default_sort: "-created_at",
+    tiebreak: "id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct BansQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub user_id: Option<String>,
+    /// Only the bans in force (true) or the lifted ones (false).
+    pub active: Option<bool>,
+    /// "pending", "accepted" or "denied".
+    pub appeal_status: Option<String>,
+}
+
+/// GET /api/server/bans?limit=&sort=&cursor=&user_id=&active=&appeal_status= — Bans, newest first (MANAGE_MEMBERS)
+pub async fn list(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<BansQuery>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+    let page = match BANS.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let user_id = crate::pagination::filter(&query.user_id);
+    let appeal_status = crate::pagination::filter(&query.appeal_status);
+    let active = match query.active {
+        Some(true) => format!(" AND {IN_FORCE}"),
+        Some(false) => format!(" AND NOT ({IN_FORCE})"),
+        None => String::new(),
+    };
+
+    let sql = format!(
+        "SELECT {COLUMNS}, {} FROM bans WHERE (? IS NULL OR user_id = ?) AND (? IS NULL OR appeal_status = ?){active}{}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut q = sqlx::query(&sql);
+    for value in [&user_id, &appeal_status] {
+        q = q.bind(value).bind(value);
+    }
+    if query.active.is_some() {
+        q = q.bind(Utc::now().to_rfc3339());
+    }
+    for value in page.keyset_binds() {
+        q = q.bind(value);
+    }
+    match q.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await {
+        Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
+            let bans: Vec<Ban> = rows.iter().map(Ban::from_row).collect();
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(bans), next_cursor)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AppealRequest {
+    pub appeal_token: String,
+    pub message: String,
+}
+
+/// POST /api/bans/appeal — Appeal a ban, with the token of a refused login (no auth)
+pub async fn appeal(pool: web::Data<SqlitePool>, body: web::Json<AppealRequest>) -> HttpResponse {
+    let Some(ban_id) = verify_appeal_token(&body.appeal_token) else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Invalid or expired appeal token" }));
+    };
+    let message = body.message.trim();
+    if message.is_empty() || message.chars().count() > MAX_APPEAL_CHARS {
+        return bad_request("Appeal must be between 1 and 2000 chars");
+    }
+
+    let submitted = sqlx::query(&format!(
+        "UPDATE bans SET appeal_message = ?, appeal_submitted_at = ?, appeal_status = 'pending' \
+         WHERE id = ? AND appealable = 1 AND appeal_message IS NULL AND {IN_FORCE}"
+    ))
+    .bind(message)
+    .bind(Utc::now().to_rfc3339())
+    .bind(&ban_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    match submitted {
+        Ok(res) if res.rows_affected() == 1 => {
+            HttpResponse::Created().json(serde_json::json!({ "status": "pending" }))
+        }
+        Ok(_) => HttpResponse::Conflict().json(serde_json::json!({ "error": "This ban cannot be appealed" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewAppealRequest {
+    pub accept: bool,
+    pub note: Option<String>,
+}
+
+/// POST /api/server/bans/{id}/appeal — Accept (lifting the ban) or deny an appeal (MANAGE_MEMBERS)
+pub async fn review_appeal(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<ReviewAppealRequest>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+    let note = body.note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    if note.is_some_and(|n| n.chars().count() > MAX_REASON_CHARS) {
+        return bad_request("Note must be at most 512 chars");
+    }
+    let ban_id = path.into_inner();
+    let status = if body.accept { "accepted" } else { "denied" };
+
+    let reviewed: Option<String> = sqlx::query_scalar(
+        "UPDATE bans SET appeal_status = ?, appeal_reviewed_by_username = ?, appeal_reviewed_at = ?, appeal_note = ? \
+         WHERE id = ? AND appeal_status = 'pending' RETURNING user_id"
+    )
+    .bind(status)
+    .bind(&claims.username)
+    .bind(Utc::now().to_rfc3339())
+    .bind(note)
+    .bind(&ban_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some(user_id) = reviewed else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No pending appeal for this ban" }));
+    };
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::BanAppealReviewed,
+        &user_id,
+        serde_json::json!({ "ban_id": ban_id, "appeal_status": status }),
+        note,
+    )
+    .await;
+    if body.accept {
+        lift(pool.get_ref(), &ban_id, Some(&claims), note).await;
+    }
+
+    match sqlx::query(&format!("SELECT {COLUMNS} FROM bans WHERE id = ?"))
+        .bind(&ban_id)
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(row) => HttpResponse::Ok().json(Ban::from_row(&row)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -42,7 +42,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .fetch_all(&pool)
             .await?;
         for row in rows {
-            let u: String = sqlx::Row::get(&row, "username");
+            let u: String = row.get("username");
             println!(" - {}", u);
         }
     }
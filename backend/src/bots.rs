@@ -0,0 +1,505 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Bot accounts and bot tokens
+// ═══════════════════════════════════════════════════════
+//
+// A bot is a user that third-party programs drive with a long-lived token
+// instead of a password and JWTs: requests send `Authorization: Bot
+// <token>`, the gateway takes `identify { bot_token }`. It reads events and
+// posts like any member, within the permissions of its own role, created
+// with it (named `bot-<name>`) and handed only permissions its creator has.
+// That role is managed here: it can't be given to anyone else, deleted, or
+// swapped for another on the bot.
+//
+// Tokens are shown once, when the bot is created or its token is reset,
+// and stored hashed. So that `extract_claims` stays synchronous, the live
+// tokens are also kept in memory (`BotTokens`, loaded at startup, kept in
+// step across instances by `bus`) with the identity they sign in as. A bot
+// has no session: sudo, refresh and the session list don't apply, and
+// resetting the token is how it is revoked. Deleting a bot revokes its
+// token; its user and role stay, so its messages keep their author.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::sudo::Sudo;
+use crate::ws::{AccessCache, Broadcaster};
+
+/// Prefix of the Authorization header carrying a bot token.
+pub const AUTH_SCHEME: &str = "Bot ";
+const MAX_NAME_CHARS: usize = 32;
+const MAX_DESCRIPTION_CHARS: usize = 400;
+/// Role names are at most 24 characters, see `auth::create_server_role`.
+const MAX_ROLE_NAME_CHARS: usize = 24;
+
+// ── Token store ─────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotIdentity {
+    user_id: String,
+    username: String,
+    role: String,
+}
+
+/// Live bot tokens: token hash -> the bot they sign in as.
+pub type BotTokens = Arc<Mutex<HashMap<String, BotIdentity>>>;
+
+pub fn create_bot_tokens() -> BotTokens {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Fill the store from the `bots` table, at startup.
+pub async fn load_bot_tokens(pool: &SqlitePool, store: &BotTokens) {
+    let rows = sqlx::query("SELECT b.token_hash, b.user_id, b.role, u.username FROM bots b JOIN users u ON u.id = b.user_id")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let mut tokens = store.lock().unwrap();
+    for row in rows {
+        tokens.insert(
+            row.get("token_hash"),
+            BotIdentity { user_id: row.get("user_id"), username: row.get("username"), role: row.get("role") },
+        );
+    }
+}
+
+/// Claims of the bot `token` signs in as, `None` for an unknown token.
+pub fn claims_for(store: &BotTokens, token: &str) -> Option<Claims> {
+    let identity = store.lock().unwrap().get(&hash_token(token.trim())).cloned()?;
+    Some(Claims {
+        sub: identity.user_id,
+        username: identity.username,
+        role: identity.role,
+        // Bot tokens don't expire, they are reset.
+        exp: usize::MAX,
+        sid: String::new(),
+        sudo_until: None,
+    })
+}
+
+/// A bot's new token (`None`: revoked), replayed on the other instances
+/// (see `bus`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotTokenChange {
+    token_hash: Option<String>,
+    identity: BotIdentity,
+}
+
+/// Replace whatever token the bot of `user_id` had with `token_hash`, here
+/// and on the other instances.
+fn store_token(store: &BotTokens, token_hash: Option<String>, identity: BotIdentity) {
+    let change = BotTokenChange { token_hash, identity };
+    apply_token_change(store, change.clone());
+    crate::bus::publish_bot_token(change);
+}
+
+/// Apply a token change, made here or on another instance.
+pub(crate) fn apply_token_change(store: &BotTokens, change: BotTokenChange) {
+    let mut tokens = store.lock().unwrap();
+    tokens.retain(|_, bot| bot.user_id != change.identity.user_id);
+    if let Some(token_hash) = change.token_hash {
+        tokens.insert(token_hash, change.identity);
+    }
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Tokens are random 256-bit values, a plain SHA-256 is enough.
+fn hash_token(raw: &str) -> String {
+    Sha256::digest(raw.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// ── Managed roles ───────────────────────────────────────
+
+/// Whether `role` is a bot's own role.
+pub(crate) async fn owns_role(pool: &SqlitePool, role: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM bots WHERE role = ?)")
+        .bind(role)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false)
+}
+
+/// Whether `user_id` is a bot.
+pub(crate) async fn is_bot(pool: &SqlitePool, user_id: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM bots WHERE user_id = ?)")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false)
+}
+
+/// A free role name for the bot `username`: `bot-<name>`, or with the bot id
+/// when that one is taken or the name has nothing usable.
+async fn allocate_role_name(pool: &SqlitePool, username: &str, bot_id: &str) -> String {
+    let slug: String = username
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '-' })
+        .collect();
+    let slug = slug.trim_matches('-');
+    let preferred = format!("bot-{slug}").chars().take(MAX_ROLE_NAME_CHARS).collect::<String>();
+    let taken: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM roles WHERE name = ?)")
+        .bind(&preferred)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(true);
+    if slug.is_empty() || taken {
+        format!("bot-{}", &bot_id.replace('-', "")[..12])
+    } else {
+        preferred
+    }
+}
+
+// ── Models ──────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct Bot {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub description: String,
+    pub role: String,
+    pub permissions: u64,
+    pub created_by_username: String,
+    pub created_at: String,
+    pub token_reset_at: Option<String>,
+    /// Only when the bot is created or its token reset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+const SELECT: &str = "SELECT b.id, b.user_id, u.username, b.description, b.role, COALESCE(r.permissions, 0) AS permissions, \
+     b.created_by_username, b.created_at, b.token_reset_at \
+     FROM bots b JOIN users u ON u.id = b.user_id LEFT JOIN roles r ON r.name = b.role";
+
+impl Bot {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            username: row.get("username"),
+            description: row.get("description"),
+            role: row.get("role"),
+            permissions: row.get::<i64, _>("permissions") as u64,
+            created_by_username: row.get("created_by_username"),
+            created_at: row.get("created_at"),
+            token_reset_at: row.get("token_reset_at"),
+            token: None,
+        }
+    }
+
+    fn identity(&self) -> BotIdentity {
+        BotIdentity { user_id: self.user_id.clone(), username: self.username.clone(), role: self.role.clone() }
+    }
+}
+
+async fn load(pool: &SqlitePool, id: &str) -> Option<Bot> {
+    sqlx::query(&format!("{SELECT} WHERE b.id = ?"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Bot::from_row(&row))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({ "error": "Bot not found" }))
+}
+
+/// The caller's claims if they manage the server's bots.
+async fn require_manager(pool: &SqlitePool, req: &HttpRequest) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require_server(pool, &claims, crate::permissions::MANAGE_SERVER).await?;
+    Ok(claims)
+}
+
+/// Known permission bits the caller may hand out.
+async fn grantable(pool: &SqlitePool, claims: &Claims, permissions: u64) -> Result<u64, HttpResponse> {
+    if permissions & !crate::permissions::ALL != 0 {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown permission bits" })));
+    }
+    let granted = crate::permissions::role_permissions(pool, &claims.role).await;
+    crate::permissions::require_grantable(granted, permissions)?;
+    Ok(permissions)
+}
+
+#[allow(clippy::result_large_err)]
+fn valid_description(description: &str) -> Result<String, HttpResponse> {
+    let description = description.trim();
+    if description.chars().count() > MAX_DESCRIPTION_CHARS {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Description must be at most {MAX_DESCRIPTION_CHARS} characters")
+        })));
+    }
+    Ok(description.to_string())
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBot {
+    pub name: String,
+    pub description: Option<String>,
+    /// Its role's permissions, the member defaults when omitted.
+    pub permissions: Option<u64>,
+}
+
+/// POST /api/server/bots — Register a bot, returns its token once (MANAGE_SERVER, sudo)
+pub async fn create_bot(
+    req: HttpRequest,
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    tokens: web::Data<BotTokens>,
+    body: web::Json<CreateBot>,
+) -> HttpResponse {
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+    let permissions = match grantable(pool.get_ref(), &claims, body.permissions.unwrap_or(crate::permissions::MEMBER)).await {
+        Ok(permissions) => permissions,
+        Err(response) => return response,
+    };
+    let name = body.name.trim();
+    if name.chars().count() < 2 || name.chars().count() > MAX_NAME_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Name must be 2 to {MAX_NAME_CHARS} characters")
+        }));
+    }
+    let description = match valid_description(body.description.as_deref().unwrap_or_default()) {
+        Ok(description) => description,
+        Err(response) => return response,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let user_id = Uuid::new_v4().to_string();
+    let username = crate::auth::allocate_unique_username(pool.get_ref(), name).await;
+    let role = allocate_role_name(pool.get_ref(), &username, &id).await;
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+    let created: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("INSERT INTO roles (name, permissions) VALUES (?, ?)")
+            .bind(&role)
+            .bind(permissions as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES (?, ?, '!', ?)")
+            .bind(&user_id)
+            .bind(&username)
+            .bind(&role)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO bots (id, user_id, role, description, token_hash, created_by, created_by_username, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&id)
+        .bind(&user_id)
+        .bind(&role)
+        .bind(&description)
+        .bind(&token_hash)
+        .bind(&claims.sub)
+        .bind(&claims.username)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+    .await;
+    if created.is_err() {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to create the bot" }));
+    }
+
+    let Some(mut bot) = load(pool.get_ref(), &id).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    store_token(tokens.get_ref(), Some(token_hash), bot.identity());
+    if let Some(role) = crate::roles::fetch_role(pool.get_ref(), &role).await {
+        crate::roles::broadcast_role_event(&broadcaster, "role_created", &role);
+    }
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::BotCreated,
+        &id,
+        serde_json::json!({ "username": bot.username, "role": bot.role, "permissions": bot.permissions }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    bot.token = Some(token);
+    HttpResponse::Created().json(bot)
+}
+
+/// GET /api/server/bots — The server's bots, without their tokens (MANAGE_SERVER)
+pub async fn list_bots(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = require_manager(pool.get_ref(), &req).await {
+        return response;
+    }
+    let rows = sqlx::query(&format!("{SELECT} ORDER BY b.created_at"))
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default();
+    HttpResponse::Ok().json(rows.iter().map(Bot::from_row).collect::<Vec<_>>())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateBot {
+    pub description: Option<String>,
+    pub permissions: Option<u64>,
+}
+
+/// PATCH /api/server/bots/{id} — Change a bot's description or permissions (MANAGE_SERVER)
+pub async fn update_bot(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    access_cache: web::Data<AccessCache>,
+    path: web::Path<String>,
+    body: web::Json<UpdateBot>,
+) -> HttpResponse {
+    let claims = match require_manager(pool.get_ref(), &req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let id = path.into_inner();
+    let Some(bot) = load(pool.get_ref(), &id).await else {
+        return not_found();
+    };
+    let description = match body.description.as_deref().map(valid_description).transpose() {
+        Ok(description) => description.unwrap_or_else(|| bot.description.clone()),
+        Err(response) => return response,
+    };
+    let permissions = match body.permissions {
+        // Taking permissions away is handing them out too.
+        Some(permissions) => match grantable(pool.get_ref(), &claims, permissions | bot.permissions).await {
+            Ok(_) => permissions,
+            Err(response) => return response,
+        },
+        None => bot.permissions,
+    };
+
+    let _ = sqlx::query("UPDATE bots SET description = ? WHERE id = ?")
+        .bind(&description)
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await;
+    if permissions != bot.permissions {
+        let _ = sqlx::query("UPDATE roles SET permissions = ? WHERE name = ?")
+            .bind(permissions as i64)
+            .bind(&bot.role)
+            .execute(pool.get_ref())
+            .await;
+        crate::ws::cache_clear_role_permissions(access_cache.get_ref());
+        if let Some(role) = crate::roles::fetch_role(pool.get_ref(), &bot.role).await {
+            crate::roles::broadcast_role_event(&broadcaster, "role_updated", &role);
+        }
+    }
+
+    let changes = crate::audit_log::diff(&[
+        ("description", bot.description.clone().into(), description.into()),
+        ("permissions", bot.permissions.into(), permissions.into()),
+    ]);
+    if changes.as_object().is_some_and(|c| !c.is_empty()) {
+        crate::audit_log::record(
+            pool.get_ref(),
+            Some(&claims),
+            crate::audit_log::Action::BotUpdated,
+            &id,
+            changes,
+            crate::audit_log::reason(&req).as_deref(),
+        )
+        .await;
+    }
+    match load(pool.get_ref(), &id).await {
+        Some(bot) => HttpResponse::Ok().json(bot),
+        None => not_found(),
+    }
+}
+
+/// POST /api/server/bots/{id}/token — Reset a bot's token, the old one stops working (MANAGE_SERVER, sudo)
+pub async fn reset_token(
+    req: HttpRequest,
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    tokens: web::Data<BotTokens>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+    let id = path.into_inner();
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+    let updated = sqlx::query("UPDATE bots SET token_hash = ?, token_reset_at = ? WHERE id = ?")
+        .bind(&token_hash)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await;
+    match updated {
+        Ok(result) if result.rows_affected() > 0 => {}
+        Ok(_) => return not_found(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let Some(mut bot) = load(pool.get_ref(), &id).await else {
+        return not_found();
+    };
+    store_token(tokens.get_ref(), Some(token_hash), bot.identity());
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::BotTokenReset,
+        &id,
+        serde_json::json!({ "username": bot.username }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    bot.token = Some(token);
+    HttpResponse::Ok().json(bot)
+}
+
+/// DELETE /api/server/bots/{id} — Delete a bot and revoke its token; its user and messages stay (MANAGE_SERVER)
+pub async fn delete_bot(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    tokens: web::Data<BotTokens>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match require_manager(pool.get_ref(), &req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let id = path.into_inner();
+    let Some(bot) = load(pool.get_ref(), &id).await else {
+        return not_found();
+    };
+    if sqlx::query("DELETE FROM bots WHERE id = ?").bind(&id).execute(pool.get_ref()).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    store_token(tokens.get_ref(), None, bot.identity());
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::BotDeleted,
+        &id,
+        serde_json::json!({ "username": bot.username, "role": bot.role }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::NoContent().finish()
+}
@@ -0,0 +1,686 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Two-way Discord channel bridges
+// ═══════════════════════════════════════════════════════
+//
+// A bridge pairs a Voxium text room with a Discord channel and relays
+// messages both ways:
+//   - Voxium → Discord: messages sent in the room are queued as they are
+//     broadcast, then posted as `**username**: content`, attachments as
+//     links. `@username` of a member with a linked Discord account becomes
+//     a Discord mention; nothing else pings (`allowed_mentions`).
+//   - Discord → Voxium: the channel is polled for new messages, posted
+//     through the bridge's webhook under the Discord author's name and
+//     avatar. Image attachments become previews, other files links.
+//     Mentions of linked accounts become `@username`; any other `@` is
+//     defused so Discord users can't ping Voxium members or `@room`.
+//
+// Discord is reached with the server's bot token (`token_source: bot`,
+// `DISCORD_BRIDGE_BOT_TOKEN`) or the linked Discord account of whoever set
+// the bridge up (`user`). Echoes are dropped on both sides: messages posted
+// by the bridge's webhook are never queued for Discord, and Discord
+// messages from the relaying account, or already relayed, are skipped.
+// `bridged_messages` keeps the pairs.
+//
+// Both directions run in the `discord_bridge` job queue. A failed relay is
+// dropped and stored as the bridge's `last_error`; a Discord rate limit
+// leaves the rest for the next pass. Edits, deletions and reactions are
+// not relayed.
+//
+// Config (env):
+//   DISCORD_BRIDGE_BOT_TOKEN      bot token for `token_source: bot`
+//   DISCORD_BRIDGE_POLL_SECS      seconds between passes (default 5, 0 turns bridges off)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::auth::{discord_api_base_url, extract_claims, DiscordUser};
+use crate::jobs::{Backlog, JobRegistry};
+use crate::unfurl::LinkEmbed;
+use crate::ws::{AccessCache, Broadcaster};
+
+const DEFAULT_POLL_SECS: u64 = 5;
+/// Discord messages fetched per poll, Discord's maximum.
+const POLL_LIMIT: usize = 50;
+/// Voxium messages sent per bridge and pass.
+const SEND_BATCH: i64 = 20;
+/// Discord's message length limit.
+const MAX_DISCORD_CHARS: usize = 2000;
+const WEBHOOK_NAME: &str = "Discord bridge";
+/// Keeps a defused `@` from starting a mention.
+const ZERO_WIDTH_SPACE: char = '\u{200B}';
+
+fn poll_interval() -> Option<Duration> {
+    let secs = std::env::var("DISCORD_BRIDGE_POLL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_POLL_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn bot_token() -> Option<String> {
+    std::env::var("DISCORD_BRIDGE_BOT_TOKEN").ok().filter(|t| !t.trim().is_empty())
+}
+
+// ── Models ──────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bridge {
+    pub id: String,
+    pub room_id: String,
+    pub channel_id: String,
+    pub token_source: String,
+    /// Whose linked Discord account relays, with `token_source: user`.
+    pub user_id: Option<String>,
+    pub webhook_id: String,
+    pub last_discord_message_id: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+    pub created_by_username: String,
+    pub created_at: String,
+    #[serde(skip)]
+    discord_user_id: Option<String>,
+}
+
+const COLUMNS: &str = "id, room_id, channel_id, token_source, user_id, webhook_id, discord_user_id, last_discord_message_id, \
+     last_error, last_error_at, created_by_username, created_at";
+
+impl Bridge {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            room_id: row.get("room_id"),
+            channel_id: row.get("channel_id"),
+            token_source: row.get("token_source"),
+            user_id: row.get("user_id"),
+            webhook_id: row.get("webhook_id"),
+            last_discord_message_id: row.get("last_discord_message_id"),
+            last_error: row.get("last_error"),
+            last_error_at: row.get("last_error_at"),
+            created_by_username: row.get("created_by_username"),
+            created_at: row.get("created_at"),
+            discord_user_id: row.get("discord_user_id"),
+        }
+    }
+}
+
+async fn room_bridge(pool: &SqlitePool, room_id: &str) -> Option<Bridge> {
+    sqlx::query(&format!("SELECT {COLUMNS} FROM discord_bridges WHERE room_id = ?"))
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Bridge::from_row(&row))
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DiscordAttachment {
+    url: String,
+    filename: String,
+    content_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DiscordMessage {
+    pub(crate) id: String,
+    #[serde(rename = "type", default)]
+    kind: u8,
+    #[serde(default)]
+    content: String,
+    author: DiscordUser,
+    #[serde(default)]
+    mentions: Vec<DiscordUser>,
+    #[serde(default)]
+    attachments: Vec<DiscordAttachment>,
+}
+
+impl DiscordMessage {
+    /// Plain messages and replies; joins, pins, boosts... are not relayed.
+    pub(crate) fn is_chat(&self) -> bool {
+        matches!(self.kind, 0 | 19)
+    }
+}
+
+// ── Translation ─────────────────────────────────────────
+
+/// `@` at the start of a word, where it would start a Voxium mention.
+fn starts_mention(text: &str, at: usize) -> bool {
+    text[..at].chars().next_back().is_none_or(char::is_whitespace)
+}
+
+/// Put a zero-width space after every `@` that would start a mention.
+fn defuse_mentions(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        out.push(c);
+        if c == '@' && starts_mention(text, i) {
+            out.push(ZERO_WIDTH_SPACE);
+        }
+    }
+    out
+}
+
+/// Voxium usernames of the members linked to these Discord accounts.
+async fn linked_usernames(pool: &SqlitePool, discord_ids: &[String]) -> Vec<(String, String)> {
+    if discord_ids.is_empty() {
+        return Vec::new();
+    }
+    let sql = format!(
+        "SELECT discord_id, username FROM users WHERE discord_id IN ({})",
+        vec!["?"; discord_ids.len()].join(", ")
+    );
+    let mut query = sqlx::query_as::<_, (String, String)>(&sql);
+    for id in discord_ids {
+        query = query.bind(id);
+    }
+    query.fetch_all(pool).await.unwrap_or_default()
+}
+
+/// Discord markup to Voxium text: `<@id>` of a linked account becomes
+/// `@username`, other mentions their name, `<:name:id>` emoji `:name:`.
+async fn incoming_content(pool: &SqlitePool, message: &DiscordMessage) -> String {
+    let mentioned: Vec<String> = message.mentions.iter().map(|u| u.id.clone()).collect();
+    let linked = linked_usernames(pool, &mentioned).await;
+    let text = defuse_mentions(&message.content);
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + len];
+        let replaced = if let Some(id) = tag.strip_prefix("@&") {
+            Some(format!("@{ZERO_WIDTH_SPACE}role-{id}"))
+        } else if let Some(id) = tag.strip_prefix('@').map(|id| id.trim_start_matches('!')) {
+            match linked.iter().find(|(discord_id, _)| discord_id == id) {
+                Some((_, username)) => Some(format!("@{username}")),
+                None => message
+                    .mentions
+                    .iter()
+                    .find(|u| u.id == id)
+                    .map(|u| format!("@{ZERO_WIDTH_SPACE}{}", u.global_name.as_deref().unwrap_or(&u.username))),
+            }
+        } else if tag.starts_with('#') {
+            Some("#channel".to_string())
+        } else {
+            // `<:name:id>` and `<a:name:id>`
+            let mut parts = tag.split(':');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some("" | "a"), Some(name), Some(_), None) if !name.is_empty() => Some(format!(":{name}:")),
+                _ => None,
+            }
+        };
+        match replaced {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A Discord message as a webhook post under its author's name and avatar:
+/// image attachments become previews, other files links. `None` when
+/// nothing is left to post.
+pub(crate) async fn webhook_post(pool: &SqlitePool, message: &DiscordMessage) -> Option<crate::webhooks::Post> {
+    let mut content = incoming_content(pool, message).await;
+    let mut embeds = Vec::new();
+    for attachment in &message.attachments {
+        if attachment.content_type.as_deref().is_some_and(|t| t.starts_with("image/")) {
+            embeds.push(LinkEmbed {
+                url: attachment.url.clone(),
+                site_name: None,
+                title: Some(attachment.filename.clone()),
+                description: None,
+                image_url: Some(attachment.url.clone()),
+                author_name: None,
+                broken_at: None,
+            });
+        } else {
+            content.push_str(&format!("\n{}", attachment.url));
+        }
+    }
+    let content: String = content.trim().chars().take(crate::messages::MAX_CONTENT_CHARS).collect();
+    if content.is_empty() && embeds.is_empty() {
+        return None;
+    }
+    Some(crate::webhooks::Post {
+        content,
+        username: message.author.global_name.clone().unwrap_or_else(|| message.author.username.clone()),
+        avatar_url: crate::auth::discord_avatar_url(&message.author),
+        embeds,
+    })
+}
+
+/// `@name` (case-insensitive, a whole mention) replaced with `replacement`.
+pub(crate) fn replace_mention(text: &str, name: &str, replacement: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (i, _) in text.match_indices('@') {
+        let end = i + 1 + name.len();
+        let matches = text.get(i + 1..end).is_some_and(|n| n.eq_ignore_ascii_case(name))
+            && text[end..].chars().next().is_none_or(|c| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'));
+        if matches && starts_mention(text, i) {
+            out.push_str(&text[last..i]);
+            out.push_str(replacement);
+            last = end;
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// Voxium text to a Discord message body: linked members become Discord
+/// mentions, the only ones allowed to ping; attachments are appended as links.
+async fn outgoing_body(pool: &SqlitePool, message_id: &str, username: &str, content: &str) -> serde_json::Value {
+    let names = crate::mentions::parse_mentions(content);
+    let mut text = content.to_string();
+    let mut pinged = Vec::new();
+    for name in names {
+        let discord_id: Option<String> = sqlx::query_scalar(
+            "SELECT discord_id FROM users WHERE username = ? COLLATE NOCASE AND discord_id IS NOT NULL"
+        )
+        .bind(&name)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+        if let Some(discord_id) = discord_id {
+            text = replace_mention(&text, &name, &format!("<@{discord_id}>"));
+            pinged.push(discord_id);
+        }
+    }
+    let base = crate::email::public_base_url();
+    for attachment in crate::files::message_attachments(pool, message_id).await {
+        text.push_str(&format!("\n{base}{}", attachment.url));
+    }
+    let text: String = format!("**{username}**: {text}").chars().take(MAX_DISCORD_CHARS).collect();
+    serde_json::json!({
+        "content": text,
+        "allowed_mentions": { "parse": [], "users": pinged },
+    })
+}
+
+// ── Relaying ────────────────────────────────────────────
+
+/// The Authorization header the bridge talks to Discord with.
+async fn authorization(pool: &SqlitePool, bridge: &Bridge) -> Result<String, String> {
+    match (bridge.token_source.as_str(), &bridge.user_id) {
+        ("bot", _) => bot_token().map(|t| format!("Bot {t}")).ok_or_else(|| "DISCORD_BRIDGE_BOT_TOKEN is not set".to_string()),
+        (_, Some(user_id)) => crate::discord_gateway::get_discord_account(pool, user_id).await.map(|account| account.token),
+        _ => Err("The relaying account is gone".to_string()),
+    }
+}
+
+async fn record_error(pool: &SqlitePool, bridge_id: &str, error: &str) {
+    let _ = sqlx::query("UPDATE discord_bridges SET last_error = ?, last_error_at = ? WHERE id = ?")
+        .bind(error)
+        .bind(Utc::now().to_rfc3339())
+        .bind(bridge_id)
+        .execute(pool)
+        .await;
+}
+
+/// Queue a broadcast `message` of a bridged room for Discord, unless the
+/// bridge posted it.
+async fn queue_outgoing(pool: &SqlitePool, event: &str) {
+    let Ok(event) = serde_json::from_str::<serde_json::Value>(event) else {
+        return;
+    };
+    if event["type"] != "message" {
+        return;
+    }
+    let (Some(id), Some(room_id)) = (event["id"].as_str(), event["room_id"].as_str()) else {
+        return;
+    };
+    let _ = sqlx::query(
+        "INSERT OR IGNORE INTO bridged_messages (message_id, bridge_id, direction, created_at) \
+         SELECT ?, id, 'to_discord', ? FROM discord_bridges WHERE room_id = ? AND webhook_id IS NOT ?"
+    )
+    .bind(id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(room_id)
+    .bind(event["webhook_id"].as_str())
+    .execute(pool)
+    .await;
+}
+
+/// Post the bridge's queued Voxium messages to Discord. `Ok(false)` when
+/// Discord rate limited us.
+async fn send_queued(pool: &SqlitePool, client: &Client, bridge: &Bridge, auth: &str, sent: &mut u64) -> Result<bool, String> {
+    let queued = sqlx::query(
+        "SELECT b.message_id, m.username, m.content, m.deleted_at FROM bridged_messages b JOIN messages m ON m.id = b.message_id \
+         WHERE b.bridge_id = ? AND b.discord_message_id IS NULL ORDER BY b.created_at LIMIT ?"
+    )
+    .bind(&bridge.id)
+    .bind(SEND_BATCH)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for row in queued {
+        let message_id: String = row.get("message_id");
+        let deleted_at: Option<String> = row.get("deleted_at");
+        if deleted_at.is_none() {
+            let body = outgoing_body(pool, &message_id, row.get("username"), row.get("content")).await;
+            let response = client
+                .post(format!("{}/channels/{}/messages", discord_api_base_url(), bridge.channel_id))
+                .header("Authorization", auth)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            match response.status() {
+                StatusCode::TOO_MANY_REQUESTS => return Ok(false),
+                status if status.is_success() => {
+                    let posted: serde_json::Value = response.json().await.unwrap_or_default();
+                    if let Some(discord_id) = posted["id"].as_str() {
+                        let _ = sqlx::query("UPDATE bridged_messages SET discord_message_id = ? WHERE message_id = ?")
+                            .bind(discord_id)
+                            .bind(&message_id)
+                            .execute(pool)
+                            .await;
+                        *sent += 1;
+                        continue;
+                    }
+                }
+                status => record_error(pool, &bridge.id, &format!("Sending to Discord failed ({status})")).await,
+            }
+        }
+        // Deleted before it went out, or refused: not retried.
+        let _ = sqlx::query("DELETE FROM bridged_messages WHERE message_id = ?").bind(&message_id).execute(pool).await;
+    }
+    Ok(true)
+}
+
+/// The Discord account the bridge relays with, asked once.
+async fn relaying_account(pool: &SqlitePool, client: &Client, bridge: &Bridge, auth: &str) -> Result<String, String> {
+    if let Some(id) = &bridge.discord_user_id {
+        return Ok(id.clone());
+    }
+    let me: DiscordUser = client
+        .get(format!("{}/users/@me", discord_api_base_url()))
+        .header("Authorization", auth)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    let _ = sqlx::query("UPDATE discord_bridges SET discord_user_id = ? WHERE id = ?")
+        .bind(&me.id)
+        .bind(&bridge.id)
+        .execute(pool)
+        .await;
+    Ok(me.id)
+}
+
+/// Relay the channel's new Discord messages into the room.
+async fn receive_new(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    access_cache: &AccessCache,
+    client: &Client,
+    bridge: &Bridge,
+    auth: &str,
+    received: &mut u64,
+) -> Result<(), String> {
+    let own_id = relaying_account(pool, client, bridge, auth).await?;
+    let url = format!("{}/channels/{}/messages", discord_api_base_url(), bridge.channel_id);
+    // A new bridge starts from the channel's latest message, without history.
+    let query: Vec<(&str, String)> = match &bridge.last_discord_message_id {
+        Some(after) => vec![("limit", POLL_LIMIT.to_string()), ("after", after.clone())],
+        None => vec![("limit", "1".to_string())],
+    };
+    let response = client.get(url).header("Authorization", auth).query(&query).send().await.map_err(|e| e.to_string())?;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+    let mut messages: Vec<DiscordMessage> = response
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    // Discord lists newest first.
+    messages.reverse();
+    let Some(webhook) = crate::webhooks::load(pool, &bridge.webhook_id).await else {
+        return Err("The bridge's webhook is gone".to_string());
+    };
+
+    for message in messages {
+        let relayed: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM bridged_messages WHERE bridge_id = ? AND discord_message_id = ?)")
+            .bind(&bridge.id)
+            .bind(&message.id)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(false);
+        let relay = bridge.last_discord_message_id.is_some() && message.is_chat() && message.author.id != own_id && !relayed;
+        if relay {
+            if let Some(post) = webhook_post(pool, &message).await {
+                match crate::webhooks::post_message(pool, broadcaster, Some(access_cache), &webhook, post).await {
+                    Ok(posted) => {
+                        let _ = sqlx::query(
+                            "INSERT OR IGNORE INTO bridged_messages (message_id, bridge_id, direction, discord_message_id, created_at) \
+                             VALUES (?, ?, 'from_discord', ?, ?)"
+                        )
+                        .bind(posted["id"].as_str())
+                        .bind(&bridge.id)
+                        .bind(&message.id)
+                        .bind(Utc::now().to_rfc3339())
+                        .execute(pool)
+                        .await;
+                        *received += 1;
+                    }
+                    // Refused by the room's lockdown, automod or slowmode like
+                    // any other author's message: dropped, not retried.
+                    Err(crate::webhooks::PostError::Refused(_)) => {}
+                    Err(error) => return Err(error.to_string()),
+                }
+            }
+        }
+        let _ = sqlx::query("UPDATE discord_bridges SET last_discord_message_id = ? WHERE id = ?")
+            .bind(&message.id)
+            .bind(&bridge.id)
+            .execute(pool)
+            .await;
+    }
+    Ok(())
+}
+
+/// One pass over every bridge. A failing bridge records its error and
+/// doesn't hold up the others.
+async fn relay_all(pool: &SqlitePool, broadcaster: &Broadcaster, access_cache: &AccessCache, client: &Client) -> Result<u64, String> {
+    let bridges: Vec<Bridge> = sqlx::query(&format!("SELECT {COLUMNS} FROM discord_bridges"))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(Bridge::from_row)
+        .collect();
+    let mut relayed = 0;
+    for bridge in bridges {
+        let outcome = async {
+            let auth = authorization(pool, &bridge).await?;
+            if send_queued(pool, client, &bridge, &auth, &mut relayed).await? {
+                receive_new(pool, broadcaster, access_cache, client, &bridge, &auth, &mut relayed).await?;
+            }
+            Ok::<(), String>(())
+        }
+        .await;
+        if let Err(error) = outcome {
+            record_error(pool, &bridge.id, &error).await;
+        }
+    }
+    Ok(relayed)
+}
+
+async fn relay_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM bridged_messages WHERE discord_message_id IS NULL"
+    )
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_bridges(pool: SqlitePool, broadcaster: Broadcaster, access_cache: AccessCache, jobs: JobRegistry) {
+    let Some(interval) = poll_interval() else {
+        return;
+    };
+    crate::jobs::register(&jobs, crate::jobs::DISCORD_BRIDGE);
+
+    let mut rx = broadcaster.subscribe();
+    let listener_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => queue_outgoing(&listener_pool, &event).await,
+                // Missed messages are not relayed.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    actix_web::rt::spawn(async move {
+        let client = Client::builder().timeout(Duration::from_secs(15)).build().unwrap_or_default();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::DISCORD_BRIDGE, None, relay_all(&pool, &broadcaster, &access_cache, &client), relay_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBridge {
+    pub channel_id: String,
+    /// `bot` (the default) or `user`, the caller's linked Discord account.
+    pub token_source: Option<String>,
+}
+
+/// The caller's claims if they manage the room.
+async fn require_manager(pool: &SqlitePool, req: &HttpRequest, room_id: &str) -> Result<crate::auth::Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require(pool, room_id, &claims, crate::permissions::MANAGE_ROOMS).await?;
+    Ok(claims)
+}
+
+/// GET /api/rooms/{id}/discord-bridge — The room's bridge, with its last error (MANAGE_ROOMS)
+pub async fn get_bridge(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let room_id = path.into_inner();
+    if let Err(response) = require_manager(pool.get_ref(), &req, &room_id).await {
+        return response;
+    }
+    match room_bridge(pool.get_ref(), &room_id).await {
+        Some(bridge) => HttpResponse::Ok().json(bridge),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "The room is not bridged" })),
+    }
+}
+
+/// POST /api/rooms/{id}/discord-bridge — Bridge a text room with a Discord channel (MANAGE_ROOMS)
+pub async fn create_bridge(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<CreateBridge>,
+) -> HttpResponse {
+    let room_id = path.into_inner();
+    let claims = match require_manager(pool.get_ref(), &req, &room_id).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let bad = |error: &str| HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if kind.as_deref() != Some("text") {
+        return bad("Only text rooms can be bridged");
+    }
+    let channel_id = body.channel_id.trim();
+    if channel_id.is_empty() || channel_id.len() > 20 || !channel_id.chars().all(|c| c.is_ascii_digit()) {
+        return bad("channel_id must be a Discord channel id");
+    }
+    let user_id = match body.token_source.as_deref().unwrap_or("bot") {
+        "bot" if bot_token().is_none() => return bad("DISCORD_BRIDGE_BOT_TOKEN is not set on this server"),
+        "bot" => None,
+        "user" => {
+            let linked: bool = sqlx::query_scalar("SELECT discord_access_token IS NOT NULL FROM users WHERE id = ?")
+                .bind(&claims.sub)
+                .fetch_optional(pool.get_ref())
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or(false);
+            if !linked {
+                return bad("Link a Discord account first");
+            }
+            Some(claims.sub.clone())
+        }
+        _ => return bad("token_source must be bot or user"),
+    };
+    if room_bridge(pool.get_ref(), &room_id).await.is_some() {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "The room is already bridged" }));
+    }
+
+    let webhook = match crate::webhooks::insert_webhook(pool.get_ref(), &room_id, WEBHOOK_NAME, None, &claims).await {
+        Ok(webhook) => webhook,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let id = Uuid::new_v4().to_string();
+    let inserted = sqlx::query(
+        "INSERT INTO discord_bridges (id, room_id, channel_id, token_source, user_id, webhook_id, created_by_username, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&room_id)
+    .bind(channel_id)
+    .bind(if user_id.is_some() { "user" } else { "bot" })
+    .bind(&user_id)
+    .bind(&webhook.id)
+    .bind(&claims.username)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if inserted.is_err() {
+        let _ = sqlx::query("DELETE FROM webhooks WHERE id = ?").bind(&webhook.id).execute(pool.get_ref()).await;
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "That channel is already bridged" }));
+    }
+    match room_bridge(pool.get_ref(), &room_id).await {
+        Some(bridge) => HttpResponse::Created().json(bridge),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/rooms/{id}/discord-bridge — Stop bridging the room (MANAGE_ROOMS)
+pub async fn delete_bridge(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let room_id = path.into_inner();
+    if let Err(response) = require_manager(pool.get_ref(), &req, &room_id).await {
+        return response;
+    }
+    let Some(bridge) = room_bridge(pool.get_ref(), &room_id).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "The room is not bridged" }));
+    };
+    // The bridge goes with its webhook; relayed messages stay.
+    match sqlx::query("DELETE FROM webhooks WHERE id = ?").bind(&bridge.webhook_id).execute(pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,260 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Bulk message deletion (moderation)
+// ═══════════════════════════════════════════════════════
+//
+// Two moderator endpoints delete many messages in one call:
+//   - in a room (MANAGE_MESSAGES there): a list of message ids, or every
+//     message in a time range, optionally only one author's;
+//   - of one member, across rooms (MANAGE_MESSAGES on the server): their
+//     messages of the last `within_seconds`, optionally in some rooms only.
+//
+// A call deletes at most `MAX_MESSAGES`, newest first, and says whether more
+// matched (`has_more`): calling it again goes on with older ones. Messages
+// become tombstones, like a single delete, `BATCH_SIZE` per transaction so
+// the SQLite writer is never held for the whole job. Each batch broadcasts
+// one `messages_bulk_deleted` event per room: `{ type, room_id, ids,
+// deleted_by }`. Both are written to the audit log.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+/// Messages deleted by one call.
+const MAX_MESSAGES: i64 = 1000;
+/// Ids accepted by one call.
+const MAX_IDS: usize = 100;
+/// Messages tombstoned per transaction.
+const BATCH_SIZE: usize = 100;
+/// How far back a member's messages can be deleted, like a ban's purge.
+const MAX_WITHIN_SECONDS: i64 = 7 * 24 * 3600;
+
+/// Tombstone `ids` for `deleted_by`, `BATCH_SIZE` per transaction, and
+/// broadcast them. Returns how many were deleted; already deleted ones are
+/// skipped.
+async fn tombstone_all(pool: &SqlitePool, broadcaster: &Broadcaster, ids: &[String], deleted_by: &str) -> Result<u64, sqlx::Error> {
+    let mut deleted = 0;
+    for batch in ids.chunks(BATCH_SIZE) {
+        let placeholders = vec!["?"; batch.len()].join(", ");
+        let mut tx = pool.begin().await?;
+        let sql = format!(
+            "UPDATE messages SET deleted_at = ?, deleted_by = ?, pinned_at = NULL, pinned_by = NULL \
+             WHERE id IN ({placeholders}) AND deleted_at IS NULL RETURNING id, room_id, post_id"
+        );
+        let mut query = sqlx::query_as::<_, (String, String, Option<String>)>(&sql)
+            .bind(Utc::now().to_rfc3339())
+            .bind(deleted_by);
+        for id in batch {
+            query = query.bind(id);
+        }
+        let rows = query.fetch_all(&mut *tx).await?;
+        // Deleted messages no longer count as mentions
+        let sql = format!("DELETE FROM message_mentions WHERE message_id IN ({placeholders})");
+        let mut query = sqlx::query(&sql);
+        for id in batch {
+            query = query.bind(id);
+        }
+        query.execute(&mut *tx).await?;
+        tx.commit().await?;
+        deleted += rows.len() as u64;
+
+        let mut by_room: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut posts = BTreeSet::new();
+        for (id, room_id, post_id) in rows {
+            by_room.entry(room_id).or_default().push(id);
+            posts.extend(post_id);
+        }
+        for post_id in &posts {
+            crate::forum::refresh_replies(pool, Some(post_id.as_str())).await;
+        }
+        for (room_id, ids) in by_room {
+            let event = serde_json::json!({
+                "type": "messages_bulk_deleted",
+                "room_id": room_id,
+                "ids": ids,
+                "deleted_by": deleted_by,
+            });
+            let _ = broadcaster.send(event.to_string());
+        }
+        // Let other writers in between batches.
+        tokio::task::yield_now().await;
+    }
+    Ok(deleted)
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_bound(value: &str) -> Result<String, HttpResponse> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|t| t.with_timezone(&Utc).to_rfc3339())
+        .map_err(|_| HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid time (expected RFC 3339)" })))
+}
+
+/// Up to `MAX_MESSAGES` of the ids `sql` selects, and whether there were more.
+async fn select_ids(pool: &SqlitePool, sql: &str, binds: &[String]) -> Result<(Vec<String>, bool), sqlx::Error> {
+    let sql = format!("{sql} ORDER BY created_at DESC LIMIT ?");
+    let mut query = sqlx::query_scalar::<_, String>(&sql);
+    for value in binds {
+        query = query.bind(value);
+    }
+    let mut ids = query.bind(MAX_MESSAGES + 1).fetch_all(pool).await?;
+    let has_more = ids.len() as i64 > MAX_MESSAGES;
+    ids.truncate(MAX_MESSAGES as usize);
+    Ok((ids, has_more))
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct RoomBulkDelete {
+    /// Either these messages...
+    pub message_ids: Option<Vec<String>>,
+    /// ...or the ones sent from `after` (RFC 3339) until `before`, now by default.
+    pub after: Option<String>,
+    pub before: Option<String>,
+    /// Only this author's, with a time range.
+    pub user_id: Option<String>,
+}
+
+/// POST /api/rooms/{room_id}/messages/bulk-delete — Delete listed messages or a time range (MANAGE_MESSAGES)
+pub async fn delete_in_room(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<RoomBulkDelete>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+    let bad = |error: &str| HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+
+    let mut binds = vec![room_id.clone()];
+    let mut sql = "SELECT id FROM messages WHERE room_id = ? AND deleted_at IS NULL".to_string();
+    let criteria = match (&body.message_ids, &body.after) {
+        (Some(ids), None) => {
+            if body.before.is_some() || body.user_id.is_some() {
+                return bad("before and user_id go with a time range, not message_ids");
+            }
+            let mut ids = ids.clone();
+            ids.sort();
+            ids.dedup();
+            if ids.is_empty() || ids.len() > MAX_IDS {
+                return bad(&format!("message_ids must list 1 to {MAX_IDS} messages"));
+            }
+            sql.push_str(&format!(" AND id IN ({})", vec!["?"; ids.len()].join(", ")));
+            binds.extend(ids.iter().cloned());
+            serde_json::json!({ "message_ids": ids })
+        }
+        (None, Some(after)) => {
+            let after = match parse_bound(after) {
+                Ok(after) => after,
+                Err(response) => return response,
+            };
+            let before = match body.before.as_deref().map(parse_bound).transpose() {
+                Ok(before) => before.unwrap_or_else(|| Utc::now().to_rfc3339()),
+                Err(response) => return response,
+            };
+            sql.push_str(" AND created_at >= ? AND created_at < ?");
+            binds.push(after.clone());
+            binds.push(before.clone());
+            if let Some(user_id) = &body.user_id {
+                sql.push_str(" AND user_id = ?");
+                binds.push(user_id.clone());
+            }
+            serde_json::json!({ "after": after, "before": before, "user_id": body.user_id })
+        }
+        _ => return bad("Send either message_ids or a time range (after, before?)"),
+    };
+
+    let (ids, has_more) = match select_ids(pool.get_ref(), &sql, &binds).await {
+        Ok(selected) => selected,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let deleted = match tombstone_all(pool.get_ref(), broadcaster.get_ref(), &ids, &claims.sub).await {
+        Ok(deleted) => deleted,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to delete messages" })),
+    };
+
+    if deleted > 0 {
+        let mut changes = criteria;
+        changes["count"] = deleted.into();
+        crate::audit_log::record(
+            pool.get_ref(),
+            Some(&claims),
+            crate::audit_log::Action::MessagesBulkDeleted,
+            &room_id,
+            changes,
+            crate::audit_log::reason(&req).as_deref(),
+        )
+        .await;
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "deleted": deleted, "has_more": has_more }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemberBulkDelete {
+    pub within_seconds: i64,
+    /// Only in these rooms; every room by default.
+    pub room_ids: Option<Vec<String>>,
+}
+
+/// POST /api/users/{id}/messages/bulk-delete — Delete a member's recent messages across rooms (MANAGE_MESSAGES)
+pub async fn delete_member_recent(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<MemberBulkDelete>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+    if !(1..=MAX_WITHIN_SECONDS).contains(&body.within_seconds) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("within_seconds must be between 1 and {MAX_WITHIN_SECONDS}")
+        }));
+    }
+    let user_id = path.into_inner();
+    let since = (Utc::now() - chrono::Duration::seconds(body.within_seconds)).to_rfc3339();
+
+    let mut binds = vec![user_id.clone(), since.clone()];
+    let mut sql = "SELECT id FROM messages WHERE user_id = ? AND created_at >= ? AND deleted_at IS NULL".to_string();
+    if let Some(room_ids) = &body.room_ids {
+        sql.push_str(&format!(" AND room_id IN ({})", vec!["?"; room_ids.len()].join(", ")));
+        binds.extend(room_ids.iter().cloned());
+    }
+    let (ids, has_more) = match select_ids(pool.get_ref(), &sql, &binds).await {
+        Ok(selected) => selected,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let deleted = match tombstone_all(pool.get_ref(), broadcaster.get_ref(), &ids, &claims.sub).await {
+        Ok(deleted) => deleted,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to delete messages" })),
+    };
+
+    if deleted > 0 {
+        crate::audit_log::record(
+            pool.get_ref(),
+            Some(&claims),
+            crate::audit_log::Action::MessagesPurged,
+            &user_id,
+            serde_json::json!({ "count": deleted, "since": since, "room_ids": body.room_ids }),
+            crate::audit_log::reason(&req).as_deref(),
+        )
+        .await;
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "deleted": deleted, "has_more": has_more }))
+}
@@ -0,0 +1,293 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Event bus between backend instances (Redis)
+// ═══════════════════════════════════════════════════════
+//
+// One process keeps realtime state in memory: the broadcaster its sockets
+// listen to, who is connected, which sessions were revoked, and caches of
+// what the database says. With `EVENT_BUS_URL` set, instances sharing a
+// Redis server exchange that state over one pub/sub channel, so several can
+// run behind a load balancer:
+//   - every broadcast event is published and replayed into the other
+//     instances' broadcasters, so a message sent through one instance
+//     reaches sockets on all of them;
+//   - each instance publishes its connected users every
+//     `PRESENCE_INTERVAL`; `presence::is_connected` counts users connected
+//     elsewhere until their instance goes quiet for `PRESENCE_TTL`;
+//   - sessions are published as they are revoked, and rejected everywhere;
+//   - every change to the access cache (`ws::AccessCacheState`: user roles,
+//     room required roles and overwrites, role permissions, user blocks,
+//     automod rules) and to the bot token store (`bots::BotTokens`) is
+//     published as it is made; the other instances drop what it touched
+//     (blocks and bot tokens are carried whole) and read it again from the
+//     database, so a revoked bot token or a changed permission takes effect
+//     on every instance at once.
+//
+// What stays per instance: linked Discord gateway sessions, QR login
+// sessions, voice signalling and gateway resume buffers. The load balancer
+// must keep a user on one instance (sticky sessions, e.g. by the
+// `Authorization` header or a cookie). Instances share the SQLite database,
+// so they must run on one host (WAL doesn't work over network filesystems).
+// Background jobs run on every instance; they work from the database and
+// tolerate that, only doing the work once.
+//
+// Without `EVENT_BUS_URL` nothing here runs and everything stays in-process.
+//
+// Config (env):
+//   EVENT_BUS_URL                 redis://[:password@]host:6379[/db]
+//   EVENT_BUS_CHANNEL             pub/sub channel (default voxium:bus), one per deployment
+
+use futures_util::StreamExt;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::bots::{BotTokenChange, BotTokens};
+use crate::presence::PresenceTracker;
+use crate::sessions::SessionStore;
+use crate::ws::{AccessCache, Broadcaster, CacheChange};
+
+const DEFAULT_CHANNEL: &str = "voxium:bus";
+const PRESENCE_INTERVAL: Duration = Duration::from_secs(5);
+/// Users of an instance silent this long are no longer counted.
+const PRESENCE_TTL: Duration = Duration::from_secs(15);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Replayed events not yet seen coming back out of the broadcaster; past
+/// this many (after a lagging listener) they are forgotten.
+const MAX_REPLAYED: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Envelope {
+    Event { origin: String, event: String },
+    Presence { origin: String, users: Vec<String> },
+    Revoked { origin: String, sessions: Vec<String> },
+    Cache { origin: String, change: CacheChange },
+    BotToken { origin: String, change: BotTokenChange },
+}
+
+#[derive(Default)]
+struct Bus {
+    instance: String,
+    /// Envelopes waiting for `publish_loop`.
+    outgoing: Option<mpsc::UnboundedSender<String>>,
+    /// Hashes of events replayed from the bus, so they aren't published back.
+    replayed: Mutex<HashMap<u64, usize>>,
+    /// Connected users of the other instances, and when each last reported.
+    remote_presence: Mutex<HashMap<String, (Instant, HashSet<String>)>>,
+}
+
+/// The in-memory state envelopes from the other instances update.
+struct Local {
+    broadcaster: Broadcaster,
+    sessions: SessionStore,
+    access_cache: AccessCache,
+    bot_tokens: BotTokens,
+}
+
+static BUS: OnceLock<Arc<Bus>> = OnceLock::new();
+
+/// Whether another instance reported `user_id` connected recently.
+pub fn connected_elsewhere(user_id: &str) -> bool {
+    let Some(bus) = BUS.get() else {
+        return false;
+    };
+    bus.remote_presence
+        .lock()
+        .unwrap()
+        .values()
+        .any(|(seen, users)| seen.elapsed() < PRESENCE_TTL && users.contains(user_id))
+}
+
+/// Send an envelope to the other instances, if on a bus.
+fn publish(make: impl FnOnce(String) -> Envelope) {
+    let Some(bus) = BUS.get() else {
+        return;
+    };
+    bus.send(make);
+}
+
+/// Sessions just revoked here.
+pub(crate) fn publish_revoked(sessions: Vec<String>) {
+    if !sessions.is_empty() {
+        publish(|origin| Envelope::Revoked { origin, sessions });
+    }
+}
+
+/// A change just made to the access cache.
+pub(crate) fn publish_cache_change(change: CacheChange) {
+    publish(|origin| Envelope::Cache { origin, change });
+}
+
+/// A bot token just created, reset or revoked.
+pub(crate) fn publish_bot_token(change: BotTokenChange) {
+    publish(|origin| Envelope::BotToken { origin, change });
+}
+
+fn hash_event(event: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    event.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Bus {
+    fn send(&self, make: impl FnOnce(String) -> Envelope) {
+        if let Some(outgoing) = &self.outgoing {
+            let _ = outgoing.send(serde_json::to_string(&make(self.instance.clone())).unwrap_or_default());
+        }
+    }
+
+    /// An event leaving the local broadcaster: `false` for one the bus replayed.
+    fn is_local(&self, event: &str) -> bool {
+        let mut replayed = self.replayed.lock().unwrap();
+        let hash = hash_event(event);
+        match replayed.get_mut(&hash) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                replayed.remove(&hash);
+            }
+            None => return true,
+        }
+        false
+    }
+
+    fn receive(&self, payload: &str, local: &Local) {
+        let Ok(envelope) = serde_json::from_str::<Envelope>(payload) else {
+            return;
+        };
+        match envelope {
+            Envelope::Event { origin, .. }
+            | Envelope::Presence { origin, .. }
+            | Envelope::Revoked { origin, .. }
+            | Envelope::Cache { origin, .. }
+            | Envelope::BotToken { origin, .. }
+                if origin == self.instance => {}
+            Envelope::Event { event, .. } => {
+                let mut replayed = self.replayed.lock().unwrap();
+                if replayed.len() >= MAX_REPLAYED {
+                    replayed.clear();
+                }
+                *replayed.entry(hash_event(&event)).or_default() += 1;
+                drop(replayed);
+                let _ = local.broadcaster.send(event);
+            }
+            Envelope::Presence { origin, users } => {
+                self.remote_presence.lock().unwrap().insert(origin, (Instant::now(), users.into_iter().collect()));
+            }
+            Envelope::Revoked { sessions, .. } => crate::sessions::mark_revoked(&local.sessions, sessions),
+            Envelope::Cache { change, .. } => crate::ws::apply_cache_change(&local.access_cache, change),
+            Envelope::BotToken { change, .. } => crate::bots::apply_token_change(&local.bot_tokens, change),
+        }
+    }
+}
+
+/// Publish what `outgoing` yields, reconnecting as needed. Messages that
+/// fail to go out are dropped: realtime events are not worth replaying late.
+async fn publish_loop(client: redis::Client, channel: String, mut outgoing: mpsc::UnboundedReceiver<String>) {
+    loop {
+        let mut connection = match client.get_multiplexed_async_connection().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("⚠️ Event bus: cannot connect to publish: {e}");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        loop {
+            let Some(payload) = outgoing.recv().await else {
+                return;
+            };
+            if let Err(e) = connection.publish::<_, _, ()>(&channel, payload).await {
+                eprintln!("⚠️ Event bus: publish failed: {e}");
+                break;
+            }
+        }
+    }
+}
+
+async fn subscribe_loop(client: redis::Client, channel: String, bus: Arc<Bus>, local: Local) {
+    loop {
+        let mut pubsub = match client.get_async_pubsub().await {
+            Ok(pubsub) => pubsub,
+            Err(e) => {
+                eprintln!("⚠️ Event bus: cannot connect to subscribe: {e}");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        if let Err(e) = pubsub.subscribe(&channel).await {
+            eprintln!("⚠️ Event bus: cannot subscribe to {channel}: {e}");
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            if let Ok(payload) = message.get_payload::<String>() {
+                bus.receive(&payload, &local);
+            }
+        }
+        eprintln!("⚠️ Event bus: subscription lost, reconnecting");
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Join the bus named by `EVENT_BUS_URL`, if any.
+pub fn spawn_bus(
+    broadcaster: Broadcaster,
+    presence: PresenceTracker,
+    sessions: SessionStore,
+    access_cache: AccessCache,
+    bot_tokens: BotTokens,
+) {
+    let Some(url) = std::env::var("EVENT_BUS_URL").ok().filter(|u| !u.trim().is_empty()) else {
+        return;
+    };
+    let client = match redis::Client::open(url.trim()) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("⚠️ Event bus disabled: invalid EVENT_BUS_URL: {e}");
+            return;
+        }
+    };
+    let channel = std::env::var("EVENT_BUS_CHANNEL")
+        .ok()
+        .filter(|c| !c.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+    let (outgoing, queued) = mpsc::unbounded_channel();
+    let bus = Arc::new(Bus { instance: Uuid::new_v4().to_string(), outgoing: Some(outgoing), ..Bus::default() });
+    let _ = BUS.set(bus.clone());
+    println!("🔀 Event bus on {channel} as instance {}", bus.instance);
+
+    let local = Local { broadcaster: broadcaster.clone(), sessions, access_cache, bot_tokens };
+    actix_web::rt::spawn(publish_loop(client.clone(), channel.clone(), queued));
+    actix_web::rt::spawn(subscribe_loop(client, channel, bus.clone(), local));
+
+    let mut rx = broadcaster.subscribe();
+    let events_bus = bus.clone();
+    actix_web::rt::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) if events_bus.is_local(&event) => events_bus.send(|origin| Envelope::Event { origin, event }),
+                Ok(_) => {}
+                // Events missed here never reach the other instances.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(PRESENCE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let users = crate::presence::connected_here(&presence);
+            bus.send(|origin| Envelope::Presence { origin, users });
+        }
+    });
+}
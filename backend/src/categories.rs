@@ -0,0 +1,413 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Room categories and the order of rooms
+// ═══════════════════════════════════════════════════════
+//
+// Rooms sit either in a category or among the rooms without one, which
+// clients list first. Categories and the rooms of each list are ordered by
+// `position`, kept compact (0, 1, 2...) by every change: creating a room or
+// a category appends it, deleting one closes the gap, and deleting a
+// category moves its rooms, in order, to the end of the rooms without one.
+//
+// `GET /api/server/layout` gives the whole structure as room ids, for the
+// rooms the caller can see (room managers see every room, to move them).
+// Dragging is saved with `PUT /api/server/layout`, which takes the complete
+// layout back and rewrites every position in one transaction; a layout that
+// misses a room or a category (e.g. one created meanwhile) is refused, so a
+// stale client cannot drop anything. Clients refetch the layout on
+// `layout_updated`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::ws::Broadcaster;
+
+const MAX_NAME_CHARS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    pub position: i64,
+    pub created_at: String,
+}
+
+const COLUMNS: &str = "id, name, position, created_at";
+
+#[derive(Debug, Serialize)]
+pub struct LayoutCategory {
+    #[serde(flatten)]
+    pub category: Category,
+    pub room_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Layout {
+    /// Rooms without a category, listed first.
+    pub room_ids: Vec<String>,
+    pub categories: Vec<LayoutCategory>,
+}
+
+/// Renumber the rooms of one list (`None`: the rooms without a category) from 0, keeping their order.
+pub(crate) async fn compact_rooms(conn: &mut sqlx::SqliteConnection, category_id: Option<&str>) -> Result<(), sqlx::Error> {
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM rooms WHERE category_id IS ? ORDER BY position, id")
+        .bind(category_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    for (position, id) in ids.iter().enumerate() {
+        sqlx::query("UPDATE rooms SET position = ? WHERE id = ? AND position != ?")
+            .bind(position as i64)
+            .bind(id)
+            .bind(position as i64)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn compact_categories(conn: &mut sqlx::SqliteConnection) -> Result<(), sqlx::Error> {
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM categories ORDER BY position, id")
+        .fetch_all(&mut *conn)
+        .await?;
+    for (position, id) in ids.iter().enumerate() {
+        sqlx::query("UPDATE categories SET position = ? WHERE id = ?")
+            .bind(position as i64)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Close the gap a deleted room left in its list.
+pub(crate) async fn room_removed(pool: &SqlitePool, category_id: Option<&str>) {
+    if let Ok(mut tx) = pool.begin().await {
+        if compact_rooms(&mut tx, category_id).await.is_ok() {
+            let _ = tx.commit().await;
+        }
+    }
+}
+
+/// Whether `category_id` exists.
+pub(crate) async fn exists(pool: &SqlitePool, category_id: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM categories WHERE id = ?)")
+        .bind(category_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false)
+}
+
+/// The layout as `claims` sees it.
+async fn load_layout(pool: &SqlitePool, claims: &Claims) -> Result<Layout, sqlx::Error> {
+    let manages_rooms = crate::permissions::role_permissions(pool, &claims.role).await & crate::permissions::MANAGE_ROOMS != 0;
+    let visible = if manages_rooms {
+        None
+    } else {
+        Some(crate::permissions::viewable_rooms(pool, &claims.sub, &claims.role).await)
+    };
+
+    let categories = sqlx::query_as::<_, Category>(&format!("SELECT {COLUMNS} FROM categories ORDER BY position, id"))
+        .fetch_all(pool)
+        .await?;
+    let rooms = sqlx::query("SELECT id, category_id FROM rooms ORDER BY position, id")
+        .fetch_all(pool)
+        .await?;
+
+    let mut uncategorized = Vec::new();
+    let mut by_category: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rooms {
+        let id: String = row.get("id");
+        if visible.as_ref().is_some_and(|v| !v.contains(&id)) {
+            continue;
+        }
+        match row.get::<Option<String>, _>("category_id") {
+            Some(category_id) => by_category.entry(category_id).or_default().push(id),
+            None => uncategorized.push(id),
+        }
+    }
+
+    let categories = categories
+        .into_iter()
+        .filter_map(|category| {
+            let room_ids = by_category.remove(&category.id).unwrap_or_default();
+            // Members don't see categories they have no room in.
+            (manages_rooms || !room_ids.is_empty()).then_some(LayoutCategory { category, room_ids })
+        })
+        .collect();
+    Ok(Layout { room_ids: uncategorized, categories })
+}
+
+pub(crate) fn layout_updated(broadcaster: &Broadcaster) {
+    let event = serde_json::json!({ "type": "layout_updated" });
+    let _ = broadcaster.send(event.to_string());
+}
+
+#[allow(clippy::result_large_err)]
+fn normalize_name(raw: &str) -> Result<String, HttpResponse> {
+    let name = raw.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Category name must be 1 to {MAX_NAME_CHARS} characters")
+        })));
+    }
+    Ok(name.to_string())
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/server/layout — Categories and room order, for the rooms the caller can see
+pub async fn get_layout(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match load_layout(pool.get_ref(), &claims).await {
+        Ok(layout) => HttpResponse::Ok().json(layout),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LayoutCategoryInput {
+    pub id: String,
+    pub room_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LayoutInput {
+    pub room_ids: Vec<String>,
+    pub categories: Vec<LayoutCategoryInput>,
+}
+
+/// PUT /api/server/layout — Reorder categories and rooms, and move rooms between categories (MANAGE_ROOMS)
+/// Body: { room_ids, categories: [{ id, room_ids }] }, listing every category and room once
+pub async fn put_layout(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<LayoutInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let incomplete = || {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The layout must list every category and every room exactly once"
+        }))
+    };
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Checked inside the transaction, so nothing is created in between.
+    let category_ids: HashSet<String> = sqlx::query_scalar("SELECT id FROM categories")
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let room_ids: HashSet<String> = sqlx::query_scalar("SELECT id FROM rooms")
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let listed_categories: HashSet<&String> = body.categories.iter().map(|c| &c.id).collect();
+    let listed_rooms: Vec<&String> = body
+        .room_ids
+        .iter()
+        .chain(body.categories.iter().flat_map(|c| c.room_ids.iter()))
+        .collect();
+    let unique_rooms: HashSet<&String> = listed_rooms.iter().copied().collect();
+    if listed_categories.len() != body.categories.len()
+        || listed_categories != category_ids.iter().collect()
+        || unique_rooms.len() != listed_rooms.len()
+        || unique_rooms != room_ids.iter().collect()
+    {
+        return incomplete();
+    }
+
+    let lists = std::iter::once((None, &body.room_ids)).chain(body.categories.iter().map(|c| (Some(&c.id), &c.room_ids)));
+    for (category_id, rooms) in lists {
+        for (position, room_id) in rooms.iter().enumerate() {
+            let moved = sqlx::query("UPDATE rooms SET category_id = ?, position = ? WHERE id = ?")
+                .bind(category_id)
+                .bind(position as i64)
+                .bind(room_id)
+                .execute(&mut *tx)
+                .await;
+            if moved.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+    for (position, category) in body.categories.iter().enumerate() {
+        let moved = sqlx::query("UPDATE categories SET position = ? WHERE id = ?")
+            .bind(position as i64)
+            .bind(&category.id)
+            .execute(&mut *tx)
+            .await;
+        if moved.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    layout_updated(broadcaster.get_ref());
+    match load_layout(pool.get_ref(), &claims).await {
+        Ok(layout) => HttpResponse::Ok().json(layout),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryInput {
+    pub name: String,
+}
+
+/// POST /api/server/categories — Create a category, after the others (MANAGE_ROOMS)
+pub async fn create_category(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<CategoryInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+    let name = match normalize_name(&body.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(
+        "INSERT INTO categories (id, name, position, created_at) \
+         VALUES (?, ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM categories), ?)"
+    )
+    .bind(&id)
+    .bind(&name)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    layout_updated(broadcaster.get_ref());
+    match sqlx::query_as::<_, Category>(&format!("SELECT {COLUMNS} FROM categories WHERE id = ?"))
+        .bind(&id)
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(category) => HttpResponse::Created().json(category),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// PATCH /api/server/categories/{id} — Rename a category (MANAGE_ROOMS)
+pub async fn rename_category(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<CategoryInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+    let name = match normalize_name(&body.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+
+    let sql = format!("UPDATE categories SET name = ? WHERE id = ? RETURNING {COLUMNS}");
+    match sqlx::query_as::<_, Category>(&sql)
+        .bind(&name)
+        .bind(path.into_inner())
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(category)) => {
+            layout_updated(broadcaster.get_ref());
+            HttpResponse::Ok().json(category)
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Category not found" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/server/categories/{id} — Delete a category; its rooms move to the end of the rooms without one (MANAGE_ROOMS)
+pub async fn delete_category(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let deleted = async {
+        // Offset past the rooms without a category, keeping the category's order.
+        sqlx::query(
+            "UPDATE rooms SET category_id = NULL, \
+             position = position + (SELECT COUNT(*) FROM rooms WHERE category_id IS NULL) + 1000000 \
+             WHERE category_id = ?"
+        )
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+        let deleted = sqlx::query("DELETE FROM categories WHERE id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        compact_rooms(&mut tx, None).await?;
+        compact_categories(&mut tx).await?;
+        Ok::<_, sqlx::Error>(deleted)
+    }
+    .await;
+
+    match deleted {
+        Ok(0) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Category not found" })),
+        Ok(_) => {
+            if tx.commit().await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            layout_updated(broadcaster.get_ref());
+            HttpResponse::NoContent().finish()
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
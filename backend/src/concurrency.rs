@@ -0,0 +1,74 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Optimistic concurrency (ETag / If-Match)
+// ═══════════════════════════════════════════════════════
+//
+// Editable admin resources (rooms, roles, the server config) carry a
+// version. Reads return it as an `ETag`; writes may send it back in
+// `If-Match`. When the resource changed in between, the write is refused
+// with 412 and enough context (current version, current values and the
+// fields in conflict) for the client to merge and retry. Writes without
+// `If-Match` keep the old last-write-wins behaviour.
+
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+
+/// One field the client wanted to change that no longer matches its view.
+#[derive(Debug, Serialize)]
+pub struct FieldConflict {
+    pub field: &'static str,
+    pub current: Option<String>,
+    pub requested: Option<String>,
+}
+
+/// `"<version>"`, the ETag of a versioned resource.
+pub fn etag(version: impl std::fmt::Display) -> String {
+    format!("\"{version}\"")
+}
+
+/// The entity tag sent in `If-Match`, without quotes or weak prefix.
+/// `None` when the header is missing or is `*`.
+pub fn if_match(req: &HttpRequest) -> Option<String> {
+    let raw = req.headers().get("If-Match")?.to_str().ok()?.trim();
+    let tag = raw.strip_prefix("W/").unwrap_or(raw).trim_matches('"');
+    (!tag.is_empty() && tag != "*").then(|| tag.to_string())
+}
+
+/// `If-Match` as an integer version. A tag that is not a number is a 400.
+pub fn expected_version(req: &HttpRequest) -> Result<Option<i64>, String> {
+    match if_match(req) {
+        Some(tag) => tag
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| "Invalid If-Match header (expected a version)".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// 412 response carrying the current state of the resource.
+pub fn precondition_failed(
+    current_version: impl Serialize + std::fmt::Display,
+    current: impl Serialize,
+    diff: impl Serialize,
+) -> HttpResponse {
+    HttpResponse::PreconditionFailed()
+        .insert_header(("ETag", etag(&current_version)))
+        .json(serde_json::json!({
+            "error": "Resource was modified by someone else",
+            "current_version": current_version,
+            "current": current,
+            "diff": diff,
+        }))
+}
+
+/// Conflicts between what the client asked for and the current values.
+pub fn diff_fields(fields: &[(&'static str, Option<&str>, Option<&str>)]) -> Vec<FieldConflict> {
+    fields
+        .iter()
+        .filter(|(_, current, requested)| current != requested)
+        .map(|(field, current, requested)| FieldConflict {
+            field,
+            current: current.map(str::to_string),
+            requested: requested.map(str::to_string),
+        })
+        .collect()
+}
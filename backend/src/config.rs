@@ -0,0 +1,287 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Server settings (file + environment)
+// ═══════════════════════════════════════════════════════
+//
+// The settings the server can't start without (where it listens, its
+// database and its secrets) and the limits handlers read from `Settings`.
+// They come from a YAML file, then environment variables override it field
+// by field, so a `.env` or a container's environment keeps working with no
+// file at all:
+//
+//   server:                        # env
+//     port: 8080                   # PORT
+//     bind_address: 0.0.0.0        # BIND_ADDRESS
+//     public_base_url: https://... # PUBLIC_BASE_URL
+//   database:
+//     url: sqlite:voxium.db        # DATABASE_URL
+//     max_connections: 16          # DB_MAX_CONNECTIONS
+//   secrets:
+//     jwt_secret: ...              # JWT_SECRET
+//     encryption_key: ...          # ENCRYPTION_KEY
+//   limits:
+//     pins_per_room: 50            # PINS_PER_ROOM_LIMIT
+//
+// The file is `--config <path>`, else `VOXIUM_CONFIG`, else `voxium.yaml`
+// when it exists. `validate` runs at startup: errors stop the server,
+// warnings are printed. `backend --check-config` only loads and validates.
+//
+// Handlers take `web::Data<Settings>`; code without a request (token
+// signing, the public URL of links) reads `current()`. Feature settings
+// (Discord, TURN, bridges, other limits...) stay with their module's env
+// lookups.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const DEFAULT_FILE: &str = "voxium.yaml";
+/// Secrets shorter than this are accepted with a warning.
+pub const MIN_SECRET_CHARS: usize = 32;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub secrets: SecretSettings,
+    pub limits: LimitSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub port: u16,
+    pub bind_address: String,
+    /// Where clients reach the server, for links sent out (emails,
+    /// webhooks, bridges). Defaults to `http://127.0.0.1:<port>`.
+    pub public_base_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub url: String,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretSettings {
+    /// Signs access tokens. Required.
+    pub jwt_secret: String,
+    /// Encrypts stored Discord tokens; linking Discord needs it.
+    pub encryption_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    /// Pinned messages a room can hold.
+    pub pins_per_room: i64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            bind_address: "0.0.0.0".to_string(),
+            public_base_url: None,
+        }
+    }
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: "sqlite:voxium.db".to_string(),
+            max_connections: 16,
+        }
+    }
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        Self { pins_per_room: 50 }
+    }
+}
+
+/// A problem `validate` found; `fatal` ones keep the server from starting.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub field: &'static str,
+    pub message: String,
+    pub fatal: bool,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = if self.fatal { "error" } else { "warning" };
+        write!(f, "{level}: {}: {}", self.field, self.message)
+    }
+}
+
+// ── Loading ─────────────────────────────────────────────
+
+impl Settings {
+    /// The file (`path`, `VOXIUM_CONFIG` or `voxium.yaml`), then the environment.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        dotenvy::dotenv().ok();
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::var("VOXIUM_CONFIG").ok().filter(|p| !p.trim().is_empty()).map(PathBuf::from))
+            .or_else(|| Path::new(DEFAULT_FILE).exists().then(|| PathBuf::from(DEFAULT_FILE)));
+        let mut settings = match path {
+            Some(path) => {
+                let raw = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+                serde_yaml::from_str(&raw).map_err(|e| format!("Invalid {}: {e}", path.display()))?
+            }
+            None => Settings::default(),
+        };
+        settings.apply_env()?;
+        Ok(settings)
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        if let Some(port) = var("PORT") {
+            self.server.port = port.parse().map_err(|_| format!("PORT={port} is not a valid port"))?;
+        }
+        if let Some(bind_address) = var("BIND_ADDRESS") {
+            self.server.bind_address = bind_address;
+        }
+        if let Some(url) = var("PUBLIC_BASE_URL") {
+            self.server.public_base_url = Some(url);
+        }
+        if let Some(url) = var("DATABASE_URL") {
+            self.database.url = url;
+        }
+        if let Some(max) = var("DB_MAX_CONNECTIONS") {
+            self.database.max_connections = max.parse().map_err(|_| format!("DB_MAX_CONNECTIONS={max} is not a number"))?;
+        }
+        if let Some(secret) = var("JWT_SECRET") {
+            self.secrets.jwt_secret = secret;
+        }
+        if let Some(key) = var("ENCRYPTION_KEY") {
+            self.secrets.encryption_key = key;
+        }
+        if let Some(limit) = var("PINS_PER_ROOM_LIMIT") {
+            self.limits.pins_per_room = limit.parse().map_err(|_| format!("PINS_PER_ROOM_LIMIT={limit} is not a number"))?;
+        }
+        Ok(())
+    }
+
+    // ── Validation ──────────────────────────────────────
+
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut issue = |field: &'static str, fatal: bool, message: String| issues.push(Issue { field, message, fatal });
+
+        if self.server.port == 0 {
+            issue("server.port", true, "must be between 1 and 65535".to_string());
+        }
+        let bind = self.server.bind_address.as_str();
+        if bind != "localhost" && bind.parse::<std::net::IpAddr>().is_err() {
+            issue("server.bind_address", true, format!("{bind} is not an IP address"));
+        }
+        if let Some(url) = &self.server.public_base_url {
+            if !is_http_url(url) {
+                issue("server.public_base_url", true, format!("{url} is not an http(s) URL"));
+            }
+        }
+        if !self.database.url.starts_with("sqlite:") {
+            issue("database.url", true, "must be a sqlite: URL".to_string());
+        }
+        if self.database.max_connections == 0 {
+            issue("database.max_connections", true, "must be at least 1".to_string());
+        }
+        if self.limits.pins_per_room < 1 {
+            issue("limits.pins_per_room", true, "must be at least 1".to_string());
+        }
+
+        let secrets = [
+            ("secrets.jwt_secret", &self.secrets.jwt_secret, true),
+            ("secrets.encryption_key", &self.secrets.encryption_key, false),
+        ];
+        for (field, secret, required) in secrets {
+            if secret.is_empty() {
+                let consequence = if required { "the server can't sign tokens" } else { "Discord accounts can't be linked" };
+                issue(field, required, format!("is not set, {consequence}"));
+            } else if secret.chars().count() < MIN_SECRET_CHARS {
+                issue(field, false, format!("is shorter than {MIN_SECRET_CHARS} characters"));
+            }
+        }
+        issues
+    }
+
+    /// `public_base_url`, without a trailing slash.
+    pub fn public_base_url(&self) -> String {
+        match &self.server.public_base_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://127.0.0.1:{}", self.server.port),
+        }
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"));
+    rest.is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/') && !url.chars().any(char::is_whitespace))
+}
+
+// ── Process-wide settings ───────────────────────────────
+
+static CURRENT: OnceLock<Settings> = OnceLock::new();
+
+/// Make `settings` the ones `current` returns. Only the first call counts.
+pub fn install(settings: Settings) {
+    let _ = CURRENT.set(settings);
+}
+
+/// The installed settings; the environment alone when none were (tests).
+pub fn current() -> &'static Settings {
+    CURRENT.get_or_init(|| {
+        let mut settings = Settings::default();
+        let _ = settings.apply_env();
+        settings
+    })
+}
+
+/// `--config <path>` from the command line.
+pub fn path_from_args() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().position(|a| a == "--config").and_then(|i| args.get(i + 1)).map(PathBuf::from)
+}
+
+/// Load the settings for startup: printed warnings, an error on fatal issues.
+pub fn load_for_startup() -> Result<Settings, String> {
+    let settings = Settings::load(path_from_args().as_deref())?;
+    let issues = settings.validate();
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+    if issues.iter().any(|i| i.fatal) {
+        return Err("Invalid configuration, see the errors above".to_string());
+    }
+    Ok(settings)
+}
+
+/// `backend --check-config`: load and validate, exit code 1 on an error.
+pub fn check_cli() -> i32 {
+    match Settings::load(path_from_args().as_deref()) {
+        Err(error) => {
+            eprintln!("error: {error}");
+            1
+        }
+        Ok(settings) => {
+            let issues = settings.validate();
+            for issue in &issues {
+                println!("{issue}");
+            }
+            if issues.iter().any(|i| i.fatal) {
+                1
+            } else {
+                println!("Configuration OK");
+                0
+            }
+        }
+    }
+}
@@ -2,13 +2,14 @@ use aes_gcm::{
     aead::{Aead, KeyInit},
     Aes256Gcm, Nonce,
 };
+use hmac::{Hmac, Mac};
 use rand::{rngs::OsRng, RngCore};
 use sha2::{Sha256, Digest};
-use std::env;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
 pub fn get_key() -> [u8; 32] {
-    let key_str = env::var("ENCRYPTION_KEY").expect("ENCRYPTION_KEY must be set");
+    let key_str = &crate::config::current().secrets.encryption_key;
+    assert!(!key_str.is_empty(), "ENCRYPTION_KEY must be set");
 
     // Derive a proper 32-byte key using SHA-256 regardless of input format.
     // This ensures full 256-bit keyspace even if ENCRYPTION_KEY is a passphrase.
@@ -49,3 +50,23 @@ pub fn decrypt_token(encrypted_data: &str) -> Option<String> {
     let plaintext = cipher.decrypt(nonce, ciphertext).ok()?;
     String::from_utf8(plaintext).ok()
 }
+
+/// HMAC-SHA256 of `data` under `key`.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Signature of something the server hands out and later takes back (file
+/// links, cursors, ban appeal tokens, join challenges), keyed on the JWT
+/// secret. Each kind has its own prefix ("cursor:", "ban-appeal:", ...) or
+/// shape, so one can't pass for another.
+pub fn sign(payload: &str) -> Vec<u8> {
+    hmac_sha256(crate::auth::jwt_secret().as_bytes(), payload.as_bytes())
+}
+
+/// Compare a signature with the expected one in constant time.
+pub fn signatures_match(expected: &[u8], given: &[u8]) -> bool {
+    expected.len() == given.len() && expected.iter().zip(given).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
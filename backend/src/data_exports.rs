@@ -0,0 +1,538 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Personal data export
+// ═══════════════════════════════════════════════════════
+//
+// `POST /api/users/@me/export` queues a copy of everything the account
+// holds, one every `COOLDOWN_HOURS`. The `data_exports` job (see `jobs`)
+// builds the archives one at a time, as ZIP files:
+//   manifest.json         what the archive holds and when it was made
+//   account.json          profile, member profile, email, role, presence, 2FA status
+//   linked_accounts.json  Discord (id and token status, never the tokens),
+//                         Matrix puppet, passkeys (names and dates)
+//   settings.json         notification settings, quiet hours, email
+//                         notifications
+//   sessions.json         sessions with their device, address and dates
+//   messages.json         the user's messages, oldest first, with the
+//                         archive paths of their attachments
+//   media/<id>-<name>     the files the user uploaded
+//
+// The export row carries a `progress` (0-100) clients poll. Archives are
+// kept in file storage (see `files`) under a random key, downloadable by
+// their owner for DATA_EXPORT_TTL_DAYS; the same job then deletes them.
+// An export interrupted by a restart starts over.
+//
+// Config (env):
+//   DATA_EXPORT_TTL_DAYS          days an archive is kept (default 7)
+//   DATA_EXPORT_MAX_MEDIA_BYTES   uploads past this total are listed in
+//                                 the manifest but left out (default 1 GiB)
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::archive::{archive_name, Archive, MAX_ARCHIVE_BYTES};
+use crate::auth::extract_claims;
+use crate::files::FileStorage;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::sudo::Sudo;
+
+const DEFAULT_TTL_DAYS: i64 = 7;
+const DEFAULT_MAX_MEDIA_BYTES: u64 = 1024 * 1024 * 1024;
+const COOLDOWN_HOURS: i64 = 24;
+const MESSAGE_BATCH: i64 = 500;
+const WORKER_INTERVAL: Duration = Duration::from_secs(15);
+
+fn ttl_days() -> i64 {
+    std::env::var("DATA_EXPORT_TTL_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|d| *d >= 1)
+        .unwrap_or(DEFAULT_TTL_DAYS)
+}
+
+fn max_media_bytes() -> u64 {
+    std::env::var("DATA_EXPORT_MAX_MEDIA_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MAX_MEDIA_BYTES)
+        .min(MAX_ARCHIVE_BYTES / 2)
+}
+
+// ── Building exports ────────────────────────────────────
+
+async fn set_progress(pool: &SqlitePool, export_id: &str, progress: i64) {
+    let _ = sqlx::query("UPDATE data_exports SET progress = ? WHERE id = ?")
+        .bind(progress.clamp(0, 100))
+        .bind(export_id)
+        .execute(pool)
+        .await;
+}
+
+async fn account(pool: &SqlitePool, user_id: &str) -> Result<(Value, Value), String> {
+    let user = sqlx::query(
+        "SELECT id, username, email, email_verified_at, role, about, avatar_url, banner_url, avatar_color, \
+         presence_status, custom_status, totp_enabled, created_at, discord_id, discord_token_status, \
+         discord_access_token IS NOT NULL AS discord_linked FROM users WHERE id = ?"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let account = json!({
+        "id": user.get::<String, _>("id"),
+        "username": user.get::<String, _>("username"),
+        "email": user.get::<Option<String>, _>("email"),
+        "email_verified_at": user.get::<Option<String>, _>("email_verified_at"),
+        "role": user.get::<String, _>("role"),
+        "about": user.get::<Option<String>, _>("about"),
+        "avatar_url": user.get::<Option<String>, _>("avatar_url"),
+        "banner_url": user.get::<Option<String>, _>("banner_url"),
+        "avatar_color": user.get::<Option<i64>, _>("avatar_color"),
+        "presence_status": user.get::<String, _>("presence_status"),
+        "custom_status": user.get::<Option<String>, _>("custom_status"),
+        "two_factor_enabled": user.get::<bool, _>("totp_enabled"),
+        "created_at": user.get::<String, _>("created_at"),
+        "member_profile": crate::member_profiles::load(pool, user_id).await,
+    });
+
+    let matrix: Option<(String, String)> = sqlx::query_as("SELECT matrix_user_id, created_at FROM matrix_puppets WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let passkeys: Vec<(String, String, Option<String>)> =
+        sqlx::query_as("SELECT name, created_at, last_used_at FROM passkeys WHERE user_id = ? ORDER BY created_at")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    let linked = json!({
+        "discord": user.get::<Option<String>, _>("discord_id").map(|id| json!({
+            "discord_id": id,
+            "linked": user.get::<bool, _>("discord_linked"),
+            "token_status": user.get::<Option<String>, _>("discord_token_status"),
+        })),
+        "matrix": matrix.map(|(matrix_user_id, created_at)| json!({ "matrix_user_id": matrix_user_id, "created_at": created_at })),
+        "passkeys": passkeys
+            .into_iter()
+            .map(|(name, created_at, last_used_at)| json!({ "name": name, "created_at": created_at, "last_used_at": last_used_at }))
+            .collect::<Vec<_>>(),
+    });
+    Ok((account, linked))
+}
+
+async fn settings(pool: &SqlitePool, user_id: &str) -> Result<Value, String> {
+    let notifications = crate::notification_settings::load(pool, user_id).await.map_err(|e| e.to_string())?;
+    let email = crate::notifications::fetch_preferences(pool, user_id).await.map_err(|e| e.to_string())?;
+    Ok(json!({
+        "notification_settings": notifications,
+        "quiet_hours": crate::quiet_hours::current(pool, user_id).await,
+        "voice_settings": crate::voice_settings::load(pool, user_id).await,
+        "email_notifications": email,
+    }))
+}
+
+async fn sessions(pool: &SqlitePool, user_id: &str) -> Result<Value, String> {
+    let rows = sqlx::query(
+        "SELECT id, device_name, user_agent, ip, created_at, last_seen_at, expires_at, revoked_at FROM sessions \
+         WHERE user_id = ? ORDER BY created_at"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(Value::Array(
+        rows.iter()
+            .map(|row| {
+                json!({
+                    "id": row.get::<String, _>("id"),
+                    "device_name": row.get::<Option<String>, _>("device_name"),
+                    "user_agent": row.get::<Option<String>, _>("user_agent"),
+                    "ip": row.get::<Option<String>, _>("ip"),
+                    "created_at": row.get::<String, _>("created_at"),
+                    "last_seen_at": row.get::<String, _>("last_seen_at"),
+                    "expires_at": row.get::<String, _>("expires_at"),
+                    "revoked_at": row.get::<Option<String>, _>("revoked_at"),
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Write the archive of `user_id` to `path`.
+async fn build(pool: &SqlitePool, storage: &FileStorage, export_id: &str, user_id: &str, path: &Path) -> Result<u64, String> {
+    let mut archive = Archive::create(path).await?;
+    let (account, linked) = account(pool, user_id).await?;
+    archive.add_json("account.json", &account).await?;
+    archive.add_json("linked_accounts.json", &linked).await?;
+    archive.add_json("settings.json", &settings(pool, user_id).await?).await?;
+    archive.add_json("sessions.json", &sessions(pool, user_id).await?).await?;
+    set_progress(pool, export_id, 5).await;
+
+    // Their uploads, including those never sent; quarantined ones are not theirs to have.
+    let uploads = sqlx::query(
+        "SELECT a.id, a.filename, a.message_id, a.created_at, f.hash, f.size, f.content_type FROM attachments a \
+         JOIN files f ON f.hash = a.file_hash \
+         WHERE a.uploader_id = ? AND a.scan_status IS NOT 'quarantined' ORDER BY a.created_at, a.id"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let media_path = |row: &sqlx::sqlite::SqliteRow| {
+        format!("media/{}-{}", row.get::<String, _>("id"), archive_name(&row.get::<String, _>("filename")))
+    };
+    let mut attachments: HashMap<String, Vec<String>> = HashMap::new();
+    for row in &uploads {
+        if let Some(message_id) = row.get::<Option<String>, _>("message_id") {
+            attachments.entry(message_id).or_default().push(media_path(row));
+        }
+    }
+
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE user_id = ? AND deleted_at IS NULL")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut messages = Vec::new();
+    let mut cursor = (String::new(), String::new());
+    loop {
+        let batch = sqlx::query(
+            "SELECT m.id, m.room_id, r.name AS room_name, m.content, m.kind, m.reply_to_id, m.created_at, m.edited_at \
+             FROM messages m LEFT JOIN rooms r ON r.id = m.room_id \
+             WHERE m.user_id = ? AND m.deleted_at IS NULL AND (m.created_at, m.id) > (?, ?) \
+             ORDER BY m.created_at, m.id LIMIT ?"
+        )
+        .bind(user_id)
+        .bind(&cursor.0)
+        .bind(&cursor.1)
+        .bind(MESSAGE_BATCH)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        cursor = (last.get("created_at"), last.get("id"));
+        for row in &batch {
+            let id: String = row.get("id");
+            messages.push(json!({
+                "room_id": row.get::<String, _>("room_id"),
+                "room_name": row.get::<Option<String>, _>("room_name"),
+                "content": row.get::<String, _>("content"),
+                "kind": row.get::<String, _>("kind"),
+                "reply_to_id": row.get::<Option<String>, _>("reply_to_id"),
+                "created_at": row.get::<String, _>("created_at"),
+                "edited_at": row.get::<Option<String>, _>("edited_at"),
+                "attachments": attachments.remove(&id).unwrap_or_default(),
+                "id": id,
+            }));
+        }
+        set_progress(pool, export_id, 5 + 45 * messages.len() as i64 / total.max(1)).await;
+    }
+    let message_count = messages.len();
+    archive.add_json("messages.json", &Value::Array(messages)).await?;
+    set_progress(pool, export_id, 50).await;
+
+    let budget = max_media_bytes();
+    let mut media_bytes = 0u64;
+    let mut included = 0;
+    let mut left_out = Vec::new();
+    for (done, row) in uploads.iter().enumerate() {
+        let name = media_path(row);
+        let size = row.get::<i64, _>("size").max(0) as u64;
+        let bytes = if media_bytes + size <= budget && archive.fits(&name, size) {
+            storage.get(&row.get::<String, _>("hash")).await
+        } else {
+            None
+        };
+        match bytes {
+            Some(bytes) => {
+                media_bytes += bytes.len() as u64;
+                archive.add(&name, &bytes).await?;
+                included += 1;
+            }
+            None => left_out.push(json!({
+                "path": name,
+                "size": size,
+                "reason": if media_bytes + size > budget { "size limit" } else { "unavailable" },
+            })),
+        }
+        set_progress(pool, export_id, 50 + 45 * (done as i64 + 1) / uploads.len() as i64).await;
+    }
+
+    archive
+        .add_json("manifest.json", &json!({
+            "export_id": export_id,
+            "user_id": user_id,
+            "created_at": Utc::now().to_rfc3339(),
+            "messages": message_count,
+            "media": included,
+            "media_bytes": media_bytes,
+            "media_left_out": left_out,
+        }))
+        .await?;
+    archive.finish().await
+}
+
+/// Take the oldest pending export, if any.
+async fn claim(pool: &SqlitePool) -> Result<Option<(String, String)>, String> {
+    sqlx::query_as(
+        "UPDATE data_exports SET status = 'running', started_at = ?, progress = 0 \
+         WHERE id = (SELECT id FROM data_exports WHERE status = 'pending' ORDER BY created_at, id LIMIT 1) \
+         RETURNING id, user_id"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+async fn run_export(pool: &SqlitePool, storage: &FileStorage, export_id: &str, user_id: &str) -> Result<(String, u64), String> {
+    let dir = storage.spool_dir();
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    let path = dir.join(format!("export-{export_id}.zip"));
+    let stored = async {
+        let size = build(pool, storage, export_id, user_id, &path).await?;
+        // Random, so the archive can't be found from anything public.
+        let key = crate::files::sha256_hex(Uuid::new_v4().as_bytes());
+        storage.put_file(&key, &path).await?;
+        Ok((key, size))
+    }
+    .await;
+    tokio::fs::remove_file(&path).await.ok();
+    stored
+}
+
+/// Delete expired archives, then build every pending export. Returns how
+/// many exports were built or deleted.
+pub async fn process_exports(pool: &SqlitePool, storage: &FileStorage) -> Result<u64, String> {
+    let now = Utc::now();
+    let expired: Vec<(String, Option<String>)> =
+        sqlx::query_as("SELECT id, archive_key FROM data_exports WHERE status = 'ready' AND expires_at <= ?")
+            .bind(now.to_rfc3339())
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    let mut processed = 0;
+    for (id, key) in expired {
+        if let Some(key) = key {
+            storage.delete(&key).await;
+        }
+        sqlx::query("UPDATE data_exports SET status = 'expired', archive_key = NULL WHERE id = ?")
+            .bind(&id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        processed += 1;
+    }
+
+    let mut failure = None;
+    while let Some((export_id, user_id)) = claim(pool).await? {
+        let finished = Utc::now();
+        let result = match run_export(pool, storage, &export_id, &user_id).await {
+            Ok((key, size)) => sqlx::query(
+                "UPDATE data_exports SET status = 'ready', progress = 100, archive_key = ?, size_bytes = ?, \
+                 completed_at = ?, expires_at = ? WHERE id = ?"
+            )
+            .bind(key)
+            .bind(size as i64)
+            .bind(finished.to_rfc3339())
+            .bind((finished + chrono::Duration::days(ttl_days())).to_rfc3339())
+            .bind(&export_id),
+            Err(e) => {
+                failure.get_or_insert(format!("export {export_id}: {e}"));
+                sqlx::query("UPDATE data_exports SET status = 'failed', error = ?, completed_at = ? WHERE id = ?")
+                    .bind(e)
+                    .bind(finished.to_rfc3339())
+                    .bind(&export_id)
+            }
+        };
+        result.execute(pool).await.map_err(|e| e.to_string())?;
+        processed += 1;
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(processed),
+    }
+}
+
+async fn export_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query("SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM data_exports WHERE status = 'pending'")
+        .fetch_one(pool)
+        .await
+        .map(|row| Backlog::from_row(&row))
+        .unwrap_or_default()
+}
+
+pub fn spawn_export_worker(pool: SqlitePool, storage: FileStorage, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::DATA_EXPORTS);
+    actix_web::rt::spawn(async move {
+        // Cut short by the last shutdown: start them over.
+        let _ = sqlx::query("UPDATE data_exports SET status = 'pending', progress = 0 WHERE status = 'running'")
+            .execute(&pool)
+            .await;
+        let mut interval = tokio::time::interval(WORKER_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::DATA_EXPORTS, None, process_exports(&pool, &storage), export_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct DataExport {
+    pub id: String,
+    /// `pending`, `running`, `ready`, `failed` or `expired`.
+    pub status: String,
+    pub progress: i64,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+    pub expires_at: Option<String>,
+    /// While `ready`.
+    pub download_url: Option<String>,
+}
+
+const EXPORT_SELECT: &str =
+    "SELECT id, status, progress, size_bytes, error, created_at, completed_at, expires_at FROM data_exports";
+
+impl DataExport {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let id: String = row.get("id");
+        let status: String = row.get("status");
+        DataExport {
+            download_url: (status == "ready").then(|| format!("/api/users/@me/exports/{id}/download")),
+            id,
+            status,
+            progress: row.get("progress"),
+            size_bytes: row.get("size_bytes"),
+            error: row.get("error"),
+            created_at: row.get("created_at"),
+            completed_at: row.get("completed_at"),
+            expires_at: row.get("expires_at"),
+        }
+    }
+}
+
+async fn fetch_export(pool: &SqlitePool, user_id: &str, export_id: &str) -> Option<DataExport> {
+    sqlx::query(&format!("{EXPORT_SELECT} WHERE id = ? AND user_id = ?"))
+        .bind(export_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| DataExport::from_row(&row))
+}
+
+/// POST /api/users/@me/export — Queue an export of the caller's data (sudo)
+pub async fn request_export(Sudo(claims): Sudo, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let now = Utc::now();
+    let latest = sqlx::query("SELECT status, created_at FROM data_exports WHERE user_id = ? ORDER BY created_at DESC LIMIT 1")
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if let Some(latest) = latest {
+        if matches!(latest.get::<String, _>("status").as_str(), "pending" | "running") {
+            return HttpResponse::Conflict().json(json!({ "error": "An export is already in progress" }));
+        }
+        let next = chrono::DateTime::parse_from_rfc3339(&latest.get::<String, _>("created_at"))
+            .map(|at| at.with_timezone(&Utc) + chrono::Duration::hours(COOLDOWN_HOURS))
+            .unwrap_or(now);
+        if next > now {
+            let retry_after = (next - now).num_seconds().max(1);
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after.to_string()))
+                .json(json!({ "error": "One export per day", "retry_after": retry_after }));
+        }
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let inserted = sqlx::query("INSERT INTO data_exports (id, user_id, status, progress, created_at) VALUES (?, ?, 'pending', 0, ?)")
+        .bind(&id)
+        .bind(&claims.sub)
+        .bind(now.to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match fetch_export(pool.get_ref(), &claims.sub, &id).await {
+        Some(export) => HttpResponse::Accepted().json(export),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/users/@me/exports — The caller's exports, newest first
+pub async fn list_exports(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let rows = sqlx::query(&format!("{EXPORT_SELECT} WHERE user_id = ? ORDER BY created_at DESC"))
+        .bind(&claims.sub)
+        .fetch_all(pool.get_ref())
+        .await;
+    match rows {
+        Ok(rows) => HttpResponse::Ok().json(json!({ "exports": rows.iter().map(DataExport::from_row).collect::<Vec<_>>() })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/users/@me/exports/{id} — One export, to poll its progress
+pub async fn get_export(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match fetch_export(pool.get_ref(), &claims.sub, &path.into_inner()).await {
+        Some(export) => HttpResponse::Ok().json(export),
+        None => HttpResponse::NotFound().json(json!({ "error": "Export not found" })),
+    }
+}
+
+/// GET /api/users/@me/exports/{id}/download — The archive of a ready export
+pub async fn download_export(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let export_id = path.into_inner();
+    let archive: Option<(Option<String>, Option<i64>)> = sqlx::query_as(
+        "SELECT archive_key, size_bytes FROM data_exports WHERE id = ? AND user_id = ? AND status = 'ready' AND expires_at > ?"
+    )
+    .bind(&export_id)
+    .bind(&claims.sub)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some((Some(key), Some(size))) = archive else {
+        return HttpResponse::NotFound().json(json!({ "error": "Export not found" }));
+    };
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("voxium-export-{export_id}.zip"))],
+        })
+        .insert_header(("Cache-Control", "private, no-store"))
+        .streaming(storage.get_ref().clone().stream(key, size.max(0) as u64))
+}
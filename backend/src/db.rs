@@ -1,15 +1,12 @@
 use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
 use std::path::Path;
 
+use crate::config::DatabaseSettings;
+
 /// Create the SQLite connection pool and run migrations.
-pub async fn init_db() -> SqlitePool {
-    dotenvy::dotenv().ok();
-    let database_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:voxium.db".into());
-    let max_connections = std::env::var("DB_MAX_CONNECTIONS")
-        .ok()
-        .and_then(|v| v.parse::<u32>().ok())
-        .filter(|v| *v > 0)
-        .unwrap_or(16);
+pub async fn init_db(settings: &DatabaseSettings) -> SqlitePool {
+    let database_url = settings.url.as_str();
+    let max_connections = settings.max_connections;
 
     // Create the DB file if it doesn't exist
     let db_path = database_url.trim_start_matches("sqlite:");
@@ -19,7 +16,7 @@ pub async fn init_db() -> SqlitePool {
 
     let pool = SqlitePoolOptions::new()
         .max_connections(max_connections)
-        .connect(&database_url)
+        .connect(database_url)
         .await
         .expect("Failed to connect to SQLite");
 
@@ -39,35 +36,216 @@ pub async fn init_db() -> SqlitePool {
         .execute(&pool)
         .await;
 
-    let migrations = [
-        include_str!("../../migrations/001_init.sql"),
-        include_str!("../../migrations/002_add_settings.sql"),
-        include_str!("../../migrations/003_add_images.sql"),
-        include_str!("../../migrations/004_add_avatar_url.sql"),
-        include_str!("../../migrations/005_add_room_kind.sql"),
-        include_str!("../../migrations/006_add_banner_url.sql"),
-        include_str!("../../migrations/007_add_room_required_role.sql"),
-        include_str!("../../migrations/008_add_message_reply.sql"),
-        include_str!("../../migrations/009_add_message_pins.sql"),
-        include_str!("../../migrations/010_add_server_roles.sql"),
-        include_str!("../../migrations/011_add_message_reactions.sql"),
-        include_str!("../../migrations/012_add_perf_indexes.sql"),
-        include_str!("../../migrations/013_add_discord_oauth.sql"),
-    ];
-
-    for sql in migrations {
-        run_migration_sql(sql, &pool).await;
+    if let Err(e) = run_migrations(&pool).await {
+        eprintln!("❌ {e}");
+        std::process::exit(1);
     }
 
     println!("✅ Database initialized");
     pool
 }
 
-async fn run_migration_sql(sql_content: &str, pool: &SqlitePool) {
-        for statement in sql_content.split(';') {
-                let trimmed = statement.trim();
-                if !trimmed.is_empty() {
-                        sqlx::query(trimmed).execute(pool).await.ok();
-                }
+// ── Migrations ──────────────────────────────────────────
+//
+// Each file in `migrations/` runs once, in its own transaction, and is
+// recorded in `schema_migrations` with a checksum. A failing migration rolls
+// back and stops the server instead of leaving the schema half-applied.
+// Append new files to `MIGRATIONS`; never edit, renumber or remove one that
+// has shipped.
+
+macro_rules! migration {
+    ($name:literal) => {
+        ($name, include_str!(concat!("../../migrations/", $name, ".sql")))
+    };
+}
+
+const MIGRATIONS: &[(&str, &str)] = &[
+    migration!("001_init"),
+    migration!("002_add_settings"),
+    migration!("003_add_images"),
+    migration!("004_add_avatar_url"),
+    migration!("005_add_room_kind"),
+    migration!("006_add_banner_url"),
+    migration!("007_add_room_required_role"),
+    migration!("008_add_message_reply"),
+    migration!("009_add_message_pins"),
+    migration!("010_add_server_roles"),
+    migration!("011_add_message_reactions"),
+    migration!("012_add_perf_indexes"),
+    migration!("013_add_discord_oauth"),
+    migration!("014_add_message_quotes"),
+    migration!("015_add_relationships"),
+    migration!("016_add_room_metadata"),
+    migration!("017_add_discord_token_status"),
+    migration!("018_add_sessions"),
+    migration!("019_add_doctor_probe"),
+    migration!("020_add_refresh_tokens"),
+    migration!("021_add_passkeys"),
+    migration!("022_add_resource_versions"),
+    migration!("023_add_totp"),
+    migration!("024_add_auth_audit"),
+    migration!("025_add_email_verification"),
+    migration!("026_add_account_recovery"),
+    migration!("027_add_message_keyset_index"),
+    migration!("028_add_read_states"),
+    migration!("029_add_role_display"),
+    migration!("030_add_announcements"),
+    migration!("031_add_message_revisions"),
+    migration!("032_add_custom_emojis"),
+    migration!("033_add_attachments"),
+    migration!("034_add_qr_session_descriptors"),
+    migration!("035_add_voice_messages"),
+    migration!("036_add_link_embeds"),
+    migration!("037_add_polls"),
+    migration!("038_add_slowmode"),
+    migration!("039_add_voice_priority"),
+    migration!("040_add_alt_text"),
+    migration!("041_add_presence"),
+    migration!("042_add_emoji_usage"),
+    migration!("043_add_voice_occupancy"),
+    migration!("044_add_guest_access"),
+    migration!("045_add_permission_overwrites"),
+    migration!("046_add_role_permissions"),
+    migration!("047_add_server_rules"),
+    migration!("048_add_room_categories"),
+    migration!("049_add_forum_posts"),
+    migration!("050_add_lockdowns"),
+    migration!("051_add_audit_log"),
+    migration!("052_add_pin_checks"),
+    migration!("053_add_bans"),
+    migration!("054_add_automod"),
+    migration!("055_add_timeouts"),
+    migration!("056_add_verification"),
+    migration!("057_add_webhooks"),
+    migration!("058_add_bots"),
+    migration!("059_add_discord_bridges"),
+    migration!("060_add_matrix_bridge"),
+    migration!("061_add_image_assets"),
+    migration!("062_add_upload_scans"),
+    migration!("063_add_email_notifications"),
+    migration!("064_add_notification_settings"),
+    migration!("065_add_quiet_hours"),
+    migration!("066_add_data_exports"),
+    migration!("067_add_discord_imports"),
+    migration!("068_add_room_archives"),
+    migration!("069_add_retention"),
+    migration!("070_add_voice_recordings"),
+    migration!("071_add_soundboard"),
+    migration!("072_add_voice_settings"),
+    migration!("073_add_voice_member_states"),
+    migration!("074_add_member_profiles"),
+    migration!("075_add_user_banner_images"),
+    migration!("076_add_uploaded_files"),
+    migration!("077_add_password_set"),
+];
+
+/// Databases created before `schema_migrations` existed ran every file on
+/// each start with errors ignored. Files up to this version are adopted
+/// once with the same tolerance (see `adopt_legacy_migration`).
+const LAST_LEGACY_VERSION: i64 = 25;
+
+fn version_of(name: &str) -> i64 {
+    name.split('_')
+        .next()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| panic!("migration {name} has no numeric prefix"))
+}
+
+fn checksum(sql: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(sql.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), String> {
+    let tracked: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'"
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Cannot read the database schema: {e}"))?;
+    let legacy = !tracked
+        && sqlx::query_scalar::<_, bool>(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'users'"
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false);
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, name TEXT NOT NULL, checksum TEXT NOT NULL, applied_at TEXT NOT NULL)"
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Cannot create schema_migrations: {e}"))?;
+
+    let applied: Vec<(i64, String, String)> =
+        sqlx::query_as("SELECT version, name, checksum FROM schema_migrations ORDER BY version")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Cannot read schema_migrations: {e}"))?;
+
+    if let Some((version, name, _)) = applied.last() {
+        let known = MIGRATIONS.last().map(|(n, _)| version_of(n)).unwrap_or(0);
+        if *version > known {
+            return Err(format!(
+                "Database is at migration {name}, newer than this build knows (up to {known}). Run a newer backend."
+            ));
         }
+    }
+
+    for (name, sql) in MIGRATIONS {
+        let version = version_of(name);
+        if let Some((_, _, recorded)) = applied.iter().find(|(v, _, _)| *v == version) {
+            if *recorded != checksum(sql) {
+                eprintln!("⚠️  Migration {name} changed since it was applied, the change is not re-run");
+            }
+            continue;
+        }
+
+        if legacy && version <= LAST_LEGACY_VERSION {
+            adopt_legacy_migration(pool, name, sql).await?;
+        } else {
+            apply_migration(pool, name, sql).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn record_migration(conn: &mut sqlx::SqliteConnection, name: &str, sql: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO schema_migrations (version, name, checksum, applied_at) VALUES (?, ?, ?, ?)")
+        .bind(version_of(name))
+        .bind(name)
+        .bind(checksum(sql))
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+async fn apply_migration(pool: &SqlitePool, name: &str, sql: &str) -> Result<(), String> {
+    let fail = |e: sqlx::Error| format!("Migration {name} failed and was rolled back: {e}");
+    let mut tx = pool.begin().await.map_err(fail)?;
+    sqlx::raw_sql(sql).execute(&mut *tx).await.map_err(fail)?;
+    record_migration(&mut tx, name, sql).await.map_err(fail)?;
+    tx.commit().await.map_err(fail)?;
+    println!("🗃️  Applied migration {name}");
+    Ok(())
+}
+
+/// Re-run a file on a database from the old runner, statement by statement.
+/// Columns and tables it already has are expected, any other error is not.
+async fn adopt_legacy_migration(pool: &SqlitePool, name: &str, sql: &str) -> Result<(), String> {
+    let fail = |e: sqlx::Error| format!("Migration {name} failed and was rolled back: {e}");
+    let mut tx = pool.begin().await.map_err(fail)?;
+    for statement in sql.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        if let Err(e) = sqlx::query(statement).execute(&mut *tx).await {
+            let message = e.to_string();
+            if !message.contains("duplicate column name") && !message.contains("already exists") {
+                return Err(fail(e));
+            }
+        }
+    }
+    record_migration(&mut tx, name, sql).await.map_err(fail)?;
+    tx.commit().await.map_err(fail)?;
+    Ok(())
 }
@@ -2,36 +2,39 @@
 //  Voxium — Discord Gateway (per-user) for voice joining
 // ═══════════════════════════════════════════════════════
 //
-// This module manages per-user Discord Gateway WebSocket connections.
+// This module manages per-account Discord Gateway WebSocket connections.
 // It is used to send Update Voice State (op 4) so the user can join
 // Discord voice channels. The gateway returns VOICE_STATE_UPDATE and
 // VOICE_SERVER_UPDATE events which contain the information needed to
 // connect to the Discord Voice Gateway.
+//
+// The connection itself (identify, heartbeat, the voice join handshake) is
+// the `voxium-discord-gateway` crate; this module keeps one client per
+// account, feeds the voice presence cache and the session log from its
+// event stream, and exposes it over HTTP.
+//
+// Sessions are keyed by Discord user id: Voxium users sharing one Discord
+// account (a community account) share one connection instead of each
+// identifying separately. Every user holding a session counts as one
+// reference; the connection closes when the last one lets go (unlinking).
 
 use actix_web::{web, HttpRequest, HttpResponse};
-use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, SqlitePool};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot, Mutex};
-use tokio_tungstenite::tungstenite::Message;
+use tokio::sync::Mutex;
+use voxium_discord_gateway::{GatewayConfig, GatewayError, GatewayEvent, GatewayEvents, GatewayHandle, IdentifyOptions, LogLevel};
 
+use crate::audit::{AuditContext, AuthEvent};
 use crate::auth::extract_claims;
+use crate::sessions::DeviceInfo;
 
-const DISCORD_GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=9&encoding=json";
+pub(crate) use voxium_discord_gateway::DISCORD_GATEWAY_URL;
+pub use voxium_discord_gateway::VoiceServerInfo;
 
 // ── Types ───────────────────────────────────────────────
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VoiceServerInfo {
-    pub token: String,
-    pub endpoint: Option<String>,
-    pub guild_id: Option<String>,
-    pub session_id: String,
-    pub user_id: String,
-}
-
 #[derive(Debug, Clone, Serialize)]
 pub struct VoiceParticipant {
     pub user_id: String,
@@ -51,567 +54,496 @@ pub struct VoiceLeavePayload {
     pub guild_id: String,
 }
 
-// Commands sent from HTTP handlers to the gateway task
-#[derive(Debug)]
-enum GatewayCommand {
-    JoinVoice {
-        guild_id: String,
-        channel_id: String,
-        reply: oneshot::Sender<Result<VoiceServerInfo, String>>,
-    },
-    LeaveVoice {
-        guild_id: String,
-        reply: oneshot::Sender<Result<(), String>>,
-    },
+#[derive(Debug, Clone, Copy)]
+enum CommandKind {
+    Join,
+    Leave,
+}
+
+impl CommandKind {
+    /// How long a handler waits for the gateway, from
+    /// `DISCORD_GW_JOIN_TIMEOUT_SECS` (20 s, to allow for identify + voice
+    /// join) and `DISCORD_GW_LEAVE_TIMEOUT_SECS` (5 s). A join given up on
+    /// is abandoned by the client, which leaves the channel if need be.
+    fn timeout(self) -> std::time::Duration {
+        let (var, default_secs) = match self {
+            Self::Join => ("DISCORD_GW_JOIN_TIMEOUT_SECS", 20),
+            Self::Leave => ("DISCORD_GW_LEAVE_TIMEOUT_SECS", 5),
+        };
+        let secs = std::env::var(var)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|n: &u64| *n > 0)
+            .unwrap_or(default_secs);
+        std::time::Duration::from_secs(secs)
+    }
 }
 
 pub struct GatewaySession {
-    cmd_tx: mpsc::Sender<GatewayCommand>,
+    client: GatewayHandle,
     presence: Arc<Mutex<VoicePresenceState>>,
+    log: GatewayLog,
+    /// Voxium users sharing this connection, its reference count.
+    holders: HashSet<String>,
 }
 
-pub type DiscordGateways = Arc<Mutex<HashMap<String, GatewaySession>>>;
+#[derive(Default)]
+pub struct GatewayPool {
+    /// Discord user id -> session
+    sessions: HashMap<String, GatewaySession>,
+    /// Voxium user id -> Discord user id of the session it holds
+    held_by: HashMap<String, String>,
+}
+
+impl GatewayPool {
+    /// Drop `user_id`'s reference, closing the session if it was the last.
+    fn release(&mut self, user_id: &str) {
+        let Some(key) = self.held_by.remove(user_id) else {
+            return;
+        };
+        if let Some(session) = self.sessions.get_mut(&key) {
+            session.holders.remove(user_id);
+            if session.holders.is_empty() {
+                self.sessions.remove(&key);
+            }
+        }
+    }
+
+    /// A session by Discord user id, or by the id of a Voxium user holding it.
+    fn find(&self, id: &str) -> Option<(&String, &GatewaySession)> {
+        let key = self.held_by.get(id).map(String::as_str).unwrap_or(id);
+        self.sessions.get_key_value(key)
+    }
+}
+
+pub type DiscordGateways = Arc<Mutex<GatewayPool>>;
 
 pub fn create_discord_gateways() -> DiscordGateways {
-    Arc::new(Mutex::new(HashMap::new()))
+    Arc::new(Mutex::new(GatewayPool::default()))
+}
+
+// ── Voice presence cache ────────────────────────────────
+//
+// Discord sends the voice states of every guild the account is in, most of
+// which Voxium never looks at. A guild is kept for as long as it is of
+// interest: queried (participants, preflight, a join) within
+// `PRESENCE_INTEREST_TTL`, or the account itself is in one of its channels.
+// Other guilds are only kept up to `DISCORD_GW_PRESENCE_MAX_GUILDS` (25 by
+// default, 0 keeps none), the least recently updated evicted first.
+
+const PRESENCE_INTEREST_TTL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+fn presence_max_guilds() -> usize {
+    std::env::var("DISCORD_GW_PRESENCE_MAX_GUILDS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(25)
+}
+
+#[derive(Default)]
+struct GuildPresence {
+    // user_id -> participant
+    users: HashMap<String, VoiceParticipant>,
+    updated_at: Option<std::time::Instant>,
+    queried_at: Option<std::time::Instant>,
 }
 
 #[derive(Default)]
 struct VoicePresenceState {
-    // guild_id -> user_id -> participant
-    by_guild: HashMap<String, HashMap<String, VoiceParticipant>>,
+    by_guild: HashMap<String, GuildPresence>,
+    /// The account's Discord user id, from READY.
+    own_user_id: Option<String>,
 }
 
-// ── Gateway task ────────────────────────────────────────
+impl VoicePresenceState {
+    fn users(&self, guild_id: &str) -> Option<&HashMap<String, VoiceParticipant>> {
+        self.by_guild.get(guild_id).map(|g| &g.users)
+    }
 
-async fn run_gateway(
-    discord_token: String,
-    mut cmd_rx: mpsc::Receiver<GatewayCommand>,
-    presence: Arc<Mutex<VoicePresenceState>>,
-) {
-    use tokio_tungstenite::connect_async;
-    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
-    use tokio_tungstenite::tungstenite::http::HeaderValue;
+    fn is_of_interest(&self, guild: &GuildPresence) -> bool {
+        guild.queried_at.is_some_and(|at| at.elapsed() < PRESENCE_INTEREST_TTL)
+            || self.own_user_id.as_ref().is_some_and(|own| guild.users.contains_key(own))
+    }
 
-    let mut request = match DISCORD_GATEWAY_URL.into_client_request() {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("[discord-gw] Failed to build request: {e}");
+    /// Mark `guild_id` as wanted, so its voice states are kept from now on.
+    fn touch(&mut self, guild_id: &str) {
+        self.by_guild.entry(guild_id.to_string()).or_default().queried_at = Some(std::time::Instant::now());
+    }
+
+    /// Apply a VOICE_STATE_UPDATE: `participant` is `None` when the user left.
+    fn update(&mut self, guild_id: &str, user_id: &str, participant: Option<VoiceParticipant>) {
+        let guild = self.by_guild.entry(guild_id.to_string()).or_default();
+        guild.updated_at = Some(std::time::Instant::now());
+        match participant {
+            Some(participant) => {
+                guild.users.insert(user_id.to_string(), participant);
+            }
+            None => {
+                guild.users.remove(user_id);
+            }
+        }
+        self.evict();
+    }
+
+    /// Drop guilds of no interest beyond the cap, least recently updated first.
+    fn evict(&mut self) {
+        let max = presence_max_guilds();
+        let mut idle: Vec<(String, Option<std::time::Instant>)> = self
+            .by_guild
+            .iter()
+            .filter(|(_, guild)| !self.is_of_interest(guild))
+            .map(|(id, guild)| (id.clone(), guild.updated_at))
+            .collect();
+        if idle.len() <= max {
             return;
         }
-    };
-    request.headers_mut().insert("Origin", HeaderValue::from_static("https://discord.com"));
-    request.headers_mut().insert(
-        "User-Agent",
-        HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36"),
-    );
+        idle.sort_by_key(|(_, updated_at)| *updated_at);
+        let excess = idle.len() - max;
+        for (id, _) in idle.into_iter().take(excess) {
+            self.by_guild.remove(&id);
+        }
+    }
 
-    eprintln!("[discord-gw] Connecting to Discord Gateway...");
-    let connect_result = connect_async(request).await;
-    let (ws_stream, _) = match connect_result {
-        Ok(r) => {
-            eprintln!("[discord-gw] Connected to Discord Gateway");
-            r
+    /// Guilds and voice states held, for diagnostics.
+    fn size(&self) -> (usize, usize) {
+        (self.by_guild.len(), self.by_guild.values().map(|g| g.users.len()).sum())
+    }
+}
+
+// ── Identify profile ────────────────────────────────────
+//
+// What a session asks Discord for when it identifies. `full` mimics the
+// desktop client: every capability it advertises and no intents, so READY
+// carries the whole account (read states, settings, every guild with its
+// channels, members and presences) and the socket then streams messages,
+// typing and presence updates for all of them. Voice only needs the session
+// id, voice states and the voice server events, so `voice` advertises the
+// capabilities that shrink READY (deduplicated users, lazy notes, protobuf
+// settings) and subscribes to GUILDS + GUILD_VOICE_STATES only. The cost:
+// a session that looks less like the official client, and nothing else
+// (messages, presences) ever reaches it.
+//
+// Config (env):
+//   DISCORD_GW_PROFILE=full|voice      (default full)
+//   DISCORD_GW_CAPABILITIES=<bitmask>  overrides the profile's capabilities
+//   DISCORD_GW_INTENTS=<bitmask>|none  overrides the profile's intents
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GatewayProfile {
+    Full,
+    Voice,
+}
+
+impl GatewayProfile {
+    fn from_env() -> Self {
+        match std::env::var("DISCORD_GW_PROFILE")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "voice" => Self::Voice,
+            _ => Self::Full,
         }
-        Err(e) => {
-            eprintln!("[discord-gw] Connection failed: {e}");
-            // Drain any pending commands
-            while let Some(cmd) = cmd_rx.recv().await {
-                match cmd {
-                    GatewayCommand::JoinVoice { reply, .. } => {
-                        let _ = reply.send(Err("Gateway connection failed".into()));
-                    }
-                    GatewayCommand::LeaveVoice { reply, .. } => {
-                        let _ = reply.send(Err("Gateway connection failed".into()));
-                    }
-                }
-            }
+    }
+
+    fn identify_options(self) -> IdentifyOptions {
+        let mut options = match self {
+            Self::Full => IdentifyOptions::full(),
+            Self::Voice => IdentifyOptions::voice(),
+        };
+        if let Some(capabilities) = std::env::var("DISCORD_GW_CAPABILITIES").ok().and_then(|v| v.trim().parse().ok()) {
+            options.capabilities = capabilities;
+        }
+        match std::env::var("DISCORD_GW_INTENTS").ok().map(|v| v.trim().to_lowercase()) {
+            Some(v) if v == "none" => options.intents = None,
+            Some(v) => options.intents = v.parse().ok().or(options.intents),
+            None => {}
+        }
+        options
+    }
+}
+
+// ── Session logging ─────────────────────────────────────
+//
+// Each gateway session keeps its recent log lines in a bounded ring buffer
+// that admins can read remotely. The level is per session so one user's
+// connection can be traced at `debug` without flooding stderr for everyone.
+
+/// Lines kept per session.
+const GATEWAY_LOG_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GatewayLogLevel {
+    Off,
+    Error,
+    Info,
+    Debug,
+}
+
+impl From<LogLevel> for GatewayLogLevel {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Self::Error,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+        }
+    }
+}
+
+impl GatewayLogLevel {
+    /// Default for new sessions, from `DISCORD_GW_LOG_LEVEL` (info if unset).
+    fn from_env() -> Self {
+        match std::env::var("DISCORD_GW_LOG_LEVEL")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "off" => Self::Off,
+            "error" => Self::Error,
+            "debug" => Self::Debug,
+            _ => Self::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayLogEntry {
+    pub at: String,
+    pub level: GatewayLogLevel,
+    pub message: String,
+    /// The HTTP request the line was logged for (see `telemetry`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+struct GatewayLogState {
+    level: GatewayLogLevel,
+    entries: VecDeque<GatewayLogEntry>,
+    /// Tokens seen by this session, scrubbed from every line.
+    secrets: Vec<String>,
+    /// The latest request waiting on the session: lines the session task
+    /// logs meanwhile are tagged with it.
+    pending_request: Option<String>,
+}
+
+/// Clears the session's pending request when the handler is done with it,
+/// including when the join is dropped.
+struct PendingRequest {
+    log: GatewayLog,
+    request_id: Option<String>,
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        let mut state = self.log.inner.lock().unwrap();
+        if state.pending_request == self.request_id {
+            state.pending_request = None;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GatewayLog {
+    /// Discord user id of the session.
+    key: String,
+    inner: Arc<std::sync::Mutex<GatewayLogState>>,
+}
+
+impl GatewayLog {
+    fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            inner: Arc::new(std::sync::Mutex::new(GatewayLogState {
+                level: GatewayLogLevel::from_env(),
+                entries: VecDeque::with_capacity(GATEWAY_LOG_CAPACITY),
+                secrets: Vec::new(),
+                pending_request: None,
+            })),
+        }
+    }
+
+    fn add_secret(&self, secret: &str) {
+        if secret.is_empty() {
             return;
         }
-    };
+        let mut state = self.inner.lock().unwrap();
+        if !state.secrets.iter().any(|s| s == secret) {
+            state.secrets.push(secret.to_string());
+        }
+    }
 
-    let (mut ws_tx, mut ws_rx) = ws_stream.split();
-
-    // State
-    let mut heartbeat_interval_ms: u64 = 41250;
-    let mut sequence: Option<u64> = None;
-    let mut session_id: Option<String> = None;
-    let mut identified = false;
-    let mut pending_voice_join: Option<(
-        String, // guild_id
-        String, // channel_id
-        oneshot::Sender<Result<VoiceServerInfo, String>>,
-    )> = None;
-    // Queued join command waiting for READY event
-    let mut queued_join: Option<GatewayCommand> = None;
-    let mut voice_token: Option<String> = None;
-    let mut voice_endpoint: Option<String> = None;
-    let mut voice_guild_id: Option<String> = None;
-    let mut discord_user_id: Option<String> = None;
-
-    // Heartbeat ticker
-    let (hb_tx, mut hb_rx) = mpsc::channel::<()>(1);
-
-    let mut running = true;
-
-    while running {
-        tokio::select! {
-            // Receive from Discord Gateway
-            msg = ws_rx.next() => {
-                match msg {
-                    Some(Ok(Message::Text(text))) => {
-                        let payload: serde_json::Value = match serde_json::from_str(&text) {
-                            Ok(v) => v,
-                            Err(_) => continue,
-                        };
-
-                        let op = payload.get("op").and_then(|v| v.as_u64()).unwrap_or(999);
-
-                        // Update sequence
-                        if let Some(s) = payload.get("s").and_then(|v| v.as_u64()) {
-                            sequence = Some(s);
-                        }
-
-                        match op {
-                            // 10 = Hello
-                            10 => {
-                                if let Some(interval) = payload
-                                    .get("d")
-                                    .and_then(|d| d.get("heartbeat_interval"))
-                                    .and_then(|v| v.as_u64())
-                                {
-                                    heartbeat_interval_ms = interval;
-                                }
-
-                                // Start heartbeat loop
-                                let hb_interval = heartbeat_interval_ms;
-                                let hb_tx_clone = hb_tx.clone();
-                                tokio::spawn(async move {
-                                    let mut interval = tokio::time::interval(
-                                        std::time::Duration::from_millis(hb_interval),
-                                    );
-                                    loop {
-                                        interval.tick().await;
-                                        if hb_tx_clone.send(()).await.is_err() {
-                                            break;
-                                        }
-                                    }
-                                });
-
-                                // Send Identify
-                                if !identified {
-                                    // Intents: GUILDS (1) + GUILD_VOICE_STATES (1<<7=128) = 129
-                                    let identify = serde_json::json!({
-                                        "op": 2,
-                                        "d": {
-                                            "token": discord_token,
-                                            "capabilities": 30717,
-                                            "properties": {
-                                                "os": "Windows",
-                                                "browser": "Chrome",
-                                                "device": "",
-                                                "system_locale": "fr-FR",
-                                                "browser_user_agent": "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
-                                                "browser_version": "131.0.0.0",
-                                                "os_version": "10",
-                                                "referrer": "",
-                                                "referring_domain": "",
-                                                "referrer_current": "",
-                                                "referring_domain_current": "",
-                                                "release_channel": "stable",
-                                                "client_build_number": 366068,
-                                                "client_event_source": serde_json::Value::Null
-                                            },
-                                            "presence": {
-                                                "activities": [],
-                                                "status": "online",
-                                                "since": 0,
-                                                "afk": false
-                                            },
-                                            "compress": false,
-                                            "client_state": {
-                                                "guild_versions": {},
-                                                "highest_last_message_id": "0",
-                                                "read_state_version": 0,
-                                                "user_guild_settings_version": -1,
-                                                "user_settings_version": -1,
-                                                "private_channels_version": "0",
-                                                "api_code_version": 0
-                                            }
-                                        }
-                                    });
-                                    eprintln!("[discord-gw] Sending Identify");
-                                    let _ = ws_tx.send(Message::Text(identify.to_string())).await;
-                                    identified = true;
-                                }
-                            }
-
-                            // 11 = Heartbeat ACK
-                            11 => {
-                                // OK
-                            }
-
-                            // 0 = Dispatch
-                            0 => {
-                                let event_name = payload.get("t").and_then(|v| v.as_str()).unwrap_or("");
-                                let d = payload.get("d");
-
-                                match event_name {
-                                    "READY" | "READY_SUPPLEMENTAL" => {
-                                        if event_name == "READY" {
-                                            if let Some(data) = d {
-                                                session_id = data.get("session_id")
-                                                    .and_then(|v| v.as_str())
-                                                    .map(|s| s.to_string());
-                                                discord_user_id = data.get("user")
-                                                    .and_then(|u| u.get("id"))
-                                                    .and_then(|v| v.as_str())
-                                                    .map(|s| s.to_string());
-                                                eprintln!("[discord-gw] READY — session_id={:?} user_id={:?}", session_id, discord_user_id);
-                                            }
-                                        } else {
-                                            eprintln!("[discord-gw] READY_SUPPLEMENTAL received");
-                                        }
-
-                                        // Process any queued join command
-                                        if let Some(GatewayCommand::JoinVoice { guild_id, channel_id, reply }) = queued_join.take() {
-                                            voice_token = None;
-                                            voice_endpoint = None;
-                                            voice_guild_id = None;
-                                            pending_voice_join = Some((guild_id.clone(), channel_id.clone(), reply));
-
-                                            eprintln!("[discord-gw] Processing queued join: guild={guild_id} channel={channel_id}");
-
-                                            let voice_state = serde_json::json!({
-                                                "op": 4,
-                                                "d": {
-                                                    "guild_id": guild_id,
-                                                    "channel_id": channel_id,
-                                                    "self_mute": false,
-                                                    "self_deaf": false,
-                                                    "self_video": false
-                                                }
-                                            });
-                                            let _ = ws_tx.send(Message::Text(voice_state.to_string())).await;
-                                        }
-                                    }
-
-                                    "VOICE_STATE_UPDATE" => {
-                                        if let Some(data) = d {
-                                            // Update presence cache for UI (all users)
-                                            let guild_id = data.get("guild_id").and_then(|v| v.as_str()).unwrap_or("");
-                                            let channel_id = data.get("channel_id").and_then(|v| v.as_str()).map(|s| s.to_string());
-                                            let event_user_id = data.get("user_id")
-                                                .and_then(|v| v.as_str())
-                                                .or_else(|| data.get("member").and_then(|m| m.get("user")).and_then(|u| u.get("id")).and_then(|v| v.as_str()))
-                                                .unwrap_or("");
-
-                                            if !guild_id.is_empty() && !event_user_id.is_empty() {
-                                                let display_name = data
-                                                    .get("member")
-                                                    .and_then(|m| m.get("nick"))
-                                                    .and_then(|v| v.as_str())
-                                                    .map(|s| s.to_string())
-                                                    .or_else(|| {
-                                                        data.get("member")
-                                                            .and_then(|m| m.get("user"))
-                                                            .and_then(|u| u.get("global_name"))
-                                                            .and_then(|v| v.as_str())
-                                                            .map(|s| s.to_string())
-                                                    })
-                                                    .or_else(|| {
-                                                        data.get("member")
-                                                            .and_then(|m| m.get("user"))
-                                                            .and_then(|u| u.get("username"))
-                                                            .and_then(|v| v.as_str())
-                                                            .map(|s| s.to_string())
-                                                    });
-
-                                                let avatar_hash = data
-                                                    .get("member")
-                                                    .and_then(|m| m.get("user"))
-                                                    .and_then(|u| u.get("avatar"))
-                                                    .and_then(|v| v.as_str())
-                                                    .map(|s| s.to_string());
-
-                                                let avatar_url = avatar_hash.map(|hash| {
-                                                    format!("https://cdn.discordapp.com/avatars/{}/{}.png?size=64", event_user_id, hash)
-                                                });
-
-                                                let mut p = presence.lock().await;
-                                                let guild_map = p.by_guild.entry(guild_id.to_string()).or_default();
-                                                if channel_id.is_none() {
-                                                    guild_map.remove(event_user_id);
-                                                } else {
-                                                    guild_map.insert(
-                                                        event_user_id.to_string(),
-                                                        VoiceParticipant {
-                                                            user_id: event_user_id.to_string(),
-                                                            channel_id: channel_id.clone(),
-                                                            display_name,
-                                                            avatar_url,
-                                                        },
-                                                    );
-                                                }
-                                            }
-
-                                            // Check this is for our user
-                                            let event_user_id = data.get("user_id")
-                                                .and_then(|v| v.as_str())
-                                                .or_else(|| data.get("member").and_then(|m| m.get("user")).and_then(|u| u.get("id")).and_then(|v| v.as_str()))
-                                                .unwrap_or("");
-                                            let our_id = discord_user_id.as_deref().unwrap_or("");
-
-                                            eprintln!("[discord-gw] VOICE_STATE_UPDATE — event_user={} our_user={} channel={:?}",
-                                                event_user_id, our_id,
-                                                data.get("channel_id").and_then(|v| v.as_str()));
-
-                                            if event_user_id == our_id {
-                                                // If VOICE_SERVER_UPDATE already arrived, reply now
-                                                if voice_token.is_some() && voice_endpoint.is_some() {
-                                                    if let Some((_, _, reply)) = pending_voice_join.take() {
-                                                        let info = VoiceServerInfo {
-                                                            token: voice_token.take().unwrap_or_default(),
-                                                            endpoint: voice_endpoint.take(),
-                                                            guild_id: voice_guild_id.take(),
-                                                            session_id: session_id.clone().unwrap_or_default(),
-                                                            user_id: our_id.to_string(),
-                                                        };
-                                                        eprintln!("[discord-gw] Sending voice info to frontend (via VSU): endpoint={:?}", info.endpoint);
-                                                        let _ = reply.send(Ok(info));
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-
-                                    "VOICE_SERVER_UPDATE" => {
-                                        if let Some(data) = d {
-                                            eprintln!("[discord-gw] VOICE_SERVER_UPDATE — endpoint={:?} guild={:?}",
-                                                data.get("endpoint").and_then(|v| v.as_str()),
-                                                data.get("guild_id").and_then(|v| v.as_str()));
-                                            voice_token = data.get("token")
-                                                .and_then(|v| v.as_str())
-                                                .map(|s| s.to_string());
-                                            voice_endpoint = data.get("endpoint")
-                                                .and_then(|v| v.as_str())
-                                                .map(|s| s.to_string());
-                                            voice_guild_id = data.get("guild_id")
-                                                .and_then(|v| v.as_str())
-                                                .map(|s| s.to_string());
-
-                                            // VOICE_SERVER_UPDATE + the gateway session_id from READY
-                                            // is everything we need to connect to the Voice Gateway
-                                            if let Some((_, _, reply)) = pending_voice_join.take() {
-                                                let info = VoiceServerInfo {
-                                                    token: voice_token.take().unwrap_or_default(),
-                                                    endpoint: voice_endpoint.take(),
-                                                    guild_id: voice_guild_id.take(),
-                                                    session_id: session_id.clone().unwrap_or_default(),
-                                                    user_id: discord_user_id.clone().unwrap_or_default(),
-                                                };
-                                                eprintln!("[discord-gw] Sending voice info to frontend: endpoint={:?}", info.endpoint);
-                                                let _ = reply.send(Ok(info));
-                                            }
-                                        }
-                                    }
-
-                                    _ => {
-                                        // Log unhandled dispatch events for debugging
-                                        eprintln!("[discord-gw] Dispatch event: {} (ignored)", event_name);
-                                    }
-                                }
-                            }
-
-                            // 7 = Reconnect
-                            7 => {
-                                eprintln!("[discord-gw] Received Reconnect (op 7)");
-                                running = false;
-                            }
-
-                            // 9 = Invalid Session
-                            9 => {
-                                eprintln!("[discord-gw] Received Invalid Session (op 9)");
-                                running = false;
-                                if let Some((_, _, reply)) = pending_voice_join.take() {
-                                    let _ = reply.send(Err("Discord session invalid".into()));
-                                }
-                            }
-
-                            _ => {}
-                        }
-                    }
-
-                    Some(Ok(Message::Close(frame))) => {
-                        eprintln!("[discord-gw] WS Closed: {:?}", frame);
-                        running = false;
-                    }
-                    None => {
-                        eprintln!("[discord-gw] WS stream ended");
-                        running = false;
-                    }
-
-                    _ => {}
-                }
-            }
+    fn level(&self) -> GatewayLogLevel {
+        self.inner.lock().unwrap().level
+    }
 
-            // Heartbeat timer
-            _ = hb_rx.recv() => {
-                let hb = serde_json::json!({
-                    "op": 1,
-                    "d": sequence
-                });
-                if ws_tx.send(Message::Text(hb.to_string())).await.is_err() {
-                    running = false;
-                }
-            }
+    fn set_level(&self, level: GatewayLogLevel) {
+        self.inner.lock().unwrap().level = level;
+    }
 
-            // Commands from HTTP handlers
-            cmd = cmd_rx.recv() => {
-                match cmd {
-                    Some(GatewayCommand::JoinVoice { guild_id, channel_id, reply }) => {
-                        if session_id.is_none() {
-                            // Gateway not ready yet, queue the command
-                            eprintln!("[discord-gw] Gateway not ready yet, queueing join for guild={guild_id} channel={channel_id}");
-                            queued_join = Some(GatewayCommand::JoinVoice { guild_id, channel_id, reply });
-                            continue;
-                        }
-
-                        // If there's a pending join, cancel it first
-                        if let Some((_, _, old_reply)) = pending_voice_join.take() {
-                            eprintln!("[discord-gw] Cancelling previous pending join");
-                            let _ = old_reply.send(Err("Superseded by new join request".into()));
-                        }
-
-                        // First, leave any current voice channel in this guild
-                        // to ensure Discord sends fresh VOICE_SERVER_UPDATE
-                        eprintln!("[discord-gw] Sending leave before join for guild={guild_id}");
-                        let leave_state = serde_json::json!({
-                            "op": 4,
-                            "d": {
-                                "guild_id": &guild_id,
-                                "channel_id": serde_json::Value::Null,
-                                "self_mute": false,
-                                "self_deaf": false
-                            }
-                        });
-                        let _ = ws_tx.send(Message::Text(leave_state.to_string())).await;
-
-                        // Small delay to let Discord process the leave
-                        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-
-                        eprintln!("[discord-gw] Sending Voice State Update (join): guild={guild_id} channel={channel_id}");
-
-                        // Clear previous voice state
-                        voice_token = None;
-                        voice_endpoint = None;
-                        voice_guild_id = None;
-
-                        // Store pending request
-                        pending_voice_join = Some((guild_id.clone(), channel_id.clone(), reply));
-
-                        // Send Update Voice State (op 4)
-                        let voice_state = serde_json::json!({
-                            "op": 4,
-                            "d": {
-                                "guild_id": guild_id,
-                                "channel_id": channel_id,
-                                "self_mute": false,
-                                "self_deaf": false,
-                                "self_video": false
-                            }
-                        });
-
-                        if ws_tx.send(Message::Text(voice_state.to_string())).await.is_err() {
-                            if let Some((_, _, reply)) = pending_voice_join.take() {
-                                let _ = reply.send(Err("Failed to send voice state update".into()));
-                            }
-                        }
-
-                        // Voice join sent; we wait for the voice events above
-                    }
-
-                    Some(GatewayCommand::LeaveVoice { guild_id, reply }) => {
-                        // Send Update Voice State with channel_id: null
-                        let voice_state = serde_json::json!({
-                            "op": 4,
-                            "d": {
-                                "guild_id": guild_id,
-                                "channel_id": serde_json::Value::Null,
-                                "self_mute": false,
-                                "self_deaf": false
-                            }
-                        });
-
-                        if ws_tx.send(Message::Text(voice_state.to_string())).await.is_err() {
-                            let _ = reply.send(Err("Failed to send voice leave".into()));
-                        } else {
-                            let _ = reply.send(Ok(()));
-                        }
-                    }
-
-                    None => {
-                        running = false;
-                    }
-                }
-            }
+    /// Tag the session's lines with the current request until the guard drops.
+    fn track_request(&self) -> PendingRequest {
+        let request_id = crate::telemetry::current_request_id();
+        if request_id.is_some() {
+            self.inner.lock().unwrap().pending_request = request_id.clone();
+        }
+        PendingRequest { log: self.clone(), request_id }
+    }
+
+    fn entries(&self, limit: usize) -> Vec<GatewayLogEntry> {
+        let state = self.inner.lock().unwrap();
+        let skip = state.entries.len().saturating_sub(limit);
+        state.entries.iter().skip(skip).cloned().collect()
+    }
+
+    fn log(&self, level: GatewayLogLevel, message: impl Into<String>) {
+        let mut state = self.inner.lock().unwrap();
+        if level == GatewayLogLevel::Off || level > state.level {
+            return;
+        }
+
+        let mut message = message.into();
+        for secret in &state.secrets {
+            message = message.replace(secret.as_str(), "[redacted]");
+        }
+
+        let request_id = crate::telemetry::current_request_id().or_else(|| state.pending_request.clone());
+        match &request_id {
+            Some(id) => eprintln!("[discord-gw] [{}] [req {id}] {message}", self.key),
+            None => eprintln!("[discord-gw] [{}] {message}", self.key),
+        }
+
+        if state.entries.len() == GATEWAY_LOG_CAPACITY {
+            state.entries.pop_front();
         }
+        state.entries.push_back(GatewayLogEntry {
+            at: chrono::Utc::now().to_rfc3339(),
+            level,
+            message,
+            request_id,
+        });
     }
 
-    // Cleanup: close the WS and drain pending
-    let _ = ws_tx.close().await;
-    if let Some((_, _, reply)) = pending_voice_join.take() {
-        let _ = reply.send(Err("Gateway connection closed".into()));
+    fn error(&self, message: impl Into<String>) {
+        self.log(GatewayLogLevel::Error, message);
+    }
+
+    fn info(&self, message: impl Into<String>) {
+        self.log(GatewayLogLevel::Info, message);
+    }
+
+    fn debug(&self, message: impl Into<String>) {
+        self.log(GatewayLogLevel::Debug, message);
     }
 }
 
-// ── Ensure a gateway session exists for the user ────────
+// ── Gateway events ──────────────────────────────────────
 
-async fn ensure_gateway(
-    user_id: &str,
-    discord_token: &str,
-    gateways: &DiscordGateways,
-) -> mpsc::Sender<GatewayCommand> {
-    ensure_gateway_session(user_id, discord_token, gateways)
-        .await
-        .0
+/// Follow the client's events until the connection is over: the voice
+/// presence cache, the audit trail and the session log.
+async fn follow_events(
+    mut events: GatewayEvents,
+    presence: Arc<Mutex<VoicePresenceState>>,
+    log: GatewayLog,
+    audit: AuditContext,
+) {
+    while let Some(event) = events.recv().await {
+        match event {
+            // Identify goes out right after hello.
+            GatewayEvent::Hello { .. } => {
+                audit.record(AuthEvent::GatewayIdentify, serde_json::json!({})).await;
+            }
+            GatewayEvent::Ready { user_id, .. } => {
+                presence.lock().await.own_user_id = user_id;
+            }
+            GatewayEvent::VoiceStateUpdate(state) => {
+                let Some(guild_id) = state.guild_id.filter(|g| !g.is_empty()) else {
+                    continue;
+                };
+                let participant = state.channel_id.is_some().then(|| VoiceParticipant {
+                    user_id: state.user_id.clone(),
+                    channel_id: state.channel_id.clone(),
+                    display_name: state.display_name,
+                    avatar_url: state.avatar_url,
+                });
+                presence.lock().await.update(&guild_id, &state.user_id, participant);
+            }
+            GatewayEvent::VoiceServerUpdate(server) => log.add_secret(&server.token),
+            GatewayEvent::Closed(reason) => log.info(format!("Session closed: {reason:?}")),
+            GatewayEvent::ReadySupplemental | GatewayEvent::Dispatch { .. } => {}
+        }
+    }
 }
 
+// ── Ensure a gateway session exists for the account ─────
+
+/// Join the account's session, opening it if there is none (or it died).
+/// `audit` identifies the user and the request the session is opened for,
+/// it is recorded when the new session identifies to Discord.
 async fn ensure_gateway_session(
-    user_id: &str,
-    discord_token: &str,
+    audit: AuditContext,
+    account: &DiscordAccount,
     gateways: &DiscordGateways,
-) -> (mpsc::Sender<GatewayCommand>, Arc<Mutex<VoicePresenceState>>) {
+) -> (GatewayHandle, Arc<Mutex<VoicePresenceState>>, GatewayLog) {
+    let user_id = audit.user_id.clone();
+    let key = account.key.clone();
     let mut map = gateways.lock().await;
 
+    // Linked to another Discord account since: let go of the old session
+    if map.held_by.get(&user_id).is_some_and(|held| *held != key) {
+        map.release(&user_id);
+    }
+    map.held_by.insert(user_id.clone(), key.clone());
+
     // Check if existing session is still alive
-    if let Some(session) = map.get(user_id) {
-        if !session.cmd_tx.is_closed() {
-            return (session.cmd_tx.clone(), session.presence.clone());
+    if let Some(session) = map.sessions.get_mut(&key) {
+        if !session.client.is_closed() {
+            session.holders.insert(user_id);
+            session.log.add_secret(&account.token);
+            return (session.client.clone(), session.presence.clone(), session.log.clone());
         }
-        // Dead session, remove it
-        map.remove(user_id);
     }
 
+    // Dead session: drop it but keep its log (and log level) and holders for the new one
+    let (log, mut holders) = map
+        .sessions
+        .remove(&key)
+        .map(|old| (old.log, old.holders))
+        .unwrap_or_else(|| (GatewayLog::new(&key), HashSet::new()));
+    holders.insert(user_id);
+    log.add_secret(&account.token);
+
     // Create new session
-    let (cmd_tx, cmd_rx) = mpsc::channel(16);
-    let token = discord_token.to_string();
+    let profile = GatewayProfile::from_env();
+    log.info(format!("Opening session — profile={profile:?}"));
+    let log_clone = log.clone();
+    let config = GatewayConfig::new(account.token.clone())
+        .identify(profile.identify_options())
+        .logger(move |level, message| log_clone.log(level.into(), message));
+    let (client, events) = voxium_discord_gateway::connect(config);
     let presence: Arc<Mutex<VoicePresenceState>> = Arc::new(Mutex::new(VoicePresenceState::default()));
-    let presence_clone = presence.clone();
+    tokio::spawn(follow_events(events, presence.clone(), log.clone(), audit));
 
-    tokio::spawn(async move {
-        run_gateway(token, cmd_rx, presence_clone).await;
-    });
-
-    map.insert(
-        user_id.to_string(),
+    map.sessions.insert(
+        key,
         GatewaySession {
-            cmd_tx: cmd_tx.clone(),
+            client: client.clone(),
             presence: presence.clone(),
+            log: log.clone(),
+            holders,
         },
     );
 
-    (cmd_tx, presence)
+    (client, presence, log)
+}
+
+/// Drop the user's reference to its session. The task stops once the last
+/// holder is gone and the command channel closes; other users of the same
+/// Discord account keep the connection.
+pub(crate) async fn close_session(gateways: &DiscordGateways, user_id: &str) {
+    gateways.lock().await.release(user_id);
 }
 
 #[derive(Debug, Deserialize)]
@@ -632,16 +564,17 @@ pub async fn voice_participants(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    let discord_token = match get_discord_token(pool.get_ref(), &claims.sub).await {
-        Ok(t) => t,
+    let account = match get_discord_account(pool.get_ref(), &claims.sub).await {
+        Ok(a) => a,
         Err(e) => {
             return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
         }
     };
 
-    let (_cmd_tx, presence) = ensure_gateway_session(&claims.sub, &discord_token, gateways.get_ref()).await;
-    let p = presence.lock().await;
-    let guild_map = match p.by_guild.get(&query.guild_id) {
+    let (_client, presence, _log) = ensure_gateway_session(gateway_audit(&req, &pool, &claims.sub), &account, gateways.get_ref()).await;
+    let mut p = presence.lock().await;
+    p.touch(&query.guild_id);
+    let guild_map = match p.users(&query.guild_id) {
         Some(m) => m,
         None => {
             return HttpResponse::Ok().json(Vec::<VoiceParticipant>::new());
@@ -656,10 +589,51 @@ pub async fn voice_participants(
     HttpResponse::Ok().json(participants)
 }
 
+/// Discord users the session of `key` has seen in a voice channel, `None`
+/// when that account has no gateway session (nothing is known then).
+pub(crate) async fn voice_channel_members(
+    gateways: &DiscordGateways,
+    key: &str,
+    guild_id: &str,
+    channel_id: &str,
+) -> Option<Vec<String>> {
+    let presence = gateways.lock().await.sessions.get(key)?.presence.clone();
+    let mut p = presence.lock().await;
+    p.touch(guild_id);
+    Some(
+        p.users(guild_id)
+            .map(|users| {
+                users
+                    .values()
+                    .filter(|u| u.channel_id.as_deref() == Some(channel_id))
+                    .map(|u| u.user_id.clone())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    )
+}
+
 // ── Helper: get Discord token for user ──────────────────
 
-async fn get_discord_token(pool: &SqlitePool, user_id: &str) -> Result<String, String> {
-    let row = sqlx::query("SELECT discord_access_token FROM users WHERE id = ?")
+fn gateway_audit(req: &HttpRequest, pool: &SqlitePool, user_id: &str) -> AuditContext {
+    AuditContext {
+        pool: pool.clone(),
+        user_id: user_id.to_string(),
+        device: DeviceInfo::from_request(req),
+    }
+}
+
+/// A user's linked Discord account: its token and the key of its gateway session.
+pub(crate) struct DiscordAccount {
+    pub token: String,
+    /// The Discord user id, or a per-user key for links that predate it.
+    pub key: String,
+}
+
+pub(crate) async fn get_discord_account(pool: &SqlitePool, user_id: &str) -> Result<DiscordAccount, String> {
+    crate::discord_oauth::refresh_if_expired(pool, user_id).await?;
+
+    let row = sqlx::query("SELECT discord_access_token, discord_id FROM users WHERE id = ?")
         .bind(user_id)
         .fetch_optional(pool)
         .await
@@ -670,7 +644,11 @@ async fn get_discord_token(pool: &SqlitePool, user_id: &str) -> Result<String, S
         .try_get("discord_access_token")
         .unwrap_or(None);
 
-    token.ok_or("No Discord token linked".to_string())
+    let token = token.ok_or("No Discord token linked".to_string())?;
+    let token = crate::crypto::decrypt_token(&token).ok_or("Failed to decrypt Discord token".to_string())?;
+    let discord_id: Option<String> = row.try_get("discord_id").unwrap_or(None);
+    let key = discord_id.unwrap_or_else(|| format!("user:{user_id}"));
+    Ok(DiscordAccount { token, key })
 }
 
 // ── HTTP Handlers ───────────────────────────────────────
@@ -689,53 +667,50 @@ pub async fn voice_join(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    let discord_token = match get_discord_token(pool.get_ref(), &claims.sub).await {
-        Ok(t) => t,
+    // Fail early with a clear message when the validator already knows the
+    // token is dead, instead of a gateway timeout.
+    let (link_status, _) = crate::discord_link::stored_status(pool.get_ref(), &claims.sub).await;
+    if link_status.needs_relink() {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Discord link expired or revoked, please re-link your account",
+            "link_status": link_status,
+            "relink_required": true,
+        }));
+    }
+
+    let account = match get_discord_account(pool.get_ref(), &claims.sub).await {
+        Ok(a) => a,
         Err(e) => {
             return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
         }
     };
 
-    let cmd_tx = ensure_gateway(&claims.sub, &discord_token, gateways.get_ref()).await;
-
-    let (reply_tx, reply_rx) = oneshot::channel();
-
-    if cmd_tx
-        .send(GatewayCommand::JoinVoice {
-            guild_id: body.guild_id.clone(),
-            channel_id: body.channel_id.clone(),
-            reply: reply_tx,
-        })
-        .await
-        .is_err()
-    {
-        // Gateway task died, remove from map
-        let mut map = gateways.lock().await;
-        map.remove(&claims.sub);
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Discord Gateway session lost"
-        }));
-    }
-
-    // Wait for the voice server info with a timeout (20s to allow for gateway identify + voice join)
-    eprintln!("[discord-gw] HTTP handler waiting for voice info (20s timeout)...");
-    match tokio::time::timeout(std::time::Duration::from_secs(20), reply_rx).await {
-        Ok(Ok(Ok(info))) => {
-            eprintln!("[discord-gw] HTTP handler returning voice info OK — endpoint={:?}", info.endpoint);
+    let (client, presence, log) = ensure_gateway_session(gateway_audit(&req, &pool, &claims.sub), &account, gateways.get_ref()).await;
+    presence.lock().await.touch(&body.guild_id);
+
+    // Dropping the join (timeout, client gone) abandons it in the gateway.
+    let timeout = CommandKind::Join.timeout();
+    let _pending = log.track_request();
+    log.info(format!("Voice join requested — guild={} channel={}", body.guild_id, body.channel_id));
+    log.debug(format!("HTTP handler waiting for voice info ({}s timeout)...", timeout.as_secs()));
+    match tokio::time::timeout(timeout, client.join_voice(&body.guild_id, &body.channel_id)).await {
+        Ok(Ok(info)) => {
+            log.debug(format!("HTTP handler returning voice info OK — endpoint={:?}", info.endpoint));
             HttpResponse::Ok().json(info)
         }
-        Ok(Ok(Err(e))) => {
-            eprintln!("[discord-gw] HTTP handler returning error from gateway: {e}");
-            HttpResponse::BadGateway().json(serde_json::json!({ "error": e }))
-        }
-        Ok(Err(_)) => {
-            eprintln!("[discord-gw] HTTP handler: oneshot channel dropped");
+        // Gateway task died: the next request replaces the session (keeping its holders)
+        Ok(Err(GatewayError::Closed)) => {
+            log.error("HTTP handler: gateway session lost");
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal channel error"
+                "error": "Discord Gateway session lost"
             }))
         }
+        Ok(Err(e)) => {
+            log.error(format!("HTTP handler returning error from gateway: {e}"));
+            HttpResponse::BadGateway().json(serde_json::json!({ "error": e.to_string() }))
+        }
         Err(_) => {
-            eprintln!("[discord-gw] HTTP handler: TIMEOUT — no voice info in 20s");
+            log.error(format!("HTTP handler: TIMEOUT — no voice info in {}s", timeout.as_secs()));
             HttpResponse::GatewayTimeout().json(serde_json::json!({
                 "error": "Timeout waiting for Discord voice server info"
             }))
@@ -756,41 +731,138 @@ pub async fn voice_leave(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    let discord_token = match get_discord_token(pool.get_ref(), &claims.sub).await {
-        Ok(t) => t,
+    let account = match get_discord_account(pool.get_ref(), &claims.sub).await {
+        Ok(a) => a,
         Err(e) => {
             return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
         }
     };
 
-    let cmd_tx = ensure_gateway(&claims.sub, &discord_token, gateways.get_ref()).await;
+    let (client, _presence, _log) = ensure_gateway_session(gateway_audit(&req, &pool, &claims.sub), &account, gateways.get_ref()).await;
 
-    let (reply_tx, reply_rx) = oneshot::channel();
-
-    if cmd_tx
-        .send(GatewayCommand::LeaveVoice {
-            guild_id: body.guild_id.clone(),
-            reply: reply_tx,
-        })
-        .await
-        .is_err()
-    {
-        let mut map = gateways.lock().await;
-        map.remove(&claims.sub);
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Discord Gateway session lost"
-        }));
-    }
-
-    match tokio::time::timeout(std::time::Duration::from_secs(5), reply_rx).await {
-        Ok(Ok(Ok(()))) => {
+    match tokio::time::timeout(CommandKind::Leave.timeout(), client.leave_voice(&body.guild_id)).await {
+        Ok(Ok(())) => {
             HttpResponse::Ok().json(serde_json::json!({ "ok": true }))
         }
-        Ok(Ok(Err(e))) => {
-            HttpResponse::BadGateway().json(serde_json::json!({ "error": e }))
+        Ok(Err(GatewayError::Closed)) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Discord Gateway session lost"
+        })),
+        Ok(Err(e)) => {
+            HttpResponse::BadGateway().json(serde_json::json!({ "error": e.to_string() }))
         }
-        _ => HttpResponse::InternalServerError().json(serde_json::json!({
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
             "error": "Failed to leave voice"
         })),
     }
 }
+
+// ── Admin diagnostics ───────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct GatewayLogsQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GatewayLogLevelPayload {
+    pub level: GatewayLogLevel,
+}
+
+/// GET /api/server/diagnostics/gateways — List Discord gateway sessions (MANAGE_SERVER)
+pub async fn list_gateway_sessions(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    gateways: web::Data<DiscordGateways>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let listed: Vec<(serde_json::Value, Arc<Mutex<VoicePresenceState>>)> = gateways
+        .lock()
+        .await
+        .sessions
+        .iter()
+        .map(|(discord_user_id, session)| {
+            let mut holders: Vec<&String> = session.holders.iter().collect();
+            holders.sort();
+            let entry = serde_json::json!({
+                "discord_user_id": discord_user_id,
+                "holders": holders,
+                "alive": !session.client.is_closed(),
+                "log_level": session.log.level(),
+            });
+            (entry, session.presence.clone())
+        })
+        .collect();
+
+    let mut sessions = Vec::with_capacity(listed.len());
+    for (mut entry, presence) in listed {
+        let (guilds, voice_states) = presence.lock().await.size();
+        entry["presence_cache"] = serde_json::json!({ "guilds": guilds, "voice_states": voice_states });
+        sessions.push(entry);
+    }
+
+    HttpResponse::Ok().json(sessions)
+}
+
+/// GET /api/server/diagnostics/gateways/{id}/logs — Recent gateway log lines, by Discord or Voxium user id (MANAGE_SERVER)
+pub async fn get_gateway_logs(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    gateways: web::Data<DiscordGateways>,
+    path: web::Path<String>,
+    query: web::Query<GatewayLogsQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let map = gateways.lock().await;
+    let Some((discord_user_id, session)) = map.find(&id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No gateway session for this user" }));
+    };
+
+    let limit = query.limit.unwrap_or(GATEWAY_LOG_CAPACITY).clamp(1, GATEWAY_LOG_CAPACITY);
+    HttpResponse::Ok().json(serde_json::json!({
+        "discord_user_id": discord_user_id,
+        "alive": !session.client.is_closed(),
+        "log_level": session.log.level(),
+        "entries": session.log.entries(limit),
+    }))
+}
+
+/// PUT /api/server/diagnostics/gateways/{id}/log-level — Change one session's log level (MANAGE_SERVER)
+pub async fn set_gateway_log_level(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    gateways: web::Data<DiscordGateways>,
+    path: web::Path<String>,
+    body: web::Json<GatewayLogLevelPayload>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let map = gateways.lock().await;
+    let Some((discord_user_id, session)) = map.find(&id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No gateway session for this user" }));
+    };
+
+    session.log.set_level(body.level);
+    HttpResponse::Ok().json(serde_json::json!({ "discord_user_id": discord_user_id, "log_level": body.level }))
+}
@@ -0,0 +1,812 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Importing a Discord server
+// ═══════════════════════════════════════════════════════
+//
+// `POST /api/server/discord-imports` copies a Discord guild's structure
+// into this server, read through the caller's linked Discord account
+// (which must be able to see the guild):
+//   - roles, except `@everyone` and integration roles: the name, lowercased
+//     and made unique, the color, hoist and mentionable flags, and the
+//     permissions that have a Voxium equivalent (see `PERMISSIONS`)
+//   - categories, then text, announcement, voice, stage and forum channels
+//     as rooms, in Discord's order. Role overwrites become room overwrites
+//     (`@everyone` the `user` role); member overwrites are dropped.
+//   - with `include_pins`, the pinned messages of text channels, posted
+//     under their Discord author's name and avatar and pinned again
+// Permissions are cut down to the importer's own. The `user` role, what
+// every member has, is left as it is.
+//
+// Imports run one at a time in the `discord_imports` job, with a `stage`
+// and a `progress` (0-100) clients poll. Everything created is recorded in
+// `discord_import_map` under its Discord id: an import stopped by a Discord
+// rate limit or a restart goes on from there on the next pass, and the
+// pairs are kept for bridging the imported rooms later (see `bridge`).
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::{discord_api_base_url, extract_claims, Claims};
+use crate::bridge::DiscordMessage;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::permissions as perms;
+use crate::ws::Broadcaster;
+
+const WORKER_INTERVAL: Duration = Duration::from_secs(10);
+const WEBHOOK_NAME: &str = "Discord import";
+const DEFAULT_ROLE_COLOR: &str = "#99aab5";
+const MAX_ROLE_CHARS: usize = 24;
+const MAX_CATEGORY_CHARS: usize = 50;
+const MAX_ROOM_CHARS: usize = 100;
+/// Role names an import never takes.
+const RESERVED_ROLES: &[&str] = &["user", "admin", crate::guests::GUEST_ROLE, crate::webhooks::WEBHOOK_ROLE];
+
+/// Discord permission bits and the Voxium permission each one becomes.
+const PERMISSIONS: &[(u32, u64)] = &[
+    (1, perms::MANAGE_MEMBERS),   // KICK_MEMBERS
+    (2, perms::MANAGE_MEMBERS),   // BAN_MEMBERS
+    (3, perms::ADMINISTRATOR),
+    (4, perms::MANAGE_ROOMS),     // MANAGE_CHANNELS
+    (5, perms::MANAGE_SERVER),    // MANAGE_GUILD
+    (6, perms::ADD_REACTIONS),
+    (10, perms::VIEW_ROOM),       // VIEW_CHANNEL
+    (11, perms::SEND_MESSAGES),
+    (13, perms::MANAGE_MESSAGES),
+    (15, perms::ATTACH_FILES),
+    (17, perms::MENTION_EVERYONE),
+    (20, perms::CONNECT),
+    (22, perms::MUTE_MEMBERS),
+    (28, perms::MANAGE_ROLES),
+    (30, perms::MANAGE_EMOJIS),   // MANAGE_GUILD_EXPRESSIONS
+    (42, perms::USE_SOUNDBOARD),
+];
+/// Discord's PRIORITY_SPEAKER, a role flag here.
+const PRIORITY_SPEAKER_BIT: u32 = 8;
+
+/// Discord's permission string as Voxium permissions.
+fn translate_permissions(discord: &str) -> u64 {
+    let bits: u64 = discord.parse().unwrap_or(0);
+    PERMISSIONS.iter().filter(|(bit, _)| bits & (1 << bit) != 0).fold(0, |acc, (_, permission)| acc | permission)
+}
+
+// ── Discord models ──────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+struct Guild {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscordRole {
+    id: String,
+    name: String,
+    #[serde(default)]
+    color: u32,
+    #[serde(default)]
+    hoist: bool,
+    #[serde(default)]
+    mentionable: bool,
+    /// Owned by an integration (a bot's own role).
+    #[serde(default)]
+    managed: bool,
+    #[serde(default)]
+    position: i64,
+    #[serde(default)]
+    permissions: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Overwrite {
+    id: String,
+    /// 0 for a role, 1 for a member.
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    allow: String,
+    #[serde(default)]
+    deny: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Channel {
+    id: String,
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    position: i64,
+    parent_id: Option<String>,
+    #[serde(default)]
+    permission_overwrites: Vec<Overwrite>,
+}
+
+const CATEGORY: u8 = 4;
+
+impl Channel {
+    /// The kind of room the channel becomes; threads, directories... are skipped.
+    fn room_kind(&self) -> Option<&'static str> {
+        match self.kind {
+            0 | 5 => Some("text"),
+            2 | 13 => Some("voice"),
+            15 | 16 => Some("forum"),
+            _ => None,
+        }
+    }
+}
+
+// ── Importing ───────────────────────────────────────────
+
+/// Why a pass over an import stopped short.
+enum Halt {
+    /// Discord asked us to slow down: go on next pass.
+    RateLimited,
+    Failed(String),
+}
+
+fn failed(error: impl ToString) -> Halt {
+    Halt::Failed(error.to_string())
+}
+
+fn no_access() -> Halt {
+    Halt::Failed("The linked Discord account can't see that server".to_string())
+}
+
+/// GET a Discord resource; `None` when the account may not see it.
+async fn get<T: DeserializeOwned>(client: &Client, auth: &str, path: &str) -> Result<Option<T>, Halt> {
+    let response = client
+        .get(format!("{}{path}", discord_api_base_url()))
+        .header("Authorization", auth)
+        .send()
+        .await
+        .map_err(failed)?;
+    match response.status() {
+        StatusCode::TOO_MANY_REQUESTS => Err(Halt::RateLimited),
+        StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Ok(None),
+        status if !status.is_success() => Err(Halt::Failed(format!("Discord answered {status} for {path}"))),
+        _ => response.json().await.map(Some).map_err(failed),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Claimed {
+    id: String,
+    guild_id: String,
+    user_id: Option<String>,
+    include_pins: bool,
+}
+
+/// What the import created for `discord_id`, if anything yet.
+async fn mapped(pool: &SqlitePool, import_id: &str, kind: &str, discord_id: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT voxium_id FROM discord_import_map WHERE import_id = ? AND kind = ? AND discord_id = ?")
+        .bind(import_id)
+        .bind(kind)
+        .bind(discord_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+async fn record(
+    conn: &mut sqlx::SqliteConnection,
+    import_id: &str,
+    kind: &str,
+    discord_id: &str,
+    voxium_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO discord_import_map (import_id, kind, discord_id, voxium_id, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(import_id)
+        .bind(kind)
+        .bind(discord_id)
+        .bind(voxium_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// `base`, else `base-2`, `base-3`..., the first name free in `table`, at
+/// most `max_chars` long.
+async fn unique_name(pool: &SqlitePool, table: &str, base: &str, max_chars: usize) -> String {
+    let mut n = 1;
+    loop {
+        let suffix = if n == 1 { String::new() } else { format!("-{n}") };
+        let name = format!("{}{suffix}", base.chars().take(max_chars - suffix.len()).collect::<String>());
+        let taken: bool = sqlx::query_scalar(&format!("SELECT EXISTS(SELECT 1 FROM {table} WHERE name = ?)"))
+            .bind(&name)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(false);
+        let reserved = table == "roles" && RESERVED_ROLES.contains(&name.as_str());
+        if !taken && !reserved {
+            return name;
+        }
+        n += 1;
+    }
+}
+
+/// A Discord role name as a Voxium one: lowercase a-z, 0-9, `_` and `-`.
+fn role_name(role: &DiscordRole) -> String {
+    let mut name = String::new();
+    for c in role.name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+            name.push(c);
+        } else if c.is_whitespace() && !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name = name.trim_end_matches('-');
+    if name.len() < 2 {
+        format!("role-{}", &role.id[role.id.len().saturating_sub(6)..])
+    } else {
+        name.to_string()
+    }
+}
+
+/// The importing member, as claims for the helpers that record who did what.
+async fn importer(pool: &SqlitePool, user_id: Option<&str>) -> Result<Claims, Halt> {
+    let gone = || Halt::Failed("The importing account is gone".to_string());
+    let user_id = user_id.ok_or_else(gone)?;
+    let row = sqlx::query("SELECT username, role FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(failed)?
+        .ok_or_else(gone)?;
+    Ok(Claims {
+        sub: user_id.to_string(),
+        username: row.get("username"),
+        role: row.get("role"),
+        exp: 0,
+        sid: String::new(),
+        sudo_until: None,
+    })
+}
+
+/// Counts the import's steps into its `progress`.
+struct Progress<'a> {
+    pool: &'a SqlitePool,
+    import_id: &'a str,
+    steps: usize,
+    done: usize,
+}
+
+impl Progress<'_> {
+    async fn stage(&self, stage: &str) {
+        let _ = sqlx::query("UPDATE discord_imports SET stage = ? WHERE id = ?")
+            .bind(stage)
+            .bind(self.import_id)
+            .execute(self.pool)
+            .await;
+    }
+
+    async fn advance(&mut self) {
+        self.done += 1;
+        let progress = (self.done * 100 / self.steps.max(1)).min(99) as i64;
+        let _ = sqlx::query("UPDATE discord_imports SET progress = ? WHERE id = ?")
+            .bind(progress)
+            .bind(self.import_id)
+            .execute(self.pool)
+            .await;
+    }
+}
+
+/// One pass over an import, with what every step needs.
+struct Run<'a> {
+    pool: &'a SqlitePool,
+    broadcaster: &'a Broadcaster,
+    client: &'a Client,
+    import_id: &'a str,
+    /// The Authorization header, from the importer's linked account.
+    auth: String,
+    importer: Claims,
+    /// The importer's permissions, the most anything imported gets.
+    granted: u64,
+}
+
+impl Run<'_> {
+    async fn mapped(&self, kind: &str, discord_id: &str) -> Option<String> {
+        mapped(self.pool, self.import_id, kind, discord_id).await
+    }
+
+    async fn role(&self, role: &DiscordRole) -> Result<String, Halt> {
+        let name = unique_name(self.pool, "roles", &role_name(role), MAX_ROLE_CHARS).await;
+        let color = if role.color == 0 { DEFAULT_ROLE_COLOR.to_string() } else { format!("#{:06x}", role.color & 0xff_ffff) };
+        let discord_bits: u64 = role.permissions.parse().unwrap_or(0);
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        sqlx::query("INSERT INTO roles (name, color, hoist, mentionable, priority_speaker, permissions) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&name)
+            .bind(&color)
+            .bind(role.hoist)
+            .bind(role.mentionable)
+            .bind(discord_bits & (1 << PRIORITY_SPEAKER_BIT) != 0)
+            .bind((translate_permissions(&role.permissions) & self.granted) as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(failed)?;
+        record(&mut tx, self.import_id, "role", &role.id, &name).await.map_err(failed)?;
+        tx.commit().await.map_err(failed)?;
+        if let Some(created) = crate::roles::fetch_role(self.pool, &name).await {
+            crate::roles::broadcast_role_event(self.broadcaster, "role_created", &created);
+        }
+        Ok(name)
+    }
+
+    async fn category(&self, channel: &Channel) -> Result<String, Halt> {
+        let id = Uuid::new_v4().to_string();
+        let name: String = channel.name.trim().chars().take(MAX_CATEGORY_CHARS).collect();
+        let name = if name.is_empty() { "Category".to_string() } else { name };
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        sqlx::query(
+            "INSERT INTO categories (id, name, position, created_at) \
+             VALUES (?, ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM categories), ?)"
+        )
+        .bind(&id)
+        .bind(&name)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(failed)?;
+        record(&mut tx, self.import_id, "category", &channel.id, &id).await.map_err(failed)?;
+        tx.commit().await.map_err(failed)?;
+        Ok(id)
+    }
+
+    /// Create the room of `channel` with its role overwrites, in one go so
+    /// the room is never visible without them.
+    async fn room(&self, channel: &Channel, category_id: Option<&str>, role_names: &HashMap<String, String>) -> Result<String, Halt> {
+        let base = channel.name.trim();
+        let name = unique_name(self.pool, "rooms", if base.is_empty() { "imported" } else { base }, MAX_ROOM_CHARS).await;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        sqlx::query(
+            "INSERT INTO rooms (id, name, kind, required_role, category_id, position) \
+             VALUES (?, ?, ?, 'user', ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM rooms WHERE category_id IS ?))"
+        )
+        .bind(&id)
+        .bind(&name)
+        .bind(channel.room_kind())
+        .bind(category_id)
+        .bind(category_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(failed)?;
+        for overwrite in channel.permission_overwrites.iter().filter(|o| o.kind == 0) {
+            let Some(role) = role_names.get(&overwrite.id) else {
+                continue;
+            };
+            let allow = translate_permissions(&overwrite.allow) & perms::ROOM & self.granted;
+            let deny = translate_permissions(&overwrite.deny) & perms::ROOM & self.granted;
+            if allow == 0 && deny == 0 {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO room_permission_overwrites (room_id, target_type, target_id, allow, deny, updated_by, updated_at) \
+                 VALUES (?, 'role', ?, ?, ?, ?, ?)"
+            )
+            .bind(&id)
+            .bind(role)
+            .bind(allow as i64)
+            .bind(deny as i64)
+            .bind(&self.importer.sub)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await
+            .map_err(failed)?;
+        }
+        record(&mut tx, self.import_id, "room", &channel.id, &id).await.map_err(failed)?;
+        tx.commit().await.map_err(failed)?;
+        Ok(id)
+    }
+
+    /// Copy the pins of `channel_id` into `room_id`, oldest first, through
+    /// a webhook removed afterwards.
+    async fn pins(&self, channel_id: &str, room_id: &str) -> Result<(), Halt> {
+        // A channel the account can't read has nothing to copy.
+        let Some(mut pins) = get::<Vec<DiscordMessage>>(self.client, &self.auth, &format!("/channels/{channel_id}/pins")).await? else {
+            return Ok(());
+        };
+        // Discord lists the latest pin first.
+        pins.reverse();
+        let mut pending = Vec::new();
+        for message in pins.into_iter().filter(DiscordMessage::is_chat) {
+            if self.mapped("message", &message.id).await.is_none() {
+                pending.push(message);
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let webhook =
+            crate::webhooks::insert_webhook(self.pool, room_id, WEBHOOK_NAME, None, &self.importer).await.map_err(failed)?;
+        let copied = async {
+            for message in pending {
+                let Some(post) = crate::bridge::webhook_post(self.pool, &message).await else {
+                    continue;
+                };
+                let posted = crate::webhooks::post_message(self.pool, self.broadcaster, None, &webhook, post).await.map_err(failed)?;
+                let message_id = posted["id"].as_str().unwrap_or_default();
+                let mut tx = self.pool.begin().await.map_err(failed)?;
+                sqlx::query("UPDATE messages SET pinned_at = ?, pinned_by = ? WHERE id = ?")
+                    .bind(Utc::now().to_rfc3339())
+                    .bind(&self.importer.sub)
+                    .bind(message_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(failed)?;
+                record(&mut tx, self.import_id, "message", &message.id, message_id).await.map_err(failed)?;
+                tx.commit().await.map_err(failed)?;
+            }
+            Ok(())
+        }
+        .await;
+        // Copied messages stay when their webhook goes.
+        let _ = sqlx::query("DELETE FROM webhooks WHERE id = ?").bind(&webhook.id).execute(self.pool).await;
+        copied
+    }
+}
+
+/// One pass over an import, skipping what earlier passes created.
+async fn run_import(pool: &SqlitePool, broadcaster: &Broadcaster, client: &Client, import: &Claimed) -> Result<(), Halt> {
+    let importer = importer(pool, import.user_id.as_deref()).await?;
+    let auth = crate::discord_gateway::get_discord_account(pool, &importer.sub).await.map_err(Halt::Failed)?.token;
+    let guild_path = format!("/guilds/{}", import.guild_id);
+    let guild: Guild = get(client, &auth, &guild_path).await?.ok_or_else(no_access)?;
+    let _ = sqlx::query("UPDATE discord_imports SET guild_name = ? WHERE id = ?")
+        .bind(&guild.name)
+        .bind(&import.id)
+        .execute(pool)
+        .await;
+    let mut roles: Vec<DiscordRole> = get(client, &auth, &format!("{guild_path}/roles")).await?.ok_or_else(no_access)?;
+    let channels: Vec<Channel> = get(client, &auth, &format!("{guild_path}/channels")).await?.ok_or_else(no_access)?;
+
+    // `@everyone` has the guild's id.
+    roles.retain(|r| r.id != guild.id && !r.managed);
+    roles.sort_by(|a, b| b.position.cmp(&a.position).then_with(|| a.id.cmp(&b.id)));
+    let mut categories: Vec<&Channel> = channels.iter().filter(|c| c.kind == CATEGORY).collect();
+    categories.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.id.cmp(&b.id)));
+    // Discord lists voice channels after text ones, whatever their position.
+    let mut rooms: Vec<&Channel> = channels.iter().filter(|c| c.room_kind().is_some()).collect();
+    rooms.sort_by_key(|c| (c.room_kind() == Some("voice"), c.position, c.id.clone()));
+    let pin_rooms: Vec<&Channel> =
+        rooms.iter().copied().filter(|c| import.include_pins && c.room_kind() == Some("text")).collect();
+
+    let granted = match perms::role_permissions(pool, &importer.role).await {
+        bits if bits & perms::ADMINISTRATOR != 0 => perms::ALL,
+        bits => bits,
+    };
+    let run = Run { pool, broadcaster, client, import_id: &import.id, auth, importer, granted };
+    let mut progress = Progress {
+        pool,
+        import_id: &import.id,
+        steps: roles.len() + categories.len() + rooms.len() + pin_rooms.len(),
+        done: 0,
+    };
+
+    progress.stage("roles").await;
+    let mut role_names = HashMap::from([(guild.id.clone(), "user".to_string())]);
+    for role in &roles {
+        let name = match run.mapped("role", &role.id).await {
+            Some(name) => name,
+            None => run.role(role).await?,
+        };
+        role_names.insert(role.id.clone(), name);
+        progress.advance().await;
+    }
+
+    progress.stage("channels").await;
+    let mut category_ids = HashMap::new();
+    for category in categories {
+        let id = match run.mapped("category", &category.id).await {
+            Some(id) => id,
+            None => run.category(category).await?,
+        };
+        category_ids.insert(category.id.clone(), id);
+        progress.advance().await;
+    }
+    let mut room_ids = HashMap::new();
+    for channel in &rooms {
+        let id = match run.mapped("room", &channel.id).await {
+            Some(id) => id,
+            None => {
+                let category_id = channel.parent_id.as_ref().and_then(|p| category_ids.get(p)).map(String::as_str);
+                run.room(channel, category_id, &role_names).await?
+            }
+        };
+        room_ids.insert(channel.id.clone(), id);
+        progress.advance().await;
+    }
+
+    progress.stage("pins").await;
+    for channel in pin_rooms {
+        if run.mapped("pins", &channel.id).await.is_none() {
+            let room_id = &room_ids[&channel.id];
+            run.pins(&channel.id, room_id).await?;
+            let mut conn = pool.acquire().await.map_err(failed)?;
+            record(&mut conn, &import.id, "pins", &channel.id, room_id).await.map_err(failed)?;
+        }
+        progress.advance().await;
+    }
+    Ok(())
+}
+
+/// Take the oldest pending import, if any.
+async fn claim(pool: &SqlitePool) -> Result<Option<Claimed>, String> {
+    sqlx::query(
+        "UPDATE discord_imports SET status = 'running', started_at = COALESCE(started_at, ?) \
+         WHERE id = (SELECT id FROM discord_imports WHERE status = 'pending' ORDER BY created_at, id LIMIT 1) \
+         RETURNING id, guild_id, user_id, include_pins"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    .map(|row| {
+        row.map(|row| Claimed {
+            id: row.get("id"),
+            guild_id: row.get("guild_id"),
+            user_id: row.get("user_id"),
+            include_pins: row.get::<i64, _>("include_pins") != 0,
+        })
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Work through the pending imports. A Discord rate limit puts the current
+/// one back for the next pass. Returns how many imports finished.
+pub async fn process_imports(pool: &SqlitePool, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let client = Client::builder().timeout(Duration::from_secs(15)).build().unwrap_or_default();
+    let mut finished = 0;
+    let mut failure = None;
+    while let Some(import) = claim(pool).await? {
+        let outcome = run_import(pool, broadcaster, &client, &import).await;
+        // Rooms and categories may have been created either way.
+        crate::categories::layout_updated(broadcaster);
+        let now = Utc::now().to_rfc3339();
+        let update = match &outcome {
+            Ok(()) => sqlx::query(
+                "UPDATE discord_imports SET status = 'done', stage = NULL, progress = 100, completed_at = ? WHERE id = ?"
+            )
+            .bind(now),
+            Err(Halt::RateLimited) => sqlx::query("UPDATE discord_imports SET status = 'pending' WHERE id = ?"),
+            Err(Halt::Failed(error)) => {
+                failure.get_or_insert(format!("import {}: {error}", import.id));
+                sqlx::query("UPDATE discord_imports SET status = 'failed', error = ?, completed_at = ? WHERE id = ?")
+                    .bind(error.clone())
+                    .bind(now)
+            }
+        };
+        update.bind(&import.id).execute(pool).await.map_err(|e| e.to_string())?;
+        match outcome {
+            Err(Halt::RateLimited) => break,
+            _ => finished += 1,
+        }
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(finished),
+    }
+}
+
+async fn import_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query("SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM discord_imports WHERE status = 'pending'")
+        .fetch_one(pool)
+        .await
+        .map(|row| Backlog::from_row(&row))
+        .unwrap_or_default()
+}
+
+pub fn spawn_import_worker(pool: SqlitePool, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::DISCORD_IMPORTS);
+    actix_web::rt::spawn(async move {
+        // Cut short by the last shutdown: they go on from their mappings.
+        let _ = sqlx::query("UPDATE discord_imports SET status = 'pending' WHERE status = 'running'").execute(&pool).await;
+        let mut interval = tokio::time::interval(WORKER_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::DISCORD_IMPORTS, None, process_imports(&pool, &broadcaster), import_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// What an import created so far.
+#[derive(Debug, Serialize)]
+pub struct Imported {
+    pub roles: i64,
+    pub categories: i64,
+    pub rooms: i64,
+    pub pins: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Mapping {
+    /// `role`, `category`, `room`, `pins` or `message`.
+    pub kind: String,
+    pub discord_id: String,
+    /// A role name, otherwise an id.
+    pub voxium_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiscordImport {
+    pub id: String,
+    pub guild_id: String,
+    pub guild_name: Option<String>,
+    pub created_by_username: String,
+    pub include_pins: bool,
+    /// `pending`, `running`, `done` or `failed`.
+    pub status: String,
+    /// `roles`, `channels` or `pins` while running.
+    pub stage: Option<String>,
+    pub progress: i64,
+    pub error: Option<String>,
+    pub imported: Imported,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+    /// Discord ids and what they became, for one import.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mappings: Option<Vec<Mapping>>,
+}
+
+const IMPORT_SELECT: &str = "SELECT i.id, i.guild_id, i.guild_name, i.created_by_username, i.include_pins, i.status, i.stage, \
+     i.progress, i.error, i.created_at, i.started_at, i.completed_at, \
+     (SELECT COUNT(*) FROM discord_import_map m WHERE m.import_id = i.id AND m.kind = 'role') AS roles, \
+     (SELECT COUNT(*) FROM discord_import_map m WHERE m.import_id = i.id AND m.kind = 'category') AS categories, \
+     (SELECT COUNT(*) FROM discord_import_map m WHERE m.import_id = i.id AND m.kind = 'room') AS rooms, \
+     (SELECT COUNT(*) FROM discord_import_map m WHERE m.import_id = i.id AND m.kind = 'message') AS pins \
+     FROM discord_imports i";
+
+impl DiscordImport {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        DiscordImport {
+            id: row.get("id"),
+            guild_id: row.get("guild_id"),
+            guild_name: row.get("guild_name"),
+            created_by_username: row.get("created_by_username"),
+            include_pins: row.get::<i64, _>("include_pins") != 0,
+            status: row.get("status"),
+            stage: row.get("stage"),
+            progress: row.get("progress"),
+            error: row.get("error"),
+            imported: Imported {
+                roles: row.get("roles"),
+                categories: row.get("categories"),
+                rooms: row.get("rooms"),
+                pins: row.get("pins"),
+            },
+            created_at: row.get("created_at"),
+            started_at: row.get("started_at"),
+            completed_at: row.get("completed_at"),
+            mappings: None,
+        }
+    }
+}
+
+async fn fetch_import(pool: &SqlitePool, import_id: &str) -> Option<DiscordImport> {
+    sqlx::query(&format!("{IMPORT_SELECT} WHERE i.id = ?"))
+        .bind(import_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| DiscordImport::from_row(&row))
+}
+
+/// The caller's claims if they may create rooms and roles and run server
+/// tasks.
+async fn require_importer(pool: &SqlitePool, req: &HttpRequest) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require_server(pool, &claims, perms::MANAGE_SERVER | perms::MANAGE_ROOMS | perms::MANAGE_ROLES).await?;
+    Ok(claims)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateImport {
+    pub guild_id: String,
+    /// Also copy the pinned messages of text channels.
+    #[serde(default)]
+    pub include_pins: bool,
+}
+
+/// POST /api/server/discord-imports — Queue an import of a Discord server through the caller's linked account (MANAGE_SERVER, MANAGE_ROOMS, MANAGE_ROLES)
+pub async fn create_import(req: HttpRequest, pool: web::Data<SqlitePool>, body: web::Json<CreateImport>) -> HttpResponse {
+    let claims = match require_importer(pool.get_ref(), &req).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let guild_id = body.guild_id.trim();
+    if guild_id.is_empty() || guild_id.len() > 20 || !guild_id.chars().all(|c| c.is_ascii_digit()) {
+        return HttpResponse::BadRequest().json(json!({ "error": "guild_id must be a Discord server id" }));
+    }
+    let linked: bool = sqlx::query_scalar("SELECT discord_access_token IS NOT NULL FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(false);
+    if !linked {
+        return HttpResponse::BadRequest().json(json!({ "error": "Link a Discord account first" }));
+    }
+    let busy: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM discord_imports WHERE status IN ('pending', 'running'))")
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if busy {
+        return HttpResponse::Conflict().json(json!({ "error": "An import is already in progress" }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let inserted = sqlx::query(
+        "INSERT INTO discord_imports (id, guild_id, user_id, created_by_username, include_pins, status, created_at) \
+         VALUES (?, ?, ?, ?, ?, 'pending', ?)"
+    )
+    .bind(&id)
+    .bind(guild_id)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(body.include_pins)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match fetch_import(pool.get_ref(), &id).await {
+        Some(import) => HttpResponse::Accepted().json(import),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/server/discord-imports — Every import, newest first (MANAGE_SERVER, MANAGE_ROOMS, MANAGE_ROLES)
+pub async fn list_imports(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = require_importer(pool.get_ref(), &req).await {
+        return response;
+    }
+    match sqlx::query(&format!("{IMPORT_SELECT} ORDER BY i.created_at DESC")).fetch_all(pool.get_ref()).await {
+        Ok(rows) => HttpResponse::Ok().json(json!({ "imports": rows.iter().map(DiscordImport::from_row).collect::<Vec<_>>() })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/server/discord-imports/{id} — One import, to poll its progress, with what each Discord id became (MANAGE_SERVER, MANAGE_ROOMS, MANAGE_ROLES)
+pub async fn get_import(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    if let Err(response) = require_importer(pool.get_ref(), &req).await {
+        return response;
+    }
+    let import_id = path.into_inner();
+    let Some(mut import) = fetch_import(pool.get_ref(), &import_id).await else {
+        return HttpResponse::NotFound().json(json!({ "error": "Import not found" }));
+    };
+    let mappings = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT kind, discord_id, voxium_id FROM discord_import_map WHERE import_id = ? ORDER BY created_at, kind, discord_id"
+    )
+    .bind(&import_id)
+    .fetch_all(pool.get_ref())
+    .await;
+    match mappings {
+        Ok(mappings) => {
+            import.mappings = Some(
+                mappings
+                    .into_iter()
+                    .map(|(kind, discord_id, voxium_id)| Mapping { kind, discord_id, voxium_id })
+                    .collect(),
+            );
+            HttpResponse::Ok().json(import)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
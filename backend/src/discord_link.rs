@@ -0,0 +1,309 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Discord link health (token validation)
+// ═══════════════════════════════════════════════════════
+//
+// Discord can invalidate a stored token at any time (password change,
+// "log out all devices", OAuth2 app deauthorized...). Voice join then fails
+// deep inside the gateway with confusing errors. A background task
+// periodically checks every linked token with `GET /users/@me` and stores
+// the result, so the UI can ask the user to re-link beforehand.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+
+use crate::audit::AuthEvent;
+use crate::auth::{discord_api_base_url, extract_claims};
+use crate::discord_gateway::DiscordGateways;
+use crate::sessions::DeviceInfo;
+use crate::sudo::Sudo;
+
+/// Default delay between two validation passes, overridable with
+/// `DISCORD_TOKEN_CHECK_INTERVAL_SECS` (0 disables the background task).
+const DEFAULT_CHECK_INTERVAL_SECS: u64 = 60 * 60;
+/// Pause between two users in a pass, to stay clear of Discord rate limits.
+const CHECK_SPACING: Duration = Duration::from_millis(500);
+/// A stored status younger than this is returned without asking Discord.
+const STATUS_MAX_AGE_SECS: i64 = 5 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStatus {
+    Unlinked,
+    Linked,
+    Expired,
+    Revoked,
+}
+
+impl LinkStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Unlinked => "unlinked",
+            Self::Linked => "linked",
+            Self::Expired => "expired",
+            Self::Revoked => "revoked",
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "linked" => Some(Self::Linked),
+            "expired" => Some(Self::Expired),
+            "revoked" => Some(Self::Revoked),
+            _ => None,
+        }
+    }
+
+    pub fn needs_relink(self) -> bool {
+        matches!(self, Self::Expired | Self::Revoked)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkStatusQuery {
+    /// Force a live check instead of returning the stored status.
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+// ── Background validator ────────────────────────────────
+
+/// Check every linked token once, returns how many were checked. The pass
+/// fails when Discord could not be asked for some of them.
+async fn validate_all(pool: &SqlitePool) -> Result<u64, String> {
+    let user_ids: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM users WHERE discord_access_token IS NOT NULL"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let total = user_ids.len();
+    let mut unreachable = 0;
+    for user_id in user_ids {
+        if validate_user_token(pool, &user_id).await.is_none() {
+            unreachable += 1;
+        }
+        tokio::time::sleep(CHECK_SPACING).await;
+    }
+    if unreachable > 0 {
+        return Err(format!("{unreachable} of {total} tokens could not be checked"));
+    }
+    Ok(total as u64)
+}
+
+/// Linked tokens not checked within the last interval.
+async fn validation_backlog(pool: &SqlitePool, interval_secs: u64) -> crate::jobs::Backlog {
+    let stale_before = (chrono::Utc::now() - chrono::Duration::seconds(interval_secs as i64)).to_rfc3339();
+    sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(discord_token_checked_at) AS oldest_due_at FROM users \
+         WHERE discord_access_token IS NOT NULL AND (discord_token_checked_at IS NULL OR discord_token_checked_at < ?)"
+    )
+    .bind(&stale_before)
+    .fetch_one(pool)
+    .await
+    .map(|row| crate::jobs::Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_token_validator(pool: SqlitePool, jobs: crate::jobs::JobRegistry) {
+    let interval_secs = std::env::var("DISCORD_TOKEN_CHECK_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CHECK_INTERVAL_SECS);
+    if interval_secs == 0 {
+        return;
+    }
+
+    crate::jobs::register(&jobs, crate::jobs::DISCORD_TOKENS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            crate::jobs::run(
+                &jobs,
+                crate::jobs::DISCORD_TOKENS,
+                None,
+                validate_all(&pool),
+                validation_backlog(&pool, interval_secs),
+            )
+            .await;
+        }
+    });
+}
+
+/// Check `user_id`'s Discord token against Discord and store the result.
+/// Network failures leave the stored status untouched and return `None`.
+pub(crate) async fn validate_user_token(pool: &SqlitePool, user_id: &str) -> Option<LinkStatus> {
+    let row = sqlx::query("SELECT discord_access_token FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)?;
+
+    let token: Option<String> = row.try_get("discord_access_token").unwrap_or(None);
+    if token.is_none() {
+        return Some(LinkStatus::Unlinked);
+    }
+
+    // OAuth2 tokens: try to refresh first. If the refresh token itself was
+    // rejected the link has expired for good.
+    if crate::discord_oauth::refresh_if_expired(pool, user_id).await.is_err() {
+        store_status(pool, user_id, LinkStatus::Expired).await;
+        return Some(LinkStatus::Expired);
+    }
+
+    // Re-read: the refresh above may have rotated the token.
+    let token: Option<String> = sqlx::query_scalar("SELECT discord_access_token FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .flatten();
+    let Some(authorization) = token.as_deref().and_then(crate::crypto::decrypt_token) else {
+        store_status(pool, user_id, LinkStatus::Revoked).await;
+        return Some(LinkStatus::Revoked);
+    };
+
+    let response = Client::new()
+        .get(format!("{}/users/@me", discord_api_base_url()))
+        .header("Authorization", authorization)
+        .send()
+        .await
+        .ok()?;
+
+    let status = match response.status().as_u16() {
+        200..=299 => LinkStatus::Linked,
+        401 | 403 => LinkStatus::Revoked,
+        // Rate limited or Discord having issues: nothing learned.
+        _ => return None,
+    };
+
+    store_status(pool, user_id, status).await;
+    Some(status)
+}
+
+async fn store_status(pool: &SqlitePool, user_id: &str, status: LinkStatus) {
+    let _ = sqlx::query("UPDATE users SET discord_token_status = ?, discord_token_checked_at = ? WHERE id = ?")
+        .bind(status.as_str())
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(user_id)
+        .execute(pool)
+        .await;
+}
+
+/// Stored status and when it was last checked, without contacting Discord.
+pub(crate) async fn stored_status(pool: &SqlitePool, user_id: &str) -> (LinkStatus, Option<String>) {
+    let row = sqlx::query(
+        "SELECT discord_access_token, discord_token_status, discord_token_checked_at FROM users WHERE id = ?"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+
+    let Some(row) = row else {
+        return (LinkStatus::Unlinked, None);
+    };
+    let token: Option<String> = row.try_get("discord_access_token").unwrap_or(None);
+    if token.is_none() {
+        return (LinkStatus::Unlinked, None);
+    }
+
+    let status: Option<String> = row.try_get("discord_token_status").unwrap_or(None);
+    let checked_at: Option<String> = row.try_get("discord_token_checked_at").unwrap_or(None);
+    let status = status
+        .as_deref()
+        .and_then(LinkStatus::parse)
+        .unwrap_or(LinkStatus::Linked);
+    (status, checked_at)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/discord/link/status — linked, expired, revoked or unlinked
+pub async fn link_status(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<LinkStatusQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let (mut status, mut checked_at) = stored_status(pool.get_ref(), &claims.sub).await;
+
+    let stale = checked_at
+        .as_deref()
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+        .is_none_or(|at| chrono::Utc::now().timestamp() - at.timestamp() > STATUS_MAX_AGE_SECS);
+
+    if status != LinkStatus::Unlinked
+        && (query.refresh || stale)
+        && validate_user_token(pool.get_ref(), &claims.sub).await.is_some()
+    {
+        (status, checked_at) = stored_status(pool.get_ref(), &claims.sub).await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": status,
+        "checked_at": checked_at,
+        "relink_required": status.needs_relink(),
+    }))
+}
+
+/// Drop `user_id`'s stored Discord token and close their gateway session.
+/// Returns the status the link had, `Unlinked` when there was nothing to drop.
+pub(crate) async fn forget_token(
+    pool: &SqlitePool,
+    gateways: &DiscordGateways,
+    user_id: &str,
+) -> Result<LinkStatus, sqlx::Error> {
+    let (status, _) = stored_status(pool, user_id).await;
+    if status == LinkStatus::Unlinked {
+        return Ok(status);
+    }
+
+    // discord_id stays: it is how the account is found on the next Discord login.
+    sqlx::query(
+        "UPDATE users SET discord_access_token = NULL, discord_refresh_token = NULL, discord_token_expires_at = NULL, discord_token_status = ?, discord_token_checked_at = ? WHERE id = ?"
+    )
+    .bind(LinkStatus::Unlinked.as_str())
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    crate::discord_gateway::close_session(gateways, user_id).await;
+    Ok(status)
+}
+
+/// DELETE /api/discord/link — Forget the stored Discord token and close the gateway session
+pub async fn unlink(
+    req: HttpRequest,
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    gateways: web::Data<DiscordGateways>,
+) -> HttpResponse {
+    let status = match forget_token(pool.get_ref(), gateways.get_ref(), &claims.sub).await {
+        Ok(LinkStatus::Unlinked) => {
+            return HttpResponse::Ok().json(serde_json::json!({ "status": LinkStatus::Unlinked }))
+        }
+        Ok(status) => status,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    crate::audit::record(
+        pool.get_ref(),
+        &claims.sub,
+        AuthEvent::DiscordUnlinked,
+        &DeviceInfo::from_request(&req),
+        serde_json::json!({ "previous_status": status }),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({ "status": LinkStatus::Unlinked }))
+}
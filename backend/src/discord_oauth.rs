@@ -0,0 +1,424 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Discord OAuth2 (official) account linking
+// ═══════════════════════════════════════════════════════
+//
+// Parallel to the user-token flows (token paste, QR, password): uses the
+// authorization code grant with `identify guilds voice`. OAuth tokens are
+// stored like user tokens (encrypted, already prefixed with "Bearer " so
+// callers can use them verbatim as the Authorization header) together
+// with the refresh token and expiry, and refreshed on demand.
+//
+// DISCORD_LINK_MODE selects which flows a deployment exposes:
+//   user_token (default) | oauth2 | both
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::auth::{discord_api_base_url, extract_claims};
+
+const OAUTH_SCOPES: &str = "identify guilds voice";
+const OAUTH_STATE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Refresh a bit before Discord's expiry to avoid racing it.
+const REFRESH_MARGIN_SECS: i64 = 60;
+
+// ── Config ──────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    UserToken,
+    OAuth2,
+    Both,
+}
+
+impl LinkMode {
+    pub fn allows_user_token(self) -> bool {
+        matches!(self, LinkMode::UserToken | LinkMode::Both)
+    }
+
+    pub fn allows_oauth2(self) -> bool {
+        matches!(self, LinkMode::OAuth2 | LinkMode::Both)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LinkMode::UserToken => "user_token",
+            LinkMode::OAuth2 => "oauth2",
+            LinkMode::Both => "both",
+        }
+    }
+}
+
+pub fn link_mode() -> LinkMode {
+    match std::env::var("DISCORD_LINK_MODE")
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+        .as_str()
+    {
+        "oauth2" | "oauth" => LinkMode::OAuth2,
+        "both" => LinkMode::Both,
+        _ => LinkMode::UserToken,
+    }
+}
+
+/// Response used by user-token handlers when the deployment only allows OAuth2.
+pub(crate) fn user_token_disabled() -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({
+        "error": "La liaison par token utilisateur est désactivée sur ce serveur (mode OAuth2)"
+    }))
+}
+
+pub(crate) fn client_id() -> Option<String> {
+    std::env::var("DISCORD_CLIENT_ID").ok().filter(|v| !v.trim().is_empty())
+}
+
+pub(crate) fn client_secret() -> Option<String> {
+    std::env::var("DISCORD_CLIENT_SECRET").ok().filter(|v| !v.trim().is_empty())
+}
+
+pub(crate) fn redirect_uri() -> Option<String> {
+    std::env::var("DISCORD_REDIRECT_URI").ok().filter(|v| !v.trim().is_empty())
+}
+
+// ── State store ─────────────────────────────────────────
+
+pub struct PendingOAuth {
+    /// Voxium user to link to, or None for a login/sign-up.
+    user_id: Option<String>,
+    /// Session that started the link, kept when other sessions are revoked.
+    session_id: Option<String>,
+    created_at: Instant,
+}
+
+pub type OAuthStates = Arc<Mutex<HashMap<String, PendingOAuth>>>;
+
+pub fn create_oauth_states() -> OAuthStates {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+// ── Request types ───────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct OAuthCallbackPayload {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+// ── Handlers ────────────────────────────────────────────
+
+/// GET /api/auth/discord/config — Public linking configuration for the client.
+pub async fn get_config() -> HttpResponse {
+    let mode = link_mode();
+    HttpResponse::Ok().json(serde_json::json!({
+        "link_mode": mode.as_str(),
+        "client_id": if mode.allows_oauth2() { client_id() } else { None },
+        "redirect_uri": if mode.allows_oauth2() { redirect_uri() } else { None },
+        "scope": OAUTH_SCOPES,
+    }))
+}
+
+/// POST /api/auth/discord/oauth/authorize — Issue a state and the authorize URL.
+/// When called with a Voxium bearer token, the callback links to that account.
+pub async fn start_authorize(req: HttpRequest, states: web::Data<OAuthStates>) -> HttpResponse {
+    if !link_mode().allows_oauth2() {
+        return oauth_disabled();
+    }
+    let (Some(client_id), Some(redirect_uri)) = (client_id(), redirect_uri()) else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "DISCORD_CLIENT_ID / DISCORD_REDIRECT_URI non configurés"
+        }));
+    };
+
+    let state = uuid::Uuid::new_v4().simple().to_string();
+    let claims = extract_claims(&req);
+    {
+        let mut map = states.lock().await;
+        map.retain(|_, p| p.created_at.elapsed() < OAUTH_STATE_TTL);
+        map.insert(
+            state.clone(),
+            PendingOAuth {
+                user_id: claims.as_ref().map(|c| c.sub.clone()),
+                session_id: claims.map(|c| c.sid),
+                created_at: Instant::now(),
+            },
+        );
+    }
+
+    let query = serde_urlencoded::to_string([
+        ("client_id", client_id.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("response_type", "code"),
+        ("scope", OAUTH_SCOPES),
+        ("state", state.as_str()),
+        ("prompt", "consent"),
+    ])
+    .unwrap_or_default();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "url": format!("https://discord.com/oauth2/authorize?{query}"),
+        "state": state,
+    }))
+}
+
+/// POST /api/auth/discord/oauth/callback — Exchange the code, then link or log in.
+pub async fn oauth_callback(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    states: web::Data<OAuthStates>,
+    body: web::Json<OAuthCallbackPayload>,
+    session_store: web::Data<crate::sessions::SessionStore>,
+) -> HttpResponse {
+    if !link_mode().allows_oauth2() {
+        return oauth_disabled();
+    }
+
+    let pending = {
+        let mut map = states.lock().await;
+        map.remove(&body.state)
+            .filter(|p| p.created_at.elapsed() < OAUTH_STATE_TTL)
+    };
+    let Some(pending) = pending else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "State OAuth invalide ou expiré" }));
+    };
+
+    let tokens = match request_tokens(&[
+        ("grant_type", "authorization_code"),
+        ("code", body.code.trim()),
+        ("redirect_uri", redirect_uri().unwrap_or_default().as_str()),
+    ])
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::BadGateway().json(serde_json::json!({ "error": e })),
+    };
+
+    let authorization = format!("Bearer {}", tokens.access_token);
+    let expires_at = tokens
+        .expires_in
+        .map(|secs| chrono::Utc::now().timestamp() + secs);
+
+    match pending.user_id {
+        Some(user_id) => {
+            let had_token = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM users WHERE id = ? AND discord_access_token IS NOT NULL"
+            )
+            .bind(&user_id)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or(0)
+                > 0;
+
+            match link_to_user(pool.get_ref(), &user_id, &authorization, tokens.refresh_token.as_deref(), expires_at).await {
+                Ok(discord_id) => {
+                    crate::audit::record(
+                        pool.get_ref(),
+                        &user_id,
+                        crate::audit::AuthEvent::DiscordLinked,
+                        &crate::sessions::DeviceInfo::from_request(&req),
+                        serde_json::json!({ "via": "oauth2", "discord_id": discord_id, "new_account": false }),
+                    )
+                    .await;
+                    // Replacing the Discord credential logs out the other devices.
+                    if had_token {
+                        crate::sessions::revoke_user_sessions(
+                            pool.get_ref(),
+                            session_store.get_ref(),
+                            &user_id,
+                            pending.session_id.as_deref(),
+                        )
+                        .await;
+                    }
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "status": "linked",
+                        "discord_id": discord_id,
+                    }))
+                }
+                Err(resp) => resp,
+            }
+        }
+        None => {
+            match crate::auth::do_discord_login_with(
+                pool.get_ref(),
+                &authorization,
+                tokens.refresh_token.as_deref(),
+                expires_at,
+                &crate::sessions::DeviceInfo::from_request(&req),
+                crate::audit::LinkMethod::OAuth2,
+            )
+            .await
+            {
+                Ok(auth) => HttpResponse::Ok().json(auth),
+                Err(msg) => HttpResponse::Unauthorized().json(serde_json::json!({ "error": msg })),
+            }
+        }
+    }
+}
+
+/// The Discord account that authorized `payload.code`, when its state was
+/// issued to `user_id`: how sudo mode re-checks a Discord login.
+pub(crate) async fn authorized_discord_id(
+    states: &OAuthStates,
+    user_id: &str,
+    payload: &OAuthCallbackPayload,
+) -> Result<String, HttpResponse> {
+    if !link_mode().allows_oauth2() {
+        return Err(oauth_disabled());
+    }
+    let pending = states
+        .lock()
+        .await
+        .remove(&payload.state)
+        .filter(|p| p.created_at.elapsed() < OAUTH_STATE_TTL && p.user_id.as_deref() == Some(user_id));
+    if pending.is_none() {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "State OAuth invalide ou expiré" })));
+    }
+    let tokens = request_tokens(&[
+        ("grant_type", "authorization_code"),
+        ("code", payload.code.trim()),
+        ("redirect_uri", redirect_uri().unwrap_or_default().as_str()),
+    ])
+    .await
+    .map_err(|e| HttpResponse::BadGateway().json(serde_json::json!({ "error": e })))?;
+    crate::auth::fetch_discord_user(&format!("Bearer {}", tokens.access_token))
+        .await
+        .map(|user| user.id)
+        .map_err(|e| HttpResponse::Unauthorized().json(serde_json::json!({ "error": e })))
+}
+
+// ── Token refresh ───────────────────────────────────────
+
+/// If the user's stored Discord credential is an OAuth token close to expiry,
+/// refresh it in place. User tokens (no refresh token) are left untouched.
+pub(crate) async fn refresh_if_expired(pool: &SqlitePool, user_id: &str) -> Result<(), String> {
+    let row = sqlx::query("SELECT discord_refresh_token, discord_token_expires_at FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| "Database error".to_string())?;
+
+    let Some(row) = row else {
+        return Ok(());
+    };
+    let refresh_enc: Option<String> = row.try_get("discord_refresh_token").unwrap_or(None);
+    let expires_at: Option<i64> = row.try_get("discord_token_expires_at").unwrap_or(None);
+
+    let (Some(refresh_enc), Some(expires_at)) = (refresh_enc, expires_at) else {
+        return Ok(());
+    };
+    if expires_at - REFRESH_MARGIN_SECS > chrono::Utc::now().timestamp() {
+        return Ok(());
+    }
+
+    let refresh_token = crate::crypto::decrypt_token(&refresh_enc)
+        .ok_or_else(|| "Echec du déchiffrement du refresh token".to_string())?;
+
+    let tokens = request_tokens(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token.as_str()),
+    ])
+    .await?;
+
+    let authorization = format!("Bearer {}", tokens.access_token);
+    let new_refresh = tokens.refresh_token.unwrap_or(refresh_token);
+    let new_expiry = tokens
+        .expires_in
+        .map(|secs| chrono::Utc::now().timestamp() + secs);
+
+    sqlx::query("UPDATE users SET discord_access_token = ?, discord_refresh_token = ?, discord_token_expires_at = ?, discord_token_status = 'linked' WHERE id = ?")
+        .bind(crate::crypto::encrypt_token(&authorization))
+        .bind(crate::crypto::encrypt_token(&new_refresh))
+        .bind(new_expiry)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|_| "Database error".to_string())?;
+
+    Ok(())
+}
+
+// ── Internal helpers ────────────────────────────────────
+
+fn oauth_disabled() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "OAuth2 Discord désactivé sur ce serveur"
+    }))
+}
+
+async fn request_tokens(grant: &[(&str, &str)]) -> Result<TokenResponse, String> {
+    let (Some(client_id), Some(client_secret)) = (client_id(), client_secret()) else {
+        return Err("DISCORD_CLIENT_ID / DISCORD_CLIENT_SECRET non configurés".into());
+    };
+
+    let mut form: Vec<(&str, &str)> = vec![
+        ("client_id", client_id.as_str()),
+        ("client_secret", client_secret.as_str()),
+    ];
+    form.extend_from_slice(grant);
+    let encoded = serde_urlencoded::to_string(&form).map_err(|e| e.to_string())?;
+
+    let response = Client::new()
+        .post(format!("{}/oauth2/token", discord_api_base_url()))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(encoded)
+        .send()
+        .await
+        .map_err(|_| "Discord API indisponible".to_string())?;
+
+    if !response.status().is_success() {
+        let details = response.text().await.unwrap_or_default();
+        return Err(format!("Échange OAuth2 refusé par Discord: {details}"));
+    }
+
+    response
+        .json::<TokenResponse>()
+        .await
+        .map_err(|_| "Réponse OAuth2 Discord invalide".to_string())
+}
+
+async fn link_to_user(
+    pool: &SqlitePool,
+    user_id: &str,
+    authorization: &str,
+    refresh_token: Option<&str>,
+    expires_at: Option<i64>,
+) -> Result<String, HttpResponse> {
+    let discord_user = crate::auth::fetch_discord_user(authorization)
+        .await
+        .map_err(|e| HttpResponse::Unauthorized().json(serde_json::json!({ "error": e })))?;
+
+    let owner: Option<String> = sqlx::query_scalar("SELECT id FROM users WHERE discord_id = ?")
+        .bind(&discord_user.id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    if owner.as_deref().is_some_and(|owner| owner != user_id) {
+        return Err(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Ce compte Discord est déjà lié à un autre utilisateur"
+        })));
+    }
+
+    sqlx::query("UPDATE users SET discord_id = ?, discord_access_token = ?, discord_refresh_token = ?, discord_token_expires_at = ?, discord_token_status = 'linked' WHERE id = ?")
+        .bind(&discord_user.id)
+        .bind(crate::crypto::encrypt_token(authorization))
+        .bind(refresh_token.map(crate::crypto::encrypt_token))
+        .bind(expires_at)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Liaison impossible" })))?;
+
+    Ok(discord_user.id)
+}
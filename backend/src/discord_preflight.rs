@@ -0,0 +1,290 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Discord voice join preflight
+// ═══════════════════════════════════════════════════════
+//
+// A voice join that Discord refuses (missing permission, full channel...)
+// does not fail: the gateway just never sends the voice events, and
+// `POST /api/discord/voice/join` times out (20 seconds by default). The
+// preflight answers beforehand whether a join can succeed, with the reasons
+// it can't, so the UI can disable the join button instead.
+//
+// It computes the user's permissions in the channel the way Discord does
+// (owner, @everyone and role permissions, ADMINISTRATOR, then channel
+// overwrites: @everyone, roles, member) from the channel, the guild and the
+// user's member object. Those are fetched with the user's token and kept
+// `CACHE_TTL` per account, so a UI checking every voice channel of a guild
+// costs a few Discord calls. Occupancy comes from the voice states the
+// account's gateway session has seen; without a session it is not checked.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::{discord_api_base_url, extract_claims};
+use crate::discord_gateway::{DiscordGateways, VoiceJoinPayload};
+
+const CACHE_TTL: Duration = Duration::from_secs(60);
+/// Expired entries are dropped once the cache grows past this.
+const CACHE_PRUNE_SIZE: usize = 1000;
+const DISCORD_TIMEOUT: Duration = Duration::from_secs(5);
+
+const ADMINISTRATOR: u64 = 1 << 3;
+const VIEW_CHANNEL: u64 = 1 << 10;
+const CONNECT: u64 = 1 << 20;
+const MOVE_MEMBERS: u64 = 1 << 24;
+
+/// Guild voice and stage channels.
+const VOICE_CHANNEL_TYPES: [u64; 2] = [2, 13];
+
+// ── Cache ───────────────────────────────────────────────
+
+/// A Discord API answer: the JSON body, or the HTTP status it failed with.
+type Fetched = Result<serde_json::Value, u16>;
+
+/// Discord API answers by account key and path.
+pub type DiscordPreflightCache = Arc<Mutex<HashMap<String, (Instant, Fetched)>>>;
+
+pub fn create_discord_preflight_cache() -> DiscordPreflightCache {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// GET `path` with the user's token, through the cache. Network errors are
+/// reported as status 0 and not cached.
+async fn fetch_cached(cache: &DiscordPreflightCache, account_key: &str, token: &str, path: &str) -> Fetched {
+    let cache_key = format!("{account_key}:{path}");
+    if let Some((at, fetched)) = cache.lock().unwrap().get(&cache_key) {
+        if at.elapsed() < CACHE_TTL {
+            return fetched.clone();
+        }
+    }
+
+    let response = Client::new()
+        .get(format!("{}{}", discord_api_base_url(), path))
+        .header("Authorization", token)
+        .timeout(DISCORD_TIMEOUT)
+        .send()
+        .await;
+    let fetched = match response {
+        Ok(res) if res.status().is_success() => res.json::<serde_json::Value>().await.map_err(|_| 0),
+        Ok(res) => Err(res.status().as_u16()),
+        Err(_) => Err(0),
+    };
+    // Rate limits and outages are not worth remembering.
+    if matches!(fetched, Err(0) | Err(429)) || matches!(fetched, Err(status) if status >= 500) {
+        return fetched;
+    }
+
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= CACHE_PRUNE_SIZE {
+        cache.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+    }
+    cache.insert(cache_key, (Instant::now(), fetched.clone()));
+    fetched
+}
+
+// ── Permissions ─────────────────────────────────────────
+
+fn parse_permissions(value: Option<&serde_json::Value>) -> u64 {
+    value.and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or(0)
+}
+
+fn str_field<'a>(value: &'a serde_json::Value, key: &str) -> &'a str {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+/// The member's permissions in the channel, as Discord computes them.
+fn channel_permissions(
+    guild: &serde_json::Value,
+    member: &serde_json::Value,
+    channel: &serde_json::Value,
+    user_id: &str,
+) -> u64 {
+    let guild_id = str_field(guild, "id");
+    if !user_id.is_empty() && str_field(guild, "owner_id") == user_id {
+        return u64::MAX;
+    }
+
+    let member_roles: Vec<&str> = member
+        .get("roles")
+        .and_then(|v| v.as_array())
+        .map(|roles| roles.iter().filter_map(|r| r.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut permissions = 0;
+    for role in guild.get("roles").and_then(|v| v.as_array()).into_iter().flatten() {
+        let id = str_field(role, "id");
+        if id == guild_id || member_roles.contains(&id) {
+            permissions |= parse_permissions(role.get("permissions"));
+        }
+    }
+    if permissions & ADMINISTRATOR != 0 {
+        return u64::MAX;
+    }
+
+    let overwrites: Vec<&serde_json::Value> = channel
+        .get("permission_overwrites")
+        .and_then(|v| v.as_array())
+        .map(|o| o.iter().collect())
+        .unwrap_or_default();
+    let apply = |permissions: u64, overwrite: &serde_json::Value| {
+        (permissions & !parse_permissions(overwrite.get("deny"))) | parse_permissions(overwrite.get("allow"))
+    };
+
+    if let Some(everyone) = overwrites.iter().find(|o| str_field(o, "id") == guild_id) {
+        permissions = apply(permissions, everyone);
+    }
+    // Role overwrites are merged: all denies, then all allows.
+    let (mut deny, mut allow) = (0, 0);
+    for overwrite in overwrites.iter().filter(|o| member_roles.contains(&str_field(o, "id"))) {
+        deny |= parse_permissions(overwrite.get("deny"));
+        allow |= parse_permissions(overwrite.get("allow"));
+    }
+    permissions = (permissions & !deny) | allow;
+    if let Some(own) = overwrites.iter().find(|o| !user_id.is_empty() && str_field(o, "id") == user_id) {
+        permissions = apply(permissions, own);
+    }
+    permissions
+}
+
+// ── Preflight ───────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct PreflightReason {
+    /// `relink_required`, `not_linked`, `channel_not_found`, `not_voice_channel`,
+    /// `not_in_guild`, `missing_view_channel`, `missing_connect`, `channel_full`
+    /// for blockers; `discord_unavailable`, `permissions_unverified` for warnings.
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VoicePreflight {
+    pub can_join: bool,
+    /// Why the join would fail. Empty when `can_join`.
+    pub reasons: Vec<PreflightReason>,
+    /// Checks that could not be made; the join may still fail.
+    pub warnings: Vec<PreflightReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_limit: Option<u64>,
+    /// Users seen in the channel, when the account has a gateway session.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub participants: Option<usize>,
+}
+
+fn reason(code: &'static str, message: impl Into<String>) -> PreflightReason {
+    PreflightReason { code, message: message.into() }
+}
+
+impl VoicePreflight {
+    fn blocked(code: &'static str, message: impl Into<String>) -> Self {
+        Self { reasons: vec![reason(code, message)], ..Default::default() }
+    }
+}
+
+async fn preflight(
+    pool: &SqlitePool,
+    gateways: &DiscordGateways,
+    cache: &DiscordPreflightCache,
+    user_id: &str,
+    guild_id: &str,
+    channel_id: &str,
+) -> VoicePreflight {
+    let is_snowflake = |id: &str| !id.is_empty() && id.len() <= 20 && id.bytes().all(|b| b.is_ascii_digit());
+    if !is_snowflake(guild_id) || !is_snowflake(channel_id) {
+        return VoicePreflight::blocked("channel_not_found", "Invalid guild or channel id");
+    }
+    let (link_status, _) = crate::discord_link::stored_status(pool, user_id).await;
+    if link_status.needs_relink() {
+        return VoicePreflight::blocked("relink_required", "Discord link expired or revoked, please re-link your account");
+    }
+    let account = match crate::discord_gateway::get_discord_account(pool, user_id).await {
+        Ok(account) => account,
+        Err(e) => return VoicePreflight::blocked("not_linked", e),
+    };
+
+    let mut result = VoicePreflight::default();
+    let channel = match fetch_cached(cache, &account.key, &account.token, &format!("/channels/{channel_id}")).await {
+        Ok(channel) => channel,
+        Err(401) => return VoicePreflight::blocked("relink_required", "Discord rejected the linked token, please re-link your account"),
+        // Discord answers 403 for channels the user can't see.
+        Err(403) => return VoicePreflight::blocked("missing_view_channel", "You can't see this channel"),
+        Err(404) => return VoicePreflight::blocked("channel_not_found", "Channel not found"),
+        Err(_) => {
+            result.can_join = true;
+            result.warnings.push(reason("discord_unavailable", "Discord could not be reached, the join was not checked"));
+            return result;
+        }
+    };
+    if str_field(&channel, "guild_id") != guild_id {
+        return VoicePreflight::blocked("channel_not_found", "Channel not found in this server");
+    }
+    let channel_type = channel.get("type").and_then(|v| v.as_u64()).unwrap_or(0);
+    if !VOICE_CHANNEL_TYPES.contains(&channel_type) {
+        return VoicePreflight::blocked("not_voice_channel", "Not a voice channel");
+    }
+    let user_limit = channel.get("user_limit").and_then(|v| v.as_u64()).unwrap_or(0);
+    result.user_limit = (user_limit > 0).then_some(user_limit);
+
+    let member = fetch_cached(cache, &account.key, &account.token, &format!("/users/@me/guilds/{guild_id}/member")).await;
+    let guild = fetch_cached(cache, &account.key, &account.token, &format!("/guilds/{guild_id}")).await;
+    let (permissions, discord_user_id) = match (&member, &guild) {
+        (Err(404), _) => return VoicePreflight::blocked("not_in_guild", "You are not a member of this server"),
+        (Ok(member), Ok(guild)) => {
+            let discord_user_id = member
+                .get("user")
+                .map(|u| str_field(u, "id"))
+                .filter(|id| !id.is_empty())
+                .unwrap_or(&account.key)
+                .to_string();
+            (Some(channel_permissions(guild, member, &channel, &discord_user_id)), discord_user_id)
+        }
+        _ => {
+            result.warnings.push(reason("permissions_unverified", "Your permissions in this server could not be read"));
+            (None, account.key.clone())
+        }
+    };
+    if let Some(permissions) = permissions {
+        if permissions & VIEW_CHANNEL == 0 {
+            result.reasons.push(reason("missing_view_channel", "You can't see this channel"));
+        } else if permissions & CONNECT == 0 {
+            result.reasons.push(reason("missing_connect", "You don't have permission to connect to this channel"));
+        }
+    }
+
+    let members = crate::discord_gateway::voice_channel_members(gateways, &account.key, guild_id, channel_id).await;
+    result.participants = members.as_ref().map(Vec::len);
+    if let Some(members) = members {
+        // Already inside, or allowed to move members: the limit doesn't apply.
+        let exempt = members.contains(&discord_user_id) || permissions.is_some_and(|p| p & MOVE_MEMBERS != 0);
+        if user_limit > 0 && members.len() as u64 >= user_limit && !exempt {
+            result.reasons.push(reason("channel_full", format!("Channel is full ({}/{user_limit})", members.len())));
+        }
+    }
+
+    result.can_join = result.reasons.is_empty();
+    result
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// POST /api/discord/voice/preflight — Whether joining a Discord voice channel would succeed, and why not
+/// Body: { guild_id, channel_id }
+pub async fn voice_preflight(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    gateways: web::Data<DiscordGateways>,
+    cache: web::Data<DiscordPreflightCache>,
+    body: web::Json<VoiceJoinPayload>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let result = preflight(pool.get_ref(), gateways.get_ref(), cache.get_ref(), &claims.sub, &body.guild_id, &body.channel_id).await;
+    HttpResponse::Ok().json(result)
+}
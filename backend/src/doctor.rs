@@ -0,0 +1,353 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Configuration doctor / startup self-test
+// ═══════════════════════════════════════════════════════
+//
+// Checks the things a deployment depends on but the server only notices
+// at runtime: DNS and Discord reachability, clock skew, free space for
+// uploads, DB write latency and obviously wrong configuration. Available
+// as `backend --doctor` (prints the report, exit code 1 on failure) and
+// as an admin endpoint.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
+
+use crate::auth::{discord_api_base_url, extract_claims};
+use crate::config::Settings;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const DISCORD_GATEWAY_HOST: &str = "gateway.discord.gg:443";
+
+const CLOCK_SKEW_WARN_SECS: i64 = 5;
+const CLOCK_SKEW_FAIL_SECS: i64 = 60;
+const DISK_FREE_WARN_BYTES: u64 = 1024 * 1024 * 1024;
+const DISK_FREE_FAIL_BYTES: u64 = 100 * 1024 * 1024;
+const DB_WRITE_WARN_MS: u128 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u128>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    pub status: CheckStatus,
+    pub checked_at: String,
+    pub checks: Vec<CheckResult>,
+}
+
+fn check(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> CheckResult {
+    CheckResult {
+        name,
+        status,
+        detail: detail.into(),
+        latency_ms: None,
+    }
+}
+
+// ── Checks ──────────────────────────────────────────────
+
+pub async fn run_checks(pool: &SqlitePool, settings: &Settings) -> DoctorReport {
+    let mut checks = check_config(settings);
+    checks.push(check_dns().await);
+    checks.extend(check_discord_api().await);
+    checks.push(check_discord_gateway().await);
+    checks.push(check_uploads_disk());
+    checks.push(check_db_write(pool).await);
+
+    let status = checks
+        .iter()
+        .map(|c| c.status)
+        .max()
+        .unwrap_or(CheckStatus::Ok);
+
+    DoctorReport {
+        status,
+        checked_at: chrono::Utc::now().to_rfc3339(),
+        checks,
+    }
+}
+
+fn check_config(settings: &Settings) -> Vec<CheckResult> {
+    // What would stop the server from starting fails the report.
+    let mut checks: Vec<CheckResult> = settings
+        .validate()
+        .into_iter()
+        .map(|issue| {
+            let status = if issue.fatal { CheckStatus::Fail } else { CheckStatus::Warn };
+            check("config.settings", status, format!("{}: {}", issue.field, issue.message))
+        })
+        .collect();
+    if checks.is_empty() {
+        checks.push(check(
+            "config.settings",
+            CheckStatus::Ok,
+            format!("Listening on {}:{}", settings.server.bind_address, settings.server.port),
+        ));
+    }
+
+    let raw_mode = std::env::var("DISCORD_LINK_MODE").unwrap_or_default();
+    let mode = crate::discord_oauth::link_mode();
+    let known = ["", "user_token", "oauth2", "oauth", "both"];
+    if !known.contains(&raw_mode.trim().to_lowercase().as_str()) {
+        checks.push(check(
+            "config.discord_link_mode",
+            CheckStatus::Warn,
+            format!("Unknown DISCORD_LINK_MODE={raw_mode}, falling back to user_token"),
+        ));
+    } else if mode.allows_oauth2() {
+        let missing: Vec<&str> = [
+            ("DISCORD_CLIENT_ID", crate::discord_oauth::client_id()),
+            ("DISCORD_CLIENT_SECRET", crate::discord_oauth::client_secret()),
+            ("DISCORD_REDIRECT_URI", crate::discord_oauth::redirect_uri()),
+        ]
+        .into_iter()
+        .filter(|(_, v)| v.is_none())
+        .map(|(k, _)| k)
+        .collect();
+        checks.push(if missing.is_empty() {
+            check("config.discord_oauth", CheckStatus::Ok, "OAuth2 credentials set")
+        } else {
+            check(
+                "config.discord_oauth",
+                CheckStatus::Fail,
+                format!("OAuth2 link mode enabled but missing {}", missing.join(", ")),
+            )
+        });
+    } else {
+        checks.push(check("config.discord_link_mode", CheckStatus::Ok, "user_token"));
+    }
+
+    let turn_problems = crate::rtc::turn_config_problems();
+    checks.push(if !turn_problems.is_empty() {
+        check("config.turn", CheckStatus::Warn, turn_problems.join("; "))
+    } else if crate::rtc::turn_urls().is_empty() {
+        check("config.turn", CheckStatus::Ok, "No TURN relay, voice rooms use STUN only")
+    } else {
+        check("config.turn", CheckStatus::Ok, format!("TURN relay: {}", crate::rtc::turn_urls().join(", ")))
+    });
+
+    if std::env::var("DISCORD_API_BASE_URL").is_ok() {
+        checks.push(check(
+            "config.discord_api_base_url",
+            CheckStatus::Warn,
+            format!("Discord API overridden: {}", discord_api_base_url()),
+        ));
+    }
+
+    checks
+}
+
+async fn check_dns() -> CheckResult {
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, tokio::net::lookup_host(DISCORD_GATEWAY_HOST)).await;
+    let mut res = match result {
+        Ok(Ok(mut addrs)) => match addrs.next() {
+            Some(addr) => check("dns", CheckStatus::Ok, format!("{DISCORD_GATEWAY_HOST} -> {}", addr.ip())),
+            None => check("dns", CheckStatus::Fail, format!("{DISCORD_GATEWAY_HOST} has no address")),
+        },
+        Ok(Err(e)) => check("dns", CheckStatus::Fail, format!("Cannot resolve {DISCORD_GATEWAY_HOST}: {e}")),
+        Err(_) => check("dns", CheckStatus::Fail, "DNS lookup timed out"),
+    };
+    res.latency_ms = Some(started.elapsed().as_millis());
+    res
+}
+
+/// Discord REST reachability, plus clock skew from the response `Date` header.
+async fn check_discord_api() -> Vec<CheckResult> {
+    let url = format!("{}/gateway", discord_api_base_url());
+    let started = Instant::now();
+    let response = reqwest::Client::new()
+        .get(&url)
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .await;
+    let latency = started.elapsed().as_millis();
+
+    let response = match response {
+        Ok(r) => r,
+        Err(e) => {
+            return vec![
+                CheckResult {
+                    latency_ms: Some(latency),
+                    ..check("discord.api", CheckStatus::Fail, format!("GET {url} failed: {e}"))
+                },
+                check("clock_skew", CheckStatus::Warn, "Not checked: Discord API unreachable"),
+            ];
+        }
+    };
+
+    let api = CheckResult {
+        latency_ms: Some(latency),
+        ..if response.status().is_success() {
+            check("discord.api", CheckStatus::Ok, format!("GET {url} -> {}", response.status()))
+        } else {
+            check("discord.api", CheckStatus::Warn, format!("GET {url} -> {}", response.status()))
+        }
+    };
+
+    let server_time = response
+        .headers()
+        .get("Date")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok());
+    let clock = match server_time {
+        Some(server_time) => {
+            let skew = (chrono::Utc::now().timestamp() - server_time.timestamp()).abs();
+            let status = if skew >= CLOCK_SKEW_FAIL_SECS {
+                CheckStatus::Fail
+            } else if skew >= CLOCK_SKEW_WARN_SECS {
+                CheckStatus::Warn
+            } else {
+                CheckStatus::Ok
+            };
+            check("clock_skew", status, format!("{skew}s off Discord's clock"))
+        }
+        None => check("clock_skew", CheckStatus::Warn, "No Date header in Discord response"),
+    };
+
+    vec![api, clock]
+}
+
+/// Open a gateway WebSocket and wait for Hello (op 10).
+async fn check_discord_gateway() -> CheckResult {
+    let started = Instant::now();
+    let attempt = async {
+        let (mut ws, _) = tokio_tungstenite::connect_async(crate::discord_gateway::DISCORD_GATEWAY_URL)
+            .await
+            .map_err(|e| format!("Connection failed: {e}"))?;
+        let first = ws.next().await;
+        let _ = ws.close(None).await;
+        match first {
+            Some(Ok(tokio_tungstenite::tungstenite::Message::Text(text))) if text.contains("\"op\":10") => Ok(()),
+            Some(Ok(other)) => Err(format!("Unexpected first frame: {other:?}")),
+            Some(Err(e)) => Err(format!("Read failed: {e}")),
+            None => Err("Closed before Hello".to_string()),
+        }
+    };
+
+    let mut res = match tokio::time::timeout(CHECK_TIMEOUT, attempt).await {
+        Ok(Ok(())) => check("discord.gateway", CheckStatus::Ok, "Received Hello"),
+        Ok(Err(e)) => check("discord.gateway", CheckStatus::Fail, e),
+        Err(_) => check("discord.gateway", CheckStatus::Fail, "Timed out waiting for Hello"),
+    };
+    res.latency_ms = Some(started.elapsed().as_millis());
+    res
+}
+
+/// Free space and write access of the disk file store (see `files`).
+fn check_uploads_disk() -> CheckResult {
+    let root = match crate::files::create_file_storage().as_ref() {
+        crate::files::FileStore::Disk(root) => root.to_string_lossy().into_owned(),
+        crate::files::FileStore::S3(_) => return check("uploads.disk", CheckStatus::Ok, "Files are stored in the S3 bucket"),
+    };
+    if let Err(e) = std::fs::create_dir_all(&root) {
+        return check("uploads.disk", CheckStatus::Fail, format!("Cannot create {root}/: {e}"));
+    }
+    let probe = std::path::Path::new(&root).join(".doctor-probe");
+    if let Err(e) = std::fs::write(&probe, b"ok") {
+        return check("uploads.disk", CheckStatus::Fail, format!("{root}/ is not writable: {e}"));
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    match free_disk_bytes(&root) {
+        Some(free) => {
+            let status = if free < DISK_FREE_FAIL_BYTES {
+                CheckStatus::Fail
+            } else if free < DISK_FREE_WARN_BYTES {
+                CheckStatus::Warn
+            } else {
+                CheckStatus::Ok
+            };
+            check("uploads.disk", status, format!("{} MiB free", free / (1024 * 1024)))
+        }
+        None => check("uploads.disk", CheckStatus::Ok, "Writable (free space unknown on this platform)"),
+    }
+}
+
+#[cfg(unix)]
+fn free_disk_bytes(path: &str) -> Option<u64> {
+    let c_path = std::ffi::CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` a valid out-pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_disk_bytes(_path: &str) -> Option<u64> {
+    None
+}
+
+async fn check_db_write(pool: &SqlitePool) -> CheckResult {
+    let started = Instant::now();
+    let result = sqlx::query(
+        "INSERT INTO doctor_probe (id, checked_at) VALUES (1, ?) \
+         ON CONFLICT(id) DO UPDATE SET checked_at = excluded.checked_at"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    let latency = started.elapsed().as_millis();
+
+    let res = match result {
+        Ok(_) if latency > DB_WRITE_WARN_MS => check("db.write", CheckStatus::Warn, format!("Slow write ({latency} ms)")),
+        Ok(_) => check("db.write", CheckStatus::Ok, "Write OK"),
+        Err(e) => check("db.write", CheckStatus::Fail, format!("Write failed: {e}")),
+    };
+    CheckResult {
+        latency_ms: Some(latency),
+        ..res
+    }
+}
+
+// ── Entry points ────────────────────────────────────────
+
+/// `backend --doctor`: print the report as JSON and return the process exit code.
+pub fn run_cli() -> i32 {
+    let rt = actix_web::rt::System::new();
+    rt.block_on(async {
+        let settings = match Settings::load(crate::config::path_from_args().as_deref()) {
+            Ok(settings) => settings,
+            Err(error) => {
+                eprintln!("{error}");
+                return 1;
+            }
+        };
+        crate::config::install(settings.clone());
+        let pool = crate::db::init_db(&settings.database).await;
+        let report = run_checks(&pool, &settings).await;
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+        if report.status == CheckStatus::Fail { 1 } else { 0 }
+    })
+}
+
+/// GET /api/server/diagnostics/doctor — Run the self-test (MANAGE_SERVER)
+pub async fn doctor(req: HttpRequest, pool: web::Data<SqlitePool>, settings: web::Data<Settings>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    HttpResponse::Ok().json(run_checks(pool.get_ref(), settings.get_ref()).await)
+}
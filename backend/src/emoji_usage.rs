@@ -0,0 +1,224 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Emoji usage analytics
+// ═══════════════════════════════════════════════════════
+//
+// Every emoji used is counted in a daily rollup (`emoji_usage`), split by
+// source: `message` for each distinct emoji in a posted message's content
+// (Unicode emoji and `:name:` custom emoji), `reaction` for each reaction
+// added. Removing a reaction or editing a message does not uncount it.
+// Custom emoji are keyed `custom:<id>`, like reactions, and their counts
+// are dropped with them.
+//
+// `GET /api/emojis/top` ranks the server's emoji over the last days; the
+// custom emoji listing (`GET /api/emojis`, the picker) is sorted by the
+// same counts over `EMOJI_POPULARITY_DAYS`.
+//
+// Config (env):
+//   EMOJI_POPULARITY_DAYS  window used to sort the custom emoji listing (default 30)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+
+use crate::auth::extract_claims;
+use crate::emojis::{CustomEmoji, REACTION_PREFIX};
+
+const DEFAULT_POPULARITY_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 365;
+const DEFAULT_TOP_LIMIT: i64 = 20;
+const MAX_TOP_LIMIT: i64 = 100;
+/// Unicode emoji past this many distinct ones in one message are not counted.
+const MAX_COUNTED_PER_MESSAGE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageSource {
+    Message,
+    Reaction,
+}
+
+impl UsageSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            UsageSource::Message => "message",
+            UsageSource::Reaction => "reaction",
+        }
+    }
+}
+
+pub fn popularity_days() -> i64 {
+    std::env::var("EMOJI_POPULARITY_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|n| (1..=MAX_DAYS).contains(n))
+        .unwrap_or(DEFAULT_POPULARITY_DAYS)
+}
+
+/// First day (`YYYY-MM-DD`) of a window of `days` ending today.
+pub(crate) fn window_start(days: i64) -> String {
+    (chrono::Utc::now() - chrono::Duration::days(days - 1)).format("%Y-%m-%d").to_string()
+}
+
+// ── Counting ────────────────────────────────────────────
+
+fn is_emoji_base(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x3030 | 0x303D)
+}
+
+fn is_modifier(c: char) -> bool {
+    matches!(c as u32, 0xFE0F | 0x1F3FB..=0x1F3FF | 0x20E3 | 0xE0020..=0xE007F)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+/// Distinct Unicode emoji in `content`, in order: a base character with its
+/// modifiers (variation selector, skin tone, keycap, tags), joined sequences
+/// (`ZWJ`) kept whole and regional indicators paired into flags.
+pub fn unicode_emojis(content: &str) -> Vec<String> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut found: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() && found.len() < MAX_COUNTED_PER_MESSAGE {
+        let c = chars[i];
+        if !is_emoji_base(c) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        if is_regional_indicator(c) {
+            if chars.get(i).is_some_and(|n| is_regional_indicator(*n)) {
+                i += 1;
+            }
+        } else {
+            loop {
+                while chars.get(i).is_some_and(|n| is_modifier(*n)) {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&'\u{200D}') && chars.get(i + 1).is_some_and(|n| is_emoji_base(*n)) {
+                    i += 2;
+                } else {
+                    break;
+                }
+            }
+        }
+        let emoji: String = chars[start..i].iter().collect();
+        if emoji.chars().count() <= 16 && !found.contains(&emoji) {
+            found.push(emoji);
+        }
+    }
+    found
+}
+
+/// Count one use of each of `emojis` (reaction keys) today.
+pub(crate) async fn record(pool: &SqlitePool, emojis: &[String], source: UsageSource) {
+    if emojis.is_empty() {
+        return;
+    }
+    let day = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    for emoji in emojis {
+        let _ = sqlx::query(
+            "INSERT INTO emoji_usage (day, emoji, source, uses) VALUES (?, ?, ?, 1) \
+             ON CONFLICT(day, emoji, source) DO UPDATE SET uses = uses + 1"
+        )
+        .bind(&day)
+        .bind(emoji)
+        .bind(source.as_str())
+        .execute(pool)
+        .await;
+    }
+}
+
+/// Count the emoji of a message just posted, given the custom emoji it resolved to.
+pub(crate) async fn record_message(pool: &SqlitePool, content: &str, custom_emojis: &[CustomEmoji]) {
+    let mut emojis = unicode_emojis(content);
+    emojis.extend(custom_emojis.iter().map(|e| format!("{REACTION_PREFIX}{}", e.id)));
+    record(pool, &emojis, UsageSource::Message).await;
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct TopQuery {
+    pub days: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopEmoji {
+    /// The emoji itself, or `custom:<id>`.
+    pub emoji: String,
+    pub custom_emoji: Option<CustomEmoji>,
+    pub messages: i64,
+    pub reactions: i64,
+    pub total: i64,
+}
+
+/// GET /api/emojis/top?days=&limit= — Most used emoji of the server
+pub async fn top_emojis(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<TopQuery>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let days = query.days.unwrap_or(DEFAULT_POPULARITY_DAYS).clamp(1, MAX_DAYS);
+    let limit = query.limit.unwrap_or(DEFAULT_TOP_LIMIT).clamp(1, MAX_TOP_LIMIT);
+    // Counts of deleted custom emoji are gone with them; the join skips a
+    // custom emoji deleted while this runs.
+    let rows = sqlx::query(
+        "SELECT u.emoji, \
+                SUM(CASE WHEN u.source = 'message' THEN u.uses ELSE 0 END) AS messages, \
+                SUM(CASE WHEN u.source = 'reaction' THEN u.uses ELSE 0 END) AS reactions, \
+                SUM(u.uses) AS total, \
+                e.id, e.name, e.image_url, e.alt_text \
+         FROM emoji_usage u \
+         LEFT JOIN custom_emojis e ON u.emoji = 'custom:' || e.id \
+         WHERE u.day >= ? AND (u.emoji NOT LIKE 'custom:%' OR e.id IS NOT NULL) \
+         GROUP BY u.emoji \
+         ORDER BY total DESC, u.emoji ASC \
+         LIMIT ?"
+    )
+    .bind(window_start(days))
+    .bind(limit)
+    .fetch_all(pool.get_ref())
+    .await;
+    let Ok(rows) = rows else {
+        return HttpResponse::InternalServerError().finish();
+    };
+
+    let emojis: Vec<TopEmoji> = rows
+        .iter()
+        .map(|row| TopEmoji {
+            emoji: row.get("emoji"),
+            custom_emoji: row.try_get::<Option<String>, _>("id").ok().flatten().map(|id| CustomEmoji {
+                id,
+                name: row.get("name"),
+                image_url: row.get("image_url"),
+                alt_text: row.try_get("alt_text").unwrap_or(None),
+            }),
+            messages: row.get("messages"),
+            reactions: row.get("reactions"),
+            total: row.get("total"),
+        })
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "days": days, "emojis": emojis }))
+}
+
+/// Uses of each custom emoji (by id) over the popularity window.
+pub(crate) async fn custom_popularity(pool: &SqlitePool) -> HashMap<String, i64> {
+    sqlx::query(
+        "SELECT emoji, SUM(uses) AS total FROM emoji_usage \
+         WHERE day >= ? AND emoji LIKE 'custom:%' GROUP BY emoji"
+    )
+    .bind(window_start(popularity_days()))
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+    .iter()
+    .filter_map(|row| {
+        let emoji: String = row.get("emoji");
+        Some((emoji.strip_prefix(REACTION_PREFIX)?.to_string(), row.get("total")))
+    })
+    .collect()
+}
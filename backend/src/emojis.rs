@@ -0,0 +1,355 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Custom emoji
+// ═══════════════════════════════════════════════════════
+//
+// Admins upload server emoji: a PNG, cropped and scaled like role icons to
+// `EMOJI_SIZE` and served under `/uploads/emojis/` (see `uploads`), under a
+// unique name of 2-32 lowercase letters, digits or `_`.
+//
+// In messages, `:name:` stands for the emoji. It is resolved at read time:
+// message payloads (history, search, pins, live `message` and
+// `message_updated` events) list the custom emoji they use in
+// `custom_emojis`, so clients can render them without a lookup. A deleted
+// or unknown name is left as plain text.
+//
+// As a reaction, a custom emoji is sent as `emoji_id` (or
+// `emoji: "custom:<id>"`) and stored under the `custom:<id>` key.
+// Deleting the emoji removes its reactions and usage counts. Emoji carry
+// alt text for screen readers (see `alt_text`). The listing puts the most
+// used first (see `emoji_usage`).
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::alt_text::{AltTextPolicy, AltTextQuery};
+use crate::auth::extract_claims;
+use crate::files::FileStorage;
+use crate::messages::Message;
+use crate::ws::Broadcaster;
+
+/// Emoji are stored as `EMOJI_SIZE`×`EMOJI_SIZE` PNGs.
+const EMOJI_SIZE: u32 = 128;
+const MAX_EMOJI_UPLOAD_BYTES: usize = 256 * 1024;
+const MAX_CUSTOM_EMOJIS: i64 = 200;
+const MIN_NAME_LEN: usize = 2;
+const MAX_NAME_LEN: usize = 32;
+/// Names past this many in one message are not resolved.
+const MAX_EMOJIS_PER_MESSAGE: usize = 50;
+/// Prefix of custom emoji reaction keys.
+pub const REACTION_PREFIX: &str = "custom:";
+
+#[derive(Debug, Clone, Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct CustomEmoji {
+    pub id: String,
+    pub name: String,
+    pub image_url: String,
+    #[serde(default)]
+    pub alt_text: Option<String>,
+}
+
+impl CustomEmoji {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            image_url: row.get("image_url"),
+            alt_text: row.try_get("alt_text").unwrap_or(None),
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn valid_name(name: &str) -> bool {
+    (MIN_NAME_LEN..=MAX_NAME_LEN).contains(&name.len()) && name.chars().all(is_name_char)
+}
+
+/// Lowercased `:name:` references in `content`, without duplicates, in order.
+pub(crate) fn parse_emoji_names(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start + 1..];
+        let len = after.find(|c: char| !is_name_char(c)).unwrap_or(after.len());
+        if after[len..].starts_with(':') && (MIN_NAME_LEN..=MAX_NAME_LEN).contains(&len) {
+            let name = after[..len].to_ascii_lowercase();
+            if !names.contains(&name) {
+                names.push(name);
+                if names.len() == MAX_EMOJIS_PER_MESSAGE {
+                    break;
+                }
+            }
+            rest = &after[len + 1..];
+        } else {
+            // The character that ended the name may open the next one.
+            rest = &after[len..];
+        }
+    }
+    names
+}
+
+pub(crate) async fn fetch_emoji(pool: &SqlitePool, id: &str) -> Option<CustomEmoji> {
+    sqlx::query("SELECT id, name, image_url, alt_text FROM custom_emojis WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| CustomEmoji::from_row(&row))
+}
+
+/// Custom emoji matching `names` or `ids`, in no particular order.
+async fn fetch_matching(pool: &SqlitePool, names: &[String], ids: &[String]) -> Vec<CustomEmoji> {
+    if names.is_empty() && ids.is_empty() {
+        return Vec::new();
+    }
+    // `IN ()` is valid SQLite and matches nothing.
+    let sql = format!(
+        "SELECT id, name, image_url, alt_text FROM custom_emojis WHERE name IN ({}) OR id IN ({})",
+        vec!["?"; names.len()].join(", "),
+        vec!["?"; ids.len()].join(", "),
+    );
+    let mut query = sqlx::query(&sql);
+    for value in names.iter().chain(ids) {
+        query = query.bind(value);
+    }
+    query
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(CustomEmoji::from_row)
+        .collect()
+}
+
+/// Custom emoji without alt text, by name.
+pub(crate) async fn fetch_without_alt_text(pool: &SqlitePool) -> Vec<CustomEmoji> {
+    sqlx::query("SELECT id, name, image_url, alt_text FROM custom_emojis WHERE alt_text IS NULL ORDER BY name")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(CustomEmoji::from_row)
+        .collect()
+}
+
+/// The custom emoji used as `:name:` in `content`.
+pub(crate) async fn resolve_content(pool: &SqlitePool, content: &str) -> Vec<CustomEmoji> {
+    fetch_matching(pool, &parse_emoji_names(content), &[]).await
+}
+
+/// Fill `custom_emojis` of each message with the custom emoji its content
+/// and its reactions use.
+pub(crate) async fn attach_custom_emojis(pool: &SqlitePool, messages: &mut [Message]) {
+    let mut names: HashSet<String> = HashSet::new();
+    let mut ids: HashSet<String> = HashSet::new();
+    let per_message: Vec<(Vec<String>, Vec<String>)> = messages
+        .iter()
+        .map(|m| {
+            let message_names = parse_emoji_names(&m.content);
+            let message_ids: Vec<String> = m
+                .reactions
+                .iter()
+                .filter_map(|r| r.emoji.strip_prefix(REACTION_PREFIX).map(str::to_string))
+                .collect();
+            names.extend(message_names.iter().cloned());
+            ids.extend(message_ids.iter().cloned());
+            (message_names, message_ids)
+        })
+        .collect();
+
+    let names: Vec<String> = names.into_iter().collect();
+    let ids: Vec<String> = ids.into_iter().collect();
+    let emojis = fetch_matching(pool, &names, &ids).await;
+    if emojis.is_empty() {
+        return;
+    }
+
+    for (message, (message_names, message_ids)) in messages.iter_mut().zip(per_message) {
+        message.custom_emojis = emojis
+            .iter()
+            .filter(|e| message_names.contains(&e.name) || message_ids.contains(&e.id))
+            .cloned()
+            .collect();
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/emojis — List the server's custom emoji, most used first
+pub async fn list_emojis(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let mut emojis: Vec<CustomEmoji> = sqlx::query("SELECT id, name, image_url, alt_text FROM custom_emojis ORDER BY name")
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(CustomEmoji::from_row)
+        .collect();
+    let popularity = crate::emoji_usage::custom_popularity(pool.get_ref()).await;
+    // Stable: equally used emoji stay by name.
+    emojis.sort_by_key(|e| std::cmp::Reverse(popularity.get(&e.id).copied().unwrap_or(0)));
+    HttpResponse::Ok().json(emojis)
+}
+
+/// POST /api/server/emojis/{name}?alt_text= — Upload a custom emoji, multipart PNG (MANAGE_EMOJIS)
+pub async fn create_emoji(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    query: web::Query<AltTextQuery>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_EMOJIS).await {
+        return response;
+    }
+
+    let name = path.into_inner().trim().to_lowercase();
+    if !valid_name(&name) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Emoji name must be 2-32 letters, digits or underscores"
+        }));
+    }
+    let alt_text = match crate::alt_text::normalize(query.alt_text.as_deref()) {
+        Ok(text) => text,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+    if alt_text.is_none() && crate::alt_text::policy() == AltTextPolicy::Required {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Alt text is required" }));
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM custom_emojis")
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if count >= MAX_CUSTOM_EMOJIS {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Custom emoji limit reached (200)" }));
+    }
+    let taken: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM custom_emojis WHERE name = ?)")
+        .bind(&name)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if taken {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "An emoji with this name already exists" }));
+    }
+
+    let mut bytes = Vec::new();
+    if let Some(Ok(mut field)) = payload.next().await {
+        while let Some(Ok(chunk)) = field.next().await {
+            if bytes.len() + chunk.len() > MAX_EMOJI_UPLOAD_BYTES {
+                return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Emoji too large (max 256KB)" }));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+    }
+    if bytes.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "No file provided" }));
+    }
+    if let Err(response) = crate::scanning::refuse_flagged(&bytes).await {
+        return response;
+    }
+
+    let png = match web::block(move || crate::roles::process_icon(&bytes, EMOJI_SIZE)).await {
+        Ok(Ok(png)) => png,
+        Ok(Err(error)) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let Ok(image_url) = crate::uploads::store(pool.get_ref(), storage.get_ref(), &format!("emojis/{}_{}.png", name, id), png).await else {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save file" }));
+    };
+
+    let emoji = CustomEmoji { id, name, image_url, alt_text };
+    let result = sqlx::query("INSERT INTO custom_emojis (id, name, image_url, alt_text, created_by, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(&emoji.id)
+        .bind(&emoji.name)
+        .bind(&emoji.image_url)
+        .bind(&emoji.alt_text)
+        .bind(&claims.sub)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+    if let Err(e) = result {
+        crate::uploads::remove(pool.get_ref(), storage.get_ref(), Some(&emoji.image_url)).await;
+        // Lost a race with another upload of the same name.
+        if e.as_database_error().is_some_and(|d| d.is_unique_violation()) {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": "An emoji with this name already exists" }));
+        }
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let event = serde_json::json!({ "type": "emoji_created", "emoji": emoji });
+    let _ = broadcaster.send(event.to_string());
+
+    HttpResponse::Created().json(emoji)
+}
+
+/// DELETE /api/server/emojis/{id} — Delete a custom emoji and its reactions (MANAGE_EMOJIS)
+pub async fn delete_emoji(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_EMOJIS).await {
+        return response;
+    }
+
+    let Some(emoji) = fetch_emoji(pool.get_ref(), &path.into_inner()).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Emoji not found" }));
+    };
+
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let key = format!("{REACTION_PREFIX}{}", emoji.id);
+    let removed = sqlx::query("DELETE FROM message_reactions WHERE emoji = ?")
+        .bind(&key)
+        .execute(&mut *tx)
+        .await
+        .is_ok()
+        && sqlx::query("DELETE FROM emoji_usage WHERE emoji = ?")
+            .bind(&key)
+            .execute(&mut *tx)
+            .await
+            .is_ok()
+        && sqlx::query("DELETE FROM custom_emojis WHERE id = ?")
+            .bind(&emoji.id)
+            .execute(&mut *tx)
+            .await
+            .is_ok();
+    if !removed || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::uploads::remove(pool.get_ref(), storage.get_ref(), Some(&emoji.image_url)).await;
+    // Its reactions were votes on forum posts.
+    crate::forum::refresh_votes(pool.get_ref(), None).await;
+
+    let event = serde_json::json!({ "type": "emoji_deleted", "id": emoji.id, "name": emoji.name });
+    let _ = broadcaster.send(event.to_string());
+
+    HttpResponse::NoContent().finish()
+}
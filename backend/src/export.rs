@@ -0,0 +1,142 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Streamed message listings (NDJSON) and room export
+// ═══════════════════════════════════════════════════════
+//
+// History and search answer one JSON document by default. A client sending
+// `Accept: application/x-ndjson` gets the same messages as newline-delimited
+// JSON instead, one message per line, serialized as the response is
+// written: nothing is buffered as a whole and the client can render each
+// line as it arrives. A listing with page flags ends with one more line,
+// `{"page": {...}}`.
+//
+// `GET /api/rooms/{id}/export` (admin) streams a whole room the same way,
+// oldest first, reading `EXPORT_BATCH` messages at a time so memory stays
+// flat whatever the size of the room. It ends with `{"end": {"count": n}}`;
+// a stream cut before that line is incomplete and can be resumed with
+// `after=<last message id>`.
+
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::extract_claims;
+use crate::messages::{self, Direction, Message};
+
+pub const NDJSON: &str = "application/x-ndjson";
+/// Messages read (and enriched) at once by an export.
+const EXPORT_BATCH: i64 = 200;
+
+// ── NDJSON responses ────────────────────────────────────
+
+/// Whether the client asked for NDJSON.
+pub fn wants_ndjson(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.split(',').any(|t| t.trim().starts_with(NDJSON)))
+}
+
+fn json_line<T: Serialize>(value: &T) -> Bytes {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Stream `messages` one per line, then `trailer` if any.
+pub fn ndjson_response(messages: Vec<Message>, trailer: Option<serde_json::Value>) -> HttpResponse {
+    let lines = stream::iter(messages)
+        .map(|message| json_line(&message))
+        .chain(stream::iter(trailer.map(|t| json_line(&t))))
+        .map(Ok::<_, actix_web::Error>);
+    HttpResponse::Ok().content_type(NDJSON).streaming(lines)
+}
+
+// ── Room export ─────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// Resume after this message id.
+    pub after: Option<String>,
+}
+
+struct ExportState {
+    pool: SqlitePool,
+    room_id: String,
+    /// `(created_at, id)` of the last message sent.
+    cursor: Option<(String, String)>,
+    count: u64,
+    done: bool,
+}
+
+/// The next batch of the export as lines, or the closing line once the room is exhausted.
+async fn next_batch(mut state: ExportState) -> Option<(Bytes, ExportState)> {
+    if state.done {
+        return None;
+    }
+    let cursor = state.cursor.as_ref().map(|(at, id)| (at.as_str(), id.as_str()));
+    let (mut batch, more) = messages::fetch_page(&state.pool, &state.room_id, true, cursor, Direction::Newer, EXPORT_BATCH).await;
+
+    messages::enrich_messages_with_reactions(&state.pool, &mut batch).await;
+    crate::emojis::attach_custom_emojis(&state.pool, &mut batch).await;
+    crate::files::attach_to_messages(&state.pool, &mut batch).await;
+    crate::polls::attach_to_messages(&state.pool, None, &mut batch).await;
+
+    let mut chunk = Vec::new();
+    for message in &batch {
+        chunk.extend_from_slice(&json_line(message));
+    }
+    state.count += batch.len() as u64;
+    if let Some(last) = batch.last() {
+        state.cursor = Some((last.created_at.clone(), last.id.clone()));
+    }
+    if !more {
+        chunk.extend_from_slice(&json_line(&serde_json::json!({ "end": { "count": state.count } })));
+        state.done = true;
+    }
+    Some((Bytes::from(chunk), state))
+}
+
+/// GET /api/rooms/{room_id}/export?after= — Every message of a room as NDJSON, oldest first (MANAGE_SERVER)
+pub async fn export_room(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let room_id = path.into_inner();
+    let exists: Option<String> = sqlx::query_scalar("SELECT id FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if exists.is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    }
+
+    let cursor = match &query.after {
+        Some(id) => match messages::cursor_position(pool.get_ref(), &room_id, id).await {
+            Some(at) => Some((at, id.clone())),
+            None => return messages::unknown_cursor(),
+        },
+        None => None,
+    };
+
+    let state = ExportState { pool: pool.get_ref().clone(), room_id: room_id.clone(), cursor, count: 0, done: false };
+    let lines = stream::unfold(state, next_batch).map(Ok::<_, actix_web::Error>);
+
+    HttpResponse::Ok()
+        .content_type(NDJSON)
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"room-{room_id}.ndjson\"")))
+        .streaming(lines)
+}
@@ -0,0 +1,1323 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — File attachments
+// ═══════════════════════════════════════════════════════
+//
+// `POST /api/files` takes one multipart file, up to FILE_MAX_BYTES. Its type
+// is sniffed from the content (the client's name and MIME type are not
+// trusted) and must be one of `ALLOWED_TYPES`. Files are content-addressed:
+// stored once under their SHA-256 however many times they are uploaded.
+// PNG images, the format the server decodes, also get a thumbnail of at
+// most `THUMBNAIL_SIZE` px, stored the same way.
+//
+// Each upload is an `attachments` row owned by the uploader and linked to
+// no message yet. A WebSocket `message` with `attachment_ids` links them
+// (only the sender's own, unlinked ones). The `attachments` job queue (see
+// `jobs`) drops uploads left unlinked for `UNLINKED_TTL_HOURS` and files no
+// attachment uses anymore. Images and videos can carry alt text (see
+// `alt_text`).
+//
+// Files are never served statically. Message payloads carry signed URLs,
+// `/api/files/{id}?variant=&expires=&sig=` (HMAC-SHA256 with JWT_SECRET),
+// valid for FILE_URL_TTL_SECS, so they work in <img> without headers.
+// Images, audio and video are served inline, everything else as a download.
+// Originals honour single `Range` requests so media can be streamed.
+//
+// Uploads are spooled to a temporary file while they arrive and hashed on
+// the way, so a large file never sits in memory: it is renamed into the
+// disk store, or streamed to the bucket. Types the server analyses
+// (`BUFFERED_TYPES`) are read back first.
+//
+// With an upload scanner configured (see `scanning`), uploads are scanned
+// before they are stored; flagged ones are kept in quarantine, out of reach
+// of messages and downloads until an admin reviews them.
+//
+// Storage (env FILE_STORAGE):
+//   disk (default)  under FILE_STORAGE_DIR (default `files`)
+//   s3              S3_ENDPOINT, S3_BUCKET, S3_REGION, S3_ACCESS_KEY_ID and
+//                   S3_SECRET_ACCESS_KEY; path-style requests, SigV4-signed.
+//                   With S3_PRESIGNED_DOWNLOADS=true, a valid download link
+//                   redirects to a short-lived presigned URL on
+//                   S3_PUBLIC_ENDPOINT (default S3_ENDPOINT) instead of
+//                   going through the server.
+// `backend --migrate-files` copies the disk store (attachments and every
+// other stored object) to the bucket.
+//
+// Config (env): FILE_MAX_BYTES (default 25 MB), FILE_URL_TTL_SECS (default
+// 86400)
+
+use actix_multipart::Multipart;
+use actix_web::http::header::{Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::alt_text::AltTextQuery;
+use crate::auth::extract_claims;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::messages::Message;
+use crate::scanning::{ScanResult, Upload};
+
+const DEFAULT_MAX_BYTES: usize = 25 * 1024 * 1024;
+const DEFAULT_URL_TTL_SECS: i64 = 24 * 60 * 60;
+const DEFAULT_STORAGE_DIR: &str = "files";
+/// Under the disk store, so a finished upload is renamed into place.
+const SPOOL_DIR: &str = ".uploads";
+/// Bytes of an upload kept in memory to sniff its type.
+const SNIFF_BYTES: usize = 512;
+/// Types the server analyses (thumbnails, waveforms, whole-file UTF-8),
+/// read back into memory; others go to storage straight from the spool.
+const BUFFERED_TYPES: [&str; 3] = ["image/png", "audio/ogg", "text/plain"];
+/// Bytes read at once by `FileStore::stream`.
+const STREAM_CHUNK_BYTES: u64 = 1024 * 1024;
+/// Lifetime of the presigned URL a download redirects to.
+const PRESIGNED_TTL_SECS: i64 = 300;
+/// Thumbnails fit in a `THUMBNAIL_SIZE`×`THUMBNAIL_SIZE` box.
+const THUMBNAIL_SIZE: u32 = 320;
+/// Larger images are stored without a thumbnail rather than decoded.
+const MAX_THUMBNAIL_SOURCE_DIMENSION: u32 = 8192;
+const MAX_FILENAME_CHARS: usize = 128;
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+pub(crate) const UNLINKED_TTL_HOURS: i64 = 24;
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+const ALLOWED_TYPES: [&str; 11] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "application/zip",
+    "text/plain",
+    "audio/mpeg",
+    "audio/ogg",
+    "video/mp4",
+    "video/webm",
+];
+
+pub(crate) fn max_bytes() -> usize {
+    std::env::var("FILE_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_BYTES)
+}
+
+fn url_ttl_secs() -> i64 {
+    std::env::var("FILE_URL_TTL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_URL_TTL_SECS)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+// ── Storage ─────────────────────────────────────────────
+
+pub struct S3Store {
+    endpoint: String,
+    /// Where clients reach the bucket, for presigned URLs.
+    public_endpoint: String,
+    /// Downloads redirect to a presigned URL instead of going through the server.
+    presign_downloads: bool,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    client: reqwest::Client,
+}
+
+pub enum FileStore {
+    Disk(PathBuf),
+    S3(S3Store),
+}
+
+pub type FileStorage = Arc<FileStore>;
+
+pub fn create_file_storage() -> FileStorage {
+    file_storage_from(|name| std::env::var(name).ok())
+}
+
+/// The store the FILE_STORAGE and S3_* settings `var` returns describe.
+pub fn file_storage_from(var: impl Fn(&str) -> Option<String>) -> FileStorage {
+    let env = |name: &str| var(name).filter(|v| !v.trim().is_empty());
+    if env("FILE_STORAGE").is_some_and(|v| v.eq_ignore_ascii_case("s3")) {
+        match (env("S3_ENDPOINT"), env("S3_BUCKET"), env("S3_ACCESS_KEY_ID"), env("S3_SECRET_ACCESS_KEY")) {
+            (Some(endpoint), Some(bucket), Some(access_key), Some(secret_key)) => {
+                let endpoint = endpoint.trim_end_matches('/').to_string();
+                return Arc::new(FileStore::S3(S3Store {
+                    public_endpoint: env("S3_PUBLIC_ENDPOINT").map(|e| e.trim_end_matches('/').to_string()).unwrap_or_else(|| endpoint.clone()),
+                    presign_downloads: env("S3_PRESIGNED_DOWNLOADS").is_some_and(|v| matches!(v.trim(), "1" | "true" | "yes")),
+                    endpoint,
+                    bucket,
+                    region: env("S3_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+                    access_key,
+                    secret_key,
+                    client: reqwest::Client::new(),
+                }));
+            }
+            _ => eprintln!("⚠️  FILE_STORAGE=s3 needs S3_ENDPOINT, S3_BUCKET, S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY, using disk"),
+        }
+    }
+    let dir = env("FILE_STORAGE_DIR").unwrap_or_else(|| DEFAULT_STORAGE_DIR.to_string());
+    Arc::new(FileStore::Disk(PathBuf::from(dir)))
+}
+
+/// `ab/cd/abcd…`: keeps directories small on disk.
+fn object_key(hash: &str) -> String {
+    format!("{}/{}/{}", &hash[..2], &hash[2..4], hash)
+}
+
+impl S3Store {
+    /// `https://host/bucket/key` on `endpoint`, and the `host[:port]` signed for it.
+    fn object_url(&self, endpoint: &str, key: &str) -> Result<(reqwest::Url, String), String> {
+        let url = format!("{}/{}/{}", endpoint, self.bucket, key);
+        let parsed = reqwest::Url::parse(&url).map_err(|e| e.to_string())?;
+        let host = match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            _ => return Err("S3_ENDPOINT has no host".to_string()),
+        };
+        Ok((parsed, host))
+    }
+
+    /// The credential scope of `date` and its SigV4 signature of `string_to_sign`.
+    fn sign(&self, date: &str, string_to_sign: &str) -> String {
+        let mut key_bytes = crate::crypto::hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key_bytes = crate::crypto::hmac_sha256(&key_bytes, part.as_bytes());
+        }
+        hex(&crate::crypto::hmac_sha256(&key_bytes, string_to_sign.as_bytes()))
+    }
+
+    /// A SigV4-signed path-style request for `key` whose body hashes to
+    /// `payload_hash` (or is `UNSIGNED-PAYLOAD`). Headers added to it
+    /// afterwards (such as `Range`) are sent unsigned.
+    fn signed(&self, method: reqwest::Method, key: &str, payload_hash: &str) -> Result<reqwest::RequestBuilder, String> {
+        let (parsed, host) = self.object_url(&self.endpoint, key)?;
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+            parsed.path(),
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", sha256_hex(canonical_request.as_bytes()));
+        let signature = self.sign(&date, &string_to_sign);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key,
+        );
+
+        Ok(self
+            .client
+            .request(method, parsed)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("Authorization", authorization))
+    }
+
+    fn request(&self, method: reqwest::Method, key: &str, body: Vec<u8>) -> Result<reqwest::RequestBuilder, String> {
+        Ok(self.signed(method, key, &sha256_hex(&body))?.body(body))
+    }
+
+    async fn send(&self, method: reqwest::Method, key: &str, body: Vec<u8>) -> Result<reqwest::Response, String> {
+        self.request(method, key, body)?.send().await.map_err(|e| e.to_string())
+    }
+
+    /// A presigned GET of `key` on the public endpoint, valid `ttl_secs`,
+    /// answered with `content_type` and `disposition`.
+    fn presign_get(&self, key: &str, ttl_secs: i64, content_type: &str, disposition: &str) -> Result<String, String> {
+        let (parsed, host) = self.object_url(&self.public_endpoint, key)?;
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+
+        // Sorted by name: the canonical query string is the URL's own.
+        let params = [
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            ("X-Amz-Credential", format!("{}/{scope}", self.access_key)),
+            ("X-Amz-Date", amz_date.clone()),
+            ("X-Amz-Expires", ttl_secs.clamp(1, 604_800).to_string()),
+            ("X-Amz-SignedHeaders", "host".to_string()),
+            ("response-content-disposition", disposition.to_string()),
+            ("response-content-type", content_type.to_string()),
+        ];
+        let query = params
+            .iter()
+            .map(|(name, value)| format!("{name}={}", urlencoding::encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let canonical_request = format!("GET\n{}\n{query}\nhost:{host}\n\nhost\nUNSIGNED-PAYLOAD", parsed.path());
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", sha256_hex(canonical_request.as_bytes()));
+        let signature = self.sign(&date, &string_to_sign);
+        Ok(format!("{}?{query}&X-Amz-Signature={signature}", parsed.as_str()))
+    }
+}
+
+impl FileStore {
+    pub(crate) async fn put(&self, hash: &str, bytes: Vec<u8>) -> Result<(), String> {
+        let key = object_key(hash);
+        match self {
+            FileStore::Disk(root) => {
+                let path = root.join(&key);
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+                }
+                // Write then rename, so a reader never sees half a file.
+                let partial = path.with_extension("partial");
+                tokio::fs::write(&partial, bytes).await.map_err(|e| e.to_string())?;
+                tokio::fs::rename(&partial, &path).await.map_err(|e| e.to_string())
+            }
+            FileStore::S3(s3) => {
+                let response = s3.send(reqwest::Method::PUT, &key, bytes).await?;
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!("S3 PUT answered {}", response.status()))
+                }
+            }
+        }
+    }
+
+    /// Move the spooled file at `path` into storage: a rename on disk (the
+    /// spool directory is on the same filesystem), a streamed PUT on S3.
+    pub(crate) async fn put_file(&self, hash: &str, path: &Path) -> Result<(), String> {
+        let key = object_key(hash);
+        match self {
+            FileStore::Disk(root) => {
+                let target = root.join(&key);
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+                }
+                tokio::fs::rename(path, &target).await.map_err(|e| e.to_string())
+            }
+            FileStore::S3(s3) => {
+                let file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+                let size = file.metadata().await.map_err(|e| e.to_string())?.len();
+                let response = s3
+                    .signed(reqwest::Method::PUT, &key, "UNSIGNED-PAYLOAD")?
+                    .header("Content-Length", size)
+                    .body(reqwest::Body::from(file))
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!("S3 PUT answered {}", response.status()))
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn get(&self, hash: &str) -> Option<Vec<u8>> {
+        let key = object_key(hash);
+        match self {
+            FileStore::Disk(root) => tokio::fs::read(root.join(key)).await.ok(),
+            FileStore::S3(s3) => {
+                let response = s3.send(reqwest::Method::GET, &key, Vec::new()).await.ok()?;
+                if !response.status().is_success() {
+                    return None;
+                }
+                response.bytes().await.ok().map(|b| b.to_vec())
+            }
+        }
+    }
+
+    /// Bytes `start..=end` of an object.
+    async fn get_range(&self, hash: &str, start: u64, end: u64) -> Option<Vec<u8>> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let key = object_key(hash);
+        let len = usize::try_from(end - start + 1).ok()?;
+        match self {
+            FileStore::Disk(root) => {
+                let mut file = tokio::fs::File::open(root.join(key)).await.ok()?;
+                file.seek(std::io::SeekFrom::Start(start)).await.ok()?;
+                let mut bytes = vec![0; len];
+                file.read_exact(&mut bytes).await.ok()?;
+                Some(bytes)
+            }
+            FileStore::S3(s3) => {
+                let response = s3
+                    .request(reqwest::Method::GET, &key, Vec::new())
+                    .ok()?
+                    .header("Range", format!("bytes={start}-{end}"))
+                    .send()
+                    .await
+                    .ok()?;
+                if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                    return None;
+                }
+                response.bytes().await.ok().map(|b| b.to_vec())
+            }
+        }
+    }
+
+    /// An object of `size` bytes as a stream, read a chunk at a time as it
+    /// is sent so a large one never sits in memory.
+    pub(crate) fn stream(
+        self: Arc<Self>,
+        hash: String,
+        size: u64,
+    ) -> impl futures_util::Stream<Item = Result<actix_web::web::Bytes, actix_web::Error>> {
+        futures_util::stream::unfold(0u64, move |start| {
+            let (store, hash) = (self.clone(), hash.clone());
+            async move {
+                if start >= size {
+                    return None;
+                }
+                let end = (start + STREAM_CHUNK_BYTES).min(size) - 1;
+                match store.get_range(&hash, start, end).await {
+                    Some(bytes) => Some((Ok(actix_web::web::Bytes::from(bytes)), end + 1)),
+                    // Ends the stream: the client sees a short body.
+                    None => Some((Err(actix_web::error::ErrorInternalServerError("File unavailable")), size)),
+                }
+            }
+        })
+    }
+
+    /// Whether the object can still be read.
+    pub(crate) async fn exists(&self, hash: &str) -> bool {
+        self.get_range(hash, 0, 0).await.is_some()
+    }
+
+    pub(crate) async fn delete(&self, hash: &str) {
+        let key = object_key(hash);
+        match self {
+            FileStore::Disk(root) => {
+                tokio::fs::remove_file(root.join(key)).await.ok();
+            }
+            FileStore::S3(s3) => {
+                let _ = s3.send(reqwest::Method::DELETE, &key, Vec::new()).await;
+            }
+        }
+    }
+
+    /// Where uploads are written while they arrive.
+    pub(crate) fn spool_dir(&self) -> PathBuf {
+        match self {
+            FileStore::Disk(root) => root.join(SPOOL_DIR),
+            FileStore::S3(_) => std::env::temp_dir().join("voxium-uploads"),
+        }
+    }
+}
+
+// ── Content ─────────────────────────────────────────────
+
+/// MIME type from the first bytes, `None` for anything not recognised.
+pub(crate) fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    let kind = match bytes {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "application/pdf",
+        [b'P', b'K', 3, 4, ..] => "application/zip",
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        [b'I', b'D', b'3', ..] | [0xFF, 0xFB, ..] | [0xFF, 0xF3, ..] => "audio/mpeg",
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => "video/webm",
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => "video/mp4",
+        _ if std::str::from_utf8(bytes).is_ok() => "text/plain",
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Base name only, without control characters, at most `MAX_FILENAME_CHARS`.
+fn clean_filename(raw: &str) -> String {
+    let base = raw.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        "file".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Dimensions and a PNG thumbnail of a PNG image. No thumbnail when the
+/// image already fits in `THUMBNAIL_SIZE`.
+fn png_preview(bytes: &[u8]) -> Option<(u32, u32, Option<Vec<u8>>)> {
+    use image::ImageEncoder;
+
+    let reader = image::ImageReader::with_format(std::io::Cursor::new(bytes), image::ImageFormat::Png);
+    let (width, height) = reader.into_dimensions().ok()?;
+    if width <= THUMBNAIL_SIZE && height <= THUMBNAIL_SIZE {
+        return Some((width, height, None));
+    }
+    if width > MAX_THUMBNAIL_SOURCE_DIMENSION || height > MAX_THUMBNAIL_SOURCE_DIMENSION {
+        return Some((width, height, None));
+    }
+
+    let img = image::load_from_memory_with_format(bytes, image::ImageFormat::Png).ok()?;
+    let thumb = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8();
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png)
+        .write_image(thumb.as_raw(), thumb.width(), thumb.height(), image::ExtendedColorType::Rgba8)
+        .ok()?;
+    Some((width, height, Some(png)))
+}
+
+// ── Attachments ─────────────────────────────────────────
+
+/// An attachment as sent to clients, with freshly signed URLs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub size: i64,
+    pub content_type: String,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub url: String,
+    pub thumbnail_url: Option<String>,
+    /// `file`, or `voice` for a voice message (see `voice_messages`).
+    pub kind: String,
+    /// Ogg Opus audio only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    /// Ogg Opus audio only: up to 256 amplitudes (0-255), base64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waveform: Option<String>,
+    /// For screen readers; `None` until written (see `alt_text`).
+    #[serde(default)]
+    pub alt_text: Option<String>,
+    /// When the stored file was found gone (see `pin_checks`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing_at: Option<String>,
+    /// The upload scanner's verdict, when one is configured (see `scanning`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_status: Option<String>,
+}
+
+const ATTACHMENT_SELECT: &str = "SELECT a.id, a.message_id, a.filename, a.kind, f.size, f.content_type, f.width, f.height, f.thumbnail_hash, \
+     f.duration_secs, f.waveform, f.missing_at, a.alt_text, a.scan_status \
+     FROM attachments a JOIN files f ON f.hash = a.file_hash";
+
+fn signature(attachment_id: &str, variant: &str, expires: i64) -> String {
+    let payload = format!("{attachment_id}:{variant}:{expires}");
+    hex(&crate::crypto::sign(&payload))
+}
+
+fn signed_url(attachment_id: &str, variant: &str, expires: i64) -> String {
+    format!(
+        "/api/files/{attachment_id}?variant={variant}&expires={expires}&sig={}",
+        signature(attachment_id, variant, expires)
+    )
+}
+
+fn attachment_from_row(row: &sqlx::sqlite::SqliteRow, expires: i64) -> Attachment {
+    let id: String = row.get("id");
+    let has_thumbnail = row.try_get::<Option<String>, _>("thumbnail_hash").ok().flatten().is_some();
+    Attachment {
+        url: signed_url(&id, "original", expires),
+        thumbnail_url: has_thumbnail.then(|| signed_url(&id, "thumbnail", expires)),
+        filename: row.get("filename"),
+        size: row.get("size"),
+        content_type: row.get("content_type"),
+        width: row.try_get("width").unwrap_or(None),
+        height: row.try_get("height").unwrap_or(None),
+        kind: row.try_get("kind").unwrap_or_else(|_| "file".to_string()),
+        duration_secs: row.try_get("duration_secs").unwrap_or(None),
+        waveform: row.try_get("waveform").unwrap_or(None),
+        alt_text: row.try_get("alt_text").unwrap_or(None),
+        missing_at: row.try_get("missing_at").unwrap_or(None),
+        scan_status: row.try_get("scan_status").unwrap_or(None),
+        id,
+    }
+}
+
+fn url_expiry() -> i64 {
+    Utc::now().timestamp() + url_ttl_secs()
+}
+
+/// Of `ids`, those `uploader_id` uploaded and has not sent yet, quarantined ones aside.
+pub(crate) async fn linkable(pool: &SqlitePool, uploader_id: &str, ids: &[String]) -> Vec<String> {
+    let ids: Vec<&String> = ids.iter().take(MAX_ATTACHMENTS_PER_MESSAGE).collect();
+    if ids.is_empty() {
+        return Vec::new();
+    }
+    let sql = format!(
+        "SELECT id FROM attachments WHERE uploader_id = ? AND message_id IS NULL \
+         AND scan_status IS NOT 'quarantined' AND id IN ({})",
+        vec!["?"; ids.len()].join(", ")
+    );
+    let mut query = sqlx::query_scalar::<_, String>(&sql).bind(uploader_id);
+    for id in &ids {
+        query = query.bind(*id);
+    }
+    let found = query.fetch_all(pool).await.unwrap_or_default();
+    // Keep the sender's order.
+    ids.into_iter().filter(|id| found.contains(id)).cloned().collect()
+}
+
+/// Link attachments (from `linkable`) to a new message and return them.
+pub(crate) async fn link_to_message(pool: &SqlitePool, message_id: &str, uploader_id: &str, ids: &[String]) -> Vec<Attachment> {
+    for id in ids {
+        let _ = sqlx::query("UPDATE attachments SET message_id = ? WHERE id = ? AND uploader_id = ? AND message_id IS NULL")
+            .bind(message_id)
+            .bind(id)
+            .bind(uploader_id)
+            .execute(pool)
+            .await;
+    }
+    message_attachments(pool, message_id).await
+}
+
+/// The attachments of a message, in order.
+pub(crate) async fn message_attachments(pool: &SqlitePool, message_id: &str) -> Vec<Attachment> {
+    let sql = format!("{ATTACHMENT_SELECT} WHERE a.message_id = ? ORDER BY a.created_at, a.id");
+    let expires = url_expiry();
+    sqlx::query(&sql)
+        .bind(message_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|row| attachment_from_row(row, expires))
+        .collect()
+}
+
+/// The attachments among `ids`, in no particular order.
+pub(crate) async fn fetch_attachments(pool: &SqlitePool, ids: &[String]) -> Vec<Attachment> {
+    if ids.is_empty() {
+        return Vec::new();
+    }
+    let sql = format!("{ATTACHMENT_SELECT} WHERE a.id IN ({})", vec!["?"; ids.len()].join(", "));
+    let mut query = sqlx::query(&sql);
+    for id in ids {
+        query = query.bind(id);
+    }
+    let expires = url_expiry();
+    query
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|row| attachment_from_row(row, expires))
+        .collect()
+}
+
+/// Fill `attachments` of each message.
+pub(crate) async fn attach_to_messages(pool: &SqlitePool, messages: &mut [Message]) {
+    if messages.is_empty() {
+        return;
+    }
+    let sql = format!(
+        "{ATTACHMENT_SELECT} WHERE a.message_id IN ({}) ORDER BY a.created_at, a.id",
+        vec!["?"; messages.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for message in messages.iter() {
+        query = query.bind(&message.id);
+    }
+    let rows = query.fetch_all(pool).await.unwrap_or_default();
+    let expires = url_expiry();
+    for row in &rows {
+        let message_id: String = row.get("message_id");
+        if let Some(message) = messages.iter_mut().find(|m| m.id == message_id) {
+            message.attachments.push(attachment_from_row(row, expires));
+        }
+    }
+}
+
+/// Look up the files of a message's attachments in storage, and record
+/// which are missing (or back). Returns whether any changed.
+pub(crate) async fn check_stored(pool: &SqlitePool, storage: &FileStore, message_id: &str) -> Result<bool, String> {
+    let files: Vec<(String, Option<String>)> = sqlx::query_as(
+        "SELECT DISTINCT f.hash, f.missing_at FROM attachments a JOIN files f ON f.hash = a.file_hash WHERE a.message_id = ?"
+    )
+    .bind(message_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut changed = false;
+    for (hash, missing_at) in files {
+        let exists = storage.exists(&hash).await;
+        if exists == missing_at.is_none() {
+            continue;
+        }
+        let missing_at = (!exists).then(|| Utc::now().to_rfc3339());
+        sqlx::query("UPDATE files SET missing_at = ? WHERE hash = ?")
+            .bind(&missing_at)
+            .bind(&hash)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        changed = true;
+    }
+    Ok(changed)
+}
+
+// ── Cleanup ─────────────────────────────────────────────
+
+/// Drop stale unlinked uploads (quarantined ones wait for review), then
+/// files nothing uses anymore.
+async fn purge_unused(pool: &SqlitePool, storage: &FileStore) -> Result<u64, String> {
+    let cutoff = (Utc::now() - chrono::Duration::hours(UNLINKED_TTL_HOURS)).to_rfc3339();
+    let dropped = sqlx::query("DELETE FROM attachments WHERE message_id IS NULL AND scan_status IS NOT 'quarantined' AND created_at < ?")
+        .bind(&cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+
+    let orphans = sqlx::query(
+        "SELECT hash, thumbnail_hash FROM files f WHERE NOT EXISTS (SELECT 1 FROM attachments a WHERE a.file_hash = f.hash)"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut removed = 0;
+    for row in orphans {
+        let hash: String = row.get("hash");
+        let thumbnail_hash: Option<String> = row.get("thumbnail_hash");
+        // Re-checked in the DELETE: an upload may have reused it meanwhile.
+        let deleted = sqlx::query(
+            "DELETE FROM files WHERE hash = ? AND NOT EXISTS (SELECT 1 FROM attachments WHERE file_hash = ?)"
+        )
+        .bind(&hash)
+        .bind(&hash)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+        if deleted == 0 {
+            continue;
+        }
+        if !crate::images::uses_object(pool, &hash).await && !crate::uploads::uses_object(pool, &hash).await {
+            storage.delete(&hash).await;
+        }
+        if let Some(thumbnail_hash) = thumbnail_hash {
+            // Another row may hold the very same bytes.
+            let shared: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM files WHERE hash = ? OR thumbnail_hash = ?)")
+                .bind(&thumbnail_hash)
+                .bind(&thumbnail_hash)
+                .fetch_one(pool)
+                .await
+                .unwrap_or(true)
+                || crate::images::uses_object(pool, &thumbnail_hash).await
+                || crate::uploads::uses_object(pool, &thumbnail_hash).await;
+            if !shared {
+                storage.delete(&thumbnail_hash).await;
+            }
+        }
+        removed += 1;
+    }
+    Ok(dropped + removed)
+}
+
+/// Unlinked uploads past their TTL.
+async fn unlinked_backlog(pool: &SqlitePool) -> Backlog {
+    let cutoff = (Utc::now() - chrono::Duration::hours(UNLINKED_TTL_HOURS)).to_rfc3339();
+    let mut backlog = sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM attachments \
+         WHERE message_id IS NULL AND scan_status IS NOT 'quarantined' AND created_at < ?"
+    )
+    .bind(&cutoff)
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default();
+    backlog.oldest_due_at = backlog
+        .oldest_due_at
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| (at + chrono::Duration::hours(UNLINKED_TTL_HOURS)).to_rfc3339());
+    backlog
+}
+
+pub fn spawn_attachment_purger(pool: SqlitePool, storage: FileStorage, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::ATTACHMENTS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::ATTACHMENTS, None, purge_unused(&pool, &storage), unlinked_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// Read the single file of a multipart upload: its cleaned name and bytes.
+pub(crate) async fn read_upload(payload: &mut Multipart, max_bytes: usize) -> Result<(String, Vec<u8>), HttpResponse> {
+    let mut bytes = Vec::new();
+    let mut filename = String::new();
+    if let Some(Ok(mut field)) = payload.next().await {
+        filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(clean_filename)
+            .unwrap_or_else(|| "file".to_string());
+        while let Some(Ok(chunk)) = field.next().await {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": format!("File too large (max {} MB)", max_bytes / (1024 * 1024))
+                })));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+    }
+    if bytes.is_empty() {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "No file provided" })));
+    }
+    Ok((filename, bytes))
+}
+
+/// An upload written to a temporary file as it arrives, hashed on the way.
+/// The file is removed when dropped, unless storage took it.
+pub(crate) struct SpooledUpload {
+    path: PathBuf,
+    hash: String,
+    size: u64,
+    /// The first `SNIFF_BYTES`.
+    head: Vec<u8>,
+}
+
+impl Drop for SpooledUpload {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+impl SpooledUpload {
+    fn content_type(&self) -> Option<&'static str> {
+        // A character cut at the end of the head doesn't make text invalid.
+        let head = match std::str::from_utf8(&self.head) {
+            Err(e) if e.error_len().is_none() && self.size > self.head.len() as u64 => &self.head[..e.valid_up_to()],
+            _ => &self.head[..],
+        };
+        sniff_content_type(head)
+    }
+}
+
+fn save_failed() -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save file" }))
+}
+
+/// Like `read_upload`, into a file under `dir` rather than memory.
+async fn spool_upload(payload: &mut Multipart, max_bytes: usize, dir: &Path) -> Result<(String, SpooledUpload), HttpResponse> {
+    use tokio::io::AsyncWriteExt;
+
+    if let Err(e) = tokio::fs::create_dir_all(dir).await {
+        eprintln!("⚠️  Cannot create {}: {e}", dir.display());
+        return Err(save_failed());
+    }
+    let path = dir.join(format!("{}.partial", Uuid::new_v4()));
+    let mut file = tokio::fs::File::create(&path).await.map_err(|_| save_failed())?;
+    let mut upload = SpooledUpload { path, hash: String::new(), size: 0, head: Vec::new() };
+    let mut hasher = Sha256::new();
+    let mut filename = String::new();
+    if let Some(Ok(mut field)) = payload.next().await {
+        filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(clean_filename)
+            .unwrap_or_else(|| "file".to_string());
+        while let Some(Ok(chunk)) = field.next().await {
+            if upload.size + chunk.len() as u64 > max_bytes as u64 {
+                return Err(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": format!("File too large (max {} MB)", max_bytes / (1024 * 1024))
+                })));
+            }
+            hasher.update(&chunk);
+            let wanted = SNIFF_BYTES.saturating_sub(upload.head.len()).min(chunk.len());
+            upload.head.extend_from_slice(&chunk[..wanted]);
+            file.write_all(&chunk).await.map_err(|_| save_failed())?;
+            upload.size += chunk.len() as u64;
+        }
+    }
+    if upload.size == 0 {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "No file provided" })));
+    }
+    file.flush().await.map_err(|_| save_failed())?;
+    upload.hash = hex(&hasher.finalize());
+    Ok((filename, upload))
+}
+
+/// Whether a `files` row exists for `hash`, and if so whether it is missing from storage.
+async fn stored_file(pool: &SqlitePool, hash: &str) -> Option<bool> {
+    sqlx::query_scalar("SELECT missing_at IS NOT NULL FROM files WHERE hash = ?")
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+}
+
+/// `store_content` for a spooled upload the server doesn't analyse.
+async fn store_spooled(pool: &SqlitePool, storage: &FileStore, upload: &SpooledUpload, content_type: &str) -> Result<String, HttpResponse> {
+    let hash = upload.hash.clone();
+    let missing = stored_file(pool, &hash).await;
+    if missing == Some(false) {
+        return Ok(hash);
+    }
+    if let Err(e) = storage.put_file(&hash, &upload.path).await {
+        eprintln!("⚠️  Storing file {hash} failed: {e}");
+        return Err(save_failed());
+    }
+    let result = if missing == Some(true) {
+        sqlx::query("UPDATE files SET missing_at = NULL WHERE hash = ?").bind(&hash).execute(pool).await
+    } else {
+        sqlx::query("INSERT OR IGNORE INTO files (hash, size, content_type, created_at) VALUES (?, ?, ?, ?)")
+            .bind(&hash)
+            .bind(upload.size as i64)
+            .bind(content_type)
+            .bind(Utc::now().to_rfc3339())
+            .execute(pool)
+            .await
+    };
+    if result.is_err() {
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+    Ok(hash)
+}
+
+/// Store `bytes` unless the same content already is, and return its hash.
+pub(crate) async fn store_content(
+    pool: &SqlitePool,
+    storage: &FileStore,
+    bytes: Vec<u8>,
+    content_type: &str,
+) -> Result<String, HttpResponse> {
+    let hash = sha256_hex(&bytes);
+    match stored_file(pool, &hash).await {
+        Some(false) => return Ok(hash),
+        // The same content again restores a file found missing.
+        Some(true) => {
+            if let Err(e) = storage.put(&hash, bytes).await {
+                eprintln!("⚠️  Storing file {hash} failed: {e}");
+                return Err(save_failed());
+            }
+            let _ = sqlx::query("UPDATE files SET missing_at = NULL WHERE hash = ?")
+                .bind(&hash)
+                .execute(pool)
+                .await;
+            return Ok(hash);
+        }
+        None => {}
+    }
+
+    let size = bytes.len() as i64;
+    let (bytes, preview) = if content_type == "image/png" {
+        match web::block(move || {
+            let preview = png_preview(&bytes);
+            (bytes, preview)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        }
+    } else {
+        (bytes, None)
+    };
+    let (width, height, thumbnail) = match preview {
+        Some((w, h, thumb)) => (Some(w as i64), Some(h as i64), thumb),
+        None => (None, None, None),
+    };
+    let audio = if content_type == "audio/ogg" {
+        crate::voice_messages::analyze(&bytes)
+    } else {
+        None
+    };
+
+    let mut thumbnail_hash = None;
+    if let Some(thumbnail) = thumbnail {
+        let thumb_hash = sha256_hex(&thumbnail);
+        if let Err(e) = storage.put(&thumb_hash, thumbnail).await {
+            eprintln!("⚠️  Storing thumbnail {thumb_hash} failed: {e}");
+        } else {
+            thumbnail_hash = Some(thumb_hash);
+        }
+    }
+    if let Err(e) = storage.put(&hash, bytes).await {
+        eprintln!("⚠️  Storing file {hash} failed: {e}");
+        return Err(save_failed());
+    }
+
+    // Another upload of the same bytes may have won the race: same content, same row.
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO files (hash, size, content_type, width, height, thumbnail_hash, duration_secs, waveform, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&hash)
+    .bind(size)
+    .bind(content_type)
+    .bind(width)
+    .bind(height)
+    .bind(&thumbnail_hash)
+    .bind(audio.as_ref().map(|a| a.duration_secs))
+    .bind(audio.as_ref().map(|a| a.waveform.clone()))
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    if result.is_err() {
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+    Ok(hash)
+}
+
+/// Record a pending upload of `file_hash` and answer with it, or with a
+/// 422 when the scanner flagged it.
+pub(crate) async fn create_attachment(
+    pool: &SqlitePool,
+    file_hash: &str,
+    uploader_id: &str,
+    filename: &str,
+    kind: &str,
+    alt_text: Option<&str>,
+    scan: Option<&ScanResult>,
+) -> HttpResponse {
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(
+        "INSERT INTO attachments (id, file_hash, uploader_id, filename, kind, alt_text, scan_status, scan_signature, scanned_at, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(file_hash)
+    .bind(uploader_id)
+    .bind(filename)
+    .bind(kind)
+    .bind(alt_text)
+    .bind(scan.map(|s| s.status))
+    .bind(scan.and_then(|s| s.signature.as_deref()))
+    .bind(scan.map(|s| s.scanned_at.as_str()))
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if let Some(scan) = scan.filter(|s| s.is_quarantined()) {
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "File flagged by the upload scanner",
+            "attachment_id": id,
+            "signature": scan.signature,
+        }));
+    }
+
+    let sql = format!("{ATTACHMENT_SELECT} WHERE a.id = ?");
+    match sqlx::query(&sql).bind(&id).fetch_one(pool).await {
+        Ok(row) => HttpResponse::Created().json(attachment_from_row(&row, url_expiry())),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/files?alt_text= — Upload one file as a pending attachment (authenticated)
+pub async fn upload_file(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    query: web::Query<AltTextQuery>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let alt_text = match crate::alt_text::normalize(query.alt_text.as_deref()) {
+        Ok(text) => text,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+
+    let (filename, upload) = match spool_upload(&mut payload, max_bytes(), &storage.spool_dir()).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    let unsupported = || {
+        HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+            "error": "Unsupported file type",
+            "allowed": ALLOWED_TYPES,
+        }))
+    };
+    let Some(content_type) = upload.content_type().filter(|t| ALLOWED_TYPES.contains(t)) else {
+        return unsupported();
+    };
+
+    let buffered = if BUFFERED_TYPES.contains(&content_type) {
+        let Ok(bytes) = tokio::fs::read(&upload.path).await else {
+            return save_failed();
+        };
+        // The head only hinted at text: the whole file must be.
+        if sniff_content_type(&bytes) != Some(content_type) {
+            return unsupported();
+        }
+        Some(bytes)
+    } else {
+        None
+    };
+    let scan = match crate::scanning::scan_upload(Upload::File(&upload.path), content_type, &upload.hash).await {
+        Ok(scan) => scan,
+        Err(response) => return response,
+    };
+
+    let stored = match buffered {
+        Some(bytes) => store_content(pool.get_ref(), storage.get_ref(), bytes, content_type).await,
+        None => store_spooled(pool.get_ref(), storage.get_ref(), &upload, content_type).await,
+    };
+    let hash = match stored {
+        Ok(hash) => hash,
+        Err(response) => return response,
+    };
+    create_attachment(pool.get_ref(), &hash, &claims.sub, &filename, "file", alt_text.as_deref(), scan.as_ref()).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    pub variant: String,
+    pub expires: i64,
+    pub sig: String,
+}
+
+/// A single `Range: bytes=` range within `size`, inclusive. `None` when the
+/// header is absent or not understood (the whole file is served), `Err` when
+/// it is out of bounds.
+fn parse_range(header: Option<&str>, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header?.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || size == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let n: u64 = suffix.parse().ok()?;
+            if n == 0 {
+                return Some(Err(()));
+            }
+            (size.saturating_sub(n), size - 1)
+        }
+        (start, "") => (start.parse().ok()?, size - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1)),
+    };
+    if range.0 > range.1 || range.0 >= size {
+        return Some(Err(()));
+    }
+    Some(Ok(range))
+}
+
+/// GET /api/files/{id}?variant=&expires=&sig= — Download an attachment through a signed URL (supports `Range`; redirects to the bucket with S3_PRESIGNED_DOWNLOADS)
+pub async fn download_file(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<String>,
+    query: web::Query<DownloadQuery>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    let expected = signature(&id, &query.variant, query.expires);
+    if !crate::crypto::signatures_match(expected.as_bytes(), query.sig.as_bytes()) || query.expires < Utc::now().timestamp() {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Invalid or expired link" }));
+    }
+
+    // Files of deleted messages are not served anymore, nor quarantined uploads.
+    let row = sqlx::query(
+        "SELECT a.filename, f.hash, f.size, f.content_type, f.thumbnail_hash FROM attachments a \
+         JOIN files f ON f.hash = a.file_hash \
+         LEFT JOIN messages m ON m.id = a.message_id \
+         WHERE a.id = ? AND m.deleted_at IS NULL AND a.scan_status IS NOT 'quarantined'"
+    )
+    .bind(&id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some(row) = row else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "File not found" }));
+    };
+
+    let filename: String = row.get("filename");
+    let mut content_type: String = row.get("content_type");
+    let size = row.get::<i64, _>("size").max(0) as u64;
+    let mut range = None;
+    let object = match query.variant.as_str() {
+        "original" => {
+            let header = req.headers().get("Range").and_then(|v| v.to_str().ok());
+            match parse_range(header, size) {
+                Some(Ok(r)) => range = Some(r),
+                Some(Err(())) => {
+                    return HttpResponse::RangeNotSatisfiable()
+                        .insert_header(("Content-Range", format!("bytes */{size}")))
+                        .finish();
+                }
+                None => {}
+            }
+            row.get::<String, _>("hash")
+        }
+        "thumbnail" => match row.get::<Option<String>, _>("thumbnail_hash") {
+            Some(hash) => {
+                content_type = "image/png".to_string();
+                hash
+            }
+            None => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No thumbnail" })),
+        },
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown variant" })),
+    };
+
+    // Media plays in place, anything else is a download.
+    let inline = ["image/", "audio/", "video/"].iter().any(|prefix| content_type.starts_with(prefix));
+    let ascii_name: String = filename.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect();
+    let disposition = ContentDisposition {
+        disposition: if inline { DispositionType::Inline } else { DispositionType::Attachment },
+        parameters: vec![
+            DispositionParam::Filename(ascii_name),
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: filename.into_bytes(),
+            }),
+        ],
+    };
+    if content_type == "text/plain" {
+        content_type = "text/plain; charset=utf-8".to_string();
+    }
+
+    // Our link checked out: the bucket serves the bytes (and ranges) itself.
+    if let FileStore::S3(s3) = storage.get_ref().as_ref() {
+        if s3.presign_downloads {
+            let ttl = (query.expires - Utc::now().timestamp()).min(PRESIGNED_TTL_SECS);
+            match s3.presign_get(&object_key(&object), ttl, &content_type, &disposition.to_string()) {
+                Ok(url) => {
+                    return HttpResponse::Found()
+                        .insert_header(("Location", url))
+                        .insert_header(("Cache-Control", "private, no-store"))
+                        .finish();
+                }
+                Err(e) => eprintln!("⚠️  Presigning {object} failed, serving it directly: {e}"),
+            }
+        }
+    }
+
+    let not_found = || HttpResponse::NotFound().json(serde_json::json!({ "error": "File not found" }));
+    let mut response = match range {
+        Some((start, end)) => {
+            let mut partial = HttpResponse::PartialContent();
+            partial.insert_header(("Content-Range", format!("bytes {start}-{end}/{size}")));
+            partial
+        }
+        None => HttpResponse::Ok(),
+    };
+    response
+        .content_type(content_type)
+        .insert_header(disposition)
+        .insert_header(("Accept-Ranges", "bytes"))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .insert_header(("Cache-Control", "private, max-age=3600"));
+    match range {
+        Some((start, end)) => match storage.get_range(&object, start, end).await {
+            Some(bytes) => response.body(bytes),
+            None => not_found(),
+        },
+        // Originals can be large: read them a chunk at a time as they go out.
+        None if query.variant == "original" => {
+            if size > 0 && !storage.exists(&object).await {
+                return not_found();
+            }
+            response.streaming(storage.get_ref().clone().stream(object, size))
+        }
+        // Thumbnails are small and their size isn't stored.
+        None => match storage.get(&object).await {
+            Some(bytes) => response.body(bytes),
+            None => not_found(),
+        },
+    }
+}
+
+// ── Moving files to S3 ──────────────────────────────────
+
+/// Every object the database points at: attachments and their thumbnails,
+/// image sizes, files under `/uploads`, sounds, recording segments and
+/// finished archives.
+const OBJECT_KEYS: &str = "SELECT hash FROM files \
+     UNION SELECT thumbnail_hash FROM files WHERE thumbnail_hash IS NOT NULL \
+     UNION SELECT hash FROM image_variants \
+     UNION SELECT hash FROM uploaded_files \
+     UNION SELECT object_key FROM soundboard_sounds \
+     UNION SELECT object_key FROM voice_recording_segments \
+     UNION SELECT archive_key FROM data_exports WHERE archive_key IS NOT NULL \
+     UNION SELECT archive_key FROM room_archives WHERE archive_key IS NOT NULL \
+     UNION SELECT archive_key FROM voice_recordings WHERE archive_key IS NOT NULL";
+
+/// `backend --migrate-files`: copy every stored object from the disk store
+/// (FILE_STORAGE_DIR) to the S3 bucket FILE_STORAGE=s3 points at, then the
+/// files of a legacy `uploads/` directory not imported yet (see `uploads`).
+/// Objects already in the bucket are skipped, so it can be run again after
+/// an interruption; the disk copies are left for the operator to remove.
+pub fn migrate_cli() -> i32 {
+    let rt = actix_web::rt::System::new();
+    rt.block_on(async {
+        let settings = match crate::config::Settings::load(crate::config::path_from_args().as_deref()) {
+            Ok(settings) => settings,
+            Err(error) => {
+                eprintln!("{error}");
+                return 1;
+            }
+        };
+        let target = create_file_storage();
+        if !matches!(target.as_ref(), FileStore::S3(_)) {
+            eprintln!("error: set FILE_STORAGE=s3 and the S3_* variables to the bucket to copy files to");
+            return 1;
+        }
+        let dir = std::env::var("FILE_STORAGE_DIR").ok().filter(|v| !v.trim().is_empty()).unwrap_or_else(|| DEFAULT_STORAGE_DIR.to_string());
+        let source = PathBuf::from(dir);
+        let pool = crate::db::init_db(&settings.database).await;
+
+        let hashes: Vec<String> = match sqlx::query_scalar(OBJECT_KEYS).fetch_all(&pool).await {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                eprintln!("error: {e}");
+                return 1;
+            }
+        };
+
+        let (mut copied, mut present, mut missing, mut failed) = (0, 0, 0, 0);
+        for hash in &hashes {
+            if target.exists(hash).await {
+                present += 1;
+                continue;
+            }
+            let path = source.join(object_key(hash));
+            if !path.exists() {
+                missing += 1;
+                continue;
+            }
+            match target.put_file(hash, &path).await {
+                Ok(()) => copied += 1,
+                Err(e) => {
+                    eprintln!("⚠️  {hash}: {e}");
+                    failed += 1;
+                }
+            }
+        }
+        println!(
+            "{} files: {copied} copied, {present} already in the bucket, {missing} not on disk, {failed} failed",
+            hashes.len()
+        );
+        let (imported, import_failed) = crate::uploads::import_legacy(&pool, &target).await;
+        println!("uploads/: {imported} imported, {import_failed} failed");
+        if failed + import_failed > 0 { 1 } else { 0 }
+    })
+}
@@ -0,0 +1,674 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Forum rooms
+// ═══════════════════════════════════════════════════════
+//
+// A room of kind `forum` holds posts instead of one stream of messages. A
+// post is a thread with a title and tags, opened by its first message (the
+// post's id is that message's); every other message of the room replies in
+// a post (`post_id` on `message` events, required there and refused in
+// other rooms). Deleting the opening message hides the post.
+//
+// The tags a forum offers are set by room managers, and each post picks up
+// to `MAX_TAGS_PER_POST` of them. Reactions to the opening message count as
+// votes: `vote_count` is the number of users who reacted, whatever the
+// emoji. Posts are listed by last activity (a reply bumps the post),
+// creation, votes or replies, and filtered by tag.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::auth::extract_claims;
+use crate::messages::{BlockedFilter, HistoryPage, Message};
+use crate::ws::Broadcaster;
+
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_TAGS_PER_POST: usize = 5;
+const MAX_TAGS_PER_ROOM: usize = 20;
+const MAX_TAG_CHARS: usize = 20;
+const DEFAULT_MESSAGES_LIMIT: i64 = 50;
+const MAX_MESSAGES_LIMIT: i64 = 100;
+
+#[derive(Debug, Serialize)]
+pub struct Post {
+    pub id: String,
+    pub room_id: String,
+    pub title: String,
+    /// `None` once the author's account is gone.
+    pub author_id: Option<String>,
+    pub author_username: String,
+    pub tags: Vec<String>,
+    pub created_at: String,
+    pub last_activity_at: String,
+    pub reply_count: i64,
+    pub vote_count: i64,
+}
+
+const POST_COLUMNS: &str = "p.id, p.room_id, p.title, p.author_id, m.username AS author_username, \
+     p.created_at, p.last_activity_at, p.reply_count, p.vote_count";
+/// Posts whose opening message is not deleted. Callers append filters.
+const POST_FROM: &str = "FROM forum_posts p JOIN messages m ON m.id = p.id WHERE m.deleted_at IS NULL";
+
+fn post_from_row(row: &SqliteRow) -> Post {
+    Post {
+        id: row.get("id"),
+        room_id: row.get("room_id"),
+        title: row.get("title"),
+        author_id: row.get("author_id"),
+        author_username: row.get("author_username"),
+        tags: Vec::new(),
+        created_at: row.get("created_at"),
+        last_activity_at: row.get("last_activity_at"),
+        reply_count: row.get("reply_count"),
+        vote_count: row.get("vote_count"),
+    }
+}
+
+async fn attach_tags(pool: &SqlitePool, posts: &mut [Post]) {
+    if posts.is_empty() {
+        return;
+    }
+    let sql = format!(
+        "SELECT post_id, tag FROM forum_post_tags WHERE post_id IN ({}) ORDER BY tag",
+        vec!["?"; posts.len()].join(", ")
+    );
+    let mut q = sqlx::query(&sql);
+    for post in posts.iter() {
+        q = q.bind(&post.id);
+    }
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for row in q.fetch_all(pool).await.unwrap_or_default() {
+        tags.entry(row.get("post_id")).or_default().push(row.get("tag"));
+    }
+    for post in posts.iter_mut() {
+        post.tags = tags.remove(&post.id).unwrap_or_default();
+    }
+}
+
+async fn load_post(pool: &SqlitePool, post_id: &str) -> Option<Post> {
+    let row = sqlx::query(&format!("SELECT {POST_COLUMNS} {POST_FROM} AND p.id = ?"))
+        .bind(post_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)?;
+    let mut posts = [post_from_row(&row)];
+    attach_tags(pool, &mut posts).await;
+    let [post] = posts;
+    Some(post)
+}
+
+async fn is_forum(pool: &SqlitePool, room_id: &str) -> bool {
+    sqlx::query_scalar::<_, String>("SELECT kind FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .is_some_and(|kind| kind == "forum")
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({ "error": "Post not found" }))
+}
+
+// ── Hooks ───────────────────────────────────────────────
+
+/// Whether a message with `post_id` may be posted in `room_id`: forum rooms
+/// only take replies to one of their visible posts, other rooms none.
+pub(crate) async fn accepts_message(pool: &SqlitePool, room_id: &str, post_id: Option<&str>) -> bool {
+    match (is_forum(pool, room_id).await, post_id) {
+        (false, post_id) => post_id.is_none(),
+        (true, None) => false,
+        (true, Some(post_id)) => sqlx::query_scalar(&format!("SELECT EXISTS(SELECT 1 {POST_FROM} AND p.id = ? AND p.room_id = ?)"))
+            .bind(post_id)
+            .bind(room_id)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(false),
+    }
+}
+
+/// Count replies again: of `post_id`, or of every post.
+pub(crate) async fn refresh_replies(pool: &SqlitePool, post_id: Option<&str>) {
+    let _ = sqlx::query(
+        "UPDATE forum_posts SET reply_count = \
+           (SELECT COUNT(*) FROM messages m WHERE m.post_id = forum_posts.id AND m.id != forum_posts.id AND m.deleted_at IS NULL) \
+         WHERE ? IS NULL OR id = ?"
+    )
+    .bind(post_id)
+    .bind(post_id)
+    .execute(pool)
+    .await;
+}
+
+/// A reply was posted in `post_id` at `at`.
+pub(crate) async fn record_reply(pool: &SqlitePool, post_id: &str, at: &str) {
+    let _ = sqlx::query("UPDATE forum_posts SET last_activity_at = ? WHERE id = ?")
+        .bind(at)
+        .bind(post_id)
+        .execute(pool)
+        .await;
+    refresh_replies(pool, Some(post_id)).await;
+}
+
+/// Count votes again after the reactions to `message_id` changed (every
+/// post's when `None`). Messages that open no post are left alone.
+pub(crate) async fn refresh_votes(pool: &SqlitePool, message_id: Option<&str>) {
+    let _ = sqlx::query(
+        "UPDATE forum_posts SET vote_count = \
+           (SELECT COUNT(DISTINCT user_id) FROM message_reactions WHERE message_id = forum_posts.id) \
+         WHERE ? IS NULL OR id = ?"
+    )
+    .bind(message_id)
+    .bind(message_id)
+    .execute(pool)
+    .await;
+}
+
+// ── Tags ────────────────────────────────────────────────
+
+async fn room_tags(pool: &SqlitePool, room_id: &str) -> Vec<String> {
+    sqlx::query_scalar("SELECT name FROM forum_tags WHERE room_id = ? ORDER BY name")
+        .bind(room_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+}
+
+fn normalize_tag(raw: &str) -> String {
+    raw.trim().to_lowercase()
+}
+
+/// The tags of a post, normalized and checked against the room's.
+async fn post_tags(pool: &SqlitePool, room_id: &str, raw: &[String]) -> Result<Vec<String>, HttpResponse> {
+    let mut tags: Vec<String> = raw.iter().map(|t| normalize_tag(t)).collect();
+    tags.sort();
+    tags.dedup();
+    if tags.len() > MAX_TAGS_PER_POST {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("A post can have at most {MAX_TAGS_PER_POST} tags")
+        })));
+    }
+    let available = room_tags(pool, room_id).await;
+    if tags.iter().any(|tag| !available.contains(tag)) {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown tag", "tags": available })));
+    }
+    Ok(tags)
+}
+
+async fn set_post_tags(conn: &mut sqlx::SqliteConnection, post_id: &str, tags: &[String]) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM forum_post_tags WHERE post_id = ?")
+        .bind(post_id)
+        .execute(&mut *conn)
+        .await?;
+    for tag in tags {
+        sqlx::query("INSERT INTO forum_post_tags (post_id, tag) VALUES (?, ?)")
+            .bind(post_id)
+            .bind(tag)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::result_large_err)]
+fn normalize_title(raw: &str) -> Result<String, HttpResponse> {
+    let title = raw.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("The title must be 1 to {MAX_TITLE_CHARS} characters")
+        })));
+    }
+    Ok(title.to_string())
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+const FORUM_POSTS: crate::pagination::Listing = crate::pagination::Listing {
+    name: "forum_posts",
+    default_limit: 25,
+    max_limit: 100,
+    sorts: &[
+        ("activity", "p.last_activity_at"),
+        ("created_at", "p.created_at"),
+        ("votes", "p.vote_count"),
+        ("replies", "p.reply_count"),
+    ],
+    default_sort: "-activity",
+    tiebreak: "p.id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ListPostsQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    /// Only posts with this tag.
+    pub tag: Option<String>,
+    /// Only posts active (created or replied to) since this RFC 3339 time.
+    pub active_since: Option<String>,
+}
+
+/// GET /api/rooms/{id}/posts?limit=&sort=&cursor=&tag=&active_since= — Posts of a forum, last active first
+pub async fn list_posts(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    query: web::Query<ListPostsQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+    let page = match FORUM_POSTS.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let tag = crate::pagination::filter(&query.tag).map(|t| normalize_tag(&t));
+    let active_since = crate::pagination::filter(&query.active_since);
+
+    let sql = format!(
+        "SELECT {POST_COLUMNS}, {} {POST_FROM} AND p.room_id = ? \
+           AND (? IS NULL OR EXISTS(SELECT 1 FROM forum_post_tags t WHERE t.post_id = p.id AND t.tag = ?)) \
+           AND (? IS NULL OR p.last_activity_at >= ?){}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut q = sqlx::query(&sql)
+        .bind(&room_id)
+        .bind(&tag)
+        .bind(&tag)
+        .bind(&active_since)
+        .bind(&active_since);
+    for value in page.keyset_binds() {
+        q = q.bind(value);
+    }
+    match q.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await {
+        Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
+            let mut posts: Vec<Post> = rows.iter().map(post_from_row).collect();
+            attach_tags(pool.get_ref(), &mut posts).await;
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(posts), next_cursor)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePostInput {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// POST /api/rooms/{id}/posts — Open a post in a forum
+/// Body: { title, content, tags? }
+pub async fn create_post(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<CreatePostInput>,
+    broadcaster: web::Data<Broadcaster>,
+    access_cache: web::Data<crate::ws::AccessCache>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::SEND_MESSAGES).await {
+        return response;
+    }
+    if !is_forum(pool.get_ref(), &room_id).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Posts can only be opened in forum rooms" }));
+    }
+    let title = match normalize_title(&body.title) {
+        Ok(title) => title,
+        Err(response) => return response,
+    };
+    let content = body.content.trim();
+    if content.is_empty() || content.chars().count() > crate::messages::MAX_CONTENT_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Content must be 1 to {} characters", crate::messages::MAX_CONTENT_CHARS)
+        }));
+    }
+    let tags = match post_tags(pool.get_ref(), &room_id, &body.tags).await {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    let flags = match crate::ws::check_post_gates(pool.get_ref(), access_cache.get_ref(), broadcaster.get_ref(), &room_id, &claims.sub, &claims.role, &format!("{title}\n{content}"), &[]).await {
+        Ok(flags) => flags,
+        Err(refusal) => return refusal.response(),
+    };
+
+    let post_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to create post" })),
+    };
+    let created = async {
+        // The opening message first: the post refers to it, then it to the post.
+        sqlx::query("INSERT INTO messages (id, room_id, user_id, username, content, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&post_id)
+            .bind(&room_id)
+            .bind(&claims.sub)
+            .bind(&claims.username)
+            .bind(content)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO forum_posts (id, room_id, title, author_id, created_at, last_activity_at) VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&post_id)
+        .bind(&room_id)
+        .bind(&title)
+        .bind(&claims.sub)
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE messages SET post_id = id WHERE id = ?")
+            .bind(&post_id)
+            .execute(&mut *tx)
+            .await?;
+        set_post_tags(&mut tx, &post_id, &tags).await
+    }
+    .await;
+    if created.is_err() || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to create post" }));
+    }
+
+    let mention_ids = crate::mentions::record_mentions(pool.get_ref(), &post_id, &room_id, &claims.sub, content, &now).await;
+    crate::unfurl::mark_pending(pool.get_ref(), &post_id, content).await;
+    crate::automod::record_flags(pool.get_ref(), &post_id, &room_id, &claims.sub, &flags).await;
+    let avatar_url: Option<String> = sqlx::query_scalar("SELECT avatar_url FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None)
+        .flatten();
+    let message = serde_json::json!({
+        "type": "message",
+        "id": post_id,
+        "room_id": room_id,
+        "post_id": post_id,
+        "user_id": claims.sub,
+        "username": claims.username,
+        "avatar_url": avatar_url,
+        "content": content,
+        "created_at": now,
+        "mention_ids": mention_ids,
+    });
+    let _ = broadcaster.send(message.to_string());
+    crate::mentions::notify_mentions(pool.get_ref(), broadcaster.get_ref(), &mention_ids, &crate::mentions::MentionSource {
+        message_id: &post_id,
+        room_id: &room_id,
+        author_id: &claims.sub,
+        author_username: &claims.username,
+        content,
+    })
+    .await;
+
+    let Some(post) = load_post(pool.get_ref(), &post_id).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let event = serde_json::json!({ "type": "forum_post_created", "room_id": room_id, "post": post });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Created().json(post)
+}
+
+/// GET /api/posts/{id} — One post
+pub async fn get_post(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let Some(post) = load_post(pool.get_ref(), &path.into_inner()).await else {
+        return not_found();
+    };
+    if crate::rooms::check_room_access(pool.get_ref(), &post.room_id, &claims).await.is_err() {
+        return not_found();
+    }
+    HttpResponse::Ok().json(post)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostMessagesQuery {
+    /// A message of the post: only older ones.
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub blocked: BlockedFilter,
+}
+
+/// GET /api/posts/{id}/messages?before=&limit=&blocked= — The post's messages, oldest first, the opening one included
+pub async fn post_messages(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    query: web::Query<PostMessagesQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let Some(post) = load_post(pool.get_ref(), &path.into_inner()).await else {
+        return not_found();
+    };
+    if crate::rooms::check_room_access(pool.get_ref(), &post.room_id, &claims).await.is_err() {
+        return not_found();
+    }
+
+    let cursor = match &query.before {
+        Some(id) => {
+            let at: Option<String> = sqlx::query_scalar("SELECT created_at FROM messages WHERE id = ? AND post_id = ?")
+                .bind(id)
+                .bind(&post.id)
+                .fetch_optional(pool.get_ref())
+                .await
+                .unwrap_or(None);
+            match at {
+                Some(at) => Some((at, id.clone())),
+                None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Cursor message not found in this post" })),
+            }
+        }
+        None => None,
+    };
+    let limit = crate::pagination::limit(query.limit, DEFAULT_MESSAGES_LIMIT, MAX_MESSAGES_LIMIT);
+    let with_deleted = crate::messages::sees_deleted(pool.get_ref(), &claims.role).await;
+
+    let sql = format!(
+        "{} WHERE m.post_id = ? {} {} ORDER BY m.created_at DESC, m.id DESC LIMIT ?",
+        crate::messages::MESSAGE_SELECT,
+        if cursor.is_some() { "AND (m.created_at, m.id) < (?, ?)" } else { "" },
+        if with_deleted { "" } else { "AND m.deleted_at IS NULL" },
+    );
+    let mut q = sqlx::query(&sql).bind(&post.id);
+    if let Some((at, id)) = &cursor {
+        q = q.bind(at).bind(id);
+    }
+    let rows = q.bind(limit + 1).fetch_all(pool.get_ref()).await.unwrap_or_default();
+    let has_more_before = rows.len() as i64 > limit;
+    let mut messages: Vec<Message> = rows.iter().take(limit as usize).map(crate::messages::message_from_row).collect();
+    messages.reverse();
+
+    crate::messages::apply_block_filter(pool.get_ref(), &claims.sub, query.blocked, &mut messages).await;
+    crate::messages::enrich_messages_with_reactions(pool.get_ref(), &mut messages).await;
+    crate::emojis::attach_custom_emojis(pool.get_ref(), &mut messages).await;
+    crate::files::attach_to_messages(pool.get_ref(), &mut messages).await;
+    HttpResponse::Ok().json(HistoryPage { messages, has_more_before, has_more_after: cursor.is_some() })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePostInput {
+    pub title: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// PATCH /api/posts/{id} — Retitle or retag a post (its author, or MANAGE_MESSAGES)
+/// Body: { title?, tags? }
+pub async fn update_post(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<UpdatePostInput>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let Some(post) = load_post(pool.get_ref(), &path.into_inner()).await else {
+        return not_found();
+    };
+    if crate::rooms::check_room_access(pool.get_ref(), &post.room_id, &claims).await.is_err() {
+        return not_found();
+    }
+    if post.author_id.as_deref() != Some(claims.sub.as_str())
+        && crate::permissions::require(pool.get_ref(), &post.room_id, &claims, crate::permissions::MANAGE_MESSAGES).await.is_err()
+    {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "You can only edit your own posts" }));
+    }
+
+    let title = match body.title.as_deref().map(normalize_title).transpose() {
+        Ok(title) => title,
+        Err(response) => return response,
+    };
+    let tags = match &body.tags {
+        Some(raw) => match post_tags(pool.get_ref(), &post.room_id, raw).await {
+            Ok(tags) => Some(tags),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let updated = async {
+        if let Some(title) = &title {
+            sqlx::query("UPDATE forum_posts SET title = ? WHERE id = ?")
+                .bind(title)
+                .bind(&post.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(tags) = &tags {
+            set_post_tags(&mut tx, &post.id, tags).await?;
+        }
+        Ok::<_, sqlx::Error>(())
+    }
+    .await;
+    if updated.is_err() || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let Some(post) = load_post(pool.get_ref(), &post.id).await else {
+        return not_found();
+    };
+    let event = serde_json::json!({ "type": "forum_post_updated", "room_id": post.room_id, "post": post });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(post)
+}
+
+/// GET /api/rooms/{id}/forum-tags — Tags posts of a forum can pick
+pub async fn get_tags(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "tags": room_tags(pool.get_ref(), &room_id).await }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagsInput {
+    pub tags: Vec<String>,
+}
+
+/// PUT /api/rooms/{id}/forum-tags — Replace a forum's tags; removed ones leave its posts (MANAGE_ROOMS)
+/// Body: { tags }
+pub async fn put_tags(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<TagsInput>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+    let room_id = path.into_inner();
+    if !is_forum(pool.get_ref(), &room_id).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Only forum rooms have tags" }));
+    }
+
+    let mut tags: Vec<String> = body.tags.iter().map(|t| normalize_tag(t)).collect();
+    tags.sort();
+    tags.dedup();
+    if tags.len() > MAX_TAGS_PER_ROOM {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("A forum can have at most {MAX_TAGS_PER_ROOM} tags")
+        }));
+    }
+    if tags.iter().any(|t| t.is_empty() || t.chars().count() > MAX_TAG_CHARS) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Tags must be 1 to {MAX_TAG_CHARS} characters")
+        }));
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let saved = async {
+        sqlx::query("DELETE FROM forum_tags WHERE room_id = ?")
+            .bind(&room_id)
+            .execute(&mut *tx)
+            .await?;
+        for tag in &tags {
+            sqlx::query("INSERT INTO forum_tags (room_id, name) VALUES (?, ?)")
+                .bind(&room_id)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query(
+            "DELETE FROM forum_post_tags WHERE post_id IN (SELECT id FROM forum_posts WHERE room_id = ?) \
+             AND tag NOT IN (SELECT name FROM forum_tags WHERE room_id = ?)"
+        )
+        .bind(&room_id)
+        .bind(&room_id)
+        .execute(&mut *tx)
+        .await?;
+        Ok::<_, sqlx::Error>(())
+    }
+    .await;
+    if saved.is_err() || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let event = serde_json::json!({ "type": "forum_tags_updated", "room_id": room_id, "tags": tags });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(serde_json::json!({ "tags": tags }))
+}
@@ -0,0 +1,754 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Realtime gateway (GET /api/gateway)
+// ═══════════════════════════════════════════════════════
+//
+// A WebSocket protocol over the same event stream as `/ws`, for clients
+// that need to survive reconnects: every event a session receives gets a
+// sequence number, and a dropped connection can resume where it stopped
+// instead of reloading everything. Frames are `{ op, d }`; dispatches add
+// `t` (the event type) and `s` (the sequence number, from 1 per session).
+//
+//   server  hello { heartbeat_interval_ms }
+//   client  identify { token | ticket | bot_token, rooms?, guild? }
+//           or resume { token | ticket | bot_token, session_id, seq }
+//   server  ready { session_id, user_id, rooms, guild, heartbeat_interval_ms }
+//           or the missed dispatches then resumed { replayed }
+//           or invalid_session { resumable: false } (identify again)
+//
+// Then, both ways:
+//   client  heartbeat { seq }               server  heartbeat_ack { seq }
+//   client  subscribe / unsubscribe { rooms?, guild? }
+//                                           server  subscriptions { rooms, guild }
+//   client  send { type, ... }              (message, typing or presence, as on `/ws`,
+//                                            or activity_state, see `voice_sync.rs`)
+//   server  dispatch (t, s, d)              server  error { code, ... }
+//
+// A Voxium instance is a single guild: `rooms` filters room events (a list
+// of room ids, `"*"` for every room the user can see, the default) and
+// `guild` the server-wide ones (presence, roles, announcements...), on by
+// default. Events are also filtered like on `/ws`: access to the room,
+// blocked authors, `recipient_id` and, for presence, sharing a room.
+//
+// A session counts as a connection for presence (see `presence.rs`) from
+// identify until it expires, so a resumed session never shows offline.
+//
+// Without a heartbeat for 1.5 intervals the connection is closed (4009)
+// and the session detached. Each heartbeat checks the credentials again: an
+// expired token, a revoked session or a reset bot token closes the
+// connection (4004) and ends the session. A detached session keeps collecting its events
+// for the resume window, the last `GATEWAY_REPLAY_BUFFER` of them kept for
+// replay; after that, or when events were lost, it can only be identified
+// again.
+//
+// Close codes: 4001 malformed frame or unknown op, 4003 op sent before
+// identify, 4004 authentication failed, 4005 already identified, 4007
+// session resumed on another connection or ended, 4009 heartbeat timeout.
+//
+// Config (env):
+//   GATEWAY_HEARTBEAT_INTERVAL_SECS  expected heartbeat period (default 30, at least 5)
+//   GATEWAY_RESUME_WINDOW_SECS       how long a detached session can be resumed (default 120)
+//   GATEWAY_REPLAY_BUFFER            dispatches kept per session for resume (default 1000)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::bots::BotTokens;
+use crate::presence::PresenceTracker;
+use crate::sessions::SessionStore;
+use crate::voice_activity::VoiceOccupancy;
+use crate::voice_sync::VoiceSync;
+use crate::ws::{AccessCache, Broadcaster, PostRefusal, WsMessage, WsTickets};
+
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 30;
+const DEFAULT_RESUME_WINDOW_SECS: u64 = 120;
+const DEFAULT_REPLAY_BUFFER: usize = 1000;
+/// How often detached sessions check whether they expired.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+const CLOSE_DECODE_ERROR: u16 = 4001;
+const CLOSE_NOT_AUTHENTICATED: u16 = 4003;
+const CLOSE_AUTHENTICATION_FAILED: u16 = 4004;
+const CLOSE_ALREADY_AUTHENTICATED: u16 = 4005;
+const CLOSE_SESSION_REPLACED: u16 = 4007;
+const CLOSE_SESSION_TIMEOUT: u16 = 4009;
+
+fn heartbeat_interval() -> Duration {
+    let secs = std::env::var("GATEWAY_HEARTBEAT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v >= 5)
+        .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS);
+    Duration::from_secs(secs)
+}
+
+fn resume_window() -> Duration {
+    let secs = std::env::var("GATEWAY_RESUME_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RESUME_WINDOW_SECS);
+    Duration::from_secs(secs)
+}
+
+fn replay_buffer() -> usize {
+    std::env::var("GATEWAY_REPLAY_BUFFER")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_REPLAY_BUFFER)
+}
+
+// ── Sessions ────────────────────────────────────────────
+
+pub struct GatewaySession {
+    user_id: String,
+    /// Rooms whose events are wanted; `None` for every room the user can see.
+    rooms: Option<HashSet<String>>,
+    /// Whether server-wide events (no `room_id`) are wanted.
+    guild: bool,
+    /// Sequence number of the last dispatch.
+    seq: u64,
+    /// Last dispatches as sent, oldest first.
+    replay: VecDeque<(u64, String)>,
+    /// The connection the session is attached to, if any. Its only sender:
+    /// replacing or dropping it ends that connection (4007).
+    sink: Option<Sink>,
+    detached_at: Option<Instant>,
+    /// Events were lost: the session can no longer be resumed.
+    broken: bool,
+}
+
+/// Where a session's dispatches go while a connection is attached.
+struct Sink {
+    connection: Uuid,
+    tx: mpsc::UnboundedSender<String>,
+}
+
+impl GatewaySession {
+    fn subscriptions(&self) -> serde_json::Value {
+        let rooms = match &self.rooms {
+            None => serde_json::json!(["*"]),
+            Some(rooms) => {
+                let mut rooms: Vec<&String> = rooms.iter().collect();
+                rooms.sort();
+                serde_json::json!(rooms)
+            }
+        };
+        serde_json::json!({ "rooms": rooms, "guild": self.guild })
+    }
+
+    /// Number `event` and hand it to the attached connection.
+    fn dispatch(&mut self, event_type: &str, event: &str) {
+        self.seq += 1;
+        let frame = format!(
+            r#"{{"op":"dispatch","t":{},"s":{},"d":{}}}"#,
+            serde_json::Value::from(event_type),
+            self.seq,
+            event
+        );
+        if self.replay.len() >= replay_buffer() {
+            self.replay.pop_front();
+        }
+        self.replay.push_back((self.seq, frame.clone()));
+        if self.sink.as_ref().is_some_and(|sink| sink.tx.send(frame).is_err()) {
+            self.detach();
+        }
+    }
+
+    fn detach(&mut self) {
+        self.sink = None;
+        self.detached_at = Some(Instant::now());
+    }
+
+    /// Detach `connection`, unless another one replaced it.
+    fn detach_connection(&mut self, connection: Uuid) {
+        if self.sink.as_ref().is_some_and(|sink| sink.connection == connection) {
+            self.detach();
+        }
+    }
+}
+
+pub type GatewaySessions = Arc<Mutex<HashMap<String, Arc<Mutex<GatewaySession>>>>>;
+
+pub fn create_gateway_sessions() -> GatewaySessions {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Shared handles a session works with.
+#[derive(Clone)]
+struct Context {
+    pool: SqlitePool,
+    broadcaster: Broadcaster,
+    access_cache: AccessCache,
+    tickets: WsTickets,
+    session_store: Option<SessionStore>,
+    bot_tokens: Option<BotTokens>,
+    sessions: GatewaySessions,
+    presence: PresenceTracker,
+    voice_occupancy: VoiceOccupancy,
+    voice_sync: VoiceSync,
+}
+
+/// Whether the session wants `event` and its user may see it.
+async fn wants(ctx: &Context, session: &Arc<Mutex<GatewaySession>>, event: &serde_json::Value) -> bool {
+    let (user_id, rooms, guild) = {
+        let s = session.lock().unwrap();
+        (s.user_id.clone(), s.rooms.clone(), s.guild)
+    };
+    let (room_id, author, recipient) = crate::ws::event_routing(event);
+    if recipient.is_some_and(|r| r != user_id) {
+        return false;
+    }
+    if author.is_some_and(|a| crate::ws::is_blocked_for(&ctx.access_cache, &user_id, &a)) {
+        return false;
+    }
+    if let Some(subject) = crate::presence::subject(event) {
+        return guild && crate::presence::visible_to(&ctx.pool, &ctx.access_cache, &user_id, subject).await;
+    }
+    let Some(room_id) = room_id else {
+        return guild;
+    };
+    if rooms.is_some_and(|rooms| !rooms.contains(&room_id)) {
+        return false;
+    }
+    // The room is gone by then, there is no access left to check.
+    event.get("type").and_then(|t| t.as_str()) == Some("room_deleted")
+        || crate::ws::can_user_access_room_cached(&ctx.pool, &ctx.access_cache, &user_id, &room_id).await
+}
+
+/// Feed the session from the broadcaster until it expires.
+async fn pump(ctx: Context, session_id: String, session: Arc<Mutex<GatewaySession>>, mut rx: broadcast::Receiver<String>) {
+    let window = resume_window();
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Ok(text) => {
+                    let Ok(event) = serde_json::from_str::<serde_json::Value>(&text) else {
+                        continue;
+                    };
+                    if wants(&ctx, &session, &event).await {
+                        let event_type = event.get("type").and_then(|t| t.as_str()).unwrap_or("unknown");
+                        session.lock().unwrap().dispatch(event_type, &text);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    // Events were skipped: replaying would leave a silent gap.
+                    let mut s = session.lock().unwrap();
+                    s.broken = true;
+                    if let Some(sink) = s.sink.take() {
+                        let _ = sink.tx.send(frame("invalid_session", serde_json::json!({ "resumable": false })));
+                    }
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = sweep.tick() => {
+                let s = session.lock().unwrap();
+                if s.broken || s.detached_at.is_some_and(|at| at.elapsed() > window) {
+                    break;
+                }
+            }
+        }
+    }
+    ctx.sessions.lock().unwrap().remove(&session_id);
+    let user_id = {
+        let mut s = session.lock().unwrap();
+        // Ends the connection still attached, if any.
+        s.sink = None;
+        s.user_id.clone()
+    };
+    crate::presence::disconnected(&ctx.pool, &ctx.presence, &ctx.broadcaster, &user_id).await;
+}
+
+// ── Frames ──────────────────────────────────────────────
+
+fn frame(op: &str, d: serde_json::Value) -> String {
+    serde_json::json!({ "op": op, "d": d }).to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientFrame {
+    op: String,
+    #[serde(default)]
+    d: serde_json::Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Credentials {
+    token: Option<String>,
+    ticket: Option<String>,
+    bot_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Subscription {
+    rooms: Option<Vec<String>>,
+    guild: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identify {
+    #[serde(flatten)]
+    credentials: Credentials,
+    #[serde(flatten)]
+    subscription: Subscription,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resume {
+    #[serde(flatten)]
+    credentials: Credentials,
+    session_id: String,
+    seq: u64,
+}
+
+fn authenticate(ctx: &Context, credentials: &Credentials) -> Option<Claims> {
+    let claims = match (&credentials.ticket, &credentials.token) {
+        (Some(ticket), _) => crate::ws::redeem_ws_ticket(&ctx.tickets, ticket)?,
+        (None, Some(token)) => crate::auth::validate_token(token)?,
+        (None, None) => return crate::bots::claims_for(ctx.bot_tokens.as_ref()?, credentials.bot_token.as_deref()?),
+    };
+    (!is_revoked(ctx, &claims)).then_some(claims)
+}
+
+fn is_revoked(ctx: &Context, claims: &Claims) -> bool {
+    !claims.sid.is_empty()
+        && ctx
+            .session_store
+            .as_ref()
+            .is_some_and(|store| crate::sessions::is_revoked(store, &claims.sid))
+}
+
+/// Whether what a connection identified with still holds: the token not
+/// expired nor its session revoked, or the bot token not reset.
+fn still_authenticated(ctx: &Context, attached: &Attached) -> bool {
+    if let Some(token) = &attached.bot_token {
+        return ctx
+            .bot_tokens
+            .as_ref()
+            .and_then(|store| crate::bots::claims_for(store, token))
+            .is_some_and(|claims| claims.sub == attached.claims.sub);
+    }
+    attached.claims.exp > chrono::Utc::now().timestamp() as usize && !is_revoked(ctx, &attached.claims)
+}
+
+/// Room set named by a subscribe/identify `rooms` list: `None` means all.
+fn room_set(rooms: &[String]) -> Option<HashSet<String>> {
+    if rooms.iter().any(|r| r == "*") {
+        None
+    } else {
+        Some(rooms.iter().cloned().collect())
+    }
+}
+
+// ── Connection ──────────────────────────────────────────
+
+/// GET /api/gateway — Realtime gateway (WebSocket upgrade, see the module doc)
+#[allow(clippy::too_many_arguments)]
+pub async fn gateway_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    access_cache: web::Data<AccessCache>,
+    tickets: web::Data<WsTickets>,
+    sessions: web::Data<GatewaySessions>,
+    presence: web::Data<PresenceTracker>,
+    voice_occupancy: web::Data<VoiceOccupancy>,
+    voice_sync: web::Data<VoiceSync>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ctx = Context {
+        pool: pool.get_ref().clone(),
+        broadcaster: broadcaster.get_ref().clone(),
+        access_cache: access_cache.get_ref().clone(),
+        tickets: tickets.get_ref().clone(),
+        session_store: req
+            .app_data::<web::Data<SessionStore>>()
+            .map(|store| store.get_ref().clone()),
+        bot_tokens: req
+            .app_data::<web::Data<BotTokens>>()
+            .map(|store| store.get_ref().clone()),
+        sessions: sessions.get_ref().clone(),
+        presence: presence.get_ref().clone(),
+        voice_occupancy: voice_occupancy.get_ref().clone(),
+        voice_sync: voice_sync.get_ref().clone(),
+    };
+    let (response, ws, msg_stream) = actix_ws::handle(&req, stream)?;
+    actix_web::rt::spawn(run_connection(ctx, ws, msg_stream));
+    Ok(response)
+}
+
+async fn close(ws: actix_ws::Session, code: u16, description: &str) {
+    let _ = ws
+        .close(Some(CloseReason { code: CloseCode::Other(code), description: Some(description.to_string()) }))
+        .await;
+}
+
+/// Next text frame, answering pings on the way. `None` when the client is gone.
+async fn next_text(ws: &mut actix_ws::Session, msg_stream: &mut actix_ws::MessageStream) -> Option<String> {
+    loop {
+        match msg_stream.next().await? {
+            Ok(Message::Text(text)) => return Some(text.to_string()),
+            Ok(Message::Ping(bytes)) => {
+                let _ = ws.pong(&bytes).await;
+            }
+            Ok(Message::Close(_)) | Err(_) => return None,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// The session a connection is attached to.
+struct Attached {
+    session: Arc<Mutex<GatewaySession>>,
+    claims: Claims,
+    /// This connection, as named in the session's `sink`.
+    connection: Uuid,
+    /// The bot token identified with, checked again on heartbeats.
+    bot_token: Option<String>,
+    events: mpsc::UnboundedReceiver<String>,
+}
+
+impl Attached {
+    fn new(session: Arc<Mutex<GatewaySession>>, claims: Claims, credentials: Credentials) -> (Self, Sink) {
+        let (tx, events) = mpsc::unbounded_channel();
+        let connection = Uuid::new_v4();
+        // Only a bot token can be the credential used: see `authenticate`.
+        let bot_token = match credentials {
+            Credentials { ticket: None, token: None, bot_token } => bot_token,
+            _ => None,
+        };
+        (Attached { session, claims, connection, bot_token, events }, Sink { connection, tx })
+    }
+}
+
+async fn run_connection(ctx: Context, mut ws: actix_ws::Session, mut msg_stream: actix_ws::MessageStream) {
+    let interval = heartbeat_interval();
+    let hello = frame("hello", serde_json::json!({ "heartbeat_interval_ms": interval.as_millis() as u64 }));
+    if ws.text(hello).await.is_err() {
+        return;
+    }
+
+    let first = match tokio::time::timeout(interval, next_text(&mut ws, &mut msg_stream)).await {
+        Ok(Some(text)) => text,
+        Ok(None) => return,
+        Err(_) => return close(ws, CLOSE_SESSION_TIMEOUT, "Identify timed out").await,
+    };
+    let Ok(first) = serde_json::from_str::<ClientFrame>(&first) else {
+        return close(ws, CLOSE_DECODE_ERROR, "Malformed frame").await;
+    };
+    let attached = match first.op.as_str() {
+        "identify" => identify(&ctx, &mut ws, first.d, interval).await,
+        "resume" => resume(&ctx, &mut ws, first.d).await,
+        _ => return close(ws, CLOSE_NOT_AUTHENTICATED, "Identify or resume first").await,
+    };
+    let mut attached = match attached {
+        Ok(Some(attached)) => attached,
+        // Resume refused; the client can identify on a new connection.
+        Ok(None) => return,
+        Err((code, description)) => return close(ws, code, description).await,
+    };
+
+    let mut deadline = tokio::time::Instant::now() + interval + interval / 2;
+    let outcome = loop {
+        tokio::select! {
+            incoming = msg_stream.next() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Ping(bytes))) => {
+                        let _ = ws.pong(&bytes).await;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break None,
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(client_frame) = serde_json::from_str::<ClientFrame>(&text) else {
+                    break Some((CLOSE_DECODE_ERROR, "Malformed frame"));
+                };
+                match client_frame.op.as_str() {
+                    "heartbeat" => {
+                        if !still_authenticated(&ctx, &attached) {
+                            attached.session.lock().unwrap().broken = true;
+                            break Some((CLOSE_AUTHENTICATION_FAILED, "Session revoked or expired"));
+                        }
+                        deadline = tokio::time::Instant::now() + interval + interval / 2;
+                        let seq = attached.session.lock().unwrap().seq;
+                        if ws.text(frame("heartbeat_ack", serde_json::json!({ "seq": seq }))).await.is_err() {
+                            break None;
+                        }
+                    }
+                    "subscribe" | "unsubscribe" => {
+                        let Ok(change) = serde_json::from_value::<Subscription>(client_frame.d) else {
+                            break Some((CLOSE_DECODE_ERROR, "Malformed frame"));
+                        };
+                        let subscribed = update_subscription(&ctx, &attached, change, client_frame.op == "subscribe").await;
+                        if ws.text(frame("subscriptions", subscribed)).await.is_err() {
+                            break None;
+                        }
+                    }
+                    "send" => {
+                        if let Some(error) = send(&ctx, &attached.claims, client_frame.d).await {
+                            if ws.text(frame("error", error)).await.is_err() {
+                                break None;
+                            }
+                        }
+                    }
+                    "identify" | "resume" => break Some((CLOSE_ALREADY_AUTHENTICATED, "Already identified")),
+                    _ => break Some((CLOSE_DECODE_ERROR, "Unknown op")),
+                }
+            }
+            event = attached.events.recv() => match event {
+                Some(text) => {
+                    if ws.text(text).await.is_err() {
+                        break None;
+                    }
+                }
+                // Another connection resumed the session, or it ended.
+                None => break Some((CLOSE_SESSION_REPLACED, "Session resumed elsewhere or ended")),
+            },
+            _ = tokio::time::sleep_until(deadline) => break Some((CLOSE_SESSION_TIMEOUT, "Heartbeat timed out")),
+        }
+    };
+
+    attached.session.lock().unwrap().detach_connection(attached.connection);
+    if let Some((code, description)) = outcome {
+        close(ws, code, description).await;
+    }
+}
+
+async fn identify(
+    ctx: &Context,
+    ws: &mut actix_ws::Session,
+    d: serde_json::Value,
+    interval: Duration,
+) -> Result<Option<Attached>, (u16, &'static str)> {
+    let Ok(identify) = serde_json::from_value::<Identify>(d) else {
+        return Err((CLOSE_DECODE_ERROR, "Malformed frame"));
+    };
+    let Some(claims) = authenticate(ctx, &identify.credentials) else {
+        return Err((CLOSE_AUTHENTICATION_FAILED, "Authentication failed"));
+    };
+
+    let blocked = crate::relationships::fetch_blocked_ids(&ctx.pool, &claims.sub).await;
+    crate::ws::cache_load_user_blocks(&ctx.access_cache, &claims.sub, blocked);
+
+    let session_id = Uuid::new_v4().simple().to_string();
+    let session = Arc::new(Mutex::new(GatewaySession {
+        user_id: claims.sub.clone(),
+        rooms: identify.subscription.rooms.as_deref().and_then(room_set),
+        guild: identify.subscription.guild.unwrap_or(true),
+        seq: 0,
+        replay: VecDeque::new(),
+        sink: None,
+        detached_at: None,
+        broken: false,
+    }));
+    let (attached, sink) = Attached::new(session.clone(), claims.clone(), identify.credentials);
+    session.lock().unwrap().sink = Some(sink);
+    // Subscribe before `ready` so nothing broadcast after it is missed.
+    let rx = ctx.broadcaster.subscribe();
+    ctx.sessions.lock().unwrap().insert(session_id.clone(), session.clone());
+    actix_web::rt::spawn(pump(ctx.clone(), session_id.clone(), session.clone(), rx));
+    crate::presence::connected(&ctx.pool, &ctx.presence, &ctx.broadcaster, &claims.sub).await;
+
+    let mut ready = session.lock().unwrap().subscriptions();
+    ready["session_id"] = session_id.clone().into();
+    ready["user_id"] = claims.sub.clone().into();
+    ready["heartbeat_interval_ms"] = (interval.as_millis() as u64).into();
+    if ws.text(frame("ready", ready)).await.is_err() {
+        session.lock().unwrap().detach_connection(attached.connection);
+        return Ok(None);
+    }
+    Ok(Some(attached))
+}
+
+async fn resume(
+    ctx: &Context,
+    ws: &mut actix_ws::Session,
+    d: serde_json::Value,
+) -> Result<Option<Attached>, (u16, &'static str)> {
+    let Ok(resume) = serde_json::from_value::<Resume>(d) else {
+        return Err((CLOSE_DECODE_ERROR, "Malformed frame"));
+    };
+    let Some(claims) = authenticate(ctx, &resume.credentials) else {
+        return Err((CLOSE_AUTHENTICATION_FAILED, "Authentication failed"));
+    };
+
+    let session = ctx.sessions.lock().unwrap().get(&resume.session_id).cloned();
+    // Collect the missed dispatches and attach in one step, so live events
+    // queue up behind the replay. Attaching ends the connection the session
+    // had, if any.
+    let attached = session.and_then(|session| {
+        let (attached, sink) = Attached::new(session.clone(), claims.clone(), resume.credentials);
+        let mut s = session.lock().unwrap();
+        let oldest = s.replay.front().map(|(seq, _)| *seq).unwrap_or(s.seq + 1);
+        if s.broken || s.user_id != claims.sub || resume.seq > s.seq || resume.seq + 1 < oldest {
+            return None;
+        }
+        let missed: Vec<String> = s.replay.iter().filter(|(seq, _)| *seq > resume.seq).map(|(_, f)| f.clone()).collect();
+        s.sink = Some(sink);
+        s.detached_at = None;
+        Some((attached, missed))
+    });
+    let Some((attached, missed)) = attached else {
+        let _ = ws.text(frame("invalid_session", serde_json::json!({ "resumable": false }))).await;
+        return Err((CLOSE_SESSION_REPLACED, "Session cannot be resumed"));
+    };
+
+    let replayed = missed.len();
+    for dispatch in missed {
+        if ws.text(dispatch).await.is_err() {
+            attached.session.lock().unwrap().detach_connection(attached.connection);
+            return Ok(None);
+        }
+    }
+    if ws.text(frame("resumed", serde_json::json!({ "replayed": replayed }))).await.is_err() {
+        attached.session.lock().unwrap().detach_connection(attached.connection);
+        return Ok(None);
+    }
+    Ok(Some(attached))
+}
+
+async fn update_subscription(ctx: &Context, attached: &Attached, change: Subscription, subscribe: bool) -> serde_json::Value {
+    // Narrowing "every room" down needs the actual list.
+    let visible = match (&change.rooms, subscribe, attached.session.lock().unwrap().rooms.is_none()) {
+        (Some(rooms), false, true) if !rooms.iter().any(|r| r == "*") => Some(()),
+        _ => None,
+    };
+    let visible = match visible {
+        Some(()) => {
+            let role = crate::ws::get_user_role_cached(&ctx.pool, &ctx.access_cache, &attached.claims.sub)
+                .await
+                .unwrap_or_else(|| "user".to_string());
+            Some(crate::ws::fetch_accessible_rooms(&ctx.pool, &attached.claims.sub, &role).await)
+        }
+        None => None,
+    };
+
+    let mut s = attached.session.lock().unwrap();
+    if let Some(guild) = change.guild {
+        s.guild = if subscribe { guild } else { !guild && s.guild };
+    }
+    if let Some(rooms) = change.rooms {
+        let all = rooms.iter().any(|r| r == "*");
+        match (subscribe, all) {
+            (true, true) => s.rooms = None,
+            (true, false) => {
+                if let Some(current) = s.rooms.as_mut() {
+                    current.extend(rooms);
+                }
+            }
+            (false, true) => s.rooms = Some(HashSet::new()),
+            (false, false) => {
+                let mut current = s.rooms.take().or(visible).unwrap_or_default();
+                for room in &rooms {
+                    current.remove(room);
+                }
+                s.rooms = Some(current);
+            }
+        }
+    }
+    s.subscriptions()
+}
+
+/// Post a client event; an `error` payload when it is refused.
+async fn send(ctx: &Context, claims: &Claims, d: serde_json::Value) -> Option<serde_json::Value> {
+    let event_type = d.get("type").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+    match event_type.as_str() {
+        "message" => {
+            let Ok(mut message) = serde_json::from_value::<WsMessage>(d) else {
+                return Some(serde_json::json!({ "code": "invalid_message" }));
+            };
+            message.user_id = Some(claims.sub.clone());
+            message.username = Some(claims.username.clone());
+            let room_id = message.room_id.clone().unwrap_or_default();
+            match crate::ws::post_message(&ctx.pool, &ctx.broadcaster, &ctx.access_cache, message).await {
+                Ok(()) => None,
+                Err(PostRefusal::Invalid) => Some(serde_json::json!({ "code": "invalid_message", "room_id": room_id })),
+                Err(PostRefusal::MissingPermission(missing)) => Some(serde_json::json!({
+                    "code": "missing_permission",
+                    "room_id": room_id,
+                    "missing": crate::permissions::names(missing),
+                })),
+                Err(PostRefusal::Slowmode(cooldown)) => Some(serde_json::json!({
+                    "code": "slowmode",
+                    "room_id": room_id,
+                    "retry_after": cooldown.retry_after,
+                    "slowmode_seconds": cooldown.slowmode_seconds,
+                })),
+                Err(PostRefusal::AltTextRequired(ids)) => Some(serde_json::json!({
+                    "code": "alt_text_required",
+                    "room_id": room_id,
+                    "attachment_ids": ids,
+                })),
+                Err(PostRefusal::RulesUnacknowledged(pending)) => Some(serde_json::json!({
+                    "code": "rules_acknowledgment_required",
+                    "room_id": room_id,
+                    "rules_version": pending.version,
+                })),
+                Err(PostRefusal::Unverified(unverified)) => Some(serde_json::json!({
+                    "code": "verification_required",
+                    "room_id": room_id,
+                    "verification": unverified.requirement,
+                    "allowed_at": unverified.allowed_at,
+                })),
+                Err(PostRefusal::Lockdown(locked)) => Some(serde_json::json!({
+                    "code": "lockdown",
+                    "room_id": room_id,
+                    "lockdown_ends_at": locked.ends_at,
+                })),
+                Err(PostRefusal::TimedOut(timed_out)) => Some(serde_json::json!({
+                    "code": "timed_out",
+                    "room_id": room_id,
+                    "timed_out_until": timed_out.until,
+                })),
+                Err(PostRefusal::Automod(blocked)) => Some(serde_json::json!({
+                    "code": "automod_blocked",
+                    "room_id": room_id,
+                    "rule": blocked.rule,
+                    "timed_out_until": blocked.timed_out_until,
+                })),
+            }
+        }
+        "presence" => {
+            let Ok(update) = serde_json::from_value::<crate::presence::PresenceUpdate>(d) else {
+                return Some(serde_json::json!({ "code": "invalid_presence" }));
+            };
+            match crate::presence::update(&ctx.pool, &ctx.presence, &ctx.broadcaster, &claims.sub, update).await {
+                Ok(_) => None,
+                Err(error) => Some(serde_json::json!({ "code": "invalid_presence", "error": error })),
+            }
+        }
+        "typing" => {
+            let mut event = d;
+            if let Some(room_id) = event.get("room_id").and_then(|r| r.as_str()) {
+                if !crate::ws::can_user_access_room_cached(&ctx.pool, &ctx.access_cache, &claims.sub, room_id).await {
+                    return Some(serde_json::json!({ "code": "forbidden", "room_id": room_id }));
+                }
+            }
+            event["user_id"] = claims.sub.clone().into();
+            event["username"] = claims.username.clone().into();
+            let _ = ctx.broadcaster.send(event.to_string());
+            None
+        }
+        "activity_state" => {
+            let Ok(update) = serde_json::from_value::<crate::voice_sync::ActivityUpdate>(d) else {
+                return Some(serde_json::json!({ "code": "invalid_activity" }));
+            };
+            let room_id = update.room_id.clone();
+            crate::voice_sync::publish(&ctx.voice_sync, &ctx.voice_occupancy, &ctx.broadcaster, claims, update)
+                .err()
+                .map(|refusal| refusal.error(&room_id))
+        }
+        _ => Some(serde_json::json!({ "code": "unsupported_event", "type": event_type })),
+    }
+}
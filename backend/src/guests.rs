@@ -0,0 +1,575 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Guest access links
+// ═══════════════════════════════════════════════════════
+//
+// Admins create a guest link for one text room: a random token, shown once
+// and stored hashed, that expires (`expires_in_hours`, at most a week) and
+// may be limited to `max_uses` redemptions. Redeeming it creates a guest: a
+// `users` row with role `guest` and no password, signed in with an ordinary
+// session. A guest can read that room's history and live events and, when
+// the link allows it (`can_post`), post in it, nothing else:
+//
+// - `extract_claims` refuses guest tokens on every route but `GUEST_ROUTES`,
+//   so new endpoints are closed to guests unless listed here;
+// - room permissions (see `permissions`) only let a guest see their room,
+//   and post in it with `can_post`, until the link's expiry;
+// - refresh tokens of an expired guest are refused;
+// - guests never bypass slowmode and wait at least
+//   `GUEST_POST_INTERVAL_SECONDS` between two messages (see `slowmode`), on
+//   top of the `RATE_LIMIT_GUEST` bucket on redemption (see `ratelimit`).
+//
+// Revoking a link ends the access of its guests and revokes their sessions.
+// A guest can turn into a full account with `POST /api/guest/convert` while
+// their access lasts: the user id stays the same, so their messages stay
+// theirs.
+//
+// Config (env):
+//   GUEST_POST_INTERVAL_SECONDS  minimum spacing of a guest's messages (default 10, 0 = none)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use bcrypt::{hash, DEFAULT_COST};
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, AuthResponse};
+use crate::sessions::{issue_token, DeviceInfo, SessionStore};
+
+/// Role of every guest principal. Cannot be used as a server role name.
+pub const GUEST_ROLE: &str = "guest";
+
+const DEFAULT_LINK_HOURS: i64 = 24;
+const MAX_LINK_HOURS: i64 = 168;
+const DEFAULT_POST_INTERVAL_SECONDS: i64 = 10;
+const MAX_DISPLAY_NAME_CHARS: usize = 32;
+
+/// Routes a guest token is accepted on, as `(method, route pattern)`.
+const GUEST_ROUTES: &[(&str, &str)] = &[
+    ("GET", "/api/rooms/{room_id}/messages"),
+    ("GET", "/api/guest/me"),
+    ("POST", "/api/guest/convert"),
+    ("POST", "/api/auth/ws-ticket"),
+];
+
+/// Whether a request authenticated as a guest may go through.
+pub(crate) fn allows_route(req: &HttpRequest) -> bool {
+    let Some(pattern) = req.match_pattern() else {
+        return false;
+    };
+    GUEST_ROUTES
+        .iter()
+        .any(|(method, route)| req.method().as_str() == *method && pattern == *route)
+}
+
+/// Minimum spacing of two messages from a guest, in seconds.
+pub(crate) fn post_interval_seconds() -> i64 {
+    std::env::var("GUEST_POST_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|s| crate::slowmode::is_valid(*s))
+        .unwrap_or(DEFAULT_POST_INTERVAL_SECONDS)
+}
+
+// ── Grants ──────────────────────────────────────────────
+
+/// What an unexpired, unconverted guest may do.
+#[derive(Debug, Clone)]
+pub(crate) struct GuestGrant {
+    pub(crate) room_id: String,
+    pub(crate) can_post: bool,
+    pub(crate) expires_at: String,
+}
+
+/// The live grant of `user_id`, `None` once it expired or for non-guests.
+pub(crate) async fn active_grant(pool: &SqlitePool, user_id: &str) -> Option<GuestGrant> {
+    let row = sqlx::query(
+        "SELECT room_id, can_post, expires_at FROM guests WHERE user_id = ? AND converted_at IS NULL AND expires_at > ?"
+    )
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()?;
+    Some(GuestGrant {
+        room_id: row.get("room_id"),
+        can_post: row.get::<i64, _>("can_post") != 0,
+        expires_at: row.get("expires_at"),
+    })
+}
+
+// ── Tokens ──────────────────────────────────────────────
+
+fn generate_link_token() -> String {
+    let mut bytes = [0u8; 24];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Link tokens are random 192-bit values, a plain SHA-256 is enough.
+fn hash_link_token(raw: &str) -> String {
+    Sha256::digest(raw.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// ── Links (admin) ───────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct GuestLink {
+    pub id: String,
+    pub room_id: String,
+    pub can_post: bool,
+    pub max_uses: Option<i64>,
+    pub uses: i64,
+    pub created_by: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+    /// Only when the link is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+const LINK_COLUMNS: &str = "id, room_id, can_post, max_uses, uses, created_by, created_at, expires_at, revoked_at";
+
+impl GuestLink {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            room_id: row.get("room_id"),
+            can_post: row.get::<i64, _>("can_post") != 0,
+            max_uses: row.get("max_uses"),
+            uses: row.get("uses"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+            revoked_at: row.get("revoked_at"),
+            token: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGuestLink {
+    #[serde(default)]
+    pub can_post: bool,
+    pub expires_in_hours: Option<i64>,
+    pub max_uses: Option<i64>,
+}
+
+/// POST /api/rooms/{id}/guest-links — Create a guest link for a text room (MANAGE_ROOMS)
+pub async fn create_link(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<CreateGuestLink>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let room_id = path.into_inner();
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    match kind.as_deref() {
+        None => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" })),
+        Some("text") => {}
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Guest links are only for text rooms" })),
+    }
+
+    let hours = body.expires_in_hours.unwrap_or(DEFAULT_LINK_HOURS);
+    if !(1..=MAX_LINK_HOURS).contains(&hours) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "expires_in_hours must be 1 to 168" }));
+    }
+    if body.max_uses.is_some_and(|n| n < 1) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "max_uses must be at least 1" }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let token = generate_link_token();
+    let now = Utc::now();
+    let result = sqlx::query(
+        "INSERT INTO guest_links (id, token_hash, room_id, can_post, max_uses, created_by, created_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(hash_link_token(&token))
+    .bind(&room_id)
+    .bind(body.can_post)
+    .bind(body.max_uses)
+    .bind(&claims.sub)
+    .bind(now.to_rfc3339())
+    .bind((now + chrono::Duration::hours(hours)).to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let sql = format!("SELECT {LINK_COLUMNS} FROM guest_links WHERE id = ?");
+    match sqlx::query(&sql).bind(&id).fetch_one(pool.get_ref()).await {
+        Ok(row) => {
+            let mut link = GuestLink::from_row(&row);
+            link.token = Some(token);
+            HttpResponse::Created().json(link)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+const GUEST_LINKS: crate::pagination::Listing = crate::pagination::Listing {
+    name: "guest_links",
+    default_limit: 50,
+    max_limit: 200,
+    sorts: &[("created_at", "created_at")],
+    default_sort: "-created_at",
+    tiebreak: "id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ListLinksQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    /// Only links that can still be redeemed (`true`) or only the others (`false`).
+    pub active: Option<bool>,
+}
+
+/// GET /api/rooms/{id}/guest-links?limit=&sort=&cursor=&active= — Guest links of a room, newest first (MANAGE_ROOMS)
+pub async fn list_links(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    query: web::Query<ListLinksQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+    let page = match GUEST_LINKS.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+
+    let sql = format!(
+        "SELECT {LINK_COLUMNS}, {} FROM guest_links WHERE room_id = ? \
+           AND (? IS NULL OR ? = (revoked_at IS NULL AND expires_at > ? AND (max_uses IS NULL OR uses < max_uses))){}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut qx = sqlx::query(&sql)
+        .bind(path.into_inner())
+        .bind(query.active)
+        .bind(query.active)
+        .bind(Utc::now().to_rfc3339());
+    for value in page.keyset_binds() {
+        qx = qx.bind(value);
+    }
+    match qx.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await {
+        Ok(rows) => {
+            let (rows, next_cursor) = page.finish_rows(rows, "id");
+            let links: Vec<GuestLink> = rows.iter().map(GuestLink::from_row).collect();
+            crate::pagination::with_next_cursor(HttpResponse::Ok().json(links), next_cursor)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/guest-links/{id} — Revoke a link and end its guests' access (MANAGE_ROOMS)
+pub async fn revoke_link(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    session_store: web::Data<SessionStore>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let link_id = path.into_inner();
+    let now = Utc::now().to_rfc3339();
+    let found = sqlx::query("UPDATE guest_links SET revoked_at = COALESCE(revoked_at, ?) WHERE id = ?")
+        .bind(&now)
+        .bind(&link_id)
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or(false);
+    if !found {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Guest link not found" }));
+    }
+
+    let guest_ids: Vec<String> = sqlx::query_scalar(
+        "SELECT user_id FROM guests WHERE link_id = ? AND converted_at IS NULL AND expires_at > ?"
+    )
+    .bind(&link_id)
+    .bind(&now)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+    let _ = sqlx::query("UPDATE guests SET expires_at = ? WHERE link_id = ? AND converted_at IS NULL AND expires_at > ?")
+        .bind(&now)
+        .bind(&link_id)
+        .bind(&now)
+        .execute(pool.get_ref())
+        .await;
+    for guest_id in &guest_ids {
+        crate::sessions::revoke_user_sessions(pool.get_ref(), session_store.get_ref(), guest_id, None).await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "status": "revoked", "guests_removed": guest_ids.len() }))
+}
+
+// ── Guests ──────────────────────────────────────────────
+
+/// A guest's view of their own access.
+#[derive(Debug, Serialize)]
+pub struct GuestAccess {
+    pub room_id: String,
+    pub room_name: String,
+    pub can_post: bool,
+    pub expires_at: String,
+}
+
+async fn guest_access(pool: &SqlitePool, grant: GuestGrant) -> GuestAccess {
+    let room_name: String = sqlx::query_scalar("SELECT name FROM rooms WHERE id = ?")
+        .bind(&grant.room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    GuestAccess {
+        room_id: grant.room_id,
+        room_name,
+        can_post: grant.can_post,
+        expires_at: grant.expires_at,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedeemGuestLink {
+    pub token: String,
+    pub display_name: Option<String>,
+    /// Required while joining takes a captcha (see `verification`).
+    pub captcha: Option<crate::verification::CaptchaSolution>,
+}
+
+/// POST /api/guest/redeem — Trade a guest link for a guest session
+pub async fn redeem(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<crate::ws::Broadcaster>,
+    body: web::Json<RedeemGuestLink>,
+) -> HttpResponse {
+    let invalid = || HttpResponse::NotFound().json(serde_json::json!({ "error": "Invalid or expired guest link" }));
+
+    let display_name = body.display_name.as_deref().map(str::trim).unwrap_or_default();
+    if display_name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Display name must be at most 32 characters" }));
+    }
+
+    if crate::lockdown::guest_links_paused(pool.get_ref()).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Guest links are paused during a lockdown" }));
+    }
+    if crate::verification::guest_links_paused(pool.get_ref()).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Guest links are paused during a join surge" }));
+    }
+    if let Err(response) = crate::verification::require_captcha(pool.get_ref(), body.captcha.as_ref()).await {
+        return response;
+    }
+
+    // Take a use first, so concurrent redemptions cannot exceed `max_uses`.
+    let token_hash = hash_link_token(body.token.trim());
+    let now = Utc::now().to_rfc3339();
+    let claimed = sqlx::query(
+        "UPDATE guest_links SET uses = uses + 1 WHERE token_hash = ? AND revoked_at IS NULL AND expires_at > ? \
+         AND (max_uses IS NULL OR uses < max_uses)"
+    )
+    .bind(&token_hash)
+    .bind(&now)
+    .execute(pool.get_ref())
+    .await
+    .map(|r| r.rows_affected() > 0)
+    .unwrap_or(false);
+    if !claimed {
+        return invalid();
+    }
+    let Ok(link) = sqlx::query("SELECT id, room_id, can_post, expires_at FROM guest_links WHERE token_hash = ?")
+        .bind(&token_hash)
+        .fetch_one(pool.get_ref())
+        .await
+    else {
+        return invalid();
+    };
+    let link_id: String = link.get("id");
+    let grant = GuestGrant {
+        room_id: link.get("room_id"),
+        can_post: link.get::<i64, _>("can_post") != 0,
+        expires_at: link.get("expires_at"),
+    };
+
+    let preferred = if display_name.is_empty() { GUEST_ROLE.to_string() } else { format!("{display_name} (guest)") };
+    let username = crate::auth::allocate_unique_username(pool.get_ref(), &preferred).await;
+    let user_id = Uuid::new_v4().to_string();
+
+    let created: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("INSERT INTO users (id, username, password_hash, password_set, role) VALUES (?, ?, '!', 0, ?)")
+            .bind(&user_id)
+            .bind(&username)
+            .bind(GUEST_ROLE)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO guests (user_id, link_id, room_id, can_post, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&user_id)
+            .bind(&link_id)
+            .bind(&grant.room_id)
+            .bind(grant.can_post)
+            .bind(&now)
+            .bind(&grant.expires_at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+    if created.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::verification::record_join(pool.get_ref(), broadcaster.get_ref()).await;
+
+    let tokens = match issue_token(pool.get_ref(), &DeviceInfo::from_request(&req), &user_id, &username, GUEST_ROLE).await {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "token": tokens.token,
+        "refresh_token": tokens.refresh_token,
+        "expires_in": tokens.expires_in,
+        "user_id": user_id,
+        "username": username,
+        "role": GUEST_ROLE,
+        "guest": guest_access(pool.get_ref(), grant).await,
+    }))
+}
+
+/// GET /api/guest/me — The caller's guest access
+pub async fn get_access(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if claims.role != GUEST_ROLE {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Not a guest" }));
+    }
+    match active_grant(pool.get_ref(), &claims.sub).await {
+        Some(grant) => HttpResponse::Ok().json(guest_access(pool.get_ref(), grant).await),
+        None => HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Guest access expired" })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertGuest {
+    pub username: String,
+    pub password: String,
+}
+
+/// POST /api/guest/convert — Turn the caller's guest principal into a full account
+pub async fn convert(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    session_store: web::Data<SessionStore>,
+    access_cache: web::Data<crate::ws::AccessCache>,
+    body: web::Json<ConvertGuest>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if claims.role != GUEST_ROLE {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Not a guest" }));
+    }
+    if active_grant(pool.get_ref(), &claims.sub).await.is_none() {
+        return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Guest access expired" }));
+    }
+
+    let username = body.username.trim();
+    if username.is_empty() || body.password.len() < 8 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Username must be non-empty and password at least 8 characters"
+        }));
+    }
+    let taken = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE username = ? AND id != ?")
+        .bind(username)
+        .bind(&claims.sub)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if taken > 0 {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Username already taken" }));
+    }
+
+    let password_hash = hash(&body.password, DEFAULT_COST).expect("hash failed");
+    let converted: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("UPDATE users SET username = ?, password_hash = ?, password_set = 1, role = 'user' WHERE id = ? AND role = ?")
+            .bind(username)
+            .bind(&password_hash)
+            .bind(&claims.sub)
+            .bind(GUEST_ROLE)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE guests SET converted_at = ? WHERE user_id = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(&claims.sub)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+    if converted.is_err() {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Conversion failed (username might be taken)" }));
+    }
+    crate::ws::cache_set_user_role(access_cache.get_ref(), &claims.sub, "user");
+    crate::quickswitch::invalidate_from(&req);
+
+    // Guest sessions end here; the account starts with a fresh one.
+    crate::sessions::revoke_user_sessions(pool.get_ref(), session_store.get_ref(), &claims.sub, None).await;
+    let tokens = match issue_token(pool.get_ref(), &DeviceInfo::from_request(&req), &claims.sub, username, "user").await {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    };
+
+    HttpResponse::Ok().json(AuthResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
+        user_id: claims.sub,
+        username: username.to_string(),
+        role: "user".to_string(),
+        avatar_color: 0,
+        about: "".to_string(),
+        avatar_url: None,
+        banner_url: None,
+        two_factor_setup_required: false,
+    })
+}
@@ -0,0 +1,643 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Avatars, profile banners and the server banner
+// ═══════════════════════════════════════════════════════
+//
+// `PUT /api/users/@me/avatar`, `PUT /api/users/@me/banner` and
+// `PUT /api/server/banner` take one multipart PNG or JPEG, up to
+// `MAX_AVATAR_BYTES` / `MAX_BANNER_BYTES`. Its dimensions are checked
+// before it is decoded (at most `MAX_SOURCE_DIMENSION` a side, at least
+// `MIN_AVATAR_SIZE`, `MIN_USER_BANNER_WIDTH` or `MIN_BANNER_WIDTH` once
+// cropped). It is center-cropped (avatars square, profile banners 3:1,
+// the server banner 16:9) and re-encoded, which also strips any metadata,
+// as PNGs in the standard sizes the source is large enough for:
+// `AVATAR_SIZES`, `USER_BANNER_WIDTHS` and `BANNER_WIDTHS`.
+//
+// The sizes go to the file store (see `files`), content-addressed like
+// attachments, and are served publicly at `/api/images/{asset}/{width}.png`,
+// cacheable forever: a new upload is a new asset, and the one it replaces
+// is deleted.
+//
+// Moderation: with IMAGE_MODERATION_URL set, the largest size is POSTed
+// there before anything is stored:
+//   { kind, owner_id, uploaded_by, sha256, width, height, content_type, image (base64) }
+// signed with IMAGE_MODERATION_SECRET, if set, as
+// `X-Voxium-Signature: sha256=<hex HMAC of the body>`. The hook answers
+// `{ "allow": bool, "reason"? }`; a refusal is a 422 carrying the reason.
+// When the hook can't be reached or answers anything else, the upload is
+// refused with 503, unless IMAGE_MODERATION_FAIL_OPEN=true.
+//
+// A new avatar becomes the user's `avatar_url` (the `AVATAR_URL_SIZE`
+// size, or the largest below) and a profile banner their `banner_url`
+// (the largest size); both are broadcast like a profile edit, and are the
+// only URLs `PATCH /api/users/me` accepts for those fields. The server
+// banner is broadcast as `server_banner_updated` and audited.
+//
+// Config (env): IMAGE_MODERATION_URL, IMAGE_MODERATION_SECRET,
+// IMAGE_MODERATION_TIMEOUT_SECS (default 10), IMAGE_MODERATION_FAIL_OPEN
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::files::{FileStorage, FileStore};
+use crate::ws::Broadcaster;
+
+const MAX_AVATAR_BYTES: usize = 4 * 1024 * 1024;
+const MAX_BANNER_BYTES: usize = 8 * 1024 * 1024;
+/// Larger images are refused before decoding.
+const MAX_SOURCE_DIMENSION: u32 = 4096;
+/// Avatars are squares of these sizes, as far as the source allows.
+const AVATAR_SIZES: [u32; 4] = [64, 128, 256, 512];
+const MIN_AVATAR_SIZE: u32 = 128;
+/// The size `avatar_url` points at, or the largest below it.
+const AVATAR_URL_SIZE: u32 = 256;
+/// Profile banners are 3:1, in these widths as far as the source allows.
+const USER_BANNER_WIDTHS: [u32; 2] = [600, 1200];
+const MIN_USER_BANNER_WIDTH: u32 = 600;
+/// Server banners are 16:9, in these widths as far as the source allows.
+const BANNER_WIDTHS: [u32; 3] = [480, 960, 1920];
+const MIN_BANNER_WIDTH: u32 = 960;
+const DEFAULT_MODERATION_TIMEOUT_SECS: u64 = 10;
+/// `owner_id` of the server banner.
+const SERVER_OWNER: &str = "";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Avatar,
+    UserBanner,
+    ServerBanner,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Avatar => "avatar",
+            Kind::UserBanner => "user_banner",
+            Kind::ServerBanner => "server_banner",
+        }
+    }
+
+    fn max_bytes(self) -> usize {
+        match self {
+            Kind::Avatar => MAX_AVATAR_BYTES,
+            Kind::UserBanner | Kind::ServerBanner => MAX_BANNER_BYTES,
+        }
+    }
+
+    /// The `users` column a user's own asset of this kind is shown through.
+    fn profile_field(self) -> Option<&'static str> {
+        match self {
+            Kind::Avatar => Some("avatar_url"),
+            Kind::UserBanner => Some("banner_url"),
+            Kind::ServerBanner => None,
+        }
+    }
+
+    /// The size `profile_field` points at.
+    fn profile_url(self, asset: &ImageAsset) -> Option<String> {
+        let size = match self {
+            Kind::Avatar => asset.sizes.iter().rev().find(|s| s.width <= AVATAR_URL_SIZE),
+            _ => asset.sizes.last(),
+        };
+        size.map(|s| s.url.clone())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageAsset {
+    pub id: String,
+    pub kind: String,
+    /// Smallest first.
+    pub sizes: Vec<ImageSize>,
+    pub created_at: String,
+}
+
+fn image_url(asset_id: &str, width: u32) -> String {
+    format!("/api/images/{asset_id}/{width}.png")
+}
+
+async fn fetch_asset(pool: &SqlitePool, kind: Kind, owner_id: &str) -> Option<ImageAsset> {
+    let row = sqlx::query("SELECT id, created_at FROM image_assets WHERE kind = ? AND owner_id = ?")
+        .bind(kind.as_str())
+        .bind(owner_id)
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+    let id: String = row.get("id");
+    let sizes = sqlx::query("SELECT width, height FROM image_variants WHERE asset_id = ? ORDER BY width")
+        .bind(&id)
+        .fetch_all(pool)
+        .await
+        .ok()?
+        .iter()
+        .map(|size| {
+            let width = size.get::<i64, _>("width") as u32;
+            ImageSize { width, height: size.get::<i64, _>("height") as u32, url: image_url(&id, width) }
+        })
+        .collect();
+    Some(ImageAsset { kind: kind.as_str().to_string(), sizes, created_at: row.get("created_at"), id })
+}
+
+// ── Processing ──────────────────────────────────────────
+
+struct Variant {
+    width: u32,
+    height: u32,
+    png: Vec<u8>,
+}
+
+/// Check, crop and resize an upload into its standard sizes, smallest first.
+fn render(kind: Kind, bytes: &[u8]) -> Result<Vec<Variant>, String> {
+    use image::ImageEncoder;
+
+    let format = match crate::files::sniff_content_type(bytes) {
+        Some("image/png") => image::ImageFormat::Png,
+        Some("image/jpeg") => image::ImageFormat::Jpeg,
+        _ => return Err("Image must be a PNG or JPEG".to_string()),
+    };
+    let reader = image::ImageReader::with_format(std::io::Cursor::new(bytes), format);
+    let (width, height) = reader.into_dimensions().map_err(|_| "Image can't be read".to_string())?;
+    if width > MAX_SOURCE_DIMENSION || height > MAX_SOURCE_DIMENSION {
+        return Err(format!("Image is too large (max {MAX_SOURCE_DIMENSION}x{MAX_SOURCE_DIMENSION})"));
+    }
+
+    let (crop_width, crop_height) = match kind {
+        Kind::Avatar => {
+            let side = width.min(height);
+            if side < MIN_AVATAR_SIZE {
+                return Err(format!("Image is too small (min {MIN_AVATAR_SIZE}x{MIN_AVATAR_SIZE})"));
+            }
+            (side, side)
+        }
+        Kind::UserBanner => {
+            let crop_width = width.min(height * 3);
+            if crop_width < MIN_USER_BANNER_WIDTH {
+                return Err(format!("Image is too small (min {MIN_USER_BANNER_WIDTH}x{})", MIN_USER_BANNER_WIDTH / 3));
+            }
+            (crop_width, crop_width / 3)
+        }
+        Kind::ServerBanner => {
+            let crop_width = width.min(height * 16 / 9);
+            if crop_width < MIN_BANNER_WIDTH {
+                return Err(format!("Image is too small (min {MIN_BANNER_WIDTH}x{})", MIN_BANNER_WIDTH * 9 / 16));
+            }
+            (crop_width, crop_width * 9 / 16)
+        }
+    };
+    let sizes: Vec<(u32, u32)> = match kind {
+        Kind::Avatar => AVATAR_SIZES.iter().filter(|s| **s <= crop_width).map(|s| (*s, *s)).collect(),
+        Kind::UserBanner => USER_BANNER_WIDTHS.iter().filter(|w| **w <= crop_width).map(|w| (*w, w / 3)).collect(),
+        Kind::ServerBanner => BANNER_WIDTHS.iter().filter(|w| **w <= crop_width).map(|w| (*w, w * 9 / 16)).collect(),
+    };
+
+    let img = image::load_from_memory_with_format(bytes, format).map_err(|_| "Image can't be read".to_string())?;
+    let cropped = img.crop_imm((width - crop_width) / 2, (height - crop_height) / 2, crop_width, crop_height);
+    sizes
+        .into_iter()
+        .map(|(width, height)| {
+            let pixels = cropped.resize_exact(width, height, image::imageops::FilterType::Lanczos3).to_rgba8();
+            let mut png = Vec::new();
+            image::codecs::png::PngEncoder::new(&mut png)
+                .write_image(pixels.as_raw(), width, height, image::ExtendedColorType::Rgba8)
+                .map_err(|_| "Failed to encode image".to_string())?;
+            Ok(Variant { width, height, png })
+        })
+        .collect()
+}
+
+// ── Moderation hook ─────────────────────────────────────
+
+enum Verdict {
+    Allowed,
+    Rejected(Option<String>),
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Ask IMAGE_MODERATION_URL about `variant`. `Err` when it gave no verdict.
+async fn moderate(kind: Kind, owner_id: &str, uploaded_by: &str, variant: &Variant, hash: &str) -> Result<Verdict, String> {
+    let Some(url) = env("IMAGE_MODERATION_URL") else {
+        return Ok(Verdict::Allowed);
+    };
+    let timeout = env("IMAGE_MODERATION_TIMEOUT_SECS")
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MODERATION_TIMEOUT_SECS);
+    let body = serde_json::json!({
+        "kind": kind.as_str(),
+        "owner_id": (owner_id != SERVER_OWNER).then_some(owner_id),
+        "uploaded_by": uploaded_by,
+        "sha256": hash,
+        "width": variant.width,
+        "height": variant.height,
+        "content_type": "image/png",
+        "image": general_purpose::STANDARD.encode(&variant.png),
+    })
+    .to_string();
+
+    let mut request = reqwest::Client::new()
+        .post(&url)
+        .timeout(Duration::from_secs(timeout))
+        .header("Content-Type", "application/json");
+    if let Some(secret) = env("IMAGE_MODERATION_SECRET") {
+        let signature: String = crate::crypto::hmac_sha256(secret.as_bytes(), body.as_bytes()).iter().map(|b| format!("{b:02x}")).collect();
+        request = request.header("X-Voxium-Signature", format!("sha256={signature}"));
+    }
+    let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("moderation hook answered {}", response.status()));
+    }
+    let verdict: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+    match verdict.get("allow").and_then(serde_json::Value::as_bool) {
+        Some(true) => Ok(Verdict::Allowed),
+        Some(false) => Ok(Verdict::Rejected(
+            verdict.get("reason").and_then(serde_json::Value::as_str).map(|r| r.chars().take(200).collect()),
+        )),
+        None => Err("moderation hook answered without `allow`".to_string()),
+    }
+}
+
+// ── Storage ─────────────────────────────────────────────
+
+/// Whether an avatar or banner uses the stored object `hash`.
+pub(crate) async fn uses_object(pool: &SqlitePool, hash: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM image_variants WHERE hash = ?)")
+        .bind(hash)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(true)
+}
+
+/// Delete the objects of a removed asset that nothing else uses.
+async fn drop_objects(pool: &SqlitePool, storage: &FileStore, hashes: Vec<String>) {
+    for hash in hashes {
+        let attached: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM files WHERE hash = ? OR thumbnail_hash = ?)")
+            .bind(&hash)
+            .bind(&hash)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(true);
+        if !attached && !uses_object(pool, &hash).await && !crate::uploads::uses_object(pool, &hash).await {
+            storage.delete(&hash).await;
+        }
+    }
+}
+
+/// Delete the asset of `kind` and `owner_id`, returning its id.
+async fn remove_asset(pool: &SqlitePool, storage: &FileStore, kind: Kind, owner_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let Some(id): Option<String> = sqlx::query_scalar("SELECT id FROM image_assets WHERE kind = ? AND owner_id = ?")
+        .bind(kind.as_str())
+        .bind(owner_id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let hashes: Vec<String> = sqlx::query_scalar("SELECT hash FROM image_variants WHERE asset_id = ?")
+        .bind(&id)
+        .fetch_all(pool)
+        .await?;
+    sqlx::query("DELETE FROM image_assets WHERE id = ?").bind(&id).execute(pool).await?;
+    drop_objects(pool, storage, hashes).await;
+    Ok(Some(id))
+}
+
+/// Read, render, moderate and store an upload as the asset of `kind` and
+/// `owner_id`. Returns it and the id of the asset it replaced.
+async fn replace_asset(
+    pool: &SqlitePool,
+    storage: &FileStore,
+    kind: Kind,
+    owner_id: &str,
+    uploaded_by: &str,
+    payload: &mut Multipart,
+) -> Result<(ImageAsset, Option<String>), HttpResponse> {
+    let (_, bytes) = crate::files::read_upload(payload, kind.max_bytes()).await?;
+    crate::scanning::refuse_flagged(&bytes).await?;
+    let variants = match web::block(move || render(kind, &bytes)).await {
+        Ok(Ok(variants)) => variants,
+        Ok(Err(error)) => return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))),
+        Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+    };
+    let hashes: Vec<String> = variants.iter().map(|v| crate::files::sha256_hex(&v.png)).collect();
+
+    let (largest, largest_hash) = (variants.last().expect("at least the minimum size"), hashes.last().expect("one hash per size"));
+    match moderate(kind, owner_id, uploaded_by, largest, largest_hash).await {
+        Ok(Verdict::Allowed) => {}
+        Ok(Verdict::Rejected(reason)) => {
+            return Err(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Image rejected by moderation",
+                "reason": reason,
+            })));
+        }
+        Err(e) if env("IMAGE_MODERATION_FAIL_OPEN").is_some_and(|v| matches!(v.as_str(), "1" | "true" | "yes")) => {
+            eprintln!("⚠️  Image moderation failed, accepting the upload: {e}");
+        }
+        Err(e) => {
+            eprintln!("⚠️  Image moderation failed: {e}");
+            return Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": "Image moderation is unavailable, try again later"
+            })));
+        }
+    }
+
+    for (variant, hash) in variants.iter().zip(&hashes) {
+        if let Err(e) = storage.put(hash, variant.png.clone()).await {
+            eprintln!("⚠️  Storing image {hash} failed: {e}");
+            return Err(HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save image" })));
+        }
+    }
+
+    let previous = remove_asset(pool, storage, kind, owner_id).await.map_err(|_| HttpResponse::InternalServerError().finish())?;
+    let id = Uuid::new_v4().to_string();
+    let stored = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("INSERT INTO image_assets (id, kind, owner_id, uploaded_by, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(&id)
+            .bind(kind.as_str())
+            .bind(owner_id)
+            .bind(uploaded_by)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        for (variant, hash) in variants.iter().zip(&hashes) {
+            sqlx::query("INSERT INTO image_variants (asset_id, width, height, hash) VALUES (?, ?, ?, ?)")
+                .bind(&id)
+                .bind(variant.width as i64)
+                .bind(variant.height as i64)
+                .bind(hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    };
+    // Another upload for the same owner may have won the race.
+    if stored.await.is_err() {
+        drop_objects(pool, storage, hashes).await;
+        return Err(HttpResponse::Conflict().json(serde_json::json!({ "error": "Another upload replaced it meanwhile, try again" })));
+    }
+
+    match fetch_asset(pool, kind, owner_id).await {
+        Some(asset) => Ok((asset, previous)),
+        None => Err(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// Whether `url` points at the asset `asset_id`.
+fn is_asset_url(url: Option<&str>, asset_id: &str) -> bool {
+    url.is_some_and(|url| url.starts_with(&format!("/api/images/{asset_id}/")))
+}
+
+/// Whether `url` is a size of `user_id`'s own avatar (`field` is
+/// `avatar_url`) or profile banner (`banner_url`).
+pub(crate) async fn is_profile_image(pool: &SqlitePool, user_id: &str, field: &str, url: &str) -> bool {
+    let kind = match field {
+        "avatar_url" => Kind::Avatar,
+        "banner_url" => Kind::UserBanner,
+        _ => return false,
+    };
+    let Some((asset_id, file)) = url.strip_prefix("/api/images/").and_then(|rest| rest.split_once('/')) else {
+        return false;
+    };
+    let Some(width) = file.strip_suffix(".png").and_then(|w| w.parse::<i64>().ok()) else {
+        return false;
+    };
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM image_variants v JOIN image_assets a ON a.id = v.asset_id \
+         WHERE a.id = ? AND a.kind = ? AND a.owner_id = ? AND v.width = ?)"
+    )
+    .bind(asset_id)
+    .bind(kind.as_str())
+    .bind(user_id)
+    .bind(width)
+    .fetch_one(pool)
+    .await
+    .unwrap_or(false)
+}
+
+/// Store an upload as the caller's asset of `kind` and show it on their profile.
+async fn upload_profile_image(
+    req: &HttpRequest,
+    pool: &SqlitePool,
+    storage: &FileStore,
+    broadcaster: &Broadcaster,
+    payload: &mut Multipart,
+    kind: Kind,
+) -> HttpResponse {
+    let claims = match extract_claims(req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let Some(field) = kind.profile_field() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let (asset, _) = match replace_asset(pool, storage, kind, &claims.sub, &claims.sub, payload).await {
+        Ok(replaced) => replaced,
+        Err(response) => return response,
+    };
+    if sqlx::query(&format!("UPDATE users SET {field} = ? WHERE id = ?"))
+        .bind(kind.profile_url(&asset))
+        .bind(&claims.sub)
+        .execute(pool)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::quickswitch::invalidate_from(req);
+    crate::auth::broadcast_profile(pool, broadcaster, &claims.sub).await;
+    HttpResponse::Ok().json(asset)
+}
+
+/// Delete the caller's asset of `kind` and take it off their profile.
+async fn delete_profile_image(req: &HttpRequest, pool: &SqlitePool, storage: &FileStore, broadcaster: &Broadcaster, kind: Kind) -> HttpResponse {
+    let claims = match extract_claims(req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let Some(field) = kind.profile_field() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let removed = match remove_asset(pool, storage, kind, &claims.sub).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No uploaded image" })),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // A URL set by other means since (a Discord login) is left alone.
+    let url: Option<String> = sqlx::query_scalar(&format!("SELECT {field} FROM users WHERE id = ?"))
+        .bind(&claims.sub)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .flatten();
+    if is_asset_url(url.as_deref(), &removed) {
+        let _ = sqlx::query(&format!("UPDATE users SET {field} = NULL WHERE id = ?"))
+            .bind(&claims.sub)
+            .execute(pool)
+            .await;
+        crate::quickswitch::invalidate_from(req);
+        crate::auth::broadcast_profile(pool, broadcaster, &claims.sub).await;
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// PUT /api/users/@me/avatar — Upload an avatar, multipart PNG or JPEG (authenticated)
+pub async fn upload_avatar(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    upload_profile_image(&req, pool.get_ref(), storage.get_ref(), broadcaster.get_ref(), &mut payload, Kind::Avatar).await
+}
+
+/// DELETE /api/users/@me/avatar — Remove the uploaded avatar (authenticated)
+pub async fn delete_avatar(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    delete_profile_image(&req, pool.get_ref(), storage.get_ref(), broadcaster.get_ref(), Kind::Avatar).await
+}
+
+/// PUT /api/users/@me/banner — Upload a profile banner, multipart PNG or JPEG (authenticated)
+pub async fn upload_user_banner(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    upload_profile_image(&req, pool.get_ref(), storage.get_ref(), broadcaster.get_ref(), &mut payload, Kind::UserBanner).await
+}
+
+/// DELETE /api/users/@me/banner — Remove the uploaded profile banner (authenticated)
+pub async fn delete_user_banner(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    delete_profile_image(&req, pool.get_ref(), storage.get_ref(), broadcaster.get_ref(), Kind::UserBanner).await
+}
+
+fn broadcast_banner(broadcaster: &Broadcaster, banner: Option<&ImageAsset>) {
+    let event = serde_json::json!({ "type": "server_banner_updated", "banner": banner });
+    let _ = broadcaster.send(event.to_string());
+}
+
+async fn audit_banner(pool: &SqlitePool, req: &HttpRequest, claims: &Claims, old: Option<&str>, new: Option<&str>) {
+    let changes = crate::audit_log::diff(&[("banner", serde_json::json!(old), serde_json::json!(new))]);
+    let reason = crate::audit_log::reason(req);
+    crate::audit_log::record(pool, Some(claims), crate::audit_log::Action::ServerBannerUpdated, "banner", changes, reason.as_deref()).await;
+}
+
+/// GET /api/server/banner — The server banner, if any (authenticated)
+pub async fn get_banner(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let banner = fetch_asset(pool.get_ref(), Kind::ServerBanner, SERVER_OWNER).await;
+    HttpResponse::Ok().json(serde_json::json!({ "banner": banner }))
+}
+
+/// PUT /api/server/banner — Upload the server banner, multipart PNG or JPEG (MANAGE_SERVER)
+pub async fn upload_banner(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let (asset, previous) = match replace_asset(pool.get_ref(), storage.get_ref(), Kind::ServerBanner, SERVER_OWNER, &claims.sub, &mut payload).await {
+        Ok(replaced) => replaced,
+        Err(response) => return response,
+    };
+    audit_banner(pool.get_ref(), &req, &claims, previous.as_deref(), Some(&asset.id)).await;
+    broadcast_banner(broadcaster.get_ref(), Some(&asset));
+    HttpResponse::Ok().json(asset)
+}
+
+/// DELETE /api/server/banner — Remove the server banner (MANAGE_SERVER)
+pub async fn delete_banner(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    match remove_asset(pool.get_ref(), storage.get_ref(), Kind::ServerBanner, SERVER_OWNER).await {
+        Ok(Some(removed)) => {
+            audit_banner(pool.get_ref(), &req, &claims, Some(&removed), None).await;
+            broadcast_banner(broadcaster.get_ref(), None);
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "No server banner" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/images/{id}/{width}.png — One size of an avatar or banner (public)
+pub async fn serve_image(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (asset_id, file) = path.into_inner();
+    let Some(width) = file.strip_suffix(".png").and_then(|w| w.parse::<i64>().ok()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let hash: Option<String> = sqlx::query_scalar("SELECT hash FROM image_variants WHERE asset_id = ? AND width = ?")
+        .bind(&asset_id)
+        .bind(width)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let Some(hash) = hash else {
+        return HttpResponse::NotFound().finish();
+    };
+    match storage.get(&hash).await {
+        Some(png) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header(("Cache-Control", "public, max-age=31536000, immutable"))
+            .insert_header(("X-Content-Type-Options", "nosniff"))
+            .body(png),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
@@ -0,0 +1,341 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Background job queues: metrics, logs, pause/resume
+// ═══════════════════════════════════════════════════════
+//
+// Each periodic background job works through a queue of its own: due
+// announcements, tombstones past retention, linked Discord tokens to
+// re-check, pending session `last_seen` updates, unused uploads, stale QR
+// login sessions, messages whose links need a preview, expired polls,
+// voice room occupancy samples, users due an email digest, personal data
+// exports to build, Discord servers to import, room transcripts to build,
+// messages past retention, voice recordings to put together.
+// Every run goes through `run`, which records in the shared `JobRegistry`,
+// per queue:
+//   - depth     items still waiting after the run, and when the oldest one
+//               became due (measured on paused queues too)
+//   - outcomes  runs, failures, skipped runs and the failure rate over the
+//               last `FAILURE_WINDOW` runs
+//   - last run  time, duration, items processed, error
+//
+// Each run also prints one JSON log line (`"log": "job"`). Runs started by
+// a request rather than the timer (an announcement published on creation)
+// carry its `request_id`. Every request gets one: the client's
+// `X-Request-Id` when it is sane, otherwise a fresh UUID. It is echoed in
+// the response headers.
+//
+// During an incident an admin can pause a queue: its timer keeps ticking
+// but runs are skipped until it is resumed. Pauses live in memory, so a
+// restart resumes every queue.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::auth::extract_claims;
+
+pub const ANNOUNCEMENTS: &str = "announcements";
+pub const MESSAGE_TOMBSTONES: &str = "message_tombstones";
+pub const DISCORD_TOKENS: &str = "discord_tokens";
+pub const SESSION_LAST_SEEN: &str = "session_last_seen";
+pub const ATTACHMENTS: &str = "attachments";
+pub const QR_SESSIONS: &str = "qr_sessions";
+pub const LINK_PREVIEWS: &str = "link_previews";
+pub const POLLS: &str = "polls";
+pub const VOICE_OCCUPANCY: &str = "voice_occupancy";
+pub const LOCKDOWNS: &str = "lockdowns";
+pub const PIN_CHECKS: &str = "pin_checks";
+pub const BANS: &str = "bans";
+pub const DISCORD_BRIDGE: &str = "discord_bridge";
+pub const MATRIX: &str = "matrix";
+pub const EMAIL_DIGESTS: &str = "email_digests";
+pub const DATA_EXPORTS: &str = "data_exports";
+pub const DISCORD_IMPORTS: &str = "discord_imports";
+pub const ROOM_ARCHIVES: &str = "room_archives";
+pub const MESSAGE_RETENTION: &str = "message_retention";
+pub const VOICE_RECORDINGS: &str = "voice_recordings";
+
+/// The failure rate is computed over this many latest runs.
+const FAILURE_WINDOW: usize = 50;
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+// ── Request ids ─────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+fn sane_request_id(raw: &str) -> bool {
+    !raw.is_empty()
+        && raw.len() <= MAX_REQUEST_ID_LEN
+        && raw.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Wrapped around the whole app with `middleware::from_fn`.
+pub async fn request_id<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| sane_request_id(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut res = next.call(req).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(res)
+}
+
+pub fn request_id_of(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<RequestId>().map(|id| id.0.clone())
+}
+
+// ── Registry ────────────────────────────────────────────
+
+/// What is left in a queue after a run.
+#[derive(Debug, Clone, Default)]
+pub struct Backlog {
+    pub depth: i64,
+    /// When the oldest waiting item became due (RFC 3339).
+    pub oldest_due_at: Option<String>,
+}
+
+impl Backlog {
+    /// From a row with `depth` and `oldest_due_at` columns.
+    pub(crate) fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        use sqlx::Row;
+        Self {
+            depth: row.try_get("depth").unwrap_or(0),
+            oldest_due_at: row.try_get("oldest_due_at").unwrap_or(None),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct QueueState {
+    paused: bool,
+    paused_by: Option<String>,
+    runs: u64,
+    failures: u64,
+    skipped: u64,
+    /// Latest outcomes, `true` for a failure.
+    recent: VecDeque<bool>,
+    last_run_at: Option<String>,
+    last_duration_ms: Option<u64>,
+    last_processed: Option<u64>,
+    last_error: Option<String>,
+    backlog: Backlog,
+}
+
+pub type JobRegistry = Arc<Mutex<BTreeMap<&'static str, QueueState>>>;
+
+pub fn create_job_registry() -> JobRegistry {
+    Arc::new(Mutex::new(BTreeMap::new()))
+}
+
+/// Make a queue show up before its first run.
+pub fn register(registry: &JobRegistry, queue: &'static str) {
+    registry.lock().unwrap().entry(queue).or_default();
+}
+
+fn log_line(queue: &str, status: &str, request_id: Option<&str>, details: serde_json::Value) {
+    let mut line = serde_json::json!({
+        "log": "job",
+        "at": Utc::now().to_rfc3339(),
+        "queue": queue,
+        "status": status,
+        "request_id": request_id,
+    });
+    if let (Some(line), serde_json::Value::Object(details)) = (line.as_object_mut(), details) {
+        line.extend(details);
+    }
+    println!("{line}");
+}
+
+/// Run one pass of `queue`: `work` processes it and returns how many items
+/// it handled, then `backlog` measures what is left. A paused queue only
+/// gets measured.
+pub async fn run(
+    registry: &JobRegistry,
+    queue: &'static str,
+    request_id: Option<&str>,
+    work: impl Future<Output = Result<u64, String>>,
+    backlog: impl Future<Output = Backlog>,
+) {
+    let paused = registry.lock().unwrap().entry(queue).or_default().paused;
+    if paused {
+        let left = backlog.await;
+        let mut queues = registry.lock().unwrap();
+        let state = queues.entry(queue).or_default();
+        state.skipped += 1;
+        state.backlog = left;
+        drop(queues);
+        log_line(queue, "skipped", request_id, serde_json::json!({ "reason": "paused" }));
+        return;
+    }
+
+    let started_at = Utc::now().to_rfc3339();
+    let timer = Instant::now();
+    let outcome = work.await;
+    let duration_ms = timer.elapsed().as_millis() as u64;
+    let left = backlog.await;
+
+    let details = serde_json::json!({
+        "duration_ms": duration_ms,
+        "processed": outcome.as_ref().ok(),
+        "error": outcome.as_ref().err(),
+        "depth": left.depth,
+    });
+    let failed = outcome.is_err();
+
+    let mut queues = registry.lock().unwrap();
+    let state = queues.entry(queue).or_default();
+    state.runs += 1;
+    if failed {
+        state.failures += 1;
+    }
+    state.recent.push_back(failed);
+    if state.recent.len() > FAILURE_WINDOW {
+        state.recent.pop_front();
+    }
+    state.last_run_at = Some(started_at);
+    state.last_duration_ms = Some(duration_ms);
+    state.last_processed = outcome.as_ref().ok().copied();
+    state.last_error = outcome.err();
+    state.backlog = left;
+    drop(queues);
+
+    log_line(queue, if failed { "failed" } else { "ok" }, request_id, details);
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueSnapshot {
+    pub name: &'static str,
+    pub paused: bool,
+    pub paused_by: Option<String>,
+    pub depth: i64,
+    /// Seconds since the oldest waiting item became due.
+    pub oldest_age_secs: Option<i64>,
+    pub runs: u64,
+    pub failures: u64,
+    pub skipped: u64,
+    /// Over the last `FAILURE_WINDOW` runs, 0 to 1.
+    pub failure_rate: f64,
+    pub last_run_at: Option<String>,
+    pub last_duration_ms: Option<u64>,
+    pub last_processed: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+fn snapshot(name: &'static str, state: &QueueState) -> QueueSnapshot {
+    let oldest_age_secs = state
+        .backlog
+        .oldest_due_at
+        .as_deref()
+        .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+        .map(|at| (Utc::now() - at.with_timezone(&Utc)).num_seconds().max(0));
+    let failure_rate = if state.recent.is_empty() {
+        0.0
+    } else {
+        state.recent.iter().filter(|failed| **failed).count() as f64 / state.recent.len() as f64
+    };
+    QueueSnapshot {
+        name,
+        paused: state.paused,
+        paused_by: state.paused_by.clone(),
+        depth: state.backlog.depth,
+        oldest_age_secs,
+        runs: state.runs,
+        failures: state.failures,
+        skipped: state.skipped,
+        failure_rate,
+        last_run_at: state.last_run_at.clone(),
+        last_duration_ms: state.last_duration_ms,
+        last_processed: state.last_processed,
+        last_error: state.last_error.clone(),
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/server/jobs — Metrics of every background queue (MANAGE_SERVER)
+pub async fn list_queues(req: HttpRequest, pool: web::Data<SqlitePool>, registry: web::Data<JobRegistry>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let queues = registry.lock().unwrap();
+    let list: Vec<QueueSnapshot> = queues.iter().map(|(name, state)| snapshot(name, state)).collect();
+    HttpResponse::Ok().json(list)
+}
+
+async fn set_paused(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    registry: web::Data<JobRegistry>,
+    name: String,
+    paused: bool,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let mut queues = registry.lock().unwrap();
+    let Some((&queue, state)) = queues.iter_mut().find(|(queue, _)| **queue == name) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown queue" }));
+    };
+    state.paused = paused;
+    state.paused_by = paused.then(|| claims.username.clone());
+    let response = snapshot(queue, state);
+    drop(queues);
+
+    log_line(
+        queue,
+        if paused { "paused" } else { "resumed" },
+        request_id_of(&req).as_deref(),
+        serde_json::json!({ "by": claims.username }),
+    );
+    HttpResponse::Ok().json(response)
+}
+
+/// POST /api/server/jobs/{name}/pause — Skip a queue's runs until resumed (MANAGE_SERVER)
+pub async fn pause_queue(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    registry: web::Data<JobRegistry>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    set_paused(req, pool, registry, path.into_inner(), true).await
+}
+
+/// POST /api/server/jobs/{name}/resume — Resume a paused queue (MANAGE_SERVER)
+pub async fn resume_queue(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    registry: web::Data<JobRegistry>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    set_paused(req, pool, registry, path.into_inner(), false).await
+}
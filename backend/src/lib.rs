@@ -1,41 +1,519 @@
+pub mod alt_text;
+pub mod announcements;
+pub mod audit;
+pub mod audit_log;
+pub mod backfill;
+pub mod bans;
+pub mod bots;
+pub mod bridge;
+pub mod bus;
+pub mod bulk_delete;
 pub mod auth;
+pub mod archive;
+pub mod automod;
+pub mod categories;
+pub mod concurrency;
+pub mod config;
+pub mod data_exports;
 pub mod db;
 pub mod discord_gateway;
+pub mod discord_import;
+pub mod discord_link;
+pub mod discord_oauth;
+pub mod discord_preflight;
+pub mod doctor;
+pub mod email;
+pub mod emoji_usage;
+pub mod emojis;
+pub mod export;
+pub mod files;
+pub mod forum;
+pub mod gateway;
+pub mod guests;
+pub mod images;
+pub mod jobs;
+pub mod lockdown;
+pub mod matrix;
+pub mod member_profiles;
+pub mod mentions;
 pub mod messages;
+pub mod notification_settings;
+pub mod notifications;
+pub mod outbound;
+pub mod pagination;
+pub mod password_auth;
+pub mod permissions;
+pub mod pin_checks;
+pub mod polls;
+pub mod presence;
+pub mod quickswitch;
+pub mod quiet_hours;
+pub mod ratelimit;
+pub mod read_states;
+pub mod recovery;
+pub mod relationships;
 pub mod remote_auth;
+pub mod retention;
+pub mod roles;
+pub mod room_archives;
+pub mod rules;
 pub mod rooms;
+pub mod rtc;
+pub mod scanning;
+pub mod server_config;
+pub mod sessions;
+pub mod slowmode;
+pub mod soundboard;
+pub mod sudo;
+pub mod telemetry;
+pub mod test_support;
+pub mod timeouts;
+pub mod totp;
+pub mod unfurl;
 pub mod uploads;
+pub mod verification;
+pub mod voice_activity;
+pub mod voice_messages;
+pub mod voice_moderation;
+pub mod voice_priority;
+pub mod voice_recordings;
+pub mod voice_settings;
+pub mod voice_sync;
+pub mod webauthn;
+pub mod webhooks;
 pub mod ws;
 pub mod crypto;
 
 use actix_cors::Cors;
-use actix_files::Files;
 use actix_web::{web, App, HttpResponse, HttpServer};
+use sqlx::SqlitePool;
 
 /// Run the backend HTTP server. This function blocks until the server shuts down.
 /// It creates its own Actix/Tokio runtime via `#[actix_web::main]`.
 pub fn run_server() {
     let rt = actix_web::rt::System::new();
     rt.block_on(async {
-        start_server().await.expect("Backend server failed");
+        if let Err(e) = start_server().await {
+            eprintln!("❌ {e}");
+            std::process::exit(1);
+        }
     });
 }
 
+/// Shared state handed to the handlers as `web::Data`, one entry per type.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: SqlitePool,
+    pub broadcaster: ws::Broadcaster,
+    pub presence: presence::PresenceTracker,
+    pub access_cache: ws::AccessCache,
+    pub ws_tickets: ws::WsTickets,
+    pub gateway_sessions: gateway::GatewaySessions,
+    pub qr_sessions: remote_auth::QrAuthSessions,
+    pub password_mfa_sessions: password_auth::PasswordMfaSessions,
+    pub oauth_states: discord_oauth::OAuthStates,
+    pub discord_gateways: discord_gateway::DiscordGateways,
+    pub discord_preflight_cache: discord_preflight::DiscordPreflightCache,
+    pub session_store: sessions::SessionStore,
+    pub bot_tokens: bots::BotTokens,
+    pub passkey_ceremonies: webauthn::PasskeyCeremonies,
+    pub two_factor_challenges: totp::TwoFactorChallenges,
+    pub quickswitch_index: quickswitch::QuickSwitchIndex,
+    pub rate_limiter: ratelimit::RateLimiter,
+    pub backfill_gate: backfill::BackfillGate,
+    pub job_registry: jobs::JobRegistry,
+    pub file_storage: files::FileStorage,
+    pub voice_occupancy: voice_activity::VoiceOccupancy,
+    pub voice_sync: voice_sync::VoiceSync,
+    pub settings: config::Settings,
+}
+
+impl AppState {
+    /// Fresh in-memory state around `pool`. Starts no background job.
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            broadcaster: ws::create_broadcaster(),
+            presence: presence::create_presence_tracker(),
+            access_cache: ws::create_access_cache(),
+            ws_tickets: ws::create_ws_tickets(),
+            gateway_sessions: gateway::create_gateway_sessions(),
+            qr_sessions: remote_auth::create_qr_sessions(),
+            password_mfa_sessions: password_auth::create_password_mfa_sessions(),
+            oauth_states: discord_oauth::create_oauth_states(),
+            discord_gateways: discord_gateway::create_discord_gateways(),
+            discord_preflight_cache: discord_preflight::create_discord_preflight_cache(),
+            session_store: sessions::create_session_store(),
+            bot_tokens: bots::create_bot_tokens(),
+            passkey_ceremonies: webauthn::create_passkey_ceremonies(),
+            two_factor_challenges: totp::create_two_factor_challenges(),
+            quickswitch_index: quickswitch::create_quickswitch_index(),
+            rate_limiter: ratelimit::create_rate_limiter(),
+            backfill_gate: backfill::create_backfill_gate(),
+            job_registry: jobs::create_job_registry(),
+            file_storage: files::create_file_storage(),
+            voice_occupancy: voice_activity::create_voice_occupancy(),
+            voice_sync: voice_sync::create_voice_sync(),
+            settings: config::current().clone(),
+        }
+    }
+
+    /// Register every piece of state as app data.
+    pub fn register(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::new(self.pool.clone()))
+            .app_data(web::Data::new(self.broadcaster.clone()))
+            .app_data(web::Data::new(self.presence.clone()))
+            .app_data(web::Data::new(self.access_cache.clone()))
+            .app_data(web::Data::new(self.ws_tickets.clone()))
+            .app_data(web::Data::new(self.gateway_sessions.clone()))
+            .app_data(web::Data::new(self.qr_sessions.clone()))
+            .app_data(web::Data::new(self.password_mfa_sessions.clone()))
+            .app_data(web::Data::new(self.oauth_states.clone()))
+            .app_data(web::Data::new(self.discord_gateways.clone()))
+            .app_data(web::Data::new(self.discord_preflight_cache.clone()))
+            .app_data(web::Data::new(self.session_store.clone()))
+            .app_data(web::Data::new(self.bot_tokens.clone()))
+            .app_data(web::Data::new(self.passkey_ceremonies.clone()))
+            .app_data(web::Data::new(self.two_factor_challenges.clone()))
+            .app_data(web::Data::new(self.quickswitch_index.clone()))
+            .app_data(web::Data::new(self.rate_limiter.clone()))
+            .app_data(web::Data::new(self.backfill_gate.clone()))
+            .app_data(web::Data::new(self.job_registry.clone()))
+            .app_data(web::Data::new(self.file_storage.clone()))
+            .app_data(web::Data::new(self.voice_occupancy.clone()))
+            .app_data(web::Data::new(self.voice_sync.clone()))
+            .app_data(web::Data::new(self.settings.clone()));
+    }
+}
+
+/// Every HTTP route and the WebSocket endpoint.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/api/health", web::get().to(|| async {
+        HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+    }))
+        // Auth
+        .route("/api/register", web::post().to(auth::register))
+        .route("/api/join/challenge", web::get().to(verification::get_challenge))
+        .route("/api/login", web::post().to(auth::login))
+        .route("/api/auth/refresh", web::post().to(sessions::refresh))
+        .route("/api/auth/ws-ticket", web::post().to(ws::create_ws_ticket))
+        .route("/api/auth/sudo", web::get().to(sudo::get_status))
+        .route("/api/auth/sudo", web::post().to(sudo::enter))
+        .route("/api/auth/email/verify", web::get().to(email::verify))
+        .route("/api/auth/recovery/start", web::post().to(recovery::start))
+        .route("/api/auth/recovery/complete", web::post().to(recovery::complete))
+        .route("/api/auth/2fa", web::get().to(totp::get_status))
+        .route("/api/auth/2fa/setup", web::post().to(totp::setup))
+        .route("/api/auth/2fa/enable", web::post().to(totp::enable))
+        .route("/api/auth/2fa/disable", web::post().to(totp::disable))
+        .route("/api/auth/2fa/backup-codes", web::post().to(totp::regenerate))
+        .route("/api/auth/2fa/login", web::post().to(totp::login))
+        .route("/api/auth/passkey/register/start", web::post().to(webauthn::register_start))
+        .route("/api/auth/passkey/register/finish", web::post().to(webauthn::register_finish))
+        .route("/api/auth/passkey/login/start", web::post().to(webauthn::login_start))
+        .route("/api/auth/passkey/login/finish", web::post().to(webauthn::login_finish))
+        .route("/api/auth/discord/token", web::post().to(auth::login_discord_token))
+        .route("/api/auth/discord/config", web::get().to(discord_oauth::get_config))
+        .route("/api/auth/discord/oauth/authorize", web::post().to(discord_oauth::start_authorize))
+        .route("/api/auth/discord/oauth/callback", web::post().to(discord_oauth::oauth_callback))
+        .route("/api/auth/discord/qr/start", web::post().to(remote_auth::start_qr_session))
+        .route("/api/auth/discord/qr/status", web::get().to(remote_auth::get_qr_status))
+        .route("/api/auth/discord/qr/cancel", web::post().to(remote_auth::cancel_qr_session))
+        .route("/api/auth/discord/password/login", web::post().to(password_auth::password_login))
+        .route("/api/auth/discord/password/mfa", web::post().to(password_auth::submit_mfa_code))
+        .route("/api/auth/discord/password/mfa/sms/send", web::post().to(password_auth::send_mfa_sms))
+        .route("/api/users/me", web::get().to(auth::get_me))
+        .route("/api/users/me", web::patch().to(auth::update_profile))
+        .route("/api/users/@me/member-profile", web::patch().to(member_profiles::update_own_profile))
+        .route("/api/discord/me", web::get().to(auth::get_discord_me))
+        .route("/api/discord/proxy", web::post().to(auth::discord_proxy))
+        .route("/api/discord/link", web::delete().to(discord_link::unlink))
+        .route("/api/discord/link/status", web::get().to(discord_link::link_status))
+        .route("/api/voice/rtc-config", web::get().to(rtc::rtc_config))
+        .route("/api/server/voice/heatmap", web::get().to(voice_activity::heatmap))
+        .route("/api/rooms/{id}/voice/priority", web::get().to(voice_priority::get_priority))
+        .route("/api/rooms/{id}/voice/priority/{user_id}", web::put().to(voice_priority::set_priority))
+        .route("/api/rooms/{id}/voice/members", web::get().to(voice_moderation::list_member_states))
+        .route("/api/rooms/{id}/voice/members/{user_id}", web::put().to(voice_moderation::set_member_state))
+        .route("/api/rooms/{id}/voice/recording", web::get().to(voice_recordings::get_recording))
+        .route("/api/rooms/{id}/voice/recording", web::post().to(voice_recordings::start_recording))
+        .route("/api/rooms/{id}/voice/recording", web::delete().to(voice_recordings::stop_recording))
+        .route("/api/rooms/{id}/voice/recording/consent", web::put().to(voice_recordings::set_consent))
+        .route("/api/rooms/{id}/voice/recording/tracks/{seq}", web::put().to(voice_recordings::upload_segment))
+        .route("/api/rooms/{id}/voice/recordings", web::get().to(voice_recordings::list_recordings))
+        .route("/api/rooms/{id}/voice/recordings/{recording_id}", web::delete().to(voice_recordings::delete_recording))
+        .route("/api/rooms/{id}/voice/recordings/{recording_id}/download", web::get().to(voice_recordings::download_recording))
+        .route("/api/rooms/{id}/voice/soundboard/{sound_id}", web::post().to(soundboard::play_in_room))
+        .route("/api/rooms/{id}/voice/activity", web::get().to(voice_sync::get_activity))
+        .route("/api/discord/voice/join", web::post().to(discord_gateway::voice_join))
+        .route("/api/discord/voice/leave", web::post().to(discord_gateway::voice_leave))
+        .route("/api/discord/voice/soundboard/{sound_id}", web::post().to(soundboard::play_in_discord))
+        .route("/api/discord/voice/preflight", web::post().to(discord_preflight::voice_preflight))
+        .route(
+            "/api/discord/voice/participants",
+            web::get().to(discord_gateway::voice_participants),
+        )
+        .route("/api/users/@me/email", web::get().to(email::get_email))
+        .route("/api/users/@me/email", web::put().to(email::set_email))
+        .route("/api/users/@me/email/resend", web::post().to(email::resend))
+        .route("/api/users/@me/email/notifications", web::get().to(notifications::get_preferences))
+        .route("/api/users/@me/email/notifications", web::patch().to(notifications::update_preferences))
+        .route("/api/users/@me/notification-settings", web::get().to(notification_settings::get_settings))
+        .route("/api/users/@me/notification-settings", web::patch().to(notification_settings::update_settings))
+        .route("/api/users/@me/quiet-hours", web::get().to(quiet_hours::get_quiet_hours))
+        .route("/api/users/@me/quiet-hours", web::put().to(quiet_hours::set_quiet_hours))
+        .route("/api/users/@me/quiet-hours", web::delete().to(quiet_hours::clear_quiet_hours))
+        .route("/api/users/@me/voice-settings", web::get().to(voice_settings::get_voice_settings))
+        .route("/api/users/@me/voice-settings", web::patch().to(voice_settings::update_voice_settings))
+        .route("/api/users/@me/voice-settings/volumes/{user_id}", web::put().to(voice_settings::set_user_volume))
+        .route("/api/users/@me/export", web::post().to(data_exports::request_export))
+        .route("/api/users/@me/exports", web::get().to(data_exports::list_exports))
+        .route("/api/users/@me/exports/{id}", web::get().to(data_exports::get_export))
+        .route("/api/users/@me/exports/{id}/download", web::get().to(data_exports::download_export))
+        .route("/api/users/@me/recovery", web::get().to(recovery::get_status))
+        .route("/api/users/@me/recovery/codes", web::post().to(recovery::regenerate_codes))
+        .route("/api/users/@me/recovery/{id}", web::delete().to(recovery::cancel))
+        .route("/api/users/@me/recovery/{id}/approve", web::post().to(recovery::approve))
+        .route("/api/users/@me/security-log", web::get().to(audit::security_log))
+        .route("/api/users/@me/sessions", web::get().to(sessions::list_sessions))
+        .route("/api/users/@me/sessions", web::delete().to(sessions::revoke_other_sessions))
+        .route("/api/users/@me/sessions/{id}", web::delete().to(sessions::revoke_session))
+        .route("/api/users/@me/passkeys", web::get().to(webauthn::list_passkeys))
+        .route("/api/users/@me/passkeys/{id}", web::delete().to(webauthn::delete_passkey))
+        .route("/api/users/@me/mentions", web::get().to(mentions::recent_mentions))
+        .route("/api/users/@me/avatar", web::put().to(images::upload_avatar))
+        .route("/api/users/@me/avatar", web::delete().to(images::delete_avatar))
+        .route("/api/users/@me/banner", web::put().to(images::upload_user_banner))
+        .route("/api/users/@me/banner", web::delete().to(images::delete_user_banner))
+        .route("/api/users/@me/presence", web::get().to(presence::get_own_presence))
+        .route("/api/users/@me/presence", web::put().to(presence::set_own_presence))
+        .route("/api/users/@me/relationships", web::get().to(relationships::list_relationships))
+        .route("/api/users/@me/friends", web::post().to(relationships::send_friend_request))
+        .route("/api/users/@me/friends/{id}", web::put().to(relationships::put_friend))
+        .route("/api/users/@me/friends/{id}", web::delete().to(relationships::remove_friend))
+        .route("/api/users/@me/blocks", web::get().to(relationships::list_blocks))
+        .route("/api/users/@me/blocks/{id}", web::put().to(relationships::block_user))
+        .route("/api/users/@me/blocks/{id}", web::delete().to(relationships::unblock_user))
+        .route("/api/users/{id}", web::delete().to(auth::delete_user))
+        .route("/api/users/{id}/role", web::patch().to(auth::update_user_role))
+        .route("/api/users/{id}/member-profile", web::get().to(member_profiles::get_member_profile))
+        .route("/api/users/{id}/member-profile", web::patch().to(member_profiles::update_member_profile))
+        .route("/api/users/{id}/2fa", web::patch().to(totp::set_requirement))
+        .route("/api/users/{id}/2fa", web::delete().to(totp::reset))
+        .route("/api/users/{id}/kick", web::post().to(bans::kick))
+        .route("/api/users/{id}/ban", web::post().to(bans::ban))
+        .route("/api/users/{id}/ban", web::delete().to(bans::unban))
+        .route("/api/users/{id}/timeout", web::post().to(timeouts::give))
+        .route("/api/users/{id}/timeout", web::delete().to(timeouts::lift))
+        .route("/api/bans/appeal", web::post().to(bans::appeal))
+        .route("/api/server/bans", web::get().to(bans::list))
+        .route("/api/server/bans/{id}/appeal", web::post().to(bans::review_appeal))
+        .route("/api/server/roles", web::get().to(auth::list_server_roles))
+        .route("/api/server/roles", web::post().to(auth::create_server_role))
+        .route("/api/server/roles/{name}", web::patch().to(auth::update_server_role))
+        .route("/api/server/roles/{name}", web::delete().to(auth::delete_server_role))
+        .route("/api/server/roles/{name}/icon", web::put().to(roles::upload_icon))
+        .route("/api/server/roles/{name}/icon", web::delete().to(roles::delete_icon))
+        .route("/api/server/emojis/{name}", web::post().to(emojis::create_emoji))
+        .route("/api/server/emojis/{id}", web::delete().to(emojis::delete_emoji))
+        .route("/api/emojis", web::get().to(emojis::list_emojis))
+        .route("/api/emojis/top", web::get().to(emoji_usage::top_emojis))
+        .route("/api/server/soundboard/{name}", web::post().to(soundboard::create_sound))
+        .route("/api/server/soundboard/{id}", web::delete().to(soundboard::delete_sound))
+        .route("/api/soundboard", web::get().to(soundboard::list_sounds))
+        .route("/api/soundboard/{id}/audio", web::get().to(soundboard::sound_audio))
+        .route("/api/server/alt-text/missing", web::get().to(alt_text::list_missing))
+        .route("/api/server/alt-text", web::post().to(alt_text::backfill))
+        .route("/api/server/jobs", web::get().to(jobs::list_queues))
+        .route("/api/server/jobs/{name}/pause", web::post().to(jobs::pause_queue))
+        .route("/api/server/jobs/{name}/resume", web::post().to(jobs::resume_queue))
+        .route("/api/server/announcements", web::get().to(announcements::list_all))
+        .route("/api/server/announcements", web::post().to(announcements::create))
+        .route("/api/server/announcements/{id}", web::delete().to(announcements::cancel))
+        .route("/api/announcements", web::get().to(announcements::list_active))
+        .route("/api/announcements/{id}/dismiss", web::post().to(announcements::dismiss))
+        .route("/api/rules", web::get().to(rules::get_current))
+        .route("/api/rules/versions/{version}", web::get().to(rules::get_version))
+        .route("/api/rules/acknowledge", web::post().to(rules::acknowledge))
+        .route("/api/server/rules", web::put().to(rules::publish))
+        .route("/api/server/rules/stats", web::get().to(rules::stats))
+        .route("/api/server/banner", web::get().to(images::get_banner))
+        .route("/api/server/banner", web::put().to(images::upload_banner))
+        .route("/api/server/banner", web::delete().to(images::delete_banner))
+        .route("/api/server/quarantine", web::get().to(scanning::list_quarantine))
+        .route("/api/server/quarantine/{id}", web::delete().to(scanning::delete_upload))
+        .route("/api/server/quarantine/{id}/release", web::post().to(scanning::release_upload))
+        .route("/api/server/lockdown", web::get().to(lockdown::get_active))
+        .route("/api/server/lockdown", web::post().to(lockdown::start))
+        .route("/api/server/lockdown", web::delete().to(lockdown::lift))
+        .route("/api/server/lockdowns", web::get().to(lockdown::list_log))
+        .route("/api/server/verification", web::get().to(verification::get_settings))
+        .route("/api/server/verification", web::patch().to(verification::update_settings))
+        .route("/api/server/verification/surge", web::delete().to(verification::end_surge))
+        .route("/api/server/retention", web::get().to(retention::get_settings))
+        .route("/api/server/retention", web::patch().to(retention::update_settings))
+        .route("/api/server/audit-log", web::get().to(audit_log::list))
+        .route("/api/server/automod/rules", web::get().to(automod::list_rules))
+        .route("/api/server/automod/rules", web::post().to(automod::create_rule))
+        .route("/api/server/automod/rules/{id}", web::patch().to(automod::update_rule))
+        .route("/api/server/automod/rules/{id}", web::delete().to(automod::delete_rule))
+        .route("/api/server/automod/queue", web::get().to(automod::list_queue))
+        .route("/api/server/automod/queue/{id}", web::post().to(automod::review))
+        .route("/api/server/users", web::get().to(auth::list_server_users))
+        .route("/api/server/config/export", web::get().to(server_config::export_config))
+        .route("/api/server/config/plan", web::post().to(server_config::plan_config))
+        .route("/api/server/config/apply", web::post().to(server_config::apply_config))
+        .route("/api/server/diagnostics/doctor", web::get().to(doctor::doctor))
+        .route("/api/server/diagnostics/gateways", web::get().to(discord_gateway::list_gateway_sessions))
+        .route(
+            "/api/server/diagnostics/gateways/{user_id}/logs",
+            web::get().to(discord_gateway::get_gateway_logs),
+        )
+        .route(
+            "/api/server/diagnostics/gateways/{user_id}/log-level",
+            web::put().to(discord_gateway::set_gateway_log_level),
+        )
+        // Rooms
+        .route("/api/quickswitch", web::get().to(quickswitch::quickswitch))
+        .route("/api/rooms", web::get().to(rooms::list_rooms))
+        .route("/api/rooms", web::post().to(rooms::create_room))
+        .route("/api/rooms/{id}", web::patch().to(rooms::update_room))
+        .route("/api/rooms/{id}", web::delete().to(rooms::delete_room))
+        .route("/api/server/layout", web::get().to(categories::get_layout))
+        .route("/api/server/layout", web::put().to(categories::put_layout))
+        .route("/api/server/categories", web::post().to(categories::create_category))
+        .route("/api/server/categories/{id}", web::patch().to(categories::rename_category))
+        .route("/api/server/categories/{id}", web::delete().to(categories::delete_category))
+        .route("/api/rooms/{id}/members", web::get().to(presence::list_room_members))
+        .route("/api/rooms/{id}/metadata", web::get().to(rooms::get_room_metadata))
+        .route("/api/rooms/{id}/metadata", web::patch().to(rooms::update_room_metadata))
+        .route("/api/rooms/{id}/metadata/history", web::get().to(rooms::get_room_metadata_history))
+        .route("/api/rooms/{id}/posts", web::get().to(forum::list_posts))
+        .route("/api/rooms/{id}/posts", web::post().to(forum::create_post))
+        .route("/api/rooms/{id}/forum-tags", web::get().to(forum::get_tags))
+        .route("/api/rooms/{id}/forum-tags", web::put().to(forum::put_tags))
+        .route("/api/posts/{id}", web::get().to(forum::get_post))
+        .route("/api/posts/{id}", web::patch().to(forum::update_post))
+        .route("/api/posts/{id}/messages", web::get().to(forum::post_messages))
+        .route("/api/rooms/{id}/guest-links", web::get().to(guests::list_links))
+        .route("/api/rooms/{id}/guest-links", web::post().to(guests::create_link))
+        .route("/api/guest-links/{id}", web::delete().to(guests::revoke_link))
+        .route("/api/rooms/{id}/permissions", web::get().to(permissions::list_overwrites))
+        .route("/api/rooms/{id}/permissions/@me", web::get().to(permissions::get_own_permissions))
+        .route("/api/rooms/{id}/permissions/{target_type}/{target_id}", web::put().to(permissions::put_overwrite))
+        .route("/api/rooms/{id}/permissions/{target_type}/{target_id}", web::delete().to(permissions::delete_overwrite))
+        .route("/api/guest/redeem", web::post().to(guests::redeem))
+        .route("/api/guest/me", web::get().to(guests::get_access))
+        .route("/api/guest/convert", web::post().to(guests::convert))
+        // Messages
+        .route("/api/messages/{id}", web::patch().to(messages::edit_message))
+        .route("/api/messages/{id}", web::delete().to(messages::delete_message))
+        .route("/api/messages/{id}/history", web::get().to(messages::message_history))
+        .route("/api/messages/{id}/reactions", web::post().to(messages::add_reaction))
+        .route("/api/messages/{id}/reactions", web::delete().to(messages::remove_reaction))
+        .route("/api/messages/search", web::get().to(messages::search_messages))
+        .route("/api/messages/{id}/pin", web::post().to(messages::pin_message))
+        .route("/api/messages/{id}/pin", web::delete().to(messages::unpin_message))
+        .route("/api/users/{id}/messages", web::delete().to(messages::delete_user_messages))
+        .route("/api/users/{id}/messages/bulk-delete", web::post().to(bulk_delete::delete_member_recent))
+        .route("/api/rooms/{room_id}/messages/bulk-delete", web::post().to(bulk_delete::delete_in_room))
+        .route("/api/server/bots", web::get().to(bots::list_bots))
+        .route("/api/server/bots", web::post().to(bots::create_bot))
+        .route("/api/server/bots/{id}", web::patch().to(bots::update_bot))
+        .route("/api/server/bots/{id}", web::delete().to(bots::delete_bot))
+        .route("/api/server/bots/{id}/token", web::post().to(bots::reset_token))
+        .route("/api/rooms/{id}/webhooks", web::get().to(webhooks::list))
+        .route("/api/rooms/{id}/webhooks", web::post().to(webhooks::create))
+        .route("/api/webhooks/{id}", web::patch().to(webhooks::update))
+        .route("/api/webhooks/{id}", web::delete().to(webhooks::delete))
+        .route("/api/webhooks/{id}/{token}", web::get().to(webhooks::get_with_token))
+        .route("/api/webhooks/{id}/{token}", web::post().to(webhooks::execute))
+        .route("/api/rooms/{id}/discord-bridge", web::get().to(bridge::get_bridge))
+        .route("/api/rooms/{id}/discord-bridge", web::post().to(bridge::create_bridge))
+        .route("/api/rooms/{id}/discord-bridge", web::delete().to(bridge::delete_bridge))
+        .route("/api/server/discord-imports", web::get().to(discord_import::list_imports))
+        .route("/api/server/discord-imports", web::post().to(discord_import::create_import))
+        .route("/api/server/discord-imports/{id}", web::get().to(discord_import::get_import))
+        .route("/api/server/matrix/registration", web::get().to(matrix::registration))
+        .route("/api/matrix/media/{server_name}/{media_id}", web::get().to(matrix::proxy_media))
+        .route("/_matrix/app/v1/transactions/{txn_id}", web::put().to(matrix::push_transaction))
+        .route("/_matrix/app/v1/users/{user_id}", web::get().to(matrix::query_user))
+        .route("/_matrix/app/v1/rooms/{alias}", web::get().to(matrix::query_room_alias))
+        .route("/api/rooms/{room_id}/messages", web::get().to(messages::get_messages))
+        .route("/api/rooms/{room_id}/backfill", web::get().to(backfill::backfill))
+        .route("/api/rooms/{room_id}/pins", web::get().to(messages::get_pinned_messages))
+        .route("/api/rooms/{room_id}/export", web::get().to(export::export_room))
+        .route("/api/rooms/{room_id}/retention", web::get().to(retention::get_room))
+        .route("/api/rooms/{room_id}/retention", web::put().to(retention::update_room))
+        .route("/api/rooms/{room_id}/archives", web::get().to(room_archives::list_archives))
+        .route("/api/rooms/{room_id}/archives", web::post().to(room_archives::create_archive))
+        .route("/api/rooms/{room_id}/archives/{archive_id}", web::get().to(room_archives::get_archive))
+        .route("/api/rooms/{room_id}/archives/{archive_id}/download", web::get().to(room_archives::download_archive))
+        .route("/api/rooms/{room_id}/polls", web::post().to(polls::create_poll))
+        .route("/api/messages/{id}/poll/votes", web::put().to(polls::vote))
+        .route("/api/messages/{id}/poll/votes", web::delete().to(polls::retract_vote))
+        .route("/api/messages/{id}/poll/close", web::post().to(polls::close_poll))
+        .route("/api/rooms/{id}/ack", web::post().to(read_states::ack))
+        // Uploads
+        .route("/api/files", web::post().to(files::upload_file))
+        .route("/api/files/voice", web::post().to(voice_messages::upload_voice_message))
+        .route("/api/files/{id}", web::get().to(files::download_file))
+        .route("/api/files/{id}", web::patch().to(alt_text::set_attachment_alt_text))
+        .route("/api/alt-text/policy", web::get().to(alt_text::get_policy))
+        .route("/api/images/{id}/{file}", web::get().to(images::serve_image))
+        .route("/uploads/{path:.*}", web::get().to(uploads::serve_upload))
+        // WebSocket
+        .route("/ws", web::get().to(ws::ws_handler))
+        .route("/api/gateway", web::get().to(gateway::gateway_handler));
+}
+
 async fn start_server() -> std::io::Result<()> {
-    dotenvy::dotenv().ok();
+    let settings = config::load_for_startup().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let bind_addr = format!("{}:{}", settings.server.bind_address, settings.server.port);
+    let database = settings.database.clone();
+    config::install(settings);
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let bind_addr = format!("0.0.0.0:{}", port);
+    let state = AppState::new(db::init_db(&database).await);
+    let pool = state.pool.clone();
+    let job_registry = state.job_registry.clone();
+    sessions::load_revoked_sessions(&pool, &state.session_store).await;
+    bots::load_bot_tokens(&pool, &state.bot_tokens).await;
+    remote_auth::recover_interrupted_sessions(&pool).await;
+    sessions::spawn_last_seen_flusher(pool.clone(), state.session_store.clone(), job_registry.clone());
 
-    let pool = db::init_db().await;
-    let broadcaster = ws::create_broadcaster();
-    let online_users = ws::create_online_users();
-    let access_cache = ws::create_access_cache();
-    let qr_sessions = remote_auth::create_qr_sessions();
-    let discord_gateways = discord_gateway::create_discord_gateways();
+    discord_link::spawn_token_validator(pool.clone(), job_registry.clone());
+    quickswitch::spawn_index_invalidator(state.broadcaster.clone(), state.quickswitch_index.clone());
+    announcements::spawn_publisher(pool.clone(), state.broadcaster.clone(), job_registry.clone());
+    messages::spawn_tombstone_purger(pool.clone(), state.file_storage.clone(), job_registry.clone());
+    files::spawn_attachment_purger(pool.clone(), state.file_storage.clone(), job_registry.clone());
+    remote_auth::spawn_session_gc(pool.clone(), state.qr_sessions.clone(), job_registry.clone());
+    unfurl::spawn_unfurler(pool.clone(), state.broadcaster.clone(), job_registry.clone());
+    pin_checks::spawn_checker(pool.clone(), state.file_storage.clone(), state.broadcaster.clone(), job_registry.clone());
+    polls::spawn_poll_closer(pool.clone(), state.broadcaster.clone(), job_registry.clone());
+    lockdown::spawn_lifter(pool.clone(), state.broadcaster.clone(), job_registry.clone());
+    bans::spawn_unbanner(pool.clone(), job_registry.clone());
+    bridge::spawn_bridges(pool.clone(), state.broadcaster.clone(), state.access_cache.clone(), job_registry.clone());
+    matrix::spawn_sender(pool.clone(), state.broadcaster.clone(), job_registry.clone());
+    voice_activity::spawn_sampler(pool.clone(), state.voice_occupancy.clone(), job_registry.clone());
+    notifications::spawn_digest_sender(pool.clone(), job_registry.clone());
+    data_exports::spawn_export_worker(pool.clone(), state.file_storage.clone(), job_registry.clone());
+    discord_import::spawn_import_worker(pool.clone(), state.broadcaster.clone(), job_registry.clone());
+    room_archives::spawn_archive_worker(pool.clone(), state.file_storage.clone(), job_registry.clone());
+    retention::spawn_retention_pruner(pool.clone(), state.file_storage.clone(), state.broadcaster.clone(), job_registry.clone());
+    voice_recordings::spawn_recording_worker(pool.clone(), state.file_storage.clone(), state.broadcaster.clone(), job_registry.clone());
+    bus::spawn_bus(
+        state.broadcaster.clone(),
+        state.presence.clone(),
+        state.session_store.clone(),
+        state.access_cache.clone(),
+        state.bot_tokens.clone(),
+    );
+    telemetry::spawn_exporter();
 
-    // Ensure uploads directory exists
-    std::fs::create_dir_all("uploads").ok();
+    let (imported, failed) = uploads::import_legacy(&pool, &state.file_storage).await;
+    if imported + failed > 0 {
+        println!("📁 Imported {imported} files from uploads/ into file storage ({failed} failed)");
+    }
 
     println!("🚀 Backend running at http://{}", bind_addr);
 
@@ -49,6 +527,7 @@ async fn start_server() -> std::io::Result<()> {
             .allowed_origin("http://127.0.0.1:1430")
             .allow_any_method()
             .allow_any_header()
+            .expose_headers(vec![pagination::NEXT_CURSOR_HEADER])
             .max_age(3600);
 
         // Rate Limiting: 10 req/s with burst of 20
@@ -59,61 +538,14 @@ async fn start_server() -> std::io::Result<()> {
             .unwrap();
 
         App::new()
+            // Per-route buckets, inside CORS so 429s stay readable by the client
+            .wrap(actix_web::middleware::from_fn(ratelimit::rate_limit))
+            .wrap(actix_web::middleware::from_fn(telemetry::trace))
+            .wrap(actix_web::middleware::from_fn(jobs::request_id))
             .wrap(cors)
             .wrap(actix_governor::Governor::new(&governor_conf))
-            .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(broadcaster.clone()))
-            .app_data(web::Data::new(online_users.clone()))
-            .app_data(web::Data::new(access_cache.clone()))
-            .app_data(web::Data::new(qr_sessions.clone()))
-            .app_data(web::Data::new(discord_gateways.clone()))
-            .route("/api/health", web::get().to(|| async {
-                HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
-            }))
-            // Auth
-            .route("/api/register", web::post().to(auth::register))
-            .route("/api/login", web::post().to(auth::login))
-            .route("/api/auth/discord/token", web::post().to(auth::login_discord_token))
-            .route("/api/auth/discord/qr/start", web::post().to(remote_auth::start_qr_session))
-            .route("/api/auth/discord/qr/status", web::get().to(remote_auth::get_qr_status))
-            .route("/api/auth/discord/qr/cancel", web::post().to(remote_auth::cancel_qr_session))
-            .route("/api/users/me", web::get().to(auth::get_me))
-            .route("/api/users/me", web::patch().to(auth::update_profile))
-            .route("/api/discord/me", web::get().to(auth::get_discord_me))
-            .route("/api/discord/proxy", web::post().to(auth::discord_proxy))
-            .route("/api/discord/voice/join", web::post().to(discord_gateway::voice_join))
-            .route("/api/discord/voice/leave", web::post().to(discord_gateway::voice_leave))
-            .route(
-                "/api/discord/voice/participants",
-                web::get().to(discord_gateway::voice_participants),
-            )
-            .route("/api/users/{id}", web::delete().to(auth::delete_user))
-            .route("/api/users/{id}/role", web::patch().to(auth::update_user_role))
-            .route("/api/server/roles", web::get().to(auth::list_server_roles))
-            .route("/api/server/roles", web::post().to(auth::create_server_role))
-            .route("/api/server/roles/{name}", web::delete().to(auth::delete_server_role))
-            .route("/api/server/users", web::get().to(auth::list_server_users))
-            // Rooms
-            .route("/api/rooms", web::get().to(rooms::list_rooms))
-            .route("/api/rooms", web::post().to(rooms::create_room))
-            .route("/api/rooms/{id}", web::patch().to(rooms::update_room))
-            .route("/api/rooms/{id}", web::delete().to(rooms::delete_room))
-            // Messages
-            .route("/api/messages/{id}", web::delete().to(messages::delete_message))
-            .route("/api/messages/{id}/reactions", web::post().to(messages::add_reaction))
-            .route("/api/messages/{id}/reactions", web::delete().to(messages::remove_reaction))
-            .route("/api/messages/search", web::get().to(messages::search_messages))
-            .route("/api/messages/{id}/pin", web::post().to(messages::pin_message))
-            .route("/api/messages/{id}/pin", web::delete().to(messages::unpin_message))
-            .route("/api/users/{id}/messages", web::delete().to(messages::delete_user_messages))
-            .route("/api/rooms/{room_id}/messages", web::get().to(messages::get_messages))
-            .route("/api/rooms/{room_id}/pins", web::get().to(messages::get_pinned_messages))
-            // Uploads
-            .route("/api/upload", web::post().to(uploads::upload_image))
-            // Serve uploaded files - DISABLE directory listing if enabled by default, but actix-files doesn't by default
-            .service(Files::new("/uploads", "uploads"))
-            // WebSocket
-            .route("/ws", web::get().to(ws::ws_handler))
+            .configure(|cfg| state.register(cfg))
+            .configure(routes)
     })
     .bind(&bind_addr)?
     .run()
@@ -0,0 +1,528 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Lockdowns (the raid "panic button")
+// ═══════════════════════════════════════════════════════
+//
+// A moderator starts a lockdown in one call, for a set duration:
+//   - the chosen rooms (by default every text and forum room) get a strict
+//     slowmode, their previous one is kept in `lockdown_rooms`;
+//   - only trusted roles post there: the roles listed in the lockdown, and
+//     any role with MANAGE_MESSAGES or ADMINISTRATOR;
+//   - guest links stop working, unless `pause_guest_links` is false.
+//
+// It is lifted by `DELETE /api/server/lockdown` or, when `ends_at` passes,
+// by a background task every `LIFT_INTERVAL`. Lifting gives each room back
+// its previous slowmode, unless someone changed it during the lockdown. At
+// most one lockdown is active; past ones stay in `lockdowns` as the log of
+// who started and ended what, readable with `GET /api/server/lockdowns`
+// (and in the server audit log, see `audit_log`).
+//
+// Starting and lifting post a system message in every affected room
+// (`kind: lockdown_started`, `lockdown_ended`) and broadcast a
+// `lockdown_started` / `lockdown_ended` event. A message refused by a
+// lockdown gets a `lockdown` event back on the sending WebSocket
+// connection: `{ type, room_id, lockdown_ends_at }`. HTTP endpoints that
+// post (polls, forum posts) answer 403 with the same `lockdown_ends_at`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::jobs::{Backlog, JobRegistry};
+use crate::ws::Broadcaster;
+
+const LIFT_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_SLOWMODE_SECONDS: i64 = 120;
+const DEFAULT_DURATION_MINUTES: i64 = 30;
+const MAX_DURATION_MINUTES: i64 = 24 * 60;
+const MAX_REASON_CHARS: usize = 200;
+const DEFAULT_LOG_LIMIT: i64 = 20;
+const MAX_LOG_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Lockdown {
+    pub id: String,
+    pub reason: Option<String>,
+    pub room_ids: Vec<String>,
+    pub slowmode_seconds: i64,
+    pub trusted_roles: Vec<String>,
+    pub pause_guest_links: bool,
+    pub started_by_username: String,
+    pub started_at: String,
+    pub ends_at: String,
+    pub ended_at: Option<String>,
+    /// None when it ran out.
+    pub ended_by_username: Option<String>,
+}
+
+const COLUMNS: &str = "id, reason, slowmode_seconds, trusted_roles, pause_guest_links, started_by, started_by_username, \
+                       started_at, ends_at, ended_at, ended_by_username";
+
+impl Lockdown {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let trusted_roles: String = row.get("trusted_roles");
+        Self {
+            id: row.get("id"),
+            reason: row.get("reason"),
+            room_ids: Vec::new(),
+            slowmode_seconds: row.get("slowmode_seconds"),
+            trusted_roles: serde_json::from_str(&trusted_roles).unwrap_or_default(),
+            pause_guest_links: row.get::<i64, _>("pause_guest_links") != 0,
+            started_by_username: row.get("started_by_username"),
+            started_at: row.get("started_at"),
+            ends_at: row.get("ends_at"),
+            ended_at: row.get("ended_at"),
+            ended_by_username: row.get("ended_by_username"),
+        }
+    }
+}
+
+async fn load_rooms(pool: &SqlitePool, lockdown: &mut Lockdown) {
+    lockdown.room_ids = sqlx::query_scalar("SELECT room_id FROM lockdown_rooms WHERE lockdown_id = ? ORDER BY room_id")
+        .bind(&lockdown.id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+}
+
+/// The active lockdown, if any.
+pub(crate) async fn active(pool: &SqlitePool) -> Option<Lockdown> {
+    let row = sqlx::query(&format!("SELECT {COLUMNS} FROM lockdowns WHERE ended_at IS NULL"))
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+    let mut lockdown = Lockdown::from_row(&row);
+    load_rooms(pool, &mut lockdown).await;
+    Some(lockdown)
+}
+
+// ── Posting gate ────────────────────────────────────────
+
+/// A refused post: the room is locked down until `ends_at`.
+#[derive(Debug, Clone)]
+pub struct Locked {
+    pub ends_at: String,
+}
+
+impl Locked {
+    /// The structured 403 for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "This room is locked down, only trusted roles can post",
+            "lockdown_ends_at": self.ends_at,
+        }))
+    }
+
+    /// The `lockdown` event for the WebSocket connection that sent the message.
+    pub fn event(&self, room_id: &str) -> String {
+        serde_json::json!({
+            "type": "lockdown",
+            "room_id": room_id,
+            "lockdown_ends_at": self.ends_at,
+        })
+        .to_string()
+    }
+}
+
+/// Whether members with `role` may post in `room_id`, or until when the
+/// room is locked down for them.
+pub(crate) async fn check(pool: &SqlitePool, room_id: &str, role: &str) -> Result<(), Locked> {
+    let row = sqlx::query(
+        "SELECT l.ends_at, l.trusted_roles FROM lockdowns l JOIN lockdown_rooms r ON r.lockdown_id = l.id \
+         WHERE r.room_id = ? AND l.ended_at IS NULL AND l.ends_at > ?"
+    )
+    .bind(room_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+    let Some(row) = row else {
+        return Ok(());
+    };
+    if role != crate::guests::GUEST_ROLE {
+        let trusted_roles: Vec<String> = serde_json::from_str(&row.get::<String, _>("trusted_roles")).unwrap_or_default();
+        let permissions = crate::permissions::role_permissions(pool, role).await;
+        if trusted_roles.iter().any(|r| r == role)
+            || permissions & (crate::permissions::ADMINISTRATOR | crate::permissions::MANAGE_MESSAGES) != 0
+        {
+            return Ok(());
+        }
+    }
+    Err(Locked { ends_at: row.get("ends_at") })
+}
+
+/// Whether an active lockdown has paused guest links.
+pub(crate) async fn guest_links_paused(pool: &SqlitePool) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM lockdowns WHERE ended_at IS NULL AND pause_guest_links = 1 AND ends_at > ?)")
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false)
+}
+
+// ── Lifting ─────────────────────────────────────────────
+
+/// End lockdown `id`: give the rooms their slowmode back, post and broadcast
+/// it. `ended_by` is the moderator, None when it ran out. Returns false when
+/// it had already ended.
+async fn end(pool: &SqlitePool, broadcaster: &Broadcaster, id: &str, ended_by: Option<&Claims>, reason: Option<&str>) -> bool {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return false,
+    };
+    // Claim it first, so the lifter and a moderator never both end it.
+    let claimed = sqlx::query("UPDATE lockdowns SET ended_at = ?, ended_by_username = ? WHERE id = ? AND ended_at IS NULL")
+        .bind(&now)
+        .bind(ended_by.map(|c| c.username.as_str()))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or(false);
+    if !claimed {
+        return false;
+    }
+    // Rooms whose slowmode was changed meanwhile keep the new one.
+    let restored = sqlx::query(
+        "UPDATE rooms SET version = version + 1, slowmode_seconds = \
+             (SELECT previous_slowmode_seconds FROM lockdown_rooms WHERE lockdown_id = ? AND room_id = rooms.id) \
+         WHERE id IN (SELECT room_id FROM lockdown_rooms WHERE lockdown_id = ?) \
+           AND slowmode_seconds = (SELECT slowmode_seconds FROM lockdowns WHERE id = ?)"
+    )
+    .bind(id)
+    .bind(id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await;
+    if restored.is_err() || tx.commit().await.is_err() {
+        return false;
+    }
+
+    let Ok(row) = sqlx::query(&format!("SELECT {COLUMNS} FROM lockdowns WHERE id = ?")).bind(id).fetch_one(pool).await else {
+        return true;
+    };
+    let mut lockdown = Lockdown::from_row(&row);
+    load_rooms(pool, &mut lockdown).await;
+    let started_by: Option<String> = row.get("started_by");
+    eprintln!(
+        "lockdown {} ended ({})",
+        lockdown.id,
+        ended_by.map_or("expired".to_string(), |c| format!("lifted by {}", c.username))
+    );
+    crate::audit_log::record(
+        pool,
+        ended_by,
+        crate::audit_log::Action::LockdownEnded,
+        &lockdown.id,
+        serde_json::json!({ "room_ids": lockdown.room_ids, "expired": ended_by.is_none() }),
+        reason,
+    )
+    .await;
+
+    let event = serde_json::json!({ "type": "lockdown_ended", "lockdown": lockdown });
+    let _ = broadcaster.send(event.to_string());
+
+    // Expired ones are announced in the name of whoever started them.
+    let author = match ended_by {
+        Some(claims) => Some((claims.sub.clone(), claims.username.clone())),
+        None => started_by.map(|user_id| (user_id, lockdown.started_by_username.clone())),
+    };
+    if let Some((user_id, username)) = author {
+        let content = match ended_by {
+            Some(_) => "lifted the lockdown",
+            None => "the lockdown has ended",
+        };
+        for room_id in &lockdown.room_ids {
+            crate::rooms::post_system_message(pool, broadcaster, room_id, &user_id, &username, "lockdown_ended", content).await;
+        }
+    }
+    true
+}
+
+/// End the lockdowns whose time ran out, returns how many ended.
+async fn lift_expired(pool: &SqlitePool, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM lockdowns WHERE ended_at IS NULL AND ends_at <= ?")
+        .bind(Utc::now().to_rfc3339())
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut lifted = 0;
+    for id in ids {
+        if end(pool, broadcaster, &id, None, None).await {
+            lifted += 1;
+        }
+    }
+    Ok(lifted)
+}
+
+/// Expired lockdowns not lifted yet.
+async fn lift_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query("SELECT COUNT(*) AS depth, MIN(ends_at) AS oldest_due_at FROM lockdowns WHERE ended_at IS NULL AND ends_at <= ?")
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(pool)
+        .await
+        .map(|row| Backlog::from_row(&row))
+        .unwrap_or_default()
+}
+
+/// Lift expired lockdowns every `LIFT_INTERVAL`.
+pub fn spawn_lifter(pool: SqlitePool, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::LOCKDOWNS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(LIFT_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::LOCKDOWNS, None, lift_expired(&pool, &broadcaster), lift_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct StartLockdown {
+    /// Defaults to every text and forum room.
+    pub room_ids: Option<Vec<String>>,
+    pub slowmode_seconds: Option<i64>,
+    pub duration_minutes: Option<i64>,
+    #[serde(default)]
+    pub trusted_roles: Vec<String>,
+    pub pause_guest_links: Option<bool>,
+    pub reason: Option<String>,
+}
+
+/// POST /api/server/lockdown — Lock rooms down for a while (MANAGE_MESSAGES)
+pub async fn start(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<StartLockdown>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+
+    let slowmode_seconds = body.slowmode_seconds.unwrap_or(DEFAULT_SLOWMODE_SECONDS);
+    if slowmode_seconds < 1 || !crate::slowmode::is_valid(slowmode_seconds) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("slowmode_seconds must be between 1 and {}", crate::slowmode::MAX_SLOWMODE_SECONDS)
+        }));
+    }
+    let duration_minutes = body.duration_minutes.unwrap_or(DEFAULT_DURATION_MINUTES);
+    if !(1..=MAX_DURATION_MINUTES).contains(&duration_minutes) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("duration_minutes must be between 1 and {MAX_DURATION_MINUTES}")
+        }));
+    }
+    let reason = body.reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+    if reason.is_some_and(|r| r.chars().count() > MAX_REASON_CHARS) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Reason must be at most 200 chars" }));
+    }
+
+    let mut trusted_roles: Vec<String> = body.trusted_roles.iter().map(|r| r.trim().to_string()).collect();
+    trusted_roles.sort();
+    trusted_roles.dedup();
+    for role in &trusted_roles {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM roles WHERE name = ?)")
+            .bind(role)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or(false);
+        if !exists || role == crate::guests::GUEST_ROLE {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Unknown role: {role}") }));
+        }
+    }
+
+    let room_ids: Vec<String> = match &body.room_ids {
+        Some(ids) => {
+            let mut ids = ids.clone();
+            ids.sort();
+            ids.dedup();
+            for room_id in &ids {
+                let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rooms WHERE id = ?)")
+                    .bind(room_id)
+                    .fetch_one(pool.get_ref())
+                    .await
+                    .unwrap_or(false);
+                if !exists {
+                    return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("Unknown room: {room_id}") }));
+                }
+            }
+            ids
+        }
+        None => sqlx::query_scalar("SELECT id FROM rooms WHERE kind IN ('text', 'forum') ORDER BY id")
+            .fetch_all(pool.get_ref())
+            .await
+            .unwrap_or_default(),
+    };
+    if room_ids.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "A lockdown needs at least one room" }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let started_at = now.to_rfc3339();
+    let ends_at = (now + chrono::Duration::minutes(duration_minutes)).to_rfc3339();
+    let pause_guest_links = body.pause_guest_links.unwrap_or(true);
+    let trusted_json = serde_json::to_string(&trusted_roles).unwrap_or_else(|_| "[]".to_string());
+    let failed = || HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to start lockdown" }));
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return failed(),
+    };
+    // Checked inside the transaction, so two moderators never both start one.
+    let already_active: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM lockdowns WHERE ended_at IS NULL)")
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap_or(true);
+    if already_active {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "A lockdown is already active" }));
+    }
+    let inserted = sqlx::query(
+        "INSERT INTO lockdowns (id, reason, slowmode_seconds, trusted_roles, pause_guest_links, started_by, started_by_username, started_at, ends_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(reason)
+    .bind(slowmode_seconds)
+    .bind(&trusted_json)
+    .bind(pause_guest_links)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(&started_at)
+    .bind(&ends_at)
+    .execute(&mut *tx)
+    .await;
+    if inserted.is_err() {
+        return failed();
+    }
+    for room_id in &room_ids {
+        let saved = sqlx::query(
+            "INSERT INTO lockdown_rooms (lockdown_id, room_id, previous_slowmode_seconds) \
+             SELECT ?, id, slowmode_seconds FROM rooms WHERE id = ?"
+        )
+        .bind(&id)
+        .bind(room_id)
+        .execute(&mut *tx)
+        .await;
+        let locked = sqlx::query("UPDATE rooms SET slowmode_seconds = ?, version = version + 1 WHERE id = ?")
+            .bind(slowmode_seconds)
+            .bind(room_id)
+            .execute(&mut *tx)
+            .await;
+        if saved.is_err() || locked.is_err() {
+            return failed();
+        }
+    }
+    if tx.commit().await.is_err() {
+        return failed();
+    }
+
+    let lockdown = Lockdown {
+        id,
+        reason: reason.map(str::to_string),
+        room_ids,
+        slowmode_seconds,
+        trusted_roles,
+        pause_guest_links,
+        started_by_username: claims.username.clone(),
+        started_at,
+        ends_at,
+        ended_at: None,
+        ended_by_username: None,
+    };
+    eprintln!(
+        "lockdown {} started by {} on {} room(s) until {}",
+        lockdown.id,
+        claims.username,
+        lockdown.room_ids.len(),
+        lockdown.ends_at
+    );
+
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::LockdownStarted,
+        &lockdown.id,
+        serde_json::to_value(&lockdown).unwrap_or_default(),
+        lockdown.reason.as_deref(),
+    )
+    .await;
+
+    let event = serde_json::json!({ "type": "lockdown_started", "lockdown": lockdown });
+    let _ = broadcaster.send(event.to_string());
+    let content = match &lockdown.reason {
+        Some(reason) => format!("locked this room down for {duration_minutes} min: {reason}"),
+        None => format!("locked this room down for {duration_minutes} min"),
+    };
+    for room_id in &lockdown.room_ids {
+        crate::rooms::post_system_message(pool.get_ref(), broadcaster.get_ref(), room_id, &claims.sub, &claims.username, "lockdown_started", &content).await;
+    }
+
+    HttpResponse::Created().json(lockdown)
+}
+
+/// GET /api/server/lockdown — The active lockdown, or null
+pub async fn get_active(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "lockdown": active(pool.get_ref()).await }))
+}
+
+/// DELETE /api/server/lockdown — Lift the active lockdown early (MANAGE_MESSAGES)
+pub async fn lift(req: HttpRequest, pool: web::Data<SqlitePool>, broadcaster: web::Data<Broadcaster>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+    let Some(lockdown) = active(pool.get_ref()).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No active lockdown" }));
+    };
+    let reason = crate::audit_log::reason(&req);
+    if !end(pool.get_ref(), broadcaster.get_ref(), &lockdown.id, Some(&claims), reason.as_deref()).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No active lockdown" }));
+    }
+    HttpResponse::NoContent().finish()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    pub limit: Option<i64>,
+}
+
+/// GET /api/server/lockdowns — Past and active lockdowns, newest first (MANAGE_MESSAGES)
+pub async fn list_log(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<LogQuery>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
+    let limit = crate::pagination::limit(query.limit, DEFAULT_LOG_LIMIT, MAX_LOG_LIMIT);
+    let rows = sqlx::query(&format!("SELECT {COLUMNS} FROM lockdowns ORDER BY started_at DESC LIMIT ?"))
+        .bind(limit)
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default();
+    let mut lockdowns = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let mut lockdown = Lockdown::from_row(row);
+        load_rooms(pool.get_ref(), &mut lockdown).await;
+        lockdowns.push(lockdown);
+    }
+    HttpResponse::Ok().json(lockdowns)
+}
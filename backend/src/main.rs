@@ -1,3 +1,12 @@
 fn main() {
+    if std::env::args().any(|arg| arg == "--check-config") {
+        std::process::exit(backend::config::check_cli());
+    }
+    if std::env::args().any(|arg| arg == "--doctor") {
+        std::process::exit(backend::doctor::run_cli());
+    }
+    if std::env::args().any(|arg| arg == "--migrate-files") {
+        std::process::exit(backend::files::migrate_cli());
+    }
     backend::run_server();
 }
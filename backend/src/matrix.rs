@@ -0,0 +1,807 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Matrix bridge (application service)
+// ═══════════════════════════════════════════════════════
+//
+// Voxium registers with a Matrix homeserver as an application service and
+// relays messages between linked rooms, with puppets on both sides:
+//   - Matrix → Voxium: the homeserver pushes events to
+//     `PUT /_matrix/app/v1/transactions/{txn_id}`. Each Matrix sender gets a
+//     Voxium user of their own (role `matrix`, no password, so it never
+//     signs in), named after their display name. Images become previews,
+//     other files links, both through the media proxy below.
+//   - Voxium → Matrix: messages of linked rooms are queued as they are
+//     broadcast and sent by the `matrix` job as ghosts, Matrix users in the
+//     bridge's exclusive namespace (`@voxium_<username>:server`) registered,
+//     invited and joined on first use. `@username` of a puppet becomes a
+//     Matrix mention; attachments are sent as links.
+// Messages from puppets are never sent back, events from ghosts never
+// relayed in; `matrix_events` pairs what was relayed. Edits, redactions and
+// reactions are not relayed.
+//
+// Rooms are linked in the `bridges.matrix` section of the server config
+// (see `server_config`). Matrix media is only proxied for relayed messages
+// (`matrix_media`), through `GET /api/matrix/media/{server}/{media_id}`, so
+// clients never need a Matrix account. The homeserver's content type is not
+// trusted: images, audio and video recognised from their first bytes are
+// served inline, anything else as an `application/octet-stream` download,
+// and media over `FILE_MAX_BYTES` is cut off. Admins download the registration
+// file for the homeserver from `GET /api/server/matrix/registration`.
+//
+// Config (env), the bridge is off unless the first four are set:
+//   MATRIX_HOMESERVER_URL         client-server API base, e.g. https://matrix.example.org
+//   MATRIX_SERVER_NAME            the homeserver's name, e.g. example.org
+//   MATRIX_AS_TOKEN               token Voxium calls the homeserver with
+//   MATRIX_HS_TOKEN               token the homeserver calls Voxium with
+//   MATRIX_BOT_LOCALPART          the bridge bot (default voxium)
+//   MATRIX_USER_PREFIX            ghost localpart prefix (default voxium_)
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde::Deserialize;
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::jobs::{Backlog, JobRegistry};
+use crate::sudo::Sudo;
+use crate::unfurl::LinkEmbed;
+use crate::ws::Broadcaster;
+
+pub const PUPPET_ROLE: &str = "matrix";
+const DEFAULT_BOT_LOCALPART: &str = "voxium";
+const DEFAULT_USER_PREFIX: &str = "voxium_";
+const SEND_INTERVAL: Duration = Duration::from_secs(2);
+/// Voxium messages sent per pass.
+const SEND_BATCH: i64 = 50;
+const MAX_NAME_CHARS: usize = 32;
+/// Bytes of proxied media read before telling its type.
+const SNIFF_BYTES: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct MatrixConfig {
+    homeserver_url: String,
+    server_name: String,
+    as_token: String,
+    hs_token: String,
+    bot_localpart: String,
+    user_prefix: String,
+}
+
+impl MatrixConfig {
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        Some(Self {
+            homeserver_url: var("MATRIX_HOMESERVER_URL")?.trim_end_matches('/').to_string(),
+            server_name: var("MATRIX_SERVER_NAME")?,
+            as_token: var("MATRIX_AS_TOKEN")?,
+            hs_token: var("MATRIX_HS_TOKEN")?,
+            bot_localpart: var("MATRIX_BOT_LOCALPART").unwrap_or_else(|| DEFAULT_BOT_LOCALPART.to_string()),
+            user_prefix: var("MATRIX_USER_PREFIX").unwrap_or_else(|| DEFAULT_USER_PREFIX.to_string()),
+        })
+    }
+
+    /// The bridge bot or a ghost: users whose events are our own.
+    fn owns_user(&self, user_id: &str) -> bool {
+        let Some(localpart) = user_id.strip_prefix('@').and_then(|u| u.strip_suffix(&format!(":{}", self.server_name))) else {
+            return false;
+        };
+        localpart == self.bot_localpart || localpart.starts_with(&self.user_prefix)
+    }
+
+    fn client_url(&self, path: &str) -> String {
+        format!("{}/_matrix/client/v3{path}", self.homeserver_url)
+    }
+
+    /// The registration file the homeserver loads.
+    fn registration(&self) -> String {
+        let users = format!("@{}.*:{}", regex::escape(&self.user_prefix), regex::escape(&self.server_name));
+        let yaml = serde_json::json!({
+            "id": "voxium",
+            "url": crate::email::public_base_url(),
+            "as_token": self.as_token,
+            "hs_token": self.hs_token,
+            "sender_localpart": self.bot_localpart,
+            "rate_limited": false,
+            "namespaces": {
+                "users": [{ "exclusive": true, "regex": users }],
+                "aliases": [],
+                "rooms": [],
+            },
+        });
+        serde_yaml::to_string(&yaml).unwrap_or_default()
+    }
+}
+
+/// `!opaque:server.name`
+pub(crate) fn is_room_id(id: &str) -> bool {
+    id.strip_prefix('!')
+        .and_then(|rest| rest.split_once(':'))
+        .is_some_and(|(opaque, server)| !opaque.is_empty() && !server.is_empty() && !id.chars().any(char::is_whitespace))
+        && id.len() <= 255
+}
+
+fn matrix_error(status: StatusCode, errcode: &str, error: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "errcode": errcode, "error": error }))
+}
+
+/// The homeserver authenticates with the `hs_token`, as a bearer token or
+/// (older homeservers) an `access_token` query parameter.
+#[allow(clippy::result_large_err)]
+fn check_hs_token(req: &HttpRequest, config: &MatrixConfig) -> Result<(), HttpResponse> {
+    let bearer = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let token = bearer.or_else(|| {
+        web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+            .ok()
+            .and_then(|q| q.get("access_token").cloned())
+    });
+    match token {
+        None => Err(matrix_error(StatusCode::UNAUTHORIZED, "M_UNAUTHORIZED", "Missing hs_token")),
+        Some(token) if token != config.hs_token => Err(matrix_error(StatusCode::FORBIDDEN, "M_FORBIDDEN", "Bad hs_token")),
+        Some(_) => Ok(()),
+    }
+}
+
+fn not_configured() -> HttpResponse {
+    matrix_error(StatusCode::NOT_FOUND, "M_UNRECOGNIZED", "The Matrix bridge is not configured")
+}
+
+// ── Media ───────────────────────────────────────────────
+
+/// `(server_name, media_id)` of an `mxc://` URI, when both are safe in a path.
+fn mxc_parts(uri: &str) -> Option<(&str, &str)> {
+    let (server, media_id) = uri.strip_prefix("mxc://")?.split_once('/')?;
+    let safe = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || "._-:".contains(c));
+    (safe(server) && safe(media_id)).then_some((server, media_id))
+}
+
+/// Allow the proxy to serve `uri` and return its Voxium URL.
+async fn proxied_media_url(pool: &SqlitePool, uri: &str) -> Option<String> {
+    let (server, media_id) = mxc_parts(uri)?;
+    sqlx::query("INSERT OR IGNORE INTO matrix_media (server_name, media_id, created_at) VALUES (?, ?, ?)")
+        .bind(server)
+        .bind(media_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .ok()?;
+    Some(format!("{}/api/matrix/media/{server}/{media_id}", crate::email::public_base_url()))
+}
+
+// ── Matrix → Voxium ─────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+struct Transaction {
+    #[serde(default)]
+    events: Vec<serde_json::Value>,
+}
+
+/// The Voxium user puppeting `matrix_user_id`, created on their first message.
+async fn ensure_puppet(
+    pool: &SqlitePool,
+    client: &Client,
+    config: &MatrixConfig,
+    matrix_user_id: &str,
+) -> Result<(String, String), sqlx::Error> {
+    let existing: Option<(String, String)> = sqlx::query_as(
+        "SELECT u.id, u.username FROM matrix_puppets p JOIN users u ON u.id = p.user_id WHERE p.matrix_user_id = ?"
+    )
+    .bind(matrix_user_id)
+    .fetch_optional(pool)
+    .await?;
+    if let Some(puppet) = existing {
+        return Ok(puppet);
+    }
+
+    // The profile is best effort: the localpart and no avatar otherwise.
+    let profile: serde_json::Value = match client
+        .get(config.client_url(&format!("/profile/{}", urlencoding::encode(matrix_user_id))))
+        .bearer_auth(&config.as_token)
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => response.json().await.unwrap_or_default(),
+        _ => serde_json::Value::Null,
+    };
+    let localpart = matrix_user_id.trim_start_matches('@').split(':').next().unwrap_or_default();
+    let name: String = profile["displayname"].as_str().unwrap_or(localpart).trim().chars().take(MAX_NAME_CHARS).collect();
+    let username = crate::auth::allocate_unique_username(pool, &name).await;
+    let avatar_url = match profile["avatar_url"].as_str() {
+        Some(uri) => proxied_media_url(pool, uri).await,
+        None => None,
+    };
+
+    let user_id = Uuid::new_v4().to_string();
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO users (id, username, password_hash, role, avatar_url) VALUES (?, ?, '!', ?, ?)")
+        .bind(&user_id)
+        .bind(&username)
+        .bind(PUPPET_ROLE)
+        .bind(&avatar_url)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO matrix_puppets (matrix_user_id, user_id, created_at) VALUES (?, ?, ?)")
+        .bind(matrix_user_id)
+        .bind(&user_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok((user_id, username))
+}
+
+/// The text of a message, without the quote Matrix clients put in replies,
+/// ghosts' Matrix ids turned into `@username`.
+async fn incoming_text(pool: &SqlitePool, content: &serde_json::Value) -> String {
+    let body = content["body"].as_str().unwrap_or_default();
+    let body = if content["m.relates_to"]["m.in_reply_to"].is_object() {
+        body.lines().skip_while(|line| line.starts_with("> ")).skip_while(|line| line.is_empty()).collect::<Vec<_>>().join("\n")
+    } else {
+        body.to_string()
+    };
+    let ghosts: Vec<(String, String)> = sqlx::query_as(
+        "SELECT g.matrix_user_id, u.username FROM matrix_ghosts g JOIN users u ON u.id = g.user_id WHERE instr(?, g.matrix_user_id) > 0"
+    )
+    .bind(&body)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    ghosts.iter().fold(body, |text, (matrix_user_id, username)| text.replace(matrix_user_id.as_str(), &format!("@{username}")))
+}
+
+/// Relay one pushed event into its linked room, if it is a message to relay.
+async fn relay_event(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    client: &Client,
+    config: &MatrixConfig,
+    event: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let (Some(matrix_room_id), Some(sender), Some(event_id)) =
+        (event["room_id"].as_str(), event["sender"].as_str(), event["event_id"].as_str())
+    else {
+        return Ok(());
+    };
+    let content = &event["content"];
+    if event["type"] != "m.room.message" || config.owns_user(sender) || content["m.relates_to"]["rel_type"] == "m.replace" {
+        return Ok(());
+    }
+    let Some(room_id) = sqlx::query_scalar::<_, String>("SELECT room_id FROM matrix_room_links WHERE matrix_room_id = ?")
+        .bind(matrix_room_id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(());
+    };
+    let relayed: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM matrix_events WHERE matrix_event_id = ?)")
+        .bind(event_id)
+        .fetch_one(pool)
+        .await?;
+    if relayed {
+        return Ok(());
+    }
+
+    let mut embeds = Vec::new();
+    let text = match content["msgtype"].as_str() {
+        Some("m.text" | "m.notice") => incoming_text(pool, content).await,
+        Some("m.emote") => format!("*{}*", incoming_text(pool, content).await),
+        Some("m.image") => {
+            let Some(url) = proxied_media_url(pool, content["url"].as_str().unwrap_or_default()).await else {
+                return Ok(());
+            };
+            embeds.push(LinkEmbed {
+                url: url.clone(),
+                site_name: None,
+                title: content["body"].as_str().map(str::to_string),
+                description: None,
+                image_url: Some(url),
+                author_name: None,
+                broken_at: None,
+            });
+            String::new()
+        }
+        Some("m.file" | "m.video" | "m.audio") => {
+            let Some(url) = proxied_media_url(pool, content["url"].as_str().unwrap_or_default()).await else {
+                return Ok(());
+            };
+            format!("{}\n{url}", content["body"].as_str().unwrap_or_default())
+        }
+        _ => return Ok(()),
+    };
+    let text: String = text.trim().chars().take(crate::messages::MAX_CONTENT_CHARS).collect();
+    if text.is_empty() && embeds.is_empty() {
+        return Ok(());
+    }
+
+    let (user_id, username) = ensure_puppet(pool, client, config, sender).await?;
+    let message_id = post_as_puppet(pool, broadcaster, &room_id, &user_id, &username, &text, &embeds).await?;
+    sqlx::query(
+        "INSERT INTO matrix_events (message_id, matrix_room_id, direction, matrix_event_id, created_at) VALUES (?, ?, 'from_matrix', ?, ?)"
+    )
+    .bind(&message_id)
+    .bind(matrix_room_id)
+    .bind(event_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Store and broadcast a puppet's message; returns its id.
+async fn post_as_puppet(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    room_id: &str,
+    user_id: &str,
+    username: &str,
+    content: &str,
+    embeds: &[LinkEmbed],
+) -> Result<String, sqlx::Error> {
+    let embeds_json = (!embeds.is_empty()).then(|| serde_json::to_string(embeds).unwrap_or_default());
+    let message_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    sqlx::query("INSERT INTO messages (id, room_id, user_id, username, content, created_at, embeds) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(&message_id)
+        .bind(room_id)
+        .bind(user_id)
+        .bind(username)
+        .bind(content)
+        .bind(&now)
+        .bind(&embeds_json)
+        .execute(pool)
+        .await?;
+
+    let mention_ids = crate::mentions::record_mentions(pool, &message_id, room_id, user_id, content, &now).await;
+    if embeds.is_empty() {
+        crate::unfurl::mark_pending(pool, &message_id, content).await;
+    }
+    let custom_emojis = crate::emojis::resolve_content(pool, content).await;
+    let avatar_url: Option<String> = sqlx::query_scalar("SELECT avatar_url FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+    let message = serde_json::json!({
+        "type": "message",
+        "id": message_id,
+        "room_id": room_id,
+        "user_id": user_id,
+        "username": username,
+        "avatar_url": avatar_url,
+        "content": content,
+        "created_at": now,
+        "kind": "default",
+        "embeds": embeds,
+        "custom_emojis": custom_emojis,
+        "mention_ids": mention_ids,
+    });
+    let _ = broadcaster.send(message.to_string());
+    crate::mentions::notify_mentions(pool, broadcaster, &mention_ids, &crate::mentions::MentionSource {
+        message_id: &message_id,
+        room_id,
+        author_id: user_id,
+        author_username: username,
+        content,
+    })
+    .await;
+    Ok(message_id)
+}
+
+// ── Voxium → Matrix ─────────────────────────────────────
+
+/// Queue a broadcast `message` of a linked room for Matrix, unless a puppet
+/// wrote it.
+async fn queue_outgoing(pool: &SqlitePool, event: &str) {
+    let Ok(event) = serde_json::from_str::<serde_json::Value>(event) else {
+        return;
+    };
+    if event["type"] != "message" {
+        return;
+    }
+    let (Some(id), Some(room_id), Some(user_id)) = (event["id"].as_str(), event["room_id"].as_str(), event["user_id"].as_str()) else {
+        return;
+    };
+    let _ = sqlx::query(
+        "INSERT OR IGNORE INTO matrix_events (message_id, matrix_room_id, direction, created_at) \
+         SELECT ?, matrix_room_id, 'to_matrix', ? FROM matrix_room_links \
+         WHERE room_id = ? AND NOT EXISTS (SELECT 1 FROM matrix_puppets WHERE user_id = ?)"
+    )
+    .bind(id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(room_id)
+    .bind(user_id)
+    .execute(pool)
+    .await;
+}
+
+/// A localpart for `username`'s ghost: the prefix and the username, or the
+/// user id when the username doesn't make a free, valid one.
+async fn ghost_localpart(pool: &SqlitePool, config: &MatrixConfig, user_id: &str, username: &str) -> String {
+    let name: String = username.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() || "._=-".contains(c) { c } else { '_' }).collect();
+    let preferred = format!("{}{name}", config.user_prefix);
+    let taken: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM matrix_ghosts WHERE matrix_user_id = ?)")
+        .bind(format!("@{preferred}:{}", config.server_name))
+        .fetch_one(pool)
+        .await
+        .unwrap_or(true);
+    if taken || name != username.to_lowercase() {
+        format!("{}{}", config.user_prefix, user_id.replace('-', ""))
+    } else {
+        preferred
+    }
+}
+
+/// The ghost of `user_id`, registered and in `matrix_room_id`.
+async fn ensure_ghost(
+    pool: &SqlitePool,
+    client: &Client,
+    config: &MatrixConfig,
+    user_id: &str,
+    username: &str,
+    matrix_room_id: &str,
+) -> Result<String, String> {
+    let existing: Option<String> = sqlx::query_scalar("SELECT matrix_user_id FROM matrix_ghosts WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let ghost = match existing {
+        Some(ghost) => ghost,
+        None => {
+            let localpart = ghost_localpart(pool, config, user_id, username).await;
+            let response = client
+                .post(config.client_url("/register"))
+                .bearer_auth(&config.as_token)
+                .json(&serde_json::json!({ "type": "m.login.application_service", "username": localpart }))
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            let status = response.status();
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            if !status.is_success() && body["errcode"] != "M_USER_IN_USE" {
+                return Err(format!("Registering {localpart} failed ({status})"));
+            }
+            let ghost = format!("@{localpart}:{}", config.server_name);
+            let _ = client
+                .put(config.client_url(&format!("/profile/{}/displayname", urlencoding::encode(&ghost))))
+                .bearer_auth(&config.as_token)
+                .query(&[("user_id", &ghost)])
+                .json(&serde_json::json!({ "displayname": username }))
+                .send()
+                .await;
+            sqlx::query("INSERT OR IGNORE INTO matrix_ghosts (user_id, matrix_user_id, registered_at) VALUES (?, ?, ?)")
+                .bind(user_id)
+                .bind(&ghost)
+                .bind(Utc::now().to_rfc3339())
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+            ghost
+        }
+    };
+
+    let joined: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM matrix_ghost_rooms WHERE user_id = ? AND matrix_room_id = ?)")
+        .bind(user_id)
+        .bind(matrix_room_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !joined {
+        let room = urlencoding::encode(matrix_room_id);
+        // Invite-only rooms need the bot's invite; public ones ignore it.
+        let _ = client
+            .post(config.client_url(&format!("/rooms/{room}/invite")))
+            .bearer_auth(&config.as_token)
+            .json(&serde_json::json!({ "user_id": ghost }))
+            .send()
+            .await;
+        client
+            .post(config.client_url(&format!("/join/{room}")))
+            .bearer_auth(&config.as_token)
+            .query(&[("user_id", &ghost)])
+            .json(&serde_json::json!({}))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Joining {matrix_room_id} as {ghost} failed: {e}"))?;
+        let _ = sqlx::query("INSERT OR IGNORE INTO matrix_ghost_rooms (user_id, matrix_room_id) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(matrix_room_id)
+            .execute(pool)
+            .await;
+    }
+    Ok(ghost)
+}
+
+/// The `m.room.message` content of a Voxium message: `@username` of a
+/// puppet becomes their Matrix id and mentions them, attachments are links.
+async fn outgoing_content(pool: &SqlitePool, message_id: &str, content: &str) -> serde_json::Value {
+    let mut text = content.to_string();
+    let mut mentioned = Vec::new();
+    for name in crate::mentions::parse_mentions(content) {
+        let matrix_user_id: Option<String> = sqlx::query_scalar(
+            "SELECT p.matrix_user_id FROM matrix_puppets p JOIN users u ON u.id = p.user_id WHERE u.username = ? COLLATE NOCASE"
+        )
+        .bind(&name)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+        if let Some(matrix_user_id) = matrix_user_id {
+            text = crate::bridge::replace_mention(&text, &name, &matrix_user_id);
+            mentioned.push(matrix_user_id);
+        }
+    }
+    let base = crate::email::public_base_url();
+    for attachment in crate::files::message_attachments(pool, message_id).await {
+        text.push_str(&format!("\n{base}{}", attachment.url));
+    }
+    serde_json::json!({
+        "msgtype": "m.text",
+        "body": text.trim(),
+        "m.mentions": { "user_ids": mentioned },
+    })
+}
+
+/// Send queued Voxium messages as their ghosts. Stops at a rate limit or a
+/// failure; a failed message is dropped rather than retried.
+async fn send_queued(pool: &SqlitePool, client: &Client, config: &MatrixConfig) -> Result<u64, String> {
+    let queued = sqlx::query(
+        "SELECT e.message_id, e.matrix_room_id, m.user_id, m.username, m.content, m.deleted_at \
+         FROM matrix_events e JOIN messages m ON m.id = e.message_id \
+         WHERE e.direction = 'to_matrix' AND e.matrix_event_id IS NULL ORDER BY e.created_at LIMIT ?"
+    )
+    .bind(SEND_BATCH)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut sent = 0;
+    for row in queued {
+        let message_id: String = row.get("message_id");
+        let matrix_room_id: String = row.get("matrix_room_id");
+        let drop_queued = || sqlx::query("DELETE FROM matrix_events WHERE message_id = ?").bind(&message_id).execute(pool);
+        if row.get::<Option<String>, _>("deleted_at").is_some() {
+            let _ = drop_queued().await;
+            continue;
+        }
+        let ghost = match ensure_ghost(pool, client, config, row.get("user_id"), row.get("username"), &matrix_room_id).await {
+            Ok(ghost) => ghost,
+            Err(error) => {
+                let _ = drop_queued().await;
+                return Err(error);
+            }
+        };
+        let content = outgoing_content(pool, &message_id, row.get("content")).await;
+        let response = client
+            .put(config.client_url(&format!(
+                "/rooms/{}/send/m.room.message/{message_id}",
+                urlencoding::encode(&matrix_room_id)
+            )))
+            .bearer_auth(&config.as_token)
+            .query(&[("user_id", &ghost)])
+            .json(&content)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            break;
+        }
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        let Some(event_id) = body["event_id"].as_str().filter(|_| status.is_success()) else {
+            let _ = drop_queued().await;
+            return Err(format!("Sending to {matrix_room_id} failed ({status})"));
+        };
+        let _ = sqlx::query("UPDATE matrix_events SET matrix_event_id = ? WHERE message_id = ?")
+            .bind(event_id)
+            .bind(&message_id)
+            .execute(pool)
+            .await;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+async fn send_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM matrix_events \
+         WHERE direction = 'to_matrix' AND matrix_event_id IS NULL"
+    )
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_sender(pool: SqlitePool, broadcaster: Broadcaster, jobs: JobRegistry) {
+    let Some(config) = MatrixConfig::from_env() else {
+        return;
+    };
+    crate::jobs::register(&jobs, crate::jobs::MATRIX);
+
+    let mut rx = broadcaster.subscribe();
+    let listener_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => queue_outgoing(&listener_pool, &event).await,
+                // Missed messages are not relayed.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    actix_web::rt::spawn(async move {
+        let client = Client::builder().timeout(Duration::from_secs(15)).build().unwrap_or_default();
+        let mut ticker = tokio::time::interval(SEND_INTERVAL);
+        loop {
+            ticker.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::MATRIX, None, send_queued(&pool, &client, &config), send_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// PUT /_matrix/app/v1/transactions/{txn_id} — Events pushed by the homeserver (hs_token)
+pub async fn push_transaction(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let Some(config) = MatrixConfig::from_env() else {
+        return not_configured();
+    };
+    if let Err(response) = check_hs_token(&req, &config) {
+        return response;
+    }
+    let Ok(transaction) = serde_json::from_slice::<Transaction>(&body) else {
+        return matrix_error(StatusCode::BAD_REQUEST, "M_NOT_JSON", "Expected a transaction");
+    };
+    let txn_id = path.into_inner();
+
+    // The homeserver retries a transaction until it is acknowledged.
+    let fresh = sqlx::query("INSERT OR IGNORE INTO matrix_transactions (txn_id, received_at) VALUES (?, ?)")
+        .bind(&txn_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected() > 0);
+    match fresh {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Ok().json(serde_json::json!({})),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let client = Client::new();
+    for event in &transaction.events {
+        if relay_event(pool.get_ref(), broadcaster.get_ref(), &client, &config, event).await.is_err() {
+            // Let the retry pick up where this one stopped: relayed events are skipped.
+            let _ = sqlx::query("DELETE FROM matrix_transactions WHERE txn_id = ?").bind(&txn_id).execute(pool.get_ref()).await;
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    HttpResponse::Ok().json(serde_json::json!({}))
+}
+
+/// GET /_matrix/app/v1/users/{user_id} — Whether a ghost exists (hs_token)
+pub async fn query_user(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let Some(config) = MatrixConfig::from_env() else {
+        return not_configured();
+    };
+    if let Err(response) = check_hs_token(&req, &config) {
+        return response;
+    }
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM matrix_ghosts WHERE matrix_user_id = ?)")
+        .bind(path.into_inner())
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if exists {
+        HttpResponse::Ok().json(serde_json::json!({}))
+    } else {
+        matrix_error(StatusCode::NOT_FOUND, "M_NOT_FOUND", "No such ghost")
+    }
+}
+
+/// GET /_matrix/app/v1/rooms/{alias} — The bridge provides no room aliases (hs_token)
+pub async fn query_room_alias(req: HttpRequest) -> HttpResponse {
+    let Some(config) = MatrixConfig::from_env() else {
+        return not_configured();
+    };
+    if let Err(response) = check_hs_token(&req, &config) {
+        return response;
+    }
+    matrix_error(StatusCode::NOT_FOUND, "M_NOT_FOUND", "No such alias")
+}
+
+/// GET /api/matrix/media/{server_name}/{media_id} — Matrix media of a relayed message (public)
+pub async fn proxy_media(pool: web::Data<SqlitePool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (server, media_id) = path.into_inner();
+    let Some(config) = MatrixConfig::from_env() else {
+        return HttpResponse::NotFound().finish();
+    };
+    let known: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM matrix_media WHERE server_name = ? AND media_id = ?)")
+        .bind(&server)
+        .bind(&media_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !known {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let url = format!("{}/_matrix/client/v1/media/download/{server}/{media_id}", config.homeserver_url);
+    let response = match Client::new().get(url).bearer_auth(&config.as_token).send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return HttpResponse::BadGateway().finish(),
+    };
+    let limit = crate::files::max_bytes() as u64;
+    if response.content_length().is_some_and(|length| length > limit) {
+        return HttpResponse::BadGateway().json(serde_json::json!({ "error": "Media too large" }));
+    }
+    let mut body = response.bytes_stream();
+    let mut head = Vec::new();
+    while head.len() < SNIFF_BYTES {
+        match body.next().await {
+            Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+            Some(Err(_)) => return HttpResponse::BadGateway().finish(),
+            None => break,
+        }
+    }
+    if head.len() as u64 > limit {
+        return HttpResponse::BadGateway().json(serde_json::json!({ "error": "Media too large" }));
+    }
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(("Cache-Control", "public, max-age=86400"))
+        .insert_header(("X-Content-Type-Options", "nosniff"));
+    // Media plays in place, anything else is a download.
+    match crate::files::sniff_content_type(&head[..head.len().min(SNIFF_BYTES)]) {
+        Some(kind) if ["image/", "audio/", "video/"].iter().any(|prefix| kind.starts_with(prefix)) => {
+            response.content_type(kind);
+        }
+        _ => {
+            response
+                .content_type("application/octet-stream")
+                .insert_header(("Content-Disposition", "attachment"));
+        }
+    }
+    let mut sent = head.len() as u64;
+    let rest = body.map(move |chunk| {
+        let chunk = chunk.map_err(actix_web::error::ErrorBadGateway)?;
+        sent += chunk.len() as u64;
+        if sent > limit {
+            return Err(actix_web::error::ErrorBadGateway("Media too large"));
+        }
+        Ok(chunk)
+    });
+    response.streaming(futures_util::stream::once(async move { Ok(web::Bytes::from(head)) }).chain(rest))
+}
+
+/// GET /api/server/matrix/registration — The homeserver's registration file (ADMINISTRATOR)
+pub async fn registration(Sudo(claims): Sudo, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::ADMINISTRATOR).await {
+        return response;
+    }
+    match MatrixConfig::from_env() {
+        Some(config) => HttpResponse::Ok()
+            .content_type("application/yaml")
+            .insert_header(("Content-Disposition", "attachment; filename=\"voxium-registration.yaml\""))
+            .body(config.registration()),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "The Matrix bridge is not configured" })),
+    }
+}
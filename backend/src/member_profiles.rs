@@ -0,0 +1,219 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Member profiles (nickname, avatar, pronouns, bio)
+// ═══════════════════════════════════════════════════════
+//
+// How a member appears on this server, over their account profile (which
+// a Discord login overwrites with the Discord avatar):
+//   - nickname    shown instead of the username (1-32 characters)
+//   - avatar_url  shown instead of the account avatar (http(s) or a
+//                 path on this server)
+//   - pronouns    up to 40 characters
+//   - bio         up to 190 characters, shown instead of `about`
+//
+// Members edit their own; moderators (MANAGE_MEMBERS) edit anyone's, to
+// reset an offensive nickname for instance, which goes to the audit log.
+// An empty string clears a field. The member list and message authors
+// carry the nickname and the avatar in effect; every change is sent as
+// `member_profile_updated`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+const MAX_NICKNAME_CHARS: usize = 32;
+const MAX_AVATAR_URL_CHARS: usize = 2048;
+const MAX_PRONOUNS_CHARS: usize = 40;
+const MAX_BIO_CHARS: usize = 190;
+
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow)]
+pub struct MemberProfile {
+    pub user_id: String,
+    pub nickname: Option<String>,
+    pub avatar_url: Option<String>,
+    pub pronouns: Option<String>,
+    pub bio: Option<String>,
+    /// `None` until the profile is first edited.
+    pub updated_at: Option<String>,
+}
+
+/// `user_id`'s member profile, empty when never set.
+pub(crate) async fn load(pool: &SqlitePool, user_id: &str) -> MemberProfile {
+    sqlx::query_as::<_, MemberProfile>(
+        "SELECT user_id, nickname, avatar_url, pronouns, bio, updated_at FROM member_profiles WHERE user_id = ?"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or_else(|| MemberProfile { user_id: user_id.to_string(), ..Default::default() })
+}
+
+/// Fields to change; missing ones are kept, empty ones cleared.
+#[derive(Debug, Deserialize)]
+pub struct MemberProfilePatch {
+    pub nickname: Option<String>,
+    pub avatar_url: Option<String>,
+    pub pronouns: Option<String>,
+    pub bio: Option<String>,
+}
+
+fn set(field: &mut Option<String>, value: &Option<String>, name: &str, max_chars: usize) -> Result<(), String> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    let value = value.trim();
+    if value.chars().count() > max_chars {
+        return Err(format!("{name} must be at most {max_chars} characters"));
+    }
+    *field = (!value.is_empty()).then(|| value.to_string());
+    Ok(())
+}
+
+impl MemberProfilePatch {
+    fn apply(&self, profile: &mut MemberProfile) -> Result<(), String> {
+        set(&mut profile.nickname, &self.nickname, "nickname", MAX_NICKNAME_CHARS)?;
+        set(&mut profile.avatar_url, &self.avatar_url, "avatar_url", MAX_AVATAR_URL_CHARS)?;
+        if let Some(url) = &profile.avatar_url {
+            let allowed = url.starts_with("https://") || url.starts_with("http://") || (url.starts_with('/') && !url.starts_with("//"));
+            if !allowed {
+                return Err("avatar_url must be an http(s) URL or a path on this server".to_string());
+            }
+        }
+        set(&mut profile.pronouns, &self.pronouns, "pronouns", MAX_PRONOUNS_CHARS)?;
+        set(&mut profile.bio, &self.bio, "bio", MAX_BIO_CHARS)?;
+        Ok(())
+    }
+}
+
+/// Apply `patch` to `user_id`'s profile, store and announce it.
+async fn update(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    user_id: &str,
+    patch: &MemberProfilePatch,
+) -> Result<(MemberProfile, MemberProfile), HttpResponse> {
+    let old = load(pool, user_id).await;
+    let mut profile = old.clone();
+    if let Err(error) = patch.apply(&mut profile) {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": error })));
+    }
+    profile.updated_at = Some(Utc::now().to_rfc3339());
+    let result = sqlx::query(
+        "INSERT INTO member_profiles (user_id, nickname, avatar_url, pronouns, bio, updated_at) VALUES (?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(user_id) DO UPDATE SET nickname = excluded.nickname, avatar_url = excluded.avatar_url, \
+         pronouns = excluded.pronouns, bio = excluded.bio, updated_at = excluded.updated_at"
+    )
+    .bind(user_id)
+    .bind(&profile.nickname)
+    .bind(&profile.avatar_url)
+    .bind(&profile.pronouns)
+    .bind(&profile.bio)
+    .bind(&profile.updated_at)
+    .execute(pool)
+    .await;
+    if result.is_err() {
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+    let event = serde_json::json!({
+        "type": "member_profile_updated",
+        "user_id": user_id,
+        "profile": profile,
+    });
+    let _ = broadcaster.send(event.to_string());
+    Ok((old, profile))
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/users/{id}/member-profile — A member's profile on this server
+pub async fn get_member_profile(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let user_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)")
+        .bind(&user_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    }
+    HttpResponse::Ok().json(load(pool.get_ref(), &user_id).await)
+}
+
+/// PATCH /api/users/@me/member-profile — Change some of the caller's member profile
+pub async fn update_own_profile(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<MemberProfilePatch>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match update(pool.get_ref(), broadcaster.get_ref(), &claims.sub, &body).await {
+        Ok((_, profile)) => HttpResponse::Ok().json(profile),
+        Err(response) => response,
+    }
+}
+
+/// PATCH /api/users/{id}/member-profile — Change some of a member's profile (MANAGE_MEMBERS)
+pub async fn update_member_profile(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<MemberProfilePatch>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+    let user_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)")
+        .bind(&user_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    }
+    let (old, profile) = match update(pool.get_ref(), broadcaster.get_ref(), &user_id, &body).await {
+        Ok(updated) => updated,
+        Err(response) => return response,
+    };
+
+    let mut changes = serde_json::Map::new();
+    for (field, was, now) in [
+        ("nickname", &old.nickname, &profile.nickname),
+        ("avatar_url", &old.avatar_url, &profile.avatar_url),
+        ("pronouns", &old.pronouns, &profile.pronouns),
+        ("bio", &old.bio, &profile.bio),
+    ] {
+        if was != now {
+            changes.insert(field.to_string(), serde_json::json!({ "old": was, "new": now }));
+        }
+    }
+    if !changes.is_empty() {
+        crate::audit_log::record(
+            pool.get_ref(),
+            Some(&claims),
+            crate::audit_log::Action::MemberProfileUpdated,
+            &user_id,
+            serde_json::Value::Object(changes),
+            crate::audit_log::reason(&req).as_deref(),
+        )
+        .await;
+    }
+    HttpResponse::Ok().json(profile)
+}
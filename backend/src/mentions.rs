@@ -0,0 +1,242 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Mentions
+// ═══════════════════════════════════════════════════════
+//
+// `@username` mentions a user, with the syntax the client highlights: an
+// `@` at the start of the message or after whitespace, followed by 2-32
+// letters, digits, `_` or `-`. Usernames match case-insensitively.
+//
+// `@rolename` mentions every member of the role, if the role is
+// `mentionable` or the author has `MENTION_EVERYONE` in the room. Otherwise
+// it is plain text.
+//
+// `@room` mentions everyone who can see the room; it takes `MENTION_EVERYONE`.
+//
+// Only users with `VIEW_ROOM` in the room are mentioned, and never by someone they
+// blocked. Mentions are resolved when the message is sent (again when it is
+// edited) and stored in `message_mentions`, so unread mention counts (see
+// `read_states`) are a plain indexed count. Each newly mentioned user also
+// gets a `mention` event unless their notification settings (see
+// `notification_settings`), quiet hours or `dnd` (see `quiet_hours`)
+// silence it, and `GET /api/users/@me/mentions`
+// lists them.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::auth::extract_claims;
+use crate::messages::{BlockedFilter, Message};
+use crate::ws::Broadcaster;
+
+const MIN_MENTION_LEN: usize = 2;
+const MAX_MENTION_LEN: usize = 32;
+/// A message pinging more people than this only records the first ones.
+const MAX_MENTIONS_PER_MESSAGE: usize = 50;
+const ROOM_MENTION: &str = "room";
+const DEFAULT_RECENT_LIMIT: i64 = 25;
+const MAX_RECENT_LIMIT: i64 = 100;
+/// Message content quoted in `mention` events.
+const EXCERPT_CHARS: usize = 200;
+
+fn is_mention_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Lowercased names mentioned in `content`, without duplicates, in order.
+pub(crate) fn parse_mentions(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let at_word_start = previous.is_none_or(char::is_whitespace);
+        previous = Some(c);
+        if c != '@' || !at_word_start {
+            continue;
+        }
+
+        let start = i + 1;
+        let mut end = start;
+        while let Some(&(j, next)) = chars.peek() {
+            if !is_mention_char(next) {
+                break;
+            }
+            end = j + next.len_utf8();
+            previous = Some(next);
+            chars.next();
+        }
+
+        let name = content[start..end].to_ascii_lowercase();
+        if (MIN_MENTION_LEN..=MAX_MENTION_LEN).contains(&name.len()) && !names.contains(&name) {
+            names.push(name);
+            if names.len() == MAX_MENTIONS_PER_MESSAGE {
+                break;
+            }
+        }
+    }
+    names
+}
+
+/// Store who `content` mentions, directly, through a role or with `@room`,
+/// and return their user ids. The author mentioning themselves does not count.
+pub(crate) async fn record_mentions(
+    pool: &SqlitePool,
+    message_id: &str,
+    room_id: &str,
+    author_id: &str,
+    content: &str,
+    created_at: &str,
+) -> Vec<String> {
+    let names = parse_mentions(content);
+    if names.is_empty() {
+        return Vec::new();
+    }
+
+    let author_role: String = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+        .bind(author_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let mentions_everyone = crate::permissions::room_permissions(pool, room_id, author_id, &author_role)
+        .await
+        .is_some_and(|granted| granted & crate::permissions::MENTION_EVERYONE != 0);
+    let mentions_room = mentions_everyone && names.iter().any(|name| name == ROOM_MENTION);
+
+    let placeholders = vec!["?"; names.len()].join(", ");
+    let sql = format!(
+        "SELECT u.id, u.role FROM users u \
+         WHERE u.id != ? \
+           AND NOT EXISTS (SELECT 1 FROM relationships b WHERE b.user_id = u.id AND b.target_id = ? AND b.kind = 'blocked') \
+           AND (? OR lower(u.username) IN ({placeholders}) \
+             OR u.role IN (SELECT name FROM roles WHERE name IN ({placeholders}) AND (mentionable = 1 OR ?)))"
+    );
+    let mut query = sqlx::query_as::<_, (String, String)>(&sql)
+        .bind(author_id)
+        .bind(author_id)
+        .bind(mentions_room);
+    for name in names.iter().chain(&names) {
+        query = query.bind(name);
+    }
+    let candidates = query.bind(mentions_everyone).fetch_all(pool).await.unwrap_or_default();
+    let user_ids = crate::permissions::members_with(pool, room_id, candidates, crate::permissions::VIEW_ROOM).await;
+
+    for user_id in &user_ids {
+        let _ = sqlx::query(
+            "INSERT OR IGNORE INTO message_mentions (message_id, user_id, room_id, created_at) VALUES (?, ?, ?, ?)"
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(room_id)
+        .bind(created_at)
+        .execute(pool)
+        .await;
+    }
+    user_ids
+}
+
+/// Users currently recorded as mentioned by a message.
+pub(crate) async fn mentioned_users(pool: &SqlitePool, message_id: &str) -> Vec<String> {
+    sqlx::query_scalar("SELECT user_id FROM message_mentions WHERE message_id = ?")
+        .bind(message_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+}
+
+/// The message a `mention` event points to.
+pub(crate) struct MentionSource<'a> {
+    pub message_id: &'a str,
+    pub room_id: &'a str,
+    pub author_id: &'a str,
+    pub author_username: &'a str,
+    pub content: &'a str,
+}
+
+/// Push a `mention` event to each of `user_ids` whose notification settings
+/// allow it (see `notification_settings`), unless in quiet hours or `dnd`
+/// (see `quiet_hours`).
+pub(crate) async fn notify_mentions(pool: &SqlitePool, broadcaster: &Broadcaster, user_ids: &[String], source: &MentionSource<'_>) {
+    let excerpt: String = source.content.chars().take(EXCERPT_CHARS).collect();
+    let recipients = crate::notification_settings::mention_recipients(pool, source.room_id, source.content, user_ids).await;
+    let recipients = crate::quiet_hours::interruptible(pool, &recipients).await;
+    for user_id in &recipients {
+        let event = serde_json::json!({
+            "type": "mention",
+            "recipient_id": user_id,
+            "message_id": source.message_id,
+            "room_id": source.room_id,
+            "author_id": source.author_id,
+            "author_username": source.author_username,
+            "excerpt": excerpt,
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct RecentMentionsQuery {
+    pub limit: Option<i64>,
+    /// `created_at` of the oldest mention already loaded, for paging.
+    pub before: Option<String>,
+    pub room_id: Option<String>,
+}
+
+/// GET /api/users/@me/mentions?limit=&before=&room_id= — Recent messages mentioning the caller, newest first
+pub async fn recent_mentions(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<RecentMentionsQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let limit = crate::pagination::limit(query.limit, DEFAULT_RECENT_LIMIT, MAX_RECENT_LIMIT);
+    let viewable: Vec<String> = crate::permissions::viewable_rooms(pool.get_ref(), &claims.sub, &claims.role)
+        .await
+        .into_iter()
+        .collect();
+    let mut sql = format!(
+        "{} JOIN message_mentions mm ON mm.message_id = m.id \
+         WHERE mm.user_id = ? AND m.deleted_at IS NULL AND m.room_id IN ({})",
+        crate::messages::MESSAGE_SELECT,
+        vec!["?"; viewable.len()].join(", ")
+    );
+    if query.before.is_some() {
+        sql.push_str(" AND mm.created_at < ?");
+    }
+    if query.room_id.is_some() {
+        sql.push_str(" AND mm.room_id = ?");
+    }
+    sql.push_str(" ORDER BY mm.created_at DESC, mm.message_id DESC LIMIT ?");
+
+    let mut rows = sqlx::query(&sql).bind(&claims.sub);
+    for room_id in &viewable {
+        rows = rows.bind(room_id);
+    }
+    if let Some(before) = &query.before {
+        rows = rows.bind(before);
+    }
+    if let Some(room_id) = &query.room_id {
+        rows = rows.bind(room_id);
+    }
+    let rows = match rows.bind(limit + 1).fetch_all(pool.get_ref()).await {
+        Ok(rows) => rows,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let has_more = rows.len() as i64 > limit;
+    let mut messages: Vec<Message> = rows.iter().take(limit as usize).map(crate::messages::message_from_row).collect();
+    crate::messages::apply_block_filter(pool.get_ref(), &claims.sub, BlockedFilter::Omit, &mut messages).await;
+    crate::messages::enrich_messages_with_reactions(pool.get_ref(), &mut messages).await;
+    crate::emojis::attach_custom_emojis(pool.get_ref(), &mut messages).await;
+    crate::files::attach_to_messages(pool.get_ref(), &mut messages).await;
+
+    HttpResponse::Ok().json(serde_json::json!({ "mentions": messages, "has_more": has_more }))
+}
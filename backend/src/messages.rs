@@ -5,6 +5,7 @@ use sqlx::sqlite::SqliteRow;
 use sqlx::SqlitePool;
 use sqlx::Row;
 use crate::auth::extract_claims;
+use crate::sudo::Sudo;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MessageReaction {
@@ -13,6 +14,25 @@ pub struct MessageReaction {
     pub user_ids: Vec<String>,
 }
 
+/// Maximum number of characters kept from the quoted message's content.
+pub const MAX_QUOTE_CHARS: usize = 500;
+
+/// Frozen copy of a quoted message, taken when the quoting message is sent so
+/// later edits or deletions of the original don't change the quote.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuoteSnapshot {
+    pub message_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub content: String,
+    pub image_url: Option<String>,
+    pub created_at: String,
+    pub truncated: bool,
+    /// Computed at read time, never stored.
+    #[serde(default)]
+    pub original_exists: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
     pub id: String,
@@ -26,11 +46,69 @@ pub struct Message {
     pub pinned_at: Option<String>,
     pub pinned_by: Option<String>,
     pub avatar_url: Option<String>,
+    /// The author's nickname on this server, see `member_profiles`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
     #[serde(default)]
     pub reactions: Vec<MessageReaction>,
+    pub quote: Option<QuoteSnapshot>,
+    /// "default" for user messages, otherwise the system event (e.g. "room_topic_changed").
+    #[serde(default)]
+    pub kind: String,
+    /// Set when the author is blocked by the viewer and `blocked=collapse`.
+    #[serde(default)]
+    pub author_blocked: bool,
+    pub edited_at: Option<String>,
+    /// Only moderators get deleted messages (tombstones), with this set.
+    pub deleted_at: Option<String>,
+    /// Custom emoji used in `content` (as `:name:`) or in `reactions`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_emojis: Vec<crate::emojis::CustomEmoji>,
+    /// Files uploaded with the message, see `files`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<crate::files::Attachment>,
+    /// Link previews, filled in shortly after sending, see `unfurl`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embeds: Vec<crate::unfurl::LinkEmbed>,
+    /// Set on messages of kind `poll`, see `polls`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<crate::polls::Poll>,
+    /// The forum post the message belongs to, see `forum`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_id: Option<String>,
+    /// Set when a webhook posted the message, see `webhooks`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
 }
 
-fn message_from_row(row: &SqliteRow) -> Message {
+/// Edited content is capped at this many characters.
+pub const MAX_CONTENT_CHARS: usize = 4000;
+/// Tombstones are purged after this many days (`MESSAGE_TOMBSTONE_RETENTION_DAYS`, 0 keeps them).
+const DEFAULT_TOMBSTONE_RETENTION_DAYS: i64 = 30;
+const TOMBSTONE_PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// Columns shared by every message listing. Callers append joins/filters.
+pub(crate) const MESSAGE_SELECT: &str = "SELECT m.id, m.room_id, m.user_id, m.username, m.content, m.reply_to_id, m.created_at, m.image_url, m.pinned_at, m.pinned_by, m.quote_snapshot, m.kind, \
+     m.edited_at, m.deleted_at, m.embeds, m.post_id, m.webhook_id, \
+     EXISTS(SELECT 1 FROM messages q WHERE q.id = m.reply_to_id AND q.deleted_at IS NULL) AS quote_original_exists, \
+     COALESCE(m.webhook_avatar_url, mp.avatar_url, u.avatar_url) AS avatar_url, \
+     CASE WHEN m.webhook_id IS NULL THEN mp.nickname END AS nickname \
+     FROM messages m LEFT JOIN users u ON m.user_id = u.id LEFT JOIN member_profiles mp ON mp.user_id = m.user_id";
+
+/// Moderators (MANAGE_MESSAGES on their role) also see deleted messages;
+/// everyone else does not.
+pub(crate) async fn sees_deleted(pool: &SqlitePool, role: &str) -> bool {
+    crate::permissions::role_permissions(pool, role).await & crate::permissions::MANAGE_MESSAGES != 0
+}
+
+fn quote_from_row(row: &SqliteRow) -> Option<QuoteSnapshot> {
+    let raw: Option<String> = row.try_get("quote_snapshot").unwrap_or(None);
+    let mut quote: QuoteSnapshot = serde_json::from_str(&raw?).ok()?;
+    quote.original_exists = row.try_get("quote_original_exists").unwrap_or(false);
+    Some(quote)
+}
+
+pub(crate) fn message_from_row(row: &SqliteRow) -> Message {
     Message {
         id: row.try_get("id").unwrap_or_default(),
         room_id: row.try_get("room_id").unwrap_or_default(),
@@ -43,10 +121,96 @@ fn message_from_row(row: &SqliteRow) -> Message {
         pinned_at: row.try_get("pinned_at").unwrap_or(None),
         pinned_by: row.try_get("pinned_by").unwrap_or(None),
         avatar_url: row.try_get("avatar_url").unwrap_or(None),
+        nickname: row.try_get("nickname").unwrap_or(None),
         reactions: Vec::new(),
+        quote: quote_from_row(row),
+        kind: row.try_get("kind").unwrap_or_else(|_| "default".to_string()),
+        author_blocked: false,
+        edited_at: row.try_get("edited_at").unwrap_or(None),
+        deleted_at: row.try_get("deleted_at").unwrap_or(None),
+        custom_emojis: Vec::new(),
+        attachments: Vec::new(),
+        embeds: crate::unfurl::parse_embeds(row.try_get("embeds").unwrap_or(None)),
+        poll: None,
+        post_id: row.try_get("post_id").unwrap_or(None),
+        webhook_id: row.try_get("webhook_id").unwrap_or(None),
     }
 }
 
+/// Snapshot `quoted_id` for a new message in `room_id`. Quotes are limited to
+/// the same room so they can't leak content from rooms the reader can't see.
+pub(crate) async fn build_quote_snapshot(
+    pool: &SqlitePool,
+    quoted_id: &str,
+    room_id: &str,
+) -> Option<QuoteSnapshot> {
+    let row = sqlx::query(
+        "SELECT id, user_id, username, content, image_url, created_at FROM messages WHERE id = ? AND room_id = ? AND deleted_at IS NULL"
+    )
+    .bind(quoted_id)
+    .bind(room_id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None)?;
+
+    let content: String = row.try_get("content").unwrap_or_default();
+    let truncated = content.chars().count() > MAX_QUOTE_CHARS;
+    let content = if truncated {
+        content.chars().take(MAX_QUOTE_CHARS).collect()
+    } else {
+        content
+    };
+
+    Some(QuoteSnapshot {
+        message_id: row.try_get("id").unwrap_or_default(),
+        user_id: row.try_get("user_id").unwrap_or_default(),
+        username: row.try_get("username").unwrap_or_default(),
+        content,
+        image_url: row.try_get("image_url").unwrap_or(None),
+        created_at: row.try_get("created_at").unwrap_or_default(),
+        truncated,
+        original_exists: true,
+    })
+}
+
+/// How messages from users the viewer has blocked are returned.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockedFilter {
+    /// Keep them, flagged with `author_blocked` so the client can fold them.
+    #[default]
+    Collapse,
+    /// Drop them from the response.
+    Omit,
+    /// Return them untouched.
+    Show,
+}
+
+pub const DEFAULT_HISTORY_LIMIT: i64 = 50;
+pub const MAX_HISTORY_LIMIT: i64 = 100;
+
+/// At most one cursor: `before`/`after` exclude the given message, `around`
+/// includes it in the middle of the page. None means the latest messages.
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub around: Option<String>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub blocked: BlockedFilter,
+}
+
+/// One page of history, oldest first.
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub messages: Vec<Message>,
+    /// Older messages exist before the first one of the page.
+    pub has_more_before: bool,
+    /// Newer messages exist after the last one of the page.
+    pub has_more_after: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SearchQuery {
     pub q: Option<String>,
@@ -55,11 +219,17 @@ pub struct SearchQuery {
     pub from: Option<String>,
     pub to: Option<String>,
     pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub blocked: BlockedFilter,
 }
 
+/// A unicode `emoji`, or a custom one as `emoji_id` (or `emoji: "custom:<id>"`).
 #[derive(Debug, Deserialize)]
 pub struct ReactionInput {
-    pub emoji: String,
+    pub emoji: Option<String>,
+    pub emoji_id: Option<String>,
 }
 
 fn normalize_emoji(raw: &str) -> Option<String> {
@@ -77,7 +247,24 @@ fn normalize_emoji(raw: &str) -> Option<String> {
     Some(trimmed.to_string())
 }
 
-async fn enrich_messages_with_reactions(pool: &SqlitePool, messages: &mut [Message]) {
+/// Stored key of a reaction, plus the custom emoji it refers to.
+async fn resolve_reaction(
+    pool: &SqlitePool,
+    input: &ReactionInput,
+) -> Option<(String, Option<crate::emojis::CustomEmoji>)> {
+    let custom_id = input.emoji_id.as_deref().or_else(|| {
+        input.emoji.as_deref().and_then(|e| e.trim().strip_prefix(crate::emojis::REACTION_PREFIX))
+    });
+    match custom_id {
+        Some(id) => {
+            let emoji = crate::emojis::fetch_emoji(pool, id.trim()).await?;
+            Some((format!("{}{}", crate::emojis::REACTION_PREFIX, emoji.id), Some(emoji)))
+        }
+        None => normalize_emoji(input.emoji.as_deref()?).map(|e| (e, None)),
+    }
+}
+
+pub(crate) async fn enrich_messages_with_reactions(pool: &SqlitePool, messages: &mut [Message]) {
     if messages.is_empty() {
         return;
     }
@@ -131,36 +318,56 @@ async fn enrich_messages_with_reactions(pool: &SqlitePool, messages: &mut [Messa
     }
 }
 
-async fn can_access_message_room(pool: &SqlitePool, message_id: &str, role: &str) -> Option<String> {
-    let row = sqlx::query(
-        "SELECT m.room_id AS room_id, r.required_role AS required_role \
-         FROM messages m \
-         LEFT JOIN rooms r ON m.room_id = r.id \
-         WHERE m.id = ?"
-    )
-    .bind(message_id)
-    .fetch_optional(pool)
-    .await
-    .unwrap_or(None)?;
-
-    let room_id: String = row.try_get("room_id").unwrap_or_default();
-    let required_role: String = row.try_get("required_role").unwrap_or_else(|_| "user".to_string());
-    if room_id.is_empty() {
-        return None;
+pub(crate) async fn apply_block_filter(
+    pool: &SqlitePool,
+    viewer_id: &str,
+    filter: BlockedFilter,
+    messages: &mut Vec<Message>,
+) {
+    if filter == BlockedFilter::Show {
+        return;
     }
-
-    if required_role != "user" && role != "admin" && role != required_role {
-        return None;
+    let blocked = crate::relationships::fetch_blocked_ids(pool, viewer_id).await;
+    if blocked.is_empty() {
+        return;
     }
+    // System messages (topic changes...) are never hidden.
+    let hidden = |m: &Message| m.kind == "default" && blocked.contains(&m.user_id);
+    match filter {
+        BlockedFilter::Omit => messages.retain(|m| !hidden(m)),
+        _ => {
+            for message in messages.iter_mut() {
+                message.author_blocked = hidden(message);
+            }
+        }
+    }
+}
+
+/// The room of a live message, if `claims` can see it and has `permission` there.
+pub(crate) async fn can_access_message_room(
+    pool: &SqlitePool,
+    message_id: &str,
+    claims: &crate::auth::Claims,
+    permission: u64,
+) -> Option<String> {
+    let room_id: String = sqlx::query_scalar("SELECT room_id FROM messages WHERE id = ? AND deleted_at IS NULL")
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)?;
 
-    Some(room_id)
+    let granted = crate::permissions::room_permissions(pool, &room_id, &claims.sub, &claims.role).await?;
+    let needed = crate::permissions::VIEW_ROOM | permission;
+    (granted & needed == needed).then_some(room_id)
 }
 
-/// GET /api/rooms/{room_id}/messages — Fetch message history
+/// GET /api/rooms/{room_id}/messages?before=|after=|around=&limit= — One page of message history
+/// (NDJSON with `Accept: application/x-ndjson`, see `export`)
 pub async fn get_messages(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
     path: web::Path<String>,
+    query: web::Query<HistoryQuery>,
 ) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
@@ -168,39 +375,169 @@ pub async fn get_messages(
     };
 
     let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
 
-    let room_role: Option<String> = sqlx::query_scalar("SELECT required_role FROM rooms WHERE id = ?")
-        .bind(&room_id)
-        .fetch_optional(pool.get_ref())
+    let cursors = [&query.before, &query.after, &query.around];
+    if cursors.iter().filter(|c| c.is_some()).count() > 1 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Use only one of before, after and around" }));
+    }
+    let limit = crate::pagination::limit(query.limit, DEFAULT_HISTORY_LIMIT, MAX_HISTORY_LIMIT);
+    let with_deleted = sees_deleted(pool.get_ref(), &claims.role).await;
+
+    let mut page = match (&query.before, &query.after, &query.around) {
+        (Some(id), _, _) => match cursor_position(pool.get_ref(), &room_id, id).await {
+            Some(at) => {
+                let (messages, more) = fetch_page(pool.get_ref(), &room_id, with_deleted, Some((&at, id)), Direction::Older, limit).await;
+                // The cursor itself is newer than the whole page.
+                HistoryPage { messages, has_more_before: more, has_more_after: true }
+            }
+            None => return unknown_cursor(),
+        },
+        (_, Some(id), _) => match cursor_position(pool.get_ref(), &room_id, id).await {
+            Some(at) => {
+                let (messages, more) = fetch_page(pool.get_ref(), &room_id, with_deleted, Some((&at, id)), Direction::Newer, limit).await;
+                HistoryPage { messages, has_more_before: true, has_more_after: more }
+            }
+            None => return unknown_cursor(),
+        },
+        (_, _, Some(id)) => match cursor_position(pool.get_ref(), &room_id, id).await {
+            Some(at) => {
+                // The target is the first "newer" row, hence the inclusive bound.
+                let newer_limit = limit / 2 + 1;
+                let (mut messages, has_more_before) =
+                    fetch_page(pool.get_ref(), &room_id, with_deleted, Some((&at, id)), Direction::Older, limit - newer_limit).await;
+                let (newer, has_more_after) =
+                    fetch_page(pool.get_ref(), &room_id, with_deleted, Some((&at, id)), Direction::NewerInclusive, newer_limit).await;
+                messages.extend(newer);
+                HistoryPage { messages, has_more_before, has_more_after }
+            }
+            None => return unknown_cursor(),
+        },
+        _ => {
+            let (messages, more) = fetch_page(pool.get_ref(), &room_id, with_deleted, None, Direction::Older, limit).await;
+            HistoryPage { messages, has_more_before: more, has_more_after: false }
+        }
+    };
+
+    apply_block_filter(pool.get_ref(), &claims.sub, query.blocked, &mut page.messages).await;
+    enrich_messages_with_reactions(pool.get_ref(), &mut page.messages).await;
+    crate::emojis::attach_custom_emojis(pool.get_ref(), &mut page.messages).await;
+    crate::files::attach_to_messages(pool.get_ref(), &mut page.messages).await;
+    crate::polls::attach_to_messages(pool.get_ref(), Some(&claims.sub), &mut page.messages).await;
+
+    if crate::export::wants_ndjson(&req) {
+        let trailer = serde_json::json!({
+            "page": { "has_more_before": page.has_more_before, "has_more_after": page.has_more_after }
+        });
+        return crate::export::ndjson_response(page.messages, Some(trailer));
+    }
+    HttpResponse::Ok().json(page)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Older,
+    Newer,
+    NewerInclusive,
+}
+
+pub(crate) fn unknown_cursor() -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": "Cursor message not found in this room" }))
+}
+
+/// `created_at` of a cursor message, if it belongs to `room_id`.
+pub(crate) async fn cursor_position(pool: &SqlitePool, room_id: &str, message_id: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT created_at FROM messages WHERE id = ? AND room_id = ?")
+        .bind(message_id)
+        .bind(room_id)
+        .fetch_optional(pool)
         .await
-        .unwrap_or(None);
+        .unwrap_or(None)
+}
 
-    let Some(required_role) = room_role else {
-        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+/// Up to `limit` messages next to `cursor` (`(created_at, id)`), or the
+/// latest ones without a cursor, oldest first, plus whether more exist in
+/// that direction. Seeks on idx_messages_room_created_id, so the cost does
+/// not grow with the depth of the page.
+pub(crate) async fn fetch_page(
+    pool: &SqlitePool,
+    room_id: &str,
+    with_deleted: bool,
+    cursor: Option<(&str, &str)>,
+    direction: Direction,
+    limit: i64,
+) -> (Vec<Message>, bool) {
+    let (bound, order) = match direction {
+        Direction::Older => ("AND (m.created_at, m.id) < (?, ?)", "DESC"),
+        Direction::Newer => ("AND (m.created_at, m.id) > (?, ?)", "ASC"),
+        Direction::NewerInclusive => ("AND (m.created_at, m.id) >= (?, ?)", "ASC"),
     };
+    let sql = format!(
+        "{MESSAGE_SELECT} WHERE m.room_id = ? {} {} ORDER BY m.created_at {order}, m.id {order} LIMIT ?",
+        if cursor.is_some() { bound } else { "" },
+        if with_deleted { "" } else { "AND m.deleted_at IS NULL" },
+    );
 
-    if required_role != "user" && claims.role != "admin" && claims.role != required_role {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Access denied for this room" }));
+    let mut q = sqlx::query(&sql).bind(room_id);
+    if let Some((created_at, id)) = cursor {
+        q = q.bind(created_at).bind(id);
     }
+    // One extra row tells whether there is more (also with `limit` 0).
+    let rows = q.bind(limit + 1).fetch_all(pool).await.unwrap_or_default();
 
-    let rows = sqlx::query(
-        "SELECT m.id, m.room_id, m.user_id, m.username, m.content, m.reply_to_id, m.created_at, m.image_url, m.pinned_at, m.pinned_by, u.avatar_url \
-         FROM messages m LEFT JOIN users u ON m.user_id = u.id \
-         WHERE m.room_id = ? ORDER BY m.created_at ASC LIMIT 200"
-    )
-    .bind(&room_id)
-    .fetch_all(pool.get_ref())
-    .await
-    .unwrap_or_default();
+    let has_more = rows.len() as i64 > limit;
+    let mut messages: Vec<Message> = rows.iter().take(limit as usize).map(message_from_row).collect();
+    if direction == Direction::Older {
+        messages.reverse();
+    }
+    (messages, has_more)
+}
 
-    let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+/// Tombstone message `id` for `deleted_by` and broadcast `message_deleted`.
+/// Its image and reactions go with the purge. Returns false when it was
+/// already deleted.
+pub(crate) async fn tombstone(
+    pool: &SqlitePool,
+    broadcaster: &crate::ws::Broadcaster,
+    id: &str,
+    deleted_by: &str,
+) -> Result<bool, sqlx::Error> {
+    let deleted: Option<(String, Option<String>)> = sqlx::query_as(
+        "UPDATE messages SET deleted_at = ?, deleted_by = ?, pinned_at = NULL, pinned_by = NULL \
+         WHERE id = ? AND deleted_at IS NULL RETURNING room_id, post_id"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(deleted_by)
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((room_id, post_id)) = deleted else {
+        return Ok(false);
+    };
 
-    enrich_messages_with_reactions(pool.get_ref(), &mut messages).await;
+    // A deleted message no longer counts as a mention
+    let _ = sqlx::query("DELETE FROM message_mentions WHERE message_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await;
+    if let Some(post_id) = &post_id {
+        crate::forum::refresh_replies(pool, Some(post_id)).await;
+    }
 
-    HttpResponse::Ok().json(messages)
+    let event = serde_json::json!({
+        "type": "message_deleted",
+        "id": id,
+        "room_id": room_id,
+        "deleted_by": deleted_by
+    });
+    let _ = broadcaster.send(event.to_string());
+    Ok(true)
 }
 
-/// DELETE /api/messages/{id}
+/// DELETE /api/messages/{id} — Delete a message. It stays as a tombstone only
+/// moderators can see until `MESSAGE_TOMBSTONE_RETENTION_DAYS` have passed.
 pub async fn delete_message(
     req: actix_web::HttpRequest,
     pool: web::Data<SqlitePool>,
@@ -218,8 +555,7 @@ pub async fn delete_message(
 
     // 1. Fetch message to check ownership and get room_id
     let msg_row = sqlx::query(
-        "SELECT m.id, m.room_id, m.user_id, m.username, m.content, m.reply_to_id, m.created_at, m.image_url, m.pinned_at, m.pinned_by, u.avatar_url \
-         FROM messages m LEFT JOIN users u ON m.user_id = u.id WHERE m.id = ?"
+        &format!("{MESSAGE_SELECT} WHERE m.id = ? AND m.deleted_at IS NULL")
     )
         .bind(&message_id)
         .fetch_optional(pool.get_ref())
@@ -232,82 +568,390 @@ pub async fn delete_message(
     };
 
     // 2. Check permissions
-    if msg.user_id != claims.sub && claims.role != "admin" {
+    if msg.user_id != claims.sub
+        && crate::permissions::require(pool.get_ref(), &msg.room_id, &claims, crate::permissions::MANAGE_MESSAGES).await.is_err()
+    {
         return HttpResponse::Forbidden().json(serde_json::json!({ "error": "You can only delete your own messages" }));
     }
 
-    // 3. Delete uploaded image if any
-    if let Some(ref url) = msg.image_url {
-        // SECURITY: Prevent path traversal
-        let clean_path = url.trim_start_matches('/');
-        if clean_path.starts_with("uploads/") && !clean_path.contains("..") {
-             std::fs::remove_file(clean_path).ok();
-        }
+    // 3. Tombstone and broadcast it
+    if tombstone(pool.get_ref(), broadcaster.get_ref(), &message_id, &claims.sub).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditMessage {
+    pub content: String,
+}
+
+/// PATCH /api/messages/{id} — Edit your own message, keeping the previous content as a revision
+pub async fn edit_message(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<EditMessage>,
+    broadcaster: web::Data<crate::ws::Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let message_id = path.into_inner();
+    let content = body.content.trim();
+    if content.chars().count() > MAX_CONTENT_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Message too long (max 4000 chars)" }));
     }
 
-    // 4. Delete related reactions + message from DB
-    let _ = sqlx::query("DELETE FROM message_reactions WHERE message_id = ?")
+    let msg = match sqlx::query(&format!("{MESSAGE_SELECT} WHERE m.id = ? AND m.deleted_at IS NULL"))
         .bind(&message_id)
-        .execute(pool.get_ref())
-        .await;
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None)
+    {
+        Some(row) => message_from_row(&row),
+        None => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Message not found" })),
+    };
+
+    if msg.user_id != claims.sub || msg.kind != "default" {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "You can only edit your own messages" }));
+    }
+    if content.is_empty() && msg.image_url.is_none() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Message cannot be empty" }));
+    }
+    if content == msg.content {
+        return HttpResponse::Ok().json(msg);
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let revision = sqlx::query(
+        "INSERT INTO message_revisions (message_id, content, created_at, replaced_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(&message_id)
+    .bind(&msg.content)
+    .bind(msg.edited_at.as_deref().unwrap_or(&msg.created_at))
+    .bind(&now)
+    .execute(&mut *tx)
+    .await;
+    if revision.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    // Guarded on the content we read, so two concurrent edits can't both win.
+    let updated = sqlx::query("UPDATE messages SET content = ?, edited_at = ? WHERE id = ? AND content = ? AND deleted_at IS NULL")
+        .bind(content)
+        .bind(&now)
+        .bind(&message_id)
+        .bind(&msg.content)
+        .execute(&mut *tx)
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+    if updated == 0 {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Message was changed meanwhile, try again" }));
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
 
-    let _ = sqlx::query("DELETE FROM messages WHERE id = ?")
+    let previously_mentioned = crate::mentions::mentioned_users(pool.get_ref(), &message_id).await;
+    let _ = sqlx::query("DELETE FROM message_mentions WHERE message_id = ?")
         .bind(&message_id)
         .execute(pool.get_ref())
         .await;
+    let mention_ids = crate::mentions::record_mentions(pool.get_ref(), &message_id, &msg.room_id, &claims.sub, content, &msg.created_at).await;
+    // Only users the edit pings for the first time are notified.
+    let newly_mentioned: Vec<String> = mention_ids.into_iter().filter(|id| !previously_mentioned.contains(id)).collect();
+    crate::unfurl::mark_pending(pool.get_ref(), &message_id, content).await;
+    let custom_emojis = crate::emojis::resolve_content(pool.get_ref(), content).await;
 
-    // 5. Broadcast
     let event = serde_json::json!({
-        "type": "message_deleted",
+        "type": "message_updated",
         "id": message_id,
-        "room_id": msg.room_id
+        "room_id": msg.room_id,
+        "user_id": msg.user_id,
+        "content": content,
+        "edited_at": now,
+        "custom_emojis": custom_emojis,
     });
     let _ = broadcaster.send(event.to_string());
+    crate::mentions::notify_mentions(pool.get_ref(), broadcaster.get_ref(), &newly_mentioned, &crate::mentions::MentionSource {
+        message_id: &message_id,
+        room_id: &msg.room_id,
+        author_id: &msg.user_id,
+        author_username: &msg.username,
+        content,
+    })
+    .await;
 
-    HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" }))
+    let mut edited = Message { content: content.to_string(), edited_at: Some(now), custom_emojis, ..msg };
+    crate::files::attach_to_messages(pool.get_ref(), std::slice::from_mut(&mut edited)).await;
+    HttpResponse::Ok().json(edited)
 }
 
-/// GET /api/rooms/{room_id}/pins — List pinned messages
-pub async fn get_pinned_messages(
+#[derive(Debug, Serialize)]
+pub struct MessageRevision {
+    pub content: String,
+    /// When this version was written (the message's creation or a previous edit).
+    pub created_at: String,
+    pub replaced_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageHistory {
+    pub message: Message,
+    /// Earlier versions, oldest first. The current one is `message.content`.
+    pub revisions: Vec<MessageRevision>,
+    /// Moderators only, for deleted messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+}
+
+/// GET /api/messages/{id}/history — Edit history of a message (its author, or a moderator)
+pub async fn message_history(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
     path: web::Path<String>,
 ) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
-        None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+        None => return HttpResponse::Unauthorized().finish(),
     };
 
-    let room_id = path.into_inner();
-
-    let room_role: Option<String> = sqlx::query_scalar("SELECT required_role FROM rooms WHERE id = ?")
-        .bind(&room_id)
+    let message_id = path.into_inner();
+    let Some(row) = sqlx::query(&format!("{MESSAGE_SELECT} WHERE m.id = ?"))
+        .bind(&message_id)
         .fetch_optional(pool.get_ref())
         .await
-        .unwrap_or(None);
+        .unwrap_or(None)
+    else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Message not found" }));
+    };
+    let message = message_from_row(&row);
 
-    let Some(required_role) = room_role else {
-        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    let moderator = sees_deleted(pool.get_ref(), &claims.role).await;
+    let visible = if message.deleted_at.is_some() { moderator } else { moderator || message.user_id == claims.sub };
+    if !visible {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Message not found" }));
+    }
+
+    let revisions = sqlx::query("SELECT content, created_at, replaced_at FROM message_revisions WHERE message_id = ? ORDER BY id")
+        .bind(&message_id)
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|r| MessageRevision {
+            content: r.get("content"),
+            created_at: r.get("created_at"),
+            replaced_at: r.get("replaced_at"),
+        })
+        .collect();
+
+    let deleted_by = if message.deleted_at.is_some() {
+        sqlx::query_scalar("SELECT deleted_by FROM messages WHERE id = ?")
+            .bind(&message_id)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or(None)
+    } else {
+        None
     };
-    if required_role != "user" && claims.role != "admin" && claims.role != required_role {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Access denied for this room" }));
+
+    HttpResponse::Ok().json(MessageHistory { message, revisions, deleted_by })
+}
+
+fn tombstone_retention_days() -> i64 {
+    std::env::var("MESSAGE_TOMBSTONE_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|d| *d >= 0)
+        .unwrap_or(DEFAULT_TOMBSTONE_RETENTION_DAYS)
+}
+
+fn tombstone_cutoff(retention_days: i64) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now() - chrono::Duration::days(retention_days)
+}
+
+/// Hard-delete tombstones past retention, with their uploaded images.
+/// Revisions, reactions and mentions go with them (ON DELETE CASCADE).
+async fn purge_tombstones(pool: &SqlitePool, storage: &crate::files::FileStore, retention_days: i64) -> Result<u64, String> {
+    let cutoff = tombstone_cutoff(retention_days).to_rfc3339();
+    let images: Vec<Option<String>> = sqlx::query_scalar("SELECT image_url FROM messages WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+        .bind(&cutoff)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let purged = sqlx::query("DELETE FROM messages WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+        .bind(&cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for url in images.into_iter().flatten() {
+        crate::uploads::remove(pool, storage, Some(&url)).await;
+    }
+    Ok(purged.rows_affected())
+}
+
+/// Tombstones past retention still in the table.
+async fn tombstone_backlog(pool: &SqlitePool, retention_days: i64) -> crate::jobs::Backlog {
+    let cutoff = tombstone_cutoff(retention_days).to_rfc3339();
+    let mut backlog = sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(deleted_at) AS oldest_due_at FROM messages WHERE deleted_at IS NOT NULL AND deleted_at < ?"
+    )
+    .bind(&cutoff)
+    .fetch_one(pool)
+    .await
+    .map(|row| crate::jobs::Backlog::from_row(&row))
+    .unwrap_or_default();
+    // Due once retention is over, not when deleted.
+    backlog.oldest_due_at = backlog
+        .oldest_due_at
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| (at + chrono::Duration::days(retention_days)).to_rfc3339());
+    backlog
+}
+
+pub fn spawn_tombstone_purger(pool: SqlitePool, storage: crate::files::FileStorage, jobs: crate::jobs::JobRegistry) {
+    let retention_days = tombstone_retention_days();
+    if retention_days == 0 {
+        return;
+    }
+    crate::jobs::register(&jobs, crate::jobs::MESSAGE_TOMBSTONES);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(TOMBSTONE_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(
+                &jobs,
+                crate::jobs::MESSAGE_TOMBSTONES,
+                None,
+                purge_tombstones(&pool, &storage, retention_days),
+                tombstone_backlog(&pool, retention_days),
+            )
+            .await;
+        }
+    });
+}
+
+/// A pinned message with who pinned it.
+#[derive(Debug, Serialize)]
+pub struct PinnedMessage {
+    #[serde(flatten)]
+    pub message: Message,
+    pub pinned_by_username: Option<String>,
+    pub pinned_by_avatar_url: Option<String>,
+}
+
+async fn room_pin_count(pool: &SqlitePool, room_id: &str) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE room_id = ? AND pinned_at IS NOT NULL AND deleted_at IS NULL")
+        .bind(room_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0)
+}
+
+/// Broadcast a pin change of `message_id`. `pinned` is `(pinned_at, pinned_by)`
+/// for a pin, `None` for an unpin.
+async fn broadcast_pin_update(
+    pool: &SqlitePool,
+    broadcaster: &crate::ws::Broadcaster,
+    room_id: &str,
+    message_id: &str,
+    pinned: Option<(&str, &str)>,
+) {
+    let pinned_by_username: Option<String> = match pinned {
+        Some((_, user_id)) => sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+    let event = serde_json::json!({
+        "type": "message_pin_update",
+        "id": message_id,
+        "room_id": room_id,
+        "pinned": pinned.is_some(),
+        "pinned_at": pinned.map(|(at, _)| at),
+        "pinned_by": pinned.map(|(_, by)| by),
+        "pinned_by_username": pinned_by_username,
+        "pin_count": room_pin_count(pool, room_id).await,
+    });
+    let _ = broadcaster.send(event.to_string());
+}
+
+/// GET /api/rooms/{room_id}/pins — List pinned messages, newest pin first, with who pinned them
+pub async fn get_pinned_messages(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    settings: web::Data<crate::config::Settings>,
+    path: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
     }
 
     let rows = sqlx::query(
-        "SELECT m.id, m.room_id, m.user_id, m.username, m.content, m.reply_to_id, m.created_at, m.image_url, m.pinned_at, m.pinned_by, u.avatar_url \
-         FROM messages m LEFT JOIN users u ON m.user_id = u.id \
-         WHERE m.room_id = ? AND m.pinned_at IS NOT NULL ORDER BY m.pinned_at DESC LIMIT 50"
+        &format!("{MESSAGE_SELECT} WHERE m.room_id = ? AND m.pinned_at IS NOT NULL AND m.deleted_at IS NULL ORDER BY m.pinned_at DESC LIMIT ?")
     )
     .bind(&room_id)
+    .bind(settings.limits.pins_per_room)
     .fetch_all(pool.get_ref())
     .await
     .unwrap_or_default();
 
     let mut messages: Vec<Message> = rows.iter().map(message_from_row).collect();
 
+    apply_block_filter(pool.get_ref(), &claims.sub, query.blocked, &mut messages).await;
     enrich_messages_with_reactions(pool.get_ref(), &mut messages).await;
+    crate::emojis::attach_custom_emojis(pool.get_ref(), &mut messages).await;
+    crate::files::attach_to_messages(pool.get_ref(), &mut messages).await;
+    crate::polls::attach_to_messages(pool.get_ref(), Some(&claims.sub), &mut messages).await;
+
+    let mut pinners: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+    for pinner_id in messages.iter().filter_map(|m| m.pinned_by.clone()) {
+        if pinners.contains_key(&pinner_id) {
+            continue;
+        }
+        let pinner = sqlx::query("SELECT username, avatar_url FROM users WHERE id = ?")
+            .bind(&pinner_id)
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None)
+            .map(|row| (row.try_get("username").unwrap_or(None), row.try_get("avatar_url").unwrap_or(None)))
+            .unwrap_or((None, None));
+        pinners.insert(pinner_id, pinner);
+    }
 
-    HttpResponse::Ok().json(messages)
+    let pins: Vec<PinnedMessage> = messages
+        .into_iter()
+        .map(|message| {
+            let (pinned_by_username, pinned_by_avatar_url) = message
+                .pinned_by
+                .as_ref()
+                .and_then(|id| pinners.get(id).cloned())
+                .unwrap_or((None, None));
+            PinnedMessage { message, pinned_by_username, pinned_by_avatar_url }
+        })
+        .collect();
+
+    HttpResponse::Ok().json(pins)
 }
 
 /// POST /api/messages/{id}/reactions
@@ -324,16 +968,16 @@ pub async fn add_reaction(
     };
 
     let message_id = path.into_inner();
-    let Some(emoji) = normalize_emoji(&body.emoji) else {
+    let Some((emoji, custom_emoji)) = resolve_reaction(pool.get_ref(), &body).await else {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid emoji" }));
     };
 
-    let Some(room_id) = can_access_message_room(pool.get_ref(), &message_id, &claims.role).await else {
+    let Some(room_id) = can_access_message_room(pool.get_ref(), &message_id, &claims, crate::permissions::ADD_REACTIONS).await else {
         return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Access denied" }));
     };
 
     let now = chrono::Utc::now().to_rfc3339();
-    let _ = sqlx::query(
+    let added = sqlx::query(
         "INSERT OR IGNORE INTO message_reactions (message_id, user_id, emoji, created_at) VALUES (?, ?, ?, ?)"
     )
     .bind(&message_id)
@@ -341,7 +985,12 @@ pub async fn add_reaction(
     .bind(&emoji)
     .bind(&now)
     .execute(pool.get_ref())
-    .await;
+    .await
+    .is_ok_and(|r| r.rows_affected() == 1);
+    if added {
+        crate::emoji_usage::record(pool.get_ref(), std::slice::from_ref(&emoji), crate::emoji_usage::UsageSource::Reaction).await;
+        crate::forum::refresh_votes(pool.get_ref(), Some(&message_id)).await;
+    }
 
     let reaction_users = sqlx::query_scalar::<_, String>(
         "SELECT user_id FROM message_reactions WHERE message_id = ? AND emoji = ? ORDER BY created_at ASC"
@@ -357,6 +1006,7 @@ pub async fn add_reaction(
         "room_id": room_id,
         "message_id": message_id,
         "emoji": emoji,
+        "custom_emoji": custom_emoji,
         "count": reaction_users.len(),
         "user_ids": reaction_users,
     });
@@ -379,11 +1029,11 @@ pub async fn remove_reaction(
     };
 
     let message_id = path.into_inner();
-    let Some(emoji) = normalize_emoji(&body.emoji) else {
+    let Some((emoji, custom_emoji)) = resolve_reaction(pool.get_ref(), &body).await else {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid emoji" }));
     };
 
-    let Some(room_id) = can_access_message_room(pool.get_ref(), &message_id, &claims.role).await else {
+    let Some(room_id) = can_access_message_room(pool.get_ref(), &message_id, &claims, 0).await else {
         return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Access denied" }));
     };
 
@@ -393,6 +1043,7 @@ pub async fn remove_reaction(
         .bind(&emoji)
         .execute(pool.get_ref())
         .await;
+    crate::forum::refresh_votes(pool.get_ref(), Some(&message_id)).await;
 
     let reaction_users = sqlx::query_scalar::<_, String>(
         "SELECT user_id FROM message_reactions WHERE message_id = ? AND emoji = ? ORDER BY created_at ASC"
@@ -408,6 +1059,7 @@ pub async fn remove_reaction(
         "room_id": room_id,
         "message_id": message_id,
         "emoji": emoji,
+        "custom_emoji": custom_emoji,
         "count": reaction_users.len(),
         "user_ids": reaction_users,
     });
@@ -416,10 +1068,12 @@ pub async fn remove_reaction(
     HttpResponse::Ok().json(event)
 }
 
-/// POST /api/messages/{id}/pin — Pin message (admin only)
+/// POST /api/messages/{id}/pin — Pin message (MANAGE_MESSAGES). Pinning a pinned
+/// message does nothing; a room holds at most `limits.pins_per_room` pins.
 pub async fn pin_message(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
+    settings: web::Data<crate::config::Settings>,
     path: web::Path<String>,
     broadcaster: web::Data<crate::ws::Broadcaster>,
 ) -> HttpResponse {
@@ -428,13 +1082,9 @@ pub async fn pin_message(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
-    }
-
     let message_id = path.into_inner();
 
-    let msg_room: Option<String> = sqlx::query_scalar("SELECT room_id FROM messages WHERE id = ?")
+    let msg_room: Option<String> = sqlx::query_scalar("SELECT room_id FROM messages WHERE id = ? AND deleted_at IS NULL")
         .bind(&message_id)
         .fetch_optional(pool.get_ref())
         .await
@@ -443,32 +1093,60 @@ pub async fn pin_message(
     let Some(room_id) = msg_room else {
         return HttpResponse::NotFound().json(serde_json::json!({ "error": "Message not found" }));
     };
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
 
+    // The limit is checked in the same statement so concurrent pins can't exceed it.
+    let limit = settings.limits.pins_per_room;
     let now = chrono::Utc::now().to_rfc3339();
-    let result = sqlx::query("UPDATE messages SET pinned_at = ?, pinned_by = ? WHERE id = ?")
-        .bind(&now)
-        .bind(&claims.sub)
-        .bind(&message_id)
-        .execute(pool.get_ref())
-        .await;
+    let result = sqlx::query(
+        "UPDATE messages SET pinned_at = ?, pinned_by = ? WHERE id = ? AND pinned_at IS NULL \
+         AND (SELECT COUNT(*) FROM messages WHERE room_id = ? AND pinned_at IS NOT NULL AND deleted_at IS NULL) < ?"
+    )
+    .bind(&now)
+    .bind(&claims.sub)
+    .bind(&message_id)
+    .bind(&room_id)
+    .bind(limit)
+    .execute(pool.get_ref())
+    .await;
 
     match result {
-        Ok(_) => {
-            let event = serde_json::json!({
-                "type": "message_pinned",
-                "id": message_id,
-                "room_id": room_id,
-                "pinned_at": now,
-                "pinned_by": claims.sub,
-            });
-            let _ = broadcaster.send(event.to_string());
+        Ok(r) if r.rows_affected() > 0 => {
+            broadcast_pin_update(pool.get_ref(), broadcaster.get_ref(), &room_id, &message_id, Some((&now, &claims.sub))).await;
+            crate::rooms::post_system_message(
+                pool.get_ref(),
+                broadcaster.get_ref(),
+                &room_id,
+                &claims.sub,
+                &claims.username,
+                "message_pinned",
+                "pinned a message to this room",
+            )
+            .await;
             HttpResponse::Ok().json(serde_json::json!({ "status": "pinned" }))
         }
+        Ok(_) => {
+            let already_pinned: bool = sqlx::query_scalar("SELECT pinned_at IS NOT NULL FROM messages WHERE id = ?")
+                .bind(&message_id)
+                .fetch_one(pool.get_ref())
+                .await
+                .unwrap_or(false);
+            if already_pinned {
+                HttpResponse::Ok().json(serde_json::json!({ "status": "pinned" }))
+            } else {
+                HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("This room already has {limit} pinned messages, unpin one first"),
+                    "pin_limit": limit,
+                }))
+            }
+        }
         Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to pin message" })),
     }
 }
 
-/// DELETE /api/messages/{id}/pin — Unpin message (admin only)
+/// DELETE /api/messages/{id}/pin — Unpin message (MANAGE_MESSAGES)
 pub async fn unpin_message(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
@@ -480,10 +1158,6 @@ pub async fn unpin_message(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
-    }
-
     let message_id = path.into_inner();
 
     let msg_room: Option<String> = sqlx::query_scalar("SELECT room_id FROM messages WHERE id = ?")
@@ -495,40 +1169,46 @@ pub async fn unpin_message(
     let Some(room_id) = msg_room else {
         return HttpResponse::NotFound().json(serde_json::json!({ "error": "Message not found" }));
     };
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
+    }
 
-    let result = sqlx::query("UPDATE messages SET pinned_at = NULL, pinned_by = NULL WHERE id = ?")
+    let result = sqlx::query("UPDATE messages SET pinned_at = NULL, pinned_by = NULL WHERE id = ? AND pinned_at IS NOT NULL")
         .bind(&message_id)
         .execute(pool.get_ref())
         .await;
 
     match result {
-        Ok(_) => {
-            let event = serde_json::json!({
-                "type": "message_unpinned",
-                "id": message_id,
-                "room_id": room_id,
-            });
-            let _ = broadcaster.send(event.to_string());
+        Ok(r) => {
+            if r.rows_affected() > 0 {
+                broadcast_pin_update(pool.get_ref(), broadcaster.get_ref(), &room_id, &message_id, None).await;
+                crate::rooms::post_system_message(
+                    pool.get_ref(),
+                    broadcaster.get_ref(),
+                    &room_id,
+                    &claims.sub,
+                    &claims.username,
+                    "message_unpinned",
+                    "unpinned a message from this room",
+                )
+                .await;
+            }
             HttpResponse::Ok().json(serde_json::json!({ "status": "unpinned" }))
         }
         Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to unpin message" })),
     }
 }
 
-/// DELETE /api/users/{id}/messages — Admin purge all messages from one user
+/// DELETE /api/users/{id}/messages — Purge all messages from one user (MANAGE_MESSAGES)
 pub async fn delete_user_messages(
-    req: actix_web::HttpRequest,
+    req: HttpRequest,
+    Sudo(claims): Sudo,
     pool: web::Data<SqlitePool>,
     path: web::Path<String>,
     broadcaster: web::Data<crate::ws::Broadcaster>,
 ) -> HttpResponse {
-    let claims = match extract_claims(&req) {
-        Some(c) => c,
-        None => return HttpResponse::Unauthorized().finish(),
-    };
-
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MESSAGES).await {
+        return response;
     }
 
     let target_user_id = path.into_inner();
@@ -540,12 +1220,22 @@ pub async fn delete_user_messages(
 
     match result {
         Ok(res) => {
+            crate::forum::refresh_replies(pool.get_ref(), None).await;
             let event = serde_json::json!({
                 "type": "messages_purged",
                 "user_id": target_user_id,
                 "count": res.rows_affected()
             });
             let _ = broadcaster.send(event.to_string());
+            crate::audit_log::record(
+                pool.get_ref(),
+                Some(&claims),
+                crate::audit_log::Action::MessagesPurged,
+                &target_user_id,
+                serde_json::json!({ "count": res.rows_affected() }),
+                crate::audit_log::reason(&req).as_deref(),
+            )
+            .await;
 
             HttpResponse::Ok().json(serde_json::json!({
                 "status": "purged",
@@ -556,7 +1246,16 @@ pub async fn delete_user_messages(
     }
 }
 
-/// GET /api/messages/search — Advanced message search
+const MESSAGE_SEARCH: crate::pagination::Listing = crate::pagination::Listing {
+    name: "message_search",
+    default_limit: 80,
+    max_limit: 200,
+    sorts: &[("created_at", "m.created_at")],
+    default_sort: "-created_at",
+    tiebreak: "m.id",
+};
+
+/// GET /api/messages/search — Advanced message search (NDJSON with `Accept: application/x-ndjson`)
 pub async fn search_messages(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
@@ -568,87 +1267,91 @@ pub async fn search_messages(
     };
 
     if let Some(room_id) = &query.room_id {
-        let room_role: Option<String> = sqlx::query_scalar("SELECT required_role FROM rooms WHERE id = ?")
-            .bind(room_id)
-            .fetch_optional(pool.get_ref())
-            .await
-            .unwrap_or(None);
-
-        let Some(required_role) = room_role else {
-            return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
-        };
-        if required_role != "user" && claims.role != "admin" && claims.role != required_role {
-            return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Access denied for this room" }));
+        if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), room_id, &claims).await {
+            return response;
         }
     }
 
-    let limit = query.limit.unwrap_or(80).clamp(1, 200);
-    let mut sql = String::from(
-        "SELECT m.id, m.room_id, m.user_id, m.username, m.content, m.reply_to_id, m.created_at, m.image_url, m.pinned_at, m.pinned_by, u.avatar_url \
-         FROM messages m \
-         LEFT JOIN users u ON m.user_id = u.id \
-         LEFT JOIN rooms r ON m.room_id = r.id \
-         WHERE 1=1"
-    );
-
-    if claims.role != "admin" {
-        sql.push_str(" AND (r.required_role = 'user' OR r.required_role = ?)");
+    let page = match MESSAGE_SEARCH.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let text = crate::pagination::filter(&query.q);
+    let author = crate::pagination::filter(&query.author);
+    let from = crate::pagination::filter(&query.from);
+    let to = crate::pagination::filter(&query.to);
+    let mut sql = format!("{MESSAGE_SELECT} LEFT JOIN rooms r ON m.room_id = r.id WHERE 1=1");
+
+    // SQLite accepts an empty `IN ()`, which matches nothing.
+    let viewable: Option<Vec<String>> = if claims.role != "admin" {
+        Some(crate::permissions::viewable_rooms(pool.get_ref(), &claims.sub, &claims.role).await.into_iter().collect())
+    } else {
+        None
+    };
+    if let Some(rooms) = &viewable {
+        sql.push_str(&format!(" AND m.room_id IN ({})", vec!["?"; rooms.len()].join(", ")));
+    }
+    if !sees_deleted(pool.get_ref(), &claims.role).await {
+        sql.push_str(" AND m.deleted_at IS NULL");
     }
 
     if query.room_id.is_some() {
         sql.push_str(" AND m.room_id = ?");
     }
-    if query.q.as_ref().map(|v| !v.trim().is_empty()).unwrap_or(false) {
+    if text.is_some() {
         sql.push_str(" AND m.content LIKE ?");
     }
-    if query.author.as_ref().map(|v| !v.trim().is_empty()).unwrap_or(false) {
+    if author.is_some() {
         sql.push_str(" AND m.username LIKE ?");
     }
-    if query.from.as_ref().map(|v| !v.trim().is_empty()).unwrap_or(false) {
+    if from.is_some() {
         sql.push_str(" AND m.created_at >= ?");
     }
-    if query.to.as_ref().map(|v| !v.trim().is_empty()).unwrap_or(false) {
+    if to.is_some() {
         sql.push_str(" AND m.created_at <= ?");
     }
-
-    sql.push_str(" ORDER BY m.created_at DESC LIMIT ?");
+    sql.push_str(&page.keyset_clause());
+    sql.push_str(&page.order_clause());
 
     let mut qx = sqlx::query(&sql);
-    if claims.role != "admin" {
-        qx = qx.bind(&claims.role);
+    for room_id in viewable.iter().flatten() {
+        qx = qx.bind(room_id);
     }
     if let Some(room_id) = &query.room_id {
         qx = qx.bind(room_id);
     }
-    if let Some(value) = &query.q {
-        if !value.trim().is_empty() {
-            qx = qx.bind(format!("%{}%", value.trim()));
-        }
+    if let Some(value) = &text {
+        qx = qx.bind(format!("%{value}%"));
     }
-    if let Some(value) = &query.author {
-        if !value.trim().is_empty() {
-            qx = qx.bind(format!("%{}%", value.trim()));
-        }
+    if let Some(value) = &author {
+        qx = qx.bind(format!("%{value}%"));
     }
-    if let Some(value) = &query.from {
-        if !value.trim().is_empty() {
-            qx = qx.bind(format!("{}T00:00:00", value.trim()));
-        }
+    if let Some(value) = &from {
+        qx = qx.bind(format!("{value}T00:00:00"));
     }
-    if let Some(value) = &query.to {
-        if !value.trim().is_empty() {
-            qx = qx.bind(format!("{}T23:59:59", value.trim()));
-        }
+    if let Some(value) = &to {
+        qx = qx.bind(format!("{value}T23:59:59"));
+    }
+    for value in page.keyset_binds() {
+        qx = qx.bind(value);
     }
-    qx = qx.bind(limit);
+    qx = qx.bind(page.fetch_limit());
 
     let rows = qx.fetch_all(pool.get_ref()).await.unwrap_or_default();
-    let mut messages: Vec<Message> = Vec::with_capacity(rows.len());
-    for row in rows {
-        messages.push(message_from_row(&row));
-    }
+    let messages: Vec<Message> = rows.iter().map(message_from_row).collect();
+    // The cursor comes before the block filter, which can shorten the page.
+    let (mut messages, next_cursor) = page.finish(messages, |m| (m.created_at.clone(), m.id.clone()));
 
+    apply_block_filter(pool.get_ref(), &claims.sub, query.blocked, &mut messages).await;
     enrich_messages_with_reactions(pool.get_ref(), &mut messages).await;
-
-    HttpResponse::Ok().json(messages)
+    crate::emojis::attach_custom_emojis(pool.get_ref(), &mut messages).await;
+    crate::files::attach_to_messages(pool.get_ref(), &mut messages).await;
+    crate::polls::attach_to_messages(pool.get_ref(), Some(&claims.sub), &mut messages).await;
+
+    let response = if crate::export::wants_ndjson(&req) {
+        crate::export::ndjson_response(messages, None)
+    } else {
+        HttpResponse::Ok().json(messages)
+    };
+    crate::pagination::with_next_cursor(response, next_cursor)
 }
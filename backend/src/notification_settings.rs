@@ -0,0 +1,340 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Notification settings
+// ═══════════════════════════════════════════════════════
+//
+// `notification_settings` holds each user's defaults (the row without a
+// room) and their per-room overrides:
+//   level              all | mentions | nothing: what to be notified of.
+//                      `nothing` silences mentions too. Rooms without one
+//                      follow the default, `all` unless changed
+//   muted              muted for good, or until `muted_until`; a muted
+//                      default mutes every room
+//   suppress_everyone  `@room` mentions don't notify; being named (or one's
+//                      role) still does. Rooms without one follow the default
+//
+// The server applies them to what it sends out about mentions: the
+// `mention` event (see `mentions::notify_mentions`) and the email digest
+// (see `notifications`). Mentions are still recorded and counted as unread.
+// Notifying of plain messages (`all`) is left to clients, which read the
+// settings here.
+//
+// `PATCH /api/users/@me/notification-settings` updates the defaults and up
+// to `MAX_ROOMS_PER_UPDATE` rooms at once; `null` puts a field back to the
+// default. The caller's connections get `notification_settings_updated`
+// with the result.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+const LEVELS: [&str; 3] = ["all", "mentions", "nothing"];
+const DEFAULT_LEVEL: &str = "all";
+const MAX_ROOMS_PER_UPDATE: usize = 100;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Settings {
+    /// `None` in a room: the default applies.
+    pub level: Option<String>,
+    pub suppress_everyone: Option<bool>,
+    pub muted: bool,
+    /// When the mute ends; `None` while muted is for good.
+    pub muted_until: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomSettings {
+    pub room_id: String,
+    #[serde(flatten)]
+    pub settings: Settings,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationSettings {
+    pub defaults: Settings,
+    pub rooms: Vec<RoomSettings>,
+}
+
+fn settings_from_row(row: &sqlx::sqlite::SqliteRow) -> Settings {
+    Settings {
+        level: row.get("level"),
+        suppress_everyone: row.get("suppress_everyone"),
+        muted: row.get("muted"),
+        muted_until: row.get("muted_until"),
+    }
+}
+
+impl Settings {
+    fn is_empty(&self) -> bool {
+        self.level.is_none() && self.suppress_everyone.is_none() && !self.muted
+    }
+}
+
+/// Muted right now.
+fn muting(muted: Option<bool>, until: Option<&str>, now: &str) -> bool {
+    muted == Some(true) && until.is_none_or(|until| until > now)
+}
+
+pub(crate) async fn load(pool: &SqlitePool, user_id: &str) -> Result<NotificationSettings, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT room_id, level, suppress_everyone, muted, muted_until FROM notification_settings WHERE user_id = ? ORDER BY room_id"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let mut defaults = Settings::default();
+    let mut rooms = Vec::new();
+    for row in &rows {
+        match row.get::<Option<String>, _>("room_id") {
+            Some(room_id) => rooms.push(RoomSettings { room_id, settings: settings_from_row(row) }),
+            None => defaults = settings_from_row(row),
+        }
+    }
+    defaults.level.get_or_insert_with(|| DEFAULT_LEVEL.to_string());
+    defaults.suppress_everyone.get_or_insert(false);
+    Ok(NotificationSettings { defaults, rooms })
+}
+
+/// Of `user_ids`, mentioned in `room_id` by `content`, those whose settings
+/// let the mention notify them, in the same order.
+pub(crate) async fn mention_recipients(pool: &SqlitePool, room_id: &str, content: &str, user_ids: &[String]) -> Vec<String> {
+    if user_ids.is_empty() {
+        return Vec::new();
+    }
+    let sql = format!(
+        "SELECT u.id, lower(u.username) AS username, lower(u.role) AS role, \
+         d.level AS default_level, d.suppress_everyone AS default_suppress, d.muted AS default_muted, d.muted_until AS default_until, \
+         r.level, r.suppress_everyone, r.muted, r.muted_until \
+         FROM users u \
+         LEFT JOIN notification_settings d ON d.user_id = u.id AND d.room_id IS NULL \
+         LEFT JOIN notification_settings r ON r.user_id = u.id AND r.room_id = ? \
+         WHERE u.id IN ({})",
+        vec!["?"; user_ids.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql).bind(room_id);
+    for user_id in user_ids {
+        query = query.bind(user_id);
+    }
+    // Settings can't be read: better an unwanted ping than a missed one.
+    let Ok(rows) = query.fetch_all(pool).await else {
+        return user_ids.to_vec();
+    };
+
+    let names = crate::mentions::parse_mentions(content);
+    let now = Utc::now().to_rfc3339();
+    let allowed: Vec<String> = rows
+        .iter()
+        .filter(|row| {
+            let default_until: Option<String> = row.get("default_until");
+            let until: Option<String> = row.get("muted_until");
+            if muting(row.get("default_muted"), default_until.as_deref(), &now) || muting(row.get("muted"), until.as_deref(), &now) {
+                return false;
+            }
+            let level: Option<String> = row.get::<Option<String>, _>("level").or(row.get("default_level"));
+            if level.as_deref() == Some("nothing") {
+                return false;
+            }
+            let suppress: Option<bool> = row.get::<Option<bool>, _>("suppress_everyone").or(row.get("default_suppress"));
+            let named = names.contains(&row.get::<String, _>("username")) || names.contains(&row.get::<String, _>("role"));
+            suppress != Some(true) || named
+        })
+        .map(|row| row.get("id"))
+        .collect();
+    user_ids.iter().filter(|id| allowed.contains(id)).cloned().collect()
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// Tells a `null` field apart from a missing one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Fields to change; `null` puts one back to the default.
+#[derive(Debug, Default, Deserialize)]
+pub struct SettingsPatch {
+    #[serde(default, deserialize_with = "present")]
+    pub level: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub suppress_everyone: Option<Option<bool>>,
+    pub muted: Option<bool>,
+    #[serde(default, deserialize_with = "present")]
+    pub muted_until: Option<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomSettingsPatch {
+    pub room_id: String,
+    #[serde(flatten)]
+    pub patch: SettingsPatch,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SettingsUpdate {
+    pub defaults: Option<SettingsPatch>,
+    #[serde(default)]
+    pub rooms: Vec<RoomSettingsPatch>,
+}
+
+impl SettingsPatch {
+    /// `current` with the patch applied, or why it can't be.
+    fn apply(&self, mut current: Settings) -> Result<Settings, String> {
+        if let Some(level) = &self.level {
+            if let Some(level) = level.as_deref().filter(|l| !LEVELS.contains(l)) {
+                return Err(format!("Unknown level {level:?} (all, mentions or nothing)"));
+            }
+            current.level = level.clone();
+        }
+        if let Some(suppress) = self.suppress_everyone {
+            current.suppress_everyone = suppress;
+        }
+        if let Some(until) = &self.muted_until {
+            current.muted_until = match until {
+                Some(raw) => {
+                    let until = chrono::DateTime::parse_from_rfc3339(raw)
+                        .map_err(|_| "muted_until must be an RFC 3339 time".to_string())?
+                        .with_timezone(&Utc);
+                    if until <= Utc::now() {
+                        return Err("muted_until must be in the future".to_string());
+                    }
+                    // A time to mute until mutes, unless told otherwise.
+                    current.muted = true;
+                    Some(until.to_rfc3339())
+                }
+                None => None,
+            };
+        }
+        if let Some(muted) = self.muted {
+            current.muted = muted;
+        }
+        if !current.muted {
+            current.muted_until = None;
+        }
+        Ok(current)
+    }
+}
+
+async fn stored(conn: &mut sqlx::SqliteConnection, user_id: &str, room_id: Option<&str>) -> Result<Settings, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT level, suppress_everyone, muted, muted_until FROM notification_settings WHERE user_id = ? AND room_id IS ?"
+    )
+    .bind(user_id)
+    .bind(room_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row.map(|row| settings_from_row(&row)).unwrap_or_default())
+}
+
+/// GET /api/users/@me/notification-settings — The caller's default and per-room notification settings
+pub async fn get_settings(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match load(pool.get_ref(), &claims.sub).await {
+        Ok(settings) => HttpResponse::Ok().json(settings),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// PATCH /api/users/@me/notification-settings — Update the defaults and any number of rooms at once
+pub async fn update_settings(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<SettingsUpdate>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let update = body.into_inner();
+    if update.rooms.len() > MAX_ROOMS_PER_UPDATE {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {MAX_ROOMS_PER_UPDATE} rooms per update")
+        }));
+    }
+    let mut room_ids: Vec<&str> = update.rooms.iter().map(|r| r.room_id.as_str()).collect();
+    room_ids.sort_unstable();
+    room_ids.dedup();
+    if !room_ids.is_empty() {
+        let sql = format!("SELECT id FROM rooms WHERE id IN ({})", vec!["?"; room_ids.len()].join(", "));
+        let mut query = sqlx::query_scalar::<_, String>(&sql);
+        for room_id in &room_ids {
+            query = query.bind(*room_id);
+        }
+        let known = query.fetch_all(pool.get_ref()).await.unwrap_or_default();
+        if let Some(unknown) = room_ids.iter().find(|id| !known.iter().any(|k| k == *id)) {
+            return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found", "room_id": unknown }));
+        }
+    }
+
+    let changes = update
+        .defaults
+        .iter()
+        .map(|patch| (None, patch))
+        .chain(update.rooms.iter().map(|r| (Some(r.room_id.as_str()), &r.patch)));
+    let now = Utc::now().to_rfc3339();
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    for (room_id, patch) in changes {
+        let current = match stored(&mut tx, &claims.sub, room_id).await {
+            Ok(current) => current,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        let next = match patch.apply(current) {
+            Ok(next) => next,
+            Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error, "room_id": room_id })),
+        };
+        let deleted = sqlx::query("DELETE FROM notification_settings WHERE user_id = ? AND room_id IS ?")
+            .bind(&claims.sub)
+            .bind(room_id)
+            .execute(&mut *tx)
+            .await;
+        if deleted.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+        if next.is_empty() {
+            continue;
+        }
+        let inserted = sqlx::query(
+            "INSERT INTO notification_settings (user_id, room_id, level, suppress_everyone, muted, muted_until, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&claims.sub)
+        .bind(room_id)
+        .bind(&next.level)
+        .bind(next.suppress_everyone)
+        .bind(next.muted)
+        .bind(&next.muted_until)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await;
+        if inserted.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let settings = match load(pool.get_ref(), &claims.sub).await {
+        Ok(settings) => settings,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let event = serde_json::json!({
+        "type": "notification_settings_updated",
+        "recipient_id": claims.sub,
+        "settings": settings,
+    });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(settings)
+}
@@ -0,0 +1,475 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Email notifications and digests
+// ═══════════════════════════════════════════════════════
+//
+// Users with a verified address (see `email`) opt in, kind by kind, through
+// `GET/PATCH /api/users/@me/email/notifications`:
+//   mention_digest   once a day, the mentions they haven't read yet
+//   friend_requests  when someone sends them a friend request
+//   security         a login from a device or address none of their
+//                    earlier sessions used, a Discord account linked
+//                    (or a QR login) or unlinked
+//
+// Every email comes from a template: a subject line, a blank line, then the
+// body, with `{{name}}` placeholders. The built-in ones (`TEMPLATES`) can be
+// replaced by `<template>.txt` files in EMAIL_TEMPLATES_DIR.
+//
+// Event emails go out in the background, so a slow SMTP server never holds
+// up the request behind them; failures are logged. The `email_digests` job
+// checks every `DIGEST_CHECK_INTERVAL` for users whose last digest is
+// `DIGEST_INTERVAL_HOURS` old, and mails them the mentions since, left
+// unread (see `read_states`). A user with nothing unread gets no email.
+// Quiet hours (see `quiet_hours`) hold a digest back until they end, and
+// drop friend request emails.
+//
+// Config (env):
+//   EMAIL_TEMPLATES_DIR   directory of template overrides
+//   (sending itself: SMTP_URL and MAIL_FROM, see `email`)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+
+use crate::audit::AuthEvent;
+use crate::auth::extract_claims;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::sessions::DeviceInfo;
+
+const DIGEST_INTERVAL_HOURS: i64 = 24;
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+/// Mentions quoted in one digest; the rest are only counted.
+const MAX_DIGEST_MENTIONS: usize = 20;
+const EXCERPT_CHARS: usize = 140;
+
+/// `(name, subject and body)` of the built-in templates.
+const TEMPLATES: [(&str, &str); 5] = [
+    (
+        "mention_digest",
+        "{{count}} mention(s) non lue(s) sur Voxium\n\n\
+         Bonjour {{username}},\n\n\
+         Vous avez été mentionné(e) {{count}} fois sans avoir encore lu ces messages :\n\n\
+         {{mentions}}\n\n\
+         Ces résumés se désactivent dans les paramètres de notification de votre compte.\n",
+    ),
+    (
+        "friend_request",
+        "{{sender}} vous a envoyé une demande d'ami\n\n\
+         Bonjour {{username}},\n\n\
+         {{sender}} souhaite vous ajouter en ami sur Voxium. Ouvrez Voxium pour accepter ou refuser.\n",
+    ),
+    (
+        "new_login",
+        "Nouvelle connexion à votre compte Voxium\n\n\
+         Bonjour {{username}},\n\n\
+         Une connexion à votre compte vient d'avoir lieu depuis un appareil ou une adresse inconnus :\n\
+         appareil : {{device}}\n\
+         adresse IP : {{ip}}\n\
+         date : {{time}}\n\n\
+         Si ce n'était pas vous, changez votre mot de passe et révoquez cette session dans vos paramètres.\n",
+    ),
+    (
+        "discord_linked",
+        "Un compte Discord a été lié à votre compte Voxium\n\n\
+         Bonjour {{username}},\n\n\
+         Un compte Discord vient d'être lié à votre compte ({{method}}) depuis l'adresse {{ip}}, le {{time}}.\n\n\
+         Si ce n'était pas vous, supprimez la liaison et changez votre mot de passe.\n",
+    ),
+    (
+        "discord_unlinked",
+        "Le compte Discord a été délié de votre compte Voxium\n\n\
+         Bonjour {{username}},\n\n\
+         Le compte Discord lié à votre compte vient d'être retiré depuis l'adresse {{ip}}, le {{time}}.\n\n\
+         Si ce n'était pas vous, changez votre mot de passe.\n",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    FriendRequests,
+    Security,
+}
+
+impl Kind {
+    fn column(self) -> &'static str {
+        match self {
+            Kind::FriendRequests => "friend_requests",
+            Kind::Security => "security",
+        }
+    }
+}
+
+// ── Templates ───────────────────────────────────────────
+
+fn template_source(name: &str) -> Option<String> {
+    if let Some(dir) = std::env::var("EMAIL_TEMPLATES_DIR").ok().filter(|d| !d.trim().is_empty()) {
+        let path = std::path::Path::new(dir.trim()).join(format!("{name}.txt"));
+        match std::fs::read_to_string(&path) {
+            Ok(source) => return Some(source),
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => eprintln!("⚠️  Email template {}: {e}", path.display()),
+            Err(_) => {}
+        }
+    }
+    TEMPLATES.iter().find(|(n, _)| *n == name).map(|(_, source)| source.to_string())
+}
+
+/// `(subject, body)` of the template `name` with `vars` filled in.
+/// Placeholders without a value are left as they are.
+pub fn render(name: &str, vars: &[(&str, String)]) -> Option<(String, String)> {
+    let mut text = template_source(name)?.replace("\r\n", "\n");
+    for (key, value) in vars {
+        text = text.replace(&format!("{{{{{key}}}}}"), value);
+    }
+    let (subject, body) = text.split_once('\n').unwrap_or((text.as_str(), ""));
+    Some((subject.trim().to_string(), body.trim_start_matches('\n').to_string()))
+}
+
+// ── Sending ─────────────────────────────────────────────
+
+/// Username and verified address of `user_id`, if they opted in to `kind`.
+async fn recipient(pool: &SqlitePool, user_id: &str, kind: Kind) -> Option<(String, String)> {
+    let sql = format!(
+        "SELECT u.username, u.email FROM users u JOIN email_preferences p ON p.user_id = u.id \
+         WHERE u.id = ? AND p.{} = 1 AND u.email IS NOT NULL AND u.email_verified_at IS NOT NULL",
+        kind.column()
+    );
+    let row = sqlx::query(&sql).bind(user_id).fetch_optional(pool).await.ok().flatten()?;
+    Some((row.get("username"), row.get("email")))
+}
+
+/// Mail `template` to `user_id` in the background, if they opted in to `kind`.
+fn notify(pool: &SqlitePool, user_id: &str, kind: Kind, template: &'static str, mut vars: Vec<(&'static str, String)>) {
+    let pool = pool.clone();
+    let user_id = user_id.to_string();
+    // Not tied to the worker: audit entries are also written from gateway tasks.
+    tokio::spawn(async move {
+        let Some((username, email)) = recipient(&pool, &user_id, kind).await else {
+            return;
+        };
+        vars.push(("username", username));
+        let Some((subject, body)) = render(template, &vars) else {
+            return;
+        };
+        if let Err(e) = crate::email::send_mail(&email, &subject, body).await {
+            eprintln!("⚠️  Email notification {template} to {user_id} failed: {e}");
+        }
+    });
+}
+
+fn now_text() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn unknown(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| "inconnu(e)".to_string())
+}
+
+/// `target_id` just received a friend request from `sender_id`.
+pub(crate) async fn friend_request(pool: &SqlitePool, target_id: &str, sender_id: &str) {
+    let sender: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+        .bind(sender_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    // Not held for later: the request itself waits for them.
+    if crate::quiet_hours::is_quiet(pool, target_id).await {
+        return;
+    }
+    if let Some(sender) = sender {
+        notify(pool, target_id, Kind::FriendRequests, "friend_request", vec![("sender", sender)]);
+    }
+}
+
+/// `session_id` was just opened for `user_id`: mail them when its device and
+/// address match none of their earlier sessions. A first session is not news.
+pub(crate) async fn new_session(pool: &SqlitePool, user_id: &str, session_id: &str, device: &DeviceInfo) {
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS earlier, COALESCE(MAX(ip IS ? AND user_agent IS ?), 0) AS known FROM sessions WHERE user_id = ? AND id != ?"
+    )
+    .bind(&device.ip)
+    .bind(&device.user_agent)
+    .bind(user_id)
+    .bind(session_id)
+    .fetch_one(pool)
+    .await;
+    let Ok(row) = row else {
+        return;
+    };
+    if row.get::<i64, _>("earlier") == 0 || row.get::<i64, _>("known") != 0 {
+        return;
+    }
+    let name = device.name.clone().or_else(|| device.user_agent.clone());
+    notify(
+        pool,
+        user_id,
+        Kind::Security,
+        "new_login",
+        vec![("device", unknown(&name)), ("ip", unknown(&device.ip)), ("time", now_text())],
+    );
+}
+
+/// A security event just written to the auth audit trail (see `audit`),
+/// mailed when it changes the linked Discord account: logging in with
+/// Discord stores the token again each time, which is no news.
+pub(crate) async fn auth_event(pool: &SqlitePool, user_id: &str, event: AuthEvent, device: &DeviceInfo, details: &serde_json::Value) {
+    let template = match event {
+        AuthEvent::DiscordLinked | AuthEvent::QrLoginCompleted => "discord_linked",
+        AuthEvent::DiscordUnlinked => "discord_unlinked",
+        _ => return,
+    };
+    if details.get("new_account").and_then(serde_json::Value::as_bool) == Some(true) {
+        return;
+    }
+    if template == "discord_linked" {
+        let previous: Option<(String, String)> = sqlx::query_as(
+            "SELECT event, details FROM auth_audit WHERE user_id = ? \
+             AND event IN ('discord_linked', 'qr_login_completed', 'discord_unlinked') \
+             ORDER BY created_at DESC LIMIT 1 OFFSET 1"
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+        let same_account = previous.is_some_and(|(previous, previous_details)| {
+            previous != "discord_unlinked"
+                && serde_json::from_str::<serde_json::Value>(&previous_details).ok().and_then(|d| d.get("discord_id").cloned())
+                    == details.get("discord_id").cloned()
+        });
+        if same_account {
+            return;
+        }
+    }
+    let via = details.get("via").and_then(serde_json::Value::as_str).unwrap_or("token").to_string();
+    notify(
+        pool,
+        user_id,
+        Kind::Security,
+        template,
+        vec![("method", via), ("ip", unknown(&device.ip)), ("time", now_text())],
+    );
+}
+
+// ── Mention digests ─────────────────────────────────────
+
+/// Users due a digest: opted in, verified, and their last one old enough.
+async fn due_users(pool: &SqlitePool) -> Result<Vec<sqlx::sqlite::SqliteRow>, String> {
+    let cutoff = (Utc::now() - chrono::Duration::hours(DIGEST_INTERVAL_HOURS)).to_rfc3339();
+    sqlx::query(
+        "SELECT p.user_id, p.digest_sent_at, u.username, u.email FROM email_preferences p JOIN users u ON u.id = p.user_id \
+         WHERE p.mention_digest = 1 AND u.email IS NOT NULL AND u.email_verified_at IS NOT NULL \
+         AND (p.digest_sent_at IS NULL OR p.digest_sent_at < ?)"
+    )
+    .bind(&cutoff)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Mail each user due one the mentions they left unread since their last
+/// digest. Returns how many digests were sent.
+pub async fn send_digests(pool: &SqlitePool) -> Result<u64, String> {
+    let now = Utc::now();
+    let mut sent = 0;
+    let mut failure = None;
+    for user in due_users(pool).await? {
+        let user_id: String = user.get("user_id");
+        // Due again on the first run after their quiet hours.
+        if crate::quiet_hours::is_quiet(pool, &user_id).await {
+            continue;
+        }
+        let since = user
+            .get::<Option<String>, _>("digest_sent_at")
+            .unwrap_or_else(|| (now - chrono::Duration::hours(DIGEST_INTERVAL_HOURS)).to_rfc3339());
+        let mentions = sqlx::query(
+            "SELECT mm.room_id, r.name AS room_name, m.username AS author, m.content FROM message_mentions mm \
+             JOIN messages m ON m.id = mm.message_id JOIN rooms r ON r.id = mm.room_id \
+             LEFT JOIN read_states rs ON rs.user_id = mm.user_id AND rs.room_id = mm.room_id \
+             WHERE mm.user_id = ? AND mm.created_at > ? AND m.deleted_at IS NULL \
+             AND (mm.created_at, mm.message_id) > (COALESCE(rs.last_read_at, ''), COALESCE(rs.last_read_message_id, '')) \
+             ORDER BY mm.created_at, mm.message_id"
+        )
+        .bind(&user_id)
+        .bind(&since)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        // Rooms muted (or set to `nothing`) now stay out of the digest.
+        let mut kept = Vec::with_capacity(mentions.len());
+        for row in mentions {
+            let room_id: String = row.get("room_id");
+            let content: String = row.get("content");
+            let recipients = crate::notification_settings::mention_recipients(pool, &room_id, &content, std::slice::from_ref(&user_id)).await;
+            if !recipients.is_empty() {
+                kept.push(row);
+            }
+        }
+        let mentions = kept;
+
+        if !mentions.is_empty() {
+            let mut lines: Vec<String> = mentions
+                .iter()
+                .take(MAX_DIGEST_MENTIONS)
+                .map(|row| {
+                    let content: String = row.get("content");
+                    let mut excerpt: String = content.chars().take(EXCERPT_CHARS).collect();
+                    if content.chars().count() > EXCERPT_CHARS {
+                        excerpt.push('…');
+                    }
+                    format!("- #{}, {} : {}", row.get::<String, _>("room_name"), row.get::<String, _>("author"), excerpt)
+                })
+                .collect();
+            if mentions.len() > MAX_DIGEST_MENTIONS {
+                lines.push(format!("… et {} autre(s)", mentions.len() - MAX_DIGEST_MENTIONS));
+            }
+            let vars = [
+                ("username", user.get::<String, _>("username")),
+                ("count", mentions.len().to_string()),
+                ("mentions", lines.join("\n")),
+            ];
+            let Some((subject, body)) = render("mention_digest", &vars) else {
+                continue;
+            };
+            // A failed send is retried on the next run.
+            if let Err(e) = crate::email::send_mail(&user.get::<String, _>("email"), &subject, body).await {
+                failure.get_or_insert(format!("digest to {user_id}: {e}"));
+                continue;
+            }
+            sent += 1;
+        }
+        sqlx::query("UPDATE email_preferences SET digest_sent_at = ? WHERE user_id = ?")
+            .bind(now.to_rfc3339())
+            .bind(&user_id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(sent),
+    }
+}
+
+async fn digest_backlog(pool: &SqlitePool) -> Backlog {
+    let cutoff = (Utc::now() - chrono::Duration::hours(DIGEST_INTERVAL_HOURS)).to_rfc3339();
+    let mut backlog = sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(p.digest_sent_at) AS oldest_due_at FROM email_preferences p JOIN users u ON u.id = p.user_id \
+         WHERE p.mention_digest = 1 AND u.email IS NOT NULL AND u.email_verified_at IS NOT NULL \
+         AND (p.digest_sent_at IS NULL OR p.digest_sent_at < ?)"
+    )
+    .bind(&cutoff)
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default();
+    backlog.oldest_due_at = backlog
+        .oldest_due_at
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| (at + chrono::Duration::hours(DIGEST_INTERVAL_HOURS)).to_rfc3339());
+    backlog
+}
+
+pub fn spawn_digest_sender(pool: SqlitePool, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::EMAIL_DIGESTS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(DIGEST_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::EMAIL_DIGESTS, None, send_digests(&pool), digest_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct EmailPreferences {
+    pub mention_digest: bool,
+    pub friend_requests: bool,
+    pub security: bool,
+    /// Without a verified address nothing is sent, whatever is enabled.
+    pub email_verified: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreferencesPatch {
+    pub mention_digest: Option<bool>,
+    pub friend_requests: Option<bool>,
+    pub security: Option<bool>,
+}
+
+pub(crate) async fn fetch_preferences(pool: &SqlitePool, user_id: &str) -> Result<EmailPreferences, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT u.email_verified_at IS NOT NULL AND u.email IS NOT NULL AS email_verified, \
+         COALESCE(p.mention_digest, 0) AS mention_digest, COALESCE(p.friend_requests, 0) AS friend_requests, \
+         COALESCE(p.security, 0) AS security \
+         FROM users u LEFT JOIN email_preferences p ON p.user_id = u.id WHERE u.id = ?"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(EmailPreferences {
+        mention_digest: row.get("mention_digest"),
+        friend_requests: row.get("friend_requests"),
+        security: row.get("security"),
+        email_verified: row.get("email_verified"),
+    })
+}
+
+/// GET /api/users/@me/email/notifications — Which emails the caller opted in to
+pub async fn get_preferences(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match fetch_preferences(pool.get_ref(), &claims.sub).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// PATCH /api/users/@me/email/notifications — Opt in to or out of emails, kind by kind
+pub async fn update_preferences(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    body: web::Json<PreferencesPatch>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let current = match fetch_preferences(pool.get_ref(), &claims.sub).await {
+        Ok(current) => current,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let enabling = [body.mention_digest, body.friend_requests, body.security].contains(&Some(true));
+    if enabling && !current.email_verified {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Verify your email address first" }));
+    }
+
+    // A digest enabled now covers mentions from now on.
+    let now = Utc::now().to_rfc3339();
+    let digest_from = (body.mention_digest == Some(true) && !current.mention_digest).then(|| now.clone());
+    let result = sqlx::query(
+        "INSERT INTO email_preferences (user_id, mention_digest, friend_requests, security, digest_sent_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(user_id) DO UPDATE SET mention_digest = excluded.mention_digest, friend_requests = excluded.friend_requests, \
+         security = excluded.security, digest_sent_at = COALESCE(?, digest_sent_at), updated_at = excluded.updated_at"
+    )
+    .bind(&claims.sub)
+    .bind(body.mention_digest.unwrap_or(current.mention_digest))
+    .bind(body.friend_requests.unwrap_or(current.friend_requests))
+    .bind(body.security.unwrap_or(current.security))
+    .bind(&digest_from)
+    .bind(&now)
+    .bind(&digest_from)
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match fetch_preferences(pool.get_ref(), &claims.sub).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
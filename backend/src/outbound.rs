@@ -0,0 +1,313 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Outgoing requests to user-chosen URLs
+// ═══════════════════════════════════════════════════════
+//
+// Every feature that makes the server fetch a URL a user chose (link
+// previews and their oEmbed endpoints today) goes through `get`, which
+// applies one policy against SSRF and abuse:
+//   - allowed schemes and ports only, no credentials in the URL
+//   - optional host allowlist, and a denylist (a host matches itself and
+//     its subdomains)
+//   - the host must resolve to public addresses only (no loopback, private,
+//     link-local, CGNAT, multicast... ranges), and the connection is pinned
+//     to the address that was checked (no proxy), so DNS can't change in
+//     between
+//   - redirects are followed by hand, each hop checked the same way
+//   - a rate limit per destination host, shared by every feature, so users
+//     can't make the server hammer a site
+//   - the feature's timeout and body size cap
+//
+// Requests to endpoints the operator configured (Discord API, S3 storage)
+// don't go through here: they may well be private on purpose.
+//
+// Config (env):
+//   OUTBOUND_ALLOWED_SCHEMES  default `http,https`
+//   OUTBOUND_ALLOWED_PORTS    default `80,443`
+//   OUTBOUND_ALLOWED_HOSTS    when set, only these hosts are fetched
+//   OUTBOUND_DENIED_HOSTS     hosts never fetched
+//   OUTBOUND_RATE_LIMIT       per destination host, `<requests>/<seconds>` or
+//                             `off` (default 30/60)
+//   OUTBOUND_ALLOW_PRIVATE    `1` skips the address and port checks, for
+//                             development only (`LINK_PREVIEW_ALLOW_PRIVATE`
+//                             is still read)
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+
+use crate::ratelimit::Limit;
+
+const DEFAULT_SCHEMES: &[&str] = &["http", "https"];
+const DEFAULT_PORTS: &[u16] = &[80, 443];
+const DEFAULT_RATE_LIMIT: Limit = Limit { requests: 30, period: Duration::from_secs(60) };
+/// Sweep idle buckets once the map grows past this.
+const MAX_TRACKED_HOSTS: usize = 10_000;
+
+/// How one feature fetches.
+pub(crate) struct Policy {
+    pub user_agent: &'static str,
+    pub timeout: Duration,
+    pub max_redirects: usize,
+    /// Bodies are cut after this many bytes.
+    pub max_body_bytes: usize,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The policy forbids the destination.
+    Blocked(String),
+    /// Too many requests to `host` lately; try again later.
+    RateLimited { host: String },
+    /// The request was allowed but failed.
+    Failed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Blocked(reason) => write!(f, "blocked: {reason}"),
+            Error::RateLimited { host } => write!(f, "rate limited: too many requests to {host}"),
+            Error::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+fn failed(e: impl fmt::Display) -> Error {
+    Error::Failed(e.to_string())
+}
+
+// ── Config ──────────────────────────────────────────────
+
+fn allow_private() -> bool {
+    ["OUTBOUND_ALLOW_PRIVATE", "LINK_PREVIEW_ALLOW_PRIVATE"]
+        .iter()
+        .any(|name| matches!(std::env::var(name).as_deref().map(str::trim), Ok("1") | Ok("true")))
+}
+
+/// Comma-separated, lowercased entries of `name`; `None` when unset or empty.
+fn list_from_env(name: &str) -> Option<Vec<String>> {
+    let raw = std::env::var(name).ok()?;
+    let entries: Vec<String> = raw
+        .split(',')
+        .map(|entry| entry.trim().trim_end_matches('.').to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect();
+    (!entries.is_empty()).then_some(entries)
+}
+
+fn scheme_allowed(scheme: &str) -> bool {
+    match list_from_env("OUTBOUND_ALLOWED_SCHEMES") {
+        Some(schemes) => schemes.iter().any(|s| s == scheme),
+        None => DEFAULT_SCHEMES.contains(&scheme),
+    }
+}
+
+fn port_allowed(port: u16) -> bool {
+    match list_from_env("OUTBOUND_ALLOWED_PORTS") {
+        Some(ports) => ports.iter().any(|p| p.parse() == Ok(port)),
+        None => DEFAULT_PORTS.contains(&port),
+    }
+}
+
+/// `host` is `entry` or one of its subdomains.
+fn host_matches(host: &str, entry: &str) -> bool {
+    host == entry || host.strip_suffix(entry).is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+fn rate_limit() -> Option<Limit> {
+    match std::env::var("OUTBOUND_RATE_LIMIT") {
+        Ok(raw) if !raw.trim().is_empty() => crate::ratelimit::parse_limit(&raw).unwrap_or_else(|e| {
+            eprintln!("⚠️  OUTBOUND_RATE_LIMIT: {e}, using the default");
+            Some(DEFAULT_RATE_LIMIT)
+        }),
+        _ => Some(DEFAULT_RATE_LIMIT),
+    }
+}
+
+// ── Destination checks ──────────────────────────────────
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_unspecified()
+                || v4.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (b & 0xC0) == 64) // CGNAT 100.64/10
+                || (a == 192 && b == 0 && c == 0) // IETF 192.0.0/24
+                || (a == 198 && (b & 0xFE) == 18)) // benchmarking 198.18/15
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let segments = v6.segments();
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (segments[0] & 0xFE00) == 0xFC00 // unique local fc00::/7
+                || (segments[0] & 0xFFC0) == 0xFE80 // link-local fe80::/10
+                || (segments[0] == 0x2001 && segments[1] == 0x0DB8) // documentation
+                || (segments[0] == 0x0064 && segments[1] == 0xFF9B)) // NAT64
+        }
+    }
+}
+
+/// Check `url` against the policy and resolve it to an address that is safe
+/// to connect to. Does not count against the rate limit.
+pub async fn check(url: &Url, timeout: Duration) -> Result<SocketAddr, Error> {
+    if !scheme_allowed(url.scheme()) {
+        return Err(Error::Blocked(format!("scheme {} not allowed", url.scheme())));
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(Error::Blocked("credentials in URL".to_string()));
+    }
+    let port = url.port_or_known_default().ok_or_else(|| Error::Blocked("no port".to_string()))?;
+    if !port_allowed(port) && !allow_private() {
+        return Err(Error::Blocked(format!("port {port} not allowed")));
+    }
+    let host = url.host_str().ok_or_else(|| Error::Blocked("no host".to_string()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.').to_ascii_lowercase();
+    if list_from_env("OUTBOUND_ALLOWED_HOSTS").is_some_and(|allowed| !allowed.iter().any(|entry| host_matches(&host, entry))) {
+        return Err(Error::Blocked(format!("{host} is not on the allowlist")));
+    }
+    if list_from_env("OUTBOUND_DENIED_HOSTS").is_some_and(|denied| denied.iter().any(|entry| host_matches(&host, entry))) {
+        return Err(Error::Blocked(format!("{host} is denied")));
+    }
+
+    let addrs: Vec<SocketAddr> = tokio::time::timeout(timeout, tokio::net::lookup_host((host.as_str(), port)))
+        .await
+        .map_err(|_| failed("DNS timeout"))?
+        .map_err(failed)?
+        .collect();
+    if addrs.is_empty() {
+        return Err(failed("host did not resolve"));
+    }
+    // Every address must be public: the client must not get to pick one that isn't.
+    if !allow_private() && addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(Error::Blocked("host resolves to a non-public address".to_string()));
+    }
+    Ok(addrs[0])
+}
+
+// ── Rate limit ──────────────────────────────────────────
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+fn buckets() -> &'static Mutex<HashMap<String, Bucket>> {
+    static BUCKETS: OnceLock<Mutex<HashMap<String, Bucket>>> = OnceLock::new();
+    BUCKETS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Take a token from `host`'s bucket.
+fn take_token(host: &str) -> Result<(), Error> {
+    let Some(limit) = rate_limit() else {
+        return Ok(());
+    };
+    let now = Instant::now();
+    let capacity = limit.requests as f64;
+    let rate = limit.refill_per_sec();
+    let mut buckets = buckets().lock().unwrap_or_else(|e| e.into_inner());
+    if buckets.len() > MAX_TRACKED_HOSTS {
+        // A bucket idle for a full period is back to capacity, same as a new one.
+        buckets.retain(|_, b| now.duration_since(b.updated_at) < limit.period);
+    }
+    let bucket = buckets
+        .entry(host.to_ascii_lowercase())
+        .or_insert(Bucket { tokens: capacity, updated_at: now });
+    bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * rate).min(capacity);
+    bucket.updated_at = now;
+    if bucket.tokens < 1.0 {
+        return Err(Error::RateLimited { host: host.to_string() });
+    }
+    bucket.tokens -= 1.0;
+    Ok(())
+}
+
+// ── Requests ────────────────────────────────────────────
+
+/// An answer that passed the policy; its body is read with `body`.
+pub(crate) struct Response {
+    /// Where the last redirect led.
+    pub url: Url,
+    /// Lowercased, without parameters.
+    pub content_type: String,
+    inner: reqwest::Response,
+    max_body_bytes: usize,
+}
+
+impl Response {
+    /// The body, cut after the policy's size cap.
+    pub async fn body(mut self) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.inner.chunk().await.map_err(failed)? {
+            let room = self.max_body_bytes - body.len();
+            body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            if body.len() == self.max_body_bytes {
+                break;
+            }
+        }
+        Ok(body)
+    }
+}
+
+/// GET `url` under `policy`, following redirects by hand so that each hop gets checked.
+pub(crate) async fn get(policy: &Policy, url: &str, accept: &str) -> Result<Response, Error> {
+    let mut url = Url::parse(url).map_err(|e| Error::Blocked(e.to_string()))?;
+    for _ in 0..=policy.max_redirects {
+        let addr = check(&url, policy.timeout).await?;
+        let host = url.host_str().ok_or_else(|| Error::Blocked("no host".to_string()))?.to_string();
+        take_token(&host)?;
+        let client = reqwest::Client::builder()
+            .no_proxy()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(policy.timeout)
+            .user_agent(policy.user_agent)
+            .resolve(&host, addr)
+            .build()
+            .map_err(failed)?;
+        let response = client
+            .get(url.clone())
+            .header(reqwest::header::ACCEPT, accept)
+            .send()
+            .await
+            .map_err(failed)?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| failed("redirect without location"))?;
+            url = url.join(location).map_err(|e| Error::Blocked(e.to_string()))?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(Error::Failed(format!("HTTP {}", response.status())));
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        return Ok(Response { url, content_type, inner: response, max_body_bytes: policy.max_body_bytes });
+    }
+    Err(failed("too many redirects"))
+}
@@ -0,0 +1,214 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Pagination, filtering and sorting of listings
+// ═══════════════════════════════════════════════════════
+//
+// Listings take the same query parameters:
+//   limit   page size, clamped to the listing's maximum (its default when absent)
+//   sort    one of the listing's sort keys, `-key` for descending
+//   cursor  the `X-Next-Cursor` of the previous page
+// Filters are trimmed, and an empty one is the same as none.
+//
+// Pages are keyset, never offsets: a cursor holds the sort value and id of
+// the last row of its page, with the listing and sort it was made for. It is
+// signed with the JWT secret, so clients cannot forge positions, and a
+// cursor is refused by another listing or sort (`sort` can be left out when
+// following one). The next cursor comes in the `X-Next-Cursor` header, left
+// out on the last page, so bodies keep their shape. Listings that filter rows
+// after reading them (visibility, blocks) can return short pages: follow the
+// header, not the page size, to know when to stop.
+
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::HttpResponse;
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+/// Longest filter value kept; longer ones are cut.
+const MAX_FILTER_CHARS: usize = 200;
+
+/// Page size: `requested` within 1..=`max`, `default` when absent.
+pub fn limit(requested: Option<i64>, default: i64, max: i64) -> i64 {
+    requested.unwrap_or(default).clamp(1, max)
+}
+
+/// A filter parameter, trimmed; `None` when absent or empty.
+pub fn filter(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.chars().take(MAX_FILTER_CHARS).collect())
+}
+
+/// How a listing pages: its size bounds and what it sorts by.
+pub struct Listing {
+    /// Binds cursors to this listing.
+    pub name: &'static str,
+    pub default_limit: i64,
+    pub max_limit: i64,
+    /// Sort keys and their SQL column (never NULL).
+    pub sorts: &'static [(&'static str, &'static str)],
+    /// `key` or `-key`.
+    pub default_sort: &'static str,
+    /// Unique column ordering rows with the same sort value.
+    pub tiebreak: &'static str,
+}
+
+/// A parsed page request, see `Listing::page`.
+#[derive(Debug)]
+pub struct Page {
+    pub limit: i64,
+    /// `key` or `-key`, as given or taken from the cursor.
+    pub sort: String,
+    listing: &'static str,
+    column: &'static str,
+    tiebreak: &'static str,
+    descending: bool,
+    /// Sort value and tiebreak of the last row already sent.
+    after: Option<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CursorPayload {
+    listing: String,
+    sort: String,
+    value: String,
+    id: String,
+}
+
+fn bad_request(error: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+}
+
+fn cursor_signature(payload: &str) -> Vec<u8> {
+    crate::crypto::sign(&format!("cursor:{payload}"))
+}
+
+fn encode_cursor(payload: &CursorPayload) -> String {
+    let json = serde_json::to_vec(payload).unwrap_or_default();
+    let encoded = general_purpose::URL_SAFE_NO_PAD.encode(json);
+    let signature = general_purpose::URL_SAFE_NO_PAD.encode(cursor_signature(&encoded));
+    format!("{encoded}.{signature}")
+}
+
+fn decode_cursor(raw: &str) -> Option<CursorPayload> {
+    let (encoded, signature) = raw.trim().split_once('.')?;
+    let signature = general_purpose::URL_SAFE_NO_PAD.decode(signature).ok()?;
+    crate::crypto::signatures_match(&cursor_signature(encoded), &signature).then_some(())?;
+    let json = general_purpose::URL_SAFE_NO_PAD.decode(encoded).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+impl Listing {
+    /// Parse `limit`, `sort` and `cursor`, or answer 400.
+    #[allow(clippy::result_large_err)]
+    pub fn page(&self, limit: Option<i64>, sort: Option<&str>, cursor: Option<&str>) -> Result<Page, HttpResponse> {
+        let cursor = match cursor.map(str::trim).filter(|c| !c.is_empty()) {
+            Some(raw) => match decode_cursor(raw) {
+                Some(payload) if payload.listing == self.name => Some(payload),
+                _ => return Err(bad_request("Invalid cursor")),
+            },
+            None => None,
+        };
+        let sort = match (sort.map(str::trim).filter(|s| !s.is_empty()), &cursor) {
+            (Some(sort), Some(payload)) if sort != payload.sort => {
+                return Err(bad_request("The cursor was made for another sort"));
+            }
+            (Some(sort), _) => sort.to_string(),
+            (None, Some(payload)) => payload.sort.clone(),
+            (None, None) => self.default_sort.to_string(),
+        };
+        let (key, descending) = match sort.strip_prefix('-') {
+            Some(key) => (key, true),
+            None => (sort.as_str(), false),
+        };
+        let Some(&(_, column)) = self.sorts.iter().find(|(name, _)| *name == key) else {
+            let keys: Vec<&str> = self.sorts.iter().map(|(name, _)| *name).collect();
+            return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown sort", "sorts": keys })));
+        };
+
+        Ok(Page {
+            limit: crate::pagination::limit(limit, self.default_limit, self.max_limit),
+            listing: self.name,
+            column,
+            tiebreak: self.tiebreak,
+            descending,
+            after: cursor.map(|payload| (payload.value, payload.id)),
+            sort,
+        })
+    }
+}
+
+impl Page {
+    /// ` AND (...)` keeping the rows after the cursor, empty without one.
+    /// Bind `keyset_binds` where it goes.
+    pub fn keyset_clause(&self) -> String {
+        if self.after.is_none() {
+            return String::new();
+        }
+        let op = if self.descending { "<" } else { ">" };
+        format!(
+            " AND ({column} {op} ? OR ({column} = ? AND {tiebreak} {op} ?))",
+            column = self.column,
+            tiebreak = self.tiebreak
+        )
+    }
+
+    pub fn keyset_binds(&self) -> Vec<String> {
+        match &self.after {
+            Some((value, id)) => vec![value.clone(), value.clone(), id.clone()],
+            None => Vec::new(),
+        }
+    }
+
+    /// ` ORDER BY ... LIMIT ?`, to bind with `fetch_limit`.
+    pub fn order_clause(&self) -> String {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        format!(" ORDER BY {} {direction}, {} {direction} LIMIT ?", self.column, self.tiebreak)
+    }
+
+    /// One more row than the page, to know whether another page follows.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Cut the extra row off `rows` and make the next cursor from the last
+    /// row kept; `key` gives a row's sort value and tiebreak.
+    pub fn finish<T>(&self, mut rows: Vec<T>, key: impl Fn(&T) -> (String, String)) -> (Vec<T>, Option<String>) {
+        if rows.len() as i64 <= self.limit {
+            return (rows, None);
+        }
+        rows.truncate(self.limit as usize);
+        let next = rows.last().map(|row| {
+            let (value, id) = key(row);
+            encode_cursor(&CursorPayload {
+                listing: self.listing.to_string(),
+                sort: self.sort.clone(),
+                value,
+                id,
+            })
+        });
+        (rows, next)
+    }
+
+    /// The sort column as `sort_value`, to select for `finish_rows`.
+    pub fn sort_value_column(&self) -> String {
+        format!("CAST({} AS TEXT) AS sort_value", self.column)
+    }
+
+    /// `finish` for rows selected with `sort_value_column`; `id` is the
+    /// tiebreak's name in the row.
+    pub fn finish_rows(&self, rows: Vec<SqliteRow>, id: &str) -> (Vec<SqliteRow>, Option<String>) {
+        self.finish(rows, |row| (row.get("sort_value"), row.get(id)))
+    }
+}
+
+/// `response` with the `X-Next-Cursor` header when another page follows.
+pub fn with_next_cursor(mut response: HttpResponse, next: Option<String>) -> HttpResponse {
+    if let Some(value) = next.and_then(|n| HeaderValue::from_str(&n).ok()) {
+        response.headers_mut().insert(HeaderName::from_static(NEXT_CURSOR_HEADER), value);
+    }
+    response
+}
@@ -0,0 +1,269 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Discord username/password login (+ MFA)
+// ═══════════════════════════════════════════════════════
+//
+// Alternative to the QR flow for users without their phone at hand.
+// Implements Discord's `/auth/login` endpoint; when the account has 2FA
+// enabled Discord answers with an MFA ticket, which we keep server-side
+// and redeem through `/auth/mfa/{totp,sms,backup}` once the user
+// submits a code. The resulting Discord token goes through the same
+// `do_discord_token_login` path as the QR flow.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::auth::discord_api_base_url;
+use crate::remote_auth::USER_AGENT;
+use crate::sessions::DeviceInfo;
+
+/// Discord MFA tickets are short-lived; don't keep ours around longer.
+const MFA_SESSION_TTL: Duration = Duration::from_secs(5 * 60);
+
+// ── Session types ───────────────────────────────────────
+
+pub struct MfaSession {
+    ticket: String,
+    methods: Vec<String>,
+    created_at: Instant,
+}
+
+pub type PasswordMfaSessions = Arc<Mutex<HashMap<String, MfaSession>>>;
+
+pub fn create_password_mfa_sessions() -> PasswordMfaSessions {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+// ── Request types ───────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct PasswordLoginPayload {
+    pub login: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaCodePayload {
+    pub mfa_session_id: String,
+    /// "totp", "sms" or "backup"
+    pub method: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaSmsSendPayload {
+    pub mfa_session_id: String,
+}
+
+// ── Handlers ────────────────────────────────────────────
+
+/// POST /api/auth/discord/password/login
+pub async fn password_login(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    sessions: web::Data<PasswordMfaSessions>,
+    body: web::Json<PasswordLoginPayload>,
+) -> HttpResponse {
+    if !crate::discord_oauth::link_mode().allows_user_token() {
+        return crate::discord_oauth::user_token_disabled();
+    }
+
+    let login = body.login.trim();
+    if login.is_empty() || body.password.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Identifiant et mot de passe requis"
+        }));
+    }
+
+    let payload = serde_json::json!({
+        "login": login,
+        "password": body.password,
+        "undelete": false,
+        "login_source": serde_json::Value::Null,
+        "gift_code_sku_id": serde_json::Value::Null,
+    });
+
+    let response = match discord_post("/auth/login", &payload).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+
+    if let Some(token) = response.get("token").and_then(|v| v.as_str()) {
+        return finish_login(pool.get_ref(), token, &DeviceInfo::from_request(&req)).await;
+    }
+
+    if response.get("mfa").and_then(|v| v.as_bool()).unwrap_or(false) {
+        let Some(ticket) = response.get("ticket").and_then(|v| v.as_str()) else {
+            return HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Réponse MFA Discord invalide"
+            }));
+        };
+
+        let methods: Vec<String> = ["totp", "sms", "backup"]
+            .into_iter()
+            .filter(|m| response.get(*m).and_then(|v| v.as_bool()).unwrap_or(false))
+            .map(|m| m.to_string())
+            .collect();
+
+        let mfa_session_id = uuid::Uuid::new_v4().to_string();
+        {
+            let mut map = sessions.lock().await;
+            map.retain(|_, s| s.created_at.elapsed() < MFA_SESSION_TTL);
+            map.insert(
+                mfa_session_id.clone(),
+                MfaSession {
+                    ticket: ticket.to_string(),
+                    methods: methods.clone(),
+                    created_at: Instant::now(),
+                },
+            );
+        }
+
+        return HttpResponse::Ok().json(serde_json::json!({
+            "mfa_required": true,
+            "mfa_session_id": mfa_session_id,
+            "methods": methods,
+        }));
+    }
+
+    HttpResponse::BadGateway().json(serde_json::json!({
+        "error": "Réponse Discord inattendue"
+    }))
+}
+
+/// POST /api/auth/discord/password/mfa/sms/send — Ask Discord to text a code.
+pub async fn send_mfa_sms(
+    sessions: web::Data<PasswordMfaSessions>,
+    body: web::Json<MfaSmsSendPayload>,
+) -> HttpResponse {
+    let ticket = match session_ticket(&sessions, &body.mfa_session_id, "sms").await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+
+    match discord_post("/auth/mfa/sms/send", &serde_json::json!({ "ticket": ticket })).await {
+        Ok(response) => HttpResponse::Ok().json(serde_json::json!({
+            "ok": true,
+            "phone": response.get("phone"),
+        })),
+        Err(resp) => resp,
+    }
+}
+
+/// POST /api/auth/discord/password/mfa — Redeem the MFA ticket with a code.
+pub async fn submit_mfa_code(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    sessions: web::Data<PasswordMfaSessions>,
+    body: web::Json<MfaCodePayload>,
+) -> HttpResponse {
+    let method = body.method.trim().to_lowercase();
+    let code = body.code.trim();
+    if code.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Code manquant" }));
+    }
+
+    let ticket = match session_ticket(&sessions, &body.mfa_session_id, &method).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+
+    let payload = serde_json::json!({
+        "code": code,
+        "ticket": ticket,
+        "login_source": serde_json::Value::Null,
+        "gift_code_sku_id": serde_json::Value::Null,
+    });
+
+    let response = match discord_post(&format!("/auth/mfa/{method}"), &payload).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+
+    let Some(token) = response.get("token").and_then(|v| v.as_str()) else {
+        return HttpResponse::BadGateway().json(serde_json::json!({
+            "error": "Aucun token dans la réponse Discord"
+        }));
+    };
+
+    sessions.lock().await.remove(&body.mfa_session_id);
+    finish_login(pool.get_ref(), token, &DeviceInfo::from_request(&req)).await
+}
+
+// ── Internal helpers ────────────────────────────────────
+
+async fn session_ticket(
+    sessions: &PasswordMfaSessions,
+    mfa_session_id: &str,
+    method: &str,
+) -> Result<String, HttpResponse> {
+    let map = sessions.lock().await;
+    let session = map
+        .get(mfa_session_id)
+        .filter(|s| s.created_at.elapsed() < MFA_SESSION_TTL)
+        .ok_or_else(|| {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Session MFA introuvable ou expirée" }))
+        })?;
+
+    if !session.methods.iter().any(|m| m == method) {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Méthode MFA non disponible pour ce compte"
+        })));
+    }
+
+    Ok(session.ticket.clone())
+}
+
+/// POST a JSON body to the Discord API and map failures to an HTTP response
+/// suitable for returning as-is (captcha, bad credentials, bad code...).
+async fn discord_post(
+    path: &str,
+    payload: &serde_json::Value,
+) -> Result<serde_json::Value, HttpResponse> {
+    let response = Client::new()
+        .post(format!("{}{}", discord_api_base_url(), path))
+        .header("Origin", "https://discord.com")
+        .header("User-Agent", USER_AGENT)
+        .json(payload)
+        .send()
+        .await
+        .map_err(|_| {
+            HttpResponse::BadGateway().json(serde_json::json!({ "error": "Discord API indisponible" }))
+        })?;
+
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+
+    if status.is_success() {
+        return Ok(body);
+    }
+
+    if body.get("captcha_key").is_some() {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Discord exige un captcha — utilisez la connexion par QR code",
+            "captcha_required": true,
+        })));
+    }
+
+    let message = body
+        .get("message")
+        .and_then(|v| v.as_str())
+        .unwrap_or("Identifiants Discord refusés")
+        .to_string();
+    Err(HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": message,
+        "details": body.get("errors"),
+    })))
+}
+
+async fn finish_login(pool: &SqlitePool, discord_token: &str, device: &DeviceInfo) -> HttpResponse {
+    match crate::auth::do_discord_token_login(pool, discord_token, device, crate::audit::LinkMethod::Password).await {
+        Ok(auth) => HttpResponse::Ok().json(auth),
+        Err(msg) => HttpResponse::Unauthorized().json(serde_json::json!({ "error": msg })),
+    }
+}
@@ -0,0 +1,591 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Permission bitsets, per role and per room
+// ═══════════════════════════════════════════════════════
+//
+// What a member may do is a bitset stored on their role (`roles.permissions`).
+// Server permissions (`MANAGE_ROLES`, `MANAGE_SERVER`, ...) are read from it
+// as is; `ADMINISTRATOR` grants every bit, and the `admin` role always has
+// it. Room permissions (`VIEW_ROOM`, `SEND_MESSAGES`, ..., the `ROOM` bits)
+// start from the room's `required_role`: the role's room bits when the
+// member can see the room that way, nothing otherwise. Overwrites stored per
+// room then deny and allow room bits, Discord style, in this order:
+//
+//   1. the `user` role overwrite, which applies to everyone (the same way
+//      `required_role = user` opens a room to everyone);
+//   2. the overwrite of the member's role;
+//   3. the overwrite of the member themselves.
+//
+// Each step clears its `deny` bits, then sets its `allow` bits. An allow can
+// open a role-gated room to a member who could not see it. Administrators
+// skip overwrites; guests have what their link grants (see `guests`).
+//
+// Handlers ask `require` for a room, `require_server` for the rest, or
+// `ws::room_permissions_cached` (realtime connections); listings use
+// `viewable_rooms`. Members can only hand out (through roles or
+// overwrites) the permissions they have.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+
+use crate::auth::{extract_claims, Claims};
+use crate::ws::{AccessCache, Broadcaster};
+
+/// See the room, its history and its live events.
+pub const VIEW_ROOM: u64 = 1 << 0;
+/// Post messages and polls.
+pub const SEND_MESSAGES: u64 = 1 << 1;
+/// React to messages.
+pub const ADD_REACTIONS: u64 = 1 << 2;
+/// Attach files to messages.
+pub const ATTACH_FILES: u64 = 1 << 3;
+/// Join the room's voice.
+pub const CONNECT: u64 = 1 << 4;
+/// Delete others' messages, pin, close others' polls, see deleted messages.
+pub const MANAGE_MESSAGES: u64 = 1 << 5;
+/// Moderate voice: choose priority speakers, server mute and deafen members.
+pub const MUTE_MEMBERS: u64 = 1 << 6;
+/// Notify the whole room with `@room`, and mention roles that are not mentionable.
+pub const MENTION_EVERYONE: u64 = 1 << 7;
+/// Create role-gated or slowmode rooms, edit and delete rooms, their
+/// overwrites and guest links.
+pub const MANAGE_ROOMS: u64 = 1 << 8;
+/// Create, edit and delete roles and give them to members.
+pub const MANAGE_ROLES: u64 = 1 << 9;
+/// Create and delete custom emoji.
+pub const MANAGE_EMOJIS: u64 = 1 << 10;
+/// List and delete accounts, require or reset their 2FA.
+pub const MANAGE_MEMBERS: u64 = 1 << 11;
+/// Announcements, diagnostics, background jobs, exports and stats.
+pub const MANAGE_SERVER: u64 = 1 << 12;
+/// Every permission, in every room.
+pub const ADMINISTRATOR: u64 = 1 << 13;
+/// Play soundboard clips in the room's voice.
+pub const USE_SOUNDBOARD: u64 = 1 << 14;
+
+/// Permissions that apply per room, the ones overwrites can change.
+pub const ROOM: u64 =
+    VIEW_ROOM | SEND_MESSAGES | ADD_REACTIONS | ATTACH_FILES | CONNECT | MANAGE_MESSAGES | MUTE_MEMBERS | MENTION_EVERYONE | USE_SOUNDBOARD;
+/// Every permission.
+pub const ALL: u64 = ROOM | MANAGE_ROOMS | MANAGE_ROLES | MANAGE_EMOJIS | MANAGE_MEMBERS | MANAGE_SERVER | ADMINISTRATOR;
+/// What a new role gets: the `user` role's defaults.
+pub const MEMBER: u64 = VIEW_ROOM | SEND_MESSAGES | ADD_REACTIONS | ATTACH_FILES | CONNECT | USE_SOUNDBOARD;
+
+/// Flag names, as listed by the API.
+pub const NAMES: &[(&str, u64)] = &[
+    ("VIEW_ROOM", VIEW_ROOM),
+    ("SEND_MESSAGES", SEND_MESSAGES),
+    ("ADD_REACTIONS", ADD_REACTIONS),
+    ("ATTACH_FILES", ATTACH_FILES),
+    ("CONNECT", CONNECT),
+    ("MANAGE_MESSAGES", MANAGE_MESSAGES),
+    ("MUTE_MEMBERS", MUTE_MEMBERS),
+    ("MENTION_EVERYONE", MENTION_EVERYONE),
+    ("MANAGE_ROOMS", MANAGE_ROOMS),
+    ("MANAGE_ROLES", MANAGE_ROLES),
+    ("MANAGE_EMOJIS", MANAGE_EMOJIS),
+    ("MANAGE_MEMBERS", MANAGE_MEMBERS),
+    ("MANAGE_SERVER", MANAGE_SERVER),
+    ("ADMINISTRATOR", ADMINISTRATOR),
+    ("USE_SOUNDBOARD", USE_SOUNDBOARD),
+];
+
+/// The role whose overwrite applies to everyone.
+pub const EVERYONE: &str = "user";
+
+/// Names of the flags set in `permissions`.
+pub fn names(permissions: u64) -> Vec<&'static str> {
+    NAMES
+        .iter()
+        .filter(|(_, bit)| permissions & bit != 0)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+// ── Resolution ──────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Overwrite {
+    /// `role` or `user`.
+    pub target_type: String,
+    /// A role name or a user id.
+    pub target_id: String,
+    pub allow: u64,
+    pub deny: u64,
+}
+
+impl Overwrite {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            target_type: row.get("target_type"),
+            target_id: row.get("target_id"),
+            allow: row.get::<i64, _>("allow") as u64,
+            deny: row.get::<i64, _>("deny") as u64,
+        }
+    }
+}
+
+/// Permissions of a member with `role`, whose bits are `role_permissions`,
+/// in a room readable by `required_role`, before overwrites.
+pub fn base(required_role: &str, role: &str, role_permissions: u64) -> u64 {
+    if role_permissions & ADMINISTRATOR != 0 {
+        ALL
+    } else if required_role == EVERYONE || role == required_role {
+        role_permissions & ROOM
+    } else {
+        0
+    }
+}
+
+/// `base` with the room's overwrites applied for `user_id` with `role`.
+pub fn apply(base: u64, overwrites: &[Overwrite], user_id: &str, role: &str) -> u64 {
+    if base & ADMINISTRATOR != 0 {
+        return ALL;
+    }
+    [("role", EVERYONE), ("role", role), ("user", user_id)]
+        .into_iter()
+        .filter_map(|(target_type, target_id)| {
+            overwrites
+                .iter()
+                .find(|o| o.target_type == target_type && o.target_id == target_id)
+        })
+        .fold(base, |permissions, o| (permissions & !o.deny) | (o.allow & ROOM))
+}
+
+/// Server permissions of members with `role`: `ALL` for `admin`, nothing for
+/// guests, the `user` defaults for a role that no longer exists.
+pub(crate) async fn role_permissions(pool: &SqlitePool, role: &str) -> u64 {
+    match role {
+        "admin" => ALL,
+        crate::guests::GUEST_ROLE => 0,
+        _ => {
+            let bits: Option<i64> = sqlx::query_scalar("SELECT permissions FROM roles WHERE name = ?")
+                .bind(role)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten();
+            match bits.map(|b| b as u64).unwrap_or(MEMBER) {
+                bits if bits & ADMINISTRATOR != 0 => ALL,
+                bits => bits,
+            }
+        }
+    }
+}
+
+fn missing_permission(missing: u64) -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({
+        "error": "Missing permission",
+        "missing": names(missing),
+    }))
+}
+
+/// Check the caller's role has `permission` server-wide, `403` otherwise.
+pub(crate) async fn require_server(pool: &SqlitePool, claims: &Claims, permission: u64) -> Result<(), HttpResponse> {
+    let granted = role_permissions(pool, &claims.role).await;
+    if granted & permission != permission {
+        return Err(missing_permission(permission & !granted));
+    }
+    Ok(())
+}
+
+/// Check the caller holds every bit of `requested`, the permissions they
+/// are about to hand out.
+#[allow(clippy::result_large_err)]
+pub(crate) fn require_grantable(granted: u64, requested: u64) -> Result<(), HttpResponse> {
+    if granted & ADMINISTRATOR == 0 && requested & !granted != 0 {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "You can only grant permissions you have",
+            "missing": names(requested & !granted),
+        })));
+    }
+    Ok(())
+}
+
+/// Permissions of `user_id` with `role` (whose bits are `role_permissions`)
+/// in `room_id`, given the room's `required_role` and `overwrites`.
+pub(crate) async fn resolve(
+    pool: &SqlitePool,
+    room_id: &str,
+    required_role: &str,
+    role_permissions: u64,
+    overwrites: &[Overwrite],
+    user_id: &str,
+    role: &str,
+) -> u64 {
+    match role {
+        crate::guests::GUEST_ROLE => match crate::guests::active_grant(pool, user_id).await {
+            Some(grant) if grant.room_id == room_id => {
+                VIEW_ROOM | if grant.can_post { SEND_MESSAGES } else { 0 }
+            }
+            _ => 0,
+        },
+        _ => apply(base(required_role, role, role_permissions), overwrites, user_id, role),
+    }
+}
+
+pub(crate) async fn load_overwrites(pool: &SqlitePool, room_id: &str) -> Vec<Overwrite> {
+    sqlx::query("SELECT target_type, target_id, allow, deny FROM room_permission_overwrites WHERE room_id = ?")
+        .bind(room_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(Overwrite::from_row)
+        .collect()
+}
+
+/// Permissions of `user_id` with `role` in `room_id`, `None` when there is no such room.
+pub(crate) async fn room_permissions(pool: &SqlitePool, room_id: &str, user_id: &str, role: &str) -> Option<u64> {
+    let required_role: String = sqlx::query_scalar("SELECT required_role FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()?;
+    let overwrites = load_overwrites(pool, room_id).await;
+    let bits = role_permissions(pool, role).await;
+    Some(resolve(pool, room_id, &required_role, bits, &overwrites, user_id, role).await)
+}
+
+/// Check the caller has `permission` in `room_id`: `404` without such a
+/// room, `403` when they cannot see it or lack the permission.
+pub(crate) async fn require(pool: &SqlitePool, room_id: &str, claims: &Claims, permission: u64) -> Result<(), HttpResponse> {
+    let Some(permissions) = room_permissions(pool, room_id, &claims.sub, &claims.role).await else {
+        return Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" })));
+    };
+    if permissions & VIEW_ROOM == 0 {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({ "error": "Access denied for this room" })));
+    }
+    if permissions & permission != permission {
+        return Err(missing_permission(permission & !permissions));
+    }
+    Ok(())
+}
+
+/// Which of `members` (user id, role) have `permission` in `room_id`, in order.
+pub(crate) async fn members_with(pool: &SqlitePool, room_id: &str, members: Vec<(String, String)>, permission: u64) -> Vec<String> {
+    let Some(required_role) = sqlx::query_scalar::<_, String>("SELECT required_role FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+    else {
+        return Vec::new();
+    };
+    let overwrites = load_overwrites(pool, room_id).await;
+
+    let mut role_bits: HashMap<String, u64> = HashMap::new();
+    let mut granted = Vec::new();
+    for (user_id, role) in members {
+        let bits = match role_bits.get(&role) {
+            Some(bits) => *bits,
+            None => {
+                let bits = role_permissions(pool, &role).await;
+                role_bits.insert(role.clone(), bits);
+                bits
+            }
+        };
+        if resolve(pool, room_id, &required_role, bits, &overwrites, &user_id, &role).await & permission == permission {
+            granted.push(user_id);
+        }
+    }
+    granted
+}
+
+/// Rooms `user_id` with `role` can see.
+pub(crate) async fn viewable_rooms(pool: &SqlitePool, user_id: &str, role: &str) -> HashSet<String> {
+    let rooms: Vec<(String, String)> = sqlx::query_as("SELECT id, required_role FROM rooms")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    if role == crate::guests::GUEST_ROLE {
+        return crate::guests::active_grant(pool, user_id).await.into_iter().map(|g| g.room_id).collect();
+    }
+    let bits = role_permissions(pool, role).await;
+    if bits & ADMINISTRATOR != 0 {
+        return rooms.into_iter().map(|(id, _)| id).collect();
+    }
+
+    let rows = sqlx::query(
+        "SELECT room_id, target_type, target_id, allow, deny FROM room_permission_overwrites \
+         WHERE (target_type = 'role' AND target_id IN (?, ?)) OR (target_type = 'user' AND target_id = ?)"
+    )
+    .bind(EVERYONE)
+    .bind(role)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    let mut overwrites: HashMap<String, Vec<Overwrite>> = HashMap::new();
+    for row in &rows {
+        overwrites.entry(row.get("room_id")).or_default().push(Overwrite::from_row(row));
+    }
+
+    rooms
+        .into_iter()
+        .filter(|(id, required_role)| {
+            let room_overwrites = overwrites.get(id).map(Vec::as_slice).unwrap_or_default();
+            apply(base(required_role, role, bits), room_overwrites, user_id, role) & VIEW_ROOM != 0
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/rooms/{id}/permissions/@me — The caller's permissions in a room
+pub async fn get_own_permissions(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = require(pool.get_ref(), &room_id, &claims, VIEW_ROOM).await {
+        return response;
+    }
+
+    let permissions = room_permissions(pool.get_ref(), &room_id, &claims.sub, &claims.role).await.unwrap_or(0);
+    HttpResponse::Ok().json(serde_json::json!({
+        "room_id": room_id,
+        "permissions": permissions,
+        "names": names(permissions),
+    }))
+}
+
+/// GET /api/rooms/{id}/permissions — A room's overwrites (MANAGE_ROOMS)
+pub async fn list_overwrites(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = require_server(pool.get_ref(), &claims, MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let room_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rooms WHERE id = ?)")
+        .bind(&room_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    }
+
+    let flags: serde_json::Map<String, serde_json::Value> = NAMES
+        .iter()
+        .filter(|(_, bit)| bit & ROOM != 0)
+        .map(|(name, bit)| (name.to_string(), (*bit).into()))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "room_id": room_id,
+        "overwrites": load_overwrites(pool.get_ref(), &room_id).await,
+        "flags": flags,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OverwriteInput {
+    #[serde(default)]
+    pub allow: u64,
+    #[serde(default)]
+    pub deny: u64,
+}
+
+fn broadcast_update(broadcaster: &Broadcaster, room_id: &str) {
+    let event = serde_json::json!({ "type": "room_permissions_updated", "room_id": room_id });
+    let _ = broadcaster.send(event.to_string());
+}
+
+/// Check an overwrite target exists and can be overwritten.
+async fn validate_target(pool: &SqlitePool, target_type: &str, target_id: &str) -> Result<(), HttpResponse> {
+    let exists = match target_type {
+        "role" if target_id == "admin" => {
+            return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Admins always have every permission" })));
+        }
+        "role" => sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM roles WHERE name = ?)"),
+        "user" => sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)"),
+        _ => {
+            return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Target type must be role or user" })));
+        }
+    }
+    .bind(target_id)
+    .fetch_one(pool)
+    .await
+    .unwrap_or(false);
+    if !exists {
+        return Err(HttpResponse::NotFound().json(serde_json::json!({ "error": format!("Unknown {target_type}") })));
+    }
+    Ok(())
+}
+
+/// PUT /api/rooms/{id}/permissions/{target_type}/{target_id} — Set a role's or member's overwrite (MANAGE_ROOMS)
+pub async fn put_overwrite(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<(String, String, String)>,
+    body: web::Json<OverwriteInput>,
+    access_cache: web::Data<AccessCache>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = require_server(pool.get_ref(), &claims, MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let (room_id, target_type, target_id) = path.into_inner();
+    if body.allow & !ROOM != 0 || body.deny & !ROOM != 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Only room permissions can be overwritten" }));
+    }
+    if body.allow & body.deny != 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "A permission cannot be both allowed and denied" }));
+    }
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rooms WHERE id = ?)")
+        .bind(&room_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    }
+    if let Err(response) = validate_target(pool.get_ref(), &target_type, &target_id).await {
+        return response;
+    }
+    let granted = room_permissions(pool.get_ref(), &room_id, &claims.sub, &claims.role).await.unwrap_or(0);
+    if let Err(response) = require_grantable(granted, body.allow | body.deny) {
+        return response;
+    }
+    let previous: Option<(i64, i64)> = sqlx::query_as(
+        "SELECT allow, deny FROM room_permission_overwrites WHERE room_id = ? AND target_type = ? AND target_id = ?"
+    )
+    .bind(&room_id)
+    .bind(&target_type)
+    .bind(&target_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+
+    let result = sqlx::query(
+        "INSERT INTO room_permission_overwrites (room_id, target_type, target_id, allow, deny, updated_by, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(room_id, target_type, target_id) DO UPDATE SET \
+         allow = excluded.allow, deny = excluded.deny, updated_by = excluded.updated_by, updated_at = excluded.updated_at"
+    )
+    .bind(&room_id)
+    .bind(&target_type)
+    .bind(&target_id)
+    .bind(body.allow as i64)
+    .bind(body.deny as i64)
+    .bind(&claims.sub)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::ws::cache_remove_room_overwrites(access_cache.get_ref(), &room_id);
+    broadcast_update(broadcaster.get_ref(), &room_id);
+    let (old_allow, old_deny) = previous.unwrap_or((0, 0));
+    let mut changes = crate::audit_log::diff(&[
+        ("allow", names(old_allow as u64).into(), names(body.allow).into()),
+        ("deny", names(old_deny as u64).into(), names(body.deny).into()),
+    ]);
+    changes["target_type"] = target_type.clone().into();
+    changes["target_id"] = target_id.clone().into();
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::PermissionsUpdated,
+        &room_id,
+        changes,
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+
+    HttpResponse::Ok().json(Overwrite {
+        target_type,
+        target_id,
+        allow: body.allow,
+        deny: body.deny,
+    })
+}
+
+/// DELETE /api/rooms/{id}/permissions/{target_type}/{target_id} — Remove an overwrite (MANAGE_ROOMS)
+pub async fn delete_overwrite(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<(String, String, String)>,
+    access_cache: web::Data<AccessCache>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = require_server(pool.get_ref(), &claims, MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let (room_id, target_type, target_id) = path.into_inner();
+    let current: Option<(i64, i64)> = sqlx::query_as(
+        "SELECT allow, deny FROM room_permission_overwrites WHERE room_id = ? AND target_type = ? AND target_id = ?"
+    )
+    .bind(&room_id)
+    .bind(&target_type)
+    .bind(&target_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some((allow, deny)) = current else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Overwrite not found" }));
+    };
+    let granted = room_permissions(pool.get_ref(), &room_id, &claims.sub, &claims.role).await.unwrap_or(0);
+    if let Err(response) = require_grantable(granted, (allow | deny) as u64) {
+        return response;
+    }
+
+    let deleted = sqlx::query("DELETE FROM room_permission_overwrites WHERE room_id = ? AND target_type = ? AND target_id = ?")
+        .bind(&room_id)
+        .bind(&target_type)
+        .bind(&target_id)
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or(false);
+    if !deleted {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Overwrite not found" }));
+    }
+    crate::ws::cache_remove_room_overwrites(access_cache.get_ref(), &room_id);
+    broadcast_update(broadcaster.get_ref(), &room_id);
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::PermissionsRemoved,
+        &room_id,
+        serde_json::json!({
+            "target_type": target_type,
+            "target_id": target_id,
+            "allow": names(allow as u64),
+            "deny": names(deny as u64),
+        }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+
+    HttpResponse::NoContent().finish()
+}
+
@@ -0,0 +1,153 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Re-checking pinned messages (dead links, lost files)
+// ═══════════════════════════════════════════════════════
+//
+// Pinned messages are what a room keeps coming back to, so their link
+// previews and attachments are re-checked every `PIN_CHECK_HOURS` rather
+// than left to rot. A background task picks the pinned messages due, a
+// batch at a time:
+//   - each link preview is fetched again, bypassing the cache. A new
+//     preview replaces the stored one; a link with nothing to show anymore
+//     keeps its last preview, marked with `broken_at`;
+//   - the files of its attachments are looked up in storage. A file that
+//     cannot be read gets `missing_at` on every attachment using it, until
+//     it can again or the same content is uploaded again.
+// Changes are pushed with `message_embeds_updated` and
+// `message_attachments_updated` events. A message whose site is rate
+// limited by `outbound` is left due and retried on a later run; one being
+// edited (previews pending, see `unfurl`) waits for the unfurler.
+//
+// Pins are capped per room, so every pinned message is checked whatever
+// its age. Link previews are only re-checked when LINK_PREVIEWS is on.
+//
+// Config (env): PIN_CHECK_HOURS (default 24)
+
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::{Row, SqlitePool};
+
+use crate::files::{FileStore, FileStorage};
+use crate::jobs::{Backlog, JobRegistry};
+use crate::ws::Broadcaster;
+
+const DEFAULT_CHECK_HOURS: i64 = 24;
+/// Messages checked per run.
+const BATCH_SIZE: i64 = 20;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+fn check_hours() -> i64 {
+    std::env::var("PIN_CHECK_HOURS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n: &i64| *n > 0)
+        .unwrap_or(DEFAULT_CHECK_HOURS)
+}
+
+/// Pinned messages with previews or attachments, not checked since `?`.
+const DUE: &str = "FROM messages WHERE pinned_at IS NOT NULL AND deleted_at IS NULL AND embeds_pending_at IS NULL \
+                   AND (embeds IS NOT NULL OR EXISTS(SELECT 1 FROM attachments a WHERE a.message_id = messages.id)) \
+                   AND (links_checked_at IS NULL OR links_checked_at < ?)";
+
+/// Check the pinned messages due, returns how many were checked.
+pub async fn check_due(pool: &SqlitePool, storage: &FileStore, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let cutoff = (Utc::now() - chrono::Duration::hours(check_hours())).to_rfc3339();
+    let due = sqlx::query(&format!(
+        "SELECT id, room_id, embeds {DUE} ORDER BY links_checked_at IS NOT NULL, links_checked_at LIMIT ?"
+    ))
+    .bind(&cutoff)
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut checked = 0;
+    for row in due {
+        let id: String = row.get("id");
+        let room_id: String = row.get("room_id");
+        let previous: Option<String> = row.get("embeds");
+        let now = Utc::now().to_rfc3339();
+
+        let mut embeds = crate::unfurl::parse_embeds(previous.clone());
+        if crate::unfurl::enabled() {
+            let mut deferred = false;
+            for embed in embeds.iter_mut() {
+                match crate::unfurl::refresh(pool, &embed.url).await {
+                    Ok(Some(fresh)) => *embed = fresh,
+                    Ok(None) => {
+                        embed.broken_at.get_or_insert_with(|| now.clone());
+                    }
+                    Err(_) => {
+                        deferred = true;
+                        break;
+                    }
+                }
+            }
+            // Left due: retried on a later run, once the site's rate limit allows.
+            if deferred {
+                continue;
+            }
+        }
+        let stored = (!embeds.is_empty()).then(|| serde_json::to_string(&embeds).unwrap_or_default());
+
+        // Guarded on what we read: an edit meanwhile flags the message for
+        // the unfurler, which redoes its previews from the new content.
+        let updated = sqlx::query(
+            "UPDATE messages SET embeds = ?, links_checked_at = ? WHERE id = ? AND embeds_pending_at IS NULL AND embeds IS ?"
+        )
+        .bind(&stored)
+        .bind(&now)
+        .bind(&id)
+        .bind(&previous)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+        checked += 1;
+
+        if updated == 1 && stored != previous {
+            let event = serde_json::json!({
+                "type": "message_embeds_updated",
+                "id": id,
+                "room_id": room_id,
+                "embeds": embeds,
+            });
+            let _ = broadcaster.send(event.to_string());
+        }
+
+        if crate::files::check_stored(pool, storage, &id).await? {
+            let event = serde_json::json!({
+                "type": "message_attachments_updated",
+                "id": id,
+                "room_id": room_id,
+                "attachments": crate::files::message_attachments(pool, &id).await,
+            });
+            let _ = broadcaster.send(event.to_string());
+        }
+    }
+    Ok(checked)
+}
+
+/// Pinned messages due for a check.
+async fn check_backlog(pool: &SqlitePool) -> Backlog {
+    let cutoff = (Utc::now() - chrono::Duration::hours(check_hours())).to_rfc3339();
+    sqlx::query(&format!(
+        "SELECT COUNT(*) AS depth, MIN(COALESCE(links_checked_at, pinned_at)) AS oldest_due_at {DUE}"
+    ))
+    .bind(&cutoff)
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_checker(pool: SqlitePool, storage: FileStorage, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::PIN_CHECKS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::PIN_CHECKS, None, check_due(&pool, &storage, &broadcaster), check_backlog(&pool)).await;
+        }
+    });
+}
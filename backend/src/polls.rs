@@ -0,0 +1,510 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Polls
+// ═══════════════════════════════════════════════════════
+//
+// A poll is a message of kind `poll` (its content is the question) with a
+// row in `polls`: the options, whether several can be picked, and when it
+// ends. Every member of the room can vote, change their vote (a vote
+// replaces the previous one) or retract it until the poll closes. Each
+// change broadcasts `poll_updated` with the new tallies; who voted for what
+// is only given back to the voter (`my_votes`).
+//
+// Polls close at `expires_at`, through the `polls` job queue which checks
+// every `CLOSE_INTERVAL`, or earlier when their author (or an admin) ends
+// them. Votes are refused once `expires_at` has passed even if the closer
+// has not run yet.
+//
+// Config (env):
+//   POLL_MAX_OPTIONS         options per poll (default 10, at most 25)
+//   POLL_MAX_DURATION_HOURS  longest poll (default 768, 32 days)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::jobs::{Backlog, JobRegistry};
+use crate::messages::Message;
+use crate::ws::Broadcaster;
+
+const DEFAULT_MAX_OPTIONS: usize = 10;
+const MAX_OPTIONS_CAP: usize = 25;
+const DEFAULT_MAX_DURATION_HOURS: i64 = 768;
+const DEFAULT_DURATION_HOURS: i64 = 24;
+const MAX_QUESTION_CHARS: usize = 300;
+const MAX_OPTION_CHARS: usize = 55;
+const CLOSE_INTERVAL: Duration = Duration::from_secs(30);
+/// Polls closed per run of the closer.
+const CLOSE_BATCH: i64 = 50;
+
+fn max_options() -> usize {
+    std::env::var("POLL_MAX_OPTIONS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n >= 2)
+        .unwrap_or(DEFAULT_MAX_OPTIONS)
+        .min(MAX_OPTIONS_CAP)
+}
+
+fn max_duration_hours() -> i64 {
+    std::env::var("POLL_MAX_DURATION_HOURS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_DURATION_HOURS)
+}
+
+// ── Types ───────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollOption {
+    pub text: String,
+    pub votes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+    pub question: String,
+    pub options: Vec<PollOption>,
+    pub multi_select: bool,
+    pub expires_at: String,
+    pub closed_at: Option<String>,
+    /// Users with at least one vote.
+    pub total_voters: i64,
+    /// Options the viewer voted for. Absent from broadcasts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub my_votes: Option<Vec<usize>>,
+}
+
+impl Poll {
+    /// Closed, or past its expiry and waiting for the closer.
+    fn is_over(&self) -> bool {
+        self.closed_at.is_some()
+            || chrono::DateTime::parse_from_rfc3339(&self.expires_at).is_ok_and(|at| at <= Utc::now())
+    }
+}
+
+/// The poll of `message_id` with its tallies, and `viewer_id`'s votes when given.
+async fn load_poll(pool: &SqlitePool, message_id: &str, viewer_id: Option<&str>) -> Option<Poll> {
+    let row = sqlx::query("SELECT question, options, multi_select, expires_at, closed_at FROM polls WHERE message_id = ?")
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)?;
+    let texts: Vec<String> = serde_json::from_str(&row.try_get::<String, _>("options").unwrap_or_default()).unwrap_or_default();
+
+    let votes = sqlx::query("SELECT user_id, option_index FROM poll_votes WHERE message_id = ?")
+        .bind(message_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let mut counts = vec![0i64; texts.len()];
+    let mut voters = HashSet::new();
+    let mut mine = Vec::new();
+    for vote in &votes {
+        let user_id: String = vote.try_get("user_id").unwrap_or_default();
+        let index = vote.try_get::<i64, _>("option_index").unwrap_or(-1);
+        let Some(count) = usize::try_from(index).ok().and_then(|i| counts.get_mut(i)) else {
+            continue;
+        };
+        *count += 1;
+        if viewer_id == Some(user_id.as_str()) {
+            mine.push(index as usize);
+        }
+        voters.insert(user_id);
+    }
+    mine.sort_unstable();
+
+    Some(Poll {
+        question: row.try_get("question").unwrap_or_default(),
+        options: texts.into_iter().zip(counts).map(|(text, votes)| PollOption { text, votes }).collect(),
+        multi_select: row.try_get::<i64, _>("multi_select").unwrap_or(0) != 0,
+        expires_at: row.try_get("expires_at").unwrap_or_default(),
+        closed_at: row.try_get("closed_at").unwrap_or(None),
+        total_voters: voters.len() as i64,
+        my_votes: viewer_id.map(|_| mine),
+    })
+}
+
+/// Fill `poll` of each poll message, with `viewer_id`'s votes when given.
+pub(crate) async fn attach_to_messages(pool: &SqlitePool, viewer_id: Option<&str>, messages: &mut [Message]) {
+    for message in messages.iter_mut().filter(|m| m.kind == "poll") {
+        message.poll = load_poll(pool, &message.id, viewer_id).await;
+    }
+}
+
+async fn broadcast_update(pool: &SqlitePool, broadcaster: &Broadcaster, message_id: &str, room_id: &str) {
+    if let Some(poll) = load_poll(pool, message_id, None).await {
+        let event = serde_json::json!({
+            "type": "poll_updated",
+            "message_id": message_id,
+            "room_id": room_id,
+            "poll": poll,
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePollInput {
+    pub question: String,
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub multi_select: bool,
+    /// Defaults to 24.
+    pub duration_hours: Option<i64>,
+}
+
+/// POST /api/rooms/{room_id}/polls — Post a poll in a text room
+/// Body: { question, options, multi_select?, duration_hours? }
+pub async fn create_poll(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<CreatePollInput>,
+    broadcaster: web::Data<Broadcaster>,
+    access_cache: web::Data<crate::ws::AccessCache>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::SEND_MESSAGES).await {
+        return response;
+    }
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if kind.as_deref() != Some("text") {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Polls can only be posted in text rooms" }));
+    }
+
+    let question = body.question.trim();
+    if question.is_empty() || question.chars().count() > MAX_QUESTION_CHARS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("The question must be 1 to {MAX_QUESTION_CHARS} characters")
+        }));
+    }
+    let options: Vec<&str> = body.options.iter().map(|o| o.trim()).collect();
+    let max = max_options();
+    if options.len() < 2 || options.len() > max {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("A poll needs 2 to {max} options") }));
+    }
+    if options.iter().any(|o| o.is_empty() || o.chars().count() > MAX_OPTION_CHARS) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Options must be 1 to {MAX_OPTION_CHARS} characters")
+        }));
+    }
+    if options.iter().collect::<HashSet<_>>().len() != options.len() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Options must be different" }));
+    }
+    let max_hours = max_duration_hours();
+    let duration_hours = body.duration_hours.unwrap_or(DEFAULT_DURATION_HOURS.min(max_hours));
+    if duration_hours < 1 || duration_hours > max_hours {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("duration_hours must be between 1 and {max_hours}")
+        }));
+    }
+    let flags = match crate::ws::check_post_gates(pool.get_ref(), access_cache.get_ref(), broadcaster.get_ref(), &room_id, &claims.sub, &claims.role, &format!("{question}\n{}", options.join("\n")), &[]).await {
+        Ok(flags) => flags,
+        Err(refusal) => return refusal.response(),
+    };
+
+    let message_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let created_at = now.to_rfc3339();
+    let expires_at = (now + chrono::Duration::hours(duration_hours)).to_rfc3339();
+    let options_json = serde_json::to_string(&options).unwrap_or_else(|_| "[]".to_string());
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to create poll" })),
+    };
+    let inserted = sqlx::query(
+        "INSERT INTO messages (id, room_id, user_id, username, content, created_at, kind) VALUES (?, ?, ?, ?, ?, ?, 'poll')"
+    )
+    .bind(&message_id)
+    .bind(&room_id)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(question)
+    .bind(&created_at)
+    .execute(&mut *tx)
+    .await
+    .is_ok()
+        && sqlx::query("INSERT INTO polls (message_id, question, options, multi_select, expires_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(&message_id)
+            .bind(question)
+            .bind(&options_json)
+            .bind(body.multi_select)
+            .bind(&expires_at)
+            .execute(&mut *tx)
+            .await
+            .is_ok();
+    if !inserted || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to create poll" }));
+    }
+
+    crate::automod::record_flags(pool.get_ref(), &message_id, &room_id, &claims.sub, &flags).await;
+
+    let poll = load_poll(pool.get_ref(), &message_id, None).await;
+    let avatar_url: Option<String> = sqlx::query_scalar("SELECT avatar_url FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None)
+        .flatten();
+    let event = serde_json::json!({
+        "type": "message",
+        "id": message_id,
+        "room_id": room_id,
+        "user_id": claims.sub,
+        "username": claims.username,
+        "avatar_url": avatar_url,
+        "content": question,
+        "created_at": created_at,
+        "kind": "poll",
+        "poll": poll,
+    });
+    let _ = broadcaster.send(event.to_string());
+
+    HttpResponse::Created().json(event)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoteInput {
+    /// Option indexes; exactly one unless the poll is multi-select.
+    pub options: Vec<usize>,
+}
+
+/// The room of poll message `message_id` if the user can see it, or the error response.
+async fn poll_room(pool: &SqlitePool, message_id: &str, claims: &Claims) -> Result<String, HttpResponse> {
+    let is_poll: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM polls WHERE message_id = ?)")
+        .bind(message_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false);
+    match crate::messages::can_access_message_room(pool, message_id, claims, 0).await {
+        Some(room_id) if is_poll => Ok(room_id),
+        _ => Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "Poll not found" }))),
+    }
+}
+
+fn poll_closed() -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({ "error": "This poll is closed" }))
+}
+
+/// PUT /api/messages/{id}/poll/votes — Vote, replacing any previous vote
+/// Body: { options: [index, ...] }
+pub async fn vote(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<VoteInput>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let message_id = path.into_inner();
+    let room_id = match poll_room(pool.get_ref(), &message_id, &claims).await {
+        Ok(room_id) => room_id,
+        Err(response) => return response,
+    };
+    let Some(poll) = load_poll(pool.get_ref(), &message_id, None).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Poll not found" }));
+    };
+    if poll.is_over() {
+        return poll_closed();
+    }
+
+    let choices: HashSet<usize> = body.options.iter().copied().collect();
+    if choices.is_empty() || choices.len() != body.options.len() || choices.iter().any(|i| *i >= poll.options.len()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid options" }));
+    }
+    if !poll.multi_select && choices.len() > 1 {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This poll allows only one option" }));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to vote" })),
+    };
+    // Guarded on the poll being open so a vote can't land after the closer.
+    let open = sqlx::query("DELETE FROM poll_votes WHERE message_id = ? AND user_id = ? AND EXISTS(SELECT 1 FROM polls WHERE message_id = ? AND closed_at IS NULL)")
+        .bind(&message_id)
+        .bind(&claims.sub)
+        .bind(&message_id)
+        .execute(&mut *tx)
+        .await
+        .is_ok();
+    let mut stored = open;
+    for index in &choices {
+        stored = stored
+            && sqlx::query(
+                "INSERT INTO poll_votes (message_id, user_id, option_index, created_at) \
+                 SELECT ?, ?, ?, ? WHERE EXISTS(SELECT 1 FROM polls WHERE message_id = ? AND closed_at IS NULL)"
+            )
+            .bind(&message_id)
+            .bind(&claims.sub)
+            .bind(*index as i64)
+            .bind(&now)
+            .bind(&message_id)
+            .execute(&mut *tx)
+            .await
+            .is_ok_and(|r| r.rows_affected() == 1);
+    }
+    if !stored {
+        let _ = tx.rollback().await;
+        return poll_closed();
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to vote" }));
+    }
+
+    broadcast_update(pool.get_ref(), broadcaster.get_ref(), &message_id, &room_id).await;
+    HttpResponse::Ok().json(load_poll(pool.get_ref(), &message_id, Some(&claims.sub)).await)
+}
+
+/// DELETE /api/messages/{id}/poll/votes — Retract one's vote
+pub async fn retract_vote(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let message_id = path.into_inner();
+    let room_id = match poll_room(pool.get_ref(), &message_id, &claims).await {
+        Ok(room_id) => room_id,
+        Err(response) => return response,
+    };
+    if load_poll(pool.get_ref(), &message_id, None).await.is_none_or(|poll| poll.is_over()) {
+        return poll_closed();
+    }
+
+    let removed = sqlx::query("DELETE FROM poll_votes WHERE message_id = ? AND user_id = ?")
+        .bind(&message_id)
+        .bind(&claims.sub)
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+    if removed > 0 {
+        broadcast_update(pool.get_ref(), broadcaster.get_ref(), &message_id, &room_id).await;
+    }
+    HttpResponse::Ok().json(load_poll(pool.get_ref(), &message_id, Some(&claims.sub)).await)
+}
+
+/// POST /api/messages/{id}/poll/close — End a poll now (its author or an admin)
+pub async fn close_poll(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let message_id = path.into_inner();
+    let room_id = match poll_room(pool.get_ref(), &message_id, &claims).await {
+        Ok(room_id) => room_id,
+        Err(response) => return response,
+    };
+    let author: String = sqlx::query_scalar("SELECT user_id FROM messages WHERE id = ?")
+        .bind(&message_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or_default();
+    if author != claims.sub
+        && crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::MANAGE_MESSAGES).await.is_err()
+    {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only the author of the poll can end it" }));
+    }
+
+    let closed = sqlx::query("UPDATE polls SET closed_at = ? WHERE message_id = ? AND closed_at IS NULL")
+        .bind(Utc::now().to_rfc3339())
+        .bind(&message_id)
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+    if closed > 0 {
+        broadcast_update(pool.get_ref(), broadcaster.get_ref(), &message_id, &room_id).await;
+    }
+    HttpResponse::Ok().json(load_poll(pool.get_ref(), &message_id, Some(&claims.sub)).await)
+}
+
+// ── Closer ──────────────────────────────────────────────
+
+/// Close expired polls and broadcast their final tallies.
+async fn close_expired(pool: &SqlitePool, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let now = Utc::now().to_rfc3339();
+    let due = sqlx::query(
+        "SELECT p.message_id, p.expires_at, m.room_id FROM polls p JOIN messages m ON m.id = p.message_id \
+         WHERE p.closed_at IS NULL AND p.expires_at <= ? ORDER BY p.expires_at LIMIT ?"
+    )
+    .bind(&now)
+    .bind(CLOSE_BATCH)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut closed = 0;
+    for row in &due {
+        let message_id: String = row.try_get("message_id").unwrap_or_default();
+        let room_id: String = row.try_get("room_id").unwrap_or_default();
+        let expires_at: String = row.try_get("expires_at").unwrap_or_default();
+        let updated = sqlx::query("UPDATE polls SET closed_at = ? WHERE message_id = ? AND closed_at IS NULL")
+            .bind(&expires_at)
+            .bind(&message_id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .rows_affected();
+        if updated > 0 {
+            closed += 1;
+            broadcast_update(pool, broadcaster, &message_id, &room_id).await;
+        }
+    }
+    Ok(closed)
+}
+
+async fn expired_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query("SELECT COUNT(*) AS depth, MIN(expires_at) AS oldest_due_at FROM polls WHERE closed_at IS NULL AND expires_at <= ?")
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(pool)
+        .await
+        .map(|row| Backlog::from_row(&row))
+        .unwrap_or_default()
+}
+
+pub fn spawn_poll_closer(pool: SqlitePool, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::POLLS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(CLOSE_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::POLLS, None, close_expired(&pool, &broadcaster), expired_backlog(&pool)).await;
+        }
+    });
+}
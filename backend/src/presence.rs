@@ -0,0 +1,387 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — User presence (online, idle, dnd, offline)
+// ═══════════════════════════════════════════════════════
+//
+// A user is connected while they have at least one realtime connection:
+// `/ws`, or an `/api/gateway` session (a detached session still counts
+// until its resume window ends, so a quick reconnect does not flicker).
+// The status they chose (`online`, `idle`, `dnd` or `invisible`) and their
+// custom status text are stored on the account and survive restarts; they
+// set them with `PUT /api/users/@me/presence` or a realtime `presence`
+// event. A chosen `dnd` also holds back `mention` events (see `quiet_hours`).
+//
+// Others see the effective status: `offline` while not connected or
+// invisible, the chosen status otherwise. Every change of it, or of the
+// custom status, is broadcast as
+// `{ type: "presence", user_id, status, custom_status }`, delivered only
+// to users who share at least one room with that user (and to the user).
+// Member lists (`GET /api/rooms/{id}/members`, `GET /api/server/users`)
+// carry the same two fields.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::auth::extract_claims;
+use crate::ws::{AccessCache, Broadcaster};
+
+pub const STATUSES: [&str; 4] = ["online", "idle", "dnd", "invisible"];
+pub const MAX_CUSTOM_STATUS_CHARS: usize = 128;
+
+/// Open realtime connections per user id.
+pub type PresenceTracker = Arc<Mutex<HashMap<String, usize>>>;
+
+pub fn create_presence_tracker() -> PresenceTracker {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Connected to this instance, or to another one when an event bus links
+/// several (see `bus`).
+pub fn is_connected(tracker: &PresenceTracker, user_id: &str) -> bool {
+    tracker.lock().unwrap().get(user_id).is_some_and(|n| *n > 0) || crate::bus::connected_elsewhere(user_id)
+}
+
+/// Users with a connection to this instance.
+pub(crate) fn connected_here(tracker: &PresenceTracker) -> Vec<String> {
+    tracker.lock().unwrap().iter().filter(|(_, n)| **n > 0).map(|(id, _)| id.clone()).collect()
+}
+
+/// The status others see for a user who chose `chosen`.
+pub fn effective_status(chosen: &str, connected: bool) -> &'static str {
+    match chosen {
+        _ if !connected => "offline",
+        "idle" => "idle",
+        "dnd" => "dnd",
+        "invisible" => "offline",
+        _ => "online",
+    }
+}
+
+/// What a user chose: their status and custom status text.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChosenPresence {
+    pub status: String,
+    pub custom_status: Option<String>,
+}
+
+async fn chosen(pool: &SqlitePool, user_id: &str) -> Option<ChosenPresence> {
+    sqlx::query("SELECT presence_status, custom_status FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| ChosenPresence {
+            status: row.get("presence_status"),
+            custom_status: row.try_get("custom_status").unwrap_or(None),
+        })
+}
+
+fn event(user_id: &str, status: &str, custom_status: Option<&str>) -> String {
+    serde_json::json!({
+        "type": "presence",
+        "user_id": user_id,
+        "status": status,
+        "custom_status": custom_status,
+    })
+    .to_string()
+}
+
+/// `{ status, custom_status }` as others see `user_id` right now.
+pub(crate) async fn current(pool: &SqlitePool, tracker: &PresenceTracker, user_id: &str) -> serde_json::Value {
+    let chosen = chosen(pool, user_id).await;
+    let status = effective_status(chosen.as_ref().map_or("online", |c| c.status.as_str()), is_connected(tracker, user_id));
+    serde_json::json!({
+        "status": status,
+        "custom_status": chosen.and_then(|c| c.custom_status).filter(|_| status != "offline"),
+    })
+}
+
+/// `(status, custom_status)` as others see the user of a row with `id`,
+/// `presence_status` and `custom_status` columns.
+pub(crate) fn from_row(tracker: &PresenceTracker, row: &sqlx::sqlite::SqliteRow) -> (&'static str, Option<String>) {
+    let id: String = row.get("id");
+    let chosen: String = row.get("presence_status");
+    let status = effective_status(&chosen, is_connected(tracker, &id));
+    let custom_status = if status == "offline" { None } else { row.try_get("custom_status").unwrap_or(None) };
+    (status, custom_status)
+}
+
+// ── Connections ─────────────────────────────────────────
+
+/// A realtime connection of `user_id` opened.
+pub(crate) async fn connected(pool: &SqlitePool, tracker: &PresenceTracker, tx: &Broadcaster, user_id: &str) {
+    let first = {
+        let mut guard = tracker.lock().unwrap();
+        let count = guard.entry(user_id.to_string()).or_insert(0);
+        *count += 1;
+        *count == 1
+    };
+    if first {
+        if let Some(chosen) = chosen(pool, user_id).await {
+            let status = effective_status(&chosen.status, true);
+            if status != "offline" {
+                let _ = tx.send(event(user_id, status, chosen.custom_status.as_deref()));
+            }
+        }
+    }
+}
+
+/// A realtime connection of `user_id` closed. `true` when others saw them
+/// go offline: it was their last connection and they were not invisible.
+pub(crate) async fn disconnected(pool: &SqlitePool, tracker: &PresenceTracker, tx: &Broadcaster, user_id: &str) -> bool {
+    let last = {
+        let mut guard = tracker.lock().unwrap();
+        match guard.get_mut(user_id) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                guard.remove(user_id);
+                true
+            }
+            None => false,
+        }
+    };
+    if !last {
+        return false;
+    }
+    match chosen(pool, user_id).await {
+        Some(chosen) if effective_status(&chosen.status, true) != "offline" => {
+            let _ = tx.send(event(user_id, "offline", chosen.custom_status.as_deref()));
+            true
+        }
+        _ => false,
+    }
+}
+
+// ── Updates ─────────────────────────────────────────────
+
+/// A change of status and/or custom status (`Some(None)` clears it).
+#[derive(Debug, Default, Deserialize)]
+pub struct PresenceUpdate {
+    pub status: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub custom_status: Option<Option<String>>,
+}
+
+/// Tells a `null` field apart from a missing one.
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+/// Store `update` for `user_id` and broadcast what others now see, if it changed.
+pub(crate) async fn update(
+    pool: &SqlitePool,
+    tracker: &PresenceTracker,
+    tx: &Broadcaster,
+    user_id: &str,
+    update: PresenceUpdate,
+) -> Result<ChosenPresence, String> {
+    let Some(before) = chosen(pool, user_id).await else {
+        return Err("User not found".to_string());
+    };
+    let status = match update.status {
+        Some(status) => {
+            let status = status.trim().to_lowercase();
+            if !STATUSES.contains(&status.as_str()) {
+                return Err("status must be online, idle, dnd or invisible".to_string());
+            }
+            status
+        }
+        None => before.status.clone(),
+    };
+    let custom_status = match update.custom_status {
+        Some(text) => {
+            let text = text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+            if text.as_ref().is_some_and(|t| t.chars().count() > MAX_CUSTOM_STATUS_CHARS) {
+                return Err(format!("Custom status too long (max {MAX_CUSTOM_STATUS_CHARS} characters)"));
+            }
+            text
+        }
+        None => before.custom_status.clone(),
+    };
+
+    let result = sqlx::query("UPDATE users SET presence_status = ?, custom_status = ? WHERE id = ?")
+        .bind(&status)
+        .bind(&custom_status)
+        .bind(user_id)
+        .execute(pool)
+        .await;
+    if result.is_err() {
+        return Err("Could not save the presence".to_string());
+    }
+
+    let connected = is_connected(tracker, user_id);
+    let seen_before = effective_status(&before.status, connected);
+    let seen_after = effective_status(&status, connected);
+    // Invisible users' custom status stays hidden until they show up.
+    let visible_custom_change = custom_status != before.custom_status && seen_after != "offline";
+    if seen_before != seen_after || visible_custom_change {
+        let _ = tx.send(event(user_id, seen_after, custom_status.as_deref()));
+    }
+    Ok(ChosenPresence { status, custom_status })
+}
+
+// ── Delivery ────────────────────────────────────────────
+
+/// The user a `join`, `leave` or `presence` event is about.
+pub(crate) fn subject(event: &serde_json::Value) -> Option<&str> {
+    if !matches!(event.get("type").and_then(|t| t.as_str()), Some("join" | "leave" | "presence")) {
+        return None;
+    }
+    event.get("user_id").and_then(|u| u.as_str())
+}
+
+/// Whether `viewer_id` may see the presence of `subject_id`: themselves, or
+/// someone they share at least one room with.
+pub(crate) async fn visible_to(pool: &SqlitePool, cache: &AccessCache, viewer_id: &str, subject_id: &str) -> bool {
+    if viewer_id == subject_id {
+        return true;
+    }
+    let (Some(viewer_role), Some(subject_role)) = (
+        crate::ws::get_user_role_cached(pool, cache, viewer_id).await,
+        crate::ws::get_user_role_cached(pool, cache, subject_id).await,
+    ) else {
+        return false;
+    };
+    let viewer_rooms = crate::permissions::viewable_rooms(pool, viewer_id, &viewer_role).await;
+    let subject_rooms = crate::permissions::viewable_rooms(pool, subject_id, &subject_role).await;
+    !viewer_rooms.is_disjoint(&subject_rooms)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/users/@me/presence — The status and custom status you chose
+pub async fn get_own_presence(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match chosen(pool.get_ref(), &claims.sub).await {
+        Some(chosen) => HttpResponse::Ok().json(chosen),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// PUT /api/users/@me/presence — Set your status and/or custom status (`null` clears it)
+pub async fn set_own_presence(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    tracker: web::Data<PresenceTracker>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<PresenceUpdate>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match update(pool.get_ref(), tracker.get_ref(), broadcaster.get_ref(), &claims.sub, body.into_inner()).await {
+        Ok(chosen) => HttpResponse::Ok().json(chosen),
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomMember {
+    pub id: String,
+    pub username: String,
+    pub role: String,
+    pub avatar_color: i32,
+    pub avatar_url: Option<String>,
+    /// From the member profile, see `member_profiles`.
+    pub nickname: Option<String>,
+    pub pronouns: Option<String>,
+    pub status: &'static str,
+    pub custom_status: Option<String>,
+    #[serde(flatten)]
+    pub role_display: crate::roles::RoleDisplay,
+}
+
+const ROOM_MEMBERS: crate::pagination::Listing = crate::pagination::Listing {
+    name: "room_members",
+    default_limit: 200,
+    max_limit: 1000,
+    sorts: &[("username", "u.username")],
+    default_sort: "username",
+    tiebreak: "u.id",
+};
+
+#[derive(Debug, Deserialize)]
+pub struct RoomMembersQuery {
+    pub limit: Option<i64>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+}
+
+/// GET /api/rooms/{id}/members?limit=&sort=&cursor= — Everyone who can see the room, with their presence
+pub async fn list_room_members(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    tracker: web::Data<PresenceTracker>,
+    path: web::Path<String>,
+    query: web::Query<RoomMembersQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+
+    let page = match ROOM_MEMBERS.page(query.limit, query.sort.as_deref(), query.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+
+    let sql = format!(
+        "SELECT u.id, u.username, u.role, u.avatar_color, COALESCE(mp.avatar_url, u.avatar_url) AS avatar_url, \
+         mp.nickname, mp.pronouns, u.presence_status, u.custom_status, {}, \
+         r.color AS role_color, r.icon_url AS role_icon_url, r.hoist AS role_hoist \
+         FROM users u LEFT JOIN roles r ON r.name = u.role LEFT JOIN member_profiles mp ON mp.user_id = u.id WHERE 1=1{}{}",
+        page.sort_value_column(),
+        page.keyset_clause(),
+        page.order_clause()
+    );
+    let mut qx = sqlx::query(&sql);
+    for value in page.keyset_binds() {
+        qx = qx.bind(value);
+    }
+    let Ok(rows) = qx.bind(page.fetch_limit()).fetch_all(pool.get_ref()).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    // Paged over every user, then filtered: pages can come out short.
+    let (rows, next_cursor) = page.finish_rows(rows, "id");
+    let everyone: Vec<(String, String)> = rows.iter().map(|row| (row.get("id"), row.get("role"))).collect();
+    let viewers: HashSet<String> = crate::permissions::members_with(pool.get_ref(), &room_id, everyone, crate::permissions::VIEW_ROOM)
+        .await
+        .into_iter()
+        .collect();
+
+    let members: Vec<RoomMember> = rows
+        .iter()
+        .filter(|row| viewers.contains(&row.get::<String, _>("id")))
+        .map(|row| {
+            let (status, custom_status) = from_row(tracker.get_ref(), row);
+            RoomMember {
+                id: row.get("id"),
+                username: row.get("username"),
+                role: row.get("role"),
+                avatar_color: row.try_get("avatar_color").unwrap_or(0),
+                avatar_url: row.try_get("avatar_url").unwrap_or(None),
+                nickname: row.try_get("nickname").unwrap_or(None),
+                pronouns: row.try_get("pronouns").unwrap_or(None),
+                status,
+                custom_status,
+                role_display: crate::roles::RoleDisplay::from_row(row),
+            }
+        })
+        .collect();
+    crate::pagination::with_next_cursor(HttpResponse::Ok().json(members), next_cursor)
+}
@@ -0,0 +1,287 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Quick switcher search (Ctrl+K)
+// ═══════════════════════════════════════════════════════
+//
+// `GET /api/quickswitch?q=` fuzzy-matches rooms and users in one call. Both
+// lists are kept in memory so a keystroke does not hit SQLite. The index is
+// marked dirty when rooms change (room events on the broadcaster, room
+// creation) or users change (register, profile, role, deletion) and rebuilt
+// by the next search. It is also rebuilt after `INDEX_MAX_AGE`, which covers
+// users created by the Discord login flows.
+//
+// Results are filtered per caller: rooms they can see (same rule as
+// `list_rooms`), users the caller blocked are left out. Discord guilds and
+// DMs are not known to the backend, the client merges those itself.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auth::extract_claims;
+use crate::relationships::fetch_blocked_ids;
+use crate::ws::Broadcaster;
+
+const INDEX_MAX_AGE: Duration = Duration::from_secs(60);
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 50;
+const MAX_QUERY_CHARS: usize = 64;
+
+// ── Index ───────────────────────────────────────────────
+
+#[derive(Clone, sqlx::FromRow)]
+struct IndexedRoom {
+    id: String,
+    name: String,
+    kind: String,
+    topic: Option<String>,
+}
+
+#[derive(Clone, sqlx::FromRow)]
+struct IndexedUser {
+    id: String,
+    username: String,
+    role: String,
+    avatar_color: i32,
+    avatar_url: Option<String>,
+}
+
+#[derive(Default)]
+pub struct IndexState {
+    rooms: Arc<Vec<IndexedRoom>>,
+    users: Arc<Vec<IndexedUser>>,
+    built_at: Option<Instant>,
+    dirty: bool,
+}
+
+pub type QuickSwitchIndex = Arc<Mutex<IndexState>>;
+
+pub fn create_quickswitch_index() -> QuickSwitchIndex {
+    Arc::new(Mutex::new(IndexState::default()))
+}
+
+/// Mark the index stale, the next search rebuilds it.
+pub fn invalidate(index: &QuickSwitchIndex) {
+    index.lock().unwrap().dirty = true;
+}
+
+/// `invalidate` for handlers that do not take the index as an argument.
+pub(crate) fn invalidate_from(req: &HttpRequest) {
+    if let Some(index) = req.app_data::<web::Data<QuickSwitchIndex>>() {
+        invalidate(index.get_ref());
+    }
+}
+
+/// Invalidate on room events, so edits made anywhere (REST, config apply)
+/// show up without each call site knowing about the index.
+pub fn spawn_index_invalidator(broadcaster: Broadcaster, index: QuickSwitchIndex) {
+    let mut rx = broadcaster.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let kind = serde_json::from_str::<serde_json::Value>(&event)
+                        .ok()
+                        .and_then(|v| v.get("type").and_then(|t| t.as_str()).map(str::to_string));
+                    if matches!(kind.as_deref(), Some("room_updated" | "room_deleted" | "layout_updated")) {
+                        invalidate(&index);
+                    }
+                }
+                // Missed events might have been room changes.
+                Err(RecvError::Lagged(_)) => invalidate(&index),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+type Snapshot = (Arc<Vec<IndexedRoom>>, Arc<Vec<IndexedUser>>);
+
+/// Current rooms and users, rebuilt from the DB first when stale.
+async fn snapshot(pool: &SqlitePool, index: &QuickSwitchIndex) -> Result<Snapshot, sqlx::Error> {
+    {
+        let state = index.lock().unwrap();
+        let fresh = state.built_at.is_some_and(|t| t.elapsed() < INDEX_MAX_AGE);
+        if fresh && !state.dirty {
+            return Ok((state.rooms.clone(), state.users.clone()));
+        }
+    }
+
+    // Cleared before reading, so a change landing during the rebuild marks
+    // it dirty again instead of being lost.
+    index.lock().unwrap().dirty = false;
+
+    let rooms = sqlx::query_as::<_, IndexedRoom>(
+        "SELECT id, name, kind, topic FROM rooms ORDER BY created_at"
+    )
+    .fetch_all(pool)
+    .await?;
+    let users = sqlx::query_as::<_, IndexedUser>(
+        "SELECT id, username, role, COALESCE(avatar_color, 0) AS avatar_color, avatar_url FROM users ORDER BY username"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut state = index.lock().unwrap();
+    state.rooms = Arc::new(rooms);
+    state.users = Arc::new(users);
+    state.built_at = Some(Instant::now());
+    Ok((state.rooms.clone(), state.users.clone()))
+}
+
+// ── Matching ────────────────────────────────────────────
+
+/// Lowercase without the accents common in French room names, so "gen"
+/// finds "général".
+fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'â' | 'ä' | 'á' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'î' | 'ï' | 'í' => 'i',
+            'ô' | 'ö' | 'ó' => 'o',
+            'ù' | 'û' | 'ü' | 'ú' => 'u',
+            'ç' => 'c',
+            'ÿ' => 'y',
+            other => other,
+        })
+        .collect()
+}
+
+/// Score of `candidate` for the folded `query`, higher is better, `None`
+/// when it does not match. Exact > prefix > word prefix > substring >
+/// subsequence, shorter candidates win ties.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate = fold(candidate);
+    let length_penalty = candidate.chars().count().min(50) as i64;
+
+    if candidate == query {
+        return Some(1000);
+    }
+    if candidate.starts_with(query) {
+        return Some(800 - length_penalty);
+    }
+    let word_prefix = candidate
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(query));
+    if word_prefix {
+        return Some(600 - length_penalty);
+    }
+    if let Some(pos) = candidate.find(query) {
+        return Some(400 - pos.min(50) as i64 - length_penalty);
+    }
+
+    // Every query char in order, e.g. "gnrl" in "general". Each skipped
+    // char costs a point so tight matches rank first.
+    let mut chars = candidate.chars();
+    let mut gaps = 0i64;
+    for q in query.chars() {
+        loop {
+            match chars.next() {
+                Some(c) if c == q => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some((200 - gaps - length_penalty).max(1))
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct QuickSwitchQuery {
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuickSwitchResult {
+    /// "room" or "user"
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: String,
+    pub name: String,
+    /// Room kind ("text"/"voice") or user role.
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_color: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    pub score: i64,
+}
+
+/// GET /api/quickswitch?q=&limit= — Rooms and users matching `q`, best first
+pub async fn quickswitch(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    index: web::Data<QuickSwitchIndex>,
+    query: web::Query<QuickSwitchQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let q: String = fold(query.q.as_deref().unwrap_or("").trim())
+        .chars()
+        .take(MAX_QUERY_CHARS)
+        .collect();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let (rooms, users) = match snapshot(pool.get_ref(), index.get_ref()).await {
+        Ok(snapshot) => snapshot,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let viewable = crate::permissions::viewable_rooms(pool.get_ref(), &claims.sub, &claims.role).await;
+    let can_see = |room: &IndexedRoom| viewable.contains(&room.id);
+
+    let mut results: Vec<QuickSwitchResult> = Vec::new();
+    for room in rooms.iter().filter(|r| can_see(r)) {
+        // No query: list rooms in sidebar order, nothing else.
+        let score = if q.is_empty() { Some(0) } else { fuzzy_score(&q, &room.name) };
+        if let Some(score) = score {
+            results.push(QuickSwitchResult {
+                kind: "room",
+                id: room.id.clone(),
+                name: room.name.clone(),
+                detail: room.kind.clone(),
+                topic: room.topic.clone(),
+                avatar_color: None,
+                avatar_url: None,
+                // Rooms are what people switch to most, they win ties.
+                score: score + 1,
+            });
+        }
+    }
+
+    if !q.is_empty() {
+        let blocked = fetch_blocked_ids(pool.get_ref(), &claims.sub).await;
+        for user in users.iter().filter(|u| u.id != claims.sub && !blocked.contains(&u.id)) {
+            if let Some(score) = fuzzy_score(&q, &user.username) {
+                results.push(QuickSwitchResult {
+                    kind: "user",
+                    id: user.id.clone(),
+                    name: user.username.clone(),
+                    detail: user.role.clone(),
+                    topic: None,
+                    avatar_color: Some(user.avatar_color),
+                    avatar_url: user.avatar_url.clone(),
+                    score,
+                });
+            }
+        }
+    }
+
+    // Stable sort keeps sidebar order among equal scores.
+    results.sort_by_key(|r| std::cmp::Reverse(r.score));
+    results.truncate(limit);
+
+    HttpResponse::Ok().json(serde_json::json!({ "results": results }))
+}
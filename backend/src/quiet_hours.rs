@@ -0,0 +1,273 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Quiet hours and Do Not Disturb
+// ═══════════════════════════════════════════════════════
+//
+// A user may set one daily window (`22:00` to `07:00`, say) during which
+// nothing is pushed at them. The window is in their local time, given as
+// a UTC offset the client keeps current (it changes with daylight saving),
+// and may be limited to some days of the week; a window crossing midnight
+// belongs to the day it starts on.
+//
+// While in quiet hours, or while their chosen presence is `dnd`, a user
+// gets no `mention` event (see `mentions::notify_mentions`). Quiet hours
+// also hold back email: the mention digest waits for the window to end
+// and friend request mails are not sent. Security alerts always go out.
+// Mentions are still recorded, so unread counts keep adding up.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const ALL_DAYS: i64 = 0b111_1111;
+/// UTC-12:00 to UTC+14:00.
+const MIN_OFFSET_MINUTES: i64 = -12 * 60;
+const MAX_OFFSET_MINUTES: i64 = 14 * 60;
+
+/// A daily window, in minutes of the user's local day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub start_minute: i64,
+    pub end_minute: i64,
+    pub utc_offset_minutes: i64,
+    /// Monday = bit 0 to Sunday = bit 6: the days a window starts on.
+    pub days: i64,
+}
+
+impl Window {
+    /// Whether `at` falls in the window.
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        let local = at + Duration::minutes(self.utc_offset_minutes);
+        let minute = i64::from(local.hour() * 60 + local.minute());
+        let starts_on = |day: DateTime<Utc>| self.days & (1 << day.weekday().num_days_from_monday()) != 0;
+        if self.start_minute < self.end_minute {
+            starts_on(local) && (self.start_minute..self.end_minute).contains(&minute)
+        } else {
+            (minute >= self.start_minute && starts_on(local))
+                || (minute < self.end_minute && starts_on(local - Duration::days(1)))
+        }
+    }
+
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Window {
+            start_minute: row.get("start_minute"),
+            end_minute: row.get("end_minute"),
+            utc_offset_minutes: row.get("utc_offset_minutes"),
+            days: row.get("days"),
+        }
+    }
+}
+
+async fn window(pool: &SqlitePool, user_id: &str) -> Option<Window> {
+    sqlx::query("SELECT start_minute, end_minute, utc_offset_minutes, days FROM quiet_hours WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Window::from_row(&row))
+}
+
+/// `user_id`'s quiet hours, if set.
+pub(crate) async fn current(pool: &SqlitePool, user_id: &str) -> Option<QuietHours> {
+    window(pool, user_id).await.as_ref().map(QuietHours::from_window)
+}
+
+/// Whether `user_id` is in their quiet hours right now.
+pub(crate) async fn is_quiet(pool: &SqlitePool, user_id: &str) -> bool {
+    window(pool, user_id).await.is_some_and(|w| w.contains(Utc::now()))
+}
+
+/// Of `user_ids`, those neither in quiet hours nor set to `dnd`, in the
+/// same order.
+pub(crate) async fn interruptible(pool: &SqlitePool, user_ids: &[String]) -> Vec<String> {
+    if user_ids.is_empty() {
+        return Vec::new();
+    }
+    let sql = format!(
+        "SELECT u.id, u.presence_status, q.start_minute, q.end_minute, q.utc_offset_minutes, q.days \
+         FROM users u LEFT JOIN quiet_hours q ON q.user_id = u.id WHERE u.id IN ({})",
+        vec!["?"; user_ids.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for user_id in user_ids {
+        query = query.bind(user_id);
+    }
+    let Ok(rows) = query.fetch_all(pool).await else {
+        return user_ids.to_vec();
+    };
+    let now = Utc::now();
+    let quiet: Vec<String> = rows
+        .iter()
+        .filter(|row| {
+            row.get::<String, _>("presence_status") == "dnd"
+                || row.get::<Option<i64>, _>("start_minute").is_some() && Window::from_row(row).contains(now)
+        })
+        .map(|row| row.get("id"))
+        .collect();
+    user_ids.iter().filter(|id| !quiet.contains(id)).cloned().collect()
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// `HH:MM`, local time.
+    pub start: String,
+    pub end: String,
+    pub utc_offset_minutes: i64,
+    /// Days the window starts on, `mon` to `sun`; every day when omitted.
+    #[serde(default)]
+    pub days: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuietHoursState {
+    pub quiet_hours: Option<QuietHours>,
+    /// In the window right now.
+    pub active: bool,
+}
+
+fn format_minute(minute: i64) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+fn parse_minute(raw: &str) -> Option<i64> {
+    let (hours, minutes) = raw.split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    ((0..24).contains(&hours) && (0..60).contains(&minutes) && raw.len() == 5).then_some(hours * 60 + minutes)
+}
+
+impl QuietHours {
+    fn from_window(window: &Window) -> Self {
+        QuietHours {
+            start: format_minute(window.start_minute),
+            end: format_minute(window.end_minute),
+            utc_offset_minutes: window.utc_offset_minutes,
+            days: Some(
+                DAYS.iter()
+                    .enumerate()
+                    .filter(|(bit, _)| window.days & (1 << bit) != 0)
+                    .map(|(_, day)| day.to_string())
+                    .collect(),
+            ),
+        }
+    }
+
+    fn to_window(&self) -> Result<Window, &'static str> {
+        let (Some(start_minute), Some(end_minute)) = (parse_minute(&self.start), parse_minute(&self.end)) else {
+            return Err("start and end must be HH:MM times");
+        };
+        if start_minute == end_minute {
+            return Err("start and end must differ");
+        }
+        if !(MIN_OFFSET_MINUTES..=MAX_OFFSET_MINUTES).contains(&self.utc_offset_minutes) {
+            return Err("utc_offset_minutes must be between -720 and 840");
+        }
+        let days = match &self.days {
+            None => ALL_DAYS,
+            Some(days) => {
+                let mut mask = 0;
+                for day in days {
+                    let Some(bit) = DAYS.iter().position(|d| d == day) else {
+                        return Err("days must be among mon, tue, wed, thu, fri, sat and sun");
+                    };
+                    mask |= 1 << bit;
+                }
+                if mask == 0 {
+                    return Err("days must not be empty");
+                }
+                mask
+            }
+        };
+        Ok(Window { start_minute, end_minute, utc_offset_minutes: self.utc_offset_minutes, days })
+    }
+}
+
+fn state(window: Option<Window>) -> QuietHoursState {
+    QuietHoursState {
+        active: window.is_some_and(|w| w.contains(Utc::now())),
+        quiet_hours: window.as_ref().map(QuietHours::from_window),
+    }
+}
+
+fn announce(broadcaster: &Broadcaster, user_id: &str, state: &QuietHoursState) {
+    let event = serde_json::json!({
+        "type": "quiet_hours_updated",
+        "recipient_id": user_id,
+        "quiet_hours": state.quiet_hours,
+    });
+    let _ = broadcaster.send(event.to_string());
+}
+
+/// GET /api/users/@me/quiet-hours — The caller's quiet hours, and whether they are on now
+pub async fn get_quiet_hours(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    HttpResponse::Ok().json(state(window(pool.get_ref(), &claims.sub).await))
+}
+
+/// PUT /api/users/@me/quiet-hours — Set the caller's quiet hours
+pub async fn set_quiet_hours(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<QuietHours>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let window = match body.to_window() {
+        Ok(window) => window,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+    let result = sqlx::query(
+        "INSERT INTO quiet_hours (user_id, start_minute, end_minute, utc_offset_minutes, days, updated_at) VALUES (?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(user_id) DO UPDATE SET start_minute = excluded.start_minute, end_minute = excluded.end_minute, \
+         utc_offset_minutes = excluded.utc_offset_minutes, days = excluded.days, updated_at = excluded.updated_at"
+    )
+    .bind(&claims.sub)
+    .bind(window.start_minute)
+    .bind(window.end_minute)
+    .bind(window.utc_offset_minutes)
+    .bind(window.days)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let state = state(Some(window));
+    announce(broadcaster.get_ref(), &claims.sub, &state);
+    HttpResponse::Ok().json(state)
+}
+
+/// DELETE /api/users/@me/quiet-hours — Turn the caller's quiet hours off
+pub async fn clear_quiet_hours(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let result = sqlx::query("DELETE FROM quiet_hours WHERE user_id = ?")
+        .bind(&claims.sub)
+        .execute(pool.get_ref())
+        .await;
+    match result {
+        Ok(_) => {
+            announce(broadcaster.get_ref(), &claims.sub, &state(None));
+            HttpResponse::NoContent().finish()
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,289 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Per-route rate limiting (token buckets)
+// ═══════════════════════════════════════════════════════
+//
+// The global governor (10 req/s per IP) is far too loose for endpoints that
+// are expensive or guess-able: a QR login spawns an RSA keygen and a Discord
+// WebSocket, a voice join opens a gateway, and login endpoints take
+// passwords and codes. Those are split into route groups, each with its own
+// token bucket per client IP and, when the request is authenticated, per
+// user. A request needs a token from every bucket it falls into.
+//
+// Config (env), as `<requests>/<seconds>` or `off`:
+//   RATE_LIMIT_AUTH    login, register, refresh, 2FA, sudo, recovery, passkey and
+//                      Discord sign-in endpoints (default 20/60)
+//   RATE_LIMIT_QR      Discord QR login start (default 3/60)
+//   RATE_LIMIT_VOICE   Discord voice join (default 6/60)
+//   RATE_LIMIT_BACKFILL  older history pages of the backfill endpoint, the
+//                      recent tier is not limited (default 30/60)
+//   RATE_LIMIT_GUEST   guest link redemption (default 5/600)
+//   RATE_LIMIT_WEBHOOK  executing an incoming webhook, per webhook rather
+//                      than per IP or user (default 5/2, like Discord)
+//   RATE_LIMIT_TRUST_PROXY=1  key on X-Forwarded-For / Forwarded instead of
+//                      the socket address (only behind a reverse proxy)
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::extract_claims;
+
+/// Sweep idle buckets once the map grows past this.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+// ── Route groups ────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Auth,
+    Qr,
+    Voice,
+    Backfill,
+    Guest,
+    Webhook,
+}
+
+impl RouteGroup {
+    fn env_var(self) -> &'static str {
+        match self {
+            RouteGroup::Auth => "RATE_LIMIT_AUTH",
+            RouteGroup::Qr => "RATE_LIMIT_QR",
+            RouteGroup::Voice => "RATE_LIMIT_VOICE",
+            RouteGroup::Backfill => "RATE_LIMIT_BACKFILL",
+            RouteGroup::Guest => "RATE_LIMIT_GUEST",
+            RouteGroup::Webhook => "RATE_LIMIT_WEBHOOK",
+        }
+    }
+
+    fn default_limit(self) -> Limit {
+        match self {
+            RouteGroup::Auth => Limit { requests: 20, period: Duration::from_secs(60) },
+            RouteGroup::Qr => Limit { requests: 3, period: Duration::from_secs(60) },
+            RouteGroup::Voice => Limit { requests: 6, period: Duration::from_secs(60) },
+            RouteGroup::Backfill => Limit { requests: 30, period: Duration::from_secs(60) },
+            RouteGroup::Guest => Limit { requests: 5, period: Duration::from_secs(600) },
+            RouteGroup::Webhook => Limit { requests: 5, period: Duration::from_secs(2) },
+        }
+    }
+
+    /// Group of a request, `None` for routes that are not limited here.
+    fn of(method: &Method, path: &str, query: &str) -> Option<RouteGroup> {
+        if method == Method::GET {
+            let is_backfill = path
+                .strip_prefix("/api/rooms/")
+                .and_then(|rest| rest.strip_suffix("/backfill"))
+                .is_some_and(|room_id| !room_id.contains('/'));
+            let older_tier = query.split('&').any(|pair| pair.starts_with("before="));
+            return (is_backfill && older_tier).then_some(RouteGroup::Backfill);
+        }
+        if method != Method::POST {
+            return None;
+        }
+        if webhook_id(path).is_some() {
+            return Some(RouteGroup::Webhook);
+        }
+        match path {
+            "/api/auth/discord/qr/start" => Some(RouteGroup::Qr),
+            "/api/discord/voice/join" => Some(RouteGroup::Voice),
+            "/api/guest/redeem" => Some(RouteGroup::Guest),
+            "/api/login"
+            | "/api/register"
+            | "/api/auth/refresh"
+            | "/api/auth/2fa/login"
+            | "/api/auth/sudo"
+            | "/api/auth/recovery/start"
+            | "/api/auth/recovery/complete"
+            | "/api/auth/passkey/login/start"
+            | "/api/auth/passkey/login/finish"
+            | "/api/auth/discord/token"
+            | "/api/auth/discord/oauth/callback"
+            | "/api/auth/discord/password/login"
+            | "/api/auth/discord/password/mfa"
+            | "/api/auth/discord/password/mfa/sms/send" => Some(RouteGroup::Auth),
+            _ => None,
+        }
+    }
+}
+
+/// The id of `/api/webhooks/{id}/{token}`.
+fn webhook_id(path: &str) -> Option<&str> {
+    let (id, token) = path.strip_prefix("/api/webhooks/")?.split_once('/')?;
+    (!id.is_empty() && !token.is_empty() && !token.contains('/')).then_some(id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl Limit {
+    pub(crate) fn refill_per_sec(&self) -> f64 {
+        self.requests as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// `<requests>/<seconds>`, `None` for `off`.
+pub(crate) fn parse_limit(raw: &str) -> Result<Option<Limit>, String> {
+    let raw = raw.trim();
+    if raw.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+    let (requests, seconds) = raw
+        .split_once('/')
+        .ok_or_else(|| format!("expected <requests>/<seconds>, got '{raw}'"))?;
+    let requests: u32 = requests.trim().parse().map_err(|_| format!("invalid request count in '{raw}'"))?;
+    let seconds: u64 = seconds.trim().parse().map_err(|_| format!("invalid period in '{raw}'"))?;
+    if requests == 0 || seconds == 0 {
+        return Err(format!("'{raw}' must be positive (use 'off' to disable)"));
+    }
+    Ok(Some(Limit { requests, period: Duration::from_secs(seconds) }))
+}
+
+fn limit_from_env(group: RouteGroup) -> Option<Limit> {
+    match std::env::var(group.env_var()) {
+        Ok(raw) if !raw.trim().is_empty() => match parse_limit(&raw) {
+            Ok(limit) => limit,
+            Err(e) => {
+                eprintln!("⚠️  {}: {e}, using the default", group.env_var());
+                Some(group.default_limit())
+            }
+        },
+        _ => Some(group.default_limit()),
+    }
+}
+
+// ── Buckets ─────────────────────────────────────────────
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub struct RateLimiterState {
+    limits: HashMap<RouteGroup, Limit>,
+    buckets: HashMap<(RouteGroup, String), Bucket>,
+    trust_proxy: bool,
+}
+
+pub type RateLimiter = Arc<Mutex<RateLimiterState>>;
+
+pub fn create_rate_limiter() -> RateLimiter {
+    let limits = [RouteGroup::Auth, RouteGroup::Qr, RouteGroup::Voice, RouteGroup::Backfill, RouteGroup::Guest, RouteGroup::Webhook]
+        .into_iter()
+        .filter_map(|group| limit_from_env(group).map(|limit| (group, limit)))
+        .collect();
+    let trust_proxy = std::env::var("RATE_LIMIT_TRUST_PROXY")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    Arc::new(Mutex::new(RateLimiterState {
+        limits,
+        buckets: HashMap::new(),
+        trust_proxy,
+    }))
+}
+
+impl RateLimiterState {
+    /// Sustained spacing between two requests of `group`, zero when the
+    /// group is not limited.
+    pub fn min_interval(&self, group: RouteGroup) -> Duration {
+        self.limits
+            .get(&group)
+            .map(|limit| limit.period / limit.requests)
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Take one token from each key's bucket. On refusal nothing is taken and
+    /// the wait until every bucket has a token again is returned.
+    fn check(&mut self, group: RouteGroup, keys: &[String]) -> Result<(), Duration> {
+        let Some(limit) = self.limits.get(&group).copied() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let capacity = limit.requests as f64;
+        let rate = limit.refill_per_sec();
+
+        if self.buckets.len() > MAX_TRACKED_BUCKETS {
+            // A bucket idle for a full period is back to capacity, same as a new one.
+            let limits = &self.limits;
+            self.buckets.retain(|(g, _), b| {
+                limits.get(g).is_some_and(|l| now.duration_since(b.updated_at) < l.period)
+            });
+        }
+
+        let mut wait = Duration::ZERO;
+        for key in keys {
+            let bucket = self.buckets.entry((group, key.clone())).or_insert(Bucket { tokens: capacity, updated_at: now });
+            let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+            bucket.updated_at = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max(Duration::from_secs_f64((1.0 - bucket.tokens) / rate));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        for key in keys {
+            if let Some(bucket) = self.buckets.get_mut(&(group, key.clone())) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ── Middleware ──────────────────────────────────────────
+
+/// Wrapped around the whole app with `middleware::from_fn`. Routes outside
+/// the groups above go straight through.
+pub async fn rate_limit<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let Some(group) = RouteGroup::of(req.method(), req.path(), req.query_string()) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let trust_proxy = limiter.lock().unwrap().trust_proxy;
+    let ip = if trust_proxy {
+        req.connection_info().realip_remote_addr().map(str::to_string)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    };
+
+    let mut keys = Vec::with_capacity(2);
+    if group == RouteGroup::Webhook {
+        // Integrations often share an IP (CI runners), the webhook is the client.
+        keys.extend(webhook_id(req.path()).map(|id| format!("webhook:{id}")));
+    } else if let Some(ip) = ip {
+        keys.push(format!("ip:{ip}"));
+    }
+    if let Some(claims) = extract_claims(req.request()) {
+        keys.push(format!("user:{}", claims.sub));
+    }
+
+    let verdict = limiter.lock().unwrap().check(group, &keys);
+    match verdict {
+        Ok(()) => next.call(req).await.map(ServiceResponse::map_into_left_body),
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let response = HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after.to_string()))
+                .json(serde_json::json!({
+                    "error": "Too many requests, slow down",
+                    "retry_after": retry_after,
+                }));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Read states and unread counters
+// ═══════════════════════════════════════════════════════
+//
+// `read_states` keeps, per user and room, the last message the user has
+// read. `POST /api/rooms/{id}/ack` moves it forward (never back) and tells
+// the user's other connections with a `read_state_updated` event, so
+// reading on one device clears the badge on the others.
+//
+// `GET /api/rooms` includes for each room:
+//   - unread_count   messages from others after the cursor (capped at
+//                    `MAX_UNREAD_COUNT`), 0 for rooms never opened
+//   - mention_count  messages mentioning the user after the cursor (all of
+//                    them for rooms never opened)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+/// Counting stops here, the client shows "99+" long before.
+const MAX_UNREAD_COUNT: i64 = 1000;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RoomCounters {
+    pub last_read_message_id: Option<String>,
+    pub unread_count: i64,
+    pub mention_count: i64,
+}
+
+/// `(created_at, id)` of the last read message of `user_id` in each room.
+async fn cursors(pool: &SqlitePool, user_id: &str) -> HashMap<String, (String, String)> {
+    sqlx::query("SELECT room_id, last_read_at, last_read_message_id FROM read_states WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| (row.get("room_id"), (row.get("last_read_at"), row.get("last_read_message_id"))))
+        .collect()
+}
+
+async fn room_counters(pool: &SqlitePool, user_id: &str, room_id: &str, cursor: Option<&(String, String)>) -> RoomCounters {
+    let unread_count = match cursor {
+        Some((at, id)) => sqlx::query_scalar(
+            "SELECT COUNT(*) FROM (SELECT 1 FROM messages WHERE room_id = ? AND user_id != ? AND deleted_at IS NULL AND (created_at, id) > (?, ?) LIMIT ?)"
+        )
+        .bind(room_id)
+        .bind(user_id)
+        .bind(at)
+        .bind(id)
+        .bind(MAX_UNREAD_COUNT)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0),
+        None => 0,
+    };
+
+    // No cursor: the empty pair sorts before every message.
+    let (at, id) = cursor.map(|(at, id)| (at.as_str(), id.as_str())).unwrap_or(("", ""));
+    let mention_count = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM message_mentions WHERE user_id = ? AND room_id = ? AND (created_at, message_id) > (?, ?)"
+    )
+    .bind(user_id)
+    .bind(room_id)
+    .bind(at)
+    .bind(id)
+    .fetch_one(pool)
+    .await
+    .unwrap_or(0);
+
+    RoomCounters {
+        last_read_message_id: cursor.map(|(_, id)| id.clone()),
+        unread_count,
+        mention_count,
+    }
+}
+
+/// Counters of `user_id` for each of `room_ids`.
+pub(crate) async fn counters_for_rooms(
+    pool: &SqlitePool,
+    user_id: &str,
+    room_ids: &[String],
+) -> HashMap<String, RoomCounters> {
+    let cursors = cursors(pool, user_id).await;
+    let mut counters = HashMap::with_capacity(room_ids.len());
+    for room_id in room_ids {
+        let c = room_counters(pool, user_id, room_id, cursors.get(room_id)).await;
+        counters.insert(room_id.clone(), c);
+    }
+    counters
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct AckPayload {
+    /// Defaults to the latest message of the room.
+    pub message_id: Option<String>,
+}
+
+/// POST /api/rooms/{id}/ack — Mark the room read up to a message (default: the latest)
+pub async fn ack(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: Option<web::Json<AckPayload>>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+
+    let requested = body.and_then(|b| b.into_inner().message_id);
+    let target = match &requested {
+        Some(id) => sqlx::query("SELECT id, created_at FROM messages WHERE id = ? AND room_id = ?")
+            .bind(id)
+            .bind(&room_id),
+        None => sqlx::query("SELECT id, created_at FROM messages WHERE room_id = ? AND deleted_at IS NULL ORDER BY created_at DESC, id DESC LIMIT 1")
+            .bind(&room_id),
+    }
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+
+    if let Some(row) = target {
+        let message_id: String = row.get("id");
+        let created_at: String = row.get("created_at");
+        // Only ever forward: a slow device acking an older message must not
+        // bring back badges that another device already cleared.
+        let result = sqlx::query(
+            "INSERT INTO read_states (user_id, room_id, last_read_message_id, last_read_at, updated_at) VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT(user_id, room_id) DO UPDATE SET \
+               last_read_message_id = excluded.last_read_message_id, \
+               last_read_at = excluded.last_read_at, \
+               updated_at = excluded.updated_at \
+             WHERE (excluded.last_read_at, excluded.last_read_message_id) > (read_states.last_read_at, read_states.last_read_message_id)"
+        )
+        .bind(&claims.sub)
+        .bind(&room_id)
+        .bind(&message_id)
+        .bind(&created_at)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+        if result.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+    } else if requested.is_some() {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Message not found in this room" }));
+    }
+
+    let cursor = cursors(pool.get_ref(), &claims.sub).await.remove(&room_id);
+    let counters = room_counters(pool.get_ref(), &claims.sub, &room_id, cursor.as_ref()).await;
+
+    let event = serde_json::json!({
+        "type": "read_state_updated",
+        "recipient_id": claims.sub,
+        "room_id": room_id,
+        "last_read_message_id": counters.last_read_message_id,
+        "unread_count": counters.unread_count,
+        "mention_count": counters.mention_count,
+    });
+    let _ = broadcaster.send(event.to_string());
+
+    HttpResponse::Ok().json(counters)
+}
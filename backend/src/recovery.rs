@@ -0,0 +1,568 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Account recovery (lost password)
+// ═══════════════════════════════════════════════════════
+//
+// Two ways back into a password account:
+//   - a recovery code, generated ahead of time in sudo mode and kept on
+//     paper. Each one resets the password once, immediately.
+//   - the verified email address. `POST /api/auth/recovery/start` mails a
+//     token that only works after a cooling-off delay, so the owner has
+//     time to react if the mailbox was compromised. Every signed-in session
+//     is told about the request (`recovery_updated` over the WebSocket) and
+//     can cancel it, or approve it to skip the delay (trusted device).
+//
+// Recovery resets the password, signs out every session and drops the
+// stored Discord token: whoever recovered the account must link Discord
+// again themselves instead of inheriting the previous owner's token. 2FA is
+// left as is, the next login still asks for a TOTP or backup code.
+//
+// Config (env):
+//   RECOVERY_DELAY_HOURS   cooling-off delay of email recovery (default 24)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine};
+use bcrypt::{hash, DEFAULT_COST};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::audit::AuthEvent;
+use crate::auth::extract_claims;
+use crate::discord_gateway::DiscordGateways;
+use crate::discord_link::LinkStatus;
+use crate::sessions::{DeviceInfo, SessionStore};
+use crate::sudo::Sudo;
+use crate::totp::{format_backup_code, generate_backup_code, hash_backup_code, normalize_code};
+use crate::ws::Broadcaster;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const DEFAULT_DELAY_HOURS: i64 = 24;
+/// How long a token stays usable once the delay is over.
+const USABLE_WINDOW_HOURS: i64 = 48;
+/// A new email request within this window of the last one is not sent.
+const REQUEST_COOLDOWN_MINUTES: i64 = 15;
+const MIN_PASSWORD_LEN: usize = 8;
+
+// ── Config ──────────────────────────────────────────────
+
+fn delay_hours() -> i64 {
+    std::env::var("RECOVERY_DELAY_HOURS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|h| *h >= 0)
+        .unwrap_or(DEFAULT_DELAY_HOURS)
+}
+
+// ── Requests ────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecoveryStatus {
+    Pending,
+    Approved,
+    Cancelled,
+    Completed,
+}
+
+impl RecoveryStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Approved => "approved",
+            Self::Cancelled => "cancelled",
+            Self::Completed => "completed",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryRequest {
+    pub id: String,
+    pub status: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+    pub usable_at: String,
+    pub expires_at: String,
+}
+
+fn request_from_row(row: &sqlx::sqlite::SqliteRow) -> RecoveryRequest {
+    RecoveryRequest {
+        id: row.get("id"),
+        status: row.get("status"),
+        ip: row.try_get("ip").unwrap_or(None),
+        user_agent: row.try_get("user_agent").unwrap_or(None),
+        created_at: row.get("created_at"),
+        usable_at: row.get("usable_at"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+const REQUEST_COLUMNS: &str = "id, user_id, status, ip, user_agent, created_at, usable_at, expires_at";
+
+/// Pending and approved requests of `user_id` that have not expired.
+async fn open_requests(pool: &SqlitePool, user_id: &str) -> Vec<RecoveryRequest> {
+    sqlx::query(&format!(
+        "SELECT {REQUEST_COLUMNS} FROM recovery_requests WHERE user_id = ? AND status IN ('pending', 'approved') AND expires_at > ? ORDER BY created_at DESC"
+    ))
+    .bind(user_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .map(|rows| rows.iter().map(request_from_row).collect())
+    .unwrap_or_default()
+}
+
+/// Tell the user's signed-in sessions that a request changed.
+fn notify_sessions(broadcaster: &Broadcaster, user_id: &str, request: &RecoveryRequest) {
+    let event = serde_json::json!({
+        "type": "recovery_updated",
+        "recipient_id": user_id,
+        "request": request,
+    });
+    let _ = broadcaster.send(event.to_string());
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+async fn verified_email(pool: &SqlitePool, user_id: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT email FROM users WHERE id = ? AND email_verified_at IS NOT NULL")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+/// Mail failures are logged only: the request itself is already stored.
+async fn mail(to: &str, subject: &str, body: String) {
+    if let Err(e) = crate::email::send_mail(to, subject, body).await {
+        eprintln!("Recovery email failed: {e}");
+    }
+}
+
+// ── Completing ──────────────────────────────────────────
+
+/// Set the new password and make the account safe to hand back: every
+/// session signed out, Discord token dropped. Returns whether a Discord
+/// link was dropped.
+async fn finish_recovery(
+    pool: &SqlitePool,
+    store: &SessionStore,
+    gateways: &DiscordGateways,
+    user_id: &str,
+    password: &str,
+) -> Result<bool, sqlx::Error> {
+    let password_hash = hash(password, DEFAULT_COST).expect("hash failed");
+    sqlx::query("UPDATE users SET password_hash = ?, password_set = 1 WHERE id = ?")
+        .bind(password_hash)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    // Whatever else was in flight is moot now.
+    sqlx::query("UPDATE recovery_requests SET status = ? WHERE user_id = ? AND status IN ('pending', 'approved')")
+        .bind(RecoveryStatus::Cancelled.as_str())
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    crate::sessions::revoke_user_sessions(pool, store, user_id, None).await;
+    let previous = crate::discord_link::forget_token(pool, gateways, user_id).await?;
+    Ok(previous != LinkStatus::Unlinked)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct StartPayload {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompletePayload {
+    /// Token from the recovery email...
+    pub token: Option<String>,
+    /// ...or a recovery code of this account.
+    pub username: Option<String>,
+    pub code: Option<String>,
+    pub new_password: String,
+}
+
+/// GET /api/users/@me/recovery — Recovery codes left and open email requests
+pub async fn get_status(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let codes_remaining: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ? AND used_at IS NULL"
+    )
+    .bind(&claims.sub)
+    .fetch_one(pool.get_ref())
+    .await
+    .unwrap_or(0);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "codes_remaining": codes_remaining,
+        "email_recovery": verified_email(pool.get_ref(), &claims.sub).await.is_some(),
+        "delay_hours": delay_hours(),
+        "requests": open_requests(pool.get_ref(), &claims.sub).await,
+    }))
+}
+
+/// POST /api/users/@me/recovery/codes — Replace the recovery codes (sudo)
+pub async fn regenerate_codes(req: HttpRequest, Sudo(claims): Sudo, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let now = chrono::Utc::now().to_rfc3339();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_backup_code()).collect();
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+            .bind(&claims.sub)
+            .execute(&mut *tx)
+            .await?;
+        for code in &codes {
+            sqlx::query("INSERT INTO recovery_codes (code_hash, user_id, created_at) VALUES (?, ?, ?)")
+                .bind(hash_backup_code(code))
+                .bind(&claims.sub)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    crate::audit::record(
+        pool.get_ref(),
+        &claims.sub,
+        AuthEvent::RecoveryCodesGenerated,
+        &DeviceInfo::from_request(&req),
+        serde_json::json!({ "count": RECOVERY_CODE_COUNT }),
+    )
+    .await;
+
+    let codes: Vec<String> = codes.iter().map(|c| format_backup_code(c)).collect();
+    HttpResponse::Ok().json(serde_json::json!({ "recovery_codes": codes }))
+}
+
+/// POST /api/auth/recovery/start — Mail a recovery token to a verified address
+///
+/// Always answers 202, so it cannot be used to find out which addresses
+/// have an account.
+pub async fn start(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<StartPayload>,
+) -> HttpResponse {
+    let accepted = HttpResponse::Accepted().json(serde_json::json!({ "sent": true, "delay_hours": delay_hours() }));
+
+    let email = body.email.trim().to_lowercase();
+    let user_id: Option<String> = sqlx::query_scalar(
+        "SELECT id FROM users WHERE email = ? AND email_verified_at IS NOT NULL"
+    )
+    .bind(&email)
+    .fetch_optional(pool.get_ref())
+    .await
+    .ok()
+    .flatten();
+    let Some(user_id) = user_id else {
+        return accepted;
+    };
+
+    let now = chrono::Utc::now();
+    let cooldown_start = (now - chrono::Duration::minutes(REQUEST_COOLDOWN_MINUTES)).to_rfc3339();
+    let recent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM recovery_requests WHERE user_id = ? AND created_at > ?")
+        .bind(&user_id)
+        .bind(&cooldown_start)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if recent > 0 {
+        return accepted;
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let token = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    let device = DeviceInfo::from_request(&req);
+    let usable_at = now + chrono::Duration::hours(delay_hours());
+    let request = RecoveryRequest {
+        id: Uuid::new_v4().to_string(),
+        status: RecoveryStatus::Pending.as_str().to_string(),
+        ip: device.ip.clone(),
+        user_agent: device.user_agent.clone(),
+        created_at: now.to_rfc3339(),
+        usable_at: usable_at.to_rfc3339(),
+        expires_at: (usable_at + chrono::Duration::hours(USABLE_WINDOW_HOURS)).to_rfc3339(),
+    };
+
+    let result = sqlx::query(
+        "INSERT INTO recovery_requests (id, user_id, token_hash, status, ip, user_agent, created_at, usable_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&request.id)
+    .bind(&user_id)
+    .bind(hash_token(&token))
+    .bind(&request.status)
+    .bind(&request.ip)
+    .bind(&request.user_agent)
+    .bind(&request.created_at)
+    .bind(&request.usable_at)
+    .bind(&request.expires_at)
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    notify_sessions(broadcaster.get_ref(), &user_id, &request);
+    crate::audit::record(
+        pool.get_ref(),
+        &user_id,
+        AuthEvent::RecoveryRequested,
+        &device,
+        serde_json::json!({ "request_id": request.id, "usable_at": request.usable_at }),
+    )
+    .await;
+
+    let body = format!(
+        "Bonjour,\n\nUne récupération de votre compte Voxium a été demandée depuis {ip}.\n\n\
+         Code de récupération : {token}\n\n\
+         Il pourra être utilisé à partir du {usable_at} (UTC) pour choisir un nouveau mot de passe. \
+         Toutes les sessions seront alors déconnectées et le compte Discord lié devra être relié à nouveau.\n\n\
+         Si vous n'êtes pas à l'origine de cette demande, annulez-la depuis un appareil connecté \
+         (Paramètres → Compte → Récupération).\n",
+        ip = request.ip.as_deref().unwrap_or("une adresse inconnue"),
+        usable_at = usable_at.format("%d/%m/%Y %H:%M"),
+    );
+    mail(&email, "Récupération de votre compte Voxium", body).await;
+
+    accepted
+}
+
+/// POST /api/auth/recovery/complete — Reset the password with an email token or a recovery code
+pub async fn complete(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+    gateways: web::Data<DiscordGateways>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<CompletePayload>,
+) -> HttpResponse {
+    if body.new_password.len() < MIN_PASSWORD_LEN {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Password must be at least 8 characters" }));
+    }
+    let now = chrono::Utc::now().to_rfc3339();
+
+    // Resolve the account and consume the proof.
+    let (user_id, via, request) = if let Some(token) = body.token.as_deref() {
+        let row = sqlx::query(&format!("SELECT {REQUEST_COLUMNS} FROM recovery_requests WHERE token_hash = ?"))
+            .bind(hash_token(token.trim()))
+            .fetch_optional(pool.get_ref())
+            .await
+            .ok()
+            .flatten();
+        let Some(row) = row else {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid recovery token" }));
+        };
+        let user_id: String = row.get("user_id");
+        let mut request = request_from_row(&row);
+        let open = request.status == RecoveryStatus::Pending.as_str() || request.status == RecoveryStatus::Approved.as_str();
+        if !open || request.expires_at < now {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This recovery request is no longer valid" }));
+        }
+        if request.status == RecoveryStatus::Pending.as_str() && request.usable_at > now {
+            return HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Recovery is still in its waiting period",
+                "usable_at": request.usable_at,
+            }));
+        }
+
+        let claimed = sqlx::query("UPDATE recovery_requests SET status = ? WHERE id = ? AND status = ?")
+            .bind(RecoveryStatus::Completed.as_str())
+            .bind(&request.id)
+            .bind(&request.status)
+            .execute(pool.get_ref())
+            .await
+            .map(|r| r.rows_affected() == 1)
+            .unwrap_or(false);
+        if !claimed {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This recovery request is no longer valid" }));
+        }
+        request.status = RecoveryStatus::Completed.as_str().to_string();
+        (user_id, "email", Some(request))
+    } else if let (Some(username), Some(code)) = (body.username.as_deref(), body.code.as_deref()) {
+        let invalid = || HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid username or recovery code" }));
+        let user_id: Option<String> = sqlx::query_scalar("SELECT id FROM users WHERE username = ?")
+            .bind(username.trim())
+            .fetch_optional(pool.get_ref())
+            .await
+            .ok()
+            .flatten();
+        let Some(user_id) = user_id else {
+            return invalid();
+        };
+        let used = sqlx::query("UPDATE recovery_codes SET used_at = ? WHERE code_hash = ? AND user_id = ? AND used_at IS NULL")
+            .bind(&now)
+            .bind(hash_backup_code(&normalize_code(code)))
+            .bind(&user_id)
+            .execute(pool.get_ref())
+            .await
+            .map(|r| r.rows_affected() == 1)
+            .unwrap_or(false);
+        if !used {
+            return invalid();
+        }
+        (user_id, "code", None)
+    } else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Provide a recovery token, or a username and recovery code" }));
+    };
+
+    let discord_dropped = match finish_recovery(pool.get_ref(), store.get_ref(), gateways.get_ref(), &user_id, &body.new_password).await {
+        Ok(dropped) => dropped,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Some(request) = &request {
+        notify_sessions(broadcaster.get_ref(), &user_id, request);
+    }
+    crate::audit::record(
+        pool.get_ref(),
+        &user_id,
+        AuthEvent::RecoveryCompleted,
+        &DeviceInfo::from_request(&req),
+        serde_json::json!({ "via": via, "discord_unlinked": discord_dropped }),
+    )
+    .await;
+    if let Some(email) = verified_email(pool.get_ref(), &user_id).await {
+        let body = "Bonjour,\n\nLe mot de passe de votre compte Voxium vient d'être réinitialisé par une procédure \
+                    de récupération. Toutes les sessions ont été déconnectées.\n\n\
+                    Si vous n'êtes pas à l'origine de cette opération, contactez un administrateur du serveur.\n"
+            .to_string();
+        mail(&email, "Votre compte Voxium a été récupéré", body).await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "recovered": true,
+        "discord_relink_required": discord_dropped,
+    }))
+}
+
+/// Load one open request of the caller, for approve/cancel.
+async fn caller_request(pool: &SqlitePool, user_id: &str, request_id: &str) -> Option<RecoveryRequest> {
+    sqlx::query(&format!(
+        "SELECT {REQUEST_COLUMNS} FROM recovery_requests WHERE id = ? AND user_id = ? AND status IN ('pending', 'approved')"
+    ))
+    .bind(request_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .map(|row| request_from_row(&row))
+}
+
+async fn set_request_status(pool: &SqlitePool, request: &mut RecoveryRequest, status: RecoveryStatus) -> bool {
+    let updated = sqlx::query("UPDATE recovery_requests SET status = ? WHERE id = ? AND status = ?")
+        .bind(status.as_str())
+        .bind(&request.id)
+        .bind(&request.status)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected() == 1)
+        .unwrap_or(false);
+    if updated {
+        request.status = status.as_str().to_string();
+    }
+    updated
+}
+
+/// POST /api/users/@me/recovery/{id}/approve — Skip the waiting period from a device signed in before the request
+pub async fn approve(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let Some(mut request) = caller_request(pool.get_ref(), &claims.sub, &path.into_inner()).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Recovery request not found" }));
+    };
+
+    // A session opened after the request could belong to whoever made it.
+    let session_created_at: Option<String> = sqlx::query_scalar("SELECT created_at FROM sessions WHERE id = ? AND user_id = ?")
+        .bind(&claims.sid)
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten();
+    if session_created_at.is_none_or(|at| at >= request.created_at) {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only a device signed in before the request can approve it" }));
+    }
+
+    if request.status != RecoveryStatus::Approved.as_str()
+        && !set_request_status(pool.get_ref(), &mut request, RecoveryStatus::Approved).await
+    {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Recovery request changed, reload it" }));
+    }
+
+    notify_sessions(broadcaster.get_ref(), &claims.sub, &request);
+    crate::audit::record(
+        pool.get_ref(),
+        &claims.sub,
+        AuthEvent::RecoveryApproved,
+        &DeviceInfo::from_request(&req),
+        serde_json::json!({ "request_id": request.id }),
+    )
+    .await;
+    HttpResponse::Ok().json(request)
+}
+
+/// DELETE /api/users/@me/recovery/{id} — Cancel an email recovery request
+pub async fn cancel(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let Some(mut request) = caller_request(pool.get_ref(), &claims.sub, &path.into_inner()).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Recovery request not found" }));
+    };
+    if !set_request_status(pool.get_ref(), &mut request, RecoveryStatus::Cancelled).await {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Recovery request changed, reload it" }));
+    }
+
+    notify_sessions(broadcaster.get_ref(), &claims.sub, &request);
+    crate::audit::record(
+        pool.get_ref(),
+        &claims.sub,
+        AuthEvent::RecoveryCancelled,
+        &DeviceInfo::from_request(&req),
+        serde_json::json!({ "request_id": request.id }),
+    )
+    .await;
+    HttpResponse::Ok().json(request)
+}
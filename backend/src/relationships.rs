@@ -0,0 +1,387 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Friends, friend requests and blocks
+// ═══════════════════════════════════════════════════════
+//
+// `relationships` holds one row per direction: `user_id`'s view of
+// `target_id`. Friends have a `friend` row each way; a pending request is
+// `pending_outgoing` on the sender's side and `pending_incoming` on the
+// other; a block is a `blocked` row on the blocker's side only, and ends
+// any friendship or request between the two.
+//
+// Blocks hide the blocked user's messages and typing from the blocker
+// (see `ws::is_blocked_for`), keep them from pinging the blocker (see
+// `mentions`) and from sending them friend requests.
+//
+// Both sides are told of every change on their realtime connections:
+// `{ type: "relationship_updated", recipient_id, relationship }` with the
+// recipient's new view, or `{ type: "relationship_removed", recipient_id,
+// user_id }` when they no longer have one.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+
+use crate::auth::extract_claims;
+use crate::ws::{cache_set_user_blocks, AccessCache, Broadcaster};
+
+pub const FRIEND: &str = "friend";
+pub const PENDING_INCOMING: &str = "pending_incoming";
+pub const PENDING_OUTGOING: &str = "pending_outgoing";
+pub const BLOCKED: &str = "blocked";
+
+/// Users `user_id` has blocked.
+pub(crate) async fn fetch_blocked_ids(pool: &SqlitePool, user_id: &str) -> HashSet<String> {
+    sqlx::query_scalar::<_, String>(
+        "SELECT target_id FROM relationships WHERE user_id = ? AND kind = 'blocked'"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .collect()
+}
+
+async fn refresh_block_cache(pool: &SqlitePool, cache: &AccessCache, user_id: &str) {
+    let blocked = fetch_blocked_ids(pool, user_id).await;
+    cache_set_user_blocks(cache, user_id, blocked);
+}
+
+/// `user_id`'s view of `target_id`, if any.
+async fn kind_of(pool: &SqlitePool, user_id: &str, target_id: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT kind FROM relationships WHERE user_id = ? AND target_id = ?")
+        .bind(user_id)
+        .bind(target_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Relationship {
+    pub user_id: String,
+    pub username: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub created_at: String,
+}
+
+async fn fetch_relationship(pool: &SqlitePool, user_id: &str, target_id: &str) -> Option<Relationship> {
+    sqlx::query(
+        "SELECT r.target_id, u.username, r.kind, r.created_at FROM relationships r \
+         JOIN users u ON u.id = r.target_id WHERE r.user_id = ? AND r.target_id = ?"
+    )
+    .bind(user_id)
+    .bind(target_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .map(|row| Relationship {
+        user_id: row.get("target_id"),
+        username: row.get("username"),
+        kind: row.get("kind"),
+        created_at: row.get("created_at"),
+    })
+}
+
+/// Tell `user_id` their current view of `target_id`.
+async fn notify(pool: &SqlitePool, broadcaster: &Broadcaster, user_id: &str, target_id: &str) {
+    let event = match fetch_relationship(pool, user_id, target_id).await {
+        Some(relationship) => serde_json::json!({
+            "type": "relationship_updated",
+            "recipient_id": user_id,
+            "relationship": relationship,
+        }),
+        None => serde_json::json!({
+            "type": "relationship_removed",
+            "recipient_id": user_id,
+            "user_id": target_id,
+        }),
+    };
+    let _ = broadcaster.send(event.to_string());
+}
+
+/// Set `user_id`'s view of `target_id` to `kind`, or remove it.
+async fn set_kind<'c, E>(executor: E, user_id: &str, target_id: &str, kind: Option<&str>) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Sqlite>,
+{
+    match kind {
+        Some(kind) => sqlx::query(
+            "INSERT INTO relationships (user_id, target_id, kind) VALUES (?, ?, ?) \
+             ON CONFLICT(user_id, target_id) DO UPDATE SET kind = excluded.kind, created_at = datetime('now')"
+        )
+        .bind(user_id)
+        .bind(target_id)
+        .bind(kind)
+        .execute(executor)
+        .await
+        .map(|_| ()),
+        None => sqlx::query("DELETE FROM relationships WHERE user_id = ? AND target_id = ?")
+            .bind(user_id)
+            .bind(target_id)
+            .execute(executor)
+            .await
+            .map(|_| ()),
+    }
+}
+
+/// Apply both views of a pair at once.
+async fn set_pair(
+    pool: &SqlitePool,
+    user_id: &str,
+    mine: Option<&str>,
+    target_id: &str,
+    theirs: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    set_kind(&mut *tx, user_id, target_id, mine).await?;
+    set_kind(&mut *tx, target_id, user_id, theirs).await?;
+    tx.commit().await
+}
+
+async fn user_exists(pool: &SqlitePool, user_id: &str) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0)
+        > 0
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/users/@me/relationships — Friends, pending requests and blocks
+pub async fn list_relationships(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let relationships: Vec<Relationship> = sqlx::query(
+        "SELECT r.target_id, u.username, r.kind, r.created_at FROM relationships r \
+         JOIN users u ON u.id = r.target_id \
+         WHERE r.user_id = ? ORDER BY r.kind, lower(u.username)"
+    )
+    .bind(&claims.sub)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default()
+    .iter()
+    .map(|row| Relationship {
+        user_id: row.get("target_id"),
+        username: row.get("username"),
+        kind: row.get("kind"),
+        created_at: row.get("created_at"),
+    })
+    .collect();
+
+    HttpResponse::Ok().json(relationships)
+}
+
+/// Send a friend request from `user_id` to `target_id`, or accept theirs.
+async fn request_friend(pool: &SqlitePool, broadcaster: &Broadcaster, user_id: &str, target_id: &str) -> HttpResponse {
+    if target_id == user_id {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "You cannot add yourself" }));
+    }
+    if !user_exists(pool, target_id).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    }
+
+    let mine = kind_of(pool, user_id, target_id).await;
+    let theirs = kind_of(pool, target_id, user_id).await;
+    if theirs.as_deref() == Some(BLOCKED) {
+        // Same answer as a refusal: the block itself stays private.
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "This user is not accepting friend requests" }));
+    }
+    let (next_mine, next_theirs) = match mine.as_deref() {
+        Some(BLOCKED) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unblock this user first" }));
+        }
+        Some(FRIEND) | Some(PENDING_OUTGOING) => {
+            let relationship = fetch_relationship(pool, user_id, target_id).await;
+            return HttpResponse::Ok().json(relationship);
+        }
+        Some(PENDING_INCOMING) => (FRIEND, FRIEND),
+        _ => (PENDING_OUTGOING, PENDING_INCOMING),
+    };
+
+    if set_pair(pool, user_id, Some(next_mine), target_id, Some(next_theirs)).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    notify(pool, broadcaster, user_id, target_id).await;
+    notify(pool, broadcaster, target_id, user_id).await;
+    if next_theirs == PENDING_INCOMING {
+        crate::notifications::friend_request(pool, target_id, user_id).await;
+    }
+    HttpResponse::Ok().json(fetch_relationship(pool, user_id, target_id).await)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FriendRequestInput {
+    pub username: String,
+}
+
+/// POST /api/users/@me/friends — Send a friend request by username (accepts theirs if pending)
+pub async fn send_friend_request(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<FriendRequestInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let target_id: Option<String> = sqlx::query_scalar("SELECT id FROM users WHERE lower(username) = lower(?)")
+        .bind(body.username.trim())
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten();
+    let Some(target_id) = target_id else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    };
+    request_friend(pool.get_ref(), broadcaster.get_ref(), &claims.sub, &target_id).await
+}
+
+/// PUT /api/users/@me/friends/{id} — Send a friend request, or accept a pending one
+pub async fn put_friend(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    request_friend(pool.get_ref(), broadcaster.get_ref(), &claims.sub, &path.into_inner()).await
+}
+
+/// DELETE /api/users/@me/friends/{id} — Remove a friend, decline or cancel a request
+pub async fn remove_friend(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let target_id = path.into_inner();
+    match kind_of(pool.get_ref(), &claims.sub, &target_id).await.as_deref() {
+        Some(FRIEND) | Some(PENDING_INCOMING) | Some(PENDING_OUTGOING) => {}
+        _ => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No friendship or request with this user" })),
+    }
+
+    if set_pair(pool.get_ref(), &claims.sub, None, &target_id, None).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    notify(pool.get_ref(), broadcaster.get_ref(), &claims.sub, &target_id).await;
+    notify(pool.get_ref(), broadcaster.get_ref(), &target_id, &claims.sub).await;
+    HttpResponse::NoContent().finish()
+}
+
+/// GET /api/users/@me/blocks — List blocked users
+pub async fn list_blocks(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let rows = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT r.target_id, u.username, r.created_at FROM relationships r \
+         JOIN users u ON u.id = r.target_id \
+         WHERE r.user_id = ? AND r.kind = 'blocked' ORDER BY r.created_at DESC"
+    )
+    .bind(&claims.sub)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+
+    let blocks: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|(user_id, username, created_at)| {
+            serde_json::json!({ "user_id": user_id, "username": username, "created_at": created_at })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(blocks)
+}
+
+/// PUT /api/users/@me/blocks/{id} — Block a user (ends any friendship or request)
+pub async fn block_user(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    access_cache: web::Data<AccessCache>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let target_id = path.into_inner();
+    if target_id == claims.sub {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "You cannot block yourself" }));
+    }
+    if !user_exists(pool.get_ref(), &target_id).await {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    }
+
+    // Their own block of us stays; anything else on their side goes.
+    let theirs = kind_of(pool.get_ref(), &target_id, &claims.sub).await;
+    let next_theirs = theirs.as_deref().filter(|kind| *kind == BLOCKED);
+    let result = set_pair(pool.get_ref(), &claims.sub, Some(BLOCKED), &target_id, next_theirs).await;
+
+    match result {
+        Ok(()) => {
+            refresh_block_cache(pool.get_ref(), access_cache.get_ref(), &claims.sub).await;
+            notify(pool.get_ref(), broadcaster.get_ref(), &claims.sub, &target_id).await;
+            if theirs.is_some() && next_theirs.is_none() {
+                notify(pool.get_ref(), broadcaster.get_ref(), &target_id, &claims.sub).await;
+            }
+            HttpResponse::Ok().json(serde_json::json!({ "status": "blocked" }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/users/@me/blocks/{id} — Unblock a user
+pub async fn unblock_user(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    access_cache: web::Data<AccessCache>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let target_id = path.into_inner();
+    let result = sqlx::query("DELETE FROM relationships WHERE user_id = ? AND target_id = ? AND kind = 'blocked'")
+        .bind(&claims.sub)
+        .bind(&target_id)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected() > 0 => {
+            refresh_block_cache(pool.get_ref(), access_cache.get_ref(), &claims.sub).await;
+            notify(pool.get_ref(), broadcaster.get_ref(), &claims.sub, &target_id).await;
+            HttpResponse::Ok().json(serde_json::json!({ "status": "unblocked" }))
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "User is not blocked" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -1,4 +1,4 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use base64::{engine::general_purpose, Engine};
 use futures_util::{SinkExt, StreamExt};
 use rsa::{pkcs8::EncodePublicKey, rand_core::OsRng, Oaep, RsaPrivateKey, RsaPublicKey};
@@ -7,13 +7,23 @@ use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, Mutex};
 use tokio_tungstenite::tungstenite::Message;
 
+use crate::jobs::{Backlog, JobRegistry};
+use crate::sessions::DeviceInfo;
+
 const DISCORD_REMOTE_AUTH_GATEWAY: &str = "wss://remote-auth-gateway.discord.gg/?v=2";
 const DISCORD_REMOTE_AUTH_LOGIN_API: &str =
     "https://discord.com/api/v9/users/@me/remote-auth/login";
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
+pub(crate) const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
+
+/// Finished sessions stay readable this long before being dropped from memory.
+const FINISHED_SESSION_RETENTION: Duration = Duration::from_secs(10 * 60);
+/// Descriptors are kept this long, far beyond any QR session's lifetime.
+const DESCRIPTOR_RETENTION_HOURS: i64 = 24;
+const QR_SESSION_GC_INTERVAL: Duration = Duration::from_secs(10 * 60);
 
 // ── Session types ───────────────────────────────────────
 
@@ -36,11 +46,30 @@ pub enum QrStatus {
     Error { message: String },
     #[serde(rename = "cancelled")]
     Cancelled,
+    /// The server restarted while the session was running, it is gone.
+    #[serde(rename = "expired_restart")]
+    ExpiredRestart,
+}
+
+impl QrStatus {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            QrStatus::Completed { .. } | QrStatus::Error { .. } | QrStatus::Cancelled | QrStatus::ExpiredRestart
+        )
+    }
 }
 
 pub struct QrSession {
     status: QrStatus,
     cancel_tx: Option<mpsc::Sender<()>>,
+    /// Returned once by `start_qr_session`; must be presented on every later call.
+    secret: String,
+    /// Requester context captured at start, checked when `QR_SESSION_BIND_CLIENT` is enabled.
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    /// Last status change.
+    updated_at: Instant,
 }
 
 pub type QrAuthSessions = Arc<Mutex<HashMap<String, QrSession>>>;
@@ -61,85 +90,280 @@ pub struct CancelPayload {
     pub session_id: String,
 }
 
+const QR_SECRET_HEADER: &str = "X-QR-Session-Secret";
+
 // ── Handlers ────────────────────────────────────────────
 
 pub async fn start_qr_session(
+    req: HttpRequest,
     pool: web::Data<SqlitePool>,
     sessions: web::Data<QrAuthSessions>,
 ) -> HttpResponse {
+    if !crate::discord_oauth::link_mode().allows_user_token() {
+        return crate::discord_oauth::user_token_disabled();
+    }
+
     let session_id = uuid::Uuid::new_v4().to_string();
+    let session_secret = generate_session_secret();
+    let (client_ip, user_agent) = client_context(&req);
     let (cancel_tx, cancel_rx) = mpsc::channel(1);
 
     // Clean finished sessions
     {
         let mut map = sessions.lock().await;
-        map.retain(|_, s| {
-            matches!(
-                s.status,
-                QrStatus::Connecting
-                    | QrStatus::WaitingForQr
-                    | QrStatus::QrReady { .. }
-                    | QrStatus::Scanned
-                    | QrStatus::Completing
-            )
-        });
+        map.retain(|_, s| !s.status.is_finished());
         map.insert(
             session_id.clone(),
             QrSession {
                 status: QrStatus::Connecting,
                 cancel_tx: Some(cancel_tx),
+                secret: session_secret.clone(),
+                client_ip,
+                user_agent,
+                updated_at: Instant::now(),
             },
         );
     }
+    persist_descriptor(pool.get_ref(), &session_id, &session_secret).await;
 
     let sessions_clone = sessions.get_ref().clone();
     let pool_clone = pool.get_ref().clone();
     let sid = session_id.clone();
+    let device = DeviceInfo::from_request(&req);
     tokio::spawn(async move {
-        run_remote_auth_flow(sid, sessions_clone, pool_clone, cancel_rx).await;
+        run_remote_auth_flow(sid, sessions_clone, pool_clone, cancel_rx, device).await;
     });
 
-    HttpResponse::Ok().json(serde_json::json!({ "session_id": session_id }))
+    HttpResponse::Ok().json(serde_json::json!({
+        "session_id": session_id,
+        "session_secret": session_secret,
+    }))
 }
 
 pub async fn get_qr_status(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
     sessions: web::Data<QrAuthSessions>,
     query: web::Query<SessionQuery>,
 ) -> HttpResponse {
-    let map = sessions.lock().await;
-    if let Some(session) = map.get(&query.session_id) {
-        HttpResponse::Ok().json(&session.status)
-    } else {
-        HttpResponse::NotFound().json(serde_json::json!({ "error": "Session introuvable" }))
+    {
+        let map = sessions.lock().await;
+        if let Some(session) = map.get(&query.session_id) {
+            if session_matches_requester(session, &req) {
+                return HttpResponse::Ok().json(&session.status);
+            }
+            return HttpResponse::NotFound().json(serde_json::json!({ "error": "Session introuvable" }));
+        }
+    }
+
+    if lost_in_restart(pool.get_ref(), &query.session_id, &req).await {
+        return HttpResponse::Ok().json(QrStatus::ExpiredRestart);
     }
+    HttpResponse::NotFound().json(serde_json::json!({ "error": "Session introuvable" }))
 }
 
 pub async fn cancel_qr_session(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
     sessions: web::Data<QrAuthSessions>,
     body: web::Json<CancelPayload>,
 ) -> HttpResponse {
-    let mut map = sessions.lock().await;
-    if let Some(session) = map.get_mut(&body.session_id) {
-        if let Some(tx) = session.cancel_tx.take() {
-            let _ = tx.try_send(());
+    {
+        let mut map = sessions.lock().await;
+        if let Some(session) = map.get_mut(&body.session_id) {
+            if !session_matches_requester(session, &req) {
+                return HttpResponse::NotFound().json(serde_json::json!({ "error": "Session introuvable" }));
+            }
+            if let Some(tx) = session.cancel_tx.take() {
+                let _ = tx.try_send(());
+            }
+            session.status = QrStatus::Cancelled;
+            session.updated_at = Instant::now();
+            return HttpResponse::Ok().json(serde_json::json!({ "ok": true }));
         }
-        session.status = QrStatus::Cancelled;
-        HttpResponse::Ok().json(serde_json::json!({ "ok": true }))
-    } else {
-        HttpResponse::NotFound().json(serde_json::json!({ "error": "Session introuvable" }))
+    }
+
+    // Nothing left to cancel: the restart already ended it
+    if lost_in_restart(pool.get_ref(), &body.session_id, &req).await {
+        return HttpResponse::Ok().json(serde_json::json!({ "ok": true, "status": "expired_restart" }));
+    }
+    HttpResponse::NotFound().json(serde_json::json!({ "error": "Session introuvable" }))
+}
+
+// ── Restart recovery ────────────────────────────────────
+//
+// Sessions live in memory, so a restart loses them while clients keep
+// polling. Each session also gets a descriptor row (id and secret hash
+// only); at startup the previous process's descriptors are marked lost, and
+// polling one of them answers `expired_restart` instead of a bare 404. The
+// `qr_sessions` job queue drops old descriptors and finished sessions.
+
+fn secret_hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+async fn persist_descriptor(pool: &SqlitePool, session_id: &str, secret: &str) {
+    let result = sqlx::query("INSERT INTO qr_session_descriptors (id, secret_hash, created_at) VALUES (?, ?, ?)")
+        .bind(session_id)
+        .bind(secret_hash(secret))
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await;
+    if let Err(e) = result {
+        eprintln!("⚠️  Failed to persist QR session descriptor: {e}");
     }
 }
 
+/// Called at startup: every descriptor still open belonged to the previous process.
+pub async fn recover_interrupted_sessions(pool: &SqlitePool) {
+    let lost = sqlx::query("UPDATE qr_session_descriptors SET lost_at = ? WHERE lost_at IS NULL")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+    if lost > 0 {
+        println!("🔁 {lost} Discord QR session(s) interrupted by the restart");
+    }
+}
+
+/// Whether `session_id` was lost to a restart, for the caller holding its secret.
+async fn lost_in_restart(pool: &SqlitePool, session_id: &str, req: &HttpRequest) -> bool {
+    let stored: Option<String> = sqlx::query_scalar(
+        "SELECT secret_hash FROM qr_session_descriptors WHERE id = ? AND lost_at IS NOT NULL"
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+    let Some(stored) = stored else {
+        return false;
+    };
+    let presented = req
+        .headers()
+        .get(QR_SECRET_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    secrets_equal(&secret_hash(presented), &stored)
+}
+
+/// Drop old descriptors and long finished sessions (which may hold auth tokens).
+async fn collect_garbage(pool: &SqlitePool, sessions: &QrAuthSessions) -> Result<u64, String> {
+    let pruned = {
+        let mut map = sessions.lock().await;
+        let before = map.len();
+        map.retain(|_, s| !s.status.is_finished() || s.updated_at.elapsed() < FINISHED_SESSION_RETENTION);
+        (before - map.len()) as u64
+    };
+    let cutoff = (chrono::Utc::now() - chrono::Duration::hours(DESCRIPTOR_RETENTION_HOURS)).to_rfc3339();
+    let deleted = sqlx::query("DELETE FROM qr_session_descriptors WHERE created_at < ?")
+        .bind(&cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+    Ok(pruned + deleted)
+}
+
+/// Descriptors past retention.
+async fn descriptor_backlog(pool: &SqlitePool) -> Backlog {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::hours(DESCRIPTOR_RETENTION_HOURS)).to_rfc3339();
+    let mut backlog = sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM qr_session_descriptors WHERE created_at < ?"
+    )
+    .bind(&cutoff)
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default();
+    backlog.oldest_due_at = backlog
+        .oldest_due_at
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| (at + chrono::Duration::hours(DESCRIPTOR_RETENTION_HOURS)).to_rfc3339());
+    backlog
+}
+
+pub fn spawn_session_gc(pool: SqlitePool, sessions: QrAuthSessions, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::QR_SESSIONS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(QR_SESSION_GC_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::QR_SESSIONS, None, collect_garbage(&pool, &sessions), descriptor_backlog(&pool)).await;
+        }
+    });
+}
+
 // ── Internal helpers ────────────────────────────────────
 
+fn generate_session_secret() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn client_context(req: &HttpRequest) -> (Option<String>, Option<String>) {
+    let ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .map(|s| s.to_string());
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    (ip, user_agent)
+}
+
+fn bind_to_client_enabled() -> bool {
+    matches!(
+        std::env::var("QR_SESSION_BIND_CLIENT").as_deref(),
+        Ok("1") | Ok("true") | Ok("yes")
+    )
+}
+
+/// Constant-time comparison so the secret can't be recovered by timing the endpoint.
+fn secrets_equal(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A session is only visible to the caller holding its secret (and, when enabled,
+/// coming from the same IP / user agent that started it). Unknown and mismatched
+/// sessions are reported identically so session ids can't be probed.
+fn session_matches_requester(session: &QrSession, req: &HttpRequest) -> bool {
+    let presented = req
+        .headers()
+        .get(QR_SECRET_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !secrets_equal(presented, &session.secret) {
+        return false;
+    }
+
+    if bind_to_client_enabled() {
+        let (ip, user_agent) = client_context(req);
+        if ip != session.client_ip || user_agent != session.user_agent {
+            return false;
+        }
+    }
+
+    true
+}
+
 async fn set_status(sessions: &QrAuthSessions, session_id: &str, status: QrStatus) {
     let mut map = sessions.lock().await;
     if let Some(session) = map.get_mut(session_id) {
         session.status = status;
+        session.updated_at = Instant::now();
     }
 }
 
-fn generate_qr_data_uri(data: &str) -> Result<String, String> {
+pub(crate) fn generate_qr_data_uri(data: &str) -> Result<String, String> {
     use image::ImageEncoder;
     use qrcode::QrCode;
 
@@ -160,6 +384,7 @@ async fn run_remote_auth_flow(
     sessions: QrAuthSessions,
     pool: SqlitePool,
     mut cancel_rx: mpsc::Receiver<()>,
+    device: DeviceInfo,
 ) {
     // Generate RSA-OAEP 2048 key pair
     let private_key = match RsaPrivateKey::new(&mut OsRng, 2048) {
@@ -444,6 +669,7 @@ async fn run_remote_auth_flow(
                                     &ticket,
                                     &private_key,
                                     &pool,
+                                    &device,
                                 )
                                 .await
                                 {
@@ -481,6 +707,7 @@ async fn run_remote_auth_flow(
                                         enc_token,
                                         &private_key,
                                         &pool,
+                                        &device,
                                     )
                                     .await
                                     {
@@ -571,6 +798,7 @@ async fn decrypt_and_login(
     encrypted_token_b64: &str,
     private_key: &RsaPrivateKey,
     pool: &SqlitePool,
+    device: &DeviceInfo,
 ) -> Result<serde_json::Value, String> {
     let encrypted = general_purpose::STANDARD
         .decode(encrypted_token_b64)
@@ -591,7 +819,7 @@ async fn decrypt_and_login(
         return Err("Empty token after decryption".into());
     }
 
-    let auth = crate::auth::do_discord_token_login(pool, &discord_token)
+    let auth = crate::auth::do_discord_token_login(pool, &discord_token, device, crate::audit::LinkMethod::Qr)
         .await
         .map_err(|e| format!("Login failed: {e}"))?;
 
@@ -602,6 +830,7 @@ async fn finalize_with_ticket(
     ticket: &str,
     private_key: &RsaPrivateKey,
     pool: &SqlitePool,
+    device: &DeviceInfo,
 ) -> Result<serde_json::Value, String> {
     let client = reqwest::Client::new();
     let resp = client
@@ -625,13 +854,13 @@ async fn finalize_with_ticket(
         .map_err(|e| format!("Bad Discord response: {e}"))?;
 
     if let Some(enc) = body.get("encrypted_token").and_then(|v| v.as_str()) {
-        return decrypt_and_login(enc, private_key, pool).await;
+        return decrypt_and_login(enc, private_key, pool, device).await;
     }
 
     if let Some(tok) = body.get("token").and_then(|v| v.as_str()) {
         let t = tok.trim();
         if !t.is_empty() {
-            let auth = crate::auth::do_discord_token_login(pool, t)
+            let auth = crate::auth::do_discord_token_login(pool, t, device, crate::audit::LinkMethod::Qr)
                 .await
                 .map_err(|e| format!("Login failed: {e}"))?;
             return Ok(serde_json::to_value(auth).unwrap_or_default());
@@ -0,0 +1,411 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Message retention and nightly pruning
+// ═══════════════════════════════════════════════════════
+//
+// A server-wide `max_age_days` (MANAGE_SERVER) and per-room overrides
+// (MANAGE_ROOMS) say how long messages are kept; 0 keeps them forever, and
+// a room without an override follows the server. Pinned messages are always
+// kept, and so is a forum post while it has had activity within the room's
+// retention.
+//
+// Once a night, from RETENTION_PRUNE_HOUR (UTC), the `message_retention`
+// job (see `jobs`) hard-deletes the messages past retention, room by room,
+// oldest first. It deletes `PRUNE_BATCH` messages per transaction and
+// pauses `BATCH_PAUSE` between them, so the SQLite writer is never held
+// long and WAL checkpoints keep up; the pages freed are reused by later
+// writes. Each room pruned gets one `messages_pruned` audit entry (no actor)
+// with the count and cutoff, and one `messages_pruned` event: `{ type,
+// room_id, before }`, so clients drop what they hold of it. Reactions,
+// revisions and attachments go with the messages (ON DELETE CASCADE), and
+// their files are then collected with the other unused uploads; an image
+// under `/uploads` nothing else uses is deleted right away (see `uploads`).
+//
+// Config (env):
+//   RETENTION_PRUNE_HOUR   hour of the day (UTC) pruning runs from (default 3)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::BTreeSet;
+
+use crate::auth::{extract_claims, Claims};
+use crate::files::{FileStorage, FileStore};
+use crate::jobs::{Backlog, JobRegistry};
+use crate::ws::Broadcaster;
+
+const DEFAULT_PRUNE_HOUR: u32 = 3;
+/// Messages deleted per transaction.
+const PRUNE_BATCH: i64 = 500;
+const BATCH_PAUSE: std::time::Duration = std::time::Duration::from_millis(50);
+const WORKER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+/// About a hundred years.
+const MAX_AGE_DAYS: i64 = 36_500;
+
+fn prune_hour() -> u32 {
+    std::env::var("RETENTION_PRUNE_HOUR")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .filter(|h| *h < 24)
+        .unwrap_or(DEFAULT_PRUNE_HOUR)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Settings {
+    pub max_age_days: i64,
+    pub updated_by_username: Option<String>,
+    pub updated_at: Option<String>,
+    pub last_pruned_at: Option<String>,
+}
+
+/// The server settings, or keep-forever when they cannot be read.
+async fn load(pool: &SqlitePool) -> Settings {
+    sqlx::query("SELECT max_age_days, updated_by_username, updated_at, last_pruned_at FROM retention_settings WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Settings {
+            max_age_days: row.get("max_age_days"),
+            updated_by_username: row.get("updated_by_username"),
+            updated_at: row.get("updated_at"),
+            last_pruned_at: row.get("last_pruned_at"),
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomRetention {
+    pub room_id: String,
+    pub max_age_days: i64,
+    pub updated_by_username: String,
+    pub updated_at: String,
+}
+
+#[allow(clippy::result_large_err)]
+fn valid_days(days: i64) -> Result<(), HttpResponse> {
+    if !(0..=MAX_AGE_DAYS).contains(&days) {
+        return Err(HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": format!("max_age_days must be between 0 (keep forever) and {MAX_AGE_DAYS}") })));
+    }
+    Ok(())
+}
+
+// ── Pruning ─────────────────────────────────────────────
+
+/// Rooms whose messages expire, with their retention in days.
+async fn expiring_rooms(pool: &SqlitePool) -> Result<Vec<(String, i64)>, String> {
+    sqlx::query_as(
+        "SELECT r.id, COALESCE(rr.max_age_days, s.max_age_days) AS days FROM rooms r \
+         JOIN retention_settings s ON s.id = 1 LEFT JOIN room_retention rr ON rr.room_id = r.id \
+         WHERE COALESCE(rr.max_age_days, s.max_age_days) > 0 ORDER BY r.id"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+fn cutoff(now: DateTime<Utc>, days: i64) -> String {
+    (now - Duration::days(days)).to_rfc3339()
+}
+
+const EXPIRED: &str = "FROM messages WHERE room_id = ? AND created_at < ? AND pinned_at IS NULL \
+     AND NOT EXISTS (SELECT 1 FROM forum_posts p WHERE p.id = messages.id AND p.last_activity_at >= ?)";
+
+/// Delete the messages of `room_id` older than `before`, a batch at a time.
+async fn prune_room(pool: &SqlitePool, storage: &FileStore, room_id: &str, before: &str) -> Result<u64, String> {
+    let mut pruned = 0;
+    loop {
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        let batch: Vec<(String, Option<String>, Option<String>)> =
+            sqlx::query_as(&format!("SELECT id, post_id, image_url {EXPIRED} ORDER BY created_at, id LIMIT ?"))
+                .bind(room_id)
+                .bind(before)
+                .bind(before)
+                .bind(PRUNE_BATCH)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        if batch.is_empty() {
+            break;
+        }
+        let placeholders = vec!["?"; batch.len()].join(", ");
+        let sql = format!("DELETE FROM messages WHERE id IN ({placeholders})");
+        let mut query = sqlx::query(&sql);
+        for (id, _, _) in &batch {
+            query = query.bind(id);
+        }
+        query.execute(&mut *tx).await.map_err(|e| e.to_string())?;
+        tx.commit().await.map_err(|e| e.to_string())?;
+        pruned += batch.len() as u64;
+
+        let posts: BTreeSet<&str> = batch.iter().filter_map(|(_, post_id, _)| post_id.as_deref()).collect();
+        for post_id in posts {
+            crate::forum::refresh_replies(pool, Some(post_id)).await;
+        }
+        for (_, _, url) in &batch {
+            crate::uploads::remove(pool, storage, url.as_deref()).await;
+        }
+        if (batch.len() as i64) < PRUNE_BATCH {
+            break;
+        }
+        tokio::time::sleep(BATCH_PAUSE).await;
+    }
+    Ok(pruned)
+}
+
+/// Delete every message past its room's retention now. Returns how many
+/// were deleted.
+pub async fn prune_messages(pool: &SqlitePool, storage: &FileStore, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let now = Utc::now();
+    let mut pruned = 0;
+    for (room_id, days) in expiring_rooms(pool).await? {
+        let before = cutoff(now, days);
+        let count = prune_room(pool, storage, &room_id, &before).await?;
+        if count == 0 {
+            continue;
+        }
+        pruned += count;
+        crate::audit_log::record(
+            pool,
+            None,
+            crate::audit_log::Action::MessagesPruned,
+            &room_id,
+            serde_json::json!({ "count": count, "before": before, "max_age_days": days }),
+            None,
+        )
+        .await;
+        let event = serde_json::json!({ "type": "messages_pruned", "room_id": room_id, "before": before });
+        let _ = broadcaster.send(event.to_string());
+    }
+    let _ = sqlx::query("PRAGMA wal_checkpoint(PASSIVE)").execute(pool).await;
+    sqlx::query("UPDATE retention_settings SET last_pruned_at = ? WHERE id = 1")
+        .bind(now.to_rfc3339())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(pruned)
+}
+
+/// Messages past retention, and when the oldest of them expired.
+async fn retention_backlog(pool: &SqlitePool) -> Backlog {
+    let now = Utc::now();
+    let mut backlog = Backlog::default();
+    for (room_id, days) in expiring_rooms(pool).await.unwrap_or_default() {
+        let before = cutoff(now, days);
+        let row = sqlx::query(&format!("SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at {EXPIRED}"))
+            .bind(&room_id)
+            .bind(&before)
+            .bind(&before)
+            .fetch_one(pool)
+            .await;
+        let Ok(row) = row else {
+            continue;
+        };
+        let room = Backlog::from_row(&row);
+        backlog.depth += room.depth;
+        // Due once retention is over, not when posted.
+        let due = room
+            .oldest_due_at
+            .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+            .map(|at| (at + Duration::days(days)).to_rfc3339());
+        backlog.oldest_due_at = match (backlog.oldest_due_at, due) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    backlog
+}
+
+/// Whether tonight's pruning is still to run.
+fn due(now: DateTime<Utc>, last_pruned_at: Option<&str>) -> bool {
+    let last = last_pruned_at.and_then(|at| DateTime::parse_from_rfc3339(at).ok());
+    now.hour() >= prune_hour() && last.is_none_or(|at| at.date_naive() < now.date_naive())
+}
+
+pub fn spawn_retention_pruner(pool: SqlitePool, storage: FileStorage, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::MESSAGE_RETENTION);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(WORKER_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !due(Utc::now(), load(&pool).await.last_pruned_at.as_deref()) {
+                continue;
+            }
+            crate::jobs::run(
+                &jobs,
+                crate::jobs::MESSAGE_RETENTION,
+                None,
+                prune_messages(&pool, &storage, &broadcaster),
+                retention_backlog(&pool),
+            )
+            .await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+async fn require_server(req: &HttpRequest, pool: &SqlitePool, permission: u64) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require_server(pool, &claims, permission).await?;
+    Ok(claims)
+}
+
+async fn room_overrides(pool: &SqlitePool) -> Vec<RoomRetention> {
+    sqlx::query("SELECT room_id, max_age_days, updated_by_username, updated_at FROM room_retention ORDER BY room_id")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|row| RoomRetention {
+            room_id: row.get("room_id"),
+            max_age_days: row.get("max_age_days"),
+            updated_by_username: row.get("updated_by_username"),
+            updated_at: row.get("updated_at"),
+        })
+        .collect()
+}
+
+async fn settings_json(pool: &SqlitePool) -> serde_json::Value {
+    let mut value = serde_json::to_value(load(pool).await).unwrap_or_default();
+    value["rooms"] = serde_json::to_value(room_overrides(pool).await).unwrap_or_default();
+    value
+}
+
+/// GET /api/server/retention — The server's retention and every room override (MANAGE_SERVER)
+pub async fn get_settings(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = require_server(&req, pool.get_ref(), crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+    HttpResponse::Ok().json(settings_json(pool.get_ref()).await)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SettingsPatch {
+    pub max_age_days: i64,
+}
+
+/// PATCH /api/server/retention — Change how long messages are kept (MANAGE_SERVER)
+pub async fn update_settings(req: HttpRequest, pool: web::Data<SqlitePool>, body: web::Json<SettingsPatch>) -> HttpResponse {
+    let claims = match require_server(&req, pool.get_ref(), crate::permissions::MANAGE_SERVER).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    if let Err(response) = valid_days(body.max_age_days) {
+        return response;
+    }
+    let old = load(pool.get_ref()).await;
+    let updated = sqlx::query("UPDATE retention_settings SET max_age_days = ?, updated_by_username = ?, updated_at = ? WHERE id = 1")
+        .bind(body.max_age_days)
+        .bind(&claims.username)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+    if updated.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::RetentionUpdated,
+        "server",
+        crate::audit_log::diff(&[("max_age_days", old.max_age_days.into(), body.max_age_days.into())]),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::Ok().json(settings_json(pool.get_ref()).await)
+}
+
+/// The retention in force in `room_id`, and whether the room sets its own.
+async fn effective(pool: &SqlitePool, room_id: &str) -> (i64, bool) {
+    let own: Option<i64> = sqlx::query_scalar("SELECT max_age_days FROM room_retention WHERE room_id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    match own {
+        Some(days) => (days, true),
+        None => (load(pool).await.max_age_days, false),
+    }
+}
+
+/// GET /api/rooms/{room_id}/retention — How long the room keeps messages (VIEW_ROOM)
+pub async fn get_room(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::VIEW_ROOM).await {
+        return response;
+    }
+    let (max_age_days, overridden) = effective(pool.get_ref(), &room_id).await;
+    HttpResponse::Ok().json(serde_json::json!({ "room_id": room_id, "max_age_days": max_age_days, "overridden": overridden }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomPatch {
+    /// `null` drops the override: the room follows the server again.
+    pub max_age_days: Option<i64>,
+}
+
+/// PUT /api/rooms/{room_id}/retention — Set or drop the room's own retention (MANAGE_ROOMS)
+pub async fn update_room(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<RoomPatch>,
+) -> HttpResponse {
+    let claims = match require_server(&req, pool.get_ref(), crate::permissions::MANAGE_ROOMS).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let room_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rooms WHERE id = ?)")
+        .bind(&room_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    }
+    let old: Option<i64> = sqlx::query_scalar("SELECT max_age_days FROM room_retention WHERE room_id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let result = match body.max_age_days {
+        Some(days) => {
+            if let Err(response) = valid_days(days) {
+                return response;
+            }
+            sqlx::query(
+                "INSERT INTO room_retention (room_id, max_age_days, updated_by_username, updated_at) VALUES (?, ?, ?, ?) \
+                 ON CONFLICT(room_id) DO UPDATE SET max_age_days = excluded.max_age_days, \
+                 updated_by_username = excluded.updated_by_username, updated_at = excluded.updated_at"
+            )
+            .bind(&room_id)
+            .bind(days)
+            .bind(&claims.username)
+            .bind(Utc::now().to_rfc3339())
+            .execute(pool.get_ref())
+            .await
+        }
+        None => sqlx::query("DELETE FROM room_retention WHERE room_id = ?").bind(&room_id).execute(pool.get_ref()).await,
+    };
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::RetentionUpdated,
+        &room_id,
+        crate::audit_log::diff(&[("max_age_days", old.into(), body.max_age_days.into())]),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    let (max_age_days, overridden) = effective(pool.get_ref(), &room_id).await;
+    HttpResponse::Ok().json(serde_json::json!({ "room_id": room_id, "max_age_days": max_age_days, "overridden": overridden }))
+}
@@ -0,0 +1,221 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Role display: icons, hoisting, role events
+// ═══════════════════════════════════════════════════════
+//
+// Role CRUD lives with the other admin endpoints in `auth`; this module
+// holds what is shared around it:
+//   - the display metadata (color, icon, hoist) attached to member payloads
+//     as `role_color`, `role_icon_url` and `role_hoist`
+//   - `role_created` / `role_updated` events, carrying the full role
+//   - role icons: a PNG upload, center-cropped to a square, scaled down to
+//     `ICON_SIZE` and re-encoded (which also strips any metadata), served
+//     under `/uploads/role-icons/` (see `uploads`)
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, ServerRole};
+use crate::files::FileStorage;
+use crate::ws::Broadcaster;
+
+/// Icons are stored as `ICON_SIZE`×`ICON_SIZE` PNGs.
+const ICON_SIZE: u32 = 64;
+const MAX_ICON_UPLOAD_BYTES: usize = 256 * 1024;
+/// Larger images are refused before decoding.
+const MAX_ICON_SOURCE_DIMENSION: u32 = 1024;
+const DEFAULT_ROLE_COLOR: &str = "#99aab5";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoleDisplay {
+    pub role_color: String,
+    pub role_icon_url: Option<String>,
+    pub role_hoist: bool,
+}
+
+impl Default for RoleDisplay {
+    fn default() -> Self {
+        Self { role_color: DEFAULT_ROLE_COLOR.to_string(), role_icon_url: None, role_hoist: false }
+    }
+}
+
+impl RoleDisplay {
+    /// From a row with `role_color`, `role_icon_url` and `role_hoist` columns
+    /// (NULL when the role is gone).
+    pub(crate) fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            role_color: row
+                .try_get::<Option<String>, _>("role_color")
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| DEFAULT_ROLE_COLOR.to_string()),
+            role_icon_url: row.try_get("role_icon_url").unwrap_or(None),
+            role_hoist: row.try_get::<Option<i64>, _>("role_hoist").ok().flatten().unwrap_or(0) != 0,
+        }
+    }
+}
+
+pub(crate) async fn fetch_role(pool: &SqlitePool, name: &str) -> Option<ServerRole> {
+    let sql = format!("SELECT {} FROM roles WHERE name = ?", ServerRole::COLUMNS);
+    sqlx::query(&sql)
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| ServerRole::from_row(&row))
+}
+
+/// Display metadata of `role`, defaults for an unknown role.
+pub(crate) async fn role_display(pool: &SqlitePool, role: &str) -> RoleDisplay {
+    match fetch_role(pool, role).await {
+        Some(role) => RoleDisplay { role_color: role.color, role_icon_url: role.icon_url, role_hoist: role.hoist },
+        None => RoleDisplay::default(),
+    }
+}
+
+pub(crate) fn broadcast_role_event(broadcaster: &Broadcaster, kind: &str, role: &ServerRole) {
+    let event = serde_json::json!({ "type": kind, "role": role });
+    let _ = broadcaster.send(event.to_string());
+}
+
+/// Decode a PNG, crop it to a centered square and scale it to `size`.
+/// Also used for custom emoji.
+pub(crate) fn process_icon(bytes: &[u8], size: u32) -> Result<Vec<u8>, &'static str> {
+    use image::ImageEncoder;
+
+    let reader = image::ImageReader::with_format(std::io::Cursor::new(bytes), image::ImageFormat::Png);
+    let (width, height) = reader.into_dimensions().map_err(|_| "Image must be a PNG")?;
+    if width > MAX_ICON_SOURCE_DIMENSION || height > MAX_ICON_SOURCE_DIMENSION {
+        return Err("Image is too large (max 1024x1024)");
+    }
+
+    let img = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .map_err(|_| "Image must be a PNG")?;
+    let side = width.min(height);
+    let square = img.crop_imm((width - side) / 2, (height - side) / 2, side, side);
+    let icon = square
+        .resize_exact(size, size, image::imageops::FilterType::Lanczos3)
+        .to_rgba8();
+
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png)
+        .write_image(icon.as_raw(), size, size, image::ExtendedColorType::Rgba8)
+        .map_err(|_| "Failed to encode image")?;
+    Ok(png)
+}
+
+/// Bump the role's version after an icon change and tell every client.
+async fn save_icon_url(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    role_name: &str,
+    icon_url: Option<&str>,
+) -> HttpResponse {
+    let result = sqlx::query("UPDATE roles SET icon_url = ?, version = version + 1 WHERE name = ?")
+        .bind(icon_url)
+        .bind(role_name)
+        .execute(pool)
+        .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match fetch_role(pool, role_name).await {
+        Some(role) => {
+            broadcast_role_event(broadcaster, "role_updated", &role);
+            HttpResponse::Ok()
+                .insert_header(("ETag", crate::concurrency::etag(role.version)))
+                .json(role)
+        }
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Role not found" })),
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// PUT /api/server/roles/{name}/icon — Upload a role icon, multipart PNG (MANAGE_ROLES)
+pub async fn upload_icon(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
+    }
+
+    let role_name = path.into_inner().trim().to_lowercase();
+    let Some(current) = fetch_role(pool.get_ref(), &role_name).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Role not found" }));
+    };
+
+    let mut bytes = Vec::new();
+    if let Some(Ok(mut field)) = payload.next().await {
+        while let Some(Ok(chunk)) = field.next().await {
+            if bytes.len() + chunk.len() > MAX_ICON_UPLOAD_BYTES {
+                return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Icon too large (max 256KB)" }));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+    }
+    if bytes.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "No file provided" }));
+    }
+    if let Err(response) = crate::scanning::refuse_flagged(&bytes).await {
+        return response;
+    }
+
+    let png = match web::block(move || process_icon(&bytes, ICON_SIZE)).await {
+        Ok(Ok(png)) => png,
+        Ok(Err(error)) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let path = format!("role-icons/{}_{}.png", role_name, Uuid::new_v4());
+    let Ok(icon_url) = crate::uploads::store(pool.get_ref(), storage.get_ref(), &path, png).await else {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save file" }));
+    };
+
+    let response = save_icon_url(pool.get_ref(), broadcaster.get_ref(), &role_name, Some(&icon_url)).await;
+    let replaced = if response.status().is_success() { current.icon_url } else { Some(icon_url) };
+    crate::uploads::remove(pool.get_ref(), storage.get_ref(), replaced.as_deref()).await;
+    response
+}
+
+/// DELETE /api/server/roles/{name}/icon — Remove a role icon (MANAGE_ROLES)
+pub async fn delete_icon(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROLES).await {
+        return response;
+    }
+
+    let role_name = path.into_inner().trim().to_lowercase();
+    let Some(current) = fetch_role(pool.get_ref(), &role_name).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Role not found" }));
+    };
+
+    let response = save_icon_url(pool.get_ref(), broadcaster.get_ref(), &role_name, None).await;
+    if response.status().is_success() {
+        crate::uploads::remove(pool.get_ref(), storage.get_ref(), current.icon_url.as_deref()).await;
+    }
+    response
+}
@@ -0,0 +1,662 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Room transcripts for moderators
+// ═══════════════════════════════════════════════════════
+//
+// `POST /api/rooms/{id}/archives` (MANAGE_MESSAGES) queues a transcript of a room's whole
+// history, deleted messages included, as JSON or as a standalone HTML page.
+// The `room_archives` job (see `jobs`) builds them one at a time, reading
+// `MESSAGE_BATCH` messages at a time and writing each batch straight to a
+// spool file, so memory stays flat however long the room's history is.
+//
+// Without attachments the archive is the transcript itself. With them it is
+// a ZIP (see `archive`):
+//   transcript.json or transcript.html
+//   attachments/<id>-<name>   the room's files, as linked from the transcript
+//   manifest.json             counts, and the files left out (quarantined,
+//                             unavailable, or past the size limit)
+//
+// Archives carry a `progress` (0-100) to poll, are kept in file storage
+// for ROOM_ARCHIVE_TTL_DAYS, then deleted by the same job, and are streamed
+// back on download. Requesting one is recorded in the audit log
+// (`room_archived`).
+//
+// Config (env):
+//   ROOM_ARCHIVE_TTL_DAYS                days an archive is kept (default 7)
+//   ROOM_ARCHIVE_MAX_ATTACHMENT_BYTES    files past this total are left out
+//                                        of a bundle (default 2 GiB)
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Row, SqlitePool};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::archive::{archive_name, Archive, MAX_ARCHIVE_BYTES};
+use crate::auth::{extract_claims, Claims};
+use crate::files::FileStorage;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::messages::{self, Direction, Message};
+
+const DEFAULT_TTL_DAYS: i64 = 7;
+const DEFAULT_MAX_ATTACHMENT_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const MESSAGE_BATCH: i64 = 500;
+const ATTACHMENT_BATCH: i64 = 200;
+const WORKER_INTERVAL: Duration = Duration::from_secs(15);
+const FORMATS: [&str; 2] = ["json", "html"];
+
+fn ttl_days() -> i64 {
+    std::env::var("ROOM_ARCHIVE_TTL_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|d| *d >= 1)
+        .unwrap_or(DEFAULT_TTL_DAYS)
+}
+
+fn max_attachment_bytes() -> u64 {
+    std::env::var("ROOM_ARCHIVE_MAX_ATTACHMENT_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MAX_ATTACHMENT_BYTES)
+        .min(MAX_ARCHIVE_BYTES)
+}
+
+fn attachment_path(id: &str, filename: &str) -> String {
+    format!("attachments/{id}-{}", archive_name(filename))
+}
+
+// ── Transcripts ─────────────────────────────────────────
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:52rem;margin:2rem auto;padding:0 1rem;color:#1e1f22}\
+     article{padding:.5rem 0;border-bottom:1px solid #e3e5e8}header{font-size:.85rem;color:#5c5e66}\
+     header strong{color:#1e1f22;margin-right:.5rem}p{margin:.25rem 0;white-space:pre-wrap;overflow-wrap:anywhere}\
+     .deleted{color:#a0a3a8;font-style:italic}img{max-width:20rem;max-height:20rem;display:block}";
+
+/// A transcript file written a message at a time.
+struct Transcript {
+    out: tokio::io::BufWriter<tokio::fs::File>,
+    format: String,
+    /// Link attachments to their `attachments/` path rather than their URL.
+    bundled: bool,
+    count: u64,
+}
+
+impl Transcript {
+    async fn create(path: &Path, format: &str, bundled: bool, room: &Value) -> Result<Self, String> {
+        let file = tokio::fs::File::create(path).await.map_err(|e| e.to_string())?;
+        let mut transcript =
+            Transcript { out: tokio::io::BufWriter::new(file), format: format.to_string(), bundled, count: 0 };
+        let exported_at = Utc::now().to_rfc3339();
+        let opening = if format == "html" {
+            let name = escape_html(room["name"].as_str().unwrap_or_default());
+            format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>#{name}</title><style>{HTML_STYLE}</style></head>\n\
+                 <body><h1>#{name}</h1><p>Exported {exported_at}</p>\n"
+            )
+        } else {
+            format!("{{\"room\":{room},\"exported_at\":\"{exported_at}\",\"messages\":[\n")
+        };
+        transcript.write(opening.as_bytes()).await?;
+        Ok(transcript)
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).await.map_err(|e| e.to_string())
+    }
+
+    fn html(&self, message: &Message) -> String {
+        let mut html = format!(
+            "<article id=\"m-{id}\"><header><strong>{author}</strong><time datetime=\"{at}\">{at}</time>",
+            id = escape_html(&message.id),
+            author = escape_html(&message.username),
+            at = escape_html(&message.created_at),
+        );
+        if message.edited_at.is_some() {
+            html.push_str(" (edited)");
+        }
+        if let Some(reply_to) = &message.reply_to_id {
+            html.push_str(&format!(" <a href=\"#m-{0}\">in reply</a>", escape_html(reply_to)));
+        }
+        html.push_str("</header>");
+        if message.deleted_at.is_some() {
+            html.push_str("<p class=\"deleted\">Message deleted</p>");
+        } else if !message.content.is_empty() {
+            html.push_str(&format!("<p>{}</p>", escape_html(&message.content)));
+        }
+        for attachment in &message.attachments {
+            let name = escape_html(&attachment.filename);
+            if !self.bundled {
+                html.push_str(&format!("<p>[{name}]</p>"));
+                continue;
+            }
+            let href = escape_html(&attachment_path(&attachment.id, &attachment.filename));
+            if attachment.content_type.starts_with("image/") {
+                html.push_str(&format!("<a href=\"{href}\"><img src=\"{href}\" alt=\"{name}\"></a>"));
+            } else {
+                html.push_str(&format!("<p><a href=\"{href}\">{name}</a></p>"));
+            }
+        }
+        html.push_str("</article>\n");
+        html
+    }
+
+    async fn push(&mut self, message: &Message) -> Result<(), String> {
+        let bytes = if self.format == "html" {
+            self.html(message).into_bytes()
+        } else {
+            let mut value = serde_json::to_value(message).map_err(|e| e.to_string())?;
+            // Signed URLs expire: a bundle points at its own copies instead.
+            if let Some(attachments) = value["attachments"].as_array_mut().filter(|_| self.bundled) {
+                for attachment in attachments {
+                    let path = attachment_path(
+                        attachment["id"].as_str().unwrap_or_default(),
+                        attachment["filename"].as_str().unwrap_or_default(),
+                    );
+                    attachment["path"] = path.into();
+                }
+            }
+            let mut line = if self.count == 0 { Vec::new() } else { b",\n".to_vec() };
+            line.extend(serde_json::to_vec(&value).map_err(|e| e.to_string())?);
+            line
+        };
+        self.write(&bytes).await?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Close the document; returns how many messages it holds.
+    async fn finish(mut self) -> Result<u64, String> {
+        let closing = if self.format == "html" {
+            format!("<footer><p>{} messages</p></footer></body></html>\n", self.count)
+        } else {
+            format!("\n],\"count\":{}}}\n", self.count)
+        };
+        self.write(closing.as_bytes()).await?;
+        self.out.flush().await.map_err(|e| e.to_string())?;
+        Ok(self.count)
+    }
+}
+
+// ── Building archives ───────────────────────────────────
+
+async fn set_progress(pool: &SqlitePool, archive_id: &str, progress: i64) {
+    let _ = sqlx::query("UPDATE room_archives SET progress = ? WHERE id = ?")
+        .bind(progress.clamp(0, 99))
+        .bind(archive_id)
+        .execute(pool)
+        .await;
+}
+
+#[derive(Debug)]
+struct Claimed {
+    id: String,
+    room_id: String,
+    format: String,
+    include_attachments: bool,
+}
+
+/// Write the transcript of the claimed room to `path`, `weight` percent of
+/// the progress. Returns the message count.
+async fn write_transcript(pool: &SqlitePool, claimed: &Claimed, path: &Path, weight: i64) -> Result<u64, String> {
+    let room = sqlx::query("SELECT id, name, kind, topic FROM rooms WHERE id = ?")
+        .bind(&claimed.room_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("The room is gone")?;
+    let room = json!({
+        "id": room.get::<String, _>("id"),
+        "name": room.get::<String, _>("name"),
+        "kind": room.get::<String, _>("kind"),
+        "topic": room.get::<Option<String>, _>("topic"),
+    });
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE room_id = ?")
+        .bind(&claimed.room_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut transcript = Transcript::create(path, &claimed.format, claimed.include_attachments, &room).await?;
+    let mut cursor: Option<(String, String)> = None;
+    loop {
+        let at = cursor.as_ref().map(|(at, id)| (at.as_str(), id.as_str()));
+        let (mut batch, more) = messages::fetch_page(pool, &claimed.room_id, true, at, Direction::Newer, MESSAGE_BATCH).await;
+        messages::enrich_messages_with_reactions(pool, &mut batch).await;
+        crate::emojis::attach_custom_emojis(pool, &mut batch).await;
+        crate::files::attach_to_messages(pool, &mut batch).await;
+        crate::polls::attach_to_messages(pool, None, &mut batch).await;
+        for message in &batch {
+            transcript.push(message).await?;
+        }
+        if let Some(last) = batch.last() {
+            cursor = Some((last.created_at.clone(), last.id.clone()));
+        }
+        set_progress(pool, &claimed.id, weight * transcript.count as i64 / total.max(1)).await;
+        if !more {
+            break;
+        }
+    }
+    transcript.finish().await
+}
+
+/// Zip the transcript at `transcript` with the room's files into `path`.
+async fn bundle(pool: &SqlitePool, storage: &FileStorage, claimed: &Claimed, transcript: &Path, path: &Path) -> Result<u64, String> {
+    let mut archive = Archive::create(path).await?;
+    archive.add_file(&format!("transcript.{}", claimed.format), transcript).await?;
+
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM attachments a JOIN messages m ON m.id = a.message_id WHERE m.room_id = ?"
+    )
+    .bind(&claimed.room_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let budget = max_attachment_bytes();
+    let (mut bytes_included, mut included, mut done) = (0u64, 0u64, 0i64);
+    let mut left_out = Vec::new();
+    let mut after = String::new();
+    loop {
+        let batch = sqlx::query(
+            "SELECT a.id, a.filename, a.file_hash, a.scan_status, f.size FROM attachments a \
+             JOIN files f ON f.hash = a.file_hash JOIN messages m ON m.id = a.message_id \
+             WHERE m.room_id = ? AND a.id > ? ORDER BY a.id LIMIT ?"
+        )
+        .bind(&claimed.room_id)
+        .bind(&after)
+        .bind(ATTACHMENT_BATCH)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after = last.get("id");
+        for row in &batch {
+            let name = attachment_path(&row.get::<String, _>("id"), &row.get::<String, _>("filename"));
+            let size = row.get::<i64, _>("size").max(0) as u64;
+            let reason = if row.get::<Option<String>, _>("scan_status").as_deref() == Some("quarantined") {
+                Some("quarantined")
+            } else if bytes_included + size > budget || !archive.fits(&name, size) {
+                Some("size limit")
+            } else {
+                match storage.get(&row.get::<String, _>("file_hash")).await {
+                    Some(bytes) => {
+                        archive.add(&name, &bytes).await?;
+                        bytes_included += bytes.len() as u64;
+                        included += 1;
+                        None
+                    }
+                    None => Some("unavailable"),
+                }
+            };
+            if let Some(reason) = reason {
+                left_out.push(json!({ "path": name, "size": size, "reason": reason }));
+            }
+            done += 1;
+        }
+        set_progress(pool, &claimed.id, 60 + 39 * done / total.max(1)).await;
+    }
+
+    archive
+        .add_json("manifest.json", &json!({
+            "archive_id": claimed.id,
+            "room_id": claimed.room_id,
+            "format": claimed.format,
+            "created_at": Utc::now().to_rfc3339(),
+            "attachments": included,
+            "attachment_bytes": bytes_included,
+            "attachments_left_out": left_out,
+        }))
+        .await?;
+    archive.finish().await
+}
+
+/// Build the claimed archive and put it in storage; returns its key, size
+/// and message count.
+async fn run_archive(pool: &SqlitePool, storage: &FileStorage, claimed: &Claimed) -> Result<(String, u64, u64), String> {
+    let dir = storage.spool_dir();
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    let transcript = dir.join(format!("room-archive-{}.{}", claimed.id, claimed.format));
+    let zip = dir.join(format!("room-archive-{}.zip", claimed.id));
+    let stored = async {
+        let weight = if claimed.include_attachments { 60 } else { 99 };
+        let count = write_transcript(pool, claimed, &transcript, weight).await?;
+        let path = if claimed.include_attachments {
+            bundle(pool, storage, claimed, &transcript, &zip).await?;
+            &zip
+        } else {
+            &transcript
+        };
+        let size = tokio::fs::metadata(path).await.map_err(|e| e.to_string())?.len();
+        // Random, so the archive can't be found from anything public.
+        let key = crate::files::sha256_hex(Uuid::new_v4().as_bytes());
+        storage.put_file(&key, path).await?;
+        Ok((key, size, count))
+    }
+    .await;
+    tokio::fs::remove_file(&transcript).await.ok();
+    tokio::fs::remove_file(&zip).await.ok();
+    stored
+}
+
+/// Take the oldest pending archive, if any.
+async fn claim(pool: &SqlitePool) -> Result<Option<Claimed>, String> {
+    sqlx::query(
+        "UPDATE room_archives SET status = 'running', started_at = ?, progress = 0 \
+         WHERE id = (SELECT id FROM room_archives WHERE status = 'pending' ORDER BY created_at, id LIMIT 1) \
+         RETURNING id, room_id, format, include_attachments"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    .map(|row| {
+        row.map(|row| Claimed {
+            id: row.get("id"),
+            room_id: row.get("room_id"),
+            format: row.get("format"),
+            include_attachments: row.get::<i64, _>("include_attachments") != 0,
+        })
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Delete expired archives, then build every pending one. Returns how many
+/// archives were built or deleted.
+pub async fn process_archives(pool: &SqlitePool, storage: &FileStorage) -> Result<u64, String> {
+    let now = Utc::now();
+    let expired: Vec<(String, Option<String>)> =
+        sqlx::query_as("SELECT id, archive_key FROM room_archives WHERE status = 'ready' AND expires_at <= ?")
+            .bind(now.to_rfc3339())
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    let mut processed = 0;
+    for (id, key) in expired {
+        if let Some(key) = key {
+            storage.delete(&key).await;
+        }
+        sqlx::query("UPDATE room_archives SET status = 'expired', archive_key = NULL WHERE id = ?")
+            .bind(&id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        processed += 1;
+    }
+
+    let mut failure = None;
+    while let Some(claimed) = claim(pool).await? {
+        let finished = Utc::now();
+        let result = match run_archive(pool, storage, &claimed).await {
+            Ok((key, size, count)) => sqlx::query(
+                "UPDATE room_archives SET status = 'ready', progress = 100, archive_key = ?, size_bytes = ?, message_count = ?, \
+                 completed_at = ?, expires_at = ? WHERE id = ?"
+            )
+            .bind(key)
+            .bind(size as i64)
+            .bind(count as i64)
+            .bind(finished.to_rfc3339())
+            .bind((finished + chrono::Duration::days(ttl_days())).to_rfc3339())
+            .bind(&claimed.id),
+            Err(e) => {
+                failure.get_or_insert(format!("room archive {}: {e}", claimed.id));
+                sqlx::query("UPDATE room_archives SET status = 'failed', error = ?, completed_at = ? WHERE id = ?")
+                    .bind(e)
+                    .bind(finished.to_rfc3339())
+                    .bind(&claimed.id)
+            }
+        };
+        result.execute(pool).await.map_err(|e| e.to_string())?;
+        processed += 1;
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(processed),
+    }
+}
+
+async fn archive_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query("SELECT COUNT(*) AS depth, MIN(created_at) AS oldest_due_at FROM room_archives WHERE status = 'pending'")
+        .fetch_one(pool)
+        .await
+        .map(|row| Backlog::from_row(&row))
+        .unwrap_or_default()
+}
+
+pub fn spawn_archive_worker(pool: SqlitePool, storage: FileStorage, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::ROOM_ARCHIVES);
+    actix_web::rt::spawn(async move {
+        // Cut short by the last shutdown: start them over.
+        let _ = sqlx::query("UPDATE room_archives SET status = 'pending', progress = 0 WHERE status = 'running'")
+            .execute(&pool)
+            .await;
+        let mut interval = tokio::time::interval(WORKER_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::ROOM_ARCHIVES, None, process_archives(&pool, &storage), archive_backlog(&pool)).await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct RoomArchive {
+    pub id: String,
+    pub room_id: String,
+    pub requested_by_username: String,
+    /// `json` or `html`.
+    pub format: String,
+    pub include_attachments: bool,
+    /// `pending`, `running`, `ready`, `failed` or `expired`.
+    pub status: String,
+    pub progress: i64,
+    pub message_count: Option<i64>,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+    pub expires_at: Option<String>,
+    /// While `ready`.
+    pub download_url: Option<String>,
+}
+
+const ARCHIVE_SELECT: &str = "SELECT id, room_id, requested_by_username, format, include_attachments, status, progress, \
+     message_count, size_bytes, error, created_at, completed_at, expires_at FROM room_archives";
+
+impl RoomArchive {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let id: String = row.get("id");
+        let room_id: String = row.get("room_id");
+        let status: String = row.get("status");
+        RoomArchive {
+            download_url: (status == "ready").then(|| format!("/api/rooms/{room_id}/archives/{id}/download")),
+            id,
+            room_id,
+            requested_by_username: row.get("requested_by_username"),
+            format: row.get("format"),
+            include_attachments: row.get::<i64, _>("include_attachments") != 0,
+            status,
+            progress: row.get("progress"),
+            message_count: row.get("message_count"),
+            size_bytes: row.get("size_bytes"),
+            error: row.get("error"),
+            created_at: row.get("created_at"),
+            completed_at: row.get("completed_at"),
+            expires_at: row.get("expires_at"),
+        }
+    }
+}
+
+async fn fetch_archive(pool: &SqlitePool, room_id: &str, archive_id: &str) -> Option<RoomArchive> {
+    sqlx::query(&format!("{ARCHIVE_SELECT} WHERE id = ? AND room_id = ?"))
+        .bind(archive_id)
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| RoomArchive::from_row(&row))
+}
+
+/// The caller's claims if they moderate the room.
+async fn require_moderator(pool: &SqlitePool, req: &HttpRequest, room_id: &str) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require(pool, room_id, &claims, crate::permissions::MANAGE_MESSAGES).await?;
+    Ok(claims)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateArchive {
+    /// `json` (the default) or `html`.
+    pub format: Option<String>,
+    /// Bundle the room's files with the transcript, as a ZIP.
+    #[serde(default)]
+    pub attachments: bool,
+}
+
+/// POST /api/rooms/{room_id}/archives — Queue a transcript of the room's history (MANAGE_MESSAGES)
+pub async fn create_archive(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<CreateArchive>,
+) -> HttpResponse {
+    let room_id = path.into_inner();
+    let claims = match require_moderator(pool.get_ref(), &req, &room_id).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let format = body.format.as_deref().unwrap_or("json").trim().to_lowercase();
+    if !FORMATS.contains(&format.as_str()) {
+        return HttpResponse::BadRequest().json(json!({ "error": "format must be json or html" }));
+    }
+    let busy: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM room_archives WHERE room_id = ? AND status IN ('pending', 'running'))"
+    )
+    .bind(&room_id)
+    .fetch_one(pool.get_ref())
+    .await
+    .unwrap_or(false);
+    if busy {
+        return HttpResponse::Conflict().json(json!({ "error": "An archive of this room is already in progress" }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let inserted = sqlx::query(
+        "INSERT INTO room_archives (id, room_id, requested_by, requested_by_username, format, include_attachments, status, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, 'pending', ?)"
+    )
+    .bind(&id)
+    .bind(&room_id)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(&format)
+    .bind(body.attachments)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::RoomArchived,
+        &room_id,
+        json!({ "archive_id": id, "format": format, "attachments": body.attachments }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    match fetch_archive(pool.get_ref(), &room_id, &id).await {
+        Some(archive) => HttpResponse::Accepted().json(archive),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/rooms/{room_id}/archives — The room's archives, newest first (MANAGE_MESSAGES)
+pub async fn list_archives(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let room_id = path.into_inner();
+    if let Err(response) = require_moderator(pool.get_ref(), &req, &room_id).await {
+        return response;
+    }
+    let rows = sqlx::query(&format!("{ARCHIVE_SELECT} WHERE room_id = ? ORDER BY created_at DESC"))
+        .bind(&room_id)
+        .fetch_all(pool.get_ref())
+        .await;
+    match rows {
+        Ok(rows) => HttpResponse::Ok().json(json!({ "archives": rows.iter().map(RoomArchive::from_row).collect::<Vec<_>>() })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/rooms/{room_id}/archives/{archive_id} — One archive, to poll its progress (MANAGE_MESSAGES)
+pub async fn get_archive(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (room_id, archive_id) = path.into_inner();
+    if let Err(response) = require_moderator(pool.get_ref(), &req, &room_id).await {
+        return response;
+    }
+    match fetch_archive(pool.get_ref(), &room_id, &archive_id).await {
+        Some(archive) => HttpResponse::Ok().json(archive),
+        None => HttpResponse::NotFound().json(json!({ "error": "Archive not found" })),
+    }
+}
+
+/// GET /api/rooms/{room_id}/archives/{archive_id}/download — The file of a ready archive, streamed (MANAGE_MESSAGES)
+pub async fn download_archive(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (room_id, archive_id) = path.into_inner();
+    if let Err(response) = require_moderator(pool.get_ref(), &req, &room_id).await {
+        return response;
+    }
+    let row = sqlx::query(
+        "SELECT archive_key, size_bytes, format, include_attachments FROM room_archives \
+         WHERE id = ? AND room_id = ? AND status = 'ready' AND expires_at > ?"
+    )
+    .bind(&archive_id)
+    .bind(&room_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some(row) = row else {
+        return HttpResponse::NotFound().json(json!({ "error": "Archive not found" }));
+    };
+    let (key, size): (String, i64) = (row.get("archive_key"), row.get("size_bytes"));
+    let (content_type, extension) = match (row.get::<i64, _>("include_attachments") != 0, row.get::<String, _>("format").as_str()) {
+        (true, _) => ("application/zip", "zip"),
+        (false, "html") => ("text/html; charset=utf-8", "html"),
+        (false, _) => ("application/json", "json"),
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("room-{room_id}-{archive_id}.{extension}"))],
+        })
+        .insert_header(("Cache-Control", "private, no-store"))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .streaming(storage.get_ref().clone().stream(key, size.max(0) as u64))
+}
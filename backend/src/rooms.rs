@@ -2,7 +2,9 @@ use actix_web::{web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use uuid::Uuid;
-use crate::auth::extract_claims;
+use crate::auth::{extract_claims, Claims};
+use crate::concurrency::{diff_fields, etag, expected_version, precondition_failed};
+use crate::sudo::Sudo;
 use crate::ws::{cache_remove_room, cache_set_room_required_role, AccessCache, Broadcaster};
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -12,6 +14,58 @@ pub struct Room {
     pub kind: String,
     pub required_role: String,
     pub created_at: String,
+    pub topic: Option<String>,
+    pub guidelines: Option<String>,
+    /// Bumped on every edit, see `concurrency`.
+    pub version: i64,
+    /// Seconds a member waits between two messages, 0 when off (see `slowmode`).
+    pub slowmode_seconds: i64,
+    /// `None` for rooms listed before the categories (see `categories`).
+    pub category_id: Option<String>,
+    /// Order within its category, from 0.
+    pub position: i64,
+}
+
+/// `forum` rooms hold posts, see `forum`.
+const ROOM_KINDS: &[&str] = &["text", "voice", "forum"];
+
+pub(crate) const ROOM_COLUMNS: &str =
+    "id, name, kind, required_role, created_at, topic, guidelines, version, slowmode_seconds, category_id, position";
+
+/// A room as listed for one user, with their unread counters.
+#[derive(Debug, Serialize)]
+pub struct RoomWithReadState {
+    #[serde(flatten)]
+    pub room: Room,
+    #[serde(flatten)]
+    pub read_state: crate::read_states::RoomCounters,
+}
+
+/// Maximum length of a room topic (markdown source).
+pub const MAX_TOPIC_CHARS: usize = 1024;
+/// Maximum length of room guidelines (markdown source).
+pub const MAX_GUIDELINES_CHARS: usize = 4000;
+
+/// Lightweight room payload for hover cards.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RoomMetadata {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub topic: Option<String>,
+    pub guidelines: Option<String>,
+    pub version: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RoomMetadataChange {
+    pub id: String,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_by: String,
+    pub changed_by_username: Option<String>,
+    pub changed_at: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -19,6 +73,10 @@ pub struct CreateRoom {
     pub name: String,
     pub kind: Option<String>,
     pub required_role: Option<String>,
+    /// Admins only; 0 (off) by default.
+    pub slowmode_seconds: Option<i64>,
+    /// Room managers only; the room goes after the others of the category.
+    pub category_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,29 +84,50 @@ pub struct UpdateRoomSettings {
     pub name: String,
     pub kind: String,
     pub required_role: String,
+    /// Omitted to keep the current slowmode.
+    pub slowmode_seconds: Option<i64>,
+}
+
+/// Omitted fields are left untouched; an empty string clears the field.
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoomMetadata {
+    pub topic: Option<String>,
+    pub guidelines: Option<String>,
 }
 
-/// GET /api/rooms — List all rooms
+#[derive(Debug, Deserialize)]
+pub struct MetadataHistoryQuery {
+    pub limit: Option<i64>,
+}
+
+/// GET /api/rooms — List the rooms the caller can see, with unread and mention counts
 pub async fn list_rooms(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
     let claims = match extract_claims(&req) {
         Some(c) => c,
         None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
     };
 
-    let rooms = if claims.role == "admin" {
-        sqlx::query_as::<_, Room>("SELECT id, name, kind, required_role, created_at FROM rooms ORDER BY created_at")
-            .fetch_all(pool.get_ref())
-            .await
-            .unwrap_or_default()
-    } else {
-        sqlx::query_as::<_, Room>(
-            "SELECT id, name, kind, required_role, created_at FROM rooms WHERE required_role = 'user' OR required_role = ? ORDER BY created_at"
-        )
-        .bind(&claims.role)
+    // Rooms without a category first, then each category in order.
+    let sql = format!(
+        "SELECT {ROOM_COLUMNS} FROM rooms \
+         ORDER BY COALESCE((SELECT c.position FROM categories c WHERE c.id = rooms.category_id), -1), position"
+    );
+    let mut rooms = sqlx::query_as::<_, Room>(&sql)
         .fetch_all(pool.get_ref())
         .await
-        .unwrap_or_default()
-    };
+        .unwrap_or_default();
+    let viewable = crate::permissions::viewable_rooms(pool.get_ref(), &claims.sub, &claims.role).await;
+    rooms.retain(|room| viewable.contains(&room.id));
+
+    let room_ids: Vec<String> = rooms.iter().map(|r| r.id.clone()).collect();
+    let mut counters = crate::read_states::counters_for_rooms(pool.get_ref(), &claims.sub, &room_ids).await;
+    let rooms: Vec<RoomWithReadState> = rooms
+        .into_iter()
+        .map(|room| {
+            let read_state = counters.remove(&room.id).unwrap_or_default();
+            RoomWithReadState { room, read_state }
+        })
+        .collect();
 
     HttpResponse::Ok().json(rooms)
 }
@@ -65,14 +144,18 @@ pub async fn create_room(
         None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
     };
 
+    if let Some(response) = crate::email::require_verified(pool.get_ref(), &claims.sub).await {
+        return response;
+    }
+
     let name = body.name.trim();
     if name.is_empty() {
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Room name is required" }));
     }
 
     let kind = body.kind.as_deref().unwrap_or("text").trim().to_lowercase();
-    if kind != "text" && kind != "voice" {
-        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Room kind must be text or voice" }));
+    if !ROOM_KINDS.contains(&kind.as_str()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Room kind must be text, voice or forum" }));
     }
 
     let required_role = body.required_role.as_deref().unwrap_or("user").trim().to_lowercase();
@@ -87,30 +170,79 @@ pub async fn create_room(
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid required role" }));
     }
 
-    if required_role != "user" && claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only admins can create restricted rooms" }));
+    let manages_rooms = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await;
+    if required_role != "user" && manages_rooms.is_err() {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only room managers can create restricted rooms" }));
+    }
+
+    let slowmode_seconds = body.slowmode_seconds.unwrap_or(0);
+    if !crate::slowmode::is_valid(slowmode_seconds) {
+        return invalid_slowmode();
+    }
+    if slowmode_seconds != 0 && manages_rooms.is_err() {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only room managers can set slowmode" }));
+    }
+
+    let category_id = body.category_id.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    if let Some(category_id) = category_id {
+        if manages_rooms.is_err() {
+            return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Only room managers can place rooms in categories" }));
+        }
+        if !crate::categories::exists(pool.get_ref(), category_id).await {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Category not found" }));
+        }
     }
 
     let id = Uuid::new_v4().to_string();
 
-    let result = sqlx::query("INSERT INTO rooms (id, name, kind, required_role) VALUES (?, ?, ?, ?)")
-        .bind(&id)
-        .bind(name)
-        .bind(&kind)
-        .bind(&required_role)
-        .execute(pool.get_ref())
-        .await;
+    let result = sqlx::query(
+        "INSERT INTO rooms (id, name, kind, required_role, slowmode_seconds, category_id, position) \
+         VALUES (?, ?, ?, ?, ?, ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM rooms WHERE category_id IS ?))"
+    )
+    .bind(&id)
+    .bind(name)
+    .bind(&kind)
+    .bind(&required_role)
+    .bind(slowmode_seconds)
+    .bind(category_id)
+    .bind(category_id)
+    .execute(pool.get_ref())
+    .await;
 
     match result {
         Ok(_) => {
+            crate::quickswitch::invalidate_from(&req);
             cache_set_room_required_role(access_cache.get_ref(), &id, &required_role);
-            HttpResponse::Ok().json(serde_json::json!({ "id": id, "name": name, "kind": kind, "required_role": required_role }))
+            let room = serde_json::json!({
+                "id": id,
+                "name": name,
+                "kind": kind,
+                "required_role": required_role,
+                "slowmode_seconds": slowmode_seconds,
+                "category_id": category_id,
+            });
+            crate::audit_log::record(
+                pool.get_ref(),
+                Some(&claims),
+                crate::audit_log::Action::RoomCreated,
+                &id,
+                room.clone(),
+                crate::audit_log::reason(&req).as_deref(),
+            )
+            .await;
+            HttpResponse::Ok().json(room)
         }
         Err(_) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Room name already exists" })),
     }
 }
 
-/// PATCH /api/rooms/{id} — Update room settings (Admin only)
+fn invalid_slowmode() -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": format!("slowmode_seconds must be between 0 and {}", crate::slowmode::MAX_SLOWMODE_SECONDS)
+    }))
+}
+
+/// PATCH /api/rooms/{id} — Update room settings (MANAGE_ROOMS)
 pub async fn update_room(
     req: HttpRequest,
     pool: web::Data<SqlitePool>,
@@ -124,8 +256,8 @@ pub async fn update_room(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
     }
 
     let room_id = path.into_inner();
@@ -135,8 +267,8 @@ pub async fn update_room(
     }
 
     let kind = body.kind.trim().to_lowercase();
-    if kind != "text" && kind != "voice" {
-        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Room kind must be text or voice" }));
+    if !ROOM_KINDS.contains(&kind.as_str()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Room kind must be text, voice or forum" }));
     }
 
     let required_role = body.required_role.trim().to_lowercase();
@@ -151,20 +283,82 @@ pub async fn update_room(
         return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid required role" }));
     }
 
-    let result = sqlx::query("UPDATE rooms SET name = ?, kind = ?, required_role = ? WHERE id = ?")
-        .bind(room_name)
-        .bind(&kind)
-        .bind(&required_role)
+    if body.slowmode_seconds.is_some_and(|s| !crate::slowmode::is_valid(s)) {
+        return invalid_slowmode();
+    }
+
+    let expected = match expected_version(&req) {
+        Ok(v) => v,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+
+    let current = sqlx::query_as::<_, Room>(&format!("SELECT {ROOM_COLUMNS} FROM rooms WHERE id = ?"))
         .bind(&room_id)
-        .execute(pool.get_ref())
-        .await;
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+
+    let Some(current) = current else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    };
+
+    let conflict = |current: &Room| {
+        let current_slowmode = current.slowmode_seconds.to_string();
+        let requested_slowmode = body.slowmode_seconds.unwrap_or(current.slowmode_seconds).to_string();
+        let diff = diff_fields(&[
+            ("name", Some(&current.name), Some(room_name)),
+            ("kind", Some(&current.kind), Some(&kind)),
+            ("required_role", Some(&current.required_role), Some(&required_role)),
+            ("slowmode_seconds", Some(&current_slowmode), Some(&requested_slowmode)),
+        ]);
+        precondition_failed(current.version, current, diff)
+    };
+    if expected.is_some_and(|v| v != current.version) {
+        return conflict(&current);
+    }
+
+    // Messages of a forum belong to posts, those of other rooms to none.
+    if (current.kind == "forum") != (kind == "forum") {
+        let has_messages: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM messages WHERE room_id = ?)")
+            .bind(&room_id)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or(true);
+        if has_messages {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": "A room with messages cannot become or stop being a forum" }));
+        }
+    }
+
+    let slowmode_seconds = body.slowmode_seconds.unwrap_or(current.slowmode_seconds);
+    // The version guard makes the check above atomic with the write.
+    let result = sqlx::query(
+        "UPDATE rooms SET name = ?, kind = ?, required_role = ?, slowmode_seconds = ?, version = version + 1 WHERE id = ? AND version = ?"
+    )
+    .bind(room_name)
+    .bind(&kind)
+    .bind(&required_role)
+    .bind(slowmode_seconds)
+    .bind(&room_id)
+    .bind(current.version)
+    .execute(pool.get_ref())
+    .await;
 
     match result {
         Ok(res) => {
             if res.rows_affected() == 0 {
-                return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+                // Someone else wrote between our read and our write.
+                let latest = sqlx::query_as::<_, Room>(&format!("SELECT {ROOM_COLUMNS} FROM rooms WHERE id = ?"))
+                    .bind(&room_id)
+                    .fetch_optional(pool.get_ref())
+                    .await
+                    .unwrap_or(None);
+                return match latest {
+                    Some(latest) => conflict(&latest),
+                    None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" })),
+                };
             }
 
+            let version = current.version + 1;
             cache_set_room_required_role(access_cache.get_ref(), &room_id, &required_role);
 
             let event = serde_json::json!({
@@ -173,30 +367,53 @@ pub async fn update_room(
                 "name": room_name,
                 "kind": kind,
                 "required_role": required_role,
+                "slowmode_seconds": slowmode_seconds,
+                "version": version,
             });
             let _ = broadcaster.send(event.to_string());
+            let changes = crate::audit_log::diff(&[
+                ("name", current.name.clone().into(), room_name.into()),
+                ("kind", current.kind.clone().into(), kind.clone().into()),
+                ("required_role", current.required_role.clone().into(), required_role.clone().into()),
+                ("slowmode_seconds", current.slowmode_seconds.into(), slowmode_seconds.into()),
+            ]);
+            crate::audit_log::record(
+                pool.get_ref(),
+                Some(&claims),
+                crate::audit_log::Action::RoomUpdated,
+                &room_id,
+                changes,
+                crate::audit_log::reason(&req).as_deref(),
+            )
+            .await;
+
+            if slowmode_seconds != current.slowmode_seconds {
+                let content = match slowmode_seconds {
+                    0 => "turned slowmode off".to_string(),
+                    s => format!("set slowmode to {s}s"),
+                };
+                post_system_message(pool.get_ref(), broadcaster.get_ref(), &room_id, &claims.sub, &claims.username, "room_slowmode_changed", &content).await;
+            }
 
-            HttpResponse::Ok().json(serde_json::json!({ "status": "updated" }))
+            HttpResponse::Ok()
+                .insert_header(("ETag", etag(version)))
+                .json(serde_json::json!({ "status": "updated", "version": version }))
         }
         Err(_) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Room name already exists" })),
     }
 }
 
-/// DELETE /api/rooms/{id} — Delete a room (Admin only)
+/// DELETE /api/rooms/{id} — Delete a room (MANAGE_ROOMS)
 pub async fn delete_room(
     req: HttpRequest,
+    Sudo(claims): Sudo,
     pool: web::Data<SqlitePool>,
     path: web::Path<String>,
     broadcaster: web::Data<Broadcaster>,
     access_cache: web::Data<AccessCache>,
 ) -> HttpResponse {
-    let claims = match extract_claims(&req) {
-        Some(c) => c,
-        None => return HttpResponse::Unauthorized().finish(),
-    };
-
-    if claims.role != "admin" {
-        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin only" }));
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
     }
 
     let room_id = path.into_inner();
@@ -207,15 +424,25 @@ pub async fn delete_room(
         .execute(pool.get_ref())
         .await;
 
-    let result = sqlx::query("DELETE FROM rooms WHERE id = ?")
+    let result = sqlx::query_as::<_, (Option<String>, String, String)>("DELETE FROM rooms WHERE id = ? RETURNING category_id, name, kind")
         .bind(&room_id)
-        .execute(pool.get_ref())
+        .fetch_optional(pool.get_ref())
         .await;
 
     match result {
-        Ok(res) => {
-            if res.rows_affected() > 0 {
+        Ok(deleted) => {
+            if let Some((category_id, name, kind)) = deleted {
                 cache_remove_room(access_cache.get_ref(), &room_id);
+                crate::audit_log::record(
+                    pool.get_ref(),
+                    Some(&claims),
+                    crate::audit_log::Action::RoomDeleted,
+                    &room_id,
+                    serde_json::json!({ "name": name, "kind": kind }),
+                    crate::audit_log::reason(&req).as_deref(),
+                )
+                .await;
+                crate::categories::room_removed(pool.get_ref(), category_id.as_deref()).await;
 
                 // Broadcast room_deleted event
                 let msg = serde_json::json!({
@@ -231,3 +458,281 @@ pub async fn delete_room(
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
+
+/// Check the caller may read the room (`VIEW_ROOM`, see `permissions`).
+pub(crate) async fn check_room_access(pool: &SqlitePool, room_id: &str, claims: &Claims) -> Result<(), HttpResponse> {
+    crate::permissions::require(pool, room_id, claims, crate::permissions::VIEW_ROOM).await
+}
+
+/// Trim and validate a topic/guidelines value. `Ok(None)` means "clear".
+pub(crate) fn normalize_metadata(field: &str, raw: &str, max_chars: usize) -> Result<Option<String>, String> {
+    let value = raw.trim();
+    if value.chars().count() > max_chars {
+        return Err(format!("Room {field} must be at most {max_chars} characters"));
+    }
+    Ok((!value.is_empty()).then(|| value.to_string()))
+}
+
+/// GET /api/rooms/{id}/metadata — Name, topic and guidelines only (hover cards)
+pub async fn get_room_metadata(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(resp) = check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return resp;
+    }
+
+    let metadata = sqlx::query_as::<_, RoomMetadata>(
+        "SELECT id, name, kind, topic, guidelines, version FROM rooms WHERE id = ?"
+    )
+    .bind(&room_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+
+    match metadata {
+        Some(m) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "private, max-age=30"))
+            .insert_header(("ETag", etag(m.version)))
+            .json(m),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" })),
+    }
+}
+
+/// GET /api/rooms/{id}/metadata/history — Past topic/guidelines changes
+pub async fn get_room_metadata_history(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    query: web::Query<MetadataHistoryQuery>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Not authenticated" })),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(resp) = check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return resp;
+    }
+
+    let limit = crate::pagination::limit(query.limit, 50, 200);
+    let history = sqlx::query_as::<_, RoomMetadataChange>(
+        "SELECT h.id, h.field, h.old_value, h.new_value, h.changed_by, u.username AS changed_by_username, h.changed_at \
+         FROM room_metadata_history h LEFT JOIN users u ON h.changed_by = u.id \
+         WHERE h.room_id = ? ORDER BY h.changed_at DESC LIMIT ?"
+    )
+    .bind(&room_id)
+    .bind(limit)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+
+    HttpResponse::Ok().json(history)
+}
+
+/// PATCH /api/rooms/{id}/metadata — Update topic and/or guidelines (MANAGE_ROOMS)
+pub async fn update_room_metadata(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<UpdateRoomMetadata>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_ROOMS).await {
+        return response;
+    }
+
+    let room_id = path.into_inner();
+
+    let mut requested: Vec<(&str, Option<String>)> = Vec::new();
+    if let Some(raw) = &body.topic {
+        match normalize_metadata("topic", raw, MAX_TOPIC_CHARS) {
+            Ok(v) => requested.push(("topic", v)),
+            Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        }
+    }
+    if let Some(raw) = &body.guidelines {
+        match normalize_metadata("guidelines", raw, MAX_GUIDELINES_CHARS) {
+            Ok(v) => requested.push(("guidelines", v)),
+            Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        }
+    }
+    if requested.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Nothing to update" }));
+    }
+
+    let expected = match expected_version(&req) {
+        Ok(v) => v,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+
+    let current = sqlx::query_as::<_, RoomMetadata>(
+        "SELECT id, name, kind, topic, guidelines, version FROM rooms WHERE id = ?"
+    )
+    .bind(&room_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+
+    let Some(current) = current else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    };
+
+    let conflict = |current: &RoomMetadata| {
+        let diff = diff_fields(
+            &requested
+                .iter()
+                .map(|(field, value)| {
+                    let now = if *field == "topic" { &current.topic } else { &current.guidelines };
+                    (*field, now.as_deref(), value.as_deref())
+                })
+                .collect::<Vec<_>>(),
+        );
+        precondition_failed(current.version, current, diff)
+    };
+    if expected.is_some_and(|v| v != current.version) {
+        return conflict(&current);
+    }
+
+    let mut topic = current.topic.clone();
+    let mut guidelines = current.guidelines.clone();
+    let mut changes = Vec::new();
+    for (field, new_value) in &requested {
+        let value = if *field == "topic" { &mut topic } else { &mut guidelines };
+        if value != new_value {
+            changes.push((*field, value.clone(), new_value.clone()));
+            value.clone_from(new_value);
+        }
+    }
+    if changes.is_empty() {
+        return HttpResponse::Ok()
+            .insert_header(("ETag", etag(current.version)))
+            .json(current);
+    }
+
+    // Both fields in one guarded write, so a concurrent edit either lands
+    // entirely before ours or makes ours fail with 412.
+    let updated = sqlx::query("UPDATE rooms SET topic = ?, guidelines = ?, version = version + 1 WHERE id = ? AND version = ?")
+        .bind(&topic)
+        .bind(&guidelines)
+        .bind(&room_id)
+        .bind(current.version)
+        .execute(pool.get_ref())
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+
+    if updated == 0 {
+        let latest = sqlx::query_as::<_, RoomMetadata>(
+            "SELECT id, name, kind, topic, guidelines, version FROM rooms WHERE id = ?"
+        )
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+        return match latest {
+            Some(latest) => conflict(&latest),
+            None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" })),
+        };
+    }
+
+    for (field, old_value, new_value) in changes {
+        let _ = sqlx::query(
+            "INSERT INTO room_metadata_history (id, room_id, field, old_value, new_value, changed_by, changed_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&room_id)
+        .bind(field)
+        .bind(&old_value)
+        .bind(&new_value)
+        .bind(&claims.sub)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+
+        let content = match (field, &new_value) {
+            ("topic", Some(v)) => format!("changed the room topic: {v}"),
+            ("topic", None) => "cleared the room topic".to_string(),
+            (_, Some(_)) => "updated the room guidelines".to_string(),
+            (_, None) => "cleared the room guidelines".to_string(),
+        };
+        post_system_message(pool.get_ref(), broadcaster.get_ref(), &room_id, &claims.sub, &claims.username, &format!("room_{field}_changed"), &content).await;
+    }
+
+    let version = current.version + 1;
+    let event = serde_json::json!({
+        "type": "room_updated",
+        "room_id": room_id,
+        "name": current.name,
+        "kind": current.kind,
+        "topic": topic,
+        "guidelines": guidelines,
+        "version": version,
+    });
+    let _ = broadcaster.send(event.to_string());
+
+    HttpResponse::Ok()
+        .insert_header(("ETag", etag(version)))
+        .json(RoomMetadata {
+            id: current.id,
+            name: current.name,
+            kind: current.kind,
+            topic,
+            guidelines,
+            version,
+        })
+}
+
+/// Store and broadcast a system message attributed to `user_id` in `room_id`.
+pub(crate) async fn post_system_message(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    room_id: &str,
+    user_id: &str,
+    username: &str,
+    kind: &str,
+    content: &str,
+) {
+    let id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let result = sqlx::query(
+        "INSERT INTO messages (id, room_id, user_id, username, content, created_at, kind) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(room_id)
+    .bind(user_id)
+    .bind(username)
+    .bind(content)
+    .bind(&now)
+    .bind(kind)
+    .execute(pool)
+    .await;
+
+    if result.is_ok() {
+        let event = serde_json::json!({
+            "type": "message",
+            "id": id,
+            "room_id": room_id,
+            "user_id": user_id,
+            "username": username,
+            "content": content,
+            "created_at": now,
+            "kind": kind,
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+}
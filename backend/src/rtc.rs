@@ -0,0 +1,134 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — ICE servers for native voice rooms (STUN/TURN)
+// ═══════════════════════════════════════════════════════
+//
+// Voice rooms are peer-to-peer WebRTC. Behind symmetric NATs and strict
+// firewalls peers need a TURN relay, and a relay with a static password
+// shipped to every client is an open relay. `GET /api/voice/rtc-config`
+// instead hands out short-lived credentials in the "TURN REST API" format
+// that coturn understands with `use-auth-secret` + `static-auth-secret`:
+//   username   = "<expiry unix time>:<voxium user id>"
+//   credential = base64(HMAC-SHA1(TURN_SECRET, username))
+// The secret itself never leaves the server.
+//
+// Config (env):
+//   RTC_STUN_URLS             comma-separated STUN URLs (default Google's,
+//                             empty to send none)
+//   TURN_URLS                 comma-separated turn:/turns: URLs
+//   TURN_SECRET               coturn `static-auth-secret`
+//   TURN_CREDENTIAL_TTL_SECS  lifetime of issued credentials (default 86400)
+
+use actix_web::{HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+
+use crate::auth::extract_claims;
+
+const DEFAULT_STUN_URL: &str = "stun:stun.l.google.com:19302";
+const DEFAULT_CREDENTIAL_TTL_SECS: i64 = 24 * 60 * 60;
+
+// ── Config ──────────────────────────────────────────────
+
+fn url_list(var: &str) -> Option<Vec<String>> {
+    std::env::var(var).ok().map(|raw| {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|u| !u.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+pub(crate) fn stun_urls() -> Vec<String> {
+    url_list("RTC_STUN_URLS").unwrap_or_else(|| vec![DEFAULT_STUN_URL.to_string()])
+}
+
+pub(crate) fn turn_urls() -> Vec<String> {
+    url_list("TURN_URLS").unwrap_or_default()
+}
+
+pub(crate) fn turn_secret() -> Option<String> {
+    std::env::var("TURN_SECRET").ok().filter(|s| !s.trim().is_empty())
+}
+
+fn credential_ttl_secs() -> i64 {
+    std::env::var("TURN_CREDENTIAL_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|ttl| *ttl > 0)
+        .unwrap_or(DEFAULT_CREDENTIAL_TTL_SECS)
+}
+
+/// Problems with the TURN settings, for `doctor`. Empty when TURN is not
+/// configured at all (STUN only is a valid setup).
+pub(crate) fn turn_config_problems() -> Vec<String> {
+    let urls = turn_urls();
+    let mut problems = Vec::new();
+    if !urls.is_empty() && turn_secret().is_none() {
+        problems.push("TURN_URLS is set but TURN_SECRET is missing, no relay will be offered".to_string());
+    }
+    if urls.is_empty() && turn_secret().is_some() {
+        problems.push("TURN_SECRET is set but TURN_URLS is empty".to_string());
+    }
+    for url in urls.iter().filter(|u| !u.starts_with("turn:") && !u.starts_with("turns:")) {
+        problems.push(format!("TURN_URLS entry '{url}' does not start with turn: or turns:"));
+    }
+    problems
+}
+
+// ── Credentials ─────────────────────────────────────────
+
+/// Username and password for `user_id`, valid until `expires_at` (unix time).
+fn turn_credentials(secret: &str, user_id: &str, expires_at: i64) -> (String, String) {
+    let username = format!("{expires_at}:{user_id}");
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(username.as_bytes());
+    let credential = general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+    (username, credential)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// Same shape as `RTCIceServer`, so clients can pass the list as is.
+#[derive(Debug, Serialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+/// GET /api/voice/rtc-config — ICE servers for voice rooms, with TURN credentials for the caller
+pub async fn rtc_config(req: HttpRequest) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let mut ice_servers = Vec::new();
+    let stun = stun_urls();
+    if !stun.is_empty() {
+        ice_servers.push(IceServer { urls: stun, username: None, credential: None });
+    }
+
+    let turn = turn_urls();
+    let mut expires_at = None;
+    if let Some(secret) = turn_secret().filter(|_| !turn.is_empty()) {
+        let ttl = credential_ttl_secs();
+        let expiry = chrono::Utc::now().timestamp() + ttl;
+        let (username, credential) = turn_credentials(&secret, &claims.sub, expiry);
+        ice_servers.push(IceServer { urls: turn, username: Some(username), credential: Some(credential) });
+        expires_at = Some(expiry);
+    }
+
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-store"))
+        .json(serde_json::json!({
+            "iceServers": ice_servers,
+            "turn": expires_at.is_some(),
+            "expires_at": expires_at,
+        }))
+}
@@ -0,0 +1,354 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Server rules and their acknowledgment
+// ═══════════════════════════════════════════════════════
+//
+// The rules are a list of sections (`{ title, body }`). Every edit publishes
+// a new version; older ones stay readable. A version published with
+// `requires_acknowledgment` must be acknowledged before posting: a member
+// whose latest acknowledged version is older than the latest such version
+// has their messages and polls refused until they acknowledge the current
+// rules. Versions without the flag (typo fixes) never lock anyone out.
+//
+// Administrators, who write the rules, and guests, who cannot reach these
+// routes, are never refused. A refused WebSocket message gets a
+// `rules_acknowledgment_required` event on the sending connection; HTTP
+// endpoints that post answer 403 with the version to acknowledge.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+const MAX_SECTIONS: usize = 50;
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_BODY_CHARS: usize = 4000;
+/// Members listed by the stats endpoint.
+const PENDING_LIST_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleSection {
+    pub title: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Rules {
+    pub version: i64,
+    pub sections: Vec<RuleSection>,
+    pub requires_acknowledgment: bool,
+    pub published_by_username: String,
+    pub published_at: String,
+}
+
+const COLUMNS: &str = "version, sections, requires_acknowledgment, published_by_username, published_at";
+
+impl Rules {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let sections: String = row.get("sections");
+        Self {
+            version: row.get("version"),
+            sections: serde_json::from_str(&sections).unwrap_or_default(),
+            requires_acknowledgment: row.get::<i64, _>("requires_acknowledgment") != 0,
+            published_by_username: row.get("published_by_username"),
+            published_at: row.get("published_at"),
+        }
+    }
+}
+
+async fn fetch(pool: &SqlitePool, version: Option<i64>) -> Result<Option<Rules>, sqlx::Error> {
+    let sql = format!("SELECT {COLUMNS} FROM server_rules WHERE ? IS NULL OR version = ? ORDER BY version DESC LIMIT 1");
+    let row = sqlx::query(&sql).bind(version).bind(version).fetch_optional(pool).await?;
+    Ok(row.as_ref().map(Rules::from_row))
+}
+
+/// The latest version `user_id` acknowledged.
+async fn acknowledged_version(pool: &SqlitePool, user_id: &str) -> Option<i64> {
+    sqlx::query_scalar("SELECT MAX(version) FROM rules_acknowledgments WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+// ── Enforcement ─────────────────────────────────────────
+
+/// A refused post: the rules version to acknowledge first.
+#[derive(Debug, Clone, Copy)]
+pub struct Unacknowledged {
+    pub version: i64,
+}
+
+impl Unacknowledged {
+    /// The structured 403 for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Acknowledge the server rules before posting",
+            "rules_version": self.version,
+        }))
+    }
+
+    /// The `rules_acknowledgment_required` event for the WebSocket connection that sent the message.
+    pub fn event(&self, room_id: &str) -> String {
+        serde_json::json!({
+            "type": "rules_acknowledgment_required",
+            "room_id": room_id,
+            "rules_version": self.version,
+        })
+        .to_string()
+    }
+}
+
+/// Whether `user_id` may post under the current rules, or which version
+/// they have to acknowledge first.
+pub(crate) async fn check(pool: &SqlitePool, user_id: &str, role: &str) -> Result<(), Unacknowledged> {
+    if role == crate::guests::GUEST_ROLE
+        || crate::permissions::role_permissions(pool, role).await & crate::permissions::ADMINISTRATOR != 0
+    {
+        return Ok(());
+    }
+    // The current version, when a version after the member's last
+    // acknowledgment requires one; NULL otherwise.
+    let pending: Option<i64> = sqlx::query_scalar(
+        "SELECT MAX(version) FROM server_rules WHERE EXISTS ( \
+             SELECT 1 FROM server_rules WHERE requires_acknowledgment = 1 AND version > \
+                 COALESCE((SELECT MAX(version) FROM rules_acknowledgments WHERE user_id = ?), 0))"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    // A failed read should not silence the server.
+    .unwrap_or(None);
+    match pending {
+        Some(version) => Err(Unacknowledged { version }),
+        None => Ok(()),
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/rules — The current rules, and whether the caller must acknowledge them
+pub async fn get_current(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let rules = match fetch(pool.get_ref(), None).await {
+        Ok(rules) => rules,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let must_acknowledge = check(pool.get_ref(), &claims.sub, &claims.role).await.is_err();
+    HttpResponse::Ok().json(serde_json::json!({
+        "rules": rules,
+        "acknowledged_version": acknowledged_version(pool.get_ref(), &claims.sub).await,
+        "must_acknowledge": must_acknowledge,
+    }))
+}
+
+/// GET /api/rules/versions/{version} — A past version of the rules
+pub async fn get_version(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<i64>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match fetch(pool.get_ref(), Some(path.into_inner())).await {
+        Ok(Some(rules)) => HttpResponse::Ok().json(rules),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Rules version not found" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcknowledgeInput {
+    pub version: i64,
+}
+
+/// POST /api/rules/acknowledge — Acknowledge the current rules, on all the caller's devices
+/// Body: { version }
+pub async fn acknowledge(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<AcknowledgeInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let current = match fetch(pool.get_ref(), None).await {
+        Ok(Some(rules)) => rules.version,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "No rules to acknowledge" })),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Only what the member read counts: a newer version needs reading first.
+    if body.version != current {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The rules have changed, read the current version",
+            "version": current,
+        }));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let result = sqlx::query("INSERT OR IGNORE INTO rules_acknowledgments (user_id, version, acknowledged_at) VALUES (?, ?, ?)")
+        .bind(&claims.sub)
+        .bind(current)
+        .bind(&now)
+        .execute(pool.get_ref())
+        .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let event = serde_json::json!({ "type": "rules_acknowledged", "version": current, "recipient_id": claims.sub });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(serde_json::json!({ "version": current, "acknowledged_at": now }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishInput {
+    pub sections: Vec<RuleSection>,
+    /// Refuse posts until members acknowledge this version. Defaults to false.
+    pub requires_acknowledgment: Option<bool>,
+}
+
+/// PUT /api/server/rules — Publish a new version of the rules (MANAGE_SERVER)
+/// Body: { sections: [{ title, body }], requires_acknowledgment? }
+pub async fn publish(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<PublishInput>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let body = body.into_inner();
+    if body.sections.is_empty() || body.sections.len() > MAX_SECTIONS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("The rules need 1 to {MAX_SECTIONS} sections")
+        }));
+    }
+    let sections: Vec<RuleSection> = body
+        .sections
+        .iter()
+        .map(|s| RuleSection { title: s.title.trim().to_string(), body: s.body.trim().to_string() })
+        .collect();
+    let invalid = sections.iter().any(|s| {
+        s.title.is_empty()
+            || s.title.chars().count() > MAX_TITLE_CHARS
+            || s.body.is_empty()
+            || s.body.chars().count() > MAX_BODY_CHARS
+    });
+    if invalid {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Section titles must be 1 to {MAX_TITLE_CHARS} characters, bodies 1 to {MAX_BODY_CHARS}")
+        }));
+    }
+
+    let sections_json = serde_json::to_string(&sections).unwrap_or_else(|_| "[]".to_string());
+    // Two publishes at once collide on the primary key instead of sharing a version.
+    let result = sqlx::query(
+        "INSERT INTO server_rules (version, sections, requires_acknowledgment, published_by, published_by_username, published_at) \
+         SELECT COALESCE(MAX(version), 0) + 1, ?, ?, ?, ?, ? FROM server_rules"
+    )
+    .bind(&sections_json)
+    .bind(body.requires_acknowledgment.unwrap_or(false) as i64)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "The rules were just published, try again" }));
+    }
+
+    let rules = match fetch(pool.get_ref(), None).await {
+        Ok(Some(rules)) => rules,
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+    let event = serde_json::json!({ "type": "rules_updated", "rules": rules });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Created().json(rules)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Defaults to the current version.
+    pub version: Option<i64>,
+}
+
+/// GET /api/server/rules/stats?version= — Who has and hasn't acknowledged a version (MANAGE_SERVER)
+pub async fn stats(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<StatsQuery>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let rules = match fetch(pool.get_ref(), query.version).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({ "error": "Rules version not found" })),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Acknowledging a later version covers this one.
+    let counts = sqlx::query(
+        "SELECT COUNT(*) AS members, \
+                COALESCE(SUM(EXISTS(SELECT 1 FROM rules_acknowledgments a WHERE a.user_id = u.id AND a.version >= ?)), 0) AS acknowledged \
+         FROM users u WHERE u.role != ?"
+    )
+    .bind(rules.version)
+    .bind(crate::guests::GUEST_ROLE)
+    .fetch_one(pool.get_ref())
+    .await;
+    let (members, acknowledged): (i64, i64) = match counts {
+        Ok(row) => (row.get("members"), row.get("acknowledged")),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let pending = sqlx::query(
+        "SELECT u.id, u.username, (SELECT MAX(a.version) FROM rules_acknowledgments a WHERE a.user_id = u.id) AS acknowledged_version \
+         FROM users u \
+         WHERE u.role != ? AND NOT EXISTS (SELECT 1 FROM rules_acknowledgments a WHERE a.user_id = u.id AND a.version >= ?) \
+         ORDER BY u.username LIMIT ?"
+    )
+    .bind(crate::guests::GUEST_ROLE)
+    .bind(rules.version)
+    .bind(PENDING_LIST_LIMIT)
+    .fetch_all(pool.get_ref())
+    .await;
+    let pending_members: Vec<serde_json::Value> = match pending {
+        Ok(rows) => rows
+            .iter()
+            .map(|row| {
+                serde_json::json!({
+                    "user_id": row.get::<String, _>("id"),
+                    "username": row.get::<String, _>("username"),
+                    "acknowledged_version": row.get::<Option<i64>, _>("acknowledged_version"),
+                })
+            })
+            .collect(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "version": rules.version,
+        "requires_acknowledgment": rules.requires_acknowledgment,
+        "members": members,
+        "acknowledged": acknowledged,
+        "pending": members - acknowledged,
+        "pending_members": pending_members,
+    }))
+}
@@ -0,0 +1,377 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Upload scanning
+// ═══════════════════════════════════════════════════════
+//
+// With UPLOAD_SCANNER set, every attachment upload (`POST /api/files`,
+// `POST /api/files/voice`) is scanned once its type checked out, before it
+// becomes an attachment, and so is every other upload the server keeps
+// (avatars and banners, custom emoji, role icons, soundboard clips, voice
+// recording segments):
+//   clamd   streamed to clamd over TCP (`zINSTREAM`) at CLAMD_ADDRESS
+//   http    POSTed raw to UPLOAD_SCANNER_URL, with the sniffed
+//           `Content-Type` and `X-Voxium-Sha256`; the scanner answers
+//           `{ "clean": bool, "signature"? }`
+//
+// The verdict is recorded on the attachment (`scan_status`,
+// `scan_signature`, `scanned_at`). A flagged file is kept but quarantined:
+// the upload answers 422 with the attachment's id, and the attachment can't
+// be sent or downloaded, nor is it purged as unsent. Admins
+// (`MANAGE_SERVER`) list the quarantine and release an upload (a false
+// positive: the uploader can send it again for `UNLINKED_TTL_HOURS`) or
+// delete it. Both are audited. Other uploads have no quarantine: a flagged
+// one is refused with 422 and never stored.
+//
+// When the scanner can't be reached, times out or answers anything else,
+// the upload is refused with 503, unless UPLOAD_SCANNER_FAIL_OPEN=true:
+// then it goes through, marked `unscanned`. An UPLOAD_SCANNER this build
+// doesn't know counts as a scanner that can't be reached.
+//
+// Config (env): UPLOAD_SCANNER (clamd | http), CLAMD_ADDRESS (default
+// 127.0.0.1:3310), UPLOAD_SCANNER_URL, UPLOAD_SCANNER_TIMEOUT_SECS
+// (default 30), UPLOAD_SCANNER_FAIL_OPEN
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::audit_log::Action;
+use crate::auth::extract_claims;
+
+const DEFAULT_CLAMD_ADDRESS: &str = "127.0.0.1:3310";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// INSTREAM data goes to clamd in chunks of at most this many bytes.
+const CHUNK_BYTES: usize = 64 * 1024;
+/// Longest clamd reply read.
+const MAX_REPLY_BYTES: u64 = 4096;
+const MAX_SIGNATURE_CHARS: usize = 200;
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+enum Scanner {
+    Clamd(String),
+    Http(String),
+}
+
+/// The configured scanner; `Err` when UPLOAD_SCANNER is set but unusable.
+fn scanner() -> Option<Result<Scanner, String>> {
+    let kind = env("UPLOAD_SCANNER")?;
+    Some(match kind.to_ascii_lowercase().as_str() {
+        "clamd" => Ok(Scanner::Clamd(env("CLAMD_ADDRESS").unwrap_or_else(|| DEFAULT_CLAMD_ADDRESS.to_string()))),
+        "http" => env("UPLOAD_SCANNER_URL").map(Scanner::Http).ok_or_else(|| "UPLOAD_SCANNER_URL is not set".to_string()),
+        other => Err(format!("unknown UPLOAD_SCANNER {other:?}")),
+    })
+}
+
+/// What a scanner made of an upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Clean,
+    Infected(String),
+}
+
+/// The content to scan: a spooled upload, or one read into memory.
+pub(crate) enum Upload<'a> {
+    File(&'a Path),
+    Bytes(&'a [u8]),
+}
+
+/// The verdict recorded on an attachment.
+#[derive(Debug, Clone)]
+pub(crate) struct ScanResult {
+    pub status: &'static str,
+    pub signature: Option<String>,
+    pub scanned_at: String,
+}
+
+impl ScanResult {
+    pub fn is_quarantined(&self) -> bool {
+        self.status == "quarantined"
+    }
+}
+
+fn signature(raw: &str) -> String {
+    raw.trim().chars().take(MAX_SIGNATURE_CHARS).collect()
+}
+
+/// clamd's answer to INSTREAM: `stream: OK`, `stream: <signature> FOUND`,
+/// anything else being an error (`... ERROR`).
+pub fn parse_clamd_reply(reply: &str) -> Result<Verdict, String> {
+    let reply = reply.trim_end_matches(['\0', '\n']).trim();
+    let result = reply.strip_prefix("stream:").map(str::trim).unwrap_or(reply);
+    if result == "OK" {
+        return Ok(Verdict::Clean);
+    }
+    match result.strip_suffix(" FOUND") {
+        Some(found) => Ok(Verdict::Infected(signature(found))),
+        None => Err(format!("clamd answered {reply:?}")),
+    }
+}
+
+async fn send_chunk(stream: &mut tokio::net::TcpStream, chunk: &[u8]) -> Result<(), String> {
+    stream.write_all(&(chunk.len() as u32).to_be_bytes()).await.map_err(|e| e.to_string())?;
+    stream.write_all(chunk).await.map_err(|e| e.to_string())
+}
+
+async fn scan_clamd(address: &str, upload: &Upload<'_>) -> Result<Verdict, String> {
+    let mut stream = tokio::net::TcpStream::connect(address)
+        .await
+        .map_err(|e| format!("cannot reach clamd at {address}: {e}"))?;
+    stream.write_all(b"zINSTREAM\0").await.map_err(|e| e.to_string())?;
+    match upload {
+        Upload::File(path) => {
+            let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+            let mut buffer = vec![0u8; CHUNK_BYTES];
+            loop {
+                let read = file.read(&mut buffer).await.map_err(|e| e.to_string())?;
+                if read == 0 {
+                    break;
+                }
+                send_chunk(&mut stream, &buffer[..read]).await?;
+            }
+        }
+        Upload::Bytes(bytes) => {
+            for chunk in bytes.chunks(CHUNK_BYTES) {
+                send_chunk(&mut stream, chunk).await?;
+            }
+        }
+    }
+    stream.write_all(&0u32.to_be_bytes()).await.map_err(|e| e.to_string())?;
+    let mut reply = Vec::new();
+    (&mut stream).take(MAX_REPLY_BYTES).read_to_end(&mut reply).await.map_err(|e| e.to_string())?;
+    parse_clamd_reply(&String::from_utf8_lossy(&reply))
+}
+
+async fn scan_http(url: &str, upload: &Upload<'_>, content_type: &str, hash: &str, timeout: Duration) -> Result<Verdict, String> {
+    let body = match upload {
+        Upload::File(path) => reqwest::Body::from(tokio::fs::File::open(path).await.map_err(|e| e.to_string())?),
+        Upload::Bytes(bytes) => reqwest::Body::from(bytes.to_vec()),
+    };
+    let response = reqwest::Client::new()
+        .post(url)
+        .timeout(timeout)
+        .header("Content-Type", content_type)
+        .header("X-Voxium-Sha256", hash)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("scanner answered {}", response.status()));
+    }
+    let verdict: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+    match verdict.get("clean").and_then(serde_json::Value::as_bool) {
+        Some(true) => Ok(Verdict::Clean),
+        Some(false) => Ok(Verdict::Infected(
+            verdict.get("signature").and_then(serde_json::Value::as_str).map(signature).unwrap_or_else(|| "unknown".to_string()),
+        )),
+        None => Err("scanner answered without `clean`".to_string()),
+    }
+}
+
+/// Scan an upload of `content_type` hashing to `hash`. `Ok(None)` without a
+/// scanner; `Err` with the response when the upload must be refused.
+pub(crate) async fn scan_upload(upload: Upload<'_>, content_type: &str, hash: &str) -> Result<Option<ScanResult>, HttpResponse> {
+    let Some(scanner) = scanner() else {
+        return Ok(None);
+    };
+    let timeout = Duration::from_secs(
+        env("UPLOAD_SCANNER_TIMEOUT_SECS")
+            .and_then(|v| v.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_TIMEOUT_SECS),
+    );
+    let verdict = match scanner {
+        Ok(Scanner::Clamd(address)) => tokio::time::timeout(timeout, scan_clamd(&address, &upload))
+            .await
+            .unwrap_or_else(|_| Err("clamd timed out".to_string())),
+        Ok(Scanner::Http(url)) => scan_http(&url, &upload, content_type, hash, timeout).await,
+        Err(e) => Err(e),
+    };
+    let scanned_at = Utc::now().to_rfc3339();
+    match verdict {
+        Ok(Verdict::Clean) => Ok(Some(ScanResult { status: "clean", signature: None, scanned_at })),
+        Ok(Verdict::Infected(signature)) => {
+            println!("🦠 Upload {hash} flagged by the scanner: {signature}");
+            Ok(Some(ScanResult { status: "quarantined", signature: Some(signature), scanned_at }))
+        }
+        Err(e) if env("UPLOAD_SCANNER_FAIL_OPEN").is_some_and(|v| matches!(v.as_str(), "1" | "true" | "yes")) => {
+            eprintln!("⚠️  Upload scan failed, accepting the upload unscanned: {e}");
+            Ok(Some(ScanResult { status: "unscanned", signature: None, scanned_at }))
+        }
+        Err(e) => {
+            eprintln!("⚠️  Upload scan failed: {e}");
+            Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": "Upload scanning is unavailable, try again later"
+            })))
+        }
+    }
+}
+
+/// Scan an upload that doesn't become an attachment (an avatar, an emoji, a
+/// soundboard clip, a recording segment...). There is no quarantine to hold
+/// those: a flagged one is refused with 422 and never stored.
+pub(crate) async fn refuse_flagged(bytes: &[u8]) -> Result<(), HttpResponse> {
+    let content_type = crate::files::sniff_content_type(bytes).unwrap_or("application/octet-stream");
+    let scan = scan_upload(Upload::Bytes(bytes), content_type, &crate::files::sha256_hex(bytes)).await?;
+    match scan {
+        Some(scan) if scan.is_quarantined() => Err(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "This file was flagged by the upload scanner",
+            "signature": scan.signature,
+        }))),
+        _ => Ok(()),
+    }
+}
+
+// ── Review ──────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct QuarantinedUpload {
+    pub id: String,
+    pub filename: String,
+    pub kind: String,
+    pub size: i64,
+    pub content_type: String,
+    pub uploader_id: String,
+    pub uploader_username: Option<String>,
+    pub scan_signature: Option<String>,
+    pub scanned_at: Option<String>,
+    pub created_at: String,
+}
+
+const QUARANTINE_SELECT: &str = "SELECT a.id, a.filename, a.kind, f.size, f.content_type, a.uploader_id, u.username AS uploader_username, \
+     a.scan_signature, a.scanned_at, a.created_at \
+     FROM attachments a JOIN files f ON f.hash = a.file_hash LEFT JOIN users u ON u.id = a.uploader_id \
+     WHERE a.scan_status = 'quarantined'";
+
+fn quarantined_from_row(row: &sqlx::sqlite::SqliteRow) -> QuarantinedUpload {
+    QuarantinedUpload {
+        id: row.get("id"),
+        filename: row.get("filename"),
+        kind: row.get("kind"),
+        size: row.get("size"),
+        content_type: row.get("content_type"),
+        uploader_id: row.get("uploader_id"),
+        uploader_username: row.get("uploader_username"),
+        scan_signature: row.get("scan_signature"),
+        scanned_at: row.get("scanned_at"),
+        created_at: row.get("created_at"),
+    }
+}
+
+async fn fetch_quarantined(pool: &SqlitePool, id: &str) -> Option<QuarantinedUpload> {
+    let sql = format!("{QUARANTINE_SELECT} AND a.id = ?");
+    sqlx::query(&sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .map(|row| quarantined_from_row(&row))
+}
+
+fn not_quarantined() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({ "error": "No such quarantined upload" }))
+}
+
+/// GET /api/server/quarantine — Uploads the scanner flagged, newest first (MANAGE_SERVER)
+pub async fn list_quarantine(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let sql = format!("{QUARANTINE_SELECT} ORDER BY a.created_at DESC, a.id");
+    match sqlx::query(&sql).fetch_all(pool.get_ref()).await {
+        Ok(rows) => {
+            let uploads: Vec<QuarantinedUpload> = rows.iter().map(quarantined_from_row).collect();
+            HttpResponse::Ok().json(serde_json::json!({ "uploads": uploads }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/server/quarantine/{id}/release — Release a flagged upload to its uploader (MANAGE_SERVER)
+pub async fn release_upload(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+    let id = path.into_inner();
+    let Some(upload) = fetch_quarantined(pool.get_ref(), &id).await else {
+        return not_quarantined();
+    };
+
+    // A fresh `created_at` gives the uploader the usual time to send it.
+    let now = Utc::now().to_rfc3339();
+    let released = sqlx::query(
+        "UPDATE attachments SET scan_status = 'released', reviewed_by = ?, reviewed_at = ?, created_at = ? \
+         WHERE id = ? AND scan_status = 'quarantined'"
+    )
+    .bind(&claims.sub)
+    .bind(&now)
+    .bind(&now)
+    .bind(&id)
+    .execute(pool.get_ref())
+    .await;
+    match released {
+        Ok(r) if r.rows_affected() == 0 => return not_quarantined(),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let changes = crate::audit_log::diff(&[("scan_status", serde_json::json!("quarantined"), serde_json::json!("released"))]);
+    let reason = crate::audit_log::reason(&req);
+    crate::audit_log::record(pool.get_ref(), Some(&claims), Action::UploadReleased, &id, changes, reason.as_deref()).await;
+
+    let expires_at = (Utc::now() + chrono::Duration::hours(crate::files::UNLINKED_TTL_HOURS)).to_rfc3339();
+    HttpResponse::Ok().json(serde_json::json!({
+        "id": upload.id,
+        "filename": upload.filename,
+        "uploader_id": upload.uploader_id,
+        "scan_status": "released",
+        "scan_signature": upload.scan_signature,
+        "expires_at": expires_at,
+    }))
+}
+
+/// DELETE /api/server/quarantine/{id} — Delete a flagged upload (MANAGE_SERVER)
+pub async fn delete_upload(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+    let id = path.into_inner();
+    let Some(upload) = fetch_quarantined(pool.get_ref(), &id).await else {
+        return not_quarantined();
+    };
+
+    // The stored file goes with the next attachments purge, unless another
+    // attachment uses the same content.
+    match sqlx::query("DELETE FROM attachments WHERE id = ? AND scan_status = 'quarantined'")
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => return not_quarantined(),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let reason = crate::audit_log::reason(&req);
+    crate::audit_log::record(pool.get_ref(), Some(&claims), Action::UploadDeleted, &id, serde_json::json!(upload), reason.as_deref()).await;
+    HttpResponse::NoContent().finish()
+}
@@ -0,0 +1,775 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Declarative server config (YAML export/apply)
+// ═══════════════════════════════════════════════════════
+//
+// Lets admins keep the server layout (roles, rooms and their permissions)
+// in a YAML file under version control. `export` dumps the current state,
+// `plan` diffs a file against the DB without touching anything and `apply`
+// runs that same plan in one transaction, so applying a file twice is a
+// no-op the second time.
+//
+// Roles are matched by name, rooms by name too: renaming a room in the file
+// shows up as "create" (plus "delete" with `?prune=true`). Things present in
+// the DB but absent from the file are only deleted when pruning. A section
+// left out of the file entirely (e.g. no `rooms:` key) is not managed.
+//
+// `bridges.matrix` links rooms (by name) to Matrix rooms for the Matrix
+// bridge, see `matrix`. The bridge's homeserver and tokens are secrets and
+// stay in the environment.
+//
+// The export carries an ETag derived from its content. Sending it back as
+// `If-Match` on plan/apply turns a file edited from a stale export into a
+// 412 instead of silently reverting someone else's changes.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::concurrency::{etag, if_match, precondition_failed};
+use crate::rooms::{normalize_metadata, Room, MAX_GUIDELINES_CHARS, MAX_TOPIC_CHARS, ROOM_COLUMNS};
+use crate::ws::{
+    cache_clear_role_permissions, cache_clear_room_overwrites, cache_clear_user_roles, cache_remove_room,
+    cache_set_room_required_role, AccessCache, Broadcaster,
+};
+use crate::sudo::Sudo;
+
+/// Schema version written by `export` and required by `plan`/`apply`.
+const CONFIG_VERSION: u32 = 1;
+const PROTECTED_ROLES: [&str; 2] = ["admin", "user"];
+const DEFAULT_ROLE_COLOR: &str = "#99aab5";
+
+// ── File format ─────────────────────────────────────────
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<RoleConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rooms: Option<Vec<RoomConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bridges: Option<BridgesConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoleConfig {
+    pub name: String,
+    #[serde(default = "default_role_color")]
+    pub color: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoomConfig {
+    pub name: String,
+    #[serde(default = "default_room_kind")]
+    pub kind: String,
+    #[serde(default = "default_required_role")]
+    pub required_role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guidelines: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BridgesConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<Vec<MatrixLinkConfig>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixLinkConfig {
+    /// A text room, by name.
+    pub room: String,
+    /// `!opaque:server.name`
+    pub matrix_room_id: String,
+}
+
+fn default_role_color() -> String {
+    DEFAULT_ROLE_COLOR.to_string()
+}
+
+fn default_room_kind() -> String {
+    "text".to_string()
+}
+
+fn default_required_role() -> String {
+    "user".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigApplyQuery {
+    /// Delete roles/rooms that exist on the server but not in the file.
+    #[serde(default)]
+    pub prune: bool,
+}
+
+// ── Plan ────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    Role,
+    Room,
+    MatrixLink,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedChange {
+    pub action: ChangeAction,
+    pub resource: ResourceKind,
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldDiff>,
+}
+
+/// Current roles, rooms and bridge links, as read from the DB.
+struct ServerState {
+    roles: Vec<RoleConfig>,
+    rooms: Vec<Room>,
+    matrix_links: Vec<MatrixLinkConfig>,
+}
+
+async fn load_state(pool: &SqlitePool) -> Result<ServerState, sqlx::Error> {
+    let roles = sqlx::query(
+        "SELECT name, color FROM roles ORDER BY CASE WHEN name='admin' THEN 0 WHEN name='user' THEN 1 ELSE 2 END, name ASC"
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| RoleConfig {
+        name: row.get("name"),
+        color: row.get("color"),
+    })
+    .collect();
+
+    let rooms = sqlx::query_as::<_, Room>(&format!("SELECT {ROOM_COLUMNS} FROM rooms ORDER BY created_at"))
+        .fetch_all(pool)
+        .await?;
+
+    let matrix_links = sqlx::query_as::<_, (String, String)>(
+        "SELECT r.name, l.matrix_room_id FROM matrix_room_links l JOIN rooms r ON r.id = l.room_id ORDER BY r.created_at"
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(room, matrix_room_id)| MatrixLinkConfig { room, matrix_room_id })
+    .collect();
+
+    Ok(ServerState { roles, rooms, matrix_links })
+}
+
+/// Parse the file and normalize names/values the same way the REST handlers do.
+fn parse_config(raw: &str) -> Result<ServerConfig, String> {
+    let mut config: ServerConfig =
+        serde_yaml::from_str(raw).map_err(|e| format!("Invalid YAML: {e}"))?;
+
+    if config.version != CONFIG_VERSION {
+        return Err(format!("Unsupported config version {} (expected {CONFIG_VERSION})", config.version));
+    }
+
+    if let Some(roles) = config.roles.as_mut() {
+        let mut seen = HashSet::new();
+        for role in roles.iter_mut() {
+            role.name = role.name.trim().to_lowercase();
+            role.color = role.color.trim().to_string();
+            if role.name.len() < 2 || role.name.len() > 24 {
+                return Err(format!("Role name '{}' must be 2 to 24 chars", role.name));
+            }
+            if !role.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(format!("Role name '{}' can only contain a-z, 0-9, _ and -", role.name));
+            }
+            if role.color.len() != 7
+                || !role.color.starts_with('#')
+                || !role.color.chars().skip(1).all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(format!("Invalid color for role '{}' (expected #RRGGBB)", role.name));
+            }
+            if !seen.insert(role.name.clone()) {
+                return Err(format!("Role '{}' is listed twice", role.name));
+            }
+        }
+    }
+
+    if let Some(rooms) = config.rooms.as_mut() {
+        let mut seen = HashSet::new();
+        for room in rooms.iter_mut() {
+            room.name = room.name.trim().to_string();
+            room.kind = room.kind.trim().to_lowercase();
+            room.required_role = room.required_role.trim().to_lowercase();
+            if room.name.is_empty() {
+                return Err("Room name is required".to_string());
+            }
+            if room.kind != "text" && room.kind != "voice" {
+                return Err(format!("Room '{}': kind must be text or voice", room.name));
+            }
+            room.topic = match room.topic.as_deref() {
+                Some(raw) => normalize_metadata("topic", raw, MAX_TOPIC_CHARS)
+                    .map_err(|e| format!("Room '{}': {e}", room.name))?,
+                None => None,
+            };
+            room.guidelines = match room.guidelines.as_deref() {
+                Some(raw) => normalize_metadata("guidelines", raw, MAX_GUIDELINES_CHARS)
+                    .map_err(|e| format!("Room '{}': {e}", room.name))?,
+                None => None,
+            };
+            if !seen.insert(room.name.clone()) {
+                return Err(format!("Room '{}' is listed twice", room.name));
+            }
+        }
+    }
+
+    if let Some(links) = config.bridges.as_mut().and_then(|b| b.matrix.as_mut()) {
+        let mut seen_rooms = HashSet::new();
+        let mut seen_matrix_rooms = HashSet::new();
+        for link in links.iter_mut() {
+            link.room = link.room.trim().to_string();
+            link.matrix_room_id = link.matrix_room_id.trim().to_string();
+            if !crate::matrix::is_room_id(&link.matrix_room_id) {
+                return Err(format!("Matrix link of '{}': matrix_room_id must look like !id:server", link.room));
+            }
+            if !seen_rooms.insert(link.room.clone()) {
+                return Err(format!("Room '{}' is linked to Matrix twice", link.room));
+            }
+            if !seen_matrix_rooms.insert(link.matrix_room_id.clone()) {
+                return Err(format!("Matrix room '{}' is linked twice", link.matrix_room_id));
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+fn diff_field(fields: &mut Vec<FieldDiff>, field: &'static str, from: Option<&str>, to: Option<&str>) {
+    if from != to {
+        fields.push(FieldDiff {
+            field,
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+        });
+    }
+}
+
+/// Compute the changes needed to go from `state` to `config`.
+fn build_plan(state: &ServerState, config: &ServerConfig, prune: bool) -> Result<Vec<PlannedChange>, String> {
+    let mut changes = Vec::new();
+
+    // Roles that will exist once the plan has run.
+    let mut final_roles: HashSet<String> = state.roles.iter().map(|r| r.name.clone()).collect();
+
+    if let Some(roles) = &config.roles {
+        let current: HashMap<&str, &RoleConfig> =
+            state.roles.iter().map(|r| (r.name.as_str(), r)).collect();
+
+        for role in roles {
+            match current.get(role.name.as_str()) {
+                None => {
+                    final_roles.insert(role.name.clone());
+                    changes.push(PlannedChange {
+                        action: ChangeAction::Create,
+                        resource: ResourceKind::Role,
+                        name: role.name.clone(),
+                        fields: vec![FieldDiff { field: "color", from: None, to: Some(role.color.clone()) }],
+                    });
+                }
+                Some(existing) => {
+                    let mut fields = Vec::new();
+                    diff_field(&mut fields, "color", Some(&existing.color), Some(&role.color));
+                    if !fields.is_empty() {
+                        changes.push(PlannedChange {
+                            action: ChangeAction::Update,
+                            resource: ResourceKind::Role,
+                            name: role.name.clone(),
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+
+        if prune {
+            let wanted: HashSet<&str> = roles.iter().map(|r| r.name.as_str()).collect();
+            for existing in &state.roles {
+                if wanted.contains(existing.name.as_str()) || PROTECTED_ROLES.contains(&existing.name.as_str()) {
+                    continue;
+                }
+                final_roles.remove(&existing.name);
+                changes.push(PlannedChange {
+                    action: ChangeAction::Delete,
+                    resource: ResourceKind::Role,
+                    name: existing.name.clone(),
+                    fields: Vec::new(),
+                });
+            }
+        }
+    }
+
+    // Rooms left alone by the plan must not point at a pruned role.
+    let mut kept_rooms: Vec<&Room> = state.rooms.iter().collect();
+
+    if let Some(rooms) = &config.rooms {
+        let current: HashMap<&str, &Room> =
+            state.rooms.iter().map(|r| (r.name.as_str(), r)).collect();
+        let wanted: HashSet<&str> = rooms.iter().map(|r| r.name.as_str()).collect();
+        kept_rooms.retain(|r| !wanted.contains(r.name.as_str()) && !prune);
+
+        for room in rooms {
+            if !final_roles.contains(&room.required_role) {
+                return Err(format!("Room '{}' requires unknown role '{}'", room.name, room.required_role));
+            }
+
+            match current.get(room.name.as_str()) {
+                None => {
+                    let mut fields = Vec::new();
+                    diff_field(&mut fields, "kind", None, Some(&room.kind));
+                    diff_field(&mut fields, "required_role", None, Some(&room.required_role));
+                    diff_field(&mut fields, "topic", None, room.topic.as_deref());
+                    diff_field(&mut fields, "guidelines", None, room.guidelines.as_deref());
+                    changes.push(PlannedChange {
+                        action: ChangeAction::Create,
+                        resource: ResourceKind::Room,
+                        name: room.name.clone(),
+                        fields,
+                    });
+                }
+                Some(existing) => {
+                    let mut fields = Vec::new();
+                    diff_field(&mut fields, "kind", Some(&existing.kind), Some(&room.kind));
+                    diff_field(&mut fields, "required_role", Some(&existing.required_role), Some(&room.required_role));
+                    diff_field(&mut fields, "topic", existing.topic.as_deref(), room.topic.as_deref());
+                    diff_field(&mut fields, "guidelines", existing.guidelines.as_deref(), room.guidelines.as_deref());
+                    if !fields.is_empty() {
+                        changes.push(PlannedChange {
+                            action: ChangeAction::Update,
+                            resource: ResourceKind::Room,
+                            name: room.name.clone(),
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+
+        if prune {
+            for existing in &state.rooms {
+                if !wanted.contains(existing.name.as_str()) {
+                    changes.push(PlannedChange {
+                        action: ChangeAction::Delete,
+                        resource: ResourceKind::Room,
+                        name: existing.name.clone(),
+                        fields: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(room) = kept_rooms.iter().find(|r| !final_roles.contains(&r.required_role)) {
+        return Err(format!(
+            "Role '{}' is still required by room '{}'",
+            room.required_role, room.name
+        ));
+    }
+
+    if let Some(links) = config.bridges.as_ref().and_then(|b| b.matrix.as_ref()) {
+        // Text rooms that will exist once the plan has run.
+        let mut final_text_rooms: HashMap<&str, bool> = kept_rooms.iter().map(|r| (r.name.as_str(), r.kind == "text")).collect();
+        final_text_rooms.extend(config.rooms.iter().flatten().map(|r| (r.name.as_str(), r.kind == "text")));
+        let current: HashMap<&str, &MatrixLinkConfig> =
+            state.matrix_links.iter().map(|l| (l.room.as_str(), l)).collect();
+
+        for link in links {
+            match final_text_rooms.get(link.room.as_str()) {
+                None => return Err(format!("Matrix link to unknown room '{}'", link.room)),
+                Some(false) => return Err(format!("Room '{}' is not a text room and can't be linked to Matrix", link.room)),
+                Some(true) => {}
+            }
+            let existing = current.get(link.room.as_str()).map(|l| l.matrix_room_id.as_str());
+            if existing == Some(link.matrix_room_id.as_str()) {
+                continue;
+            }
+            let mut fields = Vec::new();
+            diff_field(&mut fields, "matrix_room_id", existing, Some(&link.matrix_room_id));
+            changes.push(PlannedChange {
+                action: if existing.is_some() { ChangeAction::Update } else { ChangeAction::Create },
+                resource: ResourceKind::MatrixLink,
+                name: link.room.clone(),
+                fields,
+            });
+        }
+
+        if prune {
+            let wanted: HashSet<&str> = links.iter().map(|l| l.room.as_str()).collect();
+            for existing in state.matrix_links.iter().filter(|l| !wanted.contains(l.room.as_str())) {
+                changes.push(PlannedChange {
+                    action: ChangeAction::Delete,
+                    resource: ResourceKind::MatrixLink,
+                    name: existing.room.clone(),
+                    fields: Vec::new(),
+                });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+// ── Apply ───────────────────────────────────────────────
+
+/// Run `changes` in a single transaction. Returns the ids of rooms that were
+/// created/updated (with their required role) and deleted, for cache upkeep.
+async fn execute_plan(
+    pool: &SqlitePool,
+    state: &ServerState,
+    config: &ServerConfig,
+    changes: &[PlannedChange],
+    claims: &Claims,
+) -> Result<(Vec<(String, String)>, Vec<String>), sqlx::Error> {
+    let roles: HashMap<&str, &RoleConfig> = config
+        .roles
+        .iter()
+        .flatten()
+        .map(|r| (r.name.as_str(), r))
+        .collect();
+    let rooms: HashMap<&str, &RoomConfig> = config
+        .rooms
+        .iter()
+        .flatten()
+        .map(|r| (r.name.as_str(), r))
+        .collect();
+    let current_rooms: HashMap<&str, &Room> =
+        state.rooms.iter().map(|r| (r.name.as_str(), r)).collect();
+
+    let mut upserted_rooms = Vec::new();
+    let mut deleted_rooms = Vec::new();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut tx = pool.begin().await?;
+
+    // Roles first so rooms can reference newly created ones, role deletions
+    // last so no room points at a missing role in between.
+    for change in changes.iter().filter(|c| c.resource == ResourceKind::Role && c.action != ChangeAction::Delete) {
+        let role = roles[change.name.as_str()];
+        let query = match change.action {
+            // New roles start with the member defaults.
+            ChangeAction::Create => sqlx::query("INSERT INTO roles (color, name, permissions) VALUES (?, ?, ?)")
+                .bind(&role.color)
+                .bind(&role.name)
+                .bind(crate::permissions::MEMBER as i64),
+            _ => sqlx::query("UPDATE roles SET color = ?, version = version + 1 WHERE name = ?")
+                .bind(&role.color)
+                .bind(&role.name),
+        };
+        query.execute(&mut *tx).await?;
+    }
+
+    for change in changes.iter().filter(|c| c.resource == ResourceKind::Room) {
+        match change.action {
+            ChangeAction::Create => {
+                let room = rooms[change.name.as_str()];
+                let id = Uuid::new_v4().to_string();
+                sqlx::query(
+                    "INSERT INTO rooms (id, name, kind, required_role, topic, guidelines, position) \
+                     VALUES (?, ?, ?, ?, ?, ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM rooms WHERE category_id IS NULL))"
+                )
+                .bind(&id)
+                .bind(&room.name)
+                .bind(&room.kind)
+                .bind(&room.required_role)
+                .bind(&room.topic)
+                .bind(&room.guidelines)
+                .execute(&mut *tx)
+                .await?;
+                upserted_rooms.push((id, room.required_role.clone()));
+            }
+            ChangeAction::Update => {
+                let room = rooms[change.name.as_str()];
+                let existing = current_rooms[change.name.as_str()];
+                sqlx::query(
+                    "UPDATE rooms SET kind = ?, required_role = ?, topic = ?, guidelines = ?, version = version + 1 WHERE id = ?"
+                )
+                .bind(&room.kind)
+                .bind(&room.required_role)
+                .bind(&room.topic)
+                .bind(&room.guidelines)
+                .bind(&existing.id)
+                .execute(&mut *tx)
+                .await?;
+
+                for diff in change.fields.iter().filter(|d| d.field == "topic" || d.field == "guidelines") {
+                    sqlx::query(
+                        "INSERT INTO room_metadata_history (id, room_id, field, old_value, new_value, changed_by, changed_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+                    )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(&existing.id)
+                    .bind(diff.field)
+                    .bind(&diff.from)
+                    .bind(&diff.to)
+                    .bind(&claims.sub)
+                    .bind(&now)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                upserted_rooms.push((existing.id.clone(), room.required_role.clone()));
+            }
+            ChangeAction::Delete => {
+                let existing = current_rooms[change.name.as_str()];
+                sqlx::query("DELETE FROM messages WHERE room_id = ?")
+                    .bind(&existing.id)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("DELETE FROM rooms WHERE id = ?")
+                    .bind(&existing.id)
+                    .execute(&mut *tx)
+                    .await?;
+                deleted_rooms.push(existing.id.clone());
+            }
+        }
+    }
+
+    // After rooms, so links can point at rooms the plan created.
+    for change in changes.iter().filter(|c| c.resource == ResourceKind::MatrixLink) {
+        let query = match change.action {
+            ChangeAction::Create => sqlx::query(
+                "INSERT INTO matrix_room_links (matrix_room_id, created_at, room_id) \
+                 SELECT ?, ?, id FROM rooms WHERE name = ? LIMIT 1"
+            ),
+            ChangeAction::Update => sqlx::query(
+                "UPDATE matrix_room_links SET matrix_room_id = ?, created_at = ? \
+                 WHERE room_id = (SELECT id FROM rooms WHERE name = ? LIMIT 1)"
+            ),
+            ChangeAction::Delete => {
+                sqlx::query("DELETE FROM matrix_room_links WHERE room_id = (SELECT id FROM rooms WHERE name = ? LIMIT 1)")
+                    .bind(&change.name)
+                    .execute(&mut *tx)
+                    .await?;
+                continue;
+            }
+        };
+        query
+            .bind(change.fields.first().and_then(|f| f.to.as_deref()))
+            .bind(&now)
+            .bind(&change.name)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    for change in changes.iter().filter(|c| c.resource == ResourceKind::Role && c.action == ChangeAction::Delete) {
+        sqlx::query("UPDATE users SET role = 'user' WHERE role = ?")
+            .bind(&change.name)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM room_permission_overwrites WHERE target_type = 'role' AND target_id = ?")
+            .bind(&change.name)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM roles WHERE name = ?")
+            .bind(&change.name)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok((upserted_rooms, deleted_rooms))
+}
+
+fn export_state(state: &ServerState) -> ServerConfig {
+    ServerConfig {
+        version: CONFIG_VERSION,
+        roles: Some(
+            state
+                .roles
+                .iter()
+                .map(|role| RoleConfig { name: role.name.clone(), color: role.color.clone() })
+                .collect(),
+        ),
+        rooms: Some(
+            state
+                .rooms
+                .iter()
+                .map(|room| RoomConfig {
+                    name: room.name.clone(),
+                    kind: room.kind.clone(),
+                    required_role: room.required_role.clone(),
+                    topic: room.topic.clone(),
+                    guidelines: room.guidelines.clone(),
+                })
+                .collect(),
+        ),
+        bridges: (!state.matrix_links.is_empty()).then(|| BridgesConfig { matrix: Some(state.matrix_links.clone()) }),
+    }
+}
+
+/// Short content hash of an export, used as the config ETag.
+fn fingerprint(yaml: &str) -> String {
+    let digest = Sha256::digest(yaml.as_bytes());
+    digest[..8].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// 412 when `If-Match` names an export other than the current one. The diff
+/// is the plan from the current state to the submitted file, i.e. what the
+/// stale file would overwrite.
+fn check_if_match(req: &HttpRequest, state: &ServerState, changes: &[PlannedChange]) -> Option<HttpResponse> {
+    let expected = if_match(req)?;
+    let current = serde_yaml::to_string(&export_state(state)).ok()?;
+    let version = fingerprint(&current);
+    (expected != version).then(|| precondition_failed(version, current, changes))
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/server/config/export — Current roles, rooms and bridge links as YAML (ADMINISTRATOR)
+pub async fn export_config(Sudo(claims): Sudo, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::ADMINISTRATOR).await {
+        return response;
+    }
+
+    let state = match load_state(pool.get_ref()).await {
+        Ok(state) => state,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match serde_yaml::to_string(&export_state(&state)) {
+        Ok(yaml) => HttpResponse::Ok()
+            .content_type("application/yaml")
+            .insert_header(("Content-Disposition", "attachment; filename=\"voxium-server.yaml\""))
+            .insert_header(("ETag", etag(fingerprint(&yaml))))
+            .body(yaml),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/server/config/plan — Diff a YAML config against the server (ADMINISTRATOR)
+pub async fn plan_config(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<ConfigApplyQuery>,
+    body: String,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::ADMINISTRATOR).await {
+        return response;
+    }
+
+    let config = match parse_config(&body) {
+        Ok(config) => config,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+    let state = match load_state(pool.get_ref()).await {
+        Ok(state) => state,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match build_plan(&state, &config, query.prune) {
+        Ok(changes) => check_if_match(&req, &state, &changes)
+            .unwrap_or_else(|| HttpResponse::Ok().json(serde_json::json!({ "changes": changes }))),
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+/// POST /api/server/config/apply — Apply a YAML config idempotently (ADMINISTRATOR)
+pub async fn apply_config(
+    req: HttpRequest,
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<ConfigApplyQuery>,
+    body: String,
+    broadcaster: web::Data<Broadcaster>,
+    access_cache: web::Data<AccessCache>,
+) -> HttpResponse {
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::ADMINISTRATOR).await {
+        return response;
+    }
+
+    let config = match parse_config(&body) {
+        Ok(config) => config,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+    let state = match load_state(pool.get_ref()).await {
+        Ok(state) => state,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let changes = match build_plan(&state, &config, query.prune) {
+        Ok(changes) => changes,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+    if let Some(conflict) = check_if_match(&req, &state, &changes) {
+        return conflict;
+    }
+
+    if changes.is_empty() {
+        return HttpResponse::Ok().json(serde_json::json!({ "applied": false, "changes": changes }));
+    }
+
+    let (upserted_rooms, deleted_rooms) =
+        match execute_plan(pool.get_ref(), &state, &config, &changes, &claims).await {
+            Ok(result) => result,
+            Err(_) => {
+                return HttpResponse::Conflict()
+                    .json(serde_json::json!({ "error": "Server changed while applying, nothing was applied" }))
+            }
+        };
+
+    if changes.iter().any(|c| c.resource == ResourceKind::Role && c.action == ChangeAction::Delete) {
+        cache_clear_user_roles(access_cache.get_ref());
+        cache_clear_room_overwrites(access_cache.get_ref());
+        cache_clear_role_permissions(access_cache.get_ref());
+    }
+    for room_id in &deleted_rooms {
+        cache_remove_room(access_cache.get_ref(), room_id);
+        let event = serde_json::json!({ "type": "room_deleted", "room_id": room_id });
+        let _ = broadcaster.send(event.to_string());
+    }
+    for (room_id, required_role) in &upserted_rooms {
+        cache_set_room_required_role(access_cache.get_ref(), room_id, required_role);
+    }
+
+    // Same payload as the REST room handlers, for rooms the plan touched.
+    let rooms = sqlx::query_as::<_, Room>(&format!("SELECT {ROOM_COLUMNS} FROM rooms"))
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default();
+    for room in rooms.iter().filter(|r| upserted_rooms.iter().any(|(id, _)| *id == r.id)) {
+        let event = serde_json::json!({
+            "type": "room_updated",
+            "room_id": room.id,
+            "name": room.name,
+            "kind": room.kind,
+            "required_role": room.required_role,
+            "topic": room.topic,
+            "guidelines": room.guidelines,
+            "version": room.version,
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "applied": true, "changes": changes }))
+}
@@ -0,0 +1,475 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Login sessions (device list & revocation)
+// ═══════════════════════════════════════════════════════
+//
+// Every JWT we hand out is backed by a row in `sessions` and carries its id
+// in the `sid` claim. `extract_claims` rejects tokens whose session was
+// revoked; the revoked set is kept in memory so that check stays
+// synchronous. Last-seen timestamps are buffered in memory as well and
+// flushed to the DB periodically rather than on every request.
+//
+// Access JWTs are short-lived. Each session also owns a single-use refresh
+// token (stored hashed) that `POST /api/auth/refresh` swaps for a new
+// access/refresh pair. Presenting an already used refresh token means it
+// leaked, so the whole session is revoked.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::auth::{create_token, extract_claims, ACCESS_TOKEN_TTL_MINUTES, SESSION_TTL_DAYS};
+use crate::sudo::Sudo;
+
+/// How often buffered last-seen timestamps are written to the DB.
+const LAST_SEEN_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_DEVICE_NAME_CHARS: usize = 64;
+
+// ── Shared state ────────────────────────────────────────
+
+#[derive(Default)]
+pub struct SessionStoreState {
+    revoked: HashSet<String>,
+    /// session id -> last request time, not yet written to the DB
+    pending_seen: HashMap<String, String>,
+}
+
+pub type SessionStore = Arc<Mutex<SessionStoreState>>;
+
+pub fn create_session_store() -> SessionStore {
+    Arc::new(Mutex::new(SessionStoreState::default()))
+}
+
+/// Load still-unexpired revoked sessions so they stay rejected after a restart.
+pub async fn load_revoked_sessions(pool: &SqlitePool, store: &SessionStore) {
+    let ids: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM sessions WHERE revoked_at IS NOT NULL AND expires_at > ?"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    store.lock().unwrap().revoked.extend(ids);
+}
+
+pub fn is_revoked(store: &SessionStore, session_id: &str) -> bool {
+    store.lock().unwrap().revoked.contains(session_id)
+}
+
+/// Sessions revoked here: rejected from now on, on every instance (see `bus`).
+fn revoke_locally(store: &SessionStore, ids: Vec<String>) {
+    store.lock().unwrap().revoked.extend(ids.iter().cloned());
+    crate::bus::publish_revoked(ids);
+}
+
+/// Sessions another instance revoked.
+pub(crate) fn mark_revoked(store: &SessionStore, ids: impl IntoIterator<Item = String>) {
+    store.lock().unwrap().revoked.extend(ids);
+}
+
+pub fn touch(store: &SessionStore, session_id: &str) {
+    store
+        .lock()
+        .unwrap()
+        .pending_seen
+        .insert(session_id.to_string(), chrono::Utc::now().to_rfc3339());
+}
+
+/// Write pending `last_seen_at` values, returns how many were written.
+/// Failed writes are put back for the next pass.
+async fn flush_last_seen(pool: &SqlitePool, store: &SessionStore) -> Result<u64, String> {
+    let pending = std::mem::take(&mut store.lock().unwrap().pending_seen);
+    let mut written = 0;
+    let mut failed = Vec::new();
+    for (session_id, seen_at) in pending {
+        let result = sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE id = ?")
+            .bind(&seen_at)
+            .bind(&session_id)
+            .execute(pool)
+            .await;
+        match result {
+            Ok(_) => written += 1,
+            Err(_) => failed.push((session_id, seen_at)),
+        }
+    }
+
+    // Used refresh tokens only matter for replay detection while
+    // their session could still be refreshed.
+    let _ = sqlx::query(
+        "DELETE FROM refresh_tokens WHERE session_id IN (SELECT id FROM sessions WHERE expires_at <= ?)"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+
+    if failed.is_empty() {
+        return Ok(written);
+    }
+    let count = failed.len();
+    let mut state = store.lock().unwrap();
+    for (session_id, seen_at) in failed {
+        // A newer touch since the take wins.
+        state.pending_seen.entry(session_id).or_insert(seen_at);
+    }
+    Err(format!("{count} last_seen updates failed"))
+}
+
+fn last_seen_backlog(store: &SessionStore) -> crate::jobs::Backlog {
+    let state = store.lock().unwrap();
+    crate::jobs::Backlog {
+        depth: state.pending_seen.len() as i64,
+        oldest_due_at: state.pending_seen.values().min().cloned(),
+    }
+}
+
+pub fn spawn_last_seen_flusher(pool: SqlitePool, store: SessionStore, jobs: crate::jobs::JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::SESSION_LAST_SEEN);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(LAST_SEEN_FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(
+                &jobs,
+                crate::jobs::SESSION_LAST_SEEN,
+                None,
+                flush_last_seen(&pool, &store),
+                async { last_seen_backlog(&store) },
+            )
+            .await;
+        }
+    });
+}
+
+// ── Issuing ─────────────────────────────────────────────
+
+/// Where a login comes from, recorded on the session.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Device name comes from the optional `X-Device-Name` header.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        Self {
+            name: header("X-Device-Name").map(|n| n.chars().take(MAX_DEVICE_NAME_CHARS).collect()),
+            user_agent: header("User-Agent"),
+            ip: req.connection_info().realip_remote_addr().map(|s| s.to_string()),
+        }
+    }
+}
+
+/// Access JWT plus the refresh token that renews it.
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedTokens {
+    pub token: String,
+    pub refresh_token: String,
+    /// Access token lifetime in seconds.
+    pub expires_in: i64,
+}
+
+impl IssuedTokens {
+    fn new(token: String, refresh_token: String) -> Self {
+        Self {
+            token,
+            refresh_token,
+            expires_in: ACCESS_TOKEN_TTL_MINUTES * 60,
+        }
+    }
+}
+
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Refresh tokens are random 256-bit values, a plain SHA-256 is enough.
+fn hash_refresh_token(raw: &str) -> String {
+    Sha256::digest(raw.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Create a new refresh token for `session_id` and return its raw value.
+async fn store_refresh_token(pool: &SqlitePool, session_id: &str) -> Result<String, String> {
+    let raw = generate_refresh_token();
+    sqlx::query("INSERT INTO refresh_tokens (token_hash, session_id, created_at) VALUES (?, ?, ?)")
+        .bind(hash_refresh_token(&raw))
+        .bind(session_id)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .map_err(|_| "Failed to create session".to_string())?;
+    Ok(raw)
+}
+
+/// Record a new session and return tokens bound to it.
+pub(crate) async fn issue_token(
+    pool: &SqlitePool,
+    device: &DeviceInfo,
+    user_id: &str,
+    username: &str,
+    role: &str,
+) -> Result<IssuedTokens, String> {
+    // Sign-in paths with a better answer check `bans::in_force` first.
+    if crate::bans::is_banned(pool, user_id).await {
+        return Err("This account is banned".to_string());
+    }
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::days(SESSION_TTL_DAYS);
+
+    sqlx::query(
+        "INSERT INTO sessions (id, user_id, device_name, user_agent, ip, created_at, last_seen_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&session_id)
+    .bind(user_id)
+    .bind(&device.name)
+    .bind(&device.user_agent)
+    .bind(&device.ip)
+    .bind(now.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .bind(expires_at.to_rfc3339())
+    .execute(pool)
+    .await
+    .map_err(|_| "Failed to create session".to_string())?;
+
+    crate::notifications::new_session(pool, user_id, &session_id, device).await;
+    let refresh_token = store_refresh_token(pool, &session_id).await?;
+    Ok(IssuedTokens::new(create_token(user_id, username, role, &session_id), refresh_token))
+}
+
+/// Revoke every live session of `user_id` except `keep` and return how many
+/// were revoked. Used on logout-everywhere and after credential changes.
+pub(crate) async fn revoke_user_sessions(
+    pool: &SqlitePool,
+    store: &SessionStore,
+    user_id: &str,
+    keep: Option<&str>,
+) -> usize {
+    let now = chrono::Utc::now().to_rfc3339();
+    let _ = sqlx::query(
+        "UPDATE sessions SET revoked_at = ? WHERE user_id = ? AND id != ? AND revoked_at IS NULL"
+    )
+    .bind(&now)
+    .bind(user_id)
+    .bind(keep.unwrap_or(""))
+    .execute(pool)
+    .await;
+
+    let ids: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM sessions WHERE user_id = ? AND revoked_at = ?"
+    )
+    .bind(user_id)
+    .bind(&now)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    let count = ids.len();
+    revoke_locally(store, ids);
+    count
+}
+
+async fn revoke_session_id(pool: &SqlitePool, store: &SessionStore, session_id: &str) {
+    let _ = sqlx::query("UPDATE sessions SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(session_id)
+        .execute(pool)
+        .await;
+    revoke_locally(store, vec![session_id.to_string()]);
+}
+
+// ── Refresh ─────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshPayload {
+    pub refresh_token: String,
+}
+
+/// POST /api/auth/refresh — Swap a refresh token for a new access/refresh pair
+pub async fn refresh(
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+    body: web::Json<RefreshPayload>,
+) -> HttpResponse {
+    let invalid = || HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Invalid refresh token" }));
+
+    let token_hash = hash_refresh_token(body.refresh_token.trim());
+    let row = sqlx::query(
+        "SELECT r.session_id, r.used_at, s.revoked_at, s.expires_at, u.id AS user_id, u.username, u.role \
+         FROM refresh_tokens r JOIN sessions s ON s.id = r.session_id JOIN users u ON u.id = s.user_id \
+         WHERE r.token_hash = ?"
+    )
+    .bind(&token_hash)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+
+    let Some(row) = row else {
+        return invalid();
+    };
+    let session_id: String = row.get("session_id");
+    let used_at: Option<String> = row.try_get("used_at").unwrap_or(None);
+    let revoked_at: Option<String> = row.try_get("revoked_at").unwrap_or(None);
+    let expires_at: String = row.get("expires_at");
+
+    if revoked_at.is_some() || expires_at <= chrono::Utc::now().to_rfc3339() {
+        return invalid();
+    }
+    // A guest's access ends with their link, whatever the session says.
+    let user_id: String = row.get("user_id");
+    let role: String = row.get("role");
+    if role == crate::guests::GUEST_ROLE && crate::guests::active_grant(pool.get_ref(), &user_id).await.is_none() {
+        return invalid();
+    }
+
+    let reuse_detected = || HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "Refresh token reuse detected, session revoked"
+    }));
+    if used_at.is_some() {
+        revoke_session_id(pool.get_ref(), store.get_ref(), &session_id).await;
+        return reuse_detected();
+    }
+
+    // Claim the token atomically: two concurrent refreshes with the same
+    // token are a replay too.
+    let now = chrono::Utc::now();
+    let claimed = sqlx::query("UPDATE refresh_tokens SET used_at = ? WHERE token_hash = ? AND used_at IS NULL")
+        .bind(now.to_rfc3339())
+        .bind(&token_hash)
+        .execute(pool.get_ref())
+        .await
+        .map(|res| res.rows_affected() > 0)
+        .unwrap_or(false);
+    if !claimed {
+        revoke_session_id(pool.get_ref(), store.get_ref(), &session_id).await;
+        return reuse_detected();
+    }
+
+    let _ = sqlx::query("UPDATE sessions SET expires_at = ?, last_seen_at = ? WHERE id = ?")
+        .bind((now + chrono::Duration::days(SESSION_TTL_DAYS)).to_rfc3339())
+        .bind(now.to_rfc3339())
+        .bind(&session_id)
+        .execute(pool.get_ref())
+        .await;
+
+    let refresh_token = match store_refresh_token(pool.get_ref(), &session_id).await {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    };
+
+    // Username and role are re-read so renames and role changes reach the
+    // client on the next refresh.
+    let username: String = row.get("username");
+    HttpResponse::Ok().json(IssuedTokens::new(
+        create_token(&user_id, &username, &role, &session_id),
+        refresh_token,
+    ))
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SessionInfo {
+    pub id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub expires_at: String,
+    #[sqlx(skip)]
+    pub current: bool,
+}
+
+/// GET /api/users/@me/sessions — List active sessions (devices)
+pub async fn list_sessions(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let mut sessions = sqlx::query_as::<_, SessionInfo>(
+        "SELECT id, device_name, user_agent, ip, created_at, last_seen_at, expires_at FROM sessions \
+         WHERE user_id = ? AND revoked_at IS NULL AND expires_at > ? ORDER BY last_seen_at DESC"
+    )
+    .bind(&claims.sub)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+
+    {
+        let guard = store.lock().unwrap();
+        for session in sessions.iter_mut() {
+            if let Some(seen) = guard.pending_seen.get(&session.id) {
+                session.last_seen_at = seen.clone();
+            }
+            session.current = session.id == claims.sid;
+        }
+    }
+    sessions.sort_by(|a, b| b.last_seen_at.cmp(&a.last_seen_at));
+
+    HttpResponse::Ok().json(sessions)
+}
+
+/// DELETE /api/users/@me/sessions/{id} — Revoke one session
+pub async fn revoke_session(
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let session_id = path.into_inner();
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&session_id)
+    .bind(&claims.sub)
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() > 0 => {
+            revoke_locally(store.get_ref(), vec![session_id]);
+            HttpResponse::Ok().json(serde_json::json!({ "status": "revoked" }))
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Session not found" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/users/@me/sessions — Revoke every session except the current one
+pub async fn revoke_other_sessions(
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    store: web::Data<SessionStore>,
+) -> HttpResponse {
+    let count = revoke_user_sessions(pool.get_ref(), store.get_ref(), &claims.sub, Some(&claims.sid)).await;
+
+    HttpResponse::Ok().json(serde_json::json!({ "status": "revoked", "count": count }))
+}
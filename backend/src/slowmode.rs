@@ -0,0 +1,125 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Slowmode (per-room posting cooldowns)
+// ═══════════════════════════════════════════════════════
+//
+// A room with `slowmode_seconds > 0` accepts one message per member every
+// that many seconds. The cooldown is claimed when a message is accepted, in
+// one conditional write on `room_cooldowns`, so two connections of the same
+// member cannot both get a message through. Admins and members of a role
+// with `bypass_slowmode` are exempt. Guests are never exempt and wait at
+// least `guests::post_interval_seconds()` in any room.
+//
+// A message refused over the WebSocket gets a `slowmode` event back, on the
+// sending connection only: `{ type, room_id, retry_after, slowmode_seconds }`.
+// HTTP endpoints that post (polls) answer 429 with the same fields and a
+// `Retry-After` header. `retry_after` is in whole seconds, at least 1.
+
+use actix_web::HttpResponse;
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+/// Longest slowmode an admin can set (6 hours).
+pub const MAX_SLOWMODE_SECONDS: i64 = 21_600;
+
+/// A refused post: how long until the member can post again.
+#[derive(Debug, Clone, Copy)]
+pub struct Cooldown {
+    pub retry_after: u64,
+    pub slowmode_seconds: i64,
+}
+
+impl Cooldown {
+    /// The structured 429 for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", self.retry_after.to_string()))
+            .json(serde_json::json!({
+                "error": format!("Slowmode is on, wait {}s before posting again", self.retry_after),
+                "retry_after": self.retry_after,
+                "slowmode_seconds": self.slowmode_seconds,
+            }))
+    }
+
+    /// The `slowmode` event for the WebSocket connection that sent the message.
+    pub fn event(&self, room_id: &str) -> String {
+        serde_json::json!({
+            "type": "slowmode",
+            "room_id": room_id,
+            "retry_after": self.retry_after,
+            "slowmode_seconds": self.slowmode_seconds,
+        })
+        .to_string()
+    }
+}
+
+/// Whether `seconds` is a valid slowmode (0 turns it off).
+pub fn is_valid(seconds: i64) -> bool {
+    (0..=MAX_SLOWMODE_SECONDS).contains(&seconds)
+}
+
+/// Whether members with `role` post without waiting.
+pub(crate) async fn bypasses(pool: &SqlitePool, role: &str) -> bool {
+    crate::permissions::role_permissions(pool, role).await & crate::permissions::ADMINISTRATOR != 0
+        || sqlx::query_scalar::<_, i64>("SELECT bypass_slowmode FROM roles WHERE name = ?")
+            .bind(role)
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten()
+            .is_some_and(|flag| flag != 0)
+}
+
+/// Take the member's cooldown in `room_id` for a message about to be stored,
+/// or say how long they still have to wait.
+pub(crate) async fn claim(pool: &SqlitePool, room_id: &str, user_id: &str, role: &str) -> Result<(), Cooldown> {
+    let slowmode_seconds: i64 = sqlx::query_scalar("SELECT slowmode_seconds FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(0);
+    let slowmode_seconds = if role == crate::guests::GUEST_ROLE {
+        slowmode_seconds.max(crate::guests::post_interval_seconds())
+    } else {
+        slowmode_seconds
+    };
+    if slowmode_seconds <= 0 || bypasses(pool, role).await {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp_millis();
+    let window = slowmode_seconds * 1000;
+    // Only moves `last_post_ms` forward once the previous cooldown is over.
+    let claimed = sqlx::query(
+        "INSERT INTO room_cooldowns (room_id, user_id, last_post_ms) VALUES (?, ?, ?) \
+         ON CONFLICT(room_id, user_id) DO UPDATE SET last_post_ms = excluded.last_post_ms \
+         WHERE room_cooldowns.last_post_ms <= ?"
+    )
+    .bind(room_id)
+    .bind(user_id)
+    .bind(now)
+    .bind(now - window)
+    .execute(pool)
+    .await
+    .map(|r| r.rows_affected() > 0)
+    // A failed write should not silence the room.
+    .unwrap_or(true);
+    if claimed {
+        return Ok(());
+    }
+
+    let last: i64 = sqlx::query_scalar("SELECT last_post_ms FROM room_cooldowns WHERE room_id = ? AND user_id = ?")
+        .bind(room_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(now);
+    let remaining_ms = (last + window - now).max(0);
+    Err(Cooldown {
+        retry_after: ((remaining_ms + 999) / 1000).max(1) as u64,
+        slowmode_seconds,
+    })
+}
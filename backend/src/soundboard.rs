@@ -0,0 +1,409 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Soundboard
+// ═══════════════════════════════════════════════════════
+//
+// Members with `MANAGE_EMOJIS` upload short Ogg Opus clips to the server's
+// soundboard, under a unique name of 2-32 lowercase letters, digits or `_`.
+// Clips are capped in size (`MAX_SOUND_BYTES`), length (SOUNDBOARD_MAX_SECS)
+// and number (`MAX_SOUNDS`), and kept in file storage under a key of their
+// own, so they never share an object with attachments.
+//
+// The server carries no voice audio: native voice rooms are a WebRTC mesh,
+// and a Discord voice connection is held by the member's own client. So a
+// clip is played by clients, like priority speaker ducking is applied by
+// them (see `voice_priority`). The server checks the player may use the
+// soundboard (`USE_SOUNDBOARD`, plus `CONNECT` in a voice room), is in the
+// room's voice and is not timed out, and takes their cooldown. Then:
+//   - in a voice room, it sends `soundboard_play` to the room and every
+//     participant plays the clip at its volume, on top of the voices;
+//   - in Discord voice, it answers with the clip and the player's client
+//     mixes it into the stream it sends Discord.
+//
+// The cooldown is per member, across rooms, claimed in one conditional
+// write on `soundboard_cooldowns` (as slowmode does), so two connections
+// cannot both get a clip through.
+//
+// Config (env):
+//   SOUNDBOARD_MAX_SECS       longest clip accepted, in seconds (default 5)
+//   SOUNDBOARD_COOLDOWN_SECS  seconds between two clips of a member (default 3, 0 for none)
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::files::FileStorage;
+use crate::voice_activity::VoiceOccupancy;
+use crate::ws::Broadcaster;
+
+const MAX_SOUND_BYTES: usize = 512 * 1024;
+const MAX_SOUNDS: i64 = 48;
+const MIN_NAME_LEN: usize = 2;
+const MAX_NAME_LEN: usize = 32;
+const DEFAULT_MAX_SECS: f64 = 5.0;
+const DEFAULT_COOLDOWN_SECS: i64 = 3;
+
+fn max_secs() -> f64 {
+    std::env::var("SOUNDBOARD_MAX_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n: &f64| *n > 0.0)
+        .unwrap_or(DEFAULT_MAX_SECS)
+}
+
+/// Seconds a member waits between two clips.
+pub fn cooldown_secs() -> i64 {
+    std::env::var("SOUNDBOARD_COOLDOWN_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|s| *s >= 0)
+        .unwrap_or(DEFAULT_COOLDOWN_SECS)
+}
+
+fn valid_name(name: &str) -> bool {
+    (MIN_NAME_LEN..=MAX_NAME_LEN).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sound {
+    pub id: String,
+    pub name: String,
+    /// Playback gain, 0-1.
+    pub volume: f64,
+    pub duration_secs: f64,
+    pub size_bytes: i64,
+    pub created_by_username: String,
+    pub created_at: String,
+    pub audio_url: String,
+}
+
+const SOUND_SELECT: &str =
+    "SELECT id, name, volume, duration_secs, size_bytes, created_by_username, created_at FROM soundboard_sounds";
+
+impl Sound {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let id: String = row.get("id");
+        Sound {
+            audio_url: format!("/api/soundboard/{id}/audio"),
+            id,
+            name: row.get("name"),
+            volume: row.get("volume"),
+            duration_secs: row.get("duration_secs"),
+            size_bytes: row.get("size_bytes"),
+            created_by_username: row.get("created_by_username"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
+
+async fn fetch_sound(pool: &SqlitePool, id: &str) -> Option<Sound> {
+    sqlx::query(&format!("{SOUND_SELECT} WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Sound::from_row(&row))
+}
+
+/// Take the member's cooldown for a clip about to be played, or say how many
+/// seconds they still have to wait.
+async fn claim_cooldown(pool: &SqlitePool, user_id: &str) -> Result<(), u64> {
+    let cooldown = cooldown_secs();
+    if cooldown == 0 {
+        return Ok(());
+    }
+    let now = Utc::now().timestamp_millis();
+    let window = cooldown * 1000;
+    // Only moves `last_play_ms` forward once the previous cooldown is over.
+    let claimed = sqlx::query(
+        "INSERT INTO soundboard_cooldowns (user_id, last_play_ms) VALUES (?, ?) \
+         ON CONFLICT(user_id) DO UPDATE SET last_play_ms = excluded.last_play_ms \
+         WHERE soundboard_cooldowns.last_play_ms <= ?"
+    )
+    .bind(user_id)
+    .bind(now)
+    .bind(now - window)
+    .execute(pool)
+    .await
+    .map(|r| r.rows_affected() > 0)
+    .unwrap_or(false);
+    if claimed {
+        return Ok(());
+    }
+    let last: i64 = sqlx::query_scalar("SELECT last_play_ms FROM soundboard_cooldowns WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(now);
+    let remaining_ms = (last + window - now).max(0);
+    Err(((remaining_ms + 999) / 1000).max(1) as u64)
+}
+
+fn cooling_down(retry_after: u64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(json!({
+            "error": format!("Wait {retry_after}s before playing another sound"),
+            "retry_after": retry_after,
+        }))
+}
+
+/// Check the caller can play `sound_id` now and take their cooldown.
+async fn prepare_play(pool: &SqlitePool, claims: &Claims, sound_id: &str) -> Result<Sound, HttpResponse> {
+    let Some(sound) = fetch_sound(pool, sound_id).await else {
+        return Err(HttpResponse::NotFound().json(json!({ "error": "Sound not found" })));
+    };
+    crate::timeouts::check(pool, &claims.sub).await.map_err(|timed_out| timed_out.response())?;
+    claim_cooldown(pool, &claims.sub).await.map_err(cooling_down)?;
+    Ok(sound)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct SoundQuery {
+    pub volume: Option<f64>,
+}
+
+/// GET /api/soundboard — List the server's soundboard clips (authenticated)
+pub async fn list_sounds(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let sounds: Vec<Sound> = sqlx::query(&format!("{SOUND_SELECT} ORDER BY name"))
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(Sound::from_row)
+        .collect();
+    HttpResponse::Ok().json(json!({ "cooldown_secs": cooldown_secs(), "sounds": sounds }))
+}
+
+/// GET /api/soundboard/{id}/audio — The Ogg Opus clip of a sound (authenticated)
+pub async fn sound_audio(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if extract_claims(&req).is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let key: Option<String> = sqlx::query_scalar("SELECT object_key FROM soundboard_sounds WHERE id = ?")
+        .bind(path.into_inner())
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let Some(key) = key else {
+        return HttpResponse::NotFound().json(json!({ "error": "Sound not found" }));
+    };
+    match storage.get(&key).await {
+        // A clip never changes: a new upload gets a new id.
+        Some(bytes) => HttpResponse::Ok()
+            .content_type("audio/ogg")
+            .insert_header(("Cache-Control", "private, max-age=31536000, immutable"))
+            .body(bytes),
+        None => HttpResponse::NotFound().json(json!({ "error": "Sound not found" })),
+    }
+}
+
+/// POST /api/server/soundboard/{name}?volume= — Upload a soundboard clip, multipart Ogg Opus (MANAGE_EMOJIS)
+pub async fn create_sound(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    query: web::Query<SoundQuery>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_EMOJIS).await {
+        return response;
+    }
+
+    let name = path.into_inner().trim().to_lowercase();
+    if !valid_name(&name) {
+        return HttpResponse::BadRequest().json(json!({ "error": "Sound name must be 2-32 letters, digits or underscores" }));
+    }
+    let volume = query.volume.unwrap_or(1.0);
+    if !(0.0..=1.0).contains(&volume) {
+        return HttpResponse::BadRequest().json(json!({ "error": "volume must be between 0 and 1" }));
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM soundboard_sounds")
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if count >= MAX_SOUNDS {
+        return HttpResponse::BadRequest().json(json!({ "error": format!("Soundboard limit reached ({MAX_SOUNDS})") }));
+    }
+    let taken: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM soundboard_sounds WHERE name = ?)")
+        .bind(&name)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if taken {
+        return HttpResponse::Conflict().json(json!({ "error": "A sound with this name already exists" }));
+    }
+
+    let mut bytes = Vec::new();
+    if let Some(Ok(mut field)) = payload.next().await {
+        while let Some(Ok(chunk)) = field.next().await {
+            if bytes.len() + chunk.len() > MAX_SOUND_BYTES {
+                return HttpResponse::PayloadTooLarge().json(json!({ "error": "Sound too large (max 512 KB)" }));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+    }
+    if bytes.is_empty() {
+        return HttpResponse::BadRequest().json(json!({ "error": "No file provided" }));
+    }
+    let info = (crate::files::sniff_content_type(&bytes) == Some("audio/ogg"))
+        .then(|| crate::voice_messages::analyze(&bytes))
+        .flatten();
+    let Some(info) = info else {
+        return HttpResponse::UnsupportedMediaType().json(json!({ "error": "Sounds must be Ogg Opus" }));
+    };
+    let max_secs = max_secs();
+    if info.duration_secs > max_secs {
+        return HttpResponse::BadRequest().json(json!({ "error": format!("Sound too long (max {max_secs} s)") }));
+    }
+    if let Err(response) = crate::scanning::refuse_flagged(&bytes).await {
+        return response;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let key = crate::files::sha256_hex(Uuid::new_v4().as_bytes());
+    let size = bytes.len() as i64;
+    if storage.put(&key, bytes).await.is_err() {
+        return HttpResponse::InternalServerError().json(json!({ "error": "Failed to save file" }));
+    }
+    let now = Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        "INSERT INTO soundboard_sounds (id, name, object_key, size_bytes, duration_secs, volume, created_by, created_by_username, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&name)
+    .bind(&key)
+    .bind(size)
+    .bind(info.duration_secs)
+    .bind(volume)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(&now)
+    .execute(pool.get_ref())
+    .await;
+    if let Err(e) = result {
+        storage.delete(&key).await;
+        // Lost a race with another upload of the same name.
+        if e.as_database_error().is_some_and(|d| d.is_unique_violation()) {
+            return HttpResponse::Conflict().json(json!({ "error": "A sound with this name already exists" }));
+        }
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let Some(sound) = fetch_sound(pool.get_ref(), &id).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let _ = broadcaster.send(json!({ "type": "soundboard_sound_created", "sound": sound }).to_string());
+    HttpResponse::Created().json(sound)
+}
+
+/// DELETE /api/server/soundboard/{id} — Delete a soundboard clip (MANAGE_EMOJIS)
+pub async fn delete_sound(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_EMOJIS).await {
+        return response;
+    }
+    let deleted: Option<(String, String)> =
+        sqlx::query_as("DELETE FROM soundboard_sounds WHERE id = ? RETURNING name, object_key")
+            .bind(path.as_str())
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None);
+    let Some((name, key)) = deleted else {
+        return HttpResponse::NotFound().json(json!({ "error": "Sound not found" }));
+    };
+    storage.delete(&key).await;
+    let _ = broadcaster.send(json!({ "type": "soundboard_sound_deleted", "id": path.as_str(), "name": name }).to_string());
+    HttpResponse::NoContent().finish()
+}
+
+/// POST /api/rooms/{id}/voice/soundboard/{sound_id} — Play a clip to everyone in the room's voice (CONNECT, USE_SOUNDBOARD)
+pub async fn play_in_room(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    occupancy: web::Data<VoiceOccupancy>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (room_id, sound_id) = path.into_inner();
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let needed = crate::permissions::CONNECT | crate::permissions::USE_SOUNDBOARD;
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, needed).await {
+        return response;
+    }
+    let in_voice = occupancy
+        .lock()
+        .unwrap()
+        .get(&room_id)
+        .is_some_and(|users| users.contains_key(&claims.sub));
+    if !in_voice {
+        return HttpResponse::Conflict().json(json!({ "error": "Join the room's voice to play sounds" }));
+    }
+    let sound = match prepare_play(pool.get_ref(), &claims, &sound_id).await {
+        Ok(sound) => sound,
+        Err(response) => return response,
+    };
+
+    let event = json!({
+        "type": "soundboard_play",
+        "room_id": room_id,
+        "user_id": claims.sub,
+        "username": claims.username,
+        "sound": sound,
+    });
+    let _ = broadcaster.send(event.to_string());
+    HttpResponse::Ok().json(json!({ "room_id": room_id, "sound": sound, "cooldown_secs": cooldown_secs() }))
+}
+
+/// POST /api/discord/voice/soundboard/{sound_id} — Take the cooldown to mix a clip into the caller's Discord voice (USE_SOUNDBOARD)
+pub async fn play_in_discord(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::USE_SOUNDBOARD).await {
+        return response;
+    }
+    match prepare_play(pool.get_ref(), &claims, &path.into_inner()).await {
+        Ok(sound) => HttpResponse::Ok().json(json!({ "sound": sound, "cooldown_secs": cooldown_secs() })),
+        Err(response) => response,
+    }
+}
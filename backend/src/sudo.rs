@@ -0,0 +1,207 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Sudo mode (recent re-authentication)
+// ═══════════════════════════════════════════════════════
+//
+// A stolen access token should not be enough to delete rooms and users,
+// export the server or manage the account's credentials. Those endpoints
+// take the `Sudo` extractor instead of calling `extract_claims`: it only
+// accepts tokens carrying a `sudo_until` claim in the future, and answers
+// 403 `{ sudo_required: true }` otherwise.
+//
+// `POST /api/auth/sudo` re-checks the password, a TOTP/backup code, a
+// passkey or the linked Discord account and returns a new access token for
+// the same session with `sudo_until` set `SUDO_TTL_MINUTES` ahead. The
+// claim is not carried over by `/api/auth/refresh`, so it fades on its own.
+//
+// Accounts created through a Discord login have a random password nobody
+// knows (`users.password_set` is 0): the password is not offered to them,
+// signing in to Discord again is. That takes a Discord user token, or an
+// OAuth2 code whose state `POST /api/auth/discord/oauth/authorize` issued
+// to the account, depending on `DISCORD_LINK_MODE`.
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use bcrypt::verify;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::future::{ready, Ready};
+
+use crate::auth::{extract_claims, sign_claims, Claims, ACCESS_TOKEN_TTL_MINUTES};
+use crate::discord_oauth::{OAuthCallbackPayload, OAuthStates};
+use crate::webauthn::{verify_assertion, AssertionCredential, PasskeyCeremonies};
+
+pub const SUDO_TTL_MINUTES: i64 = 5;
+
+fn now() -> usize {
+    chrono::Utc::now().timestamp() as usize
+}
+
+// ── Extractor ───────────────────────────────────────────
+
+/// Claims of a token in sudo mode. Use as a handler argument:
+/// `Sudo(claims): Sudo`.
+pub struct Sudo(pub Claims);
+
+impl FromRequest for Sudo {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let result = match extract_claims(req) {
+            None => Err(InternalError::from_response(
+                "missing or invalid token",
+                HttpResponse::Unauthorized().finish(),
+            )),
+            Some(claims) if claims.sudo_until.is_some_and(|until| until > now()) => Ok(Sudo(claims)),
+            Some(_) => Err(InternalError::from_response(
+                "sudo mode required",
+                HttpResponse::Forbidden().json(serde_json::json!({
+                    "error": "Confirm your identity to continue",
+                    "sudo_required": true,
+                })),
+            )),
+        };
+        ready(result.map_err(Into::into))
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// One of these, checked in this order.
+#[derive(Deserialize)]
+pub struct SudoPayload {
+    pub password: Option<String>,
+    pub code: Option<String>,
+    pub passkey: Option<AssertionCredential>,
+    /// A Discord user token of the linked account...
+    pub discord_token: Option<String>,
+    /// ...or an OAuth2 authorization of it.
+    pub discord_oauth: Option<OAuthCallbackPayload>,
+}
+
+/// Whether the account's Discord login can be checked again.
+fn discord_reauth_available() -> bool {
+    let mode = crate::discord_oauth::link_mode();
+    mode.allows_user_token()
+        || (mode.allows_oauth2() && crate::discord_oauth::client_id().is_some() && crate::discord_oauth::redirect_uri().is_some())
+}
+
+/// GET /api/auth/sudo — Whether sudo mode is active and which methods the account can use
+pub async fn get_status(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let account: Option<(bool, Option<String>)> = sqlx::query_as("SELECT password_set, discord_id FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let (password_set, discord_id) = account.unwrap_or((false, None));
+    let mut methods = Vec::new();
+    if password_set {
+        methods.push("password");
+    }
+    if crate::totp::is_enabled(pool.get_ref(), &claims.sub).await {
+        methods.push("totp");
+    }
+    let passkeys: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM passkeys WHERE user_id = ?")
+        .bind(&claims.sub)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if passkeys > 0 {
+        methods.push("passkey");
+    }
+    if discord_id.is_some() && discord_reauth_available() {
+        methods.push("discord");
+    }
+
+    let active_until = claims.sudo_until.filter(|until| *until > now());
+    HttpResponse::Ok().json(serde_json::json!({
+        "active": active_until.is_some(),
+        "expires_at": active_until,
+        "methods": methods,
+    }))
+}
+
+/// POST /api/auth/sudo — Re-authenticate and get an access token in sudo mode
+pub async fn enter(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    ceremonies: web::Data<PasskeyCeremonies>,
+    oauth_states: web::Data<OAuthStates>,
+    body: web::Json<SudoPayload>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let confirmed = if let Some(password) = body.password.as_deref() {
+        let hash: Option<String> = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+            .bind(&claims.sub)
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None);
+        let known = hash.is_some_and(|hash| verify(password, &hash).unwrap_or(false));
+        if known {
+            crate::auth::mark_password_set(pool.get_ref(), &claims.sub).await;
+        }
+        known
+    } else if let Some(code) = body.code.as_deref() {
+        crate::totp::is_enabled(pool.get_ref(), &claims.sub).await
+            && crate::totp::verify_code(pool.get_ref(), &claims.sub, code, true).await
+    } else if let Some(credential) = body.passkey.as_ref() {
+        match verify_assertion(pool.get_ref(), ceremonies.get_ref(), credential).await {
+            Ok(user_id) => user_id == claims.sub,
+            Err(response) => return response,
+        }
+    } else if body.discord_token.is_some() || body.discord_oauth.is_some() {
+        let discord_id = match (body.discord_token.as_deref(), body.discord_oauth.as_ref()) {
+            (Some(token), _) => {
+                if !crate::discord_oauth::link_mode().allows_user_token() {
+                    return crate::discord_oauth::user_token_disabled();
+                }
+                crate::auth::fetch_discord_user(token.trim()).await.ok().map(|user| user.id)
+            }
+            (None, Some(authorization)) => {
+                match crate::discord_oauth::authorized_discord_id(oauth_states.get_ref(), &claims.sub, authorization).await {
+                    Ok(discord_id) => Some(discord_id),
+                    Err(response) => return response,
+                }
+            }
+            (None, None) => None,
+        };
+        let linked: Option<String> = sqlx::query_scalar("SELECT discord_id FROM users WHERE id = ?")
+            .bind(&claims.sub)
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None)
+            .flatten();
+        discord_id.is_some() && linked == discord_id
+    } else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "password, code, passkey, discord_token or discord_oauth is required"
+        }));
+    };
+
+    if !confirmed {
+        return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Could not confirm your identity" }));
+    }
+
+    let issued_at = chrono::Utc::now();
+    let sudo_until = (issued_at + chrono::Duration::minutes(SUDO_TTL_MINUTES)).timestamp() as usize;
+    let token = sign_claims(&Claims {
+        exp: (issued_at + chrono::Duration::minutes(ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize,
+        sudo_until: Some(sudo_until),
+        ..claims
+    });
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "token": token,
+        "sudo_until": sudo_until,
+    }))
+}
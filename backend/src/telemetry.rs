@@ -0,0 +1,331 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Request tracing
+// ═══════════════════════════════════════════════════════
+//
+// Every request runs inside a span: the request ID `jobs::request_id`
+// assigned, a trace ID (the caller's W3C `traceparent` when it sends one),
+// the matched route and the status. While a handler runs,
+// `current_request_id()` returns its ID, so log lines written on its behalf
+// carry it: the `[discord-gw]` session log tags the lines of a voice join
+// with the request waiting for it.
+//
+// Error responses (4xx/5xx) with a JSON object body get a `request_id`
+// field, next to the `X-Request-Id` header every response has, so a user
+// reporting an error can quote it. Responses carry a `traceparent` for the
+// span. 5xx responses are logged to stderr with their request ID.
+//
+// With `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are exported in batches to
+// an OpenTelemetry collector (OTLP over HTTP, JSON encoding). Spans that
+// can't keep up (full queue, collector down) are dropped.
+//
+// Config (env):
+//   OTEL_EXPORTER_OTLP_ENDPOINT          collector base URL, e.g. http://127.0.0.1:4318
+//   OTEL_EXPORTER_OTLP_TRACES_ENDPOINT   full traces URL (default <endpoint>/v1/traces)
+//   OTEL_EXPORTER_OTLP_HEADERS           extra headers, key=value,key=value
+//   OTEL_SERVICE_NAME                    service.name resource (default voxium-backend)
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use actix_web::middleware::Next;
+use actix_web::HttpResponse;
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+const TRACEPARENT_HEADER: &str = "traceparent";
+const DEFAULT_SERVICE_NAME: &str = "voxium-backend";
+/// Spans waiting for the exporter; past this, new ones are dropped.
+const QUEUE_CAPACITY: usize = 4096;
+const BATCH_SIZE: usize = 256;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+struct RequestContext {
+    request_id: String,
+}
+
+tokio::task_local! {
+    static REQUEST: RequestContext;
+}
+
+/// The ID of the request being handled, when called from its handler.
+pub fn current_request_id() -> Option<String> {
+    REQUEST.try_with(|r| r.request_id.clone()).ok()
+}
+
+// ── Trace context ───────────────────────────────────────
+
+/// `(trace_id, parent_span_id)` of a valid W3C `traceparent`.
+pub fn parse_traceparent(raw: &str) -> Option<(String, String)> {
+    let mut parts = raw.trim().split('-');
+    let (version, trace_id, parent_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase());
+    let valid = parts.next().is_none()
+        && hex(version, 2)
+        && version != "ff"
+        && hex(trace_id, 32)
+        && hex(parent_id, 16)
+        && hex(flags, 2)
+        && trace_id.chars().any(|c| c != '0')
+        && parent_id.chars().any(|c| c != '0');
+    valid.then(|| (trace_id.to_string(), parent_id.to_string()))
+}
+
+fn new_trace_id() -> String {
+    format!("{:032x}", rand::random::<u128>().max(1))
+}
+
+fn new_span_id() -> String {
+    format!("{:016x}", rand::random::<u64>().max(1))
+}
+
+fn unix_nanos(at: SystemTime) -> u128 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+/// A finished request span, ready for export.
+#[derive(Debug)]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start: SystemTime,
+    duration: Duration,
+    method: String,
+    route: String,
+    status: u16,
+    request_id: String,
+}
+
+impl Span {
+    fn to_otlp(&self) -> Value {
+        let start = unix_nanos(self.start);
+        let text = |key: &str, value: &str| json!({ "key": key, "value": { "stringValue": value } });
+        let mut span = json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": self.name,
+            // SPAN_KIND_SERVER
+            "kind": 2,
+            "startTimeUnixNano": start.to_string(),
+            "endTimeUnixNano": (start + self.duration.as_nanos()).to_string(),
+            "attributes": [
+                text("http.request.method", &self.method),
+                text("http.route", &self.route),
+                json!({ "key": "http.response.status_code", "value": { "intValue": self.status.to_string() } }),
+                text("voxium.request_id", &self.request_id),
+            ],
+            // STATUS_CODE_ERROR for server errors, UNSET otherwise
+            "status": { "code": if self.status >= 500 { 2 } else { 0 } },
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(parent);
+        }
+        span
+    }
+}
+
+// ── Middleware ──────────────────────────────────────────
+
+/// Wrapped inside `jobs::request_id` (which assigns the ID) with
+/// `middleware::from_fn`.
+pub async fn trace<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let request_id = crate::jobs::request_id_of(req.request()).unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let (trace_id, parent_span_id) = match req.headers().get(TRACEPARENT_HEADER).and_then(|v| v.to_str().ok()).and_then(parse_traceparent) {
+        Some((trace_id, parent)) => (trace_id, Some(parent)),
+        None => (new_trace_id(), None),
+    };
+    let span_id = new_span_id();
+    let method = req.method().to_string();
+    let start = SystemTime::now();
+    let started = Instant::now();
+
+    // No clone of the request may outlive this point: routing needs it
+    // unshared. An error from the inner middlewares drops it, so its
+    // response is built here and traced like any other.
+    let context = RequestContext { request_id: request_id.clone() };
+    let (http_req, res) = match REQUEST.scope(context, next.call(req)).await {
+        Ok(res) => {
+            let (http_req, res) = res.map_into_boxed_body().into_parts();
+            (Ok(http_req), res)
+        }
+        Err(error) => {
+            let res = error.error_response();
+            (Err(error), res)
+        }
+    };
+    let duration = started.elapsed();
+
+    let status = res.status();
+    let route = http_req
+        .as_ref()
+        .ok()
+        .and_then(|r| r.match_pattern())
+        .unwrap_or_else(|| "unmatched".to_string());
+    if status.is_server_error() {
+        eprintln!("[req {request_id}] {method} {route} -> {} in {} ms", status.as_u16(), duration.as_millis());
+    }
+    let mut res = if status.is_client_error() || status.is_server_error() {
+        stamp_error(res, &request_id).await
+    } else {
+        res
+    };
+    if let Ok(value) = HeaderValue::from_str(&format!("00-{trace_id}-{span_id}-01")) {
+        res.headers_mut().insert(HeaderName::from_static(TRACEPARENT_HEADER), value);
+    }
+
+    export(Span {
+        trace_id,
+        span_id,
+        parent_span_id,
+        name: format!("{method} {route}"),
+        start,
+        duration,
+        method,
+        route,
+        status: status.as_u16(),
+        request_id: request_id.clone(),
+    });
+    match http_req {
+        Ok(http_req) => Ok(ServiceResponse::new(http_req, res)),
+        // `jobs::request_id` only tags responses, this one travels as the error.
+        Err(error) => {
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut().insert(HeaderName::from_static(crate::jobs::REQUEST_ID_HEADER), value);
+            }
+            Err(InternalError::from_response(error.to_string(), res).into())
+        }
+    }
+}
+
+/// Add `request_id` to a JSON object body that doesn't have one.
+async fn stamp_error(res: HttpResponse<BoxBody>, request_id: &str) -> HttpResponse<BoxBody> {
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        return res;
+    }
+    let (res, body) = res.into_parts();
+    let Ok(bytes) = actix_web::body::to_bytes(body).await else {
+        return res.set_body(BoxBody::new(()));
+    };
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut object)) if !object.contains_key("request_id") => {
+            object.insert("request_id".to_string(), json!(request_id));
+            BoxBody::new(Value::Object(object).to_string())
+        }
+        _ => BoxBody::new(bytes),
+    };
+    res.set_body(body)
+}
+
+// ── OTLP export ─────────────────────────────────────────
+
+static EXPORTER: OnceLock<mpsc::Sender<Span>> = OnceLock::new();
+
+fn export(span: Span) {
+    if let Some(queue) = EXPORTER.get() {
+        let _ = queue.try_send(span);
+    }
+}
+
+fn traces_endpoint() -> Option<String> {
+    let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+        .or_else(|| var("OTEL_EXPORTER_OTLP_ENDPOINT").map(|base| format!("{}/v1/traces", base.trim_end_matches('/'))))
+}
+
+/// `key=value,key=value`, as in `OTEL_EXPORTER_OTLP_HEADERS`.
+fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| {
+            let value = urlencoding::decode(value.trim()).map(|v| v.into_owned()).unwrap_or_else(|_| value.trim().to_string());
+            (key.trim().to_string(), value)
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+async fn send_batch(client: &reqwest::Client, endpoint: &str, headers: &[(String, String)], service: &str, spans: &[Span]) -> Result<(), String> {
+    let body = json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": service } }] },
+            "scopeSpans": [{
+                "scope": { "name": "voxium" },
+                "spans": spans.iter().map(Span::to_otlp).collect::<Vec<_>>(),
+            }],
+        }],
+    });
+    let mut request = client.post(endpoint).json(&body);
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("collector answered {}", response.status()));
+    }
+    Ok(())
+}
+
+/// Start exporting spans when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+pub fn spawn_exporter() {
+    let Some(endpoint) = traces_endpoint() else {
+        return;
+    };
+    let headers = std::env::var("OTEL_EXPORTER_OTLP_HEADERS").map(|raw| parse_headers(&raw)).unwrap_or_default();
+    let service = std::env::var("OTEL_SERVICE_NAME")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+    let (queue, mut spans) = mpsc::channel(QUEUE_CAPACITY);
+    if EXPORTER.set(queue).is_err() {
+        return;
+    }
+    println!("🔭 Exporting request spans to {endpoint}");
+
+    actix_web::rt::spawn(async move {
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default();
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        // Only the first failure of a streak is logged.
+        let mut failing = false;
+        loop {
+            tokio::select! {
+                span = spans.recv() => match span {
+                    Some(span) => {
+                        batch.push(span);
+                        if batch.len() < BATCH_SIZE {
+                            continue;
+                        }
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    if batch.is_empty() {
+                        continue;
+                    }
+                }
+            }
+            match send_batch(&client, &endpoint, &headers, &service, &batch).await {
+                Ok(()) => failing = false,
+                Err(e) if !failing => {
+                    eprintln!("⚠️ Span export failed, dropping spans until the collector is back: {e}");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+            batch.clear();
+        }
+    });
+}
@@ -0,0 +1,256 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Test fixtures (in-memory database, factories, test app)
+// ═══════════════════════════════════════════════════════
+//
+// Handler tests under `backend/tests/` run against the real routes and a
+// private SQLite database that lives in memory with every migration applied.
+// Each `memory_pool()` is a distinct database, so tests never share rows and
+// can run in parallel.
+//
+// Factories insert rows directly, skipping the validation of the matching
+// endpoints, and give back what a test needs to act as that row: a user
+// comes with a bearer token signed for it. Tokens carry no session id, so no
+// session row is needed and revocation checks are skipped.
+//
+// Each `test_state()` also gets a file store in a temporary directory of its
+// own, so tests never read each other's files and never change the
+// process environment to point at one.
+//
+// `init_app` builds the app from `AppState` and `routes`, the same as the
+// server, without the CORS and rate-limiting middlewares; no background job
+// is started. `start_server` serves that app on a local port instead, for
+// tests that need a real connection (WebSocket upgrades).
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, App, HttpServer};
+use chrono::Utc;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Once};
+use uuid::Uuid;
+
+use crate::auth::{create_token, sign_claims, Claims};
+use crate::files::FileStore;
+use crate::AppState;
+
+/// Secret the fixtures sign tokens with, set once per test binary unless `JWT_SECRET` already is.
+pub const TEST_JWT_SECRET: &str = "voxium-test-secret";
+
+static INIT: Once = Once::new();
+
+fn init_env() {
+    INIT.call_once(|| {
+        if std::env::var("JWT_SECRET").is_err() {
+            std::env::set_var("JWT_SECRET", TEST_JWT_SECRET);
+        }
+    });
+}
+
+// ── Database ────────────────────────────────────────────
+
+/// A fresh in-memory database with all migrations applied.
+pub async fn memory_pool() -> SqlitePool {
+    init_env();
+    // A named shared-cache database lives as long as one connection to it
+    // does: keep one open for the lifetime of the pool.
+    let url = format!("sqlite:file:voxium-test-{}?mode=memory&cache=shared", Uuid::new_v4());
+    let options = SqliteConnectOptions::from_str(&url).expect("valid test database url");
+    let pool = SqlitePoolOptions::new()
+        .max_connections(4)
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .expect("in-memory database");
+    crate::db::run_migrations(&pool).await.expect("migrations apply");
+    pool
+}
+
+/// Fresh state around a new in-memory database, storing files in a
+/// directory of its own (see `storage_dir`).
+pub async fn test_state() -> AppState {
+    let mut state = AppState::new(memory_pool().await);
+    let dir = std::env::temp_dir().join(format!("voxium-test-files-{}", Uuid::new_v4()));
+    state.file_storage = Arc::new(FileStore::Disk(dir));
+    state
+}
+
+/// The directory `state` stores files in, to inspect or remove.
+pub fn storage_dir(state: &AppState) -> PathBuf {
+    match state.file_storage.as_ref() {
+        FileStore::Disk(root) => root.clone(),
+        FileStore::S3(_) => panic!("test state stores files in S3"),
+    }
+}
+
+// ── Test app ────────────────────────────────────────────
+
+/// The app with every route, reading `state`.
+pub async fn init_app(
+    state: &AppState,
+) -> impl Service<actix_http::Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error> {
+    let state = state.clone();
+    test::init_service(App::new().configure(|cfg| state.register(cfg)).configure(crate::routes)).await
+}
+
+/// The app of `init_app`, served on a free local port until the test ends.
+/// Returns its address.
+pub fn start_server(state: &AppState) -> SocketAddr {
+    let state = state.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").expect("free local port");
+    let addr = listener.local_addr().expect("bound address");
+    let server = HttpServer::new(move || {
+        let state = state.clone();
+        App::new().configure(move |cfg| state.register(cfg)).configure(crate::routes)
+    })
+    .workers(1)
+    .disable_signals()
+    .listen(listener)
+    .expect("test server")
+    .run();
+    actix_web::rt::spawn(server);
+    addr
+}
+
+/// Send `req` and read the answer as JSON (`Null` for an empty or non-JSON body).
+pub async fn call_json<S, B>(app: &S, req: test::TestRequest) -> (StatusCode, serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let res = test::call_service(app, req.to_request()).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+    (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+}
+
+/// Send `req` and read the answer as text.
+pub async fn call_text<S, B>(app: &S, req: test::TestRequest) -> (StatusCode, String)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let res = test::call_service(app, req.to_request()).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Send `req` to a paged listing: the JSON answer and its `X-Next-Cursor`, if any.
+pub async fn call_page<S, B>(app: &S, req: test::TestRequest) -> (StatusCode, serde_json::Value, Option<String>)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let res = test::call_service(app, req.to_request()).await;
+    let status = res.status();
+    let next = res
+        .headers()
+        .get(crate::pagination::NEXT_CURSOR_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let body = test::read_body(res).await;
+    (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null), next)
+}
+
+// ── Factories ───────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct TestUser {
+    pub id: String,
+    pub username: String,
+    pub role: String,
+    /// Bearer token for this user.
+    pub token: String,
+}
+
+impl TestUser {
+    /// `Authorization` header acting as this user.
+    pub fn auth(&self) -> (header::HeaderName, String) {
+        (header::AUTHORIZATION, format!("Bearer {}", self.token))
+    }
+
+    /// `req` sent as this user.
+    pub fn sign(&self, req: test::TestRequest) -> test::TestRequest {
+        req.insert_header(self.auth())
+    }
+
+    /// `req` sent as this user in sudo mode, as after `POST /api/auth/sudo`.
+    pub fn sign_sudo(&self, req: test::TestRequest) -> test::TestRequest {
+        let now = Utc::now().timestamp() as usize;
+        let claims = Claims {
+            sub: self.id.clone(),
+            username: self.username.clone(),
+            role: self.role.clone(),
+            exp: now + 600,
+            sid: String::new(),
+            sudo_until: Some(now + 300),
+        };
+        req.insert_header((header::AUTHORIZATION, format!("Bearer {}", sign_claims(&claims))))
+    }
+}
+
+/// A user with `role` (`user`, `admin` or a server role) and no password.
+pub async fn create_user(pool: &SqlitePool, username: &str, role: &str) -> TestUser {
+    init_env();
+    let id = Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES (?, ?, '!', ?)")
+        .bind(&id)
+        .bind(username)
+        .bind(role)
+        .execute(pool)
+        .await
+        .expect("insert test user");
+    TestUser {
+        token: create_token(&id, username, role, ""),
+        id,
+        username: username.to_string(),
+        role: role.to_string(),
+    }
+}
+
+/// A text room readable by `required_role` (`user` for everyone). Returns its id.
+pub async fn create_room(pool: &SqlitePool, name: &str, required_role: &str) -> String {
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO rooms (id, name, kind, required_role, position) \
+         VALUES (?, ?, 'text', ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM rooms WHERE category_id IS NULL))"
+    )
+    .bind(&id)
+    .bind(name)
+    .bind(required_role)
+    .execute(pool)
+    .await
+    .expect("insert test room");
+    id
+}
+
+/// A message from `author` in `room_id`, timestamped now. Returns its id.
+pub async fn create_message(pool: &SqlitePool, room_id: &str, author: &TestUser, content: &str) -> String {
+    let id = Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO messages (id, room_id, user_id, username, content, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(&id)
+        .bind(room_id)
+        .bind(&author.id)
+        .bind(&author.username)
+        .bind(content)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .expect("insert test message");
+    id
+}
+
+/// `count` messages from `author` in `room_id`, oldest first, with contents `{prefix} {n}`.
+pub async fn create_messages(pool: &SqlitePool, room_id: &str, author: &TestUser, prefix: &str, count: usize) -> Vec<String> {
+    let mut ids = Vec::with_capacity(count);
+    for n in 0..count {
+        ids.push(create_message(pool, room_id, author, &format!("{prefix} {n}")).await);
+    }
+    ids
+}
@@ -0,0 +1,255 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Timeouts (temporary mutes)
+// ═══════════════════════════════════════════════════════
+//
+// A timed out member stays signed in and keeps reading, but until the
+// timeout expires or is lifted they cannot:
+//   - post (WebSocket and gateway messages, forum posts, polls);
+//   - join a voice room.
+// Moderators (MANAGE_MEMBERS) give one for `MIN_SECONDS` to `MAX_SECONDS`,
+// with a reason, and lift it; automod `timeout` rules give one too, without
+// a moderator (see `automod`). A new timeout replaces the one in force.
+//
+// Giving one posts a system message (`kind: member_timed_out`) in the room
+// it was given from, when there is one, and writes the audit log. A refused
+// message gets a `timed_out` event back on the sending WebSocket
+// connection: `{ type, room_id, timed_out_until }`; HTTP endpoints that
+// post answer 403 with the same `timed_out_until`. Past timeouts stay in
+// `timeouts` as the member's record.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::auth::{extract_claims, Claims};
+use crate::ws::Broadcaster;
+
+const MIN_SECONDS: i64 = 60;
+pub(crate) const MAX_SECONDS: i64 = 28 * 24 * 3600;
+const MAX_REASON_CHARS: usize = 512;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Timeout {
+    pub id: String,
+    pub user_id: String,
+    pub reason: Option<String>,
+    /// None when given by automod.
+    pub created_by_username: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+}
+
+/// A refused post or voice join: the author is timed out until `until`.
+#[derive(Debug, Clone)]
+pub struct TimedOut {
+    pub until: String,
+}
+
+impl TimedOut {
+    /// The structured 403 for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "You are timed out",
+            "timed_out_until": self.until,
+        }))
+    }
+
+    /// The `timed_out` event for the WebSocket connection that was refused.
+    pub fn event(&self, room_id: &str) -> String {
+        serde_json::json!({
+            "type": "timed_out",
+            "room_id": room_id,
+            "timed_out_until": self.until,
+        })
+        .to_string()
+    }
+}
+
+/// Whether `user_id` may post and join voice, or until when they are timed out.
+pub(crate) async fn check(pool: &SqlitePool, user_id: &str) -> Result<(), TimedOut> {
+    let until: Option<String> = sqlx::query_scalar(
+        "SELECT MAX(expires_at) FROM timeouts WHERE user_id = ? AND lifted_at IS NULL AND expires_at > ?"
+    )
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+    .unwrap_or(None);
+    match until {
+        Some(until) => Err(TimedOut { until }),
+        None => Ok(()),
+    }
+}
+
+/// "10 min", "2 h", "3 d".
+fn duration_label(seconds: i64) -> String {
+    match seconds {
+        s if s % 86_400 == 0 => format!("{} d", s / 86_400),
+        s if s % 3600 == 0 => format!("{} h", s / 3600),
+        s => format!("{} min", (s + 59) / 60),
+    }
+}
+
+/// Time `user_id` out for `seconds`, replacing the timeout in force. `by` is
+/// the moderator, None for automod; `room_id` the room it was given from.
+pub(crate) async fn apply(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    user_id: &str,
+    seconds: i64,
+    reason: Option<&str>,
+    by: Option<&Claims>,
+    room_id: Option<&str>,
+) -> Result<Timeout, sqlx::Error> {
+    let now = Utc::now();
+    let timeout = Timeout {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        reason: reason.map(str::to_string),
+        created_by_username: by.map(|c| c.username.clone()),
+        created_at: now.to_rfc3339(),
+        expires_at: (now + chrono::Duration::seconds(seconds)).to_rfc3339(),
+    };
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE timeouts SET lifted_at = ?, lifted_by_username = ? WHERE user_id = ? AND lifted_at IS NULL AND expires_at > ?")
+        .bind(&timeout.created_at)
+        .bind(&timeout.created_by_username)
+        .bind(user_id)
+        .bind(&timeout.created_at)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO timeouts (id, user_id, reason, created_by, created_by_username, created_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&timeout.id)
+    .bind(user_id)
+    .bind(&timeout.reason)
+    .bind(by.map(|c| c.sub.as_str()))
+    .bind(&timeout.created_by_username)
+    .bind(&timeout.created_at)
+    .bind(&timeout.expires_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    crate::audit_log::record(
+        pool,
+        by,
+        crate::audit_log::Action::MemberTimedOut,
+        user_id,
+        serde_json::json!({ "timed_out_until": timeout.expires_at, "duration_seconds": seconds, "room_id": room_id }),
+        reason,
+    )
+    .await;
+
+    if let Some(room_id) = room_id {
+        let username: String = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None)
+            .unwrap_or_default();
+        let duration = duration_label(seconds);
+        // By a moderator, the message is theirs; by automod, the member's.
+        let (author_id, author_name, content) = match by {
+            Some(claims) => (claims.sub.as_str(), claims.username.as_str(), format!("timed out {username} for {duration}")),
+            None => (user_id, username.as_str(), format!("was timed out by automod for {duration}")),
+        };
+        let content = match reason.filter(|_| by.is_some()) {
+            Some(reason) => format!("{content}: {reason}"),
+            None => content,
+        };
+        crate::rooms::post_system_message(pool, broadcaster, room_id, author_id, author_name, "member_timed_out", &content).await;
+    }
+    Ok(timeout)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct TimeoutRequest {
+    pub duration_seconds: i64,
+    pub reason: Option<String>,
+    /// The room to announce it in.
+    pub room_id: Option<String>,
+}
+
+/// POST /api/users/{id}/timeout — Time a member out (MANAGE_MEMBERS)
+pub async fn give(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<TimeoutRequest>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let user_id = path.into_inner();
+    if let Err(response) = crate::bans::moderatable(pool.get_ref(), &claims, &user_id).await {
+        return response;
+    }
+    if !(MIN_SECONDS..=MAX_SECONDS).contains(&body.duration_seconds) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("duration_seconds must be between {MIN_SECONDS} and {MAX_SECONDS}")
+        }));
+    }
+    let reason = match body.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()) {
+        Some(r) if r.chars().count() > MAX_REASON_CHARS => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Reason must be at most 512 chars" }));
+        }
+        Some(r) => Some(r.to_string()),
+        None => crate::audit_log::reason(&req),
+    };
+    if let Some(room_id) = &body.room_id {
+        if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), room_id, &claims).await {
+            return response;
+        }
+    }
+
+    match apply(pool.get_ref(), broadcaster.get_ref(), &user_id, body.duration_seconds, reason.as_deref(), Some(&claims), body.room_id.as_deref()).await {
+        Ok(timeout) => HttpResponse::Created().json(timeout),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/users/{id}/timeout — Let a timed out member post again (MANAGE_MEMBERS)
+pub async fn lift(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+    let user_id = path.into_inner();
+    let now = Utc::now().to_rfc3339();
+    let lifted = sqlx::query("UPDATE timeouts SET lifted_at = ?, lifted_by_username = ? WHERE user_id = ? AND lifted_at IS NULL AND expires_at > ?")
+        .bind(&now)
+        .bind(&claims.username)
+        .bind(&user_id)
+        .bind(&now)
+        .execute(pool.get_ref())
+        .await;
+    match lifted {
+        Ok(res) if res.rows_affected() > 0 => {
+            crate::audit_log::record(
+                pool.get_ref(),
+                Some(&claims),
+                crate::audit_log::Action::MemberTimeoutRemoved,
+                &user_id,
+                serde_json::json!({}),
+                crate::audit_log::reason(&req).as_deref(),
+            )
+            .await;
+            HttpResponse::NoContent().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "This member is not timed out" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,522 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — TOTP two-factor authentication
+// ═══════════════════════════════════════════════════════
+//
+// RFC 6238 codes (HMAC-SHA1, 30 s steps, 6 digits) as a second step of the
+// password login. Enrollment is two-phase: `setup` stores a new secret and
+// returns it as an otpauth:// QR code, `enable` turns it on once the user
+// proved their app produces valid codes, and hands out backup codes.
+//
+// When 2FA is on, `POST /api/login` does not return tokens but a short-lived
+// ticket that `POST /api/auth/2fa/login` exchanges for them together with a
+// TOTP or backup code. Secrets are stored encrypted (see `crypto`), backup
+// codes only as SHA-256 hashes. Admins can require 2FA per account: such
+// users are told to enroll at login and cannot turn it off.
+//
+// Passkey and Discord logins are not affected, both already prove more
+// than a password does.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine};
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::extract_claims;
+use crate::crypto::{decrypt_token, encrypt_token};
+use crate::sessions::DeviceInfo;
+use crate::sudo::Sudo;
+
+const ISSUER: &str = "Voxium";
+const SECRET_BYTES: usize = 20;
+const STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+/// Steps accepted on each side of the current one, for clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+
+const BACKUP_CODE_COUNT: usize = 10;
+const BACKUP_CODE_LEN: usize = 8;
+/// No 0/O or 1/I, backup codes get typed from paper.
+const BACKUP_CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// How long the password step of a login stays valid.
+const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Wrong codes allowed per login ticket before it is dropped.
+const MAX_ATTEMPTS: u32 = 5;
+
+// ── Login challenges ────────────────────────────────────
+
+pub struct PendingLogin {
+    user_id: String,
+    attempts: u32,
+    created_at: Instant,
+}
+
+/// Password logins waiting for their second factor, keyed by ticket.
+pub type TwoFactorChallenges = Arc<Mutex<HashMap<String, PendingLogin>>>;
+
+pub fn create_two_factor_challenges() -> TwoFactorChallenges {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Called by the password login once the password checked out.
+pub(crate) fn start_login(challenges: &TwoFactorChallenges, user_id: &str) -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let ticket = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+
+    let mut map = challenges.lock().unwrap();
+    map.retain(|_, c| c.created_at.elapsed() < CHALLENGE_TTL);
+    map.insert(
+        ticket.clone(),
+        PendingLogin {
+            user_id: user_id.to_string(),
+            attempts: 0,
+            created_at: Instant::now(),
+        },
+    );
+    ticket
+}
+
+// ── Request types ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CodePayload {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLogin {
+    pub ticket: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorRequirement {
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub required: bool,
+    pub backup_codes_remaining: i64,
+}
+
+// ── Codes ───────────────────────────────────────────────
+
+/// RFC 4648 base32 without padding, the format authenticator apps expect.
+fn base32_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn current_step() -> i64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    (now / STEP_SECONDS) as i64
+}
+
+fn totp_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]]) & 0x7fff_ffff;
+    value % 10u32.pow(DIGITS)
+}
+
+/// The time step `code` is valid for, if any within the drift window.
+fn matching_step(secret: &[u8], code: &str) -> Option<i64> {
+    let code: u32 = code.parse().ok()?;
+    let now = current_step();
+    (now - ALLOWED_DRIFT..=now + ALLOWED_DRIFT).find(|&step| totp_at(secret, step) == code)
+}
+
+fn otpauth_url(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        issuer = urlencoding::encode(ISSUER),
+        user = urlencoding::encode(username),
+    )
+}
+
+/// Uppercase without separators, so "abcd-efgh" matches "ABCDEFGH".
+pub(crate) fn normalize_code(raw: &str) -> String {
+    raw.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+pub(crate) fn hash_backup_code(code: &str) -> String {
+    Sha256::digest(code.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+pub(crate) fn generate_backup_code() -> String {
+    let mut rng = rand::rngs::OsRng;
+    (0..BACKUP_CODE_LEN)
+        .map(|_| BACKUP_CODE_ALPHABET[rng.gen_range(0..BACKUP_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Replace the user's backup codes. Returns them formatted `XXXX-XXXX`,
+/// the only time they are ever readable.
+async fn regenerate_backup_codes(pool: &SqlitePool, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let codes: Vec<String> = (0..BACKUP_CODE_COUNT).map(|_| generate_backup_code()).collect();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM totp_backup_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    for code in &codes {
+        sqlx::query("INSERT INTO totp_backup_codes (code_hash, user_id, created_at) VALUES (?, ?, ?)")
+            .bind(hash_backup_code(code))
+            .bind(user_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(codes.iter().map(|c| format_backup_code(c)).collect())
+}
+
+/// `XXXX-XXXX`, easier to copy down.
+pub(crate) fn format_backup_code(code: &str) -> String {
+    format!("{}-{}", &code[..BACKUP_CODE_LEN / 2], &code[BACKUP_CODE_LEN / 2..])
+}
+
+/// The user's decrypted secret, enabled or still pending.
+async fn load_secret(pool: &SqlitePool, user_id: &str) -> Option<Vec<u8>> {
+    let encrypted: Option<String> = sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    let secret = decrypt_token(&encrypted?)?;
+    general_purpose::STANDARD.decode(secret).ok()
+}
+
+/// Check a TOTP code and consume its time step, so the same code cannot be
+/// used twice. `SET ... WHERE last_step < ?` keeps this atomic.
+async fn check_totp(pool: &SqlitePool, user_id: &str, code: &str) -> bool {
+    let Some(secret) = load_secret(pool, user_id).await else {
+        return false;
+    };
+    let Some(step) = matching_step(&secret, code) else {
+        return false;
+    };
+    sqlx::query("UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)")
+        .bind(step)
+        .bind(user_id)
+        .bind(step)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected() == 1)
+        .unwrap_or(false)
+}
+
+/// Mark an unused backup code as used. Each one works once.
+async fn check_backup_code(pool: &SqlitePool, user_id: &str, code: &str) -> bool {
+    sqlx::query("UPDATE totp_backup_codes SET used_at = ? WHERE code_hash = ? AND user_id = ? AND used_at IS NULL")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(hash_backup_code(code))
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected() == 1)
+        .unwrap_or(false)
+}
+
+/// A 6-digit TOTP code, or a backup code when `allow_backup` is set.
+pub(crate) async fn verify_code(pool: &SqlitePool, user_id: &str, raw: &str, allow_backup: bool) -> bool {
+    let code = normalize_code(raw);
+    if code.len() == DIGITS as usize && code.chars().all(|c| c.is_ascii_digit()) {
+        check_totp(pool, user_id, &code).await
+    } else if allow_backup && code.len() == BACKUP_CODE_LEN {
+        check_backup_code(pool, user_id, &code).await
+    } else {
+        false
+    }
+}
+
+pub(crate) async fn is_enabled(pool: &SqlitePool, user_id: &str) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT totp_enabled FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+        != 0
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/auth/2fa — Two-factor status of the current account
+pub async fn get_status(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let row = sqlx::query(
+        "SELECT totp_enabled, totp_required, (SELECT COUNT(*) FROM totp_backup_codes WHERE user_id = users.id AND used_at IS NULL) AS remaining FROM users WHERE id = ?"
+    )
+    .bind(&claims.sub)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    match row {
+        Ok(Some(row)) => HttpResponse::Ok().json(TwoFactorStatus {
+            enabled: row.get::<i64, _>("totp_enabled") != 0,
+            required: row.get::<i64, _>("totp_required") != 0,
+            backup_codes_remaining: row.get("remaining"),
+        }),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/auth/2fa/setup — Start enrollment: new secret + otpauth QR code
+pub async fn setup(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if is_enabled(pool.get_ref(), &claims.sub).await {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Two-factor authentication is already enabled" }));
+    }
+
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    let encoded = base32_encode(&secret);
+    let url = otpauth_url(&encoded, &claims.username);
+    let qr_code = match crate::remote_auth::generate_qr_data_uri(&url) {
+        Ok(qr) => qr,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    };
+
+    // Pending until `enable` confirms a first code, a new setup replaces it.
+    let stored = encrypt_token(&general_purpose::STANDARD.encode(secret));
+    let result = sqlx::query("UPDATE users SET totp_secret = ?, totp_last_step = NULL WHERE id = ? AND totp_enabled = 0")
+        .bind(&stored)
+        .bind(&claims.sub)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(serde_json::json!({
+            "secret": encoded,
+            "otpauth_url": url,
+            "qr_code": qr_code,
+        })),
+        Ok(_) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Two-factor authentication is already enabled" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/auth/2fa/enable — Confirm enrollment with a first code, returns backup codes
+pub async fn enable(req: HttpRequest, pool: web::Data<SqlitePool>, body: web::Json<CodePayload>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if is_enabled(pool.get_ref(), &claims.sub).await {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "Two-factor authentication is already enabled" }));
+    }
+    if load_secret(pool.get_ref(), &claims.sub).await.is_none() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Call /api/auth/2fa/setup first" }));
+    }
+    if !verify_code(pool.get_ref(), &claims.sub, &body.code, false).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid code" }));
+    }
+
+    let _ = sqlx::query("UPDATE users SET totp_enabled = 1 WHERE id = ?")
+        .bind(&claims.sub)
+        .execute(pool.get_ref())
+        .await;
+
+    match regenerate_backup_codes(pool.get_ref(), &claims.sub).await {
+        Ok(codes) => HttpResponse::Ok().json(serde_json::json!({ "enabled": true, "backup_codes": codes })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/auth/2fa/disable — Turn 2FA off (TOTP or backup code required)
+pub async fn disable(req: HttpRequest, pool: web::Data<SqlitePool>, body: web::Json<CodePayload>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let required: i64 = sqlx::query_scalar("SELECT totp_required FROM users WHERE id = ?")
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(0);
+    if required != 0 {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": "Two-factor authentication is required for this account" }));
+    }
+    if !is_enabled(pool.get_ref(), &claims.sub).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Two-factor authentication is not enabled" }));
+    }
+    if !verify_code(pool.get_ref(), &claims.sub, &body.code, true).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid code" }));
+    }
+
+    match clear_two_factor(pool.get_ref(), &claims.sub).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "enabled": false })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/auth/2fa/backup-codes — Replace backup codes (TOTP code required)
+pub async fn regenerate(req: HttpRequest, pool: web::Data<SqlitePool>, body: web::Json<CodePayload>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if !is_enabled(pool.get_ref(), &claims.sub).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Two-factor authentication is not enabled" }));
+    }
+    if !verify_code(pool.get_ref(), &claims.sub, &body.code, false).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid code" }));
+    }
+
+    match regenerate_backup_codes(pool.get_ref(), &claims.sub).await {
+        Ok(codes) => HttpResponse::Ok().json(serde_json::json!({ "backup_codes": codes })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// POST /api/auth/2fa/login — Second step of a password login
+pub async fn login(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    challenges: web::Data<TwoFactorChallenges>,
+    body: web::Json<TwoFactorLogin>,
+) -> HttpResponse {
+    let user_id = {
+        let map = challenges.lock().unwrap();
+        match map.get(&body.ticket) {
+            Some(c) if c.created_at.elapsed() < CHALLENGE_TTL => c.user_id.clone(),
+            _ => return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Login expired, sign in again" })),
+        }
+    };
+
+    if !verify_code(pool.get_ref(), &user_id, &body.code, true).await {
+        let mut map = challenges.lock().unwrap();
+        let exhausted = match map.get_mut(&body.ticket) {
+            Some(c) => {
+                c.attempts += 1;
+                c.attempts >= MAX_ATTEMPTS
+            }
+            None => true,
+        };
+        if exhausted {
+            map.remove(&body.ticket);
+            return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Too many invalid codes, sign in again" }));
+        }
+        return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Invalid code" }));
+    }
+
+    // Single use: a concurrent request with the same ticket loses here.
+    if challenges.lock().unwrap().remove(&body.ticket).is_none() {
+        return HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Login expired, sign in again" }));
+    }
+
+    match crate::webauthn::login_response(pool.get_ref(), &DeviceInfo::from_request(&req), &user_id).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": e })),
+    }
+}
+
+/// Drop the secret and backup codes, i.e. turn 2FA off.
+async fn clear_two_factor(pool: &SqlitePool, user_id: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE users SET totp_secret = NULL, totp_enabled = 0, totp_last_step = NULL WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM totp_backup_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// PATCH /api/users/{id}/2fa — Require 2FA for an account (MANAGE_MEMBERS)
+pub async fn set_requirement(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<TwoFactorRequirement>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+
+    let result = sqlx::query("UPDATE users SET totp_required = ? WHERE id = ?")
+        .bind(body.required as i64)
+        .bind(path.into_inner())
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected() > 0 => HttpResponse::Ok().json(serde_json::json!({ "required": body.required })),
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// DELETE /api/users/{id}/2fa — Reset 2FA of an account that lost its device (MANAGE_MEMBERS)
+pub async fn reset(Sudo(claims): Sudo, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+
+    match clear_two_factor(pool.get_ref(), &path.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "reset" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
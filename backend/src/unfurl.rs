@@ -0,0 +1,489 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Link previews (unfurling)
+// ═══════════════════════════════════════════════════════
+//
+// When a message containing http(s) URLs is sent or edited, it is flagged
+// (`messages.embeds_pending_at`) and a background worker fetches its first
+// `MAX_LINKS_PER_MESSAGE` links: OpenGraph tags and `<title>` from the page,
+// completed by its oEmbed endpoint when the page advertises one. The
+// previews are stored on the message (`messages.embeds`) and pushed with a
+// `message_embeds_updated` event. Links wrapped in `<...>` are not unfurled.
+//
+// Pages and oEmbed endpoints are fetched through `outbound`, which checks
+// them against SSRF (public addresses only, each redirect checked, bounded
+// bodies) and rate limits each site. A link whose site is rate limited
+// leaves its message pending for a later run.
+//
+// Previews are cached by URL in `link_previews`, failures included.
+//
+// Config (env): LINK_PREVIEWS (default on, `0` disables),
+//               LINK_PREVIEW_CACHE_HOURS (default 24),
+//               and the OUTBOUND_* settings of `outbound`
+
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::jobs::{Backlog, JobRegistry};
+use crate::outbound::{self, Policy};
+use crate::ws::Broadcaster;
+
+const MAX_LINKS_PER_MESSAGE: usize = 3;
+const MAX_TITLE_CHARS: usize = 256;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+const DEFAULT_CACHE_HOURS: i64 = 24;
+/// Messages unfurled per worker run.
+const BATCH_SIZE: i64 = 10;
+const UNFURL_INTERVAL: Duration = Duration::from_secs(2);
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; VoxiumBot/1.0; +link preview)";
+const PAGE_POLICY: Policy = Policy {
+    user_agent: USER_AGENT,
+    timeout: Duration::from_secs(5),
+    max_redirects: 3,
+    max_body_bytes: 512 * 1024,
+};
+const OEMBED_POLICY: Policy = Policy { max_body_bytes: 64 * 1024, ..PAGE_POLICY };
+
+pub(crate) fn enabled() -> bool {
+    !matches!(
+        std::env::var("LINK_PREVIEWS").as_deref().map(str::trim),
+        Ok("0") | Ok("false") | Ok("off")
+    )
+}
+
+fn cache_hours() -> i64 {
+    std::env::var("LINK_PREVIEW_CACHE_HOURS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n: &i64| *n > 0)
+        .unwrap_or(DEFAULT_CACHE_HOURS)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkEmbed {
+    /// The link as written in the message.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    /// When a re-check of a pinned message found the link dead (see
+    /// `pin_checks`); the preview is the last one that worked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broken_at: Option<String>,
+}
+
+/// Stored `messages.embeds`, empty when missing or unreadable.
+pub(crate) fn parse_embeds(raw: Option<String>) -> Vec<LinkEmbed> {
+    raw.and_then(|raw| serde_json::from_str(&raw).ok()).unwrap_or_default()
+}
+
+// ── Links ───────────────────────────────────────────────
+
+/// The http(s) URLs of `content` to unfurl, in order, without duplicates.
+pub(crate) fn extract_links(content: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for word in content.split_whitespace() {
+        // `<https://...>` suppresses the preview.
+        if word.starts_with('<') {
+            continue;
+        }
+        let Some(start) = word.find("https://").or_else(|| word.find("http://")) else {
+            continue;
+        };
+        let link = word[start..].trim_end_matches(|c: char| ".,;:!?)]}'\"*_~`>".contains(c));
+        let Ok(url) = Url::parse(link) else { continue };
+        if url.host_str().is_none() || links.iter().any(|l| l == link) {
+            continue;
+        }
+        links.push(link.to_string());
+        if links.len() == MAX_LINKS_PER_MESSAGE {
+            break;
+        }
+    }
+    links
+}
+
+/// Flag a message for unfurling after it was sent or edited. A message that
+/// lost its links is flagged too, so its old previews get cleared.
+pub(crate) async fn mark_pending(pool: &SqlitePool, message_id: &str, content: &str) {
+    if !enabled() {
+        return;
+    }
+    let has_links = !extract_links(content).is_empty();
+    let _ = sqlx::query("UPDATE messages SET embeds_pending_at = ? WHERE id = ? AND (? OR embeds IS NOT NULL)")
+        .bind(Utc::now().to_rfc3339())
+        .bind(message_id)
+        .bind(has_links)
+        .execute(pool)
+        .await;
+}
+
+// ── HTML parsing ────────────────────────────────────────
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(end) = rest.char_indices().take(12).find(|(_, c)| *c == ';').map(|(i, _)| i) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Attributes of a tag's inside (`meta property="og:title" content="..."`), names lowercased.
+fn tag_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        let name_end = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '/').unwrap_or(rest.len());
+        if name_end == 0 {
+            break;
+        }
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let end = after[1..].find(quote).map(|i| i + 1).unwrap_or(after.len());
+                    rest = after.get(end + 1..).unwrap_or("");
+                    &after[1..end]
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    rest = &after[end..];
+                    &after[..end]
+                }
+            }
+        } else {
+            ""
+        };
+        attributes.push((name, decode_entities(value)));
+    }
+    attributes
+}
+
+fn clean_text(text: &str, max_chars: usize) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(match text.char_indices().nth(max_chars) {
+        Some((cut, _)) => format!("{}…", text[..cut].trim_end()),
+        None => text,
+    })
+}
+
+/// Absolute http(s) URL for `href`, relative to the page.
+fn absolute_url(base: &Url, href: &str) -> Option<String> {
+    let url = base.join(href.trim()).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+struct PageMeta {
+    embed: LinkEmbed,
+    oembed_url: Option<String>,
+}
+
+fn parse_page(page_url: &Url, link: &str, html: &str) -> PageMeta {
+    let mut meta = std::collections::HashMap::new();
+    let mut oembed_url = None;
+    let mut title = None;
+    let lower = html.to_ascii_lowercase();
+
+    let mut at = 0;
+    while let Some(offset) = lower[at..].find('<') {
+        let start = at + offset + 1;
+        let Some(len) = lower[start..].find('>') else { break };
+        let end = start + len;
+        at = end + 1;
+        let tag = &html[start..end];
+        let name_len = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        match tag[..name_len].to_ascii_lowercase().as_str() {
+            "meta" => {
+                let attributes = tag_attributes(&tag[name_len..]);
+                let get = |name: &str| attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+                if let (Some(key), Some(content)) = (get("property").or_else(|| get("name")), get("content")) {
+                    meta.entry(key.to_ascii_lowercase()).or_insert(content);
+                }
+            }
+            "link" => {
+                let attributes = tag_attributes(&tag[name_len..]);
+                let get = |name: &str| attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+                if get("type").is_some_and(|t| t.eq_ignore_ascii_case("application/json+oembed")) {
+                    oembed_url = get("href").and_then(|href| absolute_url(page_url, href));
+                }
+            }
+            "title" if title.is_none() => {
+                let close = lower[at..].find("</title").map(|i| at + i).unwrap_or(at);
+                title = Some(decode_entities(&html[at..close]));
+            }
+            // Nothing of interest past the head.
+            "body" => break,
+            _ => {}
+        }
+    }
+
+    let get = |key: &str| meta.get(key).map(String::as_str);
+    PageMeta {
+        embed: LinkEmbed {
+            url: link.to_string(),
+            site_name: get("og:site_name").and_then(|s| clean_text(s, MAX_TITLE_CHARS)),
+            title: get("og:title")
+                .or_else(|| get("twitter:title"))
+                .or(title.as_deref())
+                .and_then(|s| clean_text(s, MAX_TITLE_CHARS)),
+            description: get("og:description")
+                .or_else(|| get("twitter:description"))
+                .or_else(|| get("description"))
+                .and_then(|s| clean_text(s, MAX_DESCRIPTION_CHARS)),
+            image_url: get("og:image")
+                .or_else(|| get("og:image:url"))
+                .or_else(|| get("twitter:image"))
+                .and_then(|href| absolute_url(page_url, href)),
+            author_name: get("author").and_then(|s| clean_text(s, MAX_TITLE_CHARS)),
+            broken_at: None,
+        },
+        oembed_url,
+    }
+}
+
+#[derive(Deserialize)]
+struct OEmbed {
+    title: Option<String>,
+    author_name: Option<String>,
+    provider_name: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+/// Fill what the page's tags left out from its oEmbed endpoint.
+async fn complete_from_oembed(embed: &mut LinkEmbed, oembed_url: &str) {
+    let Ok(response) = outbound::get(&OEMBED_POLICY, oembed_url, "application/json").await else {
+        return;
+    };
+    let base = response.url.clone();
+    let Some(oembed) = response.body().await.ok().and_then(|body| serde_json::from_slice::<OEmbed>(&body).ok()) else {
+        return;
+    };
+    if embed.title.is_none() {
+        embed.title = oembed.title.and_then(|s| clean_text(&s, MAX_TITLE_CHARS));
+    }
+    if embed.author_name.is_none() {
+        embed.author_name = oembed.author_name.and_then(|s| clean_text(&s, MAX_TITLE_CHARS));
+    }
+    if embed.site_name.is_none() {
+        embed.site_name = oembed.provider_name.and_then(|s| clean_text(&s, MAX_TITLE_CHARS));
+    }
+    if embed.image_url.is_none() {
+        embed.image_url = oembed.thumbnail_url.and_then(|href| absolute_url(&base, &href));
+    }
+}
+
+/// Preview of one link, `None` when there is nothing worth showing. Fails
+/// only when the site is rate limited: try again later.
+async fn unfurl(link: &str) -> Result<Option<LinkEmbed>, outbound::Error> {
+    let response = match outbound::get(&PAGE_POLICY, link, "text/html,application/xhtml+xml").await {
+        Ok(response) => response,
+        Err(e @ outbound::Error::RateLimited { .. }) => return Err(e),
+        Err(_) => return Ok(None),
+    };
+    // Images are not read: the preview only needs their URL.
+    if response.content_type.starts_with("image/") {
+        return Ok(Some(LinkEmbed {
+            url: link.to_string(),
+            site_name: None,
+            title: None,
+            description: None,
+            image_url: Some(response.url.to_string()),
+            author_name: None,
+            broken_at: None,
+        }));
+    }
+    if !matches!(response.content_type.as_str(), "text/html" | "application/xhtml+xml") {
+        return Ok(None);
+    }
+
+    let page_url = response.url.clone();
+    let Ok(body) = response.body().await else {
+        return Ok(None);
+    };
+    let html = String::from_utf8_lossy(&body);
+    let PageMeta { mut embed, oembed_url } = parse_page(&page_url, link, &html);
+    if let Some(oembed_url) = oembed_url {
+        complete_from_oembed(&mut embed, &oembed_url).await;
+    }
+    Ok((embed.title.is_some() || embed.description.is_some() || embed.image_url.is_some()).then_some(embed))
+}
+
+/// Preview of `link`, from the cache when it is fresh enough. Fails when
+/// the site is rate limited, and nothing is cached then.
+async fn cached_unfurl(pool: &SqlitePool, link: &str) -> Result<Option<LinkEmbed>, outbound::Error> {
+    let cutoff = (Utc::now() - chrono::Duration::hours(cache_hours())).to_rfc3339();
+    let cached = sqlx::query("SELECT embed FROM link_previews WHERE url = ? AND fetched_at >= ?")
+        .bind(link)
+        .bind(&cutoff)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    if let Some(row) = cached {
+        let embed: Option<String> = row.get("embed");
+        return Ok(embed.and_then(|raw| serde_json::from_str(&raw).ok()));
+    }
+
+    refresh(pool, link).await
+}
+
+/// Preview of `link` fetched again, bypassing the cache, and cached. Fails
+/// when the site is rate limited, and nothing is cached then.
+pub(crate) async fn refresh(pool: &SqlitePool, link: &str) -> Result<Option<LinkEmbed>, outbound::Error> {
+    let embed = unfurl(link).await?;
+    let _ = sqlx::query(
+        "INSERT INTO link_previews (url, embed, fetched_at) VALUES (?, ?, ?) \
+         ON CONFLICT(url) DO UPDATE SET embed = excluded.embed, fetched_at = excluded.fetched_at"
+    )
+    .bind(link)
+    .bind(embed.as_ref().and_then(|e| serde_json::to_string(e).ok()))
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    Ok(embed)
+}
+
+// ── Background worker ───────────────────────────────────
+
+async fn unfurl_pending(pool: &SqlitePool, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let cutoff = (Utc::now() - chrono::Duration::hours(cache_hours())).to_rfc3339();
+    sqlx::query("DELETE FROM link_previews WHERE fetched_at < ?")
+        .bind(&cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let pending = sqlx::query(
+        "SELECT id, room_id, content, embeds, embeds_pending_at, deleted_at FROM messages \
+         WHERE embeds_pending_at IS NOT NULL ORDER BY embeds_pending_at LIMIT ?"
+    )
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut processed = 0;
+    for row in pending {
+        let id: String = row.get("id");
+        let room_id: String = row.get("room_id");
+        let content: String = row.get("content");
+        let previous: Option<String> = row.get("embeds");
+        let pending_at: String = row.get("embeds_pending_at");
+        let deleted_at: Option<String> = row.get("deleted_at");
+
+        let mut embeds = Vec::new();
+        if deleted_at.is_none() {
+            let mut deferred = false;
+            for link in extract_links(&content) {
+                match cached_unfurl(pool, &link).await {
+                    Ok(embed) => embeds.extend(embed),
+                    Err(_) => {
+                        deferred = true;
+                        break;
+                    }
+                }
+            }
+            // Left pending: retried on a later run, once the site's rate limit allows.
+            if deferred {
+                continue;
+            }
+        }
+        let stored = (!embeds.is_empty()).then(|| serde_json::to_string(&embeds).unwrap_or_default());
+
+        // Guarded on the flag we read: an edit meanwhile re-flags the message
+        // and it will be unfurled again with the new content.
+        let updated = sqlx::query("UPDATE messages SET embeds = ?, embeds_pending_at = NULL WHERE id = ? AND embeds_pending_at = ?")
+            .bind(&stored)
+            .bind(&id)
+            .bind(&pending_at)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .rows_affected();
+        processed += 1;
+
+        if updated == 1 && deleted_at.is_none() && stored != previous {
+            let event = serde_json::json!({
+                "type": "message_embeds_updated",
+                "id": id,
+                "room_id": room_id,
+                "embeds": embeds,
+            });
+            let _ = broadcaster.send(event.to_string());
+        }
+    }
+    Ok(processed)
+}
+
+async fn pending_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query(
+        "SELECT COUNT(*) AS depth, MIN(embeds_pending_at) AS oldest_due_at FROM messages WHERE embeds_pending_at IS NOT NULL"
+    )
+    .fetch_one(pool)
+    .await
+    .map(|row| Backlog::from_row(&row))
+    .unwrap_or_default()
+}
+
+pub fn spawn_unfurler(pool: SqlitePool, broadcaster: Broadcaster, jobs: JobRegistry) {
+    if !enabled() {
+        return;
+    }
+    crate::jobs::register(&jobs, crate::jobs::LINK_PREVIEWS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(UNFURL_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(&jobs, crate::jobs::LINK_PREVIEWS, None, unfurl_pending(&pool, &broadcaster), pending_backlog(&pool)).await;
+        }
+    });
+}
@@ -1,96 +1,193 @@
-use actix_multipart::Multipart;
-use actix_web::{HttpRequest, HttpResponse};
-use futures_util::StreamExt;
-use std::io::Write;
-use uuid::Uuid;
+// ═══════════════════════════════════════════════════════
+//  Voxium — Files under /uploads
+// ═══════════════════════════════════════════════════════
+//
+// `GET /uploads/{path}` serves custom emoji (`emojis/`), role icons
+// (`role-icons/`) and the images messages and profiles pointed at before
+// attachments and the image pipeline. They live in the file store (see
+// `files`), content-addressed like attachments; `uploaded_files` maps each
+// path to its object. Nothing is read from the working directory.
+//
+// Responses are public and cacheable forever: a new emoji or icon is a new
+// path. Images are served inline with their sniffed type, anything else as
+// an `application/octet-stream` download.
+//
+// Servers that stored these in an `uploads/` directory get it imported at
+// startup (and by `backend --migrate-files`): every file not imported yet
+// is copied to the store under its path. The directory is left for the
+// operator to remove.
 
-use crate::auth::extract_claims;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
 
-/// POST /api/upload — Upload an image file (authenticated)
-pub async fn upload_image(
-    req: HttpRequest,
-    mut payload: Multipart,
-) -> HttpResponse {
-    let claims = match extract_claims(&req) {
-        Some(c) => c,
-        None => return HttpResponse::Unauthorized().finish(),
-    };
+use crate::files::{FileStorage, FileStore};
 
-    // Ensure uploads directory exists
-    let upload_dir = std::path::Path::new("uploads");
-    if !upload_dir.exists() {
-        std::fs::create_dir_all(upload_dir).ok();
-    }
+/// Where files under `/uploads` used to be written.
+const LEGACY_DIR: &str = "uploads";
+/// Types served inline; anything else is a download.
+const INLINE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
 
-    while let Some(Ok(mut field)) = payload.next().await {
-        let content_disposition = match field.content_disposition() {
-            Some(cd) => cd.clone(),
-            None => continue,
-        };
-
-        let original_filename = content_disposition
-            .get_filename()
-            .unwrap_or("file")
-            .to_string();
+/// Store `bytes` as `/uploads/{path}`, returning that URL.
+pub(crate) async fn store(pool: &SqlitePool, storage: &FileStore, path: &str, bytes: Vec<u8>) -> Result<String, String> {
+    let hash = crate::files::sha256_hex(&bytes);
+    let size = bytes.len() as i64;
+    let content_type = crate::files::sniff_content_type(&bytes).unwrap_or("application/octet-stream");
+    storage.put(&hash, bytes).await?;
+    sqlx::query("INSERT INTO uploaded_files (path, hash, size_bytes, content_type, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(path)
+        .bind(&hash)
+        .bind(size)
+        .bind(content_type)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(format!("/uploads/{path}"))
+}
 
-        // Extract extension
-        let extension = original_filename
-            .rsplit('.')
-            .next()
-            .unwrap_or("png")
-            .to_lowercase();
+/// Delete the file behind an `/uploads/…` URL, and its object if nothing
+/// else uses it. Other URLs, and files a message, profile, emoji or role
+/// still points at, are left alone: message image URLs came from clients.
+pub(crate) async fn remove(pool: &SqlitePool, storage: &FileStore, url: Option<&str>) {
+    let Some((url, path)) = url.and_then(|url| Some((url, url.strip_prefix("/uploads/")?))) else {
+        return;
+    };
+    let referenced: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM messages WHERE image_url = ?) \
+         OR EXISTS(SELECT 1 FROM users WHERE avatar_url = ? OR banner_url = ?) \
+         OR EXISTS(SELECT 1 FROM custom_emojis WHERE image_url = ?) \
+         OR EXISTS(SELECT 1 FROM roles WHERE icon_url = ?)"
+    )
+    .bind(url)
+    .bind(url)
+    .bind(url)
+    .bind(url)
+    .bind(url)
+    .fetch_one(pool)
+    .await
+    .unwrap_or(true);
+    if referenced {
+        return;
+    }
+    let hash: Option<String> = sqlx::query_scalar("DELETE FROM uploaded_files WHERE path = ? RETURNING hash")
+        .bind(path)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    let Some(hash) = hash else {
+        return;
+    };
+    let attached: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM files WHERE hash = ? OR thumbnail_hash = ?)")
+        .bind(&hash)
+        .bind(&hash)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(true);
+    if !attached && !crate::images::uses_object(pool, &hash).await && !uses_object(pool, &hash).await {
+        storage.delete(&hash).await;
+    }
+}
 
-        // Only allow image types
-        let allowed = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
-        if !allowed.contains(&extension.as_str()) {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Only image files are allowed (png, jpg, jpeg, gif, webp, svg, bmp)"
-            }));
-        }
+/// Whether a file under `/uploads` is stored as `hash`.
+pub(crate) async fn uses_object(pool: &SqlitePool, hash: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM uploaded_files WHERE hash = ?)")
+        .bind(hash)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(true)
+}
 
-        // Generate unique filename
-        let filename = format!("{}_{}.{}", claims.sub, Uuid::new_v4(), extension);
-        let filepath = upload_dir.join(&filename);
+// ── Legacy directory ────────────────────────────────────
 
-        // Write file
-        let mut file = match std::fs::File::create(&filepath) {
-            Ok(f) => f,
-            Err(_) => {
-                return HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "Failed to save file"
-                }));
-            }
+/// Paths (relative, `/`-separated) of the files under `dir`.
+fn legacy_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
         };
-
-        let mut total_size: usize = 0;
-        let max_size: usize = 8 * 1024 * 1024; // 8MB limit
-
-        while let Some(Ok(chunk)) = field.next().await {
-            total_size += chunk.len();
-            if total_size > max_size {
-                // Clean up partial file
-                drop(file);
-                std::fs::remove_file(&filepath).ok();
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "File too large (max 8MB)"
-                }));
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => pending.push(path),
+                Ok(kind) if kind.is_file() => {
+                    let Ok(relative) = path.strip_prefix(dir) else {
+                        continue;
+                    };
+                    let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+                    found.push((parts.join("/"), path));
+                }
+                _ => {}
             }
-            if file.write_all(&chunk).is_err() {
-                return HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "Failed to write file"
-                }));
+        }
+    }
+    found
+}
+
+/// Copy the files of the `uploads/` directory not imported yet to
+/// `storage`. Returns how many were imported and how many failed.
+pub async fn import_legacy(pool: &SqlitePool, storage: &FileStore) -> (u64, u64) {
+    let files = web::block(|| legacy_files(Path::new(LEGACY_DIR))).await.unwrap_or_default();
+    let (mut imported, mut failed) = (0, 0);
+    for (path, file) in files {
+        let known: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM uploaded_files WHERE path = ?)")
+            .bind(&path)
+            .fetch_one(pool)
+            .await
+            .unwrap_or(true);
+        if known {
+            continue;
+        }
+        let stored = match tokio::fs::read(&file).await {
+            Ok(bytes) => store(pool, storage, &path, bytes).await,
+            Err(e) => Err(e.to_string()),
+        };
+        match stored {
+            Ok(_) => imported += 1,
+            Err(e) => {
+                eprintln!("⚠️  uploads/{path}: {e}");
+                failed += 1;
             }
         }
+    }
+    (imported, failed)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
 
-        // Return the URL to the uploaded file
-        let url = format!("/uploads/{}", filename);
-        return HttpResponse::Ok().json(serde_json::json!({
-            "url": url,
-            "filename": original_filename
-        }));
+/// GET /uploads/{path} — A custom emoji, role icon or legacy upload (public)
+pub async fn serve_upload(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let row: Option<(String, i64, String)> =
+        sqlx::query_as("SELECT hash, size_bytes, content_type FROM uploaded_files WHERE path = ?")
+            .bind(path.into_inner())
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None);
+    let Some((hash, size, content_type)) = row else {
+        return HttpResponse::NotFound().finish();
+    };
+    let size = size.max(0) as u64;
+    if size > 0 && !storage.exists(&hash).await {
+        return HttpResponse::NotFound().finish();
     }
 
-    HttpResponse::BadRequest().json(serde_json::json!({
-        "error": "No file provided"
-    }))
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(("Cache-Control", "public, max-age=31536000, immutable"))
+        .insert_header(("X-Content-Type-Options", "nosniff"));
+    if INLINE_TYPES.contains(&content_type.as_str()) {
+        response.content_type(content_type);
+    } else {
+        response
+            .content_type("application/octet-stream")
+            .insert_header(("Content-Disposition", "attachment"));
+    }
+    response.streaming(storage.get_ref().clone().stream(hash, size))
 }
@@ -0,0 +1,505 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Verification levels and join-surge protection
+// ═══════════════════════════════════════════════════════
+//
+// Server-wide settings (MANAGE_SERVER) for what a member needs before
+// posting, and what joining takes:
+//   - `min_account_age_minutes`: accounts younger than this cannot post;
+//   - `require_discord_link`: accounts without a linked Discord account
+//     cannot post;
+//   - `captcha_on_join`: registering and redeeming a guest link take a
+//     solved captcha.
+//
+// The captcha is a proof of work, so it needs no third-party service:
+// `GET /api/join/challenge` hands out a signed challenge, valid
+// `CHALLENGE_MINUTES`, and the client finds a `nonce` such that
+// SHA-256(`challenge:nonce`) starts with `difficulty` zero bits. A solved
+// challenge is good for one join.
+//
+// The join-surge detector counts the accounts created in the last
+// `surge_window_seconds`. When a join brings them to `surge_joins` (0 turns
+// it off), a surge starts for `surge_duration_minutes`: the captcha is
+// required, the minimum account age is raised to
+// `surge_min_account_age_minutes` and, with `surge_pause_guest_links`,
+// guest links stop working. Members whose role has MANAGE_MEMBERS get a
+// `join_surge` event; ending it early (`DELETE /api/server/verification/surge`)
+// sends them `join_surge_ended`.
+//
+// Guests are gated by their link and the captcha, administrators and roles
+// with MANAGE_MESSAGES are never refused. A refused WebSocket message gets a
+// `verification_required` event on the sending connection:
+// `{ type, room_id, verification, allowed_at }`; HTTP endpoints that post
+// answer 403 with the same fields.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+
+use crate::auth::{extract_claims, Claims};
+use crate::ws::Broadcaster;
+
+const CHALLENGE_MINUTES: i64 = 10;
+/// Leading zero bits a solved captcha hash needs, about 65k tries.
+const CAPTCHA_DIFFICULTY: u32 = 16;
+const MAX_ACCOUNT_AGE_MINUTES: i64 = 30 * 24 * 60;
+const MIN_SURGE_JOINS: i64 = 2;
+const MAX_SURGE_WINDOW_SECONDS: i64 = 3600;
+const MAX_SURGE_DURATION_MINUTES: i64 = 24 * 60;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Settings {
+    pub min_account_age_minutes: i64,
+    pub require_discord_link: bool,
+    pub captcha_on_join: bool,
+    pub surge_joins: i64,
+    pub surge_window_seconds: i64,
+    pub surge_min_account_age_minutes: i64,
+    pub surge_pause_guest_links: bool,
+    pub surge_duration_minutes: i64,
+    /// Set while a surge holds the raised settings.
+    pub surge_until: Option<String>,
+    pub updated_by_username: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+impl Settings {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let surge_until: Option<String> = row.get("surge_until");
+        Self {
+            min_account_age_minutes: row.get("min_account_age_minutes"),
+            require_discord_link: row.get::<i64, _>("require_discord_link") != 0,
+            captcha_on_join: row.get::<i64, _>("captcha_on_join") != 0,
+            surge_joins: row.get("surge_joins"),
+            surge_window_seconds: row.get("surge_window_seconds"),
+            surge_min_account_age_minutes: row.get("surge_min_account_age_minutes"),
+            surge_pause_guest_links: row.get::<i64, _>("surge_pause_guest_links") != 0,
+            surge_duration_minutes: row.get("surge_duration_minutes"),
+            // A surge that ran out is not one.
+            surge_until: surge_until.filter(|until| *until > Utc::now().to_rfc3339()),
+            updated_by_username: row.get("updated_by_username"),
+            updated_at: row.get("updated_at"),
+        }
+    }
+
+    fn surge_active(&self) -> bool {
+        self.surge_until.is_some()
+    }
+
+    /// The minimum account age in force, raised during a surge.
+    fn account_age_minutes(&self) -> i64 {
+        match self.surge_active() {
+            true => self.min_account_age_minutes.max(self.surge_min_account_age_minutes),
+            false => self.min_account_age_minutes,
+        }
+    }
+
+    fn captcha_required(&self) -> bool {
+        self.captcha_on_join || self.surge_active()
+    }
+
+    fn json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        value["surge_active"] = self.surge_active().into();
+        value["captcha_required"] = self.captcha_required().into();
+        value
+    }
+}
+
+/// The settings, or all of them off when they cannot be read.
+pub(crate) async fn load(pool: &SqlitePool) -> Settings {
+    sqlx::query("SELECT * FROM verification_settings WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Settings::from_row(&row))
+        .unwrap_or_default()
+}
+
+/// Whether a surge has paused guest links.
+pub(crate) async fn guest_links_paused(pool: &SqlitePool) -> bool {
+    let settings = load(pool).await;
+    settings.surge_active() && settings.surge_pause_guest_links
+}
+
+// ── Posting gate ────────────────────────────────────────
+
+/// A refused post: the author does not meet `requirement` (`discord_link`
+/// or `account_age`), the latter until `allowed_at`.
+#[derive(Debug, Clone)]
+pub struct Unverified {
+    pub requirement: &'static str,
+    pub allowed_at: Option<String>,
+}
+
+impl Unverified {
+    /// The structured 403 for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        let error = match self.requirement {
+            "discord_link" => "Link a Discord account to post on this server",
+            _ => "Your account is too new to post on this server yet",
+        };
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": error,
+            "verification": self.requirement,
+            "allowed_at": self.allowed_at,
+        }))
+    }
+
+    /// The `verification_required` event for the WebSocket connection that was refused.
+    pub fn event(&self, room_id: &str) -> String {
+        serde_json::json!({
+            "type": "verification_required",
+            "room_id": room_id,
+            "verification": self.requirement,
+            "allowed_at": self.allowed_at,
+        })
+        .to_string()
+    }
+}
+
+/// Whether `user_id` with `role` meets the verification settings.
+pub(crate) async fn check(pool: &SqlitePool, user_id: &str, role: &str) -> Result<(), Unverified> {
+    if role == crate::guests::GUEST_ROLE {
+        return Ok(());
+    }
+    let settings = load(pool).await;
+    let age_minutes = settings.account_age_minutes();
+    if age_minutes == 0 && !settings.require_discord_link {
+        return Ok(());
+    }
+    let permissions = crate::permissions::role_permissions(pool, role).await;
+    if permissions & (crate::permissions::ADMINISTRATOR | crate::permissions::MANAGE_MESSAGES) != 0 {
+        return Ok(());
+    }
+    let row = sqlx::query(
+        "SELECT COALESCE(discord_id, '') != '' AS linked, \
+                strftime('%Y-%m-%dT%H:%M:%SZ', created_at, '+' || ? || ' minutes') AS allowed_at, \
+                julianday(created_at) + ? / 1440.0 > julianday('now') AS too_new \
+         FROM users WHERE id = ?"
+    )
+    .bind(age_minutes)
+    .bind(age_minutes)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    // A failed read should not silence the server.
+    .unwrap_or(None);
+    let Some(row) = row else {
+        return Ok(());
+    };
+    if settings.require_discord_link && !row.get::<bool, _>("linked") {
+        return Err(Unverified { requirement: "discord_link", allowed_at: None });
+    }
+    if age_minutes > 0 && row.get::<bool, _>("too_new") {
+        return Err(Unverified { requirement: "account_age", allowed_at: row.get("allowed_at") });
+    }
+    Ok(())
+}
+
+// ── Join captcha ────────────────────────────────────────
+
+fn challenge_signature(payload: &str) -> Vec<u8> {
+    crate::crypto::sign(&format!("join-challenge:{payload}"))
+}
+
+/// `<random>.<expiry, unix seconds>.<signature>`
+fn new_challenge() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let payload = format!(
+        "{}.{}",
+        general_purpose::URL_SAFE_NO_PAD.encode(bytes),
+        (Utc::now() + chrono::Duration::minutes(CHALLENGE_MINUTES)).timestamp()
+    );
+    let signature = general_purpose::URL_SAFE_NO_PAD.encode(challenge_signature(&payload));
+    format!("{payload}.{signature}")
+}
+
+/// The expiry of a valid, unexpired challenge, as RFC 3339.
+fn verify_challenge(challenge: &str) -> Option<String> {
+    let (payload, signature) = challenge.rsplit_once('.')?;
+    let signature = general_purpose::URL_SAFE_NO_PAD.decode(signature).ok()?;
+    crate::crypto::signatures_match(&challenge_signature(payload), &signature).then_some(())?;
+    let expires = payload.split_once('.')?.1.parse::<i64>().ok()?;
+    let expires = chrono::DateTime::from_timestamp(expires, 0)?;
+    (expires > Utc::now()).then(|| expires.to_rfc3339())
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// A solved join captcha, sent along with registrations and guest link
+/// redemptions.
+#[derive(Debug, Deserialize)]
+pub struct CaptchaSolution {
+    pub challenge: String,
+    pub nonce: String,
+}
+
+/// Check, when the captcha is required, that `solution` solves an unused
+/// challenge, and use it up.
+pub(crate) async fn require_captcha(pool: &SqlitePool, solution: Option<&CaptchaSolution>) -> Result<(), HttpResponse> {
+    if !load(pool).await.captcha_required() {
+        return Ok(());
+    }
+    let refused = |error: &str| {
+        HttpResponse::Forbidden().json(serde_json::json!({ "error": error, "captcha_required": true }))
+    };
+    let Some(solution) = solution else {
+        return Err(refused("Solve the captcha to join"));
+    };
+    let challenge = solution.challenge.trim();
+    let Some(expires_at) = verify_challenge(challenge) else {
+        return Err(refused("Invalid or expired captcha challenge"));
+    };
+    let hash = Sha256::digest(format!("{challenge}:{}", solution.nonce).as_bytes());
+    if leading_zero_bits(&hash) < CAPTCHA_DIFFICULTY {
+        return Err(refused("Wrong captcha solution"));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let _ = sqlx::query("DELETE FROM used_join_challenges WHERE expires_at <= ?").bind(&now).execute(pool).await;
+    let fresh = sqlx::query("INSERT OR IGNORE INTO used_join_challenges (challenge, expires_at) VALUES (?, ?)")
+        .bind(challenge)
+        .bind(&expires_at)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or(false);
+    if !fresh {
+        return Err(refused("This captcha was already used"));
+    }
+    Ok(())
+}
+
+// ── Join-surge detector ─────────────────────────────────
+
+/// Send `event` to every member whose role has MANAGE_MEMBERS.
+async fn alert_moderators(pool: &SqlitePool, broadcaster: &Broadcaster, event: serde_json::Value) {
+    let moderators: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM users WHERE role = 'admin' OR role IN (SELECT name FROM roles WHERE permissions & ? != 0)"
+    )
+    .bind((crate::permissions::MANAGE_MEMBERS | crate::permissions::ADMINISTRATOR) as i64)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+    for user_id in moderators {
+        let mut event = event.clone();
+        event["recipient_id"] = user_id.into();
+        let _ = broadcaster.send(event.to_string());
+    }
+}
+
+/// Called after an account was created: start a surge when the joins in the
+/// window reached the threshold.
+pub(crate) async fn record_join(pool: &SqlitePool, broadcaster: &Broadcaster) {
+    let settings = load(pool).await;
+    if settings.surge_joins == 0 || settings.surge_active() {
+        return;
+    }
+    // Webhooks are users too, but nobody joined.
+    let joins: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM users WHERE role != ? AND julianday(created_at) >= julianday('now') - ? / 86400.0"
+    )
+        .bind(crate::webhooks::WEBHOOK_ROLE)
+        .bind(settings.surge_window_seconds)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    if joins < settings.surge_joins {
+        return;
+    }
+
+    let now = Utc::now();
+    let surge_until = (now + chrono::Duration::minutes(settings.surge_duration_minutes)).to_rfc3339();
+    // Claimed, so concurrent joins start it once.
+    let started = sqlx::query("UPDATE verification_settings SET surge_until = ? WHERE id = 1 AND (surge_until IS NULL OR surge_until <= ?)")
+        .bind(&surge_until)
+        .bind(now.to_rfc3339())
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .unwrap_or(false);
+    if !started {
+        return;
+    }
+    eprintln!("join surge: {joins} accounts in {}s, verification raised until {surge_until}", settings.surge_window_seconds);
+
+    let details = serde_json::json!({
+        "joins": joins,
+        "window_seconds": settings.surge_window_seconds,
+        "surge_until": surge_until,
+        "min_account_age_minutes": settings.min_account_age_minutes.max(settings.surge_min_account_age_minutes),
+        "guest_links_paused": settings.surge_pause_guest_links,
+    });
+    crate::audit_log::record(pool, None, crate::audit_log::Action::JoinSurgeDetected, "verification", details.clone(), None).await;
+    let mut event = details;
+    event["type"] = "join_surge".into();
+    alert_moderators(pool, broadcaster, event).await;
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/join/challenge — A captcha challenge, and whether joining needs one now
+pub async fn get_challenge(pool: web::Data<SqlitePool>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "captcha_required": load(pool.get_ref()).await.captcha_required(),
+        "challenge": new_challenge(),
+        "difficulty": CAPTCHA_DIFFICULTY,
+        "expires_in": CHALLENGE_MINUTES * 60,
+    }))
+}
+
+async fn require_manage_server(req: &HttpRequest, pool: &SqlitePool) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require_server(pool, &claims, crate::permissions::MANAGE_SERVER).await?;
+    Ok(claims)
+}
+
+/// GET /api/server/verification — The verification settings and surge state (MANAGE_SERVER)
+pub async fn get_settings(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    if let Err(response) = require_manage_server(&req, pool.get_ref()).await {
+        return response;
+    }
+    HttpResponse::Ok().json(load(pool.get_ref()).await.json())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SettingsPatch {
+    pub min_account_age_minutes: Option<i64>,
+    pub require_discord_link: Option<bool>,
+    pub captcha_on_join: Option<bool>,
+    pub surge_joins: Option<i64>,
+    pub surge_window_seconds: Option<i64>,
+    pub surge_min_account_age_minutes: Option<i64>,
+    pub surge_pause_guest_links: Option<bool>,
+    pub surge_duration_minutes: Option<i64>,
+}
+
+/// PATCH /api/server/verification — Change the verification settings (MANAGE_SERVER)
+pub async fn update_settings(req: HttpRequest, pool: web::Data<SqlitePool>, body: web::Json<SettingsPatch>) -> HttpResponse {
+    let claims = match require_manage_server(&req, pool.get_ref()).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let old = load(pool.get_ref()).await;
+    let patch = body.into_inner();
+    let new = Settings {
+        min_account_age_minutes: patch.min_account_age_minutes.unwrap_or(old.min_account_age_minutes),
+        require_discord_link: patch.require_discord_link.unwrap_or(old.require_discord_link),
+        captcha_on_join: patch.captcha_on_join.unwrap_or(old.captcha_on_join),
+        surge_joins: patch.surge_joins.unwrap_or(old.surge_joins),
+        surge_window_seconds: patch.surge_window_seconds.unwrap_or(old.surge_window_seconds),
+        surge_min_account_age_minutes: patch.surge_min_account_age_minutes.unwrap_or(old.surge_min_account_age_minutes),
+        surge_pause_guest_links: patch.surge_pause_guest_links.unwrap_or(old.surge_pause_guest_links),
+        surge_duration_minutes: patch.surge_duration_minutes.unwrap_or(old.surge_duration_minutes),
+        ..old.clone()
+    };
+    let bad = |error: String| HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+    for (name, value) in [
+        ("min_account_age_minutes", new.min_account_age_minutes),
+        ("surge_min_account_age_minutes", new.surge_min_account_age_minutes),
+    ] {
+        if !(0..=MAX_ACCOUNT_AGE_MINUTES).contains(&value) {
+            return bad(format!("{name} must be between 0 and {MAX_ACCOUNT_AGE_MINUTES}"));
+        }
+    }
+    if new.surge_joins != 0 && new.surge_joins < MIN_SURGE_JOINS {
+        return bad(format!("surge_joins must be 0 (off) or at least {MIN_SURGE_JOINS}"));
+    }
+    if !(1..=MAX_SURGE_WINDOW_SECONDS).contains(&new.surge_window_seconds) {
+        return bad(format!("surge_window_seconds must be between 1 and {MAX_SURGE_WINDOW_SECONDS}"));
+    }
+    if !(1..=MAX_SURGE_DURATION_MINUTES).contains(&new.surge_duration_minutes) {
+        return bad(format!("surge_duration_minutes must be between 1 and {MAX_SURGE_DURATION_MINUTES}"));
+    }
+
+    let updated = sqlx::query(
+        "UPDATE verification_settings SET min_account_age_minutes = ?, require_discord_link = ?, captcha_on_join = ?, \
+         surge_joins = ?, surge_window_seconds = ?, surge_min_account_age_minutes = ?, surge_pause_guest_links = ?, \
+         surge_duration_minutes = ?, updated_by_username = ?, updated_at = ? WHERE id = 1"
+    )
+    .bind(new.min_account_age_minutes)
+    .bind(new.require_discord_link)
+    .bind(new.captcha_on_join)
+    .bind(new.surge_joins)
+    .bind(new.surge_window_seconds)
+    .bind(new.surge_min_account_age_minutes)
+    .bind(new.surge_pause_guest_links)
+    .bind(new.surge_duration_minutes)
+    .bind(&claims.username)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if updated.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let settings = load(pool.get_ref()).await;
+    let changes = crate::audit_log::diff(&[
+        ("min_account_age_minutes", old.min_account_age_minutes.into(), settings.min_account_age_minutes.into()),
+        ("require_discord_link", old.require_discord_link.into(), settings.require_discord_link.into()),
+        ("captcha_on_join", old.captcha_on_join.into(), settings.captcha_on_join.into()),
+        ("surge_joins", old.surge_joins.into(), settings.surge_joins.into()),
+        ("surge_window_seconds", old.surge_window_seconds.into(), settings.surge_window_seconds.into()),
+        ("surge_min_account_age_minutes", old.surge_min_account_age_minutes.into(), settings.surge_min_account_age_minutes.into()),
+        ("surge_pause_guest_links", old.surge_pause_guest_links.into(), settings.surge_pause_guest_links.into()),
+        ("surge_duration_minutes", old.surge_duration_minutes.into(), settings.surge_duration_minutes.into()),
+    ]);
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::VerificationUpdated,
+        "verification",
+        changes,
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::Ok().json(settings.json())
+}
+
+/// DELETE /api/server/verification/surge — End a join surge early (MANAGE_MEMBERS)
+pub async fn end_surge(req: HttpRequest, pool: web::Data<SqlitePool>, broadcaster: web::Data<Broadcaster>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_MEMBERS).await {
+        return response;
+    }
+    let ended = sqlx::query("UPDATE verification_settings SET surge_until = NULL WHERE id = 1 AND surge_until > ?")
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+    match ended {
+        Ok(res) if res.rows_affected() > 0 => {
+            crate::audit_log::record(
+                pool.get_ref(),
+                Some(&claims),
+                crate::audit_log::Action::JoinSurgeEnded,
+                "verification",
+                serde_json::json!({}),
+                crate::audit_log::reason(&req).as_deref(),
+            )
+            .await;
+            let event = serde_json::json!({ "type": "join_surge_ended", "ended_by_username": claims.username });
+            alert_moderators(pool.get_ref(), broadcaster.get_ref(), event).await;
+            HttpResponse::NoContent().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "No join surge in progress" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
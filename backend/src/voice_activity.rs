@@ -0,0 +1,236 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Voice room occupancy history and heatmap
+// ═══════════════════════════════════════════════════════
+//
+// The server keeps who is in which voice room from the `voice_join` and
+// `voice_leave` events of `/ws` connections (a closed connection leaves
+// its room). Every `VOICE_OCCUPANCY_SAMPLE_SECS` the `voice_occupancy`
+// job stores one sample per voice room, empty ones included, so averages
+// account for quiet hours. Samples older than
+// `VOICE_OCCUPANCY_RETENTION_DAYS` are dropped by the same job.
+//
+// `GET /api/server/voice/heatmap` (admins) aggregates the samples of the
+// last days by hour of the week, in the caller's time zone: for each of
+// the 168 hours (Monday 00:00 first), the average and peak number of
+// people in voice (all voice rooms together, or one room).
+//
+// Config (env):
+//   VOICE_OCCUPANCY_SAMPLE_SECS     time between two samples (default 300, at least 60)
+//   VOICE_OCCUPANCY_RETENTION_DAYS  how long samples are kept (default 90)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::auth::extract_claims;
+use crate::jobs::{Backlog, JobRegistry};
+
+const DEFAULT_SAMPLE_SECS: u64 = 300;
+const MIN_SAMPLE_SECS: u64 = 60;
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+const DEFAULT_HEATMAP_DAYS: i64 = 28;
+const HOURS_PER_WEEK: i64 = 168;
+/// The Unix epoch fell on a Thursday, 72 hours after Monday 00:00.
+const EPOCH_HOUR_OF_WEEK: i64 = 72;
+
+/// People in each voice room: room id -> user id -> connections in it.
+pub type VoiceOccupancy = Arc<Mutex<HashMap<String, HashMap<String, usize>>>>;
+
+pub fn create_voice_occupancy() -> VoiceOccupancy {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+fn sample_interval() -> Duration {
+    let secs = std::env::var("VOICE_OCCUPANCY_SAMPLE_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SAMPLE_SECS)
+        .max(MIN_SAMPLE_SECS);
+    Duration::from_secs(secs)
+}
+
+fn retention_days() -> i64 {
+    std::env::var("VOICE_OCCUPANCY_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|d| *d > 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+// ── Tracking ────────────────────────────────────────────
+
+/// A connection of `user_id` joined the voice room `room_id`.
+pub(crate) fn joined(occupancy: &VoiceOccupancy, room_id: &str, user_id: &str) {
+    let mut guard = occupancy.lock().unwrap();
+    *guard.entry(room_id.to_string()).or_default().entry(user_id.to_string()).or_insert(0) += 1;
+}
+
+/// A connection of `user_id` left the voice room `room_id`.
+pub(crate) fn left(occupancy: &VoiceOccupancy, room_id: &str, user_id: &str) {
+    let mut guard = occupancy.lock().unwrap();
+    let Some(members) = guard.get_mut(room_id) else {
+        return;
+    };
+    if let Some(count) = members.get_mut(user_id) {
+        *count -= 1;
+        if *count == 0 {
+            members.remove(user_id);
+        }
+    }
+    if members.is_empty() {
+        guard.remove(room_id);
+    }
+}
+
+/// People in each voice room right now.
+fn counts(occupancy: &VoiceOccupancy) -> HashMap<String, i64> {
+    occupancy
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(room_id, members)| (room_id.clone(), members.len() as i64))
+        .collect()
+}
+
+// ── Sampling ────────────────────────────────────────────
+
+/// Store one sample per voice room, then drop the expired ones.
+async fn sample(pool: &SqlitePool, occupancy: &VoiceOccupancy) -> Result<u64, String> {
+    let rooms: Vec<String> = sqlx::query_scalar("SELECT id FROM rooms WHERE kind = 'voice'")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let counts = counts(occupancy);
+    let now = chrono::Utc::now().timestamp();
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for room_id in &rooms {
+        sqlx::query("INSERT OR REPLACE INTO voice_occupancy_samples (room_id, sampled_at, users) VALUES (?, ?, ?)")
+            .bind(room_id)
+            .bind(now)
+            .bind(counts.get(room_id).copied().unwrap_or(0))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    sqlx::query("DELETE FROM voice_occupancy_samples WHERE sampled_at < ?")
+        .bind(now - retention_days() * 86_400)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(rooms.len() as u64)
+}
+
+pub fn spawn_sampler(pool: SqlitePool, occupancy: VoiceOccupancy, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::VOICE_OCCUPANCY);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(sample_interval());
+        loop {
+            interval.tick().await;
+            crate::jobs::run(
+                &jobs,
+                crate::jobs::VOICE_OCCUPANCY,
+                None,
+                sample(&pool, &occupancy),
+                async { Backlog::default() },
+            )
+            .await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct HeatmapQuery {
+    pub days: Option<i64>,
+    pub room_id: Option<String>,
+    /// The caller's offset from UTC, in minutes (e.g. 120 for UTC+2).
+    pub tz_offset_minutes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeatmapHour {
+    /// 0 = Monday 00:00 to 167 = Sunday 23:00.
+    pub hour_of_week: i64,
+    /// 0 = Monday.
+    pub day: i64,
+    pub hour: i64,
+    pub avg_users: f64,
+    pub peak_users: i64,
+    pub samples: i64,
+}
+
+/// GET /api/server/voice/heatmap?days=&room_id=&tz_offset_minutes= — Voice occupancy by hour of the week (MANAGE_SERVER)
+pub async fn heatmap(req: HttpRequest, pool: web::Data<SqlitePool>, query: web::Query<HeatmapQuery>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    if let Err(response) = crate::permissions::require_server(pool.get_ref(), &claims, crate::permissions::MANAGE_SERVER).await {
+        return response;
+    }
+
+    let days = query.days.unwrap_or(DEFAULT_HEATMAP_DAYS).clamp(1, retention_days());
+    let offset_secs = query.tz_offset_minutes.unwrap_or(0).clamp(-12 * 60, 14 * 60) * 60;
+    if let Some(room_id) = &query.room_id {
+        let is_voice: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rooms WHERE id = ? AND kind = 'voice')")
+            .bind(room_id)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap_or(false);
+        if !is_voice {
+            return HttpResponse::NotFound().json(serde_json::json!({ "error": "Voice room not found" }));
+        }
+    }
+
+    // Rooms sampled together share `sampled_at`: add them up per sample first.
+    let rows = sqlx::query(
+        "SELECT ((sampled_at + ?) / 3600 + ?) % ? AS hour_of_week, \
+                AVG(users) AS avg_users, MAX(users) AS peak_users, COUNT(*) AS samples \
+         FROM (SELECT sampled_at, SUM(users) AS users FROM voice_occupancy_samples \
+               WHERE sampled_at >= ? AND (? IS NULL OR room_id = ?) GROUP BY sampled_at) \
+         GROUP BY hour_of_week"
+    )
+    .bind(offset_secs)
+    .bind(EPOCH_HOUR_OF_WEEK)
+    .bind(HOURS_PER_WEEK)
+    .bind(chrono::Utc::now().timestamp() - days * 86_400)
+    .bind(&query.room_id)
+    .bind(&query.room_id)
+    .fetch_all(pool.get_ref())
+    .await;
+    let Ok(rows) = rows else {
+        return HttpResponse::InternalServerError().finish();
+    };
+
+    let mut hours: Vec<HeatmapHour> = (0..HOURS_PER_WEEK)
+        .map(|hour_of_week| HeatmapHour {
+            hour_of_week,
+            day: hour_of_week / 24,
+            hour: hour_of_week % 24,
+            avg_users: 0.0,
+            peak_users: 0,
+            samples: 0,
+        })
+        .collect();
+    for row in rows {
+        let hour_of_week: i64 = row.get("hour_of_week");
+        let slot = &mut hours[hour_of_week.rem_euclid(HOURS_PER_WEEK) as usize];
+        slot.avg_users = (row.get::<f64, _>("avg_users") * 100.0).round() / 100.0;
+        slot.peak_users = row.get("peak_users");
+        slot.samples = row.get("samples");
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "days": days,
+        "room_id": query.room_id,
+        "tz_offset_minutes": offset_secs / 60,
+        "sample_interval_secs": sample_interval().as_secs(),
+        "hours": hours,
+    }))
+}
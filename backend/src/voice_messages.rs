@@ -0,0 +1,209 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Voice messages
+// ═══════════════════════════════════════════════════════
+//
+// A voice message is a short Ogg Opus clip recorded by the client, uploaded
+// through `POST /api/files/voice` and sent like any other attachment (see
+// `files`), with `kind: "voice"`. The server reads the Ogg container itself
+// to fill in, for every Ogg Opus file:
+//   - duration_secs  from the last granule position, minus the pre-skip
+//   - waveform       up to `WAVEFORM_SAMPLES` amplitudes (0-255), base64.
+//                    The server does not decode Opus: each sample is the
+//                    bitrate of the packets it covers, which follows
+//                    loudness closely enough for a preview (silence is
+//                    coded in a few bytes).
+//
+// Playback streams the file through its signed URL, which honours `Range`.
+//
+// Config (env): VOICE_MESSAGE_MAX_SECS (default 300)
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine};
+use sqlx::SqlitePool;
+
+use crate::auth::extract_claims;
+use crate::files::FileStorage;
+
+const DEFAULT_MAX_SECS: f64 = 300.0;
+/// Clips are small: ~3 MB covers 5 minutes at a generous 64 kbit/s.
+const MAX_VOICE_BYTES: usize = 8 * 1024 * 1024;
+const WAVEFORM_SAMPLES: usize = 256;
+/// Opus always runs at 48 kHz, whatever the input rate.
+const OPUS_RATE: f64 = 48_000.0;
+const VOICE_FILENAME: &str = "voice-message.ogg";
+
+fn max_secs() -> f64 {
+    std::env::var("VOICE_MESSAGE_MAX_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n: &f64| *n > 0.0)
+        .unwrap_or(DEFAULT_MAX_SECS)
+}
+
+// ── Ogg Opus ────────────────────────────────────────────
+
+pub(crate) struct AudioInfo {
+    pub duration_secs: f64,
+    pub waveform: String,
+}
+
+/// Packets of the first logical stream, and its last granule position.
+fn ogg_packets(bytes: &[u8]) -> Option<(Vec<Vec<u8>>, Option<i64>)> {
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut serial = None;
+    let mut last_granule = None;
+    let mut offset = 0;
+
+    while offset + 27 <= bytes.len() {
+        let header = &bytes[offset..];
+        if &header[..4] != b"OggS" || header[4] != 0 {
+            return None;
+        }
+        let granule = i64::from_le_bytes(header[6..14].try_into().ok()?);
+        let page_serial = u32::from_le_bytes(header[14..18].try_into().ok()?);
+        let segments = header[26] as usize;
+        let lacing = header.get(27..27 + segments)?;
+        let body_len: usize = lacing.iter().map(|l| *l as usize).sum();
+        let body_start = offset + 27 + segments;
+        let body = bytes.get(body_start..body_start + body_len)?;
+        offset = body_start + body_len;
+
+        // Multiplexed streams: only the first one is read.
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+        if granule >= 0 {
+            last_granule = Some(granule);
+        }
+
+        let mut position = 0;
+        for &len in lacing {
+            current.extend_from_slice(&body[position..position + len as usize]);
+            position += len as usize;
+            // A lacing value under 255 ends the packet, 255 continues it.
+            if len < 255 {
+                packets.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    Some((packets, last_granule))
+}
+
+/// Samples (at 48 kHz) in an Opus packet, from its TOC byte.
+fn opus_packet_samples(packet: &[u8]) -> Option<u32> {
+    let toc = *packet.first()?;
+    let config = (toc >> 3) as usize;
+    let frame = match config {
+        0..=11 => [480, 960, 1920, 2880][config % 4],
+        12..=15 => [480, 960][config % 2],
+        _ => [120, 240, 480, 960][config % 4],
+    };
+    let frames = match toc & 3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*packet.get(1)? & 0x3F) as u32,
+    };
+    Some(frame * frames)
+}
+
+fn waveform(packets: &[(u32, usize)]) -> String {
+    let total: u64 = packets.iter().map(|(samples, _)| *samples as u64).sum();
+    let buckets = packets.len().min(WAVEFORM_SAMPLES);
+    if total == 0 || buckets == 0 {
+        return String::new();
+    }
+
+    // Bytes per sample, averaged over each bucket's span of time.
+    let mut sums = vec![(0u64, 0u64); buckets];
+    let mut at = 0u64;
+    for &(samples, len) in packets {
+        let bucket = ((at * buckets as u64) / total).min(buckets as u64 - 1) as usize;
+        sums[bucket].0 += len as u64;
+        sums[bucket].1 += samples as u64;
+        at += samples as u64;
+    }
+    let rates: Vec<f64> = sums
+        .iter()
+        .map(|(bytes, samples)| if *samples == 0 { 0.0 } else { *bytes as f64 / *samples as f64 })
+        .collect();
+    let peak = rates.iter().cloned().fold(0.0, f64::max);
+    let amplitudes: Vec<u8> = rates
+        .iter()
+        .map(|rate| if peak > 0.0 { (rate / peak * 255.0).round() as u8 } else { 0 })
+        .collect();
+    general_purpose::STANDARD.encode(amplitudes)
+}
+
+/// Duration and waveform of an Ogg Opus file, `None` for anything else.
+pub(crate) fn analyze(bytes: &[u8]) -> Option<AudioInfo> {
+    let (packets, last_granule) = ogg_packets(bytes)?;
+    let head = packets.first()?;
+    if head.len() < 19 || !head.starts_with(b"OpusHead") {
+        return None;
+    }
+    let pre_skip = u16::from_le_bytes([head[10], head[11]]) as i64;
+
+    // Packet 1 is OpusTags, audio follows.
+    let audio: Vec<(u32, usize)> = packets
+        .iter()
+        .skip(2)
+        .filter_map(|packet| opus_packet_samples(packet).map(|samples| (samples, packet.len())))
+        .collect();
+    let samples = match last_granule {
+        Some(granule) => granule - pre_skip,
+        None => audio.iter().map(|(samples, _)| *samples as i64).sum::<i64>() - pre_skip,
+    };
+
+    Some(AudioInfo {
+        duration_secs: (samples.max(0) as f64 / OPUS_RATE * 100.0).round() / 100.0,
+        waveform: waveform(&audio),
+    })
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// POST /api/files/voice — Upload an Ogg Opus clip as a pending voice message attachment (authenticated)
+pub async fn upload_voice_message(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let max_bytes = MAX_VOICE_BYTES.min(crate::files::max_bytes());
+    let bytes = match crate::files::read_upload(&mut payload, max_bytes).await {
+        Ok((_, bytes)) => bytes,
+        Err(response) => return response,
+    };
+
+    let info = (crate::files::sniff_content_type(&bytes) == Some("audio/ogg"))
+        .then(|| analyze(&bytes))
+        .flatten();
+    let Some(info) = info else {
+        return HttpResponse::UnsupportedMediaType().json(serde_json::json!({ "error": "Voice messages must be Ogg Opus" }));
+    };
+    let max_secs = max_secs();
+    if info.duration_secs > max_secs {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Voice message too long (max {max_secs} s)")
+        }));
+    }
+
+    let upload = crate::scanning::Upload::Bytes(&bytes);
+    let scan = match crate::scanning::scan_upload(upload, "audio/ogg", &crate::files::sha256_hex(&bytes)).await {
+        Ok(scan) => scan,
+        Err(response) => return response,
+    };
+
+    let hash = match crate::files::store_content(pool.get_ref(), storage.get_ref(), bytes, "audio/ogg").await {
+        Ok(hash) => hash,
+        Err(response) => return response,
+    };
+    crate::files::create_attachment(pool.get_ref(), &hash, &claims.sub, VOICE_FILENAME, "voice", None, scan.as_ref()).await
+}
@@ -0,0 +1,196 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Server mute and deafen in voice rooms
+// ═══════════════════════════════════════════════════════
+//
+// Moderators (MUTE_MEMBERS) can server-mute a member of a voice room, so the
+// others stop hearing them, and server-deafen them, so they stop hearing
+// the others. Unlike the `muted` / `deafened` a client announces for
+// itself, only a moderator lifts them, and they hold across reconnects.
+//
+// Voice rooms are a WebRTC mesh and the server carries no audio, so the
+// participants enforce it: every client drops the incoming audio of a
+// server-muted member and sends none to a server-deafened one. A modified
+// client can only fail to honour it for itself, not make the others hear
+// it. The server is the authority on the state: it stamps `server_muted`
+// and `server_deafened` on every `voice_join` and `voice_state` it relays
+// (see `stamp`), and sends a `voice_state` with them when a moderator
+// changes them. Changes are recorded in the audit log.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow)]
+pub struct MemberState {
+    pub user_id: String,
+    pub username: String,
+    pub server_muted: bool,
+    pub server_deafened: bool,
+    pub updated_by_username: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemberStatePatch {
+    pub server_muted: Option<bool>,
+    pub server_deafened: Option<bool>,
+}
+
+/// Whether `user_id` is server-muted and server-deafened in `room_id`.
+pub(crate) async fn server_state(pool: &SqlitePool, room_id: &str, user_id: &str) -> (bool, bool) {
+    sqlx::query_as("SELECT server_muted, server_deafened FROM voice_member_states WHERE room_id = ? AND user_id = ?")
+        .bind(room_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or((false, false))
+}
+
+/// A `voice_join` or `voice_state` from `user_id`, with their server mute
+/// and deafen set by the server, whatever the client sent.
+pub async fn stamp(pool: &SqlitePool, user_id: &str, text: &str) -> String {
+    let Ok(mut event) = serde_json::from_str::<serde_json::Value>(text) else {
+        return text.to_string();
+    };
+    let Some(room_id) = event.get("room_id").and_then(|r| r.as_str()) else {
+        return text.to_string();
+    };
+    let (muted, deafened) = server_state(pool, room_id, user_id).await;
+    event["server_muted"] = muted.into();
+    event["server_deafened"] = deafened.into();
+    event.to_string()
+}
+
+async fn members(pool: &SqlitePool, room_id: &str) -> Vec<MemberState> {
+    sqlx::query_as::<_, MemberState>(
+        "SELECT s.user_id, u.username, s.server_muted, s.server_deafened, s.updated_by_username, s.updated_at \
+         FROM voice_member_states s JOIN users u ON u.id = s.user_id \
+         WHERE s.room_id = ? ORDER BY s.updated_at"
+    )
+    .bind(room_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/rooms/{id}/voice/members — Members server-muted or server-deafened in a voice room
+pub async fn list_member_states(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "room_id": room_id, "members": members(pool.get_ref(), &room_id).await }))
+}
+
+/// PUT /api/rooms/{id}/voice/members/{user_id} — Server-mute or server-deafen a member, or lift it (MUTE_MEMBERS)
+pub async fn set_member_state(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<(String, String)>,
+    body: web::Json<MemberStatePatch>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let (room_id, user_id) = path.into_inner();
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::MUTE_MEMBERS).await {
+        return response;
+    }
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if kind.as_deref() != Some("voice") {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Members are only muted in voice rooms" }));
+    }
+    let username: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let Some(username) = username else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    };
+
+    let (was_muted, was_deafened) = server_state(pool.get_ref(), &room_id, &user_id).await;
+    let muted = body.server_muted.unwrap_or(was_muted);
+    let deafened = body.server_deafened.unwrap_or(was_deafened);
+    let now = Utc::now().to_rfc3339();
+    let result = if muted || deafened {
+        sqlx::query(
+            "INSERT INTO voice_member_states (room_id, user_id, server_muted, server_deafened, updated_by_username, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?) \
+             ON CONFLICT(room_id, user_id) DO UPDATE SET server_muted = excluded.server_muted, \
+             server_deafened = excluded.server_deafened, updated_by_username = excluded.updated_by_username, \
+             updated_at = excluded.updated_at"
+        )
+        .bind(&room_id)
+        .bind(&user_id)
+        .bind(muted)
+        .bind(deafened)
+        .bind(&claims.username)
+        .bind(&now)
+        .execute(pool.get_ref())
+        .await
+    } else {
+        sqlx::query("DELETE FROM voice_member_states WHERE room_id = ? AND user_id = ?")
+            .bind(&room_id)
+            .bind(&user_id)
+            .execute(pool.get_ref())
+            .await
+    };
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let state = MemberState {
+        user_id: user_id.clone(),
+        username,
+        server_muted: muted,
+        server_deafened: deafened,
+        updated_by_username: (muted || deafened).then(|| claims.username.clone()),
+        updated_at: (muted || deafened).then_some(now),
+    };
+    if (muted, deafened) != (was_muted, was_deafened) {
+        let mut changes = serde_json::json!({ "room_id": room_id });
+        if muted != was_muted {
+            changes["server_muted"] = serde_json::json!({ "old": was_muted, "new": muted });
+        }
+        if deafened != was_deafened {
+            changes["server_deafened"] = serde_json::json!({ "old": was_deafened, "new": deafened });
+        }
+        crate::audit_log::record(
+            pool.get_ref(),
+            Some(&claims),
+            crate::audit_log::Action::MemberVoiceUpdated,
+            &user_id,
+            changes,
+            crate::audit_log::reason(&req).as_deref(),
+        )
+        .await;
+        let event = serde_json::json!({
+            "type": "voice_state",
+            "room_id": room_id,
+            "user_id": user_id,
+            "server_muted": muted,
+            "server_deafened": deafened,
+        });
+        let _ = broadcaster.send(event.to_string());
+    }
+    HttpResponse::Ok().json(state)
+}
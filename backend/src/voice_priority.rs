@@ -0,0 +1,191 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Priority speaker in voice rooms
+// ═══════════════════════════════════════════════════════
+//
+// Moderators (admins) can make a member priority speaker in a voice room:
+// while a priority speaker talks, every other participant is ducked by
+// `VOICE_PRIORITY_DUCKING_DB`. Only admins and members of a role with
+// `priority_speaker` can hold it; a member whose role loses the flag stops
+// being listed without the grant being removed.
+//
+// Voice rooms are a WebRTC mesh, so each participant's audio is mixed by
+// the clients: they play the other participants at the ducked gain
+// (10^(-dB/20)) whenever they hear a priority speaker. The server owns who
+// is priority speaker and the ducking amount; clients read both from
+// `GET /api/rooms/{id}/voice/priority` when they join and follow the
+// `voice_priority` events afterwards.
+//
+// Config (env):
+//   VOICE_PRIORITY_DUCKING_DB  attenuation of the others, in dB (default 12, 0-60)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+const DEFAULT_DUCKING_DB: u32 = 12;
+const MAX_DUCKING_DB: u32 = 60;
+
+/// How much the other participants are turned down, in dB.
+pub fn ducking_db() -> u32 {
+    std::env::var("VOICE_PRIORITY_DUCKING_DB")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .filter(|v| *v <= MAX_DUCKING_DB)
+        .unwrap_or(DEFAULT_DUCKING_DB)
+}
+
+/// Whether members with `role` can be made priority speaker.
+pub(crate) async fn eligible(pool: &SqlitePool, role: &str) -> bool {
+    crate::permissions::role_permissions(pool, role).await & crate::permissions::ADMINISTRATOR != 0
+        || sqlx::query_scalar::<_, i64>("SELECT priority_speaker FROM roles WHERE name = ?")
+            .bind(role)
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten()
+            .is_some_and(|flag| flag != 0)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PrioritySpeaker {
+    pub user_id: String,
+    pub username: String,
+    pub granted_by: Option<String>,
+    pub granted_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPrioritySpeaker {
+    pub enabled: bool,
+}
+
+/// Priority speakers of `room_id` whose role still allows it.
+async fn speakers(pool: &SqlitePool, room_id: &str) -> Vec<PrioritySpeaker> {
+    sqlx::query_as::<_, PrioritySpeaker>(
+        "SELECT p.user_id, u.username, p.granted_by, p.granted_at \
+         FROM voice_priority_speakers p \
+         JOIN users u ON u.id = p.user_id \
+         LEFT JOIN roles r ON r.name = u.role \
+         WHERE p.room_id = ? AND (u.role = 'admin' OR r.priority_speaker = 1 OR r.permissions & ? != 0) \
+         ORDER BY p.granted_at"
+    )
+    .bind(room_id)
+    .bind(crate::permissions::ADMINISTRATOR as i64)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+}
+
+async fn room_state(pool: &SqlitePool, room_id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "room_id": room_id,
+        "ducking_db": ducking_db(),
+        "speakers": speakers(pool, room_id).await,
+    })
+}
+
+/// GET /api/rooms/{id}/voice/priority — Priority speakers of a voice room and the ducking amount
+pub async fn get_priority(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+
+    HttpResponse::Ok().json(room_state(pool.get_ref(), &room_id).await)
+}
+
+/// PUT /api/rooms/{id}/voice/priority/{user_id} — Make a member priority speaker, or not (MUTE_MEMBERS)
+pub async fn set_priority(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<(String, String)>,
+    body: web::Json<SetPrioritySpeaker>,
+    broadcaster: web::Data<Broadcaster>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let (room_id, user_id) = path.into_inner();
+    if let Err(response) = crate::permissions::require(pool.get_ref(), &room_id, &claims, crate::permissions::MUTE_MEMBERS).await {
+        return response;
+    }
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    let Some(kind) = kind else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Room not found" }));
+    };
+    if kind != "voice" {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Priority speakers only exist in voice rooms" }));
+    }
+
+    if body.enabled {
+        let role: Option<String> = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+            .bind(&user_id)
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None);
+        let Some(role) = role else {
+            return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+        };
+        let granted = crate::permissions::room_permissions(pool.get_ref(), &room_id, &user_id, &role).await.unwrap_or(0);
+        let needed = crate::permissions::VIEW_ROOM | crate::permissions::CONNECT;
+        if granted & needed != needed {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This user cannot join this room" }));
+        }
+        if !eligible(pool.get_ref(), &role).await {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": "This user's role cannot be priority speaker" }));
+        }
+
+        let result = sqlx::query(
+            "INSERT INTO voice_priority_speakers (room_id, user_id, granted_by, granted_at) VALUES (?, ?, ?, ?) \
+             ON CONFLICT(room_id, user_id) DO NOTHING"
+        )
+        .bind(&room_id)
+        .bind(&user_id)
+        .bind(&claims.sub)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await;
+        if result.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+    } else {
+        let result = sqlx::query("DELETE FROM voice_priority_speakers WHERE room_id = ? AND user_id = ?")
+            .bind(&room_id)
+            .bind(&user_id)
+            .execute(pool.get_ref())
+            .await;
+        if result.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let event = serde_json::json!({
+        "type": "voice_priority",
+        "room_id": room_id,
+        "user_id": user_id,
+        "enabled": body.enabled,
+        "ducking_db": ducking_db(),
+    });
+    let _ = broadcaster.send(event.to_string());
+
+    HttpResponse::Ok().json(room_state(pool.get_ref(), &room_id).await)
+}
@@ -0,0 +1,664 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Voice room recordings
+// ═══════════════════════════════════════════════════════
+//
+// A moderator (MUTE_MEMBERS) starts and stops the recording of a voice
+// room. Recording is opt-in per participant: nobody is recorded until they
+// consent (`PUT .../voice/recording/consent`), and withdrawing consent drops
+// what was uploaded of them. Every start, stop and consent change sends a
+// `voice_recording` event to the room with the whole state (who started
+// it, who consented), so clients always show that a recording runs and
+// whom it captures.
+//
+// Voice rooms are a WebRTC mesh: the server never carries the audio. Each
+// consenting client records its own microphone as one Ogg Opus stream and
+// uploads it in numbered segments (`PUT .../tracks/{seq}`, consecutive
+// byte ranges of the stream, up to the 256 KiB request limit each). Uploads
+// are taken while the recording runs and for `UPLOAD_GRACE_SECS` after it
+// stops, so the last segment makes it.
+//
+// The `voice_recordings` job (see `jobs`) then joins each participant's
+// segments into one track, streamed through a spool file, and stores a
+// ZIP (see `archive`) with `tracks/<name>-<user id>.ogg` per speaker and
+// `recording.json`; the segments are then deleted. It also stops
+// recordings past VOICE_RECORDING_MAX_MINUTES. Recordings stay, linked to
+// the room, until a moderator deletes them. Starting, stopping and deleting
+// are recorded in the audit log.
+//
+// Config (env):
+//   VOICE_RECORDING_MAX_MINUTES  a recording stops by itself after this (default 240)
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Row, SqlitePool};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::archive::{archive_name, Archive};
+use crate::auth::{extract_claims, Claims};
+use crate::files::FileStorage;
+use crate::jobs::{Backlog, JobRegistry};
+use crate::ws::Broadcaster;
+
+const DEFAULT_MAX_MINUTES: i64 = 240;
+const UPLOAD_GRACE_SECS: i64 = 30;
+const WORKER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+fn max_minutes() -> i64 {
+    std::env::var("VOICE_RECORDING_MAX_MINUTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|m| *m > 0)
+        .unwrap_or(DEFAULT_MAX_MINUTES)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Participant {
+    pub user_id: String,
+    pub username: String,
+    pub consented: bool,
+    pub updated_at: String,
+}
+
+async fn participants(pool: &SqlitePool, recording_id: &str) -> Vec<Participant> {
+    sqlx::query_as::<_, Participant>(
+        "SELECT user_id, username, consented, updated_at FROM voice_recording_participants \
+         WHERE recording_id = ? ORDER BY updated_at"
+    )
+    .bind(recording_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+}
+
+/// The id of the recording running in `room_id`, if any.
+async fn running(pool: &SqlitePool, room_id: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT id FROM voice_recordings WHERE room_id = ? AND status = 'recording'")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+}
+
+/// `{ room_id, recording }`, `recording` being null unless one runs.
+async fn room_state(pool: &SqlitePool, room_id: &str) -> serde_json::Value {
+    let row = sqlx::query("SELECT id, started_by_username, started_at FROM voice_recordings WHERE room_id = ? AND status = 'recording'")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    let recording = match row {
+        Some(row) => {
+            let id: String = row.get("id");
+            json!({
+                "participants": participants(pool, &id).await,
+                "id": id,
+                "started_by_username": row.get::<String, _>("started_by_username"),
+                "started_at": row.get::<String, _>("started_at"),
+            })
+        }
+        None => serde_json::Value::Null,
+    };
+    json!({ "room_id": room_id, "recording": recording })
+}
+
+/// Send the room's recording state to everyone in it.
+async fn announce(pool: &SqlitePool, broadcaster: &Broadcaster, room_id: &str) -> serde_json::Value {
+    let state = room_state(pool, room_id).await;
+    let mut event = state.clone();
+    event["type"] = "voice_recording".into();
+    let _ = broadcaster.send(event.to_string());
+    state
+}
+
+/// Delete the stored segments of a recording, of one participant or all.
+async fn drop_segments(pool: &SqlitePool, storage: &FileStorage, recording_id: &str, user_id: Option<&str>) -> Result<(), String> {
+    let keys: Vec<String> = sqlx::query_scalar(
+        "DELETE FROM voice_recording_segments WHERE recording_id = ? AND (? IS NULL OR user_id = ?) RETURNING object_key"
+    )
+    .bind(recording_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    for key in keys {
+        storage.delete(&key).await;
+    }
+    Ok(())
+}
+
+// ── Building recordings ─────────────────────────────────
+
+/// Join the segments of every consenting participant into the recording's
+/// ZIP and put it in storage; returns its key and size.
+async fn assemble(pool: &SqlitePool, storage: &FileStorage, recording_id: &str) -> Result<(String, u64), String> {
+    let recording = sqlx::query(
+        "SELECT room_id, started_by_username, started_at, stopped_at, stopped_by_username FROM voice_recordings WHERE id = ?"
+    )
+    .bind(recording_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let speakers: Vec<(String, String)> = sqlx::query_as(
+        "SELECT p.user_id, p.username FROM voice_recording_participants p WHERE p.recording_id = ? AND p.consented = 1 \
+         AND EXISTS (SELECT 1 FROM voice_recording_segments s WHERE s.recording_id = p.recording_id AND s.user_id = p.user_id) \
+         ORDER BY p.username"
+    )
+    .bind(recording_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    if speakers.is_empty() {
+        return Err("Nobody consented to being recorded".to_string());
+    }
+
+    let dir = storage.spool_dir();
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    let zip = dir.join(format!("voice-recording-{recording_id}.zip"));
+    let track = dir.join(format!("voice-recording-{recording_id}.ogg"));
+    let built = async {
+        let mut archive = Archive::create(&zip).await?;
+        let mut tracks = Vec::new();
+        for (user_id, username) in &speakers {
+            let segments: Vec<(String, i64)> = sqlx::query_as(
+                "SELECT object_key, size_bytes FROM voice_recording_segments WHERE recording_id = ? AND user_id = ? ORDER BY seq"
+            )
+            .bind(recording_id)
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+            let mut out = tokio::io::BufWriter::new(tokio::fs::File::create(&track).await.map_err(|e| e.to_string())?);
+            let mut bytes = 0u64;
+            for (key, _) in &segments {
+                let segment = storage.get(key).await.ok_or("A segment is missing from storage")?;
+                out.write_all(&segment).await.map_err(|e| e.to_string())?;
+                bytes += segment.len() as u64;
+            }
+            out.flush().await.map_err(|e| e.to_string())?;
+            let path = format!("tracks/{}-{user_id}.ogg", archive_name(username));
+            archive.add_file(&path, &track).await?;
+            tracks.push(json!({ "user_id": user_id, "username": username, "path": path, "segments": segments.len(), "bytes": bytes }));
+        }
+        archive
+            .add_json("recording.json", &json!({
+                "id": recording_id,
+                "room_id": recording.get::<String, _>("room_id"),
+                "started_by_username": recording.get::<String, _>("started_by_username"),
+                "started_at": recording.get::<String, _>("started_at"),
+                "stopped_by_username": recording.get::<Option<String>, _>("stopped_by_username"),
+                "stopped_at": recording.get::<Option<String>, _>("stopped_at"),
+                "tracks": tracks,
+            }))
+            .await?;
+        let size = archive.finish().await?;
+        // Random, so the recording can't be found from anything public.
+        let key = crate::files::sha256_hex(Uuid::new_v4().as_bytes());
+        storage.put_file(&key, &zip).await?;
+        Ok((key, size))
+    }
+    .await;
+    tokio::fs::remove_file(&track).await.ok();
+    tokio::fs::remove_file(&zip).await.ok();
+    built
+}
+
+/// Stop recordings past their longest duration, then build every stopped
+/// one whose upload grace is over. Returns how many were stopped or built.
+pub async fn process_recordings(pool: &SqlitePool, storage: &FileStorage, broadcaster: &Broadcaster) -> Result<u64, String> {
+    let now = Utc::now();
+    let overdue: Vec<String> = sqlx::query_scalar(
+        "UPDATE voice_recordings SET status = 'processing', stopped_at = ? WHERE status = 'recording' AND started_at <= ? \
+         RETURNING room_id"
+    )
+    .bind(now.to_rfc3339())
+    .bind((now - Duration::minutes(max_minutes())).to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let mut processed = overdue.len() as u64;
+    for room_id in &overdue {
+        announce(pool, broadcaster, room_id).await;
+    }
+
+    let ready: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM voice_recordings WHERE status = 'processing' AND stopped_at <= ? ORDER BY stopped_at"
+    )
+    .bind((now - Duration::seconds(UPLOAD_GRACE_SECS)).to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let mut failure = None;
+    for id in ready {
+        let finished = Utc::now().to_rfc3339();
+        let update = match assemble(pool, storage, &id).await {
+            Ok((key, size)) => sqlx::query(
+                "UPDATE voice_recordings SET status = 'ready', archive_key = ?, size_bytes = ?, completed_at = ? WHERE id = ?"
+            )
+            .bind(key)
+            .bind(size as i64)
+            .bind(finished)
+            .bind(&id),
+            Err(e) => {
+                failure.get_or_insert(format!("voice recording {id}: {e}"));
+                sqlx::query("UPDATE voice_recordings SET status = 'failed', error = ?, completed_at = ? WHERE id = ?")
+                    .bind(e)
+                    .bind(finished)
+                    .bind(&id)
+            }
+        };
+        update.execute(pool).await.map_err(|e| e.to_string())?;
+        drop_segments(pool, storage, &id, None).await?;
+        processed += 1;
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(processed),
+    }
+}
+
+async fn recording_backlog(pool: &SqlitePool) -> Backlog {
+    sqlx::query("SELECT COUNT(*) AS depth, MIN(stopped_at) AS oldest_due_at FROM voice_recordings WHERE status = 'processing'")
+        .fetch_one(pool)
+        .await
+        .map(|row| Backlog::from_row(&row))
+        .unwrap_or_default()
+}
+
+pub fn spawn_recording_worker(pool: SqlitePool, storage: FileStorage, broadcaster: Broadcaster, jobs: JobRegistry) {
+    crate::jobs::register(&jobs, crate::jobs::VOICE_RECORDINGS);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(WORKER_INTERVAL);
+        loop {
+            interval.tick().await;
+            crate::jobs::run(
+                &jobs,
+                crate::jobs::VOICE_RECORDINGS,
+                None,
+                process_recordings(&pool, &storage, &broadcaster),
+                recording_backlog(&pool),
+            )
+            .await;
+        }
+    });
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct Recording {
+    pub id: String,
+    pub room_id: String,
+    pub started_by_username: String,
+    /// `recording`, `processing`, `ready` or `failed`.
+    pub status: String,
+    pub started_at: String,
+    pub stopped_at: Option<String>,
+    pub stopped_by_username: Option<String>,
+    pub completed_at: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    /// While `ready`.
+    pub download_url: Option<String>,
+}
+
+const RECORDING_SELECT: &str = "SELECT id, room_id, started_by_username, status, started_at, stopped_at, \
+     stopped_by_username, completed_at, size_bytes, error FROM voice_recordings";
+
+impl Recording {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        let id: String = row.get("id");
+        let room_id: String = row.get("room_id");
+        let status: String = row.get("status");
+        Recording {
+            download_url: (status == "ready").then(|| format!("/api/rooms/{room_id}/voice/recordings/{id}/download")),
+            id,
+            room_id,
+            started_by_username: row.get("started_by_username"),
+            status,
+            started_at: row.get("started_at"),
+            stopped_at: row.get("stopped_at"),
+            stopped_by_username: row.get("stopped_by_username"),
+            completed_at: row.get("completed_at"),
+            size_bytes: row.get("size_bytes"),
+            error: row.get("error"),
+        }
+    }
+}
+
+async fn fetch_recording(pool: &SqlitePool, room_id: &str, recording_id: &str) -> Option<Recording> {
+    sqlx::query(&format!("{RECORDING_SELECT} WHERE id = ? AND room_id = ?"))
+        .bind(recording_id)
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Recording::from_row(&row))
+}
+
+/// The caller's claims if they hold `permission` in the room.
+async fn require(pool: &SqlitePool, req: &HttpRequest, room_id: &str, permission: u64) -> Result<Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require(pool, room_id, &claims, permission).await?;
+    Ok(claims)
+}
+
+/// GET /api/rooms/{id}/voice/recording — Whether the room is being recorded, and who consented
+pub async fn get_recording(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let room_id = path.into_inner();
+    if let Err(response) = require(pool.get_ref(), &req, &room_id, crate::permissions::VIEW_ROOM).await {
+        return response;
+    }
+    HttpResponse::Ok().json(room_state(pool.get_ref(), &room_id).await)
+}
+
+/// POST /api/rooms/{id}/voice/recording — Start recording a voice room (MUTE_MEMBERS)
+pub async fn start_recording(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let room_id = path.into_inner();
+    let claims = match require(pool.get_ref(), &req, &room_id, crate::permissions::MUTE_MEMBERS).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None);
+    if kind.as_deref() != Some("voice") {
+        return HttpResponse::BadRequest().json(json!({ "error": "Only voice rooms can be recorded" }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let started = sqlx::query(
+        "INSERT INTO voice_recordings (id, room_id, started_by, started_by_username, status, started_at) \
+         VALUES (?, ?, ?, ?, 'recording', ?)"
+    )
+    .bind(&id)
+    .bind(&room_id)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    match started {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return HttpResponse::Conflict().json(json!({ "error": "This room is already being recorded" }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::VoiceRecordingStarted,
+        &room_id,
+        json!({ "recording_id": id }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::Created().json(announce(pool.get_ref(), broadcaster.get_ref(), &room_id).await)
+}
+
+/// DELETE /api/rooms/{id}/voice/recording — Stop the room's recording (MUTE_MEMBERS)
+pub async fn stop_recording(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let room_id = path.into_inner();
+    let claims = match require(pool.get_ref(), &req, &room_id, crate::permissions::MUTE_MEMBERS).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let stopped: Option<String> = sqlx::query_scalar(
+        "UPDATE voice_recordings SET status = 'processing', stopped_at = ?, stopped_by_username = ? \
+         WHERE room_id = ? AND status = 'recording' RETURNING id"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(&claims.username)
+    .bind(&room_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some(id) = stopped else {
+        return HttpResponse::NotFound().json(json!({ "error": "This room is not being recorded" }));
+    };
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::VoiceRecordingStopped,
+        &room_id,
+        json!({ "recording_id": id }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    announce(pool.get_ref(), broadcaster.get_ref(), &room_id).await;
+    match fetch_recording(pool.get_ref(), &room_id, &id).await {
+        Some(recording) => HttpResponse::Ok().json(recording),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Consent {
+    pub consent: bool,
+}
+
+/// PUT /api/rooms/{id}/voice/recording/consent — Agree to be recorded, or withdraw (CONNECT)
+pub async fn set_consent(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<Consent>,
+) -> HttpResponse {
+    let room_id = path.into_inner();
+    let claims = match require(pool.get_ref(), &req, &room_id, crate::permissions::CONNECT).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Some(id) = running(pool.get_ref(), &room_id).await else {
+        return HttpResponse::NotFound().json(json!({ "error": "This room is not being recorded" }));
+    };
+    let saved = sqlx::query(
+        "INSERT INTO voice_recording_participants (recording_id, user_id, username, consented, updated_at) VALUES (?, ?, ?, ?, ?) \
+         ON CONFLICT(recording_id, user_id) DO UPDATE SET consented = excluded.consented, updated_at = excluded.updated_at"
+    )
+    .bind(&id)
+    .bind(&claims.sub)
+    .bind(&claims.username)
+    .bind(body.consent)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    if saved.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if !body.consent && drop_segments(pool.get_ref(), storage.get_ref(), &id, Some(&claims.sub)).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().json(announce(pool.get_ref(), broadcaster.get_ref(), &room_id).await)
+}
+
+/// PUT /api/rooms/{id}/voice/recording/tracks/{seq} — Upload the next segment of the caller's track (CONNECT, consented)
+pub async fn upload_segment(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<(String, i64)>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let (room_id, seq) = path.into_inner();
+    let claims = match require(pool.get_ref(), &req, &room_id, crate::permissions::CONNECT).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    // The latest recording, while it runs or within the grace after it stopped.
+    let id: Option<String> = sqlx::query_scalar(
+        "SELECT id FROM voice_recordings WHERE room_id = ? AND (status = 'recording' OR (status = 'processing' AND stopped_at > ?)) \
+         ORDER BY started_at DESC LIMIT 1"
+    )
+    .bind(&room_id)
+    .bind((Utc::now() - Duration::seconds(UPLOAD_GRACE_SECS)).to_rfc3339())
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some(id) = id else {
+        return HttpResponse::Conflict().json(json!({ "error": "This room is not being recorded" }));
+    };
+    let consented: bool = sqlx::query_scalar("SELECT consented FROM voice_recording_participants WHERE recording_id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&claims.sub)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None)
+        .unwrap_or(false);
+    if !consented {
+        return HttpResponse::Forbidden().json(json!({ "error": "Consent to the recording first" }));
+    }
+    if body.is_empty() || (seq == 0 && !body.starts_with(b"OggS")) {
+        return HttpResponse::BadRequest().json(json!({ "error": "A track is an Ogg Opus stream, sent in order" }));
+    }
+    let next: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM voice_recording_segments WHERE recording_id = ? AND user_id = ?")
+        .bind(&id)
+        .bind(&claims.sub)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if seq < next {
+        // A retry of a segment already stored.
+        return HttpResponse::NoContent().finish();
+    }
+    if seq > next {
+        return HttpResponse::Conflict().json(json!({ "error": "Segments must be sent in order", "next_seq": next }));
+    }
+
+    if let Err(response) = crate::scanning::refuse_flagged(&body).await {
+        return response;
+    }
+    let key = crate::files::sha256_hex(Uuid::new_v4().as_bytes());
+    if storage.put(&key, body.to_vec()).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let stored = sqlx::query(
+        "INSERT INTO voice_recording_segments (recording_id, user_id, seq, object_key, size_bytes, created_at) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&claims.sub)
+    .bind(seq)
+    .bind(&key)
+    .bind(body.len() as i64)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+    match stored {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            storage.delete(&key).await;
+            match e {
+                // The same segment, sent twice at once.
+                sqlx::Error::Database(e) if e.is_unique_violation() => HttpResponse::NoContent().finish(),
+                _ => HttpResponse::InternalServerError().finish(),
+            }
+        }
+    }
+}
+
+/// GET /api/rooms/{id}/voice/recordings — The room's recordings, newest first (MUTE_MEMBERS)
+pub async fn list_recordings(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let room_id = path.into_inner();
+    if let Err(response) = require(pool.get_ref(), &req, &room_id, crate::permissions::MUTE_MEMBERS).await {
+        return response;
+    }
+    let rows = sqlx::query(&format!("{RECORDING_SELECT} WHERE room_id = ? ORDER BY started_at DESC"))
+        .bind(&room_id)
+        .fetch_all(pool.get_ref())
+        .await;
+    match rows {
+        Ok(rows) => HttpResponse::Ok().json(json!({ "recordings": rows.iter().map(Recording::from_row).collect::<Vec<_>>() })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/rooms/{id}/voice/recordings/{recording_id}/download — The ZIP of a ready recording, streamed (MUTE_MEMBERS)
+pub async fn download_recording(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (room_id, recording_id) = path.into_inner();
+    if let Err(response) = require(pool.get_ref(), &req, &room_id, crate::permissions::MUTE_MEMBERS).await {
+        return response;
+    }
+    let stored: Option<(String, i64)> = sqlx::query_as(
+        "SELECT archive_key, size_bytes FROM voice_recordings WHERE id = ? AND room_id = ? AND status = 'ready'"
+    )
+    .bind(&recording_id)
+    .bind(&room_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .unwrap_or(None);
+    let Some((key, size)) = stored else {
+        return HttpResponse::NotFound().json(json!({ "error": "Recording not found" }));
+    };
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("voice-recording-{recording_id}.zip"))],
+        })
+        .insert_header(("Cache-Control", "private, no-store"))
+        .streaming(storage.get_ref().clone().stream(key, size.max(0) as u64))
+}
+
+/// DELETE /api/rooms/{id}/voice/recordings/{recording_id} — Delete a stopped recording and its audio (MUTE_MEMBERS)
+pub async fn delete_recording(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<FileStorage>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (room_id, recording_id) = path.into_inner();
+    let claims = match require(pool.get_ref(), &req, &room_id, crate::permissions::MUTE_MEMBERS).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Some(recording) = fetch_recording(pool.get_ref(), &room_id, &recording_id).await else {
+        return HttpResponse::NotFound().json(json!({ "error": "Recording not found" }));
+    };
+    if recording.status == "recording" || recording.status == "processing" {
+        return HttpResponse::Conflict().json(json!({ "error": "Stop the recording and wait for it to be ready first" }));
+    }
+    let key: Option<String> = sqlx::query_scalar("DELETE FROM voice_recordings WHERE id = ? RETURNING archive_key")
+        .bind(&recording_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or(None)
+        .flatten();
+    if let Some(key) = key {
+        storage.delete(&key).await;
+    }
+    crate::audit_log::record(
+        pool.get_ref(),
+        Some(&claims),
+        crate::audit_log::Action::VoiceRecordingDeleted,
+        &room_id,
+        json!({ "recording_id": recording_id, "started_at": recording.started_at }),
+        crate::audit_log::reason(&req).as_deref(),
+    )
+    .await;
+    HttpResponse::NoContent().finish()
+}
@@ -0,0 +1,262 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Voice settings (push-to-talk, voice activity)
+// ═══════════════════════════════════════════════════════
+//
+// How a user's microphone opens, kept on the server so the desktop and web
+// clients agree:
+//   - input_mode               `voice_activity` (default) or `push_to_talk`
+//   - vad_automatic            the client picks the voice activity threshold
+//   - vad_threshold_db         otherwise, the level that opens the mic
+//                              (-100 to 0 dBFS, default -50)
+//   - push_to_talk_release_ms  how long the mic stays open after the key is
+//                              released (0-2000, default 200)
+//   - noise_suppression        `off`, `standard` (default) or `high`
+//   - user_volumes             how loud the user plays other members, in
+//                              percent (0-200) by user id; 100 is not listed
+//
+// The audio processing itself happens in the clients. The push-to-talk key
+// is not kept: it belongs to a device. Every change goes to all the user's
+// connections as `voice_settings_updated`, so an open client picks it up
+// without a reload.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::BTreeMap;
+
+use crate::auth::extract_claims;
+use crate::ws::Broadcaster;
+
+const INPUT_MODES: [&str; 2] = ["voice_activity", "push_to_talk"];
+const NOISE_SUPPRESSION: [&str; 3] = ["off", "standard", "high"];
+const MIN_THRESHOLD_DB: i64 = -100;
+const MAX_RELEASE_MS: i64 = 2000;
+const DEFAULT_VOLUME: i64 = 100;
+const MAX_VOLUME: i64 = 200;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VoiceSettings {
+    pub input_mode: String,
+    pub vad_automatic: bool,
+    pub vad_threshold_db: i64,
+    pub push_to_talk_release_ms: i64,
+    pub noise_suppression: String,
+    /// Percent per user id, for members not at 100.
+    pub user_volumes: BTreeMap<String, i64>,
+    /// `None` until the user changes something.
+    pub updated_at: Option<String>,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        VoiceSettings {
+            input_mode: "voice_activity".to_string(),
+            vad_automatic: true,
+            vad_threshold_db: -50,
+            push_to_talk_release_ms: 200,
+            noise_suppression: "standard".to_string(),
+            user_volumes: BTreeMap::new(),
+            updated_at: None,
+        }
+    }
+}
+
+/// `user_id`'s voice settings, the defaults when never set.
+pub(crate) async fn load(pool: &SqlitePool, user_id: &str) -> VoiceSettings {
+    let row = sqlx::query(
+        "SELECT input_mode, vad_automatic, vad_threshold_db, push_to_talk_release_ms, noise_suppression, updated_at \
+         FROM voice_settings WHERE user_id = ?"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten();
+    let mut settings = match row {
+        Some(row) => VoiceSettings {
+            input_mode: row.get("input_mode"),
+            vad_automatic: row.get("vad_automatic"),
+            vad_threshold_db: row.get("vad_threshold_db"),
+            push_to_talk_release_ms: row.get("push_to_talk_release_ms"),
+            noise_suppression: row.get("noise_suppression"),
+            user_volumes: BTreeMap::new(),
+            updated_at: row.get("updated_at"),
+        },
+        None => VoiceSettings::default(),
+    };
+    let volumes: Vec<(String, i64, String)> =
+        sqlx::query_as("SELECT target_id, volume, updated_at FROM voice_user_volumes WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+    for (target_id, volume, updated_at) in volumes {
+        settings.user_volumes.insert(target_id, volume);
+        if settings.updated_at.as_ref().is_none_or(|at| *at < updated_at) {
+            settings.updated_at = Some(updated_at);
+        }
+    }
+    settings
+}
+
+fn announce(broadcaster: &Broadcaster, user_id: &str, settings: &VoiceSettings) {
+    let event = serde_json::json!({
+        "type": "voice_settings_updated",
+        "recipient_id": user_id,
+        "settings": settings,
+    });
+    let _ = broadcaster.send(event.to_string());
+}
+
+/// Fields to change; missing ones are kept.
+#[derive(Debug, Deserialize)]
+pub struct VoiceSettingsPatch {
+    pub input_mode: Option<String>,
+    pub vad_automatic: Option<bool>,
+    pub vad_threshold_db: Option<i64>,
+    pub push_to_talk_release_ms: Option<i64>,
+    pub noise_suppression: Option<String>,
+}
+
+impl VoiceSettingsPatch {
+    fn apply(&self, settings: &mut VoiceSettings) -> Result<(), String> {
+        if let Some(mode) = &self.input_mode {
+            if !INPUT_MODES.contains(&mode.as_str()) {
+                return Err("input_mode must be voice_activity or push_to_talk".to_string());
+            }
+            settings.input_mode = mode.clone();
+        }
+        if let Some(automatic) = self.vad_automatic {
+            settings.vad_automatic = automatic;
+        }
+        if let Some(threshold) = self.vad_threshold_db {
+            if !(MIN_THRESHOLD_DB..=0).contains(&threshold) {
+                return Err(format!("vad_threshold_db must be between {MIN_THRESHOLD_DB} and 0"));
+            }
+            settings.vad_threshold_db = threshold;
+        }
+        if let Some(release) = self.push_to_talk_release_ms {
+            if !(0..=MAX_RELEASE_MS).contains(&release) {
+                return Err(format!("push_to_talk_release_ms must be between 0 and {MAX_RELEASE_MS}"));
+            }
+            settings.push_to_talk_release_ms = release;
+        }
+        if let Some(level) = &self.noise_suppression {
+            if !NOISE_SUPPRESSION.contains(&level.as_str()) {
+                return Err("noise_suppression must be off, standard or high".to_string());
+            }
+            settings.noise_suppression = level.clone();
+        }
+        Ok(())
+    }
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/users/@me/voice-settings — The caller's voice settings
+pub async fn get_voice_settings(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    HttpResponse::Ok().json(load(pool.get_ref(), &claims.sub).await)
+}
+
+/// PATCH /api/users/@me/voice-settings — Change some of the caller's voice settings
+pub async fn update_voice_settings(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    body: web::Json<VoiceSettingsPatch>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let mut settings = load(pool.get_ref(), &claims.sub).await;
+    if let Err(error) = body.apply(&mut settings) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+    }
+    settings.updated_at = Some(Utc::now().to_rfc3339());
+    let result = sqlx::query(
+        "INSERT INTO voice_settings (user_id, input_mode, vad_automatic, vad_threshold_db, push_to_talk_release_ms, noise_suppression, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(user_id) DO UPDATE SET input_mode = excluded.input_mode, vad_automatic = excluded.vad_automatic, \
+         vad_threshold_db = excluded.vad_threshold_db, push_to_talk_release_ms = excluded.push_to_talk_release_ms, \
+         noise_suppression = excluded.noise_suppression, updated_at = excluded.updated_at"
+    )
+    .bind(&claims.sub)
+    .bind(&settings.input_mode)
+    .bind(settings.vad_automatic)
+    .bind(settings.vad_threshold_db)
+    .bind(settings.push_to_talk_release_ms)
+    .bind(&settings.noise_suppression)
+    .bind(&settings.updated_at)
+    .execute(pool.get_ref())
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    announce(broadcaster.get_ref(), &claims.sub, &settings);
+    HttpResponse::Ok().json(settings)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetUserVolume {
+    pub volume: i64,
+}
+
+/// PUT /api/users/@me/voice-settings/volumes/{user_id} — How loud the caller plays a member, in percent
+pub async fn set_user_volume(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    path: web::Path<String>,
+    body: web::Json<SetUserVolume>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let target_id = path.into_inner();
+    if !(0..=MAX_VOLUME).contains(&body.volume) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": format!("volume must be between 0 and {MAX_VOLUME}") }));
+    }
+    if target_id == claims.sub {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "You cannot set your own volume" }));
+    }
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)")
+        .bind(&target_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "User not found" }));
+    }
+
+    let result = if body.volume == DEFAULT_VOLUME {
+        sqlx::query("DELETE FROM voice_user_volumes WHERE user_id = ? AND target_id = ?")
+            .bind(&claims.sub)
+            .bind(&target_id)
+            .execute(pool.get_ref())
+            .await
+    } else {
+        sqlx::query(
+            "INSERT INTO voice_user_volumes (user_id, target_id, volume, updated_at) VALUES (?, ?, ?, ?) \
+             ON CONFLICT(user_id, target_id) DO UPDATE SET volume = excluded.volume, updated_at = excluded.updated_at"
+        )
+        .bind(&claims.sub)
+        .bind(&target_id)
+        .bind(body.volume)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool.get_ref())
+        .await
+    };
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let settings = load(pool.get_ref(), &claims.sub).await;
+    announce(broadcaster.get_ref(), &claims.sub, &settings);
+    HttpResponse::Ok().json(settings)
+}
@@ -0,0 +1,234 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Activity state sync in voice rooms
+// ═══════════════════════════════════════════════════════
+//
+// A small shared state per voice room that clients build activities on
+// (watching a video together, a board game...) without a server of their
+// own. The server does not understand the state: it is a JSON blob under
+// an activity name, and the server only orders the updates.
+//
+// People in the room's voice send `activity_state { room_id, activity,
+// state, expected_seq? }` over the realtime gateway (see `gateway.rs`).
+// Each accepted update gets the room's next `seq` and goes to everyone in
+// the room as an `activity_state` event, the sender included. With
+// `expected_seq`, the update is only applied if that is still the room's
+// last `seq` (refused `seq_conflict` otherwise), for activities where
+// concurrent moves must not overwrite each other. A `state` of `null`
+// ends the activity.
+//
+// The state lives in memory while someone is in the room's voice; the
+// last one leaving ends it. Late joiners read it with
+// `GET /api/rooms/{id}/voice/activity`.
+//
+// Config (env):
+//   VOICE_SYNC_MAX_STATE_BYTES  largest serialized state (default 8192)
+//   VOICE_SYNC_MIN_INTERVAL_MS  time between two updates of one user in a room (default 100)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::{extract_claims, Claims};
+use crate::voice_activity::VoiceOccupancy;
+use crate::ws::Broadcaster;
+
+const DEFAULT_MAX_STATE_BYTES: usize = 8192;
+const DEFAULT_MIN_INTERVAL_MS: u64 = 100;
+const MAX_ACTIVITY_LEN: usize = 32;
+
+/// The activity running in a voice room.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityState {
+    pub activity: String,
+    pub seq: u64,
+    pub state: serde_json::Value,
+    pub user_id: String,
+    pub username: String,
+    pub updated_at: String,
+}
+
+#[derive(Default)]
+pub struct RoomSync {
+    /// Last `seq` handed out in the room, kept when an activity ends.
+    seq: u64,
+    current: Option<ActivityState>,
+    last_update: HashMap<String, Instant>,
+}
+
+/// Activity state per voice room id.
+pub type VoiceSync = Arc<Mutex<HashMap<String, RoomSync>>>;
+
+pub fn create_voice_sync() -> VoiceSync {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+fn max_state_bytes() -> usize {
+    std::env::var("VOICE_SYNC_MAX_STATE_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_MAX_STATE_BYTES)
+}
+
+fn min_interval() -> Duration {
+    let ms = std::env::var("VOICE_SYNC_MIN_INTERVAL_MS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MIN_INTERVAL_MS);
+    Duration::from_millis(ms)
+}
+
+/// An `activity_state` sent by a client.
+#[derive(Debug, Deserialize)]
+pub struct ActivityUpdate {
+    pub room_id: String,
+    pub activity: String,
+    /// `null` ends the activity.
+    #[serde(default)]
+    pub state: serde_json::Value,
+    pub expected_seq: Option<u64>,
+}
+
+/// Why an update was refused, as a gateway `error` payload.
+#[derive(Debug)]
+pub enum SyncRefusal {
+    NotInVoice,
+    Invalid(String),
+    TooLarge(usize),
+    RateLimited(Duration),
+    SeqConflict(u64),
+}
+
+impl SyncRefusal {
+    pub fn error(&self, room_id: &str) -> serde_json::Value {
+        match self {
+            SyncRefusal::NotInVoice => serde_json::json!({ "code": "not_in_voice", "room_id": room_id }),
+            SyncRefusal::Invalid(error) => {
+                serde_json::json!({ "code": "invalid_activity", "room_id": room_id, "error": error })
+            }
+            SyncRefusal::TooLarge(max) => {
+                serde_json::json!({ "code": "activity_state_too_large", "room_id": room_id, "max_bytes": max })
+            }
+            SyncRefusal::RateLimited(wait) => serde_json::json!({
+                "code": "rate_limited",
+                "room_id": room_id,
+                "retry_after_ms": wait.as_millis().max(1) as u64,
+            }),
+            SyncRefusal::SeqConflict(seq) => serde_json::json!({ "code": "seq_conflict", "room_id": room_id, "seq": seq }),
+        }
+    }
+}
+
+fn in_voice(occupancy: &VoiceOccupancy, room_id: &str, user_id: &str) -> bool {
+    occupancy
+        .lock()
+        .unwrap()
+        .get(room_id)
+        .is_some_and(|members| members.contains_key(user_id))
+}
+
+fn is_empty(occupancy: &VoiceOccupancy, room_id: &str) -> bool {
+    occupancy.lock().unwrap().get(room_id).is_none_or(|members| members.is_empty())
+}
+
+/// The activity running in `room_id`, if anyone is still in its voice.
+pub fn current(sync: &VoiceSync, occupancy: &VoiceOccupancy, room_id: &str) -> Option<ActivityState> {
+    let stale = is_empty(occupancy, room_id);
+    let mut guard = sync.lock().unwrap();
+    if stale {
+        guard.remove(room_id);
+        return None;
+    }
+    guard.get(room_id).and_then(|room| room.current.clone())
+}
+
+/// Apply an update from `claims` and send it to the room.
+pub fn publish(
+    sync: &VoiceSync,
+    occupancy: &VoiceOccupancy,
+    broadcaster: &Broadcaster,
+    claims: &Claims,
+    update: ActivityUpdate,
+) -> Result<u64, SyncRefusal> {
+    if !in_voice(occupancy, &update.room_id, &claims.sub) {
+        return Err(SyncRefusal::NotInVoice);
+    }
+    let activity = update.activity.trim();
+    if activity.is_empty() || activity.len() > MAX_ACTIVITY_LEN {
+        return Err(SyncRefusal::Invalid(format!("activity must be 1 to {MAX_ACTIVITY_LEN} characters")));
+    }
+    let max = max_state_bytes();
+    if update.state.to_string().len() > max {
+        return Err(SyncRefusal::TooLarge(max));
+    }
+
+    let event = {
+        let mut guard = sync.lock().unwrap();
+        // The room emptied since the last update: start over.
+        if is_empty(occupancy, &update.room_id) {
+            guard.remove(&update.room_id);
+        }
+        let room = guard.entry(update.room_id.clone()).or_default();
+        let now = Instant::now();
+        if let Some(last) = room.last_update.get(&claims.sub) {
+            let wait = min_interval().saturating_sub(now.duration_since(*last));
+            if !wait.is_zero() {
+                return Err(SyncRefusal::RateLimited(wait));
+            }
+        }
+        if let Some(expected) = update.expected_seq {
+            if expected != room.seq {
+                return Err(SyncRefusal::SeqConflict(room.seq));
+            }
+        }
+        room.last_update.insert(claims.sub.clone(), now);
+        room.seq += 1;
+        let seq = room.seq;
+        room.current = (!update.state.is_null()).then(|| ActivityState {
+            activity: activity.to_string(),
+            seq,
+            state: update.state.clone(),
+            user_id: claims.sub.clone(),
+            username: claims.username.clone(),
+            updated_at: Utc::now().to_rfc3339(),
+        });
+        serde_json::json!({
+            "type": "activity_state",
+            "room_id": update.room_id,
+            "user_id": claims.sub,
+            "username": claims.username,
+            "activity": activity,
+            "seq": seq,
+            "state": update.state,
+        })
+    };
+    let seq = event["seq"].as_u64().unwrap_or_default();
+    let _ = broadcaster.send(event.to_string());
+    Ok(seq)
+}
+
+// ── HTTP Handlers ───────────────────────────────────────
+
+/// GET /api/rooms/{id}/voice/activity — The activity running in a voice room, for late joiners
+pub async fn get_activity(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    sync: web::Data<VoiceSync>,
+    occupancy: web::Data<VoiceOccupancy>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let room_id = path.into_inner();
+    if let Err(response) = crate::rooms::check_room_access(pool.get_ref(), &room_id, &claims).await {
+        return response;
+    }
+    let activity = current(sync.get_ref(), occupancy.get_ref(), &room_id);
+    HttpResponse::Ok().json(serde_json::json!({ "room_id": room_id, "activity": activity }))
+}
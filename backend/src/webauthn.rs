@@ -0,0 +1,677 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Passkey (WebAuthn) login for Voxium accounts
+// ═══════════════════════════════════════════════════════
+//
+// Hand-rolled relying party for the two WebAuthn ceremonies:
+//   - registration: a logged-in user adds a passkey to their account
+//   - assertion:    anyone signs in with a passkey, no password needed
+//
+// Only what Voxium needs is implemented: "none" attestation (the attestation
+// statement is not verified), ES256 and RS256 keys, and discoverable
+// credentials so the username can be left empty at login. A passkey replaces
+// the password, so user verification (PIN, biometrics) is required on both
+// ceremonies. Challenges live in memory for a few minutes and are single-use.
+//
+// Config (env):
+//   WEBAUTHN_RP_ID    relying party id, a domain (default "localhost")
+//   WEBAUTHN_ORIGINS  comma-separated allowed client origins
+//                     (default: the Tauri and local dev origins)
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine};
+use ciborium::Value;
+use p256::ecdsa::signature::Verifier;
+use rand::RngCore;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::{extract_claims, AuthResponse};
+use crate::sessions::{issue_token, DeviceInfo};
+use crate::sudo::Sudo;
+
+/// How long a started ceremony can be finished.
+const CEREMONY_TTL: Duration = Duration::from_secs(5 * 60);
+/// Timeout hint given to the browser, in milliseconds.
+const CEREMONY_TIMEOUT_MS: u64 = 120_000;
+const MAX_PASSKEY_NAME_CHARS: usize = 64;
+
+const COSE_ALG_ES256: i64 = -7;
+const COSE_ALG_RS256: i64 = -257;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+
+const DEFAULT_ORIGINS: [&str; 6] = [
+    "tauri://localhost",
+    "https://tauri.localhost",
+    "http://localhost:1420",
+    "http://127.0.0.1:1420",
+    "http://localhost:1430",
+    "http://127.0.0.1:1430",
+];
+
+fn rp_id() -> String {
+    std::env::var("WEBAUTHN_RP_ID")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+fn allowed_origins() -> Vec<String> {
+    match std::env::var("WEBAUTHN_ORIGINS") {
+        Ok(raw) if !raw.trim().is_empty() => raw
+            .split(',')
+            .map(|o| o.trim().trim_end_matches('/').to_string())
+            .filter(|o| !o.is_empty())
+            .collect(),
+        _ => DEFAULT_ORIGINS.iter().map(|o| o.to_string()).collect(),
+    }
+}
+
+// ── Ceremony store ──────────────────────────────────────
+
+enum CeremonyKind {
+    /// Adding a passkey to this user.
+    Registration { user_id: String },
+    /// Signing in, optionally restricted to one user's passkeys.
+    Authentication { user_id: Option<String> },
+}
+
+pub struct PendingCeremony {
+    kind: CeremonyKind,
+    created_at: Instant,
+}
+
+/// Started ceremonies keyed by their challenge (base64url).
+pub type PasskeyCeremonies = Arc<Mutex<HashMap<String, PendingCeremony>>>;
+
+pub fn create_passkey_ceremonies() -> PasskeyCeremonies {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+fn start_ceremony(ceremonies: &PasskeyCeremonies, kind: CeremonyKind) -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let challenge = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+
+    let mut map = ceremonies.lock().unwrap();
+    map.retain(|_, c| c.created_at.elapsed() < CEREMONY_TTL);
+    map.insert(
+        challenge.clone(),
+        PendingCeremony {
+            kind,
+            created_at: Instant::now(),
+        },
+    );
+    challenge
+}
+
+/// Remove and return the ceremony for `challenge`, if it has not expired.
+fn take_ceremony(ceremonies: &PasskeyCeremonies, challenge: &str) -> Option<CeremonyKind> {
+    ceremonies
+        .lock()
+        .unwrap()
+        .remove(challenge)
+        .filter(|c| c.created_at.elapsed() < CEREMONY_TTL)
+        .map(|c| c.kind)
+}
+
+// ── Request types ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyRegisterFinish {
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyLoginStart {
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssertionCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyLoginFinish {
+    pub credential: AssertionCredential,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PasskeyInfo {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+// ── Parsing & verification ──────────────────────────────
+
+fn b64url_decode(raw: &str) -> Result<Vec<u8>, String> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(raw.trim().trim_end_matches('='))
+        .map_err(|_| "Invalid base64url value".to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// Decode `clientDataJSON` and check its type and origin. The challenge is
+/// checked by the caller against the ceremony store.
+fn parse_client_data(raw: &[u8], expected_type: &str) -> Result<ClientData, String> {
+    let data: ClientData =
+        serde_json::from_slice(raw).map_err(|_| "Invalid clientDataJSON".to_string())?;
+    if data.kind != expected_type {
+        return Err("Unexpected WebAuthn ceremony type".to_string());
+    }
+    if !allowed_origins().contains(&data.origin) {
+        return Err(format!("Origin {} is not allowed", data.origin));
+    }
+    Ok(data)
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    /// Credential id and COSE public key, present during registration.
+    attested: Option<(Vec<u8>, Value)>,
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, String> {
+    let invalid = || "Invalid authenticator data".to_string();
+    if data.len() < 37 {
+        return Err(invalid());
+    }
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested = if flags & FLAG_ATTESTED_DATA != 0 {
+        // aaguid (16) | credential id length (2) | credential id | COSE key
+        let rest = data.get(37..).ok_or_else(invalid)?;
+        let id_len = u16::from_be_bytes([*rest.get(16).ok_or_else(invalid)?, *rest.get(17).ok_or_else(invalid)?]) as usize;
+        let credential_id = rest.get(18..18 + id_len).ok_or_else(invalid)?.to_vec();
+        let mut key_bytes = rest.get(18 + id_len..).ok_or_else(invalid)?;
+        let key: Value = ciborium::de::from_reader(&mut key_bytes).map_err(|_| invalid())?;
+        Some((credential_id, key))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested,
+    })
+}
+
+fn cose_param(key: &Value, label: i64) -> Option<&Value> {
+    key.as_map()?
+        .iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(label as i128))
+        .map(|(_, v)| v)
+}
+
+fn cose_int(key: &Value, label: i64) -> Option<i64> {
+    cose_param(key, label)?
+        .as_integer()
+        .and_then(|i| i64::try_from(i128::from(i)).ok())
+}
+
+fn cose_bytes(key: &Value, label: i64) -> Option<&[u8]> {
+    cose_param(key, label)?.as_bytes().map(|b| b.as_slice())
+}
+
+/// Return the COSE algorithm of a supported public key.
+fn check_cose_key(key: &Value) -> Result<i64, String> {
+    match (cose_int(key, 1), cose_int(key, 3)) {
+        // kty EC2, crv P-256
+        (Some(2), Some(COSE_ALG_ES256)) if cose_int(key, -1) == Some(1) => Ok(COSE_ALG_ES256),
+        // kty RSA
+        (Some(3), Some(COSE_ALG_RS256)) => Ok(COSE_ALG_RS256),
+        _ => Err("Unsupported passkey algorithm (ES256 or RS256 required)".to_string()),
+    }
+}
+
+fn verify_signature(key: &Value, alg: i64, message: &[u8], signature: &[u8]) -> bool {
+    match alg {
+        COSE_ALG_ES256 => {
+            let (Some(x), Some(y)) = (cose_bytes(key, -2), cose_bytes(key, -3)) else {
+                return false;
+            };
+            if x.len() != 32 || y.len() != 32 {
+                return false;
+            }
+            let point = p256::EncodedPoint::from_affine_coordinates(x.into(), y.into(), false);
+            let Ok(verifying_key) = p256::ecdsa::VerifyingKey::from_encoded_point(&point) else {
+                return false;
+            };
+            let Ok(signature) = p256::ecdsa::Signature::from_der(signature) else {
+                return false;
+            };
+            verifying_key.verify(message, &signature).is_ok()
+        }
+        COSE_ALG_RS256 => {
+            let (Some(n), Some(e)) = (cose_bytes(key, -1), cose_bytes(key, -2)) else {
+                return false;
+            };
+            let Ok(public_key) = RsaPublicKey::new(BigUint::from_bytes_be(n), BigUint::from_bytes_be(e)) else {
+                return false;
+            };
+            public_key
+                .verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(message), signature)
+                .is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Profile payload returned once a passkey (or a second factor) checked out.
+pub(crate) async fn login_response(pool: &SqlitePool, device: &DeviceInfo, user_id: &str) -> Result<AuthResponse, String> {
+    let row = sqlx::query("SELECT username, role, avatar_color, about, avatar_url, banner_url FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| "Database error".to_string())?
+        .ok_or_else(|| "User not found".to_string())?;
+
+    let username: String = row.get("username");
+    let role: String = row.get("role");
+    let tokens = issue_token(pool, device, user_id, &username, &role).await?;
+    let two_factor_setup_required = crate::auth::two_factor_setup_required(pool, user_id).await;
+    Ok(AuthResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
+        user_id: user_id.to_string(),
+        username,
+        role,
+        avatar_color: row.try_get("avatar_color").unwrap_or(0),
+        about: row.try_get("about").unwrap_or_default(),
+        avatar_url: row.try_get("avatar_url").unwrap_or(None),
+        banner_url: row.try_get("banner_url").unwrap_or(None),
+        two_factor_setup_required,
+    })
+}
+
+// ── Registration ────────────────────────────────────────
+
+/// POST /api/auth/passkey/register/start — Creation options for a new passkey
+pub async fn register_start(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    ceremonies: web::Data<PasskeyCeremonies>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let existing: Vec<String> = sqlx::query_scalar("SELECT id FROM passkeys WHERE user_id = ?")
+        .bind(&claims.sub)
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default();
+
+    let challenge = start_ceremony(
+        ceremonies.get_ref(),
+        CeremonyKind::Registration { user_id: claims.sub.clone() },
+    );
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "challenge": challenge,
+        "rp": { "id": rp_id(), "name": "Voxium" },
+        "user": {
+            "id": general_purpose::URL_SAFE_NO_PAD.encode(claims.sub.as_bytes()),
+            "name": claims.username,
+            "displayName": claims.username,
+        },
+        "pubKeyCredParams": [
+            { "type": "public-key", "alg": COSE_ALG_ES256 },
+            { "type": "public-key", "alg": COSE_ALG_RS256 },
+        ],
+        "excludeCredentials": existing
+            .iter()
+            .map(|id| serde_json::json!({ "type": "public-key", "id": id }))
+            .collect::<Vec<_>>(),
+        "authenticatorSelection": { "residentKey": "preferred", "userVerification": "required" },
+        "attestation": "none",
+        "timeout": CEREMONY_TIMEOUT_MS,
+    }))
+}
+
+/// POST /api/auth/passkey/register/finish — Verify and store the new passkey
+pub async fn register_finish(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    ceremonies: web::Data<PasskeyCeremonies>,
+    body: web::Json<PasskeyRegisterFinish>,
+) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let result = verify_registration(ceremonies.get_ref(), &claims.sub, &body.credential);
+    let (credential_id, public_key, alg, sign_count) = match result {
+        Ok(v) => v,
+        Err(error) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    };
+
+    let name = body
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or("Passkey")
+        .chars()
+        .take(MAX_PASSKEY_NAME_CHARS)
+        .collect::<String>();
+
+    let mut public_key_bytes = Vec::new();
+    if ciborium::ser::into_writer(&public_key, &mut public_key_bytes).is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO passkeys (id, user_id, name, public_key, alg, sign_count, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&credential_id)
+    .bind(&claims.sub)
+    .bind(&name)
+    .bind(&public_key_bytes)
+    .bind(alg)
+    .bind(i64::from(sign_count))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "id": credential_id, "name": name })),
+        Err(_) => HttpResponse::Conflict().json(serde_json::json!({ "error": "Passkey already registered" })),
+    }
+}
+
+/// Check an attestation and return (credential id, COSE key, algorithm,
+/// initial signature counter).
+fn verify_registration(
+    ceremonies: &PasskeyCeremonies,
+    user_id: &str,
+    credential: &RegistrationCredential,
+) -> Result<(String, Value, i64, u32), String> {
+    let client_data = parse_client_data(&b64url_decode(&credential.response.client_data_json)?, "webauthn.create")?;
+    match take_ceremony(ceremonies, &client_data.challenge) {
+        Some(CeremonyKind::Registration { user_id: expected }) if expected == user_id => {}
+        _ => return Err("Unknown or expired passkey challenge".to_string()),
+    }
+
+    let attestation: Value = ciborium::de::from_reader(b64url_decode(&credential.response.attestation_object)?.as_slice())
+        .map_err(|_| "Invalid attestation object".to_string())?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|m| m.iter().find(|(k, _)| k.as_text() == Some("authData")))
+        .and_then(|(_, v)| v.as_bytes())
+        .ok_or_else(|| "Invalid attestation object".to_string())?;
+
+    let auth_data = parse_authenticator_data(auth_data)?;
+    if auth_data.rp_id_hash != Sha256::digest(rp_id().as_bytes()).as_slice() {
+        return Err("Passkey was created for another site".to_string());
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err("User presence is required".to_string());
+    }
+    if auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err("User verification is required".to_string());
+    }
+    let Some((credential_id, public_key)) = auth_data.attested else {
+        return Err("Missing attested credential data".to_string());
+    };
+
+    let credential_id = general_purpose::URL_SAFE_NO_PAD.encode(credential_id);
+    if credential_id != credential.id.trim_end_matches('=') {
+        return Err("Credential id mismatch".to_string());
+    }
+
+    let alg = check_cose_key(&public_key)?;
+    Ok((credential_id, public_key, alg, auth_data.sign_count))
+}
+
+// ── Authentication ──────────────────────────────────────
+
+/// POST /api/auth/passkey/login/start — Request options to sign in with a passkey
+pub async fn login_start(
+    pool: web::Data<SqlitePool>,
+    ceremonies: web::Data<PasskeyCeremonies>,
+    body: Option<web::Json<PasskeyLoginStart>>,
+) -> HttpResponse {
+    let username = body
+        .as_ref()
+        .and_then(|b| b.username.as_deref())
+        .map(str::trim)
+        .filter(|u| !u.is_empty());
+
+    // Unknown usernames get the same answer as an empty one (discoverable
+    // credentials only), so this endpoint does not reveal who exists.
+    let user_id: Option<String> = match username {
+        Some(username) => sqlx::query_scalar("SELECT id FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(pool.get_ref())
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+
+    let allowed: Vec<String> = match &user_id {
+        Some(user_id) => sqlx::query_scalar("SELECT id FROM passkeys WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(pool.get_ref())
+            .await
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let challenge = start_ceremony(ceremonies.get_ref(), CeremonyKind::Authentication { user_id });
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "challenge": challenge,
+        "rpId": rp_id(),
+        "allowCredentials": allowed
+            .iter()
+            .map(|id| serde_json::json!({ "type": "public-key", "id": id }))
+            .collect::<Vec<_>>(),
+        "userVerification": "required",
+        "timeout": CEREMONY_TIMEOUT_MS,
+    }))
+}
+
+/// Check a passkey assertion against a started authentication ceremony and
+/// return the user it belongs to. Also used by sudo mode to re-authenticate.
+pub(crate) async fn verify_assertion(
+    pool: &SqlitePool,
+    ceremonies: &PasskeyCeremonies,
+    credential: &AssertionCredential,
+) -> Result<String, HttpResponse> {
+    let failed = |error: &str| HttpResponse::Unauthorized().json(serde_json::json!({ "error": error }));
+
+    let decoded = (
+        b64url_decode(&credential.response.client_data_json),
+        b64url_decode(&credential.response.authenticator_data),
+        b64url_decode(&credential.response.signature),
+    );
+    let (Ok(client_data_raw), Ok(auth_data_raw), Ok(signature)) = decoded else {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid base64url value" })));
+    };
+
+    let client_data = match parse_client_data(&client_data_raw, "webauthn.get") {
+        Ok(c) => c,
+        Err(error) => return Err(failed(&error)),
+    };
+    let Some(CeremonyKind::Authentication { user_id: expected_user }) =
+        take_ceremony(ceremonies, &client_data.challenge)
+    else {
+        return Err(failed("Unknown or expired passkey challenge"));
+    };
+
+    let credential_id = credential.id.trim_end_matches('=');
+    let row = sqlx::query("SELECT user_id, public_key, alg, sign_count FROM passkeys WHERE id = ?")
+        .bind(credential_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    let Some(row) = row else {
+        return Err(failed("Unknown passkey"));
+    };
+    let user_id: String = row.get("user_id");
+    let public_key_bytes: Vec<u8> = row.get("public_key");
+    let alg: i64 = row.get("alg");
+    let stored_count: i64 = row.get("sign_count");
+
+    if expected_user.as_deref().is_some_and(|expected| expected != user_id) {
+        return Err(failed("Passkey does not belong to this user"));
+    }
+    if let Some(handle) = credential.response.user_handle.as_deref().filter(|h| !h.is_empty()) {
+        if b64url_decode(handle).ok().as_deref() != Some(user_id.as_bytes()) {
+            return Err(failed("Passkey does not belong to this user"));
+        }
+    }
+
+    let auth_data = match parse_authenticator_data(&auth_data_raw) {
+        Ok(a) => a,
+        Err(error) => return Err(failed(&error)),
+    };
+    if auth_data.rp_id_hash != Sha256::digest(rp_id().as_bytes()).as_slice() {
+        return Err(failed("Passkey was created for another site"));
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(failed("User presence is required"));
+    }
+    if auth_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(failed("User verification is required"));
+    }
+
+    let Ok(public_key) = ciborium::de::from_reader::<Value, _>(public_key_bytes.as_slice()) else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    let mut signed = auth_data_raw.clone();
+    signed.extend_from_slice(&Sha256::digest(&client_data_raw));
+    if !verify_signature(&public_key, alg, &signed, &signature) {
+        return Err(failed("Invalid passkey signature"));
+    }
+
+    // Authenticators that keep a counter must increase it: a lower or equal
+    // value means the credential was cloned.
+    let new_count = i64::from(auth_data.sign_count);
+    if (new_count != 0 || stored_count != 0) && new_count <= stored_count {
+        return Err(failed("Passkey counter went backwards, it may have been cloned"));
+    }
+
+    let _ = sqlx::query("UPDATE passkeys SET sign_count = ?, last_used_at = ? WHERE id = ?")
+        .bind(new_count)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(credential_id)
+        .execute(pool)
+        .await;
+
+    Ok(user_id)
+}
+
+/// POST /api/auth/passkey/login/finish — Verify the assertion and log in
+pub async fn login_finish(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    ceremonies: web::Data<PasskeyCeremonies>,
+    body: web::Json<PasskeyLoginFinish>,
+) -> HttpResponse {
+    let user_id = match verify_assertion(pool.get_ref(), ceremonies.get_ref(), &body.credential).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    if let Some(ban) = crate::bans::in_force(pool.get_ref(), &user_id).await {
+        return ban.refusal();
+    }
+    match login_response(pool.get_ref(), &DeviceInfo::from_request(&req), &user_id).await {
+        Ok(auth) => HttpResponse::Ok().json(auth),
+        Err(error) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": error })),
+    }
+}
+
+// ── Management ──────────────────────────────────────────
+
+/// GET /api/users/@me/passkeys — List the caller's passkeys
+pub async fn list_passkeys(req: HttpRequest, pool: web::Data<SqlitePool>) -> HttpResponse {
+    let claims = match extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let passkeys = sqlx::query_as::<_, PasskeyInfo>(
+        "SELECT id, name, created_at, last_used_at FROM passkeys WHERE user_id = ? ORDER BY created_at"
+    )
+    .bind(&claims.sub)
+    .fetch_all(pool.get_ref())
+    .await
+    .unwrap_or_default();
+
+    HttpResponse::Ok().json(passkeys)
+}
+
+/// DELETE /api/users/@me/passkeys/{id} — Remove one of the caller's passkeys
+pub async fn delete_passkey(
+    Sudo(claims): Sudo,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = sqlx::query("DELETE FROM passkeys WHERE id = ? AND user_id = ?")
+        .bind(path.into_inner())
+        .bind(&claims.sub)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected() > 0 => HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" })),
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": "Passkey not found" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
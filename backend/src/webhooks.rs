@@ -0,0 +1,579 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Incoming webhooks (Discord-compatible)
+// ═══════════════════════════════════════════════════════
+//
+// A webhook lets CI jobs and bots post into one text room without an
+// account: `POST /api/webhooks/{id}/{token}` takes the body Discord's
+// "Execute Webhook" takes, so existing integrations only need the URL
+// changed:
+//   - `content` (up to `MAX_CONTENT_CHARS`) and/or up to `MAX_EMBEDS`
+//     `embeds`, stored as the message's previews (title, description and
+//     fields, url, image or thumbnail, author name, footer text);
+//   - `username` and `avatar_url` override the webhook's for one message;
+//   - `?wait=true` answers `200` with the message instead of `204`.
+// Other Discord fields (`tts`, `allowed_mentions`, ...) are ignored.
+//
+// Each webhook posts as its own user (role `webhook`, no password, so it
+// never signs in); messages carry `webhook_id`. The token is only shown
+// when the webhook is created, only its hash is stored. Executing is rate
+// limited per webhook (`RATE_LIMIT_WEBHOOK`, see `ratelimit`), and the
+// room's lockdown, automod and slowmode apply to webhook posts as to any
+// other author. Room moderators (MANAGE_ROOMS) create, list, edit and
+// delete webhooks.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::auth::extract_claims;
+use crate::unfurl::LinkEmbed;
+use crate::ws::{AccessCache, Broadcaster, PostRefusal};
+
+pub const WEBHOOK_ROLE: &str = "webhook";
+const MAX_NAME_CHARS: usize = 80;
+const MAX_URL_CHARS: usize = 2048;
+const MAX_WEBHOOKS_PER_ROOM: i64 = 15;
+const MAX_CONTENT_CHARS: usize = crate::messages::MAX_CONTENT_CHARS;
+const MAX_EMBEDS: usize = 10;
+const MAX_EMBED_TITLE_CHARS: usize = 256;
+const MAX_EMBED_TEXT_CHARS: usize = 4096;
+
+#[derive(Debug, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub room_id: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+    /// The user its messages are posted as.
+    pub user_id: String,
+    pub created_by_username: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    /// Only when the webhook is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+const COLUMNS: &str = "id, room_id, name, avatar_url, user_id, created_by_username, created_at, last_used_at";
+
+impl Webhook {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            room_id: row.get("room_id"),
+            name: row.get("name"),
+            avatar_url: row.get("avatar_url"),
+            user_id: row.get("user_id"),
+            created_by_username: row.get("created_by_username"),
+            created_at: row.get("created_at"),
+            last_used_at: row.get("last_used_at"),
+            token: None,
+            url: None,
+        }
+    }
+}
+
+pub(crate) async fn load(pool: &SqlitePool, id: &str) -> Option<Webhook> {
+    sqlx::query(&format!("SELECT {COLUMNS} FROM webhooks WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|row| Webhook::from_row(&row))
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Tokens are random 256-bit values, a plain SHA-256 is enough.
+fn hash_token(raw: &str) -> String {
+    Sha256::digest(raw.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The webhook `id` when `token` is its token.
+async fn authenticate(pool: &SqlitePool, id: &str, token: &str) -> Option<Webhook> {
+    let row = sqlx::query(&format!("SELECT {COLUMNS} FROM webhooks WHERE id = ? AND token_hash = ?"))
+        .bind(id)
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+    Some(Webhook::from_row(&row))
+}
+
+/// A trimmed name of 1 to `MAX_NAME_CHARS` characters.
+#[allow(clippy::result_large_err)]
+fn valid_name(name: &str) -> Result<String, HttpResponse> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Name must be 1 to {MAX_NAME_CHARS} characters")
+        })));
+    }
+    Ok(name.to_string())
+}
+
+/// An http(s) URL, or None for an empty one.
+#[allow(clippy::result_large_err)]
+fn valid_avatar_url(url: Option<&str>) -> Result<Option<String>, HttpResponse> {
+    match url.map(str::trim).filter(|u| !u.is_empty()) {
+        None => Ok(None),
+        Some(u) if (u.starts_with("https://") || u.starts_with("http://")) && u.len() <= MAX_URL_CHARS => Ok(Some(u.to_string())),
+        Some(_) => Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "avatar_url must be an http(s) URL" }))),
+    }
+}
+
+/// The room must be a text room the caller manages.
+async fn require_manage_room(pool: &SqlitePool, req: &HttpRequest, room_id: &str) -> Result<crate::auth::Claims, HttpResponse> {
+    let claims = extract_claims(req).ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+    crate::permissions::require(pool, room_id, &claims, crate::permissions::MANAGE_ROOMS).await?;
+    let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    if kind.as_deref() != Some("text") {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Webhooks are only for text rooms" })));
+    }
+    Ok(claims)
+}
+
+/// Create a webhook in `room_id` with its user, returned with its token and URL.
+pub(crate) async fn insert_webhook(
+    pool: &SqlitePool,
+    room_id: &str,
+    name: &str,
+    avatar_url: Option<String>,
+    creator: &crate::auth::Claims,
+) -> Result<Webhook, sqlx::Error> {
+    let id = Uuid::new_v4().to_string();
+    let user_id = Uuid::new_v4().to_string();
+    let username = crate::auth::allocate_unique_username(pool, &format!("{name} (webhook)")).await;
+    let token = generate_token();
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO users (id, username, password_hash, role, avatar_url) VALUES (?, ?, '!', ?, ?)")
+        .bind(&user_id)
+        .bind(&username)
+        .bind(WEBHOOK_ROLE)
+        .bind(&avatar_url)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO webhooks (id, room_id, user_id, name, avatar_url, token_hash, created_by, created_by_username, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(room_id)
+    .bind(&user_id)
+    .bind(name)
+    .bind(&avatar_url)
+    .bind(hash_token(&token))
+    .bind(&creator.sub)
+    .bind(&creator.username)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let mut webhook = load(pool, &id).await.ok_or(sqlx::Error::RowNotFound)?;
+    webhook.url = Some(format!("{}/api/webhooks/{id}/{token}", crate::email::public_base_url()));
+    webhook.token = Some(token);
+    Ok(webhook)
+}
+
+/// A message to post as a webhook, already validated.
+pub(crate) struct Post {
+    pub content: String,
+    /// The name shown for this message.
+    pub username: String,
+    /// Overrides the webhook's avatar for this message.
+    pub avatar_url: Option<String>,
+    pub embeds: Vec<LinkEmbed>,
+}
+
+/// Why a webhook post was not stored.
+pub(crate) enum PostError {
+    /// The room's lockdown, automod or slowmode refused it (see
+    /// `ws::check_post_gates`).
+    Refused(PostRefusal),
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for PostError {
+    fn from(error: sqlx::Error) -> Self {
+        PostError::Database(error)
+    }
+}
+
+impl std::fmt::Display for PostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostError::Refused(PostRefusal::Lockdown(_)) => write!(f, "The room is locked down"),
+            PostError::Refused(PostRefusal::Automod(blocked)) => write!(f, "Blocked by automod rule {}", blocked.rule),
+            PostError::Refused(PostRefusal::Slowmode(cooldown)) => write!(f, "Slowmode, retry in {}s", cooldown.retry_after),
+            PostError::Refused(_) => write!(f, "Refused by the room"),
+            PostError::Database(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl PostError {
+    /// The structured error for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            PostError::Refused(refusal) => refusal.response(),
+            PostError::Database(_) => HttpResponse::InternalServerError().finish(),
+        }
+    }
+}
+
+/// Store and broadcast `post` in the webhook's room; returns the `message`
+/// event. With `gates`, the post goes through the same gates as any other
+/// (see `ws::check_post_gates`); history imports pass `None`.
+pub(crate) async fn post_message(
+    pool: &SqlitePool,
+    broadcaster: &Broadcaster,
+    gates: Option<&AccessCache>,
+    webhook: &Webhook,
+    post: Post,
+) -> Result<serde_json::Value, PostError> {
+    let Post { content, username, avatar_url, embeds } = post;
+    let flags = match gates {
+        Some(access_cache) => {
+            crate::ws::check_post_gates(pool, access_cache, broadcaster, &webhook.room_id, &webhook.user_id, WEBHOOK_ROLE, &content, &[])
+                .await
+                .map_err(PostError::Refused)?
+        }
+        None => Vec::new(),
+    };
+    let embeds_json = (!embeds.is_empty()).then(|| serde_json::to_string(&embeds).unwrap_or_default());
+    let message_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO messages (id, room_id, user_id, username, content, created_at, embeds, webhook_id, webhook_avatar_url) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&message_id)
+    .bind(&webhook.room_id)
+    .bind(&webhook.user_id)
+    .bind(&username)
+    .bind(&content)
+    .bind(&now)
+    .bind(&embeds_json)
+    .bind(&webhook.id)
+    .bind(&avatar_url)
+    .execute(pool)
+    .await?;
+    let _ = sqlx::query("UPDATE webhooks SET last_used_at = ? WHERE id = ?")
+        .bind(&now)
+        .bind(&webhook.id)
+        .execute(pool)
+        .await;
+
+    let mention_ids = crate::mentions::record_mentions(pool, &message_id, &webhook.room_id, &webhook.user_id, &content, &now).await;
+    crate::automod::record_flags(pool, &message_id, &webhook.room_id, &webhook.user_id, &flags).await;
+    // Links are previewed as usual, unless the request brought its own embeds.
+    if embeds.is_empty() {
+        crate::unfurl::mark_pending(pool, &message_id, &content).await;
+    }
+    let custom_emojis = crate::emojis::resolve_content(pool, &content).await;
+
+    let message = serde_json::json!({
+        "type": "message",
+        "id": message_id,
+        "room_id": webhook.room_id,
+        "user_id": webhook.user_id,
+        "username": username,
+        "avatar_url": avatar_url.or_else(|| webhook.avatar_url.clone()),
+        "content": content,
+        "created_at": now,
+        "kind": "default",
+        "embeds": embeds,
+        "custom_emojis": custom_emojis,
+        "mention_ids": mention_ids,
+        "webhook_id": webhook.id,
+    });
+    let _ = broadcaster.send(message.to_string());
+    crate::mentions::notify_mentions(pool, broadcaster, &mention_ids, &crate::mentions::MentionSource {
+        message_id: &message_id,
+        room_id: &webhook.room_id,
+        author_id: &webhook.user_id,
+        author_username: &username,
+        content: &content,
+    })
+    .await;
+    Ok(message)
+}
+
+// ── Management ──────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhook {
+    pub name: String,
+    pub avatar_url: Option<String>,
+}
+
+/// POST /api/rooms/{id}/webhooks — Create a webhook posting into a text room (MANAGE_ROOMS)
+pub async fn create(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<CreateWebhook>,
+) -> HttpResponse {
+    let room_id = path.into_inner();
+    let claims = match require_manage_room(pool.get_ref(), &req, &room_id).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let name = match valid_name(&body.name) {
+        Ok(name) => name,
+        Err(response) => return response,
+    };
+    let avatar_url = match valid_avatar_url(body.avatar_url.as_deref()) {
+        Ok(url) => url,
+        Err(response) => return response,
+    };
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM webhooks WHERE room_id = ?")
+        .bind(&room_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .unwrap_or(0);
+    if count >= MAX_WEBHOOKS_PER_ROOM {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("A room can have at most {MAX_WEBHOOKS_PER_ROOM} webhooks")
+        }));
+    }
+
+    match insert_webhook(pool.get_ref(), &room_id, &name, avatar_url, &claims).await {
+        Ok(webhook) => HttpResponse::Created().json(webhook),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// GET /api/rooms/{id}/webhooks — The room's webhooks, without their tokens (MANAGE_ROOMS)
+pub async fn list(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let room_id = path.into_inner();
+    if let Err(response) = require_manage_room(pool.get_ref(), &req, &room_id).await {
+        return response;
+    }
+    let rows = sqlx::query(&format!("SELECT {COLUMNS} FROM webhooks WHERE room_id = ? ORDER BY created_at"))
+        .bind(&room_id)
+        .fetch_all(pool.get_ref())
+        .await
+        .unwrap_or_default();
+    HttpResponse::Ok().json(rows.iter().map(Webhook::from_row).collect::<Vec<_>>())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhook {
+    pub name: Option<String>,
+    /// An empty string removes it.
+    pub avatar_url: Option<String>,
+    /// Move it to another text room (MANAGE_ROOMS there too).
+    pub room_id: Option<String>,
+}
+
+/// PATCH /api/webhooks/{id} — Rename, change the avatar or move a webhook (MANAGE_ROOMS)
+pub async fn update(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    path: web::Path<String>,
+    body: web::Json<UpdateWebhook>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    let Some(webhook) = load(pool.get_ref(), &id).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Webhook not found" }));
+    };
+    if let Err(response) = require_manage_room(pool.get_ref(), &req, &webhook.room_id).await {
+        return response;
+    }
+    let name = match body.name.as_deref().map(valid_name).transpose() {
+        Ok(name) => name.unwrap_or(webhook.name),
+        Err(response) => return response,
+    };
+    let avatar_url = match &body.avatar_url {
+        Some(url) => match valid_avatar_url(Some(url)) {
+            Ok(url) => url,
+            Err(response) => return response,
+        },
+        None => webhook.avatar_url,
+    };
+    let room_id = match &body.room_id {
+        Some(room_id) => match require_manage_room(pool.get_ref(), &req, room_id).await {
+            Ok(_) => room_id.clone(),
+            Err(response) => return response,
+        },
+        None => webhook.room_id,
+    };
+
+    let updated = sqlx::query("UPDATE webhooks SET name = ?, avatar_url = ?, room_id = ? WHERE id = ?")
+        .bind(&name)
+        .bind(&avatar_url)
+        .bind(&room_id)
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await;
+    let _ = sqlx::query("UPDATE users SET avatar_url = ? WHERE id = ?")
+        .bind(&avatar_url)
+        .bind(&webhook.user_id)
+        .execute(pool.get_ref())
+        .await;
+    if updated.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match load(pool.get_ref(), &id).await {
+        Some(webhook) => HttpResponse::Ok().json(webhook),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Webhook not found" })),
+    }
+}
+
+/// DELETE /api/webhooks/{id} — Delete a webhook, its messages stay (MANAGE_ROOMS)
+pub async fn delete(req: HttpRequest, pool: web::Data<SqlitePool>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let Some(webhook) = load(pool.get_ref(), &id).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Webhook not found" }));
+    };
+    if let Err(response) = require_manage_room(pool.get_ref(), &req, &webhook.room_id).await {
+        return response;
+    }
+    match sqlx::query("DELETE FROM webhooks WHERE id = ?").bind(&id).execute(pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// ── Executing ───────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedImage {
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedAuthor {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedFooter {
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+}
+
+/// The parts of a Discord embed that map onto a link preview.
+#[derive(Debug, Deserialize)]
+pub struct DiscordEmbed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub image: Option<EmbedImage>,
+    pub thumbnail: Option<EmbedImage>,
+    pub author: Option<EmbedAuthor>,
+    pub footer: Option<EmbedFooter>,
+    #[serde(default)]
+    pub fields: Vec<EmbedField>,
+}
+
+impl DiscordEmbed {
+    fn into_preview(self) -> LinkEmbed {
+        let clip = |text: Option<String>, max: usize| {
+            text.map(|t| t.trim().chars().take(max).collect::<String>()).filter(|t| !t.is_empty())
+        };
+        // Fields have no place of their own in a preview, they follow the description.
+        let fields = self.fields.iter().map(|f| format!("{}: {}", f.name.trim(), f.value.trim()));
+        let description: Vec<String> = self.description.into_iter().chain(fields).collect();
+        LinkEmbed {
+            url: self.url.unwrap_or_default(),
+            site_name: clip(self.footer.and_then(|f| f.text), MAX_EMBED_TITLE_CHARS),
+            title: clip(self.title, MAX_EMBED_TITLE_CHARS),
+            description: clip(Some(description.join("\n")), MAX_EMBED_TEXT_CHARS),
+            image_url: self.image.or(self.thumbnail).and_then(|i| i.url),
+            author_name: clip(self.author.and_then(|a| a.name), MAX_EMBED_TITLE_CHARS),
+            broken_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteWebhook {
+    pub content: Option<String>,
+    pub username: Option<String>,
+    pub avatar_url: Option<String>,
+    #[serde(default)]
+    pub embeds: Vec<DiscordEmbed>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteQuery {
+    #[serde(default)]
+    pub wait: bool,
+}
+
+/// GET /api/webhooks/{id}/{token} — The webhook, for integrations checking their URL (token)
+pub async fn get_with_token(pool: web::Data<SqlitePool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (id, token) = path.into_inner();
+    match authenticate(pool.get_ref(), &id, &token).await {
+        Some(webhook) => HttpResponse::Ok().json(webhook),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown webhook" })),
+    }
+}
+
+/// POST /api/webhooks/{id}/{token}?wait= — Post a message as the webhook (token)
+pub async fn execute(
+    pool: web::Data<SqlitePool>,
+    broadcaster: web::Data<Broadcaster>,
+    access_cache: web::Data<AccessCache>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ExecuteQuery>,
+    body: web::Json<ExecuteWebhook>,
+) -> HttpResponse {
+    let (id, token) = path.into_inner();
+    let Some(webhook) = authenticate(pool.get_ref(), &id, &token).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Unknown webhook" }));
+    };
+    let body = body.into_inner();
+    let bad = |error: String| HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+
+    let content = body.content.as_deref().map(str::trim).unwrap_or_default().to_string();
+    if content.chars().count() > MAX_CONTENT_CHARS {
+        return bad(format!("content must be at most {MAX_CONTENT_CHARS} characters"));
+    }
+    if body.embeds.len() > MAX_EMBEDS {
+        return bad(format!("At most {MAX_EMBEDS} embeds"));
+    }
+    if content.is_empty() && body.embeds.is_empty() {
+        return bad("Cannot send an empty message".to_string());
+    }
+    let username = match body.username.as_deref().map(valid_name).transpose() {
+        Ok(username) => username.unwrap_or_else(|| webhook.name.clone()),
+        Err(response) => return response,
+    };
+    let avatar_url = match valid_avatar_url(body.avatar_url.as_deref()) {
+        Ok(url) => url,
+        Err(response) => return response,
+    };
+    let embeds: Vec<LinkEmbed> = body.embeds.into_iter().map(DiscordEmbed::into_preview).collect();
+
+    let post = Post { content, username, avatar_url, embeds };
+    match post_message(pool.get_ref(), broadcaster.get_ref(), Some(access_cache.get_ref()), &webhook, post).await {
+        Ok(message) if query.wait => HttpResponse::Ok().json(message),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => error.response(),
+    }
+}
@@ -8,6 +8,10 @@ use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
+use crate::permissions::{self, Overwrite};
+use crate::presence::PresenceTracker;
+use crate::voice_activity::VoiceOccupancy;
+
 /// Represents a chat message sent/received over WebSocket.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WsMessage {
@@ -18,11 +22,18 @@ pub struct WsMessage {
     pub username: Option<String>,
     pub content: Option<String>,
     pub reply_to_id: Option<String>,
+    /// With `reply_to_id`, snapshot the replied-to message into `quote`.
+    pub quote_mode: Option<bool>,
     pub avatar_color: Option<i32>,
     pub image_url: Option<String>,
     pub avatar_url: Option<String>,
+    /// Set by the server on `message`: the author's nickname (see `member_profiles`).
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none", default)]
+    pub nickname: Option<String>,
     pub banner_url: Option<String>,
     pub status: Option<String>,
+    /// Set by the server on `join`.
+    pub custom_status: Option<String>,
     pub role: Option<String>,
     pub about: Option<String>,
     pub target_user_id: Option<String>,
@@ -31,6 +42,26 @@ pub struct WsMessage {
     pub sdp: Option<serde_json::Value>,
     pub candidate: Option<serde_json::Value>,
     #[serde(skip_deserializing, default)]
+    pub quote: Option<crate::messages::QuoteSnapshot>,
+    /// Users the message pings, directly or through a role.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none", default)]
+    pub mention_ids: Option<Vec<String>>,
+    /// Custom emoji used in `content` as `:name:`.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none", default)]
+    pub custom_emojis: Option<Vec<crate::emojis::CustomEmoji>>,
+    /// Required in forum rooms: the post the message replies in (see `forum`).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub post_id: Option<String>,
+    /// Sent by clients: files from `POST /api/files` to attach.
+    #[serde(skip_serializing, default)]
+    pub attachment_ids: Option<Vec<String>>,
+    /// Set by the server on `message`, from `attachment_ids`.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none", default)]
+    pub attachments: Option<Vec<crate::files::Attachment>>,
+    /// Set by the server on `join`.
+    #[serde(skip_deserializing, flatten, default)]
+    pub role_display: Option<crate::roles::RoleDisplay>,
+    #[serde(skip_deserializing, default)]
     pub id: String,
     #[serde(skip_deserializing, default)]
     pub created_at: String,
@@ -39,13 +70,17 @@ pub struct WsMessage {
 /// Shared broadcast channel for all WebSocket connections.
 pub type Broadcaster = Arc<broadcast::Sender<String>>;
 
-/// Shared state for online users: user_id -> username
-pub type OnlineUsers = Arc<Mutex<HashMap<String, i32>>>; // user_id -> avatar_color (simplified)
-
 #[derive(Default)]
 pub struct AccessCacheState {
     pub user_roles: HashMap<String, String>,
     pub room_required_roles: HashMap<String, String>,
+    /// user_id -> users they have blocked (only for users with a live connection)
+    pub user_blocks: HashMap<String, HashSet<String>>,
+    pub room_overwrites: HashMap<String, Vec<Overwrite>>,
+    /// role name -> its permission bits
+    pub role_permissions: HashMap<String, u64>,
+    /// The enabled automod rules, compiled (see `automod`)
+    pub automod_rules: Option<Arc<Vec<crate::automod::CompiledRule>>>,
 }
 
 pub type AccessCache = Arc<Mutex<AccessCacheState>>;
@@ -55,22 +90,62 @@ pub fn create_broadcaster() -> Broadcaster {
     Arc::new(tx)
 }
 
-pub fn create_online_users() -> OnlineUsers {
-    Arc::new(Mutex::new(HashMap::new()))
-}
-
 pub fn create_access_cache() -> AccessCache {
     Arc::new(Mutex::new(AccessCacheState::default()))
 }
 
+/// A change to the access cache made here, replayed on the other instances
+/// (see `bus`). They drop what it touched and read it again when needed;
+/// blocks, only kept for live connections, travel whole.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cache", rename_all = "snake_case")]
+pub enum CacheChange {
+    UserRole { user_id: String },
+    UserRoles,
+    Room { room_id: String },
+    RoomOverwrites { room_id: String },
+    AllRoomOverwrites,
+    RolePermissions,
+    UserBlocks { user_id: String, blocked: Vec<String> },
+    AutomodRules,
+}
+
+/// Apply a change another instance made.
+pub(crate) fn apply_cache_change(cache: &AccessCache, change: CacheChange) {
+    let mut guard = cache.lock().unwrap();
+    match change {
+        CacheChange::UserRole { user_id } => {
+            guard.user_roles.remove(&user_id);
+        }
+        CacheChange::UserRoles => guard.user_roles.clear(),
+        CacheChange::Room { room_id } => {
+            guard.room_required_roles.remove(&room_id);
+            guard.room_overwrites.remove(&room_id);
+        }
+        CacheChange::RoomOverwrites { room_id } => {
+            guard.room_overwrites.remove(&room_id);
+        }
+        CacheChange::AllRoomOverwrites => guard.room_overwrites.clear(),
+        CacheChange::RolePermissions => guard.role_permissions.clear(),
+        CacheChange::UserBlocks { user_id, blocked } => {
+            if let Some(current) = guard.user_blocks.get_mut(&user_id) {
+                *current = blocked.into_iter().collect();
+            }
+        }
+        CacheChange::AutomodRules => guard.automod_rules = None,
+    }
+}
+
 pub fn cache_set_user_role(cache: &AccessCache, user_id: &str, role: &str) {
     let mut guard = cache.lock().unwrap();
     guard.user_roles.insert(user_id.to_string(), role.to_string());
+    crate::bus::publish_cache_change(CacheChange::UserRole { user_id: user_id.to_string() });
 }
 
 pub fn cache_clear_user_roles(cache: &AccessCache) {
     let mut guard = cache.lock().unwrap();
     guard.user_roles.clear();
+    crate::bus::publish_cache_change(CacheChange::UserRoles);
 }
 
 pub fn cache_set_room_required_role(cache: &AccessCache, room_id: &str, required_role: &str) {
@@ -78,14 +153,56 @@ pub fn cache_set_room_required_role(cache: &AccessCache, room_id: &str, required
     guard
         .room_required_roles
         .insert(room_id.to_string(), required_role.to_string());
+    crate::bus::publish_cache_change(CacheChange::Room { room_id: room_id.to_string() });
+}
+
+/// The users `user_id` blocks, as read when they connect. Not published:
+/// each instance loads its own connections.
+pub(crate) fn cache_load_user_blocks(cache: &AccessCache, user_id: &str, blocked: HashSet<String>) {
+    cache.lock().unwrap().user_blocks.insert(user_id.to_string(), blocked);
+}
+
+pub fn cache_set_user_blocks(cache: &AccessCache, user_id: &str, blocked: HashSet<String>) {
+    let change = CacheChange::UserBlocks { user_id: user_id.to_string(), blocked: blocked.iter().cloned().collect() };
+    let mut guard = cache.lock().unwrap();
+    guard.user_blocks.insert(user_id.to_string(), blocked);
+    crate::bus::publish_cache_change(change);
+}
+
+pub(crate) fn is_blocked_for(cache: &AccessCache, viewer_id: &str, author_id: &str) -> bool {
+    let guard = cache.lock().unwrap();
+    guard
+        .user_blocks
+        .get(viewer_id)
+        .is_some_and(|blocked| blocked.contains(author_id))
 }
 
 pub fn cache_remove_room(cache: &AccessCache, room_id: &str) {
     let mut guard = cache.lock().unwrap();
     guard.room_required_roles.remove(room_id);
+    guard.room_overwrites.remove(room_id);
+    crate::bus::publish_cache_change(CacheChange::Room { room_id: room_id.to_string() });
+}
+
+pub fn cache_remove_room_overwrites(cache: &AccessCache, room_id: &str) {
+    let mut guard = cache.lock().unwrap();
+    guard.room_overwrites.remove(room_id);
+    crate::bus::publish_cache_change(CacheChange::RoomOverwrites { room_id: room_id.to_string() });
+}
+
+pub fn cache_clear_room_overwrites(cache: &AccessCache) {
+    let mut guard = cache.lock().unwrap();
+    guard.room_overwrites.clear();
+    crate::bus::publish_cache_change(CacheChange::AllRoomOverwrites);
+}
+
+pub fn cache_clear_role_permissions(cache: &AccessCache) {
+    let mut guard = cache.lock().unwrap();
+    guard.role_permissions.clear();
+    crate::bus::publish_cache_change(CacheChange::RolePermissions);
 }
 
-async fn get_user_role_cached(pool: &SqlitePool, cache: &AccessCache, user_id: &str) -> Option<String> {
+pub(crate) async fn get_user_role_cached(pool: &SqlitePool, cache: &AccessCache, user_id: &str) -> Option<String> {
     {
         let guard = cache.lock().unwrap();
         if let Some(role) = guard.user_roles.get(user_id) {
@@ -100,7 +217,7 @@ async fn get_user_role_cached(pool: &SqlitePool, cache: &AccessCache, user_id: &
         .unwrap_or(None);
 
     if let Some(ref role_value) = role {
-        cache_set_user_role(cache, user_id, role_value);
+        cache.lock().unwrap().user_roles.insert(user_id.to_string(), role_value.clone());
     }
 
     role
@@ -121,143 +238,508 @@ async fn get_room_required_role_cached(pool: &SqlitePool, cache: &AccessCache, r
         .unwrap_or(None);
 
     if let Some(ref role_value) = required_role {
-        cache_set_room_required_role(cache, room_id, role_value);
+        cache.lock().unwrap().room_required_roles.insert(room_id.to_string(), role_value.clone());
     }
 
     required_role
 }
 
-pub async fn can_user_access_room_cached(
+async fn get_room_overwrites_cached(pool: &SqlitePool, cache: &AccessCache, room_id: &str) -> Vec<Overwrite> {
+    {
+        let guard = cache.lock().unwrap();
+        if let Some(overwrites) = guard.room_overwrites.get(room_id) {
+            return overwrites.clone();
+        }
+    }
+
+    let overwrites = crate::permissions::load_overwrites(pool, room_id).await;
+    cache
+        .lock()
+        .unwrap()
+        .room_overwrites
+        .insert(room_id.to_string(), overwrites.clone());
+    overwrites
+}
+
+pub(crate) async fn get_role_permissions_cached(pool: &SqlitePool, cache: &AccessCache, role: &str) -> u64 {
+    {
+        let guard = cache.lock().unwrap();
+        if let Some(bits) = guard.role_permissions.get(role) {
+            return *bits;
+        }
+    }
+
+    let bits = crate::permissions::role_permissions(pool, role).await;
+    cache.lock().unwrap().role_permissions.insert(role.to_string(), bits);
+    bits
+}
+
+/// Permissions of `user_id` in `room_id` (see `permissions`), 0 for an unknown room or user.
+pub(crate) async fn room_permissions_cached(
     pool: &SqlitePool,
     cache: &AccessCache,
     user_id: &str,
     room_id: &str,
-) -> bool {
+) -> u64 {
     let room_required_role = get_room_required_role_cached(pool, cache, room_id).await;
     let user_role = get_user_role_cached(pool, cache, user_id).await;
+    let (Some(required_role), Some(role)) = (room_required_role, user_role) else {
+        return 0;
+    };
+    let overwrites = get_room_overwrites_cached(pool, cache, room_id).await;
+    let bits = get_role_permissions_cached(pool, cache, &role).await;
+    crate::permissions::resolve(pool, room_id, &required_role, bits, &overwrites, user_id, &role).await
+}
 
-    match (room_required_role.as_deref(), user_role.as_deref()) {
-        (Some("user"), Some(_)) => true,
-        (Some(_), Some("admin")) => true,
-        (Some(required), Some(user_r)) => required == user_r,
-        _ => false,
-    }
+pub async fn can_user_access_room_cached(
+    pool: &SqlitePool,
+    cache: &AccessCache,
+    user_id: &str,
+    room_id: &str,
+) -> bool {
+    room_permissions_cached(pool, cache, user_id, room_id).await & permissions::VIEW_ROOM != 0
 }
 
-fn extract_room_id(payload: &str) -> Option<String> {
-    let value = serde_json::from_str::<serde_json::Value>(payload).ok()?;
-    value
+/// Room the event belongs to, its author for user-authored events (the
+/// ones hidden from users who blocked that author), and the only user it
+/// is meant for when it carries a `recipient_id`.
+pub(crate) fn event_routing(value: &serde_json::Value) -> (Option<String>, Option<String>, Option<String>) {
+    let room_id = value
         .get("room_id")
         .and_then(|v| v.as_str())
-        .map(|v| v.to_string())
+        .map(|v| v.to_string());
+    // System messages (topic changes...) are shown regardless of blocks.
+    let is_system = value
+        .get("kind")
+        .and_then(|v| v.as_str())
+        .is_some_and(|k| k != "default");
+    let author = match value.get("type").and_then(|v| v.as_str()) {
+        Some("message") | Some("typing") if !is_system => value
+            .get("user_id")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string()),
+        _ => None,
+    };
+    let recipient = value
+        .get("recipient_id")
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string());
+    (room_id, author, recipient)
 }
 
-async fn fetch_accessible_rooms(pool: &SqlitePool, role: &str) -> HashSet<String> {
-    let rows = if role == "admin" {
-        sqlx::query_scalar::<_, String>("SELECT id FROM rooms")
-            .fetch_all(pool)
-            .await
-            .unwrap_or_default()
-    } else {
-        sqlx::query_scalar::<_, String>(
-            "SELECT id FROM rooms WHERE required_role = 'user' OR required_role = ?"
-        )
-        .bind(role)
-        .fetch_all(pool)
+pub(crate) async fn fetch_accessible_rooms(pool: &SqlitePool, user_id: &str, role: &str) -> HashSet<String> {
+    crate::permissions::viewable_rooms(pool, user_id, role).await
+}
+
+// ── Posting messages ────────────────────────────────────
+
+/// Why a `message` sent over a realtime connection was not posted.
+pub(crate) enum PostRefusal {
+    /// Missing fields, a room the author cannot see, a banned author, or
+    /// nothing to post.
+    Invalid,
+    /// The author lacks these permission bits in the room.
+    MissingPermission(u64),
+    Slowmode(crate::slowmode::Cooldown),
+    /// The author has to acknowledge the server rules first (see `rules`).
+    RulesUnacknowledged(crate::rules::Unacknowledged),
+    /// The author does not meet the verification settings (see `verification`).
+    Unverified(crate::verification::Unverified),
+    /// The room is locked down and the author's role is not trusted (see `lockdown`).
+    Lockdown(crate::lockdown::Locked),
+    /// The author is timed out (see `timeouts`).
+    TimedOut(crate::timeouts::TimedOut),
+    /// An automod rule refused the content (see `automod`).
+    Automod(crate::automod::Blocked),
+    /// These attachments need alt text first (see `alt_text`).
+    AltTextRequired(Vec<String>),
+}
+
+impl PostRefusal {
+    /// The structured error for HTTP endpoints.
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            PostRefusal::Invalid => HttpResponse::BadRequest().json(serde_json::json!({ "error": "Invalid message" })),
+            PostRefusal::MissingPermission(missing) => HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Missing permission",
+                "missing": permissions::names(*missing),
+            })),
+            PostRefusal::Slowmode(cooldown) => cooldown.response(),
+            PostRefusal::RulesUnacknowledged(pending) => pending.response(),
+            PostRefusal::Unverified(unverified) => unverified.response(),
+            PostRefusal::Lockdown(locked) => locked.response(),
+            PostRefusal::TimedOut(timed_out) => timed_out.response(),
+            PostRefusal::Automod(blocked) => blocked.response(),
+            PostRefusal::AltTextRequired(ids) => HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Alt text is required on these attachments",
+                "attachment_ids": ids,
+            })),
+        }
+    }
+}
+
+/// Every gate a post of `content` by `user_id` in `room_id` goes through,
+/// whichever way it arrives (messages, forum posts, polls, webhooks): the
+/// server rules, verification and timeouts (webhooks are not members and
+/// skip them), alt text on `attachment_ids`, then the room's lockdown,
+/// automod and slowmode (claimed last, so a refused post doesn't use it up).
+/// Returns the automod flags to record once the message is stored.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn check_post_gates(
+    pool: &SqlitePool,
+    access_cache: &AccessCache,
+    tx: &Broadcaster,
+    room_id: &str,
+    user_id: &str,
+    role: &str,
+    content: &str,
+    attachment_ids: &[String],
+) -> Result<Vec<crate::automod::Flag>, PostRefusal> {
+    if role != crate::webhooks::WEBHOOK_ROLE {
+        crate::rules::check(pool, user_id, role).await.map_err(PostRefusal::RulesUnacknowledged)?;
+        crate::verification::check(pool, user_id, role).await.map_err(PostRefusal::Unverified)?;
+        crate::timeouts::check(pool, user_id).await.map_err(PostRefusal::TimedOut)?;
+    }
+    let lacking_alt_text = crate::alt_text::missing_for_post(pool, attachment_ids).await;
+    if !lacking_alt_text.is_empty() {
+        return Err(PostRefusal::AltTextRequired(lacking_alt_text));
+    }
+    crate::lockdown::check(pool, room_id, role).await.map_err(PostRefusal::Lockdown)?;
+    let flags = crate::automod::check(pool, access_cache, tx, room_id, user_id, role, content).await.map_err(PostRefusal::Automod)?;
+    crate::slowmode::claim(pool, room_id, user_id, role).await.map_err(PostRefusal::Slowmode)?;
+    Ok(flags)
+}
+
+/// Store and broadcast a `message` sent by `ws_msg.user_id`. The caller has
+/// already checked that the connection is authenticated as that user.
+pub(crate) async fn post_message(
+    pool: &SqlitePool,
+    tx: &Broadcaster,
+    access_cache: &AccessCache,
+    mut ws_msg: WsMessage,
+) -> Result<(), PostRefusal> {
+    let (Some(content), Some(rid), Some(uid), Some(uname)) = (&ws_msg.content, &ws_msg.room_id, &ws_msg.user_id, &ws_msg.username) else {
+        return Err(PostRefusal::Invalid);
+    };
+
+    let granted = room_permissions_cached(pool, access_cache, uid, rid).await;
+    if granted & permissions::VIEW_ROOM == 0 {
+        return Err(PostRefusal::Invalid);
+    }
+    // A connection opened before the ban stays open until the client drops it.
+    if crate::bans::is_banned(pool, uid).await {
+        return Err(PostRefusal::Invalid);
+    }
+
+    let has_content = !content.trim().is_empty();
+    let has_image = ws_msg.image_url.as_ref().is_some_and(|u| !u.is_empty());
+    let attachment_ids = match &ws_msg.attachment_ids {
+        Some(ids) => crate::files::linkable(pool, uid, ids).await,
+        None => Vec::new(),
+    };
+    if !has_content && !has_image && attachment_ids.is_empty() {
+        return Err(PostRefusal::Invalid);
+    }
+    // Forum rooms only take replies in a post, other rooms none.
+    if !crate::forum::accepts_message(pool, rid, ws_msg.post_id.as_deref()).await {
+        return Err(PostRefusal::Invalid);
+    }
+    let needed = permissions::SEND_MESSAGES | if attachment_ids.is_empty() { 0 } else { permissions::ATTACH_FILES };
+    if granted & needed != needed {
+        return Err(PostRefusal::MissingPermission(needed & !granted));
+    }
+    let role = get_user_role_cached(pool, access_cache, uid)
         .await
-        .unwrap_or_default()
+        .unwrap_or_else(|| "user".to_string());
+    let flags = check_post_gates(pool, access_cache, tx, rid, uid, &role, content, &attachment_ids).await?;
+
+    let msg_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let quote = match (&ws_msg.reply_to_id, ws_msg.quote_mode) {
+        (Some(quoted_id), Some(true)) => crate::messages::build_quote_snapshot(pool, quoted_id, rid).await,
+        _ => None,
+    };
+    let quote_json = quote
+        .as_ref()
+        .and_then(|q| serde_json::to_string(q).ok());
+
+    let _ = sqlx::query(
+        "INSERT INTO messages (id, room_id, user_id, username, content, created_at, image_url, reply_to_id, quote_snapshot, post_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&msg_id)
+    .bind(rid)
+    .bind(uid)
+    .bind(uname)
+    .bind(content)
+    .bind(&now)
+    .bind(&ws_msg.image_url)
+    .bind(&ws_msg.reply_to_id)
+    .bind(&quote_json)
+    .bind(&ws_msg.post_id)
+    .execute(pool)
+    .await;
+    if let Some(post_id) = &ws_msg.post_id {
+        crate::forum::record_reply(pool, post_id, &now).await;
+    }
+    let mention_ids = crate::mentions::record_mentions(pool, &msg_id, rid, uid, content, &now).await;
+    crate::unfurl::mark_pending(pool, &msg_id, content).await;
+    crate::automod::record_flags(pool, &msg_id, rid, uid, &flags).await;
+
+    ws_msg.id = msg_id;
+    ws_msg.created_at = now;
+    let profile = crate::member_profiles::load(pool, uid).await;
+    ws_msg.nickname = profile.nickname;
+    if profile.avatar_url.is_some() {
+        ws_msg.avatar_url = profile.avatar_url;
+    }
+    ws_msg.quote = quote;
+    ws_msg.mention_ids = Some(mention_ids);
+    let custom_emojis = crate::emojis::resolve_content(pool, content).await;
+    crate::emoji_usage::record_message(pool, content, &custom_emojis).await;
+    ws_msg.custom_emojis = (!custom_emojis.is_empty()).then_some(custom_emojis);
+    if !attachment_ids.is_empty() {
+        let attachments = crate::files::link_to_message(pool, &ws_msg.id, uid, &attachment_ids).await;
+        ws_msg.attachments = Some(attachments);
+    }
+
+    let _ = tx.send(serde_json::to_string(&ws_msg).unwrap());
+    crate::mentions::notify_mentions(pool, tx, ws_msg.mention_ids.as_deref().unwrap_or_default(), &crate::mentions::MentionSource {
+        message_id: &ws_msg.id,
+        room_id: rid,
+        author_id: uid,
+        author_username: uname,
+        content,
+    })
+    .await;
+    Ok(())
+}
+
+// ── Connection tickets ──────────────────────────────────
+//
+// Browsers cannot set an Authorization header on a WebSocket, and putting the
+// JWT in the URL leaks it into logs. Clients instead trade their token for a
+// short-lived, single-use ticket and present that when connecting.
+
+const WS_TICKET_TTL: std::time::Duration = std::time::Duration::from_secs(30);
+/// How long an unauthenticated connection may wait before sending its ticket.
+const WS_AUTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+pub struct WsTicket {
+    claims: crate::auth::Claims,
+    created_at: std::time::Instant,
+}
+
+pub type WsTickets = Arc<Mutex<HashMap<String, WsTicket>>>;
+
+pub fn create_ws_tickets() -> WsTickets {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// First message of a connection opened without credentials.
+#[derive(Debug, Deserialize)]
+struct WsAuthMessage {
+    #[serde(rename = "type")]
+    msg_type: String,
+    ticket: String,
+}
+
+/// Consume `ticket`. It is removed even if it turns out to be expired.
+pub(crate) fn redeem_ws_ticket(tickets: &WsTickets, ticket: &str) -> Option<crate::auth::Claims> {
+    tickets
+        .lock()
+        .unwrap()
+        .remove(ticket)
+        .filter(|t| t.created_at.elapsed() < WS_TICKET_TTL)
+        .map(|t| t.claims)
+}
+
+/// POST /api/auth/ws-ticket — One-time ticket to open the WebSocket
+pub async fn create_ws_ticket(req: HttpRequest, tickets: web::Data<WsTickets>) -> HttpResponse {
+    let claims = match crate::auth::extract_claims(&req) {
+        Some(c) => c,
+        None => return HttpResponse::Unauthorized().finish(),
     };
 
-    rows.into_iter().collect()
+    let ticket = Uuid::new_v4().simple().to_string();
+    {
+        let mut map = tickets.lock().unwrap();
+        map.retain(|_, t| t.created_at.elapsed() < WS_TICKET_TTL);
+        map.insert(
+            ticket.clone(),
+            WsTicket {
+                claims,
+                created_at: std::time::Instant::now(),
+            },
+        );
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "ticket": ticket,
+        "expires_in": WS_TICKET_TTL.as_secs(),
+    }))
 }
 
 /// GET /ws — WebSocket upgrade
+#[allow(clippy::too_many_arguments)]
 pub async fn ws_handler(
     req: HttpRequest,
     stream: web::Payload,
     pool: web::Data<SqlitePool>,
     broadcaster: web::Data<Broadcaster>,
-    online_users: web::Data<OnlineUsers>,
+    presence: web::Data<PresenceTracker>,
     access_cache: web::Data<AccessCache>,
+    tickets: web::Data<WsTickets>,
+    voice_occupancy: web::Data<VoiceOccupancy>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let (response, session, mut msg_stream) = actix_ws::handle(&req, stream)?;
-
     let pool = pool.get_ref().clone();
+    let voice = voice_occupancy.get_ref().clone();
     let tx = broadcaster.get_ref().clone();
-    let users = online_users.get_ref().clone();
+    let users = presence.get_ref().clone();
     let access_cache = access_cache.get_ref().clone();
-    let mut rx = tx.subscribe();
-
-    // We'll wait for a "join" message to hydrate user context.
-    let mut my_user_id: Option<String> = None;
-    let allowed_rooms: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
-    let is_admin = Arc::new(Mutex::new(false));
+    let session_store = req
+        .app_data::<web::Data<crate::sessions::SessionStore>>()
+        .map(|store| store.get_ref().clone());
+    let revoked = move |claims: &crate::auth::Claims| {
+        !claims.sid.is_empty()
+            && session_store
+                .as_ref()
+                .is_some_and(|store| crate::sessions::is_revoked(store, &claims.sid))
+    };
 
-    // Authenticate immediately
+    // Authenticate: a one-time `ticket` or an `access_token` in the query
+    // string, or an Authorization header. Browsers that can set neither send
+    // `{"type":"auth","ticket":"..."}` as their first message instead.
     use crate::auth::validate_token;
-    
-    // Try to get token from query string first
-    let query_string = req.query_string();
-    let mut token = None;
-    
-    if let Ok(params) = serde_urlencoded::from_str::<HashMap<String, String>>(query_string) {
-        if let Some(t) = params.get("access_token") {
-             token = Some(t.clone());
+
+    let params = serde_urlencoded::from_str::<HashMap<String, String>>(req.query_string()).unwrap_or_default();
+
+    let claims = if let Some(ticket) = params.get("ticket") {
+        match redeem_ws_ticket(&tickets, ticket) {
+            Some(claims) => Some(claims),
+            None => return Err(actix_web::error::ErrorUnauthorized("Invalid ticket")),
         }
+    } else {
+        let token = params.get("access_token").cloned().or_else(|| {
+            req.headers()
+                .get("Authorization")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .map(|t| t.to_string())
+        });
+        match token {
+            Some(t) => match validate_token(&t) {
+                Some(claims) => Some(claims),
+                None => return Err(actix_web::error::ErrorUnauthorized("Invalid token")),
+            },
+            None => None,
+        }
+    };
+
+    if claims.as_ref().is_some_and(&revoked) {
+        return Err(actix_web::error::ErrorUnauthorized("Session revoked"));
     }
-    
-    // Fallback to Authorization header
-    if token.is_none() {
-        if let Some(auth_header) = req.headers().get("Authorization") {
-             if let Ok(auth_str) = auth_header.to_str() {
-                 if let Some(t) = auth_str.strip_prefix("Bearer ") {
-                     token = Some(t.to_string());
-                 }
-             }
+
+    let (response, session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+
+    match claims {
+        Some(claims) => start_session(claims, session, msg_stream, pool, tx, users, access_cache, voice).await,
+        None => {
+            let tickets = tickets.get_ref().clone();
+            actix_web::rt::spawn(async move {
+                let first = tokio::time::timeout(WS_AUTH_TIMEOUT, msg_stream.next()).await;
+                let claims = match first {
+                    Ok(Some(Ok(Message::Text(text)))) => serde_json::from_str::<WsAuthMessage>(&text)
+                        .ok()
+                        .filter(|m| m.msg_type == "auth")
+                        .and_then(|m| redeem_ws_ticket(&tickets, &m.ticket))
+                        .filter(|c| !revoked(c)),
+                    _ => None,
+                };
+                match claims {
+                    Some(claims) => start_session(claims, session, msg_stream, pool, tx, users, access_cache, voice).await,
+                    None => {
+                        let _ = session
+                            .close(Some(actix_ws::CloseReason {
+                                code: actix_ws::CloseCode::Policy,
+                                description: Some("Authentication required".to_string()),
+                            }))
+                            .await;
+                    }
+                }
+            });
         }
     }
 
-    let claims = match token {
-        Some(t) => match validate_token(&t) {
-            Some(claims) => claims,
-            None => return Err(actix_web::error::ErrorUnauthorized("Invalid token")),
-        },
-        None => return Err(actix_web::error::ErrorUnauthorized("No token provided")),
-    };
+    Ok(response)
+}
+
+/// Register an authenticated connection and spawn its send/receive loops.
+#[allow(clippy::too_many_arguments)]
+async fn start_session(
+    claims: crate::auth::Claims,
+    session: actix_ws::Session,
+    mut msg_stream: actix_ws::MessageStream,
+    pool: SqlitePool,
+    tx: Broadcaster,
+    users: PresenceTracker,
+    access_cache: AccessCache,
+    voice: VoiceOccupancy,
+) {
+    let mut rx = tx.subscribe();
+
+    let allowed_rooms: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+    let is_admin = Arc::new(Mutex::new(false));
 
     // Pre-hydrate user session
-    my_user_id = Some(claims.sub.clone());
+    let my_user_id: Option<String> = Some(claims.sub.clone());
     
     // Fetch initial state
     let role = get_user_role_cached(&pool, &access_cache, &claims.sub)
         .await
         .unwrap_or_else(|| "user".to_string());
-    let rooms = fetch_accessible_rooms(&pool, &role).await;
+    let rooms = fetch_accessible_rooms(&pool, &claims.sub, &role).await;
+    // Guests only read and post, they don't type, talk or relay signaling.
+    let is_guest = role == crate::guests::GUEST_ROLE;
     {
         let mut guard = allowed_rooms.lock().unwrap();
         *guard = rooms;
     }
+    let administrator = get_role_permissions_cached(&pool, &access_cache, &role).await & permissions::ADMINISTRATOR != 0;
     {
         let mut admin_guard = is_admin.lock().unwrap();
-        *admin_guard = role == "admin";
+        *admin_guard = administrator;
     }
     
-    // Add to online users
-    {
-         let mut guard = users.lock().unwrap();
-         // We don't have avatar_color here without DB query, default to 0 for now. 
-         // Realistically we should fetch user profile or include it in token if needed, 
-         // but for now 0 is safe. 
-         guard.insert(claims.sub.clone(), 0);
-    }
+    let blocked = crate::relationships::fetch_blocked_ids(&pool, &claims.sub).await;
+    cache_load_user_blocks(&access_cache, &claims.sub, blocked);
+
+    crate::presence::connected(&pool, &users, &tx, &claims.sub).await;
 
     // Spawn task: forward broadcast messages to this client
     let mut send_session = session.clone();
     let send_allowed_rooms = allowed_rooms.clone();
     let send_is_admin = is_admin.clone();
+    let send_access_cache = access_cache.clone();
+    let send_pool = pool.clone();
+    let viewer_id = claims.sub.clone();
     actix_web::rt::spawn(async move {
         while let Ok(text) = rx.recv().await {
-            let room_id = extract_room_id(&text);
+            let event = serde_json::from_str::<serde_json::Value>(&text).unwrap_or_default();
+            let (room_id, author, recipient) = event_routing(&event);
+            if recipient.is_some_and(|r| r != viewer_id) {
+                continue;
+            }
+            if let Some(subject) = crate::presence::subject(&event) {
+                if !crate::presence::visible_to(&send_pool, &send_access_cache, &viewer_id, subject).await {
+                    continue;
+                }
+            }
+            if let Some(author_id) = author {
+                if is_blocked_for(&send_access_cache, &viewer_id, &author_id) {
+                    continue;
+                }
+            }
             if let Some(rid) = room_id {
                 let allowed = {
                     let admin = *send_is_admin.lock().unwrap();
@@ -281,18 +763,21 @@ pub async fn ws_handler(
     });
 
     // Spawn task: read messages from this client
+    let mut reply_session = session;
     actix_web::rt::spawn(async move {
         // Per-connection message rate limiter: max 10 messages per second
         let mut msg_timestamps: std::collections::VecDeque<std::time::Instant> = std::collections::VecDeque::new();
         let max_msgs_per_window: usize = 10;
         let rate_window = std::time::Duration::from_secs(1);
+        // Voice room this connection is in, for occupancy history
+        let mut voice_room: Option<String> = None;
 
         while let Some(Ok(msg)) = msg_stream.next().await {
             match msg {
                 Message::Text(text) => {
                     // Rate limit: drop messages that exceed the threshold
                     let now = std::time::Instant::now();
-                    while msg_timestamps.front().map_or(false, |t| now.duration_since(*t) > rate_window) {
+                    while msg_timestamps.front().is_some_and(|t| now.duration_since(*t) > rate_window) {
                         msg_timestamps.pop_front();
                     }
                     if msg_timestamps.len() >= max_msgs_per_window {
@@ -310,84 +795,105 @@ pub async fn ws_handler(
                              if let Some(uid) = &my_user_id {
                                 // Force ID to match token
                                 ws_msg.user_id = Some(uid.clone());
-                                
-                                // Update color in map if provided
-                                if let Some(color) = ws_msg.avatar_color {
-                                     let mut guard = users.lock().unwrap();
-                                     guard.insert(uid.clone(), color);
+                                let role = get_user_role_cached(&pool, &access_cache, uid)
+                                    .await
+                                    .unwrap_or_else(|| "user".to_string());
+                                ws_msg.role_display = Some(crate::roles::role_display(&pool, &role).await);
+                                ws_msg.role = Some(role);
+
+                                // The status is the server's, not the client's;
+                                // invisible users don't announce themselves.
+                                let presence = crate::presence::current(&pool, &users, uid).await;
+                                ws_msg.status = presence["status"].as_str().map(|s| s.to_string());
+                                ws_msg.custom_status = presence["custom_status"].as_str().map(|s| s.to_string());
+                                if ws_msg.status.as_deref() != Some("offline") {
+                                    let _ = tx.send(serde_json::to_string(&ws_msg).unwrap());
                                 }
-                                
-                                // Broadcast join
-                                let _ = tx.send(serde_json::to_string(&ws_msg).unwrap());
                              }
                         }
-                        // Handle LEAVE (explicit)
+                        // Handle LEAVE (explicit): the cleanup below announces it
                         else if ws_msg.msg_type == "leave" {
-                             if let Some(uid) = &my_user_id {
-                                {
-                                    let mut guard = users.lock().unwrap();
-                                    guard.remove(uid);
-                                }
-                                let _ = tx.send(text.to_string()); // Broadcast leave
-                             }
                              break;
                         }
                                 // Handle MESSAGE
                         else if ws_msg.msg_type == "message" {
-                             if let (Some(content), Some(rid), Some(uid), Some(uname)) = (&ws_msg.content, &ws_msg.room_id, &ws_msg.user_id, &ws_msg.username) {
-                                // SECURITY: Force user_id to match token
-                                if Some(uid) != my_user_id.as_ref() {
-                                    continue;
+                            // SECURITY: Force user_id to match token
+                            if ws_msg.user_id.is_none() || ws_msg.user_id != my_user_id {
+                                continue;
+                            }
+                            let room_id = ws_msg.room_id.clone().unwrap_or_default();
+                            match post_message(&pool, &tx, &access_cache, ws_msg).await {
+                                Err(PostRefusal::Slowmode(cooldown)) => {
+                                    let _ = reply_session.text(cooldown.event(&room_id)).await;
                                 }
-
-                                let allowed = can_user_access_room_cached(&pool, &access_cache, uid, rid).await;
-
-                                if !allowed {
-                                    continue;
+                                Err(PostRefusal::AltTextRequired(ids)) => {
+                                    let _ = reply_session.text(crate::alt_text::required_event(&room_id, &ids)).await;
                                 }
-
-                                let has_content = !content.trim().is_empty();
-                                let has_image = ws_msg.image_url.as_ref().map_or(false, |u| !u.is_empty());
-                                if has_content || has_image {
-                                    let msg_id = Uuid::new_v4().to_string();
-                                    let now = chrono::Utc::now().to_rfc3339();
-
-                                    let _ = sqlx::query(
-                                        "INSERT INTO messages (id, room_id, user_id, username, content, created_at, image_url, reply_to_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
-                                    )
-                                    .bind(&msg_id)
-                                    .bind(rid)
-                                    .bind(uid)
-                                    .bind(uname)
-                                    .bind(content)
-                                    .bind(&now)
-                                    .bind(&ws_msg.image_url)
-                                    .bind(&ws_msg.reply_to_id)
-                                    .execute(&pool)
-                                    .await;
-
-                                    ws_msg.id = msg_id;
-                                    ws_msg.created_at = now;
-
-                                    let _ = tx.send(serde_json::to_string(&ws_msg).unwrap());
+                                Err(PostRefusal::RulesUnacknowledged(pending)) => {
+                                    let _ = reply_session.text(pending.event(&room_id)).await;
                                 }
-                             }
-                        }
-                        // Handle TYPING relay
-                        else if ws_msg.msg_type == "typing" {
-                            let _ = tx.send(text.to_string());
+                                Err(PostRefusal::Unverified(unverified)) => {
+                                    let _ = reply_session.text(unverified.event(&room_id)).await;
+                                }
+                                Err(PostRefusal::Lockdown(locked)) => {
+                                    let _ = reply_session.text(locked.event(&room_id)).await;
+                                }
+                                Err(PostRefusal::TimedOut(timed_out)) => {
+                                    let _ = reply_session.text(timed_out.event(&room_id)).await;
+                                }
+                                Err(PostRefusal::Automod(blocked)) => {
+                                    let _ = reply_session.text(blocked.event(&room_id)).await;
+                                }
+                                _ => {}
+                            }
                         }
-                        // Handle PRESENCE relay
+                        // Handle PRESENCE: store the chosen status, broadcast what others see
                         else if ws_msg.msg_type == "presence" {
-                            let _ = tx.send(text.to_string());
+                            if let (Some(uid), Ok(update)) = (&my_user_id, serde_json::from_str::<crate::presence::PresenceUpdate>(&text)) {
+                                let _ = crate::presence::update(&pool, &users, &tx, uid, update).await;
+                            }
                         }
-                        // Handle VOICE events relay
-                        else if ws_msg.msg_type == "voice_join"
+                        // Relay TYPING and VOICE events
+                        else if ws_msg.msg_type == "typing"
+                            || ws_msg.msg_type == "voice_join"
                             || ws_msg.msg_type == "voice_leave"
                             || ws_msg.msg_type == "voice_state"
                             || ws_msg.msg_type == "voice_signal"
                         {
-                            let _ = tx.send(text.to_string());
+                            if is_guest {
+                                continue;
+                            }
+                            if let Some(uid) = &my_user_id {
+                                if ws_msg.msg_type == "voice_leave" {
+                                    if let Some(room) = voice_room.take() {
+                                        crate::voice_activity::left(&voice, &room, uid);
+                                    }
+                                } else if ws_msg.msg_type == "voice_join" {
+                                    // Joining takes CONNECT, a refused join is not relayed.
+                                    let Some(room) = ws_msg.room_id.as_ref() else {
+                                        continue;
+                                    };
+                                    if room_permissions_cached(&pool, &access_cache, uid, room).await & permissions::CONNECT == 0 {
+                                        continue;
+                                    }
+                                    if let Err(timed_out) = crate::timeouts::check(&pool, uid).await {
+                                        let _ = reply_session.text(timed_out.event(room)).await;
+                                        continue;
+                                    }
+                                    if voice_room.as_ref() != Some(room) {
+                                        if let Some(previous) = voice_room.replace(room.clone()) {
+                                            crate::voice_activity::left(&voice, &previous, uid);
+                                        }
+                                        crate::voice_activity::joined(&voice, room, uid);
+                                    }
+                                }
+                            }
+                            // Server mute and deafen come from the server, not the client.
+                            let relayed = match (&my_user_id, ws_msg.msg_type.as_str()) {
+                                (Some(uid), "voice_join" | "voice_state") => crate::voice_moderation::stamp(&pool, uid, &text).await,
+                                _ => text.to_string(),
+                            };
+                            let _ = tx.send(relayed);
                         }
                     }
                 }
@@ -396,20 +902,19 @@ pub async fn ws_handler(
             }
         }
 
-        // Cleanup on disconnect
+        // Cleanup on disconnect: announce the leave once the last connection is gone
         if let Some(uid) = my_user_id {
-            {
-                let mut guard = users.lock().unwrap();
-                guard.remove(&uid);
+            if let Some(room) = voice_room {
+                crate::voice_activity::left(&voice, &room, &uid);
+            }
+            if crate::presence::disconnected(&pool, &users, &tx, &uid).await {
+                let offline_msg = serde_json::json!({
+                    "type": "leave",
+                    "user_id": uid
+                });
+                let _ = tx.send(offline_msg.to_string());
             }
-            // Broadcast offline
-            let offline_msg = serde_json::json!({
-                "type": "leave",
-                "user_id": uid
-            });
-            let _ = tx.send(offline_msg.to_string());
         }
     });
 
-    Ok(response)
 }
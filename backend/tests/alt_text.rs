@@ -0,0 +1,89 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state, TestUser};
+
+/// An attachment of `content_type` uploaded by `uploader`, sent with `message_id` if any.
+async fn create_attachment(pool: &sqlx::SqlitePool, uploader: &TestUser, content_type: &str, message_id: Option<&str>) -> String {
+    let hash = uuid::Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO files (hash, size, content_type, created_at) VALUES (?, 10, ?, '2026-01-01T00:00:00+00:00')")
+        .bind(&hash)
+        .bind(content_type)
+        .execute(pool)
+        .await
+        .unwrap();
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO attachments (id, file_hash, uploader_id, message_id, filename, created_at) VALUES (?, ?, ?, ?, 'cat.png', ?)"
+    )
+    .bind(&id)
+    .bind(&hash)
+    .bind(&uploader.id)
+    .bind(message_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await
+    .unwrap();
+    id
+}
+
+#[actix_web::test]
+async fn moderators_backfill_missing_alt_text() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let message = create_message(&state.pool, &room, &alice, "look").await;
+    let image = create_attachment(&state.pool, &alice, "image/png", Some(&message)).await;
+    let pdf = create_attachment(&state.pool, &alice, "application/pdf", Some(&message)).await;
+    let pending = create_attachment(&state.pool, &alice, "image/png", None).await;
+    let missing = || TestRequest::get().uri("/api/server/alt-text/missing");
+
+    let (status, _) = call_json(&app, alice.sign(missing())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = call_json(&app, admin.sign(missing())).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let listed: Vec<&str> = body["attachments"].as_array().unwrap().iter().map(|m| m["attachment"]["id"].as_str().unwrap()).collect();
+    assert_eq!(listed, [image.as_str()], "only sent images and videos are listed, not {pdf} or {pending}");
+    assert_eq!(body["attachments"][0]["room_id"], room.as_str());
+
+    let backfill = |items: serde_json::Value| TestRequest::post().uri("/api/server/alt-text").set_json(serde_json::json!({ "items": items }));
+    let (status, _) = call_json(&app, alice.sign(backfill(serde_json::json!([{ "kind": "attachment", "id": image, "alt_text": "A cat" }])))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(backfill(serde_json::json!([{ "kind": "attachment", "id": image, "alt_text": "  " }])))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = call_json(&app, admin.sign(backfill(serde_json::json!([
+        { "kind": "attachment", "id": image, "alt_text": " A cat asleep on a keyboard " },
+        { "kind": "emoji", "id": "nope", "alt_text": "Party parrot" },
+    ])))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["updated"], 1);
+    assert_eq!(body["not_found"], serde_json::json!(["nope"]));
+
+    let (_, body) = call_json(&app, admin.sign(missing())).await;
+    assert!(body["attachments"].as_array().unwrap().is_empty());
+    let (_, messages) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/messages")))).await;
+    let sent = messages["messages"].as_array().unwrap().iter().find(|m| m["id"] == message.as_str()).unwrap();
+    let attachment = sent["attachments"].as_array().unwrap().iter().find(|a| a["id"] == image.as_str()).unwrap();
+    assert_eq!(attachment["alt_text"], "A cat asleep on a keyboard");
+}
+
+#[actix_web::test]
+async fn uploaders_edit_the_alt_text_of_their_own_attachments() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let pending = create_attachment(&state.pool, &alice, "image/png", None).await;
+    let edit = |text: &str| TestRequest::patch().uri(&format!("/api/files/{pending}")).set_json(serde_json::json!({ "alt_text": text }));
+
+    let (status, _) = call_json(&app, bob.sign(edit("Not mine"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, alice.sign(edit(&"x".repeat(backend::alt_text::MAX_ALT_TEXT_CHARS + 1)))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = call_json(&app, alice.sign(edit("A red bicycle"))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["alt_text"], "A red bicycle");
+    let (_, body) = call_json(&app, alice.sign(edit(""))).await;
+    assert_eq!(body["alt_text"], serde_json::Value::Null);
+}
@@ -0,0 +1,56 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::permissions::SEND_MESSAGES;
+use backend::test_support::{call_json, call_page, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn moderation_actions_are_logged_with_reasons() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+
+    let create_role = TestRequest::post()
+        .uri("/api/server/roles")
+        .insert_header(("X-Audit-Log-Reason", "new helpers"))
+        .set_json(serde_json::json!({ "name": "helper" }));
+    let (status, _) = call_json(&app, admin.sign(create_role)).await;
+    assert_eq!(status, StatusCode::OK);
+    let promote = TestRequest::patch()
+        .uri(&format!("/api/users/{}/role", alice.id))
+        .set_json(serde_json::json!({ "role": "helper" }));
+    let (status, _) = call_json(&app, admin.sign(promote)).await;
+    assert_eq!(status, StatusCode::OK);
+    let overwrite = TestRequest::put()
+        .uri(&format!("/api/rooms/{lobby}/permissions/role/helper"))
+        .set_json(serde_json::json!({ "deny": SEND_MESSAGES }));
+    let (status, _) = call_json(&app, admin.sign(overwrite)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let log = |query: &str| TestRequest::get().uri(&format!("/api/server/audit-log?{query}"));
+    let (status, _) = call_json(&app, alice.sign(log(""))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, entries) = call_json(&app, admin.sign(log(""))).await;
+    assert_eq!(status, StatusCode::OK, "{entries}");
+    let actions: Vec<&str> = entries.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["permissions_updated", "member_role_updated", "role_created"]);
+    assert_eq!(entries[0]["changes"]["deny"]["new"], serde_json::json!(["SEND_MESSAGES"]));
+    assert_eq!(entries[0]["changes"]["target_id"], "helper");
+    assert_eq!(entries[2]["reason"], "new helpers");
+    assert_eq!(entries[2]["actor_username"], "root");
+
+    let (_, promoted) = call_json(&app, admin.sign(log(&format!("action=member_role_updated&target_id={}", alice.id)))).await;
+    assert_eq!(promoted.as_array().unwrap().len(), 1);
+    assert_eq!(promoted[0]["changes"]["role"], serde_json::json!({ "old": "user", "new": "helper" }));
+
+    let (_, first, next) = call_page(&app, admin.sign(log("limit=2"))).await;
+    assert_eq!(first.as_array().unwrap().len(), 2);
+    let (_, rest, next) = call_page(&app, admin.sign(log(&format!("cursor={}", next.unwrap())))).await;
+    assert_eq!(rest[0]["action"], "role_created");
+    assert!(next.is_none());
+
+    let (status, _) = call_json(&app, admin.sign(log("action=kicked"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
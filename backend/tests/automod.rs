@@ -0,0 +1,83 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn automod_rules_block_flag_and_time_out() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+
+    let add_rule = |rule: serde_json::Value| TestRequest::post().uri("/api/server/automod/rules").set_json(rule);
+    let spam = serde_json::json!({ "name": "spam", "kind": "keyword", "patterns": ["Spam*"], "action": "block" });
+    let (status, _) = call_json(&app, alice.sign(add_rule(spam))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let bad = serde_json::json!({ "name": "broken", "kind": "regex", "patterns": ["("], "action": "flag" });
+    let (status, _) = call_json(&app, admin.sign(add_rule(bad))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let mut rule_ids = Vec::new();
+    for rule in [
+        serde_json::json!({ "name": "spam", "kind": "keyword", "patterns": ["Spam*"], "action": "block" }),
+        serde_json::json!({ "name": "phone numbers", "kind": "regex", "patterns": [r"\d{3}-\d{4}"], "action": "flag" }),
+        serde_json::json!({ "name": "threats", "kind": "keyword", "patterns": ["nuke"], "action": "timeout", "timeout_seconds": 60 }),
+    ] {
+        let (status, created) = call_json(&app, admin.sign(add_rule(rule))).await;
+        assert_eq!(status, StatusCode::CREATED, "{created}");
+        rule_ids.push(created["id"].as_str().unwrap().to_string());
+    }
+
+    let poll = |question: &str| {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{room}/polls"))
+            .set_json(serde_json::json!({ "question": question, "options": ["Yes", "No"] }))
+    };
+    let (status, refused) = call_json(&app, alice.sign(poll("Who likes SPAMMING?"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["rule"], "spam");
+    let (status, _) = call_json(&app, admin.sign(poll("Who likes spam?"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, flagged) = call_json(&app, alice.sign(poll("Call me at 555-1234?"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let queue = || TestRequest::get().uri("/api/server/automod/queue");
+    let (status, pending) = call_json(&app, admin.sign(queue())).await;
+    assert_eq!(status, StatusCode::OK, "{pending}");
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+    assert_eq!(pending[0]["message_id"], flagged["id"]);
+    assert_eq!(pending[0]["matched"], "555-1234");
+    let review = TestRequest::post()
+        .uri(&format!("/api/server/automod/queue/{}", pending[0]["id"].as_str().unwrap()))
+        .set_json(serde_json::json!({ "action": "remove" }));
+    let (status, _) = call_json(&app, admin.sign(review)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, pending) = call_json(&app, admin.sign(queue())).await;
+    assert!(pending.as_array().unwrap().is_empty());
+    let deleted: Option<String> = sqlx::query_scalar("SELECT deleted_at FROM messages WHERE id = ?")
+        .bind(flagged["id"].as_str().unwrap())
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert!(deleted.is_some());
+
+    let (status, refused) = call_json(&app, alice.sign(poll("Should we nuke it?"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(refused["timed_out_until"].is_string());
+    let (status, refused) = call_json(&app, alice.sign(poll("Lunch?"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["error"], "You are timed out");
+    let untimeout = TestRequest::delete().uri(&format!("/api/users/{}/timeout", alice.id));
+    let (status, _) = call_json(&app, admin.sign(untimeout)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, alice.sign(poll("Lunch?"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let disable = TestRequest::patch()
+        .uri(&format!("/api/server/automod/rules/{}", rule_ids[0]))
+        .set_json(serde_json::json!({ "enabled": false }));
+    let (status, _) = call_json(&app, admin.sign(disable)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call_json(&app, alice.sign(poll("Spam, anyone?"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
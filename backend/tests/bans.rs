@@ -0,0 +1,118 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn banned_members_cannot_sign_in_until_their_appeal_is_accepted() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let helper = create_user(&state.pool, "helper", "user").await;
+
+    let credentials = serde_json::json!({ "username": "mallory", "password": "correct horse battery" });
+    let register = TestRequest::post().uri("/api/register").set_json(&credentials);
+    let (status, account) = call_json(&app, register).await;
+    assert_eq!(status, StatusCode::OK, "{account}");
+    let mallory = account["user_id"].as_str().unwrap().to_string();
+    let login = || TestRequest::post().uri("/api/login").set_json(&credentials);
+
+    let ban = |body: serde_json::Value| TestRequest::post().uri(&format!("/api/users/{mallory}/ban")).set_json(body);
+    let (status, _) = call_json(&app, helper.sign(ban(serde_json::json!({})))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, banned) = call_json(&app, admin.sign(ban(serde_json::json!({ "reason": "spam", "duration_hours": 24 })))).await;
+    assert_eq!(status, StatusCode::CREATED, "{banned}");
+    assert!(banned["expires_at"].is_string());
+    let (status, _) = call_json(&app, admin.sign(ban(serde_json::json!({})))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, refused) = call_json(&app, login()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["ban"]["reason"], "spam");
+    let token = refused["appeal_token"].as_str().unwrap().to_string();
+
+    let appeal = |message: &str| {
+        TestRequest::post()
+            .uri("/api/bans/appeal")
+            .set_json(serde_json::json!({ "appeal_token": token, "message": message }))
+    };
+    let (status, _) = call_json(&app, appeal("It was my cat")).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = call_json(&app, appeal("Really")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, refused) = call_json(&app, login()).await;
+    assert_eq!(refused["ban"]["appeal_status"], "pending");
+    assert!(refused.get("appeal_token").is_none());
+
+    let pending = TestRequest::get().uri("/api/server/bans?appeal_status=pending&active=true");
+    let (status, bans) = call_json(&app, admin.sign(pending)).await;
+    assert_eq!(status, StatusCode::OK, "{bans}");
+    assert_eq!(bans[0]["appeal"]["message"], "It was my cat");
+    let review = TestRequest::post()
+        .uri(&format!("/api/server/bans/{}/appeal", banned["id"].as_str().unwrap()))
+        .set_json(serde_json::json!({ "accept": true, "note": "fair enough" }));
+    let (status, reviewed) = call_json(&app, admin.sign(review)).await;
+    assert_eq!(status, StatusCode::OK, "{reviewed}");
+    assert_eq!(reviewed["appeal"]["status"], "accepted");
+    assert!(reviewed["lifted_at"].is_string());
+
+    let (status, _) = call_json(&app, login()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let log = TestRequest::get().uri(&format!("/api/server/audit-log?target_id={mallory}"));
+    let (_, entries) = call_json(&app, admin.sign(log)).await;
+    let actions: Vec<&str> = entries.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["member_unbanned", "ban_appeal_reviewed", "member_banned"]);
+}
+
+#[actix_web::test]
+async fn bans_purge_recent_messages_and_kicks_check_the_hierarchy() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    sqlx::query(
+        "INSERT INTO roles (name, color, permissions) SELECT 'moderator', '#00aa00', permissions | 2048 FROM roles WHERE name = 'user'"
+    )
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let moderator = create_user(&state.pool, "mod", "moderator").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+
+    let old = create_message(&state.pool, &lobby, &alice, "last week").await;
+    sqlx::query("UPDATE messages SET created_at = ? WHERE id = ?")
+        .bind((chrono::Utc::now() - chrono::Duration::days(6)).to_rfc3339())
+        .bind(&old)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    create_message(&state.pool, &lobby, &alice, "just now").await;
+
+    let kick = |id: &str| TestRequest::post().uri(&format!("/api/users/{id}/kick")).set_json(serde_json::json!({}));
+    let (status, _) = call_json(&app, moderator.sign(kick(&admin.id))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, moderator.sign(kick(&moderator.id))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, kicked) = call_json(&app, moderator.sign(kick(&alice.id))).await;
+    assert_eq!(status, StatusCode::OK, "{kicked}");
+
+    let ban = TestRequest::post()
+        .uri(&format!("/api/users/{}/ban", alice.id))
+        .set_json(serde_json::json!({ "delete_message_seconds": 3600, "appealable": false }));
+    let (status, banned) = call_json(&app, moderator.sign(ban)).await;
+    assert_eq!(status, StatusCode::CREATED, "{banned}");
+    assert_eq!(banned["purged_messages"], 1);
+    assert!(banned["expires_at"].is_null());
+    let left: Vec<String> = sqlx::query_scalar("SELECT id FROM messages WHERE user_id = ?")
+        .bind(&alice.id)
+        .fetch_all(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(left, [old]);
+
+    let unban = || TestRequest::delete().uri(&format!("/api/users/{}/ban", alice.id));
+    let (status, _) = call_json(&app, moderator.sign(unban())).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, moderator.sign(unban())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
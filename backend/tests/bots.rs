@@ -0,0 +1,75 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+fn as_bot(token: &str, req: TestRequest) -> TestRequest {
+    req.insert_header(("Authorization", format!("Bot {token}")))
+}
+
+#[actix_web::test]
+async fn bots_sign_in_with_their_token_and_act_within_their_role() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "general", "user").await;
+
+    let create = |body: serde_json::Value| TestRequest::post().uri("/api/server/bots").set_json(body);
+    let (status, _) = call_json(&app, alice.sign(create(serde_json::json!({ "name": "Helper" })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, refused) = call_json(&app, admin.sign(create(serde_json::json!({ "name": "Helper" })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["sudo_required"], true);
+    let (status, bot) = call_json(&app, admin.sign_sudo(create(serde_json::json!({ "name": "Helper", "description": "Says hi" })))).await;
+    assert_eq!(status, StatusCode::CREATED, "{bot}");
+    assert_eq!(bot["role"], "bot-helper");
+    let id = bot["id"].as_str().unwrap().to_string();
+    let token = bot["token"].as_str().unwrap().to_string();
+
+    let poll = || {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{room}/polls"))
+            .set_json(serde_json::json!({ "question": "Deploy?", "options": ["Yes", "No"] }))
+    };
+    let (status, message) = call_json(&app, as_bot(&token, poll())).await;
+    assert_eq!(status, StatusCode::CREATED, "{message}");
+    assert_eq!(message["user_id"], bot["user_id"]);
+    let (status, _) = call_json(&app, as_bot(&token, create(serde_json::json!({ "name": "Minion" })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The bot's role is its own.
+    let give_role = TestRequest::patch()
+        .uri(&format!("/api/users/{}/role", alice.id))
+        .set_json(serde_json::json!({ "role": "bot-helper" }));
+    let (status, _) = call_json(&app, admin.sign(give_role)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri("/api/server/roles/bot-helper"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let update = TestRequest::patch().uri(&format!("/api/server/bots/{id}")).set_json(serde_json::json!({ "permissions": 0 }));
+    let (status, updated) = call_json(&app, admin.sign(update)).await;
+    assert_eq!(status, StatusCode::OK, "{updated}");
+    assert_eq!(updated["permissions"], 0);
+    let (status, _) = call_json(&app, as_bot(&token, poll())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let reset = TestRequest::post().uri(&format!("/api/server/bots/{id}/token"));
+    let (status, reset) = call_json(&app, admin.sign_sudo(reset)).await;
+    assert_eq!(status, StatusCode::OK);
+    let new_token = reset["token"].as_str().unwrap().to_string();
+    let me = || TestRequest::get().uri("/api/server/bots");
+    let (status, _) = call_json(&app, as_bot(&token, me())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call_json(&app, as_bot(&new_token, me())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&format!("/api/server/bots/{id}")))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, as_bot(&new_token, me())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let log = TestRequest::get().uri("/api/server/audit-log?target_type=bot");
+    let (_, entries) = call_json(&app, admin.sign(log)).await;
+    let actions: Vec<&str> = entries.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["bot_deleted", "bot_token_reset", "bot_updated", "bot_created"]);
+}
@@ -0,0 +1,56 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn room_managers_bridge_a_room_with_one_discord_channel() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "general", "user").await;
+    let other = create_room(&state.pool, "random", "user").await;
+
+    let bridge = |room: &str| format!("/api/rooms/{room}/discord-bridge");
+    let create = |room: &str, channel_id: &str| {
+        TestRequest::post().uri(&bridge(room)).set_json(serde_json::json!({ "channel_id": channel_id, "token_source": "bot" }))
+    };
+    let (status, _) = call_json(&app, alice.sign(create(&room, "1100000000000000001"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(create(&room, "general"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    std::env::remove_var("DISCORD_BRIDGE_BOT_TOKEN");
+    let (status, _) = call_json(&app, admin.sign(create(&room, "1100000000000000001"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let user_source =
+        TestRequest::post().uri(&bridge(&room)).set_json(serde_json::json!({ "channel_id": "1100000000000000001", "token_source": "user" }));
+    let (status, _) = call_json(&app, admin.sign(user_source)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    std::env::set_var("DISCORD_BRIDGE_BOT_TOKEN", "test-bot-token");
+    let (status, created) = call_json(&app, admin.sign(create(&room, "1100000000000000001"))).await;
+    assert_eq!(status, StatusCode::CREATED, "{created}");
+    assert_eq!(created["channel_id"], "1100000000000000001");
+    assert_eq!(created["token_source"], "bot");
+    assert!(created["last_discord_message_id"].is_null());
+    let (status, _) = call_json(&app, admin.sign(create(&room, "1100000000000000002"))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = call_json(&app, admin.sign(create(&other, "1100000000000000001"))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, fetched) = call_json(&app, admin.sign(TestRequest::get().uri(&bridge(&room)))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched["id"], created["id"]);
+    let (_, webhooks) = call_json(&app, admin.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/webhooks")))).await;
+    assert_eq!(webhooks[0]["id"], created["webhook_id"]);
+    assert_eq!(webhooks[0]["name"], "Discord bridge");
+
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&bridge(&room)))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, admin.sign(TestRequest::get().uri(&bridge(&room)))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, webhooks) = call_json(&app, admin.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/webhooks")))).await;
+    assert_eq!(webhooks.as_array().unwrap().len(), 0);
+    let (status, _) = call_json(&app, admin.sign(create(&other, "1100000000000000001"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
@@ -0,0 +1,57 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_message, create_messages, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn moderators_bulk_delete_by_id_time_range_and_member() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    let games = create_room(&state.pool, "games", "user").await;
+
+    let flood = create_messages(&state.pool, &lobby, &alice, "spam", 150).await;
+    let kept = create_message(&state.pool, &lobby, &bob, "hello").await;
+    let elsewhere = create_message(&state.pool, &games, &alice, "more spam").await;
+
+    let bulk_delete = |body: serde_json::Value| {
+        TestRequest::post().uri(&format!("/api/rooms/{lobby}/messages/bulk-delete")).set_json(body)
+    };
+    let (status, _) = call_json(&app, bob.sign(bulk_delete(serde_json::json!({ "message_ids": [flood[0]] })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(bulk_delete(serde_json::json!({})))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A message of another room is not deleted through this one.
+    let by_id = serde_json::json!({ "message_ids": [flood[0], flood[1], elsewhere] });
+    let (status, result) = call_json(&app, admin.sign(bulk_delete(by_id))).await;
+    assert_eq!(status, StatusCode::OK, "{result}");
+    assert_eq!(result["deleted"], 2);
+
+    let after = (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+    let by_range = serde_json::json!({ "after": after, "user_id": alice.id });
+    let (status, result) = call_json(&app, admin.sign(bulk_delete(by_range))).await;
+    assert_eq!(status, StatusCode::OK, "{result}");
+    assert_eq!(result["deleted"], 148);
+    assert_eq!(result["has_more"], false);
+    let left: Vec<String> = sqlx::query_scalar("SELECT id FROM messages WHERE room_id = ? AND deleted_at IS NULL")
+        .bind(&lobby)
+        .fetch_all(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(left, [kept]);
+
+    let member = TestRequest::post()
+        .uri(&format!("/api/users/{}/messages/bulk-delete", alice.id))
+        .set_json(serde_json::json!({ "within_seconds": 3600 }));
+    let (status, result) = call_json(&app, admin.sign(member)).await;
+    assert_eq!(status, StatusCode::OK, "{result}");
+    assert_eq!(result["deleted"], 1);
+
+    let log = TestRequest::get().uri(&format!("/api/server/audit-log?target_id={lobby}"));
+    let (_, entries) = call_json(&app, admin.sign(log)).await;
+    let counts: Vec<i64> = entries.as_array().unwrap().iter().map(|e| e["changes"]["count"].as_i64().unwrap()).collect();
+    assert_eq!(counts, [148, 2]);
+}
@@ -0,0 +1,133 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_user, init_app, test_state};
+use backend::AppState;
+use chrono::{Duration, Utc};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+type Subscribers = Arc<Mutex<Vec<mpsc::UnboundedSender<Vec<u8>>>>>;
+
+fn bulk(value: &str) -> String {
+    format!("${}\r\n{value}\r\n", value.len())
+}
+
+/// A command as its bulk strings, `None` once the client is gone.
+async fn read_command(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok().filter(|n| *n > 0)?;
+    let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+    let mut parts = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+        let mut value = vec![0; len + 2];
+        reader.read_exact(&mut value).await.ok()?;
+        value.truncate(len);
+        parts.push(String::from_utf8(value).ok()?);
+    }
+    Some(parts)
+}
+
+/// A Redis speaking just enough pub/sub for the bus, on one channel; returns
+/// its URL and the connections subscribed to it.
+async fn fake_redis() -> (String, Subscribers) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://{}", listener.local_addr().unwrap());
+    let subscribers: Subscribers = Arc::default();
+    let shared = subscribers.clone();
+    actix_web::rt::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let (read, mut write) = socket.into_split();
+            let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+            actix_web::rt::spawn(async move {
+                while let Some(bytes) = rx.recv().await {
+                    if write.write_all(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            let subscribers = shared.clone();
+            actix_web::rt::spawn(async move {
+                let mut reader = BufReader::new(read);
+                while let Some(command) = read_command(&mut reader).await {
+                    let reply = match command[0].to_ascii_uppercase().as_str() {
+                        "SUBSCRIBE" => {
+                            subscribers.lock().unwrap().push(tx.clone());
+                            format!("*3\r\n{}{}:1\r\n", bulk("subscribe"), bulk(&command[1]))
+                        }
+                        "PUBLISH" => {
+                            let message = format!("*3\r\n{}{}{}", bulk("message"), bulk(&command[1]), bulk(&command[2]));
+                            let mut subscribers = subscribers.lock().unwrap();
+                            subscribers.retain(|s| s.send(message.clone().into_bytes()).is_ok());
+                            format!(":{}\r\n", subscribers.len())
+                        }
+                        "PING" => "+PONG\r\n".to_string(),
+                        _ => "+OK\r\n".to_string(),
+                    };
+                    let _ = tx.send(reply.into_bytes());
+                }
+            });
+        }
+    });
+    (url, subscribers)
+}
+
+/// Put `state` on the bus, as `run` does for a server instance.
+fn join_bus(state: &AppState) {
+    backend::bus::spawn_bus(
+        state.broadcaster.clone(),
+        state.presence.clone(),
+        state.session_store.clone(),
+        state.access_cache.clone(),
+        state.bot_tokens.clone(),
+    );
+}
+
+#[actix_web::test]
+async fn sessions_revoked_on_one_instance_are_rejected_on_the_others() {
+    let (url, subscribers) = fake_redis().await;
+    std::env::set_var("EVENT_BUS_URL", url);
+    let here = test_state().await;
+    let elsewhere = test_state().await;
+    join_bus(&here);
+    join_bus(&elsewhere);
+    for _ in 0..100 {
+        if subscribers.lock().unwrap().len() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(subscribers.lock().unwrap().len(), 2, "both instances subscribed");
+
+    let app = init_app(&here).await;
+    let alice = create_user(&here.pool, "alice", "user").await;
+    let now = Utc::now();
+    sqlx::query("INSERT INTO sessions (id, user_id, created_at, last_seen_at, expires_at) VALUES ('phone', ?, ?, ?, ?)")
+        .bind(&alice.id)
+        .bind(now.to_rfc3339())
+        .bind(now.to_rfc3339())
+        .bind((now + Duration::days(30)).to_rfc3339())
+        .execute(&here.pool)
+        .await
+        .unwrap();
+    assert!(!backend::sessions::is_revoked(&elsewhere.session_store, "phone"));
+
+    let (status, body) = call_json(&app, alice.sign_sudo(TestRequest::delete().uri("/api/users/@me/sessions/phone"))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    for _ in 0..100 {
+        if backend::sessions::is_revoked(&elsewhere.session_store, "phone") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(backend::sessions::is_revoked(&elsewhere.session_store, "phone"), "the revocation reached the other instance");
+
+    // The other instance refuses the session's token without asking the database.
+    let other_app = init_app(&elsewhere).await;
+    let token = backend::auth::create_token(&alice.id, &alice.username, &alice.role, "phone");
+    let (status, _) = call_json(&other_app, TestRequest::get().uri("/api/users/@me/sessions").insert_header(("Authorization", format!("Bearer {token}")))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
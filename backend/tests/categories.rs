@@ -0,0 +1,63 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn layout_reorders_rooms_and_categories_atomically() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    // Only the rooms below, not the one the first migration seeds.
+    sqlx::query("DELETE FROM rooms").execute(&state.pool).await.unwrap();
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    let general = create_room(&state.pool, "general", "user").await;
+    let staff = create_room(&state.pool, "staff", "moderator").await;
+
+    let create = |name: &str| TestRequest::post().uri("/api/server/categories").set_json(serde_json::json!({ "name": name }));
+    let (status, _) = call_json(&app, alice.sign(create("Text"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, text) = call_json(&app, admin.sign(create("Text"))).await;
+    assert_eq!(status, StatusCode::CREATED, "{text}");
+    let (_, voice) = call_json(&app, admin.sign(create("Voice"))).await;
+    assert_eq!(voice["position"], 1);
+    let (text, voice) = (text["id"].as_str().unwrap(), voice["id"].as_str().unwrap());
+
+    let layout = |body: serde_json::Value| TestRequest::put().uri("/api/server/layout").set_json(body);
+    // Every room and category, exactly once.
+    let missing = serde_json::json!({ "room_ids": [lobby], "categories": [{ "id": text, "room_ids": [general] }] });
+    let (status, _) = call_json(&app, admin.sign(layout(missing))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let twice = serde_json::json!({
+        "room_ids": [lobby, general],
+        "categories": [{ "id": voice, "room_ids": [] }, { "id": text, "room_ids": [general, staff] }],
+    });
+    let (status, _) = call_json(&app, admin.sign(layout(twice))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let full = serde_json::json!({
+        "room_ids": [],
+        "categories": [{ "id": voice, "room_ids": [] }, { "id": text, "room_ids": [general, staff, lobby] }],
+    });
+    let (status, saved) = call_json(&app, admin.sign(layout(full))).await;
+    assert_eq!(status, StatusCode::OK, "{saved}");
+    assert_eq!(saved["categories"][0]["id"], voice);
+    assert_eq!(saved["categories"][1]["room_ids"], serde_json::json!([general, staff, lobby]));
+
+    // Members only see their rooms, and no empty category.
+    let (_, seen) = call_json(&app, alice.sign(TestRequest::get().uri("/api/server/layout"))).await;
+    assert_eq!(seen["categories"].as_array().unwrap().len(), 1);
+    assert_eq!(seen["categories"][0]["room_ids"], serde_json::json!([general, lobby]));
+    let (_, rooms) = call_json(&app, alice.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert_eq!(rooms[0]["id"], general.as_str());
+    assert_eq!(rooms[1]["position"], 2);
+
+    // Deleting a category frees its rooms in order.
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&format!("/api/server/categories/{text}")))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, after) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/layout"))).await;
+    assert_eq!(after["room_ids"], serde_json::json!([general, staff, lobby]));
+    assert_eq!(after["categories"][0]["position"], 0);
+    let (_, rooms) = call_json(&app, admin.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert_eq!(rooms[2]["position"], 2);
+}
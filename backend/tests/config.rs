@@ -0,0 +1,29 @@
+use backend::config::Settings;
+
+#[test]
+fn settings_load_from_yaml_and_validate() {
+    let dir = std::env::temp_dir().join(format!("voxium-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("voxium.yaml");
+
+    std::fs::write(&path, "database:\n  max_connections: 4\nsecrets:\n  jwt_secret: short\n").unwrap();
+    let settings = Settings::load(Some(&path)).unwrap();
+    assert_eq!(settings.database.max_connections, 4);
+    assert_eq!(settings.server.bind_address, "0.0.0.0");
+    let issues = settings.validate();
+    assert!(issues.iter().all(|i| !i.fatal), "{issues:?}");
+    assert!(issues.iter().any(|i| i.field == "secrets.jwt_secret"));
+
+    std::fs::write(&path, "server:\n  public_base_url: ftp://example.com\ndatabase:\n  url: postgres://db\n").unwrap();
+    let fatal: Vec<&str> = Settings::load(Some(&path)).unwrap().validate().into_iter().filter(|i| i.fatal).map(|i| i.field).collect();
+    assert!(fatal.contains(&"server.public_base_url"));
+    assert!(fatal.contains(&"database.url"));
+
+    std::fs::write(&path, "limits:\n  pins_per_room: 0\n").unwrap();
+    let fatal: Vec<&str> = Settings::load(Some(&path)).unwrap().validate().into_iter().filter(|i| i.fatal).map(|i| i.field).collect();
+    assert!(fatal.contains(&"limits.pins_per_room"));
+
+    std::fs::write(&path, "server:\n  prot: 8080\n").unwrap();
+    assert!(Settings::load(Some(&path)).is_err());
+    std::fs::remove_dir_all(&dir).ok();
+}
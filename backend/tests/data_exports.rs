@@ -0,0 +1,82 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::data_exports::process_exports;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, storage_dir, test_state};
+
+fn upload(bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::post()
+        .uri("/api/files")
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn exports_are_built_downloaded_and_expired() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "general-chat", "user").await;
+    create_message(&state.pool, &room, &alice, "hello from alice").await;
+    create_message(&state.pool, &room, &bob, "hello from bob").await;
+    let (status, uploaded) = call_json(&app, alice.sign(upload(b"meeting notes"))).await;
+    assert_eq!(status, StatusCode::CREATED, "{uploaded}");
+
+    let export = || TestRequest::post().uri("/api/users/@me/export");
+    let (status, refused) = call_json(&app, alice.sign(export())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["sudo_required"], true);
+    let (status, queued) = call_json(&app, alice.sign_sudo(export())).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(queued["status"], "pending");
+    let (status, _) = call_json(&app, alice.sign_sudo(export())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    assert_eq!(process_exports(&state.pool, &state.file_storage).await, Ok(1));
+    let uri = format!("/api/users/@me/exports/{}", queued["id"].as_str().unwrap());
+    let (status, ready) = call_json(&app, alice.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ready["status"], "ready");
+    assert_eq!(ready["progress"], 100);
+    let (status, _) = call_json(&app, bob.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let download = ready["download_url"].as_str().unwrap().to_string();
+    let res = test::call_service(&app, alice.sign(TestRequest::get().uri(&download)).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let archive = test::read_body(res).await;
+    assert_eq!(archive.len() as i64, ready["size_bytes"].as_i64().unwrap());
+    assert!(archive.starts_with(b"PK\x03\x04"));
+    // End of central directory: 7 entries, one of them the upload.
+    let end = &archive[archive.len() - 22..];
+    assert!(end.starts_with(b"PK\x05\x06"));
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 7);
+    let contains = |needle: &[u8]| archive.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"hello from alice"));
+    assert!(!contains(b"hello from bob"));
+    assert!(contains(b"meeting notes"));
+    assert!(!contains(b"password_hash"));
+
+    // One a day, and gone once expired.
+    let (status, _) = call_json(&app, alice.sign_sudo(export())).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    sqlx::query("UPDATE data_exports SET expires_at = ?")
+        .bind((chrono::Utc::now() - chrono::Duration::minutes(1)).to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri(&download))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(process_exports(&state.pool, &state.file_storage).await, Ok(1));
+    let (_, listed) = call_json(&app, alice.sign(TestRequest::get().uri("/api/users/@me/exports"))).await;
+    assert_eq!(listed["exports"][0]["status"], "expired");
+    assert!(listed["exports"][0]["download_url"].is_null());
+    std::fs::remove_dir_all(storage_dir(&state)).ok();
+}
@@ -0,0 +1,141 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use backend::discord_import::process_imports;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static RATE_LIMITED_ONCE: AtomicBool = AtomicBool::new(false);
+
+/// Enough of Discord's API for one guild, `900`.
+async fn fake_discord(req: HttpRequest) -> HttpResponse {
+    if req.headers().get("Authorization").and_then(|v| v.to_str().ok()) != Some("discord-user-token") {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let author = json!({ "id": "700", "username": "ann", "global_name": "Ann", "avatar": null });
+    let body = match req.path() {
+        "/guilds/900" => json!({ "id": "900", "name": "Old friends" }),
+        "/guilds/900/roles" => json!([
+            { "id": "900", "name": "@everyone", "position": 0, "permissions": "3072" },
+            // VIEW_CHANNEL, SEND_MESSAGES and MANAGE_MESSAGES
+            { "id": "901", "name": "Moderators", "color": 43520, "hoist": true, "position": 2, "permissions": "11264" },
+            { "id": "902", "name": "Some Bot", "managed": true, "position": 1, "permissions": "8" },
+        ]),
+        "/guilds/900/channels" => json!([
+            { "id": "912", "type": 2, "name": "Lounge", "position": 0, "parent_id": "910" },
+            { "id": "913", "type": 5, "name": "news", "position": 1, "parent_id": null },
+            { "id": "910", "type": 4, "name": "Text Channels", "position": 0 },
+            { "id": "911", "type": 0, "name": "general", "position": 0, "parent_id": "910", "permission_overwrites": [
+                { "id": "900", "type": 0, "allow": "0", "deny": "1024" },
+                { "id": "901", "type": 0, "allow": "1024", "deny": "0" },
+            ] },
+        ]),
+        "/channels/911/pins" => json!([
+            { "id": "802", "type": 0, "content": "second pin", "author": author },
+            { "id": "801", "type": 0, "content": "first pin", "author": author },
+        ]),
+        "/channels/913/pins" if !RATE_LIMITED_ONCE.swap(true, Ordering::SeqCst) => {
+            return HttpResponse::TooManyRequests().json(json!({ "retry_after": 1.0 }));
+        }
+        "/channels/913/pins" => json!([]),
+        _ => return HttpResponse::NotFound().finish(),
+    };
+    HttpResponse::Ok().json(body)
+}
+
+#[actix_web::test]
+async fn a_discord_server_is_imported_and_resumed_after_a_rate_limit() {
+    std::env::set_var("ENCRYPTION_KEY", "discord-import-test-key");
+    let server = HttpServer::new(|| App::new().default_service(web::to(fake_discord)))
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let address = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    std::env::set_var("DISCORD_API_BASE_URL", format!("http://{address}"));
+
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    create_room(&state.pool, "general", "user").await;
+
+    let imports = "/api/server/discord-imports";
+    let start = || TestRequest::post().uri(imports).set_json(json!({ "guild_id": "900", "include_pins": true }));
+    let (status, _) = call_json(&app, alice.sign(start())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(start())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    sqlx::query("UPDATE users SET discord_id = '600', discord_access_token = ? WHERE id = ?")
+        .bind(backend::crypto::encrypt_token("discord-user-token"))
+        .bind(&admin.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let bad_id = TestRequest::post().uri(imports).set_json(json!({ "guild_id": "old-friends" }));
+    let (status, _) = call_json(&app, admin.sign(bad_id)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, queued) = call_json(&app, admin.sign(start())).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{queued}");
+    assert_eq!(queued["status"], "pending");
+    let (status, _) = call_json(&app, admin.sign(start())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Stopped by the rate limit on the second channel's pins, then resumed.
+    let uri = format!("{imports}/{}", queued["id"].as_str().unwrap());
+    assert_eq!(process_imports(&state.pool, &state.broadcaster).await, Ok(0));
+    let (_, paused) = call_json(&app, admin.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(paused["status"], "pending");
+    assert_eq!(paused["stage"], "pins");
+    assert_eq!(process_imports(&state.pool, &state.broadcaster).await, Ok(1));
+
+    let (status, done) = call_json(&app, admin.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(done["status"], "done", "{done}");
+    assert_eq!(done["progress"], 100);
+    assert_eq!(done["guild_name"], "Old friends");
+    assert_eq!(done["imported"], json!({ "roles": 1, "categories": 1, "rooms": 3, "pins": 2 }));
+    let mappings = done["mappings"].as_array().unwrap();
+    let find = |kind: &str, discord_id: &str| {
+        mappings
+            .iter()
+            .find(|m| m["kind"] == kind && m["discord_id"] == discord_id)
+            .map(|m| m["voxium_id"].as_str().unwrap().to_string())
+            .unwrap()
+    };
+    assert_eq!(find("role", "901"), "moderators");
+    let general = find("room", "911");
+    let category = find("category", "910");
+
+    let (_, roles) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/roles"))).await;
+    let moderators = roles.as_array().unwrap().iter().find(|r| r["name"] == "moderators").unwrap();
+    assert_eq!(moderators["color"], "#00aa00");
+    assert_eq!(moderators["hoist"], true);
+    assert_eq!(moderators["permissions"], 1 | 2 | 32);
+
+    let (_, layout) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/layout"))).await;
+    let imported = layout["categories"].as_array().unwrap().iter().find(|c| c["id"] == category.as_str()).unwrap();
+    assert_eq!(imported["name"], "Text Channels");
+    assert_eq!(imported["room_ids"], json!([general, find("room", "912")]));
+    let name: String = sqlx::query_scalar("SELECT name FROM rooms WHERE id = ?").bind(&general).fetch_one(&state.pool).await.unwrap();
+    assert_eq!(name, "general-2");
+
+    // `@everyone` could not see the channel, so members can't see the room.
+    let pins = format!("/api/rooms/{general}/pins");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri(&pins))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, pinned) = call_json(&app, admin.sign(TestRequest::get().uri(&pins))).await;
+    assert_eq!(status, StatusCode::OK);
+    let mut contents: Vec<&str> = pinned.as_array().unwrap().iter().map(|m| m["content"].as_str().unwrap()).collect();
+    contents.sort();
+    assert_eq!(contents, ["first pin", "second pin"]);
+    assert_eq!(pinned[0]["username"], "Ann");
+    let (_, webhooks) = call_json(&app, admin.sign(TestRequest::get().uri(&format!("/api/rooms/{general}/webhooks")))).await;
+    assert_eq!(webhooks.as_array().unwrap().len(), 0);
+
+    let (_, listed) = call_json(&app, admin.sign(TestRequest::get().uri(imports))).await;
+    assert_eq!(listed["imports"].as_array().unwrap().len(), 1);
+    assert!(listed["imports"][0].get("mappings").is_none());
+}
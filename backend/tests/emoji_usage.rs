@@ -0,0 +1,69 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+
+async fn create_emoji(pool: &sqlx::SqlitePool, name: &str) -> String {
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO custom_emojis (id, name, image_url, created_at) VALUES (?, ?, ?, '2026-01-01T00:00:00+00:00')")
+        .bind(&id)
+        .bind(name)
+        .bind(format!("/uploads/emojis/{name}.png"))
+        .execute(pool)
+        .await
+        .unwrap();
+    id
+}
+
+#[test]
+fn unicode_emoji_sequences_are_counted_whole() {
+    let found = backend::emoji_usage::unicode_emojis("hi 👍🏽 and 👨‍👩‍👧 in 🇫🇷, again 👍🏽 ❤️ → ok");
+    assert_eq!(found, ["👍🏽", "👨‍👩‍👧", "🇫🇷", "❤️"]);
+}
+
+#[actix_web::test]
+async fn reactions_rank_the_top_emoji_and_the_picker() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let messages = [
+        create_message(&state.pool, &room, &alice, "one").await,
+        create_message(&state.pool, &room, &alice, "two").await,
+    ];
+    let apple = create_emoji(&state.pool, "apple").await;
+    let zebra = create_emoji(&state.pool, "zebra").await;
+    let react = |message: &str, body: serde_json::Value| {
+        TestRequest::post().uri(&format!("/api/messages/{message}/reactions")).set_json(body)
+    };
+
+    for message in &messages {
+        for user in [&admin, &alice] {
+            call_json(&app, user.sign(react(message, serde_json::json!({ "emoji_id": zebra })))).await;
+        }
+        call_json(&app, alice.sign(react(message, serde_json::json!({ "emoji": "🎉" })))).await;
+    }
+    // Reacting twice with the same emoji counts once.
+    call_json(&app, alice.sign(react(&messages[0], serde_json::json!({ "emoji": "🎉" })))).await;
+
+    let (status, body) = call_json(&app, alice.sign(TestRequest::get().uri("/api/emojis/top"))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let top = body["emojis"].as_array().unwrap();
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0]["emoji"], format!("custom:{zebra}"));
+    assert_eq!(top[0]["custom_emoji"]["name"], "zebra");
+    assert_eq!(top[0]["reactions"], 4);
+    assert_eq!(top[1]["emoji"], "🎉");
+    assert_eq!(top[1]["total"], 2);
+    assert_eq!(top[1]["custom_emoji"], serde_json::Value::Null);
+
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri("/api/emojis"))).await;
+    let names: Vec<&str> = body.as_array().unwrap().iter().map(|e| e["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["zebra", "apple"], "{apple} is never used");
+
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&format!("/api/server/emojis/{zebra}")))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri("/api/emojis/top?limit=5"))).await;
+    let keys: Vec<&str> = body["emojis"].as_array().unwrap().iter().map(|e| e["emoji"].as_str().unwrap()).collect();
+    assert_eq!(keys, ["🎉"]);
+}
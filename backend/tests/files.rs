@@ -0,0 +1,50 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::files::file_storage_from;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn s3_downloads_redirect_to_presigned_urls() {
+    // Nothing listens there: a redirected download never reaches the bucket.
+    let settings = [
+        ("FILE_STORAGE", "s3"),
+        ("S3_ENDPOINT", "http://127.0.0.1:9"),
+        ("S3_BUCKET", "voxium"),
+        ("S3_ACCESS_KEY_ID", "access"),
+        ("S3_SECRET_ACCESS_KEY", "secret"),
+        ("S3_PRESIGNED_DOWNLOADS", "true"),
+        ("S3_PUBLIC_ENDPOINT", "https://files.example.org/"),
+    ];
+    let mut state = test_state().await;
+    state.file_storage =
+        file_storage_from(|name| settings.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()));
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let message = create_message(&state.pool, &room, &alice, "the report").await;
+    let hash = "ab".repeat(32);
+    sqlx::query("INSERT INTO files (hash, size, content_type, created_at) VALUES (?, 10, 'application/pdf', '2026-01-01T00:00:00+00:00')")
+        .bind(&hash)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO attachments (id, file_hash, uploader_id, message_id, filename, created_at) VALUES ('att', ?, ?, ?, 'report.pdf', '2026-01-01T00:00:00+00:00')")
+        .bind(&hash)
+        .bind(&alice.id)
+        .bind(&message)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let (_, history) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/messages")))).await;
+    let url = history["messages"][0]["attachments"][0]["url"].as_str().unwrap().to_string();
+    let res = test::call_service(&app, TestRequest::get().uri(&url).to_request()).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    assert!(location.starts_with(&format!("https://files.example.org/voxium/ab/ab/{hash}?X-Amz-Algorithm=AWS4-HMAC-SHA256&")), "{location}");
+    assert!(location.contains("&response-content-type=application%2Fpdf&X-Amz-Signature="), "{location}");
+    assert!(location.contains("response-content-disposition=attachment"), "{location}");
+
+    let (status, _) = call_json(&app, TestRequest::get().uri(&url.replace("variant=original", "variant=thumbnail"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
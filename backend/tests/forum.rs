@@ -0,0 +1,78 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, call_page, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn forum_posts_are_tagged_voted_and_sorted() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let forum = create_room(&state.pool, "help-desk", "user").await;
+    sqlx::query("UPDATE rooms SET kind = 'forum' WHERE id = ?")
+        .bind(&forum)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let tags = TestRequest::put()
+        .uri(&format!("/api/rooms/{forum}/forum-tags"))
+        .set_json(serde_json::json!({ "tags": ["Help", "bug", "help"] }));
+    let (status, body) = call_json(&app, admin.sign(tags)).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["tags"], serde_json::json!(["bug", "help"]));
+
+    let open = |title: &str, tags: serde_json::Value| {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{forum}/posts"))
+            .set_json(serde_json::json!({ "title": title, "content": "Details inside.", "tags": tags }))
+    };
+    let (status, unknown) = call_json(&app, alice.sign(open("Crash", serde_json::json!(["feature"])))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(unknown["tags"], serde_json::json!(["bug", "help"]));
+    let (status, crash) = call_json(&app, alice.sign(open("Crash on start", serde_json::json!(["BUG"])))).await;
+    assert_eq!(status, StatusCode::CREATED, "{crash}");
+    assert_eq!(crash["tags"], serde_json::json!(["bug"]));
+    let (_, setup) = call_json(&app, bob.sign(open("How to set up voice?", serde_json::json!(["help"])))).await;
+    let (crash_id, setup_id) = (crash["id"].as_str().unwrap(), setup["id"].as_str().unwrap());
+
+    // Reactions to the opening message are votes, one per user.
+    for (user, emoji) in [(&bob, "👍"), (&admin, "👍"), (&admin, "🔥")] {
+        let react = TestRequest::post()
+            .uri(&format!("/api/messages/{crash_id}/reactions"))
+            .set_json(serde_json::json!({ "emoji": emoji }));
+        let (status, _) = call_json(&app, user.sign(react)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let posts = |query: &str| TestRequest::get().uri(&format!("/api/rooms/{forum}/posts?{query}"));
+    let (_, by_votes, _) = call_page(&app, alice.sign(posts("sort=-votes"))).await;
+    assert_eq!(by_votes[0]["id"], crash_id);
+    assert_eq!(by_votes[0]["vote_count"], 2);
+    let (_, latest, next) = call_page(&app, alice.sign(posts("limit=1"))).await;
+    assert_eq!(latest[0]["id"], setup_id);
+    let (_, rest, _) = call_page(&app, alice.sign(posts(&format!("limit=1&cursor={}", next.unwrap())))).await;
+    assert_eq!(rest[0]["id"], crash_id);
+    let (_, tagged, _) = call_page(&app, alice.sign(posts("tag=help"))).await;
+    assert_eq!(tagged.as_array().unwrap().len(), 1);
+    assert_eq!(tagged[0]["id"], setup_id);
+
+    let (_, thread) = call_json(&app, bob.sign(TestRequest::get().uri(&format!("/api/posts/{crash_id}/messages")))).await;
+    assert_eq!(thread["messages"][0]["post_id"], crash_id);
+    assert_eq!(thread["messages"][0]["content"], "Details inside.");
+
+    let retitle = |title: &str| TestRequest::patch().uri(&format!("/api/posts/{crash_id}")).set_json(serde_json::json!({ "title": title }));
+    let (status, _) = call_json(&app, bob.sign(retitle("Mine now"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, renamed) = call_json(&app, alice.sign(retitle("Crash on start (Windows)"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(renamed["tags"], serde_json::json!(["bug"]));
+
+    // Dropping a tag from the forum takes it off its posts.
+    let tags = TestRequest::put()
+        .uri(&format!("/api/rooms/{forum}/forum-tags"))
+        .set_json(serde_json::json!({ "tags": ["help"] }));
+    call_json(&app, admin.sign(tags)).await;
+    let (_, post) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/posts/{crash_id}")))).await;
+    assert_eq!(post["tags"], serde_json::json!([]));
+}
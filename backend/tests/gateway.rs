@@ -0,0 +1,155 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_user, init_app, start_server, test_state};
+use chrono::{Duration, Utc};
+use futures_util::{SinkExt, StreamExt};
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A gateway connection, past `hello`.
+async fn connect(addr: SocketAddr) -> Client {
+    let (mut ws, _) = connect_async(format!("ws://{addr}/api/gateway")).await.unwrap();
+    assert_eq!(recv(&mut ws).await["op"], "hello");
+    ws
+}
+
+async fn send(ws: &mut Client, op: &str, d: serde_json::Value) {
+    ws.send(Message::Text(serde_json::json!({ "op": op, "d": d }).to_string())).await.unwrap();
+}
+
+/// The next frame, as JSON.
+async fn recv(ws: &mut Client) -> serde_json::Value {
+    loop {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+            .await
+            .expect("a frame in time")
+            .expect("connection open")
+            .unwrap();
+        match message {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Close(reason) => panic!("closed: {reason:?}"),
+            _ => {}
+        }
+    }
+}
+
+/// The close code the server ends the connection with, skipping frames before it.
+async fn close_code(ws: &mut Client) -> u16 {
+    loop {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+            .await
+            .expect("closed in time")
+            .expect("a close frame")
+            .unwrap();
+        if let Message::Close(reason) = message {
+            return reason.map(|r| u16::from(r.code)).unwrap_or_default();
+        }
+    }
+}
+
+#[actix_web::test]
+async fn identified_sessions_get_numbered_dispatches_and_resume_on_a_new_connection() {
+    let state = test_state().await;
+    let addr = start_server(&state);
+    let alice = create_user(&state.pool, "alice", "user").await;
+
+    let mut ws = connect(addr).await;
+    send(&mut ws, "identify", serde_json::json!({ "token": "nope" })).await;
+    assert_eq!(close_code(&mut ws).await, 4004);
+
+    let mut first = connect(addr).await;
+    send(&mut first, "identify", serde_json::json!({ "token": alice.token })).await;
+    let ready = recv(&mut first).await;
+    assert_eq!(ready["op"], "ready");
+    assert_eq!(ready["d"]["user_id"], alice.id.as_str());
+    assert_eq!(ready["d"]["rooms"], serde_json::json!(["*"]));
+    let session_id = ready["d"]["session_id"].as_str().unwrap().to_string();
+    // Coming online is the first event.
+    let online = recv(&mut first).await;
+    assert_eq!((online["t"].as_str(), online["s"].as_u64()), (Some("presence"), Some(1)));
+    assert_eq!(online["d"]["user_id"], alice.id.as_str());
+
+    let _ = state.broadcaster.send(serde_json::json!({ "type": "server_updated", "name": "Voxium" }).to_string());
+    let dispatch = recv(&mut first).await;
+    assert_eq!((dispatch["op"].as_str(), dispatch["t"].as_str(), dispatch["s"].as_u64()), (Some("dispatch"), Some("server_updated"), Some(2)));
+    assert_eq!(dispatch["d"]["name"], "Voxium");
+
+    send(&mut first, "heartbeat", serde_json::json!({ "seq": 2 })).await;
+    assert_eq!(recv(&mut first).await, serde_json::json!({ "op": "heartbeat_ack", "d": { "seq": 2 } }));
+    send(&mut first, "identify", serde_json::json!({ "token": alice.token })).await;
+    assert_eq!(close_code(&mut first).await, 4005);
+
+    // Detached now, the session can be resumed with what was missed.
+    let mut second = connect(addr).await;
+    send(&mut second, "resume", serde_json::json!({ "token": alice.token, "session_id": session_id, "seq": 1 })).await;
+    let replayed = recv(&mut second).await;
+    assert_eq!((replayed["s"].as_u64(), replayed["d"]["name"].as_str()), (Some(2), Some("Voxium")));
+    assert_eq!(recv(&mut second).await, serde_json::json!({ "op": "resumed", "d": { "replayed": 1 } }));
+
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let mut stranger = connect(addr).await;
+    send(&mut stranger, "resume", serde_json::json!({ "token": bob.token, "session_id": session_id, "seq": 2 })).await;
+    assert_eq!(recv(&mut stranger).await, serde_json::json!({ "op": "invalid_session", "d": { "resumable": false } }));
+    assert_eq!(close_code(&mut stranger).await, 4007);
+}
+
+#[actix_web::test]
+async fn resuming_elsewhere_closes_the_connection_the_session_had() {
+    let state = test_state().await;
+    let addr = start_server(&state);
+    let alice = create_user(&state.pool, "alice", "user").await;
+
+    let mut first = connect(addr).await;
+    send(&mut first, "identify", serde_json::json!({ "token": alice.token })).await;
+    let session_id = recv(&mut first).await["d"]["session_id"].as_str().unwrap().to_string();
+    assert_eq!(recv(&mut first).await["t"], "presence");
+
+    let mut second = connect(addr).await;
+    send(&mut second, "resume", serde_json::json!({ "token": alice.token, "session_id": session_id, "seq": 1 })).await;
+    assert_eq!(recv(&mut second).await, serde_json::json!({ "op": "resumed", "d": { "replayed": 0 } }));
+    assert_eq!(close_code(&mut first).await, 4007);
+
+    // Events follow the session to the new connection.
+    let _ = state.broadcaster.send(serde_json::json!({ "type": "server_updated" }).to_string());
+    assert_eq!(recv(&mut second).await["s"], 2);
+    assert_eq!(state.presence.lock().unwrap().get(&alice.id), Some(&1));
+}
+
+#[actix_web::test]
+async fn a_revoked_session_is_closed_on_its_next_heartbeat() {
+    let state = test_state().await;
+    let addr = start_server(&state);
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let now = Utc::now();
+    sqlx::query("INSERT INTO sessions (id, user_id, created_at, last_seen_at, expires_at) VALUES ('phone', ?, ?, ?, ?)")
+        .bind(&alice.id)
+        .bind(now.to_rfc3339())
+        .bind(now.to_rfc3339())
+        .bind((now + Duration::days(30)).to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let token = backend::auth::create_token(&alice.id, &alice.username, &alice.role, "phone");
+
+    let mut ws = connect(addr).await;
+    send(&mut ws, "identify", serde_json::json!({ "token": token })).await;
+    let session_id = recv(&mut ws).await["d"]["session_id"].as_str().unwrap().to_string();
+    assert_eq!(recv(&mut ws).await["t"], "presence");
+    send(&mut ws, "heartbeat", serde_json::json!({ "seq": 1 })).await;
+    assert_eq!(recv(&mut ws).await["op"], "heartbeat_ack");
+
+    let (status, body) = call_json(&app, alice.sign_sudo(TestRequest::delete().uri("/api/users/@me/sessions/phone"))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    send(&mut ws, "heartbeat", serde_json::json!({ "seq": 1 })).await;
+    assert_eq!(close_code(&mut ws).await, 4004);
+
+    // The gateway session ended with it.
+    let mut again = connect(addr).await;
+    send(&mut again, "resume", serde_json::json!({ "token": alice.token, "session_id": session_id, "seq": 1 })).await;
+    assert_eq!(recv(&mut again).await["op"], "invalid_session");
+}
@@ -0,0 +1,128 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state, TestUser};
+
+fn guest_from(body: &serde_json::Value) -> TestUser {
+    TestUser {
+        id: body["user_id"].as_str().unwrap().to_string(),
+        username: body["username"].as_str().unwrap().to_string(),
+        role: body["role"].as_str().unwrap().to_string(),
+        token: body["token"].as_str().unwrap().to_string(),
+    }
+}
+
+fn redeem(token: &serde_json::Value) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/guest/redeem")
+        .set_json(serde_json::json!({ "token": token, "display_name": "Visitor" }))
+}
+
+#[actix_web::test]
+async fn guests_only_see_the_room_of_their_link() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    let other = create_room(&state.pool, "other", "user").await;
+    create_message(&state.pool, &lobby, &alice, "welcome").await;
+
+    let input = serde_json::json!({ "max_uses": 1 });
+    let uri = format!("/api/rooms/{lobby}/guest-links");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri(&uri).set_json(&input))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, link) = call_json(&app, admin.sign(TestRequest::post().uri(&uri).set_json(&input))).await;
+    assert_eq!(status, StatusCode::CREATED, "{link}");
+    assert_eq!(link["can_post"], false);
+
+    let (status, body) = call_json(&app, redeem(&link["token"])).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["role"], "guest");
+    assert_eq!(body["guest"]["room_id"], lobby.as_str());
+    let guest = guest_from(&body);
+
+    // Used up.
+    let (status, _) = call_json(&app, redeem(&link["token"])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let history = |room: &str| TestRequest::get().uri(&format!("/api/rooms/{room}/messages"));
+    let (status, page) = call_json(&app, guest.sign(history(&lobby))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["messages"][0]["content"], "welcome");
+    let (status, _) = call_json(&app, guest.sign(history(&other))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, guest.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, access) = call_json(&app, guest.sign(TestRequest::get().uri("/api/guest/me"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(access["room_name"], "lobby");
+}
+
+#[actix_web::test]
+async fn revoking_a_link_ends_its_guests_access() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+
+    let uri = format!("/api/rooms/{lobby}/guest-links");
+    let (_, link) = call_json(&app, admin.sign(TestRequest::post().uri(&uri).set_json(serde_json::json!({})))).await;
+    let (_, body) = call_json(&app, redeem(&link["token"])).await;
+
+    let revoke = TestRequest::delete().uri(&format!("/api/guest-links/{}", link["id"].as_str().unwrap()));
+    let (status, revoked) = call_json(&app, admin.sign(revoke)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(revoked["guests_removed"], 1);
+
+    // Their sessions are revoked, and their grant is gone for any session left.
+    let (status, _) = call_json(&app, guest_from(&body).sign(TestRequest::get().uri(&format!("/api/rooms/{lobby}/messages")))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let refresh = TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": body["refresh_token"] }));
+    let (status, _) = call_json(&app, refresh).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call_json(&app, redeem(&link["token"])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn converting_keeps_the_guests_messages() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+
+    let uri = format!("/api/rooms/{lobby}/guest-links");
+    let input = serde_json::json!({ "can_post": true, "expires_in_hours": 2 });
+    let (_, link) = call_json(&app, admin.sign(TestRequest::post().uri(&uri).set_json(input))).await;
+    let (_, body) = call_json(&app, redeem(&link["token"])).await;
+    let guest = guest_from(&body);
+    let message_id = create_message(&state.pool, &lobby, &guest, "hello from a guest").await;
+
+    let convert = |username: &str| {
+        TestRequest::post()
+            .uri("/api/guest/convert")
+            .set_json(serde_json::json!({ "username": username, "password": "correct horse" }))
+    };
+    let (status, _) = call_json(&app, guest.sign(convert("root"))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, account) = call_json(&app, guest.sign(convert("visitor"))).await;
+    assert_eq!(status, StatusCode::OK, "{account}");
+    assert_eq!(account["user_id"], guest.id.as_str());
+    assert_eq!(account["role"], "user");
+
+    let (status, _) = call_json(&app, guest.sign(TestRequest::get().uri("/api/guest/me"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let member = guest_from(&account);
+    let (status, _) = call_json(&app, member.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let author: String = sqlx::query_scalar("SELECT user_id FROM messages WHERE id = ?")
+        .bind(&message_id)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(author, guest.id);
+}
@@ -0,0 +1,107 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::test_support::{call_json, create_user, init_app, storage_dir, test_state};
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(width, height, image::Rgba([200, 40, 90, 255])));
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+fn upload(uri: &str, bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"image.png\"\r\nContent-Type: image/png\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::put()
+        .uri(uri)
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn avatars_and_banners_are_resized_moderated_and_served() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+
+    let (status, _) = call_json(&app, alice.sign(upload("/api/users/@me/avatar", &png(100, 100)))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, alice.sign(upload("/api/users/@me/avatar", b"not an image"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, avatar) = call_json(&app, alice.sign(upload("/api/users/@me/avatar", &png(300, 200)))).await;
+    assert_eq!(status, StatusCode::OK, "{avatar}");
+    let widths: Vec<u64> = avatar["sizes"].as_array().unwrap().iter().map(|s| s["width"].as_u64().unwrap()).collect();
+    assert_eq!(widths, [64, 128]);
+    let avatar_url: Option<String> = sqlx::query_scalar("SELECT avatar_url FROM users WHERE id = ?")
+        .bind(&alice.id)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(avatar_url.as_deref(), avatar["sizes"][1]["url"].as_str());
+
+    let res = test::call_service(&app, TestRequest::get().uri(avatar_url.as_deref().unwrap()).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let served = image::load_from_memory(&test::read_body(res).await).unwrap();
+    assert_eq!((served.width(), served.height()), (128, 128));
+
+    // A new upload replaces the asset; the old sizes are gone.
+    let (_, replaced) = call_json(&app, alice.sign(upload("/api/users/@me/avatar", &png(600, 600)))).await;
+    assert_eq!(replaced["sizes"].as_array().unwrap().len(), 4);
+    let (status, _) = call_json(&app, TestRequest::get().uri(avatar_url.as_deref().unwrap())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Profile banners are 3:1; the profile only takes URLs the uploads minted.
+    let (status, profile_banner) = call_json(&app, alice.sign(upload("/api/users/@me/banner", &png(1500, 600)))).await;
+    assert_eq!(status, StatusCode::OK, "{profile_banner}");
+    assert_eq!(profile_banner["sizes"][1]["width"], 1200);
+    assert_eq!(profile_banner["sizes"][1]["height"], 400);
+    let profile = |body: serde_json::Value| TestRequest::patch().uri("/api/users/me").set_json(body);
+    let (status, _) = call_json(&app, alice.sign(profile(serde_json::json!({ "banner_url": "https://evil.example/x.png" })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, alice.sign(profile(serde_json::json!({ "avatar_url": avatar_url })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, admin.sign(profile(serde_json::json!({ "avatar_url": replaced["sizes"][0]["url"] })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, alice.sign(profile(serde_json::json!({ "avatar_url": replaced["sizes"][3]["url"] })))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call_json(&app, alice.sign(TestRequest::delete().uri("/api/users/@me/banner"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let banner_url: Option<String> = sqlx::query_scalar("SELECT banner_url FROM users WHERE id = ?")
+        .bind(&alice.id)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert!(banner_url.is_none());
+
+    let (status, _) = call_json(&app, alice.sign(upload("/api/server/banner", &png(1280, 720)))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(upload("/api/server/banner", &png(800, 600)))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, banner) = call_json(&app, admin.sign(upload("/api/server/banner", &png(1280, 1280)))).await;
+    assert_eq!(status, StatusCode::OK, "{banner}");
+    assert_eq!(banner["sizes"][1]["width"], 960);
+    assert_eq!(banner["sizes"][1]["height"], 540);
+    let (_, current) = call_json(&app, alice.sign(TestRequest::get().uri("/api/server/banner"))).await;
+    assert_eq!(current["banner"]["id"], banner["id"]);
+
+    // Nothing listens there: uploads wait for a moderation verdict.
+    std::env::set_var("IMAGE_MODERATION_URL", "http://127.0.0.1:9/check");
+    let (status, _) = call_json(&app, admin.sign(upload("/api/server/banner", &png(1280, 720)))).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    std::env::remove_var("IMAGE_MODERATION_URL");
+
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri("/api/server/banner"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, current) = call_json(&app, alice.sign(TestRequest::get().uri("/api/server/banner"))).await;
+    assert!(current["banner"].is_null());
+    let (status, _) = call_json(&app, alice.sign(TestRequest::delete().uri("/api/users/@me/avatar"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    std::fs::remove_dir_all(storage_dir(&state)).ok();
+}
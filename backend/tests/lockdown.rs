@@ -0,0 +1,76 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn lockdown_restricts_posting_until_lifted() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    sqlx::query("INSERT INTO roles (name, color) VALUES ('veteran', '#ffffff')")
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "veteran").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    let quiet = create_room(&state.pool, "quiet", "user").await;
+
+    let (_, link) = call_json(
+        &app,
+        admin.sign(TestRequest::post().uri(&format!("/api/rooms/{lobby}/guest-links")).set_json(serde_json::json!({}))),
+    )
+    .await;
+
+    let start = || {
+        TestRequest::post().uri("/api/server/lockdown").set_json(serde_json::json!({
+            "room_ids": [lobby],
+            "slowmode_seconds": 60,
+            "duration_minutes": 10,
+            "trusted_roles": ["veteran"],
+            "reason": "raid",
+        }))
+    };
+    let (status, _) = call_json(&app, alice.sign(start())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, lockdown) = call_json(&app, admin.sign(start())).await;
+    assert_eq!(status, StatusCode::CREATED, "{lockdown}");
+    assert_eq!(lockdown["room_ids"], serde_json::json!([lobby]));
+    let (status, _) = call_json(&app, admin.sign(start())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let poll = |room: &str| {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{room}/polls"))
+            .set_json(serde_json::json!({ "question": "Lunch?", "options": ["yes", "no"] }))
+    };
+    let (status, refused) = call_json(&app, alice.sign(poll(&lobby))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["lockdown_ends_at"], lockdown["ends_at"]);
+    let (status, body) = call_json(&app, bob.sign(poll(&lobby))).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let (status, _) = call_json(&app, alice.sign(poll(&quiet))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let redeem = TestRequest::post().uri("/api/guest/redeem").set_json(serde_json::json!({ "token": link["token"] }));
+    let (status, _) = call_json(&app, redeem).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (_, active) = call_json(&app, alice.sign(TestRequest::get().uri("/api/server/lockdown"))).await;
+    assert_eq!(active["lockdown"]["id"], lockdown["id"]);
+
+    // Lifting gives the room its slowmode back and logs who did it.
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri("/api/server/lockdown"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let slowmode: i64 = sqlx::query_scalar("SELECT slowmode_seconds FROM rooms WHERE id = ?")
+        .bind(&lobby)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(slowmode, 0);
+    let (status, _) = call_json(&app, alice.sign(poll(&lobby))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, log) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/lockdowns"))).await;
+    assert_eq!(log[0]["ended_by_username"], "root");
+    assert_eq!(log[0]["reason"], "raid");
+}
@@ -0,0 +1,117 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+/// A homeserver serving `cat123` as a PNG and `page` as HTML, both labelled `text/html`.
+fn fake_homeserver() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let media = |path: web::Path<(String, String)>| async move {
+        let body: &[u8] = match path.1.as_str() {
+            "cat123" => b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+            "page" => b"<html><script>alert(document.cookie)</script></html>",
+            _ => return HttpResponse::NotFound().finish(),
+        };
+        HttpResponse::Ok().content_type("text/html").body(body)
+    };
+    let server = HttpServer::new(move || App::new().route("/_matrix/client/v1/media/download/{server}/{id}", web::get().to(media)))
+        .workers(1)
+        .disable_signals()
+        .listen(listener)
+        .unwrap()
+        .run();
+    actix_web::rt::spawn(server);
+    format!("http://{addr}")
+}
+
+#[actix_web::test]
+async fn linked_rooms_relay_matrix_transactions_through_puppets() {
+    // Serves media only: profiles are best effort.
+    std::env::set_var("MATRIX_HOMESERVER_URL", fake_homeserver());
+    std::env::set_var("MATRIX_SERVER_NAME", "example.org");
+    std::env::set_var("MATRIX_AS_TOKEN", "as-secret");
+    std::env::set_var("MATRIX_HS_TOKEN", "hs-secret");
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let room = create_room(&state.pool, "general", "user").await;
+
+    let plan = |yaml: &str| TestRequest::post().uri("/api/server/config/plan").set_payload(yaml.to_string());
+    let (status, _) = call_json(&app, admin.sign(plan("version: 1\nbridges:\n  matrix:\n    - room: nowhere\n      matrix_room_id: \"!abc:example.org\"\n"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, planned) = call_json(&app, admin.sign(plan("version: 1\nbridges:\n  matrix:\n    - room: general\n      matrix_room_id: \"!abc:example.org\"\n"))).await;
+    assert_eq!(status, StatusCode::OK, "{planned}");
+    assert_eq!(planned["changes"][0]["resource"], "matrix_link");
+    assert_eq!(planned["changes"][0]["action"], "create");
+    sqlx::query("INSERT INTO matrix_room_links (room_id, matrix_room_id, created_at) VALUES (?, '!abc:example.org', '2026-01-01T00:00:00Z')")
+        .bind(&room)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let message = |event_id: &str, sender: &str, content: serde_json::Value| {
+        serde_json::json!({
+            "type": "m.room.message",
+            "room_id": "!abc:example.org",
+            "event_id": event_id,
+            "sender": sender,
+            "content": content,
+        })
+    };
+    let events = serde_json::json!({ "events": [
+        message("$1", "@bob:matrix.org", serde_json::json!({ "msgtype": "m.text", "body": "hello from Matrix" })),
+        message("$2", "@voxium_root:example.org", serde_json::json!({ "msgtype": "m.text", "body": "an echo" })),
+        message("$3", "@bob:matrix.org", serde_json::json!({ "msgtype": "m.image", "body": "cat.png", "url": "mxc://matrix.org/cat123" })),
+    ]});
+    let push = |txn: &str, token: Option<&str>, body: &serde_json::Value| {
+        let req = TestRequest::put().uri(&format!("/_matrix/app/v1/transactions/{txn}")).set_json(body);
+        match token {
+            Some(token) => req.insert_header(("Authorization", format!("Bearer {token}"))),
+            None => req,
+        }
+    };
+    let (status, _) = call_json(&app, push("t1", None, &events)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call_json(&app, push("t1", Some("as-secret"), &events)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, push("t1", Some("hs-secret"), &events)).await;
+    assert_eq!(status, StatusCode::OK);
+    // A retried transaction, and the same event in another one, are relayed once.
+    let (status, _) = call_json(&app, push("t1", Some("hs-secret"), &events)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call_json(&app, push("t2", Some("hs-secret"), &events)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, history) = call_json(&app, admin.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/messages")))).await;
+    let history = history["messages"].as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["username"], "bob");
+    assert_eq!(history[0]["content"], "hello from Matrix");
+    assert_eq!(history[1]["user_id"], history[0]["user_id"]);
+    let image = history[1]["embeds"][0]["image_url"].as_str().unwrap();
+    assert!(image.ends_with("/api/matrix/media/matrix.org/cat123"));
+
+    let (status, _) = call_json(&app, TestRequest::get().uri("/api/matrix/media/matrix.org/other")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // The homeserver's content type is ignored: an image plays, HTML is a download.
+    let res = test::call_service(&app, TestRequest::get().uri("/api/matrix/media/matrix.org/cat123").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("content-type").unwrap(), "image/png");
+    assert!(res.headers().get("content-disposition").is_none());
+    assert!(test::read_body(res).await.starts_with(b"\x89PNG"));
+    sqlx::query("INSERT INTO matrix_media (server_name, media_id, created_at) VALUES ('matrix.org', 'page', '2026-01-01T00:00:00Z')")
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let res = test::call_service(&app, TestRequest::get().uri("/api/matrix/media/matrix.org/page").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("content-type").unwrap(), "application/octet-stream");
+    assert_eq!(res.headers().get("content-disposition").unwrap(), "attachment");
+    assert_eq!(res.headers().get("x-content-type-options").unwrap(), "nosniff");
+    let user = TestRequest::get()
+        .uri("/_matrix/app/v1/users/@voxium_nobody:example.org")
+        .insert_header(("Authorization", "Bearer hs-secret"));
+    let (status, _) = call_json(&app, user).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,59 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+use serde_json::json;
+
+#[actix_web::test]
+async fn member_profiles_show_in_members_and_messages() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "general", "user").await;
+    create_message(&state.pool, &room, &alice, "hello").await;
+
+    let own = || TestRequest::patch().uri("/api/users/@me/member-profile");
+    let (status, _) = call_json(&app, alice.sign(own().set_json(json!({ "nickname": "x".repeat(33) })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, alice.sign(own().set_json(json!({ "avatar_url": "javascript:alert(1)" })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let mut events = state.broadcaster.subscribe();
+    let (status, profile) = call_json(&app, alice.sign(own().set_json(json!({
+        "nickname": " Ali ",
+        "avatar_url": "https://cdn.example.com/ali.png",
+        "pronouns": "she/her",
+        "bio": "Plays bass.",
+    }))))
+    .await;
+    assert_eq!(status, StatusCode::OK, "{profile}");
+    assert_eq!(profile["nickname"], "Ali");
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event["type"], "member_profile_updated");
+    assert_eq!(event["profile"]["pronouns"], "she/her");
+
+    let uri = format!("/api/users/{}/member-profile", alice.id);
+    let (_, read) = call_json(&app, bob.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(read["bio"], "Plays bass.");
+    let (_, members) = call_json(&app, bob.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/members")))).await;
+    let listed = members.as_array().unwrap().iter().find(|m| m["id"] == alice.id.as_str()).unwrap();
+    assert_eq!(listed["nickname"], "Ali");
+    assert_eq!(listed["pronouns"], "she/her");
+    assert_eq!(listed["avatar_url"], "https://cdn.example.com/ali.png");
+    let (_, messages) = call_json(&app, bob.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/messages")))).await;
+    assert_eq!(messages["messages"][0]["username"], "alice");
+    assert_eq!(messages["messages"][0]["nickname"], "Ali");
+    assert_eq!(messages["messages"][0]["avatar_url"], "https://cdn.example.com/ali.png");
+
+    // Moderators reset it, in the audit log; members can't.
+    let (status, _) = call_json(&app, bob.sign(TestRequest::patch().uri(&uri).set_json(json!({ "nickname": "" })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, reset) = call_json(&app, admin.sign(TestRequest::patch().uri(&uri).set_json(json!({ "nickname": "" })))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reset["nickname"], serde_json::Value::Null);
+    assert_eq!(reset["pronouns"], "she/her");
+    let (_, log) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/audit-log?action=member_profile_updated"))).await;
+    assert_eq!(log[0]["target_id"], alice.id.as_str());
+    assert_eq!(log[0]["changes"]["nickname"], json!({ "old": "Ali", "new": null }));
+}
@@ -0,0 +1,113 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{
+    call_json, call_text, create_message, create_messages, create_room, create_user, init_app, test_state,
+};
+
+fn contents(body: &serde_json::Value) -> Vec<String> {
+    body["messages"].as_array().unwrap().iter().map(|m| m["content"].as_str().unwrap().to_string()).collect()
+}
+
+#[actix_web::test]
+async fn history_pages_back_with_before() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let ids = create_messages(&state.pool, &room, &alice, "msg", 5).await;
+
+    let uri = format!("/api/rooms/{room}/messages?limit=3");
+    let (status, body) = call_json(&app, alice.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(contents(&body), ["msg 2", "msg 3", "msg 4"]);
+    assert_eq!(body["has_more_before"], true);
+    assert_eq!(body["has_more_after"], false);
+
+    let uri = format!("/api/rooms/{room}/messages?limit=3&before={}", ids[2]);
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(contents(&body), ["msg 0", "msg 1"]);
+    assert_eq!(body["has_more_before"], false);
+}
+
+#[actix_web::test]
+async fn history_rejects_several_cursors_and_unknown_rooms() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let id = create_message(&state.pool, &room, &alice, "hello").await;
+
+    let uri = format!("/api/rooms/{room}/messages?before={id}&after={id}");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri("/api/rooms/nope/messages"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn restricted_room_history_is_forbidden() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let moderator = create_user(&state.pool, "bob", "moderator").await;
+    let room = create_room(&state.pool, "staff", "moderator").await;
+    create_message(&state.pool, &room, &moderator, "internal").await;
+
+    let uri = format!("/api/rooms/{room}/messages");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = call_json(&app, moderator.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(contents(&body), ["internal"]);
+}
+
+#[actix_web::test]
+async fn history_streams_as_ndjson_on_request() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    create_messages(&state.pool, &room, &alice, "msg", 2).await;
+
+    let req = TestRequest::get()
+        .uri(&format!("/api/rooms/{room}/messages"))
+        .insert_header(("Accept", backend::export::NDJSON));
+    let (status, body) = call_text(&app, alice.sign(req)).await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<serde_json::Value> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["content"], "msg 0");
+    assert_eq!(lines[2]["page"]["has_more_before"], false);
+}
+
+#[actix_web::test]
+async fn pins_are_admin_only_idempotent_and_limited() {
+    let mut state = test_state().await;
+    state.settings.limits.pins_per_room = 2;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let ids = create_messages(&state.pool, &room, &alice, "msg", 3).await;
+    let pin = |id: &str| TestRequest::post().uri(&format!("/api/messages/{id}/pin"));
+
+    let (status, _) = call_json(&app, alice.sign(pin(&ids[0]))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    for id in &ids[..2] {
+        let (status, body) = call_json(&app, admin.sign(pin(id))).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+    let (status, _) = call_json(&app, admin.sign(pin(&ids[1]))).await;
+    assert_eq!(status, StatusCode::OK, "pinning again is a no-op");
+
+    let (status, body) = call_json(&app, admin.sign(pin(&ids[2]))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["pin_limit"], 2);
+
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/pins")))).await;
+    let pinned = body.as_array().unwrap();
+    assert_eq!(pinned.len(), 2);
+    assert!(pinned.iter().all(|m| m["pinned_by_username"] == "root"));
+}
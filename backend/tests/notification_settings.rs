@@ -0,0 +1,84 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::notifications::send_digests;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+use chrono::Utc;
+
+#[actix_web::test]
+async fn room_overrides_keep_muted_mentions_out_of_the_digest() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "general-chat", "user").await;
+    let settings = || TestRequest::patch().uri("/api/users/@me/notification-settings");
+
+    let (status, current) = call_json(&app, alice.sign(TestRequest::get().uri("/api/users/@me/notification-settings"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(current["defaults"]["level"], "all");
+    assert_eq!(current["defaults"]["suppress_everyone"], false);
+    assert!(current["rooms"].as_array().unwrap().is_empty());
+
+    let (status, _) = call_json(&app, alice.sign(settings().set_json(serde_json::json!({
+        "rooms": [{ "room_id": room, "level": "loud" }]
+    })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, alice.sign(settings().set_json(serde_json::json!({
+        "rooms": [{ "room_id": "no-such-room", "muted": true }]
+    })))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let until = (Utc::now() + chrono::Duration::hours(8)).to_rfc3339();
+    let (status, updated) = call_json(&app, alice.sign(settings().set_json(serde_json::json!({
+        "defaults": { "suppress_everyone": true },
+        "rooms": [{ "room_id": room, "level": "mentions", "muted_until": until }]
+    })))).await;
+    assert_eq!(status, StatusCode::OK, "{updated}");
+    assert_eq!(updated["defaults"]["suppress_everyone"], true);
+    let rooms = updated["rooms"].as_array().unwrap();
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0]["level"], "mentions");
+    assert_eq!(rooms[0]["muted"], true);
+    assert!(rooms[0]["suppress_everyone"].is_null());
+
+    sqlx::query("UPDATE users SET email = 'alice@example.org', email_verified_at = ? WHERE id = ?")
+        .bind(Utc::now().to_rfc3339())
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let yesterday = (Utc::now() - chrono::Duration::hours(25)).to_rfc3339();
+    sqlx::query("INSERT INTO email_preferences (user_id, mention_digest, digest_sent_at, updated_at) VALUES (?, 1, ?, ?)")
+        .bind(&alice.id)
+        .bind(&yesterday)
+        .bind(&yesterday)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let message = create_message(&state.pool, &room, &bob, "@alice look at this").await;
+    sqlx::query("INSERT INTO message_mentions (message_id, user_id, room_id, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&message)
+        .bind(&alice.id)
+        .bind(&room)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    // Muted: the mention stays unread but out of the digest.
+    assert_eq!(send_digests(&state.pool).await, Ok(0));
+
+    // Unmuting (and resetting the level) leaves nothing to override.
+    sqlx::query("UPDATE email_preferences SET digest_sent_at = ? WHERE user_id = ?")
+        .bind(&yesterday)
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let (status, updated) = call_json(&app, alice.sign(settings().set_json(serde_json::json!({
+        "rooms": [{ "room_id": room, "level": null, "muted": false }]
+    })))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(updated["rooms"].as_array().unwrap().is_empty());
+    assert_eq!(send_digests(&state.pool).await, Ok(1));
+}
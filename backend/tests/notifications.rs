@@ -0,0 +1,65 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::notifications::{render, send_digests};
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+use chrono::Utc;
+
+#[actix_web::test]
+async fn opted_in_users_get_a_digest_of_unread_mentions() {
+    let (subject, body) = render("friend_request", &[("sender", "bob".to_string()), ("username", "alice".to_string())]).unwrap();
+    assert_eq!(subject, "bob vous a envoyé une demande d'ami");
+    assert!(body.starts_with("Bonjour alice,"));
+    assert!(render("no_such_template", &[]).is_none());
+
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let notifications = || TestRequest::patch().uri("/api/users/@me/email/notifications");
+
+    let (status, _) = call_json(&app, alice.sign(notifications().set_json(serde_json::json!({ "mention_digest": true })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    sqlx::query("UPDATE users SET email = 'alice@example.org', email_verified_at = ? WHERE id = ?")
+        .bind(Utc::now().to_rfc3339())
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let (status, preferences) =
+        call_json(&app, alice.sign(notifications().set_json(serde_json::json!({ "mention_digest": true, "security": true })))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(preferences["mention_digest"], true);
+    assert_eq!(preferences["friend_requests"], false);
+    assert_eq!(preferences["email_verified"], true);
+
+    // Enabled just now: nothing is due for a day.
+    assert_eq!(send_digests(&state.pool).await, Ok(0));
+    let yesterday = (Utc::now() - chrono::Duration::hours(25)).to_rfc3339();
+    sqlx::query("UPDATE email_preferences SET digest_sent_at = ? WHERE user_id = ?")
+        .bind(&yesterday)
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let room = create_room(&state.pool, "general-chat", "user").await;
+    let message = create_message(&state.pool, &room, &bob, "@alice look at this").await;
+    sqlx::query("INSERT INTO message_mentions (message_id, user_id, room_id, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&message)
+        .bind(&alice.id)
+        .bind(&room)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(send_digests(&state.pool).await, Ok(1));
+    // The mention went out; the next digest is a day away.
+    assert_eq!(send_digests(&state.pool).await, Ok(0));
+
+    let (status, preferences) =
+        call_json(&app, alice.sign(TestRequest::get().uri("/api/users/@me/email/notifications"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(preferences["security"], true);
+}
@@ -0,0 +1,58 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, call_page, create_message, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn listings_page_with_signed_cursors() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    for name in ["ana", "bob", "cid", "dee"] {
+        create_user(&state.pool, name, "user").await;
+    }
+
+    let users = |query: &str| TestRequest::get().uri(&format!("/api/server/users?{query}"));
+    let mut names = Vec::new();
+    let (status, page, mut next) = call_page(&app, admin.sign(users("limit=2"))).await;
+    assert_eq!(status, StatusCode::OK);
+    names.extend(page.as_array().unwrap().iter().map(|u| u["username"].as_str().unwrap().to_string()));
+    while let Some(cursor) = next {
+        let (status, page, cursor) = call_page(&app, admin.sign(users(&format!("limit=2&cursor={cursor}")))).await;
+        assert_eq!(status, StatusCode::OK, "{page}");
+        names.extend(page.as_array().unwrap().iter().map(|u| u["username"].as_str().unwrap().to_string()));
+        next = cursor;
+    }
+    assert_eq!(names, ["ana", "bob", "cid", "dee", "root"]);
+
+    let (_, page, next) = call_page(&app, admin.sign(users("limit=3&sort=-username&q=e"))).await;
+    assert_eq!(page.as_array().unwrap().len(), 1);
+    assert_eq!(page[0]["username"], "dee");
+    assert!(next.is_none());
+
+    // Tampered, reused for another sort, or unknown sort.
+    let (_, _, next) = call_page(&app, admin.sign(users("limit=1"))).await;
+    let cursor = next.unwrap();
+    let (status, _) = call_json(&app, admin.sign(users(&format!("cursor={cursor}x")))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, admin.sign(users(&format!("cursor={cursor}&sort=created_at")))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = call_json(&app, admin.sign(users("sort=password_hash"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["sorts"], serde_json::json!(["username", "created_at"]));
+
+    // A cursor belongs to its listing.
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    let search = TestRequest::get().uri(&format!("/api/messages/search?cursor={cursor}"));
+    let (status, _) = call_json(&app, admin.sign(search)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    for content in ["one", "two", "three"] {
+        create_message(&state.pool, &lobby, &admin, content).await;
+    }
+    let (_, first, next) = call_page(&app, admin.sign(TestRequest::get().uri("/api/messages/search?limit=2"))).await;
+    assert_eq!(first.as_array().unwrap().len(), 2);
+    let uri = format!("/api/messages/search?limit=2&cursor={}", next.unwrap());
+    let (_, rest, next) = call_page(&app, admin.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(rest.as_array().unwrap().len(), 1);
+    assert!(next.is_none());
+}
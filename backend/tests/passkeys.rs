@@ -0,0 +1,133 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_user, init_app, test_state};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::Value;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+const ORIGIN: &str = "http://localhost:1420";
+const FLAG_UP: u8 = 0x01;
+const FLAG_UV: u8 = 0x04;
+const FLAG_AT: u8 = 0x40;
+
+/// A software ES256 authenticator holding one credential.
+struct Authenticator {
+    key: SigningKey,
+    id: Vec<u8>,
+}
+
+impl Authenticator {
+    fn new() -> Self {
+        Self { key: SigningKey::random(&mut rand::rngs::OsRng), id: b"test-credential".to_vec() }
+    }
+
+    fn credential_id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.id)
+    }
+
+    fn client_data(kind: &str, challenge: &str) -> Vec<u8> {
+        json!({ "type": kind, "challenge": challenge, "origin": ORIGIN }).to_string().into_bytes()
+    }
+
+    fn auth_data(flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(b"localhost").to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+
+    fn register(&self, challenge: &str, flags: u8, sign_count: u32) -> serde_json::Value {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(-7)),
+            (Value::from(-1), Value::from(1)),
+            (Value::from(-2), Value::Bytes(point.x().unwrap().to_vec())),
+            (Value::from(-3), Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut auth_data = Self::auth_data(flags | FLAG_AT, sign_count);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(self.id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.id);
+        ciborium::ser::into_writer(&cose_key, &mut auth_data).unwrap();
+
+        let attestation = Value::Map(vec![
+            (Value::from("fmt"), Value::from("none")),
+            (Value::from("attStmt"), Value::Map(Vec::new())),
+            (Value::from("authData"), Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(&attestation, &mut attestation_object).unwrap();
+        json!({
+            "id": self.credential_id(),
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(Self::client_data("webauthn.create", challenge)),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object),
+            },
+        })
+    }
+
+    fn assert(&self, challenge: &str, flags: u8, sign_count: u32) -> serde_json::Value {
+        let client_data = Self::client_data("webauthn.get", challenge);
+        let auth_data = Self::auth_data(flags, sign_count);
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed);
+        json!({
+            "id": self.credential_id(),
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+            },
+        })
+    }
+}
+
+#[actix_web::test]
+async fn passkeys_need_user_verification_and_keep_the_registered_counter() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let authenticator = Authenticator::new();
+
+    let register_start = || alice.sign(TestRequest::post().uri("/api/auth/passkey/register/start"));
+    let (_, options) = call_json(&app, register_start()).await;
+    assert_eq!(options["authenticatorSelection"]["userVerification"], "required");
+    let challenge = options["challenge"].as_str().unwrap();
+    let credential = authenticator.register(challenge, FLAG_UP, 5);
+    let (status, body) = call_json(&app, alice.sign(TestRequest::post().uri("/api/auth/passkey/register/finish")).set_json(json!({ "credential": credential }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "User verification is required");
+
+    let (_, options) = call_json(&app, register_start()).await;
+    let credential = authenticator.register(options["challenge"].as_str().unwrap(), FLAG_UP | FLAG_UV, 5);
+    let (status, body) = call_json(&app, alice.sign(TestRequest::post().uri("/api/auth/passkey/register/finish")).set_json(json!({ "name": "Key", "credential": credential }))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let login = |flags: u8, sign_count: u32| {
+        let app = &app;
+        let authenticator = &authenticator;
+        async move {
+            let (_, options) = call_json(app, TestRequest::post().uri("/api/auth/passkey/login/start").set_json(json!({ "username": "alice" }))).await;
+            assert_eq!(options["userVerification"], "required");
+            let credential = authenticator.assert(options["challenge"].as_str().unwrap(), flags, sign_count);
+            call_json(app, TestRequest::post().uri("/api/auth/passkey/login/finish").set_json(json!({ "credential": credential }))).await
+        }
+    };
+
+    // Presence alone is not enough to stand in for the password.
+    let (status, body) = login(FLAG_UP, 6).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "User verification is required");
+    // The counter starts where the authenticator was at registration.
+    let (status, body) = login(FLAG_UP | FLAG_UV, 5).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "Passkey counter went backwards, it may have been cloned");
+    let (status, body) = login(FLAG_UP | FLAG_UV, 6).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user_id"], alice.id.as_str());
+}
@@ -0,0 +1,147 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::permissions::{self, Overwrite};
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+
+fn overwrite(target_type: &str, target_id: &str, allow: u64, deny: u64) -> Overwrite {
+    Overwrite {
+        target_type: target_type.to_string(),
+        target_id: target_id.to_string(),
+        allow,
+        deny,
+    }
+}
+
+#[test]
+fn overwrites_apply_everyone_then_role_then_member() {
+    let overwrites = [
+        overwrite("user", "u1", permissions::SEND_MESSAGES, 0),
+        overwrite("role", "muted", 0, permissions::SEND_MESSAGES | permissions::ADD_REACTIONS),
+        overwrite("role", "user", 0, permissions::ATTACH_FILES),
+    ];
+    let base = permissions::base("user", "muted", permissions::MEMBER);
+    assert_eq!(base, permissions::MEMBER);
+
+    let member = permissions::apply(base, &overwrites, "u1", "muted");
+    let voice = permissions::CONNECT | permissions::USE_SOUNDBOARD;
+    assert_eq!(member, permissions::VIEW_ROOM | permissions::SEND_MESSAGES | voice);
+    let other = permissions::apply(base, &overwrites, "u2", "muted");
+    assert_eq!(other, permissions::VIEW_ROOM | voice);
+
+    // An allow opens a gated room.
+    let gated = permissions::base("staff", "user", permissions::MEMBER);
+    assert_eq!(gated, 0);
+    let opened = permissions::apply(gated, &[overwrite("user", "u3", permissions::VIEW_ROOM, 0)], "u3", "user");
+    assert_eq!(opened, permissions::VIEW_ROOM);
+}
+
+#[test]
+fn role_bits_seed_rooms_and_administrators_skip_overwrites() {
+    let moderator = permissions::MEMBER | permissions::MANAGE_MESSAGES | permissions::MANAGE_ROLES;
+    // Server bits stay out of room permissions, and overwrites cannot add them.
+    assert_eq!(permissions::base("user", "mod", moderator), permissions::MEMBER | permissions::MANAGE_MESSAGES);
+    let sneaky = [overwrite("role", "mod", permissions::MANAGE_SERVER | permissions::MUTE_MEMBERS, 0)];
+    let granted = permissions::apply(permissions::base("user", "mod", moderator), &sneaky, "u1", "mod");
+    assert_eq!(granted, permissions::MEMBER | permissions::MANAGE_MESSAGES | permissions::MUTE_MEMBERS);
+
+    let denied = [overwrite("role", "boss", 0, permissions::ROOM)];
+    let boss = permissions::base("staff", "boss", permissions::ADMINISTRATOR);
+    assert_eq!(permissions::apply(boss, &denied, "u2", "boss"), permissions::ALL);
+
+    assert_eq!(permissions::base("user", "lurker", permissions::VIEW_ROOM), permissions::VIEW_ROOM);
+}
+
+#[actix_web::test]
+async fn room_overwrites_gate_history_and_posting() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let staff = create_room(&state.pool, "staff", "admin").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    create_message(&state.pool, &staff, &admin, "internal").await;
+
+    let history = TestRequest::get().uri(&format!("/api/rooms/{staff}/messages"));
+    let (status, _) = call_json(&app, alice.sign(history)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let put = |room: &str, target: &str, body: serde_json::Value| {
+        TestRequest::put().uri(&format!("/api/rooms/{room}/permissions/{target}")).set_json(body)
+    };
+    let member = format!("user/{}", alice.id);
+    let (status, _) = call_json(&app, alice.sign(put(&staff, &member, serde_json::json!({ "allow": 1 })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = call_json(&app, admin.sign(put(&staff, &member, serde_json::json!({ "allow": 1 })))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let history = TestRequest::get().uri(&format!("/api/rooms/{staff}/messages"));
+    let (status, page) = call_json(&app, alice.sign(history)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["messages"][0]["content"], "internal");
+    let (_, mine) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{staff}/permissions/@me")))).await;
+    assert_eq!(mine["names"], serde_json::json!(["VIEW_ROOM"]));
+
+    // Everyone loses SEND_MESSAGES in the lobby; polls are refused.
+    let (status, _) = call_json(&app, admin.sign(put(&lobby, "role/user", serde_json::json!({ "deny": 2 })))).await;
+    assert_eq!(status, StatusCode::OK);
+    let poll = serde_json::json!({ "question": "Lunch?", "options": ["yes", "no"] });
+    let create = TestRequest::post().uri(&format!("/api/rooms/{lobby}/polls")).set_json(&poll);
+    let (status, refused) = call_json(&app, alice.sign(create)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["missing"], serde_json::json!(["SEND_MESSAGES"]));
+
+    let (status, _) = call_json(&app, admin.sign(put(&lobby, "role/admin", serde_json::json!({ "deny": 2 })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, admin.sign(put(&lobby, "role/user", serde_json::json!({ "allow": 2, "deny": 2 })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let remove = TestRequest::delete().uri(&format!("/api/rooms/{lobby}/permissions/role/user"));
+    let (status, _) = call_json(&app, admin.sign(remove)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let create = TestRequest::post().uri(&format!("/api/rooms/{lobby}/polls")).set_json(&poll);
+    let (status, body) = call_json(&app, alice.sign(create)).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+}
+
+#[actix_web::test]
+async fn role_permissions_gate_moderation_and_cannot_escalate() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let manage = permissions::MEMBER | permissions::MANAGE_MESSAGES | permissions::MANAGE_ROLES;
+    sqlx::query("INSERT INTO roles (name, color, permissions) VALUES ('mod', '#ffffff', ?)")
+        .bind(manage as i64)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let moderator = create_user(&state.pool, "mod", "mod").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+    let message = create_message(&state.pool, &lobby, &alice, "pin me").await;
+
+    let pin = || TestRequest::post().uri(&format!("/api/messages/{message}/pin"));
+    let (status, body) = call_json(&app, alice.sign(pin())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["missing"], serde_json::json!(["MANAGE_MESSAGES"]));
+    let (status, body) = call_json(&app, moderator.sign(pin())).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (status, _) = call_json(&app, moderator.sign(TestRequest::get().uri("/api/server/announcements"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let create = |permissions: u64| {
+        TestRequest::post()
+            .uri("/api/server/roles")
+            .set_json(serde_json::json!({ "name": format!("r{permissions}"), "permissions": permissions }))
+    };
+    let (status, _) = call_json(&app, moderator.sign(create(permissions::MEMBER | permissions::MANAGE_MESSAGES))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = call_json(&app, moderator.sign(create(permissions::ADMINISTRATOR))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["missing"], serde_json::json!(["ADMINISTRATOR"]));
+
+    let promote = TestRequest::patch()
+        .uri(&format!("/api/users/{}/role", alice.id))
+        .set_json(serde_json::json!({ "role": "admin" }));
+    let (status, _) = call_json(&app, moderator.sign(promote)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,87 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::pin_checks::check_due;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, storage_dir, test_state};
+
+fn upload(bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::post()
+        .uri("/api/files")
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn stale_pins_get_their_lost_files_marked_and_found_again() {
+    std::env::set_var("LINK_PREVIEWS", "0");
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let pinned = create_message(&state.pool, &room, &alice, "the agenda").await;
+    let unpinned = create_message(&state.pool, &room, &alice, "an old draft").await;
+    for (message, bytes) in [(&pinned, &b"agenda"[..]), (&unpinned, &b"draft"[..])] {
+        let (status, uploaded) = call_json(&app, alice.sign(upload(bytes))).await;
+        assert_eq!(status, StatusCode::CREATED, "{uploaded}");
+        sqlx::query("UPDATE attachments SET message_id = ? WHERE id = ?")
+            .bind(message)
+            .bind(uploaded["id"].as_str().unwrap())
+            .execute(&state.pool)
+            .await
+            .unwrap();
+    }
+    sqlx::query("UPDATE messages SET pinned_at = ? WHERE id = ?")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(&pinned)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let missing = |message: &str| {
+        sqlx::query_scalar::<_, Option<String>>(
+            "SELECT f.missing_at FROM attachments a JOIN files f ON f.hash = a.file_hash WHERE a.message_id = ?",
+        )
+        .bind(message.to_string())
+        .fetch_one(&state.pool)
+    };
+    let make_stale = || {
+        sqlx::query("UPDATE messages SET links_checked_at = ? WHERE id = ?")
+            .bind((chrono::Utc::now() - chrono::Duration::days(2)).to_rfc3339())
+            .bind(&pinned)
+            .execute(&state.pool)
+    };
+    let mut events = state.broadcaster.subscribe();
+
+    // Only pins are checked, and then not again until they are due.
+    assert_eq!(check_due(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(1));
+    assert_eq!(check_due(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(0));
+    assert_eq!(missing(&pinned).await.unwrap(), None);
+    assert!(events.try_recv().is_err(), "nothing changed");
+
+    // Storage lost its files.
+    let dir = storage_dir(&state);
+    let moved = dir.with_extension("moved");
+    std::fs::rename(&dir, &moved).unwrap();
+    make_stale().await.unwrap();
+    assert_eq!(check_due(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(1));
+    assert!(missing(&pinned).await.unwrap().is_some());
+    assert_eq!(missing(&unpinned).await.unwrap(), None);
+    let event: serde_json::Value = serde_json::from_str(&events.try_recv().unwrap()).unwrap();
+    assert_eq!(event["type"], "message_attachments_updated");
+    assert_eq!(event["id"], pinned.as_str());
+    assert!(event["attachments"][0]["missing_at"].is_string());
+
+    // And got them back.
+    std::fs::rename(&moved, &dir).unwrap();
+    make_stale().await.unwrap();
+    assert_eq!(check_due(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(1));
+    assert_eq!(missing(&pinned).await.unwrap(), None);
+    let event: serde_json::Value = serde_json::from_str(&events.try_recv().unwrap()).unwrap();
+    assert!(event["attachments"][0].get("missing_at").is_none());
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,77 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state, TestUser};
+
+async fn create_poll<S, B>(app: &S, author: &TestUser, room: &str, input: serde_json::Value) -> String
+where
+    S: actix_web::dev::Service<actix_http::Request, Response = actix_web::dev::ServiceResponse<B>, Error = actix_web::Error>,
+    B: actix_web::body::MessageBody,
+{
+    let req = TestRequest::post().uri(&format!("/api/rooms/{room}/polls")).set_json(input);
+    let (status, body) = call_json(app, author.sign(req)).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    body["id"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn poll_creation_is_validated() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let uri = format!("/api/rooms/{room}/polls");
+
+    for input in [
+        serde_json::json!({ "question": "Lunch?", "options": ["Pizza"] }),
+        serde_json::json!({ "question": "Lunch?", "options": ["Pizza", "Pizza"] }),
+        serde_json::json!({ "question": "  ", "options": ["Pizza", "Sushi"] }),
+    ] {
+        let (status, body) = call_json(&app, alice.sign(TestRequest::post().uri(&uri).set_json(&input))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{input} -> {body}");
+    }
+}
+
+#[actix_web::test]
+async fn votes_replace_and_retract() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let id = create_poll(&app, &alice, &room, serde_json::json!({ "question": "Lunch?", "options": ["Pizza", "Sushi"] })).await;
+    let votes = format!("/api/messages/{id}/poll/votes");
+
+    let (status, _) = call_json(&app, bob.sign(TestRequest::put().uri(&votes).set_json(serde_json::json!({ "options": [0, 1] })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "single-select poll");
+
+    call_json(&app, bob.sign(TestRequest::put().uri(&votes).set_json(serde_json::json!({ "options": [0] })))).await;
+    let (status, poll) = call_json(&app, bob.sign(TestRequest::put().uri(&votes).set_json(serde_json::json!({ "options": [1] })))).await;
+    assert_eq!(status, StatusCode::OK, "{poll}");
+    assert_eq!(poll["options"][0]["votes"], 0);
+    assert_eq!(poll["options"][1]["votes"], 1);
+    assert_eq!(poll["my_votes"], serde_json::json!([1]));
+
+    let (_, poll) = call_json(&app, bob.sign(TestRequest::delete().uri(&votes))).await;
+    assert_eq!(poll["total_voters"], 0);
+}
+
+#[actix_web::test]
+async fn only_the_author_closes_and_closed_polls_refuse_votes() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let id = create_poll(&app, &alice, &room, serde_json::json!({ "question": "Lunch?", "options": ["Pizza", "Sushi"] })).await;
+    let close = format!("/api/messages/{id}/poll/close");
+
+    let (status, _) = call_json(&app, bob.sign(TestRequest::post().uri(&close))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, poll) = call_json(&app, alice.sign(TestRequest::post().uri(&close))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(poll["closed_at"].is_string());
+
+    let req = TestRequest::put().uri(&format!("/api/messages/{id}/poll/votes")).set_json(serde_json::json!({ "options": [0] }));
+    let (status, _) = call_json(&app, bob.sign(req)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
@@ -0,0 +1,86 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn chosen_status_is_stored_and_broadcast_as_seen_by_others() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let mut events = state.broadcaster.subscribe();
+    let set = |body: serde_json::Value| TestRequest::put().uri("/api/users/@me/presence").set_json(body);
+
+    let (status, _) = call_json(&app, alice.sign(set(serde_json::json!({ "status": "away" })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let too_long = "x".repeat(backend::presence::MAX_CUSTOM_STATUS_CHARS + 1);
+    let (status, _) = call_json(&app, alice.sign(set(serde_json::json!({ "custom_status": too_long })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Not connected: stored, but nobody sees a change.
+    let (status, body) = call_json(&app, alice.sign(set(serde_json::json!({ "status": "DND", "custom_status": " Focus " })))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, serde_json::json!({ "status": "dnd", "custom_status": "Focus" }));
+    assert!(events.try_recv().is_err());
+
+    state.presence.lock().unwrap().insert(alice.id.clone(), 1);
+    let (_, body) = call_json(&app, alice.sign(set(serde_json::json!({ "status": "idle" })))).await;
+    assert_eq!(body["custom_status"], "Focus", "a missing field keeps its value");
+    let event: serde_json::Value = serde_json::from_str(&events.try_recv().unwrap()).unwrap();
+    assert_eq!(event, serde_json::json!({ "type": "presence", "user_id": alice.id, "status": "idle", "custom_status": "Focus" }));
+
+    let (_, body) = call_json(&app, alice.sign(set(serde_json::json!({ "status": "invisible", "custom_status": null })))).await;
+    assert_eq!(body["custom_status"], serde_json::Value::Null);
+    let event: serde_json::Value = serde_json::from_str(&events.try_recv().unwrap()).unwrap();
+    assert_eq!(event["status"], "offline");
+
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri("/api/users/@me/presence"))).await;
+    assert_eq!(body, serde_json::json!({ "status": "invisible", "custom_status": null }));
+}
+
+#[actix_web::test]
+async fn room_members_carry_their_presence() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let staff = create_room(&state.pool, "staff", "admin").await;
+    let chat = create_room(&state.pool, "chat", "user").await;
+    sqlx::query("UPDATE users SET presence_status = 'dnd', custom_status = 'Busy' WHERE id = ?")
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE users SET presence_status = 'invisible' WHERE id = ?")
+        .bind(&bob.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    state.presence.lock().unwrap().insert(alice.id.clone(), 2);
+    state.presence.lock().unwrap().insert(bob.id.clone(), 1);
+    let members = |room: &str| TestRequest::get().uri(&format!("/api/rooms/{room}/members"));
+
+    let (status, _) = call_json(&app, alice.sign(members(&staff))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, body) = call_json(&app, admin.sign(members(&staff))).await;
+    let names: Vec<&str> = body.as_array().unwrap().iter().map(|m| m["username"].as_str().unwrap()).collect();
+    assert_eq!(names, ["root"]);
+
+    let (status, body) = call_json(&app, bob.sign(members(&chat))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let seen: Vec<(&str, &str, &serde_json::Value)> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| (m["username"].as_str().unwrap(), m["status"].as_str().unwrap(), &m["custom_status"]))
+        .collect();
+    assert_eq!(seen, [
+        ("alice", "dnd", &serde_json::json!("Busy")),
+        ("bob", "offline", &serde_json::Value::Null),
+        ("root", "offline", &serde_json::Value::Null),
+    ]);
+
+    let (_, body) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/users"))).await;
+    let alice_row = body.as_array().unwrap().iter().find(|u| u["id"] == alice.id.as_str()).unwrap();
+    assert_eq!(alice_row["status"], "dnd");
+}
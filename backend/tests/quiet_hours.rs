@@ -0,0 +1,72 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::notifications::send_digests;
+use backend::quiet_hours::Window;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+use chrono::{Duration, TimeZone, Utc};
+
+#[actix_web::test]
+async fn quiet_hours_hold_the_digest_until_they_end() {
+    // 22:00 to 07:00 in UTC+02:00, starting Monday to Friday.
+    let overnight = Window { start_minute: 22 * 60, end_minute: 7 * 60, utc_offset_minutes: 120, days: 0b001_1111 };
+    let utc = |day, hour, minute| Utc.with_ymd_and_hms(2026, 6, day, hour, minute, 0).unwrap();
+    assert!(overnight.contains(utc(1, 20, 30))); // Monday 22:30 local
+    assert!(overnight.contains(utc(2, 4, 0))); // Tuesday 06:00 local, from Monday night
+    assert!(!overnight.contains(utc(2, 5, 0))); // Tuesday 07:00 local
+    assert!(!overnight.contains(utc(6, 21, 0))); // Saturday 23:00 local
+    assert!(overnight.contains(utc(6, 3, 0))); // Saturday 05:00 local, from Friday night
+
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let quiet_hours = || TestRequest::put().uri("/api/users/@me/quiet-hours");
+
+    let (status, current) = call_json(&app, alice.sign(TestRequest::get().uri("/api/users/@me/quiet-hours"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(current["quiet_hours"].is_null());
+    assert_eq!(current["active"], false);
+    let (status, _) = call_json(&app, alice.sign(quiet_hours().set_json(serde_json::json!({
+        "start": "25:00", "end": "07:00", "utc_offset_minutes": 0
+    })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A window around the current time.
+    let hhmm = |at: chrono::DateTime<Utc>| at.format("%H:%M").to_string();
+    let (status, set) = call_json(&app, alice.sign(quiet_hours().set_json(serde_json::json!({
+        "start": hhmm(Utc::now() - Duration::hours(1)), "end": hhmm(Utc::now() + Duration::hours(1)), "utc_offset_minutes": 0
+    })))).await;
+    assert_eq!(status, StatusCode::OK, "{set}");
+    assert_eq!(set["active"], true);
+    assert_eq!(set["quiet_hours"]["days"].as_array().unwrap().len(), 7);
+
+    sqlx::query("UPDATE users SET email = 'alice@example.org', email_verified_at = ? WHERE id = ?")
+        .bind(Utc::now().to_rfc3339())
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let yesterday = (Utc::now() - Duration::hours(25)).to_rfc3339();
+    sqlx::query("INSERT INTO email_preferences (user_id, mention_digest, digest_sent_at, updated_at) VALUES (?, 1, ?, ?)")
+        .bind(&alice.id)
+        .bind(&yesterday)
+        .bind(&yesterday)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let room = create_room(&state.pool, "general-chat", "user").await;
+    let message = create_message(&state.pool, &room, &bob, "@alice look at this").await;
+    sqlx::query("INSERT INTO message_mentions (message_id, user_id, room_id, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&message)
+        .bind(&alice.id)
+        .bind(&room)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    assert_eq!(send_digests(&state.pool).await, Ok(0));
+    let (status, _) = call_json(&app, alice.sign(TestRequest::delete().uri("/api/users/@me/quiet-hours"))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(send_digests(&state.pool).await, Ok(1));
+}
@@ -0,0 +1,69 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn friend_requests_are_sent_accepted_and_removed() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let mut events = state.broadcaster.subscribe();
+    let relationships = || TestRequest::get().uri("/api/users/@me/relationships");
+
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri("/api/users/@me/friends").set_json(serde_json::json!({ "username": "ALICE" })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = call_json(&app, alice.sign(TestRequest::post().uri("/api/users/@me/friends").set_json(serde_json::json!({ "username": "Bob" })))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["type"], "pending_outgoing");
+    let (_, body) = call_json(&app, bob.sign(relationships())).await;
+    assert_eq!(body[0]["user_id"], alice.id.as_str());
+    assert_eq!(body[0]["type"], "pending_incoming");
+
+    let sent: Vec<serde_json::Value> = std::iter::from_fn(|| events.try_recv().ok()).map(|e| serde_json::from_str(&e).unwrap()).collect();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1]["type"], "relationship_updated");
+    assert_eq!(sent[1]["recipient_id"], bob.id.as_str());
+    assert_eq!(sent[1]["relationship"]["type"], "pending_incoming");
+
+    let (_, body) = call_json(&app, bob.sign(TestRequest::put().uri(&format!("/api/users/@me/friends/{}", alice.id)))).await;
+    assert_eq!(body["type"], "friend");
+    let (_, body) = call_json(&app, alice.sign(relationships())).await;
+    assert_eq!(body[0]["type"], "friend");
+
+    let (status, _) = call_json(&app, alice.sign(TestRequest::delete().uri(&format!("/api/users/@me/friends/{}", bob.id)))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, body) = call_json(&app, bob.sign(relationships())).await;
+    assert_eq!(body, serde_json::json!([]));
+    let (status, _) = call_json(&app, alice.sign(TestRequest::delete().uri(&format!("/api/users/@me/friends/{}", bob.id)))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn blocking_ends_the_friendship_and_stops_requests() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let befriend = |id: &str| TestRequest::put().uri(&format!("/api/users/@me/friends/{id}"));
+    call_json(&app, alice.sign(befriend(&bob.id))).await;
+    call_json(&app, bob.sign(befriend(&alice.id))).await;
+
+    let (status, _) = call_json(&app, bob.sign(TestRequest::put().uri(&format!("/api/users/@me/blocks/{}", alice.id)))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri("/api/users/@me/relationships"))).await;
+    assert_eq!(body, serde_json::json!([]));
+    let (_, body) = call_json(&app, bob.sign(TestRequest::get().uri("/api/users/@me/relationships"))).await;
+    assert_eq!(body[0]["type"], "blocked");
+
+    let (status, _) = call_json(&app, alice.sign(befriend(&bob.id))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, bob.sign(befriend(&alice.id))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = call_json(&app, bob.sign(TestRequest::delete().uri(&format!("/api/users/@me/blocks/{}", alice.id)))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = call_json(&app, alice.sign(befriend(&bob.id))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["type"], "pending_outgoing");
+}
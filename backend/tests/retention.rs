@@ -0,0 +1,77 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::retention::prune_messages;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, test_state};
+use serde_json::json;
+
+#[actix_web::test]
+async fn messages_past_retention_are_pruned_and_audited() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let chat = create_room(&state.pool, "chat", "user").await;
+    let records = create_room(&state.pool, "records", "user").await;
+    let old = create_message(&state.pool, &chat, &alice, "from last year").await;
+    let pinned = create_message(&state.pool, &chat, &alice, "house rules").await;
+    let recent = create_message(&state.pool, &chat, &alice, "from last week").await;
+    let kept = create_message(&state.pool, &records, &alice, "minutes of the first meeting").await;
+    let age = |days: i64| (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+    for (id, days) in [(&old, 400), (&pinned, 400), (&recent, 7), (&kept, 400)] {
+        sqlx::query("UPDATE messages SET created_at = ? WHERE id = ?").bind(age(days)).bind(id).execute(&state.pool).await.unwrap();
+    }
+    sqlx::query("UPDATE messages SET pinned_at = ? WHERE id = ?").bind(age(1)).bind(&pinned).execute(&state.pool).await.unwrap();
+
+    // Keeping forever is the default.
+    assert_eq!(prune_messages(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(0));
+
+    let server = || TestRequest::patch().uri("/api/server/retention");
+    let (status, _) = call_json(&app, alice.sign(server().set_json(json!({ "max_age_days": 90 })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(server().set_json(json!({ "max_age_days": -1 })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, settings) = call_json(&app, admin.sign(server().set_json(json!({ "max_age_days": 90 })))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settings["max_age_days"], 90);
+
+    let room = format!("/api/rooms/{records}/retention");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::put().uri(&room).set_json(json!({ "max_age_days": 0 })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, own) = call_json(&app, admin.sign(TestRequest::put().uri(&room).set_json(json!({ "max_age_days": 0 })))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(own, json!({ "room_id": records, "max_age_days": 0, "overridden": true }));
+    let (_, inherited) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{chat}/retention")))).await;
+    assert_eq!(inherited, json!({ "room_id": chat, "max_age_days": 90, "overridden": false }));
+
+    let mut events = state.broadcaster.subscribe();
+    assert_eq!(prune_messages(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(1));
+    let left: Vec<String> = sqlx::query_scalar("SELECT id FROM messages WHERE id IN (?, ?, ?, ?)")
+        .bind(&old)
+        .bind(&pinned)
+        .bind(&recent)
+        .bind(&kept)
+        .fetch_all(&state.pool)
+        .await
+        .unwrap();
+    assert!(!left.contains(&old));
+    assert_eq!(left.len(), 3);
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event["type"], "messages_pruned");
+    assert_eq!(event["room_id"], chat.as_str());
+
+    let (_, entries) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/audit-log?action=messages_pruned"))).await;
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["target_id"], chat.as_str());
+    assert!(entries[0]["actor_id"].is_null());
+    assert_eq!(entries[0]["changes"]["count"], 1);
+    assert_eq!(entries[0]["changes"]["max_age_days"], 90);
+
+    // Dropping the override puts the room back on the server's retention.
+    let (_, inherited) = call_json(&app, admin.sign(TestRequest::put().uri(&room).set_json(json!({ "max_age_days": null })))).await;
+    assert_eq!(inherited["overridden"], false);
+    assert_eq!(prune_messages(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(1));
+    let (_, settings) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/retention"))).await;
+    assert!(settings["last_pruned_at"].is_string());
+    assert_eq!(settings["rooms"], json!([]));
+}
@@ -0,0 +1,129 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::room_archives::process_archives;
+use backend::test_support::{call_json, create_message, create_room, create_user, init_app, storage_dir, test_state};
+use serde_json::json;
+
+fn upload(bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"minutes.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::post()
+        .uri("/api/files")
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn room_transcripts_are_built_as_json_html_and_bundles() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "town-hall", "user").await;
+    let first = create_message(&state.pool, &room, &alice, "<b>hello</b> & welcome").await;
+    let gone = create_message(&state.pool, &room, &alice, "said in haste").await;
+    let with_file = create_message(&state.pool, &room, &alice, "notes attached").await;
+    sqlx::query("UPDATE messages SET deleted_at = ? WHERE id = ?")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(&gone)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let (status, uploaded) = call_json(&app, alice.sign(upload(b"minutes of the meeting"))).await;
+    assert_eq!(status, StatusCode::CREATED, "{uploaded}");
+    let attachment_id = uploaded["id"].as_str().unwrap();
+    sqlx::query("UPDATE attachments SET message_id = ? WHERE id = ?")
+        .bind(&with_file)
+        .bind(attachment_id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let archives = format!("/api/rooms/{room}/archives");
+    let request = |body: serde_json::Value| TestRequest::post().uri(&archives).set_json(body);
+    let (status, _) = call_json(&app, alice.sign(request(json!({})))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(request(json!({ "format": "pdf" })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, queued) = call_json(&app, admin.sign(request(json!({})))).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{queued}");
+    assert_eq!(queued["status"], "pending");
+    assert_eq!(queued["format"], "json");
+    let (status, _) = call_json(&app, admin.sign(request(json!({ "format": "html" })))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let download = |archive: &serde_json::Value| {
+        let uri = archive["download_url"].as_str().unwrap().to_string();
+        admin.sign(TestRequest::get().uri(&uri)).to_request()
+    };
+    let fetch = |id: &serde_json::Value| admin.sign(TestRequest::get().uri(&format!("{archives}/{}", id.as_str().unwrap())));
+
+    // JSON: the whole history, deleted messages included.
+    assert_eq!(process_archives(&state.pool, &state.file_storage).await, Ok(1));
+    let (status, ready) = call_json(&app, fetch(&queued["id"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ready["status"], "ready", "{ready}");
+    assert_eq!(ready["progress"], 100);
+    assert_eq!(ready["message_count"], 3);
+    let res = test::call_service(&app, download(&ready)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "application/json");
+    let transcript: serde_json::Value = serde_json::from_slice(&test::read_body(res).await).unwrap();
+    assert_eq!(transcript["room"]["name"], "town-hall");
+    assert_eq!(transcript["count"], 3);
+    let ids: Vec<&str> = transcript["messages"].as_array().unwrap().iter().map(|m| m["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, [first.as_str(), gone.as_str(), with_file.as_str()]);
+    assert!(transcript["messages"][1]["deleted_at"].is_string());
+    assert_eq!(transcript["messages"][2]["attachments"][0]["id"], attachment_id);
+
+    // HTML: escaped, and the deleted message shown as such.
+    let (_, queued) = call_json(&app, admin.sign(request(json!({ "format": "html" })))).await;
+    assert_eq!(process_archives(&state.pool, &state.file_storage).await, Ok(1));
+    let (_, ready) = call_json(&app, fetch(&queued["id"])).await;
+    let res = test::call_service(&app, download(&ready)).await;
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "text/html; charset=utf-8");
+    let page = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(page.contains("&lt;b&gt;hello&lt;/b&gt; &amp; welcome"), "{page}");
+    assert!(!page.contains("said in haste"));
+    assert!(page.contains("Message deleted"));
+
+    // Bundled: a ZIP of the transcript, the file and a manifest.
+    let (_, queued) = call_json(&app, admin.sign(request(json!({ "attachments": true })))).await;
+    assert_eq!(process_archives(&state.pool, &state.file_storage).await, Ok(1));
+    let (_, ready) = call_json(&app, fetch(&queued["id"])).await;
+    assert_eq!(ready["status"], "ready", "{ready}");
+    let res = test::call_service(&app, download(&ready)).await;
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "application/zip");
+    let archive = test::read_body(res).await;
+    assert_eq!(archive.len() as i64, ready["size_bytes"].as_i64().unwrap());
+    let end = &archive[archive.len() - 22..];
+    assert!(end.starts_with(b"PK\x05\x06"));
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 3);
+    let contains = |needle: &[u8]| archive.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"minutes of the meeting"));
+    assert!(contains(format!("\"path\":\"attachments/{attachment_id}-minutes.txt\"").as_bytes()));
+
+    let (_, listed) = call_json(&app, admin.sign(TestRequest::get().uri(&archives))).await;
+    assert_eq!(listed["archives"].as_array().unwrap().len(), 3);
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri(&archives))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Expired archives are deleted from storage.
+    sqlx::query("UPDATE room_archives SET expires_at = ?")
+        .bind((chrono::Utc::now() - chrono::Duration::minutes(1)).to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let (status, _) = call_json(&app, admin.sign(TestRequest::get().uri(ready["download_url"].as_str().unwrap()))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(process_archives(&state.pool, &state.file_storage).await, Ok(3));
+    let (_, expired) = call_json(&app, fetch(&queued["id"])).await;
+    assert_eq!(expired["status"], "expired");
+    assert!(expired["download_url"].is_null());
+    std::fs::remove_dir_all(storage_dir(&state)).ok();
+}
@@ -0,0 +1,53 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn health_needs_no_auth() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+
+    let (status, body) = call_json(&app, TestRequest::get().uri("/api/health")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+}
+
+#[actix_web::test]
+async fn room_list_hides_restricted_rooms() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let user = create_user(&state.pool, "alice", "user").await;
+    let moderator = create_user(&state.pool, "bob", "moderator").await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    create_room(&state.pool, "staff", "moderator").await;
+
+    let names = |body: serde_json::Value| -> Vec<String> {
+        body.as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap().to_string()).collect()
+    };
+
+    let (status, body) = call_json(&app, TestRequest::get().uri("/api/rooms")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "{body}");
+
+    let (_, body) = call_json(&app, user.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert!(!names(body).contains(&"staff".to_string()));
+    let (_, body) = call_json(&app, moderator.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert!(names(body).contains(&"staff".to_string()));
+    let (_, body) = call_json(&app, admin.sign(TestRequest::get().uri("/api/rooms"))).await;
+    assert!(names(body).contains(&"staff".to_string()));
+}
+
+#[actix_web::test]
+async fn only_admins_create_restricted_rooms() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let user = create_user(&state.pool, "alice", "user").await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let input = serde_json::json!({ "name": "secret", "required_role": "admin" });
+
+    let (status, _) = call_json(&app, user.sign(TestRequest::post().uri("/api/rooms").set_json(&input))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = call_json(&app, admin.sign(TestRequest::post().uri("/api/rooms").set_json(&input))).await;
+    assert!(status.is_success(), "{status} {body}");
+    assert_eq!(body["name"], "secret");
+}
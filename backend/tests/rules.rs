@@ -0,0 +1,71 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+fn publish(title: &str, requires_acknowledgment: bool) -> TestRequest {
+    TestRequest::put().uri("/api/server/rules").set_json(serde_json::json!({
+        "sections": [{ "title": title, "body": "Be kind." }],
+        "requires_acknowledgment": requires_acknowledgment,
+    }))
+}
+
+fn acknowledge(version: i64) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/rules/acknowledge")
+        .set_json(serde_json::json!({ "version": version }))
+}
+
+#[actix_web::test]
+async fn required_rules_block_posting_until_acknowledged() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lobby = create_room(&state.pool, "lobby", "user").await;
+
+    let (status, _) = call_json(&app, alice.sign(publish("Conduct", true))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, rules) = call_json(&app, admin.sign(publish("Conduct", true))).await;
+    assert_eq!(status, StatusCode::CREATED, "{rules}");
+    assert_eq!(rules["version"], 1);
+
+    let poll = || {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{lobby}/polls"))
+            .set_json(serde_json::json!({ "question": "Lunch?", "options": ["yes", "no"] }))
+    };
+    let (status, refused) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["rules_version"], 1);
+    let (_, current) = call_json(&app, alice.sign(TestRequest::get().uri("/api/rules"))).await;
+    assert_eq!(current["must_acknowledge"], true);
+    assert_eq!(current["rules"]["sections"][0]["title"], "Conduct");
+
+    let (status, _) = call_json(&app, alice.sign(acknowledge(1))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+
+    // An edit that does not require it leaves her posting, but shows up as pending.
+    call_json(&app, admin.sign(publish("Conduct, fixed", false))).await;
+    let (status, _) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, stats) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/rules/stats"))).await;
+    assert_eq!(stats["version"], 2);
+    assert_eq!(stats["members"], 2);
+    assert_eq!(stats["acknowledged"], 0);
+    assert_eq!(stats["pending_members"][0]["username"], "alice");
+    assert_eq!(stats["pending_members"][0]["acknowledged_version"], 1);
+    let (_, first) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/rules/stats?version=1"))).await;
+    assert_eq!(first["acknowledged"], 1);
+
+    call_json(&app, admin.sign(publish("New conduct", true))).await;
+    let (status, _) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, stale) = call_json(&app, alice.sign(acknowledge(2))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(stale["version"], 3);
+    let (status, old) = call_json(&app, alice.sign(TestRequest::get().uri("/api/rules/versions/1"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(old["sections"][0]["title"], "Conduct");
+}
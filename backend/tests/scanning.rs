@@ -0,0 +1,126 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::scanning::{parse_clamd_reply, Verdict};
+use backend::test_support::{call_json, create_user, init_app, storage_dir, test_state};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn upload(bytes: &[u8]) -> TestRequest {
+    multipart("/api/files", bytes)
+}
+
+fn multipart(uri: &str, bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::post()
+        .uri(uri)
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+/// A clamd speaking just enough INSTREAM: anything containing "EICAR" is flagged.
+async fn fake_clamd() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    actix_web::rt::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut command = [0u8; 10];
+            socket.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+            let mut data = Vec::new();
+            loop {
+                let mut len = [0u8; 4];
+                socket.read_exact(&mut len).await.unwrap();
+                let len = u32::from_be_bytes(len) as usize;
+                if len == 0 {
+                    break;
+                }
+                let mut chunk = vec![0u8; len];
+                socket.read_exact(&mut chunk).await.unwrap();
+                data.extend_from_slice(&chunk);
+            }
+            let reply = if data.windows(5).any(|w| w == b"EICAR") {
+                "stream: Eicar-Test-Signature FOUND\0"
+            } else {
+                "stream: OK\0"
+            };
+            socket.write_all(reply.as_bytes()).await.unwrap();
+        }
+    });
+    address
+}
+
+#[actix_web::test]
+async fn flagged_uploads_are_quarantined_for_review() {
+    assert_eq!(parse_clamd_reply("stream: OK\0"), Ok(Verdict::Clean));
+    assert_eq!(parse_clamd_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0"), Ok(Verdict::Infected("Win.Test.EICAR_HDB-1".to_string())));
+    assert!(parse_clamd_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+
+    std::env::set_var("UPLOAD_SCANNER", "clamd");
+    std::env::set_var("CLAMD_ADDRESS", fake_clamd().await);
+    let (status, clean) = call_json(&app, alice.sign(upload(b"meeting notes"))).await;
+    assert_eq!(status, StatusCode::CREATED, "{clean}");
+    assert_eq!(clean["scan_status"], "clean");
+
+    let (status, flagged) = call_json(&app, alice.sign(upload(b"X5O!P%@AP-STANDARD-ANTIVIRUS-TEST-FILE EICAR"))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(flagged["signature"], "Eicar-Test-Signature");
+    let flagged_id = flagged["attachment_id"].as_str().unwrap().to_string();
+
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri("/api/server/quarantine"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, quarantine) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/quarantine"))).await;
+    assert_eq!(status, StatusCode::OK);
+    let uploads = quarantine["uploads"].as_array().unwrap();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0]["id"], flagged_id.as_str());
+    assert_eq!(uploads[0]["uploader_username"], "alice");
+
+    let release = format!("/api/server/quarantine/{flagged_id}/release");
+    let (status, released) = call_json(&app, admin.sign(TestRequest::post().uri(&release))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(released["scan_status"], "released");
+    let (status, _) = call_json(&app, admin.sign(TestRequest::post().uri(&release))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let audited: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_log WHERE action = 'upload_released' AND target_id = ?")
+        .bind(&flagged_id)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(audited, 1);
+
+    let (_, flagged) = call_json(&app, alice.sign(upload(b"EICAR again"))).await;
+    let uri = format!("/api/server/quarantine/{}", flagged["attachment_id"].as_str().unwrap());
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&uri))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, quarantine) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/quarantine"))).await;
+    assert!(quarantine["uploads"].as_array().unwrap().is_empty());
+
+    // Uploads that aren't attachments have no quarantine: refused outright.
+    let (status, refused) = call_json(&app, admin.sign(multipart("/api/server/emojis/virus", b"EICAR in an emoji"))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(refused["signature"], "Eicar-Test-Signature");
+    let emojis: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM custom_emojis").fetch_one(&state.pool).await.unwrap();
+    assert_eq!(emojis, 0);
+
+    // Nothing listens there: uploads wait for the scanner, unless failing open.
+    std::env::set_var("CLAMD_ADDRESS", "127.0.0.1:9");
+    let (status, _) = call_json(&app, alice.sign(upload(b"more notes"))).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    std::env::set_var("UPLOAD_SCANNER_FAIL_OPEN", "true");
+    let (status, unscanned) = call_json(&app, alice.sign(upload(b"more notes"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(unscanned["scan_status"], "unscanned");
+    std::env::remove_var("UPLOAD_SCANNER_FAIL_OPEN");
+    std::env::remove_var("UPLOAD_SCANNER");
+    std::fs::remove_dir_all(storage_dir(&state)).ok();
+}
@@ -0,0 +1,86 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+fn poll() -> serde_json::Value {
+    serde_json::json!({ "question": "Lunch?", "options": ["Pizza", "Sushi"] })
+}
+
+#[actix_web::test]
+async fn slowmode_is_set_by_admins_within_bounds() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    let settings = |seconds: i64| {
+        TestRequest::patch()
+            .uri(&format!("/api/rooms/{room}"))
+            .set_json(serde_json::json!({ "name": "chat", "kind": "text", "required_role": "user", "slowmode_seconds": seconds }))
+    };
+
+    let (status, _) = call_json(&app, alice.sign(settings(30))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(settings(-1))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, admin.sign(settings(backend::slowmode::MAX_SLOWMODE_SECONDS + 1))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = call_json(&app, admin.sign(settings(30))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (_, rooms) = call_json(&app, alice.sign(TestRequest::get().uri("/api/rooms"))).await;
+    let chat = rooms.as_array().unwrap().iter().find(|r| r["id"] == room.as_str()).unwrap();
+    assert_eq!(chat["slowmode_seconds"], 30);
+
+    let input = serde_json::json!({ "name": "fast", "slowmode_seconds": 10 });
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri("/api/rooms").set_json(&input))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn posting_twice_within_the_cooldown_is_refused() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    sqlx::query("UPDATE rooms SET slowmode_seconds = 60 WHERE id = ?").bind(&room).execute(&state.pool).await.unwrap();
+    let uri = format!("/api/rooms/{room}/polls");
+
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri(&uri).set_json(poll()))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let res = actix_web::test::call_service(&app, alice.sign(TestRequest::post().uri(&uri).set_json(poll())).to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_header: u64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+    let body: serde_json::Value = actix_web::test::read_body_json(res).await;
+    assert_eq!(body["slowmode_seconds"], 60);
+    assert_eq!(body["retry_after"], retry_header);
+    assert!((1..=60).contains(&retry_header));
+
+    // Cooldowns are per room and per member.
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let (status, _) = call_json(&app, bob.sign(TestRequest::post().uri(&uri).set_json(poll()))).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn admins_and_bypass_roles_skip_the_cooldown() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+    sqlx::query("UPDATE rooms SET slowmode_seconds = 60 WHERE id = ?").bind(&room).execute(&state.pool).await.unwrap();
+    let uri = format!("/api/rooms/{room}/polls");
+
+    let role = serde_json::json!({ "name": "moderator", "bypass_slowmode": true });
+    let (status, _) = call_json(&app, admin.sign(TestRequest::post().uri("/api/server/roles").set_json(role))).await;
+    assert_eq!(status, StatusCode::OK);
+    let moderator = create_user(&state.pool, "mod", "moderator").await;
+
+    for user in [&admin, &moderator] {
+        for _ in 0..2 {
+            let (status, body) = call_json(&app, user.sign(TestRequest::post().uri(&uri).set_json(poll()))).await;
+            assert_eq!(status, StatusCode::CREATED, "{} {body}", user.username);
+        }
+    }
+}
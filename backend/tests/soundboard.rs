@@ -0,0 +1,111 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::permissions::USE_SOUNDBOARD;
+use backend::test_support::{call_json, create_room, create_user, init_app, storage_dir, test_state};
+use serde_json::json;
+
+/// One Ogg page holding `packets`, with `granule` as its position.
+fn ogg_page(seq: u32, granule: i64, packets: &[&[u8]]) -> Vec<u8> {
+    let mut page = b"OggS\0\0".to_vec();
+    page.extend_from_slice(&granule.to_le_bytes());
+    page.extend_from_slice(&1u32.to_le_bytes());
+    page.extend_from_slice(&seq.to_le_bytes());
+    page.extend_from_slice(&[0; 4]);
+    page.push(packets.len() as u8);
+    page.extend(packets.iter().map(|p| p.len() as u8));
+    for packet in packets {
+        page.extend_from_slice(packet);
+    }
+    page
+}
+
+/// An Ogg Opus stream of `frames` 60 ms frames.
+fn opus_clip(frames: usize) -> Vec<u8> {
+    let mut head = b"OpusHead\x01\x01".to_vec();
+    head.extend_from_slice(&[0; 9]);
+    let mut clip = ogg_page(0, 0, &[&head]);
+    clip.extend(ogg_page(1, 0, &[b"OpusTags\0\0\0\0\0\0\0\0"]));
+    // TOC 0x18: SILK, 60 ms, one frame.
+    let frame: &[u8] = &[0x18, 1, 2, 3];
+    clip.extend(ogg_page(2, frames as i64 * 2880, &vec![frame; frames]));
+    clip
+}
+
+fn upload(name: &str, bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"clip.ogg\"\r\nContent-Type: audio/ogg\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::post()
+        .uri(&format!("/api/server/soundboard/{name}?volume=0.5"))
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn clips_play_in_voice_with_a_cooldown() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "lounge", "user").await;
+    sqlx::query("UPDATE rooms SET kind = 'voice' WHERE id = ?").bind(&room).execute(&state.pool).await.unwrap();
+
+    let (status, _) = call_json(&app, alice.sign(upload("airhorn", &opus_clip(10)))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(upload("airhorn", b"not a clip"))).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    // 6 s is past the 5 s default.
+    let (status, _) = call_json(&app, admin.sign(upload("airhorn", &opus_clip(100)))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, sound) = call_json(&app, admin.sign(upload("airhorn", &opus_clip(20)))).await;
+    assert_eq!(status, StatusCode::CREATED, "{sound}");
+    assert_eq!(sound["duration_secs"], 1.2);
+    assert_eq!(sound["volume"], 0.5);
+    let (status, _) = call_json(&app, admin.sign(upload("airhorn", &opus_clip(10)))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (_, listed) = call_json(&app, alice.sign(TestRequest::get().uri("/api/soundboard"))).await;
+    assert_eq!(listed["sounds"][0]["name"], "airhorn");
+    let res = test::call_service(&app, alice.sign(TestRequest::get().uri(sound["audio_url"].as_str().unwrap())).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(test::read_body(res).await, opus_clip(20));
+
+    let play = format!("/api/rooms/{room}/voice/soundboard/{}", sound["id"].as_str().unwrap());
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri(&play))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    state.voice_occupancy.lock().unwrap().entry(room.clone()).or_default().insert(alice.id.clone(), 1);
+
+    let mut events = state.broadcaster.subscribe();
+    let (status, played) = call_json(&app, alice.sign(TestRequest::post().uri(&play))).await;
+    assert_eq!(status, StatusCode::OK, "{played}");
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event["type"], "soundboard_play");
+    assert_eq!(event["user_id"], alice.id.as_str());
+    assert_eq!(event["sound"]["id"], sound["id"]);
+    let (status, waiting) = call_json(&app, alice.sign(TestRequest::post().uri(&play))).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(waiting["retry_after"].as_u64().unwrap() >= 1);
+    // The cooldown holds across voice connections.
+    let discord = format!("/api/discord/voice/soundboard/{}", sound["id"].as_str().unwrap());
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri(&discord))).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Denied per room like any other room permission.
+    let overwrite = format!("/api/rooms/{room}/permissions/role/user");
+    let (status, _) = call_json(&app, admin.sign(TestRequest::put().uri(&overwrite).set_json(json!({ "deny": USE_SOUNDBOARD })))).await;
+    assert_eq!(status, StatusCode::OK);
+    sqlx::query("DELETE FROM soundboard_cooldowns").execute(&state.pool).await.unwrap();
+    let (status, denied) = call_json(&app, alice.sign(TestRequest::post().uri(&play))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(denied["missing"], json!(["USE_SOUNDBOARD"]));
+
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&format!("/api/server/soundboard/{}", sound["id"].as_str().unwrap())))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, listed) = call_json(&app, alice.sign(TestRequest::get().uri("/api/soundboard"))).await;
+    assert_eq!(listed["sounds"], json!([]));
+    std::fs::remove_dir_all(storage_dir(&state)).ok();
+}
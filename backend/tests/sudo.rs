@@ -0,0 +1,63 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use backend::test_support::{call_json, init_app, test_state};
+use serde_json::json;
+
+/// Discord's `/users/@me` for two user tokens.
+async fn fake_discord(req: HttpRequest) -> HttpResponse {
+    let user = match req.headers().get("Authorization").and_then(|v| v.to_str().ok()) {
+        Some("ann-token") => json!({ "id": "700", "username": "ann", "global_name": "Ann", "avatar": null }),
+        Some("eve-token") => json!({ "id": "701", "username": "eve", "global_name": "Eve", "avatar": null }),
+        _ => return HttpResponse::Unauthorized().finish(),
+    };
+    match req.path() {
+        "/users/@me" => HttpResponse::Ok().json(user),
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[actix_web::test]
+async fn accounts_created_through_discord_enter_sudo_mode_with_discord() {
+    std::env::set_var("ENCRYPTION_KEY", "sudo-test-key");
+    let server = HttpServer::new(|| App::new().default_service(web::to(fake_discord)))
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let address = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    std::env::set_var("DISCORD_API_BASE_URL", format!("http://{address}"));
+
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let (status, auth) = call_json(&app, TestRequest::post().uri("/api/auth/discord/token").set_json(json!({ "discord_token": "ann-token" }))).await;
+    assert_eq!(status, StatusCode::OK, "{auth}");
+    let bearer = ("Authorization", format!("Bearer {}", auth["token"].as_str().unwrap()));
+
+    // Nobody knows the password Discord sign-ups get.
+    let (_, sudo) = call_json(&app, TestRequest::get().uri("/api/auth/sudo").insert_header(bearer.clone())).await;
+    assert_eq!(sudo["methods"], json!(["discord"]));
+
+    let enter = |body: serde_json::Value| TestRequest::post().uri("/api/auth/sudo").insert_header(bearer.clone()).set_json(body);
+    let (status, _) = call_json(&app, enter(json!({ "discord_token": "eve-token" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call_json(&app, enter(json!({ "discord_token": "expired" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, entered) = call_json(&app, enter(json!({ "discord_token": "ann-token" }))).await;
+    assert_eq!(status, StatusCode::OK, "{entered}");
+    let sudo_bearer = ("Authorization", format!("Bearer {}", entered["token"].as_str().unwrap()));
+    let (_, sudo) = call_json(&app, TestRequest::get().uri("/api/auth/sudo").insert_header(sudo_bearer)).await;
+    assert_eq!(sudo["active"], true);
+
+    // Once a password is chosen, it is offered too.
+    let (status, _) = call_json(&app, TestRequest::patch().uri("/api/users/me").insert_header(bearer.clone()).set_json(json!({ "password": "a new password" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, sudo) = call_json(&app, TestRequest::get().uri("/api/auth/sudo").insert_header(bearer.clone())).await;
+    assert_eq!(sudo["methods"], json!(["password", "discord"]));
+
+    // A 2FA requirement shows on Discord logins as it does on password ones.
+    sqlx::query("UPDATE users SET totp_required = 1 WHERE discord_id = '700'").execute(&state.pool).await.unwrap();
+    let (_, auth) = call_json(&app, TestRequest::post().uri("/api/auth/discord/token").set_json(json!({ "discord_token": "ann-token" }))).await;
+    assert_eq!(auth["two_factor_setup_required"], true);
+}
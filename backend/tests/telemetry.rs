@@ -0,0 +1,81 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::middleware::{from_fn, Next};
+use actix_web::test::{self, TestRequest};
+use actix_web::{App, HttpResponse};
+use backend::telemetry::parse_traceparent;
+use backend::test_support::{create_user, test_state};
+
+#[actix_web::test]
+async fn error_responses_carry_the_request_id_and_trace() {
+    let state = test_state().await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(backend::telemetry::trace))
+            .wrap(from_fn(backend::jobs::request_id))
+            .configure(|cfg| state.register(cfg))
+            .configure(backend::routes),
+    )
+    .await;
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let req = TestRequest::post()
+        .uri("/api/login")
+        .insert_header(("X-Request-Id", "report-42"))
+        .insert_header(("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01")))
+        .set_json(serde_json::json!({ "username": "nobody", "password": "wrong password" }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.status().is_client_error());
+    assert_eq!(res.headers().get("x-request-id").unwrap(), "report-42");
+    let traceparent = res.headers().get("traceparent").unwrap().to_str().unwrap().to_string();
+    let (echoed_trace, span_id) = parse_traceparent(&traceparent).unwrap();
+    assert_eq!(echoed_trace, trace_id);
+    assert_ne!(span_id, "00f067aa0ba902b7");
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["request_id"], "report-42");
+    assert!(body["error"].is_string());
+
+    // Successful responses are left alone; a bad traceparent starts a new trace.
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let req = alice.sign(TestRequest::get().uri("/api/alt-text/policy").insert_header(("traceparent", "00-zz-00-01"))).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let traceparent = res.headers().get("traceparent").unwrap().to_str().unwrap().to_string();
+    assert!(parse_traceparent(&traceparent).is_some());
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert!(body.get("request_id").is_none());
+
+    // Errors from the middlewares inside are traced and stamped too.
+    let failing = test::init_service(
+        App::new()
+            .wrap(from_fn(refuse))
+            .wrap(from_fn(backend::telemetry::trace))
+            .wrap(from_fn(backend::jobs::request_id))
+            .configure(|cfg| state.register(cfg))
+            .configure(backend::routes),
+    )
+    .await;
+    let req = TestRequest::get().uri("/api/alt-text/policy").insert_header(("X-Request-Id", "report-43")).to_request();
+    let Err(error) = test::try_call_service(&failing, req).await else { panic!("the middleware let the request through") };
+    let res = error.error_response();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers().get("x-request-id").unwrap(), "report-43");
+    assert!(res.headers().get("traceparent").is_some());
+    let bytes = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["request_id"], "report-43");
+}
+
+async fn refuse(
+    _req: ServiceRequest,
+    _next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    Err::<ServiceResponse, _>(InternalError::from_response(
+        "maintenance",
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": "Down for maintenance" })),
+    )
+    .into())
+}
@@ -0,0 +1,58 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn timed_out_members_cannot_post_until_lifted() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+
+    let timeout = |seconds: i64| {
+        TestRequest::post()
+            .uri(&format!("/api/users/{}/timeout", alice.id))
+            .set_json(serde_json::json!({ "duration_seconds": seconds, "reason": "cool off", "room_id": room }))
+    };
+    let (status, _) = call_json(&app, bob.sign(timeout(600))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(timeout(5))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, given) = call_json(&app, admin.sign(timeout(600))).await;
+    assert_eq!(status, StatusCode::CREATED, "{given}");
+    assert_eq!(given["created_by_username"], "root");
+
+    let announced: String = sqlx::query_scalar("SELECT content FROM messages WHERE room_id = ? AND kind = 'member_timed_out'")
+        .bind(&room)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(announced, "timed out alice for 10 min: cool off");
+
+    let poll = || {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{room}/polls"))
+            .set_json(serde_json::json!({ "question": "Lunch?", "options": ["Pizza", "Sushi"] }))
+    };
+    let (status, refused) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["timed_out_until"], given["expires_at"]);
+    let (status, _) = call_json(&app, bob.sign(poll())).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let lift = || TestRequest::delete().uri(&format!("/api/users/{}/timeout", alice.id));
+    let (status, _) = call_json(&app, admin.sign(lift())).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, admin.sign(lift())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let log = TestRequest::get().uri(&format!("/api/server/audit-log?target_id={}", alice.id));
+    let (_, entries) = call_json(&app, admin.sign(log)).await;
+    let actions: Vec<&str> = entries.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["member_timeout_removed", "member_timed_out"]);
+    assert_eq!(entries[1]["reason"], "cool off");
+}
@@ -0,0 +1,60 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::test_support::{call_json, create_user, init_app, storage_dir, test_state};
+
+fn png(size: u32) -> Vec<u8> {
+    let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(size, size, image::Rgba([30, 120, 200, 255])));
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+fn upload(uri: &str, bytes: &[u8]) -> TestRequest {
+    let boundary = "voxium-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"emoji.png\"\r\nContent-Type: image/png\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    TestRequest::post()
+        .uri(uri)
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn emoji_images_are_served_from_the_file_store() {
+    let state = test_state().await;
+    let dir = storage_dir(&state);
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+
+    let (status, emoji) = call_json(&app, admin.sign(upload("/api/server/emojis/wave", &png(200)))).await;
+    assert_eq!(status, StatusCode::CREATED, "{emoji}");
+    let url = emoji["image_url"].as_str().unwrap().to_string();
+    assert!(url.starts_with("/uploads/emojis/wave_"), "{url}");
+    assert!(!std::path::Path::new("uploads/emojis").exists());
+
+    // Public, no token needed.
+    let res = test::call_service(&app, TestRequest::get().uri(&url).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("content-type").unwrap(), "image/png");
+    let served = image::load_from_memory(&test::read_body(res).await).unwrap();
+    assert_eq!((served.width(), served.height()), (128, 128));
+    let hash: String = sqlx::query_scalar("SELECT hash FROM uploaded_files").fetch_one(&state.pool).await.unwrap();
+    let object = dir.join(&hash[..2]).join(&hash[2..4]).join(&hash);
+    assert!(object.exists());
+
+    let id = emoji["id"].as_str().unwrap();
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&format!("/api/server/emojis/{id}")))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, TestRequest::get().uri(&url)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(!object.exists());
+
+    let (status, _) = call_json(&app, TestRequest::get().uri("/uploads/../Cargo.toml")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
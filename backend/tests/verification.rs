@@ -0,0 +1,103 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state, TestUser};
+use sha2::{Digest, Sha256};
+
+/// A nonce whose hash with `challenge` starts with `difficulty` zero bits.
+fn solve(challenge: &str, difficulty: u32) -> String {
+    (0u64..)
+        .map(|n| n.to_string())
+        .find(|nonce| {
+            let hash = Sha256::digest(format!("{challenge}:{nonce}").as_bytes());
+            u128::from_be_bytes(hash[..16].try_into().unwrap()).leading_zeros() >= difficulty
+        })
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn captcha_account_age_and_join_surges_gate_new_members() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "chat", "user").await;
+
+    let settings = |patch: serde_json::Value| TestRequest::patch().uri("/api/server/verification").set_json(patch);
+    let (status, _) = call_json(&app, alice.sign(settings(serde_json::json!({ "captcha_on_join": true })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(settings(serde_json::json!({ "surge_joins": 1 })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, updated) = call_json(&app, admin.sign(settings(serde_json::json!({ "captcha_on_join": true })))).await;
+    assert_eq!(status, StatusCode::OK, "{updated}");
+    assert_eq!(updated["captcha_required"], true);
+
+    let register = |username: &str, captcha: serde_json::Value| {
+        TestRequest::post().uri("/api/register").set_json(serde_json::json!({
+            "username": username,
+            "password": "correct horse battery",
+            "captcha": captcha,
+        }))
+    };
+    let (status, refused) = call_json(&app, register("newbie", serde_json::Value::Null)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["captcha_required"], true);
+    let (status, challenge) = call_json(&app, TestRequest::get().uri("/api/join/challenge")).await;
+    assert_eq!(status, StatusCode::OK);
+    let challenge_token = challenge["challenge"].as_str().unwrap();
+    let solution = serde_json::json!({
+        "challenge": challenge_token,
+        "nonce": solve(challenge_token, challenge["difficulty"].as_u64().unwrap() as u32),
+    });
+    let (status, account) = call_json(&app, register("newbie", solution.clone())).await;
+    assert_eq!(status, StatusCode::OK, "{account}");
+    let (status, _) = call_json(&app, register("copycat", solution)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let newbie = TestUser {
+        id: account["user_id"].as_str().unwrap().to_string(),
+        username: "newbie".to_string(),
+        role: "user".to_string(),
+        token: account["token"].as_str().unwrap().to_string(),
+    };
+
+    let patch = serde_json::json!({ "captcha_on_join": false, "min_account_age_minutes": 60 });
+    let (status, _) = call_json(&app, admin.sign(settings(patch))).await;
+    assert_eq!(status, StatusCode::OK);
+    let poll = || {
+        TestRequest::post()
+            .uri(&format!("/api/rooms/{room}/polls"))
+            .set_json(serde_json::json!({ "question": "Lunch?", "options": ["Yes", "No"] }))
+    };
+    let (status, refused) = call_json(&app, newbie.sign(poll())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(refused["verification"], "account_age");
+    assert!(refused["allowed_at"].is_string());
+    sqlx::query("UPDATE users SET created_at = datetime('now', '-2 hours') WHERE id = ?")
+        .bind(&alice.id)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let (status, _) = call_json(&app, alice.sign(poll())).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // root and newbie joined within the window, a third join is a surge.
+    let (status, _) = call_json(&app, admin.sign(settings(serde_json::json!({ "surge_joins": 3 })))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call_json(&app, register("eve", serde_json::Value::Null)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, current) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/verification"))).await;
+    assert_eq!(current["surge_active"], true);
+    assert_eq!(current["captcha_required"], true);
+    let (status, _) = call_json(&app, register("mallory", serde_json::Value::Null)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let end_surge = || TestRequest::delete().uri("/api/server/verification/surge");
+    let (status, _) = call_json(&app, admin.sign(end_surge())).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, admin.sign(end_surge())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let log = TestRequest::get().uri("/api/server/audit-log?target_type=verification");
+    let (_, entries) = call_json(&app, admin.sign(log)).await;
+    let actions: Vec<&str> = entries.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+    assert_eq!(actions[..2], ["join_surge_ended", "join_surge_detected"]);
+}
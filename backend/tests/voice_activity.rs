@@ -0,0 +1,62 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+async fn voice_room(pool: &sqlx::SqlitePool, name: &str) -> String {
+    let room = create_room(pool, name, "user").await;
+    sqlx::query("UPDATE rooms SET kind = 'voice' WHERE id = ?").bind(&room).execute(pool).await.unwrap();
+    room
+}
+
+async fn sample(pool: &sqlx::SqlitePool, room_id: &str, sampled_at: i64, users: i64) {
+    sqlx::query("INSERT INTO voice_occupancy_samples (room_id, sampled_at, users) VALUES (?, ?, ?)")
+        .bind(room_id)
+        .bind(sampled_at)
+        .bind(users)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn heatmap_averages_samples_by_hour_of_week() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let lounge = voice_room(&state.pool, "lounge").await;
+    let gaming = voice_room(&state.pool, "gaming").await;
+    let text = create_room(&state.pool, "chat", "user").await;
+
+    // Two samples within the same hour, two days ago; one far too old.
+    let now = chrono::Utc::now().timestamp();
+    let at = (now - 2 * 86_400) / 3600 * 3600 + 600;
+    sample(&state.pool, &lounge, at, 2).await;
+    sample(&state.pool, &gaming, at, 1).await;
+    sample(&state.pool, &lounge, at + 60, 1).await;
+    sample(&state.pool, &gaming, at + 60, 0).await;
+    sample(&state.pool, &lounge, now - 200 * 86_400, 9).await;
+    let heatmap = |query: &str| TestRequest::get().uri(&format!("/api/server/voice/heatmap{query}"));
+
+    let (status, _) = call_json(&app, alice.sign(heatmap(""))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(heatmap(&format!("?room_id={text}")))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = call_json(&app, admin.sign(heatmap("?tz_offset_minutes=120"))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let hours = body["hours"].as_array().unwrap();
+    assert_eq!(hours.len(), 168);
+    let expected = ((at + 7200) / 3600 + 72) % 168;
+    let filled: Vec<&serde_json::Value> = hours.iter().filter(|h| h["samples"] != 0).collect();
+    assert_eq!(filled.len(), 1);
+    let slot = filled[0];
+    assert_eq!(slot["hour_of_week"], expected);
+    assert_eq!(slot["day"], expected / 24);
+    assert_eq!(slot["hour"], expected % 24);
+    assert_eq!((slot["avg_users"].as_f64().unwrap(), slot["peak_users"].as_i64().unwrap(), slot["samples"].as_i64().unwrap()), (2.0, 3, 2));
+
+    let (_, body) = call_json(&app, admin.sign(heatmap(&format!("?room_id={lounge}&tz_offset_minutes=120")))).await;
+    let slot = &body["hours"][expected as usize];
+    assert_eq!((slot["avg_users"].as_f64().unwrap(), slot["peak_users"].as_i64().unwrap()), (1.5, 2));
+}
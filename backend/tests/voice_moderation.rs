@@ -0,0 +1,66 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+use backend::voice_moderation::stamp;
+use serde_json::json;
+
+#[actix_web::test]
+async fn server_mutes_hold_over_what_clients_announce() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let text = create_room(&state.pool, "lobby", "user").await;
+    let room = create_room(&state.pool, "stage", "user").await;
+    sqlx::query("UPDATE rooms SET kind = 'voice' WHERE id = ?").bind(&room).execute(&state.pool).await.unwrap();
+
+    let member = format!("/api/rooms/{room}/voice/members/{}", alice.id);
+    let mute = |body: serde_json::Value| TestRequest::put().uri(&member).set_json(body);
+    let (status, _) = call_json(&app, bob.sign(mute(json!({ "server_muted": true })))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let in_text = format!("/api/rooms/{text}/voice/members/{}", alice.id);
+    let (status, _) = call_json(&app, admin.sign(TestRequest::put().uri(&in_text).set_json(json!({ "server_muted": true })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let mut events = state.broadcaster.subscribe();
+    let (status, muted) = call_json(&app, admin.sign(mute(json!({ "server_muted": true })))).await;
+    assert_eq!(status, StatusCode::OK, "{muted}");
+    assert_eq!(muted["server_muted"], true);
+    assert_eq!(muted["server_deafened"], false);
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event, json!({ "type": "voice_state", "room_id": room, "user_id": alice.id, "server_muted": true, "server_deafened": false }));
+
+    // What alice's client relays carries the server's word, not its own.
+    let announced = json!({ "type": "voice_state", "room_id": room, "user_id": alice.id, "muted": false, "server_muted": false });
+    let relayed: serde_json::Value = serde_json::from_str(&stamp(&state.pool, &alice.id, &announced.to_string()).await).unwrap();
+    assert_eq!(relayed["server_muted"], true);
+    assert_eq!(relayed["muted"], false);
+
+    let (_, listed) = call_json(&app, bob.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/voice/members")))).await;
+    assert_eq!(listed["members"][0]["username"], "alice");
+    assert_eq!(listed["members"][0]["updated_by_username"], "root");
+
+    let (_, lifted) = call_json(&app, admin.sign(mute(json!({ "server_muted": false })))).await;
+    assert_eq!(lifted["server_muted"], false);
+    let (_, listed) = call_json(&app, bob.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/voice/members")))).await;
+    assert_eq!(listed["members"], json!([]));
+    let (_, entries) = call_json(&app, admin.sign(TestRequest::get().uri("/api/server/audit-log?action=member_voice_updated"))).await;
+    assert_eq!(entries.as_array().unwrap().len(), 2);
+    assert_eq!(entries[0]["target_id"], alice.id.as_str());
+    assert_eq!(entries[0]["changes"]["server_muted"], json!({ "old": true, "new": false }));
+
+    // Listening volumes are the listener's own, synced like their other voice settings.
+    let volume = |user_id: &str, volume: i64| {
+        TestRequest::put().uri(&format!("/api/users/@me/voice-settings/volumes/{user_id}")).set_json(json!({ "volume": volume }))
+    };
+    let (status, _) = call_json(&app, bob.sign(volume(&alice.id, 250))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, bob.sign(volume("nobody", 50))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, settings) = call_json(&app, bob.sign(volume(&alice.id, 40))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settings["user_volumes"], json!({ alice.id.clone(): 40 }));
+    let (_, settings) = call_json(&app, bob.sign(volume(&alice.id, 100))).await;
+    assert_eq!(settings["user_volumes"], json!({}));
+}
@@ -0,0 +1,57 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+async fn create_voice_room(pool: &sqlx::SqlitePool, name: &str) -> String {
+    let room = create_room(pool, name, "user").await;
+    sqlx::query("UPDATE rooms SET kind = 'voice' WHERE id = ?").bind(&room).execute(pool).await.unwrap();
+    room
+}
+
+#[actix_web::test]
+async fn admins_grant_priority_to_eligible_members_only() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    sqlx::query("INSERT INTO roles (name, color, priority_speaker) VALUES ('host', '#ffffff', 1)")
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let host = create_user(&state.pool, "host", "host").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let voice = create_voice_room(&state.pool, "stage").await;
+    let text = create_room(&state.pool, "chat", "user").await;
+    let grant = |room: &str, user: &str, enabled: bool| {
+        TestRequest::put()
+            .uri(&format!("/api/rooms/{room}/voice/priority/{user}"))
+            .set_json(serde_json::json!({ "enabled": enabled }))
+    };
+
+    let (status, _) = call_json(&app, alice.sign(grant(&voice, &host.id, true))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(grant(&voice, &alice.id, true))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, admin.sign(grant(&text, &host.id, true))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = call_json(&app, admin.sign(grant(&voice, &host.id, true))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["ducking_db"], backend::voice_priority::ducking_db());
+
+    let (status, body) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{voice}/voice/priority")))).await;
+    assert_eq!(status, StatusCode::OK);
+    let speakers = body["speakers"].as_array().unwrap();
+    assert_eq!(speakers.len(), 1);
+    assert_eq!(speakers[0]["user_id"], host.id.as_str());
+    assert_eq!(speakers[0]["granted_by"], admin.id.as_str());
+
+    // A role that loses the permission takes its members off the list.
+    sqlx::query("UPDATE roles SET priority_speaker = 0 WHERE name = 'host'").execute(&state.pool).await.unwrap();
+    let (_, body) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{voice}/voice/priority")))).await;
+    assert_eq!(body["speakers"].as_array().unwrap().len(), 0);
+
+    let (status, body) = call_json(&app, admin.sign(grant(&voice, &host.id, false))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM voice_priority_speakers").fetch_one(&state.pool).await.unwrap();
+    assert_eq!(left, 0);
+}
@@ -0,0 +1,105 @@
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use backend::test_support::{call_json, create_room, create_user, init_app, storage_dir, test_state};
+use backend::voice_recordings::process_recordings;
+use serde_json::json;
+
+#[actix_web::test]
+async fn consenting_participants_are_recorded_into_one_archive() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let text = create_room(&state.pool, "lobby", "user").await;
+    let room = create_room(&state.pool, "stage", "user").await;
+    sqlx::query("UPDATE rooms SET kind = 'voice' WHERE id = ?").bind(&room).execute(&state.pool).await.unwrap();
+
+    let recording = format!("/api/rooms/{room}/voice/recording");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::post().uri(&recording))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_json(&app, admin.sign(TestRequest::post().uri(&format!("/api/rooms/{text}/voice/recording")))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let mut events = state.broadcaster.subscribe();
+    let (status, started) = call_json(&app, admin.sign(TestRequest::post().uri(&recording))).await;
+    assert_eq!(status, StatusCode::CREATED, "{started}");
+    assert_eq!(started["recording"]["started_by_username"], "root");
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event["type"], "voice_recording");
+    assert_eq!(event["recording"]["id"], started["recording"]["id"]);
+    let (status, _) = call_json(&app, admin.sign(TestRequest::post().uri(&recording))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let segment = |seq: i64, bytes: &'static [u8]| {
+        TestRequest::put().uri(&format!("{recording}/tracks/{seq}")).set_payload(bytes)
+    };
+    let consent = |consent: bool| TestRequest::put().uri(&format!("{recording}/consent")).set_json(json!({ "consent": consent }));
+
+    // Nobody is recorded without consent.
+    let (status, _) = call_json(&app, alice.sign(segment(0, b"OggS alice 0;"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, state_now) = call_json(&app, alice.sign(consent(true))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state_now["recording"]["participants"][0]["username"], "alice");
+    assert_eq!(state_now["recording"]["participants"][0]["consented"], true);
+    let (status, _) = call_json(&app, alice.sign(segment(0, b"not ogg"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    for (seq, bytes) in [(0, &b"OggS alice 0;"[..]), (1, b"alice 1;"), (0, b"OggS alice 0;")] {
+        let res = test::call_service(&app, alice.sign(segment(seq, bytes)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+    let (status, skipped) = call_json(&app, alice.sign(segment(3, b"alice 3;"))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(skipped["next_seq"], 2);
+
+    // Withdrawing consent drops what was uploaded.
+    call_json(&app, bob.sign(consent(true))).await;
+    let res = test::call_service(&app, bob.sign(segment(0, b"OggS bob 0;")).to_request()).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let (_, state_now) = call_json(&app, bob.sign(consent(false))).await;
+    assert_eq!(state_now["recording"]["participants"][1]["consented"], false);
+
+    let (status, stopped) = call_json(&app, admin.sign(TestRequest::delete().uri(&recording))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stopped["status"], "processing");
+    let (_, now) = call_json(&app, alice.sign(TestRequest::get().uri(&recording))).await;
+    assert!(now["recording"].is_null());
+    // The last segment still makes it during the grace period.
+    let res = test::call_service(&app, alice.sign(segment(2, b"alice 2;")).to_request()).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(process_recordings(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(0));
+    sqlx::query("UPDATE voice_recordings SET stopped_at = ?")
+        .bind((chrono::Utc::now() - chrono::Duration::minutes(1)).to_rfc3339())
+        .execute(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(process_recordings(&state.pool, &state.file_storage, &state.broadcaster).await, Ok(1));
+
+    let recordings = format!("/api/rooms/{room}/voice/recordings");
+    let (status, _) = call_json(&app, alice.sign(TestRequest::get().uri(&recordings))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, listed) = call_json(&app, admin.sign(TestRequest::get().uri(&recordings))).await;
+    let ready = &listed["recordings"][0];
+    assert_eq!(ready["status"], "ready", "{ready}");
+    let res = test::call_service(&app, admin.sign(TestRequest::get().uri(ready["download_url"].as_str().unwrap())).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let archive = test::read_body(res).await;
+    assert_eq!(archive.len() as i64, ready["size_bytes"].as_i64().unwrap());
+    let end = &archive[archive.len() - 22..];
+    assert!(end.starts_with(b"PK\x05\x06"));
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+    let contains = |needle: &[u8]| archive.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"OggS alice 0;alice 1;alice 2;"));
+    assert!(contains(format!("tracks/alice-{}.ogg", alice.id).as_bytes()));
+    assert!(!contains(b"bob 0"));
+    let segments: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM voice_recording_segments").fetch_one(&state.pool).await.unwrap();
+    assert_eq!(segments, 0);
+
+    let one = format!("{recordings}/{}", ready["id"].as_str().unwrap());
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&one))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, listed) = call_json(&app, admin.sign(TestRequest::get().uri(&recordings))).await;
+    assert_eq!(listed["recordings"], json!([]));
+    std::fs::remove_dir_all(storage_dir(&state)).ok();
+}
@@ -0,0 +1,42 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_user, init_app, test_state};
+use serde_json::json;
+
+#[actix_web::test]
+async fn voice_settings_follow_the_user_across_devices() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let settings = "/api/users/@me/voice-settings";
+
+    let (status, defaults) = call_json(&app, alice.sign(TestRequest::get().uri(settings))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(defaults["input_mode"], "voice_activity");
+    assert_eq!(defaults["vad_automatic"], true);
+    assert!(defaults["updated_at"].is_null());
+
+    let patch = |body: serde_json::Value| TestRequest::patch().uri(settings).set_json(body);
+    for invalid in [json!({ "input_mode": "always_on" }), json!({ "vad_threshold_db": 6 }), json!({ "noise_suppression": "max" })] {
+        let (status, _) = call_json(&app, alice.sign(patch(invalid))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let mut events = state.broadcaster.subscribe();
+    let (status, changed) = call_json(&app, alice.sign(patch(json!({ "input_mode": "push_to_talk", "push_to_talk_release_ms": 350 })))).await;
+    assert_eq!(status, StatusCode::OK, "{changed}");
+    assert_eq!(changed["input_mode"], "push_to_talk");
+    assert_eq!(changed["noise_suppression"], "standard");
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event["type"], "voice_settings_updated");
+    assert_eq!(event["recipient_id"], alice.id.as_str());
+    assert_eq!(event["settings"], changed);
+
+    // A later change from another device keeps the rest.
+    let (_, changed) = call_json(&app, alice.sign(patch(json!({ "vad_automatic": false, "vad_threshold_db": -42 })))).await;
+    let (_, stored) = call_json(&app, alice.sign(TestRequest::get().uri(settings))).await;
+    assert_eq!(stored, changed);
+    assert_eq!(stored["input_mode"], "push_to_talk");
+    assert_eq!(stored["push_to_talk_release_ms"], 350);
+    assert_eq!(stored["vad_threshold_db"], -42);
+}
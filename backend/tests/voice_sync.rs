@@ -0,0 +1,73 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::auth::Claims;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state, TestUser};
+use backend::voice_sync::{publish, ActivityUpdate, SyncRefusal};
+use serde_json::json;
+use std::time::Duration;
+
+fn claims(user: &TestUser) -> Claims {
+    Claims {
+        sub: user.id.clone(),
+        username: user.username.clone(),
+        role: user.role.clone(),
+        exp: usize::MAX,
+        sid: String::new(),
+        sudo_until: None,
+    }
+}
+
+fn update(room_id: &str, state: serde_json::Value, expected_seq: Option<u64>) -> ActivityUpdate {
+    ActivityUpdate { room_id: room_id.to_string(), activity: "youtube".to_string(), state, expected_seq }
+}
+
+#[actix_web::test]
+async fn activity_state_is_ordered_and_rate_limited_in_voice() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let bob = create_user(&state.pool, "bob", "user").await;
+    let room = create_room(&state.pool, "lounge", "user").await;
+    let (sync, occupancy, broadcaster) = (&state.voice_sync, &state.voice_occupancy, &state.broadcaster);
+    let playing = json!({ "video": "dQw4w9WgXcQ", "position": 12.5, "paused": false });
+
+    let refused = publish(sync, occupancy, broadcaster, &claims(&alice), update(&room, playing.clone(), None));
+    assert!(matches!(refused, Err(SyncRefusal::NotInVoice)));
+    for user in [&alice, &bob] {
+        occupancy.lock().unwrap().entry(room.clone()).or_default().insert(user.id.clone(), 1);
+    }
+
+    let mut events = broadcaster.subscribe();
+    let seq = publish(sync, occupancy, broadcaster, &claims(&alice), update(&room, playing.clone(), None)).unwrap();
+    assert_eq!(seq, 1);
+    let event: serde_json::Value = serde_json::from_str(&events.recv().await.unwrap()).unwrap();
+    assert_eq!(event["type"], "activity_state");
+    assert_eq!(event["room_id"], room.as_str());
+    assert_eq!(event["user_id"], alice.id.as_str());
+    assert_eq!(event["seq"], 1);
+    assert_eq!(event["state"], playing);
+
+    let refused = publish(sync, occupancy, broadcaster, &claims(&alice), update(&room, json!({ "paused": true }), None));
+    let Err(SyncRefusal::RateLimited(wait)) = refused else { panic!("not rate limited: {refused:?}") };
+    assert_eq!(SyncRefusal::RateLimited(wait).error(&room)["code"], "rate_limited");
+    // Bob moved on top of seq 1 before Alice's stale move.
+    assert_eq!(publish(sync, occupancy, broadcaster, &claims(&bob), update(&room, json!({ "paused": true }), Some(1))).unwrap(), 2);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let refused = publish(sync, occupancy, broadcaster, &claims(&alice), update(&room, json!({ "paused": false }), Some(1)));
+    assert!(matches!(refused, Err(SyncRefusal::SeqConflict(2))));
+    let huge = json!({ "blob": "x".repeat(10_000) });
+    assert!(matches!(publish(sync, occupancy, broadcaster, &claims(&alice), update(&room, huge, None)), Err(SyncRefusal::TooLarge(8192))));
+
+    // Late joiners read the current state.
+    let uri = format!("/api/rooms/{room}/voice/activity");
+    let (status, current) = call_json(&app, bob.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(current["activity"]["activity"], "youtube");
+    assert_eq!(current["activity"]["seq"], 2);
+    assert_eq!(current["activity"]["state"], json!({ "paused": true }));
+
+    // The activity ends with the room's voice.
+    occupancy.lock().unwrap().remove(&room);
+    let (_, current) = call_json(&app, bob.sign(TestRequest::get().uri(&uri))).await;
+    assert_eq!(current["activity"], serde_json::Value::Null);
+}
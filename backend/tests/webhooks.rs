@@ -0,0 +1,74 @@
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use backend::test_support::{call_json, create_room, create_user, init_app, test_state};
+
+#[actix_web::test]
+async fn room_managers_create_webhooks_that_post_with_discord_bodies() {
+    let state = test_state().await;
+    let app = init_app(&state).await;
+    let admin = create_user(&state.pool, "root", "admin").await;
+    let alice = create_user(&state.pool, "alice", "user").await;
+    let room = create_room(&state.pool, "ci", "user").await;
+
+    let create = |name: &str| {
+        TestRequest::post().uri(&format!("/api/rooms/{room}/webhooks")).set_json(serde_json::json!({ "name": name }))
+    };
+    let (status, _) = call_json(&app, alice.sign(create("Builds"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, webhook) = call_json(&app, admin.sign(create("Builds"))).await;
+    assert_eq!(status, StatusCode::CREATED, "{webhook}");
+    let id = webhook["id"].as_str().unwrap();
+    let token = webhook["token"].as_str().unwrap();
+    assert!(webhook["url"].as_str().unwrap().ends_with(&format!("/api/webhooks/{id}/{token}")));
+
+    let (_, listed) = call_json(&app, admin.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/webhooks")))).await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert!(listed[0].get("token").is_none());
+
+    let execute = |token: &str, query: &str, body: serde_json::Value| {
+        TestRequest::post().uri(&format!("/api/webhooks/{id}/{token}{query}")).set_json(body)
+    };
+    let (status, _) = call_json(&app, execute("wrong", "", serde_json::json!({ "content": "hi" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call_json(&app, execute(token, "", serde_json::json!({ "tts": false }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call_json(&app, execute(token, "", serde_json::json!({ "content": "Build #1 started" }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let body = serde_json::json!({
+        "username": "GitHub Actions",
+        "avatar_url": "https://example.com/gh.png",
+        "embeds": [{
+            "title": "Build #1 passed",
+            "url": "https://ci.example.com/1",
+            "fields": [{ "name": "Branch", "value": "main" }],
+            "footer": { "text": "CI" },
+        }],
+    });
+    let (status, message) = call_json(&app, execute(token, "?wait=true", body)).await;
+    assert_eq!(status, StatusCode::OK, "{message}");
+    assert_eq!(message["username"], "GitHub Actions");
+    assert_eq!(message["embeds"][0]["description"], "Branch: main");
+    assert_eq!(message["embeds"][0]["site_name"], "CI");
+
+    let (_, history) = call_json(&app, alice.sign(TestRequest::get().uri(&format!("/api/rooms/{room}/messages")))).await;
+    let history = history["messages"].as_array().unwrap();
+    let last = history.last().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(last["webhook_id"], id);
+    assert_eq!(last["avatar_url"], "https://example.com/gh.png");
+    assert_eq!(history[0]["username"], "Builds");
+
+    // The room's slowmode holds webhooks back like any other author.
+    sqlx::query("UPDATE rooms SET slowmode_seconds = 60 WHERE id = ?").bind(&room).execute(&state.pool).await.unwrap();
+    let (status, _) = call_json(&app, execute(token, "", serde_json::json!({ "content": "first" }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, waiting) = call_json(&app, execute(token, "", serde_json::json!({ "content": "second" }))).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(waiting["retry_after"].as_u64().unwrap() > 0);
+
+    let (status, _) = call_json(&app, admin.sign(TestRequest::delete().uri(&format!("/api/webhooks/{id}")))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = call_json(&app, execute(token, "", serde_json::json!({ "content": "still here?" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
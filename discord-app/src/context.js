@@ -104,6 +104,8 @@ window.VoxiumContext = (() => {
             dom.ctxDeleteMessage.classList.add("hidden");
             dom.ctxPromoteAdmin.classList.add("hidden");
             dom.ctxPurgeUserMessages.classList.add("hidden");
+            dom.ctxFriend?.classList.add("hidden");
+            dom.ctxBlockUser?.classList.add("hidden");
 
             if (type === "room") {
                 if (state.role === "admin") {
@@ -111,6 +113,17 @@ window.VoxiumContext = (() => {
                     dom.ctxRoomSettings.classList.remove("hidden");
                 }
             } else if (type === "user") {
+                if (id !== state.userId && dom.ctxFriend && dom.ctxBlockUser) {
+                    const relationship = state.relationships?.[id]?.type;
+                    dom.ctxFriendLabel.textContent = {
+                        friend: "Retirer l'ami",
+                        pending_incoming: "Accepter la demande d'ami",
+                        pending_outgoing: "Annuler la demande d'ami",
+                    }[relationship] || "Ajouter en ami";
+                    dom.ctxBlockLabel.textContent = relationship === "blocked" ? "Débloquer" : "Bloquer";
+                    dom.ctxFriend.classList.toggle("hidden", relationship === "blocked");
+                    dom.ctxBlockUser.classList.remove("hidden");
+                }
                 if (state.role === "admin" && id !== state.userId) {
                     dom.ctxPromoteAdmin.classList.remove("hidden");
                     dom.ctxPurgeUserMessages.classList.remove("hidden");
@@ -248,6 +261,43 @@ window.VoxiumContext = (() => {
                 });
             }
 
+            dom.ctxFriend?.addEventListener("click", async () => {
+                const state = getState();
+                if (!ctxTarget || ctxTarget.type !== "user") return;
+                dom.contextMenu.classList.add("hidden");
+                const relationship = state.relationships?.[ctxTarget.id]?.type;
+                const removing = relationship === "friend" || relationship === "pending_outgoing";
+                if (relationship === "friend" && !confirm(`Retirer ${ctxTarget.name} de vos amis ?`)) return;
+                try {
+                    const res = await fetch(`${deps.API}/api/users/@me/friends/${ctxTarget.id}`, {
+                        method: removing ? "DELETE" : "PUT",
+                        headers: { Authorization: `Bearer ${state.token}` }
+                    });
+                    if (!res.ok) {
+                        const data = await res.json().catch(() => ({}));
+                        alert(data.error || "Erreur");
+                    }
+                } catch (e) { alert("Erreur réseau"); }
+            });
+
+            dom.ctxBlockUser?.addEventListener("click", async () => {
+                const state = getState();
+                if (!ctxTarget || ctxTarget.type !== "user") return;
+                dom.contextMenu.classList.add("hidden");
+                const blocked = state.relationships?.[ctxTarget.id]?.type === "blocked";
+                if (!blocked && !confirm(`Bloquer ${ctxTarget.name} ? Ses messages seront masqués.`)) return;
+                try {
+                    const res = await fetch(`${deps.API}/api/users/@me/blocks/${ctxTarget.id}`, {
+                        method: blocked ? "DELETE" : "PUT",
+                        headers: { Authorization: `Bearer ${state.token}` }
+                    });
+                    if (!res.ok) {
+                        const data = await res.json().catch(() => ({}));
+                        alert(data.error || "Erreur");
+                    }
+                } catch (e) { alert("Erreur réseau"); }
+            });
+
             dom.ctxPromoteAdmin.addEventListener("click", async () => {
                 const state = getState();
                 if (!ctxTarget || ctxTarget.type !== "user") return;
@@ -51,6 +51,8 @@ let state = {
     avatarUrl: null,
     bannerUrl: null,
     presence: localStorage.getItem("presence") || "online",
+    customStatus: null,
+    relationships: {},
     about: "",
     currentRoomId: null,
     currentRoomName: null,
@@ -59,12 +61,18 @@ let state = {
     rooms: [],
     serverRoles: [],
     serverUsers: [],
+    announcements: [],
+    customEmojis: {},
     users: {},
     unreadByRoom: {},
     mentionByRoom: {},
     messageMetaById: {},
     replyingTo: null,
     pinnedMessageIds: new Set(),
+    // message id -> option indexes the user voted for (broadcasts don't carry them)
+    myPollVotes: {},
+    // room id -> time (ms) until which slowmode refuses our messages
+    slowmodeUntil: {},
     threadRootId: null,
     voice: createVoiceState(),
 };
@@ -119,6 +127,7 @@ const authDiscordQrPlaceholder = $("#auth-discord-qr-placeholder");
 const authDiscordQrStatus = $("#auth-discord-qr-status");
 const authDiscordCancelBtn = $("#auth-discord-cancel-btn");
 const authError = $("#auth-error");
+const authPasskeyBtn = $("#auth-passkey-btn");
 const tabLogin = $("#tab-login");
 const tabRegister = $("#tab-register");
 const roomsList = $("#rooms-list");
@@ -162,6 +171,12 @@ const serverUserSelect = $("#server-user-select");
 const serverRoleSelect = $("#server-role-select");
 const serverAssignBtn = $("#server-assign-btn");
 const serverSettingsFeedback = $("#server-settings-feedback");
+const serverAnnouncementForm = $("#server-announcement-form");
+const serverAnnouncementsList = $("#server-announcements-list");
+const serverEmojiForm = $("#server-emoji-form");
+const serverEmojisList = $("#server-emojis-list");
+const serverJobsList = $("#server-jobs-list");
+const announcementBanner = $("#announcement-banner");
 const userAvatar = $("#user-avatar");
 const selfStatusDot = $("#self-status-dot");
 const userName = $("#user-name");
@@ -169,6 +184,8 @@ const userDiscriminator = $("#user-discriminator");
 const muteBtn = $("#mute-btn");
 const deafenBtn = $("#deafen-btn");
 const deleteRoomBtn = $("#delete-room-btn");
+const slowmodeBtn = $("#slowmode-btn");
+const currentRoomSlowmode = $("#current-room-slowmode");
 const pinnedBtn = $("#pinned-btn");
 const membersList = $("#members-list");
 const memberCount = $("#member-count");
@@ -185,6 +202,10 @@ const voiceScreenBtn = $("#voice-screen-btn");
 const voiceScreenQualitySelect = $("#voice-screen-quality");
 const voiceScreenFpsSelect = $("#voice-screen-fps");
 const voiceMembersList = $("#voice-members-list");
+const voiceRecordingWrap = $("#voice-recording-wrap");
+const voiceRecordingStatus = $("#voice-recording-status");
+const voiceRecordingConsentBtn = $("#voice-recording-consent-btn");
+const voiceRecordingToggleBtn = $("#voice-recording-toggle-btn");
 const voiceScreensWrap = $("#voice-screens-wrap");
 const voiceScreensGrid = $("#voice-screens-grid");
 const voiceQuickStatus = $("#voice-quick-status");
@@ -229,6 +250,7 @@ const sidebarHeaderText = document.querySelector(".sidebar-header > span");
 const homeGuild = $("#home-guild");
 
 let voiceController = null;
+let recordingController = null;
 
 function getScreenQualityPreset(value) {
     return voiceController.getScreenQualityPreset(value);
@@ -284,6 +306,7 @@ const settingsRoleBadge = $("#settings-role-badge");
 const settingsStatusDot = $("#settings-status-dot");
 const previewStatusDot = $("#preview-status-dot");
 const presenceSelect = $("#presence-select");
+const customStatusInput = $("#custom-status-input");
 const avatarColorPicker = $("#avatar-color-picker");
 const settingsAvatarColorInput = $("#settings-avatar-color");
 const settingsFeedback = $("#settings-feedback");
@@ -314,6 +337,10 @@ const ctxRoomSettings = $("#ctx-room-settings");
 const ctxDeleteMessage = $("#ctx-delete-message");
 const ctxPromoteAdmin = $("#ctx-promote-admin");
 const ctxPurgeUserMessages = $("#ctx-purge-user-messages");
+const ctxFriend = $("#ctx-friend");
+const ctxFriendLabel = $("#ctx-friend-label");
+const ctxBlockUser = $("#ctx-block-user");
+const ctxBlockLabel = $("#ctx-block-label");
 
 let contextController = null;
 
@@ -332,6 +359,8 @@ const popoutBadges = $("#popout-badges");
 
 const pinnedModal = $("#pinned-modal");
 const pinnedList = $("#pinned-list");
+const messageHistoryModal = $("#message-history-modal");
+const messageHistoryList = $("#message-history-list");
 const pinnedCloseBtn = $("#pinned-close-btn");
 const threadPanel = $("#thread-panel");
 const threadCloseBtn = $("#thread-close-btn");
@@ -343,6 +372,9 @@ const threadInput = $("#thread-input");
 // Chat search
 const chatSearch = $("#chat-search");
 const searchModal = $("#search-modal");
+const quickSwitchModal = $("#quickswitch-modal");
+const quickSwitchInput = $("#quickswitch-input");
+const quickSwitchResults = $("#quickswitch-results");
 const searchQueryInput = $("#search-query");
 const searchAuthorInput = $("#search-author");
 const searchFromInput = $("#search-from");
@@ -352,6 +384,17 @@ const searchResults = $("#search-results");
 const searchCloseBtn = $("#search-close-btn");
 const searchRunBtn = $("#search-run-btn");
 const globalMentionBadge = $("#global-mention-badge");
+const recentMentionsModal = $("#recent-mentions-modal");
+const recentMentionsList = $("#recent-mentions-list");
+const recentMentionsMoreBtn = $("#recent-mentions-more-btn");
+const pollBtn = $("#poll-btn");
+const createPollModal = $("#create-poll-modal");
+const createPollForm = $("#create-poll-form");
+const pollQuestionInput = $("#poll-question-input");
+const pollOptionsInput = $("#poll-options-input");
+const pollDurationInput = $("#poll-duration-input");
+const pollMultiInput = $("#poll-multi-input");
+const cancelPollBtn = $("#cancel-poll-btn");
 
 // Members toggle
 const membersToggleBtn = $("#members-toggle-btn");
@@ -381,23 +424,36 @@ function updateGlobalMentionBadge() {
 
 // ── Auth Mode ──────────────────────────────────────────
 let authMode = "login";
+// Set when the password checked out and the account asks for a 2FA code.
+let twoFactorTicket = null;
+
+function resetTwoFactorStep() {
+    twoFactorTicket = null;
+    $("#auth-totp-group")?.classList.add("hidden");
+    $("#auth-totp").value = "";
+}
 
 tabLogin.addEventListener("click", () => {
+    resetTwoFactorStep();
     authMode = "login";
     tabLogin.classList.add("active");
     tabRegister.classList.remove("active");
     authSubmit.textContent = "Se connecter";
+    $("#auth-email-group")?.classList.add("hidden");
 });
 
 tabRegister.addEventListener("click", () => {
+    resetTwoFactorStep();
     authMode = "register";
     tabRegister.classList.add("active");
     tabLogin.classList.remove("active");
     authSubmit.textContent = "S'inscrire";
+    $("#auth-email-group")?.classList.remove("hidden");
 });
 
 // ── Discord QR Auth (server-side flow) ──────────────────
 let discordQrSessionId = null;
+let discordQrSessionSecret = null;
 let discordQrPollTimer = null;
 
 function setDiscordQrStatus(message, isError = false) {
@@ -433,13 +489,17 @@ function stopDiscordQrPoll() {
 function cleanupDiscordQr() {
     stopDiscordQrPoll();
     discordQrSessionId = null;
+    discordQrSessionSecret = null;
 }
 
 function cancelDiscordQrAuth(message = "Connexion QR annulée.") {
     if (discordQrSessionId) {
         fetch(`${API}/api/auth/discord/qr/cancel`, {
             method: "POST",
-            headers: { "Content-Type": "application/json" },
+            headers: {
+                "Content-Type": "application/json",
+                "X-QR-Session-Secret": discordQrSessionSecret || "",
+            },
             body: JSON.stringify({ session_id: discordQrSessionId }),
         }).catch(() => { });
     }
@@ -463,6 +523,7 @@ async function startDiscordQrAuth() {
             throw new Error(data.error || "Impossible de démarrer la session QR.");
         }
         discordQrSessionId = data.session_id;
+        discordQrSessionSecret = data.session_secret;
     } catch (err) {
         setDiscordQrStatus(err.message || "Erreur démarrage QR.", true);
         return;
@@ -472,7 +533,9 @@ async function startDiscordQrAuth() {
     discordQrPollTimer = setInterval(async () => {
         if (!discordQrSessionId) { stopDiscordQrPoll(); return; }
         try {
-            const res = await fetch(`${API}/api/auth/discord/qr/status?session_id=${encodeURIComponent(discordQrSessionId)}`);
+            const res = await fetch(`${API}/api/auth/discord/qr/status?session_id=${encodeURIComponent(discordQrSessionId)}`, {
+                headers: { "X-QR-Session-Secret": discordQrSessionSecret || "" },
+            });
             const status = await res.json();
             if (!res.ok) { throw new Error(status.error || "Session expirée."); }
 
@@ -516,6 +579,11 @@ async function startDiscordQrAuth() {
                     setDiscordQrStatus("Connexion annulée.", true);
                     cleanupDiscordQr();
                     break;
+                case "expired_restart":
+                    stopDiscordQrPoll();
+                    setDiscordQrStatus("Le serveur a redémarré, relancez la connexion QR.", true);
+                    cleanupDiscordQr();
+                    break;
             }
         } catch (err) {
             stopDiscordQrPoll();
@@ -546,18 +614,42 @@ authForm.addEventListener("submit", async (e) => {
     if (!username || !password) return;
 
     try {
-        const res = await fetch(`${API}/api/${authMode}`, {
-            method: "POST",
-            headers: { "Content-Type": "application/json" },
-            body: JSON.stringify({ username, password }),
-        });
+        const res = twoFactorTicket
+            ? await fetch(`${API}/api/auth/2fa/login`, {
+                method: "POST",
+                headers: { "Content-Type": "application/json" },
+                body: JSON.stringify({ ticket: twoFactorTicket, code: $("#auth-totp").value.trim() }),
+            })
+            : await fetch(`${API}/api/${authMode}`, {
+                method: "POST",
+                headers: { "Content-Type": "application/json" },
+                body: JSON.stringify(authMode === "register"
+                    ? { username, password, email: $("#auth-email").value.trim() || undefined }
+                    : { username, password }),
+            });
         const data = await res.json();
         if (!res.ok) {
             authError.textContent = data.error || "Erreur d'authentification";
+            // Expired or exhausted ticket: start over from the password.
+            if (twoFactorTicket && !/Invalid code/.test(data.error || "")) resetTwoFactorStep();
+            return;
+        }
+        if (data.two_factor_required) {
+            twoFactorTicket = data.ticket;
+            $("#auth-totp-group").classList.remove("hidden");
+            $("#auth-totp").focus();
             return;
         }
+        resetTwoFactorStep();
         saveSession(data);
-        enterApp();
+        await enterApp();
+        if (authMode === "register" && $("#auth-email").value.trim()) {
+            showToast("Un lien de vérification a été envoyé à votre adresse e-mail", "success", 6000);
+        }
+        if (data.two_factor_setup_required) {
+            showToast("Votre compte exige l'authentification à deux facteurs, activez-la dans Mon compte", "error", 8000);
+            settingsBtn.click();
+        }
     } catch (err) {
         authError.textContent = "Impossible de contacter le serveur";
     }
@@ -573,19 +665,139 @@ function saveSession(data) {
     if (data.banner_url !== undefined) state.bannerUrl = data.banner_url;
     if (data.about !== undefined) state.about = data.about;
     localStorage.setItem("token", data.token);
+    if (data.refresh_token) localStorage.setItem("refreshToken", data.refresh_token);
     localStorage.setItem("userId", data.user_id);
     localStorage.setItem("username", data.username);
 }
 
+// ── Access token refresh ───────────────────────────────
+// Access tokens expire after a few minutes. When our API answers 401 to an
+// authenticated request, swap the refresh token for a new pair (once, shared
+// by concurrent requests) and replay the request with the new token.
+const nativeFetch = window.fetch.bind(window);
+let refreshInFlight = null;
+
+function refreshAccessToken() {
+    const refreshToken = localStorage.getItem("refreshToken");
+    if (!refreshToken) return Promise.resolve(false);
+    if (!refreshInFlight) {
+        refreshInFlight = nativeFetch(`${API.replace(/\/$/, "")}/api/auth/refresh`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ refresh_token: refreshToken }),
+        })
+            .then(async (res) => {
+                if (!res.ok) {
+                    if (res.status === 401) localStorage.removeItem("refreshToken");
+                    return false;
+                }
+                const data = await res.json();
+                state.token = data.token;
+                localStorage.setItem("token", data.token);
+                localStorage.setItem("refreshToken", data.refresh_token);
+                return true;
+            })
+            .catch(() => false)
+            .finally(() => { refreshInFlight = null; });
+    }
+    return refreshInFlight;
+}
+
+// ── Sudo mode ──────────────────────────────────────────
+// Sensitive endpoints answer 403 { sudo_required } unless the access token
+// was recently re-confirmed. Ask for a passkey, password or 2FA code, swap
+// the token for the elevated one and let the caller's request be replayed.
+async function confirmIdentity() {
+    const statusRes = await nativeFetch(`${API}/api/auth/sudo`, {
+        headers: { Authorization: `Bearer ${state.token}` },
+    });
+    if (!statusRes.ok) return false;
+    const { methods } = await statusRes.json();
+
+    let payload = null;
+    if (methods.includes("passkey") && passkeysSupported()
+        && confirm("Confirmez votre identité avec une clé d'accès ?")) {
+        const startRes = await nativeFetch(`${API}/api/auth/passkey/login/start`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ username: state.username }),
+        });
+        const options = await startRes.json();
+        const credential = await navigator.credentials.get({
+            publicKey: {
+                ...options,
+                challenge: b64urlToBuffer(options.challenge),
+                allowCredentials: (options.allowCredentials || []).map((c) => ({ ...c, id: b64urlToBuffer(c.id) })),
+            },
+        }).catch(() => null);
+        if (!credential) return false;
+        payload = {
+            passkey: {
+                id: credential.id,
+                response: {
+                    clientDataJSON: bufferToB64url(credential.response.clientDataJSON),
+                    authenticatorData: bufferToB64url(credential.response.authenticatorData),
+                    signature: bufferToB64url(credential.response.signature),
+                    userHandle: credential.response.userHandle ? bufferToB64url(credential.response.userHandle) : null,
+                },
+            },
+        };
+    } else {
+        const withCode = methods.includes("totp");
+        const secret = prompt(withCode
+            ? "Confirmez votre identité : mot de passe, code 2FA ou code de secours"
+            : "Confirmez votre identité avec votre mot de passe");
+        if (!secret) return false;
+        const looksLikeCode = /^\d{6}$|^[A-Za-z0-9]{4}-[A-Za-z0-9]{4}$/.test(secret.trim());
+        payload = withCode && looksLikeCode ? { code: secret } : { password: secret };
+    }
+
+    const res = await nativeFetch(`${API}/api/auth/sudo`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+        body: JSON.stringify(payload),
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) {
+        showToast(data.error || "Identité non confirmée", "error");
+        return false;
+    }
+    state.token = data.token;
+    localStorage.setItem("token", data.token);
+    return true;
+}
+
+window.fetch = async (input, init = {}) => {
+    const res = await nativeFetch(input, init);
+    const url = typeof input === "string" ? input : input?.url || "";
+    const headers = new Headers(init.headers || {});
+    if (
+        (res.status !== 401 && res.status !== 403)
+        || !url.startsWith(API)
+        || url.includes("/api/auth/refresh")
+        || !headers.get("Authorization")?.startsWith("Bearer ")
+    ) {
+        return res;
+    }
+    if (res.status === 403) {
+        const body = await res.clone().json().catch(() => null);
+        if (!body?.sudo_required || !(await confirmIdentity())) return res;
+    } else if (!(await refreshAccessToken())) {
+        return res;
+    }
+    headers.set("Authorization", `Bearer ${state.token}`);
+    return nativeFetch(input, { ...init, headers });
+};
+
 function normalizePresence(value) {
     const v = (value || "").toLowerCase();
-    if (v === "online" || v === "idle" || v === "dnd" || v === "invisible") return v;
+    if (v === "online" || v === "idle" || v === "dnd" || v === "invisible" || v === "offline") return v;
     return "online";
 }
 
 function presenceDotClass(value) {
     const normalized = normalizePresence(value);
-    if (normalized === "invisible") return "offline";
+    if (normalized === "invisible" || normalized === "offline") return "offline";
     return normalized;
 }
 
@@ -593,7 +805,7 @@ function presenceLabel(value) {
     const normalized = normalizePresence(value);
     if (normalized === "idle") return "Absent";
     if (normalized === "dnd") return "Ne pas déranger";
-    if (normalized === "invisible") return "Hors ligne";
+    if (normalized === "invisible" || normalized === "offline") return "Hors ligne";
     return "En ligne";
 }
 
@@ -610,6 +822,9 @@ function applyOwnPresenceUI() {
     if (presenceSelect) {
         presenceSelect.value = normalizePresence(state.presence);
     }
+    if (customStatusInput && document.activeElement !== customStatusInput) {
+        customStatusInput.value = state.customStatus || "";
+    }
 }
 
 function syncOwnPresenceInUsersMap() {
@@ -623,9 +838,11 @@ function syncOwnPresenceInUsersMap() {
             role: state.role || "user",
             about: state.about || null,
             status: normalizePresence(state.presence),
+            custom_status: state.customStatus || null,
         };
     } else {
         state.users[state.userId].status = normalizePresence(state.presence);
+        state.users[state.userId].custom_status = state.customStatus || null;
     }
 }
 
@@ -661,6 +878,594 @@ function broadcastPresence() {
     });
 }
 
+/** The status and custom status stored on the server win over localStorage. */
+async function loadOwnPresence() {
+    try {
+        const res = await fetch(`${API}/api/users/@me/presence`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        if (!res.ok) return;
+        const data = await res.json();
+        state.customStatus = data.custom_status || null;
+        applyOwnPresenceState(data.status, false);
+    } catch (err) {
+        console.error("Failed to load presence", err);
+    }
+}
+
+async function saveCustomStatus(text) {
+    try {
+        const res = await fetch(`${API}/api/users/@me/presence`, {
+            method: "PUT",
+            headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+            body: JSON.stringify({ custom_status: text.trim() || null })
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            showToast(data.error || "Impossible d'enregistrer le statut personnalisé");
+            return;
+        }
+        state.customStatus = data.custom_status || null;
+        applyOwnPresenceState(data.status, false);
+    } catch (err) {
+        console.error("Failed to save custom status", err);
+    }
+}
+
+/**
+ * Every page of a listing, following its `X-Next-Cursor` header.
+ * Resolves to `{ ok, status, items, error }`; stops at the first failed page.
+ */
+async function fetchAllPages(url, maxPages = 50) {
+    const items = [];
+    let cursor = null;
+    for (let i = 0; i < maxPages; i++) {
+        const pageUrl = cursor ? `${url}${url.includes("?") ? "&" : "?"}cursor=${encodeURIComponent(cursor)}` : url;
+        const res = await fetch(pageUrl, { headers: { Authorization: `Bearer ${state.token}` } });
+        const data = await res.json().catch(() => []);
+        if (!res.ok) return { ok: false, status: res.status, items, error: data.error };
+        if (Array.isArray(data)) items.push(...data);
+        cursor = res.headers.get("X-Next-Cursor");
+        if (!cursor) break;
+    }
+    return { ok: true, status: 200, items };
+}
+
+/** Who else is connected among the room's members, for users who joined before us. */
+async function loadRoomMembers(roomId) {
+    try {
+        const page = await fetchAllPages(`${API}/api/rooms/${roomId}/members`);
+        if (!page.ok || roomId !== state.currentRoomId) return;
+        const members = page.items;
+        members.forEach((m) => {
+            if (m.id === state.userId || m.status === "offline") return;
+            state.users[m.id] = {
+                ...(state.users[m.id] || {}),
+                username: m.username,
+                avatar_color: m.avatar_color || 0,
+                avatar_url: m.avatar_url || null,
+                role: m.role || "user",
+                role_color: m.role_color || null,
+                role_icon_url: m.role_icon_url || null,
+                role_hoist: Boolean(m.role_hoist),
+                status: normalizePresence(m.status),
+                custom_status: m.custom_status || null,
+            };
+        });
+        scheduleMembersRender();
+    } catch (err) {
+        console.error("Failed to load room members", err);
+    }
+}
+
+// ── Passkeys (WebAuthn) ────────────────────────────────
+function b64urlToBuffer(value) {
+    const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+    const binary = atob(base64 + "=".repeat((4 - (base64.length % 4)) % 4));
+    return Uint8Array.from(binary, (c) => c.charCodeAt(0)).buffer;
+}
+
+function bufferToB64url(buffer) {
+    const bytes = new Uint8Array(buffer);
+    let binary = "";
+    bytes.forEach((b) => { binary += String.fromCharCode(b); });
+    return btoa(binary).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}
+
+function passkeysSupported() {
+    return !!(window.PublicKeyCredential && navigator.credentials);
+}
+
+async function loginWithPasskey() {
+    authError.textContent = "";
+    if (!passkeysSupported()) {
+        authError.textContent = "Les clés d'accès ne sont pas prises en charge ici";
+        return;
+    }
+    try {
+        const username = authUsername.value.trim();
+        const startRes = await fetch(`${API}/api/auth/passkey/login/start`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify(username ? { username } : {}),
+        });
+        const options = await startRes.json();
+        const credential = await navigator.credentials.get({
+            publicKey: {
+                ...options,
+                challenge: b64urlToBuffer(options.challenge),
+                allowCredentials: (options.allowCredentials || []).map((c) => ({ ...c, id: b64urlToBuffer(c.id) })),
+            },
+        });
+        if (!credential) return;
+
+        const res = await fetch(`${API}/api/auth/passkey/login/finish`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({
+                credential: {
+                    id: credential.id,
+                    response: {
+                        clientDataJSON: bufferToB64url(credential.response.clientDataJSON),
+                        authenticatorData: bufferToB64url(credential.response.authenticatorData),
+                        signature: bufferToB64url(credential.response.signature),
+                        userHandle: credential.response.userHandle ? bufferToB64url(credential.response.userHandle) : null,
+                    },
+                },
+            }),
+        });
+        const data = await res.json();
+        if (!res.ok) {
+            authError.textContent = data.error || "Connexion par clé d'accès refusée";
+            return;
+        }
+        saveSession(data);
+        enterApp();
+    } catch (err) {
+        if (err?.name !== "NotAllowedError") {
+            authError.textContent = "Connexion par clé d'accès impossible";
+        }
+    }
+}
+
+async function addPasskey() {
+    if (!passkeysSupported()) {
+        showToast("Les clés d'accès ne sont pas prises en charge ici", "error");
+        return;
+    }
+    try {
+        const startRes = await fetch(`${API}/api/auth/passkey/register/start`, {
+            method: "POST",
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!startRes.ok) throw new Error("start failed");
+        const options = await startRes.json();
+        const credential = await navigator.credentials.create({
+            publicKey: {
+                ...options,
+                challenge: b64urlToBuffer(options.challenge),
+                user: { ...options.user, id: b64urlToBuffer(options.user.id) },
+                excludeCredentials: (options.excludeCredentials || []).map((c) => ({ ...c, id: b64urlToBuffer(c.id) })),
+            },
+        });
+        if (!credential) return;
+
+        const res = await fetch(`${API}/api/auth/passkey/register/finish`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+            body: JSON.stringify({
+                name: navigator.userAgentData?.platform || navigator.platform || "Passkey",
+                credential: {
+                    id: credential.id,
+                    response: {
+                        clientDataJSON: bufferToB64url(credential.response.clientDataJSON),
+                        attestationObject: bufferToB64url(credential.response.attestationObject),
+                    },
+                },
+            }),
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            showToast(data.error || "Impossible d'ajouter la clé d'accès", "error");
+            return;
+        }
+        showToast("Clé d'accès ajoutée", "success");
+        loadPasskeys();
+    } catch (err) {
+        if (err?.name !== "NotAllowedError") {
+            showToast("Impossible d'ajouter la clé d'accès", "error");
+        }
+    }
+}
+
+async function loadPasskeys() {
+    const display = $("#acct-passkeys-display");
+    const list = $("#passkeys-list");
+    if (!display || !list) return;
+    try {
+        const res = await fetch(`${API}/api/users/@me/passkeys`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return;
+        const passkeys = await res.json();
+        display.textContent = passkeys.length ? `${passkeys.length} enregistrée(s)` : "Aucune";
+        list.innerHTML = "";
+        passkeys.forEach((passkey) => {
+            const row = document.createElement("div");
+            row.className = "account-field";
+            const lastUsed = passkey.last_used_at
+                ? `Utilisée le ${new Date(passkey.last_used_at).toLocaleDateString()}`
+                : "Jamais utilisée";
+            row.innerHTML = `
+                <div class="account-field-info">
+                    <div class="account-field-label">${escapeHtml(passkey.name)}</div>
+                    <div class="account-field-value">${escapeHtml(lastUsed)}</div>
+                </div>
+                <button class="btn-field-edit">Supprimer</button>`;
+            row.querySelector("button").addEventListener("click", async () => {
+                if (!confirm(`Supprimer la clé d'accès "${passkey.name}" ?`)) return;
+                await fetch(`${API}/api/users/@me/passkeys/${encodeURIComponent(passkey.id)}`, {
+                    method: "DELETE",
+                    headers: { Authorization: `Bearer ${state.token}` },
+                });
+                loadPasskeys();
+            });
+            list.appendChild(row);
+        });
+    } catch (err) {
+        console.error("Failed to load passkeys", err);
+    }
+}
+
+authPasskeyBtn?.addEventListener("click", loginWithPasskey);
+$("#btn-add-passkey")?.addEventListener("click", addPasskey);
+
+// ── Two-factor authentication (TOTP) ───────────────────
+let twoFactorEnabled = false;
+
+async function loadTwoFactorStatus() {
+    const display = $("#acct-totp-display");
+    const button = $("#btn-totp");
+    if (!display || !button) return;
+    $("#totp-setup").classList.add("hidden");
+    $("#totp-backup-codes").classList.add("hidden");
+    try {
+        const res = await fetch(`${API}/api/auth/2fa`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return;
+        const status = await res.json();
+        twoFactorEnabled = status.enabled;
+        display.textContent = status.enabled
+            ? `Activée · ${status.backup_codes_remaining} code(s) de secours restant(s)`
+            : (status.required ? "Requise par un administrateur" : "Désactivée");
+        button.textContent = status.enabled ? "Désactiver" : "Activer";
+        button.disabled = status.enabled && status.required;
+    } catch (err) {
+        console.error("Failed to load 2FA status", err);
+    }
+}
+
+async function toggleTwoFactor() {
+    if (twoFactorEnabled) {
+        const code = prompt("Code de vérification ou code de secours pour désactiver la 2FA :");
+        if (!code) return;
+        const res = await fetch(`${API}/api/auth/2fa/disable`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+            body: JSON.stringify({ code }),
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            showToast(data.error || "Impossible de désactiver la 2FA", "error");
+            return;
+        }
+        showToast("Authentification à deux facteurs désactivée", "success");
+        loadTwoFactorStatus();
+        return;
+    }
+
+    const res = await fetch(`${API}/api/auth/2fa/setup`, {
+        method: "POST",
+        headers: { Authorization: `Bearer ${state.token}` },
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) {
+        showToast(data.error || "Impossible de démarrer la configuration 2FA", "error");
+        return;
+    }
+    $("#totp-qr").src = data.qr_code;
+    $("#totp-secret").textContent = data.secret;
+    $("#totp-code").value = "";
+    $("#totp-setup").classList.remove("hidden");
+    $("#totp-code").focus();
+}
+
+async function confirmTwoFactor() {
+    const code = $("#totp-code").value.trim();
+    if (!code) return;
+    const res = await fetch(`${API}/api/auth/2fa/enable`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+        body: JSON.stringify({ code }),
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) {
+        showToast(data.error || "Code invalide", "error");
+        return;
+    }
+    await loadTwoFactorStatus();
+    $("#totp-backup-list").textContent = data.backup_codes.join("\n");
+    $("#totp-backup-codes").classList.remove("hidden");
+    showToast("Authentification à deux facteurs activée", "success");
+}
+
+$("#btn-totp")?.addEventListener("click", toggleTwoFactor);
+$("#btn-totp-confirm")?.addEventListener("click", confirmTwoFactor);
+
+// ── Email address ──────────────────────────────────────
+
+async function loadEmailStatus() {
+    const field = $("#acct-email-field");
+    if (!field) return;
+    try {
+        const res = await fetch(`${API}/api/users/@me/email`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return;
+        const status = await res.json();
+        field.classList.toggle("hidden", status.mode === "off");
+        $("#acct-email-display").textContent = status.email
+            ? `${status.email} · ${status.verified ? "vérifiée" : "non vérifiée"}`
+            : (status.mode === "required" ? "Requise pour créer des salons" : "Aucune adresse");
+        $("#btn-email-resend").classList.toggle("hidden", !status.email || status.verified);
+    } catch (err) {
+        console.error("Failed to load email status", err);
+    }
+}
+
+async function emailRequest(path, options, successMessage) {
+    const res = await fetch(`${API}${path}`, {
+        ...options,
+        headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) {
+        showToast(data.error || "Impossible d'envoyer l'e-mail de vérification", "error");
+        return;
+    }
+    showToast(successMessage, "success");
+    loadEmailStatus();
+}
+
+$("#btn-email-edit")?.addEventListener("click", () => {
+    const email = prompt("Nouvelle adresse e-mail :");
+    if (!email) return;
+    emailRequest("/api/users/@me/email", { method: "PUT", body: JSON.stringify({ email }) },
+        "Lien de vérification envoyé");
+});
+
+$("#btn-email-resend")?.addEventListener("click", () => {
+    emailRequest("/api/users/@me/email/resend", { method: "POST" }, "Lien de vérification renvoyé");
+});
+
+// ── Linked Discord account & security log ──────────────
+
+const SECURITY_EVENT_LABELS = {
+    discord_linked: "Compte Discord lié",
+    discord_unlinked: "Compte Discord délié",
+    qr_login_completed: "Connexion par QR code",
+    gateway_identify: "Connexion vocale à Discord",
+    recovery_codes_generated: "Codes de récupération générés",
+    recovery_requested: "Récupération du compte demandée",
+    recovery_approved: "Récupération approuvée",
+    recovery_cancelled: "Récupération annulée",
+    recovery_completed: "Compte récupéré",
+};
+
+async function loadDiscordLink() {
+    const display = $("#acct-discord-link-display");
+    const button = $("#btn-discord-unlink");
+    if (!display || !button) return;
+    try {
+        const res = await fetch(`${API}/api/discord/link/status`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return;
+        const link = await res.json();
+        display.textContent = {
+            linked: "Lié",
+            expired: "Expiré, reliez votre compte",
+            revoked: "Révoqué, reliez votre compte",
+            unlinked: "Non lié",
+        }[link.status] || link.status;
+        button.disabled = link.status === "unlinked";
+    } catch (err) {
+        console.error("Failed to load Discord link status", err);
+    }
+}
+
+async function loadSecurityLog() {
+    const list = $("#security-log-list");
+    if (!list) return;
+    try {
+        const res = await fetch(`${API}/api/users/@me/security-log?limit=20`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return;
+        const entries = await res.json();
+        list.innerHTML = "";
+        if (!entries.length) {
+            list.innerHTML = `<div class="account-field-value">Aucune activité enregistrée</div>`;
+            return;
+        }
+        entries.forEach((entry) => {
+            const row = document.createElement("div");
+            row.className = "account-field";
+            const label = SECURITY_EVENT_LABELS[entry.event] || entry.event;
+            const where = [entry.ip, entry.user_agent].filter(Boolean).join(" · ") || "Origine inconnue";
+            row.innerHTML = `
+                <div class="account-field-info">
+                    <div class="account-field-label">${escapeHtml(label)} — ${escapeHtml(new Date(entry.created_at).toLocaleString())}</div>
+                    <div class="account-field-value">${escapeHtml(where)}</div>
+                </div>`;
+            list.appendChild(row);
+        });
+    } catch (err) {
+        console.error("Failed to load security log", err);
+    }
+}
+
+$("#btn-discord-unlink")?.addEventListener("click", async () => {
+    if (!confirm("Délier votre compte Discord ? Voxium oubliera votre jeton Discord.")) return;
+    const res = await fetch(`${API}/api/discord/link`, {
+        method: "DELETE",
+        headers: { Authorization: `Bearer ${state.token}` },
+    });
+    if (!res.ok) {
+        showToast("Impossible de délier le compte Discord", "error");
+        return;
+    }
+    showToast("Compte Discord délié", "success");
+    loadDiscordLink();
+    loadSecurityLog();
+});
+
+// ── Account recovery ───────────────────────────────────
+
+async function loadRecoveryStatus() {
+    const display = $("#acct-recovery-display");
+    const list = $("#recovery-requests-list");
+    if (!display || !list) return;
+    try {
+        const res = await fetch(`${API}/api/users/@me/recovery`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return;
+        const status = await res.json();
+        const parts = [status.codes_remaining
+            ? `${status.codes_remaining} code(s) de récupération restant(s)`
+            : "Aucun code de récupération"];
+        if (status.email_recovery) parts.push(`par e-mail après ${status.delay_hours} h`);
+        display.textContent = parts.join(" · ");
+
+        list.innerHTML = "";
+        status.requests.forEach((request) => {
+            const row = document.createElement("div");
+            row.className = "account-field";
+            const where = [request.ip, request.user_agent].filter(Boolean).join(" · ") || "Origine inconnue";
+            const when = request.status === "approved"
+                ? "approuvée"
+                : `utilisable le ${new Date(request.usable_at).toLocaleString()}`;
+            row.innerHTML = `
+                <div class="account-field-info">
+                    <div class="account-field-label">RÉCUPÉRATION DEMANDÉE — ${escapeHtml(when)}</div>
+                    <div class="account-field-value">${escapeHtml(where)}</div>
+                </div>
+                ${request.status === "pending" ? `<button class="btn-field-edit" data-action="approve">Approuver</button>` : ""}
+                <button class="btn-field-edit" data-action="cancel">Annuler</button>`;
+            row.querySelector('[data-action="approve"]')?.addEventListener("click", () => {
+                if (!confirm("Approuver cette récupération ? Elle pourra être utilisée immédiatement.")) return;
+                recoveryRequestAction(`/api/users/@me/recovery/${request.id}/approve`, "POST", "Récupération approuvée");
+            });
+            row.querySelector('[data-action="cancel"]').addEventListener("click", () => {
+                recoveryRequestAction(`/api/users/@me/recovery/${request.id}`, "DELETE", "Récupération annulée");
+            });
+            list.appendChild(row);
+        });
+    } catch (err) {
+        console.error("Failed to load recovery status", err);
+    }
+}
+
+async function recoveryRequestAction(path, method, successMessage) {
+    const res = await fetch(`${API}${path}`, {
+        method,
+        headers: { Authorization: `Bearer ${state.token}` },
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) {
+        showToast(data.error || "Action impossible", "error");
+    } else {
+        showToast(successMessage, "success");
+    }
+    loadRecoveryStatus();
+    loadSecurityLog();
+}
+
+$("#btn-recovery-codes")?.addEventListener("click", async () => {
+    if (!confirm("Générer de nouveaux codes de récupération ? Les anciens ne fonctionneront plus.")) return;
+    const res = await fetch(`${API}/api/users/@me/recovery/codes`, {
+        method: "POST",
+        headers: { Authorization: `Bearer ${state.token}` },
+    });
+    if (!res.ok) {
+        showToast("Impossible de générer les codes de récupération", "error");
+        return;
+    }
+    const data = await res.json();
+    $("#recovery-codes-list").textContent = data.recovery_codes.join("\n");
+    $("#recovery-codes").classList.remove("hidden");
+    loadRecoveryStatus();
+    loadSecurityLog();
+});
+
+/** Lost password: mail a code to the verified address, or redeem a code. */
+async function recoverAccount() {
+    const email = prompt("Adresse e-mail vérifiée du compte (laissez vide si vous avez déjà un code) :");
+    if (email === null) return;
+    if (email.trim()) {
+        const res = await fetch(`${API}/api/auth/recovery/start`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ email: email.trim() }),
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            showToast(data.error || "Impossible de lancer la récupération", "error");
+            return;
+        }
+        showToast(`Si un compte correspond, un code a été envoyé. Il sera utilisable dans ${data.delay_hours} h.`, "success", 8000);
+        return;
+    }
+
+    const code = prompt("Code reçu par e-mail, ou code de récupération :")?.trim();
+    if (!code) return;
+    // Recovery codes are XXXX-XXXX, email tokens are much longer.
+    const isRecoveryCode = code.replace(/[\s-]/g, "").length === 8;
+    const body = {};
+    if (isRecoveryCode) {
+        const username = $("#auth-username").value.trim() || prompt("Nom d'utilisateur :")?.trim();
+        if (!username) return;
+        body.username = username;
+        body.code = code;
+    } else {
+        body.token = code;
+    }
+    const password = prompt("Nouveau mot de passe (8 caractères minimum) :");
+    if (!password) return;
+    body.new_password = password;
+
+    const res = await fetch(`${API}/api/auth/recovery/complete`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify(body),
+    });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) {
+        const message = data.usable_at
+            ? `Récupération en attente jusqu'au ${new Date(data.usable_at).toLocaleString()}`
+            : (data.error || "Récupération impossible");
+        showToast(message, "error", 8000);
+        return;
+    }
+    showToast(data.discord_relink_required
+        ? "Mot de passe changé. Connectez-vous puis reliez votre compte Discord."
+        : "Mot de passe changé, vous pouvez vous connecter.", "success", 8000);
+}
+
+$("#auth-recover-btn")?.addEventListener("click", recoverAccount);
+
 // ── Logout ─────────────────────────────────────────────
 function logout() {
     if (state.voice?.joinedRoomId) {
@@ -669,13 +1474,14 @@ function logout() {
     stopMicMeter();
     if (state.ws) state.ws.close();
     localStorage.removeItem("token");
+    localStorage.removeItem("refreshToken");
     localStorage.removeItem("userId");
     localStorage.removeItem("username");
     state = {
         token: null, userId: null, username: null, role: null,
-        avatarColor: 0, avatarUrl: null, bannerUrl: null, presence: localStorage.getItem("presence") || "online", about: "",
+        avatarColor: 0, avatarUrl: null, bannerUrl: null, presence: localStorage.getItem("presence") || "online", customStatus: null, relationships: {}, about: "",
         currentRoomId: null, currentRoomName: null, currentRoomKind: null,
-        ws: null, rooms: [], serverRoles: [], serverUsers: [], users: {}, unreadByRoom: {}, mentionByRoom: {}, messageMetaById: {}, replyingTo: null, pinnedMessageIds: new Set(), threadRootId: null, voice: createVoiceState()
+        ws: null, rooms: [], serverRoles: [], serverUsers: [], announcements: [], customEmojis: {}, users: {}, unreadByRoom: {}, mentionByRoom: {}, messageMetaById: {}, replyingTo: null, pinnedMessageIds: new Set(), myPollVotes: {}, slowmodeUntil: {}, threadRootId: null, voice: createVoiceState()
     };
     updateGlobalMentionBadge();
     app.classList.add("hidden");
@@ -696,9 +1502,143 @@ async function enterApp() {
     await fetchMyProfile();
     updateUserPanel();
     loadRooms();
+    loadAnnouncements();
+    loadCustomEmojis();
+    loadAltTextPolicy();
+    loadRelationships();
     connectWebSocket();
 }
 
+// ── Friends & blocks ───────────────────────────────────
+/** Our view of other users, by user id: `{ user_id, username, type }`. */
+async function loadRelationships() {
+    try {
+        const res = await fetch(`${API}/api/users/@me/relationships`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        if (!res.ok) return;
+        state.relationships = {};
+        (await res.json()).forEach((r) => { state.relationships[r.user_id] = r; });
+    } catch (err) {
+        console.error("Failed to load relationships", err);
+    }
+}
+
+// ── Alt text ───────────────────────────────────────────
+/** Whether the server refuses images and videos without alt text. */
+async function loadAltTextPolicy() {
+    try {
+        const res = await fetch(`${API}/api/alt-text/policy`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        if (!res.ok) return;
+        const data = await res.json();
+        state.altTextRequired = data.policy === "required";
+    } catch (err) {
+        console.error("Failed to load the alt text policy", err);
+    }
+}
+
+function needsAltText(file) {
+    return !!file && (file.type.startsWith("image/") || file.type.startsWith("video/"));
+}
+
+// ── Custom emoji ───────────────────────────────────────
+/** Known custom emoji by id, from `/api/emojis` and message payloads. */
+function rememberCustomEmojis(emojis) {
+    (emojis || []).forEach((emoji) => {
+        if (emoji?.id) state.customEmojis[emoji.id] = emoji;
+    });
+}
+
+async function loadCustomEmojis() {
+    try {
+        const res = await fetch(`${API}/api/emojis`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        if (!res.ok) return;
+        state.customEmojis = {};
+        rememberCustomEmojis(await res.json());
+    } catch (err) {
+        console.error("Failed to load custom emoji", err);
+    }
+}
+
+function customEmojiImgHtml(emoji, cls = "custom-emoji") {
+    const alt = emoji.alt_text || `:${emoji.name}:`;
+    return `<img class="${cls}" src="${API}${escapeHtml(emoji.image_url)}" alt="${escapeHtml(alt)}" title=":${escapeHtml(emoji.name)}:">`;
+}
+
+/** A reaction key as HTML: the unicode emoji, or the image of a `custom:<id>` one. */
+function reactionEmojiHtml(key) {
+    if (!key.startsWith("custom:")) return escapeHtml(key);
+    const emoji = state.customEmojis[key.slice("custom:".length)];
+    return emoji ? customEmojiImgHtml(emoji, "custom-emoji is-reaction") : "❔";
+}
+
+function reactionEmojiLabel(key) {
+    if (!key.startsWith("custom:")) return key;
+    const emoji = state.customEmojis[key.slice("custom:".length)];
+    return emoji ? `:${emoji.name}:` : "emoji supprimé";
+}
+
+// ── Announcements ──────────────────────────────────────
+let announcementExpiryTimer = null;
+
+async function loadAnnouncements() {
+    try {
+        const res = await fetch(`${API}/api/announcements`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        if (!res.ok) return;
+        state.announcements = await res.json();
+        renderAnnouncementBanner();
+    } catch (err) {
+        console.error("Failed to load announcements", err);
+    }
+}
+
+/** Show the latest live announcement; hide it again when it expires. */
+function renderAnnouncementBanner() {
+    if (!announcementBanner) return;
+    const now = Date.now();
+    state.announcements = state.announcements.filter((a) => !a.expires_at || Date.parse(a.expires_at) > now);
+    const current = state.announcements[0];
+
+    clearTimeout(announcementExpiryTimer);
+    if (!current) {
+        announcementBanner.classList.add("hidden");
+        announcementBanner.innerHTML = "";
+        return;
+    }
+    if (current.expires_at) {
+        announcementExpiryTimer = setTimeout(renderAnnouncementBanner, Math.min(Date.parse(current.expires_at) - now, 2 ** 31 - 1));
+    }
+
+    const more = state.announcements.length > 1 ? ` <span class="announcement-more">+${state.announcements.length - 1}</span>` : "";
+    announcementBanner.className = `announcement-banner level-${escapeHtml(current.level || "info")}`;
+    announcementBanner.innerHTML = `
+        <span class="announcement-text">📢 ${escapeHtml(current.content)}${more}</span>
+        <button class="announcement-dismiss" title="Masquer">×</button>
+    `;
+    announcementBanner.querySelector(".announcement-dismiss").addEventListener("click", () => {
+        dismissAnnouncement(current.id);
+    });
+}
+
+async function dismissAnnouncement(id) {
+    state.announcements = state.announcements.filter((a) => a.id !== id);
+    renderAnnouncementBanner();
+    try {
+        await fetch(`${API}/api/announcements/${encodeURIComponent(id)}/dismiss`, {
+            method: "POST",
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+    } catch (err) {
+        console.error("Failed to dismiss announcement", err);
+    }
+}
+
 async function fetchMyProfile() {
     try {
         const res = await fetch(`${API}/api/users/me`, {
@@ -712,6 +1652,7 @@ async function fetchMyProfile() {
             state.avatarUrl = data.avatar_url || null;
             state.bannerUrl = data.banner_url || null;
             updateUserPanel();
+            loadOwnPresence();
         }
     } catch (err) {
         console.error("Failed to fetch profile", err);
@@ -734,6 +1675,7 @@ function updateUserPanel() {
     }
     if (state.role === "admin") {
         deleteRoomBtn.classList.remove("hidden");
+        slowmodeBtn?.classList.remove("hidden");
     }
 }
 
@@ -750,6 +1692,11 @@ async function loadRooms() {
             kind: room.kind === "voice" ? "voice" : "text",
             required_role: (room.required_role || "user").toLowerCase()
         }));
+        state.rooms.forEach((room) => {
+            state.unreadByRoom[room.id] = room.unread_count || 0;
+            state.mentionByRoom[room.id] = room.mention_count || 0;
+        });
+        updateGlobalMentionBadge();
 
         if (state.currentRoomId && !state.rooms.find((r) => r.id === state.currentRoomId)) {
             if (state.voice.joinedRoomId) {
@@ -883,16 +1830,44 @@ async function selectRoom(room) {
     } else {
         deleteRoomBtn.classList.add("hidden");
     }
+    slowmodeBtn?.classList.toggle("hidden", state.role !== "admin" || room.kind !== "text");
+    renderSlowmode();
 
     renderRooms();
+    loadRoomMembers(room.id);
 
     if (room.kind === "text") {
         await loadMessages(room.id);
+        ackRoom(room.id);
     }
 }
 
+let ackTimer = null;
+
+/** Tell the server the room is read, so other devices clear their badges. */
+function ackRoom(roomId, delay = 0) {
+    if (discordState.mode) return;
+    clearTimeout(ackTimer);
+    ackTimer = setTimeout(() => {
+        fetch(`${API}/api/rooms/${roomId}/ack`, {
+            method: "POST",
+            headers: { Authorization: `Bearer ${state.token}` }
+        }).catch(() => {});
+    }, delay);
+}
+
+window.addEventListener("focus", () => {
+    if (state.token && state.currentRoomId && state.currentRoomKind === "text") {
+        ackRoom(state.currentRoomId);
+    }
+});
+
 let loadMessagesVersion = 0;
 const MESSAGE_RENDER_CHUNK_SIZE = 40;
+const HISTORY_PAGE_SIZE = 50;
+/** Cursor of the native room history shown, for loading older pages. */
+const historyState = { roomId: null, oldestId: null, hasMoreBefore: false, loading: false, loaded: 0, hint: null };
+let backfillTimer = null;
 
 function nextFrame() {
     return new Promise((resolve) => requestAnimationFrame(resolve));
@@ -902,7 +1877,9 @@ async function loadMessages(roomId) {
     const version = ++loadMessagesVersion;
 
     try {
-        const res = await fetch(`${API}/api/rooms/${roomId}/messages`, {
+        historyState.roomId = null;
+        clearTimeout(backfillTimer);
+        const res = await fetch(`${API}/api/rooms/${roomId}/backfill`, {
             headers: { Authorization: `Bearer ${state.token}` }
         });
 
@@ -925,12 +1902,18 @@ async function loadMessages(roomId) {
             throw new Error("Failed to load messages");
         }
 
-        const messages = await res.json();
+        const page = await res.json();
+        const messages = page.messages;
 
         if (version !== loadMessagesVersion || state.currentRoomId !== roomId) {
             return;
         }
 
+        historyState.roomId = roomId;
+        historyState.oldestId = messages[0]?.id || null;
+        historyState.hasMoreBefore = page.has_more_before;
+        historyState.loaded = messages.length;
+        historyState.hint = page.hint || null;
         messagesContainer.innerHTML = "";
         state.messageMetaById = {};
         state.pinnedMessageIds = new Set();
@@ -987,23 +1970,139 @@ async function loadMessages(roomId) {
         }
 
         scrollToBottom();
+        scheduleBackfill();
     } catch (err) {
         console.error("Failed to load messages:", err);
     }
 }
 
+/**
+ * Prefetch older pages in the background, paced by the server hint, until
+ * `default_depth` messages are loaded. Past that, only scrolling loads more.
+ */
+function scheduleBackfill(delayMs) {
+    clearTimeout(backfillTimer);
+    const hint = historyState.hint;
+    if (!hint || !historyState.hasMoreBefore || historyState.loaded >= hint.default_depth) return;
+    const roomId = historyState.roomId;
+    backfillTimer = setTimeout(async () => {
+        if (roomId !== historyState.roomId || roomId !== state.currentRoomId) return;
+        if (historyState.loading) {
+            scheduleBackfill();
+            return;
+        }
+        const retryAfter = await loadOlderMessages({ background: true });
+        if (roomId === historyState.roomId) scheduleBackfill(retryAfter);
+    }, delayMs ?? hint.min_interval_ms);
+}
+
+/**
+ * Prepend the page before the oldest message shown, keeping the scroll position.
+ * Background prefetching goes through the backfill tier; returns the delay
+ * asked by the server when it refused the page.
+ */
+async function loadOlderMessages({ background = false } = {}) {
+    const roomId = historyState.roomId;
+    if (!roomId || historyState.loading || !historyState.hasMoreBefore || roomId !== state.currentRoomId) return;
+    historyState.loading = true;
+    const version = loadMessagesVersion;
+    try {
+        const url = background
+            ? `${API}/api/rooms/${roomId}/backfill?${new URLSearchParams({ before: historyState.oldestId })}`
+            : `${API}/api/rooms/${roomId}/messages?${new URLSearchParams({ before: historyState.oldestId, limit: HISTORY_PAGE_SIZE })}`;
+        const res = await fetch(url, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        if (background && (res.status === 429 || res.status === 503)) {
+            const seconds = Number(res.headers.get("Retry-After")) || 1;
+            return seconds * 1000;
+        }
+        if (!res.ok) throw new Error("Failed to load older messages");
+        const page = await res.json();
+        if (version !== loadMessagesVersion || state.currentRoomId !== roomId) return;
+
+        const fragment = document.createDocumentFragment();
+        let lastUsername = null;
+        let lastDate = null;
+        page.messages.forEach((msg) => {
+            const msgDate = msg.created_at ? msg.created_at.split('T')[0] : null;
+            const dateChanged = lastDate && msgDate && msgDate !== lastDate;
+            if (dateChanged) {
+                const sep = document.createElement("div");
+                sep.className = "date-separator";
+                sep.innerHTML = `<span>${formatDateLabel(msgDate)}</span>`;
+                fragment.appendChild(sep);
+            }
+            appendMessage(msg, lastUsername !== msg.username || dateChanged, fragment);
+            if (msg.pinned_at) {
+                state.pinnedMessageIds.add(msg.id);
+            }
+            lastUsername = msg.username;
+            lastDate = msgDate;
+        });
+
+        // The first message already shown may start on another day.
+        const firstShown = state.messageMetaById[historyState.oldestId];
+        const firstDate = firstShown?.created_at ? firstShown.created_at.split('T')[0] : null;
+        if (lastDate && firstDate && firstDate !== lastDate) {
+            const sep = document.createElement("div");
+            sep.className = "date-separator";
+            sep.innerHTML = `<span>${formatDateLabel(firstDate)}</span>`;
+            fragment.appendChild(sep);
+        }
+
+        const distanceFromBottom = messagesContainer.scrollHeight - messagesContainer.scrollTop;
+        messagesContainer.prepend(fragment);
+        messagesContainer.scrollTop = messagesContainer.scrollHeight - distanceFromBottom;
+
+        historyState.oldestId = page.messages[0]?.id || historyState.oldestId;
+        historyState.hasMoreBefore = page.has_more_before;
+        historyState.loaded += page.messages.length;
+    } catch (err) {
+        console.error("Failed to load older messages:", err);
+    } finally {
+        historyState.loading = false;
+    }
+}
+
 // ── WebSocket & Member List ────────────────────────────
-function connectWebSocket() {
+async function fetchWsTicket() {
+    try {
+        const res = await fetch(`${API}/api/auth/ws-ticket`, {
+            method: "POST",
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) return null;
+        const data = await res.json();
+        return data.ticket || null;
+    } catch {
+        return null;
+    }
+}
+
+async function connectWebSocket() {
     if (state.ws) {
         state.ws.onmessage = null;
         state.ws.onclose = null;
         state.ws.close();
+        state.ws = null;
+    }
+    if (!state.token) return;
+
+    // The token never goes in the URL: trade it for a one-time ticket and
+    // send that as the first message.
+    const ticket = await fetchWsTicket();
+    if (!ticket) {
+        if (state.token) setTimeout(connectWebSocket, 3000);
+        return;
     }
+    if (state.ws) return;
 
     state.ws = new WebSocket(WS_URL);
 
     state.ws.onopen = () => {
         console.log("✅ WebSocket connected");
+        state.ws.send(JSON.stringify({ type: "auth", ticket }));
         state.ws.send(JSON.stringify({
             type: "join",
             user_id: state.userId,
@@ -1042,13 +2141,19 @@ function connectWebSocket() {
                 }
                 appendMessage(msg, isFirstInGroup);
                 scrollToBottom();
+                if (msg.username !== state.username && document.hasFocus()) {
+                    ackRoom(msg.room_id, 1000);
+                }
                 if (state.threadRootId && (msg.id === state.threadRootId || msg.reply_to_id === state.threadRootId)) {
                     renderThreadPanel();
                 }
             }
             else if (msg.type === "message" && msg.room_id && msg.username !== state.username) {
                 state.unreadByRoom[msg.room_id] = (state.unreadByRoom[msg.room_id] || 0) + 1;
-                if (messageMentionsCurrentUser(msg.content || "")) {
+                const mentioned = Array.isArray(msg.mention_ids)
+                    ? msg.mention_ids.includes(state.userId)
+                    : messageMentionsCurrentUser(msg.content || "");
+                if (mentioned) {
                     state.mentionByRoom[msg.room_id] = (state.mentionByRoom[msg.room_id] || 0) + 1;
                 }
                 updateGlobalMentionBadge();
@@ -1064,8 +2169,12 @@ function connectWebSocket() {
                         || (existing.avatar_url || null) !== (msg.avatar_url || null)
                         || (existing.banner_url || null) !== (msg.banner_url || null)
                         || (existing.role || "user") !== (msg.role || "user")
+                        || (existing.role_color || null) !== (msg.role_color || null)
+                        || (existing.role_icon_url || null) !== (msg.role_icon_url || null)
+                        || Boolean(existing.role_hoist) !== Boolean(msg.role_hoist)
                         || (existing.about || null) !== (msg.about || null)
-                        || normalizePresence(existing.status || "online") !== nextStatus;
+                        || normalizePresence(existing.status || "online") !== nextStatus
+                        || (existing.custom_status || null) !== (msg.custom_status || null);
 
                     state.users[msg.user_id] = {
                         username: msg.username,
@@ -1074,7 +2183,11 @@ function connectWebSocket() {
                         banner_url: msg.banner_url || null,
                         status: nextStatus,
                         role: msg.role || "user",
+                        role_color: msg.role_color || null,
+                        role_icon_url: msg.role_icon_url || null,
+                        role_hoist: Boolean(msg.role_hoist),
                         about: msg.about || null,
+                        custom_status: msg.custom_status || null,
                     };
                     if (changed) {
                         scheduleMembersRender();
@@ -1087,10 +2200,19 @@ function connectWebSocket() {
                 }
             }
             else if (msg.type === "presence") {
-                if (msg.user_id && state.users[msg.user_id]) {
+                if (msg.user_id === state.userId) {
+                    // Our own events say "offline" while invisible: keep the chosen status.
+                    if ((state.customStatus || null) !== (msg.custom_status || null) && msg.status !== "offline") {
+                        state.customStatus = msg.custom_status || null;
+                        applyOwnPresenceState(state.presence, false);
+                    }
+                }
+                else if (msg.user_id && state.users[msg.user_id]) {
+                    const user = state.users[msg.user_id];
                     const nextStatus = normalizePresence(msg.status || "online");
-                    if (state.users[msg.user_id].status !== nextStatus) {
-                        state.users[msg.user_id].status = nextStatus;
+                    if (user.status !== nextStatus || (user.custom_status || null) !== (msg.custom_status || null)) {
+                        user.status = nextStatus;
+                        user.custom_status = msg.custom_status || null;
                         scheduleMembersRender();
                     }
                     if (currentPopoutUserId === msg.user_id) {
@@ -1137,8 +2259,10 @@ function connectWebSocket() {
                         if (msg.name) room.name = String(msg.name);
                         if (msg.kind) room.kind = String(msg.kind) === "voice" ? "voice" : "text";
                         if (msg.required_role) room.required_role = String(msg.required_role).toLowerCase();
+                        if (msg.slowmode_seconds !== undefined) room.slowmode_seconds = Number(msg.slowmode_seconds) || 0;
 
                         if (state.currentRoomId === room.id) {
+                            renderSlowmode();
                             state.currentRoomName = room.name;
                             state.currentRoomKind = room.kind;
                             currentRoomName.textContent = room.name;
@@ -1155,10 +2279,63 @@ function connectWebSocket() {
                     }
                 }
             }
+            else if (msg.type === "slowmode") {
+                // Only sent to us: our last message was refused.
+                state.slowmodeUntil[msg.room_id] = Date.now() + (Number(msg.retry_after) || 1) * 1000;
+                if (msg.room_id === state.currentRoomId && lastSentMessage?.roomId === msg.room_id && !messageInput.value) {
+                    messageInput.value = lastSentMessage.content;
+                }
+                showToast(`Mode lent : réessayez dans ${msg.retry_after} s`);
+                renderSlowmode();
+            }
+            else if (msg.type === "alt_text_required") {
+                // Only sent to us: our last message had images without alt text.
+                if (msg.room_id === state.currentRoomId && lastSentMessage?.roomId === msg.room_id && !messageInput.value) {
+                    messageInput.value = lastSentMessage.content;
+                }
+                showToast("Ajoutez un texte alternatif à vos images avant de les envoyer.");
+            }
+            else if (msg.type === "alt_text_updated") {
+                if (msg.room_id === state.currentRoomId) {
+                    const img = messagesContainer.querySelector(`.message[data-id="${msg.message_id}"] img[data-attachment-id="${msg.attachment_id}"]`);
+                    if (img && msg.alt_text) img.alt = msg.alt_text;
+                }
+            }
+            else if (msg.type === "message_updated") {
+                if (msg.room_id === state.currentRoomId) {
+                    applyMessageUpdate(msg);
+                }
+            }
+            else if (msg.type === "mention") {
+                // Badges follow the `message` event; this one only notifies.
+                if (msg.room_id !== state.currentRoomId || document.hidden) {
+                    const room = state.rooms.find((r) => r.id === msg.room_id);
+                    const where = room ? ` dans #${room.name}` : "";
+                    showToast(`${msg.author_username} vous a mentionné${where} : ${msg.excerpt}`, "info");
+                }
+            }
+            else if (msg.type === "poll_updated") {
+                if (msg.room_id === state.currentRoomId && msg.poll) {
+                    refreshPoll(msg.message_id, msg.poll);
+                }
+            }
+            else if (msg.type === "message_embeds_updated") {
+                if (msg.room_id === state.currentRoomId) {
+                    const el = messagesContainer.querySelector(`.message[data-id="${msg.id}"] .message-link-embeds`);
+                    if (el) el.outerHTML = renderLinkEmbedsHtml(msg.embeds);
+                }
+            }
             else if (msg.type === "message_deleted") {
                 if (msg.room_id === state.currentRoomId) {
                     const el = messagesContainer.querySelector(`.message[data-id="${msg.id}"]`);
-                    if (el) el.remove();
+                    // Moderators keep seeing it as a tombstone.
+                    if (el && state.role === "admin") {
+                        el.classList.add("message-deleted");
+                        el.querySelector(".msg-action-btn.danger")?.remove();
+                        el.querySelector(".msg-action-btn.edit")?.remove();
+                    } else if (el) {
+                        el.remove();
+                    }
                 }
                 if (msg.id && document.getElementById(MESSAGE_REACTION_PICKER_ID)?.getAttribute("data-message-id") === msg.id) {
                     closeMessageReactionPicker();
@@ -1178,35 +2355,29 @@ function connectWebSocket() {
                     clearReplyTarget();
                 }
             }
-            else if (msg.type === "message_pinned") {
+            else if (msg.type === "message_pin_update") {
                 if (msg.room_id === state.currentRoomId && msg.id) {
-                    state.pinnedMessageIds.add(msg.id);
-                    const hasFlag = messagesContainer.querySelector(`.message[data-id="${msg.id}"] .message-pinned-flag`);
-                    if (!hasFlag) {
+                    const existingFlag = messagesContainer.querySelector(`.message[data-id="${msg.id}"] .message-pinned-flag`);
+                    if (msg.pinned) {
+                        state.pinnedMessageIds.add(msg.id);
                         const body = messagesContainer.querySelector(`.message[data-id="${msg.id}"] .message-body`);
-                        if (body) {
+                        if (!existingFlag && body) {
                             const flag = document.createElement("div");
                             flag.className = "message-pinned-flag";
                             flag.textContent = "📌 Message épinglé";
                             body.prepend(flag);
                         }
+                    } else {
+                        state.pinnedMessageIds.delete(msg.id);
+                        if (existingFlag) existingFlag.remove();
                     }
                     if (pinnedModal && !pinnedModal.classList.contains("hidden")) {
                         loadPinnedMessages();
                     }
                 }
             }
-            else if (msg.type === "message_unpinned") {
-                if (msg.room_id === state.currentRoomId && msg.id) {
-                    state.pinnedMessageIds.delete(msg.id);
-                    const flag = messagesContainer.querySelector(`.message[data-id="${msg.id}"] .message-pinned-flag`);
-                    if (flag) flag.remove();
-                    if (pinnedModal && !pinnedModal.classList.contains("hidden")) {
-                        loadPinnedMessages();
-                    }
-                }
-            }
             else if (msg.type === "message_reaction_updated") {
+                if (msg.custom_emoji) rememberCustomEmojis([msg.custom_emoji]);
                 if (msg.message_id && msg.emoji) {
                     mergeMessageReaction(msg.message_id, msg.emoji, msg.count, msg.user_ids);
                     if (msg.room_id === state.currentRoomId) {
@@ -1214,6 +2385,22 @@ function connectWebSocket() {
                     }
                 }
             }
+            else if (msg.type === "emoji_created" || msg.type === "emoji_updated") {
+                rememberCustomEmojis([msg.emoji]);
+                if (serverSettingsModal && !serverSettingsModal.classList.contains("hidden")) renderServerEmojis();
+            }
+            else if (msg.type === "emoji_deleted") {
+                delete state.customEmojis[msg.id];
+                // Its reactions are gone server-side.
+                const key = `custom:${msg.id}`;
+                Object.entries(state.messageMetaById).forEach(([id, meta]) => {
+                    if ((meta.reactions || []).some((r) => r.emoji === key)) {
+                        mergeMessageReaction(id, key, 0, []);
+                        refreshMessageReactionUI(id);
+                    }
+                });
+                if (serverSettingsModal && !serverSettingsModal.classList.contains("hidden")) renderServerEmojis();
+            }
             else if (msg.type === "messages_purged") {
                 if (state.currentRoomKind === "text") {
                     const selector = `.message[data-user-id="${msg.user_id}"]`;
@@ -1229,14 +2416,59 @@ function connectWebSocket() {
                     renderThreadPanel();
                 }
             }
+            else if (msg.type === "announcement") {
+                if (msg.announcement && !state.announcements.some((a) => a.id === msg.announcement.id)) {
+                    state.announcements.unshift(msg.announcement);
+                    renderAnnouncementBanner();
+                }
+                if (state.role === "admin") loadServerAnnouncements();
+            }
+            else if (msg.type === "announcement_removed") {
+                state.announcements = state.announcements.filter((a) => a.id !== msg.id);
+                renderAnnouncementBanner();
+            }
+            else if (msg.type === "role_created" || msg.type === "role_updated") {
+                applyRoleDisplay(msg.role);
+            }
+            else if (msg.type === "role_deleted") {
+                applyRoleDisplay({ name: msg.name, deleted: true }, msg.fallback || "user");
+            }
+            else if (msg.type === "read_state_updated") {
+                state.unreadByRoom[msg.room_id] = msg.unread_count || 0;
+                state.mentionByRoom[msg.room_id] = msg.mention_count || 0;
+                updateGlobalMentionBadge();
+                scheduleRoomsRender();
+            }
+            else if (msg.type === "relationship_updated" && msg.relationship) {
+                const previous = state.relationships[msg.relationship.user_id]?.type;
+                state.relationships[msg.relationship.user_id] = msg.relationship;
+                if (msg.relationship.type === "pending_incoming" && previous !== "pending_incoming") {
+                    showToast(`${msg.relationship.username} vous a envoyé une demande d'ami.`);
+                } else if (msg.relationship.type === "friend" && previous === "pending_outgoing") {
+                    showToast(`${msg.relationship.username} a accepté votre demande d'ami.`);
+                }
+            }
+            else if (msg.type === "relationship_removed") {
+                delete state.relationships[msg.user_id];
+            }
+            else if (msg.type === "recovery_updated") {
+                if (msg.request?.status === "pending") {
+                    showToast("Une récupération de votre compte a été demandée. Si ce n'est pas vous, annulez-la dans Paramètres → Compte.", "error", 10000);
+                }
+                loadRecoveryStatus();
+                loadSecurityLog();
+            }
             else if (msg.type === "typing") {
                 if (msg.username !== state.username && msg.room_id === state.currentRoomId) {
                     showTypingIndicator(msg.username);
                 }
             }
-            else if (msg.type === "voice_join" || msg.type === "voice_leave" || msg.type === "voice_state" || msg.type === "voice_signal") {
+            else if (msg.type === "voice_join" || msg.type === "voice_leave" || msg.type === "voice_state" || msg.type === "voice_signal" || msg.type === "voice_priority") {
                 handleVoiceWsEvent(msg);
             }
+            else if (msg.type === "voice_recording") {
+                recordingController.handleRecordingWsEvent(msg);
+            }
         } catch (err) {
             console.error("WS error:", err);
         }
@@ -1282,6 +2514,19 @@ voiceController = window.VoxiumVoice.createVoiceController({
     },
 });
 
+recordingController = window.VoxiumRecording.createRecordingController({
+    getState: () => state,
+    API,
+    escapeHtml,
+    showToast,
+    dom: {
+        voiceRecordingWrap,
+        voiceRecordingStatus,
+        voiceRecordingConsentBtn,
+        voiceRecordingToggleBtn,
+    },
+});
+
 contextController = window.VoxiumContext.createContextController({
     getState: () => state,
     API,
@@ -1297,6 +2542,10 @@ contextController = window.VoxiumContext.createContextController({
         ctxDeleteMessage,
         ctxPromoteAdmin,
         ctxPurgeUserMessages,
+        ctxFriend,
+        ctxFriendLabel,
+        ctxBlockUser,
+        ctxBlockLabel,
         roomSettingsModal,
         roomSettingsForm,
         roomSettingsName,
@@ -1393,10 +2642,12 @@ function handleVoiceWsEvent(msg) {
 }
 
 async function joinVoiceRoom() {
-    return voiceController.joinVoiceRoom();
+    await voiceController.joinVoiceRoom();
+    return recordingController.syncRecording();
 }
 
 function leaveVoiceRoom() {
+    recordingController.leaveRoom();
     return voiceController.leaveVoiceRoom();
 }
 
@@ -1408,8 +2659,50 @@ function toggleVoiceDeafen() {
     return voiceController.toggleVoiceDeafen();
 }
 
-function toggleVoiceScreenShare() {
-    return voiceController.toggleVoiceScreenShare();
+function toggleVoiceScreenShare() {
+    return voiceController.toggleVoiceScreenShare();
+}
+
+/** Update members (and the server settings list) after a role event. */
+function applyRoleDisplay(role, fallbackRole = "user") {
+    if (!role?.name) return;
+    Object.values(state.users).forEach((u) => {
+        if (u.role !== role.name) return;
+        if (role.deleted) {
+            u.role = fallbackRole;
+            u.role_color = null;
+            u.role_icon_url = null;
+            u.role_hoist = false;
+        } else {
+            u.role_color = role.color || null;
+            u.role_icon_url = role.icon_url || null;
+            u.role_hoist = Boolean(role.hoist);
+        }
+    });
+    if (Array.isArray(state.serverRoles) && state.serverRoles.length) {
+        const others = state.serverRoles.filter((r) => r.name !== role.name);
+        state.serverRoles = role.deleted ? others : [...others, role].sort((a, b) => {
+            const rank = (r) => (r.name === "admin" ? 0 : r.name === "user" ? 1 : 2);
+            return rank(a) - rank(b) || a.name.localeCompare(b.name);
+        });
+        renderServerRoles();
+    }
+    scheduleMembersRender();
+    if (currentPopoutUserId && state.users[currentPopoutUserId]) {
+        renderUserPopoutContent(currentPopoutUserId, state.users[currentPopoutUserId]);
+    }
+}
+
+/** Hoisted roles get their own group, everyone else is listed under "En ligne". */
+function groupMembers(entries) {
+    const groups = new Map();
+    entries.forEach(([uid, u]) => {
+        const key = u.role_hoist ? u.role : "";
+        if (!groups.has(key)) groups.set(key, []);
+        groups.get(key).push([uid, u]);
+    });
+    const hoisted = [...groups.keys()].filter((k) => k).sort((a, b) => a.localeCompare(b));
+    return [...hoisted, ""].filter((k) => groups.has(k)).map((k) => ({ role: k, members: groups.get(k) }));
 }
 
 function renderMembers() {
@@ -1417,30 +2710,45 @@ function renderMembers() {
     const entries = Object.entries(state.users);
     memberCount.textContent = entries.length;
 
-    entries.forEach(([uid, u]) => {
-        const li = document.createElement("li");
-        const status = normalizePresence(u.status || "online");
-        const avatarContent = u.avatar_url
-            ? `<img src="${API}${escapeHtml(u.avatar_url)}" style="width:100%;height:100%;border-radius:50%;object-fit:cover">`
-            : u.username[0].toUpperCase();
-        li.innerHTML = `
-            <div class="member-avatar-wrapper">
-                <div class="avatar avatar-bg-${u.avatar_color % 8}">
-                    ${avatarContent}
-                </div>
-                <div class="status-dot ${presenceDotClass(status)}"></div>
-            </div>
-            <div class="member-meta">
-                <div class="name">${escapeHtml(u.username)}</div>
-                <div class="member-status-label">${presenceLabel(status)}</div>
-            </div>
-        `;
-        li.addEventListener("contextmenu", (e) => showContextMenu(e, "user", uid, u.username));
-        li.addEventListener("click", (e) => showUserPopout(e, uid, u));
-        membersList.appendChild(li);
+    const groups = groupMembers(entries);
+    groups.forEach(({ role, members }) => {
+        if (groups.length > 1) {
+            const header = document.createElement("li");
+            header.className = "members-group-header";
+            header.textContent = `${role || "En ligne"} — ${members.length}`;
+            membersList.appendChild(header);
+        }
+        members.forEach(([uid, u]) => renderMemberItem(uid, u));
     });
 }
 
+function renderMemberItem(uid, u) {
+    const li = document.createElement("li");
+    const status = normalizePresence(u.status || "online");
+    const avatarContent = u.avatar_url
+        ? `<img src="${API}${escapeHtml(u.avatar_url)}" style="width:100%;height:100%;border-radius:50%;object-fit:cover">`
+        : u.username[0].toUpperCase();
+    const nameStyle = u.role_color && u.role_color !== "#99aab5" ? ` style="color:${escapeHtml(u.role_color)}"` : "";
+    const roleIcon = u.role_icon_url
+        ? `<img class="member-role-icon" src="${API}${escapeHtml(u.role_icon_url)}" alt="" title="${escapeHtml(u.role)}">`
+        : "";
+    li.innerHTML = `
+        <div class="member-avatar-wrapper">
+            <div class="avatar avatar-bg-${u.avatar_color % 8}">
+                ${avatarContent}
+            </div>
+            <div class="status-dot ${presenceDotClass(status)}"></div>
+        </div>
+        <div class="member-meta">
+            <div class="name"${nameStyle}>${escapeHtml(u.username)}${roleIcon}</div>
+            <div class="member-status-label">${u.custom_status ? escapeHtml(u.custom_status) : presenceLabel(status)}</div>
+        </div>
+    `;
+    li.addEventListener("contextmenu", (e) => showContextMenu(e, "user", uid, u.username));
+    li.addEventListener("click", (e) => showUserPopout(e, uid, u));
+    membersList.appendChild(li);
+}
+
 function escapeRegExp(value) {
     return (value || "").replace(/[.*+?^${}()|[\]\\]/g, "\\$&");
 }
@@ -1452,14 +2760,21 @@ function messageMentionsCurrentUser(content) {
     return mentionRegex.test(content);
 }
 
-function renderMessageContentHtml(content) {
+function renderMessageContentHtml(content, customEmojis = []) {
     const escaped = escapeHtml(content || "");
-    return escaped.replace(/(^|\s)(@[\w-]{2,32})/g, (match, prefix, tag) => {
-        const selfTag = `@${state.username || ""}`;
-        const isSelf = selfTag.length > 1 && tag.toLowerCase() === selfTag.toLowerCase();
-        const cls = isSelf ? "mention-token is-self" : "mention-token";
-        return `${prefix}<span class="${cls}">${tag}</span>`;
-    });
+    const byName = {};
+    (customEmojis || []).forEach((emoji) => { byName[emoji.name] = emoji; });
+    return escaped
+        .replace(/(^|\s)(@[\w-]{2,32})/g, (match, prefix, tag) => {
+            const selfTag = `@${state.username || ""}`;
+            const isSelf = selfTag.length > 1 && tag.toLowerCase() === selfTag.toLowerCase();
+            const cls = isSelf ? "mention-token is-self" : "mention-token";
+            return `${prefix}<span class="${cls}">${tag}</span>`;
+        })
+        .replace(/:([A-Za-z0-9_]{2,32}):/g, (match, name) => {
+            const emoji = byName[name.toLowerCase()];
+            return emoji ? customEmojiImgHtml(emoji) : match;
+        });
 }
 
 function normalizeReactions(reactions) {
@@ -1481,8 +2796,8 @@ function renderReactionBadgesHtml(reactions) {
     const chips = normalized.map((reaction) => {
         const reactedByMe = reaction.user_ids.includes(state.userId);
         return `
-            <button class="message-reaction-chip${reactedByMe ? " is-active" : ""}" data-emoji="${escapeHtml(reaction.emoji)}" title="Réagir avec ${escapeHtml(reaction.emoji)}">
-                <span class="message-reaction-emoji">${escapeHtml(reaction.emoji)}</span>
+            <button class="message-reaction-chip${reactedByMe ? " is-active" : ""}" data-emoji="${escapeHtml(reaction.emoji)}" title="Réagir avec ${escapeHtml(reactionEmojiLabel(reaction.emoji))}">
+                <span class="message-reaction-emoji">${reactionEmojiHtml(reaction.emoji)}</span>
                 <span class="message-reaction-count">${reaction.count}</span>
             </button>
         `;
@@ -1521,8 +2836,10 @@ function openMessageReactionPicker(anchorBtn, messageId) {
     }
 
     picker.setAttribute("data-message-id", messageId);
-    picker.innerHTML = QUICK_REACTION_EMOJIS.map((emoji) => (
-        `<button class="message-reaction-picker-btn" type="button" data-emoji="${escapeHtml(emoji)}" title="Réagir avec ${escapeHtml(emoji)}">${escapeHtml(emoji)}</button>`
+    // `/api/emojis` lists the most used first.
+    const customKeys = Object.values(state.customEmojis).map((emoji) => `custom:${emoji.id}`);
+    picker.innerHTML = [...QUICK_REACTION_EMOJIS, ...customKeys].map((emoji) => (
+        `<button class="message-reaction-picker-btn" type="button" data-emoji="${escapeHtml(emoji)}" title="Réagir avec ${escapeHtml(reactionEmojiLabel(emoji))}">${reactionEmojiHtml(emoji)}</button>`
     )).join("");
 
     picker.onclick = (event) => {
@@ -1746,7 +3063,157 @@ function openThreadForMessage(messageId) {
     renderThreadPanel();
 }
 
+function formatVoiceDuration(secs) {
+    const total = Math.max(0, Math.round(secs || 0));
+    return `${Math.floor(total / 60)}:${String(total % 60).padStart(2, "0")}`;
+}
+
+// Waveform (base64 amplitudes 0-255) downsampled to a fixed number of bars
+function renderWaveformHtml(waveform, bars = 48) {
+    let amplitudes = [];
+    try {
+        amplitudes = Array.from(atob(waveform || ""), (c) => c.charCodeAt(0));
+    } catch {
+        amplitudes = [];
+    }
+    if (!amplitudes.length) return "";
+    const html = [];
+    for (let i = 0; i < bars; i++) {
+        const from = Math.floor((i * amplitudes.length) / bars);
+        const to = Math.max(from + 1, Math.floor(((i + 1) * amplitudes.length) / bars));
+        const slice = amplitudes.slice(from, to);
+        const avg = slice.reduce((sum, v) => sum + v, 0) / slice.length;
+        html.push(`<span style="height:${Math.max(8, Math.round((avg / 255) * 100))}%"></span>`);
+    }
+    return `<div class="voice-message-waveform">${html.join("")}</div>`;
+}
+
+// Files from /api/files: images inline (thumbnail, full size in the lightbox),
+// voice messages as a player, others as a download card
+function renderAttachmentsHtml(attachments) {
+    return (attachments || []).map((a) => {
+        const url = API + a.url;
+        if (a.kind === "voice") {
+            return `<div class="voice-message">
+                ${renderWaveformHtml(a.waveform)}
+                <audio controls preload="none" src="${escapeHtml(url)}"></audio>
+                <span class="voice-message-duration">${formatVoiceDuration(a.duration_secs)}</span>
+            </div>`;
+        }
+        if (a.content_type?.startsWith("image/")) {
+            const src = API + (a.thumbnail_url || a.url);
+            return `<div class="message-image-wrapper">
+                <img class="message-image" src="${escapeHtml(src)}" alt="${escapeHtml(a.alt_text || a.filename)}" loading="lazy" data-lightbox="${escapeHtml(url)}" data-attachment-id="${escapeHtml(a.id)}" />
+            </div>`;
+        }
+        return `<div class="message-attachment-file">
+            <a href="${escapeHtml(url)}" target="_blank" rel="noopener">${escapeHtml(a.filename)}</a>
+            <span class="dc-file-size">(${formatFileSize(a.size)})</span>
+        </div>`;
+    }).join("");
+}
+
+// Link previews unfurled by the server; filled in by `message_embeds_updated`
+function renderLinkEmbedsHtml(embeds) {
+    const cards = (embeds || []).map((e) => {
+        const parts = [];
+        if (e.site_name || e.author_name) {
+            const label = [e.site_name, e.author_name].filter(Boolean).join(" — ");
+            parts.push(`<div class="dc-embed-author">${escapeHtml(label)}</div>`);
+        }
+        if (e.title) {
+            parts.push(`<div class="dc-embed-title"><a href="${escapeHtml(e.url)}" target="_blank" rel="noopener" class="dc-link dc-embed-title-link">${escapeHtml(e.title)}</a></div>`);
+        }
+        if (e.description) {
+            parts.push(`<div class="dc-embed-desc">${escapeHtml(e.description)}</div>`);
+        }
+        if (e.image_url) {
+            parts.push(`<div class="dc-embed-image"><img src="${escapeHtml(e.image_url)}" loading="lazy" referrerpolicy="no-referrer" data-lightbox="${escapeHtml(e.image_url)}" /></div>`);
+        }
+        return `<div class="dc-embed link-embed">${parts.join("")}</div>`;
+    }).join("");
+    return `<div class="message-link-embeds">${cards}</div>`;
+}
+
+// Poll of a `poll` message; tallies are refreshed by `poll_updated`
+function renderPollHtml(messageId, poll, authorId) {
+    const mine = new Set(state.myPollVotes[messageId] || []);
+    const closed = !!poll.closed_at || new Date(poll.expires_at) <= new Date();
+    const totalVotes = poll.options.reduce((sum, o) => sum + o.votes, 0);
+    const options = poll.options.map((o, i) => {
+        const percent = totalVotes ? Math.round((o.votes * 100) / totalVotes) : 0;
+        return `
+            <button type="button" class="poll-option${mine.has(i) ? " voted" : ""}" data-poll-option="${i}" ${closed ? "disabled" : ""}>
+                <span class="poll-option-bar" style="width:${percent}%"></span>
+                <span class="poll-option-text">${escapeHtml(o.text)}</span>
+                <span class="poll-option-count">${o.votes} · ${percent}%</span>
+            </button>`;
+    }).join("");
+    const status = closed ? "Sondage terminé" : `Se termine ${formatTime(poll.expires_at)}`;
+    const canEnd = !closed && (authorId === state.userId || state.role === "admin");
+    return `
+        <div class="message-poll" data-poll-id="${escapeHtml(messageId)}">
+            <div class="poll-question">📊 ${escapeHtml(poll.question)}</div>
+            ${poll.multi_select && !closed ? `<div class="poll-hint">Plusieurs réponses possibles</div>` : ""}
+            <div class="poll-options">${options}</div>
+            <div class="poll-footer">
+                <span>${poll.total_voters} votant${poll.total_voters > 1 ? "s" : ""} · ${escapeHtml(status)}</span>
+                ${canEnd ? `<button type="button" class="poll-end-btn">Terminer</button>` : ""}
+            </div>
+        </div>`;
+}
+
+function refreshPoll(messageId, poll) {
+    const messageEl = messagesContainer.querySelector(`.message[data-id="${messageId}"]`);
+    const el = messageEl?.querySelector(".message-poll");
+    if (el) el.outerHTML = renderPollHtml(messageId, poll, messageEl.getAttribute("data-user-id"));
+}
+
+async function votePoll(messageId, optionIndex, multiSelect) {
+    const mine = new Set(state.myPollVotes[messageId] || []);
+    if (multiSelect) {
+        if (mine.has(optionIndex)) mine.delete(optionIndex); else mine.add(optionIndex);
+    } else if (mine.has(optionIndex)) {
+        mine.clear();
+    } else {
+        mine.clear();
+        mine.add(optionIndex);
+    }
+    try {
+        const res = await fetch(`${API}/api/messages/${messageId}/poll/votes`, {
+            method: mine.size ? "PUT" : "DELETE",
+            headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+            body: mine.size ? JSON.stringify({ options: [...mine] }) : undefined,
+        });
+        const data = await res.json();
+        if (!res.ok) {
+            showToast(data.error || "Vote impossible");
+            return;
+        }
+        state.myPollVotes[messageId] = data.my_votes || [];
+        refreshPoll(messageId, data);
+    } catch (e) {
+        showToast("Erreur réseau");
+    }
+}
+
+async function endPoll(messageId) {
+    try {
+        const res = await fetch(`${API}/api/messages/${messageId}/poll/close`, {
+            method: "POST",
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (!res.ok) {
+            const data = await res.json();
+            showToast(data.error || "Impossible de terminer le sondage");
+        }
+    } catch (e) {
+        showToast("Erreur réseau");
+    }
+}
+
 function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
+    rememberCustomEmojis(msg.custom_emojis);
     const div = document.createElement("div");
     div.classList.add("message");
     div.setAttribute("data-username", msg.username);
@@ -1805,7 +3272,14 @@ function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
                     <path d="M6 7l2 8h8l2-8"></path>
                 </svg>
             </button>` : ''}
-            ${(state.role === "admin" || msg.username === state.username) ? `
+            ${(msg.user_id === state.userId && (msg.kind || "default") === "default" && !msg.deleted_at) ? `
+            <button class="msg-action-btn edit" title="Modifier">
+                <svg width="18" height="18" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
+                    <path d="M12 20h9"></path>
+                    <path d="M16.5 3.5a2.1 2.1 0 0 1 3 3L7 19l-4 1 1-4z"></path>
+                </svg>
+            </button>` : ''}
+            ${(state.role === "admin" || msg.username === state.username) && !msg.deleted_at ? `
             <button class="msg-action-btn danger" title="Supprimer" data-delete-msg="${escapeHtml(msg.id)}">
                 <svg width="18" height="18" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
                     <polyline points="3 6 5 6 21 6"></polyline>
@@ -1822,18 +3296,28 @@ function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
         </div>
     ` : '';
 
+    const attachmentsHtml = renderAttachmentsHtml(msg.attachments);
+    const linkEmbedsHtml = renderLinkEmbedsHtml(msg.embeds);
+    if (msg.poll?.my_votes) state.myPollVotes[msg.id] = msg.poll.my_votes;
+    const pollHtml = msg.poll ? renderPollHtml(msg.id, msg.poll, msg.user_id) : "";
+
     // Detect emoji-only messages for jumbo display
     const emojiClass = msg.content ? getEmojiClass(msg.content) : '';
     const mentionsMe = msg.username !== state.username && messageMentionsCurrentUser(msg.content || "");
     if (mentionsMe) {
         div.classList.add("message-mentioned");
     }
-    const contentHtml = msg.content
-        ? `<div class="message-content${emojiClass ? ' ' + emojiClass : ''}">${renderMessageContentHtml(msg.content)}</div>`
+    const contentHtml = msg.content && !msg.poll
+        ? `<div class="message-content${emojiClass ? ' ' + emojiClass : ''}">${renderMessageContentHtml(msg.content, msg.custom_emojis)}${editedLabelHtml(msg)}</div>`
         : '';
+    if (msg.deleted_at) {
+        div.classList.add("message-deleted");
+    }
     const reactionsHtml = renderReactionBadgesHtml(normalizedReactions);
 
-    const pinnedFlagHtml = msg.pinned_at ? `<div class="message-pinned-flag">📌 Message épinglé</div>` : "";
+    const pinnedFlagHtml = msg.deleted_at
+        ? `<div class="message-pinned-flag">🗑 Message supprimé, visible par les modérateurs</div>`
+        : msg.pinned_at ? `<div class="message-pinned-flag">📌 Message épinglé</div>` : "";
 
     let replyRefHtml = "";
     if (msg.reply_to_id) {
@@ -1869,7 +3353,10 @@ function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
                 ${pinnedFlagHtml}
                 ${replyRefHtml}
                 ${contentHtml}
+                ${pollHtml}
                 ${imageHtml}
+                ${attachmentsHtml}
+                ${linkEmbedsHtml}
                 ${reactionsHtml}
             </div>
         `;
@@ -1881,12 +3368,26 @@ function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
                 ${pinnedFlagHtml}
                 ${replyRefHtml}
                 ${contentHtml}
+                ${pollHtml}
                 ${imageHtml}
+                ${attachmentsHtml}
+                ${linkEmbedsHtml}
                 ${reactionsHtml}
             </div>
         `;
     }
 
+    if (msg.poll) {
+        div.addEventListener("click", (event) => {
+            const option = event.target.closest(".poll-option");
+            if (option && !option.disabled) {
+                votePoll(msg.id, Number(option.getAttribute("data-poll-option")), msg.poll.multi_select);
+            } else if (event.target.closest(".poll-end-btn")) {
+                endPoll(msg.id);
+            }
+        });
+    }
+
     const reactBtn = div.querySelector(".msg-action-btn.react");
     if (reactBtn) {
         reactBtn.addEventListener("click", (event) => {
@@ -1923,6 +3424,20 @@ function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
         });
     }
 
+    const editBtn = div.querySelector(".msg-action-btn.edit");
+    if (editBtn) {
+        editBtn.addEventListener("click", (event) => {
+            event.preventDefault();
+            event.stopPropagation();
+            editMessage(msg.id);
+        });
+    }
+
+    div.querySelector(".message-edited")?.addEventListener("click", (event) => {
+        event.stopPropagation();
+        showMessageHistory(msg.id);
+    });
+
     const threadBtn = div.querySelector(".msg-action-btn.thread");
     if (threadBtn) {
         threadBtn.addEventListener("click", (event) => {
@@ -1973,6 +3488,166 @@ function appendMessage(msg, isFirstInGroup = true, parent = messagesContainer) {
     parent.appendChild(div);
 }
 
+/** "(modifié)" marker; the author and moderators can open the history from it. */
+function editedLabelHtml(msg) {
+    if (!msg.edited_at) return "";
+    const canSeeHistory = state.role === "admin" || msg.user_id === state.userId;
+    const title = `Modifié le ${new Date(msg.edited_at).toLocaleString()}`;
+    return ` <span class="message-edited${canSeeHistory ? " clickable" : ""}" title="${escapeHtml(title)}">(modifié)</span>`;
+}
+
+async function editMessage(messageId) {
+    const meta = state.messageMetaById[messageId];
+    if (!meta) return;
+    const next = prompt("Modifier le message", meta.content || "");
+    if (next === null || next.trim() === (meta.content || "").trim()) return;
+    try {
+        const res = await fetch(`${API}/api/messages/${encodeURIComponent(messageId)}`, {
+            method: "PATCH",
+            headers: {
+                "Content-Type": "application/json",
+                Authorization: `Bearer ${state.token}`
+            },
+            body: JSON.stringify({ content: next })
+        });
+        if (!res.ok) {
+            const data = await res.json().catch(() => ({}));
+            showToast(data.error || "Impossible de modifier le message.");
+        }
+    } catch (e) {
+        showToast("Erreur réseau");
+    }
+}
+
+/** Apply a `message_updated` event to the rendered message. */
+function applyMessageUpdate(msg) {
+    const meta = state.messageMetaById[msg.id];
+    if (meta) {
+        meta.content = msg.content || "";
+        meta.edited_at = msg.edited_at;
+    }
+    const el = messagesContainer.querySelector(`.message[data-id="${msg.id}"] .message-content`);
+    if (!el) return;
+    el.innerHTML = `${renderMessageContentHtml(msg.content, msg.custom_emojis)}${editedLabelHtml({ ...meta, ...msg })}`;
+    el.querySelector(".message-edited")?.addEventListener("click", (event) => {
+        event.stopPropagation();
+        showMessageHistory(msg.id);
+    });
+}
+
+async function showMessageHistory(messageId) {
+    if (!messageHistoryModal || !messageHistoryList) return;
+    if (state.role !== "admin" && state.messageMetaById[messageId]?.user_id !== state.userId) return;
+    messageHistoryList.innerHTML = `<div class="pinned-item">Chargement...</div>`;
+    messageHistoryModal.classList.remove("hidden");
+    try {
+        const res = await fetch(`${API}/api/messages/${encodeURIComponent(messageId)}/history`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            messageHistoryList.innerHTML = `<div class="pinned-item">${escapeHtml(data.error || "Erreur")}</div>`;
+            return;
+        }
+        const versions = [
+            ...data.revisions.map((r) => ({ content: r.content, at: r.created_at, label: "Version précédente" })),
+            { content: data.message.content, at: data.message.edited_at || data.message.created_at, label: "Version actuelle" },
+        ].reverse();
+        messageHistoryList.innerHTML = versions.map((v) => `
+            <div class="pinned-item">
+                <div class="pinned-item-header">
+                    <span class="pinned-item-user">${escapeHtml(v.label)}</span>
+                    <span class="pinned-item-time">${escapeHtml(new Date(v.at).toLocaleString())}</span>
+                </div>
+                <div class="pinned-item-content">${escapeHtml(v.content || "[Image]")}</div>
+            </div>
+        `).join("");
+    } catch (err) {
+        messageHistoryList.innerHTML = `<div class="pinned-item">Erreur réseau.</div>`;
+    }
+}
+
+if (messageHistoryModal) {
+    $("#message-history-close-btn")?.addEventListener("click", () => messageHistoryModal.classList.add("hidden"));
+    messageHistoryModal.addEventListener("click", (event) => {
+        if (event.target === messageHistoryModal) {
+            messageHistoryModal.classList.add("hidden");
+        }
+    });
+}
+
+// Recent mentions (GET /api/users/@me/mentions), newest first
+let recentMentionsBefore = null;
+
+async function loadRecentMentions(append = false) {
+    if (!recentMentionsList) return;
+    if (!append) {
+        recentMentionsBefore = null;
+        recentMentionsList.innerHTML = `<div class="pinned-item">Chargement...</div>`;
+    }
+    const params = new URLSearchParams({ limit: "25" });
+    if (recentMentionsBefore) params.set("before", recentMentionsBefore);
+    try {
+        const res = await fetch(`${API}/api/users/@me/mentions?${params}`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            recentMentionsList.innerHTML = `<div class="pinned-item">${escapeHtml(data.error || "Erreur")}</div>`;
+            return;
+        }
+        if (!append) recentMentionsList.innerHTML = "";
+        if (!append && data.mentions.length === 0) {
+            recentMentionsList.innerHTML = `<div class="pinned-item">Aucune mention récente.</div>`;
+        }
+        data.mentions.forEach((item) => recentMentionsList.appendChild(renderRecentMention(item)));
+        recentMentionsBefore = data.mentions.at(-1)?.created_at || recentMentionsBefore;
+        recentMentionsMoreBtn?.classList.toggle("hidden", !data.has_more);
+    } catch {
+        recentMentionsList.innerHTML = `<div class="pinned-item">Erreur réseau.</div>`;
+    }
+}
+
+function renderRecentMention(item) {
+    const row = document.createElement("div");
+    row.className = "pinned-item recent-mention-item";
+    const room = state.rooms.find((r) => r.id === item.room_id);
+    const roomLabel = room ? `#${room.name}` : "salon";
+    row.innerHTML = `
+        <div class="pinned-item-header">
+            <span class="pinned-item-user">${escapeHtml(item.username || "Utilisateur")}</span>
+            <span class="pinned-item-time">${escapeHtml(roomLabel)} • ${escapeHtml(new Date(item.created_at).toLocaleString())}</span>
+        </div>
+        <div class="pinned-item-content">${renderMessageContentHtml(item.content || "", item.custom_emojis)}</div>
+    `;
+    row.addEventListener("click", async () => {
+        if (!room) return;
+        if (state.currentRoomId !== room.id) await selectRoom(room);
+        recentMentionsModal.classList.add("hidden");
+        const target = messagesContainer.querySelector(`.message[data-id="${item.id}"]`);
+        if (target) {
+            target.scrollIntoView({ behavior: "smooth", block: "center" });
+            target.classList.add("message-mentioned");
+            setTimeout(() => target.classList.remove("message-mentioned"), 1400);
+        }
+    });
+    return row;
+}
+
+if (recentMentionsModal) {
+    globalMentionBadge?.addEventListener("click", () => {
+        recentMentionsModal.classList.remove("hidden");
+        loadRecentMentions();
+    });
+    recentMentionsMoreBtn?.addEventListener("click", () => loadRecentMentions(true));
+    $("#recent-mentions-close-btn")?.addEventListener("click", () => recentMentionsModal.classList.add("hidden"));
+    recentMentionsModal.addEventListener("click", (event) => {
+        if (event.target === recentMentionsModal) {
+            recentMentionsModal.classList.add("hidden");
+        }
+    });
+}
+
 // Expose for inline onclick
 window.deleteMessageFromBtn = async function (msgId) {
     if (!confirm("Supprimer ce message ?")) return;
@@ -1989,12 +3664,12 @@ window.deleteMessageFromBtn = async function (msgId) {
 };
 
 // ── Send Message ───────────────────────────────────────
-let pendingImageUrl = null;
 const fileInput = $("#file-input");
 const attachBtn = $("#attach-btn");
 const uploadPreview = $("#upload-preview");
 const uploadPreviewImg = $("#upload-preview-img");
 const uploadFilename = $("#upload-filename");
+const uploadAltText = $("#upload-alt-text");
 const uploadCancelBtn = $("#upload-cancel-btn");
 
 // File attach button
@@ -2004,6 +3679,17 @@ attachBtn.addEventListener("click", () => fileInput.click());
 fileInput.addEventListener("change", () => {
     const file = fileInput.files[0];
     if (!file) return;
+    uploadAltText.value = "";
+    uploadAltText.classList.toggle("hidden", !needsAltText(file));
+    // Only images get a preview, other files just show their name
+    if (!file.type.startsWith("image/")) {
+        uploadPreviewImg.removeAttribute("src");
+        uploadPreviewImg.classList.add("hidden");
+        uploadFilename.textContent = file.name;
+        uploadPreview.classList.remove("hidden");
+        return;
+    }
+    uploadPreviewImg.classList.remove("hidden");
     // Show preview
     const reader = new FileReader();
     reader.onload = (e) => {
@@ -2017,7 +3703,6 @@ fileInput.addEventListener("change", () => {
 // Cancel upload
 uploadCancelBtn.addEventListener("click", () => {
     fileInput.value = "";
-    pendingImageUrl = null;
     uploadPreview.classList.add("hidden");
 });
 
@@ -2042,22 +3727,33 @@ messageForm.addEventListener("submit", async (e) => {
 
     if (state.currentRoomKind !== "text") return;
     if (!state.currentRoomId || !state.ws) return;
+    if (slowmodeRemaining(state.currentRoomId) > 0) {
+        showToast(`Mode lent : réessayez dans ${slowmodeRemaining(state.currentRoomId)} s`);
+        return;
+    }
 
-    let imageUrl = null;
+    let attachmentId = null;
+    const altText = needsAltText(file) ? uploadAltText.value.trim() : "";
+    if (needsAltText(file) && !altText && state.altTextRequired) {
+        showToast("Un texte alternatif est requis pour les images et vidéos.");
+        uploadAltText.focus();
+        return;
+    }
 
-    // Upload image first if there is one
+    // Upload the file first if there is one, the message then refers to it
     if (file) {
         try {
             const formData = new FormData();
             formData.append("file", file);
-            const res = await fetch(`${API}/api/upload`, {
+            const query = altText ? `?alt_text=${encodeURIComponent(altText)}` : "";
+            const res = await fetch(`${API}/api/files${query}`, {
                 method: "POST",
                 headers: { Authorization: `Bearer ${state.token}` },
                 body: formData
             });
             if (res.ok) {
                 const data = await res.json();
-                imageUrl = data.url;
+                attachmentId = data.id;
             } else {
                 const data = await res.json();
                 alert(data.error || "Erreur d'upload");
@@ -2080,11 +3776,13 @@ messageForm.addEventListener("submit", async (e) => {
     if (state.replyingTo?.id) {
         msg.reply_to_id = state.replyingTo.id;
     }
-    if (imageUrl) msg.image_url = imageUrl;
+    if (attachmentId) msg.attachment_ids = [attachmentId];
 
+    lastSentMessage = { roomId: state.currentRoomId, content: msg.content };
     state.ws.send(JSON.stringify(msg));
     messageInput.value = "";
     fileInput.value = "";
+    uploadAltText.value = "";
     uploadPreview.classList.add("hidden");
     clearReplyTarget();
 });
@@ -2095,6 +3793,82 @@ if (replyCancelBtn) {
     });
 }
 
+// ── Voice Messages ─────────────────────────────────────
+// The server only takes Ogg Opus: the button is hidden where the recorder can't produce it.
+const VOICE_MESSAGE_MIME = "audio/ogg;codecs=opus";
+const voiceRecordBtn = $("#voice-record-btn");
+let voiceRecorder = null;
+
+if (voiceRecordBtn && !(window.MediaRecorder && MediaRecorder.isTypeSupported(VOICE_MESSAGE_MIME))) {
+    voiceRecordBtn.classList.add("hidden");
+}
+
+async function sendVoiceMessage(blob) {
+    if (!state.currentRoomId || !state.ws) return;
+    try {
+        const formData = new FormData();
+        formData.append("file", blob, "voice-message.ogg");
+        const res = await fetch(`${API}/api/files/voice`, {
+            method: "POST",
+            headers: { Authorization: `Bearer ${state.token}` },
+            body: formData
+        });
+        const data = await res.json();
+        if (!res.ok) {
+            alert(data.error || "Erreur d'envoi du message vocal");
+            return;
+        }
+        const msg = {
+            type: "message",
+            room_id: state.currentRoomId,
+            user_id: state.userId,
+            username: state.username,
+            content: "",
+            avatar_color: state.avatarColor,
+            attachment_ids: [data.id]
+        };
+        if (state.replyingTo?.id) msg.reply_to_id = state.replyingTo.id;
+        state.ws.send(JSON.stringify(msg));
+        clearReplyTarget();
+    } catch (err) {
+        alert("Erreur réseau lors de l'envoi du message vocal");
+    }
+}
+
+if (voiceRecordBtn) {
+    voiceRecordBtn.addEventListener("click", async () => {
+        // Second click: stop and send
+        if (voiceRecorder) {
+            voiceRecorder.stop();
+            return;
+        }
+        if (discordState.mode || state.currentRoomKind !== "text" || !state.currentRoomId) return;
+
+        let stream;
+        try {
+            stream = await navigator.mediaDevices.getUserMedia({ audio: true });
+        } catch (err) {
+            alert("Micro inaccessible");
+            return;
+        }
+        const chunks = [];
+        voiceRecorder = new MediaRecorder(stream, { mimeType: VOICE_MESSAGE_MIME });
+        voiceRecorder.addEventListener("dataavailable", (e) => {
+            if (e.data.size) chunks.push(e.data);
+        });
+        voiceRecorder.addEventListener("stop", () => {
+            stream.getTracks().forEach((track) => track.stop());
+            voiceRecorder = null;
+            voiceRecordBtn.classList.remove("recording");
+            voiceRecordBtn.title = "Enregistrer un message vocal";
+            if (chunks.length) sendVoiceMessage(new Blob(chunks, { type: "audio/ogg" }));
+        });
+        voiceRecorder.start();
+        voiceRecordBtn.classList.add("recording");
+        voiceRecordBtn.title = "Arrêter et envoyer";
+    });
+}
+
 async function loadPinnedMessages() {
     if (!state.currentRoomId || state.currentRoomKind !== "text") return;
     try {
@@ -2122,6 +3896,7 @@ async function loadPinnedMessages() {
                     <span class="pinned-item-time">${escapeHtml(formatTime(item.created_at))}</span>
                 </div>
                 <div class="pinned-item-content">${escapeHtml((item.content && item.content.trim()) || (item.image_url ? "[Image]" : "Message"))}</div>
+                ${item.pinned_by_username ? `<div class="pinned-item-pinner">Épinglé par ${escapeHtml(item.pinned_by_username)}${item.pinned_at ? ` · ${escapeHtml(formatTime(item.pinned_at))}` : ""}</div>` : ""}
             `;
             row.addEventListener("click", () => {
                 pinnedModal.classList.add("hidden");
@@ -2220,6 +3995,61 @@ function showTypingIndicator(username) {
     }, 4000);
 }
 
+// ── Slowmode ───────────────────────────────────────────
+// The server refuses messages sent too soon with a `slowmode` event; the
+// header then counts down and the refused text is put back in the input.
+let lastSentMessage = null;
+let slowmodeTimer = null;
+
+/** Seconds before we can post in `roomId` again, 0 when we can. */
+function slowmodeRemaining(roomId) {
+    return Math.max(0, Math.ceil(((state.slowmodeUntil[roomId] || 0) - Date.now()) / 1000));
+}
+
+function renderSlowmode() {
+    if (!currentRoomSlowmode) return;
+    clearTimeout(slowmodeTimer);
+    const room = state.rooms.find((r) => r.id === state.currentRoomId);
+    const seconds = room && room.kind === "text" && !discordState.mode ? Number(room.slowmode_seconds) || 0 : 0;
+    const remaining = room ? slowmodeRemaining(room.id) : 0;
+    if (!seconds && !remaining) {
+        currentRoomSlowmode.classList.add("hidden");
+        return;
+    }
+    currentRoomSlowmode.textContent = remaining > 0 ? `⏱ ${remaining} s` : `⏱ Mode lent : ${seconds} s`;
+    currentRoomSlowmode.title = `Un message toutes les ${seconds} s`;
+    currentRoomSlowmode.classList.toggle("cooling", remaining > 0);
+    currentRoomSlowmode.classList.remove("hidden");
+    if (remaining > 0) slowmodeTimer = setTimeout(renderSlowmode, 1000);
+}
+
+if (slowmodeBtn) {
+    slowmodeBtn.addEventListener("click", async () => {
+        const room = state.rooms.find((r) => r.id === state.currentRoomId);
+        if (!room) return;
+        const answer = prompt("Mode lent : secondes entre deux messages (0 pour désactiver)", String(room.slowmode_seconds || 0));
+        if (answer === null) return;
+        const seconds = Number.parseInt(answer, 10);
+        if (!Number.isFinite(seconds) || seconds < 0) return;
+        try {
+            const res = await fetch(`${API}/api/rooms/${room.id}`, {
+                method: "PATCH",
+                headers: {
+                    "Content-Type": "application/json",
+                    Authorization: `Bearer ${state.token}`
+                },
+                body: JSON.stringify({ name: room.name, kind: room.kind, required_role: room.required_role, slowmode_seconds: seconds })
+            });
+            if (!res.ok) {
+                const data = await res.json().catch(() => ({}));
+                showToast(data.error || "Impossible de changer le mode lent.");
+            }
+        } catch (err) {
+            showToast("Erreur réseau");
+        }
+    });
+}
+
 // ── Admin: Delete Room ─────────────────────────────────
 deleteRoomBtn.addEventListener("click", async () => {
     if (!confirm(`Voulez-vous vraiment supprimer le salon #${state.currentRoomName} ?`)) return;
@@ -2251,6 +4081,14 @@ leaveVoiceBtn.addEventListener("click", () => {
     leaveVoiceRoom();
 });
 
+voiceRecordingConsentBtn?.addEventListener("click", () => {
+    recordingController.toggleConsent();
+});
+
+voiceRecordingToggleBtn?.addEventListener("click", () => {
+    recordingController.toggleRecording();
+});
+
 voiceMuteBtn.addEventListener("click", () => {
     toggleVoiceMute();
 });
@@ -2291,6 +4129,12 @@ if (presenceSelect) {
     });
 }
 
+if (customStatusInput) {
+    customStatusInput.addEventListener("change", () => {
+        saveCustomStatus(customStatusInput.value);
+    });
+}
+
 if (selfStatusDot) {
     selfStatusDot.title = "Cliquer pour changer le statut";
     selfStatusDot.addEventListener("click", (event) => {
@@ -2372,15 +4216,44 @@ function renderServerRoles() {
             row.className = "server-role-item";
 
             const canDelete = role.name !== "admin" && role.name !== "user";
+            const icon = role.icon_url
+                ? `<img class="server-role-icon" src="${API}${escapeHtml(role.icon_url)}" alt="">`
+                : `<span class="server-role-dot" style="background:${escapeHtml(role.color || "#99aab5")}"></span>`;
             row.innerHTML = `
                 <div class="server-role-left">
-                    <span class="server-role-dot" style="background:${escapeHtml(role.color || "#99aab5")}"></span>
+                    ${icon}
                     <span class="server-role-name">${escapeHtml(role.name)}</span>
                 </div>
-                <button class="server-role-delete" data-role="${escapeHtml(role.name)}" ${canDelete ? "" : "disabled"}>Suppr.</button>
+                <div class="server-role-actions">
+                    <label title="Afficher ses membres séparément"><input type="checkbox" data-flag="hoist" ${role.hoist ? "checked" : ""}> Séparé</label>
+                    <label title="Tout le monde peut mentionner ce rôle"><input type="checkbox" data-flag="mentionable" ${role.mentionable ? "checked" : ""}> @</label>
+                    <label title="Ignore le mode lent des salons"><input type="checkbox" data-flag="bypass_slowmode" ${role.bypass_slowmode ? "checked" : ""}> ⏱</label>
+                    <label title="Peut être orateur prioritaire en vocal"><input type="checkbox" data-flag="priority_speaker" ${role.priority_speaker ? "checked" : ""}> ⭐</label>
+                    <button class="server-role-delete server-role-icon-btn">Icône</button>
+                    ${role.icon_url ? `<button class="server-role-delete server-role-icon-remove">×</button>` : ""}
+                    <button class="server-role-delete" data-role="${escapeHtml(role.name)}" ${canDelete ? "" : "disabled"}>Suppr.</button>
+                </div>
             `;
 
-            const delBtn = row.querySelector(".server-role-delete");
+            row.querySelectorAll("input[data-flag]").forEach((input) => {
+                input.addEventListener("change", () => {
+                    updateServerRole(role, { [input.dataset.flag]: input.checked });
+                });
+            });
+            row.querySelector(".server-role-icon-btn")?.addEventListener("click", () => {
+                const picker = document.createElement("input");
+                picker.type = "file";
+                picker.accept = "image/png";
+                picker.addEventListener("change", () => {
+                    if (picker.files?.[0]) uploadServerRoleIcon(role, picker.files[0]);
+                });
+                picker.click();
+            });
+            row.querySelector(".server-role-icon-remove")?.addEventListener("click", () => {
+                uploadServerRoleIcon(role, null);
+            });
+
+            const delBtn = row.querySelector(".server-role-delete[data-role]");
             if (delBtn && canDelete) {
                 delBtn.addEventListener("click", async () => {
                     if (!confirm(`Supprimer le rôle \"${role.name}\" ?`)) return;
@@ -2419,6 +4292,52 @@ function renderServerRoles() {
     }
 }
 
+async function updateServerRole(role, changes) {
+    try {
+        const res = await fetch(`${API}/api/server/roles/${encodeURIComponent(role.name)}`, {
+            method: "PATCH",
+            headers: {
+                "Content-Type": "application/json",
+                Authorization: `Bearer ${state.token}`,
+                "If-Match": `"${role.version}"`
+            },
+            body: JSON.stringify(changes)
+        });
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            setServerSettingsFeedback(res.status === 412 ? "Rôle modifié entre-temps, rechargé." : (data.error || "Erreur"), true);
+            await loadServerSettingsData();
+            return;
+        }
+        applyRoleDisplay(data);
+        setServerSettingsFeedback("Rôle mis à jour.");
+    } catch (err) {
+        setServerSettingsFeedback("Erreur réseau.", true);
+    }
+}
+
+/** Upload a PNG icon for `role`, or remove it when `file` is null. */
+async function uploadServerRoleIcon(role, file) {
+    const options = { method: file ? "PUT" : "DELETE", headers: { Authorization: `Bearer ${state.token}` } };
+    if (file) {
+        const formData = new FormData();
+        formData.append("file", file);
+        options.body = formData;
+    }
+    try {
+        const res = await fetch(`${API}/api/server/roles/${encodeURIComponent(role.name)}/icon`, options);
+        const data = await res.json().catch(() => ({}));
+        if (!res.ok) {
+            setServerSettingsFeedback(data.error || "Erreur", true);
+            return;
+        }
+        applyRoleDisplay(data);
+        setServerSettingsFeedback(file ? "Icône mise à jour." : "Icône retirée.");
+    } catch (err) {
+        setServerSettingsFeedback("Erreur réseau.", true);
+    }
+}
+
 function renderServerUsers() {
     if (!serverUserSelect) return;
     const users = Array.isArray(state.serverUsers) ? state.serverUsers : [];
@@ -2438,25 +4357,181 @@ function renderServerUsers() {
 }
 
 async function loadServerSettingsData() {
-    const [rolesRes, usersRes] = await Promise.all([
+    const [rolesRes, users] = await Promise.all([
         fetch(`${API}/api/server/roles`, { headers: { Authorization: `Bearer ${state.token}` } }),
-        fetch(`${API}/api/server/users`, { headers: { Authorization: `Bearer ${state.token}` } }),
+        fetchAllPages(`${API}/api/server/users?limit=500`),
     ]);
 
     const rolesData = await rolesRes.json().catch(() => []);
-    const usersData = await usersRes.json().catch(() => []);
 
     if (!rolesRes.ok) {
         throw new Error(rolesData.error || "Impossible de charger les rôles");
     }
-    if (!usersRes.ok) {
-        throw new Error(usersData.error || "Impossible de charger les membres");
+    if (!users.ok) {
+        throw new Error(users.error || "Impossible de charger les membres");
     }
 
     state.serverRoles = Array.isArray(rolesData) ? rolesData : [];
-    state.serverUsers = Array.isArray(usersData) ? usersData : [];
+    state.serverUsers = users.items;
     renderServerRoles();
     renderServerUsers();
+    await loadCustomEmojis();
+    renderServerEmojis();
+    await loadServerAnnouncements();
+    await loadServerJobs();
+}
+
+function formatJobAge(secs) {
+    if (secs == null) return "—";
+    if (secs < 60) return `${secs} s`;
+    if (secs < 3600) return `${Math.round(secs / 60)} min`;
+    return `${Math.round(secs / 3600)} h`;
+}
+
+async function loadServerJobs() {
+    if (!serverJobsList || serverSettingsModal?.classList.contains("hidden")) return;
+    try {
+        const res = await fetch(`${API}/api/server/jobs`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        const queues = await res.json().catch(() => []);
+        if (!res.ok) return;
+
+        serverJobsList.innerHTML = "";
+        queues.forEach((q) => {
+            const row = document.createElement("div");
+            row.className = "server-role-item";
+            const failures = `${Math.round(q.failure_rate * 100)} % d'échecs`;
+            const title = q.last_error ? ` title="${escapeHtml(q.last_error)}"` : "";
+            row.innerHTML = `
+                <div class="server-role-left">
+                    <span class="server-role-name">${escapeHtml(q.name)}${q.paused ? " (en pause)" : ""}</span>
+                </div>
+                <div class="server-role-actions"${title}>
+                    <span>${q.depth} en attente · plus ancien ${formatJobAge(q.oldest_age_secs)} · ${failures}</span>
+                    <button class="server-role-delete">${q.paused ? "Reprendre" : "Pause"}</button>
+                </div>
+            `;
+            row.querySelector("button").addEventListener("click", async () => {
+                const action = q.paused ? "resume" : "pause";
+                const res = await fetch(`${API}/api/server/jobs/${encodeURIComponent(q.name)}/${action}`, {
+                    method: "POST",
+                    headers: { Authorization: `Bearer ${state.token}` }
+                }).catch(() => null);
+                if (!res?.ok) {
+                    setServerSettingsFeedback("Impossible de modifier la file.", true);
+                    return;
+                }
+                loadServerJobs();
+            });
+            serverJobsList.appendChild(row);
+        });
+    } catch (err) {
+        console.error("Failed to load background jobs", err);
+    }
+}
+
+function renderServerEmojis() {
+    if (!serverEmojisList) return;
+    const emojis = Object.values(state.customEmojis).sort((a, b) => a.name.localeCompare(b.name));
+    serverEmojisList.innerHTML = "";
+    if (!emojis.length) {
+        serverEmojisList.innerHTML = `<div class="server-role-item"><span class="server-role-name">Aucun émoji.</span></div>`;
+    }
+    emojis.forEach((emoji) => {
+        const row = document.createElement("div");
+        row.className = "server-role-item";
+        row.innerHTML = `
+            <div class="server-role-left">
+                ${customEmojiImgHtml(emoji, "server-role-icon")}
+                <span class="server-role-name">:${escapeHtml(emoji.name)}:</span>
+            </div>
+            <div class="server-role-actions">
+                <button class="server-role-alt" title="${escapeHtml(emoji.alt_text || "Pas de texte alternatif")}">${emoji.alt_text ? "Alt ✓" : "Alt"}</button>
+                <button class="server-role-delete">Supprimer</button>
+            </div>
+        `;
+        row.querySelector(".server-role-alt").addEventListener("click", async () => {
+            const altText = prompt(`Texte alternatif de :${emoji.name}:`, emoji.alt_text || "");
+            if (!altText?.trim()) return;
+            const res = await fetch(`${API}/api/server/alt-text`, {
+                method: "POST",
+                headers: { Authorization: `Bearer ${state.token}`, "Content-Type": "application/json" },
+                body: JSON.stringify({ items: [{ kind: "emoji", id: emoji.id, alt_text: altText }] })
+            }).catch(() => null);
+            if (!res?.ok) {
+                const data = await res?.json().catch(() => ({}));
+                setServerSettingsFeedback(data?.error || "Impossible d'enregistrer le texte alternatif.", true);
+                return;
+            }
+            setServerSettingsFeedback("Texte alternatif enregistré.");
+        });
+        row.querySelector(".server-role-delete").addEventListener("click", async () => {
+            if (!confirm(`Supprimer :${emoji.name}: ? Ses réactions seront retirées.`)) return;
+            const res = await fetch(`${API}/api/server/emojis/${encodeURIComponent(emoji.id)}`, {
+                method: "DELETE",
+                headers: { Authorization: `Bearer ${state.token}` }
+            }).catch(() => null);
+            if (!res?.ok) {
+                setServerSettingsFeedback("Impossible de supprimer l'émoji.", true);
+                return;
+            }
+            setServerSettingsFeedback("Émoji supprimé.");
+        });
+        serverEmojisList.appendChild(row);
+    });
+}
+
+function announcementStatusLabel(a) {
+    if (a.cancelled_at) return "annulée";
+    if (a.expires_at && Date.parse(a.expires_at) <= Date.now()) return "expirée";
+    if (!a.published_at) return `prévue le ${new Date(a.starts_at).toLocaleString()}`;
+    return `publiée · masquée par ${a.dismissed_count || 0}`;
+}
+
+async function loadServerAnnouncements() {
+    if (!serverAnnouncementsList || serverSettingsModal?.classList.contains("hidden")) return;
+    try {
+        const res = await fetch(`${API}/api/server/announcements`, {
+            headers: { Authorization: `Bearer ${state.token}` }
+        });
+        const list = await res.json().catch(() => []);
+        if (!res.ok) return;
+
+        serverAnnouncementsList.innerHTML = "";
+        if (!list.length) {
+            serverAnnouncementsList.innerHTML = `<div class="server-role-item"><span class="server-role-name">Aucune annonce.</span></div>`;
+        }
+        list.forEach((a) => {
+            const row = document.createElement("div");
+            row.className = "server-role-item";
+            const active = !a.cancelled_at && !(a.expires_at && Date.parse(a.expires_at) <= Date.now());
+            row.innerHTML = `
+                <div class="server-role-left">
+                    <span class="server-role-name" title="${escapeHtml(a.content)}">${escapeHtml(a.content)}</span>
+                </div>
+                <div class="server-role-actions">
+                    <span>${escapeHtml(announcementStatusLabel(a))}</span>
+                    <button class="server-role-delete" ${active ? "" : "disabled"}>Annuler</button>
+                </div>
+            `;
+            row.querySelector("button").addEventListener("click", async () => {
+                if (!confirm("Annuler cette annonce ?")) return;
+                const res = await fetch(`${API}/api/server/announcements/${encodeURIComponent(a.id)}`, {
+                    method: "DELETE",
+                    headers: { Authorization: `Bearer ${state.token}` }
+                }).catch(() => null);
+                if (!res?.ok) {
+                    setServerSettingsFeedback("Impossible d'annuler l'annonce.", true);
+                    return;
+                }
+                loadServerAnnouncements();
+            });
+            serverAnnouncementsList.appendChild(row);
+        });
+    } catch (err) {
+        console.error("Failed to load announcements", err);
+    }
 }
 
 async function openServerSettingsModal() {
@@ -2542,6 +4617,76 @@ if (serverRoleForm) {
     });
 }
 
+if (serverAnnouncementForm) {
+    serverAnnouncementForm.addEventListener("submit", async (event) => {
+        event.preventDefault();
+        const content = ($("#server-announcement-content")?.value || "").trim();
+        if (!content) return;
+        const toIso = (value) => (value ? new Date(value).toISOString() : undefined);
+        const body = {
+            content,
+            level: $("#server-announcement-level")?.value || "info",
+            starts_at: toIso($("#server-announcement-starts")?.value),
+            expires_at: toIso($("#server-announcement-expires")?.value),
+            room_id: $("#server-announcement-post")?.checked && state.currentRoomKind === "text" ? state.currentRoomId : undefined,
+        };
+
+        try {
+            const res = await fetch(`${API}/api/server/announcements`, {
+                method: "POST",
+                headers: {
+                    "Content-Type": "application/json",
+                    Authorization: `Bearer ${state.token}`
+                },
+                body: JSON.stringify(body)
+            });
+            const data = await res.json().catch(() => ({}));
+            if (!res.ok) {
+                setServerSettingsFeedback(data.error || "Erreur", true);
+                return;
+            }
+            serverAnnouncementForm.reset();
+            await loadServerAnnouncements();
+            setServerSettingsFeedback(data.published_at ? "Annonce publiée." : "Annonce programmée.");
+        } catch (err) {
+            setServerSettingsFeedback("Erreur réseau.", true);
+        }
+    });
+}
+
+if (serverEmojiForm) {
+    serverEmojiForm.addEventListener("submit", async (event) => {
+        event.preventDefault();
+        const name = ($("#server-emoji-name")?.value || "").trim().toLowerCase();
+        const file = $("#server-emoji-file")?.files?.[0];
+        if (!name || !file) return;
+
+        const altText = ($("#server-emoji-alt")?.value || "").trim();
+        const query = altText ? `?alt_text=${encodeURIComponent(altText)}` : "";
+
+        const formData = new FormData();
+        formData.append("file", file);
+        try {
+            const res = await fetch(`${API}/api/server/emojis/${encodeURIComponent(name)}${query}`, {
+                method: "POST",
+                headers: { Authorization: `Bearer ${state.token}` },
+                body: formData
+            });
+            const data = await res.json().catch(() => ({}));
+            if (!res.ok) {
+                setServerSettingsFeedback(data.error || "Erreur", true);
+                return;
+            }
+            serverEmojiForm.reset();
+            rememberCustomEmojis([data]);
+            renderServerEmojis();
+            setServerSettingsFeedback(`Émoji :${data.name}: ajouté.`);
+        } catch (err) {
+            setServerSettingsFeedback("Erreur réseau.", true);
+        }
+    });
+}
+
 if (serverAssignBtn) {
     serverAssignBtn.addEventListener("click", async () => {
         const userId = serverUserSelect?.value;
@@ -2580,6 +4725,10 @@ settingsModal.addEventListener("click", (event) => {
 // Close on ESC
 document.addEventListener("keydown", (e) => {
     if (e.key === "Escape") {
+        if (quickSwitchModal && !quickSwitchModal.classList.contains("hidden")) {
+            closeQuickSwitcher();
+            return;
+        }
         if (searchModal && !searchModal.classList.contains("hidden")) {
             closeSearchModal();
             return;
@@ -2651,6 +4800,12 @@ function populateSettingsUI() {
     settingsUsernameDisplay.textContent = state.username;
     settingsDiscDisplay.textContent = disc;
     acctUsernameDisplay.textContent = state.username;
+    loadPasskeys();
+    loadTwoFactorStatus();
+    loadEmailStatus();
+    loadRecoveryStatus();
+    loadDiscordLink();
+    loadSecurityLog();
     settingsRoleBadge.textContent = (state.role || "USER").toUpperCase();
     setBannerBackground($("#settings-banner"), state.bannerUrl, state.avatarColor);
     applyOwnPresenceUI();
@@ -2877,7 +5032,7 @@ function renderColorPickerTo(container, selectedIndex, onClick) {
 }
 
 // Account field edit buttons
-document.querySelectorAll(".btn-field-edit").forEach(btn => {
+document.querySelectorAll(".btn-field-edit[data-edit]").forEach(btn => {
     btn.addEventListener("click", () => {
         const field = btn.dataset.edit;
         editPanel.classList.remove("hidden");
@@ -3121,42 +5276,54 @@ async function exportBannerCroppedBlob() {
     ctx.drawImage(bannerCropImage, drawX, drawY, drawW, drawH);
 
     return await new Promise((resolve) => {
-        canvas.toBlob((blob) => resolve(blob), "image/webp", 0.92);
+        canvas.toBlob((blob) => resolve(blob), "image/png");
     });
 }
 
-async function uploadAndSaveBanner(blob) {
-    if (!blob) throw new Error("Invalid banner blob");
-
+// Profile images go through the server's image pipeline, which resizes
+// them and sets the profile URL itself. It takes PNG or JPEG only.
+async function uploadProfileImage(path, file) {
     const formData = new FormData();
-    formData.append("file", new File([blob], "banner.webp", { type: "image/webp" }));
-
-    const uploadRes = await fetch(`${API}/api/upload`, {
-        method: "POST",
+    formData.append("file", file);
+    const res = await fetch(`${API}${path}`, {
+        method: "PUT",
         headers: { Authorization: `Bearer ${state.token}` },
         body: formData
     });
+    const data = await res.json().catch(() => ({}));
+    if (!res.ok) throw new Error(data.error || "Erreur d'upload");
+    return data;
+}
 
-    if (!uploadRes.ok) {
-        const data = await uploadRes.json();
-        throw new Error(data.error || "Erreur d'upload");
-    }
-
-    const uploadData = await uploadRes.json();
-    const bannerUrl = uploadData.url;
-
-    const saveRes = await fetch(`${API}/api/users/me`, {
+async function removeProfileImage(path, field) {
+    const headers = { Authorization: `Bearer ${state.token}` };
+    const res = await fetch(`${API}${path}`, { method: "DELETE", headers });
+    if (res.status !== 404) return res;
+    // Not an upload (a Discord avatar): clear the field itself.
+    return fetch(`${API}/api/users/me`, {
         method: "PATCH",
-        headers: {
-            "Content-Type": "application/json",
-            Authorization: `Bearer ${state.token}`
-        },
-        body: JSON.stringify({ banner_url: bannerUrl })
+        headers: { ...headers, "Content-Type": "application/json" },
+        body: JSON.stringify({ [field]: "" })
     });
+}
 
-    if (!saveRes.ok) {
-        throw new Error("Erreur lors de la sauvegarde");
-    }
+async function toPngFile(file) {
+    if (file.type === "image/png" || file.type === "image/jpeg") return file;
+    const bitmap = await createImageBitmap(file);
+    const canvas = document.createElement("canvas");
+    canvas.width = bitmap.width;
+    canvas.height = bitmap.height;
+    canvas.getContext("2d").drawImage(bitmap, 0, 0);
+    const blob = await new Promise((resolve) => canvas.toBlob(resolve, "image/png"));
+    if (!blob) throw new Error("Image invalide");
+    return new File([blob], "avatar.png", { type: "image/png" });
+}
+
+async function uploadAndSaveBanner(blob) {
+    if (!blob) throw new Error("Invalid banner blob");
+
+    const asset = await uploadProfileImage("/api/users/@me/banner", new File([blob], "banner.png", { type: "image/png" }));
+    const bannerUrl = asset.sizes[asset.sizes.length - 1].url;
 
     state.bannerUrl = bannerUrl;
     if (bannerRemoveBtn) bannerRemoveBtn.style.display = "inline-flex";
@@ -3281,14 +5448,7 @@ if (bannerCropModal) {
 if (bannerRemoveBtn) {
     bannerRemoveBtn.addEventListener("click", async () => {
         try {
-            const res = await fetch(`${API}/api/users/me`, {
-                method: "PATCH",
-                headers: {
-                    "Content-Type": "application/json",
-                    Authorization: `Bearer ${state.token}`
-                },
-                body: JSON.stringify({ banner_url: "" })
-            });
+            const res = await removeProfileImage("/api/users/@me/banner", "banner_url");
             if (res.ok) {
                 state.bannerUrl = null;
                 bannerRemoveBtn.style.display = "none";
@@ -3316,59 +5476,24 @@ avatarFileInput.addEventListener("change", async () => {
     avatarUploadStatus.textContent = "Upload en cours...";
 
     try {
-        const formData = new FormData();
-        formData.append("file", file);
-        const uploadRes = await fetch(`${API}/api/upload`, {
-            method: "POST",
-            headers: { Authorization: `Bearer ${state.token}` },
-            body: formData
-        });
-
-        if (!uploadRes.ok) {
-            const data = await uploadRes.json();
-            avatarUploadStatus.textContent = data.error || "Erreur d'upload";
-            return;
-        }
-
-        const uploadData = await uploadRes.json();
-        const avatarUrl = uploadData.url;
-
-        // Save to profile
-        const saveRes = await fetch(`${API}/api/users/me`, {
-            method: "PATCH",
-            headers: {
-                "Content-Type": "application/json",
-                Authorization: `Bearer ${state.token}`
-            },
-            body: JSON.stringify({ avatar_url: avatarUrl })
-        });
-
-        if (saveRes.ok) {
-            state.avatarUrl = avatarUrl;
-            avatarUploadStatus.textContent = "\u2713 Avatar mis \u00e0 jour !";
-            avatarRemoveBtn.style.display = "inline-flex";
-            updateUserPanel();
-            populateSettingsUI();
-            connectWebSocket();
-        } else {
-            avatarUploadStatus.textContent = "Erreur lors de la sauvegarde";
-        }
+        const asset = await uploadProfileImage("/api/users/@me/avatar", await toPngFile(file));
+        // The server points the profile at the 256px size, or the largest below.
+        const size = [...asset.sizes].reverse().find((s) => s.width <= 256) || asset.sizes[0];
+        state.avatarUrl = size.url;
+        avatarUploadStatus.textContent = "\u2713 Avatar mis \u00e0 jour !";
+        avatarRemoveBtn.style.display = "inline-flex";
+        updateUserPanel();
+        populateSettingsUI();
+        connectWebSocket();
     } catch (err) {
-        avatarUploadStatus.textContent = "Erreur r\u00e9seau";
+        avatarUploadStatus.textContent = err.message || "Erreur r\u00e9seau";
     }
     avatarFileInput.value = "";
 });
 
 avatarRemoveBtn.addEventListener("click", async () => {
     try {
-        const res = await fetch(`${API}/api/users/me`, {
-            method: "PATCH",
-            headers: {
-                "Content-Type": "application/json",
-                Authorization: `Bearer ${state.token}`
-            },
-            body: JSON.stringify({ avatar_url: "" })
-        });
+        const res = await removeProfileImage("/api/users/@me/avatar", "avatar_url");
         if (res.ok) {
             state.avatarUrl = null;
             avatarRemoveBtn.style.display = "none";
@@ -3461,6 +5586,51 @@ createRoomForm.addEventListener("submit", async (e) => {
     } catch (err) { alert("Erreur réseau"); }
 });
 
+if (pollBtn) {
+    pollBtn.addEventListener("click", () => {
+        if (!state.currentRoomId || state.currentRoomKind !== "text") return;
+        createPollForm.reset();
+        createPollModal.classList.remove("hidden");
+        pollQuestionInput.focus();
+    });
+}
+
+if (cancelPollBtn) {
+    cancelPollBtn.addEventListener("click", () => createPollModal.classList.add("hidden"));
+}
+
+if (createPollForm) {
+    createPollForm.addEventListener("submit", async (e) => {
+        e.preventDefault();
+        const question = pollQuestionInput.value.trim();
+        const options = pollOptionsInput.value.split("\n").map((o) => o.trim()).filter(Boolean);
+        if (!question || options.length < 2) {
+            showToast("Un sondage a besoin d'une question et d'au moins deux réponses");
+            return;
+        }
+        try {
+            const res = await fetch(`${API}/api/rooms/${state.currentRoomId}/polls`, {
+                method: "POST",
+                headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+                body: JSON.stringify({
+                    question,
+                    options,
+                    multi_select: pollMultiInput.checked,
+                    duration_hours: Number(pollDurationInput.value),
+                }),
+            });
+            if (res.ok) {
+                createPollModal.classList.add("hidden");
+            } else {
+                const data = await res.json();
+                showToast(data.error || "Impossible de créer le sondage");
+            }
+        } catch (err) {
+            showToast("Erreur réseau");
+        }
+    });
+}
+
 // ── User Popout Card ───────────────────────────────────
 let currentPopoutUserId = null;
 
@@ -3495,7 +5665,8 @@ function renderUserPopoutContent(uid, user) {
         <div style="display:inline-flex;align-items:center;gap:6px;background:var(--background-secondary);padding:4px 8px;border-radius:4px;font-size:12px">
             <div style="width:8px;height:8px;border-radius:50%;background:${presenceDotClass(user.status || 'online') === 'online' ? '#3ba55d' : presenceDotClass(user.status || 'online') === 'idle' ? '#faa61a' : presenceDotClass(user.status || 'online') === 'dnd' ? '#ed4245' : '#747f8d'}"></div>
             ${statusText}
-        </div>`;
+        </div>
+        ${user.custom_status ? `<div class="popout-custom-status">${escapeHtml(user.custom_status)}</div>` : ""}`;
     }
     // About Me display
     const aboutSection = userPopout.querySelector("#popout-about-section");
@@ -3648,6 +5819,164 @@ function closeSearchModal() {
     searchModal?.classList.add("hidden");
 }
 
+// ── Quick switcher (Ctrl+K) ────────────────────────────
+// Rooms and users come from /api/quickswitch (ranked and permission-filtered
+// server-side). Discord guilds and DMs are only known here, they are matched
+// locally when Discord mode is on and listed after the Voxium results.
+let quickSwitchItems = [];
+let quickSwitchActive = 0;
+let quickSwitchTimer = null;
+let quickSwitchSeq = 0;
+
+function foldSearchText(text) {
+    return (text || "").toLowerCase().normalize("NFD").replace(/[\u0300-\u036f]/g, "");
+}
+
+function matchDiscordItems(query) {
+    if (!discordState.mode) return [];
+    const q = foldSearchText(query);
+    const items = [
+        ...discordState.guilds.map((g) => ({ type: "guild", id: g.id, name: g.name, guild: g })),
+        ...discordState.dms.map((dm) => ({ type: "dm", id: dm.id, name: discordDMName(dm) })),
+    ];
+    if (!q) return items.slice(0, 10);
+    return items
+        .map((item) => {
+            const name = foldSearchText(item.name);
+            const score = name.startsWith(q) ? 2 : (name.includes(q) ? 1 : 0);
+            return { ...item, score };
+        })
+        .filter((item) => item.score > 0)
+        .sort((a, b) => b.score - a.score)
+        .slice(0, 10);
+}
+
+function renderQuickSwitch() {
+    if (!quickSwitchResults) return;
+    if (!quickSwitchItems.length) {
+        quickSwitchResults.innerHTML = `<div class="quickswitch-item">Aucun résultat.</div>`;
+        return;
+    }
+    quickSwitchResults.innerHTML = "";
+    quickSwitchItems.forEach((item, index) => {
+        const icons = { room: item.detail === "voice" ? "🔊" : "#", user: "@", guild: "D", dm: "@" };
+        const details = {
+            room: item.topic || "",
+            user: item.detail,
+            guild: "Serveur Discord",
+            dm: "Message privé Discord",
+        };
+        const row = document.createElement("div");
+        row.className = `quickswitch-item${index === quickSwitchActive ? " active" : ""}`;
+        row.setAttribute("role", "option");
+        row.innerHTML = `
+            <span class="quickswitch-icon">${icons[item.type] || "#"}</span>
+            <span class="quickswitch-name">${escapeHtml(item.name)}</span>
+            <span class="quickswitch-detail">${escapeHtml(details[item.type] || "")}</span>`;
+        row.addEventListener("mouseenter", () => {
+            quickSwitchActive = index;
+            quickSwitchResults.querySelectorAll(".quickswitch-item").forEach((el, i) => el.classList.toggle("active", i === index));
+        });
+        row.addEventListener("click", (event) => {
+            // Keep the document click handler from closing the user popout we open.
+            event.stopPropagation();
+            activateQuickSwitchItem(item);
+        });
+        quickSwitchResults.appendChild(row);
+    });
+    quickSwitchResults.children[quickSwitchActive]?.scrollIntoView({ block: "nearest" });
+}
+
+async function runQuickSwitch(query) {
+    const seq = ++quickSwitchSeq;
+    let results = [];
+    try {
+        const res = await fetch(`${API}/api/quickswitch?q=${encodeURIComponent(query)}`, {
+            headers: { Authorization: `Bearer ${state.token}` },
+        });
+        if (res.ok) results = (await res.json()).results || [];
+    } catch (err) {
+        console.error("Quick switcher search failed", err);
+    }
+    // A newer keystroke already asked for something else.
+    if (seq !== quickSwitchSeq) return;
+    quickSwitchItems = [...results, ...matchDiscordItems(query)];
+    quickSwitchActive = 0;
+    renderQuickSwitch();
+}
+
+function openQuickSwitcher() {
+    if (!quickSwitchModal || !state.token) return;
+    quickSwitchModal.classList.remove("hidden");
+    quickSwitchInput.value = "";
+    quickSwitchInput.focus();
+    runQuickSwitch("");
+}
+
+function closeQuickSwitcher() {
+    quickSwitchModal?.classList.add("hidden");
+}
+
+async function activateQuickSwitchItem(item) {
+    closeQuickSwitcher();
+    if (item.type === "room") {
+        if (discordState.mode) exitDiscordMode();
+        const room = state.rooms.find((r) => r.id === item.id);
+        if (room) selectRoom(room);
+    } else if (item.type === "user") {
+        const user = state.users[item.id] || {
+            username: item.name,
+            role: item.detail,
+            avatar_color: item.avatar_color || 0,
+            avatar_url: item.avatar_url || null,
+        };
+        showUserPopout(null, item.id, user);
+        userPopout.style.top = "120px";
+        userPopout.style.left = "50%";
+        userPopout.style.right = "auto";
+        userPopout.classList.remove("hidden");
+    } else if (item.type === "guild") {
+        selectDiscordGuild(item.guild);
+    } else if (item.type === "dm") {
+        await loadDiscordDMsList();
+        openDiscordDM(item.id, item.name);
+    }
+}
+
+quickSwitchInput?.addEventListener("input", () => {
+    clearTimeout(quickSwitchTimer);
+    quickSwitchTimer = setTimeout(() => runQuickSwitch(quickSwitchInput.value.trim()), 120);
+});
+
+quickSwitchInput?.addEventListener("keydown", (event) => {
+    if (event.key === "ArrowDown" || event.key === "ArrowUp") {
+        event.preventDefault();
+        if (!quickSwitchItems.length) return;
+        const step = event.key === "ArrowDown" ? 1 : -1;
+        quickSwitchActive = (quickSwitchActive + step + quickSwitchItems.length) % quickSwitchItems.length;
+        renderQuickSwitch();
+    } else if (event.key === "Enter") {
+        event.preventDefault();
+        const item = quickSwitchItems[quickSwitchActive];
+        if (item) activateQuickSwitchItem(item);
+    }
+});
+
+quickSwitchModal?.addEventListener("click", (event) => {
+    if (event.target === quickSwitchModal) closeQuickSwitcher();
+});
+
+document.addEventListener("keydown", (event) => {
+    if ((event.ctrlKey || event.metaKey) && event.key.toLowerCase() === "k") {
+        event.preventDefault();
+        if (quickSwitchModal?.classList.contains("hidden")) {
+            openQuickSwitcher();
+        } else {
+            closeQuickSwitcher();
+        }
+    }
+});
+
 function renderSearchResults(items) {
     if (!searchResults) return;
     if (!Array.isArray(items) || items.length === 0) {
@@ -3747,6 +6076,7 @@ const discordState = {
     currentChannelId: null,
     guilds: [],
     channels: [],
+    dms: [],
     oldestMessageId: null,
     loadingMore: false,
 };
@@ -3821,6 +6151,8 @@ function enterDiscordMode() {
         currentRoomTopic.classList.remove("hidden");
     }
     deleteRoomBtn?.classList.add("hidden");
+    slowmodeBtn?.classList.add("hidden");
+    currentRoomSlowmode?.classList.add("hidden");
     messageInputArea?.classList.add("hidden");
     if (pinnedBtn) pinnedBtn.classList.add("hidden");
 
@@ -3877,9 +6209,26 @@ function exitDiscordMode() {
     membersSidebar?.classList.remove("hidden");
 }
 
+// ── Warn when the linked Discord token is dead ──────────
+async function checkDiscordLinkStatus() {
+    const token = localStorage.getItem("token") || "";
+    try {
+        const resp = await fetch(`${API.replace(/\/$/, "")}/api/discord/link/status`, {
+            headers: { Authorization: `Bearer ${token}` },
+        });
+        if (!resp.ok) return;
+        const data = await resp.json().catch(() => null);
+        if (data?.relink_required) {
+            const reason = data.status === "expired" ? "a expiré" : "a été révoquée";
+            showToast(`Votre liaison Discord ${reason}. Reconnectez votre compte Discord.`, "error", 8000);
+        }
+    } catch (_) { }
+}
+
 // ── Load guilds into the guild bar ──────────────────────
 async function loadDiscordGuildsBar() {
     if (!discordGuildsContainer) return;
+    checkDiscordLinkStatus();
     discordGuildsContainer.innerHTML = `
         <div class="guild-icon is-skeleton" aria-hidden="true"></div>
         <div class="guild-icon is-skeleton" aria-hidden="true"></div>
@@ -4070,6 +6419,7 @@ async function loadDiscordDMsList() {
 
     try {
         const dms = await VoxiumDiscord.getDMChannels();
+        discordState.dms = [];
         roomsList.innerHTML = "";
 
         if (!dms?.length) {
@@ -4086,22 +6436,21 @@ async function loadDiscordDMsList() {
             return 0;
         });
 
+        discordState.dms = sorted;
         sorted.forEach(dm => {
             const li = document.createElement("li");
             li.className = "discord-dm-item";
             li.dataset.id = dm.id;
 
-            let name = "DM";
+            const name = discordDMName(dm);
             let avatarUrl = "";
 
             if (dm.type === CHAN_DM && dm.recipients?.length) {
                 const r = dm.recipients[0];
-                name = r.global_name || r.username || "Utilisateur";
                 avatarUrl = r.avatar
                     ? `https://cdn.discordapp.com/avatars/${r.id}/${r.avatar}.webp?size=32`
                     : `https://cdn.discordapp.com/embed/avatars/${(parseInt(r.discriminator || "0") || 0) % 5}.png`;
             } else if (dm.type === CHAN_GROUP_DM) {
-                name = dm.name || dm.recipients?.map(r => r.global_name || r.username).join(", ") || "Groupe";
                 avatarUrl = dm.icon
                     ? `https://cdn.discordapp.com/channel-icons/${dm.id}/${dm.icon}.webp?size=32`
                     : "";
@@ -4112,15 +6461,7 @@ async function loadDiscordDMsList() {
                 : `<span class="channel-hash">@</span>`;
 
             li.innerHTML = `${avatarHtml}<span>${escapeHtml(name)}</span><span class="integration-mini" aria-hidden="true">D</span>`;
-            li.addEventListener("click", () => {
-                discordState.currentGuildId = null;
-                discordState.currentChannelId = dm.id;
-                roomsList.querySelectorAll("li").forEach(l => l.classList.toggle("active", l.dataset.id === dm.id));
-                if (currentRoomName) currentRoomName.textContent = name;
-                if (roomKindIcon) roomKindIcon.textContent = "@";
-                messageInputArea?.classList.remove("hidden");
-                loadDiscordMessages(dm.id);
-            });
+            li.addEventListener("click", () => openDiscordDM(dm.id, name));
 
             roomsList.appendChild(li);
         });
@@ -4130,6 +6471,27 @@ async function loadDiscordDMsList() {
     }
 }
 
+function discordDMName(dm) {
+    if (dm.type === CHAN_DM && dm.recipients?.length) {
+        const r = dm.recipients[0];
+        return r.global_name || r.username || "Utilisateur";
+    }
+    if (dm.type === CHAN_GROUP_DM) {
+        return dm.name || dm.recipients?.map(r => r.global_name || r.username).join(", ") || "Groupe";
+    }
+    return "DM";
+}
+
+function openDiscordDM(dmId, name) {
+    discordState.currentGuildId = null;
+    discordState.currentChannelId = dmId;
+    roomsList.querySelectorAll("li").forEach(l => l.classList.toggle("active", l.dataset.id === dmId));
+    if (currentRoomName) currentRoomName.textContent = name;
+    if (roomKindIcon) roomKindIcon.textContent = "@";
+    messageInputArea?.classList.remove("hidden");
+    loadDiscordMessages(dmId);
+}
+
 // ── Select Discord guild ────────────────────────────────
 async function selectDiscordGuild(guild) {
     discordState.currentGuildId = guild.id;
@@ -4229,10 +6591,53 @@ function appendDiscordChannelItem(channel) {
         li.classList.add("discord-voice-channel");
         li.title = "Cliquer pour rejoindre le vocal";
         li.addEventListener("click", () => joinDiscordVoiceChannel(channel));
+        const guildId = discordState.currentGuildId;
+        preflightDiscordVoice(guildId, channel.id).then((result) => {
+            if (discordState.currentGuildId === guildId) markDiscordVoiceAvailability(li, result);
+        });
     }
     roomsList.appendChild(li);
 }
 
+const DISCORD_VOICE_PREFLIGHT_MESSAGES = {
+    relink_required: "Lien Discord expiré, reconnectez votre compte",
+    not_linked: "Aucun compte Discord lié",
+    channel_not_found: "Salon introuvable",
+    not_voice_channel: "Ce salon n'est pas un salon vocal",
+    not_in_guild: "Vous n'êtes pas membre de ce serveur",
+    missing_view_channel: "Vous ne pouvez pas voir ce salon",
+    missing_connect: "Vous n'avez pas la permission de vous connecter à ce salon",
+    channel_full: "Ce salon vocal est plein",
+};
+
+function discordVoicePreflightMessage(result) {
+    const first = result?.reasons?.[0];
+    if (!first) return "";
+    return DISCORD_VOICE_PREFLIGHT_MESSAGES[first.code] || first.message;
+}
+
+// Asks the server whether a join would succeed; null when it can't tell.
+async function preflightDiscordVoice(guildId, channelId) {
+    if (!guildId || !channelId) return null;
+    try {
+        const res = await fetch(`${API}/api/discord/voice/preflight`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json", Authorization: `Bearer ${state.token}` },
+            body: JSON.stringify({ guild_id: guildId, channel_id: channelId }),
+        });
+        if (!res.ok) return null;
+        return await res.json();
+    } catch {
+        return null;
+    }
+}
+
+function markDiscordVoiceAvailability(li, result) {
+    const blocked = result && !result.can_join;
+    li.classList.toggle("discord-voice-unavailable", !!blocked);
+    li.title = blocked ? discordVoicePreflightMessage(result) : "Cliquer pour rejoindre le vocal";
+}
+
 // ── Discord Voice Channel Handling ──────────────────────
 
 // State tracking for Discord voice
@@ -4449,6 +6854,15 @@ async function joinDiscordVoiceChannel(channel) {
         return;
     }
 
+    // Don't wait 20 s for a join Discord will refuse
+    const preflight = await preflightDiscordVoice(discordState.currentGuildId, channel.id);
+    const channelLi = roomsList.querySelector(`.discord-voice-channel[data-id="${channel.id}"]`);
+    if (channelLi && preflight) markDiscordVoiceAvailability(channelLi, preflight);
+    if (preflight && !preflight.can_join) {
+        showToast(discordVoicePreflightMessage(preflight), "error");
+        return;
+    }
+
     // If in another channel, leave first
     if (window.VoxiumDiscordVoice.isConnected()) {
         await leaveDiscordVoiceChannel();
@@ -4826,8 +7240,13 @@ async function loadDiscordMessages(channelId, before = null) {
     }
 }
 
-// ── Scroll-up infinite loading (Discord mode) ───────────
+// ── Scroll-up infinite loading ──────────────────────────
 if (messagesContainer) {
+    messagesContainer.addEventListener("scroll", () => {
+        if (discordState.mode || messagesContainer.scrollTop >= 200) return;
+        loadOlderMessages();
+    });
+
     messagesContainer.addEventListener("scroll", async () => {
         if (!discordState.mode) return;
         if (discordState.loadingMore) return;
@@ -5095,7 +7514,7 @@ chatDropArea.addEventListener("drop", (e) => {
     e.preventDefault();
     if (!state.currentRoomId) return;
     const files = e.dataTransfer.files;
-    if (files.length > 0 && files[0].type.startsWith("image/")) {
+    if (files.length > 0) {
         const dt = new DataTransfer();
         dt.items.add(files[0]);
         fileInput.files = dt.files;
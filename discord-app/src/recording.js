@@ -0,0 +1,298 @@
+window.VoxiumRecording = (() => {
+    // The server joins each participant's segments into one Ogg Opus track:
+    // the recorder must produce Ogg Opus, segments go up in order.
+    const RECORDING_MIME = "audio/ogg;codecs=opus";
+    const SEGMENT_MS = 5000;
+    // Under the server's default body limit.
+    const MAX_SEGMENT_BYTES = 256 * 1024;
+    const UPLOAD_RETRIES = 3;
+    const RETRY_DELAY_MS = 2000;
+
+    function isSupported() {
+        return !!(window.MediaRecorder && MediaRecorder.isTypeSupported(RECORDING_MIME));
+    }
+
+    function createRecordingController(deps) {
+        const dom = deps.dom;
+        const getState = deps.getState;
+        const API = deps.API;
+
+        // Recording running in the joined voice room, as last announced.
+        let recording = null;
+        let recorder = null;
+        let recorderRecordingId = null;
+        // Segments waiting for upload: { roomId, recordingId, blob }.
+        const queue = [];
+        let uploading = false;
+
+        function authHeaders(extra = {}) {
+            return { Authorization: `Bearer ${getState().token}`, ...extra };
+        }
+
+        function joinedRoomId() {
+            return getState().voice.joinedRoomId;
+        }
+
+        function myConsent() {
+            const userId = getState().userId;
+            return !!recording?.participants?.find((p) => p.user_id === userId)?.consented;
+        }
+
+        // The next segment number of a track; kept for the session so a
+        // rejoin or reload carries on where the track stopped.
+        function seqKey(recordingId) {
+            return `voiceRecordingSeq:${recordingId}`;
+        }
+
+        function getSeq(recordingId) {
+            return Number(sessionStorage.getItem(seqKey(recordingId))) || 0;
+        }
+
+        function setSeq(recordingId, seq) {
+            sessionStorage.setItem(seqKey(recordingId), String(seq));
+        }
+
+        function render() {
+            if (!dom.voiceRecordingWrap) return;
+            const state = getState();
+            const inRoom = !!joinedRoomId();
+            dom.voiceRecordingWrap.classList.toggle("hidden", !inRoom);
+            if (!inRoom) return;
+
+            const consented = myConsent();
+            dom.voiceRecordingWrap.classList.toggle("active", !!recording);
+            if (dom.voiceRecordingStatus) {
+                if (!recording) {
+                    dom.voiceRecordingStatus.textContent = "Ce salon n'est pas enregistré.";
+                } else {
+                    const count = (recording.participants || []).filter((p) => p.consented).length;
+                    const who = deps.escapeHtml(recording.started_by_username || "?");
+                    const mine = consented
+                        ? (recorder ? "Votre micro est enregistré." : "Vous avez accepté d'être enregistré.")
+                        : "Votre micro n'est pas enregistré.";
+                    dom.voiceRecordingStatus.innerHTML = `<strong>Enregistrement en cours</strong> — lancé par ${who}, ${count} participant(s) enregistré(s). ${mine}`;
+                }
+            }
+            if (dom.voiceRecordingConsentBtn) {
+                dom.voiceRecordingConsentBtn.classList.toggle("hidden", !recording || !isSupported());
+                dom.voiceRecordingConsentBtn.textContent = consented ? "Ne plus être enregistré" : "Accepter d'être enregistré";
+            }
+            if (dom.voiceRecordingToggleBtn) {
+                dom.voiceRecordingToggleBtn.classList.toggle("hidden", state.role !== "admin");
+                dom.voiceRecordingToggleBtn.textContent = recording ? "Arrêter l'enregistrement" : "Enregistrer le salon";
+            }
+        }
+
+        // ── Capture ─────────────────────────────────────────
+
+        function startCapture() {
+            if (!recording || !isSupported()) return;
+            if (recorder && recorderRecordingId === recording.id) return;
+            stopCapture();
+            const tracks = getState().voice.localStream?.getAudioTracks() || [];
+            if (!tracks.length) return;
+
+            const recordingId = recording.id;
+            const roomId = joinedRoomId();
+            try {
+                recorder = new MediaRecorder(new MediaStream(tracks), { mimeType: RECORDING_MIME });
+            } catch (err) {
+                console.error("Voice recording:", err);
+                recorder = null;
+                return;
+            }
+            recorderRecordingId = recordingId;
+            recorder.addEventListener("dataavailable", (e) => {
+                if (!e.data.size) return;
+                for (let offset = 0; offset < e.data.size; offset += MAX_SEGMENT_BYTES) {
+                    queue.push({ roomId, recordingId, blob: e.data.slice(offset, offset + MAX_SEGMENT_BYTES) });
+                }
+                pumpUploads();
+            });
+            recorder.start(SEGMENT_MS);
+        }
+
+        // Stop the recorder; what it still holds is queued and sent.
+        function stopCapture() {
+            if (!recorder) return;
+            const stopping = recorder;
+            recorder = null;
+            recorderRecordingId = null;
+            if (stopping.state !== "inactive") stopping.stop();
+        }
+
+        // Stop and forget the track: the server dropped it.
+        function discardCapture(recordingId) {
+            stopCapture();
+            for (let i = queue.length - 1; i >= 0; i--) {
+                if (queue[i].recordingId === recordingId) queue.splice(i, 1);
+            }
+            sessionStorage.removeItem(seqKey(recordingId));
+        }
+
+        // ── Upload ──────────────────────────────────────────
+
+        function wait(ms) {
+            return new Promise((resolve) => window.setTimeout(resolve, ms));
+        }
+
+        // Returns false when the rest of the track can't be sent either.
+        async function uploadSegment({ roomId, recordingId, blob }) {
+            for (let attempt = 0; attempt <= UPLOAD_RETRIES; attempt++) {
+                if (attempt) await wait(RETRY_DELAY_MS);
+                const seq = getSeq(recordingId);
+                let res;
+                try {
+                    res = await fetch(`${API}/api/rooms/${roomId}/voice/recording/tracks/${seq}`, {
+                        method: "PUT",
+                        headers: authHeaders({ "Content-Type": "application/octet-stream" }),
+                        body: blob,
+                    });
+                } catch (err) {
+                    continue;
+                }
+                if (res.ok) {
+                    setSeq(recordingId, seq + 1);
+                    return true;
+                }
+                const data = await res.json().catch(() => ({}));
+                if (res.status === 409 && Number.isInteger(data.next_seq) && data.next_seq !== seq) {
+                    // Out of step with the server: send this one as its next.
+                    setSeq(recordingId, data.next_seq);
+                    attempt--;
+                    continue;
+                }
+                if (res.status === 409 || res.status === 403 || res.status === 404) {
+                    // Over (grace included), or consent withdrawn elsewhere.
+                    return false;
+                }
+                if (res.status < 500 && res.status !== 429) {
+                    deps.showToast(data.error || "Erreur d'envoi de l'enregistrement");
+                    return false;
+                }
+            }
+            deps.showToast("Un morceau de l'enregistrement n'a pas pu être envoyé");
+            return true;
+        }
+
+        async function pumpUploads() {
+            if (uploading) return;
+            uploading = true;
+            try {
+                while (queue.length) {
+                    const segment = queue.shift();
+                    if (!(await uploadSegment(segment))) {
+                        discardCapture(segment.recordingId);
+                    }
+                }
+            } finally {
+                uploading = false;
+            }
+        }
+
+        // ── State ───────────────────────────────────────────
+
+        function applyRecording(next) {
+            recording = next || null;
+            if (!recording || !joinedRoomId()) {
+                // Ended: what was recorded is still sent within the grace.
+                stopCapture();
+            } else if (myConsent()) {
+                startCapture();
+            } else {
+                // Withdrawn: the server dropped the track.
+                discardCapture(recording.id);
+            }
+            render();
+        }
+
+        async function syncRecording() {
+            const roomId = joinedRoomId();
+            if (!roomId) {
+                applyRecording(null);
+                return;
+            }
+            try {
+                const res = await fetch(`${API}/api/rooms/${roomId}/voice/recording`, { headers: authHeaders() });
+                if (!res.ok) return;
+                const data = await res.json();
+                if (data.room_id === joinedRoomId()) applyRecording(data.recording);
+            } catch (err) {
+                console.error("Voice recording:", err);
+            }
+        }
+
+        function handleRecordingWsEvent(msg) {
+            if (!msg.room_id || msg.room_id !== joinedRoomId()) return;
+            applyRecording(msg.recording);
+        }
+
+        // Before leaving the voice room: the microphone is about to stop.
+        function leaveRoom() {
+            stopCapture();
+            recording = null;
+            render();
+        }
+
+        async function toggleConsent() {
+            const roomId = joinedRoomId();
+            if (!roomId || !recording) return;
+            const consent = !myConsent();
+            if (!consent) discardCapture(recording.id);
+            try {
+                const res = await fetch(`${API}/api/rooms/${roomId}/voice/recording/consent`, {
+                    method: "PUT",
+                    headers: authHeaders({ "Content-Type": "application/json" }),
+                    body: JSON.stringify({ consent }),
+                });
+                const data = await res.json().catch(() => ({}));
+                if (!res.ok) {
+                    deps.showToast(data.error || "Impossible de modifier votre consentement");
+                    return;
+                }
+                if (data.room_id === joinedRoomId()) applyRecording(data.recording);
+            } catch (err) {
+                deps.showToast("Erreur réseau");
+            }
+        }
+
+        async function toggleRecording() {
+            const roomId = joinedRoomId();
+            if (!roomId) return;
+            const stopping = !!recording;
+            try {
+                const res = await fetch(`${API}/api/rooms/${roomId}/voice/recording`, {
+                    method: stopping ? "DELETE" : "POST",
+                    headers: authHeaders(),
+                });
+                const data = await res.json().catch(() => ({}));
+                if (!res.ok) {
+                    deps.showToast(data.error || "Impossible de modifier l'enregistrement");
+                    return;
+                }
+                if (stopping) {
+                    deps.showToast("Enregistrement arrêté, il sera disponible dans quelques instants.", "success");
+                    applyRecording(null);
+                } else if (data.room_id === joinedRoomId()) {
+                    applyRecording(data.recording);
+                }
+            } catch (err) {
+                deps.showToast("Erreur réseau");
+            }
+        }
+
+        return {
+            render,
+            syncRecording,
+            handleRecordingWsEvent,
+            leaveRoom,
+            toggleConsent,
+            toggleRecording,
+        };
+    }
+
+    return {
+        isSupported,
+        createRecordingController,
+    };
+})();
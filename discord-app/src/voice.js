@@ -16,6 +16,9 @@ window.VoxiumVoice = (() => {
             members: {},
             muted: false,
             deafened: false,
+            prioritySpeakers: new Set(),
+            duckingDb: 12,
+            ducking: false,
         };
     }
 
@@ -27,6 +30,16 @@ window.VoxiumVoice = (() => {
         let micMeterSource = null;
         let micMeterData = null;
         let micMeterAnim = null;
+        let rtcConfig = deps.WEBRTC_CONFIG;
+
+        // Priority speaker ducking: one analyser per priority speaker we
+        // receive, polled while any exists.
+        let duckingAudioCtx = null;
+        let duckingTimer = null;
+        let priorityHeardAt = 0;
+        const priorityAnalysers = {};
+        const PRIORITY_SPEECH_LEVEL = 0.08;
+        const PRIORITY_HANGOVER_MS = 400;
 
         const getState = deps.getState;
         const videoController = window.VoxiumVideo.createVideoShareController({
@@ -257,6 +270,7 @@ window.VoxiumVoice = (() => {
                     : deps.escapeHtml((member.username || "U")[0].toUpperCase());
 
                 const badges = [];
+                if (state.voice.prioritySpeakers.has(member.user_id)) badges.push('<span class="voice-badge is-priority" title="Orateur prioritaire">⭐</span>');
                 if (member.muted) badges.push('<span class="voice-badge is-danger">Muet</span>');
                 if (member.deafened) badges.push('<span class="voice-badge is-danger">Casque</span>');
                 if (member.screenSharing) badges.push('<span class="voice-badge is-good">Écran</span>');
@@ -269,6 +283,15 @@ window.VoxiumVoice = (() => {
                     </div>
                     <div class="voice-member-badges">${badges.join("")}</div>
                 `;
+                if (state.role === "admin" && state.voice.joinedRoomId) {
+                    const isPriority = state.voice.prioritySpeakers.has(member.user_id);
+                    const toggle = document.createElement("button");
+                    toggle.className = "voice-priority-toggle";
+                    toggle.textContent = isPriority ? "Retirer ⭐" : "⭐";
+                    toggle.title = isPriority ? "Retirer l'orateur prioritaire" : "Rendre orateur prioritaire";
+                    toggle.addEventListener("click", () => setPrioritySpeaker(member.user_id, !isPriority));
+                    li.querySelector(".voice-member-badges").appendChild(toggle);
+                }
                 dom.voiceMembersList.appendChild(li);
             });
         }
@@ -327,6 +350,7 @@ window.VoxiumVoice = (() => {
             delete state.voice.remoteStreams[userId];
             delete state.voice.screenSenders[userId];
             removeRemoteScreenTile(userId);
+            syncPriorityAnalysers();
         }
 
         function resetVoiceConnections() {
@@ -347,7 +371,7 @@ window.VoxiumVoice = (() => {
                 return state.voice.peers[remoteUserId];
             }
 
-            const peer = new RTCPeerConnection(deps.WEBRTC_CONFIG);
+            const peer = new RTCPeerConnection(rtcConfig);
             state.voice.peers[remoteUserId] = peer;
 
             if (state.voice.localStream) {
@@ -390,6 +414,7 @@ window.VoxiumVoice = (() => {
                 audioEl.srcObject = remoteStream;
                 audioEl.muted = localState.voice.deafened;
                 audioEl.play().catch(() => { });
+                syncPriorityAnalysers();
 
                 syncRemoteScreenTile(remoteUserId, remoteStream);
                 remoteStream.onremovetrack = () => {
@@ -465,6 +490,129 @@ window.VoxiumVoice = (() => {
             });
         }
 
+        function duckedVolume() {
+            return Math.pow(10, -getState().voice.duckingDb / 20);
+        }
+
+        /** Play everyone but the priority speakers at the ducked volume while `active`. */
+        function applyDucking(active) {
+            const state = getState();
+            Object.entries(state.voice.audioEls).forEach(([userId, audioEl]) => {
+                audioEl.volume = active && !state.voice.prioritySpeakers.has(userId) ? duckedVolume() : 1;
+            });
+            if (state.voice.ducking !== active) {
+                state.voice.ducking = active;
+                dom.voiceMembersList.classList.toggle("ducking", active);
+            }
+        }
+
+        function priorityLevel({ analyser, data }) {
+            analyser.getByteTimeDomainData(data);
+            let sum = 0;
+            for (let i = 0; i < data.length; i++) {
+                const normalized = (data[i] - 128) / 128;
+                sum += normalized * normalized;
+            }
+            return Math.min(1, Math.sqrt(sum / data.length) * 7.5);
+        }
+
+        function updateDucking() {
+            const heard = Object.values(priorityAnalysers).some((entry) => priorityLevel(entry) > PRIORITY_SPEECH_LEVEL);
+            if (heard) priorityHeardAt = Date.now();
+            applyDucking(Date.now() - priorityHeardAt < PRIORITY_HANGOVER_MS);
+        }
+
+        /** Follow the audio of the priority speakers we receive, and only theirs. */
+        function syncPriorityAnalysers() {
+            const state = getState();
+            Object.keys(priorityAnalysers).forEach((userId) => {
+                if (state.voice.prioritySpeakers.has(userId) && state.voice.remoteStreams[userId]) return;
+                priorityAnalysers[userId].source.disconnect();
+                delete priorityAnalysers[userId];
+            });
+
+            const AudioCtx = window.AudioContext || window.webkitAudioContext;
+            if (AudioCtx && state.voice.joinedRoomId) {
+                state.voice.prioritySpeakers.forEach((userId) => {
+                    const stream = state.voice.remoteStreams[userId];
+                    if (userId === state.userId || priorityAnalysers[userId] || !stream || stream.getAudioTracks().length === 0) return;
+                    try {
+                        duckingAudioCtx = duckingAudioCtx || new AudioCtx();
+                        const analyser = duckingAudioCtx.createAnalyser();
+                        analyser.fftSize = 512;
+                        const source = duckingAudioCtx.createMediaStreamSource(stream);
+                        source.connect(analyser);
+                        priorityAnalysers[userId] = { analyser, source, data: new Uint8Array(analyser.fftSize) };
+                    } catch (err) {
+                        console.error("Priority speaker analyser error", err);
+                    }
+                });
+            }
+
+            const following = Object.keys(priorityAnalysers).length > 0;
+            if (following && !duckingTimer) {
+                duckingTimer = setInterval(updateDucking, 100);
+            } else if (!following && duckingTimer) {
+                clearInterval(duckingTimer);
+                duckingTimer = null;
+            }
+            if (!following) applyDucking(false);
+        }
+
+        function stopDucking() {
+            Object.keys(priorityAnalysers).forEach((userId) => {
+                priorityAnalysers[userId].source.disconnect();
+                delete priorityAnalysers[userId];
+            });
+            if (duckingTimer) {
+                clearInterval(duckingTimer);
+                duckingTimer = null;
+            }
+            if (duckingAudioCtx) {
+                duckingAudioCtx.close().catch(() => { });
+                duckingAudioCtx = null;
+            }
+            priorityHeardAt = 0;
+            applyDucking(false);
+        }
+
+        async function loadPrioritySpeakers(roomId) {
+            const state = getState();
+            try {
+                const res = await fetch(`${deps.API}/api/rooms/${encodeURIComponent(roomId)}/voice/priority`, {
+                    headers: { Authorization: `Bearer ${state.token}` },
+                });
+                if (!res.ok) return;
+                const data = await res.json();
+                if (state.voice.joinedRoomId !== roomId) return;
+                state.voice.prioritySpeakers = new Set((data.speakers || []).map((speaker) => speaker.user_id));
+                if (typeof data.ducking_db === "number") state.voice.duckingDb = data.ducking_db;
+                syncPriorityAnalysers();
+                renderVoiceMembers();
+            } catch (err) {
+                console.warn("Could not load priority speakers", err);
+            }
+        }
+
+        async function setPrioritySpeaker(userId, enabled) {
+            const state = getState();
+            const roomId = state.voice.joinedRoomId;
+            if (!roomId) return;
+            try {
+                const res = await fetch(`${deps.API}/api/rooms/${encodeURIComponent(roomId)}/voice/priority/${encodeURIComponent(userId)}`, {
+                    method: "PUT",
+                    headers: { Authorization: `Bearer ${state.token}`, "Content-Type": "application/json" },
+                    body: JSON.stringify({ enabled }),
+                });
+                if (!res.ok) {
+                    const data = await res.json().catch(() => ({}));
+                    alert(data.error || "Impossible de changer l'orateur prioritaire.");
+                }
+            } catch (err) {
+                console.error("Priority speaker update failed", err);
+            }
+        }
+
         function broadcastVoiceState() {
             const state = getState();
             if (!state.voice.joinedRoomId) return;
@@ -534,6 +682,34 @@ window.VoxiumVoice = (() => {
 
             if (msg.type === "voice_signal") {
                 handleVoiceSignal(msg).catch((err) => console.error("Voice signal error", err));
+                return;
+            }
+
+            if (msg.type === "voice_priority") {
+                if (!msg.user_id || msg.room_id !== state.voice.joinedRoomId) return;
+                if (msg.enabled) state.voice.prioritySpeakers.add(msg.user_id);
+                else state.voice.prioritySpeakers.delete(msg.user_id);
+                if (typeof msg.ducking_db === "number") state.voice.duckingDb = msg.ducking_db;
+                syncPriorityAnalysers();
+                renderVoiceMembers();
+            }
+        }
+
+        // ICE servers from the backend (TURN credentials are per user and
+        // expire), falling back to the runtime config.
+        async function loadRtcConfig() {
+            const state = getState();
+            try {
+                const res = await fetch(`${deps.API}/api/voice/rtc-config`, {
+                    headers: { Authorization: `Bearer ${state.token}` },
+                });
+                if (!res.ok) return;
+                const data = await res.json();
+                if (Array.isArray(data.iceServers) && data.iceServers.length > 0) {
+                    rtcConfig = { ...deps.WEBRTC_CONFIG, iceServers: data.iceServers };
+                }
+            } catch (err) {
+                console.warn("Could not load ICE servers, using the runtime config", err);
             }
         }
 
@@ -542,6 +718,8 @@ window.VoxiumVoice = (() => {
             if (state.currentRoomKind !== "voice" || !state.currentRoomId) return;
             if (state.voice.joinedRoomId === state.currentRoomId) return;
 
+            await loadRtcConfig();
+
             if (!navigator.mediaDevices || !navigator.mediaDevices.getUserMedia) {
                 alert("Votre navigateur ne supporte pas l'audio WebRTC.");
                 return;
@@ -559,6 +737,8 @@ window.VoxiumVoice = (() => {
 
                 applyLocalTrackState();
                 startMicMeter(stream);
+                state.voice.prioritySpeakers = new Set();
+                loadPrioritySpeakers(state.currentRoomId);
                 renderVoiceMembers();
                 updateVoiceButtons();
                 updateVoiceQuickStatus();
@@ -596,10 +776,12 @@ window.VoxiumVoice = (() => {
                 state.voice.localStream.getTracks().forEach((track) => track.stop());
             }
             stopMicMeter();
+            stopDucking();
 
             state.voice.joinedRoomId = null;
             state.voice.localStream = null;
             state.voice.members = {};
+            state.voice.prioritySpeakers = new Set();
             renderVoiceMembers();
             updateVoiceButtons();
             updateVoiceQuickStatus();
@@ -0,0 +1,603 @@
+// The connection task and the two ends handed to the caller.
+//
+// Commands carry an id: a caller that stops waiting for a join (timeout,
+// request dropped) cancels it through a guard in the join future, so the
+// task drops the join instead of answering into the void later and keeping
+// it as its pending join.
+
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::event::{CloseReason, GatewayEvent, VoiceServer, VoiceServerInfo, VoiceState};
+use crate::{GatewayConfig, IdentifyOptions, LogLevel, Logger, DESKTOP_USER_AGENT};
+
+/// Heartbeat period until Discord says otherwise in hello.
+const DEFAULT_HEARTBEAT_INTERVAL_MS: u64 = 41250;
+/// Pause between the leave and the join of a voice join, so Discord sends a
+/// fresh VOICE_SERVER_UPDATE.
+const REJOIN_DELAY: Duration = Duration::from_millis(200);
+
+type CommandId = u64;
+
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_command_id() -> CommandId {
+    NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// ── Errors ──────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GatewayError {
+    /// The connection task is gone.
+    Closed,
+    /// The connection could not be opened.
+    ConnectFailed,
+    /// A newer join replaced this one.
+    Superseded,
+    /// Discord refused the session (op 9).
+    SessionInvalid,
+    /// The command could not be written to the socket.
+    SendFailed,
+}
+
+impl std::fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Closed => "Gateway connection closed",
+            Self::ConnectFailed => "Gateway connection failed",
+            Self::Superseded => "Superseded by new join request",
+            Self::SessionInvalid => "Discord session invalid",
+            Self::SendFailed => "Failed to send to the gateway",
+        })
+    }
+}
+
+impl std::error::Error for GatewayError {}
+
+// ── Handle ──────────────────────────────────────────────
+
+#[derive(Debug)]
+enum Command {
+    JoinVoice {
+        id: CommandId,
+        guild_id: String,
+        channel_id: String,
+        reply: oneshot::Sender<Result<VoiceServerInfo, GatewayError>>,
+    },
+    LeaveVoice {
+        id: CommandId,
+        guild_id: String,
+        reply: oneshot::Sender<Result<(), GatewayError>>,
+    },
+    Send {
+        payload: serde_json::Value,
+        reply: oneshot::Sender<Result<(), GatewayError>>,
+    },
+    /// The caller of command `id` no longer waits for its reply.
+    Cancel { id: CommandId },
+}
+
+impl Command {
+    fn fail(self, error: GatewayError) {
+        match self {
+            Self::JoinVoice { reply, .. } => {
+                let _ = reply.send(Err(error));
+            }
+            Self::LeaveVoice { reply, .. } | Self::Send { reply, .. } => {
+                let _ = reply.send(Err(error));
+            }
+            Self::Cancel { .. } => {}
+        }
+    }
+}
+
+/// Cancels its command when dropped while still armed.
+struct CancelOnDrop {
+    id: CommandId,
+    cmd_tx: mpsc::Sender<Command>,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = self.cmd_tx.try_send(Command::Cancel { id: self.id });
+        }
+    }
+}
+
+/// Sends commands to the connection. Cheap to clone; the connection closes
+/// once every clone is dropped.
+#[derive(Debug, Clone)]
+pub struct GatewayHandle {
+    cmd_tx: mpsc::Sender<Command>,
+}
+
+impl GatewayHandle {
+    /// Join a voice channel and wait for the voice server. Before READY the
+    /// join is queued (a newer one replaces it). Dropping the future before
+    /// it resolves abandons the join, leaving the channel if Discord already
+    /// put the account in it.
+    pub async fn join_voice(&self, guild_id: &str, channel_id: &str) -> Result<VoiceServerInfo, GatewayError> {
+        let id = next_command_id();
+        let (reply, answer) = oneshot::channel();
+        let command = Command::JoinVoice {
+            id,
+            guild_id: guild_id.to_string(),
+            channel_id: channel_id.to_string(),
+            reply,
+        };
+        self.cmd_tx.send(command).await.map_err(|_| GatewayError::Closed)?;
+        let mut cancel = CancelOnDrop { id, cmd_tx: self.cmd_tx.clone(), armed: true };
+        let result = answer.await;
+        cancel.armed = false;
+        result.unwrap_or(Err(GatewayError::Closed))
+    }
+
+    /// Leave the voice channel the account is in, in `guild_id`.
+    pub async fn leave_voice(&self, guild_id: &str) -> Result<(), GatewayError> {
+        let (reply, answer) = oneshot::channel();
+        let command = Command::LeaveVoice { id: next_command_id(), guild_id: guild_id.to_string(), reply };
+        self.cmd_tx.send(command).await.map_err(|_| GatewayError::Closed)?;
+        answer.await.unwrap_or(Err(GatewayError::Closed))
+    }
+
+    /// Send a raw `{ op, d }` payload.
+    pub async fn send(&self, payload: serde_json::Value) -> Result<(), GatewayError> {
+        let (reply, answer) = oneshot::channel();
+        self.cmd_tx.send(Command::Send { payload, reply }).await.map_err(|_| GatewayError::Closed)?;
+        answer.await.unwrap_or(Err(GatewayError::Closed))
+    }
+
+    /// Whether the connection is over: commands can only fail from now on.
+    pub fn is_closed(&self) -> bool {
+        self.cmd_tx.is_closed()
+    }
+}
+
+/// The typed event stream of a connection. Dropping it does not close the
+/// connection; events are then discarded.
+#[derive(Debug)]
+pub struct GatewayEvents {
+    rx: mpsc::UnboundedReceiver<GatewayEvent>,
+}
+
+impl GatewayEvents {
+    /// The next event, `None` after `Closed`.
+    pub async fn recv(&mut self) -> Option<GatewayEvent> {
+        self.rx.recv().await
+    }
+}
+
+/// Open a connection in a new task (on the current tokio runtime).
+pub fn connect(config: GatewayConfig) -> (GatewayHandle, GatewayEvents) {
+    let (cmd_tx, cmd_rx) = mpsc::channel(16);
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    tokio::spawn(run(config, cmd_rx, event_tx));
+    (GatewayHandle { cmd_tx }, GatewayEvents { rx: event_rx })
+}
+
+// ── Connection task ─────────────────────────────────────
+
+struct Log(Option<Logger>);
+
+impl Log {
+    fn log(&self, level: LogLevel, message: impl Into<String>) {
+        if let Some(logger) = &self.0 {
+            logger(level, message.into());
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) {
+        self.log(LogLevel::Error, message);
+    }
+
+    fn info(&self, message: impl Into<String>) {
+        self.log(LogLevel::Info, message);
+    }
+
+    fn debug(&self, message: impl Into<String>) {
+        self.log(LogLevel::Debug, message);
+    }
+}
+
+/// A join waiting for READY (queued) or for the voice events (pending).
+struct PendingVoiceJoin {
+    id: CommandId,
+    guild_id: String,
+    channel_id: String,
+    reply: oneshot::Sender<Result<VoiceServerInfo, GatewayError>>,
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+struct Connection {
+    ws_tx: SplitSink<Socket, Message>,
+    events: mpsc::UnboundedSender<GatewayEvent>,
+    log: Log,
+    sequence: Option<u64>,
+    session_id: Option<String>,
+    user_id: Option<String>,
+    identified: bool,
+    pending_join: Option<PendingVoiceJoin>,
+    queued_join: Option<PendingVoiceJoin>,
+    /// VOICE_SERVER_UPDATE received for the pending join.
+    voice_server: Option<VoiceServer>,
+}
+
+async fn run(config: GatewayConfig, mut cmd_rx: mpsc::Receiver<Command>, events: mpsc::UnboundedSender<GatewayEvent>) {
+    let log = Log(config.logger.clone());
+    let socket = match open(&config.url, &log).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            log.error(format!("Connection failed: {e}"));
+            // Fail whatever was sent meanwhile; the handle reports closed from now on.
+            cmd_rx.close();
+            while let Ok(command) = cmd_rx.try_recv() {
+                command.fail(GatewayError::ConnectFailed);
+            }
+            let _ = events.send(GatewayEvent::Closed(CloseReason::ConnectFailed(e)));
+            return;
+        }
+    };
+    let (ws_tx, mut ws_rx) = socket.split();
+
+    let mut conn = Connection {
+        ws_tx,
+        events,
+        log,
+        sequence: None,
+        session_id: None,
+        user_id: None,
+        identified: false,
+        pending_join: None,
+        queued_join: None,
+        voice_server: None,
+    };
+    let mut heartbeat: Option<tokio::time::Interval> = None;
+
+    let reason = loop {
+        tokio::select! {
+            msg = ws_rx.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let Ok(payload) = serde_json::from_str::<serde_json::Value>(&text) else {
+                        continue;
+                    };
+                    if let Some(reason) = conn.receive(payload, &config, &mut heartbeat).await {
+                        break reason;
+                    }
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    conn.log.info(format!("WS Closed: {frame:?}"));
+                    break CloseReason::ClosedByServer(frame.map(|f| format!("{} {}", f.code, f.reason)));
+                }
+                None => {
+                    conn.log.info("WS stream ended");
+                    break CloseReason::StreamEnded;
+                }
+                _ => {}
+            },
+
+            _ = tick(&mut heartbeat) => {
+                let hb = serde_json::json!({ "op": 1, "d": conn.sequence });
+                if conn.ws_tx.send(Message::Text(hb.to_string())).await.is_err() {
+                    break CloseReason::SendFailed;
+                }
+            }
+
+            command = cmd_rx.recv() => match command {
+                Some(command) => conn.command(command).await,
+                None => break CloseReason::Shutdown,
+            },
+        }
+    };
+
+    let _ = conn.ws_tx.close().await;
+    let error = match reason {
+        CloseReason::InvalidSession => GatewayError::SessionInvalid,
+        _ => GatewayError::Closed,
+    };
+    for join in [conn.pending_join.take(), conn.queued_join.take()].into_iter().flatten() {
+        let _ = join.reply.send(Err(error.clone()));
+    }
+    let _ = conn.events.send(GatewayEvent::Closed(reason));
+}
+
+async fn open(url: &str, log: &Log) -> Result<Socket, String> {
+    let mut request = url.into_client_request().map_err(|e| e.to_string())?;
+    request.headers_mut().insert("Origin", HeaderValue::from_static("https://discord.com"));
+    request.headers_mut().insert("User-Agent", HeaderValue::from_static(DESKTOP_USER_AGENT));
+
+    log.info("Connecting to Discord Gateway...");
+    let (socket, _) = tokio_tungstenite::connect_async(request).await.map_err(|e| e.to_string())?;
+    log.info("Connected to Discord Gateway");
+    Ok(socket)
+}
+
+/// The next heartbeat, never before hello.
+async fn tick(heartbeat: &mut Option<tokio::time::Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn identify_payload(token: &str, options: &IdentifyOptions) -> serde_json::Value {
+    let mut identify = serde_json::json!({
+        "op": 2,
+        "d": {
+            "token": token,
+            "capabilities": options.capabilities,
+            "properties": {
+                "os": "Windows",
+                "browser": "Chrome",
+                "device": "",
+                "system_locale": options.system_locale,
+                "browser_user_agent": DESKTOP_USER_AGENT,
+                "browser_version": "131.0.0.0",
+                "os_version": "10",
+                "referrer": "",
+                "referring_domain": "",
+                "referrer_current": "",
+                "referring_domain_current": "",
+                "release_channel": "stable",
+                "client_build_number": 366068,
+                "client_event_source": serde_json::Value::Null
+            },
+            "presence": {
+                "activities": [],
+                "status": "online",
+                "since": 0,
+                "afk": false
+            },
+            "compress": false,
+            "client_state": {
+                "guild_versions": {},
+                "highest_last_message_id": "0",
+                "read_state_version": 0,
+                "user_guild_settings_version": -1,
+                "user_settings_version": -1,
+                "private_channels_version": "0",
+                "api_code_version": 0
+            }
+        }
+    });
+    if let Some(intents) = options.intents {
+        identify["d"]["intents"] = serde_json::json!(intents);
+    }
+    identify
+}
+
+fn voice_state_payload(guild_id: &str, channel_id: Option<&str>) -> Message {
+    let mut d = serde_json::json!({
+        "guild_id": guild_id,
+        "channel_id": channel_id,
+        "self_mute": false,
+        "self_deaf": false,
+    });
+    if channel_id.is_some() {
+        d["self_video"] = false.into();
+    }
+    Message::Text(serde_json::json!({ "op": 4, "d": d }).to_string())
+}
+
+impl Connection {
+    fn emit(&self, event: GatewayEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Handle one payload from Discord; `Some` when the connection must end.
+    async fn receive(
+        &mut self,
+        payload: serde_json::Value,
+        config: &GatewayConfig,
+        heartbeat: &mut Option<tokio::time::Interval>,
+    ) -> Option<CloseReason> {
+        if let Some(s) = payload.get("s").and_then(|v| v.as_u64()) {
+            self.sequence = Some(s);
+        }
+        match payload.get("op").and_then(|v| v.as_u64()) {
+            // Hello
+            Some(10) => {
+                let interval_ms = payload
+                    .get("d")
+                    .and_then(|d| d.get("heartbeat_interval"))
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_MS);
+                let period = Duration::from_millis(interval_ms.max(1));
+                *heartbeat = Some(tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+                self.emit(GatewayEvent::Hello { heartbeat_interval_ms: interval_ms });
+
+                if !self.identified {
+                    let options = &config.identify;
+                    self.log.info(format!(
+                        "Sending Identify — capabilities={} intents={:?}",
+                        options.capabilities, options.intents
+                    ));
+                    let identify = identify_payload(&config.token, options);
+                    let _ = self.ws_tx.send(Message::Text(identify.to_string())).await;
+                    self.identified = true;
+                }
+            }
+            // Dispatch
+            Some(0) => {
+                let name = payload.get("t").and_then(|v| v.as_str()).unwrap_or("").to_string();
+                let data = payload.get("d").cloned().unwrap_or(serde_json::Value::Null);
+                self.dispatch(name, data).await;
+            }
+            // Reconnect
+            Some(7) => {
+                self.log.info("Received Reconnect (op 7)");
+                return Some(CloseReason::Reconnect);
+            }
+            // Invalid Session
+            Some(9) => {
+                self.log.error("Received Invalid Session (op 9)");
+                return Some(CloseReason::InvalidSession);
+            }
+            // Heartbeat ACK and the rest
+            _ => {}
+        }
+        None
+    }
+
+    async fn dispatch(&mut self, name: String, data: serde_json::Value) {
+        match name.as_str() {
+            "READY" | "READY_SUPPLEMENTAL" => {
+                if name == "READY" {
+                    self.session_id = data.get("session_id").and_then(|v| v.as_str()).map(|s| s.to_string());
+                    self.user_id = data
+                        .get("user")
+                        .and_then(|u| u.get("id"))
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string());
+                    self.log.info(format!("READY — session_id={:?} user_id={:?}", self.session_id, self.user_id));
+                    self.emit(GatewayEvent::Ready {
+                        session_id: self.session_id.clone().unwrap_or_default(),
+                        user_id: self.user_id.clone(),
+                    });
+                } else {
+                    self.log.debug("READY_SUPPLEMENTAL received");
+                    self.emit(GatewayEvent::ReadySupplemental);
+                }
+
+                if let Some(join) = self.queued_join.take() {
+                    self.log.info(format!(
+                        "Processing queued join #{}: guild={} channel={}",
+                        join.id, join.guild_id, join.channel_id
+                    ));
+                    self.voice_server = None;
+                    let message = voice_state_payload(&join.guild_id, Some(&join.channel_id));
+                    self.pending_join = Some(join);
+                    let _ = self.ws_tx.send(message).await;
+                }
+            }
+
+            "VOICE_STATE_UPDATE" => {
+                let Some(state) = VoiceState::from_dispatch(&data) else {
+                    return;
+                };
+                let ours = self.user_id.as_deref() == Some(state.user_id.as_str());
+                self.log.debug(format!(
+                    "VOICE_STATE_UPDATE — event_user={} our_user={} channel={:?}",
+                    state.user_id,
+                    self.user_id.as_deref().unwrap_or(""),
+                    state.channel_id
+                ));
+                self.emit(GatewayEvent::VoiceStateUpdate(state));
+                // VOICE_SERVER_UPDATE came first: the join is complete now.
+                if ours && self.voice_server.is_some() {
+                    self.complete_join("via VSU");
+                }
+            }
+
+            "VOICE_SERVER_UPDATE" => {
+                let server = VoiceServer::from_dispatch(&data);
+                self.log.info(format!(
+                    "VOICE_SERVER_UPDATE — endpoint={:?} guild={:?}",
+                    server.endpoint, server.guild_id
+                ));
+                self.emit(GatewayEvent::VoiceServerUpdate(server.clone()));
+                // With the session id from READY, that is all the Voice Gateway needs.
+                self.voice_server = Some(server);
+                self.complete_join("via VOICE_SERVER_UPDATE");
+            }
+
+            _ => {
+                self.log.debug(format!("Dispatch event: {name}"));
+                self.emit(GatewayEvent::Dispatch { name, data });
+            }
+        }
+    }
+
+    /// Answer the pending join with the voice server received, if both are there.
+    fn complete_join(&mut self, via: &str) {
+        if self.pending_join.is_none() {
+            return;
+        }
+        let (Some(server), Some(join)) = (self.voice_server.take(), self.pending_join.take()) else {
+            return;
+        };
+        let info = VoiceServerInfo {
+            token: server.token,
+            endpoint: server.endpoint,
+            guild_id: server.guild_id,
+            session_id: self.session_id.clone().unwrap_or_default(),
+            user_id: self.user_id.clone().unwrap_or_default(),
+        };
+        self.log.info(format!("Join #{} complete ({via}): endpoint={:?}", join.id, info.endpoint));
+        let _ = join.reply.send(Ok(info));
+    }
+
+    async fn command(&mut self, command: Command) {
+        match command {
+            Command::JoinVoice { id, guild_id, channel_id, reply } => {
+                let join = PendingVoiceJoin { id, guild_id: guild_id.clone(), channel_id: channel_id.clone(), reply };
+                if self.session_id.is_none() {
+                    self.log.info(format!("Gateway not ready yet, queueing join #{id} for guild={guild_id} channel={channel_id}"));
+                    if let Some(old) = self.queued_join.replace(join) {
+                        let _ = old.reply.send(Err(GatewayError::Superseded));
+                    }
+                    return;
+                }
+
+                if let Some(old) = self.pending_join.take() {
+                    self.log.info(format!("Cancelling previous pending join #{}", old.id));
+                    let _ = old.reply.send(Err(GatewayError::Superseded));
+                }
+
+                // Leave first, so Discord sends a fresh VOICE_SERVER_UPDATE.
+                self.log.debug(format!("Sending leave before join for guild={guild_id}"));
+                let _ = self.ws_tx.send(voice_state_payload(&guild_id, None)).await;
+                tokio::time::sleep(REJOIN_DELAY).await;
+
+                self.log.info(format!("Sending Voice State Update (join #{id}): guild={guild_id} channel={channel_id}"));
+                self.voice_server = None;
+                self.pending_join = Some(join);
+                if self.ws_tx.send(voice_state_payload(&guild_id, Some(&channel_id))).await.is_err() {
+                    if let Some(join) = self.pending_join.take() {
+                        let _ = join.reply.send(Err(GatewayError::SendFailed));
+                    }
+                }
+            }
+
+            Command::LeaveVoice { id, guild_id, reply } => {
+                self.log.debug(format!("Leave #{id}: guild={guild_id}"));
+                let sent = self.ws_tx.send(voice_state_payload(&guild_id, None)).await;
+                let _ = reply.send(sent.map_err(|_| GatewayError::SendFailed));
+            }
+
+            Command::Send { payload, reply } => {
+                let sent = self.ws_tx.send(Message::Text(payload.to_string())).await;
+                let _ = reply.send(sent.map_err(|_| GatewayError::SendFailed));
+            }
+
+            Command::Cancel { id } => {
+                if self.queued_join.as_ref().is_some_and(|join| join.id == id) {
+                    self.queued_join = None;
+                    self.log.info(format!("Join #{id} abandoned by its caller before READY, dropped"));
+                } else if let Some(join) = self.pending_join.take_if(|join| join.id == id) {
+                    // Discord may still complete the join: undo it rather
+                    // than leave the account half-connected.
+                    self.log.info(format!("Join #{id} abandoned by its caller, leaving guild={}", join.guild_id));
+                    self.voice_server = None;
+                    let _ = self.ws_tx.send(voice_state_payload(&join.guild_id, None)).await;
+                } else {
+                    // Already answered (or superseded): nothing left to clean up.
+                    self.log.debug(format!("Cancel for #{id}: nothing pending"));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// Typed events read from the gateway, and the payloads they carry.
+
+use serde::{Deserialize, Serialize};
+
+/// Something that happened on the connection, in the order Discord sent it.
+#[derive(Debug, Clone)]
+pub enum GatewayEvent {
+    /// Discord said hello; identify is sent right after.
+    Hello { heartbeat_interval_ms: u64 },
+    /// The session is ready: commands can reach Discord from now on.
+    Ready {
+        session_id: String,
+        /// The account's Discord user id.
+        user_id: Option<String>,
+    },
+    ReadySupplemental,
+    VoiceStateUpdate(VoiceState),
+    VoiceServerUpdate(VoiceServer),
+    /// Any other dispatch, as Discord sent it.
+    Dispatch { name: String, data: serde_json::Value },
+    /// The connection is over; no event follows.
+    Closed(CloseReason),
+}
+
+/// Why the connection ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloseReason {
+    /// It never opened.
+    ConnectFailed(String),
+    /// Discord asked for a reconnect (op 7).
+    Reconnect,
+    /// Discord refused the session (op 9).
+    InvalidSession,
+    /// Discord closed the socket, with its close frame if any.
+    ClosedByServer(Option<String>),
+    /// The socket ended without a close frame.
+    StreamEnded,
+    /// A heartbeat could not be sent.
+    SendFailed,
+    /// Every handle was dropped.
+    Shutdown,
+}
+
+/// A user's voice state in a guild: `channel_id` is `None` when they left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceState {
+    pub guild_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub user_id: String,
+    /// Guild nickname, else global name, else username.
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+impl VoiceState {
+    pub(crate) fn from_dispatch(data: &serde_json::Value) -> Option<Self> {
+        let member = data.get("member");
+        let member_user = member.and_then(|m| m.get("user"));
+        let text = |value: Option<&serde_json::Value>| value.and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        let user_id = text(data.get("user_id")).or_else(|| text(member_user.and_then(|u| u.get("id"))))?;
+        let display_name = text(member.and_then(|m| m.get("nick")))
+            .or_else(|| text(member_user.and_then(|u| u.get("global_name"))))
+            .or_else(|| text(member_user.and_then(|u| u.get("username"))));
+        let avatar_url = text(member_user.and_then(|u| u.get("avatar")))
+            .map(|hash| format!("https://cdn.discordapp.com/avatars/{user_id}/{hash}.png?size=64"));
+        Some(Self {
+            guild_id: text(data.get("guild_id")),
+            channel_id: text(data.get("channel_id")),
+            user_id,
+            display_name,
+            avatar_url,
+        })
+    }
+}
+
+/// Where the account's voice connection goes (VOICE_SERVER_UPDATE).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceServer {
+    pub token: String,
+    pub endpoint: Option<String>,
+    pub guild_id: Option<String>,
+}
+
+impl VoiceServer {
+    pub(crate) fn from_dispatch(data: &serde_json::Value) -> Self {
+        let text = |key: &str| data.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+        Self {
+            token: text("token").unwrap_or_default(),
+            endpoint: text("endpoint"),
+            guild_id: text("guild_id"),
+        }
+    }
+}
+
+/// Everything needed to connect to the Voice Gateway after a join.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoiceServerInfo {
+    pub token: String,
+    pub endpoint: Option<String>,
+    pub guild_id: Option<String>,
+    /// The gateway session id, from READY.
+    pub session_id: String,
+    /// The account's Discord user id.
+    pub user_id: String,
+}
@@ -0,0 +1,151 @@
+// ═══════════════════════════════════════════════════════
+//  Voxium — Discord Gateway client
+// ═══════════════════════════════════════════════════════
+//
+// A client for the Discord Gateway as a user account sees it: it connects,
+// identifies with the options given, keeps the heartbeat going and tracks
+// the sequence, then hands out two ends:
+//
+//   - `GatewayEvents`, the typed event stream: hello, READY, voice state and
+//     voice server updates, every other dispatch as raw JSON, and a final
+//     `Closed` with the reason the connection ended;
+//   - `GatewayHandle`, the command API: join or leave a voice channel (the
+//     join resolves with what the Voice Gateway needs), or send a raw op.
+//
+// The connection runs in its own task until it closes or every handle is
+// dropped. It does not reconnect: a closed client is replaced by connecting
+// again, which `GatewayHandle::is_closed` tells.
+//
+// Logging goes through an optional callback, so the embedding program
+// decides where lines end up; the client never logs tokens.
+
+mod client;
+mod event;
+
+use std::sync::Arc;
+
+pub use client::{connect, GatewayError, GatewayEvents, GatewayHandle};
+pub use event::{CloseReason, GatewayEvent, VoiceServer, VoiceServerInfo, VoiceState};
+
+pub const DISCORD_GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=9&encoding=json";
+
+/// What the desktop client sends as its user agent, also used in identify.
+pub const DESKTOP_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
+
+// ── Identify options ────────────────────────────────────
+
+pub const CAPABILITY_LAZY_USER_NOTES: u64 = 1 << 0;
+pub const CAPABILITY_NO_AFFINE_USER_IDS: u64 = 1 << 1;
+pub const CAPABILITY_DEDUPE_USER_OBJECTS: u64 = 1 << 4;
+pub const CAPABILITY_USER_SETTINGS_PROTO: u64 = 1 << 9;
+
+/// What the desktop client sends.
+pub const FULL_CAPABILITIES: u64 = 30717;
+/// The capabilities that shrink READY, enough for voice.
+pub const VOICE_CAPABILITIES: u64 = CAPABILITY_LAZY_USER_NOTES
+    | CAPABILITY_NO_AFFINE_USER_IDS
+    | CAPABILITY_DEDUPE_USER_OBJECTS
+    | CAPABILITY_USER_SETTINGS_PROTO;
+
+pub const INTENT_GUILDS: u64 = 1 << 0;
+pub const INTENT_GUILD_VOICE_STATES: u64 = 1 << 7;
+
+/// What the client asks Discord for when it identifies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifyOptions {
+    pub capabilities: u64,
+    /// `None` sends no intents: everything the account can see.
+    pub intents: Option<u64>,
+    /// Locale reported in the client properties.
+    pub system_locale: String,
+}
+
+impl IdentifyOptions {
+    /// Like the desktop client: the whole account in READY, then every
+    /// message, typing and presence update of every guild.
+    pub fn full() -> Self {
+        Self {
+            capabilities: FULL_CAPABILITIES,
+            intents: None,
+            system_locale: "fr-FR".to_string(),
+        }
+    }
+
+    /// Guilds and voice states only: a small READY and a quiet socket.
+    pub fn voice() -> Self {
+        Self {
+            capabilities: VOICE_CAPABILITIES,
+            intents: Some(INTENT_GUILDS | INTENT_GUILD_VOICE_STATES),
+            system_locale: "fr-FR".to_string(),
+        }
+    }
+}
+
+impl Default for IdentifyOptions {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+// ── Logging ─────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Info,
+    Debug,
+}
+
+pub type Logger = Arc<dyn Fn(LogLevel, String) + Send + Sync>;
+
+// ── Configuration ───────────────────────────────────────
+
+/// How to reach the gateway and identify. Built with `GatewayConfig::new`
+/// and the chained setters, then passed to `connect`.
+#[derive(Clone)]
+pub struct GatewayConfig {
+    pub(crate) token: String,
+    pub(crate) url: String,
+    pub(crate) identify: IdentifyOptions,
+    pub(crate) logger: Option<Logger>,
+}
+
+impl GatewayConfig {
+    /// Connect to Discord with the account `token` and the full profile.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            url: DISCORD_GATEWAY_URL.to_string(),
+            identify: IdentifyOptions::default(),
+            logger: None,
+        }
+    }
+
+    /// Another gateway URL (a proxy, or a test server).
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    pub fn identify(mut self, identify: IdentifyOptions) -> Self {
+        self.identify = identify;
+        self
+    }
+
+    /// Receive the client's log lines.
+    pub fn logger(mut self, logger: impl Fn(LogLevel, String) + Send + Sync + 'static) -> Self {
+        self.logger = Some(Arc::new(logger));
+        self
+    }
+}
+
+impl std::fmt::Debug for GatewayConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GatewayConfig")
+            .field("url", &self.url)
+            .field("identify", &self.identify)
+            .field("logger", &self.logger.is_some())
+            .finish_non_exhaustive()
+    }
+}
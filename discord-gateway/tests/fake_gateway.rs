@@ -0,0 +1,156 @@
+// The client against a local stand-in for the Discord Gateway.
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use voxium_discord_gateway::{connect, CloseReason, GatewayConfig, GatewayError, GatewayEvent, GatewayEvents, IdentifyOptions};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+/// A listening fake gateway and the config to reach it.
+async fn fake_gateway() -> (TcpListener, GatewayConfig) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let config = GatewayConfig::new("account-token").url(url).identify(IdentifyOptions::voice());
+    (listener, config)
+}
+
+async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+    let (stream, _) = listener.accept().await.unwrap();
+    tokio_tungstenite::accept_async(stream).await.unwrap()
+}
+
+async fn send(ws: &mut WebSocketStream<TcpStream>, payload: Value) {
+    ws.send(Message::Text(payload.to_string())).await.unwrap();
+}
+
+/// The next payload the client sent, skipping heartbeats.
+async fn next_payload(ws: &mut WebSocketStream<TcpStream>) -> Value {
+    loop {
+        let message = tokio::time::timeout(WAIT, ws.next()).await.unwrap().unwrap().unwrap();
+        if let Message::Text(text) = message {
+            let payload: Value = serde_json::from_str(&text).unwrap();
+            if payload["op"] != 1 {
+                return payload;
+            }
+        }
+    }
+}
+
+/// Hello, identify and READY for the account `42`.
+async fn handshake(ws: &mut WebSocketStream<TcpStream>) -> Value {
+    send(ws, json!({ "op": 10, "d": { "heartbeat_interval": 45000 } })).await;
+    let identify = next_payload(ws).await;
+    send(ws, json!({ "op": 0, "t": "READY", "s": 1, "d": { "session_id": "sess-1", "user": { "id": "42" } } })).await;
+    identify
+}
+
+/// Wait until the client processed READY.
+async fn ready(events: &mut GatewayEvents) {
+    loop {
+        match tokio::time::timeout(WAIT, events.recv()).await.unwrap().unwrap() {
+            GatewayEvent::Ready { .. } => return,
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn identifies_and_streams_typed_events() {
+    let (listener, config) = fake_gateway().await;
+    let (_handle, mut events) = connect(config);
+    let mut ws = accept(&listener).await;
+
+    let identify = handshake(&mut ws).await;
+    assert_eq!(identify["op"], 2);
+    assert_eq!(identify["d"]["token"], "account-token");
+    assert_eq!(identify["d"]["intents"], (1 << 0) | (1 << 7));
+
+    send(&mut ws, json!({ "op": 0, "t": "MESSAGE_CREATE", "s": 2, "d": { "content": "hi" } })).await;
+    ws.close(None).await.unwrap();
+
+    let mut seen = Vec::new();
+    while let Some(event) = tokio::time::timeout(WAIT, events.recv()).await.unwrap() {
+        seen.push(event);
+    }
+    assert!(matches!(seen[0], GatewayEvent::Hello { heartbeat_interval_ms: 45000 }));
+    assert!(matches!(&seen[1], GatewayEvent::Ready { session_id, user_id } if session_id == "sess-1" && user_id.as_deref() == Some("42")));
+    assert!(matches!(&seen[2], GatewayEvent::Dispatch { name, data } if name == "MESSAGE_CREATE" && data["content"] == "hi"));
+    assert!(matches!(&seen[3], GatewayEvent::Closed(CloseReason::ClosedByServer(_))));
+}
+
+#[tokio::test]
+async fn join_voice_resolves_with_the_voice_server() {
+    let (listener, config) = fake_gateway().await;
+    let (handle, mut events) = connect(config);
+    let mut ws = accept(&listener).await;
+    handshake(&mut ws).await;
+    ready(&mut events).await;
+
+    let join = tokio::spawn(async move { handle.join_voice("g1", "c1").await });
+    let leave_first = next_payload(&mut ws).await;
+    assert_eq!(leave_first["op"], 4);
+    assert_eq!(leave_first["d"]["channel_id"], Value::Null);
+    let voice_state = next_payload(&mut ws).await;
+    assert_eq!(voice_state["d"]["guild_id"], "g1");
+    assert_eq!(voice_state["d"]["channel_id"], "c1");
+
+    send(&mut ws, json!({ "op": 0, "t": "VOICE_STATE_UPDATE", "s": 2, "d": {
+        "guild_id": "g1", "channel_id": "c1", "user_id": "42",
+        "member": { "nick": "Nick", "user": { "id": "42", "username": "user", "avatar": "abc" } }
+    } }))
+    .await;
+    send(&mut ws, json!({ "op": 0, "t": "VOICE_SERVER_UPDATE", "s": 3, "d": {
+        "token": "voice-token", "endpoint": "voice.example:443", "guild_id": "g1"
+    } }))
+    .await;
+
+    let info = tokio::time::timeout(WAIT, join).await.unwrap().unwrap().unwrap();
+    assert_eq!(info.token, "voice-token");
+    assert_eq!(info.endpoint.as_deref(), Some("voice.example:443"));
+    assert_eq!(info.session_id, "sess-1");
+    assert_eq!(info.user_id, "42");
+
+    let state = loop {
+        match events.recv().await.unwrap() {
+            GatewayEvent::VoiceStateUpdate(state) => break state,
+            _ => continue,
+        }
+    };
+    assert_eq!(state.display_name.as_deref(), Some("Nick"));
+    assert_eq!(state.avatar_url.as_deref(), Some("https://cdn.discordapp.com/avatars/42/abc.png?size=64"));
+}
+
+#[tokio::test]
+async fn abandoned_join_leaves_the_channel() {
+    let (listener, config) = fake_gateway().await;
+    let (handle, mut events) = connect(config);
+    let mut ws = accept(&listener).await;
+    handshake(&mut ws).await;
+    ready(&mut events).await;
+
+    let abandoned = tokio::time::timeout(Duration::from_millis(500), handle.join_voice("g1", "c1")).await;
+    assert!(abandoned.is_err());
+
+    next_payload(&mut ws).await; // leave before join
+    next_payload(&mut ws).await; // join
+    let undo = next_payload(&mut ws).await;
+    assert_eq!(undo["op"], 4);
+    assert_eq!(undo["d"]["guild_id"], "g1");
+    assert_eq!(undo["d"]["channel_id"], Value::Null);
+}
+
+#[tokio::test]
+async fn failed_connection_closes_the_handle() {
+    let (listener, config) = fake_gateway().await;
+    drop(listener);
+    let (handle, mut events) = connect(config);
+
+    let closed = tokio::time::timeout(WAIT, events.recv()).await.unwrap();
+    assert!(matches!(closed, Some(GatewayEvent::Closed(CloseReason::ConnectFailed(_)))));
+    assert!(handle.is_closed());
+    assert_eq!(handle.join_voice("g1", "c1").await, Err(GatewayError::Closed));
+}
@@ -0,0 +1 @@
+ALTER TABLE messages ADD COLUMN quote_snapshot TEXT DEFAULT NULL;
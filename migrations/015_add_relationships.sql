@@ -0,0 +1,13 @@
+-- One row per directed relationship. For now only 'blocked' is used.
+CREATE TABLE IF NOT EXISTS relationships (
+    user_id TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (user_id, target_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (target_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_relationships_target
+    ON relationships(target_id);
@@ -0,0 +1,20 @@
+-- Room topic / guidelines (markdown, rendered client-side)
+ALTER TABLE rooms ADD COLUMN topic TEXT DEFAULT NULL;
+ALTER TABLE rooms ADD COLUMN guidelines TEXT DEFAULT NULL;
+
+-- 'default' for user messages, otherwise the system event that produced it
+ALTER TABLE messages ADD COLUMN kind TEXT NOT NULL DEFAULT 'default';
+
+CREATE TABLE IF NOT EXISTS room_metadata_history (
+    id TEXT PRIMARY KEY,
+    room_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT DEFAULT NULL,
+    new_value TEXT DEFAULT NULL,
+    changed_by TEXT NOT NULL,
+    changed_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_room_metadata_history_room
+    ON room_metadata_history(room_id, changed_at);
@@ -0,0 +1,3 @@
+-- Last result of validating the linked Discord token: linked, expired or revoked
+ALTER TABLE users ADD COLUMN discord_token_status TEXT DEFAULT NULL;
+ALTER TABLE users ADD COLUMN discord_token_checked_at TEXT DEFAULT NULL;
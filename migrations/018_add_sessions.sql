@@ -0,0 +1,16 @@
+-- One row per issued Voxium JWT (the token carries the id as `sid`)
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    device_name TEXT DEFAULT NULL,
+    user_agent TEXT DEFAULT NULL,
+    ip TEXT DEFAULT NULL,
+    created_at TEXT NOT NULL,
+    last_seen_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT DEFAULT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user
+    ON sessions(user_id, last_seen_at);
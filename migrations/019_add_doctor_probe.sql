@@ -0,0 +1,5 @@
+-- Single row rewritten by the doctor check to measure DB write latency
+CREATE TABLE IF NOT EXISTS doctor_probe (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    checked_at TEXT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Refresh tokens are stored hashed. A used row is kept until its session
+-- expires so that presenting it again can be detected as a replay.
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    used_at TEXT DEFAULT NULL,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session
+    ON refresh_tokens(session_id);
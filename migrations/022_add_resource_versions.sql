@@ -0,0 +1,3 @@
+-- Bumped on every edit, exposed as ETag for If-Match checks
+ALTER TABLE rooms ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE roles ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
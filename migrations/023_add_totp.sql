@@ -0,0 +1,18 @@
+-- TOTP two-factor authentication. The secret is stored encrypted and only
+-- counts once totp_enabled is set (after the first code was confirmed).
+-- totp_required is the per-account enforcement flag set by admins.
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN totp_required INTEGER NOT NULL DEFAULT 0;
+-- Last accepted time step, so a code cannot be replayed within its window.
+ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
+
+CREATE TABLE IF NOT EXISTS totp_backup_codes (
+    code_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    used_at TEXT,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_totp_backup_codes_user ON totp_backup_codes(user_id);
@@ -0,0 +1,15 @@
+-- Security-relevant events on a user's linked Discord account (links,
+-- unlinks, QR logins, gateway identifies), shown in their security log.
+CREATE TABLE IF NOT EXISTS auth_audit (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    ip TEXT DEFAULT NULL,
+    user_agent TEXT DEFAULT NULL,
+    details TEXT DEFAULT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_audit_user
+    ON auth_audit(user_id, created_at);
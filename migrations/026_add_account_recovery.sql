@@ -0,0 +1,29 @@
+-- One-time recovery codes: each resets the password once. Stored as
+-- SHA-256 hashes, like the TOTP backup codes.
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    code_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    used_at TEXT,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovery_codes_user ON recovery_codes(user_id);
+
+-- Recovery through the verified email address. The mailed token only works
+-- from usable_at on (cooling-off delay), unless a signed-in device approved
+-- the request. status: pending, approved, cancelled or completed.
+CREATE TABLE IF NOT EXISTS recovery_requests (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL DEFAULT 'pending',
+    ip TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL,
+    usable_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovery_requests_user ON recovery_requests(user_id, created_at);
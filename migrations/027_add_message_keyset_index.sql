@@ -0,0 +1,7 @@
+-- History is paged with (created_at, id) cursors, id breaking ties between
+-- messages sent in the same instant. This index serves both the seek and
+-- the order, and replaces the (room_id, created_at) one from 012.
+CREATE INDEX IF NOT EXISTS idx_messages_room_created_id
+    ON messages(room_id, created_at, id);
+
+DROP INDEX IF EXISTS idx_messages_room_created_at;
@@ -0,0 +1,26 @@
+-- Last message each user has read in each room. The cursor is the
+-- (created_at, id) pair of that message, the same keys history is paged on,
+-- so it stays valid if the message is deleted.
+CREATE TABLE IF NOT EXISTS read_states (
+    user_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    last_read_message_id TEXT NOT NULL,
+    last_read_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, room_id),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+-- Users mentioned by each message, filled when the message is sent.
+CREATE TABLE IF NOT EXISTS message_mentions (
+    message_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (message_id, user_id),
+    FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_mentions_user_room
+    ON message_mentions(user_id, room_id, created_at, message_id);
@@ -0,0 +1,3 @@
+ALTER TABLE roles ADD COLUMN icon_url TEXT;
+ALTER TABLE roles ADD COLUMN hoist INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE roles ADD COLUMN mentionable INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS announcements (
+    id TEXT PRIMARY KEY,
+    author_id TEXT NOT NULL,
+    author_username TEXT NOT NULL,
+    content TEXT NOT NULL,
+    level TEXT NOT NULL DEFAULT 'info',
+    room_id TEXT,
+    starts_at TEXT NOT NULL,
+    expires_at TEXT,
+    published_at TEXT,
+    cancelled_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_announcements_pending ON announcements(published_at, starts_at);
+
+CREATE TABLE IF NOT EXISTS announcement_dismissals (
+    announcement_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    dismissed_at TEXT NOT NULL,
+    PRIMARY KEY (announcement_id, user_id),
+    FOREIGN KEY (announcement_id) REFERENCES announcements(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,9 @@
+-- Server custom emoji, used as :name: in messages and as reactions
+-- (stored in message_reactions.emoji as "custom:<id>").
+CREATE TABLE IF NOT EXISTS custom_emojis (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    image_url TEXT NOT NULL,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL
+);
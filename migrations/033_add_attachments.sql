@@ -0,0 +1,25 @@
+-- Content-addressed file store: one row per distinct content (SHA-256).
+CREATE TABLE IF NOT EXISTS files (
+    hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    -- Hash of the stored thumbnail, for images the server can decode.
+    thumbnail_hash TEXT,
+    created_at TEXT NOT NULL
+);
+
+-- One upload of a file; linked to a message when the message is sent.
+CREATE TABLE IF NOT EXISTS attachments (
+    id TEXT PRIMARY KEY,
+    file_hash TEXT NOT NULL REFERENCES files(hash),
+    uploader_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+    message_id TEXT REFERENCES messages(id) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_attachments_message ON attachments(message_id);
+CREATE INDEX IF NOT EXISTS idx_attachments_file ON attachments(file_hash);
+CREATE INDEX IF NOT EXISTS idx_attachments_unlinked ON attachments(created_at) WHERE message_id IS NULL;
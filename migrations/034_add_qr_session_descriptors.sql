@@ -0,0 +1,12 @@
+-- Minimal trace of each Discord QR login session, which otherwise only lives
+-- in memory: lets clients polling a session lost to a restart be told so.
+CREATE TABLE IF NOT EXISTS qr_session_descriptors (
+    id TEXT PRIMARY KEY,
+    -- SHA-256 of the session secret, hex
+    secret_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    -- Set at startup for sessions the previous process was running.
+    lost_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_qr_session_descriptors_created ON qr_session_descriptors(created_at);
@@ -0,0 +1,7 @@
+-- Audio metadata, for Ogg Opus files.
+ALTER TABLE files ADD COLUMN duration_secs REAL;
+-- Up to 256 amplitude samples (0-255), base64.
+ALTER TABLE files ADD COLUMN waveform TEXT;
+
+-- `file`, or `voice` for clips sent as voice messages.
+ALTER TABLE attachments ADD COLUMN kind TEXT NOT NULL DEFAULT 'file';
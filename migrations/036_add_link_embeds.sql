@@ -0,0 +1,14 @@
+-- Link previews of the URLs in a message (JSON array), filled in by the unfurler.
+ALTER TABLE messages ADD COLUMN embeds TEXT;
+-- Set when the message's links need (re)unfurling, cleared once done.
+ALTER TABLE messages ADD COLUMN embeds_pending_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_embeds_pending ON messages(embeds_pending_at) WHERE embeds_pending_at IS NOT NULL;
+
+-- Fetched previews by URL. `embed` is NULL when the page had nothing to show
+-- or could not be fetched, so failures are not retried on every message.
+CREATE TABLE IF NOT EXISTS link_previews (
+    url TEXT PRIMARY KEY,
+    embed TEXT,
+    fetched_at TEXT NOT NULL
+);
@@ -0,0 +1,24 @@
+-- Polls, one per message of kind 'poll'. `options` is a JSON array of the
+-- option texts; votes refer to them by index.
+CREATE TABLE IF NOT EXISTS polls (
+    message_id TEXT PRIMARY KEY,
+    question TEXT NOT NULL,
+    options TEXT NOT NULL,
+    multi_select INTEGER NOT NULL DEFAULT 0,
+    expires_at TEXT NOT NULL,
+    -- Set when the poll ends: at expiry by the closer task, or early by its author.
+    closed_at TEXT,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_polls_open_expiry ON polls(expires_at) WHERE closed_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS poll_votes (
+    message_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    option_index INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (message_id, user_id, option_index),
+    FOREIGN KEY (message_id) REFERENCES polls(message_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
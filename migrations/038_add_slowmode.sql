@@ -0,0 +1,14 @@
+-- Slowmode: seconds each member waits between two messages in a room (0 = off).
+ALTER TABLE rooms ADD COLUMN slowmode_seconds INTEGER NOT NULL DEFAULT 0;
+-- Members of these roles post without waiting (admins always do).
+ALTER TABLE roles ADD COLUMN bypass_slowmode INTEGER NOT NULL DEFAULT 0;
+
+-- Last accepted message of each member in a room, in Unix milliseconds.
+CREATE TABLE IF NOT EXISTS room_cooldowns (
+    room_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    last_post_ms INTEGER NOT NULL,
+    PRIMARY KEY (room_id, user_id),
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+-- Members of these roles can be made priority speaker in voice rooms (admins always can).
+ALTER TABLE roles ADD COLUMN priority_speaker INTEGER NOT NULL DEFAULT 0;
+
+-- Priority speakers of each voice room, set by moderators.
+CREATE TABLE IF NOT EXISTS voice_priority_speakers (
+    room_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    granted_by TEXT,
+    granted_at TEXT NOT NULL,
+    PRIMARY KEY (room_id, user_id),
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Text alternatives for screen readers (NULL = none yet).
+ALTER TABLE attachments ADD COLUMN alt_text TEXT;
+ALTER TABLE custom_emojis ADD COLUMN alt_text TEXT;
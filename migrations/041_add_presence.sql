@@ -0,0 +1,3 @@
+-- Status chosen by the user (online, idle, dnd or invisible) and their custom status text.
+ALTER TABLE users ADD COLUMN presence_status TEXT NOT NULL DEFAULT 'online';
+ALTER TABLE users ADD COLUMN custom_status TEXT;
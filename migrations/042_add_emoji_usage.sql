@@ -0,0 +1,11 @@
+-- Daily rollup of emoji use: `emoji` is the character(s) or `custom:<id>`,
+-- `source` is 'message' (used in a message's content) or 'reaction'.
+CREATE TABLE IF NOT EXISTS emoji_usage (
+    day TEXT NOT NULL,
+    emoji TEXT NOT NULL,
+    source TEXT NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, emoji, source)
+);
+
+CREATE INDEX IF NOT EXISTS idx_emoji_usage_emoji ON emoji_usage(emoji, day);
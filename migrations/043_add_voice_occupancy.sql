@@ -0,0 +1,10 @@
+-- People in each voice room, sampled periodically (`sampled_at` in Unix seconds).
+CREATE TABLE IF NOT EXISTS voice_occupancy_samples (
+    room_id TEXT NOT NULL,
+    sampled_at INTEGER NOT NULL,
+    users INTEGER NOT NULL,
+    PRIMARY KEY (room_id, sampled_at),
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_voice_occupancy_sampled_at ON voice_occupancy_samples(sampled_at);
@@ -0,0 +1,32 @@
+-- Guest links: a token (stored hashed) that lets someone into one room without an account.
+CREATE TABLE IF NOT EXISTS guest_links (
+    id TEXT PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    room_id TEXT NOT NULL,
+    can_post INTEGER NOT NULL DEFAULT 0,
+    max_uses INTEGER DEFAULT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT DEFAULT NULL,
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_guest_links_room ON guest_links(room_id, created_at);
+
+-- One row per guest principal (a `users` row with role `guest`). Kept after
+-- conversion to a full account, with `converted_at` set.
+CREATE TABLE IF NOT EXISTS guests (
+    user_id TEXT PRIMARY KEY,
+    link_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    can_post INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    converted_at TEXT DEFAULT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_guests_link ON guests(link_id);
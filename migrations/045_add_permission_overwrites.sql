@@ -0,0 +1,16 @@
+-- Per-room permission overwrites: bits to allow and deny for a role (`target_type = 'role'`,
+-- `target_id` the role name, `user` meaning everyone) or a member (`'user'`, a user id).
+CREATE TABLE IF NOT EXISTS room_permission_overwrites (
+    room_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    allow INTEGER NOT NULL DEFAULT 0,
+    deny INTEGER NOT NULL DEFAULT 0,
+    updated_by TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (room_id, target_type, target_id),
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_room_permission_overwrites_target
+    ON room_permission_overwrites(target_type, target_id);
@@ -0,0 +1,7 @@
+-- Permission bits of each role (see `backend/src/permissions.rs`). Roles start
+-- with the member defaults: VIEW_ROOM, SEND_MESSAGES, ADD_REACTIONS,
+-- ATTACH_FILES and CONNECT.
+ALTER TABLE roles ADD COLUMN permissions INTEGER NOT NULL DEFAULT 31;
+
+-- `admin` always has every bit; store it so listings show it.
+UPDATE roles SET permissions = 16383 WHERE name = 'admin';
@@ -0,0 +1,22 @@
+-- Server rules: every edit publishes a new version with its sections (JSON `[{ title, body }]`).
+-- While the latest version with `requires_acknowledgment` is not acknowledged (that version or a
+-- later one), a member cannot post.
+CREATE TABLE IF NOT EXISTS server_rules (
+    version INTEGER PRIMARY KEY,
+    sections TEXT NOT NULL,
+    requires_acknowledgment INTEGER NOT NULL DEFAULT 0,
+    published_by TEXT NOT NULL,
+    published_by_username TEXT NOT NULL,
+    published_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS rules_acknowledgments (
+    user_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    acknowledged_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, version),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (version) REFERENCES server_rules(version) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_rules_acknowledgments_version ON rules_acknowledgments(version);
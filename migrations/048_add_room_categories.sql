@@ -0,0 +1,19 @@
+-- Room categories, and the order of rooms within them (or among the rooms without one).
+-- Positions are kept compact: 0, 1, 2... in each list.
+CREATE TABLE IF NOT EXISTS categories (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL
+);
+
+ALTER TABLE rooms ADD COLUMN category_id TEXT REFERENCES categories(id) ON DELETE SET NULL;
+ALTER TABLE rooms ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+-- Existing rooms keep their creation order.
+UPDATE rooms SET position = (
+    SELECT COUNT(*) FROM rooms r
+    WHERE r.created_at < rooms.created_at OR (r.created_at = rooms.created_at AND r.id < rooms.id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rooms_category_position ON rooms(category_id, position);
@@ -0,0 +1,39 @@
+-- Forum rooms (rooms.kind = 'forum'): every message belongs to a post, a thread
+-- opened by its first message, whose id is the post's. `vote_count` counts the
+-- users who reacted to the opening message and `reply_count` the other messages,
+-- both kept up to date so posts can be sorted on them.
+CREATE TABLE IF NOT EXISTS forum_posts (
+    id TEXT PRIMARY KEY,
+    room_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    author_id TEXT,
+    created_at TEXT NOT NULL,
+    last_activity_at TEXT NOT NULL,
+    reply_count INTEGER NOT NULL DEFAULT 0,
+    vote_count INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (id) REFERENCES messages(id) ON DELETE CASCADE,
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE,
+    FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_forum_posts_room_activity ON forum_posts(room_id, last_activity_at);
+
+ALTER TABLE messages ADD COLUMN post_id TEXT REFERENCES forum_posts(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS idx_messages_post_created ON messages(post_id, created_at, id);
+
+-- Tags a forum offers, set by room managers; posts pick among them.
+CREATE TABLE IF NOT EXISTS forum_tags (
+    room_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (room_id, name),
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS forum_post_tags (
+    post_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (post_id, tag),
+    FOREIGN KEY (post_id) REFERENCES forum_posts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_forum_post_tags_tag ON forum_post_tags(tag, post_id);
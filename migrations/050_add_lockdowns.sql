@@ -0,0 +1,33 @@
+-- Lockdowns ("panic button"): for a while, chosen rooms get a strict slowmode,
+-- only trusted roles can post there and guest links stop working. At most one
+-- is active (`ended_at IS NULL`); past ones stay as the log of what happened.
+CREATE TABLE IF NOT EXISTS lockdowns (
+    id TEXT PRIMARY KEY,
+    reason TEXT,
+    slowmode_seconds INTEGER NOT NULL,
+    -- JSON array of the roles that can still post.
+    trusted_roles TEXT NOT NULL DEFAULT '[]',
+    pause_guest_links INTEGER NOT NULL DEFAULT 1,
+    started_by TEXT,
+    started_by_username TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ends_at TEXT NOT NULL,
+    ended_at TEXT,
+    -- NULL when it ran out.
+    ended_by_username TEXT,
+    FOREIGN KEY (started_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_lockdowns_active ON lockdowns(ends_at) WHERE ended_at IS NULL;
+
+-- The rooms of a lockdown, with the slowmode to give back when it ends.
+CREATE TABLE IF NOT EXISTS lockdown_rooms (
+    lockdown_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    previous_slowmode_seconds INTEGER NOT NULL,
+    PRIMARY KEY (lockdown_id, room_id),
+    FOREIGN KEY (lockdown_id) REFERENCES lockdowns(id) ON DELETE CASCADE,
+    FOREIGN KEY (room_id) REFERENCES rooms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_lockdown_rooms_room ON lockdown_rooms(room_id);
@@ -0,0 +1,22 @@
+-- Moderation and administration actions, for `GET /api/server/audit-log`.
+-- `actor_id` is NULL for actions taken by the server itself (an expired
+-- lockdown) or by an account deleted since; the username stays.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id TEXT PRIMARY KEY,
+    action TEXT NOT NULL,
+    actor_id TEXT,
+    actor_username TEXT,
+    target_type TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    -- JSON object: `{ field: { old, new } }` for edits, the created or
+    -- deleted thing otherwise.
+    changes TEXT NOT NULL DEFAULT '{}',
+    reason TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (actor_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_action ON audit_log(action, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log(actor_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_target ON audit_log(target_type, target_id, created_at);
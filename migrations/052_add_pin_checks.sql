@@ -0,0 +1,10 @@
+-- Pinned messages are re-checked periodically (see `pin_checks`): their link
+-- previews are fetched again and their attachments' files looked up in
+-- storage. `links_checked_at` is when that last happened.
+ALTER TABLE messages ADD COLUMN links_checked_at TEXT;
+
+-- Set when the stored object of a file cannot be read anymore; cleared when
+-- it can again, or when the same content is uploaded again.
+ALTER TABLE files ADD COLUMN missing_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_pinned_checked ON messages(links_checked_at) WHERE pinned_at IS NOT NULL;
@@ -0,0 +1,34 @@
+-- Bans: a banned member cannot sign in until the ban is lifted or, for a
+-- temporary ban, until `expires_at` passes. Lifted and expired bans stay as
+-- the member's record; at most one ban per member is in force.
+CREATE TABLE IF NOT EXISTS bans (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    reason TEXT,
+    banned_by TEXT,
+    banned_by_username TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    -- NULL for a permanent ban.
+    expires_at TEXT,
+    -- Messages of the last `delete_message_seconds` removed with the ban.
+    purged_messages INTEGER NOT NULL DEFAULT 0,
+    lifted_at TEXT,
+    -- NULL when it expired.
+    lifted_by_username TEXT,
+    -- Appeal: written once by the banned member, then reviewed by staff.
+    appealable INTEGER NOT NULL DEFAULT 1,
+    appeal_message TEXT,
+    appeal_submitted_at TEXT,
+    -- NULL (no appeal), 'pending', 'accepted' or 'denied'.
+    appeal_status TEXT,
+    appeal_reviewed_by_username TEXT,
+    appeal_reviewed_at TEXT,
+    appeal_note TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (banned_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_bans_in_force ON bans(user_id) WHERE lifted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_bans_expiring ON bans(expires_at) WHERE lifted_at IS NULL AND expires_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_bans_created ON bans(created_at);
@@ -0,0 +1,44 @@
+-- Automod: rules matching message content (keyword lists or regexes) and
+-- what to do with a match. Rules apply server-wide; when several match, the
+-- strongest action wins (timeout, then block, then flag).
+CREATE TABLE IF NOT EXISTS automod_rules (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- 'keyword' or 'regex'.
+    kind TEXT NOT NULL,
+    -- JSON array: keywords (`*` at either end matches inside words) or regexes.
+    patterns TEXT NOT NULL DEFAULT '[]',
+    -- 'block', 'flag' or 'timeout'.
+    action TEXT NOT NULL,
+    -- For 'timeout': how long the author cannot post.
+    timeout_seconds INTEGER,
+    -- JSON array of roles the rule does not apply to.
+    exempt_roles TEXT NOT NULL DEFAULT '[]',
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Messages posted but flagged by a rule, for moderators to review.
+CREATE TABLE IF NOT EXISTS automod_flags (
+    id TEXT PRIMARY KEY,
+    message_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    rule_id TEXT,
+    rule_name TEXT NOT NULL,
+    -- The text the rule matched.
+    matched TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    -- 'pending', 'kept' or 'removed'.
+    status TEXT NOT NULL DEFAULT 'pending',
+    reviewed_by_username TEXT,
+    reviewed_at TEXT,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    FOREIGN KEY (rule_id) REFERENCES automod_rules(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_automod_flags_status ON automod_flags(status, created_at);
+
+-- A member timed out (by automod) cannot post until then.
+ALTER TABLE users ADD COLUMN timed_out_until TEXT;
@@ -0,0 +1,25 @@
+-- Timeouts (temporary mutes): until `expires_at`, or until lifted, the member
+-- cannot post or join voice. Given by a moderator or by an automod rule
+-- (`created_by_username` NULL); past ones stay as the member's record.
+CREATE TABLE IF NOT EXISTS timeouts (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    reason TEXT,
+    created_by TEXT,
+    created_by_username TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    lifted_at TEXT,
+    lifted_by_username TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_timeouts_user ON timeouts(user_id, expires_at) WHERE lifted_at IS NULL;
+
+-- Automod timeouts were a column of users until now.
+INSERT INTO timeouts (id, user_id, reason, created_at, expires_at)
+SELECT lower(hex(randomblob(16))), id, 'Automod', timed_out_until, timed_out_until
+FROM users WHERE timed_out_until IS NOT NULL;
+
+ALTER TABLE users DROP COLUMN timed_out_until;
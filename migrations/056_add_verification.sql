@@ -0,0 +1,24 @@
+-- Server verification settings (a single row): what a member needs before
+-- posting, whether joining takes a captcha, and the join-surge detector.
+-- `surge_until` is set while a detected surge holds the raised settings.
+CREATE TABLE IF NOT EXISTS verification_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    min_account_age_minutes INTEGER NOT NULL DEFAULT 0,
+    require_discord_link INTEGER NOT NULL DEFAULT 0,
+    captcha_on_join INTEGER NOT NULL DEFAULT 0,
+    surge_joins INTEGER NOT NULL DEFAULT 0,
+    surge_window_seconds INTEGER NOT NULL DEFAULT 60,
+    surge_min_account_age_minutes INTEGER NOT NULL DEFAULT 10,
+    surge_pause_guest_links INTEGER NOT NULL DEFAULT 1,
+    surge_duration_minutes INTEGER NOT NULL DEFAULT 30,
+    surge_until TEXT,
+    updated_by_username TEXT,
+    updated_at TEXT
+);
+INSERT OR IGNORE INTO verification_settings (id) VALUES (1);
+
+-- Solved join captchas, so a challenge is only good for one join.
+CREATE TABLE IF NOT EXISTS used_join_challenges (
+    challenge TEXT PRIMARY KEY,
+    expires_at TEXT NOT NULL
+);
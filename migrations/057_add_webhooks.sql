@@ -0,0 +1,20 @@
+-- Incoming webhooks: a URL with a secret token that posts into one room.
+-- Each webhook has its own author (`user_id`, role `webhook`, no password)
+-- so its messages can be told apart and blocked; messages carry the
+-- webhook and, when the request overrode it, the avatar to show.
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY,
+    room_id TEXT NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    name TEXT NOT NULL,
+    avatar_url TEXT,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_by_username TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_webhooks_room ON webhooks(room_id);
+
+ALTER TABLE messages ADD COLUMN webhook_id TEXT;
+ALTER TABLE messages ADD COLUMN webhook_avatar_url TEXT;
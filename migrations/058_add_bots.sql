@@ -0,0 +1,14 @@
+-- Bot accounts: a user (the author of what the bot posts) signed in with a
+-- long-lived token instead of a JWT. The bot's permissions are those of its
+-- own role (`role`), created with it. Only the token's hash is stored.
+CREATE TABLE IF NOT EXISTS bots (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL UNIQUE REFERENCES users(id),
+    role TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    token_hash TEXT NOT NULL UNIQUE,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_by_username TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    token_reset_at TEXT
+);
@@ -0,0 +1,32 @@
+-- Two-way text bridges between a Voxium room and a Discord channel.
+-- Messages from Discord are posted through the bridge's webhook
+-- (`webhook_id`); messages to Discord go out with the server's bot token
+-- or the linked Discord account of `user_id`.
+CREATE TABLE IF NOT EXISTS discord_bridges (
+    id TEXT PRIMARY KEY,
+    room_id TEXT NOT NULL UNIQUE REFERENCES rooms(id) ON DELETE CASCADE,
+    channel_id TEXT NOT NULL UNIQUE,
+    token_source TEXT NOT NULL CHECK (token_source IN ('bot', 'user')),
+    user_id TEXT REFERENCES users(id) ON DELETE CASCADE,
+    webhook_id TEXT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    -- The Discord account relaying: its messages are our own echoes.
+    discord_user_id TEXT,
+    -- Newest Discord message seen, where the next poll starts.
+    last_discord_message_id TEXT,
+    last_error TEXT,
+    last_error_at TEXT,
+    created_by_username TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Messages relayed by a bridge, both ways. A Voxium message waiting to be
+-- sent to Discord has no `discord_message_id` yet.
+CREATE TABLE IF NOT EXISTS bridged_messages (
+    message_id TEXT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    bridge_id TEXT NOT NULL REFERENCES discord_bridges(id) ON DELETE CASCADE,
+    direction TEXT NOT NULL CHECK (direction IN ('to_discord', 'from_discord')),
+    discord_message_id TEXT,
+    created_at TEXT NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_bridged_messages_discord ON bridged_messages(bridge_id, discord_message_id);
+CREATE INDEX IF NOT EXISTS idx_bridged_messages_pending ON bridged_messages(bridge_id, created_at) WHERE discord_message_id IS NULL;
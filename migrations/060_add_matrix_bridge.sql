@@ -0,0 +1,53 @@
+-- Matrix bridge (application service). Rooms are linked to Matrix rooms
+-- from the `bridges.matrix` section of the server config. Matrix users are
+-- puppeted as Voxium users (role `matrix`, no password) and Voxium users as
+-- Matrix ghosts in the bridge's user namespace.
+CREATE TABLE IF NOT EXISTS matrix_room_links (
+    room_id TEXT PRIMARY KEY REFERENCES rooms(id) ON DELETE CASCADE,
+    matrix_room_id TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS matrix_puppets (
+    matrix_user_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS matrix_ghosts (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    matrix_user_id TEXT NOT NULL UNIQUE,
+    registered_at TEXT NOT NULL
+);
+
+-- Matrix rooms a ghost has joined, so it only joins once.
+CREATE TABLE IF NOT EXISTS matrix_ghost_rooms (
+    user_id TEXT NOT NULL REFERENCES matrix_ghosts(user_id) ON DELETE CASCADE,
+    matrix_room_id TEXT NOT NULL,
+    PRIMARY KEY (user_id, matrix_room_id)
+);
+
+-- Relayed messages both ways; `to_matrix` rows without an event id are
+-- waiting to be sent.
+CREATE TABLE IF NOT EXISTS matrix_events (
+    message_id TEXT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    matrix_room_id TEXT NOT NULL,
+    direction TEXT NOT NULL CHECK (direction IN ('to_matrix', 'from_matrix')),
+    matrix_event_id TEXT UNIQUE,
+    created_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_matrix_events_pending ON matrix_events(created_at) WHERE matrix_event_id IS NULL;
+
+-- Transactions pushed by the homeserver, which retries until acknowledged.
+CREATE TABLE IF NOT EXISTS matrix_transactions (
+    txn_id TEXT PRIMARY KEY,
+    received_at TEXT NOT NULL
+);
+
+-- Matrix media referenced by relayed messages, the only media proxied.
+CREATE TABLE IF NOT EXISTS matrix_media (
+    server_name TEXT NOT NULL,
+    media_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (server_name, media_id)
+);
@@ -0,0 +1,22 @@
+-- Avatars and the server banner: each upload is resized into a set of
+-- PNGs stored in the file store (see `files`), one row per size. A user
+-- has at most one avatar asset and the server one banner; replacing it
+-- deletes the old one.
+CREATE TABLE IF NOT EXISTS image_assets (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('avatar', 'server_banner')),
+    -- The user, for avatars; '' for the server banner.
+    owner_id TEXT NOT NULL,
+    uploaded_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE (kind, owner_id)
+);
+
+CREATE TABLE IF NOT EXISTS image_variants (
+    asset_id TEXT NOT NULL REFERENCES image_assets(id) ON DELETE CASCADE,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (asset_id, width)
+);
+CREATE INDEX IF NOT EXISTS idx_image_variants_hash ON image_variants(hash);
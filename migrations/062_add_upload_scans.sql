@@ -0,0 +1,11 @@
+-- Upload scanning (see `scanning`): the verdict on each attachment when a
+-- scanner is configured, NULL otherwise. Quarantined uploads can't be sent
+-- or downloaded until an admin releases them.
+ALTER TABLE attachments ADD COLUMN scan_status TEXT
+    CHECK (scan_status IN ('clean', 'quarantined', 'unscanned', 'released'));
+ALTER TABLE attachments ADD COLUMN scan_signature TEXT;
+ALTER TABLE attachments ADD COLUMN scanned_at TEXT;
+ALTER TABLE attachments ADD COLUMN reviewed_by TEXT REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE attachments ADD COLUMN reviewed_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_attachments_quarantined ON attachments(created_at) WHERE scan_status = 'quarantined';
@@ -0,0 +1,13 @@
+-- Email notifications (see `notifications`): what each user opted in to.
+-- Users without a row get nothing. `digest_sent_at` is when the last
+-- mention digest went out; the next one covers mentions after it.
+CREATE TABLE IF NOT EXISTS email_preferences (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    mention_digest INTEGER NOT NULL DEFAULT 0,
+    friend_requests INTEGER NOT NULL DEFAULT 0,
+    security INTEGER NOT NULL DEFAULT 0,
+    digest_sent_at TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_preferences_digest ON email_preferences(digest_sent_at) WHERE mention_digest = 1;
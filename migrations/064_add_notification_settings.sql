@@ -0,0 +1,15 @@
+-- Notification settings (see `notification_settings`): each user's defaults
+-- (`room_id` NULL) and per-room overrides. NULL `level` / `suppress_everyone`
+-- in a room follow the defaults.
+CREATE TABLE IF NOT EXISTS notification_settings (
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    room_id TEXT REFERENCES rooms(id) ON DELETE CASCADE,
+    level TEXT CHECK (level IN ('all', 'mentions', 'nothing')),
+    suppress_everyone INTEGER,
+    muted INTEGER NOT NULL DEFAULT 0,
+    muted_until TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_settings_defaults ON notification_settings(user_id) WHERE room_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_settings_rooms ON notification_settings(user_id, room_id) WHERE room_id IS NOT NULL;
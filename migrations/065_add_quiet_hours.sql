@@ -0,0 +1,11 @@
+-- Quiet hours (see `quiet_hours`): one daily window per user, in minutes of
+-- their local day. `days` is a bitmask, Monday = 1 to Sunday = 64, of the
+-- days a window starts on.
+CREATE TABLE IF NOT EXISTS quiet_hours (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    start_minute INTEGER NOT NULL CHECK (start_minute BETWEEN 0 AND 1439),
+    end_minute INTEGER NOT NULL CHECK (end_minute BETWEEN 0 AND 1439),
+    utc_offset_minutes INTEGER NOT NULL DEFAULT 0,
+    days INTEGER NOT NULL DEFAULT 127,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,18 @@
+-- Personal data exports (see `data_exports`). `archive_key` names the ZIP
+-- file in file storage while the export is `ready`.
+CREATE TABLE IF NOT EXISTS data_exports (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'running', 'ready', 'failed', 'expired')),
+    progress INTEGER NOT NULL DEFAULT 0,
+    archive_key TEXT,
+    size_bytes INTEGER,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    started_at TEXT,
+    completed_at TEXT,
+    expires_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_data_exports_user ON data_exports(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_data_exports_status ON data_exports(status, created_at);
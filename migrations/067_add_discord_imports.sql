@@ -0,0 +1,33 @@
+-- Discord server imports (see `discord_import`). `user_id` is whose linked
+-- Discord account reads the guild.
+CREATE TABLE IF NOT EXISTS discord_imports (
+    id TEXT PRIMARY KEY,
+    guild_id TEXT NOT NULL,
+    guild_name TEXT,
+    user_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_by_username TEXT NOT NULL,
+    include_pins INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'running', 'done', 'failed')),
+    stage TEXT,
+    progress INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    started_at TEXT,
+    completed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_discord_imports_status ON discord_imports(status, created_at);
+
+-- What each import created, by Discord id: `role` (a role name), `category`,
+-- `room`, `pins` (a room whose pins are copied) and `message`. An interrupted
+-- import skips what is already here; bridges can look channels up.
+CREATE TABLE IF NOT EXISTS discord_import_map (
+    import_id TEXT NOT NULL REFERENCES discord_imports(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('role', 'category', 'room', 'pins', 'message')),
+    discord_id TEXT NOT NULL,
+    voxium_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (import_id, kind, discord_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_discord_import_map_discord ON discord_import_map(kind, discord_id);
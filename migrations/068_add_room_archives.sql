@@ -0,0 +1,23 @@
+-- Room transcripts built for moderators (see `room_archives`). `archive_key`
+-- names the file in file storage while the archive is `ready`.
+CREATE TABLE IF NOT EXISTS room_archives (
+    id TEXT PRIMARY KEY,
+    room_id TEXT NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    requested_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    requested_by_username TEXT NOT NULL,
+    format TEXT NOT NULL CHECK (format IN ('json', 'html')),
+    include_attachments INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'running', 'ready', 'failed', 'expired')),
+    progress INTEGER NOT NULL DEFAULT 0,
+    message_count INTEGER,
+    archive_key TEXT,
+    size_bytes INTEGER,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    started_at TEXT,
+    completed_at TEXT,
+    expires_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_room_archives_room ON room_archives(room_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_room_archives_status ON room_archives(status, created_at);
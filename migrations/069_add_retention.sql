@@ -0,0 +1,18 @@
+-- Message retention (see `retention`). The server setting (a single row)
+-- applies to every room without one of its own; 0 keeps messages forever.
+-- `last_pruned_at` is when the nightly pruning last ran.
+CREATE TABLE IF NOT EXISTS retention_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    max_age_days INTEGER NOT NULL DEFAULT 0,
+    updated_by_username TEXT,
+    updated_at TEXT,
+    last_pruned_at TEXT
+);
+INSERT OR IGNORE INTO retention_settings (id) VALUES (1);
+
+CREATE TABLE IF NOT EXISTS room_retention (
+    room_id TEXT PRIMARY KEY REFERENCES rooms(id) ON DELETE CASCADE,
+    max_age_days INTEGER NOT NULL,
+    updated_by_username TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
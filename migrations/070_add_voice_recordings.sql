@@ -0,0 +1,41 @@
+-- Voice room recordings (see `voice_recordings`). Each participant who
+-- consents uploads their own Opus track in numbered segments while the
+-- recording runs; once stopped, the segments are joined into one ZIP in
+-- file storage (`archive_key`) and dropped.
+CREATE TABLE IF NOT EXISTS voice_recordings (
+    id TEXT PRIMARY KEY,
+    room_id TEXT NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    started_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    started_by_username TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('recording', 'processing', 'ready', 'failed')),
+    started_at TEXT NOT NULL,
+    stopped_at TEXT,
+    stopped_by_username TEXT,
+    completed_at TEXT,
+    archive_key TEXT,
+    size_bytes INTEGER,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_voice_recordings_room ON voice_recordings(room_id, started_at);
+-- One recording at a time per room.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_voice_recordings_active ON voice_recordings(room_id) WHERE status = 'recording';
+
+CREATE TABLE IF NOT EXISTS voice_recording_participants (
+    recording_id TEXT NOT NULL REFERENCES voice_recordings(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    consented INTEGER NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (recording_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS voice_recording_segments (
+    recording_id TEXT NOT NULL REFERENCES voice_recordings(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    object_key TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (recording_id, user_id, seq)
+);
@@ -0,0 +1,23 @@
+-- Server soundboard (see `backend/src/soundboard.rs`): short Ogg Opus clips
+-- kept in file storage (`object_key`), played into voice rooms by clients.
+CREATE TABLE IF NOT EXISTS soundboard_sounds (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    object_key TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    duration_secs REAL NOT NULL,
+    volume REAL NOT NULL DEFAULT 1.0,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_by_username TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- When each member last played a sound, for the cooldown.
+CREATE TABLE IF NOT EXISTS soundboard_cooldowns (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    last_play_ms INTEGER NOT NULL
+);
+
+-- USE_SOUNDBOARD (16384) joins the member defaults: roles that can connect
+-- to voice get it.
+UPDATE roles SET permissions = permissions | 16384 WHERE permissions & 16 != 0 OR name = 'admin';
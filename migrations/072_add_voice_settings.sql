@@ -0,0 +1,11 @@
+-- Per-user voice settings (see `voice_settings`), shared by all of a user's
+-- devices. Users without a row have the defaults.
+CREATE TABLE IF NOT EXISTS voice_settings (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    input_mode TEXT NOT NULL DEFAULT 'voice_activity' CHECK (input_mode IN ('voice_activity', 'push_to_talk')),
+    vad_automatic INTEGER NOT NULL DEFAULT 1,
+    vad_threshold_db INTEGER NOT NULL DEFAULT -50 CHECK (vad_threshold_db BETWEEN -100 AND 0),
+    push_to_talk_release_ms INTEGER NOT NULL DEFAULT 200 CHECK (push_to_talk_release_ms BETWEEN 0 AND 2000),
+    noise_suppression TEXT NOT NULL DEFAULT 'standard' CHECK (noise_suppression IN ('off', 'standard', 'high')),
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,23 @@
+-- Voice moderation and listening volumes (see `voice_moderation` and
+-- `voice_settings`).
+
+-- Members a moderator server-muted or server-deafened in a voice room. A
+-- row goes away once both are lifted.
+CREATE TABLE IF NOT EXISTS voice_member_states (
+    room_id TEXT NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    server_muted INTEGER NOT NULL DEFAULT 0,
+    server_deafened INTEGER NOT NULL DEFAULT 0,
+    updated_by_username TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (room_id, user_id)
+);
+
+-- How loud each listener plays another member, in percent. 100 is not stored.
+CREATE TABLE IF NOT EXISTS voice_user_volumes (
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    volume INTEGER NOT NULL CHECK (volume BETWEEN 0 AND 200),
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, target_id)
+);
@@ -0,0 +1,10 @@
+-- How members appear on this server (see `member_profiles`), over their
+-- account profile. Members without a row show their account profile.
+CREATE TABLE IF NOT EXISTS member_profiles (
+    user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    nickname TEXT,
+    avatar_url TEXT,
+    pronouns TEXT,
+    bio TEXT,
+    updated_at TEXT NOT NULL
+);
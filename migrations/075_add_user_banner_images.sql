@@ -0,0 +1,32 @@
+-- Profile banners go through the image pipeline like avatars: a third
+-- kind of asset, owned by its user. SQLite can't change a CHECK in place,
+-- so both tables are rebuilt. The new variants table points at the new
+-- assets table from the start (the rename below follows it), so dropping
+-- the old tables cascades into nothing that is kept.
+CREATE TABLE image_assets_new (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('avatar', 'user_banner', 'server_banner')),
+    -- The user, for avatars and profile banners; '' for the server banner.
+    owner_id TEXT NOT NULL,
+    uploaded_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE (kind, owner_id)
+);
+INSERT INTO image_assets_new (id, kind, owner_id, uploaded_by, created_at)
+    SELECT id, kind, owner_id, uploaded_by, created_at FROM image_assets;
+
+CREATE TABLE image_variants_new (
+    asset_id TEXT NOT NULL REFERENCES image_assets_new(id) ON DELETE CASCADE,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (asset_id, width)
+);
+INSERT INTO image_variants_new (asset_id, width, height, hash)
+    SELECT asset_id, width, height, hash FROM image_variants;
+
+DROP TABLE image_variants;
+DROP TABLE image_assets;
+ALTER TABLE image_assets_new RENAME TO image_assets;
+ALTER TABLE image_variants_new RENAME TO image_variants;
+CREATE INDEX IF NOT EXISTS idx_image_variants_hash ON image_variants(hash);
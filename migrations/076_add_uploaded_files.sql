@@ -0,0 +1,12 @@
+-- Files served under `/uploads/` (see `backend/src/uploads.rs`): custom
+-- emoji, role icons and images from before attachments. They live in file
+-- storage like attachments; `path` is the part of the URL after `/uploads/`.
+CREATE TABLE IF NOT EXISTS uploaded_files (
+    path TEXT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_uploaded_files_hash ON uploaded_files(hash);
@@ -0,0 +1,16 @@
+-- Whether the account's password is one its owner chose. Accounts created
+-- through a Discord login get a random one nobody knows, guests, bots and
+-- puppets none at all: sudo mode (see `backend/src/sudo.rs`) does not offer
+-- the password to them.
+ALTER TABLE users ADD COLUMN password_set INTEGER NOT NULL DEFAULT 1;
+
+UPDATE users SET password_set = 0 WHERE password_hash = '!';
+
+-- Discord sign-ups are in the security log; a password set since then
+-- flips the flag back on the next password login.
+UPDATE users SET password_set = 0
+WHERE id IN (
+    SELECT user_id FROM auth_audit
+    WHERE json_extract(details, '$.new_account') = 1 AND json_extract(details, '$.discord_id') IS NOT NULL
+)
+AND id NOT IN (SELECT user_id FROM auth_audit WHERE event = 'recovery_completed');